@@ -85,7 +85,7 @@ fn bench_type_checking_small(c: &mut Criterion) {
     c.bench_function("type_check_small_program", |b| {
         b.iter(|| {
             let mut type_checker = TypeChecker::new();
-            let _ = type_checker.check_program(black_box(&program)).unwrap();
+            type_checker.check_program(black_box(&program)).unwrap();
         })
     });
 }
@@ -98,7 +98,7 @@ fn bench_type_checking_medium(c: &mut Criterion) {
     c.bench_function("type_check_medium_program", |b| {
         b.iter(|| {
             let mut type_checker = TypeChecker::new();
-            let _ = type_checker.check_program(black_box(&program)).unwrap();
+            type_checker.check_program(black_box(&program)).unwrap();
         })
     });
 }
@@ -133,7 +133,7 @@ fn bench_optimization_small(c: &mut Criterion) {
     let source = create_test_program(5);
     let mut parser = Parser::new(&source);
     let program = parser.parse_program().unwrap();
-    let manager = create_default_manager();
+    let mut manager = create_default_manager();
 
     c.bench_function("optimize_small_program", |b| {
         b.iter(|| {
@@ -146,7 +146,7 @@ fn bench_optimization_medium(c: &mut Criterion) {
     let source = create_test_program(50);
     let mut parser = Parser::new(&source);
     let program = parser.parse_program().unwrap();
-    let manager = create_default_manager();
+    let mut manager = create_default_manager();
 
     c.bench_function("optimize_medium_program", |b| {
         b.iter(|| {
@@ -0,0 +1,432 @@
+//! Generates the bundled cost table, `src/analyzer/costs.toml`, from
+//! timings on the reference interpreter
+//!
+//! `cargo bench --bench cost_weights` prints the table it measures, and
+//! `cargo bench --bench cost_weights -- --write` replaces the bundled one.
+//!
+//! Each weight is measured as follows:
+//! - `step` is the time one executed instruction takes.
+//! - An instruction class is measured by compiling a function with `K`
+//!   copies of a snippet and one without. The gas profiler counts the
+//!   operations the copies add. The executed instructions they add, less
+//!   those of the classes measured before, are charged to the class, at
+//!   the time of a step.
+//! - A host call is measured by timing the runtime function behind it.
+//!   Its proof size is what the runtime meters for a 32-byte key and a
+//!   32-byte value. An external call is timed on the interpreter calling
+//!   a deployed contract, less the steps the caller and the callee run.
+//! - A `_byte` weight is the slope between a host call on little data
+//!   and the same call on more.
+//!
+//! The RISC-V code generator cannot compile a few of the constructs the
+//! profiler counts. Those classes take the weight of the class closest
+//! to them, as listed in `BORROWED`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use bend_pvm::analyzer::gas_profiler::GasProfiler;
+use bend_pvm::analyzer::{CostTable, Weight};
+use bend_pvm::compiler::address::{Address, Hash};
+use bend_pvm::compiler::codegen::risc_v::{Instruction, RiscVCodegen};
+use bend_pvm::compiler::lowering::lower_program;
+use bend_pvm::compiler::parser::parser::Parser;
+use bend_pvm::runtime::env::{Environment, ExecutionContext};
+use bend_pvm::runtime::interpreter::Interpreter;
+
+/// The bundled table, relative to the package
+const TABLE_FILE: &str = "src/analyzer/costs.toml";
+
+/// Picoseconds of `ref_time` one unit of gas stands for, chosen so that a
+/// step costs about one unit of gas
+const REF_TIME_PER_GAS: u64 = 20_000;
+
+/// Instruction classes, in the order they are measured, with a snippet
+/// adding one of each and how many copies of it to measure. A snippet may
+/// add operations of classes measured before it. Snippets follow `x = 0;`
+/// in a function of a program where `one()` returns 1.
+const SNIPPETS: &[(&str, &str, usize)] = &[
+    ("literal", "1;", 16),
+    // Only the first return runs
+    ("return", "return 0;", 1),
+    ("variable_access", "x;", 16),
+    ("assignment", "y = 1;", 16),
+    ("binary_op", "1 + 1;", 16),
+    ("if_branch", "if x == 0 { } else { }", 16),
+    ("function_call", "one();", 16),
+];
+
+/// Classes of constructs the code generator cannot compile, or that the
+/// profiler never charges on their own, and the class whose weight each
+/// takes
+const BORROWED: &[(&str, &str)] = &[
+    ("match_branch", "if_branch"),
+    ("bend_iteration", "function_call"),
+    ("with_block", "assignment"),
+    ("pattern", "assignment"),
+    ("statement", "assignment"),
+    ("expression", "binary_op"),
+    ("tuple", "binary_op"),
+    ("list", "binary_op"),
+    ("constructor", "binary_op"),
+];
+
+/// The shortest of `runs` timings of `f`
+fn fastest(runs: usize, mut f: impl FnMut()) -> Duration {
+    (0..runs)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn picoseconds(duration: Duration) -> u64 {
+    duration.as_nanos() as u64 * 1_000
+}
+
+fn compile(source: &str) -> Vec<Instruction> {
+    let program = Parser::new(source)
+        .parse_program()
+        .unwrap_or_else(|e| panic!("{}\n{}", e, source));
+    RiscVCodegen::new()
+        .generate(&program)
+        .unwrap_or_else(|e| panic!("{}\n{}", e, source))
+}
+
+fn context() -> ExecutionContext {
+    let mut context = ExecutionContext::new_default();
+    context.gas_limit = u64::MAX / 2;
+    context
+}
+
+/// Instructions executed by the function `name` of `code`
+fn steps(code: &[Instruction], name: &str) -> u64 {
+    let mut interpreter = Interpreter::new(context());
+    interpreter.execute_function(code, name).unwrap();
+    interpreter.steps()
+}
+
+/// Picoseconds one executed instruction takes, timed on a loop
+fn measure_step() -> u64 {
+    let code = compile(
+        "fn spin() -> u24 {
+            total = 0;
+            for i in range(0, 20000) bound 20000 {
+                total = total + i;
+            }
+            return total;
+        }",
+    );
+    let steps = steps(&code, "spin");
+    let time = fastest(30, || {
+        let mut interpreter = Interpreter::new(context());
+        black_box(interpreter.execute_function(&code, "spin").unwrap());
+    });
+    picoseconds(time) / steps
+}
+
+/// A program with the function `name` running `body`, and `one()`
+fn program(name: &str, body: &str) -> String {
+    format!(
+        "fn one() -> u24 {{ return 1; }}\nfn {}() -> u24 {{ {} }}\n",
+        name, body
+    )
+}
+
+/// Operations of each class the gas profiler counts in the function
+/// `name`, and the instructions it executes
+fn count(name: &str, body: &str) -> (BTreeMap<String, u64>, u64) {
+    let source = program(name, body);
+    let mut unit = CostTable {
+        ref_time_per_gas: 1,
+        ..CostTable::default()
+    };
+    for weight in unit.instructions.values_mut().chain(unit.host.values_mut()) {
+        *weight = Weight {
+            ref_time: 1,
+            proof_size: 0,
+        };
+    }
+    let profile = GasProfiler::with_costs(unit)
+        .profile_source(&source, "snippet.bend")
+        .unwrap();
+    let estimate = profile
+        .estimates
+        .into_iter()
+        .find(|estimate| estimate.name == name)
+        .unwrap();
+    let operations = estimate.cost_breakdown.into_iter().collect();
+    (operations, steps(&compile(&source), name))
+}
+
+/// Instructions each instruction class executes
+fn measure_classes() -> BTreeMap<String, u64> {
+    // An empty function is all overhead
+    let (_, overhead) = count("empty", "");
+    let mut classes = BTreeMap::from([
+        ("step".to_string(), 1),
+        ("function_overhead".to_string(), overhead),
+    ]);
+
+    let (base, base_steps) = count("measured", "x = 0;");
+    for (class, snippet, copies) in SNIPPETS {
+        let body = format!("x = 0; {}", snippet.repeat(*copies));
+        let (added, steps) = count("measured", &body);
+        let mut steps = steps - base_steps;
+
+        let mut measured = 0;
+        for (operation, n) in added {
+            let n = n - base.get(&operation).copied().unwrap_or(0);
+            if n == 0 {
+                continue;
+            }
+            if operation == *class {
+                measured = n;
+            } else {
+                let known = classes.get(&operation).unwrap_or_else(|| {
+                    panic!("`{}` adds {}, not measured yet", snippet, operation)
+                });
+                steps -= n * known;
+            }
+        }
+        assert!(measured > 0, "`{}` adds no {}", snippet, class);
+        classes.insert(class.to_string(), steps.div_ceil(measured));
+    }
+
+    for (class, from) in BORROWED {
+        classes.insert(class.to_string(), classes[*from]);
+    }
+    classes
+}
+
+/// The weight of each host call, and of each byte of data it takes
+fn measure_host() -> BTreeMap<String, Weight> {
+    let key = [7u8; 32];
+    let value = [9u8; 32];
+    let large = [9u8; 320];
+    let runs = 2_000;
+    let mut host = BTreeMap::new();
+
+    // Time `f` on a fresh environment, and take the proof size it meters
+    let mut measure = |name: &str, f: &dyn Fn(&mut Environment)| {
+        let mut environments: Vec<Environment> =
+            (0..runs).map(|_| prepared(&key, &value)).collect();
+        let mut iter = environments.iter_mut();
+        let time = fastest(runs, || f(iter.next().unwrap()));
+        let mut environment = prepared(&key, &value);
+        let before = environment.context.proof_size_used;
+        f(&mut environment);
+        let weight = Weight {
+            ref_time: picoseconds(time).max(1_000),
+            proof_size: environment.context.proof_size_used - before,
+        };
+        host.insert(name.to_string(), weight);
+        weight
+    };
+
+    measure("storage_read", &|env| {
+        black_box(env.storage_get(&key).unwrap());
+    });
+    let small = measure("storage_write", &|env| {
+        env.storage_set(&key, &value).unwrap()
+    });
+    let big = measure("storage_write_large", &|env| {
+        env.storage_set(&key, &large).unwrap()
+    });
+    measure("storage_delete", &|env| env.storage_clear(&key).unwrap());
+    let event = measure("event_emit", &|env| {
+        env.emit_event(vec![key.to_vec()], value.to_vec()).unwrap()
+    });
+    let big_event = measure("event_emit_large", &|env| {
+        env.emit_event(vec![key.to_vec()], large.to_vec()).unwrap()
+    });
+    measure("transfer", &|env| {
+        env.transfer(Address::ZERO, Address::new([1; 32]), 1)
+            .unwrap()
+    });
+    measure("set_code_hash", &|env| {
+        env.set_code_hash(Hash::new([3; 32])).unwrap()
+    });
+    measure("terminate", &|env| {
+        env.terminate(Address::new([1; 32])).unwrap();
+    });
+
+    // Per-byte weights are the slope between 32 and 320 bytes of data
+    let extra = (large.len() - value.len()) as u64;
+    for (name, small, big) in [
+        ("storage_write", small, big),
+        ("event_emit", event, big_event),
+    ] {
+        host.remove(&format!("{}_large", name));
+        let per_byte = Weight {
+            ref_time: big.ref_time.saturating_sub(small.ref_time) / extra,
+            proof_size: (big.proof_size - small.proof_size) / extra,
+        };
+        host.insert(format!("{}_byte", name), per_byte);
+    }
+
+    // `with IO` blocks run host calls the profiler does not see
+    host.insert("io_operations".to_string(), host["storage_read"]);
+    host
+}
+
+/// A table charging one unit of gas for each `operation`, and nothing for
+/// anything else
+fn only(operation: &str) -> CostTable {
+    let mut table = CostTable {
+        ref_time_per_gas: 1,
+        ..CostTable::default()
+    };
+    for (name, weight) in table.instructions.iter_mut().chain(table.host.iter_mut()) {
+        *weight = Weight {
+            ref_time: u64::from(name == operation),
+            proof_size: 0,
+        };
+    }
+    table
+}
+
+/// The contract external calls are measured against
+const CALLEE: &str = "
+    fn noop() -> u24 { return 0; }
+    fn take(a: u24, b: u24, c: u24, d: u24, e: u24, f: u24, g: u24, h: u24) -> u24 {
+        return 0;
+    }
+";
+
+/// A caller making `calls` calls to `signature` of the callee
+fn caller(calls: usize, signature: &str) -> Vec<Instruction> {
+    let call = format!("x = call(2, 0, 0, {});", signature);
+    let source = format!("fn main() -> u24 {{ {} return 0; }}", call.repeat(calls));
+    let program = lower_program(Parser::new(&source).parse_program().unwrap());
+    RiscVCodegen::new().generate(&program).unwrap()
+}
+
+/// Run `code` against the callee, charging gas from `costs`, and return
+/// the time it took, the gas and the proof size it used
+fn run_caller(code: &[Instruction], costs: CostTable) -> (Duration, u64, u64) {
+    let program = Parser::new(CALLEE).parse_program().unwrap();
+    let callee = RiscVCodegen::new()
+        .with_dispatcher()
+        .generate(&program)
+        .unwrap();
+    let mut callee_address = [0u8; 32];
+    callee_address[0] = 2;
+    let mut environment = Environment::new(context()).with_costs(costs);
+    environment.deploy(Address::new(callee_address), callee);
+
+    let mut interpreter = Interpreter::with_environment(environment);
+    let start = Instant::now();
+    black_box(interpreter.execute(code).unwrap());
+    let time = start.elapsed();
+    let context = &interpreter.environment().context;
+    (time, context.gas_used, context.proof_size_used)
+}
+
+/// The weight of an external call, and of each byte of its input, timed
+/// on the interpreter calling a deployed contract. The instructions the
+/// caller and the callee execute are charged as steps, not to the call.
+fn measure_call(step: u64) -> (Weight, Weight) {
+    let calls = 16;
+    // Time, steps, input bytes and proof size of one call to `signature`
+    let per_call = |signature: &str| {
+        let [with, without] = [calls, 0].map(|n| {
+            let code = caller(n, signature);
+            let time = (0..200)
+                .map(|_| run_caller(&code, only("step")).0)
+                .min()
+                .unwrap();
+            let (_, steps, proof_size) = run_caller(&code, only("step"));
+            let (_, bytes, _) = run_caller(&code, only("external_call_byte"));
+            (picoseconds(time), steps, bytes, proof_size)
+        });
+        let n = calls as u64;
+        let steps = (with.1 - without.1) / n;
+        let ref_time = ((with.0 - without.0) / n).saturating_sub(steps * step);
+        let bytes = (with.2 - without.2) / n;
+        let proof_size = (with.3 - without.3) / n;
+        (ref_time, bytes, proof_size)
+    };
+
+    let small = per_call("\"noop()\"");
+    let big = per_call("\"take(u24,u24,u24,u24,u24,u24,u24,u24)\", 1, 2, 3, 4, 5, 6, 7, 8");
+    let extra = big.1 - small.1;
+    let per_byte = Weight {
+        ref_time: big.0.saturating_sub(small.0) / extra,
+        proof_size: big.2.saturating_sub(small.2) / extra,
+    };
+    let call = Weight {
+        ref_time: small
+            .0
+            .saturating_sub(per_byte.ref_time * small.1)
+            .max(1_000),
+        proof_size: small.2.saturating_sub(per_byte.proof_size * small.1),
+    };
+    (call, per_byte)
+}
+
+/// An environment with `value` stored at `key`, funds and uploaded code
+fn prepared(key: &[u8], value: &[u8]) -> Environment {
+    let mut environment = Environment::new(context());
+    environment.storage.insert(key.to_vec(), value.to_vec());
+    environment.set_balance(Address::ZERO, 1_000_000);
+    environment.register_code(Hash::new([3; 32]), Vec::new());
+    environment
+}
+
+fn main() {
+    let write = std::env::args().any(|arg| arg == "--write");
+
+    let step = measure_step();
+    let classes = measure_classes();
+    let mut host = measure_host();
+    let (call, per_byte) = measure_call(step);
+    host.insert("external_call".to_string(), call);
+    host.insert("external_call_byte".to_string(), per_byte);
+
+    let mut table = CostTable {
+        runtime: "reference-interpreter".to_string(),
+        spec_version: 0,
+        ref_time_per_gas: REF_TIME_PER_GAS,
+        instructions: BTreeMap::new(),
+        host,
+    };
+    for (class, steps) in classes {
+        let weight = Weight {
+            ref_time: steps * step,
+            proof_size: 0,
+        };
+        table.instructions.insert(class, weight);
+    }
+
+    let text = format!(
+        "# Weights the gas profiler and the runtime charge for each operation\n\
+         #\n\
+         # `ref_time` is picoseconds of execution and `proof_size` bytes of storage\n\
+         # proof, as the chain runtime reports weight. Gas is `ref_time` divided by\n\
+         # `ref_time_per_gas`, rounded up.\n\
+         #\n\
+         # Generated by `cargo bench --bench cost_weights -- --write`, which times\n\
+         # the reference interpreter; do not edit by hand. A step took {} ps on\n\
+         # the machine that generated it. Use a table measured on the target\n\
+         # runtime via the `[gas]` table of `bend.toml` or `--costs`.\n\n{}",
+        step,
+        table.to_toml()
+    );
+    // The table must read back as it was written
+    let parsed = bend_pvm::package::toml::parse(&text).unwrap();
+    assert_eq!(CostTable::from_toml(&parsed).unwrap().host, table.host);
+
+    if write {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(TABLE_FILE);
+        fs::write(&path, &text).unwrap();
+        println!("wrote {}", path.display());
+    } else {
+        print!("{}", text);
+    }
+}
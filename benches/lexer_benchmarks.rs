@@ -0,0 +1,86 @@
+use bend_pvm::compiler::lexer::lexer::{BendLexer, TokenBuffer};
+use bend_pvm::compiler::lexer::token::Token;
+use bend_pvm::compiler::parser::parser::Parser;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// A source of at least `size` bytes made of distinct definitions that mix
+/// every kind of token the lexer reads
+fn create_source(size: usize) -> String {
+    let mut source = String::with_capacity(size + 1024);
+    let mut i = 0;
+
+    while source.len() < size {
+        source.push_str(&format!(
+            "# Definitions for account group {i}\n\
+             type Account{i} {{\n    Active(balance: u24, owner: String),\n    Frozen,\n}}\n\n\
+             #{{ Moves `amount` between the balances of group {i} }}#\n\
+             fn transfer_{i}(sender_balance: u24, amount: u24) -> u24 {{\n\
+             \x20   let fee = amount / 100 + {i};\n\
+             \x20   let label = \"transfer #{i}: ok\";\n\
+             \x20   if sender_balance >= amount + fee {{\n\
+             \x20       return sender_balance - amount - fee; # enough funds\n\
+             \x20   }} else {{\n\
+             \x20       return 0x{i:x}u24;\n\
+             \x20   }}\n\
+             }}\n\n",
+        ));
+        i += 1;
+    }
+
+    source
+}
+
+fn lex(source: &str) -> usize {
+    let mut lexer = BendLexer::new(source);
+    let mut count = 0;
+    while lexer.next_token().token != Token::EOF {
+        count += 1;
+    }
+    count
+}
+
+fn bench_lexing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lex");
+
+    for size in [64 * 1024, 1024 * 1024] {
+        let source = create_source(size);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &source, |b, source| {
+            b.iter(|| lex(black_box(source)))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_tokenizing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize");
+
+    for size in [64 * 1024, 1024 * 1024] {
+        let source = create_source(size);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &source, |b, source| {
+            b.iter(|| TokenBuffer::new(black_box(source)).len())
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(20);
+
+    for size in [64 * 1024, 1024 * 1024] {
+        let source = create_source(size);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &source, |b, source| {
+            b.iter(|| Parser::new(black_box(source)).parse_program().unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_lexing, bench_tokenizing, bench_parsing);
+criterion_main!(benches);
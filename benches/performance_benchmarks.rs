@@ -181,7 +181,7 @@ fn bench_gas_hash(c: &mut Criterion) {
 
 /// Helper function for SHA256 benchmark
 fn use_sha256(data: &[u8]) -> [u8; 32] {
-    use digest::Digest;
+    use sha2::Digest;
     use sha2::Sha256;
     let mut hasher = Sha256::new();
     hasher.update(data);
@@ -346,11 +346,7 @@ fn bench_codegen_risc_v(c: &mut Criterion) {
     group.bench_function("generate_risc_v", |b| {
         b.iter(|| {
             // Simulate RISC-V code generation
-            let mut instructions = Vec::new();
-            // Add instructions
-            for _ in 0..black_box(100) {
-                instructions.push("add t0, t1, t2");
-            }
+            let instructions = vec!["add t0, t1, t2"; black_box(100)];
             black_box(instructions.join("\n"))
         })
     });
@@ -0,0 +1,390 @@
+//! The weights the gas profiler charges, per chain runtime
+//!
+//! A cost table gives each instruction class and host call the weight it
+//! has on a chain runtime, as `ref_time` (picoseconds) and `proof_size`
+//! (bytes), and the `ref_time` one unit of gas stands for:
+//!
+//! ```toml
+//! [runtime]
+//! name = "asset-hub-westend"
+//! spec_version = 1_016_000
+//! ref_time_per_gas = 25_000
+//!
+//! [instructions]
+//! binary_op = { ref_time = 90_000, proof_size = 0 }
+//!
+//! [host]
+//! storage_write = { ref_time = 120_000_000, proof_size = 96 }
+//! ```
+//!
+//! The compiler bundles a reference table. A table read from a file only
+//! replaces the entries it lists, so it may be partial, but every name must
+//! be one the bundled table has. A package picks its table in the `[gas]`
+//! table of its `bend.toml`, with `costs` naming a table file relative to
+//! the manifest and the same sections as above overriding single entries:
+//!
+//! ```toml
+//! [gas]
+//! costs = "costs/asset-hub-westend.toml"
+//!
+//! [gas.host]
+//! storage_read = { ref_time = 5_000_000 }
+//! ```
+//!
+//! The same table meters execution in the runtime: an executed instruction
+//! costs `instructions.step` and each host call its entry in `[host]`,
+//! with host calls taking data paying their `_byte` entry per byte. The
+//! bundled table is generated by the `cost_weights` benchmark, which times
+//! these on the reference interpreter; rerun it with
+//! `cargo bench --bench cost_weights -- --write` to update it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::package::toml::{self, TomlTable, TomlValue};
+use crate::package::workspace::MANIFEST_FILE;
+
+/// The cost table the compiler ships with
+const BUNDLED: &str = include_str!("costs.toml");
+
+/// What one operation costs on the chain
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Weight {
+    /// Execution time, in picoseconds
+    pub ref_time: u64,
+    /// Storage proof the operation adds, in bytes
+    pub proof_size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CostTable {
+    /// Name of the chain runtime the weights were measured on
+    pub runtime: String,
+    pub spec_version: u64,
+    /// Picoseconds of `ref_time` one unit of gas stands for
+    pub ref_time_per_gas: u64,
+    /// Weights of instruction classes, by name
+    pub instructions: BTreeMap<String, Weight>,
+    /// Weights of host calls, by name
+    pub host: BTreeMap<String, Weight>,
+}
+
+impl Default for CostTable {
+    /// The bundled reference table
+    fn default() -> Self {
+        static TABLE: OnceLock<CostTable> = OnceLock::new();
+        TABLE
+            .get_or_init(|| {
+                let table = toml::parse(BUNDLED).expect("bundled cost table is valid TOML");
+                let mut costs = CostTable {
+                    runtime: String::new(),
+                    spec_version: 0,
+                    ref_time_per_gas: 1,
+                    instructions: BTreeMap::new(),
+                    host: BTreeMap::new(),
+                };
+                costs
+                    .merge(&table, true)
+                    .expect("bundled cost table is valid");
+                costs
+            })
+            .clone()
+    }
+}
+
+impl CostTable {
+    /// The bundled table with the entries of a parsed cost table in place
+    /// of its own
+    pub fn from_toml(table: &TomlTable) -> Result<Self, String> {
+        let mut costs = CostTable::default();
+        costs.apply(table)?;
+        Ok(costs)
+    }
+
+    /// Read the cost table file at `path`
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut costs = CostTable::default();
+        costs.apply(&read(path)?).map_err(in_file(path))?;
+        Ok(costs)
+    }
+
+    /// Replace the entries `table` lists, which uses the layout of a cost
+    /// table file
+    pub fn apply(&mut self, table: &TomlTable) -> Result<(), String> {
+        self.merge(table, false)
+    }
+
+    /// The cost table for the file or directory at `path`: the `[gas]`
+    /// table of the closest `bend.toml` with one decides, and the bundled
+    /// table is used when there is none
+    pub fn discover(path: &Path) -> Result<Self, String> {
+        let start = if path.is_dir() {
+            path
+        } else {
+            path.parent().unwrap_or(Path::new(""))
+        };
+        // A relative file name has an empty parent
+        let start = if start.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            start
+        };
+        let start = start.canonicalize().unwrap_or_else(|_| start.to_path_buf());
+
+        for dir in start.ancestors() {
+            let manifest = dir.join(MANIFEST_FILE);
+            if !manifest.is_file() {
+                continue;
+            }
+            let mut manifest_table = read(&manifest)?;
+            let Some(gas) = manifest_table.remove("gas") else {
+                continue;
+            };
+            let TomlValue::Table(mut gas) = gas else {
+                return Err(in_file(&manifest)("'gas' must be a table".to_string()));
+            };
+            let mut costs = match gas.remove("costs") {
+                Some(TomlValue::String(file)) => CostTable::load(&dir.join(file))?,
+                Some(_) => {
+                    return Err(in_file(&manifest)(
+                        "'gas.costs' must be a file name".to_string(),
+                    ))
+                }
+                None => CostTable::default(),
+            };
+            costs.apply(&gas).map_err(in_file(&manifest))?;
+            return Ok(costs);
+        }
+        Ok(CostTable::default())
+    }
+
+    /// The weight of an instruction class or host call
+    pub fn weight(&self, operation: &str) -> Option<Weight> {
+        self.instructions
+            .get(operation)
+            .or_else(|| self.host.get(operation))
+            .copied()
+    }
+
+    /// The gas an instruction class or host call costs
+    pub fn gas(&self, operation: &str) -> Option<u64> {
+        self.weight(operation)
+            .map(|weight| self.gas_for(weight.ref_time))
+    }
+
+    /// The gas a host call costs on `bytes` bytes of data, each paying the
+    /// weight of `per_byte`, rounded up once for the whole call
+    pub fn gas_with_bytes(&self, operation: &str, per_byte: &str, bytes: usize) -> u64 {
+        let ref_time = |operation| self.weight(operation).unwrap_or_default().ref_time;
+        let total =
+            ref_time(operation).saturating_add(ref_time(per_byte).saturating_mul(bytes as u64));
+        self.gas_for(total)
+    }
+
+    /// Gas standing for `ref_time` picoseconds, rounded up
+    pub fn gas_for(&self, ref_time: u64) -> u64 {
+        ref_time.div_ceil(self.ref_time_per_gas)
+    }
+
+    /// The table as the TOML of a cost table file, listing every entry
+    pub fn to_toml(&self) -> String {
+        let mut out = format!(
+            "[runtime]\nname = {:?}\nspec_version = {}\nref_time_per_gas = {}\n",
+            self.runtime,
+            self.spec_version,
+            group(self.ref_time_per_gas)
+        );
+        for (section, weights) in [("instructions", &self.instructions), ("host", &self.host)] {
+            out.push_str(&format!("\n[{}]\n", section));
+            for (operation, weight) in weights {
+                out.push_str(&format!(
+                    "{} = {{ ref_time = {}, proof_size = {} }}\n",
+                    operation,
+                    group(weight.ref_time),
+                    group(weight.proof_size)
+                ));
+            }
+        }
+        out
+    }
+
+    /// Take the entries of `table`. New instruction classes and host calls
+    /// are only accepted when `extend` is set.
+    fn merge(&mut self, table: &TomlTable, extend: bool) -> Result<(), String> {
+        for (key, value) in table {
+            let section = value
+                .as_table()
+                .ok_or_else(|| format!("'{}' must be a table", key))?;
+            match key.as_str() {
+                "runtime" => self.merge_runtime(section)?,
+                "instructions" => merge_weights(&mut self.instructions, key, section, extend)?,
+                "host" => merge_weights(&mut self.host, key, section, extend)?,
+                _ => return Err(format!("Unknown cost table section '{}'", key)),
+            }
+        }
+        Ok(())
+    }
+
+    fn merge_runtime(&mut self, table: &TomlTable) -> Result<(), String> {
+        for (key, value) in table {
+            match key.as_str() {
+                "name" => {
+                    self.runtime = value
+                        .as_str()
+                        .ok_or("'runtime.name' must be a string")?
+                        .to_string()
+                }
+                "spec_version" => self.spec_version = amount("runtime", key, value)?,
+                "ref_time_per_gas" => match amount("runtime", key, value)? {
+                    0 => return Err("'runtime.ref_time_per_gas' must be positive".to_string()),
+                    n => self.ref_time_per_gas = n,
+                },
+                _ => return Err(format!("Unknown runtime setting '{}'", key)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Take the weights in the section `name` of a cost table
+fn merge_weights(
+    weights: &mut BTreeMap<String, Weight>,
+    name: &str,
+    table: &TomlTable,
+    extend: bool,
+) -> Result<(), String> {
+    for (operation, value) in table {
+        let path = format!("{}.{}", name, operation);
+        let entry = value
+            .as_table()
+            .ok_or_else(|| format!("'{}' must be a table of ref_time and proof_size", path))?;
+        if !extend && !weights.contains_key(operation) {
+            return Err(format!("Unknown {} entry '{}'", name, operation));
+        }
+        let weight = weights.entry(operation.clone()).or_default();
+        for (key, value) in entry {
+            match key.as_str() {
+                "ref_time" => weight.ref_time = amount(&path, key, value)?,
+                "proof_size" => weight.proof_size = amount(&path, key, value)?,
+                _ => return Err(format!("Unknown weight '{}.{}'", path, key)),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn amount(section: &str, key: &str, value: &TomlValue) -> Result<u64, String> {
+    match value {
+        TomlValue::Integer(n) if *n >= 0 => Ok(*n as u64),
+        _ => Err(format!(
+            "'{}.{}' must be a non-negative integer",
+            section, key
+        )),
+    }
+}
+
+/// `n` with its digits grouped in threes by underscores
+fn group(n: u64) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push('_');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// The parsed TOML file at `path`
+fn read(path: &Path) -> Result<TomlTable, String> {
+    let source = fs::read_to_string(path).map_err(|e| in_file(path)(e.to_string()))?;
+    toml::parse(&source).map_err(|e| in_file(path)(e.to_string()))
+}
+
+/// Prefix an error with the file it is about
+fn in_file(path: &Path) -> impl Fn(String) -> String + '_ {
+    move |e| format!("{}: {}", path.display(), e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_table() {
+        let costs = CostTable::default();
+        assert_eq!(costs.runtime, "reference-interpreter");
+        assert_eq!(costs.ref_time_per_gas, 20_000);
+        assert_eq!(costs.gas("step"), Some(1));
+        assert!(costs.gas("binary_op") >= costs.gas("step"));
+        assert!(costs.gas("storage_write") > costs.gas("storage_read"));
+        assert_eq!(costs.weight("storage_read").unwrap().proof_size, 32);
+        assert!(costs.gas("transfer").is_some());
+        assert_eq!(costs.gas("storage_raed"), None);
+    }
+
+    #[test]
+    fn test_table_overrides() {
+        let table = toml::parse(
+            "[runtime]\nname = \"testnet\"\nspec_version = 7\nref_time_per_gas = 30_000\n\n\
+             [host]\nstorage_read = { ref_time = 6_000_001 }\n",
+        )
+        .unwrap();
+        let costs = CostTable::from_toml(&table).unwrap();
+        assert_eq!((costs.runtime.as_str(), costs.spec_version), ("testnet", 7));
+        // Rounded up, with the proof size left as it was
+        assert_eq!(costs.gas("storage_read"), Some(201));
+        assert_eq!(costs.weight("storage_read").unwrap().proof_size, 32);
+        assert_eq!(costs.gas("return"), Some(1));
+
+        for bad in [
+            "[host]\nstorage_raed = { ref_time = 1 }",
+            "[host]\nstorage_read = { ref_time = -1 }",
+            "[host]\nstorage_read = { time = 1 }",
+            "[host]\nstorage_read = 1",
+            "[runtime]\nref_time_per_gas = 0",
+            "[weights]\nx = 1",
+        ] {
+            let table = toml::parse(bad).unwrap();
+            assert!(CostTable::from_toml(&table).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_discover_table() {
+        let root = std::env::temp_dir().join(format!("bend-cost-table-{}", std::process::id()));
+        let src = root.join("src");
+        fs::create_dir_all(&src).unwrap();
+        let file = src.join("main.bend");
+        fs::write(&file, "fn main() {}\n").unwrap();
+
+        // A manifest without a [gas] table keeps the bundled table
+        fs::write(root.join(MANIFEST_FILE), "[package]\nname = \"demo\"\n").unwrap();
+        assert_eq!(CostTable::discover(&file).unwrap(), CostTable::default());
+
+        fs::write(
+            root.join("chain.toml"),
+            "[runtime]\nname = \"testnet\"\n\n[instructions]\nliteral = { ref_time = 100_000 }\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join(MANIFEST_FILE),
+            "[package]\nname = \"demo\"\n\n[gas]\ncosts = \"chain.toml\"\n\n\
+             [gas.instructions]\nbinary_op = { ref_time = 200_000 }\n",
+        )
+        .unwrap();
+        let costs = CostTable::discover(&file).unwrap();
+        assert_eq!(costs.runtime, "testnet");
+        assert_eq!(costs.gas("literal"), Some(5));
+        assert_eq!(costs.gas("binary_op"), Some(10));
+
+        fs::write(root.join("chain.toml"), "[host]\nsend = { ref_time = 1 }\n").unwrap();
+        let error = CostTable::discover(&src).unwrap_err();
+        assert!(error.contains("chain.toml"), "{}", error);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,10 +1,14 @@
-use std::collections::HashMap;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 use thiserror::Error;
 
+use super::costs::CostTable;
+use crate::compiler::codegen::risc_v::LOOP_ITERATION_GAS;
 use crate::compiler::parser::ast::*;
 use crate::compiler::parser::parser::Parser;
+use crate::runtime::metering::StorageDepositCosts;
 
 /// Error types for gas profiling
 #[derive(Error, Debug)]
@@ -34,18 +38,31 @@ pub struct GasEstimate {
     /// Breakdown of gas costs by operation type
     pub cost_breakdown: HashMap<String, u64>,
 
+    /// Estimated storage proof size in bytes, counting every host call
+    pub proof_size: u64,
+
     /// Whether the function is recursive
     pub is_recursive: bool,
 
     /// Whether the function calls external contracts
     pub has_external_calls: bool,
 
+    /// Whether the function has a loop without a static iteration count
+    pub has_unbounded_loops: bool,
+
+    /// Number of storage writes that may create a new key
+    pub storage_writes: usize,
+
+    /// Worst-case storage deposit, assuming every write creates a new
+    /// 32-byte entry (deletions are refunded at runtime)
+    pub max_storage_deposit: u128,
+
     /// Line range in the source code
     pub line_range: (usize, usize),
 }
 
 /// Result of gas profiling
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GasProfile {
     /// Gas estimates for functions
     pub estimates: Vec<GasEstimate>,
@@ -53,17 +70,54 @@ pub struct GasProfile {
     /// Path to the profiled file
     pub file_path: String,
 
+    /// Chain runtime the cost table was measured on
+    pub runtime: String,
+
+    /// Spec version of that runtime
+    pub spec_version: u64,
+
     /// Total estimated gas usage
     pub total_gas: u64,
 
+    /// Total worst-case storage deposit
+    pub total_storage_deposit: u128,
+
     /// Most expensive function
     pub most_expensive_function: Option<String>,
 }
 
+impl GasProfile {
+    /// The profile as JSON, with storage deposits as strings since they
+    /// may not fit in a JSON number
+    pub fn to_json(&self) -> Value {
+        json!({
+            "file": self.file_path,
+            "runtime": { "name": self.runtime, "spec_version": self.spec_version },
+            "total_gas": self.total_gas,
+            "total_storage_deposit": self.total_storage_deposit.to_string(),
+            "most_expensive_function": self.most_expensive_function,
+            "functions": self.estimates.iter().map(|estimate| json!({
+                "name": estimate.name,
+                "base_cost": estimate.base_cost,
+                "max_cost": estimate.max_cost,
+                "avg_cost": estimate.avg_cost,
+                "cost_breakdown": estimate.cost_breakdown.iter().collect::<BTreeMap<_, _>>(),
+                "proof_size": estimate.proof_size,
+                "is_recursive": estimate.is_recursive,
+                "has_external_calls": estimate.has_external_calls,
+                "has_unbounded_loops": estimate.has_unbounded_loops,
+                "storage_writes": estimate.storage_writes,
+                "max_storage_deposit": estimate.max_storage_deposit.to_string(),
+                "lines": [estimate.line_range.0, estimate.line_range.1],
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
 /// Gas profiler for Bend contracts
 pub struct GasProfiler {
-    /// Gas costs for various operations
-    costs: HashMap<String, u64>,
+    /// Weights of the operations the profiler counts
+    costs: CostTable,
 }
 
 impl Default for GasProfiler {
@@ -72,32 +126,31 @@ impl Default for GasProfiler {
     }
 }
 
+/// Gas and proof size charged while profiling a function
+#[derive(Default)]
+struct Tally {
+    /// Gas by operation
+    breakdown: HashMap<String, u64>,
+    /// Bytes of storage proof
+    proof_size: u64,
+}
+
 impl GasProfiler {
-    /// Create a new gas profiler
+    /// Create a gas profiler charging the bundled reference costs
     pub fn new() -> Self {
-        let mut costs = HashMap::new();
-
-        // Base costs for various operations
-        costs.insert("storage_read".to_string(), 200);
-        costs.insert("storage_write".to_string(), 5000);
-        costs.insert("storage_delete".to_string(), 500);
-        costs.insert("external_call".to_string(), 2500);
-        costs.insert("event_emit".to_string(), 375);
-        costs.insert("if_branch".to_string(), 10);
-        costs.insert("bend_iteration".to_string(), 25);
-        costs.insert("match_branch".to_string(), 15);
-        costs.insert("function_call".to_string(), 40);
-        costs.insert("binary_op".to_string(), 5);
-        costs.insert("variable_access".to_string(), 3);
-        costs.insert("literal".to_string(), 3);
-        costs.insert("tuple".to_string(), 10);
-        costs.insert("list".to_string(), 15);
-        costs.insert("constructor".to_string(), 20);
-        costs.insert("return".to_string(), 5);
+        Self::with_costs(CostTable::default())
+    }
 
+    /// Create a gas profiler charging the weights of `costs`
+    pub fn with_costs(costs: CostTable) -> Self {
         GasProfiler { costs }
     }
 
+    /// The weights the profiler charges
+    pub fn costs(&self) -> &CostTable {
+        &self.costs
+    }
+
     /// Profile a file for gas usage
     pub fn profile_file<P: AsRef<Path>>(&self, file_path: P) -> Result<GasProfile, ProfilerError> {
         let source = fs::read_to_string(&file_path)?;
@@ -136,6 +189,7 @@ impl GasProfiler {
 
         // Calculate total gas and find the most expensive function
         let total_gas = estimates.iter().map(|e| e.avg_cost).sum();
+        let total_storage_deposit = estimates.iter().map(|e| e.max_storage_deposit).sum();
 
         let most_expensive_function = estimates
             .iter()
@@ -145,20 +199,23 @@ impl GasProfiler {
         Ok(GasProfile {
             estimates,
             file_path: file_path.to_string(),
+            runtime: self.costs.runtime.clone(),
+            spec_version: self.costs.spec_version,
             total_gas,
+            total_storage_deposit,
             most_expensive_function,
         })
     }
 
-    /// Profile a function for gas usage
-    fn profile_function(&self, name: &str, body: &Block, _program: &Program) -> GasEstimate {
-        let mut cost_breakdown = HashMap::new();
+    /// Profile a function for gas usage, `program` being the one it is in
+    pub fn profile_function(&self, name: &str, body: &Block, program: &Program) -> GasEstimate {
+        let mut tally = Tally::default();
 
         // Add base cost for the function
-        cost_breakdown.insert("function_overhead".to_string(), 20);
+        self.charge("function_overhead", 1, &mut tally);
 
         // Calculate costs for the function body
-        let _body_cost = self.profile_block(body, &mut cost_breakdown);
+        let _body_cost = self.profile_block(body, &mut tally);
 
         // Determine if the function is recursive
         let is_recursive = self.is_function_recursive(name, body);
@@ -166,11 +223,19 @@ impl GasProfiler {
         // Determine if the function calls external contracts
         let has_external_calls = self.has_external_calls(body);
 
+        let has_unbounded_loops = has_unbounded_loops(body);
+
+        // Estimate the storage deposit the function may lock up
+        let storage_fields = storage_field_names(program);
+        let storage_writes = count_storage_writes(body, &storage_fields);
+        let max_storage_deposit =
+            storage_writes as u128 * StorageDepositCosts::default().deposit_for(32);
+
         // Calculate total costs
-        let base_cost = cost_breakdown.values().sum();
+        let base_cost = tally.breakdown.values().sum();
         let avg_cost = base_cost;
-        let max_cost = if is_recursive || has_external_calls {
-            // For recursive or external calling functions, max cost is harder to estimate
+        let max_cost = if is_recursive || has_external_calls || has_unbounded_loops {
+            // For recursive, external calling or unbounded looping functions, max cost is harder to estimate
             // For simplicity, we multiply the base cost by a factor
             base_cost * 5
         } else {
@@ -182,47 +247,43 @@ impl GasProfiler {
             base_cost,
             max_cost,
             avg_cost,
-            cost_breakdown,
+            cost_breakdown: tally.breakdown,
+            proof_size: tally.proof_size,
             is_recursive,
             has_external_calls,
+            has_unbounded_loops,
+            storage_writes,
+            max_storage_deposit,
             line_range: (body.location.line, body.location.line + count_lines(body)),
         }
     }
 
     /// Profile a block for gas usage
-    fn profile_block(&self, block: &Block, cost_breakdown: &mut HashMap<String, u64>) -> u64 {
+    fn profile_block(&self, block: &Block, tally: &mut Tally) -> u64 {
         let mut total_cost = 0;
 
         for statement in &block.statements {
-            total_cost += self.profile_statement(statement, cost_breakdown);
+            total_cost += self.profile_statement(statement, tally);
         }
 
         total_cost
     }
 
     /// Profile a statement for gas usage
-    fn profile_statement(
-        &self,
-        statement: &Statement,
-        cost_breakdown: &mut HashMap<String, u64>,
-    ) -> u64 {
+    fn profile_statement(&self, statement: &Statement, tally: &mut Tally) -> u64 {
         match statement {
             Statement::Return { value, .. } => {
-                let return_cost = self.get_cost("return");
-                let value_cost = self.profile_expr(value, cost_breakdown);
-
-                *cost_breakdown.entry("return".to_string()).or_insert(0) += return_cost;
+                let value_cost = self.profile_expr(value, tally);
+                let return_cost = self.charge("return", 1, tally);
 
                 return_cost + value_cost
             }
             Statement::Assignment {
                 pattern: _, value, ..
             } => {
-                let assignment_cost = 10;
-                let pattern_cost = 5; // Simplified, should depend on pattern complexity
-                let value_cost = self.profile_expr(value, cost_breakdown);
-
-                *cost_breakdown.entry("assignment".to_string()).or_insert(0) += assignment_cost;
+                let pattern_cost = self.get_cost("pattern"); // Simplified, should depend on pattern complexity
+                let value_cost = self.profile_expr(value, tally);
+                let assignment_cost = self.charge("assignment", 1, tally);
 
                 assignment_cost + pattern_cost + value_cost
             }
@@ -232,15 +293,14 @@ impl GasProfiler {
                 else_branch,
                 ..
             } => {
-                let if_cost = self.get_cost("if_branch");
-                let condition_cost = self.profile_expr(condition, cost_breakdown);
-                let then_cost = self.profile_block(then_branch, cost_breakdown);
-                let else_cost = self.profile_block(else_branch, cost_breakdown);
+                let condition_cost = self.profile_expr(condition, tally);
+                let then_cost = self.profile_block(then_branch, tally);
+                let else_cost = self.profile_block(else_branch, tally);
 
                 // Assume average case: half the time we take the then branch, half the time the else branch
                 let avg_branch_cost = (then_cost + else_cost) / 2;
 
-                *cost_breakdown.entry("if_branch".to_string()).or_insert(0) += if_cost;
+                let if_cost = self.charge("if_branch", 1, tally);
 
                 if_cost + condition_cost + avg_branch_cost
             }
@@ -251,17 +311,16 @@ impl GasProfiler {
                 else_body,
                 ..
             } => {
-                let bend_cost = self.get_cost("bend_iteration");
-                let condition_cost = self.profile_expr(condition, cost_breakdown);
+                let condition_cost = self.profile_expr(condition, tally);
 
                 let mut initial_states_cost = 0;
                 for (_, expr) in initial_states {
-                    initial_states_cost += self.profile_expr(expr, cost_breakdown);
+                    initial_states_cost += self.profile_expr(expr, tally);
                 }
 
-                let body_cost = self.profile_block(body, cost_breakdown);
+                let body_cost = self.profile_block(body, tally);
                 let else_cost = if let Some(else_body) = else_body {
-                    self.profile_block(else_body, cost_breakdown)
+                    self.profile_block(else_body, tally)
                 } else {
                     0
                 };
@@ -269,23 +328,20 @@ impl GasProfiler {
                 // Estimate number of iterations (very rough estimate)
                 let estimated_iterations = 5;
 
-                *cost_breakdown
-                    .entry("bend_iteration".to_string())
-                    .or_insert(0) += bend_cost * estimated_iterations;
+                let bend_cost = self.charge("bend_iteration", estimated_iterations, tally);
 
-                bend_cost * estimated_iterations
+                bend_cost
                     + condition_cost
                     + initial_states_cost
                     + (body_cost * estimated_iterations)
                     + else_cost
             }
             Statement::Match { value, cases, .. } => {
-                let match_cost = self.get_cost("match_branch");
-                let value_cost = self.profile_expr(value, cost_breakdown);
+                let value_cost = self.profile_expr(value, tally);
 
                 let mut cases_cost = 0;
                 for case in cases {
-                    cases_cost += self.profile_block(&case.body, cost_breakdown);
+                    cases_cost += self.profile_block(&case.body, tally);
                 }
 
                 // Assume average case: each branch has equal probability
@@ -295,100 +351,122 @@ impl GasProfiler {
                     0
                 };
 
-                *cost_breakdown
-                    .entry("match_branch".to_string())
-                    .or_insert(0) += match_cost;
+                let match_cost = self.charge("match_branch", 1, tally);
 
                 match_cost + value_cost + avg_case_cost
             }
             Statement::With {
                 monad_type, body, ..
             } => {
-                let with_cost = 15;
-                let body_cost = self.profile_block(body, cost_breakdown);
+                let body_cost = self.profile_block(body, tally);
+                let with_cost = self.charge("with_block", 1, tally);
 
                 // If this is an IO monad, it's likely to have external calls
                 let external_cost = if monad_type == "IO" {
-                    100 // Additional cost for IO operations
+                    self.charge("io_operations", 1, tally)
                 } else {
                     0
                 };
 
-                *cost_breakdown.entry("with_block".to_string()).or_insert(0) += with_cost;
-                if external_cost > 0 {
-                    *cost_breakdown
-                        .entry("io_operations".to_string())
-                        .or_insert(0) += external_cost;
-                }
-
                 with_cost + body_cost + external_cost
             }
-            Statement::Expr { expr, .. } => self.profile_expr(expr, cost_breakdown),
+            Statement::While {
+                condition,
+                body,
+                bound,
+                ..
+            } => {
+                let iterations = bound.map_or(ESTIMATED_LOOP_ITERATIONS, u64::from);
+                self.profile_loop(iterations, Some(condition), body, tally)
+            }
+            Statement::For {
+                start,
+                end,
+                body,
+                bound,
+                ..
+            } => {
+                let range_cost = self.profile_expr(start, tally) + self.profile_expr(end, tally);
+                let iterations = match (bound, range_size(start, end)) {
+                    (Some(bound), Some(size)) => size.min(u64::from(*bound)),
+                    (Some(bound), None) => u64::from(*bound),
+                    (None, Some(size)) => size,
+                    (None, None) => ESTIMATED_LOOP_ITERATIONS,
+                };
+                range_cost + self.profile_loop(iterations, None, body, tally)
+            }
+            Statement::Unchecked { body, .. } => self.profile_block(body, tally),
+            Statement::Expr { expr, .. } => self.profile_expr(expr, tally),
             // Add gas estimates for other statement types
-            _ => 10, // Default cost for unhandled statement types
+            _ => self.get_cost("statement"),
         }
     }
 
+    /// Profile a `while` or `for` loop running `iterations` times
+    ///
+    /// The condition and body are charged once per iteration, on top of the
+    /// gas the loop charges for the iteration itself.
+    fn profile_loop(
+        &self,
+        iterations: u64,
+        condition: Option<&Expr>,
+        body: &Block,
+        tally: &mut Tally,
+    ) -> u64 {
+        let mut iteration = Tally::default();
+        let condition_cost =
+            condition.map_or(0, |condition| self.profile_expr(condition, &mut iteration));
+        let body_cost = self.profile_block(body, &mut iteration);
+        for (operation, cost) in iteration.breakdown {
+            *tally.breakdown.entry(operation).or_insert(0) += cost * iterations;
+        }
+        tally.proof_size += iteration.proof_size * iterations;
+
+        // The compiled loop charges this itself, whatever the chain's weights
+        let loop_cost = LOOP_ITERATION_GAS as u64;
+        *tally
+            .breakdown
+            .entry("loop_iteration".to_string())
+            .or_insert(0) += loop_cost * iterations;
+
+        (loop_cost + condition_cost + body_cost) * iterations
+    }
+
     /// Profile an expression for gas usage
-    fn profile_expr(&self, expr: &Expr, cost_breakdown: &mut HashMap<String, u64>) -> u64 {
+    fn profile_expr(&self, expr: &Expr, tally: &mut Tally) -> u64 {
         match expr {
-            Expr::Variable { .. } => {
-                let cost = self.get_cost("variable_access");
-                *cost_breakdown
-                    .entry("variable_access".to_string())
-                    .or_insert(0) += cost;
-                cost
-            }
-            Expr::Literal { .. } => {
-                let cost = self.get_cost("literal");
-                *cost_breakdown.entry("literal".to_string()).or_insert(0) += cost;
-                cost
-            }
+            Expr::Variable { .. } => self.charge("variable_access", 1, tally),
+            Expr::Literal { .. } => self.charge("literal", 1, tally),
             Expr::FunctionCall { function, args, .. } => {
                 let call_cost = self.get_cost("function_call");
-                let function_cost = self.profile_expr(function, cost_breakdown);
+                let function_cost = self.profile_expr(function, tally);
 
                 let mut args_cost = 0;
                 for arg in args {
-                    args_cost += self.profile_expr(arg, cost_breakdown);
+                    args_cost += self.profile_expr(arg, tally);
                 }
 
                 // Check for special functions with known gas costs
-                let special_cost = if let Expr::Variable { name, .. } = &**function {
-                    if name.starts_with("IO/storage_") {
-                        if name == "IO/storage_read" {
-                            self.get_cost("storage_read")
-                        } else if name == "IO/storage_write" {
-                            self.get_cost("storage_write")
-                        } else if name == "IO/storage_delete" {
-                            self.get_cost("storage_delete")
-                        } else {
-                            0
-                        }
-                    } else if name.starts_with("IO/call") || name.starts_with("IO/static_call") {
-                        self.get_cost("external_call")
-                    } else if name.starts_with("IO/emit_event") {
-                        self.get_cost("event_emit")
-                    } else {
+                let host_weight = match &**function {
+                    Expr::Variable { name, .. } => host_call(name)
+                        .and_then(|call| self.costs.weight(call))
+                        .map(|weight| (name, weight)),
+                    _ => None,
+                };
+
+                let special_cost = match host_weight {
+                    Some((name, weight)) => {
+                        let cost = self.costs.gas_for(weight.ref_time);
+                        *tally.breakdown.entry(name.to_string()).or_insert(0) += cost;
+                        tally.proof_size += weight.proof_size;
+                        cost
+                    }
+                    None => {
+                        self.charge("function_call", 1, tally);
                         0
                     }
-                } else {
-                    0
                 };
 
-                if special_cost > 0 {
-                    let operation = if let Expr::Variable { name, .. } = &**function {
-                        name.to_string()
-                    } else {
-                        "special_operation".to_string()
-                    };
-                    *cost_breakdown.entry(operation).or_insert(0) += special_cost;
-                } else {
-                    *cost_breakdown
-                        .entry("function_call".to_string())
-                        .or_insert(0) += call_cost;
-                }
-
                 call_cost + function_cost + args_cost + special_cost
             }
             Expr::BinaryOp {
@@ -397,55 +475,56 @@ impl GasProfiler {
                 right,
                 ..
             } => {
-                let op_cost = self.get_cost("binary_op");
-                let left_cost = self.profile_expr(left, cost_breakdown);
-                let right_cost = self.profile_expr(right, cost_breakdown);
-
-                *cost_breakdown.entry("binary_op".to_string()).or_insert(0) += op_cost;
+                let left_cost = self.profile_expr(left, tally);
+                let right_cost = self.profile_expr(right, tally);
+                let op_cost = self.charge("binary_op", 1, tally);
 
                 op_cost + left_cost + right_cost
             }
             Expr::Tuple { elements, .. } => {
-                let tuple_cost = self.get_cost("tuple");
-
                 let mut elements_cost = 0;
                 for element in elements {
-                    elements_cost += self.profile_expr(element, cost_breakdown);
+                    elements_cost += self.profile_expr(element, tally);
                 }
 
-                *cost_breakdown.entry("tuple".to_string()).or_insert(0) += tuple_cost;
+                let tuple_cost = self.charge("tuple", 1, tally);
 
                 tuple_cost + elements_cost
             }
             Expr::List { elements, .. } => {
-                let list_cost = self.get_cost("list");
-
                 let mut elements_cost = 0;
                 for element in elements {
-                    elements_cost += self.profile_expr(element, cost_breakdown);
+                    elements_cost += self.profile_expr(element, tally);
                 }
 
-                *cost_breakdown.entry("list".to_string()).or_insert(0) += list_cost;
+                let list_cost = self.charge("list", 1, tally);
 
                 list_cost + elements_cost
             }
             Expr::Constructor { args, .. } => {
-                let constructor_cost = self.get_cost("constructor");
-
                 let mut args_cost = 0;
                 for arg in args {
-                    args_cost += self.profile_expr(arg, cost_breakdown);
+                    args_cost += self.profile_expr(arg, tally);
                 }
 
-                *cost_breakdown.entry("constructor".to_string()).or_insert(0) += constructor_cost;
+                let constructor_cost = self.charge("constructor", 1, tally);
 
                 constructor_cost + args_cost
             }
             // Add gas estimates for other expression types
-            _ => 5, // Default cost for unhandled expression types
+            _ => self.get_cost("expression"),
         }
     }
 
+    /// Charge `operation` `times` times, returning the gas charged
+    fn charge(&self, operation: &str, times: u64, tally: &mut Tally) -> u64 {
+        let weight = self.costs.weight(operation).unwrap_or_default();
+        let cost = self.costs.gas_for(weight.ref_time) * times;
+        *tally.breakdown.entry(operation.to_string()).or_insert(0) += cost;
+        tally.proof_size += weight.proof_size * times;
+        cost
+    }
+
     /// Check if a function is recursive
     fn is_function_recursive(&self, name: &str, body: &Block) -> bool {
         // Simplified check: look for calls to the function itself
@@ -456,28 +535,24 @@ impl GasProfiler {
     fn contains_call_to(&self, block: &Block, function_name: &str) -> bool {
         for statement in &block.statements {
             match statement {
-                Statement::Expr { expr, .. } => {
-                    if self.expr_calls_function(expr, function_name) {
-                        return true;
-                    }
+                Statement::Expr { expr, .. } if self.expr_calls_function(expr, function_name) => {
+                    return true;
                 }
-                Statement::Return { value, .. } => {
-                    if self.expr_calls_function(value, function_name) {
-                        return true;
-                    }
+                Statement::Return { value, .. }
+                    if self.expr_calls_function(value, function_name) =>
+                {
+                    return true;
                 }
                 Statement::If {
                     condition,
                     then_branch,
                     else_branch,
                     ..
-                } => {
-                    if self.expr_calls_function(condition, function_name)
-                        || self.contains_call_to(then_branch, function_name)
-                        || self.contains_call_to(else_branch, function_name)
-                    {
-                        return true;
-                    }
+                } if (self.expr_calls_function(condition, function_name)
+                    || self.contains_call_to(then_branch, function_name)
+                    || self.contains_call_to(else_branch, function_name)) =>
+                {
+                    return true;
                 }
                 Statement::Match { value, cases, .. } => {
                     if self.expr_calls_function(value, function_name) {
@@ -518,10 +593,25 @@ impl GasProfiler {
                         return true;
                     }
                 }
-                Statement::With { body, .. } => {
-                    if self.contains_call_to(body, function_name) {
-                        return true;
-                    }
+                Statement::While {
+                    condition, body, ..
+                } if (self.expr_calls_function(condition, function_name)
+                    || self.contains_call_to(body, function_name)) =>
+                {
+                    return true;
+                }
+                Statement::For {
+                    start, end, body, ..
+                } if (self.expr_calls_function(start, function_name)
+                    || self.expr_calls_function(end, function_name)
+                    || self.contains_call_to(body, function_name)) =>
+                {
+                    return true;
+                }
+                Statement::With { body, .. } | Statement::Unchecked { body, .. }
+                    if self.contains_call_to(body, function_name) =>
+                {
+                    return true;
                 }
                 // Check other statement types
                 _ => {}
@@ -576,28 +666,22 @@ impl GasProfiler {
     fn has_external_calls(&self, block: &Block) -> bool {
         for statement in &block.statements {
             match statement {
-                Statement::Expr { expr, .. } => {
-                    if self.expr_has_external_call(expr) {
-                        return true;
-                    }
+                Statement::Expr { expr, .. } if self.expr_has_external_call(expr) => {
+                    return true;
                 }
-                Statement::Return { value, .. } => {
-                    if self.expr_has_external_call(value) {
-                        return true;
-                    }
+                Statement::Return { value, .. } if self.expr_has_external_call(value) => {
+                    return true;
                 }
                 Statement::If {
                     condition,
                     then_branch,
                     else_branch,
                     ..
-                } => {
-                    if self.expr_has_external_call(condition)
-                        || self.has_external_calls(then_branch)
-                        || self.has_external_calls(else_branch)
-                    {
-                        return true;
-                    }
+                } if (self.expr_has_external_call(condition)
+                    || self.has_external_calls(then_branch)
+                    || self.has_external_calls(else_branch)) =>
+                {
+                    return true;
                 }
                 Statement::Match { value, cases, .. } => {
                     if self.expr_has_external_call(value) {
@@ -638,10 +722,23 @@ impl GasProfiler {
                         return true;
                     }
                 }
-                Statement::With { body, .. } => {
-                    if self.has_external_calls(body) {
-                        return true;
-                    }
+                Statement::While {
+                    condition, body, ..
+                } if (self.expr_has_external_call(condition) || self.has_external_calls(body)) => {
+                    return true;
+                }
+                Statement::For {
+                    start, end, body, ..
+                } if (self.expr_has_external_call(start)
+                    || self.expr_has_external_call(end)
+                    || self.has_external_calls(body)) =>
+                {
+                    return true;
+                }
+                Statement::With { body, .. } | Statement::Unchecked { body, .. }
+                    if self.has_external_calls(body) =>
+                {
+                    return true;
                 }
                 // Check other statement types
                 _ => {}
@@ -694,9 +791,16 @@ impl GasProfiler {
         }
     }
 
-    /// Get cost for an operation
+    /// Get cost for an operation, or for an unclassified expression when
+    /// the cost table does not know it
     pub fn get_cost(&self, operation: &str) -> u64 {
-        *self.costs.get(operation).unwrap_or(&5)
+        if operation == "loop_iteration" {
+            return LOOP_ITERATION_GAS as u64;
+        }
+        self.costs
+            .gas(operation)
+            .or_else(|| self.costs.gas("expression"))
+            .unwrap_or(0)
     }
 }
 
@@ -720,7 +824,10 @@ fn count_lines(block: &Block) -> usize {
             Statement::Bend {
                 body, else_body, ..
             } => 2 + count_lines(body) + else_body.as_ref().map(count_lines).unwrap_or(0),
-            Statement::With { body, .. } => 1 + count_lines(body),
+            Statement::With { body, .. }
+            | Statement::Unchecked { body, .. }
+            | Statement::While { body, .. }
+            | Statement::For { body, .. } => 1 + count_lines(body),
             _ => 1,
         };
     }
@@ -728,9 +835,130 @@ fn count_lines(block: &Block) -> usize {
     line_count
 }
 
+/// The cost table entry of the host call a function name makes, if any
+fn host_call(name: &str) -> Option<&'static str> {
+    match name {
+        "IO/storage_read" => Some("storage_read"),
+        "IO/storage_write" => Some("storage_write"),
+        "IO/storage_delete" => Some("storage_delete"),
+        _ if name.starts_with("IO/call") || name.starts_with("IO/static_call") => {
+            Some("external_call")
+        }
+        _ if name.starts_with("IO/emit_event") => Some("event_emit"),
+        _ => None,
+    }
+}
+
+/// Iterations assumed for a loop without a bound or a constant range
+const ESTIMATED_LOOP_ITERATIONS: u64 = 5;
+
+/// Number of iterations of `range(start, end)` when both ends are literals
+fn range_size(start: &Expr, end: &Expr) -> Option<u64> {
+    let literal = |expr: &Expr| match expr {
+        Expr::Literal {
+            kind: LiteralKind::Uint(value),
+            ..
+        } => Some(u64::from(*value)),
+        _ => None,
+    };
+    Some(literal(end)?.saturating_sub(literal(start)?))
+}
+
+/// Whether a block has a loop whose iteration count is not known statically
+fn has_unbounded_loops(block: &Block) -> bool {
+    block.statements.iter().any(|statement| match statement {
+        Statement::While { bound, body, .. } => bound.is_none() || has_unbounded_loops(body),
+        Statement::For {
+            start,
+            end,
+            bound,
+            body,
+            ..
+        } => (bound.is_none() && range_size(start, end).is_none()) || has_unbounded_loops(body),
+        Statement::If {
+            then_branch,
+            else_branch,
+            ..
+        } => has_unbounded_loops(then_branch) || has_unbounded_loops(else_branch),
+        Statement::Match { cases, .. } => cases.iter().any(|case| has_unbounded_loops(&case.body)),
+        Statement::With { body, .. } | Statement::Unchecked { body, .. } => {
+            has_unbounded_loops(body)
+        }
+        _ => false,
+    })
+}
+
+/// Names of the fields declared in `storage { ... }` blocks
+fn storage_field_names(program: &Program) -> Vec<&str> {
+    program
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            Definition::StorageDef { fields, .. } => Some(fields),
+            _ => None,
+        })
+        .flatten()
+        .map(|field| field.name.as_str())
+        .collect()
+}
+
+/// Count statements in a block that write to storage
+fn count_storage_writes(block: &Block, storage_fields: &[&str]) -> usize {
+    block
+        .statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::Assignment {
+                pattern: Pattern::Variable { name, .. },
+                ..
+            } => usize::from(storage_fields.contains(&name.as_str())),
+            Statement::Expr {
+                expr: Expr::FunctionCall { function, .. },
+                ..
+            } => {
+                let writes_collection = function.as_method_target().is_some_and(|(target, method)| {
+                    storage_fields.contains(&target.as_str()) && matches!(method, "insert" | "push" | "set")
+                });
+                let writes_raw =
+                    matches!(&**function, Expr::Variable { name, .. } if name == "IO/storage_write");
+                usize::from(writes_collection || writes_raw)
+            }
+            Statement::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                count_storage_writes(then_branch, storage_fields)
+                    + count_storage_writes(else_branch, storage_fields)
+            }
+            Statement::Match { cases, .. } => cases
+                .iter()
+                .map(|case| count_storage_writes(&case.body, storage_fields))
+                .sum(),
+            Statement::Bend {
+                body, else_body, ..
+            } => {
+                count_storage_writes(body, storage_fields)
+                    + else_body
+                        .as_ref()
+                        .map_or(0, |b| count_storage_writes(b, storage_fields))
+            }
+            Statement::With { body, .. }
+            | Statement::Unchecked { body, .. }
+            | Statement::While { body, .. }
+            | Statement::For { body, .. } => count_storage_writes(body, storage_fields),
+            _ => 0,
+        })
+        .sum()
+}
+
 /// Print a gas profile
 pub fn print_profile(profile: &GasProfile) {
     println!("Gas profile report for {}", profile.file_path);
+    println!(
+        "Costs of runtime {} (spec version {})",
+        profile.runtime, profile.spec_version
+    );
     println!("-------------------------------------");
 
     if profile.estimates.is_empty() {
@@ -749,6 +977,15 @@ pub fn print_profile(profile: &GasProfile) {
             println!("   Base gas: {}", estimate.base_cost);
             println!("   Max gas: {}", estimate.max_cost);
             println!("   Avg gas: {}", estimate.avg_cost);
+            if estimate.proof_size > 0 {
+                println!("   Proof size: {} bytes", estimate.proof_size);
+            }
+            if estimate.storage_writes > 0 {
+                println!(
+                    "   Storage deposit (max): {} ({} writes)",
+                    estimate.max_storage_deposit, estimate.storage_writes
+                );
+            }
 
             if estimate.is_recursive {
                 println!("   ⚠️ Recursive function (gas estimate may be inaccurate)");
@@ -758,6 +995,10 @@ pub fn print_profile(profile: &GasProfile) {
                 println!("   ⚠️ Contains external calls (gas depends on called contracts)");
             }
 
+            if estimate.has_unbounded_loops {
+                println!("   ⚠️ Contains loops without a bound (gas depends on the iterations)");
+            }
+
             println!("   Cost breakdown:");
             for (operation, cost) in &estimate.cost_breakdown {
                 println!("     - {}: {} gas", operation, cost);
@@ -767,6 +1008,12 @@ pub fn print_profile(profile: &GasProfile) {
         }
 
         println!("Total estimated gas: {}", profile.total_gas);
+        if profile.total_storage_deposit > 0 {
+            println!(
+                "Total storage deposit (max): {}",
+                profile.total_storage_deposit
+            );
+        }
 
         if let Some(most_expensive) = &profile.most_expensive_function {
             println!("Most expensive function: {}", most_expensive);
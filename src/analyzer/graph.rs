@@ -0,0 +1,853 @@
+//! Call and module graphs of a contract, for `bend-pvm graph`
+//!
+//! The call graph has a node for every function of the modules the
+//! contract is written in, grouped by module, and an edge from each
+//! function to those it calls. A node is annotated with the gas the
+//! profiler estimates for the function, the storage fields it reads and
+//! writes, and whether it calls other contracts. Calls into the standard
+//! library are left out.
+//!
+//! The module graph has a node for every module and an edge from each
+//! module to those it imports. Standard library modules only appear when
+//! imported explicitly, not through the prelude, and are drawn dashed.
+//!
+//! Both are drawn as Graphviz DOT or as a Mermaid flowchart.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::gas_profiler::GasProfiler;
+use crate::compiler::analyzer::type_checker::contract_call_leading_args;
+use crate::compiler::module::{import_paths, Module};
+use crate::compiler::parser::ast::*;
+use crate::security::security_scanner::children;
+use crate::stdlib::modules;
+
+/// Methods of storage collections changing what they hold
+const STORAGE_WRITE_METHODS: [&str; 6] = ["insert", "remove", "set", "push", "pop", "clear"];
+
+/// How a graph is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    #[default]
+    Dot,
+    /// A Mermaid flowchart
+    Mermaid,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            _ => Err(format!(
+                "Unknown graph format '{}', expected dot or mermaid",
+                s
+            )),
+        }
+    }
+}
+
+/// Which graph of a contract is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GraphKind {
+    /// Functions and the functions they call
+    #[default]
+    Calls,
+    /// Modules and the modules they import
+    Modules,
+}
+
+impl FromStr for GraphKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "calls" => Ok(GraphKind::Calls),
+            "modules" => Ok(GraphKind::Modules),
+            _ => Err(format!(
+                "Unknown graph kind '{}', expected calls or modules",
+                s
+            )),
+        }
+    }
+}
+
+/// A node of a graph
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub name: String,
+    /// The module a function is in
+    pub group: Option<String>,
+    /// Lines drawn under the name
+    pub notes: Vec<String>,
+    /// Drawn dashed, for the standard library
+    pub dashed: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    /// Pairs of node indices
+    pub edges: BTreeSet<(usize, usize)>,
+}
+
+impl Graph {
+    /// The call graph of the functions of `module` and the modules it
+    /// imports, with gas estimated by `profiler`
+    pub fn calls(module: &Module, profiler: &GasProfiler) -> Self {
+        let program = module.program_without_std();
+        let storage: HashSet<&str> = program
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                Definition::StorageDef { fields, .. } => Some(fields),
+                _ => None,
+            })
+            .flatten()
+            .map(|field| field.name.as_str())
+            .collect();
+
+        let mut graph = Graph::default();
+        let mut bodies = Vec::new();
+        for module in user_modules(module) {
+            for definition in &module.ast.definitions {
+                if let Definition::FunctionDef {
+                    name, params, body, ..
+                } = definition
+                {
+                    graph.nodes.push(Node {
+                        name: name.to_string(),
+                        group: Some(module.name.to_string()),
+                        notes: Vec::new(),
+                        dashed: false,
+                    });
+                    bodies.push((params, body));
+                }
+            }
+        }
+        let functions: HashMap<String, usize> = graph
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.name.clone(), i))
+            .collect();
+
+        for (caller, (params, body)) in bodies.into_iter().enumerate() {
+            let mut access = Access::new(&storage, &functions);
+            access
+                .locals
+                .extend(params.iter().map(|param| param.name.to_string()));
+            access.block(body);
+            graph
+                .edges
+                .extend(access.callees.into_iter().map(|callee| (caller, callee)));
+
+            let node = &mut graph.nodes[caller];
+            let estimate = profiler.profile_function(&node.name, body, &program);
+            node.notes
+                .push(match estimate.max_cost > estimate.avg_cost {
+                    true => format!("gas {} (up to {})", estimate.avg_cost, estimate.max_cost),
+                    false => format!("gas {}", estimate.avg_cost),
+                });
+            if !access.reads.is_empty() {
+                node.notes.push(format!("reads {}", join(&access.reads)));
+            }
+            if !access.writes.is_empty() {
+                node.notes.push(format!("writes {}", join(&access.writes)));
+            }
+            if access.calls_contracts {
+                node.notes.push("calls other contracts".to_string());
+            }
+        }
+        graph
+    }
+
+    /// The graph of `module` and the modules it imports
+    pub fn modules(module: &Module) -> Self {
+        let mut graph = Graph::default();
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        let mut index = |graph: &mut Graph, module: &Module| {
+            *indices.entry(module.name.to_string()).or_insert_with(|| {
+                graph.nodes.push(Node {
+                    name: module.name.to_string(),
+                    group: None,
+                    notes: Vec::new(),
+                    dashed: modules::embedded(&module.path).is_some(),
+                });
+                graph.nodes.len() - 1
+            })
+        };
+
+        for module in user_modules(module) {
+            let importer = index(&mut graph, module);
+            let explicit: Vec<&str> = module.ast.imports.iter().flat_map(import_paths).collect();
+            let mut imported: Vec<&Arc<Module>> = module
+                .imports
+                .values()
+                .filter(|imported| {
+                    modules::embedded(&imported.path).is_none()
+                        || explicit
+                            .iter()
+                            .any(|path| modules::module_path(path).as_ref() == Some(&imported.path))
+                })
+                .collect();
+            imported.sort_by_key(|a| a.name);
+            for imported in imported {
+                let imported = index(&mut graph, imported);
+                graph.edges.insert((importer, imported));
+            }
+        }
+        graph
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Mermaid => self.to_mermaid(),
+        }
+    }
+
+    /// The graph in Graphviz DOT
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph {\n    node [shape=box];\n");
+        let node = |out: &mut String, indent: &str, i: usize, node: &Node| {
+            let label: Vec<String> = std::iter::once(&node.name)
+                .chain(&node.notes)
+                .map(|line| line.replace('\\', "\\\\").replace('"', "\\\""))
+                .collect();
+            let style = if node.dashed { ", style=dashed" } else { "" };
+            let _ = writeln!(
+                out,
+                "{}n{} [label=\"{}\"{}];",
+                indent,
+                i,
+                label.join("\\n"),
+                style
+            );
+        };
+        for (group, members) in self.groups() {
+            match group {
+                Some(group) => {
+                    let _ = writeln!(
+                        out,
+                        "    subgraph \"cluster_{}\" {{\n        label=\"{}\";",
+                        group, group
+                    );
+                    for i in members {
+                        node(&mut out, "        ", i, &self.nodes[i]);
+                    }
+                    out.push_str("    }\n");
+                }
+                None => {
+                    for i in members {
+                        node(&mut out, "    ", i, &self.nodes[i]);
+                    }
+                }
+            }
+        }
+        for (from, to) in &self.edges {
+            let _ = writeln!(out, "    n{} -> n{};", from, to);
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// The graph as a Mermaid flowchart
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart TD\n");
+        let node = |out: &mut String, indent: &str, i: usize, node: &Node| {
+            let label: Vec<String> = std::iter::once(&node.name)
+                .chain(&node.notes)
+                .map(|line| line.replace('"', "#quot;"))
+                .collect();
+            let _ = writeln!(out, "{}n{}[\"{}\"]", indent, i, label.join("<br/>"));
+        };
+        for (group, members) in self.groups() {
+            match group {
+                Some(group) => {
+                    let _ = writeln!(out, "    subgraph {}", group);
+                    for i in members {
+                        node(&mut out, "        ", i, &self.nodes[i]);
+                    }
+                    out.push_str("    end\n");
+                }
+                None => {
+                    for i in members {
+                        node(&mut out, "    ", i, &self.nodes[i]);
+                    }
+                }
+            }
+        }
+        for (from, to) in &self.edges {
+            let _ = writeln!(out, "    n{} --> n{}", from, to);
+        }
+        let dashed: Vec<String> = (0..self.nodes.len())
+            .filter(|&i| self.nodes[i].dashed)
+            .map(|i| format!("n{}", i))
+            .collect();
+        if !dashed.is_empty() {
+            out.push_str("    classDef dashed stroke-dasharray: 5 5\n");
+            let _ = writeln!(out, "    class {} dashed", dashed.join(","));
+        }
+        out
+    }
+
+    /// Indices of the nodes of each group, in the order the groups first
+    /// appear
+    fn groups(&self) -> Vec<(Option<&str>, Vec<usize>)> {
+        let mut groups: Vec<(Option<&str>, Vec<usize>)> = Vec::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            let group = node.group.as_deref();
+            match groups.iter_mut().find(|(name, _)| *name == group) {
+                Some((_, members)) => members.push(i),
+                None => groups.push((group, vec![i])),
+            }
+        }
+        groups
+    }
+}
+
+/// `module` and the modules it imports, directly or indirectly, outside the
+/// standard library
+fn user_modules(module: &Module) -> Vec<&Module> {
+    let mut seen = HashSet::new();
+    let mut found = Vec::new();
+    let mut pending = vec![module];
+    while let Some(module) = pending.pop() {
+        if modules::embedded(&module.path).is_some() || !seen.insert(&module.name) {
+            continue;
+        }
+        found.push(module);
+        let mut imported: Vec<&Module> = module.imports.values().map(|m| &**m).collect();
+        imported.sort_by_key(|m| std::cmp::Reverse(m.name));
+        pending.extend(imported);
+    }
+    found
+}
+
+fn join(names: &BTreeSet<String>) -> String {
+    names.iter().cloned().collect::<Vec<_>>().join(", ")
+}
+
+/// The storage a function body touches and the functions it calls
+struct Access<'a> {
+    storage: &'a HashSet<&'a str>,
+    /// Node of each function, by name
+    functions: &'a HashMap<String, usize>,
+    /// Parameters and locals in scope, hiding fields and functions
+    locals: Vec<String>,
+    reads: BTreeSet<String>,
+    writes: BTreeSet<String>,
+    callees: BTreeSet<usize>,
+    /// Whether it calls, delegates to or instantiates another contract
+    calls_contracts: bool,
+}
+
+impl<'a> Access<'a> {
+    fn new(storage: &'a HashSet<&'a str>, functions: &'a HashMap<String, usize>) -> Self {
+        Access {
+            storage,
+            functions,
+            locals: Vec::new(),
+            reads: BTreeSet::new(),
+            writes: BTreeSet::new(),
+            callees: BTreeSet::new(),
+            calls_contracts: false,
+        }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.locals.iter().any(|local| local == name)
+    }
+
+    /// Whether `name` is a storage field not hidden by a local
+    fn is_field(&self, name: &str) -> bool {
+        self.storage.contains(name) && !self.is_local(name)
+    }
+
+    /// Visit `f` with what `names` binds in scope
+    fn scoped<'n>(&mut self, names: impl IntoIterator<Item = &'n str>, f: impl FnOnce(&mut Self)) {
+        let depth = self.locals.len();
+        self.locals.extend(names.into_iter().map(str::to_string));
+        f(self);
+        self.locals.truncate(depth);
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scoped([], |access| {
+            for statement in &block.statements {
+                access.statement(statement);
+            }
+        });
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Assignment { pattern, value, .. } => {
+                self.expr(value);
+                self.pattern(pattern);
+            }
+            Statement::Use { name, value, .. } => {
+                self.expr(value);
+                self.locals.push(name.to_string());
+            }
+            Statement::InPlaceOp { target, value, .. } => {
+                self.expr(value);
+                if self.is_field(target) {
+                    self.reads.insert(target.to_string());
+                    self.writes.insert(target.to_string());
+                }
+            }
+            Statement::Return { value, .. } | Statement::Open { value, .. } => self.expr(value),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expr(condition);
+                self.block(then_branch);
+                self.block(else_branch);
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.expr(condition);
+                self.block(body);
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                ..
+            } => {
+                self.expr(start);
+                self.expr(end);
+                self.scoped([variable.as_str()], |access| access.block(body));
+            }
+            Statement::Switch { value, cases, .. } => {
+                self.expr(value);
+                for case in cases {
+                    self.block(&case.body);
+                }
+            }
+            Statement::Match { value, cases, .. } | Statement::Fold { value, cases, .. } => {
+                self.expr(value);
+                for case in cases {
+                    let mut names = Vec::new();
+                    bound_names(&case.pattern, &mut names);
+                    self.scoped(names, |access| access.block(&case.body));
+                }
+            }
+            Statement::Bend {
+                initial_states,
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                for (_, value) in initial_states {
+                    self.expr(value);
+                }
+                let names = initial_states.iter().map(|(name, _)| name.as_str());
+                self.scoped(names, |access| {
+                    access.expr(condition);
+                    access.block(body);
+                    if let Some(else_body) = else_body {
+                        access.block(else_body);
+                    }
+                });
+            }
+            Statement::With { body, .. } | Statement::Unchecked { body, .. } => self.block(body),
+            Statement::LocalDef { function_def, .. } => {
+                if let Definition::FunctionDef {
+                    name, params, body, ..
+                } = function_def.as_ref()
+                {
+                    self.locals.push(name.to_string());
+                    let params = params.iter().map(|param| param.name.as_str());
+                    self.scoped(params, |access| access.block(body));
+                }
+            }
+            Statement::Expr { expr, .. } => self.expr(expr),
+            Statement::TryCatch {
+                try_block,
+                catch_blocks,
+                ..
+            } => {
+                self.block(try_block);
+                for catch in catch_blocks {
+                    let names = catch.error_var.as_deref();
+                    self.scoped(names, |access| access.block(&catch.body));
+                }
+            }
+            Statement::Emit { args, .. } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Statement::Revert {
+                condition, reason, ..
+            } => {
+                for expr in condition.iter().chain(reason) {
+                    self.expr(expr);
+                }
+            }
+        }
+    }
+
+    /// The target of an assignment, whose storage field is written, or
+    /// which binds new locals
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Variable { name, .. } => {
+                if self.is_field(name) {
+                    self.writes.insert(name.to_string());
+                } else if !self.is_local(name) {
+                    self.locals.push(name.to_string());
+                }
+            }
+            Pattern::Tuple { elements, .. } | Pattern::TupleConstructor { args: elements, .. } => {
+                for element in elements {
+                    self.pattern(element);
+                }
+            }
+            Pattern::Constructor { fields, .. } => {
+                for field in fields.values() {
+                    self.pattern(field);
+                }
+            }
+            Pattern::Member { parent, .. } => {
+                let mut root = parent.as_ref();
+                while let Pattern::Member { parent, .. } = root {
+                    root = parent;
+                }
+                if let Pattern::Variable { name, .. } = root {
+                    if self.is_field(name) {
+                        self.writes.insert(name.to_string());
+                    }
+                }
+            }
+            Pattern::MapAccess { map, key, .. } => {
+                self.expr(key);
+                if let Expr::Variable { name, .. } = map.as_ref() {
+                    if self.is_field(name) {
+                        self.writes.insert(name.to_string());
+                    }
+                }
+            }
+            Pattern::Literal { .. } | Pattern::Wildcard { .. } => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Variable { name, .. } => {
+                let field = name.split_once('.').map_or(&**name, |(field, _)| field);
+                if self.is_local(field) {
+                    return;
+                }
+                // A field shadows the getter of the same name
+                if self.storage.contains(field) {
+                    self.reads.insert(field.to_string());
+                } else if let Some(&callee) = self.functions.get(&**name) {
+                    self.callees.insert(callee);
+                }
+                return;
+            }
+            Expr::FunctionCall { function, args, .. } => {
+                if let Some((field, method)) = function.as_method_target() {
+                    if self.is_field(&field) {
+                        match STORAGE_WRITE_METHODS.contains(&method) {
+                            true => self.writes.insert(field.to_string()),
+                            false => self.reads.insert(field.to_string()),
+                        };
+                        for arg in args {
+                            self.expr(arg);
+                        }
+                        return;
+                    }
+                }
+                // The builtins, unless a function or local of the same name
+                // hides them
+                if let Expr::Variable { name, .. } = function.as_ref() {
+                    if contract_call_leading_args(name).is_some()
+                        && !self.functions.contains_key(&**name)
+                        && !self.is_local(name)
+                    {
+                        self.calls_contracts = true;
+                    }
+                }
+            }
+            Expr::Block { block, .. } => {
+                self.block(block);
+                return;
+            }
+            Expr::Lambda { params, body, .. } => {
+                let params = params.iter().map(|param| param.name.as_str());
+                self.scoped(params, |access| access.expr(body));
+                return;
+            }
+            Expr::UnsccopedLambda { params, body, .. } => {
+                let params = params.iter().map(String::as_str);
+                self.scoped(params, |access| access.expr(body));
+                return;
+            }
+            Expr::ListComprehension {
+                element,
+                variable,
+                iterable,
+                condition,
+                ..
+            } => {
+                self.expr(iterable);
+                self.scoped([variable.as_str()], |access| {
+                    access.expr(element);
+                    condition
+                        .iter()
+                        .for_each(|condition| access.expr(condition));
+                });
+                return;
+            }
+            Expr::MapComprehension {
+                key,
+                value,
+                variable,
+                iterable,
+                condition,
+                ..
+            } => {
+                self.expr(iterable);
+                self.scoped([variable.as_str()], |access| {
+                    access.expr(key);
+                    access.expr(value);
+                    condition
+                        .iter()
+                        .for_each(|condition| access.expr(condition));
+                });
+                return;
+            }
+            _ => {}
+        }
+        for child in children(expr) {
+            self.expr(child);
+        }
+    }
+}
+
+/// The variables a pattern binds
+fn bound_names<'a>(pattern: &'a Pattern, names: &mut Vec<&'a str>) {
+    match pattern {
+        Pattern::Variable { name, .. } => names.push(name),
+        Pattern::Tuple { elements, .. } | Pattern::TupleConstructor { args: elements, .. } => {
+            for element in elements {
+                bound_names(element, names);
+            }
+        }
+        Pattern::Constructor { fields, .. } => {
+            for field in fields.values() {
+                bound_names(field, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::module::ModuleSystem;
+    use std::fs;
+
+    const TOKEN: &str = r#"from ledger import Ledger/credit;
+from std/Math import *;
+
+contract Token {
+    pub let supply: u24;
+    let balances: StorageMap<u24, u24>;
+
+    pub fn mint(to: u24, amount: u24) -> u24 {
+        supply = supply + amount;
+        return Ledger/credit(to, amount);
+    }
+
+    pub fn burn(amount: u24) -> u24 {
+        supply = supply - amount;
+        return mint(0, 0);
+    }
+}
+"#;
+
+    const LEDGER: &str = r#"storage {
+    credits: StorageMap<u24, u24>,
+}
+
+pub fn Ledger/credit(to: u24, amount: u24) -> u24 {
+    credits.insert(to, amount);
+    return credits.get(to);
+}
+"#;
+
+    fn load(name: &str) -> (std::path::PathBuf, Arc<Module>) {
+        let root = std::env::temp_dir().join(format!("bend-graph-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("token.bend"), TOKEN).unwrap();
+        fs::write(root.join("ledger.bend"), LEDGER).unwrap();
+        let mut modules = ModuleSystem::new();
+        modules.add_search_path(&root);
+        let module = modules.load_module(root.join("token.bend")).unwrap();
+        (root, module)
+    }
+
+    fn node<'a>(graph: &'a Graph, name: &str) -> (usize, &'a Node) {
+        graph
+            .nodes
+            .iter()
+            .enumerate()
+            .find(|(_, node)| node.name == name)
+            .unwrap_or_else(|| panic!("No node {}", name))
+    }
+
+    #[test]
+    fn test_call_graph() {
+        let (root, module) = load("calls");
+        let graph = Graph::calls(&module, &GasProfiler::new());
+
+        let (mint, node_mint) = node(&graph, "mint");
+        let (burn, node_burn) = node(&graph, "burn");
+        let (credit, node_credit) = node(&graph, "Ledger/credit");
+        let (supply, _) = node(&graph, "supply");
+        assert!(graph.edges.contains(&(mint, credit)));
+        assert!(graph.edges.contains(&(burn, mint)));
+        assert!(!graph.edges.iter().any(|&(from, _)| from == supply));
+        assert!(!graph
+            .nodes
+            .iter()
+            .any(|node| node.name.starts_with("Math/")));
+
+        assert_eq!(node_mint.group.as_deref(), Some("token"));
+        assert_eq!(node_credit.group.as_deref(), Some("ledger"));
+        assert!(node_mint.notes[0].starts_with("gas "));
+        assert!(node_mint.notes.contains(&"reads supply".to_string()));
+        assert!(node_mint.notes.contains(&"writes supply".to_string()));
+        assert!(node_burn.notes.contains(&"writes supply".to_string()));
+        assert!(node_credit.notes.contains(&"reads credits".to_string()));
+        assert!(node_credit.notes.contains(&"writes credits".to_string()));
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.contains("subgraph \"cluster_ledger\""));
+        assert!(dot.contains(&format!("n{} -> n{};", mint, credit)));
+        assert!(dot.contains("label=\"mint\\ngas "));
+
+        let mermaid = graph.to_mermaid();
+        assert!(mermaid.starts_with("flowchart TD\n"));
+        assert!(mermaid.contains("    subgraph token\n"));
+        assert!(mermaid.contains(&format!("n{} --> n{}", burn, mint)));
+        assert!(mermaid.contains(&format!("n{}[\"mint<br/>gas ", mint)));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_locals_hide_functions_and_fields() {
+        let root = std::env::temp_dir().join(format!("bend-graph-locals-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(
+            root.join("vault.bend"),
+            r#"storage {
+    total: u24,
+}
+
+fn helper() -> u24 {
+    return 1;
+}
+
+fn params(helper: u24, total: u24) -> u24 {
+    return helper + total;
+}
+
+fn locals() -> u24 {
+    helper = 2;
+    total = 3;
+    return helper;
+}
+
+fn loops() -> u24 {
+    for helper in range(0, 3) {
+        total = helper;
+    }
+    return helper();
+}
+
+fn calls(token: u24) -> u24 {
+    return call(token, 0, 0, "get()");
+}
+
+fn transfers(to: u24) -> u24 {
+    return transfer(to, 1);
+}
+"#,
+        )
+        .unwrap();
+        let mut modules = ModuleSystem::new();
+        let module = modules.load_module(root.join("vault.bend")).unwrap();
+        let graph = Graph::calls(&module, &GasProfiler::new());
+
+        let (helper, _) = node(&graph, "helper");
+        let (params, node_params) = node(&graph, "params");
+        let (locals, node_locals) = node(&graph, "locals");
+        let (loops, node_loops) = node(&graph, "loops");
+        assert!(!graph.edges.contains(&(params, helper)));
+        assert!(!node_params.notes.iter().any(|note| note.contains("total")));
+        assert!(!graph.edges.contains(&(locals, helper)));
+        assert!(node_locals.notes.contains(&"writes total".to_string()));
+        // The loop variable is out of scope after the loop
+        assert!(graph.edges.contains(&(loops, helper)));
+        assert!(node_loops.notes.contains(&"writes total".to_string()));
+
+        let calls_contracts = |name| {
+            node(&graph, name)
+                .1
+                .notes
+                .contains(&"calls other contracts".to_string())
+        };
+        assert!(calls_contracts("calls"));
+        assert!(!calls_contracts("transfers"));
+        assert!(!calls_contracts("helper"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_module_graph() {
+        let (root, module) = load("modules");
+        let graph = Graph::modules(&module);
+
+        let names: Vec<&str> = graph.nodes.iter().map(|node| node.name.as_str()).collect();
+        // The prelude modules are left out
+        assert_eq!(names, ["token", "Math", "ledger"]);
+        assert_eq!(graph.edges, BTreeSet::from([(0, 1), (0, 2)]));
+        assert!(graph.nodes[1].dashed && !graph.nodes[2].dashed);
+
+        let dot = graph.render(GraphFormat::Dot);
+        assert!(dot.contains("n1 [label=\"Math\", style=dashed];"));
+        assert!(dot.contains("n0 -> n2;"));
+        let mermaid = graph.render(GraphFormat::Mermaid);
+        assert!(mermaid.contains("    n0 --> n1\n"));
+        assert!(mermaid.contains("    class n1 dashed\n"));
+
+        assert_eq!("mermaid".parse(), Ok(GraphFormat::Mermaid));
+        assert!("svg".parse::<GraphFormat>().is_err());
+        assert_eq!("modules".parse(), Ok(GraphKind::Modules));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,3 +1,7 @@
+pub mod costs;
 pub mod gas_profiler;
+pub mod graph;
 
+pub use costs::{CostTable, Weight};
 pub use gas_profiler::{GasEstimate, GasProfile, ProfilerError};
+pub use graph::{Graph, GraphFormat, GraphKind};
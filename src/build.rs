@@ -18,6 +18,7 @@ use crate::compiler::codegen::risc_v::RiscVCodegen;
 use crate::compiler::optimizer::passes::{create_default_manager, OptimizationLevel};
 use crate::compiler::parser::parser::Parser;
 use crate::error::{BendError, BendResult, ErrorReporter};
+use crate::package::build::source_date_epoch;
 use crate::security::{SecurityConfig, SecurityManager};
 
 /// Build configuration
@@ -495,10 +496,7 @@ impl BuildSystem {
             TargetPlatform::Native => config.output_dir.join(format!("{}.o", file_name)),
         };
 
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        let timestamp = Self::build_timestamp();
 
         // Parse source
         let mut parser = Parser::new(&content);
@@ -562,6 +560,17 @@ impl BuildSystem {
         })
     }
 
+    /// Time of the build in seconds since the Unix epoch, taken from
+    /// `SOURCE_DATE_EPOCH` when it is set, as package builds do
+    fn build_timestamp() -> u64 {
+        source_date_epoch().unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        })
+    }
+
     /// Calculate file hash for change detection
     fn calculate_hash(content: &str) -> String {
         use std::collections::hash_map::DefaultHasher;
@@ -739,12 +748,14 @@ mod tests {
 
     #[test]
     fn test_build_stats() {
-        let mut stats = BuildStats::default();
-        stats.files_processed = 5;
-        stats.files_compiled = 3;
-        stats.files_skipped = 2;
-        stats.errors = 0;
-        stats.warnings = 1;
+        let stats = BuildStats {
+            files_processed: 5,
+            files_compiled: 3,
+            files_skipped: 2,
+            errors: 0,
+            warnings: 1,
+            ..Default::default()
+        };
 
         assert_eq!(stats.files_processed, 5);
         assert_eq!(stats.files_compiled, 3);
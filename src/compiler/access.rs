@@ -0,0 +1,765 @@
+//! Role-based access control generated from `#[only_owner]` and
+//! `#[only_role("name")]`
+//!
+//! The pass runs before type checking, so the code it generates is checked,
+//! described in the metadata and dispatched like the code of the source.
+//! When a function of the program restricts its callers:
+//!
+//! - storage gains the owner, `%access.owner`, and for every role a map
+//!   from account to membership, `%access.role.<name>`;
+//! - each restricted function starts by checking its caller, reverting with
+//!   "caller is not the owner" or "caller is missing role <name>";
+//! - the `OwnershipTransferred`, `RoleGranted` and `RoleRevoked` events and
+//!   the messages below are added.
+//!
+//! ```text
+//! #[message] #[view] fn owner() -> Address
+//! #[message] fn initialize_owner() -> bool      // claims the unset owner
+//! #[message] #[only_owner] fn transfer_ownership(new_owner: Address) -> bool
+//!
+//! // When a function takes a role:
+//! #[message] #[view] fn has_role(role: u24, account: Address) -> bool
+//! #[message] #[only_owner] fn grant_role(role: u24, account: Address) -> bool
+//! #[message] #[only_owner] fn revoke_role(role: u24, account: Address) -> bool
+//! #[message] fn renounce_role(role: u24) -> bool
+//! ```
+//!
+//! Roles are passed by [`role_id`]; an unknown id reverts.
+
+use std::collections::BTreeSet;
+
+use crate::compiler::analyzer::attributes::{Access, FunctionAttributes};
+use crate::compiler::analyzer::type_checker::{TypeError, ZERO_ADDRESS};
+use crate::compiler::parser::ast::{
+    Attribute, BinaryOperator, Block, Definition, EventField, Expr, LiteralKind, Location,
+    LocationProvider, Parameter, Pattern, Program, RevertKind, Statement, StorageField, Type,
+    Visibility,
+};
+use crate::stdlib::crypto::CryptoFunctions;
+
+/// Storage field holding the owner, named so that no source identifier can
+/// clash with it
+pub const OWNER: &str = "%access.owner";
+
+/// Definitions added to a program restricting its callers
+pub const OWNER_DEFINITIONS: [&str; 6] = [
+    "owner",
+    "initialize_owner",
+    "transfer_ownership",
+    "OwnershipTransferred",
+    "RoleGranted",
+    "RoleRevoked",
+];
+
+/// Definitions added to a program with a function taking a role
+pub const ROLE_DEFINITIONS: [&str; 4] = ["has_role", "grant_role", "revoke_role", "renounce_role"];
+
+/// Storage field mapping the members of `role` to 1
+pub fn role_field(role: &str) -> String {
+    format!("%access.role.{}", role)
+}
+
+/// Id of a role in the generated messages: the first three bytes of the
+/// Keccak-256 hash of its name, big-endian
+pub fn role_id(role: &str) -> u32 {
+    let hash = CryptoFunctions::keccak256(role.as_bytes());
+    u32::from_be_bytes([0, hash[0], hash[1], hash[2]])
+}
+
+/// Generate the access control of the functions of `program` taking
+/// `#[only_owner]` or `#[only_role(...)]`
+///
+/// Attributes that do not validate are left to the type checker.
+pub fn expand(program: &mut Program) -> Result<(), TypeError> {
+    let mut roles = BTreeSet::new();
+    let mut restricted = false;
+    visit_functions(&mut program.definitions, &mut |access, _| {
+        restricted = true;
+        if let Access::Role(role) = access {
+            roles.insert(role.clone());
+        }
+    });
+    if !restricted {
+        return Ok(());
+    }
+
+    for definition in &program.definitions {
+        let name = match definition {
+            Definition::FunctionDef { name, .. } | Definition::EventDef { name, .. } => name,
+            _ => continue,
+        };
+        if OWNER_DEFINITIONS.contains(&name.as_str())
+            || (!roles.is_empty() && ROLE_DEFINITIONS.contains(&name.as_str()))
+        {
+            let location = definition.location();
+            return Err(TypeError::Generic(format!(
+                "'{}' is generated for access control and cannot be defined (line {}, column {})",
+                name, location.line, location.column
+            )));
+        }
+    }
+    let roles: Vec<String> = roles.into_iter().collect();
+    for (i, role) in roles.iter().enumerate() {
+        if let Some(other) = roles[..i]
+            .iter()
+            .find(|other| role_id(other) == role_id(role))
+        {
+            return Err(TypeError::Generic(format!(
+                "Roles '{}' and '{}' have the same id {:#08x}",
+                other,
+                role,
+                role_id(role)
+            )));
+        }
+    }
+
+    add_storage(program, &roles);
+    program.definitions.extend(events());
+    program.definitions.extend(owner_messages());
+    if !roles.is_empty() {
+        program.definitions.extend(role_messages(&roles));
+    }
+
+    visit_functions(&mut program.definitions, &mut |access, body| {
+        body.statements
+            .insert(0, check_caller(access, &body.location));
+    });
+    Ok(())
+}
+
+/// Call `f` with the access and body of every restricted function
+fn visit_functions(definitions: &mut [Definition], f: &mut impl FnMut(&Access, &mut Block)) {
+    for definition in definitions {
+        match definition {
+            Definition::FunctionDef {
+                params,
+                attributes,
+                body,
+                ..
+            } => {
+                let access = FunctionAttributes::from_attributes(attributes, params)
+                    .ok()
+                    .and_then(|attributes| attributes.access);
+                if let Some(access) = access {
+                    f(&access, body);
+                }
+            }
+            Definition::ObjectDef { functions, .. } => visit_functions(functions, f),
+            Definition::Module { definitions, .. } => visit_functions(definitions, f),
+            _ => {}
+        }
+    }
+}
+
+/// `require(...)` reverting unless the caller may call a function
+fn check_caller(access: &Access, location: &Location) -> Statement {
+    let (condition, reason) = match access {
+        Access::Owner => (
+            binary(
+                caller(location),
+                BinaryOperator::Equal,
+                variable(OWNER, location),
+            ),
+            "caller is not the owner".to_string(),
+        ),
+        Access::Role(role) => (
+            binary(
+                method(&role_field(role), "get", vec![caller(location)], location),
+                BinaryOperator::Equal,
+                uint(1, location),
+            ),
+            format!("caller is missing role {}", role),
+        ),
+    };
+    Statement::Revert {
+        kind: RevertKind::Require,
+        condition: Some(condition),
+        reason: Some(string(&reason, location)),
+        location: location.clone(),
+    }
+}
+
+/// Append the owner and role fields to the first storage block, or a new
+/// one when the program has none
+fn add_storage(program: &mut Program, roles: &[String]) {
+    let location = Location::default();
+    let mut fields = vec![StorageField {
+        name: OWNER.into(),
+        ty: named("Address", Vec::new()),
+        visibility: Visibility::Private,
+        location: location.clone(),
+    }];
+    fields.extend(roles.iter().map(|role| StorageField {
+        name: role_field(role).into(),
+        ty: named(
+            "StorageMap",
+            vec![
+                named("Address", Vec::new()),
+                Type::U24 {
+                    location: location.clone(),
+                },
+            ],
+        ),
+        visibility: Visibility::Private,
+        location: location.clone(),
+    }));
+
+    let storage = program
+        .definitions
+        .iter_mut()
+        .find_map(|definition| match definition {
+            Definition::StorageDef { fields, .. } => Some(fields),
+            _ => None,
+        });
+    match storage {
+        Some(existing) => existing.extend(fields),
+        None => program.definitions.push(Definition::StorageDef {
+            fields,
+            attributes: Vec::new(),
+            location,
+        }),
+    }
+}
+
+fn events() -> Vec<Definition> {
+    let field = |name: &str, ty: Type| EventField {
+        name: name.into(),
+        ty,
+        indexed: true,
+        location: Location::default(),
+    };
+    let address = || named("Address", Vec::new());
+    let role_event = |name: &str| Definition::EventDef {
+        name: name.into(),
+        fields: vec![
+            field(
+                "role",
+                Type::U24 {
+                    location: Location::default(),
+                },
+            ),
+            field("account", address()),
+            field("sender", address()),
+        ],
+        visibility: Visibility::Public,
+        location: Location::default(),
+    };
+
+    vec![
+        Definition::EventDef {
+            name: "OwnershipTransferred".into(),
+            fields: vec![
+                field("previous_owner", address()),
+                field("new_owner", address()),
+            ],
+            visibility: Visibility::Public,
+            location: Location::default(),
+        },
+        role_event("RoleGranted"),
+        role_event("RoleRevoked"),
+    ]
+}
+
+fn owner_messages() -> Vec<Definition> {
+    let at = Location::default();
+    let at = &at;
+    vec![
+        function(
+            "owner",
+            Vec::new(),
+            named("Address", Vec::new()),
+            Some("view"),
+            vec![ret(variable(OWNER, at))],
+        ),
+        // Contracts have no constructor, so the first caller claims them,
+        // as they would with an `initialize` message
+        function(
+            "initialize_owner",
+            Vec::new(),
+            named("bool", Vec::new()),
+            None,
+            vec![
+                require(
+                    binary(
+                        variable(OWNER, at),
+                        BinaryOperator::Equal,
+                        variable(ZERO_ADDRESS, at),
+                    ),
+                    "owner already set",
+                ),
+                assign(OWNER, caller(at)),
+                emit(
+                    "OwnershipTransferred",
+                    vec![variable(ZERO_ADDRESS, at), caller(at)],
+                ),
+                ret(boolean(true)),
+            ],
+        ),
+        // The zero address would let anyone claim the contract again
+        function(
+            "transfer_ownership",
+            vec![param("new_owner", named("Address", Vec::new()))],
+            named("bool", Vec::new()),
+            Some("only_owner"),
+            vec![
+                require(
+                    binary(
+                        variable("new_owner", at),
+                        BinaryOperator::NotEqual,
+                        variable(ZERO_ADDRESS, at),
+                    ),
+                    "new owner is the zero address",
+                ),
+                emit(
+                    "OwnershipTransferred",
+                    vec![variable(OWNER, at), variable("new_owner", at)],
+                ),
+                assign(OWNER, variable("new_owner", at)),
+                ret(boolean(true)),
+            ],
+        ),
+    ]
+}
+
+fn role_messages(roles: &[String]) -> Vec<Definition> {
+    let at = Location::default();
+    let at = &at;
+    let role_param = || {
+        param(
+            "role",
+            Type::U24 {
+                location: Location::default(),
+            },
+        )
+    };
+    let account_param = || param("account", named("Address", Vec::new()));
+    let set_member = |account: Expr, member: u32| {
+        by_role(roles, |field| Statement::Expr {
+            expr: method(field, "insert", vec![account.clone(), uint(member, at)], at),
+            location: at.clone(),
+        })
+    };
+    let role_event =
+        |name: &str, account: Expr| emit(name, vec![variable("role", at), account, caller(at)]);
+
+    vec![
+        function(
+            "has_role",
+            vec![role_param(), account_param()],
+            named("bool", Vec::new()),
+            Some("view"),
+            vec![
+                assign("member", uint(0, at)),
+                by_role(roles, |field| {
+                    assign(
+                        "member",
+                        method(field, "get", vec![variable("account", at)], at),
+                    )
+                }),
+                ret(binary(
+                    variable("member", at),
+                    BinaryOperator::Equal,
+                    uint(1, at),
+                )),
+            ],
+        ),
+        function(
+            "grant_role",
+            vec![role_param(), account_param()],
+            named("bool", Vec::new()),
+            Some("only_owner"),
+            vec![
+                set_member(variable("account", at), 1),
+                role_event("RoleGranted", variable("account", at)),
+                ret(boolean(true)),
+            ],
+        ),
+        function(
+            "revoke_role",
+            vec![role_param(), account_param()],
+            named("bool", Vec::new()),
+            Some("only_owner"),
+            vec![
+                set_member(variable("account", at), 0),
+                role_event("RoleRevoked", variable("account", at)),
+                ret(boolean(true)),
+            ],
+        ),
+        function(
+            "renounce_role",
+            vec![role_param()],
+            named("bool", Vec::new()),
+            None,
+            vec![
+                set_member(caller(at), 0),
+                role_event("RoleRevoked", caller(at)),
+                ret(boolean(true)),
+            ],
+        ),
+    ]
+}
+
+/// `if role == <id> { <statement on its field> } else if ...`, reverting
+/// for an unknown id
+fn by_role(roles: &[String], statement: impl Fn(&str) -> Statement) -> Statement {
+    let at = Location::default();
+    let mut result = Statement::Revert {
+        kind: RevertKind::Revert,
+        condition: None,
+        reason: Some(string("unknown role", &at)),
+        location: at.clone(),
+    };
+    for role in roles.iter().rev() {
+        result = Statement::If {
+            condition: binary(
+                variable("role", &at),
+                BinaryOperator::Equal,
+                uint(role_id(role), &at),
+            ),
+            then_branch: block(vec![statement(&role_field(role))]),
+            else_branch: block(vec![result]),
+            location: at.clone(),
+        };
+    }
+    result
+}
+
+fn function(
+    name: &str,
+    params: Vec<Parameter>,
+    return_type: Type,
+    attribute: Option<&str>,
+    statements: Vec<Statement>,
+) -> Definition {
+    Definition::FunctionDef {
+        name: name.into(),
+        params,
+        return_type: Some(return_type),
+        body: block(statements),
+        checked: None,
+        attributes: std::iter::once("message")
+            .chain(attribute)
+            .map(|name| Attribute {
+                name: name.into(),
+                args: Vec::new(),
+                location: Location::default(),
+            })
+            .collect(),
+        visibility: Visibility::Public,
+        location: Location::default(),
+    }
+}
+
+fn param(name: &str, ty: Type) -> Parameter {
+    Parameter {
+        name: name.into(),
+        ty,
+        location: Location::default(),
+    }
+}
+
+fn named(name: &str, params: Vec<Type>) -> Type {
+    Type::Named {
+        name: name.into(),
+        params,
+        location: Location::default(),
+    }
+}
+
+fn block(statements: Vec<Statement>) -> Block {
+    Block {
+        statements,
+        location: Location::default(),
+    }
+}
+
+fn require(condition: Expr, reason: &str) -> Statement {
+    let at = Location::default();
+    Statement::Revert {
+        kind: RevertKind::Require,
+        condition: Some(condition),
+        reason: Some(string(reason, &at)),
+        location: at,
+    }
+}
+
+fn assign(name: &str, value: Expr) -> Statement {
+    Statement::Assignment {
+        pattern: Pattern::Variable {
+            name: name.into(),
+            location: Location::default(),
+        },
+        value,
+        location: Location::default(),
+    }
+}
+
+fn emit(event: &str, args: Vec<Expr>) -> Statement {
+    Statement::Emit {
+        event: event.into(),
+        args,
+        location: Location::default(),
+    }
+}
+
+fn ret(value: Expr) -> Statement {
+    Statement::Return {
+        value,
+        location: Location::default(),
+    }
+}
+
+fn variable(name: &str, location: &Location) -> Expr {
+    Expr::Variable {
+        name: name.into(),
+        location: location.clone(),
+    }
+}
+
+fn caller(location: &Location) -> Expr {
+    call(variable("caller", location), Vec::new(), location)
+}
+
+/// `field.method(args)` on a storage field
+fn method(field: &str, method: &str, args: Vec<Expr>, location: &Location) -> Expr {
+    call(
+        variable(&format!("{}.{}", field, method), location),
+        args,
+        location,
+    )
+}
+
+fn call(function: Expr, args: Vec<Expr>, location: &Location) -> Expr {
+    Expr::FunctionCall {
+        function: Box::new(function),
+        args,
+        named_args: Default::default(),
+        location: location.clone(),
+    }
+}
+
+fn binary(left: Expr, operator: BinaryOperator, right: Expr) -> Expr {
+    let location = left.location().clone();
+    Expr::BinaryOp {
+        left: Box::new(left),
+        operator,
+        right: Box::new(right),
+        location,
+    }
+}
+
+fn uint(value: u32, location: &Location) -> Expr {
+    Expr::Literal {
+        kind: LiteralKind::Uint(value),
+        location: location.clone(),
+    }
+}
+
+fn boolean(value: bool) -> Expr {
+    Expr::Literal {
+        kind: LiteralKind::Bool(value),
+        location: Location::default(),
+    }
+}
+
+fn string(value: &str, location: &Location) -> Expr {
+    Expr::Literal {
+        kind: LiteralKind::String(value.to_string()),
+        location: location.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::address::Address;
+    use crate::compiler::codegen::metadata::{collect_function_metadata, selector_for};
+    use crate::compiler::parser::parser::Parser;
+    use crate::runtime::env::{Environment, ExecutionContext, ExecutionResult};
+    use crate::runtime::interpreter::Interpreter;
+    use crate::testing::differential::CompiledContract;
+
+    const TOKEN: &str = r#"
+        storage {
+            supply: u24,
+        }
+
+        #[message]
+        #[only_role("minter")]
+        fn mint(amount: u24) -> u24 {
+            supply = supply + amount;
+            return supply;
+        }
+
+        #[message]
+        #[only_owner]
+        fn burn(amount: u24) -> u24 {
+            supply = supply - amount;
+            return supply;
+        }
+
+        #[message]
+        fn total() -> u24 {
+            return supply;
+        }
+    "#;
+
+    fn expanded(source: &str) -> Result<Program, TypeError> {
+        let mut program = Parser::new(source).parse_program().unwrap();
+        expand(&mut program)?;
+        Ok(program)
+    }
+
+    fn function<'a>(program: &'a Program, name: &str) -> &'a Block {
+        program
+            .definitions
+            .iter()
+            .find_map(|definition| match definition {
+                Definition::FunctionDef {
+                    name: function,
+                    body,
+                    ..
+                } if function == name => Some(body),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_expand() {
+        let program = expanded(TOKEN).unwrap();
+
+        let fields: Vec<&str> = program
+            .definitions
+            .iter()
+            .find_map(|definition| match definition {
+                Definition::StorageDef { fields, .. } => Some(fields),
+                _ => None,
+            })
+            .unwrap()
+            .iter()
+            .map(|field| field.name.as_str())
+            .collect();
+        assert_eq!(fields, ["supply", OWNER, "%access.role.minter"]);
+
+        for (name, reason) in [
+            ("mint", "caller is missing role minter"),
+            ("burn", "caller is not the owner"),
+            ("grant_role", "caller is not the owner"),
+        ] {
+            match &function(&program, name).statements[0] {
+                Statement::Revert {
+                    reason:
+                        Some(Expr::Literal {
+                            kind: LiteralKind::String(found),
+                            ..
+                        }),
+                    ..
+                } => assert_eq!(found, reason),
+                other => panic!("expected a caller check in {}, got {:?}", name, other),
+            }
+        }
+        assert_eq!(function(&program, "total").statements.len(), 1);
+
+        let metadata = collect_function_metadata(&program);
+        assert_eq!(
+            metadata["mint"].access,
+            Some(Access::Role("minter".to_string()))
+        );
+        assert_eq!(metadata["revoke_role"].access, Some(Access::Owner));
+        assert_eq!(metadata["renounce_role"].access, None);
+
+        // Without restricted functions nothing is generated
+        let source = "fn total() -> u24 { return 0; }";
+        assert_eq!(
+            expanded(source).unwrap(),
+            Parser::new(source).parse_program().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_generated_names_are_reserved() {
+        let error = expanded(
+            r#"
+            #[only_owner]
+            fn reset() -> u24 { return 0; }
+
+            fn owner() -> u24 { return 1; }
+            "#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("'owner' is generated"));
+
+        // The role messages are only generated along with a role
+        let source = r#"
+            #[only_owner]
+            fn reset() -> u24 { return 0; }
+
+            fn has_role(role: u24) -> u24 { return role; }
+        "#;
+        assert!(expanded(source).is_ok());
+    }
+
+    #[test]
+    fn test_roles_are_enforced() {
+        let contract = CompiledContract::with_dispatcher(TOKEN).unwrap();
+        let (alice, bob) = (Address::from([1; 32]), Address::from([2; 32]));
+        let minter = role_id("minter").to_le_bytes();
+        let mut environment = Environment::new(ExecutionContext::new_default());
+        let mut events = Vec::new();
+        let mut call = |caller: Address, signature: &str, args: &[&[u8]]| {
+            let mut context = ExecutionContext::new_default();
+            context.caller = caller;
+            context.input = selector_for(signature).to_vec();
+            for arg in args {
+                context.input.extend_from_slice(arg);
+            }
+            let mut next = Environment::new(context);
+            next.storage = environment.storage.clone();
+            let mut interpreter = Interpreter::with_environment(next);
+            let result = interpreter.execute(&contract.instructions).unwrap();
+            environment = interpreter.into_environment();
+            events.append(&mut environment.events);
+            match result {
+                ExecutionResult::Success { data, .. } => Some(data),
+                _ => None,
+            }
+        };
+        let amount = 5u32.to_le_bytes();
+
+        assert_eq!(call(alice, "mint(u24)", &[&amount]), None);
+        assert!(call(alice, "initialize_owner()", &[]).is_some());
+        // Claimed once
+        assert_eq!(call(bob, "initialize_owner()", &[]), None);
+        assert_eq!(
+            call(bob, "grant_role(u24,Address)", &[&minter, bob.as_bytes()]),
+            None
+        );
+        assert!(call(alice, "grant_role(u24,Address)", &[&minter, bob.as_bytes()]).is_some());
+        assert_eq!(
+            call(bob, "has_role(u24,Address)", &[&minter, bob.as_bytes()]),
+            Some(vec![1])
+        );
+        assert_eq!(call(bob, "mint(u24)", &[&amount]), Some(amount.to_vec()));
+        assert_eq!(call(bob, "burn(u24)", &[&amount]), None);
+        assert!(call(bob, "renounce_role(u24)", &[&minter]).is_some());
+        assert_eq!(call(bob, "mint(u24)", &[&amount]), None);
+        assert_eq!(
+            call(
+                alice,
+                "grant_role(u24,Address)",
+                &[&[9, 0, 0, 0], bob.as_bytes()]
+            ),
+            None
+        );
+        assert!(call(alice, "transfer_ownership(Address)", &[bob.as_bytes()]).is_some());
+        assert_eq!(call(bob, "burn(u24)", &[&amount]), Some(vec![0; 4]));
+        assert_eq!(call(alice, "owner()", &[]), Some(bob.as_bytes().to_vec()));
+
+        // Accounts are indexed whole
+        let transferred = events.last().unwrap();
+        assert_eq!(transferred.topics[1], alice.as_bytes());
+        assert_eq!(transferred.topics[2], bob.as_bytes());
+        assert_eq!(events.len(), 4);
+    }
+}
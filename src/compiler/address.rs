@@ -0,0 +1,343 @@
+//! 32-byte account ids (`Address`) and hashes (`Hash`, also known as H256)
+//!
+//! Both are opaque byte strings: their SCALE encoding is the 32 bytes
+//! themselves, and generated code keeps them as eight consecutive words, so
+//! arguments, results and storage entries are copied without conversion.
+//! Addresses are written in source as `@` followed by an SS58 string or a
+//! `0x`-prefixed hex string; hashes as a bare `0x`-prefixed hex string.
+
+use std::fmt;
+use std::str::FromStr;
+
+use blake2::{Blake2b512, Digest};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Number of bytes of an address or hash
+pub const BYTES32_LEN: usize = 32;
+
+/// SS58 address format of generic Substrate chains
+pub const DEFAULT_SS58_PREFIX: u16 = 42;
+
+/// Largest SS58 address format
+const MAX_SS58_PREFIX: u16 = 16383;
+
+/// Base58 alphabet used by SS58
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    #[error("Invalid hex string: {0}")]
+    InvalidHex(String),
+
+    #[error("Expected 32 bytes, found {0}")]
+    InvalidLength(usize),
+
+    #[error("Invalid base58 character '{0}'")]
+    InvalidBase58(char),
+
+    #[error("Unsupported SS58 address format: {0}")]
+    InvalidPrefix(u16),
+
+    #[error("SS58 checksum mismatch")]
+    InvalidChecksum,
+}
+
+macro_rules! bytes32_type {
+    ($name:ident) => {
+        impl $name {
+            /// All zero bytes
+            pub const ZERO: $name = $name([0; BYTES32_LEN]);
+
+            /// Wrap raw bytes
+            pub const fn new(bytes: [u8; BYTES32_LEN]) -> Self {
+                $name(bytes)
+            }
+
+            /// Decode a hex string of 32 bytes, with or without `0x`
+            pub fn from_hex(text: &str) -> Result<Self, AddressError> {
+                let digits = text.strip_prefix("0x").unwrap_or(text);
+                let bytes =
+                    hex::decode(digits).map_err(|_| AddressError::InvalidHex(text.to_string()))?;
+                Self::from_slice(&bytes)
+            }
+
+            /// Copy 32 bytes from a slice
+            pub fn from_slice(bytes: &[u8]) -> Result<Self, AddressError> {
+                let bytes: [u8; BYTES32_LEN] = bytes
+                    .try_into()
+                    .map_err(|_| AddressError::InvalidLength(bytes.len()))?;
+                Ok($name(bytes))
+            }
+
+            /// The raw bytes
+            pub fn as_bytes(&self) -> &[u8; BYTES32_LEN] {
+                &self.0
+            }
+
+            /// Whether every byte is zero
+            pub fn is_zero(&self) -> bool {
+                self.0 == [0; BYTES32_LEN]
+            }
+
+            /// Little-endian words, in the order generated code keeps them
+            pub fn words(&self) -> [u32; BYTES32_LEN / 4] {
+                let mut words = [0; BYTES32_LEN / 4];
+                for (word, chunk) in words.iter_mut().zip(self.0.chunks(4)) {
+                    *word = u32::from_le_bytes(chunk.try_into().unwrap());
+                }
+                words
+            }
+        }
+
+        impl From<[u8; BYTES32_LEN]> for $name {
+            fn from(bytes: [u8; BYTES32_LEN]) -> Self {
+                $name(bytes)
+            }
+        }
+
+        impl From<$name> for [u8; BYTES32_LEN] {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "0x{}", hex::encode(self.0))
+            }
+        }
+    };
+}
+
+/// A 32-byte account id
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Address([u8; BYTES32_LEN]);
+
+/// A 32-byte hash
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Hash([u8; BYTES32_LEN]);
+
+bytes32_type!(Address);
+bytes32_type!(Hash);
+
+impl Address {
+    /// Decode an SS58 address of any format
+    pub fn from_ss58(text: &str) -> Result<Self, AddressError> {
+        Self::from_ss58_with_prefix(text).map(|(address, _)| address)
+    }
+
+    /// Decode an SS58 address, also returning its address format
+    pub fn from_ss58_with_prefix(text: &str) -> Result<(Self, u16), AddressError> {
+        let data = base58_decode(text)?;
+        let prefix_len = match data.first() {
+            Some(0..=63) => 1,
+            Some(64..=127) => 2,
+            Some(&first) => return Err(AddressError::InvalidPrefix(first as u16)),
+            None => return Err(AddressError::InvalidLength(0)),
+        };
+        if data.len() != prefix_len + BYTES32_LEN + 2 {
+            return Err(AddressError::InvalidLength(
+                data.len().saturating_sub(prefix_len + 2),
+            ));
+        }
+
+        let prefix = if prefix_len == 1 {
+            data[0] as u16
+        } else {
+            let lower = (data[0] << 2) | (data[1] >> 6);
+            let upper = data[1] & 0b0011_1111;
+            lower as u16 | ((upper as u16) << 8)
+        };
+        let (body, checksum) = data.split_at(prefix_len + BYTES32_LEN);
+        if ss58_checksum(body) != checksum {
+            return Err(AddressError::InvalidChecksum);
+        }
+
+        Ok((Self::from_slice(&body[prefix_len..])?, prefix))
+    }
+
+    /// Encode as an SS58 address of the given format
+    pub fn to_ss58(&self, prefix: u16) -> Result<String, AddressError> {
+        let mut data = match prefix {
+            0..=63 => vec![prefix as u8],
+            64..=MAX_SS58_PREFIX => vec![
+                ((prefix & 0b1111_1100) >> 2) as u8 | 0b0100_0000,
+                (prefix >> 8) as u8 | ((prefix & 0b11) << 6) as u8,
+            ],
+            _ => return Err(AddressError::InvalidPrefix(prefix)),
+        };
+        data.extend_from_slice(&self.0);
+        let checksum = ss58_checksum(&data);
+        data.extend_from_slice(&checksum);
+        Ok(base58_encode(&data))
+    }
+
+    /// The address as a hash of the same bytes
+    pub fn to_hash(self) -> Hash {
+        Hash(self.0)
+    }
+}
+
+impl Hash {
+    /// The hash as an address of the same bytes
+    pub fn to_address(self) -> Address {
+        Address(self.0)
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    /// Parse an SS58 or `0x`-prefixed hex address
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if text.starts_with("0x") {
+            Self::from_hex(text)
+        } else {
+            Self::from_ss58(text)
+        }
+    }
+}
+
+impl FromStr for Hash {
+    type Err = AddressError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(text)
+    }
+}
+
+/// First two bytes of `blake2b-512("SS58PRE" ++ data)`
+fn ss58_checksum(data: &[u8]) -> [u8; 2] {
+    let mut hasher = Blake2b512::new();
+    hasher.update(b"SS58PRE");
+    hasher.update(data);
+    let hash = hasher.finalize();
+    [hash[0], hash[1]]
+}
+
+fn base58_encode(data: &[u8]) -> String {
+    // Base-58 digits, least significant first
+    let mut digits: Vec<u8> = Vec::new();
+    for &byte in data {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let zeros = data.iter().take_while(|&&byte| byte == 0).count();
+    std::iter::repeat_n(BASE58_ALPHABET[0] as char, zeros)
+        .chain(
+            digits
+                .iter()
+                .rev()
+                .map(|&digit| BASE58_ALPHABET[digit as usize] as char),
+        )
+        .collect()
+}
+
+fn base58_decode(text: &str) -> Result<Vec<u8>, AddressError> {
+    // Bytes, least significant first
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in text.chars() {
+        let mut carry = BASE58_ALPHABET
+            .iter()
+            .position(|&digit| digit as char == c)
+            .ok_or(AddressError::InvalidBase58(c))? as u32;
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+
+    let zeros = text
+        .chars()
+        .take_while(|&c| c == BASE58_ALPHABET[0] as char)
+        .count();
+    bytes.extend(std::iter::repeat_n(0, zeros));
+    bytes.reverse();
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE_SS58: &str = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY";
+    const ALICE_HEX: &str = "0xd43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
+
+    #[test]
+    fn test_ss58_round_trip() {
+        let alice = Address::from_ss58(ALICE_SS58).unwrap();
+        assert_eq!(alice, Address::from_hex(ALICE_HEX).unwrap());
+        assert_eq!(alice.to_string(), ALICE_HEX);
+        assert_eq!(alice.to_ss58(DEFAULT_SS58_PREFIX).unwrap(), ALICE_SS58);
+
+        // Two-byte address formats
+        let encoded = alice.to_ss58(1284).unwrap();
+        assert_eq!(
+            Address::from_ss58_with_prefix(&encoded).unwrap(),
+            (alice, 1284)
+        );
+        assert_eq!(
+            alice.to_ss58(MAX_SS58_PREFIX + 1),
+            Err(AddressError::InvalidPrefix(MAX_SS58_PREFIX + 1))
+        );
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        let mut corrupted = ALICE_SS58.to_string();
+        corrupted.replace_range(10..11, "z");
+        assert_eq!(
+            Address::from_ss58(&corrupted),
+            Err(AddressError::InvalidChecksum)
+        );
+        assert_eq!(
+            Address::from_ss58("5Grw0"),
+            Err(AddressError::InvalidBase58('0'))
+        );
+        assert_eq!(
+            Hash::from_hex("0x1234"),
+            Err(AddressError::InvalidLength(2))
+        );
+        assert!(matches!(
+            "0xzz".parse::<Hash>(),
+            Err(AddressError::InvalidHex(_))
+        ));
+    }
+
+    #[test]
+    fn test_words_and_conversions() {
+        let mut bytes = [0u8; BYTES32_LEN];
+        bytes[0] = 1;
+        bytes[31] = 0x80;
+        let hash = Hash::new(bytes);
+        assert_eq!(hash.words(), [1, 0, 0, 0, 0, 0, 0, 0x8000_0000]);
+        assert_eq!(hash.to_address().to_hash(), hash);
+        assert!(Address::ZERO.is_zero());
+        assert_eq!(Address::ZERO, Address::default());
+    }
+}
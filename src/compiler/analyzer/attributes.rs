@@ -0,0 +1,625 @@
+//! Semantics of the `#[...]` attributes attached to definitions
+//!
+//! Functions accept `payable`, `view`, `pure`, `selector(0x........)`,
+//! `inline`, `inline(always)`, `inline(never)`, `deprecated`,
+//! `deprecated("note")`, `test`, `should_revert`, `guard(...)`, `non_reentrant`,
+//! `only_owner`, `only_role("name")`, `extern`, `suppress(...)`, `migrate`,
+//! `export`, `export("name")`, `internal`, `message`, `invariant`, the specifications
+//! `requires(...)` and `ensures(...)`, and the lint levels `allow(...)`,
+//! `warn(...)` and `deny(...)`. Types and objects only accept
+//! `deprecated`, and storage `invariant(...)` and `version(n)`. Any of
+//! them can also be conditional with `cfg(...)`, which
+//! [`crate::compiler::cfg`] evaluates before type checking.
+
+use serde::{Deserialize, Serialize};
+
+use crate::compiler::analyzer::lints::{Level, Lint};
+use crate::compiler::analyzer::type_checker::TypeError;
+use crate::compiler::cfg::CFG;
+use crate::compiler::parser::ast::*;
+
+/// How much contract state a function may touch
+///
+/// Ordered from most to least restrictive, so a caller may only call
+/// functions whose mutability is at most its own.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub enum Mutability {
+    /// Reads neither storage nor the chain state
+    Pure,
+    /// Reads but never writes state
+    View,
+    /// May read and write state
+    #[default]
+    Mutable,
+}
+
+impl std::fmt::Display for Mutability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mutability::Pure => write!(f, "pure"),
+            Mutability::View => write!(f, "view"),
+            Mutability::Mutable => write!(f, "mutable"),
+        }
+    }
+}
+
+/// Who may call a function, given with `#[only_owner]` or
+/// `#[only_role("name")]`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    /// The owner of the contract
+    Owner,
+    /// The accounts granted the named role
+    Role(String),
+}
+
+impl std::fmt::Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Access::Owner => write!(f, "only_owner"),
+            Access::Role(role) => write!(f, "only_role(\"{}\")", role),
+        }
+    }
+}
+
+/// Inlining hint given with `#[inline]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InlineHint {
+    Always,
+    Never,
+}
+
+/// Validated attributes of a function definition
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FunctionAttributes {
+    pub payable: bool,
+    pub mutability: Mutability,
+    /// Dispatcher selector replacing the one derived from the signature
+    pub selector: Option<[u8; 4]>,
+    pub inline: Option<InlineHint>,
+    /// Deprecation note, empty when none was given
+    pub deprecated: Option<String>,
+    pub test: bool,
+    /// A `#[test]` passing only when running it reverts or traps
+    pub should_revert: bool,
+    /// Guards wrapped around the body, outermost first
+    pub guards: Vec<GuardCall>,
+    /// Holds a storage lock while the body runs, so calls back into any
+    /// `#[non_reentrant]` function revert
+    pub non_reentrant: bool,
+    /// Accounts allowed to call the function, checked before its body
+    pub access: Option<Access>,
+    /// Declared without a body, defined by a linked object
+    pub external: bool,
+    /// Audit rules whose findings in the function are accepted, with
+    /// underscores for hyphens, e.g. `unbounded_loop`
+    pub suppressed: Vec<String>,
+    /// Preconditions, over the arguments and storage on entry
+    pub requires: Vec<Expr>,
+    /// Postconditions, over the arguments, the storage on return, the
+    /// `result` and `old(...)` values of entry
+    pub ensures: Vec<Expr>,
+    /// Moves the storage of earlier schema versions to the one declared
+    /// by `#[version(n)]`, once
+    pub migrate: bool,
+    /// Name of the program export entering the function directly, from
+    /// `#[export("name")]`, or `#[export]` for the function's own name,
+    /// which is empty until [`Self::of`] fills it in. The parameters of the
+    /// function are decoded from the input the host enters it with.
+    pub export: Option<String>,
+    /// Only called by the contract's own code: no message is routed to it
+    /// and the ABI leaves it out, as for the standard library functions a
+    /// contract is linked with
+    pub internal: bool,
+    /// A message the dispatcher routes calls to by selector, listed in the
+    /// ABI. Only messages are callable from outside the contract, besides
+    /// exports; `pub` functions of a `contract` declaration are messages.
+    pub message: bool,
+    /// A view over storage returning whether an invariant of the contract
+    /// holds, which invariant testing checks after every message call.
+    /// Like tests, it is left out of deployed code.
+    pub invariant: bool,
+}
+
+/// A guard applied to a function, e.g. `min_amount(amount, 10)` in
+/// `#[guard(only_owner, min_amount(amount, 10))]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuardCall {
+    pub name: String,
+    /// Arguments, evaluated in the scope of the guarded function
+    pub args: Vec<Expr>,
+    pub location: Location,
+}
+
+impl FunctionAttributes {
+    /// Validate the attributes of a function with the given parameters
+    pub fn from_attributes(
+        attributes: &[Attribute],
+        params: &[Parameter],
+    ) -> Result<Self, TypeError> {
+        check_duplicates(attributes)?;
+
+        let mut result = FunctionAttributes::default();
+        for attribute in attributes {
+            match attribute.name.as_str() {
+                "payable" => {
+                    expect_no_args(attribute)?;
+                    result.payable = true;
+                }
+                "view" | "pure" => {
+                    expect_no_args(attribute)?;
+                    if result.mutability != Mutability::Mutable {
+                        return Err(invalid(attribute, "view and pure are exclusive"));
+                    }
+                    result.mutability = if attribute.name == "view" {
+                        Mutability::View
+                    } else {
+                        Mutability::Pure
+                    };
+                }
+                "selector" => result.selector = Some(selector_arg(attribute)?),
+                "inline" => {
+                    result.inline = Some(match attribute.args.as_slice() {
+                        [] => InlineHint::Always,
+                        [Expr::Variable { name, .. }] if name == "always" => InlineHint::Always,
+                        [Expr::Variable { name, .. }] if name == "never" => InlineHint::Never,
+                        _ => return Err(invalid(attribute, "expected `always` or `never`")),
+                    })
+                }
+                "deprecated" => result.deprecated = Some(deprecation_note(attribute)?),
+                "test" => {
+                    expect_no_args(attribute)?;
+                    if !params.is_empty() {
+                        return Err(invalid(attribute, "test functions take no parameters"));
+                    }
+                    result.test = true;
+                }
+                "should_revert" => {
+                    expect_no_args(attribute)?;
+                    result.should_revert = true;
+                }
+                "guard" => result.guards = guard_calls(attribute)?,
+                "non_reentrant" => {
+                    expect_no_args(attribute)?;
+                    result.non_reentrant = true;
+                }
+                "only_owner" | "only_role" => {
+                    if result.access.is_some() {
+                        return Err(invalid(attribute, "only_owner and only_role are exclusive"));
+                    }
+                    result.access = Some(if attribute.name == "only_owner" {
+                        expect_no_args(attribute)?;
+                        Access::Owner
+                    } else {
+                        Access::Role(role_arg(attribute)?)
+                    });
+                }
+                "extern" => {
+                    expect_no_args(attribute)?;
+                    result.external = true;
+                }
+                "suppress" => result.suppressed = rule_names(attribute)?,
+                "migrate" => {
+                    expect_no_args(attribute)?;
+                    result.migrate = true;
+                }
+                "export" => result.export = Some(export_name(attribute)?),
+                "internal" => {
+                    expect_no_args(attribute)?;
+                    result.internal = true;
+                }
+                "message" => {
+                    expect_no_args(attribute)?;
+                    result.message = true;
+                }
+                "invariant" => {
+                    expect_no_args(attribute)?;
+                    if !params.is_empty() {
+                        return Err(invalid(attribute, "invariants take no parameters"));
+                    }
+                    result.invariant = true;
+                }
+                "requires" => result.requires.extend(conditions(attribute)?),
+                "ensures" => result.ensures.extend(conditions(attribute)?),
+                "allow" | "warn" | "deny" => {
+                    lint_names(attribute)?;
+                }
+                CFG => {}
+                _ => return Err(invalid(attribute, "unknown function attribute")),
+            }
+        }
+
+        if result.payable && result.mutability != Mutability::Mutable {
+            let attribute = attributes
+                .iter()
+                .find(|attribute| attribute.name == "payable")
+                .unwrap();
+            return Err(invalid(
+                attribute,
+                &format!("a {} function cannot receive value", result.mutability),
+            ));
+        }
+
+        // Taking the lock writes storage
+        if result.non_reentrant && result.mutability != Mutability::Mutable {
+            let attribute = attributes
+                .iter()
+                .find(|attribute| attribute.name == "non_reentrant")
+                .unwrap();
+            return Err(invalid(
+                attribute,
+                &format!(
+                    "a {} function cannot take the reentrancy lock",
+                    result.mutability
+                ),
+            ));
+        }
+
+        if result.should_revert && !result.test {
+            let attribute = attributes
+                .iter()
+                .find(|attribute| attribute.name == "should_revert")
+                .unwrap();
+            return Err(invalid(
+                attribute,
+                "only test functions can expect to revert",
+            ));
+        }
+
+        // Migrating writes the new storage version
+        if result.migrate && (result.mutability != Mutability::Mutable || result.test) {
+            let attribute = attributes
+                .iter()
+                .find(|attribute| attribute.name == "migrate")
+                .unwrap();
+            return Err(invalid(
+                attribute,
+                "a migration must be a function that may change state",
+            ));
+        }
+
+        // An export is entered by the host, not called from the contract
+        if result.export.is_some() && (result.test || result.external) {
+            let attribute = attributes
+                .iter()
+                .find(|attribute| attribute.name == "export")
+                .unwrap();
+            return Err(invalid(
+                attribute,
+                "test and extern functions cannot be exported",
+            ));
+        }
+
+        // A message is entered through the dispatcher
+        if result.message
+            && (result.test || result.external || result.internal || result.export.is_some())
+        {
+            let attribute = attributes
+                .iter()
+                .find(|attribute| attribute.name == "message")
+                .unwrap();
+            return Err(invalid(
+                attribute,
+                "test, extern, internal and exported functions cannot be messages",
+            ));
+        }
+
+        // An invariant is entered by invariant testing, between calls
+        if result.invariant
+            && (result.test
+                || result.external
+                || result.message
+                || result.export.is_some()
+                || result.migrate
+                || result.payable
+                || result.non_reentrant)
+        {
+            let attribute = attributes
+                .iter()
+                .find(|attribute| attribute.name == "invariant")
+                .unwrap();
+            return Err(invalid(
+                attribute,
+                "an invariant is a view that is not a test, message, export or extern function",
+            ));
+        }
+        // It only reads the state it checks
+        if result.invariant && result.mutability == Mutability::Mutable {
+            result.mutability = Mutability::View;
+        }
+
+        // Checking the caller reads storage
+        if result.access.is_some() && result.mutability == Mutability::Pure {
+            let attribute = attributes
+                .iter()
+                .find(|attribute| matches!(attribute.name.as_str(), "only_owner" | "only_role"))
+                .unwrap();
+            return Err(invalid(
+                attribute,
+                "a pure function cannot check its caller",
+            ));
+        }
+
+        Ok(result)
+    }
+
+    /// Validated attributes of a function definition, or the defaults for
+    /// any other definition
+    pub fn of(definition: &Definition) -> Result<Self, TypeError> {
+        match definition {
+            Definition::FunctionDef {
+                name,
+                params,
+                attributes,
+                body,
+                ..
+            } => {
+                let mut result = Self::from_attributes(attributes, params)?;
+                if result.external && !body.statements.is_empty() {
+                    let attribute = attributes.iter().find(|a| a.name == "extern").unwrap();
+                    return Err(invalid(attribute, "extern functions have no body"));
+                }
+                if let Some(export) = result.export.as_mut().filter(|export| export.is_empty()) {
+                    if !is_export_name(name) {
+                        let attribute = attributes.iter().find(|a| a.name == "export").unwrap();
+                        return Err(invalid(
+                            attribute,
+                            &format!("'{}' cannot name an export, give one", name),
+                        ));
+                    }
+                    *export = name.to_string();
+                }
+                Ok(result)
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+}
+
+/// Validate the attributes of a type or object definition, returning its
+/// deprecation note if it has one
+pub fn type_deprecation(attributes: &[Attribute]) -> Result<Option<String>, TypeError> {
+    check_duplicates(attributes)?;
+
+    let mut deprecated = None;
+    for attribute in attributes {
+        match attribute.name.as_str() {
+            "deprecated" => deprecated = Some(deprecation_note(attribute)?),
+            CFG => {}
+            _ => return Err(invalid(attribute, "unknown type attribute")),
+        }
+    }
+    Ok(deprecated)
+}
+
+/// Validated attributes of a storage definition
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StorageAttributes {
+    /// Invariants of the contract, from `#[invariant(...)]`
+    pub invariants: Vec<Expr>,
+    /// Version of the storage schema, from `#[version(n)]`, that the
+    /// `#[migrate]` function moves the storage of earlier versions to
+    pub version: Option<u32>,
+}
+
+impl StorageAttributes {
+    /// Validate the attributes of a storage definition
+    pub fn from_attributes(attributes: &[Attribute]) -> Result<Self, TypeError> {
+        let mut result = StorageAttributes::default();
+        for attribute in attributes {
+            match attribute.name.as_str() {
+                "invariant" => result.invariants.extend(conditions(attribute)?),
+                "version" => {
+                    if result.version.is_some() {
+                        return Err(invalid(attribute, "duplicate attribute"));
+                    }
+                    result.version = Some(version_arg(attribute)?);
+                }
+                CFG => {}
+                _ => return Err(invalid(attribute, "unknown storage attribute")),
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// The lint levels set by `#[allow(...)]`, `#[warn(...)]` and
+/// `#[deny(...)]` attributes, in the order they are written
+pub fn lint_levels(attributes: &[Attribute]) -> Result<Vec<(Lint, Level)>, TypeError> {
+    let mut levels = Vec::new();
+    for attribute in attributes {
+        if let Some(level) = Level::from_attribute(&attribute.name) {
+            levels.extend(lint_names(attribute)?.into_iter().map(|lint| (lint, level)));
+        }
+    }
+    Ok(levels)
+}
+
+fn lint_names(attribute: &Attribute) -> Result<Vec<Lint>, TypeError> {
+    if attribute.args.is_empty() {
+        return Err(invalid(attribute, "expected at least one lint"));
+    }
+
+    attribute
+        .args
+        .iter()
+        .map(|arg| match arg {
+            Expr::Variable { name, .. } => name
+                .parse()
+                .map_err(|_| invalid(attribute, &format!("unknown lint `{}`", name))),
+            _ => Err(invalid(attribute, "expected a lint name")),
+        })
+        .collect()
+}
+
+fn rule_names(attribute: &Attribute) -> Result<Vec<String>, TypeError> {
+    if attribute.args.is_empty() {
+        return Err(invalid(attribute, "expected at least one rule"));
+    }
+
+    attribute
+        .args
+        .iter()
+        .map(|arg| match arg {
+            Expr::Variable { name, .. } => Ok(name.to_string()),
+            _ => Err(invalid(attribute, "expected a rule name")),
+        })
+        .collect()
+}
+
+fn version_arg(attribute: &Attribute) -> Result<u32, TypeError> {
+    match attribute.args.as_slice() {
+        [Expr::Literal {
+            kind: LiteralKind::Uint(version),
+            ..
+        }] if *version > 0 => Ok(*version),
+        _ => Err(invalid(attribute, "expected a version number above 0")),
+    }
+}
+
+fn conditions(attribute: &Attribute) -> Result<Vec<Expr>, TypeError> {
+    if attribute.args.is_empty() {
+        return Err(invalid(attribute, "expected at least one condition"));
+    }
+    Ok(attribute.args.clone())
+}
+
+/// Every attribute but `cfg` and the specifications, which may be
+/// repeated, is only allowed once
+fn check_duplicates(attributes: &[Attribute]) -> Result<(), TypeError> {
+    for (i, attribute) in attributes.iter().enumerate() {
+        if !matches!(attribute.name.as_str(), CFG | "requires" | "ensures")
+            && attributes[..i]
+                .iter()
+                .any(|other| other.name == attribute.name)
+        {
+            return Err(invalid(attribute, "duplicate attribute"));
+        }
+    }
+    Ok(())
+}
+
+fn expect_no_args(attribute: &Attribute) -> Result<(), TypeError> {
+    if attribute.args.is_empty() {
+        Ok(())
+    } else {
+        Err(invalid(attribute, "takes no arguments"))
+    }
+}
+
+/// The selector given as a 32-bit integer, e.g. `0x0a1b2c3d`, or as
+/// 4 bytes, e.g. `hex"0a1b2c3d"`
+fn selector_arg(attribute: &Attribute) -> Result<[u8; 4], TypeError> {
+    let word = match attribute.args.as_slice() {
+        [Expr::Literal {
+            kind: LiteralKind::Bytes(bytes),
+            ..
+        }] if bytes.len() == 4 => return Ok([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        [Expr::Literal {
+            kind: LiteralKind::Uint(word),
+            ..
+        }] => Some(*word),
+        [Expr::Literal {
+            kind: LiteralKind::WideUint(value),
+            ..
+        }] => match value.limbs() {
+            [word, rest @ ..] if rest.iter().all(|&limb| limb == 0) => Some(*word),
+            _ => None,
+        },
+        _ => None,
+    };
+    word.map(u32::to_be_bytes)
+        .ok_or_else(|| invalid(attribute, "expected a 32-bit hex literal"))
+}
+
+/// The export name given to `#[export("name")]`, empty for `#[export]`
+fn export_name(attribute: &Attribute) -> Result<String, TypeError> {
+    match attribute.args.as_slice() {
+        [] => Ok(String::new()),
+        [Expr::Literal {
+            kind: LiteralKind::String(name),
+            ..
+        }] if is_export_name(name) => Ok(name.clone()),
+        _ => Err(invalid(
+            attribute,
+            "expected an export name of letters, digits and underscores",
+        )),
+    }
+}
+
+/// Whether `name` can name a program export: an identifier of ASCII
+/// letters, digits and underscores
+pub fn is_export_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn guard_calls(attribute: &Attribute) -> Result<Vec<GuardCall>, TypeError> {
+    if attribute.args.is_empty() {
+        return Err(invalid(attribute, "expected at least one guard"));
+    }
+
+    attribute
+        .args
+        .iter()
+        .map(|arg| match arg {
+            Expr::Variable { name, location } => Ok(GuardCall {
+                name: name.to_string(),
+                args: Vec::new(),
+                location: location.clone(),
+            }),
+            Expr::FunctionCall {
+                function,
+                args,
+                named_args,
+                location,
+            } if named_args.is_empty() => match &**function {
+                Expr::Variable { name, .. } => Ok(GuardCall {
+                    name: name.to_string(),
+                    args: args.clone(),
+                    location: location.clone(),
+                }),
+                _ => Err(invalid(attribute, "expected a guard name")),
+            },
+            _ => Err(invalid(attribute, "expected a guard name")),
+        })
+        .collect()
+}
+
+fn role_arg(attribute: &Attribute) -> Result<String, TypeError> {
+    match attribute.args.as_slice() {
+        [Expr::Literal {
+            kind: LiteralKind::String(role),
+            ..
+        }] if !role.is_empty() && role.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => {
+            Ok(role.clone())
+        }
+        _ => Err(invalid(
+            attribute,
+            "expected a role name of letters, digits and underscores",
+        )),
+    }
+}
+
+fn deprecation_note(attribute: &Attribute) -> Result<String, TypeError> {
+    match attribute.args.as_slice() {
+        [] => Ok(String::new()),
+        [Expr::Literal {
+            kind: LiteralKind::String(note),
+            ..
+        }] => Ok(note.clone()),
+        _ => Err(invalid(attribute, "expected a string note")),
+    }
+}
+
+fn invalid(attribute: &Attribute, reason: &str) -> TypeError {
+    TypeError::InvalidAttribute {
+        name: attribute.name.to_string(),
+        reason: reason.to_string(),
+        line: attribute.location.line,
+        column: attribute.location.column,
+    }
+}
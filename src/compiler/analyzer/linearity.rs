@@ -0,0 +1,1004 @@
+//! Linearity of in-memory maps
+//!
+//! On HVM a value used more than once is copied by a `dup` node and a value
+//! never used is freed by an eraser. PolkaVM copies words freely and never
+//! reclaims heap memory, so only in-memory maps need either: they live on
+//! the heap and `map[key] = value` updates them in place, so two variables
+//! holding the same map would see each other's writes.
+//!
+//! [`analyze`] counts how often every map variable is used: erased, linear
+//! or duplicated. [`Linearity`] then gives maps the semantics of values: a
+//! write to a map that may be shared first copies it with the [`DUP`]
+//! builtin, and the copy is written instead. A map is not shared while the
+//! function built it, with a literal, a comprehension, `Map::new()` or a
+//! copy, and did not since use it other than to read an entry, but for its
+//! last use. Parameters are shared with the caller.
+//!
+//! A copy costs an allocation and four words of loads and stores for every
+//! entry of the map. When a loop writes to a map shared on entry, the map
+//! is copied once before the loop rather than on every iteration, unless
+//! the loop shares it again. Nothing is inserted for erased maps, since
+//! their memory would not be reclaimed anyway.
+//!
+//! A write through anything else than a local, such as
+//! `pair.first[key] = value`, would update a map whose other holders cannot
+//! be told apart, and is rejected by the type checker.
+//!
+//! # Examples
+//!
+//! ```text
+//! // Before:
+//! fn bump(prices: Map<u24, u24>) -> u24 {
+//!     old = prices;
+//!     prices[1] = old[1] + 1;
+//!     return old[1];
+//! }
+//!
+//! // After:
+//! fn bump(prices: Map<u24, u24>) -> u24 {
+//!     old = prices;
+//!     prices = %dup(prices);
+//!     prices[1] = old[1] + 1;
+//!     return old[1];
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::parser::ast::*;
+use crate::security::security_scanner::children;
+
+/// Builtin copying an in-memory map, named so that no source identifier can
+/// clash with it
+pub const DUP: &str = "%dup";
+
+/// How often a function uses a map variable, counting the most used branch
+/// of a conditional and a loop body as run twice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Usage {
+    /// Never used, HVM would erase it
+    Erased,
+    /// Used once
+    Linear,
+    /// Used more than once, HVM would duplicate it
+    Duplicated,
+}
+
+/// The usage of a map variable of a function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapUsage {
+    pub function: String,
+    pub name: String,
+    pub usage: Usage,
+}
+
+/// The usage of every map variable of every function of `program`, in the
+/// order the functions are defined and then by name
+pub fn analyze(program: &Program) -> Vec<MapUsage> {
+    let mut usages = Vec::new();
+    for_each_function(&program.definitions, &mut |name, params, body| {
+        let maps = map_variables(params, body);
+        let counts = count_block(body, &maps);
+        let mut names: Vec<&Symbol> = maps.iter().collect();
+        names.sort();
+        for variable in names {
+            let usage = match counts.get(variable).copied().unwrap_or(0) {
+                0 => Usage::Erased,
+                1 => Usage::Linear,
+                _ => Usage::Duplicated,
+            };
+            usages.push(MapUsage {
+                function: name.to_string(),
+                name: variable.to_string(),
+                usage,
+            });
+        }
+    });
+    usages
+}
+
+/// Pass inserting a [`DUP`] before every write to a map that may be shared
+#[derive(Debug, Default)]
+pub struct Linearity {
+    /// Number of copies inserted so far
+    pub inserted_dups: u32,
+    /// Map variables of the function being rewritten
+    maps: HashSet<Symbol>,
+}
+
+impl Linearity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert the copies every function of `program` needs, in place
+    pub fn lower_program(&mut self, program: &mut Program) {
+        for definition in &mut program.definitions {
+            self.lower_definition(definition);
+        }
+    }
+
+    fn lower_definition(&mut self, definition: &mut Definition) {
+        match definition {
+            Definition::FunctionDef { params, body, .. } => {
+                self.maps = map_variables(params, body);
+                if !self.maps.is_empty() {
+                    self.block(body, &mut HashSet::new(), &HashSet::new());
+                }
+            }
+            Definition::ObjectDef { functions, .. } => {
+                for function in functions {
+                    self.lower_definition(function);
+                }
+            }
+            Definition::Module { definitions, .. } => {
+                for definition in definitions {
+                    self.lower_definition(definition);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Rewrite `block`, where the maps in `owned` are not shared on entry
+    /// and those in `after` may be used once it is done
+    fn block(&mut self, block: &mut Block, owned: &mut HashSet<Symbol>, after: &HashSet<Symbol>) {
+        let statements = std::mem::take(&mut block.statements);
+
+        // The maps each statement is followed by a use of
+        let mut later = after.clone();
+        let mut followed = Vec::with_capacity(statements.len());
+        for statement in statements.iter().rev() {
+            followed.push(later.clone());
+            later.extend(self.uses(statement).into_keys());
+        }
+        followed.reverse();
+
+        for (statement, after) in statements.into_iter().zip(followed) {
+            self.statement(statement, owned, &after, &mut block.statements);
+        }
+    }
+
+    fn statement(
+        &mut self,
+        mut statement: Statement,
+        owned: &mut HashSet<Symbol>,
+        after: &HashSet<Symbol>,
+        out: &mut Vec<Statement>,
+    ) {
+        // A map mentioned twice by the statement is still used after either
+        let mut live = after.clone();
+        live.extend(
+            self.uses(&statement)
+                .into_iter()
+                .filter(|(_, count)| *count > 1)
+                .map(|(name, _)| name),
+        );
+
+        match &mut statement {
+            Statement::Assignment {
+                pattern: Pattern::MapAccess { map, key, .. },
+                value,
+                ..
+            } => {
+                self.expr(key, owned, &live);
+                self.expr(value, owned, &live);
+                match &**map {
+                    Expr::Variable { name, location } if self.maps.contains(name) => {
+                        if !owned.contains(name) {
+                            out.push(dup(name, location));
+                            owned.insert(*name);
+                            self.inserted_dups += 1;
+                        }
+                    }
+                    _ => self.expr(map, owned, &live),
+                }
+            }
+            Statement::Assignment {
+                pattern: Pattern::Variable { name, .. },
+                value,
+                ..
+            }
+            | Statement::Use { name, value, .. } => {
+                let name = Symbol::intern(name);
+                match value {
+                    Expr::Variable { name: source, .. } if self.maps.contains(source) => {
+                        // Moved when this was its last use, shared otherwise
+                        let source = *source;
+                        let moved = owned.remove(&source) && !live.contains(&source);
+                        owned.remove(&name);
+                        if moved {
+                            owned.insert(name);
+                        }
+                    }
+                    _ => {
+                        self.expr(value, owned, &live);
+                        if is_fresh(value) {
+                            owned.insert(name);
+                        } else {
+                            owned.remove(&name);
+                        }
+                    }
+                }
+            }
+            Statement::Assignment { pattern, value, .. } => {
+                self.expr(value, owned, &live);
+                for name in bound_names(pattern) {
+                    owned.remove(&name);
+                }
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expr(condition, owned, &live);
+                let mut then_owned = owned.clone();
+                self.block(then_branch, &mut then_owned, after);
+                self.block(else_branch, owned, after);
+                owned.retain(|name| then_owned.contains(name));
+            }
+            Statement::Match { value, cases, .. } => {
+                self.expr(value, owned, &live);
+                let entry = owned.clone();
+                for case in cases {
+                    let mut case_owned = entry.clone();
+                    for name in bound_names(&case.pattern) {
+                        case_owned.remove(&name);
+                    }
+                    self.block(&mut case.body, &mut case_owned, after);
+                    owned.retain(|name| case_owned.contains(name));
+                }
+            }
+            Statement::Switch { value, cases, .. } => {
+                self.expr(value, owned, &live);
+                let entry = owned.clone();
+                for case in cases {
+                    let mut case_owned = entry.clone();
+                    self.block(&mut case.body, &mut case_owned, after);
+                    owned.retain(|name| case_owned.contains(name));
+                }
+            }
+            Statement::TryCatch {
+                try_block,
+                catch_blocks,
+                ..
+            } => {
+                // A catch may start anywhere in the try block
+                let entry = owned.clone();
+                self.block(try_block, owned, after);
+                for catch in catch_blocks {
+                    let mut catch_owned = entry.clone();
+                    catch_owned.retain(|name| owned.contains(name));
+                    self.block(&mut catch.body, &mut catch_owned, after);
+                    owned.retain(|name| catch_owned.contains(name));
+                }
+            }
+            Statement::With { body, .. } | Statement::Unchecked { body, .. } => {
+                self.block(body, owned, after)
+            }
+            Statement::While { .. }
+            | Statement::For { .. }
+            | Statement::Fold { .. }
+            | Statement::Bend { .. } => {
+                self.looped(statement, owned, after, &live, out);
+                return;
+            }
+            Statement::InPlaceOp { value, .. }
+            | Statement::Return { value, .. }
+            | Statement::Open { value, .. }
+            | Statement::Expr { expr: value, .. } => self.expr(value, owned, &live),
+            Statement::Emit { args, .. } => {
+                for arg in args {
+                    self.expr(arg, owned, &live);
+                }
+            }
+            Statement::Revert {
+                condition, reason, ..
+            } => {
+                for expr in condition.iter_mut().chain(reason) {
+                    self.expr(expr, owned, &live);
+                }
+            }
+            // Local functions are not compiled
+            Statement::LocalDef { .. } => {}
+        }
+        out.push(statement);
+    }
+
+    /// Rewrite a loop, which runs its body again with what the body left
+    /// owned, so a map is only owned in the body when every iteration
+    /// leaves it owned
+    fn looped(
+        &mut self,
+        mut statement: Statement,
+        owned: &mut HashSet<Symbol>,
+        after: &HashSet<Symbol>,
+        live: &HashSet<Symbol>,
+        out: &mut Vec<Statement>,
+    ) {
+        // Everything the loop uses may be used by the next iteration
+        let mut again = after.clone();
+        again.extend(self.uses(&statement).into_keys());
+        let mut live = live.clone();
+        live.extend(again.iter().cloned());
+
+        // Maps written by the loop are copied before it, if that keeps them
+        // owned through every iteration
+        let mut written = HashSet::new();
+        visit_statement(&statement, &mut |expr| {
+            if let Expr::Variable { name, .. } = expr {
+                if self.maps.contains(name) {
+                    written.insert(*name);
+                }
+            }
+        });
+        written.retain(|name| written_in(&statement, name));
+        let mut entry = owned.clone();
+        entry.extend(written.iter().cloned());
+
+        let inserted = self.inserted_dups;
+        loop {
+            let mut probe = statement.clone();
+            let mut state = entry.clone();
+            self.loop_once(&mut probe, &mut state, &again, &live);
+            let mut next: HashSet<Symbol> = entry.intersection(&state).cloned().collect();
+            if next == entry {
+                // A copy before the loop is wasted when it copies anyway
+                next.retain(|name| owned.contains(name) || !copies(&probe, name));
+                if next == entry {
+                    break;
+                }
+            }
+            entry = next;
+        }
+        self.inserted_dups = inserted;
+
+        let mut hoisted: Vec<&Symbol> = entry.difference(owned).collect();
+        hoisted.sort();
+        for name in hoisted {
+            out.push(dup(name, statement.location()));
+            self.inserted_dups += 1;
+        }
+
+        let mut state = entry.clone();
+        self.loop_once(&mut statement, &mut state, &again, &live);
+        *owned = entry;
+        out.push(statement);
+    }
+
+    /// Rewrite one run of the head and body of a loop
+    fn loop_once(
+        &mut self,
+        statement: &mut Statement,
+        owned: &mut HashSet<Symbol>,
+        again: &HashSet<Symbol>,
+        live: &HashSet<Symbol>,
+    ) {
+        match statement {
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.expr(condition, owned, live);
+                self.block(body, owned, again);
+            }
+            Statement::For {
+                start, end, body, ..
+            } => {
+                self.expr(start, owned, live);
+                self.expr(end, owned, live);
+                self.block(body, owned, again);
+            }
+            Statement::Fold { value, cases, .. } => {
+                self.expr(value, owned, live);
+                let entry = owned.clone();
+                for case in cases {
+                    let mut case_owned = entry.clone();
+                    for name in bound_names(&case.pattern) {
+                        case_owned.remove(&name);
+                    }
+                    self.block(&mut case.body, &mut case_owned, again);
+                    owned.retain(|name| case_owned.contains(name));
+                }
+            }
+            Statement::Bend {
+                initial_states,
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                for (name, value) in initial_states {
+                    self.expr(value, owned, live);
+                    owned.remove(&Symbol::intern(name));
+                }
+                self.expr(condition, owned, live);
+                let mut else_owned = owned.clone();
+                if let Some(else_body) = else_body {
+                    self.block(else_body, &mut else_owned, again);
+                }
+                self.block(body, owned, again);
+                owned.retain(|name| else_owned.contains(name));
+            }
+            _ => {}
+        }
+    }
+
+    /// Rewrite the blocks in `expr`, and forget the maps it lets escape
+    /// while they are still in `live`
+    fn expr(&mut self, expr: &mut Expr, owned: &mut HashSet<Symbol>, live: &HashSet<Symbol>) {
+        match expr {
+            Expr::Variable { name, .. } => {
+                if live.contains(name) {
+                    owned.remove(name);
+                }
+            }
+            // Reading an entry shares nothing
+            Expr::MapAccess { map, key, .. } if matches!(&**map, Expr::Variable { .. }) => {
+                self.expr(key, owned, live)
+            }
+            Expr::Block { block, .. } => self.block(block, owned, live),
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expr(condition, owned, live);
+                let mut then_owned = owned.clone();
+                self.expr(then_branch, &mut then_owned, live);
+                self.expr(else_branch, owned, live);
+                owned.retain(|name| then_owned.contains(name));
+            }
+            Expr::Lambda { body, .. } | Expr::UnsccopedLambda { body, .. } => {
+                // The body runs any number of times, later, and shares what
+                // it captures with the enclosing function
+                let all = self.maps.clone();
+                self.expr(body, &mut HashSet::new(), &all);
+                for name in self.uses_in_expr(body).into_keys() {
+                    if live.contains(&name) {
+                        owned.remove(&name);
+                    }
+                }
+            }
+            _ => {
+                for operand in operands_mut(expr) {
+                    self.expr(operand, owned, live);
+                }
+            }
+        }
+    }
+
+    /// How often `statement` mentions each map variable
+    fn uses(&self, statement: &Statement) -> HashMap<Symbol, usize> {
+        let mut uses = HashMap::new();
+        visit_statement(statement, &mut |expr| self.count(expr, &mut uses));
+        uses
+    }
+
+    fn uses_in_expr(&self, expr: &Expr) -> HashMap<Symbol, usize> {
+        let mut uses = HashMap::new();
+        visit_expr(expr, &mut |expr| self.count(expr, &mut uses));
+        uses
+    }
+
+    fn count(&self, expr: &Expr, uses: &mut HashMap<Symbol, usize>) {
+        if let Expr::Variable { name, .. } = expr {
+            if self.maps.contains(name) {
+                *uses.entry(*name).or_insert(0) += 1;
+            }
+        }
+    }
+}
+
+/// Run `visit` on the name, parameters and body of every function
+fn for_each_function(
+    definitions: &[Definition],
+    visit: &mut dyn FnMut(&str, &[Parameter], &Block),
+) {
+    for definition in definitions {
+        match definition {
+            Definition::FunctionDef {
+                name, params, body, ..
+            } => visit(name, params, body),
+            Definition::ObjectDef { functions, .. } => for_each_function(functions, visit),
+            Definition::Module { definitions, .. } => for_each_function(definitions, visit),
+            _ => {}
+        }
+    }
+}
+
+/// The locals of a function written in place, and those assigned from or
+/// to them, which all hold in-memory maps
+fn map_variables(params: &[Parameter], body: &Block) -> HashSet<Symbol> {
+    let mut locals: HashSet<Symbol> = params
+        .iter()
+        .map(|param| Symbol::intern(&param.name))
+        .collect();
+    let mut written = HashSet::new();
+    let mut aliases = Vec::new();
+    for statement in &body.statements {
+        visit_statements(statement, &mut |statement| match statement {
+            Statement::Assignment { pattern, value, .. } => match (pattern, value) {
+                (Pattern::MapAccess { map, .. }, _) => {
+                    if let Expr::Variable { name, .. } = &**map {
+                        written.insert(*name);
+                    }
+                }
+                (Pattern::Variable { name, .. }, Expr::Variable { name: source, .. }) => {
+                    let name = Symbol::intern(name);
+                    locals.insert(name);
+                    aliases.push((name, *source));
+                }
+                _ => locals.extend(bound_names(pattern)),
+            },
+            Statement::Use { name, value, .. } => {
+                let name = Symbol::intern(name);
+                locals.insert(name);
+                if let Expr::Variable { name: source, .. } = value {
+                    aliases.push((name, *source));
+                }
+            }
+            _ => {}
+        });
+    }
+
+    // Writes to anything but a local are to storage
+    let mut maps: HashSet<Symbol> = written.intersection(&locals).cloned().collect();
+    loop {
+        let before = maps.len();
+        for (name, source) in &aliases {
+            if maps.contains(name) || maps.contains(source) {
+                maps.insert(*name);
+                if locals.contains(source) {
+                    maps.insert(*source);
+                }
+            }
+        }
+        if maps.len() == before {
+            return maps;
+        }
+    }
+}
+
+/// Whether `expr` builds a map nothing else holds
+fn is_fresh(expr: &Expr) -> bool {
+    match expr {
+        Expr::Map { .. } | Expr::MapComprehension { .. } => true,
+        Expr::FunctionCall { function, .. } => matches!(
+            &**function,
+            Expr::Variable { name, .. } if name == DUP || name == "Map::new"
+        ),
+        _ => false,
+    }
+}
+
+/// Whether `statement` writes to an entry of the map `name`
+fn written_in(statement: &Statement, name: &str) -> bool {
+    let mut written = false;
+    visit_statements(statement, &mut |statement| {
+        if let Statement::Assignment {
+            pattern: Pattern::MapAccess { map, .. },
+            ..
+        } = statement
+        {
+            written |= matches!(&**map, Expr::Variable { name: target, .. } if target == name);
+        }
+    });
+    written
+}
+
+/// Whether `statement` copies the map `name`
+fn copies(statement: &Statement, name: &str) -> bool {
+    let mut copied = false;
+    visit_statements(statement, &mut |statement| {
+        if let Statement::Assignment {
+            pattern: Pattern::Variable { name: target, .. },
+            value: Expr::FunctionCall { function, .. },
+            ..
+        } = statement
+        {
+            copied |=
+                target == name && matches!(&**function, Expr::Variable { name, .. } if name == DUP);
+        }
+    });
+    copied
+}
+
+/// `name = %dup(name)`
+fn dup(name: &str, location: &Location) -> Statement {
+    let variable = Expr::Variable {
+        name: Symbol::intern(name),
+        location: location.clone(),
+    };
+    Statement::Assignment {
+        pattern: Pattern::Variable {
+            name: name.into(),
+            location: location.clone(),
+        },
+        value: Expr::FunctionCall {
+            function: Box::new(Expr::Variable {
+                name: Symbol::intern(DUP),
+                location: location.clone(),
+            }),
+            args: vec![variable],
+            named_args: HashMap::new(),
+            location: location.clone(),
+        },
+        location: location.clone(),
+    }
+}
+
+/// The names a pattern binds
+fn bound_names(pattern: &Pattern) -> Vec<Symbol> {
+    match pattern {
+        Pattern::Variable { name, .. } => vec![Symbol::intern(name)],
+        Pattern::Tuple { elements, .. } | Pattern::TupleConstructor { args: elements, .. } => {
+            elements.iter().flat_map(bound_names).collect()
+        }
+        Pattern::Constructor { fields, .. } => fields.values().flat_map(bound_names).collect(),
+        Pattern::Member { parent, .. } => bound_names(parent),
+        Pattern::Literal { .. } | Pattern::MapAccess { .. } | Pattern::Wildcard { .. } => {
+            Vec::new()
+        }
+    }
+}
+
+/// How often every map variable is used by `block`
+fn count_block(block: &Block, maps: &HashSet<Symbol>) -> HashMap<Symbol, usize> {
+    let mut counts = HashMap::new();
+    for statement in &block.statements {
+        add(&mut counts, count_statement(statement, maps), 1);
+    }
+    counts
+}
+
+fn count_statement(statement: &Statement, maps: &HashSet<Symbol>) -> HashMap<Symbol, usize> {
+    let mut counts = HashMap::new();
+    let (exprs, blocks) = parts(statement);
+    for expr in exprs {
+        visit_expr(expr, &mut |expr| {
+            if let Expr::Variable { name, .. } = expr {
+                if maps.contains(name) {
+                    *counts.entry(*name).or_insert(0) += 1;
+                }
+            }
+        });
+    }
+    let looping = matches!(
+        statement,
+        Statement::While { .. }
+            | Statement::For { .. }
+            | Statement::Fold { .. }
+            | Statement::Bend { .. }
+    );
+    let branching = matches!(
+        statement,
+        Statement::If { .. }
+            | Statement::Match { .. }
+            | Statement::Switch { .. }
+            | Statement::TryCatch { .. }
+    );
+    if branching {
+        let mut most = HashMap::new();
+        for block in blocks {
+            for (name, count) in count_block(block, maps) {
+                let entry = most.entry(name).or_insert(0);
+                *entry = count.max(*entry);
+            }
+        }
+        add(&mut counts, most, 1);
+    } else {
+        for block in blocks {
+            add(
+                &mut counts,
+                count_block(block, maps),
+                if looping { 2 } else { 1 },
+            );
+        }
+    }
+    counts
+}
+
+fn add(counts: &mut HashMap<Symbol, usize>, more: HashMap<Symbol, usize>, times: usize) {
+    for (name, count) in more {
+        *counts.entry(name).or_insert(0) += count * times;
+    }
+}
+
+/// The expressions of `statement` itself and the blocks nested in it
+fn parts(statement: &Statement) -> (Vec<&Expr>, Vec<&Block>) {
+    match statement {
+        Statement::Assignment { pattern, value, .. } => {
+            let mut exprs = vec![value];
+            if let Pattern::MapAccess { map, key, .. } = pattern {
+                exprs.extend([&**map, &**key]);
+            }
+            (exprs, Vec::new())
+        }
+        Statement::Use { value, .. }
+        | Statement::InPlaceOp { value, .. }
+        | Statement::Return { value, .. }
+        | Statement::Open { value, .. }
+        | Statement::Expr { expr: value, .. } => (vec![value], Vec::new()),
+        Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => (vec![condition], vec![then_branch, else_branch]),
+        Statement::While {
+            condition, body, ..
+        } => (vec![condition], vec![body]),
+        Statement::For {
+            start, end, body, ..
+        } => (vec![start, end], vec![body]),
+        Statement::Switch { value, cases, .. } => {
+            (vec![value], cases.iter().map(|case| &case.body).collect())
+        }
+        Statement::Match { value, cases, .. } | Statement::Fold { value, cases, .. } => {
+            (vec![value], cases.iter().map(|case| &case.body).collect())
+        }
+        Statement::Bend {
+            initial_states,
+            condition,
+            body,
+            else_body,
+            ..
+        } => (
+            initial_states
+                .iter()
+                .map(|(_, value)| value)
+                .chain([condition])
+                .collect(),
+            std::iter::once(body).chain(else_body).collect(),
+        ),
+        Statement::With { body, .. } | Statement::Unchecked { body, .. } => {
+            (Vec::new(), vec![body])
+        }
+        Statement::TryCatch {
+            try_block,
+            catch_blocks,
+            ..
+        } => (
+            Vec::new(),
+            std::iter::once(try_block)
+                .chain(catch_blocks.iter().map(|catch| &catch.body))
+                .collect(),
+        ),
+        Statement::Emit { args, .. } => (args.iter().collect(), Vec::new()),
+        Statement::Revert {
+            condition, reason, ..
+        } => (condition.iter().chain(reason).collect(), Vec::new()),
+        Statement::LocalDef { .. } => (Vec::new(), Vec::new()),
+    }
+}
+
+/// Run `visit` on every expression of `statement` and of the statements in
+/// it, outermost first
+pub(crate) fn visit_statement(statement: &Statement, visit: &mut dyn FnMut(&Expr)) {
+    let (exprs, blocks) = parts(statement);
+    for expr in exprs {
+        visit_expr(expr, visit);
+    }
+    for block in blocks {
+        for statement in &block.statements {
+            visit_statement(statement, visit);
+        }
+    }
+}
+
+pub(crate) fn visit_expr(expr: &Expr, visit: &mut dyn FnMut(&Expr)) {
+    visit(expr);
+    if let Expr::Block { block, .. } = expr {
+        for statement in &block.statements {
+            visit_statement(statement, visit);
+        }
+    }
+    for child in children(expr) {
+        visit_expr(child, visit);
+    }
+}
+
+/// Run `visit` on `statement` and every statement in it, also in blocks of
+/// its expressions
+pub(crate) fn visit_statements(statement: &Statement, visit: &mut dyn FnMut(&Statement)) {
+    visit(statement);
+    let (exprs, blocks) = parts(statement);
+    let mut nested = Vec::new();
+    for expr in exprs {
+        blocks_in(expr, &mut nested);
+    }
+    for block in nested.into_iter().chain(blocks) {
+        for statement in &block.statements {
+            visit_statements(statement, visit);
+        }
+    }
+}
+
+/// The blocks in `expr`, but those nested in them
+fn blocks_in<'a>(expr: &'a Expr, blocks: &mut Vec<&'a Block>) {
+    if let Expr::Block { block, .. } = expr {
+        blocks.push(block);
+    }
+    for child in children(expr) {
+        blocks_in(child, blocks);
+    }
+}
+
+/// The expressions directly inside `expr`, but those in blocks
+fn operands_mut(expr: &mut Expr) -> Vec<&mut Expr> {
+    match expr {
+        Expr::Tuple { elements, .. }
+        | Expr::List { elements, .. }
+        | Expr::Array { elements, .. }
+        | Expr::Superposition { elements, .. } => elements.iter_mut().collect(),
+        Expr::Constructor {
+            args, named_args, ..
+        } => args
+            .iter_mut()
+            .chain(named_args_in_order_mut(named_args))
+            .collect(),
+        Expr::FunctionCall {
+            function,
+            args,
+            named_args,
+            ..
+        } => std::iter::once(&mut **function)
+            .chain(args.iter_mut())
+            .chain(named_args_in_order_mut(named_args))
+            .collect(),
+        Expr::Lambda { body, .. }
+        | Expr::UnsccopedLambda { body, .. }
+        | Expr::TreeLeaf { value: body, .. }
+        | Expr::Try { expr: body, .. }
+        | Expr::UnaryOp { operand: body, .. }
+        | Expr::FieldAccess { object: body, .. } => vec![body],
+        Expr::BinaryOp { left, right, .. }
+        | Expr::TreeNode { left, right, .. }
+        | Expr::MapAccess {
+            map: left,
+            key: right,
+            ..
+        } => vec![left, right],
+        Expr::Map { entries, .. } => entries
+            .iter_mut()
+            .flat_map(|(key, value)| [key, value])
+            .collect(),
+        Expr::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => vec![condition, then_branch, else_branch],
+        Expr::ListComprehension {
+            element,
+            iterable,
+            condition,
+            ..
+        } => [element, iterable]
+            .into_iter()
+            .chain(condition)
+            .map(|expr| &mut **expr)
+            .collect(),
+        Expr::MapComprehension {
+            key,
+            value,
+            iterable,
+            condition,
+            ..
+        } => [key, value, iterable]
+            .into_iter()
+            .chain(condition)
+            .map(|expr| &mut **expr)
+            .collect(),
+        Expr::InlineAsm { inputs, .. } => inputs.iter_mut().map(|(_, value)| value).collect(),
+        Expr::Variable { .. } | Expr::Literal { .. } | Expr::Block { .. } | Expr::Eraser { .. } => {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parser::Parser;
+
+    /// The variables copied by the first function of `source`, in order,
+    /// each with the index of the statement of the body holding the copy
+    fn dups(source: &str) -> Vec<(usize, String)> {
+        let mut program = Parser::new(source).parse_program().unwrap();
+        Linearity::new().lower_program(&mut program);
+        let Definition::FunctionDef { body, .. } = &program.definitions[0] else {
+            panic!("not a function");
+        };
+        let mut dups = Vec::new();
+        for (index, statement) in body.statements.iter().enumerate() {
+            visit_statements(statement, &mut |statement| {
+                if let Statement::Assignment {
+                    pattern: Pattern::Variable { name, .. },
+                    value: Expr::FunctionCall { function, .. },
+                    ..
+                } = statement
+                {
+                    if matches!(&**function, Expr::Variable { name, .. } if name == DUP) {
+                        dups.push((index, name.to_string()));
+                    }
+                }
+            });
+        }
+        dups
+    }
+
+    #[test]
+    fn test_analyze() {
+        let program = Parser::new(
+            "fn main(m: Map<u24, u24>, n: Map<u24, u24>) -> u24 {\n    a = {1: 2};\n    a[1] = 3;\n    b = {1: 2};\n    b[2] = b[1];\n    n[1] = 2;\n    if m[1] > 0 { m[1] = 0; } else { m[1] = 1; }\n    return 0;\n}\n",
+        )
+        .parse_program()
+        .unwrap();
+        let usages: Vec<(String, Usage)> = analyze(&program)
+            .into_iter()
+            .map(|usage| (usage.name, usage.usage))
+            .collect();
+        assert_eq!(
+            usages,
+            vec![
+                ("a".to_string(), Usage::Linear),
+                ("b".to_string(), Usage::Duplicated),
+                ("m".to_string(), Usage::Duplicated),
+                ("n".to_string(), Usage::Linear),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_writes_to_shared_maps_copy_them() {
+        // Built by the function and not shared
+        assert!(dups("fn main() -> u24 {\n    a = {1: 2};\n    a[1] = 3;\n    a[2] = a[1];\n    return a[2];\n}\n").is_empty());
+        // Moved on its last use
+        assert!(dups("fn main() -> u24 {\n    a = Map::new();\n    b = a;\n    b[1] = 3;\n    return b[1];\n}\n").is_empty());
+
+        // Still read through the other variable
+        assert_eq!(
+            dups("fn main() -> u24 {\n    a = {1: 2};\n    b = a;\n    b[1] = 3;\n    return a[1];\n}\n"),
+            vec![(2, "b".to_string())]
+        );
+        // Shared with the caller, copied once
+        assert_eq!(
+            dups("fn main(m: Map<u24, u24>) -> u24 {\n    m[1] = 2;\n    m[2] = 3;\n    return m[1];\n}\n"),
+            vec![(0, "m".to_string())]
+        );
+        // Only in the branch writing it
+        assert_eq!(
+            dups("fn main(m: Map<u24, u24>, c: u24) -> u24 {\n    if c > 0 {\n        m[1] = 2;\n    } else {\n        c = 0;\n    }\n    m[2] = 3;\n    return m[1];\n}\n"),
+            vec![(0, "m".to_string()), (1, "m".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_loops_copy_before_they_start() {
+        assert_eq!(
+            dups("fn main(m: Map<u24, u24>) -> u24 {\n    for i in range(0, 10) {\n        m[i] = i;\n    }\n    return m[1];\n}\n"),
+            vec![(0, "m".to_string())]
+        );
+
+        // Shared again by every iteration
+        let mut program = Parser::new(
+            "fn main(m: Map<u24, u24>) -> u24 {\n    n = m;\n    for i in range(0, 10) {\n        n = m;\n        m[i] = n[i];\n    }\n    return m[1];\n}\n",
+        )
+        .parse_program()
+        .unwrap();
+        let mut linearity = Linearity::new();
+        linearity.lower_program(&mut program);
+        assert_eq!(linearity.inserted_dups, 1);
+        let Definition::FunctionDef { body, .. } = &program.definitions[0] else {
+            panic!("not a function");
+        };
+        let Statement::For { body, .. } = &body.statements[1] else {
+            panic!("not a loop");
+        };
+        assert_eq!(body.statements.len(), 3);
+    }
+}
@@ -0,0 +1,952 @@
+//! Lints: warnings about code that compiles but is probably a mistake
+//!
+//! Every lint has a name and warns by default. Its level can be changed
+//! for a whole build with `--allow`, `--warn` and `--deny`, and for one
+//! function with the `#[allow(...)]`, `#[warn(...)]` and `#[deny(...)]`
+//! attributes, which take precedence. `--deny-warnings` then turns every
+//! lint still warning into an error.
+//!
+//! Uses of deprecated definitions are found by the type checker; the other
+//! lints only need the syntax tree and are found by [`lint_program`].
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compiler::analyzer::attributes::lint_levels;
+use crate::compiler::intern::Symbol;
+use crate::compiler::parser::ast::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Lint {
+    /// A local or parameter that is never read
+    UnusedVariables,
+    /// An imported name that is never used
+    UnusedImports,
+    /// Statements after a `return` or `revert`
+    UnreachableCode,
+    /// A binding hiding a variable of an enclosing scope
+    Shadowing,
+    /// A use of a function or type marked `#[deprecated]`
+    Deprecated,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 5] = [
+        Lint::UnusedVariables,
+        Lint::UnusedImports,
+        Lint::UnreachableCode,
+        Lint::Shadowing,
+        Lint::Deprecated,
+    ];
+
+    /// The name used on the command line and in attributes
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "unused_variables",
+            Lint::UnusedImports => "unused_imports",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::Shadowing => "shadowing",
+            Lint::Deprecated => "deprecated",
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Lint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Lint::ALL
+            .into_iter()
+            .find(|lint| lint.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Lint::ALL.iter().map(|lint| lint.name()).collect();
+                format!("Unknown lint '{}', expected one of {}", s, names.join(", "))
+            })
+    }
+}
+
+/// What becomes of the warnings of a lint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    /// The level set by an attribute named `name`, if it sets one
+    pub fn from_attribute(name: &str) -> Option<Self> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+/// A problem found by a lint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Warning {
+    pub lint: Lint,
+    pub message: String,
+    pub location: Location,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (line {}, column {})",
+            self.message, self.location.line, self.location.column
+        )
+    }
+}
+
+/// Levels of the lints for a build
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LintLevels {
+    levels: HashMap<Lint, Level>,
+    deny_warnings: bool,
+}
+
+impl LintLevels {
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+
+    /// Report every lint that would warn as an error instead
+    pub fn deny_warnings(&mut self) {
+        self.deny_warnings = true;
+    }
+
+    /// The level of `warning`, found in `program`: the one set by the
+    /// attributes of the function it is in, or else the one of the build
+    pub fn level(&self, program: &Program, warning: &Warning) -> Level {
+        let level = enclosing_function(&program.definitions, warning.location.start)
+            .and_then(|attributes| {
+                lint_levels(attributes)
+                    .ok()?
+                    .into_iter()
+                    .rev()
+                    .find_map(|(lint, level)| (lint == warning.lint).then_some(level))
+            })
+            .or_else(|| self.levels.get(&warning.lint).copied())
+            .unwrap_or(Level::Warn);
+        match level {
+            Level::Warn if self.deny_warnings => Level::Deny,
+            level => level,
+        }
+    }
+}
+
+/// The attributes of the function whose source contains `offset`
+fn enclosing_function(definitions: &[Definition], offset: usize) -> Option<&[Attribute]> {
+    definitions.iter().find_map(|definition| match definition {
+        Definition::FunctionDef {
+            attributes,
+            location,
+            ..
+        } => (location.start..location.end)
+            .contains(&offset)
+            .then_some(attributes.as_slice()),
+        Definition::ObjectDef { functions, .. }
+        | Definition::InterfaceDef { functions, .. }
+        | Definition::Module {
+            definitions: functions,
+            ..
+        } => enclosing_function(functions, offset),
+        _ => None,
+    })
+}
+
+/// Find unused variables and imports, unreachable code and shadowing
+pub fn lint_program(program: &Program) -> Vec<Warning> {
+    let mut linter = Linter::default();
+    for definition in &program.definitions {
+        if let Definition::StorageDef { fields, .. } = definition {
+            linter.storage.extend(fields.iter().map(|field| field.name));
+        }
+    }
+    for definition in &program.definitions {
+        linter.definition(definition);
+    }
+    linter.unused_imports(&program.imports);
+    linter
+        .warnings
+        .sort_by_key(|warning| warning.location.start);
+    linter.warnings
+}
+
+struct Binding {
+    name: String,
+    location: Location,
+    parameter: bool,
+    read: bool,
+}
+
+#[derive(Default)]
+struct Linter {
+    /// Local scopes, innermost last, mapping names to their bindings
+    scopes: Vec<HashMap<String, usize>>,
+    /// Index in `scopes` of the scope of each function being linted,
+    /// where plain assignments bind
+    function_scopes: Vec<usize>,
+    bindings: Vec<Binding>,
+    /// Names used that are not locals
+    globals: HashSet<String>,
+    /// Storage fields, which assignments write instead of binding
+    storage: HashSet<Symbol>,
+    warnings: Vec<Warning>,
+}
+
+impl Linter {
+    fn warn(&mut self, lint: Lint, message: String, location: &Location) {
+        self.warnings.push(Warning {
+            lint,
+            message,
+            location: location.clone(),
+        });
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied()
+    }
+
+    fn scoped(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashMap::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    /// Bind `name` in the scope at `scope`, warning when it hides a
+    /// variable of an enclosing scope
+    fn define(&mut self, name: &str, location: &Location, parameter: bool, scope: usize) {
+        if let Some(outer) = self.lookup(name) {
+            let line = self.bindings[outer].location.line;
+            self.warn(
+                Lint::Shadowing,
+                format!("'{}' shadows the variable bound at line {}", name, line),
+                location,
+            );
+        }
+        self.bindings.push(Binding {
+            name: name.to_string(),
+            location: location.clone(),
+            parameter,
+            read: false,
+        });
+        self.scopes[scope].insert(name.to_string(), self.bindings.len() - 1);
+    }
+
+    /// Bind `name` in the innermost scope
+    fn define_local(&mut self, name: &str, location: &Location) {
+        self.define(name, location, false, self.scopes.len() - 1);
+    }
+
+    /// Read `name`, a local or a global. Dotted names like `self.owner`
+    /// read the variable before the first dot.
+    fn read(&mut self, name: &str) {
+        let base = name.split('.').next().unwrap_or(name);
+        match self.lookup(base) {
+            Some(binding) => self.bindings[binding].read = true,
+            None => {
+                self.globals.insert(name.to_string());
+            }
+        }
+    }
+
+    fn definition(&mut self, definition: &Definition) {
+        match definition {
+            Definition::FunctionDef {
+                params,
+                return_type,
+                body,
+                attributes,
+                ..
+            } => self.function(params, return_type.as_ref(), body, attributes),
+            Definition::TypeDef { variants, .. } | Definition::ErrorDef { variants, .. } => {
+                for field in variants.iter().flat_map(|variant| &variant.fields) {
+                    if let Some(ty) = &field.type_annotation {
+                        self.type_names(ty);
+                    }
+                }
+            }
+            Definition::ObjectDef {
+                fields, functions, ..
+            } => {
+                for field in fields {
+                    if let Some(ty) = &field.type_annotation {
+                        self.type_names(ty);
+                    }
+                }
+                for function in functions {
+                    self.definition(function);
+                }
+            }
+            Definition::TypeAlias { target_type, .. } => self.type_names(target_type),
+            Definition::Module { definitions, .. } => {
+                for definition in definitions {
+                    self.definition(definition);
+                }
+            }
+            Definition::EventDef { fields, .. } => {
+                for field in fields {
+                    self.type_names(&field.ty);
+                }
+            }
+            Definition::StorageDef { fields, .. } => {
+                for field in fields {
+                    self.type_names(&field.ty);
+                }
+            }
+            Definition::ContractDef {
+                fields,
+                definitions,
+                ..
+            } => {
+                for field in fields {
+                    self.type_names(&field.ty);
+                }
+                for definition in definitions {
+                    self.definition(definition);
+                }
+            }
+            Definition::GuardDef {
+                params,
+                before,
+                after,
+                ..
+            } => {
+                // Both halves of a guard share one scope
+                let body = Block {
+                    statements: before
+                        .statements
+                        .iter()
+                        .chain(&after.statements)
+                        .cloned()
+                        .collect(),
+                    location: before.location.clone(),
+                };
+                self.function(params, None, &body, &[]);
+            }
+            Definition::InterfaceDef { functions, .. } => {
+                for function in functions {
+                    self.definition(function);
+                }
+            }
+        }
+    }
+
+    fn function(
+        &mut self,
+        params: &[Parameter],
+        return_type: Option<&Type>,
+        body: &Block,
+        attributes: &[Attribute],
+    ) {
+        if let Some(ty) = return_type {
+            self.type_names(ty);
+        }
+        let first = self.bindings.len();
+        self.function_scopes.push(self.scopes.len());
+        self.scoped(|linter| {
+            for param in params {
+                linter.type_names(&param.ty);
+                let location = Location {
+                    end: param.location.start + param.name.len(),
+                    ..param.location.clone()
+                };
+                linter.define_local(&param.name, &location);
+                let binding = linter.bindings.len() - 1;
+                linter.bindings[binding].parameter = true;
+                // Declarations without a body have nothing to read them
+                linter.bindings[binding].read = body.statements.is_empty();
+            }
+            // Guards are called with the parameters of the function
+            for attribute in attributes {
+                linter.exprs(&attribute.args);
+            }
+            linter.statements(body);
+        });
+        self.function_scopes.pop();
+
+        for binding in self.bindings.split_off(first) {
+            if binding.read || binding.name.starts_with('_') || binding.name == "self" {
+                continue;
+            }
+            let kind = if binding.parameter {
+                "parameter"
+            } else {
+                "variable"
+            };
+            self.warn(
+                Lint::UnusedVariables,
+                format!("Unused {} '{}'", kind, binding.name),
+                &binding.location,
+            );
+        }
+    }
+
+    fn type_names(&mut self, ty: &Type) {
+        match ty {
+            Type::Named { name, params, .. } => {
+                self.globals.insert(name.to_string());
+                for param in params {
+                    self.type_names(param);
+                }
+            }
+            Type::Function { param, result, .. } => {
+                self.type_names(param);
+                self.type_names(result);
+            }
+            Type::Effect { input, output, .. } => {
+                self.type_names(input);
+                self.type_names(output);
+            }
+            Type::Tuple { elements, .. } => {
+                for element in elements {
+                    self.type_names(element);
+                }
+            }
+            Type::Constrained { base, .. } => self.type_names(base),
+            _ => {}
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scoped(|linter| linter.statements(block));
+    }
+
+    /// The statements of a block, warning about the first one that can
+    /// never run
+    fn statements(&mut self, block: &Block) {
+        let mut terminated = false;
+        for statement in &block.statements {
+            if terminated {
+                self.warn(
+                    Lint::UnreachableCode,
+                    "Unreachable statement".to_string(),
+                    statement.location(),
+                );
+                terminated = false;
+            }
+            self.statement(statement);
+            terminated |= terminates(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Assignment { pattern, value, .. } => {
+                self.expr(value);
+                self.assign(pattern);
+            }
+            Statement::Use {
+                name,
+                value,
+                location,
+            } => {
+                self.expr(value);
+                self.define_local(name, location);
+            }
+            Statement::InPlaceOp { target, value, .. } => {
+                self.read(target);
+                self.expr(value);
+            }
+            Statement::Return { value, .. } | Statement::Expr { expr: value, .. } => {
+                self.expr(value)
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expr(condition);
+                self.block(then_branch);
+                self.block(else_branch);
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.expr(condition);
+                self.block(body);
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                location,
+                ..
+            } => {
+                self.expr(start);
+                self.expr(end);
+                self.scoped(|linter| {
+                    linter.define_local(variable, location);
+                    linter.statements(body);
+                });
+            }
+            Statement::Switch { value, cases, .. } => {
+                self.expr(value);
+                for case in cases {
+                    self.block(&case.body);
+                }
+            }
+            Statement::Match { value, cases, .. } | Statement::Fold { value, cases, .. } => {
+                self.expr(value);
+                for case in cases {
+                    self.scoped(|linter| {
+                        linter.bind(&case.pattern);
+                        linter.statements(&case.body);
+                    });
+                }
+            }
+            Statement::Bend {
+                initial_states,
+                condition,
+                body,
+                else_body,
+                location,
+            } => {
+                for (_, value) in initial_states {
+                    self.expr(value);
+                }
+                self.scoped(|linter| {
+                    for (name, _) in initial_states {
+                        linter.define_local(name, location);
+                    }
+                    linter.expr(condition);
+                    linter.block(body);
+                    if let Some(else_body) = else_body {
+                        linter.block(else_body);
+                    }
+                });
+            }
+            Statement::Open {
+                type_name, value, ..
+            } => {
+                self.globals.insert(type_name.clone());
+                self.expr(value);
+            }
+            Statement::With { body, .. } | Statement::Unchecked { body, .. } => self.block(body),
+            Statement::LocalDef { function_def, .. } => {
+                if let Definition::FunctionDef {
+                    name,
+                    params,
+                    return_type,
+                    body,
+                    attributes,
+                    location,
+                    ..
+                } = function_def.as_ref()
+                {
+                    self.define_local(name, location);
+                    // A local function may be defined only to be called
+                    // from itself, or kept for later
+                    let binding = self.bindings.len() - 1;
+                    self.bindings[binding].read = true;
+                    self.function(params, return_type.as_ref(), body, attributes);
+                }
+            }
+            Statement::TryCatch {
+                try_block,
+                catch_blocks,
+                ..
+            } => {
+                self.block(try_block);
+                for catch in catch_blocks {
+                    if let Some(error_type) = &catch.error_type {
+                        self.globals.insert(error_type.clone());
+                    }
+                    self.scoped(|linter| {
+                        if let Some(error_var) = &catch.error_var {
+                            linter.define_local(error_var, &catch.location);
+                        }
+                        linter.statements(&catch.body);
+                    });
+                }
+            }
+            Statement::Emit { event, args, .. } => {
+                self.globals.insert(event.to_string());
+                self.exprs(args);
+            }
+            Statement::Revert {
+                condition, reason, ..
+            } => {
+                for expr in condition.iter().chain(reason) {
+                    self.expr(expr);
+                }
+            }
+        }
+    }
+
+    /// Assign to the names of a pattern. A name not bound yet is bound for
+    /// the rest of the function, as it stays in scope after the block it
+    /// is assigned in.
+    fn assign(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Variable { name, location } if !is_constructor(name) => {
+                if self.lookup(name).is_none() && !self.storage.contains(name) {
+                    let scope = self.function_scopes.last().copied().unwrap_or_default();
+                    self.define(name, &name_location(name, location), false, scope);
+                }
+            }
+            Pattern::Tuple { elements, .. } => {
+                for element in elements {
+                    self.assign(element);
+                }
+            }
+            other => self.bind(other),
+        }
+    }
+
+    /// Bind the names of a pattern in the innermost scope
+    fn bind(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Variable { name, .. } if is_constructor(name) => {
+                self.globals.insert(name.to_string());
+            }
+            Pattern::Variable { name, location } => {
+                self.define_local(name, &name_location(name, location))
+            }
+            Pattern::Tuple { elements, .. } => {
+                for element in elements {
+                    self.bind(element);
+                }
+            }
+            Pattern::Constructor { name, fields, .. } => {
+                self.globals.insert(name.to_string());
+                let mut fields: Vec<_> = fields.iter().collect();
+                fields.sort_by_key(|(field, _)| *field);
+                for (_, field) in fields {
+                    self.bind(field);
+                }
+            }
+            Pattern::TupleConstructor { name, args, .. } => {
+                self.globals.insert(name.to_string());
+                for arg in args {
+                    self.bind(arg);
+                }
+            }
+            // Writing to a member or an entry reads what it belongs to
+            Pattern::Member { parent, .. } => match parent.as_ref() {
+                Pattern::Variable { name, .. } => self.read(name),
+                parent => self.bind(parent),
+            },
+            Pattern::MapAccess { map, key, .. } => {
+                self.expr(map);
+                self.expr(key);
+            }
+            Pattern::Literal { .. } | Pattern::Wildcard { .. } => {}
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Variable { name, .. } => self.read(name),
+            Expr::Constructor {
+                name,
+                args,
+                named_args,
+                ..
+            } => {
+                self.globals.insert(name.to_string());
+                self.exprs(args);
+                for value in named_args.values() {
+                    self.expr(value);
+                }
+            }
+            Expr::FunctionCall {
+                function,
+                args,
+                named_args,
+                ..
+            } => {
+                self.expr(function);
+                self.exprs(args);
+                for value in named_args.values() {
+                    self.expr(value);
+                }
+            }
+            Expr::Lambda { params, body, .. } => self.scoped(|linter| {
+                for param in params {
+                    linter.type_names(&param.ty);
+                    linter.define_local(&param.name, &param.location);
+                    // Lambdas often ignore some of what they are given
+                    let binding = linter.bindings.len() - 1;
+                    linter.bindings[binding].read = true;
+                }
+                linter.expr(body);
+            }),
+            Expr::UnsccopedLambda {
+                params,
+                body,
+                location,
+            } => self.scoped(|linter| {
+                for param in params {
+                    linter.define_local(param, location);
+                    let binding = linter.bindings.len() - 1;
+                    linter.bindings[binding].read = true;
+                }
+                linter.expr(body);
+            }),
+            Expr::BinaryOp { left, right, .. }
+            | Expr::TreeNode { left, right, .. }
+            | Expr::MapAccess {
+                map: left,
+                key: right,
+                ..
+            } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::UnaryOp { operand: inner, .. }
+            | Expr::FieldAccess { object: inner, .. }
+            | Expr::TreeLeaf { value: inner, .. }
+            | Expr::Try { expr: inner, .. } => self.expr(inner),
+            Expr::Tuple { elements, .. }
+            | Expr::List { elements, .. }
+            | Expr::Array { elements, .. }
+            | Expr::Superposition { elements, .. } => self.exprs(elements),
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expr(condition);
+                self.expr(then_branch);
+                self.expr(else_branch);
+            }
+            Expr::Block { block, .. } => self.block(block),
+            Expr::InlineAsm { inputs, .. } => {
+                for (_, input) in inputs {
+                    self.expr(input);
+                }
+            }
+            Expr::ListComprehension {
+                element,
+                variable,
+                iterable,
+                condition,
+                location,
+            } => {
+                self.expr(iterable);
+                self.scoped(|linter| {
+                    linter.define_local(variable, location);
+                    linter.expr(element);
+                    if let Some(condition) = condition {
+                        linter.expr(condition);
+                    }
+                });
+            }
+            Expr::MapComprehension {
+                key,
+                value,
+                variable,
+                iterable,
+                condition,
+                location,
+            } => {
+                self.expr(iterable);
+                self.scoped(|linter| {
+                    linter.define_local(variable, location);
+                    linter.expr(key);
+                    linter.expr(value);
+                    if let Some(condition) = condition {
+                        linter.expr(condition);
+                    }
+                });
+            }
+            Expr::Literal { .. } | Expr::Eraser { .. } => {}
+        }
+    }
+
+    /// Warn about the imported names nothing uses. Importing a type also
+    /// imports its variants, which may be used on their own, so a type
+    /// counts as used when any unknown constructor is.
+    fn unused_imports(&mut self, imports: &[Import]) {
+        let unknown_constructor = self
+            .globals
+            .iter()
+            .any(|name| !name.contains('/') && is_constructor(name));
+        for import in imports {
+            let Import::FromImport { names, .. } = import else {
+                continue;
+            };
+            for imported in names {
+                let name = imported.alias.as_ref().unwrap_or(&imported.name);
+                let prefix = format!("{}/", name);
+                let used = self.globals.iter().any(|global| {
+                    global == name
+                        || global.starts_with(&prefix)
+                        || global.split('.').next() == Some(name.as_str())
+                });
+                let is_type = !name.contains('/') && is_constructor(name);
+                // Glob imports bring in whatever the module exports
+                if used || name == "*" || name.starts_with('_') || (is_type && unknown_constructor)
+                {
+                    continue;
+                }
+                self.warn(
+                    Lint::UnusedImports,
+                    format!("Unused import '{}'", name),
+                    &imported.location,
+                );
+            }
+        }
+    }
+}
+
+/// Whether a statement always leaves the block it is in
+fn terminates(statement: &Statement) -> bool {
+    let block_terminates = |block: &Block| block.statements.last().is_some_and(terminates);
+    match statement {
+        Statement::Return { .. }
+        | Statement::Revert {
+            kind: RevertKind::Revert,
+            ..
+        } => true,
+        Statement::If {
+            then_branch,
+            else_branch,
+            ..
+        } => block_terminates(then_branch) && block_terminates(else_branch),
+        _ => false,
+    }
+}
+
+/// Constructors in patterns and expressions are capitalized, like
+/// `Empty` or `Option/Some`, where variables are not
+fn is_constructor(name: &str) -> bool {
+    name.contains('/') || name.starts_with(|c: char| c.is_uppercase())
+}
+
+/// The location of just the name at the start of `location`
+fn name_location(name: &str, location: &Location) -> Location {
+    Location {
+        end: location.start + name.len(),
+        ..location.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parser::Parser;
+
+    fn lint(source: &str) -> Vec<(Lint, String)> {
+        let program = Parser::new(source).parse_program().unwrap();
+        lint_program(&program)
+            .into_iter()
+            .map(|warning| (warning.lint, warning.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_unused_variables() {
+        let warnings = lint(
+            "fn main(used: u24, unused: u24, _ignored: u24) -> u24 {\n    x = 1;\n    y = used;\n    y = y + 1;\n    return y;\n}\n",
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                (
+                    Lint::UnusedVariables,
+                    "Unused parameter 'unused'".to_string()
+                ),
+                (Lint::UnusedVariables, "Unused variable 'x'".to_string()),
+            ]
+        );
+
+        // Assigned in both branches and read after them
+        let warnings = lint(
+            "fn pick(flag: Bool) -> u24 {\n    if flag {\n        x = 1;\n    } else {\n        x = 2;\n    }\n    return x;\n}\n",
+        );
+        assert!(warnings.is_empty(), "{:?}", warnings);
+
+        // Assigning a storage field writes it
+        let warnings = lint(
+            "storage {\n    owner: u24,\n}\n\nfn init(creator: u24) -> u24 {\n    owner = creator;\n    return 0;\n}\n",
+        );
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn test_unreachable_code_and_shadowing() {
+        let warnings = lint(
+            "fn main(i: u24) -> u24 {\n    for i in range(0, 3) {\n        emit Seen(i);\n    }\n    return i;\n    return 0;\n}\n",
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                (
+                    Lint::Shadowing,
+                    "'i' shadows the variable bound at line 1".to_string()
+                ),
+                (Lint::UnreachableCode, "Unreachable statement".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unused_imports() {
+        let warnings = lint(
+            "from std/List import List/map, List/sum;\nfrom std/Option import Option;\n\nfn main() -> u24 {\n    x = Some(1);\n    return List/sum([x]);\n}\n",
+        );
+        assert_eq!(
+            warnings,
+            vec![(Lint::UnusedImports, "Unused import 'List/map'".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_levels_from_attributes_and_flags() {
+        let source = "#[allow(unused_variables)]\nfn quiet(x: u24) -> u24 {\n    return 1;\n}\n\nfn loud(x: u24) -> u24 {\n    return 1;\n}\n";
+        let program = Parser::new(source).parse_program().unwrap();
+        let warnings = lint_program(&program);
+        assert_eq!(warnings.len(), 2);
+
+        let mut levels = LintLevels::default();
+        assert_eq!(levels.level(&program, &warnings[0]), Level::Allow);
+        assert_eq!(levels.level(&program, &warnings[1]), Level::Warn);
+
+        levels.deny_warnings();
+        assert_eq!(levels.level(&program, &warnings[0]), Level::Allow);
+        assert_eq!(levels.level(&program, &warnings[1]), Level::Deny);
+
+        let mut levels = LintLevels::default();
+        levels.set(Lint::UnusedVariables, Level::Allow);
+        assert_eq!(levels.level(&program, &warnings[1]), Level::Allow);
+
+        assert_eq!("shadowing".parse(), Ok(Lint::Shadowing));
+        assert!("unused".parse::<Lint>().is_err());
+    }
+}
@@ -1,9 +1,19 @@
 #![allow(clippy::only_used_in_recursion)]
 
 use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 use thiserror::Error;
 
+use crate::compiler::analyzer::attributes::{
+    self, FunctionAttributes, GuardCall, Mutability, StorageAttributes,
+};
+use crate::compiler::analyzer::lints::{Lint, Warning};
+use crate::compiler::codegen::metadata::signature_params;
+use crate::compiler::codegen::risc_v::{CodegenError, InlineAsm};
+use crate::compiler::intern::Symbol as Name;
+use crate::compiler::lowering::{superposition_copies, MAX_SUPERPOSITION_COPIES};
 use crate::compiler::parser::ast::*;
+use crate::compiler::polkavm::host::{ChainExtension, ChainExtensionRegistry, ExtensionType};
 
 #[derive(Error, Debug, Clone)]
 pub enum TypeError {
@@ -39,6 +49,13 @@ pub enum TypeError {
         column: usize,
     },
 
+    #[error("Undefined event '{name}' at line {line}, column {column}")]
+    UndefinedEvent {
+        name: String,
+        line: usize,
+        column: usize,
+    },
+
     #[error(
         "Incompatible types for operation: {left} {op} {right} at line {line}, column {column}"
     )]
@@ -49,6 +66,51 @@ pub enum TypeError {
         line: usize,
         column: usize,
     },
+
+    #[error("Invalid attribute '{name}': {reason} at line {line}, column {column}")]
+    InvalidAttribute {
+        name: String,
+        reason: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("Cannot {action} in a {mutability} function at line {line}, column {column}")]
+    MutabilityViolation {
+        action: String,
+        mutability: Mutability,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("Literal {value} does not fit in {target} at line {line}, column {column}")]
+    LiteralOutOfRange {
+        value: String,
+        target: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("Invalid inline assembly: {reason} at line {line}, column {column}")]
+    InvalidAssembly {
+        reason: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("Invalid superposition: {reason} at line {line}, column {column}")]
+    InvalidSuperposition {
+        reason: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("Unsupported sharing: {reason} at line {line}, column {column}")]
+    UnsupportedSharing {
+        reason: String,
+        line: usize,
+        column: usize,
+    },
 }
 
 /// Represents a type in the type system
@@ -60,6 +122,13 @@ pub enum TypeInfo {
     U24,
     I24,
     F24,
+    U64,
+    U128,
+    U256,
+    Address,
+    Hash,
+    Bytes,
+    Bool,
     Any,
     None,
     Unknown,
@@ -98,6 +167,13 @@ impl std::fmt::Display for TypeInfo {
             TypeInfo::U24 => write!(f, "u24"),
             TypeInfo::I24 => write!(f, "i24"),
             TypeInfo::F24 => write!(f, "f24"),
+            TypeInfo::U64 => write!(f, "u64"),
+            TypeInfo::U128 => write!(f, "u128"),
+            TypeInfo::U256 => write!(f, "u256"),
+            TypeInfo::Address => write!(f, "Address"),
+            TypeInfo::Hash => write!(f, "Hash"),
+            TypeInfo::Bytes => write!(f, "Bytes"),
+            TypeInfo::Bool => write!(f, "Bool"),
             TypeInfo::Any => write!(f, "Any"),
             TypeInfo::None => write!(f, "None"),
             TypeInfo::Unknown => write!(f, "_"),
@@ -114,24 +190,189 @@ pub enum Symbol {
     Constructor(String, TypeInfo), // Type name, constructor type
 }
 
+/// Names bound in a scope and in the scopes enclosing it
+///
+/// Each scope is a frame of its own bindings. A nested scope shares the
+/// frames of the enclosing ones rather than copying them, so checking a
+/// function costs what its own bindings do, whatever the size of the
+/// program around it.
+#[derive(Debug, Clone)]
+struct Scopes<T> {
+    /// Innermost last. `None` hides a binding of an enclosing frame.
+    frames: Vec<Rc<HashMap<Name, Option<T>>>>,
+}
+
+impl<T: Clone> Scopes<T> {
+    fn new() -> Self {
+        Scopes {
+            frames: vec![Rc::default()],
+        }
+    }
+
+    /// Scopes sharing these frames, with an empty innermost one
+    fn nested(&self) -> Self {
+        let mut nested = self.clone();
+        nested.push();
+        nested
+    }
+
+    fn push(&mut self) {
+        self.frames.push(Rc::default());
+    }
+
+    /// Forget the bindings of the innermost scope
+    fn pop(&mut self) {
+        self.frames.pop();
+    }
+
+    fn get(&self, name: impl Into<Name>) -> Option<&T> {
+        let name = name.into();
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|frame| frame.get(&name))
+            .and_then(Option::as_ref)
+    }
+
+    fn contains_key(&self, name: impl Into<Name>) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Bind `name` in the innermost scope, returning what it shadows
+    fn insert(&mut self, name: impl Into<Name>, value: T) -> Option<T> {
+        let name = name.into();
+        let outer = match self.frames.last().and_then(|frame| frame.get(&name)) {
+            Some(_) => None,
+            None => self.get(name).cloned(),
+        };
+        match self.innermost().insert(name, Some(value)) {
+            Some(shadowed) => shadowed,
+            None => outer,
+        }
+    }
+
+    /// Hide `name` in the innermost scope, returning what it was bound to
+    fn remove(&mut self, name: impl Into<Name>) -> Option<T> {
+        let name = name.into();
+        let removed = self.get(name).cloned();
+        if removed.is_some() {
+            if self.frames.len() == 1 {
+                self.innermost().remove(&name);
+            } else {
+                self.innermost().insert(name, None);
+            }
+        }
+        removed
+    }
+
+    /// The frame of the innermost scope, which is only copied while a
+    /// nested scope still shares it
+    fn innermost(&mut self) -> &mut HashMap<Name, Option<T>> {
+        Rc::make_mut(
+            self.frames
+                .last_mut()
+                .expect("the outermost scope is never popped"),
+        )
+    }
+}
+
+/// Signature of a message declared in an interface
+#[derive(Debug, Clone)]
+struct InterfaceMessage {
+    params: Vec<TypeInfo>,
+    result: TypeInfo,
+    payable: bool,
+}
+
 /// Environment for type checking
+///
+/// The tables of definitions are filled in before any function is checked
+/// and shared with the scopes of the functions, which only add bindings of
+/// their own.
 pub struct TypeChecker {
     /// Symbol table for variables, functions, types, and constructors
-    symbols: HashMap<String, Symbol>,
+    symbols: Scopes<Symbol>,
 
     /// Type definitions
-    types: HashMap<String, Vec<TypeVariant>>,
+    types: Rc<HashMap<Name, Vec<TypeVariant>>>,
 
     /// Type parameters for generic types
-    type_params: HashMap<String, HashSet<String>>,
+    type_params: Rc<HashMap<Name, HashSet<String>>>,
 
     /// Check for cyclic type definitions
-    visited_types: HashSet<String>,
+    visited_types: HashSet<Name>,
 
     /// Track function return types for checking
     current_function_return_type: Option<TypeInfo>,
+
+    /// Event definitions (name -> field types and whether each is indexed)
+    events: Rc<HashMap<Name, Vec<(TypeInfo, bool)>>>,
+
+    /// Persistent storage fields visible in the current scope
+    storage: Scopes<TypeInfo>,
+
+    /// Chain extensions callable by name
+    chain_extensions: Rc<HashMap<Name, ChainExtension>>,
+
+    /// Validated attributes of every function definition
+    function_attributes: Rc<HashMap<Name, FunctionAttributes>>,
+
+    /// Deprecated functions and types, with their deprecation notes
+    deprecated: Rc<HashMap<Name, String>>,
+
+    /// State the function being checked may touch
+    current_mutability: Mutability,
+
+    /// Non-fatal diagnostics, e.g. uses of deprecated definitions
+    warnings: Vec<Warning>,
+
+    /// Guard definitions by name
+    guards: Rc<HashMap<Name, Definition>>,
+
+    /// Whether a guard body is being checked, where `return` is not allowed
+    in_guard: bool,
+
+    /// Whether a postcondition is being checked, where `old(...)` is allowed
+    in_postcondition: bool,
+
+    /// Version of the storage schema, from `#[version(n)]`
+    storage_version: Option<u32>,
+
+    /// Messages of the declared interfaces
+    interfaces: Rc<HashMap<Name, HashMap<Name, InterfaceMessage>>>,
+
+    /// Names of variables and functions with their types, at every place
+    /// they are bound or used
+    name_types: Vec<(String, Location, TypeInfo)>,
+
+    /// Results found for the bodies of functions without an annotated
+    /// result
+    inferred_results: Vec<(String, TypeInfo)>,
+
+    /// Whether the expression being checked is always evaluated by a
+    /// statement lowering duplicates for its superpositions
+    superpositions_allowed: bool,
 }
 
+/// Name of the all-zero `Address` constant
+pub const ZERO_ADDRESS: &str = "ZERO_ADDRESS";
+
+/// Name of the returned value in a postcondition
+pub const RESULT: &str = "result";
+
+/// Builtin reading the value an expression had on entry, in a postcondition
+pub const OLD: &str = "old";
+
+/// Builtin replacing the code of the executing contract, from its next call
+pub const SET_CODE_HASH: &str = "set_code_hash";
+
+/// Builtin reading the storage schema version the storage was last
+/// migrated to
+pub const STORAGE_VERSION: &str = "storage_version";
+
+/// Maximum number of indexed event fields (one topic is the event signature)
+pub const MAX_INDEXED_EVENT_FIELDS: usize = 3;
+
 impl Default for TypeChecker {
     fn default() -> Self {
         Self::new()
@@ -141,11 +382,26 @@ impl Default for TypeChecker {
 impl TypeChecker {
     pub fn new() -> Self {
         let mut checker = TypeChecker {
-            symbols: HashMap::new(),
-            types: HashMap::new(),
-            type_params: HashMap::new(),
+            symbols: Scopes::new(),
+            types: Rc::default(),
+            type_params: Rc::default(),
             visited_types: HashSet::new(),
             current_function_return_type: None,
+            events: Rc::default(),
+            storage: Scopes::new(),
+            chain_extensions: Rc::default(),
+            function_attributes: Rc::default(),
+            deprecated: Rc::default(),
+            current_mutability: Mutability::Mutable,
+            warnings: Vec::new(),
+            guards: Rc::default(),
+            in_guard: false,
+            in_postcondition: false,
+            storage_version: None,
+            interfaces: Rc::default(),
+            name_types: Vec::new(),
+            inferred_results: Vec::new(),
+            superpositions_allowed: false,
         };
 
         // Add built-in types and functions
@@ -154,46 +410,86 @@ impl TypeChecker {
         checker
     }
 
+    /// Make the registered chain extensions callable
+    pub fn with_chain_extensions(mut self, registry: &ChainExtensionRegistry) -> Self {
+        self.chain_extensions = Rc::new(
+            registry
+                .iter()
+                .map(|extension| (Name::from(&extension.name), extension.clone()))
+                .collect(),
+        );
+        self
+    }
+
     /// Add built-in types and functions to the environment
     fn add_builtin_types(&mut self) {
         // Basic types
-        self.symbols.insert("u24".to_string(), Symbol::Type(vec![]));
-        self.symbols.insert("i24".to_string(), Symbol::Type(vec![]));
-        self.symbols.insert("f24".to_string(), Symbol::Type(vec![]));
-        self.symbols.insert("Any".to_string(), Symbol::Type(vec![]));
+        self.symbols.insert("u24", Symbol::Type(vec![]));
+        self.symbols.insert("i24", Symbol::Type(vec![]));
+        self.symbols.insert("f24", Symbol::Type(vec![]));
+        self.symbols.insert("Any", Symbol::Type(vec![]));
         self.symbols
             .insert("None".to_string(), Symbol::Type(vec![]));
+        self.symbols
+            .insert("Address".to_string(), Symbol::Type(vec![]));
+        self.symbols
+            .insert("Hash".to_string(), Symbol::Type(vec![]));
+        self.symbols
+            .insert("Bytes".to_string(), Symbol::Type(vec![]));
+        self.symbols
+            .insert("Bool".to_string(), Symbol::Type(vec![]));
+
+        // The all-zero account id
+        self.symbols.insert(
+            ZERO_ADDRESS.to_string(),
+            Symbol::Variable(TypeInfo::Address),
+        );
 
         // Common generic types
         self.symbols
             .insert("List".to_string(), Symbol::Type(vec!["T".to_string()]));
-        self.type_params.insert(
-            "List".to_string(),
-            vec!["T".to_string()].into_iter().collect(),
+        Rc::make_mut(&mut self.type_params)
+            .insert("List".into(), vec!["T".to_string()].into_iter().collect());
+
+        self.symbols.insert(
+            "Map".to_string(),
+            Symbol::Type(vec!["K".to_string(), "V".to_string()]),
+        );
+        Rc::make_mut(&mut self.type_params).insert(
+            "Map".into(),
+            vec!["K".to_string(), "V".to_string()].into_iter().collect(),
         );
 
         self.symbols
             .insert("Option".to_string(), Symbol::Type(vec!["T".to_string()]));
-        self.type_params.insert(
-            "Option".to_string(),
-            vec!["T".to_string()].into_iter().collect(),
-        );
+        Rc::make_mut(&mut self.type_params)
+            .insert("Option".into(), vec!["T".to_string()].into_iter().collect());
 
         self.symbols.insert(
             "Result".to_string(),
             Symbol::Type(vec!["T".to_string(), "E".to_string()]),
         );
-        self.type_params.insert(
-            "Result".to_string(),
+        Rc::make_mut(&mut self.type_params).insert(
+            "Result".into(),
             vec!["T".to_string(), "E".to_string()].into_iter().collect(),
         );
 
         self.symbols
             .insert("Tree".to_string(), Symbol::Type(vec!["T".to_string()]));
-        self.type_params.insert(
-            "Tree".to_string(),
-            vec!["T".to_string()].into_iter().collect(),
-        );
+        Rc::make_mut(&mut self.type_params)
+            .insert("Tree".into(), vec!["T".to_string()].into_iter().collect());
+
+        // Lazy storage collections
+        for (name, params) in [
+            ("StorageValue", vec!["T"]),
+            ("StorageMap", vec!["K", "V"]),
+            ("StorageVec", vec!["T"]),
+        ] {
+            let params: Vec<String> = params.into_iter().map(String::from).collect();
+            self.symbols
+                .insert(name.to_string(), Symbol::Type(params.clone()));
+            Rc::make_mut(&mut self.type_params).insert(name.into(), params.into_iter().collect());
+        }
 
         // Some common constructors
         self.symbols.insert(
@@ -218,28 +514,59 @@ impl TypeChecker {
             ),
         );
 
+        // `Result` constructors; the other parameter stays open
+        let result_type = TypeInfo::Named(
+            "Result".to_string(),
+            vec![TypeInfo::Unknown, TypeInfo::Unknown],
+        );
+        for variant in ["Ok", "Err"] {
+            self.symbols.insert(
+                format!("Result/{}", variant),
+                Symbol::Constructor(
+                    "Result".to_string(),
+                    TypeInfo::Function(Box::new(TypeInfo::Unknown), Box::new(result_type.clone())),
+                ),
+            );
+        }
+
         // Add more built-in types and constructors as needed
     }
 
     /// Type check a program
     pub fn check_program(&mut self, program: &Program) -> Result<(), TypeError> {
+        self.check_linked(program, &[])
+    }
+
+    /// Type check `program`, which uses the definitions of `library`, such
+    /// as those [`crate::stdlib::modules::link`] links it with. Only the
+    /// signatures of the library's functions are checked, as for
+    /// `#[extern]` functions.
+    pub fn check_linked(
+        &mut self,
+        program: &Program,
+        library: &[Definition],
+    ) -> Result<(), TypeError> {
         // First pass: collect all type definitions
-        for definition in &program.definitions {
+        for definition in library.iter().chain(&program.definitions) {
             match definition {
                 Definition::TypeDef {
                     name,
                     type_params,
                     variants,
+                    attributes,
                     ..
                 } => {
+                    if let Some(note) = attributes::type_deprecation(attributes)? {
+                        Rc::make_mut(&mut self.deprecated).insert(*name, note);
+                    }
+
                     let params = type_params.clone();
-                    self.symbols
-                        .insert(name.clone(), Symbol::Type(params.clone()));
-                    self.types.insert(name.clone(), variants.clone());
+                    self.symbols.insert(*name, Symbol::Type(params.clone()));
+                    Rc::make_mut(&mut self.types).insert(*name, variants.clone());
 
                     // Add type parameters
                     let param_set: HashSet<String> = params.into_iter().collect();
-                    self.type_params.insert(name.clone(), param_set);
+                    Rc::make_mut(&mut self.type_params).insert(*name, param_set);
 
                     // Add constructors
                     for variant in variants {
@@ -248,7 +575,21 @@ impl TypeChecker {
                             self.variant_to_type_info(name, type_params, variant)?;
                         self.symbols.insert(
                             constructor_name,
-                            Symbol::Constructor(name.clone(), constructor_type),
+                            Symbol::Constructor(name.to_string(), constructor_type),
+                        );
+                    }
+                }
+                Definition::ErrorDef { name, variants, .. } => {
+                    self.symbols.insert(name, Symbol::Type(vec![]));
+                    Rc::make_mut(&mut self.types).insert(*name, variants.clone());
+                    Rc::make_mut(&mut self.type_params).insert(*name, HashSet::new());
+
+                    // Variants construct values of the error type
+                    for variant in variants {
+                        let constructor_type = self.variant_to_type_info(name, &[], variant)?;
+                        self.symbols.insert(
+                            format!("{}/{}", name, variant.name),
+                            Symbol::Constructor(name.to_string(), constructor_type),
                         );
                     }
                 }
@@ -256,41 +597,144 @@ impl TypeChecker {
                     name,
                     type_params,
                     fields,
+                    functions,
+                    attributes,
                     ..
                 } => {
+                    if let Some(note) = attributes::type_deprecation(attributes)? {
+                        Rc::make_mut(&mut self.deprecated).insert(*name, note);
+                    }
+                    for function in functions {
+                        FunctionAttributes::of(function)?;
+                    }
+
                     let params = type_params.clone();
-                    self.symbols
-                        .insert(name.clone(), Symbol::Type(params.clone()));
+                    self.symbols.insert(*name, Symbol::Type(params.clone()));
 
                     // Create a single variant for the object
                     let object_variant = TypeVariant {
-                        name: name.clone(),
+                        name: *name,
                         fields: fields.clone(),
                         location: definition.location().clone(),
                     };
 
-                    self.types
-                        .insert(name.clone(), vec![object_variant.clone()]);
+                    Rc::make_mut(&mut self.types).insert(*name, vec![object_variant.clone()]);
 
                     // Add type parameters
                     let param_set: HashSet<String> = params.into_iter().collect();
-                    self.type_params.insert(name.clone(), param_set);
+                    Rc::make_mut(&mut self.type_params).insert(*name, param_set);
 
                     // Add constructor
-                    let constructor_name = name.clone();
+                    let constructor_name = *name;
                     let constructor_type =
                         self.variant_to_type_info(name, type_params, &object_variant)?;
                     self.symbols.insert(
                         constructor_name,
-                        Symbol::Constructor(name.clone(), constructor_type),
+                        Symbol::Constructor(name.to_string(), constructor_type),
                     );
                 }
+                Definition::EventDef {
+                    name,
+                    fields,
+                    location,
+                    ..
+                } => {
+                    let indexed = fields.iter().filter(|field| field.indexed).count();
+                    if indexed > MAX_INDEXED_EVENT_FIELDS {
+                        return Err(TypeError::Generic(format!(
+                            "Event '{}' has {} indexed fields, at most {} are allowed (line {}, column {})",
+                            name, indexed, MAX_INDEXED_EVENT_FIELDS, location.line, location.column
+                        )));
+                    }
+
+                    let mut field_types = Vec::new();
+                    for field in fields {
+                        field_types.push((self.ast_type_to_type_info(&field.ty)?, field.indexed));
+                    }
+                    Rc::make_mut(&mut self.events).insert(*name, field_types);
+                }
+                Definition::FunctionDef { name, .. } => {
+                    let function_attributes = FunctionAttributes::of(definition)?;
+                    if let Some(note) = &function_attributes.deprecated {
+                        Rc::make_mut(&mut self.deprecated).insert(*name, note.clone());
+                    }
+                    Rc::make_mut(&mut self.function_attributes).insert(*name, function_attributes);
+                }
+                Definition::InterfaceDef {
+                    name,
+                    functions,
+                    location,
+                    ..
+                } => {
+                    if self.interfaces.contains_key(name) {
+                        return Err(TypeError::Generic(format!(
+                            "Interface '{}' is defined more than once (line {}, column {})",
+                            name, location.line, location.column
+                        )));
+                    }
+                    let messages = self.interface_messages(name, functions)?;
+                    Rc::make_mut(&mut self.interfaces).insert(*name, messages);
+                }
+                Definition::GuardDef { name, location, .. } => {
+                    if self.guards.contains_key(name) {
+                        return Err(TypeError::Generic(format!(
+                            "Guard '{}' is defined more than once (line {}, column {})",
+                            name, location.line, location.column
+                        )));
+                    }
+                    Rc::make_mut(&mut self.guards).insert(*name, definition.clone());
+                }
+                Definition::StorageDef { fields, .. } => {
+                    for field in fields {
+                        if self.storage.contains_key(field.name) {
+                            return Err(TypeError::Generic(format!(
+                                "Storage field '{}' is declared more than once (line {}, column {})",
+                                field.name, field.location.line, field.location.column
+                            )));
+                        }
+
+                        let field_type = self.ast_type_to_type_info(&field.ty)?;
+                        self.symbols
+                            .insert(field.name, Symbol::Variable(field_type.clone()));
+                        self.storage.insert(field.name, field_type);
+                    }
+                }
                 _ => {}
             }
         }
 
-        // Second pass: type check function definitions
+        // Second pass: type check guards, invariants and function
+        // definitions
+        let mut has_invariants = false;
         for definition in &program.definitions {
+            if let Definition::StorageDef {
+                attributes,
+                location,
+                ..
+            } = definition
+            {
+                let storage = StorageAttributes::from_attributes(attributes)?;
+                self.check_conditions(&storage.invariants, Mutability::View)?;
+                has_invariants |= !storage.invariants.is_empty();
+                if let Some(version) = storage.version {
+                    if self.storage_version.is_some() {
+                        return Err(TypeError::Generic(format!(
+                            "The storage version is declared more than once (line {}, column {})",
+                            location.line, location.column
+                        )));
+                    }
+                    self.storage_version = Some(version);
+                }
+            }
+        }
+        self.check_migrations(program)?;
+        self.check_exports(program)?;
+        for (index, definition) in library.iter().chain(&program.definitions).enumerate() {
+            if let Definition::GuardDef { .. } = definition {
+                let mut warnings = self.check_guard_body(definition, Mutability::Mutable)?;
+                self.warnings.append(&mut warnings);
+            }
+
             if let Definition::FunctionDef {
                 name,
                 params,
@@ -305,17 +749,36 @@ impl TypeChecker {
                     continue;
                 }
 
+                // Body-less declarations of chain extensions must match the
+                // registered signature
+                if body.statements.is_empty() {
+                    if let Some(extension) = self.chain_extensions.get(name).cloned() {
+                        self.check_extension_declaration(
+                            &extension,
+                            params,
+                            return_type.as_ref(),
+                            definition.location(),
+                        )?;
+                        continue;
+                    }
+                }
+
                 // Create a new scope for the function
                 let mut checker = self.new_scope();
+                checker.current_mutability = self
+                    .function_attributes
+                    .get(name)
+                    .map(|attributes| attributes.mutability)
+                    .unwrap_or_default();
 
                 // Add parameters to the scope
                 let mut param_types = Vec::new();
                 for param in params {
                     let param_type = checker.ast_type_to_type_info(&param.ty)?;
 
-                    checker
-                        .symbols
-                        .insert(param.name.clone(), Symbol::Variable(param_type.clone()));
+                    checker.bind_variable(&param.name, &param.location, param_type.clone());
+                    // Parameters shadow storage fields of the same name
+                    checker.storage.remove(param.name);
                     param_types.push(param_type);
                 }
 
@@ -326,8 +789,99 @@ impl TypeChecker {
                     Some(TypeInfo::Any)
                 };
 
-                // Type check the function body
-                let inferred_return_type = checker.check_block(body)?;
+                // Functions with an annotated result may call themselves
+                if let (Some(_), Some(result)) =
+                    (return_type, &checker.current_function_return_type)
+                {
+                    let signature =
+                        param_types
+                            .iter()
+                            .rev()
+                            .fold(result.clone(), |fn_type, param| {
+                                TypeInfo::Function(Box::new(param.clone()), Box::new(fn_type))
+                            });
+                    if !checker.symbols.contains_key(name) {
+                        checker.symbols.insert(*name, Symbol::Function(signature));
+                    }
+                }
+
+                // Guards are applied with arguments from the function scope
+                if let Some(attributes) = self.function_attributes.get(name) {
+                    self.check_guard_calls(&mut checker, name, &attributes.guards, body)?;
+                    // The lock is released where the body falls through to
+                    // its final `return`
+                    if attributes.non_reentrant {
+                        if let Some(location) = early_return(body) {
+                            return Err(TypeError::Generic(format!(
+                                "Function '{}' returns before the end of its body, but it is non_reentrant (line {}, column {})",
+                                name, location.line, location.column
+                            )));
+                        }
+                    }
+
+                    // The new storage version is recorded there too
+                    if attributes.migrate {
+                        if let Some(location) = early_return(body) {
+                            return Err(TypeError::Generic(format!(
+                                "Function '{}' returns before the end of its body, but it records the migrated storage version there (line {}, column {})",
+                                name, location.line, location.column
+                            )));
+                        }
+                    }
+
+                    // Invariant testing reads whether the invariant holds
+                    // from the result
+                    if attributes.invariant
+                        && (return_type.is_none()
+                            || checker.current_function_return_type != Some(TypeInfo::Bool))
+                    {
+                        let location = definition.location();
+                        return Err(TypeError::Generic(format!(
+                            "Invariant '{}' must return Bool (line {}, column {})",
+                            name, location.line, location.column
+                        )));
+                    }
+
+                    // Preconditions see the arguments, before any local
+                    checker.check_conditions(&attributes.requires, checker.current_mutability)?;
+                    // Postconditions and invariants are checked where the
+                    // body falls through to its final `return`
+                    let invariants = has_invariants
+                        && attributes.mutability == Mutability::Mutable
+                        && !attributes.test;
+                    if !attributes.ensures.is_empty() || invariants {
+                        if let Some(location) = early_return(body) {
+                            return Err(TypeError::Generic(format!(
+                                "Function '{}' returns before the end of its body, but its postconditions are checked there (line {}, column {})",
+                                name, location.line, location.column
+                            )));
+                        }
+                    }
+                }
+
+                // Type check the function body, keeping the types found
+                // before any error. Functions defined by a linked object or
+                // the library only have their signature.
+                let external = index < library.len()
+                    || self
+                        .function_attributes
+                        .get(name)
+                        .is_some_and(|attributes| attributes.external);
+                let inferred_return_type = match &checker.current_function_return_type {
+                    Some(annotated) if external && return_type.is_some() => annotated.clone(),
+                    _ if external => TypeInfo::None,
+                    _ => {
+                        let inferred_return_type = checker.check_block(body);
+                        self.name_types.append(&mut checker.name_types);
+                        let inferred_return_type = inferred_return_type?;
+                        self.warnings.append(&mut checker.warnings);
+                        if return_type.is_none() {
+                            self.inferred_results
+                                .push((name.to_string(), inferred_return_type.clone()));
+                        }
+                        inferred_return_type
+                    }
+                };
 
                 // Check if the inferred return type matches the annotated return type
                 if let Some(ret_type) = &checker.current_function_return_type {
@@ -341,11 +895,37 @@ impl TypeChecker {
                     }
                 }
 
-                // Construct the function type
+                // Construct the function type, preferring the annotated
+                // return type, which may be more precise than the body's,
+                // e.g. for `Result/Ok(value)`
+                let result_type = match (return_type, &checker.current_function_return_type) {
+                    (Some(_), Some(annotated)) => annotated.clone(),
+                    _ => inferred_return_type.clone(),
+                };
+
+                // Postconditions see the arguments and the result
+                if let Some(attributes) = self.function_attributes.get(name) {
+                    if !attributes.ensures.is_empty() {
+                        let mut postcondition = self.new_scope();
+                        postcondition.in_postcondition = true;
+                        for (param, param_type) in params.iter().zip(&param_types) {
+                            postcondition.bind_variable(
+                                &param.name,
+                                &param.location,
+                                param_type.clone(),
+                            );
+                            postcondition.storage.remove(param.name);
+                        }
+                        postcondition.bind_variable(RESULT, &body.location, result_type.clone());
+                        postcondition
+                            .check_conditions(&attributes.ensures, checker.current_mutability)?;
+                    }
+                }
+
                 let function_type = if params.is_empty() {
-                    inferred_return_type.clone()
+                    result_type
                 } else {
-                    let mut fn_type = inferred_return_type.clone();
+                    let mut fn_type = result_type;
 
                     // Build the function type from right to left
                     for param_type in param_types.into_iter().rev() {
@@ -355,94 +935,471 @@ impl TypeChecker {
                     fn_type
                 };
 
-                // Add the function to the symbol table
-                self.symbols
-                    .insert(name.clone(), Symbol::Function(function_type));
+                // Add the function to the symbol table, once the scope of
+                // its body no longer shares the outermost frame
+                drop(checker);
+                self.symbols.insert(*name, Symbol::Function(function_type));
             }
         }
 
         Ok(())
     }
 
-    /// Create a new scope with inherited symbols and type definitions
+    /// Create a new scope with inherited symbols and type definitions,
+    /// sharing them rather than copying them
     fn new_scope(&self) -> TypeChecker {
         TypeChecker {
-            symbols: self.symbols.clone(),
+            symbols: self.symbols.nested(),
             types: self.types.clone(),
             type_params: self.type_params.clone(),
             visited_types: HashSet::new(),
             current_function_return_type: self.current_function_return_type.clone(),
+            events: self.events.clone(),
+            storage: self.storage.nested(),
+            chain_extensions: self.chain_extensions.clone(),
+            function_attributes: self.function_attributes.clone(),
+            deprecated: self.deprecated.clone(),
+            current_mutability: self.current_mutability,
+            warnings: Vec::new(),
+            guards: self.guards.clone(),
+            in_guard: self.in_guard,
+            in_postcondition: self.in_postcondition,
+            storage_version: self.storage_version,
+            interfaces: self.interfaces.clone(),
+            name_types: Vec::new(),
+            inferred_results: Vec::new(),
+            superpositions_allowed: false,
         }
     }
 
-    /// Convert a type variant to a type info
-    fn variant_to_type_info(
+    /// Check the body of a guard as if it ran in a function that may touch
+    /// state as `mutability`, returning the warnings it raised
+    fn check_guard_body(
         &self,
-        type_name: &str,
-        type_params: &[String],
-        variant: &TypeVariant,
-    ) -> Result<TypeInfo, TypeError> {
-        // Create a map of type parameters to Unknown types
-        let mut type_param_map = HashMap::new();
-        for param in type_params {
-            type_param_map.insert(param.clone(), TypeInfo::Unknown);
+        guard: &Definition,
+        mutability: Mutability,
+    ) -> Result<Vec<Warning>, TypeError> {
+        let Definition::GuardDef {
+            params,
+            before,
+            after,
+            ..
+        } = guard
+        else {
+            return Ok(Vec::new());
+        };
+
+        let mut checker = self.new_scope();
+        checker.current_mutability = mutability;
+        checker.in_guard = true;
+        for param in params {
+            let param_type = checker.ast_type_to_type_info(&param.ty)?;
+            checker
+                .symbols
+                .insert(param.name, Symbol::Variable(param_type));
+            checker.storage.remove(param.name);
         }
 
-        // Build the constructor type from right to left
-        let mut constructor_type = TypeInfo::Named(
-            type_name.to_string(),
-            type_params.iter().map(|_| TypeInfo::Unknown).collect(),
-        );
+        // Both halves share a scope, like the function body between them
+        checker.check_block(before)?;
+        checker.check_block(after)?;
+        Ok(checker.warnings)
+    }
 
-        // Add fields from right to left
-        for field in variant.fields.iter().rev() {
-            let field_type = if let Some(type_annotation) = &field.type_annotation {
-                self.ast_type_to_type_info_with_params(type_annotation, &type_param_map)?
-            } else {
-                TypeInfo::Any
+    /// Check the guards applied to a function against their definitions
+    fn check_guard_calls(
+        &self,
+        function_scope: &mut TypeChecker,
+        function: &str,
+        calls: &[GuardCall],
+        body: &Block,
+    ) -> Result<(), TypeError> {
+        for call in calls {
+            let guard = self.guards.get(&Name::from(&call.name)).ok_or_else(|| {
+                TypeError::Generic(format!(
+                    "Unknown guard '{}' (line {}, column {})",
+                    call.name, call.location.line, call.location.column
+                ))
+            })?;
+            let Definition::GuardDef { params, after, .. } = guard else {
+                unreachable!("only guard definitions are registered as guards");
             };
 
-            constructor_type = TypeInfo::Function(Box::new(field_type), Box::new(constructor_type));
+            if params.len() != call.args.len() {
+                return Err(TypeError::Generic(format!(
+                    "Guard '{}' expects {} arguments, found {} (line {}, column {})",
+                    call.name,
+                    params.len(),
+                    call.args.len(),
+                    call.location.line,
+                    call.location.column
+                )));
+            }
+            for (param, arg) in params.iter().zip(&call.args) {
+                let param_type = function_scope.ast_type_to_type_info(&param.ty)?;
+                let arg_type = function_scope.check_expr(arg)?;
+                if !function_scope.is_compatible(&param_type, &arg_type)? {
+                    return Err(TypeError::TypeMismatch {
+                        expected: param_type.to_string(),
+                        found: arg_type.to_string(),
+                        line: arg.location().line,
+                        column: arg.location().column,
+                    });
+                }
+            }
+
+            // The guard runs with the permissions of the guarded function;
+            // its warnings were already reported with the guard itself
+            if function_scope.current_mutability != Mutability::Mutable {
+                self.check_guard_body(guard, function_scope.current_mutability)?;
+            }
+
+            // Code after the placeholder runs where the body falls through
+            // to its final `return`, so the body cannot leave any earlier
+            if !after.statements.is_empty() {
+                if let Some(location) = early_return(body) {
+                    return Err(TypeError::Generic(format!(
+                        "Function '{}' returns before the end of its body, but guard '{}' runs code after it (line {}, column {})",
+                        function, call.name, location.line, location.column
+                    )));
+                }
+            }
         }
 
-        Ok(constructor_type)
+        Ok(())
     }
 
-    /// Convert AST type to TypeInfo
-    fn ast_type_to_type_info(&self, ast_type: &Type) -> Result<TypeInfo, TypeError> {
-        match ast_type {
-            Type::Named {
+    /// Collect the message signatures of an interface
+    ///
+    /// Messages are called with the `call` builtin, so their parameters
+    /// must be words, wide integers, addresses or hashes, and their results
+    /// words or `Bool`.
+    fn interface_messages(
+        &self,
+        interface: &str,
+        functions: &[Definition],
+    ) -> Result<HashMap<Name, InterfaceMessage>, TypeError> {
+        let mut messages = HashMap::new();
+
+        for function in functions {
+            let Definition::FunctionDef {
                 name,
                 params,
+                return_type,
+                body,
+                attributes,
                 location,
-            } => {
-                match name.as_str() {
-                    "u24" => Ok(TypeInfo::U24),
-                    "i24" => Ok(TypeInfo::I24),
-                    "f24" => Ok(TypeInfo::F24),
-                    "Any" => Ok(TypeInfo::Any),
-                    "None" => Ok(TypeInfo::None),
-                    "_" => Ok(TypeInfo::Unknown),
-                    _ => {
-                        // Check if the type exists
-                        if !self.symbols.contains_key(name) {
-                            return Err(TypeError::UndefinedType {
-                                name: name.clone(),
-                                line: location.line,
-                                column: location.column,
-                            });
-                        }
+                ..
+            } = function
+            else {
+                continue;
+            };
 
-                        // Check if the type parameters match
-                        if let Some(param_set) = self.type_params.get(name) {
-                            if params.len() != param_set.len() {
-                                return Err(TypeError::TypeMismatch {
-                                    expected: format!(
-                                        "{} with {} type parameters",
-                                        name,
-                                        param_set.len()
-                                    ),
-                                    found: format!(
+            if !body.statements.is_empty() {
+                return Err(TypeError::Generic(format!(
+                    "Message '{}' of interface '{}' cannot have a body (line {}, column {})",
+                    name, interface, location.line, location.column
+                )));
+            }
+            if let Some(attribute) = attributes.iter().find(|a| a.name != "payable") {
+                return Err(TypeError::InvalidAttribute {
+                    name: attribute.name.to_string(),
+                    reason: "interface messages only accept payable".to_string(),
+                    line: attribute.location.line,
+                    column: attribute.location.column,
+                });
+            }
+            let payable = FunctionAttributes::from_attributes(attributes, params)?.payable;
+
+            let mut param_types = Vec::new();
+            for param in params {
+                param_types.push(self.message_param_type(&param.ty, &param.location)?);
+            }
+            let result = match return_type {
+                Some(ty) => self.ast_type_to_type_info(ty)?,
+                None => TypeInfo::None,
+            };
+            if !matches!(
+                result,
+                TypeInfo::U24 | TypeInfo::I24 | TypeInfo::Bool | TypeInfo::None
+            ) {
+                return Err(TypeError::TypeMismatch {
+                    expected: "u24, i24 or Bool message result".to_string(),
+                    found: result.to_string(),
+                    line: location.line,
+                    column: location.column,
+                });
+            }
+
+            let message = InterfaceMessage {
+                params: param_types,
+                result,
+                payable,
+            };
+            if messages.insert(*name, message).is_some() {
+                return Err(TypeError::Generic(format!(
+                    "Message '{}' of interface '{}' is declared more than once (line {}, column {})",
+                    name, interface, location.line, location.column
+                )));
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// The type of a parameter of a message of another contract, which
+    /// must be a word, a wide integer, an `Address` or a `Hash`
+    fn message_param_type(&self, ty: &Type, location: &Location) -> Result<TypeInfo, TypeError> {
+        let param_type = self.ast_type_to_type_info(ty)?;
+        match param_type {
+            TypeInfo::U24
+            | TypeInfo::I24
+            | TypeInfo::U64
+            | TypeInfo::U128
+            | TypeInfo::U256
+            | TypeInfo::Address
+            | TypeInfo::Hash => Ok(param_type),
+            _ => Err(TypeError::TypeMismatch {
+                expected: "word, wide integer, Address or Hash message parameter".to_string(),
+                found: param_type.to_string(),
+                line: location.line,
+                column: location.column,
+            }),
+        }
+    }
+
+    /// Type check `Interface(account, value: v, gas: g).message(args)`
+    ///
+    /// The account may be an `Address` or a word-sized account id; the
+    /// optional `value` (for payable messages) and `gas` are words.
+    fn check_interface_call(
+        &mut self,
+        target: InterfaceMessageTarget<'_>,
+        args: &[Expr],
+        location: &Location,
+    ) -> Result<TypeInfo, TypeError> {
+        let InterfaceMessageTarget {
+            interface,
+            accounts,
+            options,
+            message,
+        } = target;
+        self.require_mutability(Mutability::Mutable, "call contracts", location)?;
+        let signature = self.interfaces[&interface]
+            .get(&Name::from(message))
+            .cloned()
+            .ok_or_else(|| {
+                TypeError::Generic(format!(
+                    "Interface '{}' has no message '{}' (line {}, column {})",
+                    interface, message, location.line, location.column
+                ))
+            })?;
+
+        let [account] = accounts else {
+            return Err(TypeError::TypeMismatch {
+                expected: format!("an account for {}", interface),
+                found: format!("{} arguments", accounts.len()),
+                line: location.line,
+                column: location.column,
+            });
+        };
+        let account_type = self.check_expr(account)?;
+        if account_type != TypeInfo::Address
+            && !self.is_compatible(&TypeInfo::U24, &account_type)?
+        {
+            return Err(TypeError::TypeMismatch {
+                expected: TypeInfo::Address.to_string(),
+                found: account_type.to_string(),
+                line: account.location().line,
+                column: account.location().column,
+            });
+        }
+
+        let mut words: Vec<(TypeInfo, &Expr)> = Vec::new();
+        for (option, value) in options {
+            match option.as_str() {
+                "gas" => {}
+                "value" if signature.payable => {}
+                "value" => {
+                    return Err(TypeError::Generic(format!(
+                        "Message '{}' of interface '{}' is not payable (line {}, column {})",
+                        message, interface, location.line, location.column
+                    )))
+                }
+                _ => {
+                    return Err(TypeError::Generic(format!(
+                        "Unknown call option '{}', expected value or gas (line {}, column {})",
+                        option, location.line, location.column
+                    )))
+                }
+            }
+            words.push((TypeInfo::U24, value));
+        }
+
+        if args.len() != signature.params.len() {
+            return Err(TypeError::TypeMismatch {
+                expected: format!(
+                    "{} arguments for {}.{}",
+                    signature.params.len(),
+                    interface,
+                    message
+                ),
+                found: format!("{} arguments", args.len()),
+                line: location.line,
+                column: location.column,
+            });
+        }
+        words.extend(signature.params.iter().cloned().zip(args));
+
+        for (expected, arg) in words {
+            let arg_type = self.check_expr(arg)?;
+            if !self.is_compatible(&expected, &arg_type)? {
+                return Err(TypeError::TypeMismatch {
+                    expected: expected.to_string(),
+                    found: arg_type.to_string(),
+                    line: arg.location().line,
+                    column: arg.location().column,
+                });
+            }
+        }
+
+        Ok(signature.result)
+    }
+
+    /// Warnings collected while checking the program
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Names of variables and functions with their types, at every place
+    /// they are bound or used, for tools like the language server
+    pub fn name_types(&self) -> &[(String, Location, TypeInfo)] {
+        &self.name_types
+    }
+
+    /// Functions without an annotated result, with the result found for
+    /// their bodies
+    pub fn inferred_results(&self) -> &[(String, TypeInfo)] {
+        &self.inferred_results
+    }
+
+    /// Bring a variable into scope, recording its type
+    fn bind_variable(&mut self, name: &str, location: &Location, type_info: TypeInfo) {
+        self.name_types
+            .push((name.to_string(), location.clone(), type_info.clone()));
+        self.symbols
+            .insert(name.to_string(), Symbol::Variable(type_info));
+    }
+
+    /// Fail unless the current function may touch state as `required`
+    fn require_mutability(
+        &self,
+        required: Mutability,
+        action: &str,
+        location: &Location,
+    ) -> Result<(), TypeError> {
+        if required <= self.current_mutability {
+            Ok(())
+        } else {
+            Err(TypeError::MutabilityViolation {
+                action: action.to_string(),
+                mutability: self.current_mutability,
+                line: location.line,
+                column: location.column,
+            })
+        }
+    }
+
+    /// Warn about a use of a deprecated function or type
+    fn warn_if_deprecated(&mut self, name: &str, location: &Location) {
+        if let Some(note) = self.deprecated.get(&Name::from(name)) {
+            let mut message = format!("'{}' is deprecated", name);
+            if !note.is_empty() {
+                message.push_str(&format!(": {}", note));
+            }
+            self.warnings.push(Warning {
+                lint: Lint::Deprecated,
+                message,
+                location: location.clone(),
+            });
+        }
+    }
+
+    /// Convert a type variant to a type info
+    fn variant_to_type_info(
+        &self,
+        type_name: &str,
+        type_params: &[String],
+        variant: &TypeVariant,
+    ) -> Result<TypeInfo, TypeError> {
+        // Create a map of type parameters to Unknown types
+        let mut type_param_map = HashMap::new();
+        for param in type_params {
+            type_param_map.insert(param.clone(), TypeInfo::Unknown);
+        }
+
+        // Build the constructor type from right to left
+        let mut constructor_type = TypeInfo::Named(
+            type_name.to_string(),
+            type_params.iter().map(|_| TypeInfo::Unknown).collect(),
+        );
+
+        // Add fields from right to left
+        for field in variant.fields.iter().rev() {
+            let field_type = if let Some(type_annotation) = &field.type_annotation {
+                self.ast_type_to_type_info_with_params(type_annotation, &type_param_map)?
+            } else {
+                TypeInfo::Any
+            };
+
+            constructor_type = TypeInfo::Function(Box::new(field_type), Box::new(constructor_type));
+        }
+
+        Ok(constructor_type)
+    }
+
+    /// Convert AST type to TypeInfo
+    fn ast_type_to_type_info(&self, ast_type: &Type) -> Result<TypeInfo, TypeError> {
+        match ast_type {
+            Type::Named {
+                name,
+                params,
+                location,
+            } => {
+                match name.as_str() {
+                    "u24" => Ok(TypeInfo::U24),
+                    "i24" => Ok(TypeInfo::I24),
+                    "f24" => Ok(TypeInfo::F24),
+                    "u64" => Ok(TypeInfo::U64),
+                    "u128" => Ok(TypeInfo::U128),
+                    "u256" => Ok(TypeInfo::U256),
+                    "Address" => Ok(TypeInfo::Address),
+                    "Hash" => Ok(TypeInfo::Hash),
+                    "Bytes" => Ok(TypeInfo::Bytes),
+                    "Bool" | "bool" => Ok(TypeInfo::Bool),
+                    "Any" => Ok(TypeInfo::Any),
+                    "None" => Ok(TypeInfo::None),
+                    "_" => Ok(TypeInfo::Unknown),
+                    _ => {
+                        // Check if the type exists
+                        if !self.symbols.contains_key(name) {
+                            return Err(TypeError::UndefinedType {
+                                name: name.to_string(),
+                                line: location.line,
+                                column: location.column,
+                            });
+                        }
+
+                        // Check if the type parameters match
+                        if let Some(param_set) = self.type_params.get(name) {
+                            if params.len() != param_set.len() {
+                                return Err(TypeError::TypeMismatch {
+                                    expected: format!(
+                                        "{} with {} type parameters",
+                                        name,
+                                        param_set.len()
+                                    ),
+                                    found: format!(
                                         "{} with {} type parameters",
                                         name,
                                         params.len()
@@ -458,7 +1415,7 @@ impl TypeChecker {
                                 param_types.push(self.ast_type_to_type_info(param)?);
                             }
 
-                            Ok(TypeInfo::Named(name.clone(), param_types))
+                            Ok(TypeInfo::Named(name.to_string(), param_types))
                         } else {
                             // Non-generic type shouldn't have parameters
                             if !params.is_empty() {
@@ -474,7 +1431,7 @@ impl TypeChecker {
                                 });
                             }
 
-                            Ok(TypeInfo::Named(name.clone(), vec![]))
+                            Ok(TypeInfo::Named(name.to_string(), vec![]))
                         }
                     }
                 }
@@ -522,7 +1479,7 @@ impl TypeChecker {
                 location,
             } => {
                 // Check if it's a type parameter
-                if let Some(param_type) = type_param_map.get(name) {
+                if let Some(param_type) = type_param_map.get(name.as_str()) {
                     if !params.is_empty() {
                         return Err(TypeError::TypeMismatch {
                             expected: format!("type parameter {} with no type parameters", name),
@@ -535,8 +1492,25 @@ impl TypeChecker {
                     return Ok(param_type.clone());
                 }
 
-                // Otherwise, proceed as normal
-                self.ast_type_to_type_info(ast_type)
+                // Otherwise, proceed as normal, except that the type
+                // arguments may mention the parameters too, e.g. `List<T>`
+                let holes = params
+                    .iter()
+                    .map(|_| Type::Hole {
+                        location: location.clone(),
+                    })
+                    .collect();
+                let mut type_info = self.ast_type_to_type_info(&Type::Named {
+                    name: *name,
+                    params: holes,
+                    location: location.clone(),
+                })?;
+                if let TypeInfo::Named(_, args) = &mut type_info {
+                    for (arg, param) in args.iter_mut().zip(params) {
+                        *arg = self.ast_type_to_type_info_with_params(param, type_param_map)?;
+                    }
+                }
+                Ok(type_info)
             }
             // Other cases same as ast_type_to_type_info
             _ => self.ast_type_to_type_info(ast_type),
@@ -546,28 +1520,56 @@ impl TypeChecker {
     /// Type check a block
     fn check_block(&mut self, block: &Block) -> Result<TypeInfo, TypeError> {
         let mut result_type = TypeInfo::None;
+        let superpositions_allowed = self.superpositions_allowed;
 
         for statement in &block.statements {
+            // Lowering duplicates expression statements, `emit`s and writes
+            // to map entries, once per alternative of their superpositions
+            let duplicated = matches!(
+                statement,
+                Statement::Expr { .. }
+                    | Statement::Emit { .. }
+                    | Statement::Assignment {
+                        pattern: Pattern::MapAccess { .. },
+                        ..
+                    }
+            );
+            self.superpositions_allowed = duplicated;
             result_type = self.check_statement(statement)?;
+
+            if duplicated && superposition_copies(statement) > MAX_SUPERPOSITION_COPIES {
+                let location = statement.location();
+                return Err(TypeError::InvalidSuperposition {
+                    reason: format!(
+                        "the statement expands into more than {} copies",
+                        MAX_SUPERPOSITION_COPIES
+                    ),
+                    line: location.line,
+                    column: location.column,
+                });
+            }
         }
 
+        self.superpositions_allowed = superpositions_allowed;
         Ok(result_type)
     }
 
     /// Type check a statement
     fn check_statement(&mut self, statement: &Statement) -> Result<TypeInfo, TypeError> {
         match statement {
-            Statement::Return { value, .. } => {
+            Statement::Return { value, location } => {
+                if self.in_guard {
+                    return Err(TypeError::Generic(format!(
+                        "Guards cannot return, the guarded body produces the result (line {}, column {})",
+                        location.line, location.column
+                    )));
+                }
+
                 let value_type = self.check_expr(value)?;
 
                 if let Some(ret_type) = &self.current_function_return_type {
                     if !self.is_compatible(ret_type, &value_type)? {
-                        return Err(TypeError::TypeMismatch {
-                            expected: ret_type.to_string(),
-                            found: value_type.to_string(),
-                            line: value.location().line,
-                            column: value.location().column,
-                        });
+                        return Err(mismatch(ret_type, &value_type, value));
                     }
                 }
 
@@ -575,9 +1577,203 @@ impl TypeChecker {
             }
             Statement::Assignment { pattern, value, .. } => {
                 let value_type = self.check_expr(value)?;
+
+                // Writes to storage must keep the declared field type
+                if let Pattern::Variable { name, location } = pattern {
+                    if let Some(field_type) = self.storage.get(name).cloned() {
+                        self.require_mutability(Mutability::Mutable, "write storage", location)?;
+                        if !self.is_compatible(&field_type, &value_type)? {
+                            return Err(mismatch(&field_type, &value_type, value));
+                        }
+                        return Ok(TypeInfo::None);
+                    }
+                }
+
+                // Writes to map entries, e.g. `balances[owner] = amount`
+                if let Pattern::MapAccess { map, key, location } = pattern {
+                    match &**map {
+                        Expr::Variable { name, .. } => {
+                            if self.storage.contains_key(name.as_str()) {
+                                self.require_mutability(
+                                    Mutability::Mutable,
+                                    "write storage",
+                                    location,
+                                )?;
+                            }
+                        }
+                        // Maps are written in place, and only a local or a
+                        // storage field can be copied first when shared
+                        _ => {
+                            return Err(TypeError::UnsupportedSharing {
+                                reason: "only a map held by a variable can be written".to_string(),
+                                line: location.line,
+                                column: location.column,
+                            });
+                        }
+                    }
+                    let (_, entry_type) = self.check_map_access(map, key)?;
+                    if !self.is_compatible(&entry_type, &value_type)? {
+                        return Err(mismatch(&entry_type, &value_type, value));
+                    }
+                    return Ok(TypeInfo::None);
+                }
+
                 self.check_pattern(pattern, &value_type)?;
                 Ok(TypeInfo::None)
             }
+            Statement::Emit {
+                event,
+                args,
+                location,
+            } => {
+                self.require_mutability(Mutability::Mutable, "emit events", location)?;
+                let fields = self
+                    .events
+                    .get(&Name::from(event))
+                    .cloned()
+                    .ok_or_else(|| TypeError::UndefinedEvent {
+                        name: event.to_string(),
+                        line: location.line,
+                        column: location.column,
+                    })?;
+
+                if fields.len() != args.len() {
+                    return Err(TypeError::TypeMismatch {
+                        expected: format!("{} arguments for event {}", fields.len(), event),
+                        found: format!("{} arguments", args.len()),
+                        line: location.line,
+                        column: location.column,
+                    });
+                }
+
+                for ((field_type, _), arg) in fields.iter().zip(args) {
+                    let arg_type = self.check_expr(arg)?;
+                    if !self.is_compatible(field_type, &arg_type)? {
+                        return Err(TypeError::TypeMismatch {
+                            expected: field_type.to_string(),
+                            found: arg_type.to_string(),
+                            line: arg.location().line,
+                            column: arg.location().column,
+                        });
+                    }
+                }
+
+                Ok(TypeInfo::None)
+            }
+            Statement::Expr { expr, .. } => {
+                self.check_expr(expr)?;
+                Ok(TypeInfo::None)
+            }
+            Statement::Revert {
+                kind,
+                condition,
+                reason,
+                ..
+            } => {
+                if let Some(condition) = condition {
+                    let condition_type = self.check_expr(condition)?;
+                    self.expect_bool(condition, &condition_type)?;
+                }
+                if let Some(reason) = reason {
+                    self.check_revert_reason(*kind, reason)?;
+                }
+
+                // `revert` never falls through, so it agrees with any type
+                Ok(match kind {
+                    RevertKind::Revert => TypeInfo::Any,
+                    _ => TypeInfo::None,
+                })
+            }
+            Statement::Unchecked { body, .. } => self.check_block(body),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let condition_type = self.check_expr(condition)?;
+                self.expect_bool(condition, &condition_type)?;
+                let then_type = self.check_block(then_branch)?;
+                let else_type = self.check_block(else_branch)?;
+
+                // Both branches agree when they end in the same type, e.g.
+                // both return, or when one of them reverts
+                if then_type == TypeInfo::Any {
+                    Ok(else_type)
+                } else if self.is_compatible(&then_type, &else_type)? {
+                    Ok(then_type)
+                } else {
+                    Ok(TypeInfo::None)
+                }
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                let condition_type = self.check_expr(condition)?;
+                self.expect_bool(condition, &condition_type)?;
+                self.check_block(body)?;
+
+                // The body may not run, so the loop has no type
+                Ok(TypeInfo::None)
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                location,
+                ..
+            } => {
+                let start_type = self.check_expr(start)?;
+                let end_type = self.check_expr(end)?;
+                // Ranges count in words
+                for (bound, bound_type) in [(start, &start_type), (end, &end_type)] {
+                    if !matches!(bound_type, TypeInfo::U24 | TypeInfo::I24 | TypeInfo::Any) {
+                        return Err(TypeError::TypeMismatch {
+                            expected: "u24 or i24".to_string(),
+                            found: bound_type.to_string(),
+                            line: bound.location().line,
+                            column: bound.location().column,
+                        });
+                    }
+                }
+                let variable_type =
+                    self.operand_type(&start_type, &end_type)?.ok_or_else(|| {
+                        TypeError::TypeMismatch {
+                            expected: start_type.to_string(),
+                            found: end_type.to_string(),
+                            line: end.location().line,
+                            column: end.location().column,
+                        }
+                    })?;
+
+                self.bind_variable(variable, location, variable_type);
+                self.check_block(body)?;
+                Ok(TypeInfo::None)
+            }
+            Statement::Match { value, cases, .. } => {
+                let value_type = self.check_expr(value)?;
+
+                // Like `if`, the cases agree when they all end in the same
+                // type, ignoring cases that revert
+                let mut result_type = TypeInfo::Any;
+                for case in cases {
+                    // Bindings of one case are not visible in the others
+                    self.symbols.push();
+                    self.check_pattern(&case.pattern, &value_type)?;
+                    let case_type = self.check_block(&case.body)?;
+                    self.symbols.pop();
+
+                    if result_type == TypeInfo::Any {
+                        result_type = case_type;
+                    } else if case_type != TypeInfo::Any
+                        && !self.is_compatible(&result_type, &case_type)?
+                    {
+                        result_type = TypeInfo::None;
+                    }
+                }
+                Ok(result_type)
+            }
             // Add type checking for other statement types
             // For brevity, we're not implementing all statement types here
             _ => Err(TypeError::Generic(
@@ -593,10 +1789,94 @@ impl TypeChecker {
         expected_type: &TypeInfo,
     ) -> Result<(), TypeError> {
         match pattern {
-            Pattern::Variable { name, location: _ } => {
+            Pattern::Variable { name, location } => {
+                // A bare variant name, e.g. `None`, matches that variant
+                if let Some(fields) = self.pattern_constructor(name, expected_type, location)? {
+                    if !fields.is_empty() {
+                        return Err(TypeError::Generic(format!(
+                            "Variant '{}' has {} fields (line {}, column {})",
+                            name,
+                            fields.len(),
+                            location.line,
+                            location.column
+                        )));
+                    }
+                    return Ok(());
+                }
+
                 // Add the variable to the symbol table with the expected type
-                self.symbols
-                    .insert(name.clone(), Symbol::Variable(expected_type.clone()));
+                self.bind_variable(name, location, expected_type.clone());
+                Ok(())
+            }
+            Pattern::TupleConstructor {
+                name,
+                args,
+                location,
+            } => {
+                let fields = self
+                    .pattern_constructor(name, expected_type, location)?
+                    .ok_or_else(|| TypeError::UndefinedVariable {
+                        name: name.to_string(),
+                        line: location.line,
+                        column: location.column,
+                    })?;
+                if fields.len() != args.len() {
+                    return Err(TypeError::Generic(format!(
+                        "Variant '{}' has {} fields, the pattern binds {} (line {}, column {})",
+                        name,
+                        fields.len(),
+                        args.len(),
+                        location.line,
+                        location.column
+                    )));
+                }
+                for (arg, field_type) in args.iter().zip(fields.iter()) {
+                    self.check_pattern(arg, field_type)?;
+                }
+                Ok(())
+            }
+            Pattern::Constructor {
+                name,
+                fields,
+                location,
+            } => {
+                let field_types = self
+                    .pattern_constructor(name, expected_type, location)?
+                    .ok_or_else(|| TypeError::UndefinedVariable {
+                        name: name.to_string(),
+                        line: location.line,
+                        column: location.column,
+                    })?;
+                let field_names = self.variant_field_names(name, expected_type);
+
+                let mut bound: Vec<_> = fields.iter().collect();
+                bound.sort_by(|a, b| a.0.cmp(b.0));
+                for (field, pattern) in bound {
+                    let index = field_names
+                        .iter()
+                        .position(|candidate| candidate == field)
+                        .ok_or_else(|| {
+                            TypeError::Generic(format!(
+                                "Variant '{}' has no field '{}' (line {}, column {})",
+                                name, field, location.line, location.column
+                            ))
+                        })?;
+                    let field_type = field_types.get(index).cloned().unwrap_or(TypeInfo::Any);
+                    self.check_pattern(pattern, &field_type)?;
+                }
+                Ok(())
+            }
+            Pattern::Wildcard { .. } => Ok(()),
+            Pattern::Literal { value, location } => {
+                let value_type = self.check_expr(value)?;
+                if !self.is_compatible(expected_type, &value_type)? {
+                    return Err(TypeError::TypeMismatch {
+                        expected: expected_type.to_string(),
+                        found: value_type.to_string(),
+                        line: location.line,
+                        column: location.column,
+                    });
+                }
                 Ok(())
             }
             Pattern::Tuple { elements, location } => {
@@ -643,38 +1923,188 @@ impl TypeChecker {
         }
     }
 
+    /// Field types of the variant a constructor pattern names, or `None` if
+    /// `name` is not a variant. Variants may be named without their type,
+    /// e.g. `Some(x)` for `Option/Some(x)`.
+    fn pattern_constructor(
+        &self,
+        name: &str,
+        expected_type: &TypeInfo,
+        location: &Location,
+    ) -> Result<Option<Vec<TypeInfo>>, TypeError> {
+        let qualified = match expected_type {
+            TypeInfo::Named(type_name, _) => Some(format!("{}/{}", type_name, name)),
+            _ => None,
+        };
+        let constructor = [Some(name.to_string()), qualified]
+            .into_iter()
+            .flatten()
+            .find_map(|candidate| match self.symbols.get(&candidate) {
+                Some(Symbol::Constructor(type_name, type_info)) => {
+                    Some((candidate, type_name.clone(), type_info.clone()))
+                }
+                _ => None,
+            });
+        let Some((constructor_name, type_name, mut constructor_type)) = constructor else {
+            return Ok(None);
+        };
+
+        if let TypeInfo::Named(expected_name, _) = expected_type {
+            if *expected_name != type_name {
+                return Err(TypeError::TypeMismatch {
+                    expected: expected_type.to_string(),
+                    found: type_name,
+                    line: location.line,
+                    column: location.column,
+                });
+            }
+        }
+
+        // Fields of declared types take the type arguments of the matched
+        // value, e.g. `head` is a `u24` when matching a `List<u24>`
+        let variant = constructor_name
+            .rsplit_once('/')
+            .map_or(constructor_name.as_str(), |(_, variant)| variant);
+        let declared = self
+            .types
+            .get(&Name::from(&type_name))
+            .and_then(|variants| variants.iter().find(|candidate| candidate.name == variant));
+        if let (Some(variant), Some(Symbol::Type(type_params)), TypeInfo::Named(_, type_args)) =
+            (declared, self.symbols.get(&type_name), expected_type)
+        {
+            if type_params.len() == type_args.len() {
+                let type_param_map: HashMap<String, TypeInfo> = type_params
+                    .iter()
+                    .cloned()
+                    .zip(type_args.iter().cloned())
+                    .collect();
+                let mut fields = Vec::new();
+                for field in &variant.fields {
+                    fields.push(match &field.type_annotation {
+                        Some(annotation) => {
+                            self.ast_type_to_type_info_with_params(annotation, &type_param_map)?
+                        }
+                        None => TypeInfo::Any,
+                    });
+                }
+                return Ok(Some(fields));
+            }
+        }
+
+        // Constructors are curried, one parameter per field
+        let mut fields = Vec::new();
+        while let TypeInfo::Function(param, result) = constructor_type {
+            fields.push(*param);
+            constructor_type = *result;
+        }
+        Ok(Some(fields))
+    }
+
+    /// Field names of the variant a constructor pattern names, in order
+    fn variant_field_names(&self, name: &str, expected_type: &TypeInfo) -> Vec<String> {
+        let (type_name, variant_name) = match name.split_once('/') {
+            Some((type_name, variant_name)) => (type_name.to_string(), variant_name),
+            None => match expected_type {
+                TypeInfo::Named(type_name, _) => (type_name.clone(), name),
+                _ => return Vec::new(),
+            },
+        };
+        self.types
+            .get(&Name::from(&type_name))
+            .and_then(|variants| variants.iter().find(|variant| variant.name == variant_name))
+            .map(|variant| {
+                variant
+                    .fields
+                    .iter()
+                    .map(|field| field.name.to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     /// Type check an expression
     fn check_expr(&mut self, expr: &Expr) -> Result<TypeInfo, TypeError> {
         match expr {
             Expr::Variable { name, location } => {
                 // Look up the variable in the symbol table
-                if let Some(symbol) = self.symbols.get(name) {
+                if self.storage.contains_key(name.as_str()) {
+                    self.require_mutability(Mutability::View, "read storage", location)?;
+                }
+                if let Some(callee) = self.function_attributes.get(name) {
+                    let action = format!("call {} function '{}'", callee.mutability, name);
+                    self.require_mutability(callee.mutability, &action, location)?;
+                }
+
+                if let Some(symbol) = self.symbols.get(name.as_str()).cloned() {
+                    if let Symbol::Variable(type_info)
+                    | Symbol::Function(type_info)
+                    | Symbol::Constructor(_, type_info) = &symbol
+                    {
+                        self.name_types.push((
+                            name.to_string(),
+                            location.clone(),
+                            type_info.clone(),
+                        ));
+                    }
                     match symbol {
-                        Symbol::Variable(type_info) => Ok(type_info.clone()),
-                        Symbol::Function(type_info) => Ok(type_info.clone()),
-                        Symbol::Constructor(_, type_info) => Ok(type_info.clone()),
+                        Symbol::Variable(type_info) => Ok(type_info),
+                        Symbol::Function(type_info) => {
+                            self.warn_if_deprecated(name, location);
+                            Ok(type_info)
+                        }
+                        Symbol::Constructor(type_name, type_info) => {
+                            self.warn_if_deprecated(&type_name, location);
+                            Ok(type_info)
+                        }
                         _ => Err(TypeError::UndefinedVariable {
-                            name: name.clone(),
+                            name: name.to_string(),
                             line: location.line,
                             column: location.column,
                         }),
                     }
                 } else {
                     Err(TypeError::UndefinedVariable {
-                        name: name.clone(),
+                        name: name.to_string(),
                         line: location.line,
                         column: location.column,
                     })
                 }
             }
-            Expr::Literal { kind, location: _ } => match kind {
-                LiteralKind::Uint(_) => Ok(TypeInfo::U24),
+            Expr::Literal { kind, location } => match kind {
+                // The lexer keeps anything a u32 or i32 holds for `u24`
+                // suffixed and signed literals; whether it fits the 24-bit
+                // type is checked here
+                LiteralKind::Uint(value) if *value > 0xFF_FFFF => {
+                    Err(TypeError::LiteralOutOfRange {
+                        value: value.to_string(),
+                        target: "u24".to_string(),
+                        line: location.line,
+                        column: location.column,
+                    })
+                }
+                LiteralKind::Int(value) if !(-0x80_0000..=0x7F_FFFF).contains(value) => {
+                    Err(TypeError::LiteralOutOfRange {
+                        value: format!("{:+}", value),
+                        target: "i24".to_string(),
+                        line: location.line,
+                        column: location.column,
+                    })
+                }
+                LiteralKind::Uint(_) => Ok(TypeInfo::U24),
+                LiteralKind::WideUint(value) => Ok(match value.bits() {
+                    64 => TypeInfo::U64,
+                    128 => TypeInfo::U128,
+                    _ => TypeInfo::U256,
+                }),
+                LiteralKind::Address(_) => Ok(TypeInfo::Address),
+                LiteralKind::Hash(_) => Ok(TypeInfo::Hash),
+                LiteralKind::Bytes(_) => Ok(TypeInfo::Bytes),
                 LiteralKind::Int(_) => Ok(TypeInfo::I24),
                 LiteralKind::Float(_) => Ok(TypeInfo::F24),
                 LiteralKind::String(_) => Ok(TypeInfo::Named("String".to_string(), vec![])),
                 LiteralKind::Char(_) => Ok(TypeInfo::U24),
                 LiteralKind::Symbol(_) => Ok(TypeInfo::U24),
-                LiteralKind::Bool(_) => Ok(TypeInfo::U24),
+                LiteralKind::Bool(_) => Ok(TypeInfo::Bool),
             },
             Expr::Tuple {
                 elements,
@@ -713,50 +2143,251 @@ impl TypeChecker {
 
                 Ok(TypeInfo::Named("List".to_string(), vec![element_type]))
             }
+            Expr::Map { entries, .. } => {
+                // Infer the key and value types from the first entry, or use
+                // Any if empty
+                let (key_type, value_type) = if let Some((key, value)) = entries.first() {
+                    (self.check_expr(key)?, self.check_expr(value)?)
+                } else {
+                    (TypeInfo::Any, TypeInfo::Any)
+                };
+
+                // Check that all entries have the same types
+                for (key, value) in entries {
+                    for (expected, element) in [(&key_type, key), (&value_type, value)] {
+                        let current_type = self.check_expr(element)?;
+                        if !self.is_compatible(expected, &current_type)? {
+                            return Err(TypeError::TypeMismatch {
+                                expected: expected.to_string(),
+                                found: current_type.to_string(),
+                                line: element.location().line,
+                                column: element.location().column,
+                            });
+                        }
+                    }
+                }
+
+                Ok(TypeInfo::Named(
+                    "Map".to_string(),
+                    vec![key_type, value_type],
+                ))
+            }
+            Expr::MapAccess { map, key, .. } => Ok(self.check_map_access(map, key)?.1),
+            Expr::ListComprehension {
+                element,
+                variable,
+                iterable,
+                condition,
+                ..
+            } => {
+                let shadowed =
+                    self.bind_comprehension_variable(variable, iterable, condition.as_deref())?;
+                let element_type = self.check_lazy_expr(element)?;
+                self.unbind_comprehension_variable(variable, shadowed);
+                Ok(TypeInfo::Named("List".to_string(), vec![element_type]))
+            }
+            Expr::MapComprehension {
+                key,
+                value,
+                variable,
+                iterable,
+                condition,
+                ..
+            } => {
+                let shadowed =
+                    self.bind_comprehension_variable(variable, iterable, condition.as_deref())?;
+                let key_type = self.check_lazy_expr(key)?;
+                let value_type = self.check_lazy_expr(value)?;
+                self.unbind_comprehension_variable(variable, shadowed);
+                Ok(TypeInfo::Named(
+                    "Map".to_string(),
+                    vec![key_type, value_type],
+                ))
+            }
             Expr::FunctionCall {
                 function,
                 args,
-                named_args: _,
+                named_args,
                 location,
             } => {
-                let function_type = self.check_expr(function)?;
+                // Methods of storage collections, e.g. `balances.get(owner)`
+                if let Some((name, method)) = function.as_method_target() {
+                    if let Some(field_type) = self.storage.get(name).cloned() {
+                        return self.check_storage_method(&field_type, method, args, location);
+                    }
+                }
 
-                // Check if the function type is a function
-                match function_type {
-                    TypeInfo::Function(param_type, result_type) => {
-                        // Check if the argument matches the parameter type
-                        if args.len() != 1 {
-                            return Err(TypeError::TypeMismatch {
-                                expected: "function with 1 argument".to_string(),
-                                found: format!("function with {} arguments", args.len()),
-                                line: location.line,
-                                column: location.column,
-                            });
+                // Messages of external contracts, e.g. `IToken(token).transfer(to, amount)`
+                if let Some(target) = function.as_interface_message() {
+                    if self.interfaces.contains_key(&target.interface) {
+                        return self.check_interface_call(target, args, location);
+                    }
+                }
+
+                if let Some(name) = named_args.keys().min() {
+                    return Err(TypeError::Generic(format!(
+                        "Unexpected named argument '{}', only interface bindings take them (line {}, column {})",
+                        name, location.line, location.column
+                    )));
+                }
+
+                // Cross-contract calls, unless shadowed by a user definition
+                if let Expr::Variable { name, .. } = &**function {
+                    if !self.symbols.contains_key(name.as_str()) {
+                        if self.interfaces.contains_key(name) {
+                            return Err(TypeError::Generic(format!(
+                                "Interface '{}' only binds an account to call its messages, e.g. {}(account).message(...) (line {}, column {})",
+                                name, name, location.line, location.column
+                            )));
+                        }
+                        if let Some(leading) = contract_call_leading_args(name) {
+                            return self.check_contract_call(name, leading, args, location);
+                        }
+                        if let Some(arity) = balance_builtin_arity(name) {
+                            return self.check_balance_builtin(name, arity, args, location);
+                        }
+                        if matches!(name.as_str(), "caller" | "address") {
+                            return self.check_account(name, args, location);
+                        }
+                        if name == OLD && self.in_postcondition {
+                            return self.check_old(args, location);
+                        }
+                        if name == STORAGE_VERSION {
+                            return self.check_storage_version(args, location);
+                        }
+                        if name == SET_CODE_HASH {
+                            return self.check_set_code_hash(args, location);
+                        }
+                        if name == "Map::new" && args.is_empty() {
+                            return Ok(TypeInfo::Named(
+                                "Map".to_string(),
+                                vec![TypeInfo::Any, TypeInfo::Any],
+                            ));
+                        }
+                        if let Some(target) = conversion_target(name) {
+                            return self.check_conversion(name, target, args, location);
                         }
+                        if name == "to_bytes" {
+                            return self.check_to_bytes(args, location);
+                        }
+                        if let Some((params, result)) = bytes_builtin_signature(name) {
+                            return self.check_bytes_builtin(name, &params, result, args, location);
+                        }
+                        if let Some(extension) = self.chain_extensions.get(name).cloned() {
+                            return self.check_chain_extension_call(&extension, args, location);
+                        }
+                    }
+                }
+
+                let function_type = self.check_expr(function)?;
 
-                        let arg_type = self.check_expr(&args[0])?;
-                        if !self.is_compatible(&param_type, &arg_type)? {
+                // Functions and constructors are curried, so each argument
+                // is applied in turn
+                let mut result_type = function_type;
+                for arg in args {
+                    match result_type {
+                        TypeInfo::Function(param_type, next_type) => {
+                            // Check if the argument matches the parameter type
+                            let arg_type = self.check_expr(arg)?;
+                            if !self.is_compatible(&param_type, &arg_type)? {
+                                return Err(mismatch(&param_type, &arg_type, arg));
+                            }
+                            result_type = *next_type;
+                        }
+                        TypeInfo::Any => {
+                            // Any can be called with any arguments
+                            return Ok(TypeInfo::Any);
+                        }
+                        _ => {
                             return Err(TypeError::TypeMismatch {
-                                expected: param_type.to_string(),
-                                found: arg_type.to_string(),
-                                line: args[0].location().line,
-                                column: args[0].location().column,
-                            });
+                                expected: "function".to_string(),
+                                found: result_type.to_string(),
+                                line: function.location().line,
+                                column: function.location().column,
+                            })
                         }
+                    }
+                }
 
-                        Ok(*result_type)
+                Ok(result_type)
+            }
+            Expr::InlineAsm {
+                inputs,
+                output,
+                instructions,
+                location,
+            } => {
+                let asm =
+                    InlineAsm::parse(inputs, output.as_deref(), instructions).map_err(|e| {
+                        TypeError::InvalidAssembly {
+                            reason: match e {
+                                CodegenError::InvalidAssembly(reason) => reason,
+                                e => e.to_string(),
+                            },
+                            line: location.line,
+                            column: location.column,
+                        }
+                    })?;
+                // The host may be asked for anything, storage writes included
+                if asm.calls_host() {
+                    self.require_mutability(Mutability::Mutable, "call the host", location)?;
+                }
+                // Inputs are loaded into a register each
+                for (_, input) in inputs {
+                    let input_type = self.check_expr(input)?;
+                    if !matches!(
+                        input_type,
+                        TypeInfo::U24 | TypeInfo::I24 | TypeInfo::Bool | TypeInfo::Any
+                    ) {
+                        return Err(TypeError::TypeMismatch {
+                            expected: "u24, i24 or Bool".to_string(),
+                            found: input_type.to_string(),
+                            line: input.location().line,
+                            column: input.location().column,
+                        });
                     }
-                    TypeInfo::Any => {
-                        // Any can be called with any arguments
-                        Ok(TypeInfo::Any)
+                }
+                Ok(TypeInfo::U24)
+            }
+            Expr::Try { expr, location } => {
+                let expr_type = self.check_expr(expr)?;
+                let (value_type, error_type) = match expr_type {
+                    TypeInfo::Named(name, params) if name == "Result" && params.len() == 2 => {
+                        (params[0].clone(), params[1].clone())
                     }
-                    _ => Err(TypeError::TypeMismatch {
-                        expected: "function".to_string(),
-                        found: function_type.to_string(),
-                        line: function.location().line,
-                        column: function.location().column,
-                    }),
+                    TypeInfo::Any => (TypeInfo::Any, TypeInfo::Any),
+                    _ => {
+                        return Err(TypeError::TypeMismatch {
+                            expected: "Result".to_string(),
+                            found: expr_type.to_string(),
+                            line: expr.location().line,
+                            column: expr.location().column,
+                        })
+                    }
+                };
+
+                // The error is returned as is, so the enclosing function must
+                // return a Result with the same error type
+                let propagated =
+                    TypeInfo::Named("Result".to_string(), vec![TypeInfo::Unknown, error_type]);
+                let return_type = self
+                    .current_function_return_type
+                    .clone()
+                    .unwrap_or(TypeInfo::Any);
+                let returns_result = match &return_type {
+                    TypeInfo::Named(name, _) => name == "Result",
+                    _ => return_type == TypeInfo::Any,
+                };
+                if !returns_result || !self.is_compatible(&return_type, &propagated)? {
+                    return Err(TypeError::TypeMismatch {
+                        expected: propagated.to_string(),
+                        found: return_type.to_string(),
+                        line: location.line,
+                        column: location.column,
+                    });
                 }
+
+                Ok(value_type)
             }
             Expr::BinaryOp {
                 left,
@@ -765,7 +2396,10 @@ impl TypeChecker {
                 location,
             } => {
                 let left_type = self.check_expr(left)?;
-                let right_type = self.check_expr(right)?;
+                let right_type = match operator {
+                    BinaryOperator::And | BinaryOperator::Or => self.check_lazy_expr(right)?,
+                    _ => self.check_expr(right)?,
+                };
 
                 // Check operator compatibility
                 match operator {
@@ -785,18 +2419,16 @@ impl TypeChecker {
                             });
                         }
 
-                        // Check numeric type compatibility
-                        if !self.is_compatible(&left_type, &right_type)? {
-                            return Err(TypeError::TypeMismatch {
+                        // Check numeric type compatibility; the result has
+                        // the type of the wider operand
+                        self.operand_type(&left_type, &right_type)?.ok_or_else(|| {
+                            TypeError::TypeMismatch {
                                 expected: left_type.to_string(),
                                 found: right_type.to_string(),
                                 line: right.location().line,
                                 column: right.location().column,
-                            });
-                        }
-
-                        // Result has the same type as the operands
-                        Ok(left_type)
+                            }
+                        })
                     }
                     BinaryOperator::Equal
                     | BinaryOperator::NotEqual
@@ -805,7 +2437,7 @@ impl TypeChecker {
                     | BinaryOperator::Greater
                     | BinaryOperator::GreaterEqual => {
                         // Comparison operations
-                        if !self.is_compatible(&left_type, &right_type)? {
+                        if self.operand_type(&left_type, &right_type)?.is_none() {
                             return Err(TypeError::TypeMismatch {
                                 expected: left_type.to_string(),
                                 found: right_type.to_string(),
@@ -814,8 +2446,35 @@ impl TypeChecker {
                             });
                         }
 
-                        // Result is a u24 (boolean)
-                        Ok(TypeInfo::U24)
+                        // Addresses, hashes, bytes and booleans are only compared
+                        // for equality
+                        let ordered =
+                            !matches!(operator, BinaryOperator::Equal | BinaryOperator::NotEqual);
+                        if ordered
+                            && matches!(
+                                left_type,
+                                TypeInfo::Address
+                                    | TypeInfo::Hash
+                                    | TypeInfo::Bytes
+                                    | TypeInfo::Bool
+                            )
+                        {
+                            return Err(TypeError::IncompatibleOperation {
+                                left: left_type.to_string(),
+                                op: operator.to_string(),
+                                right: right_type.to_string(),
+                                line: location.line,
+                                column: location.column,
+                            });
+                        }
+
+                        Ok(TypeInfo::Bool)
+                    }
+                    BinaryOperator::And | BinaryOperator::Or => {
+                        for (operand, operand_type) in [(left, &left_type), (right, &right_type)] {
+                            self.expect_bool(operand, operand_type)?;
+                        }
+                        Ok(TypeInfo::Bool)
                     }
                     BinaryOperator::BitAnd | BinaryOperator::BitOr | BinaryOperator::BitXor => {
                         // Bitwise operations
@@ -829,8 +2488,10 @@ impl TypeChecker {
                             });
                         }
 
-                        // Result has the same type as the operands
-                        Ok(left_type)
+                        // Result has the type of the wider operand
+                        Ok(self
+                            .operand_type(&left_type, &right_type)?
+                            .unwrap_or(left_type))
                     }
                     BinaryOperator::Pow => {
                         // Exponentiation (only for f24)
@@ -863,6 +2524,44 @@ impl TypeChecker {
                     }
                 }
             }
+            Expr::UnaryOp {
+                operator: UnaryOperator::Not,
+                operand,
+                ..
+            } => {
+                let operand_type = self.check_expr(operand)?;
+                self.expect_bool(operand, &operand_type)?;
+                Ok(TypeInfo::Bool)
+            }
+            Expr::Superposition { elements, location } => {
+                if !self.superpositions_allowed {
+                    return Err(TypeError::InvalidSuperposition {
+                        reason: "it can only be evaluated by an expression statement, an emit or \
+                                 a write to a map entry, and not conditionally"
+                            .to_string(),
+                        line: location.line,
+                        column: location.column,
+                    });
+                }
+
+                // Every alternative stands for the whole superposition, so
+                // they all have one type
+                let mut superposition_type = TypeInfo::Any;
+                for element in elements {
+                    let element_type = self.check_expr(element)?;
+                    if superposition_type == TypeInfo::Any {
+                        superposition_type = element_type;
+                    } else if !self.is_compatible(&superposition_type, &element_type)? {
+                        return Err(TypeError::TypeMismatch {
+                            expected: superposition_type.to_string(),
+                            found: element_type.to_string(),
+                            line: element.location().line,
+                            column: element.location().column,
+                        });
+                    }
+                }
+                Ok(superposition_type)
+            }
             // Add type checking for other expression types
             // For brevity, we're not implementing all expression types here
             _ => Err(TypeError::Generic(
@@ -871,99 +2570,2187 @@ impl TypeChecker {
         }
     }
 
-    /// Check if a type is numeric (u24, i24, f24)
-    fn is_numeric(&self, type_info: &TypeInfo) -> Result<bool, TypeError> {
-        Ok(matches!(
-            type_info,
-            TypeInfo::U24 | TypeInfo::I24 | TypeInfo::F24 | TypeInfo::Any
-        ))
+    /// Type check an expression that may not be evaluated, such as the right
+    /// of `&&`, where a superposition cannot be duplicated
+    fn check_lazy_expr(&mut self, expr: &Expr) -> Result<TypeInfo, TypeError> {
+        let superpositions_allowed = std::mem::replace(&mut self.superpositions_allowed, false);
+        let result = self.check_expr(expr);
+        self.superpositions_allowed = superpositions_allowed;
+        result
     }
 
-    /// Check if a type is integral (u24, i24)
-    fn is_integral(&self, type_info: &TypeInfo) -> Result<bool, TypeError> {
-        Ok(matches!(
-            type_info,
-            TypeInfo::U24 | TypeInfo::I24 | TypeInfo::Any
-        ))
-    }
+    /// Type check the reason of a revert statement: a string message, or for
+    /// `require` and `revert` a variant of an error type with arguments
+    /// matching its fields
+    fn check_revert_reason(&mut self, kind: RevertKind, reason: &Expr) -> Result<(), TypeError> {
+        if let Expr::Literal {
+            kind: LiteralKind::String(_),
+            ..
+        } = reason
+        {
+            return Ok(());
+        }
 
-    /// Check if one type is compatible with another
-    fn is_compatible(&self, expected: &TypeInfo, actual: &TypeInfo) -> Result<bool, TypeError> {
-        match (expected, actual) {
-            (TypeInfo::Any, _) | (_, TypeInfo::Any) => Ok(true),
-            (TypeInfo::Unknown, _) | (_, TypeInfo::Unknown) => Ok(true),
-            (TypeInfo::U24, TypeInfo::U24) => Ok(true),
-            (TypeInfo::I24, TypeInfo::I24) => Ok(true),
-            (TypeInfo::F24, TypeInfo::F24) => Ok(true),
-            (TypeInfo::None, TypeInfo::None) => Ok(true),
-            (TypeInfo::Tuple(expected_elements), TypeInfo::Tuple(actual_elements)) => {
-                if expected_elements.len() != actual_elements.len() {
-                    return Ok(false);
-                }
+        let location = reason.location();
+        let variant = match reason.as_variant_constructor() {
+            Some(variant) if kind != RevertKind::Assert => variant,
+            _ => {
+                return Err(TypeError::TypeMismatch {
+                    expected: match kind {
+                        RevertKind::Assert => "string message".to_string(),
+                        _ => "string message or error variant".to_string(),
+                    },
+                    found: self.check_expr(reason)?.to_string(),
+                    line: location.line,
+                    column: location.column,
+                })
+            }
+        };
+        let (name, args) = variant;
+        let undefined = || TypeError::UndefinedConstructor {
+            name: name.to_string(),
+            line: location.line,
+            column: location.column,
+        };
 
-                for (expected_element, actual_element) in
-                    expected_elements.iter().zip(actual_elements.iter())
-                {
-                    if !self.is_compatible(expected_element, actual_element)? {
-                        return Ok(false);
+        let (type_name, variant_name) = name.rsplit_once('/').ok_or_else(undefined)?;
+        let fields = self
+            .types
+            .get(&Name::from(type_name))
+            .and_then(|variants| variants.iter().find(|variant| variant.name == variant_name))
+            .map(|variant| variant.fields.clone())
+            .ok_or_else(undefined)?;
+
+        if fields.len() != args.len() {
+            return Err(TypeError::TypeMismatch {
+                expected: format!("{} arguments for error {}", fields.len(), name),
+                found: format!("{} arguments", args.len()),
+                line: location.line,
+                column: location.column,
+            });
+        }
+
+        for (field, arg) in fields.iter().zip(args) {
+            let arg_type = self.check_expr(arg)?;
+            let field_type = match &field.type_annotation {
+                Some(ty) => self.ast_type_to_type_info(ty)?,
+                None => TypeInfo::Any,
+            };
+            if !self.is_compatible(&field_type, &arg_type)? {
+                return Err(TypeError::TypeMismatch {
+                    expected: field_type.to_string(),
+                    found: arg_type.to_string(),
+                    line: arg.location().line,
+                    column: arg.location().column,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Bind the variable of a comprehension to the element type of the
+    /// `List` it iterates, or to `u24` for a `range(...)`, and check its
+    /// filter, returning the symbol the variable shadows
+    fn bind_comprehension_variable(
+        &mut self,
+        variable: &str,
+        iterable: &Expr,
+        condition: Option<&Expr>,
+    ) -> Result<Option<Symbol>, TypeError> {
+        let iterated = match iterable.as_range() {
+            Some(bounds) => {
+                for bound in bounds {
+                    let bound_type = self.check_expr(bound)?;
+                    if !self.is_compatible(&TypeInfo::U24, &bound_type)? {
+                        return Err(TypeError::TypeMismatch {
+                            expected: TypeInfo::U24.to_string(),
+                            found: bound_type.to_string(),
+                            line: bound.location().line,
+                            column: bound.location().column,
+                        });
                     }
                 }
+                TypeInfo::Named("List".to_string(), vec![TypeInfo::U24])
+            }
+            None => self.check_expr(iterable)?,
+        };
+        let element_type = match iterated {
+            TypeInfo::Named(name, params) if name == "List" => {
+                params.into_iter().next().unwrap_or(TypeInfo::Any)
+            }
+            TypeInfo::Any => TypeInfo::Any,
+            other => {
+                return Err(TypeError::TypeMismatch {
+                    expected: "List".to_string(),
+                    found: other.to_string(),
+                    line: iterable.location().line,
+                    column: iterable.location().column,
+                })
+            }
+        };
 
-                Ok(true)
+        let shadowed = self
+            .symbols
+            .insert(variable.to_string(), Symbol::Variable(element_type));
+        if let Some(condition) = condition {
+            let condition_type = self.check_lazy_expr(condition)?;
+            self.expect_bool(condition, &condition_type)?;
+        }
+        Ok(shadowed)
+    }
+
+    /// Restore the symbol shadowed by the variable of a comprehension
+    fn unbind_comprehension_variable(&mut self, variable: &str, shadowed: Option<Symbol>) {
+        match shadowed {
+            Some(symbol) => self.symbols.insert(variable, symbol),
+            None => self.symbols.remove(variable),
+        };
+    }
+
+    /// Require a condition or logical operand to be a `Bool`
+    fn expect_bool(&self, expr: &Expr, found: &TypeInfo) -> Result<(), TypeError> {
+        if self.is_compatible(&TypeInfo::Bool, found)? {
+            return Ok(());
+        }
+        Err(TypeError::TypeMismatch {
+            expected: TypeInfo::Bool.to_string(),
+            found: found.to_string(),
+            line: expr.location().line,
+            column: expr.location().column,
+        })
+    }
+
+    /// Type check `call`, `delegate_call` or `instantiate`: word-sized
+    /// leading arguments, a message signature string literal, then the
+    /// message arguments, of the types the signature declares or words
+    ///
+    /// The callee (or, for `instantiate`, the code hash) may also be an
+    /// `Address` (or `Hash`).
+    fn check_contract_call(
+        &mut self,
+        name: &str,
+        leading: usize,
+        args: &[Expr],
+        location: &Location,
+    ) -> Result<TypeInfo, TypeError> {
+        self.require_mutability(
+            Mutability::Mutable,
+            &format!("{} contracts", name),
+            location,
+        )?;
+        let signature = match args.get(leading) {
+            Some(Expr::Literal {
+                kind: LiteralKind::String(signature),
+                ..
+            }) => signature,
+            _ => {
+                return Err(TypeError::TypeMismatch {
+                    expected: format!(
+                        "{} arguments followed by a message signature string for {}",
+                        leading, name
+                    ),
+                    found: format!("{} arguments", args.len()),
+                    line: location.line,
+                    column: location.column,
+                })
             }
-            (
-                TypeInfo::Function(expected_param, expected_result),
-                TypeInfo::Function(actual_param, actual_result),
-            ) => Ok(self.is_compatible(expected_param, actual_param)?
-                && self.is_compatible(expected_result, actual_result)?),
-            (
-                TypeInfo::Named(expected_name, expected_params),
-                TypeInfo::Named(actual_name, actual_params),
-            ) => {
-                if expected_name != actual_name {
-                    return Ok(false);
-                }
+        };
 
-                if expected_params.len() != actual_params.len() {
-                    return Ok(false);
+        // Message arguments take the types the signature declares, if it
+        // declares one for each, and are words otherwise
+        let message_args = args.len().saturating_sub(leading + 1);
+        let mut expected = vec![TypeInfo::U24; leading + 1];
+        match signature_params(signature).filter(|params| params.len() == message_args) {
+            Some(params) => {
+                for param in &params {
+                    expected.push(self.message_param_type(param, location)?);
                 }
+            }
+            None => expected.resize(args.len(), TypeInfo::U24),
+        }
 
-                for (expected_param, actual_param) in
-                    expected_params.iter().zip(actual_params.iter())
-                {
-                    if !self.is_compatible(expected_param, actual_param)? {
-                        return Ok(false);
-                    }
-                }
+        for (i, (arg, expected)) in args.iter().zip(expected).enumerate() {
+            if i == leading {
+                continue;
+            }
+            let arg_type = self.check_expr(arg)?;
+            let account = match name {
+                "instantiate" => TypeInfo::Hash,
+                _ => TypeInfo::Address,
+            };
+            if i == 0 && arg_type == account {
+                continue;
+            }
+            if !self.is_compatible(&expected, &arg_type)? {
+                return Err(TypeError::TypeMismatch {
+                    expected: expected.to_string(),
+                    found: arg_type.to_string(),
+                    line: arg.location().line,
+                    column: arg.location().column,
+                });
+            }
+        }
 
-                Ok(true)
+        Ok(TypeInfo::U24)
+    }
+
+    /// Type check `balance()`, `transfer(to, value)` or
+    /// `terminate(beneficiary)`, whose arguments are all words except for
+    /// the account, which may also be an `Address`
+    fn check_balance_builtin(
+        &mut self,
+        name: &str,
+        arity: usize,
+        args: &[Expr],
+        location: &Location,
+    ) -> Result<TypeInfo, TypeError> {
+        let required = match name {
+            "balance" => Mutability::View,
+            _ => Mutability::Mutable,
+        };
+        self.require_mutability(required, &format!("call {}", name), location)?;
+        if args.len() != arity {
+            return Err(TypeError::TypeMismatch {
+                expected: format!("{} arguments for {}", arity, name),
+                found: format!("{} arguments", args.len()),
+                line: location.line,
+                column: location.column,
+            });
+        }
+
+        for (i, arg) in args.iter().enumerate() {
+            let arg_type = self.check_expr(arg)?;
+            if i == 0 && arg_type == TypeInfo::Address {
+                continue;
+            }
+            if !self.is_compatible(&TypeInfo::U24, &arg_type)? {
+                return Err(TypeError::TypeMismatch {
+                    expected: TypeInfo::U24.to_string(),
+                    found: arg_type.to_string(),
+                    line: arg.location().line,
+                    column: arg.location().column,
+                });
             }
-            _ => Ok(false),
         }
+
+        Ok(TypeInfo::U24)
     }
-}
 
-// Helper trait for getting string representation of binary operators
-impl std::fmt::Display for BinaryOperator {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            BinaryOperator::Add => write!(f, "+"),
-            BinaryOperator::Sub => write!(f, "-"),
-            BinaryOperator::Mul => write!(f, "*"),
-            BinaryOperator::Div => write!(f, "/"),
-            BinaryOperator::Mod => write!(f, "%"),
-            BinaryOperator::Pow => write!(f, "**"),
-            BinaryOperator::BitAnd => write!(f, "&"),
-            BinaryOperator::BitOr => write!(f, "|"),
-            BinaryOperator::BitXor => write!(f, "^"),
-            BinaryOperator::Equal => write!(f, "=="),
-            BinaryOperator::NotEqual => write!(f, "!="),
-            BinaryOperator::Less => write!(f, "<"),
-            BinaryOperator::LessEqual => write!(f, "<="),
-            BinaryOperator::Greater => write!(f, ">"),
-            BinaryOperator::GreaterEqual => write!(f, ">="),
-            BinaryOperator::BitShiftLeft => write!(f, "<<"),
-            BinaryOperator::BitShiftRight => write!(f, ">>"),
+    /// Type check `caller()`, the account the running call comes from, or
+    /// `address()`, the account of the contract itself
+    fn check_account(
+        &mut self,
+        name: &str,
+        args: &[Expr],
+        location: &Location,
+    ) -> Result<TypeInfo, TypeError> {
+        let what = match name {
+            "caller" => "read the caller",
+            _ => "read the contract's address",
+        };
+        self.require_mutability(Mutability::View, what, location)?;
+        if !args.is_empty() {
+            return Err(TypeError::TypeMismatch {
+                expected: format!("0 arguments for {}", name),
+                found: format!("{} arguments", args.len()),
+                line: location.line,
+                column: location.column,
+            });
+        }
+        Ok(TypeInfo::Address)
+    }
+
+    /// Type check `old(value)`, the value an expression had on entry, in a
+    /// postcondition
+    fn check_old(&mut self, args: &[Expr], location: &Location) -> Result<TypeInfo, TypeError> {
+        let [arg] = args else {
+            return Err(TypeError::TypeMismatch {
+                expected: "1 argument for old".to_string(),
+                found: format!("{} arguments", args.len()),
+                line: location.line,
+                column: location.column,
+            });
+        };
+        // The arguments and storage on entry, not the result
+        self.in_postcondition = false;
+        let returned = self.symbols.remove(RESULT);
+        let result = self.check_expr(arg);
+        if let Some(symbol) = returned {
+            self.symbols.insert(RESULT, symbol);
+        }
+        self.in_postcondition = true;
+        result
+    }
+
+    /// Type check `set_code_hash(code_hash)`, whose code hash may also be
+    /// given as a word
+    fn check_set_code_hash(
+        &mut self,
+        args: &[Expr],
+        location: &Location,
+    ) -> Result<TypeInfo, TypeError> {
+        self.require_mutability(Mutability::Mutable, "replace the code", location)?;
+        let [code_hash] = args else {
+            return Err(TypeError::TypeMismatch {
+                expected: "1 argument for set_code_hash".to_string(),
+                found: format!("{} arguments", args.len()),
+                line: location.line,
+                column: location.column,
+            });
+        };
+        let code_hash_type = self.check_expr(code_hash)?;
+        if code_hash_type != TypeInfo::Hash
+            && !self.is_compatible(&TypeInfo::U24, &code_hash_type)?
+        {
+            return Err(TypeError::TypeMismatch {
+                expected: TypeInfo::Hash.to_string(),
+                found: code_hash_type.to_string(),
+                line: code_hash.location().line,
+                column: code_hash.location().column,
+            });
         }
+        Ok(TypeInfo::U24)
+    }
+
+    /// Type check `storage_version()`, the schema version the storage was
+    /// last migrated to, or 0 before any migration
+    fn check_storage_version(
+        &mut self,
+        args: &[Expr],
+        location: &Location,
+    ) -> Result<TypeInfo, TypeError> {
+        self.require_mutability(Mutability::View, "read the storage version", location)?;
+        if !args.is_empty() {
+            return Err(TypeError::TypeMismatch {
+                expected: "0 arguments for storage_version".to_string(),
+                found: format!("{} arguments", args.len()),
+                line: location.line,
+                column: location.column,
+            });
+        }
+        if self.storage_version.is_none() {
+            return Err(TypeError::Generic(format!(
+                "storage_version() needs a storage declared with #[version(n)] (line {}, column {})",
+                location.line, location.column
+            )));
+        }
+        Ok(TypeInfo::U24)
+    }
+
+    /// Check that there is at most one `#[migrate]` function, and only with
+    /// a versioned storage to migrate to
+    fn check_migrations(&self, program: &Program) -> Result<(), TypeError> {
+        let mut migration: Option<&str> = None;
+        for definition in &program.definitions {
+            let Definition::FunctionDef { name, location, .. } = definition else {
+                continue;
+            };
+            if !self
+                .function_attributes
+                .get(name)
+                .is_some_and(|attributes| attributes.migrate)
+            {
+                continue;
+            }
+            if let Some(other) = migration {
+                return Err(TypeError::Generic(format!(
+                    "Function '{}' is a second migration after '{}' (line {}, column {})",
+                    name, other, location.line, location.column
+                )));
+            }
+            if self.storage_version.is_none() {
+                return Err(TypeError::Generic(format!(
+                    "Function '{}' is a migration, but no storage is declared with #[version(n)] (line {}, column {})",
+                    name, location.line, location.column
+                )));
+            }
+            migration = Some(name);
+        }
+        Ok(())
+    }
+
+    /// Check that every export name is used once, and that exported
+    /// functions return at most a word, which the host gets in `a0`
+    fn check_exports(&self, program: &Program) -> Result<(), TypeError> {
+        let mut exports: HashMap<&str, &str> = HashMap::new();
+        for definition in &program.definitions {
+            let Definition::FunctionDef {
+                name,
+                return_type,
+                location,
+                ..
+            } = definition
+            else {
+                continue;
+            };
+            let Some(export) = self
+                .function_attributes
+                .get(name)
+                .and_then(|attributes| attributes.export.as_deref())
+            else {
+                continue;
+            };
+            if let Some(other) = exports.insert(export, name) {
+                return Err(TypeError::Generic(format!(
+                    "Function '{}' is exported as '{}', like '{}' (line {}, column {})",
+                    name, export, other, location.line, location.column
+                )));
+            }
+            let result = match return_type {
+                Some(ty) => self.ast_type_to_type_info(ty)?,
+                None => TypeInfo::None,
+            };
+            if !matches!(
+                result,
+                TypeInfo::None | TypeInfo::U24 | TypeInfo::I24 | TypeInfo::Bool
+            ) {
+                return Err(TypeError::Generic(format!(
+                    "Function '{}' is exported, but returns {} instead of a u24, i24 or Bool (line {}, column {})",
+                    name, result, location.line, location.column
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that `conditions` are booleans, as a function that may touch
+    /// state as `mutability` would
+    fn check_conditions(
+        &mut self,
+        conditions: &[Expr],
+        mutability: Mutability,
+    ) -> Result<(), TypeError> {
+        let outer = std::mem::replace(&mut self.current_mutability, mutability);
+        let result = conditions.iter().try_for_each(|condition| {
+            let condition_type = self.check_expr(condition)?;
+            self.expect_bool(condition, &condition_type)
+        });
+        self.current_mutability = outer;
+        result
+    }
+
+    /// Type check `to_address(value)` or `to_hash(value)`
+    ///
+    /// Addresses, hashes and `u256`s convert into each other; a `u24`
+    /// converts into either by zero extension.
+    fn check_conversion(
+        &mut self,
+        name: &str,
+        target: TypeInfo,
+        args: &[Expr],
+        location: &Location,
+    ) -> Result<TypeInfo, TypeError> {
+        let [arg] = args else {
+            return Err(TypeError::TypeMismatch {
+                expected: format!("1 argument for {}", name),
+                found: format!("{} arguments", args.len()),
+                line: location.line,
+                column: location.column,
+            });
+        };
+
+        let arg_type = self.check_expr(arg)?;
+        if !matches!(
+            arg_type,
+            TypeInfo::Address | TypeInfo::Hash | TypeInfo::U24 | TypeInfo::U256 | TypeInfo::Any
+        ) {
+            return Err(TypeError::TypeMismatch {
+                expected: "Address, Hash, u24 or u256".to_string(),
+                found: arg_type.to_string(),
+                line: arg.location().line,
+                column: arg.location().column,
+            });
+        }
+
+        Ok(target)
+    }
+
+    /// Type check `len`, `concat`, `slice`, `keccak256` or `random` on
+    /// `Bytes`
+    fn check_bytes_builtin(
+        &mut self,
+        name: &str,
+        params: &[TypeInfo],
+        result: TypeInfo,
+        args: &[Expr],
+        location: &Location,
+    ) -> Result<TypeInfo, TypeError> {
+        if args.len() != params.len() {
+            return Err(TypeError::TypeMismatch {
+                expected: format!("{} arguments for {}", params.len(), name),
+                found: format!("{} arguments", args.len()),
+                line: location.line,
+                column: location.column,
+            });
+        }
+
+        for (param, arg) in params.iter().zip(args) {
+            let arg_type = self.check_expr(arg)?;
+            if !self.is_compatible(param, &arg_type)? {
+                return Err(TypeError::TypeMismatch {
+                    expected: param.to_string(),
+                    found: arg_type.to_string(),
+                    line: arg.location().line,
+                    column: arg.location().column,
+                });
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Type check `to_bytes(value)`, the little-endian bytes of a word, a
+    /// wide integer, an `Address` or a `Hash`
+    fn check_to_bytes(
+        &mut self,
+        args: &[Expr],
+        location: &Location,
+    ) -> Result<TypeInfo, TypeError> {
+        let [value] = args else {
+            return Err(TypeError::TypeMismatch {
+                expected: "1 argument for to_bytes".to_string(),
+                found: format!("{} arguments", args.len()),
+                line: location.line,
+                column: location.column,
+            });
+        };
+        let value_type = self.check_expr(value)?;
+        if !matches!(
+            value_type,
+            TypeInfo::U24
+                | TypeInfo::I24
+                | TypeInfo::U64
+                | TypeInfo::U128
+                | TypeInfo::U256
+                | TypeInfo::Address
+                | TypeInfo::Hash
+                | TypeInfo::Any
+        ) {
+            return Err(TypeError::TypeMismatch {
+                expected: "word, wide integer, Address or Hash".to_string(),
+                found: value_type.to_string(),
+                line: value.location().line,
+                column: value.location().column,
+            });
+        }
+        Ok(TypeInfo::Bytes)
+    }
+
+    /// Type check a call to a chain extension against its signature
+    fn check_chain_extension_call(
+        &mut self,
+        extension: &ChainExtension,
+        args: &[Expr],
+        location: &Location,
+    ) -> Result<TypeInfo, TypeError> {
+        if args.len() != extension.params.len() {
+            return Err(TypeError::TypeMismatch {
+                expected: format!(
+                    "{} arguments for {}",
+                    extension.params.len(),
+                    extension.name
+                ),
+                found: format!("{} arguments", args.len()),
+                line: location.line,
+                column: location.column,
+            });
+        }
+
+        for (arg, (_, param_type)) in args.iter().zip(&extension.params) {
+            let expected = extension_type_info(*param_type);
+            let arg_type = self.check_expr(arg)?;
+            if !self.is_compatible(&expected, &arg_type)? {
+                return Err(TypeError::TypeMismatch {
+                    expected: expected.to_string(),
+                    found: arg_type.to_string(),
+                    line: arg.location().line,
+                    column: arg.location().column,
+                });
+            }
+        }
+
+        Ok(extension_type_info(extension.returns))
+    }
+
+    /// Check that a declaration of a chain extension matches its signature
+    fn check_extension_declaration(
+        &mut self,
+        extension: &ChainExtension,
+        params: &[Parameter],
+        return_type: Option<&Type>,
+        location: &Location,
+    ) -> Result<(), TypeError> {
+        let mismatch = || TypeError::TypeMismatch {
+            expected: extension.extern_declaration(),
+            found: format!("a different declaration of {}", extension.name),
+            line: location.line,
+            column: location.column,
+        };
+
+        if params.len() != extension.params.len() {
+            return Err(mismatch());
+        }
+        for (param, (_, param_type)) in params.iter().zip(&extension.params) {
+            if self.ast_type_to_type_info(&param.ty)? != extension_type_info(*param_type) {
+                return Err(mismatch());
+            }
+        }
+        let declared = match return_type {
+            Some(ty) => self.ast_type_to_type_info(ty)?,
+            None => TypeInfo::None,
+        };
+        if declared != extension_type_info(extension.returns) {
+            return Err(mismatch());
+        }
+
+        Ok(())
+    }
+
+    /// Type check `map[key]`, returning the key and value types of the map
+    ///
+    /// Both in-memory maps and storage maps can be indexed.
+    fn check_map_access(
+        &mut self,
+        map: &Expr,
+        key: &Expr,
+    ) -> Result<(TypeInfo, TypeInfo), TypeError> {
+        let map_type = self.check_expr(map)?;
+        let (key_type, value_type) = match &map_type {
+            TypeInfo::Named(name, params) if is_map_type(name) && params.len() == 2 => {
+                (params[0].clone(), params[1].clone())
+            }
+            TypeInfo::Any => (TypeInfo::Any, TypeInfo::Any),
+            _ => {
+                return Err(TypeError::TypeMismatch {
+                    expected: "Map".to_string(),
+                    found: map_type.to_string(),
+                    line: map.location().line,
+                    column: map.location().column,
+                })
+            }
+        };
+
+        let actual_key = self.check_expr(key)?;
+        if !self.is_compatible(&key_type, &actual_key)? {
+            return Err(TypeError::TypeMismatch {
+                expected: key_type.to_string(),
+                found: actual_key.to_string(),
+                line: key.location().line,
+                column: key.location().column,
+            });
+        }
+
+        Ok((key_type, value_type))
+    }
+
+    /// Type check a call to a storage collection method
+    fn check_storage_method(
+        &mut self,
+        field_type: &TypeInfo,
+        method: &str,
+        args: &[Expr],
+        location: &Location,
+    ) -> Result<TypeInfo, TypeError> {
+        let (required, action) = match method {
+            "get" | "contains" | "len" => (Mutability::View, "read storage"),
+            _ => (Mutability::Mutable, "write storage"),
+        };
+        self.require_mutability(required, action, location)?;
+        let (params, result) = match (field_type, method) {
+            (TypeInfo::Named(name, params), "get") if name == "StorageValue" => {
+                (vec![], params[0].clone())
+            }
+            (TypeInfo::Named(name, params), "set") if name == "StorageValue" => {
+                (vec![params[0].clone()], TypeInfo::None)
+            }
+            (TypeInfo::Named(name, params), "get") if is_map_type(name) => {
+                (vec![params[0].clone()], params[1].clone())
+            }
+            (TypeInfo::Named(name, params), "insert") if is_map_type(name) => {
+                (vec![params[0].clone(), params[1].clone()], TypeInfo::None)
+            }
+            (TypeInfo::Named(name, params), "remove") if is_map_type(name) => {
+                (vec![params[0].clone()], TypeInfo::None)
+            }
+            (TypeInfo::Named(name, params), "contains") if is_map_type(name) => {
+                (vec![params[0].clone()], TypeInfo::U24)
+            }
+            (TypeInfo::Named(name, _), "len") if name == "StorageVec" => (vec![], TypeInfo::U24),
+            (TypeInfo::Named(name, params), "get") if name == "StorageVec" => {
+                (vec![TypeInfo::U24], params[0].clone())
+            }
+            (TypeInfo::Named(name, params), "set") if name == "StorageVec" => {
+                (vec![TypeInfo::U24, params[0].clone()], TypeInfo::None)
+            }
+            (TypeInfo::Named(name, params), "push") if name == "StorageVec" => {
+                (vec![params[0].clone()], TypeInfo::None)
+            }
+            (TypeInfo::Named(name, params), "pop") if name == "StorageVec" => {
+                (vec![], params[0].clone())
+            }
+            _ => {
+                return Err(TypeError::Generic(format!(
+                    "Type {} has no storage method '{}' (line {}, column {})",
+                    field_type, method, location.line, location.column
+                )))
+            }
+        };
+
+        if params.len() != args.len() {
+            return Err(TypeError::TypeMismatch {
+                expected: format!("{} arguments for {}", params.len(), method),
+                found: format!("{} arguments", args.len()),
+                line: location.line,
+                column: location.column,
+            });
+        }
+
+        for (param, arg) in params.iter().zip(args) {
+            let arg_type = self.check_expr(arg)?;
+            if !self.is_compatible(param, &arg_type)? {
+                return Err(TypeError::TypeMismatch {
+                    expected: param.to_string(),
+                    found: arg_type.to_string(),
+                    line: arg.location().line,
+                    column: arg.location().column,
+                });
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Check if a type is numeric (u24, i24, f24 and the wide unsigned types)
+    fn is_numeric(&self, type_info: &TypeInfo) -> Result<bool, TypeError> {
+        Ok(matches!(type_info, TypeInfo::F24) || self.is_integral(type_info)?)
+    }
+
+    /// Check if a type is integral (u24, i24 and the wide unsigned types)
+    fn is_integral(&self, type_info: &TypeInfo) -> Result<bool, TypeError> {
+        Ok(
+            matches!(type_info, TypeInfo::I24 | TypeInfo::Any)
+                || unsigned_bits(type_info).is_some(),
+        )
+    }
+
+    /// Type of a binary operation on two operands, or `None` if they are
+    /// incompatible
+    ///
+    /// Unsigned operands of different widths are widened to the wider one.
+    fn operand_type(
+        &self,
+        left: &TypeInfo,
+        right: &TypeInfo,
+    ) -> Result<Option<TypeInfo>, TypeError> {
+        if let (Some(left_bits), Some(right_bits)) = (unsigned_bits(left), unsigned_bits(right)) {
+            let wider = if left_bits >= right_bits { left } else { right };
+            return Ok(Some(wider.clone()));
+        }
+
+        Ok(self.is_compatible(left, right)?.then(|| left.clone()))
+    }
+
+    /// Check if one type is compatible with another
+    fn is_compatible(&self, expected: &TypeInfo, actual: &TypeInfo) -> Result<bool, TypeError> {
+        match (expected, actual) {
+            (TypeInfo::Any, _) | (_, TypeInfo::Any) => Ok(true),
+            (TypeInfo::Unknown, _) | (_, TypeInfo::Unknown) => Ok(true),
+            (TypeInfo::U24, TypeInfo::U24) => Ok(true),
+            (TypeInfo::I24, TypeInfo::I24) => Ok(true),
+            (TypeInfo::F24, TypeInfo::F24) => Ok(true),
+            (TypeInfo::Address, TypeInfo::Address) => Ok(true),
+            (TypeInfo::Hash, TypeInfo::Hash) => Ok(true),
+            (TypeInfo::Bytes, TypeInfo::Bytes) => Ok(true),
+            (TypeInfo::Bool, TypeInfo::Bool) => Ok(true),
+            // Unsigned values widen implicitly
+            (expected, actual)
+                if unsigned_bits(expected).is_some() && unsigned_bits(actual).is_some() =>
+            {
+                Ok(unsigned_bits(expected) >= unsigned_bits(actual))
+            }
+            (TypeInfo::None, TypeInfo::None) => Ok(true),
+            (TypeInfo::Tuple(expected_elements), TypeInfo::Tuple(actual_elements)) => {
+                if expected_elements.len() != actual_elements.len() {
+                    return Ok(false);
+                }
+
+                for (expected_element, actual_element) in
+                    expected_elements.iter().zip(actual_elements.iter())
+                {
+                    if !self.is_compatible(expected_element, actual_element)? {
+                        return Ok(false);
+                    }
+                }
+
+                Ok(true)
+            }
+            (
+                TypeInfo::Function(expected_param, expected_result),
+                TypeInfo::Function(actual_param, actual_result),
+            ) => Ok(self.is_compatible(expected_param, actual_param)?
+                && self.is_compatible(expected_result, actual_result)?),
+            (
+                TypeInfo::Named(expected_name, expected_params),
+                TypeInfo::Named(actual_name, actual_params),
+            ) => {
+                if expected_name != actual_name {
+                    return Ok(false);
+                }
+
+                if expected_params.len() != actual_params.len() {
+                    return Ok(false);
+                }
+
+                for (expected_param, actual_param) in
+                    expected_params.iter().zip(actual_params.iter())
+                {
+                    if !self.is_compatible(expected_param, actual_param)? {
+                        return Ok(false);
+                    }
+                }
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+// Helper trait for getting string representation of binary operators
+impl std::fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BinaryOperator::Add => write!(f, "+"),
+            BinaryOperator::Sub => write!(f, "-"),
+            BinaryOperator::Mul => write!(f, "*"),
+            BinaryOperator::Div => write!(f, "/"),
+            BinaryOperator::Mod => write!(f, "%"),
+            BinaryOperator::Pow => write!(f, "**"),
+            BinaryOperator::BitAnd => write!(f, "&"),
+            BinaryOperator::BitOr => write!(f, "|"),
+            BinaryOperator::BitXor => write!(f, "^"),
+            BinaryOperator::Equal => write!(f, "=="),
+            BinaryOperator::NotEqual => write!(f, "!="),
+            BinaryOperator::Less => write!(f, "<"),
+            BinaryOperator::LessEqual => write!(f, "<="),
+            BinaryOperator::Greater => write!(f, ">"),
+            BinaryOperator::GreaterEqual => write!(f, ">="),
+            BinaryOperator::BitShiftLeft => write!(f, "<<"),
+            BinaryOperator::BitShiftRight => write!(f, ">>"),
+            BinaryOperator::And => write!(f, "&&"),
+            BinaryOperator::Or => write!(f, "||"),
+        }
+    }
+}
+
+impl std::fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnaryOperator::Not => write!(f, "!"),
+        }
+    }
+}
+
+/// Number of arguments before the message signature of a contract call
+/// builtin, or `None` if `name` is not one
+/// The first `return` of a body other than its final statement
+fn early_return(body: &Block) -> Option<&Location> {
+    let (last, rest) = body.statements.split_last()?;
+    find_return(rest).or_else(|| match last {
+        Statement::Return { .. } => None,
+        other => find_return(std::slice::from_ref(other)),
+    })
+}
+
+/// The first `return` among the statements or the blocks nested in them
+fn find_return(statements: &[Statement]) -> Option<&Location> {
+    statements.iter().find_map(|statement| match statement {
+        Statement::Return { location, .. } => Some(location),
+        Statement::If {
+            then_branch,
+            else_branch,
+            ..
+        } => find_return(&then_branch.statements).or_else(|| find_return(&else_branch.statements)),
+        Statement::While { body, .. }
+        | Statement::For { body, .. }
+        | Statement::With { body, .. }
+        | Statement::Unchecked { body, .. } => find_return(&body.statements),
+        Statement::Switch { cases, .. } => cases
+            .iter()
+            .find_map(|case| find_return(&case.body.statements)),
+        Statement::Match { cases, .. } | Statement::Fold { cases, .. } => cases
+            .iter()
+            .find_map(|case| find_return(&case.body.statements)),
+        Statement::Bend {
+            body, else_body, ..
+        } => find_return(&body.statements).or_else(|| {
+            else_body
+                .as_ref()
+                .and_then(|block| find_return(&block.statements))
+        }),
+        Statement::TryCatch {
+            try_block,
+            catch_blocks,
+            ..
+        } => find_return(&try_block.statements).or_else(|| {
+            catch_blocks
+                .iter()
+                .find_map(|catch| find_return(&catch.body.statements))
+        }),
+        _ => None,
+    })
+}
+
+/// The arguments before the signature of the builtin `name` calling,
+/// delegating to or instantiating another contract, if it is one
+pub(crate) fn contract_call_leading_args(name: &str) -> Option<usize> {
+    match name {
+        "call" | "instantiate" => Some(3),
+        "delegate_call" => Some(2),
+        _ => None,
+    }
+}
+
+/// Type of a chain extension argument or result
+fn extension_type_info(ty: ExtensionType) -> TypeInfo {
+    match ty {
+        ExtensionType::U24 => TypeInfo::U24,
+        ExtensionType::I24 => TypeInfo::I24,
+        ExtensionType::Unit => TypeInfo::None,
+    }
+}
+
+/// Whether a type name is `Map` or `StorageMap`, which share their methods
+fn is_map_type(name: &str) -> bool {
+    matches!(name, "Map" | "StorageMap")
+}
+
+/// Result type of a conversion builtin, if `name` is one
+fn conversion_target(name: &str) -> Option<TypeInfo> {
+    match name {
+        "to_address" => Some(TypeInfo::Address),
+        "to_hash" => Some(TypeInfo::Hash),
+        _ => None,
+    }
+}
+
+/// Parameter and result types of a `Bytes` builtin, if `name` is one
+fn bytes_builtin_signature(name: &str) -> Option<(Vec<TypeInfo>, TypeInfo)> {
+    match name {
+        "len" => Some((vec![TypeInfo::Bytes], TypeInfo::U24)),
+        "concat" => Some((vec![TypeInfo::Bytes, TypeInfo::Bytes], TypeInfo::Bytes)),
+        "slice" => Some((
+            vec![TypeInfo::Bytes, TypeInfo::U24, TypeInfo::U24],
+            TypeInfo::Bytes,
+        )),
+        "keccak256" => Some((vec![TypeInfo::Bytes], TypeInfo::Hash)),
+        "u256_at" => Some((vec![TypeInfo::Bytes, TypeInfo::U24], TypeInfo::U256)),
+        "random" => Some((vec![TypeInfo::Bytes], TypeInfo::Hash)),
+        _ => None,
+    }
+}
+
+/// Number of arguments taken by a balance builtin, if `name` is one
+fn balance_builtin_arity(name: &str) -> Option<usize> {
+    match name {
+        "balance" => Some(0),
+        "transfer" => Some(2),
+        "terminate" => Some(1),
+        _ => None,
+    }
+}
+
+/// Width in bits of an unsigned integer type
+/// The error for `value`, of type `found`, where a value of type `expected`
+/// goes: an integer literal too large for an unsigned `expected` is out of
+/// its range
+fn mismatch(expected: &TypeInfo, found: &TypeInfo, value: &Expr) -> TypeError {
+    let location = value.location();
+    let literal = match value {
+        Expr::Literal {
+            kind: LiteralKind::Uint(value),
+            ..
+        } => Some((value.to_string(), 32 - value.leading_zeros() as u16)),
+        Expr::Literal {
+            kind: LiteralKind::WideUint(value),
+            ..
+        } => {
+            let top = value.limbs().iter().rposition(|&limb| limb != 0);
+            let bits = top.map_or(0, |top| {
+                32 * top as u16 + 32 - value.limbs()[top].leading_zeros() as u16
+            });
+            Some((value.to_string(), bits))
+        }
+        _ => None,
+    };
+    match (literal, unsigned_bits(expected)) {
+        (Some((value, bits)), Some(target)) if bits > target => TypeError::LiteralOutOfRange {
+            value,
+            target: expected.to_string(),
+            line: location.line,
+            column: location.column,
+        },
+        _ => TypeError::TypeMismatch {
+            expected: expected.to_string(),
+            found: found.to_string(),
+            line: location.line,
+            column: location.column,
+        },
+    }
+}
+
+fn unsigned_bits(type_info: &TypeInfo) -> Option<u16> {
+    match type_info {
+        TypeInfo::U24 => Some(24),
+        TypeInfo::U64 => Some(64),
+        TypeInfo::U128 => Some(128),
+        TypeInfo::U256 => Some(256),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parser::Parser;
+
+    fn check(source: &str) -> Result<(), TypeError> {
+        let program = Parser::new(source).parse_program().unwrap();
+        TypeChecker::new().check_program(&program)
+    }
+
+    #[test]
+    fn test_nested_scopes_share_enclosing_frames() {
+        let mut outer = Scopes::new();
+        outer.insert("balance", 1);
+        outer.insert("owner", 2);
+
+        let mut inner = outer.nested();
+        assert!(Rc::ptr_eq(&outer.frames[0], &inner.frames[0]));
+        assert_eq!(inner.insert("balance", 3), Some(1));
+        assert_eq!(inner.remove("owner"), Some(2));
+        assert_eq!(inner.get("balance"), Some(&3));
+        assert!(!inner.contains_key("owner"));
+        assert_eq!(outer.get("balance"), Some(&1));
+        assert_eq!(outer.get("owner"), Some(&2));
+
+        inner.push();
+        inner.insert("owner", 4);
+        inner.pop();
+        assert!(!inner.contains_key("owner"));
+
+        // The outermost frame is no longer shared, so it is not copied
+        drop(inner);
+        outer.insert("total", 5);
+        assert_eq!(Rc::strong_count(&outer.frames[0]), 1);
+        assert_eq!(outer.remove("total"), Some(5));
+        assert!(outer.frames[0].get(&Name::intern("total")).is_none());
+    }
+
+    #[test]
+    fn test_storage_collection_methods() {
+        let source = r#"
+            storage {
+                balances: StorageMap<u24, u24>,
+                holders: StorageVec<u24>,
+            }
+
+            fn main() -> u24 {
+                balances.insert(1, 2);
+                holders.push(1);
+                return balances.get(1);
+            }
+        "#;
+        check(source).unwrap();
+    }
+
+    #[test]
+    fn test_storage_collection_method_errors() {
+        let wrong_arity = r#"
+            storage {
+                balances: StorageMap<u24, u24>,
+            }
+
+            fn main() -> u24 {
+                balances.insert(1);
+                return 0;
+            }
+        "#;
+        assert!(matches!(
+            check(wrong_arity),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+
+        let unknown_method = r#"
+            storage {
+                balances: StorageMap<u24, u24>,
+            }
+
+            fn main() -> u24 {
+                balances.push(1);
+                return 0;
+            }
+        "#;
+        assert!(matches!(check(unknown_method), Err(TypeError::Generic(_))));
+    }
+
+    #[test]
+    fn test_contract_call_builtins() {
+        check(
+            r#"
+            fn main() -> u24 {
+                token = instantiate(7, 0, 0, "new(u24)", 100);
+                return call(token, 0, 0, "balance_of(u24)", 1);
+            }
+        "#,
+        )
+        .unwrap();
+
+        let missing_signature = r#"
+            fn main() -> u24 {
+                return delegate_call(2, 0, 5);
+            }
+        "#;
+        assert!(matches!(
+            check(missing_signature),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_balance_builtins() {
+        check(
+            r#"
+            fn main() -> u24 {
+                transfer(3, balance());
+                return terminate(4);
+            }
+        "#,
+        )
+        .unwrap();
+
+        let wrong_arity = r#"
+            fn main() -> u24 {
+                return transfer(3);
+            }
+        "#;
+        assert!(matches!(
+            check(wrong_arity),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_chain_extension_calls() {
+        let mut registry = ChainExtensionRegistry::new();
+        registry
+            .register(
+                ChainExtension::new("xcm_send")
+                    .with_param("dest", ExtensionType::U24)
+                    .with_param("message", ExtensionType::U24),
+            )
+            .unwrap();
+        let check = |source: &str| {
+            let program = Parser::new(source).parse_program().unwrap();
+            TypeChecker::new()
+                .with_chain_extensions(&registry)
+                .check_program(&program)
+        };
+
+        check(
+            r#"
+            fn xcm_send(dest: u24, message: u24) -> u24
+
+            fn main() -> u24 {
+                return xcm_send(1000, 7);
+            }
+        "#,
+        )
+        .unwrap();
+
+        let wrong_arity = r#"
+            fn main() -> u24 {
+                return xcm_send(1000);
+            }
+        "#;
+        assert!(matches!(
+            check(wrong_arity),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+
+        let wrong_declaration = r#"
+            fn xcm_send(dest: u24) -> u24
+
+            fn main() -> u24 {
+                return 0;
+            }
+        "#;
+        assert!(matches!(
+            check(wrong_declaration),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_wide_integers() {
+        check(
+            r#"
+            fn mint(supply: u256, amount: u128) -> u256 {
+                return supply + amount * 2;
+            }
+
+            fn is_large(value: u64) -> Bool {
+                return 1 + value > 18446744073709551615u64;
+            }
+        "#,
+        )
+        .unwrap();
+
+        let narrowing = r#"
+            fn main(value: u256) -> u64 {
+                return value;
+            }
+        "#;
+        assert!(matches!(
+            check(narrowing),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_literal_ranges() {
+        check(
+            r#"
+            fn main() -> u24 {
+                a = 0xff_ffffu24 + 16_777_215;
+                b = -8_388_608;
+                c = 0b1000_0000_0000_0000_0000_0000_0000_0000u64;
+                return a;
+            }
+        "#,
+        )
+        .unwrap();
+
+        // Unsuffixed literals take the width of their value and are checked
+        // against the type they flow into
+        check(
+            r#"
+            fn wide() -> u64 {
+                return 5000000000;
+            }
+
+            fn hex() -> u24 {
+                return 0xff;
+            }
+
+            fn total(amount: u64) -> u64 {
+                return amount + 5_000_000_000;
+            }
+
+            fn main() -> u64 {
+                return total(5000000000);
+            }
+        "#,
+        )
+        .unwrap();
+
+        for (source, target) in [
+            ("fn main() -> u24 { return 5000000000; }", "u24"),
+            ("fn main() -> u24 { return 16_777_216; }", "u24"),
+            (
+                "fn main() -> u64 { return 0x1_0000_0000_0000_0000; }",
+                "u64",
+            ),
+            ("fn main() -> u24 { x = 0x100_0000u24; return 0; }", "u24"),
+            ("fn main() -> u24 { x = +8388608; return 0; }", "i24"),
+        ] {
+            match check(source) {
+                Err(TypeError::LiteralOutOfRange { target: found, .. }) => {
+                    assert_eq!(found, target)
+                }
+                other => panic!("Expected {} to be out of range, got {:?}", source, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_address_and_hash() {
+        check(
+            r#"
+            fn is_alice(who: Address) -> Bool {
+                return who == @5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY;
+            }
+
+            fn is_unset(who: Address) -> Bool {
+                return who != ZERO_ADDRESS;
+            }
+
+            fn code_of(who: Address) -> Hash {
+                return to_hash(who);
+            }
+
+            fn from_hash(code: Hash) -> Address {
+                return to_address(code);
+            }
+        "#,
+        )
+        .unwrap();
+
+        let ordering = r#"
+            fn main(a: Address, b: Address) -> u24 {
+                return a < b;
+            }
+        "#;
+        assert!(matches!(
+            check(ordering),
+            Err(TypeError::IncompatibleOperation { .. })
+        ));
+
+        let mixed = r#"
+            fn main(code: Hash) -> Address {
+                return code;
+            }
+        "#;
+        assert!(matches!(check(mixed), Err(TypeError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_maps() {
+        check(
+            r#"
+            storage {
+                owners: Map<u24, Address>,
+            }
+
+            fn lookup(key: u24) -> u24 {
+                prices = {1: 10, 2: 20};
+                prices[3] = prices[key] + 1;
+                empty = Map::new();
+                empty[key] = 1;
+                return prices[3];
+            }
+
+            fn claim(token: u24, owner: Address) -> Address {
+                owners[token] = owner;
+                return owners[token];
+            }
+        "#,
+        )
+        .unwrap();
+
+        let mixed_values = r#"
+            fn main() -> u24 {
+                prices = {1: 10, 2: @5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY};
+                return 0;
+            }
+        "#;
+        assert!(matches!(
+            check(mixed_values),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+
+        let wrong_value = r#"
+            storage {
+                owners: Map<u24, Address>,
+            }
+
+            fn main() -> u24 {
+                owners[1] = 2;
+                return 0;
+            }
+        "#;
+        assert!(matches!(
+            check(wrong_value),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+
+        let not_a_map = r#"
+            fn main(value: u24) -> u24 {
+                return value[1];
+            }
+        "#;
+        assert!(matches!(
+            check(not_a_map),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_bytes() {
+        check(
+            r#"
+            fn main(data: Bytes) -> u24 {
+                tail = slice(concat(data, hex"deadbeef"), 1, 3);
+                digest = keccak256(tail);
+                if data == hex"00ff" {
+                    return len(tail);
+                } else {
+                    return 0;
+                }
+            }
+        "#,
+        )
+        .unwrap();
+
+        let ordered = r#"
+            fn main(a: Bytes, b: Bytes) -> u24 {
+                return a < b;
+            }
+        "#;
+        assert!(matches!(
+            check(ordered),
+            Err(TypeError::IncompatibleOperation { .. })
+        ));
+
+        let not_bytes = r#"
+            fn main(value: u24) -> u24 {
+                return len(value);
+            }
+        "#;
+        assert!(matches!(
+            check(not_bytes),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_bool() {
+        check(
+            r#"
+            fn in_range(value: u24, paused: Bool) -> Bool {
+                return not paused && (value > 1 || value == 0) and !false;
+            }
+
+            fn pick(flag: Bool) -> u24 {
+                if flag != true {
+                    return 0;
+                } else {
+                    return 1;
+                }
+            }
+        "#,
+        )
+        .unwrap();
+
+        let arithmetic = r#"
+            fn main(flag: Bool) -> u24 {
+                return flag + 1;
+            }
+        "#;
+        assert!(check(arithmetic).is_err());
+
+        let ordered = r#"
+            fn main(a: Bool, b: Bool) -> Bool {
+                return a < b;
+            }
+        "#;
+        assert!(matches!(
+            check(ordered),
+            Err(TypeError::IncompatibleOperation { .. })
+        ));
+
+        for source in [
+            "fn main(value: u24) -> u24 { if value { return 1; } else { return 0; } }",
+            "fn main(value: u24) -> Bool { return value && true; }",
+            "fn main(value: u24) -> Bool { return !value; }",
+        ] {
+            assert!(matches!(check(source), Err(TypeError::TypeMismatch { .. })));
+        }
+    }
+
+    #[test]
+    fn test_revert_statements() {
+        let errors = r#"
+            type TokenError {
+                InsufficientBalance(needed: u24, available: u24),
+                Paused,
+            }
+        "#;
+        let with_errors = |body: &str| format!("{}\n{}", errors, body);
+
+        check(&with_errors(
+            r#"
+            fn withdraw(amount: u24, balance: u24) -> u24 {
+                assert(balance < 1000000, "balance out of range");
+                require(amount > 0);
+                require(amount <= balance, TokenError/InsufficientBalance(amount, balance));
+                if amount == 7 {
+                    revert(TokenError/Paused);
+                } else {
+                    return balance - amount;
+                }
+            }
+        "#,
+        ))
+        .unwrap();
+
+        let not_bool = "fn main(value: u24) -> u24 { require(value, \"zero\"); return value; }";
+        assert!(matches!(
+            check(not_bool),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+
+        let assert_variant = with_errors(
+            "fn main(value: u24) -> u24 { assert(value > 0, TokenError/Paused); return value; }",
+        );
+        assert!(matches!(
+            check(&assert_variant),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+
+        let wrong_arity = with_errors(
+            "fn main(value: u24) -> u24 { revert(TokenError/InsufficientBalance(value)); }",
+        );
+        assert!(matches!(
+            check(&wrong_arity),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+
+        let undefined = with_errors("fn main(value: u24) -> u24 { revert(TokenError/Missing); }");
+        assert!(matches!(
+            check(&undefined),
+            Err(TypeError::UndefinedConstructor { .. })
+        ));
+    }
+
+    #[test]
+    fn test_error_definitions_and_try() {
+        let errors = r#"
+            error TokenError {
+                InsufficientBalance(needed: u24, available: u24),
+                Paused,
+            }
+
+            fn withdraw(amount: u24, balance: u24) -> Result<u24, TokenError> {
+                if amount > balance {
+                    return Result/Err(TokenError/InsufficientBalance(amount, balance));
+                } else {
+                    return Result/Ok(balance - amount);
+                }
+            }
+        "#;
+        let with_errors = |body: &str| format!("{}\n{}", errors, body);
+
+        check(&with_errors(
+            r#"
+            fn transfer(amount: u24) -> Result<u24, TokenError> {
+                left = withdraw(amount, 100)?;
+                require(left > 0, TokenError/Paused);
+                return Result/Ok(left + 1);
+            }
+        "#,
+        ))
+        .unwrap();
+
+        // `?` needs a Result ...
+        let not_result =
+            "fn main(value: u24) -> Result<u24, u24> { x = value?; return Result/Ok(x); }";
+        assert!(matches!(
+            check(not_result),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+
+        // ... inside a function returning a Result with the same error type
+        let plain_return = with_errors(
+            "fn main(amount: u24) -> u24 { left = withdraw(amount, 1)?; return left; }",
+        );
+        assert!(matches!(
+            check(&plain_return),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+
+        let other_error = with_errors(
+            r#"
+            error OtherError { Failed }
+
+            fn main(amount: u24) -> Result<u24, OtherError> {
+                left = withdraw(amount, 1)?;
+                return Result/Ok(left);
+            }
+        "#,
+        );
+        assert!(matches!(
+            check(&other_error),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+
+        let wrong_field = with_errors(
+            "fn main(flag: Bool) -> Result<u24, TokenError> { return Result/Err(TokenError/InsufficientBalance(flag, 1)); }",
+        );
+        assert!(matches!(
+            check(&wrong_field),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_loops() {
+        check(
+            r#"
+            fn sum(n: u24) -> u24 {
+                total = 0;
+                i = 0;
+                while i < n bound 100 {
+                    total = total + i;
+                    i = i + 1;
+                }
+                for j in range(n) {
+                    total = total + j;
+                }
+                return total;
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check("fn main(n: u24) -> u24 { while n { n = n - 1; } return n; }"),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            check("fn main(flag: Bool) -> u24 { for i in range(0, flag) { } return 0; }"),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_comprehensions() {
+        check(
+            r#"
+            fn main(xs: List<u24>) -> u24 {
+                doubled = [x * 2 for x in xs if x < 10];
+                squares = {x: x * x for x in doubled};
+                return squares[3];
+            }
+        "#,
+        )
+        .unwrap();
+
+        // The variable is only bound inside the comprehension
+        assert!(matches!(
+            check("fn main(xs: List<u24>) -> u24 { ys = [x for x in xs]; return x; }"),
+            Err(TypeError::UndefinedVariable { .. })
+        ));
+        assert!(matches!(
+            check("fn main(n: u24) -> u24 { ys = [x for x in n]; return 0; }"),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            check("fn main(xs: List<u24>) -> u24 { ys = [x for x in xs if x]; return 0; }"),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_attributes() {
+        check(
+            r#"
+            #[payable, selector(0xa9059cbb)]
+            fn deposit() -> u24 {
+                return 1;
+            }
+
+            #[inline(never), deprecated("use deposit")]
+            fn old() -> u24 {
+                return 0;
+            }
+
+            #[test]
+            fn check_deposit() -> u24 {
+                return deposit();
+            }
+
+            #[allow(unused_variables, shadowing), deny(deprecated)]
+            fn quiet(unused: u24) -> u24 {
+                return 0;
+            }
+        "#,
+        )
+        .unwrap();
+
+        for source in [
+            "#[frozen] fn main() -> u24 { return 0; }",
+            "#[view, view] fn main() -> u24 { return 0; }",
+            "#[view, pure] fn main() -> u24 { return 0; }",
+            "#[payable, view] fn main() -> u24 { return 0; }",
+            "#[selector(0x1_0000_0000)] fn main() -> u24 { return 0; }",
+            "#[selector(hex\"01\")] fn main() -> u24 { return 0; }",
+            "#[inline(sometimes)] fn main() -> u24 { return 0; }",
+            "#[test] fn main(a: u24) -> u24 { return a; }",
+            "#[payable] type Flag { On, Off }",
+            "#[allow(unused)] fn main() -> u24 { return 0; }",
+            "#[deny] fn main() -> u24 { return 0; }",
+        ] {
+            assert!(
+                matches!(check(source), Err(TypeError::InvalidAttribute { .. })),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_view_and_pure_functions() {
+        let storage = r#"
+            storage {
+                total: u24,
+                balances: StorageMap<u24, u24>,
+            }
+
+            event Touched { value: u24 }
+
+            fn bump() -> u24 {
+                total = total + 1;
+                return total;
+            }
+        "#;
+        let with = |function: &str| check(&format!("{}\n{}", storage, function));
+
+        with(
+            "#[view] fn get(owner: u24) -> u24 { return total + balances.get(owner) + balance(); }",
+        )
+        .unwrap();
+        with("#[pure] fn add(a: u24, b: u24) -> u24 { return a + b; }").unwrap();
+        with("#[pure] fn one() -> u24 { return 1; } #[view] fn two() -> u24 { return one() + 1; }")
+            .unwrap();
+
+        for function in [
+            "#[view] fn set() -> u24 { total = 1; return 0; }",
+            "#[view] fn put(owner: u24) -> u24 { balances[owner] = 1; return 0; }",
+            "#[view] fn put(owner: u24) -> u24 { balances.insert(owner, 1); return 0; }",
+            "#[view] fn touch() -> u24 { emit Touched(1); return 0; }",
+            "#[view] fn pay() -> u24 { return transfer(1, 2); }",
+            "#[view] fn nested() -> u24 { return bump(); }",
+            "#[pure] fn read() -> u24 { return total; }",
+            "#[pure] fn funds() -> u24 { return balance(); }",
+        ] {
+            assert!(
+                matches!(with(function), Err(TypeError::MutabilityViolation { .. })),
+                "{}",
+                function
+            );
+        }
+    }
+
+    #[test]
+    fn test_guards() {
+        let guards = r#"
+            storage {
+                total: u24,
+            }
+
+            guard at_least(amount: u24, min: u24) {
+                require(amount >= min, "too small");
+                _;
+                total = total + amount;
+            }
+
+            guard positive(amount: u24) {
+                valid = amount > 0;
+                require(valid, "zero amount");
+                _;
+            }
+        "#;
+        let with = |function: &str| check(&format!("{}\n{}", guards, function));
+
+        with("#[guard(positive(value), at_least(value, 10))] fn deposit(value: u24) -> u24 { doubled = value * 2; return doubled; }")
+            .unwrap();
+        with("#[guard(positive(value))] fn early(value: u24) -> u24 { if value > 5 { return 1; } else { return 0; } }")
+            .unwrap();
+
+        for function in [
+            "#[guard(missing)] fn f() -> u24 { return 0; }",
+            "#[guard(positive)] fn f() -> u24 { return 0; }",
+            "#[guard(positive(1, 2))] fn f() -> u24 { return 0; }",
+            "#[guard(positive(\"one\"))] fn f() -> u24 { return 0; }",
+            "#[guard(at_least(value, 1))] fn f(value: u24) -> u24 { if value > 5 { return 1; } else { return 0; } }",
+        ] {
+            assert!(with(function).is_err(), "{}", function);
+        }
+
+        // Guards run with the permissions of the guarded function
+        assert!(matches!(
+            with("#[view, guard(at_least(value, 1))] fn f(value: u24) -> u24 { return value; }"),
+            Err(TypeError::MutabilityViolation { .. })
+        ));
+        with("#[view, guard(positive(value))] fn f(value: u24) -> u24 { return value; }").unwrap();
+
+        // Only the guarded body produces the result
+        assert!(check("guard bad() { _; return 1; }").is_err());
+        // Guard bodies are checked even when unused
+        assert!(check("guard bad() { x = unknown; _; }").is_err());
+    }
+
+    #[test]
+    fn test_non_reentrant() {
+        check(
+            "#[non_reentrant] fn withdraw(amount: u24) -> u24 { left = amount - 1; return left; }",
+        )
+        .unwrap();
+
+        for function in [
+            "#[non_reentrant] fn f(value: u24) -> u24 { if value > 5 { return 1; } else { return 0; } }",
+            "#[view, non_reentrant] fn f() -> u24 { return 0; }",
+            "#[non_reentrant(1)] fn f() -> u24 { return 0; }",
+        ] {
+            assert!(check(function).is_err(), "{}", function);
+        }
+    }
+
+    #[test]
+    fn test_access_attributes() {
+        check("#[view] fn me() -> Address { return caller(); }").unwrap();
+        check("#[only_role(\"minter_2\")] fn f() -> u24 { return 0; }").unwrap();
+        check("#[view, only_owner] fn f() -> u24 { return 0; }").unwrap();
+
+        for function in [
+            "#[pure] fn me() -> Address { return caller(); }",
+            "fn me() -> Address { return caller(1); }",
+            "#[pure, only_owner] fn f() -> u24 { return 0; }",
+            "#[only_owner, only_role(\"minter\")] fn f() -> u24 { return 0; }",
+            "#[only_owner(1)] fn f() -> u24 { return 0; }",
+            "#[only_role(minter)] fn f() -> u24 { return 0; }",
+            "#[only_role(\"a.b\")] fn f() -> u24 { return 0; }",
+        ] {
+            assert!(check(function).is_err(), "{}", function);
+        }
+    }
+
+    #[test]
+    fn test_specification_attributes() {
+        let storage = "#[invariant(total <= 100, total != 7)]\nstorage { total: u24 }\n";
+        let with = |function: &str| check(&format!("{}{}", storage, function));
+
+        with("#[requires(amount > 0)]\n#[ensures(result == old(total) + amount, total >= old(total))]\nfn add(amount: u24) -> u24 { total = total + amount; return total; }")
+            .unwrap();
+        with("#[view, ensures(result == total)] fn get() -> u24 { return total; }").unwrap();
+        // Views do not check the invariants, so they may return early
+        with("#[view] fn f(x: u24) -> u24 { if x > 0 { return 1; } else { x = 0; } return x; }")
+            .unwrap();
+
+        for function in [
+            "#[requires(amount)] fn f(amount: u24) -> u24 { return amount; }",
+            "#[requires(old(amount) > 0)] fn f(amount: u24) -> u24 { return amount; }",
+            "#[requires(result > 0)] fn f(amount: u24) -> u24 { return amount; }",
+            "#[ensures(old(result) > 0)] fn f(amount: u24) -> u24 { return amount; }",
+            "#[ensures(result == \"a\")] fn f(amount: u24) -> u24 { return amount; }",
+            "#[ensures()] fn f(amount: u24) -> u24 { return amount; }",
+            "#[pure, ensures(result == total)] fn f() -> u24 { return 0; }",
+            // Postconditions and invariants are checked at the final return
+            "fn f(x: u24) -> u24 { if x > 0 { return 1; } else { x = 0; } return x; }",
+        ] {
+            assert!(with(function).is_err(), "{}", function);
+        }
+
+        for storage in [
+            "#[invariant(total + 1)] storage { total: u24 }",
+            "#[invariant(missing > 0)] storage { total: u24 }",
+            "#[deprecated] storage { total: u24 }",
+        ] {
+            assert!(check(storage).is_err(), "{}", storage);
+        }
+    }
+
+    #[test]
+    fn test_upgrades() {
+        let storage = "#[version(2)]\nstorage { total: u24 }\n";
+        let with = |function: &str| check(&format!("{}{}", storage, function));
+
+        with("#[migrate] fn migrate() -> u24 { total = total * 2; return 0; }").unwrap();
+        with("#[view] fn version() -> u24 { return storage_version(); }").unwrap();
+        with("fn upgrade(code: Hash) -> u24 { return set_code_hash(code); }").unwrap();
+
+        for function in [
+            "#[view] fn upgrade(code: Hash) -> u24 { return set_code_hash(code); }",
+            "fn upgrade() -> u24 { return set_code_hash(); }",
+            "fn upgrade(code: String) -> u24 { return set_code_hash(code); }",
+            "#[view, migrate] fn migrate() -> u24 { return 0; }",
+            "#[migrate(1)] fn migrate() -> u24 { return 0; }",
+            "#[migrate] fn a() -> u24 { return 0; }\n#[migrate] fn b() -> u24 { return 0; }",
+            // The migrated version is recorded at the final return
+            "#[migrate] fn migrate(x: u24) -> u24 { if x > 0 { return 1; } else { x = 0; } return x; }",
+        ] {
+            assert!(with(function).is_err(), "{}", function);
+        }
+
+        for source in [
+            "storage { total: u24 }\n#[migrate] fn migrate() -> u24 { return 0; }",
+            "storage { total: u24 }\nfn f() -> u24 { return storage_version(); }",
+            "#[version(0)] storage { total: u24 }",
+            "#[version(\"2\")] storage { total: u24 }",
+            "#[version(1)]\n#[version(2)] storage { total: u24 }",
+        ] {
+            assert!(check(source).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_exports() {
+        check("#[export(\"deploy\")] fn init() { }\n#[export] fn ping() -> u24 { return 1; }")
+            .unwrap();
+        check("#[export] fn ready() -> Bool { return true; }").unwrap();
+        check("#[export(\"deploy\")] fn init(x: u24, active: Bool) -> u24 { return x; }").unwrap();
+
+        for source in [
+            "#[export] fn f() -> Hash { return to_hash(0); }",
+            "#[export(\"deploy\")] fn a() { }\n#[export(\"deploy\")] fn b() { }",
+            "#[export(\"1st\")] fn f() { }",
+            "#[export(deploy)] fn f() { }",
+            "#[export] fn Token/f() { }",
+            "#[test, export] fn f() { }",
+        ] {
+            assert!(check(source).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_interfaces() {
+        let interface = r#"
+            interface IToken {
+                fn transfer(to: u24, amount: u24) -> u24;
+                #[payable]
+                fn deposit() -> u24;
+                fn ping();
+            }
+        "#;
+        let with = |function: &str| check(&format!("{}\n{}", interface, function));
+
+        with("fn main(token: Address) -> u24 { IToken(token).ping(); return IToken(token, gas: 100).transfer(3, 4) + IToken(2, value: 5).deposit(); }")
+            .unwrap();
+
+        for function in [
+            "fn main() -> u24 { return IToken(2).burn(1); }",
+            "fn main() -> u24 { return IToken(2).transfer(1); }",
+            "fn main() -> u24 { return IToken(2).transfer(1, \"two\"); }",
+            "fn main() -> u24 { return IToken(\"two\").deposit(); }",
+            "fn main() -> u24 { return IToken(2, value: 5).transfer(1, 2); }",
+            "fn main() -> u24 { return IToken(2, fee: 5).deposit(); }",
+            "fn main() -> u24 { token = IToken(2); return 0; }",
+            "fn main() -> u24 { return IToken(2).ping(); }",
+            "fn add(a: u24) -> u24 { return a; } fn main() -> u24 { return add(a: 1); }",
+        ] {
+            assert!(with(function).is_err(), "{}", function);
+        }
+        assert!(matches!(
+            with("#[view] fn main() -> u24 { return IToken(2).deposit(); }"),
+            Err(TypeError::MutabilityViolation { .. })
+        ));
+
+        // Messages cross the boundary as words and have no bodies
+        for interface in [
+            "interface Bad { fn take(data: Bytes) -> u24; }",
+            "interface Bad { fn give() -> Address; }",
+            "interface Bad { fn body() -> u24 { return 1; } }",
+            "interface Bad { #[view] fn read() -> u24; }",
+        ] {
+            assert!(check(interface).is_err(), "{}", interface);
+        }
+
+        // Wide arguments take the words of their types, declared by the
+        // interface or the signature of `call`
+        let wide = "interface IVault { fn deposit(owner: Address, amount: u256) -> Bool; }";
+        check(&format!(
+            "{}\nfn main(owner: Address, amount: u256) -> Bool {{ return IVault(2).deposit(owner, amount); }}",
+            wide
+        ))
+        .unwrap();
+        check("fn main(owner: Address) -> u24 { return call(2, 0, 0, \"credit(Address,u128)\", owner, 5); }")
+            .unwrap();
+        for function in [
+            "fn main(owner: Address) -> Bool { return IVault(2).deposit(1, owner); }",
+            "fn main(owner: Address) -> u24 { return call(2, 0, 0, \"credit(u24,u24)\", owner, 5); }",
+            "fn main() -> u24 { return call(2, 0, 0, \"log(Bytes)\", 5); }",
+        ] {
+            assert!(
+                check(&format!("{}\n{}", wide, function)).is_err(),
+                "{}",
+                function
+            );
+        }
+    }
+
+    #[test]
+    fn test_deprecation_warnings() {
+        let program = Parser::new(
+            r#"
+            #[deprecated("use Shape")]
+            type Old { Square(side: u24) }
+
+            #[deprecated]
+            fn legacy() -> u24 {
+                return 1;
+            }
+
+            fn main() -> u24 {
+                shape = Old/Square(2);
+                return legacy();
+            }
+        "#,
+        )
+        .parse_program()
+        .unwrap();
+        let mut checker = TypeChecker::new();
+        checker.check_program(&program).unwrap();
+
+        assert_eq!(checker.warnings().len(), 2);
+        assert!(checker.warnings()[0]
+            .to_string()
+            .starts_with("'Old' is deprecated: use Shape"));
+        assert!(checker.warnings()[1]
+            .to_string()
+            .starts_with("'legacy' is deprecated ("));
+        assert_eq!(checker.warnings()[1].lint, Lint::Deprecated);
+    }
+
+    #[test]
+    fn test_match() {
+        let option = "type Option<T> { None, Some(value: T) }\n";
+        let with = |function: &str| check(&format!("{}{}", option, function));
+
+        with("fn get(x: Option<u24>) -> u24 { match x { None => { return 0; } Some(v) => { return v + 1; } } }")
+            .unwrap();
+        with("fn get(x: Option<u24>) -> u24 { match x { Option/Some { value: v } => { return v; } _ => { return 0; } } }")
+            .unwrap();
+        // Recursion through a match on a recursive type
+        check("type Stack<T> { Empty, Push(top: T, rest: Stack<T>) }\nfn sum(xs: Stack<u24>) -> u24 { match xs { Stack/Empty => { return 0; } Stack/Push(top, rest) => { return top + sum(rest); } } }")
+            .unwrap();
+
+        for function in [
+            "fn get(x: Option<u24>) -> u24 { match x { Some(v, w) => { return v; } } }",
+            "fn get(x: Option<u24>) -> u24 { match x { None(v) => { return v; } } }",
+            "fn get(x: Option<u24>) -> u24 { match x { Some { other: v } => { return v; } } }",
+            "fn get(x: Option<u24>) -> u24 { match x { Some(v) => { return v == true; } } }",
+            "fn get(x: u24) -> u24 { match x { Some(v) => { return v; } } }",
+            "fn get(x: Option<u24>) -> u24 { match x { Some(v) => { y = v; } None => { return y; } } }",
+        ] {
+            assert!(with(function).is_err(), "{}", function);
+        }
+    }
+
+    #[test]
+    fn test_name_types() {
+        let source = "fn double(x: u24) -> u24 {\n    y = x * 2;\n    return y;\n}\n";
+        let program = Parser::new(source).parse_program().unwrap();
+        let mut checker = TypeChecker::new();
+        checker.check_program(&program).unwrap();
+
+        let types: Vec<(&str, usize, String)> = checker
+            .name_types()
+            .iter()
+            .map(|(name, location, type_info)| {
+                (name.as_str(), location.line, type_info.to_string())
+            })
+            .collect();
+        assert!(types.contains(&("x", 1, "u24".to_string())));
+        assert!(types.contains(&("x", 2, "u24".to_string())));
+        assert!(types.contains(&("y", 3, "u24".to_string())));
+    }
+
+    #[test]
+    fn test_inferred_results() {
+        let source = "fn double(x: u24) {\n    return x * 2;\n}\n\nfn id(x: u24) -> u24 {\n    return x;\n}\n";
+        let program = Parser::new(source).parse_program().unwrap();
+        let mut checker = TypeChecker::new();
+        checker.check_program(&program).unwrap();
+        assert_eq!(
+            checker.inferred_results(),
+            &[("double".to_string(), TypeInfo::U24)]
+        );
+    }
+
+    #[test]
+    fn test_inline_asm() {
+        check(
+            r#"#[pure] fn add(a: u24, b: i24) -> u24 {
+                return asm(in a0 = a, in a1 = b, out a0) { "add a0, a0, a1" };
+            }"#,
+        )
+        .unwrap();
+        check(r#"fn halt() -> u24 { asm { "li a7, 0" "ecall" }; return 0; }"#).unwrap();
+
+        for source in [
+            r#"fn f() -> u24 { return asm { "addz a0, a0, a0" }; }"#,
+            r#"fn f() -> u24 { return asm(in q0 = 1) { }; }"#,
+            r#"fn f() -> u24 { return asm(in a0 = 1, in x10 = 2) { }; }"#,
+            r#"fn f() -> u24 { return asm(in zero = 1) { }; }"#,
+            r#"fn f() -> u24 { return asm(out a9) { }; }"#,
+        ] {
+            assert!(
+                matches!(check(source), Err(TypeError::InvalidAssembly { .. })),
+                "{}",
+                source
+            );
+        }
+        assert!(matches!(
+            check(r#"#[view] fn f() -> u24 { return asm(out a0) { "ecall" }; }"#),
+            Err(TypeError::MutabilityViolation { .. })
+        ));
+        assert!(matches!(
+            check(r#"fn f(x: u64) -> u24 { return asm(in a0 = x) { }; }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_superpositions() {
+        let program = r#"
+            storage {
+                balances: StorageMap<u24, u24>,
+            }
+
+            event Approval { owner: u24, amount: u24 }
+
+            fn pay(to: u24) -> u24 {
+                return to;
+            }
+        "#;
+        let with = |function: &str| check(&format!("{}\n{}", program, function));
+
+        with("fn main(a: u24, b: u24) -> u24 { pay({a, b} + 1); return 0; }").unwrap();
+        with("fn main(a: u24) -> u24 { emit Approval({a, 2}, {0, 1, 2}); return 0; }").unwrap();
+        with("fn main(a: u24) -> u24 { balances[{a, 1}] = {0, 1}; return 0; }").unwrap();
+        with("fn main(a: u24) -> u24 { pay({{a, 1}, 2}); return 0; }").unwrap();
+
+        // Nowhere lowering cannot duplicate them
+        for function in [
+            "fn main(a: u24) -> u24 { return {a, 1}; }",
+            "fn main(a: u24) -> u24 { x = {a, 1}; return x; }",
+            "fn main(a: u24) -> u24 { require({a, 1} > 0, \"low\"); return 0; }",
+            "fn main(a: Bool) -> u24 { pay(a && {true, false}); return 0; }",
+            "fn main(xs: List<u24>) -> u24 { pay([{x, 1} for x in xs]); return 0; }",
+        ] {
+            assert!(
+                matches!(with(function), Err(TypeError::InvalidSuperposition { .. })),
+                "{}",
+                function
+            );
+        }
+
+        assert!(matches!(
+            with("fn main(a: u24) -> u24 { pay({a, true}); return 0; }"),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+
+        // 4 * 4 * 4 copies are fine, one more factor of 4 is too many
+        let spread = "{0, 1, 2, 3}";
+        let within = format!("pay({0} + {0} + {0});", spread);
+        with(&format!("fn main() -> u24 {{ {} return 0; }}", within)).unwrap();
+        let beyond = format!("pay({0} + {0} + {0} + {0});", spread);
+        assert!(matches!(
+            with(&format!("fn main() -> u24 {{ {} return 0; }}", beyond)),
+            Err(TypeError::InvalidSuperposition { .. })
+        ));
+    }
+    #[test]
+    fn test_unsupported_sharing() {
+        check("fn main(m: Map<u24, u24>) -> u24 { n = m; n[1] = 3; return m[1]; }").unwrap();
+
+        // The map written is not held by a variable that could be copied
+        let nested = check("fn main(m: Map<u24, Map<u24, u24>>) -> u24 { m[1][2] = 3; return 0; }");
+        assert!(matches!(
+            nested,
+            Err(TypeError::UnsupportedSharing { line: 1, .. })
+        ));
     }
 }
@@ -388,7 +388,7 @@ impl TypeInferrer {
                     param_types.push(param_type.clone());
                     self.env
                         .symbols
-                        .insert(param.name.clone(), Symbol::Variable(param_type));
+                        .insert(param.name.to_string(), Symbol::Variable(param_type));
                 }
 
                 let return_type = return_type
@@ -405,7 +405,7 @@ impl TypeInferrer {
 
                 self.env
                     .symbols
-                    .insert(name.clone(), Symbol::Function(fn_type));
+                    .insert(name.to_string(), Symbol::Function(fn_type));
                 let body_type = self.check_block(body)?;
                 self.solver.unify(&body_type, &return_type)?;
                 Ok(InferType::None)
@@ -415,9 +415,21 @@ impl TypeInferrer {
             } => {
                 let schema = TypeSchema {
                     type_vars: type_params.iter().cloned().collect(),
-                    type_: InferType::Named(name.clone(), vec![]),
+                    type_: InferType::Named(name.to_string(), vec![]),
                 };
-                self.env.symbols.insert(name.clone(), Symbol::Type(schema));
+                self.env
+                    .symbols
+                    .insert(name.to_string(), Symbol::Type(schema));
+                Ok(InferType::None)
+            }
+            Definition::ErrorDef { name, .. } => {
+                let schema = TypeSchema {
+                    type_vars: BTreeSet::new(),
+                    type_: InferType::Named(name.to_string(), vec![]),
+                };
+                self.env
+                    .symbols
+                    .insert(name.to_string(), Symbol::Type(schema));
                 Ok(InferType::None)
             }
             Definition::ObjectDef {
@@ -425,9 +437,11 @@ impl TypeInferrer {
             } => {
                 let schema = TypeSchema {
                     type_vars: type_params.iter().cloned().collect(),
-                    type_: InferType::Named(name.clone(), vec![]),
+                    type_: InferType::Named(name.to_string(), vec![]),
                 };
-                self.env.symbols.insert(name.clone(), Symbol::Type(schema));
+                self.env
+                    .symbols
+                    .insert(name.to_string(), Symbol::Type(schema));
                 Ok(InferType::None)
             }
             Definition::TypeAlias {
@@ -438,16 +452,31 @@ impl TypeInferrer {
                     type_vars: BTreeSet::new(),
                     type_: target,
                 };
-                self.env.symbols.insert(name.clone(), Symbol::Type(schema));
+                self.env
+                    .symbols
+                    .insert(name.to_string(), Symbol::Type(schema));
                 Ok(InferType::None)
             }
             Definition::Module { name, .. } => {
                 self.env.symbols.insert(
-                    name.clone(),
-                    Symbol::Module(InferType::Named(name.clone(), vec![])),
+                    name.to_string(),
+                    Symbol::Module(InferType::Named(name.to_string(), vec![])),
                 );
                 Ok(InferType::None)
             }
+            Definition::EventDef { .. }
+            | Definition::GuardDef { .. }
+            | Definition::InterfaceDef { .. }
+            | Definition::ContractDef { .. } => Ok(InferType::None),
+            Definition::StorageDef { fields, .. } => {
+                for field in fields {
+                    let field_type = self.infer_from_ast_type(&field.ty)?;
+                    self.env
+                        .symbols
+                        .insert(field.name.to_string(), Symbol::Variable(field_type));
+                }
+                Ok(InferType::None)
+            }
         }
     }
 
@@ -471,7 +500,7 @@ impl TypeInferrer {
                 let value_type = self.check_expr(value)?;
                 self.env
                     .symbols
-                    .insert(name.clone(), Symbol::Variable(value_type.clone()));
+                    .insert(name.to_string(), Symbol::Variable(value_type.clone()));
                 Ok(value_type)
             }
             Statement::If {
@@ -480,7 +509,9 @@ impl TypeInferrer {
                 else_branch,
                 ..
             } => {
-                let _cond_type = self.check_expr(condition)?;
+                let cond_type = self.check_expr(condition)?;
+                self.solver
+                    .unify(&cond_type, &InferType::Named("Bool".to_string(), vec![]))?;
                 let then_type = self.check_block(then_branch)?;
                 let else_type = self.check_block(else_branch)?;
                 self.solver.unify(&then_type, &else_type)?;
@@ -500,6 +531,32 @@ impl TypeInferrer {
                 Ok(result_type.unwrap_or(InferType::None))
             }
             Statement::Expr { expr, .. } => self.check_expr(expr),
+            Statement::Unchecked { body, .. } => self.check_block(body),
+            Statement::While {
+                condition, body, ..
+            } => {
+                let cond_type = self.check_expr(condition)?;
+                self.solver
+                    .unify(&cond_type, &InferType::Named("Bool".to_string(), vec![]))?;
+                self.check_block(body)?;
+                Ok(InferType::None)
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                ..
+            } => {
+                let start_type = self.check_expr(start)?;
+                let end_type = self.check_expr(end)?;
+                self.solver.unify(&start_type, &end_type)?;
+                self.env
+                    .symbols
+                    .insert(variable.to_string(), Symbol::Variable(start_type));
+                self.check_block(body)?;
+                Ok(InferType::None)
+            }
             Statement::LocalDef { function_def, .. } => self.check_definition(function_def),
             _ => Ok(InferType::None),
         }
@@ -508,7 +565,7 @@ impl TypeInferrer {
     fn check_expr(&mut self, expr: &Expr) -> Result<InferType, TypeError> {
         match expr {
             Expr::Variable { name, .. } => {
-                if let Some(symbol) = self.env.symbols.get(name) {
+                if let Some(symbol) = self.env.symbols.get(name.as_str()) {
                     match symbol {
                         Symbol::Variable(t) | Symbol::Function(t) => Ok(self.solver.apply_subst(t)),
                         Symbol::Constructor { type_, .. } => Ok(self.solver.apply_subst(type_)),
@@ -516,17 +573,25 @@ impl TypeInferrer {
                         Symbol::Module(t) => Ok(self.solver.apply_subst(t)),
                     }
                 } else {
-                    Err(TypeError::UndefinedVariable { name: name.clone() })
+                    Err(TypeError::UndefinedVariable {
+                        name: name.to_string(),
+                    })
                 }
             }
             Expr::Literal { kind, .. } => match kind {
                 LiteralKind::Uint(_) => Ok(InferType::U24),
+                LiteralKind::WideUint(value) => {
+                    Ok(InferType::Named(format!("u{}", value.bits()), vec![]))
+                }
+                LiteralKind::Address(_) => Ok(InferType::Named("Address".to_string(), vec![])),
+                LiteralKind::Hash(_) => Ok(InferType::Named("Hash".to_string(), vec![])),
+                LiteralKind::Bytes(_) => Ok(InferType::Named("Bytes".to_string(), vec![])),
                 LiteralKind::Int(_) => Ok(InferType::I24),
                 LiteralKind::Float(_) => Ok(InferType::F24),
                 LiteralKind::String(_) => Ok(InferType::Named("String".to_string(), vec![])),
                 LiteralKind::Char(_) => Ok(InferType::U24),
                 LiteralKind::Symbol(_) => Ok(InferType::U24),
-                LiteralKind::Bool(_) => Ok(InferType::U24),
+                LiteralKind::Bool(_) => Ok(InferType::Named("Bool".to_string(), vec![])),
             },
             Expr::Tuple { elements, .. } => {
                 let types: Result<Vec<_>, _> =
@@ -541,6 +606,23 @@ impl TypeInferrer {
                 };
                 Ok(InferType::Named("List".to_string(), vec![element_type]))
             }
+            Expr::Map { entries, .. } => {
+                let (key_type, value_type) = if let Some((key, value)) = entries.first() {
+                    (self.check_expr(key)?, self.check_expr(value)?)
+                } else {
+                    (InferType::Any, InferType::Any)
+                };
+                for (key, value) in entries.iter().skip(1) {
+                    let current_key = self.check_expr(key)?;
+                    self.solver.unify(&key_type, &current_key)?;
+                    let current_value = self.check_expr(value)?;
+                    self.solver.unify(&value_type, &current_value)?;
+                }
+                Ok(InferType::Named(
+                    "Map".to_string(),
+                    vec![key_type, value_type],
+                ))
+            }
             Expr::Constructor { name, args, .. } => {
                 let type_name = self
                     .env
@@ -571,11 +653,37 @@ impl TypeInferrer {
                     _ => Err(TypeError::Generic("Cannot call non-function".to_string())),
                 }
             }
-            Expr::BinaryOp { left, right, .. } => {
+            Expr::BinaryOp {
+                left,
+                operator,
+                right,
+                ..
+            } => {
                 let left_type = self.check_expr(left)?;
                 let right_type = self.check_expr(right)?;
                 self.solver.unify(&left_type, &right_type)?;
-                Ok(left_type)
+                match operator {
+                    BinaryOperator::Equal
+                    | BinaryOperator::NotEqual
+                    | BinaryOperator::Less
+                    | BinaryOperator::LessEqual
+                    | BinaryOperator::Greater
+                    | BinaryOperator::GreaterEqual => {
+                        Ok(InferType::Named("Bool".to_string(), vec![]))
+                    }
+                    BinaryOperator::And | BinaryOperator::Or => {
+                        let bool_type = InferType::Named("Bool".to_string(), vec![]);
+                        self.solver.unify(&left_type, &bool_type)?;
+                        Ok(bool_type)
+                    }
+                    _ => Ok(left_type),
+                }
+            }
+            Expr::UnaryOp { operand, .. } => {
+                let operand_type = self.check_expr(operand)?;
+                let bool_type = InferType::Named("Bool".to_string(), vec![]);
+                self.solver.unify(&operand_type, &bool_type)?;
+                Ok(bool_type)
             }
             Expr::Lambda { params, body, .. } => {
                 let mut param_types = Vec::new();
@@ -584,7 +692,7 @@ impl TypeInferrer {
                     param_types.push(param_type.clone());
                     self.env
                         .symbols
-                        .insert(param.name.clone(), Symbol::Variable(param_type));
+                        .insert(param.name.to_string(), Symbol::Variable(param_type));
                 }
                 let body_type = self.check_expr(body)?;
                 let fn_type = param_types.into_iter().rev().fold(body_type, |acc, param| {
@@ -606,7 +714,7 @@ impl TypeInferrer {
             Pattern::Variable { name, .. } => {
                 self.env
                     .symbols
-                    .insert(name.clone(), Symbol::Variable(expected_type.clone()));
+                    .insert(name.to_string(), Symbol::Variable(expected_type.clone()));
                 Ok(())
             }
             Pattern::Tuple { elements, .. } => {
@@ -630,7 +738,7 @@ impl TypeInferrer {
                     .iter()
                     .map(|p| self.infer_from_ast_type(p))
                     .collect::<Result<Vec<_>, _>>()?;
-                Ok(InferType::Named(name.clone(), param_types))
+                Ok(InferType::Named(name.to_string(), param_types))
             }
             Type::Function { param, result, .. } => {
                 let param_type = self.infer_from_ast_type(param)?;
@@ -655,7 +763,7 @@ impl TypeInferrer {
             Type::Hole { .. } => Ok(InferType::Variable("_".to_string())),
             Type::Unknown { .. } => Ok(InferType::Variable("_".to_string())),
             Type::Generic { name, bounds, .. } => Ok(InferType::Generic {
-                name: name.clone(),
+                name: name.to_string(),
                 bounds: bounds.iter().map(|b| b.trait_name.clone()).collect(),
             }),
             Type::Constrained { bounds, .. } => Ok(InferType::Generic {
@@ -696,7 +804,7 @@ mod tests {
         let program = Program {
             imports: vec![],
             definitions: vec![Definition::FunctionDef {
-                name: "test".to_string(),
+                name: "test".into(),
                 params: vec![],
                 return_type: Some(Type::U24 {
                     location: Location::default(),
@@ -712,6 +820,8 @@ mod tests {
                     location: Location::default(),
                 },
                 checked: Some(true),
+                attributes: Vec::new(),
+                visibility: Visibility::Private,
                 location: Location::default(),
             }],
             location: Location::default(),
@@ -0,0 +1,267 @@
+//! Conditional compilation with `#[cfg(...)]`
+//!
+//! Functions, types and objects, including the functions of objects and
+//! interfaces, can be left out of a build unless a predicate holds:
+//!
+//! ```text
+//! #[cfg(feature = "testnet")]
+//! fn faucet(amount: u24) -> u24 { ... }
+//!
+//! #[cfg(not(feature = "testnet"))]
+//! fn faucet(amount: u24) -> u24 { ... }
+//!
+//! #[cfg(any(test, feature = "debug"))]
+//! fn check_invariants() -> u24 { ... }
+//! ```
+//!
+//! `test` holds when the tests of a module run and `feature = "name"` when
+//! the feature is enabled, with `--features` or the `default` feature of
+//! `bend.toml`. `not`, `any` and `all` combine predicates. A definition
+//! with several `#[cfg]` attributes is kept when all of them hold.
+//!
+//! Definitions are removed right after parsing, so what they contain is
+//! never type checked nor compiled when their predicate does not hold.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compiler::analyzer::type_checker::TypeError;
+use crate::compiler::parser::ast::*;
+
+/// Name of the attribute making a definition conditional
+pub const CFG: &str = "cfg";
+
+/// What `#[cfg(...)]` predicates are evaluated against
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cfg {
+    /// The enabled features
+    pub features: BTreeSet<String>,
+    /// Whether the tests of the module are being run
+    pub test: bool,
+}
+
+impl Cfg {
+    /// No features enabled, outside of tests
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_features<I, S>(mut self, features: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.features.extend(features.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_test(mut self, test: bool) -> Self {
+        self.test = test;
+        self
+    }
+
+    /// Whether every `#[cfg]` of `attributes` holds
+    pub fn enabled(&self, attributes: &[Attribute]) -> Result<bool, TypeError> {
+        for attribute in attributes.iter().filter(|a| a.name == CFG) {
+            let holds = match attribute.args.as_slice() {
+                [predicate] => self.holds(attribute, predicate)?,
+                _ => return Err(invalid(attribute, "expected one predicate")),
+            };
+            if !holds {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Remove the definitions of `program` whose `#[cfg]` does not hold
+    pub fn configure(&self, program: &mut Program) -> Result<(), TypeError> {
+        self.retain(&mut program.definitions)
+    }
+
+    fn retain(&self, definitions: &mut Vec<Definition>) -> Result<(), TypeError> {
+        let mut kept = Vec::with_capacity(definitions.len());
+        for mut definition in definitions.drain(..) {
+            if !self.enabled(definition.attributes())? {
+                continue;
+            }
+            match &mut definition {
+                Definition::ObjectDef { functions, .. }
+                | Definition::InterfaceDef { functions, .. } => self.retain(functions)?,
+                Definition::Module { definitions, .. } => self.retain(definitions)?,
+                _ => {}
+            }
+            kept.push(definition);
+        }
+        *definitions = kept;
+        Ok(())
+    }
+
+    fn holds(&self, attribute: &Attribute, predicate: &Expr) -> Result<bool, TypeError> {
+        match predicate {
+            Expr::Variable { name, .. } if name == "test" => Ok(self.test),
+            Expr::BinaryOp {
+                left,
+                operator: BinaryOperator::Equal,
+                right,
+                ..
+            } => match (&**left, &**right) {
+                (
+                    Expr::Variable { name, .. },
+                    Expr::Literal {
+                        kind: LiteralKind::String(feature),
+                        ..
+                    },
+                ) if name == "feature" => Ok(self.features.contains(feature)),
+                _ => Err(invalid(attribute, "expected `feature = \"name\"`")),
+            },
+            Expr::FunctionCall { function, args, .. } => {
+                let name = match &**function {
+                    Expr::Variable { name, .. } => name.as_str(),
+                    _ => return Err(invalid(attribute, "expected a predicate")),
+                };
+                match (name, args.as_slice()) {
+                    ("not", [predicate]) => Ok(!self.holds(attribute, predicate)?),
+                    ("not", _) => Err(invalid(attribute, "`not` takes one predicate")),
+                    ("any", _) => {
+                        for predicate in args {
+                            if self.holds(attribute, predicate)? {
+                                return Ok(true);
+                            }
+                        }
+                        Ok(false)
+                    }
+                    ("all", _) => {
+                        for predicate in args {
+                            if !self.holds(attribute, predicate)? {
+                                return Ok(false);
+                            }
+                        }
+                        Ok(true)
+                    }
+                    _ => Err(invalid(
+                        attribute,
+                        &format!(
+                            "unknown predicate `{}`, expected `not`, `any` or `all`",
+                            name
+                        ),
+                    )),
+                }
+            }
+            Expr::Variable { name, .. } => Err(invalid(
+                attribute,
+                &format!(
+                    "unknown predicate `{}`, expected `test` or `feature = \"name\"`",
+                    name
+                ),
+            )),
+            _ => Err(invalid(attribute, "expected a predicate")),
+        }
+    }
+}
+
+fn invalid(attribute: &Attribute, reason: &str) -> TypeError {
+    TypeError::InvalidAttribute {
+        name: attribute.name.to_string(),
+        reason: reason.to_string(),
+        line: attribute.location.line,
+        column: attribute.location.column,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parser::Parser;
+
+    const SOURCE: &str = r#"
+        #[cfg(feature = "testnet")]
+        fn faucet(amount: u24) -> u24 {
+            return amount;
+        }
+
+        #[cfg(not(feature = "testnet"))]
+        fn faucet() -> u24 {
+            return 0;
+        }
+
+        #[cfg(any(test, all(feature = "debug", feature = "testnet")))]
+        fn check() -> u24 {
+            return 1;
+        }
+
+        object Vault {
+            #[cfg(test)]
+            fn reset() -> u24 {
+                return 0;
+            }
+
+            fn size() -> u24 {
+                return 1;
+            }
+        }
+    "#;
+
+    fn configured(cfg: &Cfg) -> Vec<String> {
+        let mut program = Parser::new(SOURCE).parse_program().unwrap();
+        cfg.configure(&mut program).unwrap();
+        let mut names = Vec::new();
+        for definition in &program.definitions {
+            match definition {
+                Definition::FunctionDef { name, params, .. } => {
+                    names.push(format!("{}/{}", name, params.len()))
+                }
+                Definition::ObjectDef {
+                    name, functions, ..
+                } => {
+                    for function in functions {
+                        if let Definition::FunctionDef { name: function, .. } = function {
+                            names.push(format!("{}.{}", name, function));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        names
+    }
+
+    #[test]
+    fn test_predicates() {
+        assert_eq!(configured(&Cfg::new()), ["faucet/0", "Vault.size"]);
+        assert_eq!(
+            configured(&Cfg::new().with_features(["testnet"])),
+            ["faucet/1", "Vault.size"]
+        );
+        assert_eq!(
+            configured(&Cfg::new().with_features(["testnet", "debug"])),
+            ["faucet/1", "check/0", "Vault.size"]
+        );
+        assert_eq!(
+            configured(&Cfg::new().with_test(true)),
+            ["faucet/0", "check/0", "Vault.reset", "Vault.size"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_predicates() {
+        for source in [
+            "#[cfg(debug)]\nfn f() -> u24 { return 0; }",
+            "#[cfg(feature = 1)]\nfn f() -> u24 { return 0; }",
+            "#[cfg(not(test, test))]\nfn f() -> u24 { return 0; }",
+            "#[cfg(either(test))]\nfn f() -> u24 { return 0; }",
+            "#[cfg]\nfn f() -> u24 { return 0; }",
+        ] {
+            let mut program = Parser::new(source).parse_program().unwrap();
+            assert!(
+                matches!(
+                    Cfg::new().configure(&mut program),
+                    Err(TypeError::InvalidAttribute { .. })
+                ),
+                "{}",
+                source
+            );
+        }
+    }
+}
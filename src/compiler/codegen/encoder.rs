@@ -0,0 +1,1058 @@
+//! RISC-V machine code encoder
+//!
+//! Assembles [`Instruction`]s to their 32-bit RISC-V encoding and decodes
+//! machine code back. Encoding runs in two passes: the first lays out every
+//! instruction and records label addresses, the second emits the words and
+//! records a [`Relocation`] for each label reference, which is patched once
+//! the whole program is emitted.
+//!
+//! Pseudo-instructions expand to their standard sequences: `li` to `addi`
+//! or `lui` + `addi`, `la` to `auipc` + `addi`, `mv`, `not` and `neg` to
+//! `addi`, `xori` and `sub`, `j` to `jal x0` and `ble a, b` to `bge b, a`.
+//! Labels and comments take no space.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::compiler::codegen::risc_v::{Instruction, Register};
+
+/// Base instruction set the code is generated and encoded for, always with
+/// the M extension
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Isa {
+    /// RV32EM, the 16 register base ISA PolkaVM implements
+    #[default]
+    Rv32Em,
+    /// RV32IM, with the full 32 register file
+    Rv32Im,
+    /// RV64IM, for 64-bit PolkaVM
+    Rv64Im,
+}
+
+impl Isa {
+    /// Register width in bits
+    pub fn xlen(self) -> u32 {
+        match self {
+            Isa::Rv32Em | Isa::Rv32Im => 32,
+            Isa::Rv64Im => 64,
+        }
+    }
+
+    /// Register width in bytes
+    pub fn word_size(self) -> i32 {
+        (self.xlen() / 8) as i32
+    }
+
+    /// Whether registers are 64 bits wide
+    pub fn is_64_bit(self) -> bool {
+        self.xlen() == 64
+    }
+
+    /// Number of integer registers
+    pub fn registers(self) -> u32 {
+        match self {
+            Isa::Rv32Em => 16,
+            Isa::Rv32Im | Isa::Rv64Im => 32,
+        }
+    }
+}
+
+impl fmt::Display for Isa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Isa::Rv32Em => write!(f, "RV32EM"),
+            Isa::Rv32Im => write!(f, "RV32IM"),
+            Isa::Rv64Im => write!(f, "RV64IM"),
+        }
+    }
+}
+
+impl FromStr for Isa {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rv32e" | "rv32em" => Ok(Isa::Rv32Em),
+            "rv32i" | "rv32im" => Ok(Isa::Rv32Im),
+            "rv64" | "rv64i" | "rv64im" => Ok(Isa::Rv64Im),
+            _ => Err(format!(
+                "Unknown ISA '{}', expected rv32e, rv32i or rv64",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum EncodeError {
+    #[error("Undefined label: {0}")]
+    UndefinedLabel(String),
+
+    #[error("Duplicate label: {0}")]
+    DuplicateLabel(String),
+
+    #[error("Register {register} is not available on {isa}")]
+    UnsupportedRegister { register: Register, isa: Isa },
+
+    #[error("`{instruction}` is not available on {isa}")]
+    UnsupportedInstruction { instruction: String, isa: Isa },
+
+    #[error("Immediate {value} of `{instruction}` is out of range")]
+    ImmediateOutOfRange { instruction: String, value: i64 },
+
+    #[error("Label {label} is out of range of {kind} at {offset:#x}")]
+    TargetOutOfRange {
+        label: String,
+        kind: RelocationKind,
+        offset: u32,
+    },
+
+    #[error("Invalid instruction {word:#010x} at {address:#x}")]
+    InvalidInstruction { address: u32, word: u32 },
+
+    #[error("Jump target {0:#x} is not an instruction boundary")]
+    InvalidTarget(u32),
+
+    #[error("Machine code length {0} is not a multiple of 4")]
+    Truncated(usize),
+}
+
+/// How a label reference is patched into an instruction word
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocationKind {
+    /// 13-bit PC-relative offset of a conditional branch
+    Branch,
+    /// 21-bit PC-relative offset of `jal`
+    Jal,
+    /// Upper 20 bits of a PC-relative address, in `auipc`
+    PcrelHi20,
+    /// Lower 12 bits of a PC-relative address, in the `addi` that follows
+    /// its `auipc`
+    PcrelLo12,
+}
+
+impl fmt::Display for RelocationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelocationKind::Branch => write!(f, "branch"),
+            RelocationKind::Jal => write!(f, "jal"),
+            RelocationKind::PcrelHi20 => write!(f, "pcrel_hi20"),
+            RelocationKind::PcrelLo12 => write!(f, "pcrel_lo12"),
+        }
+    }
+}
+
+/// A label reference in the emitted code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// Byte offset of the patched word
+    pub offset: u32,
+    /// Encoding of the reference
+    pub kind: RelocationKind,
+    /// Referenced label
+    pub label: String,
+}
+
+impl Relocation {
+    /// Address the offset is relative to
+    fn anchor(&self) -> u32 {
+        match self.kind {
+            // The low part is relative to its `auipc`
+            RelocationKind::PcrelLo12 => self.offset - 4,
+            _ => self.offset,
+        }
+    }
+}
+
+/// Assembled machine code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineCode {
+    /// Little-endian instruction words
+    pub code: Vec<u8>,
+    /// Byte address of every input instruction
+    pub addresses: Vec<u32>,
+    /// Address of every label
+    pub labels: BTreeMap<String, u32>,
+    /// Resolved label references
+    pub relocations: Vec<Relocation>,
+}
+
+impl MachineCode {
+    /// Encoding of the instruction at `index`, empty for labels and comments
+    pub fn bytes(&self, index: usize) -> &[u8] {
+        let start = self.addresses[index] as usize;
+        let end = self
+            .addresses
+            .get(index + 1)
+            .map_or(self.code.len(), |end| *end as usize);
+        &self.code[start..end]
+    }
+}
+
+/// Assemble instructions to machine code for `isa`
+pub fn assemble(instructions: &[Instruction], isa: Isa) -> Result<MachineCode, EncodeError> {
+    let mut addresses = Vec::with_capacity(instructions.len());
+    let mut labels = BTreeMap::new();
+    let mut address = 0;
+    for instruction in instructions {
+        addresses.push(address);
+        if let Instruction::Label(name) = instruction {
+            if labels.insert(name.clone(), address).is_some() {
+                return Err(EncodeError::DuplicateLabel(name.clone()));
+            }
+        }
+        address += size(instruction);
+    }
+
+    let mut encoder = Encoder {
+        isa,
+        code: Vec::with_capacity(address as usize),
+        relocations: Vec::new(),
+    };
+    for instruction in instructions {
+        encoder.encode(instruction)?;
+    }
+
+    for relocation in &encoder.relocations {
+        let target = *labels
+            .get(&relocation.label)
+            .ok_or_else(|| EncodeError::UndefinedLabel(relocation.label.clone()))?;
+        let delta = target.wrapping_sub(relocation.anchor()) as i32;
+        let at = relocation.offset as usize;
+        let word = u32::from_le_bytes(encoder.code[at..at + 4].try_into().unwrap());
+        let patched =
+            patch(word, relocation.kind, delta).ok_or_else(|| EncodeError::TargetOutOfRange {
+                label: relocation.label.clone(),
+                kind: relocation.kind,
+                offset: relocation.offset,
+            })?;
+        encoder.code[at..at + 4].copy_from_slice(&patched.to_le_bytes());
+    }
+
+    Ok(MachineCode {
+        code: encoder.code,
+        addresses,
+        labels,
+        relocations: encoder.relocations,
+    })
+}
+
+/// Size in bytes of an instruction's encoding
+pub fn size(instruction: &Instruction) -> u32 {
+    match instruction {
+        Instruction::Label(_) | Instruction::Comment(_) => 0,
+        Instruction::La(_, _) => 8,
+        Instruction::Li(_, value) => {
+            let (upper, lower) = li_parts(*value);
+            4 * (upper.is_some() as u32 + lower.is_some() as u32)
+        }
+        _ => 4,
+    }
+}
+
+/// Immediates of the `lui` and `addi` that load `value`, each optional
+fn li_parts(value: i32) -> (Option<i32>, Option<i32>) {
+    if fits_signed(value as i64, 12) {
+        return (None, Some(value));
+    }
+    let low = sign_extend(value as u32 & 0xFFF, 12);
+    (Some(value.wrapping_sub(low)), (low != 0).then_some(low))
+}
+
+struct Encoder {
+    isa: Isa,
+    code: Vec<u8>,
+    relocations: Vec<Relocation>,
+}
+
+impl Encoder {
+    fn encode(&mut self, instruction: &Instruction) -> Result<(), EncodeError> {
+        use Instruction::*;
+
+        if instruction.is_rv64_only() && !self.isa.is_64_bit() {
+            return Err(EncodeError::UnsupportedInstruction {
+                instruction: instruction.to_string().trim().to_string(),
+                isa: self.isa,
+            });
+        }
+
+        match instruction {
+            Load64(rd, rs1, offset) => self.i_type(instruction, *offset, *rs1, 3, *rd, 0x03),
+            Store64(rs2, rs1, offset) => self.s_type(instruction, *offset, *rs2, *rs1, 3),
+            Load(rd, rs1, offset) => self.i_type(instruction, *offset, *rs1, 2, *rd, 0x03),
+            LoadByteU(rd, rs1, offset) => self.i_type(instruction, *offset, *rs1, 4, *rd, 0x03),
+            Store(rs2, rs1, offset) => self.s_type(instruction, *offset, *rs2, *rs1, 2),
+            StoreByte(rs2, rs1, offset) => self.s_type(instruction, *offset, *rs2, *rs1, 0),
+
+            Add(rd, rs1, rs2) => self.r_type(0, *rs2, *rs1, 0, *rd),
+            Sub(rd, rs1, rs2) => self.r_type(0x20, *rs2, *rs1, 0, *rd),
+            ShiftLeft(rd, rs1, rs2) => self.r_type(0, *rs2, *rs1, 1, *rd),
+            SetLessThan(rd, rs1, rs2) => self.r_type(0, *rs2, *rs1, 2, *rd),
+            SetLessThanU(rd, rs1, rs2) => self.r_type(0, *rs2, *rs1, 3, *rd),
+            Xor(rd, rs1, rs2) => self.r_type(0, *rs2, *rs1, 4, *rd),
+            ShiftRight(rd, rs1, rs2) => self.r_type(0, *rs2, *rs1, 5, *rd),
+            ShiftRightArith(rd, rs1, rs2) => self.r_type(0x20, *rs2, *rs1, 5, *rd),
+            Or(rd, rs1, rs2) => self.r_type(0, *rs2, *rs1, 6, *rd),
+            And(rd, rs1, rs2) => self.r_type(0, *rs2, *rs1, 7, *rd),
+            Mul(rd, rs1, rs2) => self.r_type(1, *rs2, *rs1, 0, *rd),
+            MulHigh(rd, rs1, rs2) => self.r_type(1, *rs2, *rs1, 1, *rd),
+            MulHighU(rd, rs1, rs2) => self.r_type(1, *rs2, *rs1, 3, *rd),
+            Div(rd, rs1, rs2) => self.r_type(1, *rs2, *rs1, 4, *rd),
+            Rem(rd, rs1, rs2) => self.r_type(1, *rs2, *rs1, 6, *rd),
+
+            AddW(rd, rs1, rs2) => self.r_type_w(0, *rs2, *rs1, 0, *rd),
+            SubW(rd, rs1, rs2) => self.r_type_w(0x20, *rs2, *rs1, 0, *rd),
+            ShiftLeftW(rd, rs1, rs2) => self.r_type_w(0, *rs2, *rs1, 1, *rd),
+            ShiftRightW(rd, rs1, rs2) => self.r_type_w(0, *rs2, *rs1, 5, *rd),
+            ShiftRightArithW(rd, rs1, rs2) => self.r_type_w(0x20, *rs2, *rs1, 5, *rd),
+            MulW(rd, rs1, rs2) => self.r_type_w(1, *rs2, *rs1, 0, *rd),
+            DivW(rd, rs1, rs2) => self.r_type_w(1, *rs2, *rs1, 4, *rd),
+            RemW(rd, rs1, rs2) => self.r_type_w(1, *rs2, *rs1, 6, *rd),
+            AddImmW(rd, rs1, imm) => self.i_type(instruction, *imm, *rs1, 0, *rd, 0x1B),
+            ShiftLeftImmW(rd, rs1, shamt) => self.shift_w(instruction, 0, *shamt, *rs1, 1, *rd),
+            ShiftRightImmW(rd, rs1, shamt) => self.shift_w(instruction, 0, *shamt, *rs1, 5, *rd),
+            ShiftRightArithImmW(rd, rs1, shamt) => {
+                self.shift_w(instruction, 0x20, *shamt, *rs1, 5, *rd)
+            }
+
+            AddImm(rd, rs1, imm) => self.i_type(instruction, *imm, *rs1, 0, *rd, 0x13),
+            SetLessThanImm(rd, rs1, imm) => self.i_type(instruction, *imm, *rs1, 2, *rd, 0x13),
+            SetLessThanImmU(rd, rs1, imm) => self.i_type(instruction, *imm, *rs1, 3, *rd, 0x13),
+            XorImm(rd, rs1, imm) => self.i_type(instruction, *imm, *rs1, 4, *rd, 0x13),
+            OrImm(rd, rs1, imm) => self.i_type(instruction, *imm, *rs1, 6, *rd, 0x13),
+            AndImm(rd, rs1, imm) => self.i_type(instruction, *imm, *rs1, 7, *rd, 0x13),
+            ShiftLeftImm(rd, rs1, shamt) => self.shift(instruction, 0, *shamt, *rs1, 1, *rd),
+            ShiftRightImm(rd, rs1, shamt) => self.shift(instruction, 0, *shamt, *rs1, 5, *rd),
+            ShiftRightArithImm(rd, rs1, shamt) => {
+                self.shift(instruction, 0x20, *shamt, *rs1, 5, *rd)
+            }
+
+            BranchEq(rs1, rs2, label) => self.branch(*rs1, *rs2, 0, label),
+            BranchNe(rs1, rs2, label) => self.branch(*rs1, *rs2, 1, label),
+            BranchLt(rs1, rs2, label) => self.branch(*rs1, *rs2, 4, label),
+            BranchGe(rs1, rs2, label) => self.branch(*rs1, *rs2, 5, label),
+            BranchLe(rs1, rs2, label) => self.branch(*rs2, *rs1, 5, label),
+            BranchLtU(rs1, rs2, label) => self.branch(*rs1, *rs2, 6, label),
+            BranchGeU(rs1, rs2, label) => self.branch(*rs1, *rs2, 7, label),
+
+            Jump(label) => self.jal(Register::X0, label),
+            JumpAndLink(rd, label) => self.jal(*rd, label),
+            JumpAndLinkReg(rd, rs1, offset) => {
+                self.i_type(instruction, *offset, *rs1, 0, *rd, 0x67)
+            }
+
+            Ecall => {
+                self.emit(0x0000_0073);
+                Ok(())
+            }
+            Ebreak => {
+                self.emit(0x0010_0073);
+                Ok(())
+            }
+
+            Li(rd, value) => {
+                let rd = self.register(*rd)?;
+                let (upper, lower) = li_parts(*value);
+                if let Some(upper) = upper {
+                    self.emit(upper as u32 | rd << 7 | 0x37);
+                }
+                if let Some(lower) = lower {
+                    // On RV64 `lui` sign-extends, so the low part is added
+                    // as a word to stay within 32 bits
+                    let (source, opcode) = match upper {
+                        Some(_) if self.isa.is_64_bit() => (rd, 0x1B),
+                        Some(_) => (rd, 0x13),
+                        None => (0, 0x13),
+                    };
+                    self.emit(i_word(lower, source, 0, rd, opcode));
+                }
+                Ok(())
+            }
+            La(rd, label) => {
+                let rd = self.register(*rd)?;
+                self.relocate(RelocationKind::PcrelHi20, label);
+                self.emit(rd << 7 | 0x17);
+                self.relocate(RelocationKind::PcrelLo12, label);
+                self.emit(i_word(0, rd, 0, rd, 0x13));
+                Ok(())
+            }
+            Mv(rd, rs1) => self.i_type(instruction, 0, *rs1, 0, *rd, 0x13),
+            Not(rd, rs1) => self.i_type(instruction, -1, *rs1, 4, *rd, 0x13),
+            Neg(rd, rs1) => self.r_type(0x20, *rs1, Register::X0, 0, *rd),
+
+            Label(_) | Comment(_) => Ok(()),
+        }
+    }
+
+    fn register(&self, register: Register) -> Result<u32, EncodeError> {
+        let number = register as u32;
+        if self.isa == Isa::Rv32Em && number >= 16 {
+            return Err(EncodeError::UnsupportedRegister {
+                register,
+                isa: self.isa,
+            });
+        }
+        Ok(number)
+    }
+
+    fn emit(&mut self, word: u32) {
+        self.code.extend_from_slice(&word.to_le_bytes());
+    }
+
+    /// Record a reference to `label` from the next emitted word
+    fn relocate(&mut self, kind: RelocationKind, label: &str) {
+        self.relocations.push(Relocation {
+            offset: self.code.len() as u32,
+            kind,
+            label: label.to_string(),
+        });
+    }
+
+    fn r_type(
+        &mut self,
+        funct7: u32,
+        rs2: Register,
+        rs1: Register,
+        funct3: u32,
+        rd: Register,
+    ) -> Result<(), EncodeError> {
+        self.r_word(funct7, rs2, rs1, funct3, rd, 0x33)
+    }
+
+    /// An RV64 word operation
+    fn r_type_w(
+        &mut self,
+        funct7: u32,
+        rs2: Register,
+        rs1: Register,
+        funct3: u32,
+        rd: Register,
+    ) -> Result<(), EncodeError> {
+        self.r_word(funct7, rs2, rs1, funct3, rd, 0x3B)
+    }
+
+    fn r_word(
+        &mut self,
+        funct7: u32,
+        rs2: Register,
+        rs1: Register,
+        funct3: u32,
+        rd: Register,
+        opcode: u32,
+    ) -> Result<(), EncodeError> {
+        let word = funct7 << 25
+            | self.register(rs2)? << 20
+            | self.register(rs1)? << 15
+            | funct3 << 12
+            | self.register(rd)? << 7
+            | opcode;
+        self.emit(word);
+        Ok(())
+    }
+
+    fn i_type(
+        &mut self,
+        instruction: &Instruction,
+        imm: i32,
+        rs1: Register,
+        funct3: u32,
+        rd: Register,
+        opcode: u32,
+    ) -> Result<(), EncodeError> {
+        check_immediate(instruction, imm, 12)?;
+        let word = i_word(imm, self.register(rs1)?, funct3, self.register(rd)?, opcode);
+        self.emit(word);
+        Ok(())
+    }
+
+    fn shift(
+        &mut self,
+        instruction: &Instruction,
+        funct7: u32,
+        shamt: i32,
+        rs1: Register,
+        funct3: u32,
+        rd: Register,
+    ) -> Result<(), EncodeError> {
+        // RV64 shifts take a 6-bit amount, whose top bit overlaps funct7
+        if !(0..self.isa.xlen() as i32).contains(&shamt) {
+            return Err(out_of_range(instruction, shamt));
+        }
+        let word = i_word(
+            (funct7 << 5) as i32 | shamt,
+            self.register(rs1)?,
+            funct3,
+            self.register(rd)?,
+            0x13,
+        );
+        self.emit(word);
+        Ok(())
+    }
+
+    /// An RV64 word shift by an immediate
+    fn shift_w(
+        &mut self,
+        instruction: &Instruction,
+        funct7: u32,
+        shamt: i32,
+        rs1: Register,
+        funct3: u32,
+        rd: Register,
+    ) -> Result<(), EncodeError> {
+        if !(0..32).contains(&shamt) {
+            return Err(out_of_range(instruction, shamt));
+        }
+        let word = i_word(
+            (funct7 << 5) as i32 | shamt,
+            self.register(rs1)?,
+            funct3,
+            self.register(rd)?,
+            0x1B,
+        );
+        self.emit(word);
+        Ok(())
+    }
+
+    fn s_type(
+        &mut self,
+        instruction: &Instruction,
+        imm: i32,
+        rs2: Register,
+        rs1: Register,
+        funct3: u32,
+    ) -> Result<(), EncodeError> {
+        check_immediate(instruction, imm, 12)?;
+        let imm = imm as u32;
+        let word = (imm >> 5 & 0x7F) << 25
+            | self.register(rs2)? << 20
+            | self.register(rs1)? << 15
+            | funct3 << 12
+            | (imm & 0x1F) << 7
+            | 0x23;
+        self.emit(word);
+        Ok(())
+    }
+
+    fn branch(
+        &mut self,
+        rs1: Register,
+        rs2: Register,
+        funct3: u32,
+        label: &str,
+    ) -> Result<(), EncodeError> {
+        let word = self.register(rs2)? << 20 | self.register(rs1)? << 15 | funct3 << 12 | 0x63;
+        self.relocate(RelocationKind::Branch, label);
+        self.emit(word);
+        Ok(())
+    }
+
+    fn jal(&mut self, rd: Register, label: &str) -> Result<(), EncodeError> {
+        let word = self.register(rd)? << 7 | 0x6F;
+        self.relocate(RelocationKind::Jal, label);
+        self.emit(word);
+        Ok(())
+    }
+}
+
+fn i_word(imm: i32, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    (imm as u32 & 0xFFF) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+
+/// Patch the PC-relative `delta` into an instruction word, `None` if it
+/// does not fit
+fn patch(word: u32, kind: RelocationKind, delta: i32) -> Option<u32> {
+    let imm = delta as u32;
+    match kind {
+        RelocationKind::Branch => (delta % 2 == 0 && fits_signed(delta as i64, 13)).then_some(
+            word | (imm >> 12 & 1) << 31
+                | (imm >> 5 & 0x3F) << 25
+                | (imm >> 1 & 0xF) << 8
+                | (imm >> 11 & 1) << 7,
+        ),
+        RelocationKind::Jal => (delta % 2 == 0 && fits_signed(delta as i64, 21)).then_some(
+            word | (imm >> 20 & 1) << 31
+                | (imm >> 1 & 0x3FF) << 21
+                | (imm >> 11 & 1) << 20
+                | (imm >> 12 & 0xFF) << 12,
+        ),
+        // A 32-bit address space is covered by every 32-bit delta
+        RelocationKind::PcrelHi20 => Some(word | (imm.wrapping_add(0x800) & 0xFFFF_F000)),
+        RelocationKind::PcrelLo12 => Some(word | (imm & 0xFFF) << 20),
+    }
+}
+
+fn fits_signed(value: i64, bits: u32) -> bool {
+    let limit = 1i64 << (bits - 1);
+    (-limit..limit).contains(&value)
+}
+
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((value << shift) as i32) >> shift
+}
+
+fn check_immediate(instruction: &Instruction, imm: i32, bits: u32) -> Result<(), EncodeError> {
+    if fits_signed(imm as i64, bits) {
+        Ok(())
+    } else {
+        Err(out_of_range(instruction, imm))
+    }
+}
+
+fn out_of_range(instruction: &Instruction, value: i32) -> EncodeError {
+    EncodeError::ImmediateOutOfRange {
+        instruction: instruction.to_string(),
+        value: value as i64,
+    }
+}
+
+/// A decoded word whose label references are still addresses
+enum Decoded {
+    Plain(Instruction),
+    Branch(
+        fn(Register, Register, String) -> Instruction,
+        Register,
+        Register,
+        u32,
+    ),
+    Jal(Register, u32),
+    Auipc(Register, u32),
+}
+
+/// Decode machine code back to instructions
+///
+/// Branch and jump targets get labels named after their address, like
+/// `.L1c`, and `auipc` + `addi` pairs decode to `la`. Every instruction
+/// decodes to one that [`assemble`] encodes to the same bytes, for the ISA
+/// the code was assembled for.
+pub fn disassemble(code: &[u8]) -> Result<Vec<Instruction>, EncodeError> {
+    if !code.len().is_multiple_of(4) {
+        return Err(EncodeError::Truncated(code.len()));
+    }
+
+    let mut decoded = Vec::with_capacity(code.len() / 4);
+    for (index, chunk) in code.chunks_exact(4).enumerate() {
+        let address = index as u32 * 4;
+        let word = u32::from_le_bytes(chunk.try_into().unwrap());
+        decoded.push(decode(address, word)?);
+    }
+
+    let mut targets = BTreeSet::new();
+    let mut loads = HashMap::new();
+    for (index, word) in decoded.iter().enumerate() {
+        let address = index as u32 * 4;
+        match word {
+            Decoded::Branch(_, _, _, target) | Decoded::Jal(_, target) => {
+                targets.insert(*target);
+            }
+            Decoded::Auipc(rd, high) => {
+                let low = match decoded.get(index + 1) {
+                    Some(Decoded::Plain(Instruction::AddImm(dest, src, low)))
+                        if dest == rd && src == rd =>
+                    {
+                        *low
+                    }
+                    _ => {
+                        return Err(EncodeError::InvalidInstruction {
+                            address,
+                            word: high | (*rd as u32) << 7 | 0x17,
+                        })
+                    }
+                };
+                let target = address.wrapping_add(*high).wrapping_add(low as u32);
+                targets.insert(target);
+                loads.insert(index, target);
+            }
+            Decoded::Plain(_) => {}
+        }
+    }
+
+    let end = code.len() as u32;
+    let label = |target: u32| format!(".L{:x}", target);
+    let mut instructions = Vec::with_capacity(decoded.len() + targets.len());
+    let mut skip = false;
+    for (index, word) in decoded.into_iter().enumerate() {
+        let address = index as u32 * 4;
+        if targets.contains(&address) {
+            if skip {
+                // The low half of an `la` cannot be a jump target
+                return Err(EncodeError::InvalidTarget(address));
+            }
+            instructions.push(Instruction::Label(label(address)));
+        }
+        if std::mem::take(&mut skip) {
+            continue;
+        }
+        instructions.push(match word {
+            Decoded::Plain(instruction) => instruction,
+            Decoded::Branch(build, rs1, rs2, target) => build(rs1, rs2, label(target)),
+            Decoded::Jal(Register::X0, target) => Instruction::Jump(label(target)),
+            Decoded::Jal(rd, target) => Instruction::JumpAndLink(rd, label(target)),
+            Decoded::Auipc(rd, _) => {
+                skip = true;
+                Instruction::La(rd, label(loads[&index]))
+            }
+        });
+    }
+    if targets.contains(&end) {
+        instructions.push(Instruction::Label(label(end)));
+    }
+    if let Some(target) = targets
+        .into_iter()
+        .find(|target| *target > end || target % 4 != 0)
+    {
+        return Err(EncodeError::InvalidTarget(target));
+    }
+
+    Ok(instructions)
+}
+
+fn decode(address: u32, word: u32) -> Result<Decoded, EncodeError> {
+    use Instruction::*;
+
+    let invalid = EncodeError::InvalidInstruction { address, word };
+    let register = |shift: u32| Register::from_number(word >> shift & 0x1F).unwrap();
+    let (rd, rs1, rs2) = (register(7), register(15), register(20));
+    let funct3 = word >> 12 & 0x7;
+    let funct7 = word >> 25;
+    let i_imm = (word as i32) >> 20;
+
+    let instruction = match word & 0x7F {
+        0x33 => match (funct7, funct3) {
+            (0, 0) => Add(rd, rs1, rs2),
+            (0x20, 0) => Sub(rd, rs1, rs2),
+            (0, 1) => ShiftLeft(rd, rs1, rs2),
+            (0, 2) => SetLessThan(rd, rs1, rs2),
+            (0, 3) => SetLessThanU(rd, rs1, rs2),
+            (0, 4) => Xor(rd, rs1, rs2),
+            (0, 5) => ShiftRight(rd, rs1, rs2),
+            (0x20, 5) => ShiftRightArith(rd, rs1, rs2),
+            (0, 6) => Or(rd, rs1, rs2),
+            (0, 7) => And(rd, rs1, rs2),
+            (1, 0) => Mul(rd, rs1, rs2),
+            (1, 1) => MulHigh(rd, rs1, rs2),
+            (1, 3) => MulHighU(rd, rs1, rs2),
+            (1, 4) => Div(rd, rs1, rs2),
+            (1, 6) => Rem(rd, rs1, rs2),
+            _ => return Err(invalid),
+        },
+        0x3B => match (funct7, funct3) {
+            (0, 0) => AddW(rd, rs1, rs2),
+            (0x20, 0) => SubW(rd, rs1, rs2),
+            (0, 1) => ShiftLeftW(rd, rs1, rs2),
+            (0, 5) => ShiftRightW(rd, rs1, rs2),
+            (0x20, 5) => ShiftRightArithW(rd, rs1, rs2),
+            (1, 0) => MulW(rd, rs1, rs2),
+            (1, 4) => DivW(rd, rs1, rs2),
+            (1, 6) => RemW(rd, rs1, rs2),
+            _ => return Err(invalid),
+        },
+        0x1B => {
+            let shamt = i_imm & 0x1F;
+            match (funct3, funct7) {
+                (0, _) => AddImmW(rd, rs1, i_imm),
+                (1, 0) => ShiftLeftImmW(rd, rs1, shamt),
+                (5, 0) => ShiftRightImmW(rd, rs1, shamt),
+                (5, 0x20) => ShiftRightArithImmW(rd, rs1, shamt),
+                _ => return Err(invalid),
+            }
+        }
+        0x13 => {
+            // The top bit of an RV64 shift amount is the low bit of funct7
+            let shamt = i_imm & 0x3F;
+            match (funct3, funct7 & !1) {
+                (0, _) => AddImm(rd, rs1, i_imm),
+                (1, 0) => ShiftLeftImm(rd, rs1, shamt),
+                (2, _) => SetLessThanImm(rd, rs1, i_imm),
+                (3, _) => SetLessThanImmU(rd, rs1, i_imm),
+                (4, _) => XorImm(rd, rs1, i_imm),
+                (5, 0) => ShiftRightImm(rd, rs1, shamt),
+                (5, 0x20) => ShiftRightArithImm(rd, rs1, shamt),
+                (6, _) => OrImm(rd, rs1, i_imm),
+                (7, _) => AndImm(rd, rs1, i_imm),
+                _ => return Err(invalid),
+            }
+        }
+        0x03 => match funct3 {
+            2 => Load(rd, rs1, i_imm),
+            3 => Load64(rd, rs1, i_imm),
+            4 => LoadByteU(rd, rs1, i_imm),
+            _ => return Err(invalid),
+        },
+        0x23 => {
+            let imm = ((word & 0xFE00_0000) as i32 >> 20) | (word >> 7 & 0x1F) as i32;
+            match funct3 {
+                2 => Store(rs2, rs1, imm),
+                3 => Store64(rs2, rs1, imm),
+                0 => StoreByte(rs2, rs1, imm),
+                _ => return Err(invalid),
+            }
+        }
+        0x63 => {
+            let imm = (word >> 31) << 12
+                | (word >> 7 & 1) << 11
+                | (word >> 25 & 0x3F) << 5
+                | (word >> 8 & 0xF) << 1;
+            let target = address.wrapping_add(sign_extend(imm, 13) as u32);
+            let build: fn(Register, Register, String) -> Instruction = match funct3 {
+                0 => BranchEq,
+                1 => BranchNe,
+                4 => BranchLt,
+                5 => BranchGe,
+                6 => BranchLtU,
+                7 => BranchGeU,
+                _ => return Err(invalid),
+            };
+            return Ok(Decoded::Branch(build, rs1, rs2, target));
+        }
+        0x6F => {
+            let imm = (word >> 31) << 20
+                | (word >> 12 & 0xFF) << 12
+                | (word >> 20 & 1) << 11
+                | (word >> 21 & 0x3FF) << 1;
+            let target = address.wrapping_add(sign_extend(imm, 21) as u32);
+            return Ok(Decoded::Jal(rd, target));
+        }
+        0x67 if funct3 == 0 => JumpAndLinkReg(rd, rs1, i_imm),
+        // `li` of a multiple of 4096; `lui rd, 0` has no `li` that encodes
+        // to it
+        0x37 if word >> 12 != 0 => Li(rd, (word & 0xFFFF_F000) as i32),
+        0x17 => return Ok(Decoded::Auipc(rd, word & 0xFFFF_F000)),
+        0x73 if word == 0x0000_0073 => Ecall,
+        0x73 if word == 0x0010_0073 => Ebreak,
+        _ => return Err(invalid),
+    };
+    Ok(Decoded::Plain(instruction))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(machine_code: &MachineCode) -> Vec<u32> {
+        machine_code
+            .code
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_known_encodings() {
+        let machine_code = assemble(
+            &[
+                Instruction::AddImm(Register::X10, Register::X10, 1),
+                Instruction::Add(Register::X10, Register::X11, Register::X12),
+                Instruction::Mul(Register::X5, Register::X6, Register::X7),
+                Instruction::Load(Register::X10, Register::X2, -4),
+                Instruction::Store(Register::X11, Register::X2, 8),
+                Instruction::ShiftRightArithImm(Register::X5, Register::X5, 3),
+                Instruction::JumpAndLinkReg(Register::X0, Register::X1, 0),
+                Instruction::Ecall,
+            ],
+            Isa::Rv32Em,
+        )
+        .unwrap();
+
+        assert_eq!(
+            words(&machine_code),
+            [
+                0x0015_0513, // addi a0, a0, 1
+                0x00C5_8533, // add a0, a1, a2
+                0x0273_02B3, // mul t0, t1, t2
+                0xFFC1_2503, // lw a0, -4(sp)
+                0x00B1_2423, // sw a1, 8(sp)
+                0x4032_D293, // srai t0, t0, 3
+                0x0000_8067, // ret
+                0x0000_0073, // ecall
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rv64_encodings() {
+        let program = [
+            Instruction::AddW(Register::X10, Register::X11, Register::X12),
+            Instruction::AddImmW(Register::X10, Register::X10, 1),
+            Instruction::Load64(Register::X10, Register::X2, 8),
+            Instruction::Store64(Register::X1, Register::X2, 8),
+            Instruction::ShiftLeftImm(Register::X10, Register::X10, 40),
+            Instruction::ShiftRightArithImmW(Register::X5, Register::X5, 3),
+            Instruction::Li(Register::X11, 0x12345),
+        ];
+        let machine_code = assemble(&program, Isa::Rv64Im).unwrap();
+
+        assert_eq!(
+            words(&machine_code),
+            [
+                0x00C5_853B, // addw a0, a1, a2
+                0x0015_051B, // addiw a0, a0, 1
+                0x0081_3503, // ld a0, 8(sp)
+                0x0011_3423, // sd ra, 8(sp)
+                0x0285_1513, // slli a0, a0, 40
+                0x4032_D29B, // sraiw t0, t0, 3
+                0x0001_25B7, // lui a1, 0x12
+                0x3455_859B, // addiw a1, a1, 0x345
+            ]
+        );
+        let decoded = disassemble(&machine_code.code).unwrap();
+        let reassembled = assemble(&decoded, Isa::Rv64Im).unwrap();
+        assert_eq!(reassembled.code, machine_code.code);
+
+        assert_eq!(
+            assemble(&program[..1], Isa::Rv32Im).unwrap_err(),
+            EncodeError::UnsupportedInstruction {
+                instruction: "addw a0, a1, a2".to_string(),
+                isa: Isa::Rv32Im,
+            }
+        );
+        assert!(matches!(
+            assemble(&program[4..5], Isa::Rv32Im),
+            Err(EncodeError::ImmediateOutOfRange { value: 40, .. })
+        ));
+    }
+
+    #[test]
+    fn test_isa_names() {
+        assert_eq!("rv32e".parse::<Isa>().unwrap(), Isa::Rv32Em);
+        assert_eq!("RV32IM".parse::<Isa>().unwrap(), Isa::Rv32Im);
+        assert_eq!("rv64".parse::<Isa>().unwrap(), Isa::Rv64Im);
+        assert!("rv128".parse::<Isa>().is_err());
+        assert_eq!(Isa::Rv64Im.word_size(), 8);
+        assert_eq!(serde_json::to_string(&Isa::Rv64Im).unwrap(), "\"rv64im\"");
+    }
+
+    #[test]
+    fn test_labels_and_relocations() {
+        let machine_code = assemble(
+            &[
+                Instruction::Label("loop".to_string()),
+                Instruction::Comment("count down".to_string()),
+                Instruction::AddImm(Register::X10, Register::X10, -1),
+                Instruction::BranchNe(Register::X10, Register::X0, "loop".to_string()),
+                Instruction::Jump("done".to_string()),
+                Instruction::Li(Register::X11, 0x12345),
+                Instruction::Label("done".to_string()),
+                Instruction::La(Register::X12, "loop".to_string()),
+            ],
+            Isa::Rv32Em,
+        )
+        .unwrap();
+
+        assert_eq!(machine_code.addresses, [0, 0, 0, 4, 8, 12, 20, 20]);
+        assert_eq!(machine_code.labels["done"], 20);
+        assert_eq!(machine_code.bytes(5).len(), 8);
+        assert!(machine_code.bytes(1).is_empty());
+        assert_eq!(
+            words(&machine_code),
+            [
+                0xFFF5_0513, // addi a0, a0, -1
+                0xFE05_1EE3, // bne a0, zero, -4
+                0x00C0_006F, // j +12
+                0x0001_25B7, // lui a1, 0x12
+                0x3455_8593, // addi a1, a1, 0x345
+                0x0000_0617, // auipc a2, 0
+                0xFEC6_0613, // addi a2, a2, -20
+            ]
+        );
+        assert_eq!(machine_code.relocations.len(), 4);
+        assert_eq!(machine_code.relocations[3].kind, RelocationKind::PcrelLo12);
+    }
+
+    #[test]
+    fn test_encoding_errors() {
+        assert_eq!(
+            assemble(
+                &[Instruction::Mv(Register::X10, Register::X28)],
+                Isa::Rv32Em
+            )
+            .unwrap_err(),
+            EncodeError::UnsupportedRegister {
+                register: Register::X28,
+                isa: Isa::Rv32Em,
+            }
+        );
+        assert!(assemble(
+            &[Instruction::Mv(Register::X10, Register::X28)],
+            Isa::Rv32Im
+        )
+        .is_ok());
+        assert!(matches!(
+            assemble(
+                &[Instruction::AddImm(Register::X10, Register::X10, 2048)],
+                Isa::Rv32Em
+            ),
+            Err(EncodeError::ImmediateOutOfRange { value: 2048, .. })
+        ));
+        assert_eq!(
+            assemble(&[Instruction::Jump("missing".to_string())], Isa::Rv32Em).unwrap_err(),
+            EncodeError::UndefinedLabel("missing".to_string())
+        );
+
+        let mut far = vec![Instruction::BranchEq(
+            Register::X10,
+            Register::X0,
+            "far".to_string(),
+        )];
+        far.extend((0..1024).map(|_| Instruction::Ecall));
+        far.push(Instruction::Label("far".to_string()));
+        assert!(matches!(
+            assemble(&far, Isa::Rv32Em),
+            Err(EncodeError::TargetOutOfRange {
+                kind: RelocationKind::Branch,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        let program = [
+            Instruction::Label("start".to_string()),
+            Instruction::Li(Register::X10, -5),
+            Instruction::Li(Register::X11, 0x7FFF_F800),
+            Instruction::Li(Register::X12, 0x3000),
+            Instruction::LoadByteU(Register::X5, Register::X10, 3),
+            Instruction::StoreByte(Register::X5, Register::X10, -3),
+            Instruction::BranchLe(Register::X10, Register::X11, "end".to_string()),
+            Instruction::BranchGeU(Register::X10, Register::X11, "start".to_string()),
+            Instruction::JumpAndLink(Register::X1, "start".to_string()),
+            Instruction::Not(Register::X6, Register::X7),
+            Instruction::Neg(Register::X6, Register::X7),
+            Instruction::MulHighU(Register::X6, Register::X7, Register::X8),
+            Instruction::SetLessThanImmU(Register::X6, Register::X7, -1),
+            Instruction::La(Register::X13, "end".to_string()),
+            Instruction::Ebreak,
+            Instruction::Label("end".to_string()),
+        ];
+        let machine_code = assemble(&program, Isa::Rv32Em).unwrap();
+
+        let decoded = disassemble(&machine_code.code).unwrap();
+        assert!(matches!(&decoded[0], Instruction::Label(name) if name == ".L0"));
+        assert!(matches!(decoded.last(), Some(Instruction::Label(_))));
+        let reassembled = assemble(&decoded, Isa::Rv32Em).unwrap();
+        assert_eq!(reassembled.code, machine_code.code);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(disassemble(&[0; 3]).unwrap_err(), EncodeError::Truncated(3));
+        assert_eq!(
+            disassemble(&0xFFFF_FFFFu32.to_le_bytes()).unwrap_err(),
+            EncodeError::InvalidInstruction {
+                address: 0,
+                word: 0xFFFF_FFFF,
+            }
+        );
+        // jal past the end of the code
+        assert_eq!(
+            disassemble(&0x0080_006Fu32.to_le_bytes()).unwrap_err(),
+            EncodeError::InvalidTarget(8)
+        );
+    }
+}
@@ -0,0 +1,1341 @@
+//! EVM code generation
+//!
+//! An experimental backend for Ethereum-compatible chains, selected with
+//! `--target evm`. It reads the same lowered and optimized program as the
+//! other backends and produces a [`YulObject`]: the deploy code returning
+//! the runtime code, an object of its own. The object prints as Yul and
+//! assembles to EVM bytecode.
+//!
+//! The backend covers the word-sized contracts the wasm32 one does: `u24`,
+//! `i24`, `u32`, `i32` and `Bool` values with their arithmetic, control
+//! flow, calls between functions, storage values, events and reverts with
+//! a message. Anything else is a [`CodegenError::UnsupportedFeature`]
+//! naming where it is in the source.
+//!
+//! Contracts follow the Solidity ABI, so Solidity contracts and Ethereum
+//! tooling can call them:
+//!
+//! - selectors hash the signature with Solidity type names, e.g.
+//!   `transfer(uint24,uint24)`, unless set with `#[selector]`
+//! - arguments and results are 32-byte big-endian words, and arguments out
+//!   of the range of their type revert
+//! - the first topic of an event hashes its Solidity signature, indexed
+//!   fields are the other topics and the rest is ABI encoded as data
+//! - `require` and `revert` with a message revert with `Error(string)`;
+//!   failed assertions, overflows and divisions by zero with
+//!   `Panic(uint256)`, the way Solidity does
+//!
+//! Unlike on the other targets, unchecked arithmetic wraps at the width of
+//! its type, as in Solidity, and storage fields take one slot each, in the
+//! order they are declared.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::compiler::analyzer::attributes::FunctionAttributes;
+use crate::compiler::codegen::metadata::selector_for;
+use crate::compiler::codegen::risc_v::CodegenError;
+use crate::compiler::codegen::wasm::WordType;
+use crate::compiler::codegen::yul::{
+    signed_word, word, Word, YulExpr, YulFunction, YulObject, YulStatement,
+};
+use crate::compiler::parser::ast::*;
+use crate::security::safe_math::CheckedInt;
+use crate::stdlib::crypto::CryptoFunctions;
+
+/// Panic code of a failed assertion
+pub const PANIC_ASSERT: u8 = 0x01;
+
+/// Panic code of an overflowing operation
+pub const PANIC_OVERFLOW: u8 = 0x11;
+
+/// Panic code of a division or modulo by zero
+pub const PANIC_DIVISION_BY_ZERO: u8 = 0x12;
+
+/// Where the data of the event being emitted is ABI encoded, after the
+/// memory Solidity reserves
+const EVENT_OFFSET: u128 = 0x80;
+
+/// Name of the object generated when none is given
+const DEFAULT_NAME: &str = "Contract";
+
+/// Solidity name of a word type, e.g. `uint24`
+fn abi_type_name(ty: WordType) -> String {
+    match ty {
+        WordType::Bool => "bool".to_string(),
+        WordType::Int(kind) if kind.is_signed() => format!("int{}", kind.bits()),
+        WordType::Int(kind) => format!("uint{}", kind.bits()),
+    }
+}
+
+/// A function of the program
+#[derive(Debug, Clone)]
+struct Signature {
+    params: Vec<WordType>,
+    result: Option<WordType>,
+}
+
+/// Code generator for the EVM target
+pub struct EvmCodegen {
+    name: String,
+    functions: HashMap<Symbol, Signature>,
+    /// Slot and type of every storage field
+    storage: HashMap<Symbol, (u32, WordType)>,
+    events: HashMap<Symbol, (Vec<EventField>, Word)>,
+    /// Functions the generated code calls, by name
+    helpers: BTreeMap<String, YulFunction>,
+
+    // The function being generated
+    checked: bool,
+    locals: HashSet<Symbol>,
+    local_kinds: HashMap<Symbol, CheckedInt>,
+    /// Locals in the order they were first assigned, declared at the top
+    /// of the function since Bend scopes them to it
+    declared: Vec<String>,
+    temps: usize,
+}
+
+impl Default for EvmCodegen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvmCodegen {
+    pub fn new() -> Self {
+        EvmCodegen {
+            name: DEFAULT_NAME.to_string(),
+            functions: HashMap::new(),
+            storage: HashMap::new(),
+            events: HashMap::new(),
+            helpers: BTreeMap::new(),
+            checked: true,
+            locals: HashSet::new(),
+            local_kinds: HashMap::new(),
+            declared: Vec::new(),
+            temps: 0,
+        }
+    }
+
+    /// Name the generated object, and its runtime object after it
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Generate the object of a program
+    pub fn generate(&mut self, program: &Program) -> Result<YulObject, CodegenError> {
+        for definition in &program.definitions {
+            match definition {
+                Definition::FunctionDef {
+                    name,
+                    params,
+                    return_type,
+                    location,
+                    ..
+                } => {
+                    let params = params
+                        .iter()
+                        .map(|param| {
+                            WordType::of(&param.ty).ok_or_else(|| {
+                                unsupported(
+                                    &format!(
+                                        "Parameter {} of {} of type {}",
+                                        param.name, name, param.ty
+                                    ),
+                                    &param.location,
+                                )
+                            })
+                        })
+                        .collect::<Result<_, _>>()?;
+                    let result = match return_type {
+                        Some(Type::None { .. }) | None => None,
+                        Some(ty) => Some(WordType::of(ty).ok_or_else(|| {
+                            unsupported(&format!("Return type {} of {}", ty, name), location)
+                        })?),
+                    };
+                    self.functions.insert(*name, Signature { params, result });
+                }
+                Definition::StorageDef { fields, .. } => {
+                    for field in fields {
+                        let ty = storage_value_type(&field.ty).ok_or_else(|| {
+                            unsupported(
+                                &format!("Storage field {} of type {}", field.name, field.ty),
+                                &field.location,
+                            )
+                        })?;
+                        let slot = self.storage.len() as u32;
+                        self.storage.insert(field.name, (slot, ty));
+                    }
+                }
+                Definition::EventDef {
+                    name,
+                    fields,
+                    location,
+                    ..
+                } => {
+                    let types = fields
+                        .iter()
+                        .map(|field| {
+                            WordType::of(&field.ty).map(abi_type_name).ok_or_else(|| {
+                                unsupported(
+                                    &format!(
+                                        "Field {} of event {} of type {}",
+                                        field.name, name, field.ty
+                                    ),
+                                    &field.location,
+                                )
+                            })
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if fields.iter().filter(|field| field.indexed).count() > 3 {
+                        return Err(unsupported(
+                            &format!("Event {} with more than 3 indexed fields", name),
+                            location,
+                        ));
+                    }
+                    let signature = format!("{}({})", name, types.join(","));
+                    let topic = CryptoFunctions::keccak256(signature.as_bytes());
+                    self.events.insert(*name, (fields.clone(), topic));
+                }
+                _ => {}
+            }
+        }
+
+        let mut functions = Vec::new();
+        for definition in &program.definitions {
+            if let Definition::FunctionDef {
+                name,
+                params,
+                body,
+                checked,
+                ..
+            } = definition
+            {
+                self.checked = *checked != Some(false);
+                functions.push(self.generate_function(name, params, body)?);
+            }
+        }
+
+        let mut code = self.generate_dispatcher(program)?;
+        code.extend(functions.into_iter().map(YulStatement::Function));
+        code.extend(
+            std::mem::take(&mut self.helpers)
+                .into_values()
+                .map(YulStatement::Function),
+        );
+
+        let runtime = format!("{}_deployed", self.name);
+        let object = || YulExpr::String(runtime.clone());
+        Ok(YulObject {
+            name: self.name.clone(),
+            code: vec![
+                YulStatement::Expr(YulExpr::call(
+                    "datacopy",
+                    vec![
+                        YulExpr::num(0),
+                        YulExpr::call("dataoffset", vec![object()]),
+                        YulExpr::call("datasize", vec![object()]),
+                    ],
+                )),
+                YulStatement::Expr(YulExpr::call(
+                    "return",
+                    vec![YulExpr::num(0), YulExpr::call("datasize", vec![object()])],
+                )),
+            ],
+            objects: vec![YulObject {
+                name: runtime.clone(),
+                code,
+                objects: Vec::new(),
+            }],
+        })
+    }
+
+    /// Generate a function of the program
+    ///
+    /// Every function returns a word, zero when it ends without `return`,
+    /// so calls are expressions whatever the function returns.
+    fn generate_function(
+        &mut self,
+        name: &str,
+        params: &[Parameter],
+        body: &Block,
+    ) -> Result<YulFunction, CodegenError> {
+        self.locals.clear();
+        self.local_kinds.clear();
+        self.declared.clear();
+        self.temps = 0;
+        for param in params {
+            self.locals.insert(param.name);
+            if let Some(ty) = WordType::of(&param.ty) {
+                self.local_kinds.insert(param.name, ty.kind());
+            }
+        }
+
+        let mut statements = Vec::new();
+        self.generate_block(body, &mut statements)?;
+        let mut body: Vec<YulStatement> = self
+            .declared
+            .iter()
+            .map(|local| YulStatement::Let(variable(local), None))
+            .collect();
+        body.extend(statements);
+
+        Ok(YulFunction {
+            name: function_name(name),
+            params: params.iter().map(|param| variable(&param.name)).collect(),
+            result: Some("ret".to_string()),
+            body,
+        })
+    }
+
+    /// Generate the message dispatcher, the top of the runtime code
+    ///
+    /// It compares the first 4 bytes of the call data against the selector
+    /// of every `#[message]` function and calls the external function
+    /// decoding its arguments. Call data too short for a selector and
+    /// unknown selectors revert with empty data.
+    fn generate_dispatcher(
+        &mut self,
+        program: &Program,
+    ) -> Result<Vec<YulStatement>, CodegenError> {
+        let mut cases = Vec::new();
+        let mut selectors: HashMap<[u8; 4], &str> = HashMap::new();
+        let mut externals = Vec::new();
+        for definition in &program.definitions {
+            if let Definition::FunctionDef { name, location, .. } = definition {
+                let attributes = FunctionAttributes::of(definition)
+                    .map_err(|e| CodegenError::Generic(e.to_string()))?;
+                if attributes.export.is_some() {
+                    return Err(unsupported(&format!("Exporting {}", name), location));
+                }
+                if !attributes.message {
+                    continue;
+                }
+                let signature = self.functions[name].clone();
+                let types: Vec<String> = signature
+                    .params
+                    .iter()
+                    .copied()
+                    .map(abi_type_name)
+                    .collect();
+                let selector = attributes
+                    .selector
+                    .unwrap_or_else(|| selector_for(&format!("{}({})", name, types.join(","))));
+                if let Some(existing) = selectors.insert(selector, name) {
+                    return Err(CodegenError::Generic(format!(
+                        "Selector 0x{} of {} collides with {}",
+                        hex::encode(selector),
+                        name,
+                        existing
+                    )));
+                }
+
+                let external = format!("external_{}", function_name(name));
+                cases.push((
+                    word(u32::from_be_bytes(selector) as u128),
+                    vec![YulStatement::Expr(YulExpr::call(&external, Vec::new()))],
+                ));
+                externals.push(YulStatement::Function(Self::generate_external(
+                    name,
+                    &external,
+                    &signature,
+                    attributes.payable,
+                )));
+            }
+        }
+
+        let mut code = Vec::new();
+        if !cases.is_empty() {
+            code.push(YulStatement::If(
+                YulExpr::call(
+                    "iszero",
+                    vec![YulExpr::call(
+                        "lt",
+                        vec![YulExpr::call("calldatasize", Vec::new()), YulExpr::num(4)],
+                    )],
+                ),
+                vec![
+                    YulStatement::Let(
+                        "selector".to_string(),
+                        Some(YulExpr::call(
+                            "shr",
+                            vec![
+                                YulExpr::num(224),
+                                YulExpr::call("calldataload", vec![YulExpr::num(0)]),
+                            ],
+                        )),
+                    ),
+                    YulStatement::Switch {
+                        expr: YulExpr::id("selector"),
+                        cases,
+                        default: Some(Vec::new()),
+                    },
+                ],
+            ));
+        }
+        code.push(revert_empty());
+        code.extend(externals);
+        Ok(code)
+    }
+
+    /// Generate the external function of `name`, which rejects value sent
+    /// to functions that are not `#[payable]`, decodes and checks the
+    /// arguments, calls the function and returns its ABI encoded result
+    fn generate_external(
+        name: &str,
+        external: &str,
+        signature: &Signature,
+        payable: bool,
+    ) -> YulFunction {
+        let mut body = Vec::new();
+        if !payable {
+            body.push(YulStatement::If(
+                YulExpr::call("callvalue", Vec::new()),
+                vec![revert_empty()],
+            ));
+        }
+        body.push(YulStatement::If(
+            YulExpr::call(
+                "lt",
+                vec![
+                    YulExpr::call("calldatasize", Vec::new()),
+                    YulExpr::num(4 + 32 * signature.params.len() as u128),
+                ],
+            ),
+            vec![revert_empty()],
+        ));
+
+        let mut args = Vec::new();
+        for (i, ty) in signature.params.iter().enumerate() {
+            let param = format!("param_{}", i);
+            body.push(YulStatement::Let(
+                param.clone(),
+                Some(YulExpr::call(
+                    "calldataload",
+                    vec![YulExpr::num(4 + 32 * i as u128)],
+                )),
+            ));
+            body.push(YulStatement::If(
+                out_of_range(YulExpr::id(&param), *ty),
+                vec![revert_empty()],
+            ));
+            args.push(YulExpr::id(param));
+        }
+
+        let call = YulExpr::call(function_name(name), args);
+        let len = match signature.result {
+            Some(_) => {
+                body.push(YulStatement::Expr(YulExpr::call(
+                    "mstore",
+                    vec![YulExpr::num(0), call],
+                )));
+                32
+            }
+            None => {
+                body.push(YulStatement::Expr(YulExpr::call("pop", vec![call])));
+                0
+            }
+        };
+        body.push(YulStatement::Expr(YulExpr::call(
+            "return",
+            vec![YulExpr::num(0), YulExpr::num(len)],
+        )));
+
+        YulFunction {
+            name: external.to_string(),
+            params: Vec::new(),
+            result: None,
+            body,
+        }
+    }
+
+    fn generate_block(
+        &mut self,
+        block: &Block,
+        out: &mut Vec<YulStatement>,
+    ) -> Result<(), CodegenError> {
+        for statement in &block.statements {
+            self.generate_statement(statement, out)?;
+        }
+        Ok(())
+    }
+
+    fn generate_statement(
+        &mut self,
+        statement: &Statement,
+        out: &mut Vec<YulStatement>,
+    ) -> Result<(), CodegenError> {
+        match statement {
+            Statement::Return { value, .. } => {
+                let value = self.generate_expr(value, out)?;
+                out.push(YulStatement::Assign("ret".to_string(), value));
+                out.push(YulStatement::Leave);
+            }
+            Statement::Assignment {
+                pattern: Pattern::Variable { name, .. },
+                value,
+                ..
+            }
+            | Statement::Use { name, value, .. } => {
+                self.record_local_kind(*name, value);
+                let value = self.generate_expr(value, out)?;
+                self.generate_assignment(*name, value, out);
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let condition = self.generate_expr(condition, out)?;
+                let mut then = Vec::new();
+                self.generate_block(then_branch, &mut then)?;
+                let mut otherwise = Vec::new();
+                self.generate_block(else_branch, &mut otherwise)?;
+                out.push(if otherwise.is_empty() {
+                    YulStatement::If(condition, then)
+                } else {
+                    YulStatement::Switch {
+                        expr: condition,
+                        cases: vec![(word(0), otherwise)],
+                        default: Some(then),
+                    }
+                });
+            }
+            Statement::While {
+                condition,
+                body,
+                bound,
+                ..
+            } => {
+                let counter = self.generate_loop_counter(*bound, out);
+                let mut statements = Vec::new();
+                let condition = self.generate_expr(condition, &mut statements)?;
+                statements.push(YulStatement::If(
+                    YulExpr::call("iszero", vec![condition]),
+                    vec![YulStatement::Break],
+                ));
+                self.generate_loop_iteration(counter, *bound, &mut statements);
+                self.generate_block(body, &mut statements)?;
+                out.push(YulStatement::For {
+                    init: Vec::new(),
+                    condition: YulExpr::num(1),
+                    post: Vec::new(),
+                    body: statements,
+                });
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                bound,
+                ..
+            } => self.generate_for(*variable, start, end, body, *bound, out)?,
+            Statement::Expr { expr, .. } => {
+                let value = self.generate_expr(expr, out)?;
+                out.push(YulStatement::Expr(YulExpr::call("pop", vec![value])));
+            }
+            Statement::Unchecked { body, .. } => {
+                let checked = std::mem::replace(&mut self.checked, false);
+                let result = self.generate_block(body, out);
+                self.checked = checked;
+                result?;
+            }
+            Statement::Emit {
+                event,
+                args,
+                location,
+            } => self.generate_emit(*event, args, location, out)?,
+            Statement::Revert {
+                kind,
+                condition,
+                reason,
+                location,
+            } => {
+                let revert = match reason {
+                    Some(Expr::Literal {
+                        kind: LiteralKind::String(message),
+                        ..
+                    }) => self.error_revert(message),
+                    Some(reason) => {
+                        return Err(unsupported(
+                            "Reverting with an error variant",
+                            reason.location(),
+                        ))
+                    }
+                    None if *kind == RevertKind::Assert => self.panic(PANIC_ASSERT),
+                    None => revert_empty(),
+                };
+                match condition {
+                    Some(condition) => {
+                        let condition = self.generate_expr(condition, out)?;
+                        out.push(YulStatement::If(
+                            YulExpr::call("iszero", vec![condition]),
+                            vec![revert],
+                        ));
+                    }
+                    None => {
+                        let _ = location;
+                        out.push(revert);
+                    }
+                }
+            }
+            _ => return Err(unsupported("Statement type", statement.location())),
+        }
+        Ok(())
+    }
+
+    /// Assign a value to a local or storage field, declaring the local on
+    /// first assignment
+    fn generate_assignment(&mut self, name: Symbol, value: YulExpr, out: &mut Vec<YulStatement>) {
+        if !self.locals.contains(&name) {
+            if let Some(&(slot, _)) = self.storage.get(&name) {
+                out.push(YulStatement::Expr(YulExpr::call(
+                    "sstore",
+                    vec![YulExpr::num(slot as u128), value],
+                )));
+                return;
+            }
+            self.locals.insert(name);
+            self.declared.push(name.to_string());
+        }
+        out.push(YulStatement::Assign(variable(&name), value));
+    }
+
+    /// Declare the iteration counter of a bounded loop
+    fn generate_loop_counter(
+        &mut self,
+        bound: Option<u32>,
+        out: &mut Vec<YulStatement>,
+    ) -> Option<String> {
+        bound.map(|_| {
+            let counter = self.temp();
+            out.push(YulStatement::Let(counter.clone(), Some(YulExpr::num(0))));
+            counter
+        })
+    }
+
+    /// Start a loop iteration, reverting once a bounded loop would run more
+    /// iterations than its bound
+    fn generate_loop_iteration(
+        &mut self,
+        counter: Option<String>,
+        bound: Option<u32>,
+        out: &mut Vec<YulStatement>,
+    ) {
+        if let (Some(counter), Some(bound)) = (counter, bound) {
+            out.push(YulStatement::Assign(
+                counter.clone(),
+                YulExpr::call("add", vec![YulExpr::id(&counter), YulExpr::num(1)]),
+            ));
+            let revert = self.error_revert("loop bound exceeded");
+            out.push(YulStatement::If(
+                YulExpr::call(
+                    "gt",
+                    vec![YulExpr::id(counter), YulExpr::num(bound as u128)],
+                ),
+                vec![revert],
+            ));
+        }
+    }
+
+    /// Generate a `for i in range(start, end)` loop
+    ///
+    /// The end is evaluated once, before the first iteration.
+    fn generate_for(
+        &mut self,
+        variable_name: Symbol,
+        start: &Expr,
+        end: &Expr,
+        body: &Block,
+        bound: Option<u32>,
+        out: &mut Vec<YulStatement>,
+    ) -> Result<(), CodegenError> {
+        let signed = self
+            .expr_kind(start)
+            .combine(self.expr_kind(end))
+            .is_signed();
+
+        self.record_local_kind(variable_name, start);
+        let first = self.generate_expr(start, out)?;
+        self.generate_assignment(variable_name, first, out);
+        let end_value = self.generate_expr(end, out)?;
+        let end = self.hoist(end_value, out);
+        let counter = self.generate_loop_counter(bound, out);
+
+        let read = Expr::Variable {
+            name: variable_name,
+            location: start.location().clone(),
+        };
+        let mut statements = Vec::new();
+        let current = self.generate_expr(&read, &mut statements)?;
+        statements.push(YulStatement::If(
+            YulExpr::call(
+                "iszero",
+                vec![YulExpr::call(
+                    if signed { "slt" } else { "lt" },
+                    vec![current, end],
+                )],
+            ),
+            vec![YulStatement::Break],
+        ));
+        self.generate_loop_iteration(counter, bound, &mut statements);
+        self.generate_block(body, &mut statements)?;
+
+        let mut post = Vec::new();
+        let current = self.generate_expr(&read, &mut post)?;
+        let next = YulExpr::call("add", vec![current, YulExpr::num(1)]);
+        self.generate_assignment(variable_name, next, &mut post);
+
+        out.push(YulStatement::For {
+            init: Vec::new(),
+            condition: YulExpr::num(1),
+            post,
+            body: statements,
+        });
+        Ok(())
+    }
+
+    /// Generate an emit statement
+    ///
+    /// The arguments are evaluated first, then the fields that are not
+    /// indexed are ABI encoded at [`EVENT_OFFSET`] and logged with the
+    /// topics.
+    fn generate_emit(
+        &mut self,
+        event: Symbol,
+        args: &[Expr],
+        location: &Location,
+        out: &mut Vec<YulStatement>,
+    ) -> Result<(), CodegenError> {
+        let (fields, topic) = self
+            .events
+            .get(&event)
+            .cloned()
+            .ok_or_else(|| CodegenError::UndefinedEvent(event.to_string()))?;
+
+        if fields.len() != args.len() {
+            return Err(CodegenError::InvalidOperation(format!(
+                "Event {} expects {} arguments, found {} at line {}, column {}",
+                event,
+                fields.len(),
+                args.len(),
+                location.line,
+                location.column
+            )));
+        }
+
+        let mut values = Vec::new();
+        for arg in args {
+            let value = self.generate_expr(arg, out)?;
+            values.push(self.hoist(value, out));
+        }
+
+        let mut topics = vec![YulExpr::Literal(topic)];
+        let mut data = 0;
+        for (field, value) in fields.iter().zip(values) {
+            if field.indexed {
+                topics.push(value);
+            } else {
+                out.push(YulStatement::Expr(YulExpr::call(
+                    "mstore",
+                    vec![YulExpr::num(EVENT_OFFSET + 32 * data), value],
+                )));
+                data += 1;
+            }
+        }
+
+        let mut log = vec![YulExpr::num(EVENT_OFFSET), YulExpr::num(32 * data)];
+        let name = format!("log{}", topics.len());
+        log.extend(topics);
+        out.push(YulStatement::Expr(YulExpr::call(name, log)));
+        Ok(())
+    }
+
+    /// Generate an expression, adding the statements it needs first to
+    /// `out`
+    ///
+    /// Calls to functions are kept in temporaries, so they run where the
+    /// expression is, in order.
+    fn generate_expr(
+        &mut self,
+        expr: &Expr,
+        out: &mut Vec<YulStatement>,
+    ) -> Result<YulExpr, CodegenError> {
+        Ok(match expr {
+            Expr::Literal { kind, location } => match kind {
+                LiteralKind::Uint(value) => YulExpr::num(*value as u128),
+                LiteralKind::Int(value) => YulExpr::Literal(signed_word(*value as i64)),
+                LiteralKind::Bool(value) => YulExpr::num(*value as u128),
+                _ => return Err(unsupported("Literal type", location)),
+            },
+            Expr::Variable { name, location } => {
+                if self.locals.contains(name) {
+                    YulExpr::id(variable(name))
+                } else if let Some(&(slot, _)) = self.storage.get(name) {
+                    YulExpr::call("sload", vec![YulExpr::num(slot as u128)])
+                } else if self.functions.contains_key(name) {
+                    return Err(unsupported("Function values", location));
+                } else {
+                    return Err(CodegenError::UndefinedVariable(name.to_string()));
+                }
+            }
+            Expr::BinaryOp {
+                left,
+                operator,
+                right,
+                location,
+            } => self.generate_binary(operator, left, right, location, out)?,
+            Expr::UnaryOp {
+                operator: UnaryOperator::Not,
+                operand,
+                ..
+            } => {
+                let operand = self.generate_expr(operand, out)?;
+                YulExpr::call("iszero", vec![operand])
+            }
+            Expr::FunctionCall {
+                function,
+                args,
+                location,
+                ..
+            } => self.generate_call(function, args, location, out)?,
+            _ => return Err(unsupported("Expression type", expr.location())),
+        })
+    }
+
+    /// Generate operands left to right
+    ///
+    /// Yul evaluates arguments from the last one, so an operand is kept in
+    /// a temporary when a later one could revert or change storage.
+    fn generate_operands(
+        &mut self,
+        operands: &[&Expr],
+        out: &mut Vec<YulStatement>,
+    ) -> Result<Vec<YulExpr>, CodegenError> {
+        let mut values = Vec::new();
+        for (i, operand) in operands.iter().enumerate() {
+            let mut value = self.generate_expr(operand, out)?;
+            if operands[i + 1..].iter().any(|later| has_effects(later)) {
+                value = self.hoist(value, out);
+            }
+            values.push(value);
+        }
+        Ok(values)
+    }
+
+    /// Generate a call to a function or to a method of a storage value
+    fn generate_call(
+        &mut self,
+        function: &Expr,
+        args: &[Expr],
+        location: &Location,
+        out: &mut Vec<YulStatement>,
+    ) -> Result<YulExpr, CodegenError> {
+        if let Some((name, method)) = function.as_method_target() {
+            if !self.locals.contains(&name) {
+                if let Some(&(slot, _)) = self.storage.get(&name) {
+                    return match (method, args) {
+                        ("get", []) => Ok(YulExpr::call("sload", vec![YulExpr::num(slot as u128)])),
+                        ("set", [value]) => {
+                            let value = self.generate_expr(value, out)?;
+                            out.push(YulStatement::Expr(YulExpr::call(
+                                "sstore",
+                                vec![YulExpr::num(slot as u128), value],
+                            )));
+                            Ok(YulExpr::num(0))
+                        }
+                        _ => Err(CodegenError::InvalidOperation(format!(
+                            "Unknown storage method {}.{} with {} arguments",
+                            name,
+                            method,
+                            args.len()
+                        ))),
+                    };
+                }
+            }
+        }
+
+        let Expr::Variable { name, .. } = function else {
+            return Err(CodegenError::InvalidOperation(
+                "Function call with non-variable target".to_string(),
+            ));
+        };
+        let Some(signature) = self.functions.get(name).cloned() else {
+            return Err(unsupported(&format!("The builtin {}", name), location));
+        };
+        if signature.params.len() != args.len() {
+            return Err(CodegenError::InvalidOperation(format!(
+                "{} expects {} arguments, found {}",
+                name,
+                signature.params.len(),
+                args.len()
+            )));
+        }
+        let args: Vec<&Expr> = args.iter().collect();
+        let args = self.generate_operands(&args, out)?;
+        Ok(self.hoist(YulExpr::call(function_name(name), args), out))
+    }
+
+    fn generate_binary(
+        &mut self,
+        operator: &BinaryOperator,
+        left: &Expr,
+        right: &Expr,
+        location: &Location,
+        out: &mut Vec<YulStatement>,
+    ) -> Result<YulExpr, CodegenError> {
+        if matches!(operator, BinaryOperator::And | BinaryOperator::Or) {
+            return self.generate_logical(operator, left, right, out);
+        }
+
+        let kind = self.expr_kind(left).combine(self.expr_kind(right));
+        let function = match operator {
+            BinaryOperator::Add
+            | BinaryOperator::Sub
+            | BinaryOperator::Mul
+            | BinaryOperator::Div
+            | BinaryOperator::Mod => self.arithmetic(operator, kind),
+            BinaryOperator::BitAnd => "and".to_string(),
+            BinaryOperator::BitOr => "or".to_string(),
+            BinaryOperator::BitXor => "xor".to_string(),
+            BinaryOperator::Equal | BinaryOperator::NotEqual => "eq".to_string(),
+            BinaryOperator::Less | BinaryOperator::GreaterEqual if kind.is_signed() => {
+                "slt".to_string()
+            }
+            BinaryOperator::Less | BinaryOperator::GreaterEqual => "lt".to_string(),
+            BinaryOperator::Greater | BinaryOperator::LessEqual if kind.is_signed() => {
+                "sgt".to_string()
+            }
+            BinaryOperator::Greater | BinaryOperator::LessEqual => "gt".to_string(),
+            _ => {
+                return Err(unsupported(
+                    &format!("The operator {:?}", operator),
+                    location,
+                ))
+            }
+        };
+
+        let operands = self.generate_operands(&[left, right], out)?;
+        let value = YulExpr::call(function, operands);
+        Ok(match operator {
+            BinaryOperator::NotEqual | BinaryOperator::GreaterEqual | BinaryOperator::LessEqual => {
+                YulExpr::call("iszero", vec![value])
+            }
+            _ => value,
+        })
+    }
+
+    /// Generate `&&` or `||`, which only evaluate their right operand when
+    /// the left one does not decide the result, as 0 or 1
+    fn generate_logical(
+        &mut self,
+        operator: &BinaryOperator,
+        left: &Expr,
+        right: &Expr,
+        out: &mut Vec<YulStatement>,
+    ) -> Result<YulExpr, CodegenError> {
+        let is_true = |value| YulExpr::call("iszero", vec![YulExpr::call("iszero", vec![value])]);
+        let left = self.generate_expr(left, out)?;
+        let result = self.hoist(is_true(left), out);
+
+        let mut otherwise = Vec::new();
+        let right = self.generate_expr(right, &mut otherwise)?;
+        let YulExpr::Identifier(name) = &result else {
+            unreachable!("hoisted values are temporaries");
+        };
+        otherwise.push(YulStatement::Assign(name.clone(), is_true(right)));
+        let condition = match operator {
+            BinaryOperator::Or => YulExpr::call("iszero", vec![result.clone()]),
+            _ => result.clone(),
+        };
+        out.push(YulStatement::If(condition, otherwise));
+        Ok(result)
+    }
+
+    /// Name of the helper applying an arithmetic operator to values of
+    /// `kind`, generating it on first use
+    ///
+    /// Checked helpers panic when the result leaves the range of `kind`,
+    /// unchecked ones wrap it. Both panic on division by zero, as in
+    /// Solidity.
+    fn arithmetic(&mut self, operator: &BinaryOperator, kind: CheckedInt) -> String {
+        let (operation, opcode) = match (operator, kind.is_signed()) {
+            (BinaryOperator::Add, _) => ("add", "add"),
+            (BinaryOperator::Sub, _) => ("sub", "sub"),
+            (BinaryOperator::Mul, _) => ("mul", "mul"),
+            (BinaryOperator::Div, true) => ("div", "sdiv"),
+            (BinaryOperator::Div, false) => ("div", "div"),
+            (BinaryOperator::Mod, true) => ("mod", "smod"),
+            _ => ("mod", "mod"),
+        };
+        let name = format!(
+            "{}_{}_{}",
+            if self.checked { "checked" } else { "wrapping" },
+            operation,
+            abi_type_name(WordType::Int(kind))
+        );
+        if self.helpers.contains_key(&name) {
+            return name;
+        }
+
+        let (x, y, r) = (YulExpr::id("x"), YulExpr::id("y"), YulExpr::id("r"));
+        let mut body = Vec::new();
+        if operation == "div" || operation == "mod" {
+            let panic = self.panic(PANIC_DIVISION_BY_ZERO);
+            body.push(YulStatement::If(
+                YulExpr::call("iszero", vec![y.clone()]),
+                vec![panic],
+            ));
+        }
+        body.push(YulStatement::Assign(
+            "r".to_string(),
+            YulExpr::call(opcode, vec![x, y]),
+        ));
+        if self.checked {
+            if operation != "mod" {
+                let panic = self.panic(PANIC_OVERFLOW);
+                body.push(YulStatement::If(
+                    out_of_range(r, WordType::Int(kind)),
+                    vec![panic],
+                ));
+            }
+        } else {
+            body.push(YulStatement::Assign("r".to_string(), wrap(r, kind)));
+        }
+
+        self.helpers.insert(
+            name.clone(),
+            YulFunction {
+                name: name.clone(),
+                params: vec!["x".to_string(), "y".to_string()],
+                result: Some("r".to_string()),
+                body,
+            },
+        );
+        name
+    }
+
+    /// A call to the helper reverting with `Panic(code)`
+    fn panic(&mut self, code: u8) -> YulStatement {
+        let name = format!("panic_error_0x{:02x}", code);
+        self.helpers.entry(name.clone()).or_insert_with(|| {
+            let selector = u32::from_be_bytes(selector_for("Panic(uint256)"));
+            YulFunction {
+                name: name.clone(),
+                params: Vec::new(),
+                result: None,
+                body: vec![
+                    mstore(0, shifted_selector(selector)),
+                    mstore(4, YulExpr::num(code as u128)),
+                    YulStatement::Expr(YulExpr::call(
+                        "revert",
+                        vec![YulExpr::num(0), YulExpr::num(0x24)],
+                    )),
+                ],
+            }
+        });
+        YulStatement::Expr(YulExpr::call(name, Vec::new()))
+    }
+
+    /// A call to the helper reverting with `Error(message)`
+    fn error_revert(&mut self, message: &str) -> YulStatement {
+        let hash = CryptoFunctions::keccak256(message.as_bytes());
+        let name = format!("revert_error_{}", hex::encode(&hash[..8]));
+        self.helpers.entry(name.clone()).or_insert_with(|| {
+            let selector = u32::from_be_bytes(selector_for("Error(string)"));
+            let mut body = vec![
+                mstore(0, shifted_selector(selector)),
+                mstore(4, YulExpr::num(32)),
+                mstore(36, YulExpr::num(message.len() as u128)),
+            ];
+            let chunks = message.as_bytes().chunks(32);
+            let len = 68 + 32 * chunks.len() as u128;
+            for (i, chunk) in chunks.enumerate() {
+                let mut padded = [0; 32];
+                padded[..chunk.len()].copy_from_slice(chunk);
+                body.push(mstore(68 + 32 * i as u128, YulExpr::Literal(padded)));
+            }
+            body.push(YulStatement::Expr(YulExpr::call(
+                "revert",
+                vec![YulExpr::num(0), YulExpr::num(len)],
+            )));
+            YulFunction {
+                name: name.clone(),
+                params: Vec::new(),
+                result: None,
+                body,
+            }
+        });
+        YulStatement::Expr(YulExpr::call(name, Vec::new()))
+    }
+
+    /// Keep a value in a new temporary, returning the temporary
+    fn hoist(&mut self, value: YulExpr, out: &mut Vec<YulStatement>) -> YulExpr {
+        if matches!(value, YulExpr::Literal(_)) {
+            return value;
+        }
+        let temp = self.temp();
+        out.push(YulStatement::Let(temp.clone(), Some(value)));
+        YulExpr::id(temp)
+    }
+
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("expr_{}", self.temps)
+    }
+
+    fn record_local_kind(&mut self, name: Symbol, value: &Expr) {
+        if !self.locals.contains(&name) && !self.storage.contains_key(&name) {
+            let kind = self.expr_kind(value);
+            self.local_kinds.insert(name, kind);
+        }
+    }
+
+    /// Integer type of an expression, `u24` unless it involves signed or
+    /// 32-bit values
+    fn expr_kind(&self, expr: &Expr) -> CheckedInt {
+        match expr {
+            Expr::Literal {
+                kind: LiteralKind::Int(_),
+                ..
+            } => CheckedInt::I24,
+            Expr::Variable { name, .. } => self
+                .local_kinds
+                .get(name)
+                .copied()
+                .or_else(|| {
+                    (!self.locals.contains(name))
+                        .then(|| self.storage.get(name).map(|(_, ty)| ty.kind()))
+                        .flatten()
+                })
+                .unwrap_or(CheckedInt::U24),
+            Expr::BinaryOp {
+                left,
+                operator:
+                    BinaryOperator::Add
+                    | BinaryOperator::Sub
+                    | BinaryOperator::Mul
+                    | BinaryOperator::Div
+                    | BinaryOperator::Mod
+                    | BinaryOperator::BitAnd
+                    | BinaryOperator::BitOr
+                    | BinaryOperator::BitXor,
+                right,
+                ..
+            } => self.expr_kind(left).combine(self.expr_kind(right)),
+            Expr::FunctionCall { function, .. } => {
+                if let Some((name, "get")) = function.as_method_target() {
+                    if !self.locals.contains(&name) {
+                        if let Some((_, ty)) = self.storage.get(&name) {
+                            return ty.kind();
+                        }
+                    }
+                }
+                match &**function {
+                    Expr::Variable { name, .. } => self
+                        .functions
+                        .get(name)
+                        .and_then(|signature| signature.result)
+                        .map_or(CheckedInt::U24, WordType::kind),
+                    _ => CheckedInt::U24,
+                }
+            }
+            _ => CheckedInt::U24,
+        }
+    }
+}
+
+/// Type of the value of a storage field
+fn storage_value_type(ty: &Type) -> Option<WordType> {
+    match ty {
+        Type::Named { name, params, .. } if name == "StorageValue" && params.len() == 1 => {
+            WordType::of(&params[0])
+        }
+        ty => WordType::of(ty),
+    }
+}
+
+/// Whether evaluating an expression could revert or change storage
+fn has_effects(expr: &Expr) -> bool {
+    match expr {
+        Expr::FunctionCall { .. } => true,
+        Expr::BinaryOp {
+            left,
+            operator,
+            right,
+            ..
+        } => {
+            matches!(
+                operator,
+                BinaryOperator::Add
+                    | BinaryOperator::Sub
+                    | BinaryOperator::Mul
+                    | BinaryOperator::Div
+                    | BinaryOperator::Mod
+            ) || has_effects(left)
+                || has_effects(right)
+        }
+        Expr::UnaryOp { operand, .. } => has_effects(operand),
+        _ => false,
+    }
+}
+
+/// Whether a word holds a value outside the range of `ty`
+fn out_of_range(value: YulExpr, ty: WordType) -> YulExpr {
+    match ty {
+        WordType::Bool => YulExpr::call("gt", vec![value, YulExpr::num(1)]),
+        WordType::Int(kind) if kind.is_signed() => YulExpr::call(
+            "iszero",
+            vec![YulExpr::call(
+                "eq",
+                vec![
+                    YulExpr::call(
+                        "signextend",
+                        vec![YulExpr::num(kind.bits() as u128 / 8 - 1), value.clone()],
+                    ),
+                    value,
+                ],
+            )],
+        ),
+        WordType::Int(kind) => YulExpr::call("gt", vec![value, YulExpr::num(kind.max() as u128)]),
+    }
+}
+
+/// Wrap a word to the range of `kind`
+fn wrap(value: YulExpr, kind: CheckedInt) -> YulExpr {
+    if kind.is_signed() {
+        YulExpr::call(
+            "signextend",
+            vec![YulExpr::num(kind.bits() as u128 / 8 - 1), value],
+        )
+    } else {
+        YulExpr::call("and", vec![value, YulExpr::num(kind.max() as u128)])
+    }
+}
+
+fn mstore(offset: u128, value: YulExpr) -> YulStatement {
+    YulStatement::Expr(YulExpr::call("mstore", vec![YulExpr::num(offset), value]))
+}
+
+/// A selector moved to the first 4 bytes of a word
+fn shifted_selector(selector: u32) -> YulExpr {
+    YulExpr::call(
+        "shl",
+        vec![YulExpr::num(224), YulExpr::num(selector as u128)],
+    )
+}
+
+fn revert_empty() -> YulStatement {
+    YulStatement::Expr(YulExpr::call(
+        "revert",
+        vec![YulExpr::num(0), YulExpr::num(0)],
+    ))
+}
+
+/// Yul name of a local, clear of Yul builtins
+fn variable(name: &str) -> String {
+    format!("var_{}", name)
+}
+
+/// Yul name of a function of the program
+fn function_name(name: &str) -> String {
+    format!("fun_{}", name)
+}
+
+fn unsupported(what: &str, location: &Location) -> CodegenError {
+    CodegenError::UnsupportedFeature(format!(
+        "{} is not supported on the evm target at line {}, column {}",
+        what, location.line, location.column
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parser::Parser;
+
+    fn generate(source: &str) -> Result<YulObject, CodegenError> {
+        let program = Parser::new(source).parse_program().unwrap();
+        EvmCodegen::new().with_name("Token").generate(&program)
+    }
+
+    #[test]
+    fn test_generate_object() {
+        let object = generate(
+            r#"
+            storage {
+                total: u24,
+            }
+
+            #[message]
+            fn mint(amount: u24) -> u24 {
+                total = total + amount;
+                return total;
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(object.name, "Token");
+        let runtime = object.object("Token_deployed").unwrap();
+        let text = object.to_string();
+        let selector = hex::encode(selector_for("mint(uint24)"));
+        assert!(text.contains(&format!("case 0x{} {{", selector.trim_start_matches('0'))));
+        assert!(text.contains("function fun_mint(var_amount) -> ret {"));
+        assert!(text.contains("sstore(0, checked_add_uint24(sload(0), var_amount))"));
+        assert!(text.contains("function checked_add_uint24(x, y) -> r {"));
+        assert!(text.contains("function panic_error_0x11() {"));
+
+        let deploy = object.assemble().unwrap();
+        assert!(deploy.ends_with(&runtime.assemble().unwrap()));
+    }
+
+    #[test]
+    fn test_calls_run_in_order() {
+        let object = generate(
+            r#"
+            storage {
+                count: u24,
+            }
+
+            fn bump() -> u24 {
+                count = count + 1;
+                return count;
+            }
+
+            fn total() -> u24 {
+                return count + bump();
+            }
+        "#,
+        )
+        .unwrap();
+
+        // The storage read is kept before the call changes it
+        let text = object.to_string();
+        let read = text.find("let expr_1 := sload(0)").unwrap();
+        let call = text.find("let expr_2 := fun_bump()").unwrap();
+        assert!(read < call);
+        assert!(text.contains("ret := checked_add_uint24(expr_1, expr_2)"));
+    }
+
+    #[test]
+    fn test_unsupported_features() {
+        let error = generate("fn hash(data: Bytes) -> u24 { return 0; }").unwrap_err();
+        assert!(matches!(error, CodegenError::UnsupportedFeature(_)));
+        assert!(error
+            .to_string()
+            .contains("on the evm target at line 1, column 9"));
+
+        let error = generate(
+            r#"
+            storage {
+                balances: StorageMap<u24, u24>,
+            }
+        "#,
+        )
+        .unwrap_err();
+        assert!(matches!(error, CodegenError::UnsupportedFeature(_)));
+    }
+}
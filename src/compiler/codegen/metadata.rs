@@ -1,5 +1,16 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::compiler::analyzer::attributes::{
+    Access, FunctionAttributes, Mutability, StorageAttributes,
+};
+use crate::compiler::codegen::encoder::Isa;
+use crate::compiler::parser::ast::{
+    Block, Definition, EventField, Parameter, Program, Statement, Type, TypeVariant,
+};
+use crate::compiler::parser::parser::Parser;
+use crate::compiler::polkavm::blob::DEPLOY_EXPORT;
+use crate::stdlib::crypto::CryptoFunctions;
 
 /// Metadata for a contract
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,18 +31,71 @@ pub struct ContractMetadata {
     pub description: Option<String>,
 
     /// Contract functions (name -> function metadata)
+    #[serde(serialize_with = "sorted")]
     pub functions: HashMap<String, FunctionMetadata>,
 
     /// Contract types (name -> type metadata)
+    #[serde(serialize_with = "sorted")]
     pub types: HashMap<String, TypeMetadata>,
 
     /// Contract objects (name -> object metadata)
+    #[serde(serialize_with = "sorted")]
     pub objects: HashMap<String, ObjectMetadata>,
 
+    /// Contract events (name -> event metadata)
+    #[serde(default, serialize_with = "sorted")]
+    pub events: HashMap<String, EventMetadata>,
+
+    /// Errors a call can revert with (name -> error metadata)
+    #[serde(default, serialize_with = "sorted")]
+    pub errors: HashMap<String, ErrorMetadata>,
+
+    /// Persistent storage layout, in declaration order
+    #[serde(default)]
+    pub storage_layout: Vec<StorageFieldMetadata>,
+
+    /// Version of the storage schema declared with `#[version(n)]`, 0 when
+    /// it is not versioned
+    #[serde(default)]
+    pub storage_version: u32,
+
+    /// The `#[migrate]` function moving storage of earlier versions to
+    /// `storage_version`, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub migration: Option<String>,
+
+    /// Program exports entering a function directly, given with
+    /// `#[export]` (export name -> function). `call` enters the dispatcher
+    /// unless it is listed.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub exports: BTreeMap<String, String>,
+
+    /// The function exported as `deploy`, run with the arguments the
+    /// contract is instantiated with, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constructor: Option<FunctionMetadata>,
+
+    /// Features enabled for `#[cfg(feature = "...")]` when compiling
+    #[serde(default)]
+    pub features: Vec<String>,
+
+    /// RISC-V instruction set the code was generated for, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isa: Option<Isa>,
+
     /// Contract source files
     pub sources: Vec<SourceMetadata>,
 }
 
+/// Serialize a map with its keys in order, so the metadata of a contract
+/// is the same on every build
+fn sorted<V: Serialize, S: Serializer>(
+    map: &HashMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
 /// Metadata for a contract function
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionMetadata {
@@ -58,6 +122,22 @@ pub struct FunctionMetadata {
 
     /// Source location
     pub source_location: Option<SourceLocation>,
+
+    /// Whether calls may transfer value
+    #[serde(default)]
+    pub payable: bool,
+
+    /// State the function may touch
+    #[serde(default)]
+    pub mutability: Mutability,
+
+    /// Deprecation note, empty when none was given
+    #[serde(default)]
+    pub deprecated: Option<String>,
+
+    /// Accounts allowed to call the function
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<Access>,
 }
 
 /// Function visibility
@@ -156,13 +236,74 @@ pub struct FieldMetadata {
     pub documentation: Option<String>,
 }
 
+/// Metadata for a contract event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventMetadata {
+    /// Event name
+    pub name: String,
+
+    /// Canonical signature, e.g. `Transfer(u24,u24,u24)`
+    pub signature: String,
+
+    /// First topic of every emitted event (keccak256 of the signature)
+    pub topic: [u8; 32],
+
+    /// Event fields
+    pub fields: Vec<EventFieldMetadata>,
+}
+
+/// Metadata for an event field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventFieldMetadata {
+    /// Field name
+    pub name: String,
+
+    /// Field type
+    pub type_name: String,
+
+    /// Whether the field is emitted as a topic
+    pub indexed: bool,
+}
+
+/// Metadata for an error that reverted calls return
+///
+/// The revert data of a call is the selector followed by the SCALE-encoded
+/// fields.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorMetadata {
+    /// Error name, e.g. `Error/InsufficientBalance`
+    pub name: String,
+
+    /// Canonical signature, e.g. `Error/InsufficientBalance(u24,u24)`
+    pub signature: String,
+
+    /// First 4 bytes of the revert data (keccak256 of the signature)
+    pub selector: [u8; 4],
+
+    /// Error fields
+    pub fields: Vec<ParameterMetadata>,
+}
+
+/// Metadata for a persistent storage field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageFieldMetadata {
+    /// Field name
+    pub name: String,
+
+    /// Field type
+    pub type_name: String,
+
+    /// Storage key (keccak256 of the field path)
+    pub key: [u8; 32],
+}
+
 /// Metadata for a source file
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SourceMetadata {
     /// Source file name
     pub name: String,
 
-    /// Content hash (for verification)
+    /// BLAKE2b-256 of the source, `0x`-prefixed (for verification)
     pub content_hash: String,
 }
 
@@ -196,9 +337,11 @@ pub fn build_metadata(
 ) -> ContractMetadata {
     // Create source metadata
     let mut source_metadata = Vec::new();
-    for (name, _content) in sources {
-        // In a real implementation, compute a proper hash
-        let content_hash = format!("hash_{}", name.replace('.', "_"));
+    for (name, content) in sources {
+        let content_hash = format!(
+            "0x{}",
+            hex::encode(CryptoFunctions::blake2b_256(content.as_bytes()))
+        );
 
         source_metadata.push(SourceMetadata {
             name: name.to_string(),
@@ -215,20 +358,365 @@ pub fn build_metadata(
         functions,
         types,
         objects,
+        events: HashMap::new(),
+        errors: HashMap::new(),
+        storage_layout: Vec::new(),
+        storage_version: 0,
+        migration: None,
+        exports: BTreeMap::new(),
+        constructor: None,
+        features: Vec::new(),
+        isa: None,
         sources: source_metadata,
     }
 }
 
+/// Collect metadata for every `#[message]` function, which the dispatcher
+/// exposes
+///
+/// `#[selector(...)]` overrides the selector derived from the signature.
+pub fn collect_function_metadata(program: &Program) -> HashMap<String, FunctionMetadata> {
+    let mut functions = HashMap::new();
+
+    for definition in &program.definitions {
+        if let Definition::FunctionDef {
+            name,
+            params,
+            return_type,
+            ..
+        } = definition
+        {
+            let attributes = FunctionAttributes::of(definition).unwrap_or_default();
+            if !attributes.message {
+                continue;
+            }
+            functions.insert(
+                name.to_string(),
+                function_metadata(name, params, return_type.as_ref(), attributes),
+            );
+        }
+    }
+
+    functions
+}
+
+/// Collect the metadata of the function exported as `deploy`, if any
+pub fn collect_constructor(program: &Program) -> Option<FunctionMetadata> {
+    program.definitions.iter().find_map(|definition| {
+        let Definition::FunctionDef {
+            name,
+            params,
+            return_type,
+            ..
+        } = definition
+        else {
+            return None;
+        };
+        let attributes = FunctionAttributes::of(definition).ok()?;
+        (attributes.export.as_deref() == Some(DEPLOY_EXPORT))
+            .then(|| function_metadata(name, params, return_type.as_ref(), attributes))
+    })
+}
+
+fn function_metadata(
+    name: &str,
+    params: &[Parameter],
+    return_type: Option<&Type>,
+    attributes: FunctionAttributes,
+) -> FunctionMetadata {
+    FunctionMetadata {
+        name: name.to_string(),
+        selector: attributes
+            .selector
+            .unwrap_or_else(|| compute_selector_for_params(name, params)),
+        visibility: FunctionVisibility::Public,
+        params: params
+            .iter()
+            .map(|param| ParameterMetadata {
+                name: param.name.to_string(),
+                type_name: type_name(&param.ty),
+                documentation: None,
+            })
+            .collect(),
+        return_type: return_type.map(type_name),
+        gas_cost: None,
+        documentation: None,
+        source_location: None,
+        payable: attributes.payable,
+        mutability: attributes.mutability,
+        deprecated: attributes.deprecated,
+        access: attributes.access,
+    }
+}
+
 /// Compute a function selector (similar to Ethereum)
-pub fn compute_function_selector(name: &str, _params: &[ParameterMetadata]) -> [u8; 4] {
-    // In a real implementation, this would compute a proper function selector
-    // by hashing the function signature (name and parameter types)
-    let mut selector = [0u8; 4];
+///
+/// The selector is the first 4 bytes of the Keccak-256 hash of the function
+/// signature, e.g. `transfer(u24,u24)`.
+pub fn compute_function_selector(name: &str, params: &[ParameterMetadata]) -> [u8; 4] {
+    let types: Vec<&str> = params
+        .iter()
+        .map(|param| param.type_name.as_str())
+        .collect();
+    selector_for(&format!("{}({})", name, types.join(",")))
+}
 
-    // Simple approach: use first 4 bytes of the function name
-    let name_bytes = name.as_bytes();
-    selector[..std::cmp::min(4, name_bytes.len())]
-        .copy_from_slice(&name_bytes[..std::cmp::min(4, name_bytes.len())]);
+/// Compute the selector of a function definition's parameters
+pub fn compute_selector_for_params(name: &str, params: &[Parameter]) -> [u8; 4] {
+    let types: Vec<String> = params.iter().map(|param| type_name(&param.ty)).collect();
+    selector_for(&format!("{}({})", name, types.join(",")))
+}
 
+/// Compute the selector of a canonical function signature
+pub fn selector_for(signature: &str) -> [u8; 4] {
+    let hash = CryptoFunctions::keccak256(signature.as_bytes());
+    let mut selector = [0u8; 4];
+    selector.copy_from_slice(&hash[..4]);
     selector
 }
+
+/// The parameter types of a canonical function signature, e.g. `Address`
+/// and `u256` for `transfer(Address,u256)`, or `None` unless it is one
+pub fn signature_params(signature: &str) -> Option<Vec<Type>> {
+    let (_, params) = signature.strip_suffix(')')?.split_once('(')?;
+    if params.is_empty() {
+        return Some(Vec::new());
+    }
+    params
+        .split(',')
+        .map(|param| Parser::new(param).parse_type().ok())
+        .collect()
+}
+
+/// Render a type the way it appears in event signatures
+pub fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Named { name, params, .. } if params.is_empty() => name.to_string(),
+        Type::Named { name, params, .. } => {
+            let params: Vec<String> = params.iter().map(type_name).collect();
+            format!("{}<{}>", name, params.join(","))
+        }
+        Type::Tuple { elements, .. } => {
+            let elements: Vec<String> = elements.iter().map(type_name).collect();
+            format!("({})", elements.join(","))
+        }
+        Type::U24 { .. } => "u24".to_string(),
+        Type::I24 { .. } => "i24".to_string(),
+        Type::F24 { .. } => "f24".to_string(),
+        _ => "Any".to_string(),
+    }
+}
+
+/// Compute the canonical signature of an event, e.g. `Transfer(u24,u24,u24)`
+pub fn compute_event_signature(name: &str, fields: &[EventField]) -> String {
+    let types: Vec<String> = fields.iter().map(|field| type_name(&field.ty)).collect();
+    format!("{}({})", name, types.join(","))
+}
+
+/// Compute the first topic of an event from its signature
+pub fn compute_event_topic(signature: &str) -> [u8; 32] {
+    CryptoFunctions::keccak256(signature.as_bytes())
+}
+
+/// Collect metadata for every event defined in a program
+pub fn collect_event_metadata(program: &Program) -> HashMap<String, EventMetadata> {
+    let mut events = HashMap::new();
+
+    for definition in &program.definitions {
+        if let Definition::EventDef { name, fields, .. } = definition {
+            let signature = compute_event_signature(name, fields);
+            events.insert(
+                name.to_string(),
+                EventMetadata {
+                    name: name.to_string(),
+                    topic: compute_event_topic(&signature),
+                    signature,
+                    fields: fields
+                        .iter()
+                        .map(|field| EventFieldMetadata {
+                            name: field.name.to_string(),
+                            type_name: type_name(&field.ty),
+                            indexed: field.indexed,
+                        })
+                        .collect(),
+                },
+            );
+        }
+    }
+
+    events
+}
+
+/// Signature of the error `assert` reverts with
+pub const PANIC_SIGNATURE: &str = "Panic(String)";
+
+/// Signature of the error `require` and `revert` revert with for a message
+pub const ERROR_SIGNATURE: &str = "Error(String)";
+
+/// Compute the canonical signature of an error variant, e.g.
+/// `Error/InsufficientBalance(u24,u24)`
+pub fn compute_error_signature(enum_name: &str, variant: &TypeVariant) -> String {
+    let types: Vec<String> = variant
+        .fields
+        .iter()
+        .map(|field| {
+            field
+                .type_annotation
+                .as_ref()
+                .map_or("Any".to_string(), type_name)
+        })
+        .collect();
+    format!("{}/{}({})", enum_name, variant.name, types.join(","))
+}
+
+/// Collect metadata for the errors calls into a program can revert with
+///
+/// These are the `Error(String)` and `Panic(String)` messages, every
+/// variant of an `error` definition and every variant of a type that a
+/// `require` or `revert` uses as its reason.
+pub fn collect_error_metadata(program: &Program) -> HashMap<String, ErrorMetadata> {
+    let message = |name: &str, signature: &str| ErrorMetadata {
+        name: name.to_string(),
+        signature: signature.to_string(),
+        selector: selector_for(signature),
+        fields: vec![ParameterMetadata {
+            name: "message".to_string(),
+            type_name: "String".to_string(),
+            documentation: None,
+        }],
+    };
+    let mut errors = HashMap::new();
+    for (name, signature) in [("Error", ERROR_SIGNATURE), ("Panic", PANIC_SIGNATURE)] {
+        errors.insert(name.to_string(), message(name, signature));
+    }
+
+    let mut reasons = HashSet::new();
+    for definition in &program.definitions {
+        if let Definition::FunctionDef { body, .. } = definition {
+            collect_revert_types(body, &mut reasons);
+        }
+    }
+
+    for definition in &program.definitions {
+        let (name, variants) = match definition {
+            Definition::ErrorDef { name, variants, .. } => (name, variants),
+            Definition::TypeDef { name, variants, .. } if reasons.contains(name.as_str()) => {
+                (name, variants)
+            }
+            _ => continue,
+        };
+        for variant in variants {
+            let signature = compute_error_signature(name, variant);
+            let error_name = format!("{}/{}", name, variant.name);
+            errors.insert(
+                error_name.clone(),
+                ErrorMetadata {
+                    name: error_name,
+                    selector: selector_for(&signature),
+                    signature,
+                    fields: variant
+                        .fields
+                        .iter()
+                        .map(|field| ParameterMetadata {
+                            name: field.name.to_string(),
+                            type_name: field
+                                .type_annotation
+                                .as_ref()
+                                .map_or("Any".to_string(), type_name),
+                            documentation: None,
+                        })
+                        .collect(),
+                },
+            );
+        }
+    }
+
+    errors
+}
+
+/// Collect the types of the error variants revert statements use as reasons
+fn collect_revert_types<'a>(block: &'a Block, types: &mut HashSet<&'a str>) {
+    for statement in &block.statements {
+        if let Statement::Revert {
+            reason: Some(reason),
+            ..
+        } = statement
+        {
+            if let Some((variant, _)) = reason.as_variant_constructor() {
+                if let Some((type_name, _)) = variant.as_str().rsplit_once('/') {
+                    types.insert(type_name);
+                }
+            }
+        }
+        for nested in statement.nested_blocks() {
+            collect_revert_types(nested, types);
+        }
+    }
+}
+
+/// Compute the storage key of a field path, e.g. `total_supply`
+pub fn compute_storage_key(path: &str) -> [u8; 32] {
+    CryptoFunctions::keccak256(path.as_bytes())
+}
+
+/// Collect the storage layout declared by a program
+pub fn collect_storage_layout(program: &Program) -> Vec<StorageFieldMetadata> {
+    let mut layout = Vec::new();
+
+    for definition in &program.definitions {
+        if let Definition::StorageDef { fields, .. } = definition {
+            for field in fields {
+                layout.push(StorageFieldMetadata {
+                    name: field.name.to_string(),
+                    type_name: type_name(&field.ty),
+                    key: compute_storage_key(&field.name),
+                });
+            }
+        }
+    }
+
+    layout
+}
+
+/// The storage schema version declared by a program, 0 when it has none
+pub fn collect_storage_version(program: &Program) -> u32 {
+    program
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            Definition::StorageDef { attributes, .. } => {
+                StorageAttributes::from_attributes(attributes).ok()?.version
+            }
+            _ => None,
+        })
+        .next()
+        .unwrap_or(0)
+}
+
+/// The name of the `#[migrate]` function of a program, if it has one
+pub fn collect_migration(program: &Program) -> Option<String> {
+    program
+        .definitions
+        .iter()
+        .find_map(|definition| match definition {
+            Definition::FunctionDef { name, .. } => FunctionAttributes::of(definition)
+                .is_ok_and(|attributes| attributes.migrate)
+                .then(|| name.to_string()),
+            _ => None,
+        })
+}
+
+/// Collect the `#[export]` functions of a program by export name
+pub fn collect_exports(program: &Program) -> BTreeMap<String, String> {
+    program
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            Definition::FunctionDef { name, .. } => FunctionAttributes::of(definition)
+                .ok()?
+                .export
+                .map(|export| (export, name.to_string())),
+            _ => None,
+        })
+        .collect()
+}
@@ -1,12 +1,30 @@
 #![allow(clippy::only_used_in_recursion)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::compiler::address::BYTES32_LEN;
+use crate::compiler::analyzer::attributes::FunctionAttributes;
+use crate::compiler::analyzer::linearity;
+use crate::compiler::analyzer::type_checker::{SET_CODE_HASH, ZERO_ADDRESS};
+use crate::compiler::codegen::encoder::Isa;
+use crate::compiler::codegen::metadata::{
+    compute_error_signature, compute_event_signature, compute_event_topic,
+    compute_selector_for_params, compute_storage_key, selector_for, signature_params, type_name,
+    ERROR_SIGNATURE, PANIC_SIGNATURE,
+};
 use crate::compiler::parser::ast::*;
+use crate::compiler::polkavm::host::{
+    ChainExtension, ChainExtensionRegistry, ExtensionType, HostFunction,
+};
+use crate::compiler::wide::wide_type_bits;
+use crate::security::safe_math::CheckedInt;
+use crate::stdlib::bytes::encode_compact;
 
-#[derive(Error, Debug, Clone)]
+#[derive(Error, Debug, Clone, PartialEq)]
 pub enum CodegenError {
     #[error("Codegen error: {0}")]
     Generic(String),
@@ -17,12 +35,18 @@ pub enum CodegenError {
     #[error("Undefined variable: {0}")]
     UndefinedVariable(String),
 
+    #[error("Undefined event: {0}")]
+    UndefinedEvent(String),
+
     #[error("Unsupported feature: {0}")]
     UnsupportedFeature(String),
+
+    #[error("Invalid assembly: {0}")]
+    InvalidAssembly(String),
 }
 
 /// RISC-V register allocation
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Register {
     X0, // Zero register
     X1, // Return address
@@ -102,7 +126,70 @@ impl Display for Register {
     }
 }
 
+/// Every register, by number
+const REGISTERS: [Register; 32] = [
+    Register::X0,
+    Register::X1,
+    Register::X2,
+    Register::X3,
+    Register::X4,
+    Register::X5,
+    Register::X6,
+    Register::X7,
+    Register::X8,
+    Register::X9,
+    Register::X10,
+    Register::X11,
+    Register::X12,
+    Register::X13,
+    Register::X14,
+    Register::X15,
+    Register::X16,
+    Register::X17,
+    Register::X18,
+    Register::X19,
+    Register::X20,
+    Register::X21,
+    Register::X22,
+    Register::X23,
+    Register::X24,
+    Register::X25,
+    Register::X26,
+    Register::X27,
+    Register::X28,
+    Register::X29,
+    Register::X30,
+    Register::X31,
+];
+
+impl std::str::FromStr for Register {
+    type Err = CodegenError;
+
+    /// A register by its ABI name, like `a0`, or its number, like `x10`
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let number = name
+            .strip_prefix('x')
+            .and_then(|number| number.parse::<usize>().ok());
+        let named = REGISTERS.iter().find(|register| {
+            register.to_string() == name || name == "fp" && **register == Register::X8
+        });
+        match (number, named) {
+            (_, Some(register)) => Ok(*register),
+            (Some(number), None) if number < REGISTERS.len() => Ok(REGISTERS[number]),
+            _ => Err(CodegenError::InvalidAssembly(format!(
+                "unknown register `{}`",
+                name
+            ))),
+        }
+    }
+}
+
 impl Register {
+    /// The register `x{number}`
+    pub fn from_number(number: u32) -> Option<Register> {
+        REGISTERS.get(number as usize).copied()
+    }
+
     pub fn arg_registers() -> Vec<Register> {
         vec![
             Register::X10,
@@ -147,19 +234,39 @@ impl Register {
 }
 
 /// RISC-V instructions
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Instruction {
     // Load and store
     Load(Register, Register, i32), // Load from memory, e.g., lw rd, offset(rs1)
     Store(Register, Register, i32), // Store to memory, e.g., sw rs2, offset(rs1)
+    LoadByteU(Register, Register, i32), // Load a zero-extended byte, e.g., lbu rd, offset(rs1)
+    StoreByte(Register, Register, i32), // Store the low byte, e.g., sb rs2, offset(rs1)
+    Load64(Register, Register, i32), // Load a doubleword (RV64), e.g., ld rd, offset(rs1)
+    Store64(Register, Register, i32), // Store a doubleword (RV64), e.g., sd rs2, offset(rs1)
 
     // Arithmetic
     Add(Register, Register, Register), // Add, e.g., add rd, rs1, rs2
     AddImm(Register, Register, i32),   // Add immediate, e.g., addi rd, rs1, imm
     Sub(Register, Register, Register), // Subtract, e.g., sub rd, rs1, rs2
     Mul(Register, Register, Register), // Multiply, e.g., mul rd, rs1, rs2
-    Div(Register, Register, Register), // Divide, e.g., div rd, rs1, rs2
-    Rem(Register, Register, Register), // Remainder, e.g., rem rd, rs1, rs2
+    MulHigh(Register, Register, Register), // Upper word of a signed multiply, e.g., mulh rd, rs1, rs2
+    MulHighU(Register, Register, Register), // Upper word of an unsigned multiply, e.g., mulhu rd, rs1, rs2
+    Div(Register, Register, Register),      // Divide, e.g., div rd, rs1, rs2
+    Rem(Register, Register, Register),      // Remainder, e.g., rem rd, rs1, rs2
+
+    // Word arithmetic on the low 32 bits, sign-extending the result (RV64)
+    AddW(Register, Register, Register), // Add word, e.g., addw rd, rs1, rs2
+    AddImmW(Register, Register, i32),   // Add immediate word, e.g., addiw rd, rs1, imm
+    SubW(Register, Register, Register), // Subtract word, e.g., subw rd, rs1, rs2
+    MulW(Register, Register, Register), // Multiply word, e.g., mulw rd, rs1, rs2
+    DivW(Register, Register, Register), // Divide word, e.g., divw rd, rs1, rs2
+    RemW(Register, Register, Register), // Remainder word, e.g., remw rd, rs1, rs2
+    ShiftLeftW(Register, Register, Register), // Shift left word, e.g., sllw rd, rs1, rs2
+    ShiftRightW(Register, Register, Register), // Shift right word, e.g., srlw rd, rs1, rs2
+    ShiftRightArithW(Register, Register, Register), // Arithmetic shift right word, e.g., sraw rd, rs1, rs2
+    ShiftLeftImmW(Register, Register, i32), // Shift left immediate word, e.g., slliw rd, rs1, imm
+    ShiftRightImmW(Register, Register, i32), // Shift right immediate word, e.g., srliw rd, rs1, imm
+    ShiftRightArithImmW(Register, Register, i32), // Arithmetic shift right immediate word, e.g., sraiw rd, rs1, imm
 
     // Logical
     And(Register, Register, Register), // AND, e.g., and rd, rs1, rs2
@@ -224,6 +331,18 @@ impl std::fmt::Display for Instruction {
             Instruction::Store(rs2, rs1, offset) => {
                 write!(f, "    sw {}, {}({})", rs2, offset, rs1)
             }
+            Instruction::LoadByteU(rd, rs1, offset) => {
+                write!(f, "    lbu {}, {}({})", rd, offset, rs1)
+            }
+            Instruction::StoreByte(rs2, rs1, offset) => {
+                write!(f, "    sb {}, {}({})", rs2, offset, rs1)
+            }
+            Instruction::Load64(rd, rs1, offset) => {
+                write!(f, "    ld {}, {}({})", rd, offset, rs1)
+            }
+            Instruction::Store64(rs2, rs1, offset) => {
+                write!(f, "    sd {}, {}({})", rs2, offset, rs1)
+            }
             Instruction::Add(rd, rs1, rs2) => {
                 write!(f, "    add {}, {}, {}", rd, rs1, rs2)
             }
@@ -236,12 +355,54 @@ impl std::fmt::Display for Instruction {
             Instruction::Mul(rd, rs1, rs2) => {
                 write!(f, "    mul {}, {}, {}", rd, rs1, rs2)
             }
+            Instruction::MulHigh(rd, rs1, rs2) => {
+                write!(f, "    mulh {}, {}, {}", rd, rs1, rs2)
+            }
+            Instruction::MulHighU(rd, rs1, rs2) => {
+                write!(f, "    mulhu {}, {}, {}", rd, rs1, rs2)
+            }
             Instruction::Div(rd, rs1, rs2) => {
                 write!(f, "    div {}, {}, {}", rd, rs1, rs2)
             }
             Instruction::Rem(rd, rs1, rs2) => {
                 write!(f, "    rem {}, {}, {}", rd, rs1, rs2)
             }
+            Instruction::AddW(rd, rs1, rs2) => {
+                write!(f, "    addw {}, {}, {}", rd, rs1, rs2)
+            }
+            Instruction::AddImmW(rd, rs1, imm) => {
+                write!(f, "    addiw {}, {}, {}", rd, rs1, imm)
+            }
+            Instruction::SubW(rd, rs1, rs2) => {
+                write!(f, "    subw {}, {}, {}", rd, rs1, rs2)
+            }
+            Instruction::MulW(rd, rs1, rs2) => {
+                write!(f, "    mulw {}, {}, {}", rd, rs1, rs2)
+            }
+            Instruction::DivW(rd, rs1, rs2) => {
+                write!(f, "    divw {}, {}, {}", rd, rs1, rs2)
+            }
+            Instruction::RemW(rd, rs1, rs2) => {
+                write!(f, "    remw {}, {}, {}", rd, rs1, rs2)
+            }
+            Instruction::ShiftLeftW(rd, rs1, rs2) => {
+                write!(f, "    sllw {}, {}, {}", rd, rs1, rs2)
+            }
+            Instruction::ShiftRightW(rd, rs1, rs2) => {
+                write!(f, "    srlw {}, {}, {}", rd, rs1, rs2)
+            }
+            Instruction::ShiftRightArithW(rd, rs1, rs2) => {
+                write!(f, "    sraw {}, {}, {}", rd, rs1, rs2)
+            }
+            Instruction::ShiftLeftImmW(rd, rs1, imm) => {
+                write!(f, "    slliw {}, {}, {}", rd, rs1, imm)
+            }
+            Instruction::ShiftRightImmW(rd, rs1, imm) => {
+                write!(f, "    srliw {}, {}, {}", rd, rs1, imm)
+            }
+            Instruction::ShiftRightArithImmW(rd, rs1, imm) => {
+                write!(f, "    sraiw {}, {}, {}", rd, rs1, imm)
+            }
             Instruction::And(rd, rs1, rs2) => {
                 write!(f, "    and {}, {}, {}", rd, rs1, rs2)
             }
@@ -351,13 +512,513 @@ impl std::fmt::Display for Instruction {
     }
 }
 
+impl Instruction {
+    /// The label a branch, jump or `la` refers to
+    pub fn target(&self) -> Option<&str> {
+        match self {
+            Instruction::BranchEq(_, _, label)
+            | Instruction::BranchNe(_, _, label)
+            | Instruction::BranchLt(_, _, label)
+            | Instruction::BranchLe(_, _, label)
+            | Instruction::BranchGe(_, _, label)
+            | Instruction::BranchLtU(_, _, label)
+            | Instruction::BranchGeU(_, _, label)
+            | Instruction::Jump(label)
+            | Instruction::JumpAndLink(_, label)
+            | Instruction::La(_, label) => Some(label),
+            _ => None,
+        }
+    }
+
+    /// Mutable [`target`](Self::target), for renaming labels
+    pub fn target_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Instruction::BranchEq(_, _, label)
+            | Instruction::BranchNe(_, _, label)
+            | Instruction::BranchLt(_, _, label)
+            | Instruction::BranchLe(_, _, label)
+            | Instruction::BranchGe(_, _, label)
+            | Instruction::BranchLtU(_, _, label)
+            | Instruction::BranchGeU(_, _, label)
+            | Instruction::Jump(label)
+            | Instruction::JumpAndLink(_, label)
+            | Instruction::La(_, label) => Some(label),
+            _ => None,
+        }
+    }
+
+    /// Whether the instruction only exists on RV64
+    pub fn is_rv64_only(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Load64(..)
+                | Instruction::Store64(..)
+                | Instruction::AddW(..)
+                | Instruction::AddImmW(..)
+                | Instruction::SubW(..)
+                | Instruction::MulW(..)
+                | Instruction::DivW(..)
+                | Instruction::RemW(..)
+                | Instruction::ShiftLeftW(..)
+                | Instruction::ShiftRightW(..)
+                | Instruction::ShiftRightArithW(..)
+                | Instruction::ShiftLeftImmW(..)
+                | Instruction::ShiftRightImmW(..)
+                | Instruction::ShiftRightArithImmW(..)
+        )
+    }
+}
+
+/// Mnemonics read back by [`Instruction::from_str`], with how many
+/// operands they take
+const MNEMONICS: &[(&str, usize)] = &[
+    ("lw", 2),
+    ("sw", 2),
+    ("lbu", 2),
+    ("sb", 2),
+    ("ld", 2),
+    ("sd", 2),
+    ("add", 3),
+    ("addi", 3),
+    ("sub", 3),
+    ("mul", 3),
+    ("mulh", 3),
+    ("mulhu", 3),
+    ("div", 3),
+    ("rem", 3),
+    ("addw", 3),
+    ("addiw", 3),
+    ("subw", 3),
+    ("mulw", 3),
+    ("divw", 3),
+    ("remw", 3),
+    ("sllw", 3),
+    ("srlw", 3),
+    ("sraw", 3),
+    ("slliw", 3),
+    ("srliw", 3),
+    ("sraiw", 3),
+    ("and", 3),
+    ("or", 3),
+    ("xor", 3),
+    ("andi", 3),
+    ("ori", 3),
+    ("xori", 3),
+    ("sll", 3),
+    ("srl", 3),
+    ("sra", 3),
+    ("slli", 3),
+    ("srli", 3),
+    ("srai", 3),
+    ("slt", 3),
+    ("sltu", 3),
+    ("slti", 3),
+    ("sltiu", 3),
+    ("beq", 3),
+    ("bne", 3),
+    ("blt", 3),
+    ("ble", 3),
+    ("bge", 3),
+    ("bltu", 3),
+    ("bgeu", 3),
+    ("j", 1),
+    ("jal", 2),
+    ("jalr", 3),
+    ("ecall", 0),
+    ("ebreak", 0),
+    ("li", 2),
+    ("la", 2),
+    ("mv", 2),
+    ("not", 2),
+    ("neg", 2),
+];
+
+impl std::str::FromStr for Instruction {
+    type Err = CodegenError;
+
+    /// An instruction as the listing writes it, like `addi a0, a0, 1`,
+    /// `lw a0, 4(sp)`, `loop:` or `# comment`
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            return Ok(Instruction::Comment(comment.trim().to_string()));
+        }
+        if let Some(label) = line.strip_suffix(':') {
+            return Ok(Instruction::Label(Operands::label(label)?));
+        }
+
+        let (mnemonic, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let Some(&(_, arity)) = MNEMONICS.iter().find(|(known, _)| *known == mnemonic) else {
+            return Err(CodegenError::InvalidAssembly(format!(
+                "unknown instruction `{}`",
+                mnemonic
+            )));
+        };
+        let operands = Operands::new(operands);
+        if operands.0.len() != arity {
+            return Err(CodegenError::InvalidAssembly(format!(
+                "`{}` takes {} operand(s), found {}",
+                mnemonic,
+                arity,
+                operands.0.len()
+            )));
+        }
+
+        let o = &operands;
+        Ok(match mnemonic {
+            "lw" => {
+                let (offset, rs1) = o.memory(1)?;
+                Instruction::Load(o.register(0)?, rs1, offset)
+            }
+            "sw" => {
+                let (offset, rs1) = o.memory(1)?;
+                Instruction::Store(o.register(0)?, rs1, offset)
+            }
+            "lbu" => {
+                let (offset, rs1) = o.memory(1)?;
+                Instruction::LoadByteU(o.register(0)?, rs1, offset)
+            }
+            "sb" => {
+                let (offset, rs1) = o.memory(1)?;
+                Instruction::StoreByte(o.register(0)?, rs1, offset)
+            }
+            "ld" => {
+                let (offset, rs1) = o.memory(1)?;
+                Instruction::Load64(o.register(0)?, rs1, offset)
+            }
+            "sd" => {
+                let (offset, rs1) = o.memory(1)?;
+                Instruction::Store64(o.register(0)?, rs1, offset)
+            }
+            "add" => Instruction::Add(o.register(0)?, o.register(1)?, o.register(2)?),
+            "addi" => Instruction::AddImm(o.register(0)?, o.register(1)?, o.immediate(2)?),
+            "sub" => Instruction::Sub(o.register(0)?, o.register(1)?, o.register(2)?),
+            "mul" => Instruction::Mul(o.register(0)?, o.register(1)?, o.register(2)?),
+            "mulh" => Instruction::MulHigh(o.register(0)?, o.register(1)?, o.register(2)?),
+            "mulhu" => Instruction::MulHighU(o.register(0)?, o.register(1)?, o.register(2)?),
+            "div" => Instruction::Div(o.register(0)?, o.register(1)?, o.register(2)?),
+            "rem" => Instruction::Rem(o.register(0)?, o.register(1)?, o.register(2)?),
+            "addw" => Instruction::AddW(o.register(0)?, o.register(1)?, o.register(2)?),
+            "addiw" => Instruction::AddImmW(o.register(0)?, o.register(1)?, o.immediate(2)?),
+            "subw" => Instruction::SubW(o.register(0)?, o.register(1)?, o.register(2)?),
+            "mulw" => Instruction::MulW(o.register(0)?, o.register(1)?, o.register(2)?),
+            "divw" => Instruction::DivW(o.register(0)?, o.register(1)?, o.register(2)?),
+            "remw" => Instruction::RemW(o.register(0)?, o.register(1)?, o.register(2)?),
+            "sllw" => Instruction::ShiftLeftW(o.register(0)?, o.register(1)?, o.register(2)?),
+            "srlw" => Instruction::ShiftRightW(o.register(0)?, o.register(1)?, o.register(2)?),
+            "sraw" => Instruction::ShiftRightArithW(o.register(0)?, o.register(1)?, o.register(2)?),
+            "slliw" => Instruction::ShiftLeftImmW(o.register(0)?, o.register(1)?, o.immediate(2)?),
+            "srliw" => Instruction::ShiftRightImmW(o.register(0)?, o.register(1)?, o.immediate(2)?),
+            "sraiw" => {
+                Instruction::ShiftRightArithImmW(o.register(0)?, o.register(1)?, o.immediate(2)?)
+            }
+            "and" => Instruction::And(o.register(0)?, o.register(1)?, o.register(2)?),
+            "or" => Instruction::Or(o.register(0)?, o.register(1)?, o.register(2)?),
+            "xor" => Instruction::Xor(o.register(0)?, o.register(1)?, o.register(2)?),
+            "andi" => Instruction::AndImm(o.register(0)?, o.register(1)?, o.immediate(2)?),
+            "ori" => Instruction::OrImm(o.register(0)?, o.register(1)?, o.immediate(2)?),
+            "xori" => Instruction::XorImm(o.register(0)?, o.register(1)?, o.immediate(2)?),
+            "sll" => Instruction::ShiftLeft(o.register(0)?, o.register(1)?, o.register(2)?),
+            "srl" => Instruction::ShiftRight(o.register(0)?, o.register(1)?, o.register(2)?),
+            "sra" => Instruction::ShiftRightArith(o.register(0)?, o.register(1)?, o.register(2)?),
+            "slli" => Instruction::ShiftLeftImm(o.register(0)?, o.register(1)?, o.immediate(2)?),
+            "srli" => Instruction::ShiftRightImm(o.register(0)?, o.register(1)?, o.immediate(2)?),
+            "srai" => {
+                Instruction::ShiftRightArithImm(o.register(0)?, o.register(1)?, o.immediate(2)?)
+            }
+            "slt" => Instruction::SetLessThan(o.register(0)?, o.register(1)?, o.register(2)?),
+            "sltu" => Instruction::SetLessThanU(o.register(0)?, o.register(1)?, o.register(2)?),
+            "slti" => Instruction::SetLessThanImm(o.register(0)?, o.register(1)?, o.immediate(2)?),
+            "sltiu" => {
+                Instruction::SetLessThanImmU(o.register(0)?, o.register(1)?, o.immediate(2)?)
+            }
+            "beq" => Instruction::BranchEq(o.register(0)?, o.register(1)?, o.target(2)?),
+            "bne" => Instruction::BranchNe(o.register(0)?, o.register(1)?, o.target(2)?),
+            "blt" => Instruction::BranchLt(o.register(0)?, o.register(1)?, o.target(2)?),
+            "ble" => Instruction::BranchLe(o.register(0)?, o.register(1)?, o.target(2)?),
+            "bge" => Instruction::BranchGe(o.register(0)?, o.register(1)?, o.target(2)?),
+            "bltu" => Instruction::BranchLtU(o.register(0)?, o.register(1)?, o.target(2)?),
+            "bgeu" => Instruction::BranchGeU(o.register(0)?, o.register(1)?, o.target(2)?),
+            "j" => Instruction::Jump(o.target(0)?),
+            "jal" => Instruction::JumpAndLink(o.register(0)?, o.target(1)?),
+            "jalr" => Instruction::JumpAndLinkReg(o.register(0)?, o.register(1)?, o.immediate(2)?),
+            "ecall" => Instruction::Ecall,
+            "ebreak" => Instruction::Ebreak,
+            "li" => Instruction::Li(o.register(0)?, o.immediate(1)?),
+            "la" => Instruction::La(o.register(0)?, o.target(1)?),
+            "mv" => Instruction::Mv(o.register(0)?, o.register(1)?),
+            "not" => Instruction::Not(o.register(0)?, o.register(1)?),
+            "neg" => Instruction::Neg(o.register(0)?, o.register(1)?),
+            _ => unreachable!("`{}` is in MNEMONICS", mnemonic),
+        })
+    }
+}
+
+/// The comma separated operands of an instruction
+struct Operands<'a>(Vec<&'a str>);
+
+impl<'a> Operands<'a> {
+    fn new(operands: &'a str) -> Self {
+        let operands = operands.trim();
+        if operands.is_empty() {
+            Operands(Vec::new())
+        } else {
+            Operands(operands.split(',').map(str::trim).collect())
+        }
+    }
+
+    fn register(&self, index: usize) -> Result<Register, CodegenError> {
+        self.0[index].parse()
+    }
+
+    /// A decimal or `0x` hexadecimal immediate, maybe negative
+    fn immediate(&self, index: usize) -> Result<i32, CodegenError> {
+        Self::number(self.0[index])
+    }
+
+    fn number(text: &str) -> Result<i32, CodegenError> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let value = match digits.strip_prefix("0x") {
+            Some(hex) => i64::from_str_radix(hex, 16),
+            None => digits.parse::<i64>(),
+        };
+        value
+            .ok()
+            .map(|value| if negative { -value } else { value })
+            .and_then(|value| i32::try_from(value).ok())
+            .ok_or_else(|| CodegenError::InvalidAssembly(format!("invalid immediate `{}`", text)))
+    }
+
+    /// A branch or jump target
+    fn target(&self, index: usize) -> Result<String, CodegenError> {
+        Self::label(self.0[index])
+    }
+
+    fn label(text: &str) -> Result<String, CodegenError> {
+        let valid = text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '.')
+            && text
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$'));
+        if valid {
+            Ok(text.to_string())
+        } else {
+            Err(CodegenError::InvalidAssembly(format!(
+                "invalid label `{}`",
+                text
+            )))
+        }
+    }
+
+    /// A memory operand, `offset(register)`
+    fn memory(&self, index: usize) -> Result<(i32, Register), CodegenError> {
+        let text = self.0[index];
+        let invalid = || {
+            CodegenError::InvalidAssembly(format!(
+                "invalid memory operand `{}`, expected `offset(register)`",
+                text
+            ))
+        };
+        let (offset, register) = text
+            .strip_suffix(')')
+            .and_then(|text| text.split_once('('))
+            .ok_or_else(invalid)?;
+        let offset = if offset.trim().is_empty() {
+            0
+        } else {
+            Self::number(offset.trim())?
+        };
+        Ok((offset, register.trim().parse()?))
+    }
+}
+
+/// The registers and instructions of an `asm` block, read and checked
+#[derive(Debug, Clone)]
+pub struct InlineAsm {
+    /// Registers loaded with the inputs, in order
+    pub inputs: Vec<Register>,
+    /// Register holding the value of the block, `zero` without `out`
+    pub output: Register,
+    pub instructions: Vec<Instruction>,
+}
+
+impl InlineAsm {
+    /// Read the bindings and instructions of an `asm` block. Inputs cannot
+    /// be bound to `zero` nor to `sp`, which they are loaded from, and each
+    /// register is bound once.
+    pub fn parse(
+        inputs: &[(String, Expr)],
+        output: Option<&str>,
+        instructions: &[String],
+    ) -> Result<Self, CodegenError> {
+        let mut registers = Vec::with_capacity(inputs.len());
+        for (name, _) in inputs {
+            let register: Register = name.parse()?;
+            if matches!(register, Register::X0 | Register::X2) {
+                return Err(CodegenError::InvalidAssembly(format!(
+                    "`{}` cannot be an input",
+                    name
+                )));
+            }
+            if registers.contains(&register) {
+                return Err(CodegenError::InvalidAssembly(format!(
+                    "`{}` is bound twice",
+                    name
+                )));
+            }
+            registers.push(register);
+        }
+        let output = match output {
+            Some(name) => name.parse()?,
+            None => Register::X0,
+        };
+        let instructions = instructions
+            .iter()
+            .map(|line| {
+                line.parse().map_err(|e| match e {
+                    CodegenError::InvalidAssembly(reason) => {
+                        CodegenError::InvalidAssembly(format!("{} in `{}`", reason, line.trim()))
+                    }
+                    e => e,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(InlineAsm {
+            inputs: registers,
+            output,
+            instructions,
+        })
+    }
+
+    /// Whether the block calls the host
+    pub fn calls_host(&self) -> bool {
+        self.instructions
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::Ecall))
+    }
+}
+
+/// Scratch space for a storage access: 32-byte key, value word, length word
+const STORAGE_SCRATCH_SIZE: i32 = 48;
+
+/// Scratch space for a storage collection access
+///
+/// Layout: 32-byte key at 0, hash suffix at 32, value and length words at
+/// 36 and 40, evaluated arguments from 48.
+const COLLECTION_SCRATCH_SIZE: i32 = 64;
+
+/// Offset of the first evaluated argument in the collection scratch area
+const COLLECTION_ARGS_OFFSET: i32 = 48;
+
+/// Size of an in-memory map header: the root of the search tree
+const MAP_HEADER_SIZE: i32 = 4;
+
+/// Size of an in-memory map node: key, value, left and right child
+const MAP_NODE_SIZE: i32 = 16;
+
+/// Words reserved below the frame by a map operation
+///
+/// Layout: map pointer at 0, key at 4, value at 8 and the link being
+/// followed at 12.
+const MAP_SCRATCH_WORDS: usize = 4;
+
+/// Size of the length word before the data of a `Bytes` value
+const BYTES_HEADER_SIZE: i32 = 4;
+
+/// Builtins on `Bytes` values with their number of arguments
+const BYTES_BUILTINS: [(&str, usize); 5] = [
+    ("len", 1),
+    ("concat", 2),
+    ("slice", 3),
+    ("keccak256", 1),
+    ("random", 1),
+];
+
+/// Builtin holding the little-endian bytes of a value as `Bytes`
+const TO_BYTES: &str = "to_bytes";
+
+/// Builtin reading a `u256` out of `Bytes`
+const U256_AT: &str = "u256_at";
+
+/// Scratch space for a cross-contract call, followed by the call input
+///
+/// Layout: 32-byte address or code hash at 0, 16-byte value at 32, gas word
+/// at 48, output length at 52, output at 56 (a word, or the 32-byte address
+/// of an instantiated contract), selector and arguments from 88.
+const CONTRACT_CALL_SCRATCH_SIZE: i32 = 88;
+
+/// Contract call builtins with the number of arguments before the signature
+const CONTRACT_CALL_BUILTINS: [(&str, usize); 3] =
+    [("call", 3), ("delegate_call", 2), ("instantiate", 3)];
+
+/// Balance builtins with their number of arguments
+///
+/// `balance()` reads the executing contract's balance, `transfer(to, value)`
+/// sends value to an account and `terminate(beneficiary)` removes the
+/// contract, sending its balance to the beneficiary.
+const BALANCE_BUILTINS: [(&str, usize); 3] = [("balance", 0), ("transfer", 2), ("terminate", 1)];
+
+/// How a message argument or result crosses the contract boundary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AbiValue {
+    /// Little-endian words of a word, wide integer, `Address` or `Hash`
+    Words(usize),
+    /// A SCALE compact length followed by the data of a `Bytes` value
+    Bytes,
+    /// A single byte that is 0 or 1
+    Bool,
+}
+
+/// Kind of a declared storage field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StorageKind {
+    /// A single word (`u24` or `StorageValue<T>`)
+    Value,
+    /// `StorageMap<K, V>` (or a `Map<K, V>` field): one entry per key
+    Map,
+    /// `StorageVec<T>`: length under the root, one entry per element
+    Vec,
+}
+
+/// Gas charged through the `ChargeGas` host call on every loop iteration
+pub const LOOP_ITERATION_GAS: u32 = 10;
+
+/// Label of the generated message dispatcher
+pub const DISPATCH_LABEL: &str = "call";
+
+/// Prefix of the labels entering `#[export]` functions, followed by the
+/// export name
+pub const EXPORT_PREFIX: &str = "export.";
+
+/// Label entering the function exported as `export`
+pub fn export_label(export: &str) -> String {
+    format!("{}{}", EXPORT_PREFIX, export)
+}
+
+/// Whether the backend can keep a storage field of type `ty`: a word or
+/// wide integer, or a storage collection of them
+pub fn is_storable(ty: &Type) -> bool {
+    RiscVCodegen::storage_kind(ty).is_some()
+}
+
+/// The error for a construct the backend cannot generate, with where it is
+/// written, e.g. `unsupported("Lists are", location)`
+fn unsupported(what: &str, location: &Location) -> CodegenError {
+    CodegenError::UnsupportedFeature(format!(
+        "{} not supported by the RISC-V backend at line {}, column {}",
+        what, location.line, location.column
+    ))
+}
+
 /// Code generator for RISC-V assembly
 pub struct RiscVCodegen {
     /// Instructions generated
     instructions: Vec<Instruction>,
 
     /// Local variable mapping to stack offsets
-    locals: HashMap<String, i32>,
+    locals: HashMap<Symbol, i32>,
 
     /// Current stack frame size
     frame_size: i32,
@@ -366,10 +1027,83 @@ pub struct RiscVCodegen {
     next_label_id: u32,
 
     /// Labels for function entry points
-    function_labels: HashMap<String, String>,
+    function_labels: HashMap<Symbol, String>,
 
     /// Current offset for next local variable
     current_local_offset: i32,
+
+    /// Event definitions with their signature topic
+    events: HashMap<Symbol, (Vec<EventField>, [u8; 32])>,
+
+    /// Variants of error types, e.g. `Error/Paused`, with their field types
+    /// and selector
+    errors: HashMap<Symbol, (Vec<Type>, [u8; 4])>,
+
+    /// Types declared with `error`, whose variants are values
+    error_types: HashSet<Symbol>,
+
+    /// Variants of the types declared with `type`, e.g. `Option/Some`, with
+    /// their tag and the names of their fields
+    variants: HashMap<Symbol, (i32, Vec<Symbol>)>,
+
+    /// Whether to generate a selector-based message dispatcher
+    dispatch: bool,
+
+    /// Storage fields with their storage key
+    storage: HashMap<Symbol, ([u8; 32], StorageKind)>,
+
+    /// Bytes reserved below the frame by an in-progress storage access
+    stack_adjust: i32,
+
+    /// Chain extensions callable by name
+    chain_extensions: HashMap<Symbol, ChainExtension>,
+
+    /// Number of words of wide integer locals and parameters
+    local_widths: HashMap<Symbol, usize>,
+
+    /// Number of words of wide integer storage values
+    storage_widths: HashMap<Symbol, usize>,
+
+    /// Number of words of each part of the keys of storage maps with wide
+    /// or tuple keys
+    storage_key_parts: HashMap<Symbol, Vec<usize>>,
+
+    /// Number of words of wide integer function results
+    function_widths: HashMap<Symbol, usize>,
+
+    /// Number of words of each parameter of the functions with wide ones
+    function_params: HashMap<Symbol, Vec<usize>>,
+
+    /// Number of words returned by the function being generated
+    return_words: usize,
+
+    /// Label of the epilogue of the function being generated
+    return_label: String,
+
+    /// Whether arithmetic traps on overflow
+    checked: bool,
+
+    /// Integer types of word-sized locals and parameters
+    local_kinds: HashMap<Symbol, CheckedInt>,
+
+    /// Integer types of word-sized storage values
+    storage_kinds: HashMap<Symbol, CheckedInt>,
+
+    /// Integer types of word-sized function results
+    function_kinds: HashMap<Symbol, CheckedInt>,
+
+    /// Locals and parameters holding a `Bytes` pointer
+    bytes_locals: HashSet<Symbol>,
+
+    /// Functions returning `Bytes`
+    bytes_functions: HashSet<Symbol>,
+
+    /// Parameters holding a function, which shadow the functions of the
+    /// same name
+    function_args: HashSet<Symbol>,
+
+    /// Instruction set the code is generated for
+    isa: Isa,
 }
 
 impl Default for RiscVCodegen {
@@ -387,517 +1121,5446 @@ impl RiscVCodegen {
             next_label_id: 0,
             function_labels: HashMap::new(),
             current_local_offset: 0,
+            events: HashMap::new(),
+            errors: HashMap::new(),
+            error_types: HashSet::new(),
+            variants: HashMap::new(),
+            dispatch: false,
+            storage: HashMap::new(),
+            stack_adjust: 0,
+            chain_extensions: HashMap::new(),
+            local_widths: HashMap::new(),
+            storage_widths: HashMap::new(),
+            storage_key_parts: HashMap::new(),
+            function_widths: HashMap::new(),
+            function_params: HashMap::new(),
+            return_words: 1,
+            return_label: String::new(),
+            checked: true,
+            local_kinds: HashMap::new(),
+            storage_kinds: HashMap::new(),
+            function_kinds: HashMap::new(),
+            bytes_locals: HashSet::new(),
+            bytes_functions: HashSet::new(),
+            function_args: HashSet::new(),
+            isa: Isa::default(),
         }
     }
 
+    /// Generate code for `isa`
+    ///
+    /// On 64-bit ISAs return addresses are saved as double words and the
+    /// arithmetic is rewritten by [`rv64::widen`](super::rv64::widen). On
+    /// 32-bit ones `asm` blocks cannot use RV64 instructions.
+    pub fn with_isa(mut self, isa: Isa) -> Self {
+        self.isa = isa;
+        self
+    }
+
+    /// Generate a message dispatcher at `DISPATCH_LABEL`
+    pub fn with_dispatcher(mut self) -> Self {
+        self.dispatch = true;
+        self
+    }
+
+    /// Route calls to the registered chain extensions through their host
+    /// call ids
+    ///
+    /// A body-less declaration of an extension (see
+    /// [`ChainExtension::extern_declaration`]) generates no code.
+    pub fn with_chain_extensions(mut self, registry: &ChainExtensionRegistry) -> Self {
+        self.chain_extensions = registry
+            .iter()
+            .map(|extension| (Symbol::from(&extension.name), extension.clone()))
+            .collect();
+        self
+    }
+
+    /// Whether a function definition only declares a chain extension
+    fn is_extension_declaration(&self, name: Symbol, body: &Block) -> bool {
+        body.statements.is_empty() && self.chain_extensions.contains_key(&name)
+    }
+
     /// Generate code for a program
     pub fn generate(&mut self, program: &Program) -> Result<Vec<Instruction>, CodegenError> {
-        // Generate function labels
+        // Generate function labels and collect events
         for definition in &program.definitions {
-            if let Definition::FunctionDef { name, .. } = definition {
-                let label = self.generate_function_label(name);
-                self.function_labels.insert(name.clone(), label);
+            match definition {
+                Definition::FunctionDef {
+                    name,
+                    params,
+                    body,
+                    return_type,
+                    ..
+                } if !self.is_extension_declaration(*name, body) => {
+                    let label = self.generate_function_label(name);
+                    self.function_labels.insert(name.into(), label);
+                    if params
+                        .iter()
+                        .any(|param| Self::wide_words(&param.ty).is_some())
+                    {
+                        let words = params
+                            .iter()
+                            .map(|param| Self::wide_words(&param.ty).unwrap_or(1))
+                            .collect();
+                        self.function_params.insert(name.into(), words);
+                    }
+                    if let Some(words) = return_type.as_ref().and_then(Self::wide_words) {
+                        self.function_widths.insert(name.into(), words);
+                    }
+                    if let Some(kind) = return_type.as_ref().and_then(Self::word_int) {
+                        self.function_kinds.insert(name.into(), kind);
+                    }
+                    if return_type.as_ref().is_some_and(Self::is_bytes_type) {
+                        self.bytes_functions.insert(name.into());
+                    }
+                }
+                Definition::EventDef { name, fields, .. } => {
+                    let topic = compute_event_topic(&compute_event_signature(name, fields));
+                    self.events.insert(name.into(), (fields.clone(), topic));
+                }
+                Definition::TypeDef { name, variants, .. }
+                | Definition::ErrorDef { name, variants, .. } => {
+                    if matches!(definition, Definition::ErrorDef { .. }) {
+                        self.error_types.insert(name.into());
+                    }
+                    for (tag, variant) in variants.iter().enumerate() {
+                        if matches!(definition, Definition::TypeDef { .. }) {
+                            let fields = variant.fields.iter().map(|field| field.name).collect();
+                            self.variants.insert(
+                                format!("{}/{}", name, variant.name).into(),
+                                (tag as i32, fields),
+                            );
+                        }
+                        let selector = selector_for(&compute_error_signature(name, variant));
+                        let fields = variant
+                            .fields
+                            .iter()
+                            .map(|field| {
+                                field.type_annotation.clone().unwrap_or(Type::U24 {
+                                    location: field.location.clone(),
+                                })
+                            })
+                            .collect();
+                        self.errors.insert(
+                            format!("{}/{}", name, variant.name).into(),
+                            (fields, selector),
+                        );
+                    }
+                }
+                Definition::StorageDef { fields, .. } => {
+                    for field in fields {
+                        let kind = Self::storage_kind(&field.ty).ok_or_else(|| {
+                            CodegenError::UnsupportedFeature(format!(
+                                "Storage field {} must have a word-sized type or a storage collection of them",
+                                field.name
+                            ))
+                        })?;
+                        self.storage
+                            .insert(field.name, (compute_storage_key(&field.name), kind));
+                        let value_type = Self::storage_value_type(&field.ty);
+                        if let Some(words) = Self::wide_words(value_type) {
+                            self.storage_widths.insert(field.name, words);
+                        }
+                        if let Some(parts) =
+                            Self::storage_key_type(&field.ty).and_then(Self::key_parts)
+                        {
+                            if parts.iter().sum::<usize>() > 1 {
+                                self.storage_key_parts.insert(field.name, parts);
+                            }
+                        }
+                        if let Some(kind) = Self::word_int(value_type) {
+                            self.storage_kinds.insert(field.name, kind);
+                        }
+                    }
+                }
+                _ => {}
             }
         }
 
+        if self.dispatch {
+            self.generate_dispatcher(program)?;
+        }
+
         // Generate code for each function
         for definition in &program.definitions {
             if let Definition::FunctionDef {
-                name, params, body, ..
+                name,
+                params,
+                body,
+                checked,
+                attributes,
+                ..
             } = definition
             {
-                self.generate_function(name, params, body)?;
+                // `#[extern]` functions are called at the label the linker
+                // resolves
+                if !self.function_labels.contains_key(name)
+                    || attributes
+                        .iter()
+                        .any(|attribute| attribute.name == "extern")
+                {
+                    continue;
+                }
+                self.checked = *checked != Some(false);
+                self.generate_function(*name, params, body)?;
             }
         }
+        self.generate_exports(program)?;
 
+        if self.isa.is_64_bit() {
+            return Ok(super::rv64::widen(&self.instructions));
+        }
         Ok(self.instructions.clone())
     }
 
-    /// Generate a unique label
-    fn generate_label(&mut self, prefix: &str) -> String {
-        let label = format!("{}.{}", prefix, self.next_label_id);
-        self.next_label_id += 1;
-        label
-    }
+    /// Generate an entry label for every `#[export]` function
+    ///
+    /// The host enters it with the return address of the host in `ra` and
+    /// the input in `a0` and `a1`, as for the dispatcher. The parameters of
+    /// the function are decoded from the input the way the dispatcher
+    /// decodes the arguments after the selector, reverting on malformed
+    /// input, and the function returns its result in `a0` to the host.
+    fn generate_exports(&mut self, program: &Program) -> Result<(), CodegenError> {
+        for definition in &program.definitions {
+            let Definition::FunctionDef { name, params, .. } = definition else {
+                continue;
+            };
+            let Some(function_label) = self.function_labels.get(name).cloned() else {
+                continue;
+            };
+            let attributes = FunctionAttributes::of(definition)
+                .map_err(|e| CodegenError::Generic(e.to_string()))?;
+            let Some(export) = attributes.export else {
+                continue;
+            };
+            if self.dispatch && export.as_str() == DISPATCH_LABEL {
+                return Err(CodegenError::Generic(format!(
+                    "Export '{}' of {} collides with the message dispatcher",
+                    export, name
+                )));
+            }
 
-    /// Generate a function label
-    fn generate_function_label(&mut self, name: &str) -> String {
-        if name == "main" {
-            "main".to_string()
-        } else {
-            format!("function.{}", name.replace('/', "_"))
+            let mut args = Vec::new();
+            for param in params {
+                args.push(Self::abi_value(&param.ty).ok_or_else(|| {
+                    CodegenError::UnsupportedFeature(format!(
+                        "Parameter {} of {} cannot be decoded from the input of export '{}'",
+                        param.name, name, export
+                    ))
+                })?);
+            }
+
+            let label = export_label(&export);
+            self.instructions.push(Instruction::Label(label.clone()));
+            self.instructions
+                .push(Instruction::Comment(format!("Export {}", export)));
+            if args.is_empty() {
+                self.instructions.push(Instruction::Jump(function_label));
+                continue;
+            }
+
+            // Keep the return address of the host above the arguments
+            let revert_label = format!("{}.revert", label);
+            let args_size = (Self::abi_words(&args) * 4) as i32;
+            self.instructions
+                .push(Instruction::AddImm(Register::X2, Register::X2, -4));
+            self.instructions
+                .push(Instruction::Store(Register::X1, Register::X2, 0));
+            self.generate_decode_args(&args, 0, &revert_label);
+            self.instructions
+                .push(Instruction::JumpAndLink(Register::X1, function_label));
+            self.instructions
+                .push(Instruction::AddImm(Register::X2, Register::X2, args_size));
+            self.instructions
+                .push(Instruction::Load(Register::X1, Register::X2, 0));
+            self.instructions
+                .push(Instruction::AddImm(Register::X2, Register::X2, 4));
+            self.instructions
+                .push(Instruction::JumpAndLinkReg(Register::X0, Register::X1, 0));
+
+            self.instructions.push(Instruction::Label(revert_label));
+            self.instructions.push(Instruction::Li(Register::X10, 0));
+            self.instructions.push(Instruction::Li(Register::X11, 0));
+            self.instructions
+                .push(Instruction::Li(Register::X17, HostFunction::Revert as i32));
+            self.instructions.push(Instruction::Ecall);
         }
+        Ok(())
     }
 
-    /// Helper to count local variables
-    fn collect_locals(&self, block: &Block) -> usize {
-        let mut count = 0;
-        for stmt in &block.statements {
-            match stmt {
-                Statement::Use { .. } => count += 1,
-                Statement::If {
-                    then_branch,
-                    else_branch,
-                    ..
-                } => {
-                    count += self.collect_locals(then_branch);
-                    count += self.collect_locals(else_branch);
-                }
-                Statement::Match { cases, .. } => {
-                    for case in cases {
-                        count += self.collect_locals(&case.body);
-                    }
-                }
-                Statement::Switch { cases, .. } => {
-                    for case in cases {
-                        count += self.collect_locals(&case.body);
-                    }
-                }
-                Statement::Fold { cases, .. } => {
-                    for case in cases {
-                        count += self.collect_locals(&case.body);
-                    }
+    /// Generate the message dispatcher
+    ///
+    /// The dispatcher is entered with the call input pointer in `a0` and its
+    /// length in `a1`. It reads the 4-byte selector, compares it against the
+    /// selector of every function, decodes the SCALE-encoded word, wide
+    /// integer and `Bytes` arguments onto the stack, calls the target and returns its
+    /// result through the `Return` host function. Unknown selectors and
+    /// malformed input revert, as do functions returning the `Err` of a
+    /// `Result`, with the data of its error value, and calls transferring
+    /// value to functions not marked `#[payable]`. Only `#[message]`
+    /// functions are dispatched; `#[export]` functions are entered by the
+    /// host directly and the others are helpers. A library, which declares
+    /// neither, gets no dispatcher and is entered at `main` as before.
+    fn generate_dispatcher(&mut self, program: &Program) -> Result<(), CodegenError> {
+        let mut targets = Vec::new();
+        let mut selectors: HashMap<[u8; 4], &str> = HashMap::new();
+        let mut exports = false;
+
+        for definition in &program.definitions {
+            if let Definition::FunctionDef {
+                name,
+                params,
+                return_type,
+                ..
+            } = definition
+            {
+                if !self.function_labels.contains_key(name) {
+                    continue;
                 }
-                Statement::Bend {
-                    body, else_body, ..
-                } => {
-                    count += self.collect_locals(body);
-                    if let Some(else_b) = else_body {
-                        count += self.collect_locals(else_b);
-                    }
+                let attributes = FunctionAttributes::of(definition)
+                    .map_err(|e| CodegenError::Generic(e.to_string()))?;
+                exports |= attributes.export.is_some();
+                if !attributes.message {
+                    continue;
                 }
-                Statement::LocalDef { function_def, .. } => {
-                    // If we support local functions, we might need to recurse if they capture?
-                    // For now, ignore.
-                    if let Definition::FunctionDef { .. } = function_def.as_ref() {
-                        // But local functions are separate frames?
-                        // Yes, usually. So don't count their locals here.
-                    }
+                let mut args = Vec::new();
+                for param in params {
+                    args.push(Self::abi_value(&param.ty).ok_or_else(|| {
+                        CodegenError::UnsupportedFeature(format!(
+                            "Parameter {} of message {} cannot be decoded from call data",
+                            param.name, name
+                        ))
+                    })?);
                 }
-                Statement::With { body, .. } => {
-                    count += self.collect_locals(body);
+                // A `Result` returns its `Ok` value and reverts with its error
+                let fallible = return_type.as_ref().and_then(Self::result_value_type);
+                let result =
+                    fallible
+                        .or(return_type.as_ref())
+                        .map(|ty| match Self::abi_value(ty) {
+                            Some(value @ (AbiValue::Bytes | AbiValue::Bool)) => value,
+                            _ => AbiValue::Words(Self::wide_words(ty).unwrap_or(1)),
+                        });
+
+                let selector = attributes
+                    .selector
+                    .unwrap_or_else(|| compute_selector_for_params(name, params));
+                if let Some(existing) = selectors.insert(selector, name) {
+                    return Err(CodegenError::Generic(format!(
+                        "Selector 0x{} of {} collides with {}",
+                        hex::encode(selector),
+                        name,
+                        existing
+                    )));
                 }
-                _ => {}
-            }
-        }
-        count
-    }
-
-    /// Generate code for a function
-    fn generate_function(
-        &mut self,
-        name: &str,
-        params: &[Parameter],
-        body: &Block,
-    ) -> Result<(), CodegenError> {
-        // Reset local variables and frame size
-        self.locals.clear();
-        self.current_local_offset = 0;
-
-        // Calculate frame size
-        // Locals: collect_locals(body) * 4
-        // Params: passed by caller, but we map them to stack offsets if needed?
-        // Wait, current logic maps params to [8, 12, ...].
-        // The implementation assumes params are ALREADY on the stack above the return address.
-        // Or it assumes we need to spill them?
-        // "Allocate space for local variables and function arguments" - the comment said.
-        // But usually arguments are in registers a0-a7.
-        // The previous code seemed to assume we put them on stack?
-        // But it didn't generate store instructions for them!
-        // It just inserted them into self.locals!
-        // This implies the compiler expects params to BE at those offsets.
-        // This is only true if the CALLER put them there.
-        // Or if we spill them in prologue.
-        // Since `generate_function` prologue didn't spill a0-a7,
-        // AND `Expr::Variable` loads from `offset(SP)`,
-        // The only logical conclusion is that the calling convention used by this simple compiler
-        // expects arguments to be passed on the stack.
 
-        // Let's preserve this behavior.
-        // Arguments are at `CallerSP + 0`, `CallerSP + 4`...
-        // `SP = CallerSP - 8` (in original). So `args at SP + 8`.
+                targets.push((
+                    *name,
+                    selector,
+                    args,
+                    result,
+                    fallible.is_some(),
+                    attributes.payable,
+                ));
+            }
+        }
 
-        let locals_count = self.collect_locals(body);
-        let locals_size = (locals_count * 4) as i32;
-        let total_frame_size = locals_size + 8; // RA + alignment/padding + locals
+        if targets.is_empty() && !exports {
+            return Ok(());
+        }
 
-        // Function label
-        let function_label = self.function_labels.get(name).unwrap().clone();
-        self.instructions.push(Instruction::Label(function_label));
+        let revert_label = format!("{}.revert", DISPATCH_LABEL);
 
-        // Function prologue: save return address
-        self.instructions.push(Instruction::Comment(format!(
-            "Function prologue for {}",
-            name
-        )));
+        self.instructions
+            .push(Instruction::Label(DISPATCH_LABEL.to_string()));
+        self.instructions
+            .push(Instruction::Comment("Message dispatcher".to_string()));
 
-        // Allocate stack frame
-        self.instructions.push(Instruction::AddImm(
-            Register::X2,
-            Register::X2,
-            -total_frame_size,
+        // Input must at least contain the selector
+        self.instructions.push(Instruction::Li(Register::X6, 4));
+        self.instructions.push(Instruction::BranchLtU(
+            Register::X11,
+            Register::X6,
+            revert_label.clone(),
         ));
-
-        // Save RA at `locals_size` (just below caller args)
         self.instructions
-            .push(Instruction::Store(Register::X1, Register::X2, locals_size));
+            .push(Instruction::Load(Register::X5, Register::X10, 0));
 
-        // Map params (Caller args start at `total_frame_size + 8` relative to new SP??)
-        // Original: `AddImm -8`. Args at `8`.
-        // `CallerSP = NewSP + 8`.
-        // Args at `CallerSP + 0` = `NewSP + 8`.
+        let mut target_labels = Vec::new();
+        for (name, selector, ..) in &targets {
+            let label = format!("{}.{}", DISPATCH_LABEL, name.replace('/', "_"));
+            self.instructions.push(Instruction::Li(
+                Register::X6,
+                u32::from_le_bytes(*selector) as i32,
+            ));
+            self.instructions.push(Instruction::BranchEq(
+                Register::X5,
+                Register::X6,
+                label.clone(),
+            ));
+            target_labels.push(label);
+        }
+        self.instructions
+            .push(Instruction::Jump(revert_label.clone()));
 
-        // New: `AddImm -total`.
-        // `CallerSP = NewSP + total`.
-        // Args at `CallerSP + 0` = `NewSP + total`.
+        for ((name, _, args, result, fallible, payable), label) in targets.iter().zip(target_labels)
+        {
+            let args_size = (Self::abi_words(args) * 4) as i32;
+            let function_label = self.function_labels.get(name).unwrap().clone();
 
-        // Actually, if original args were at `0(CallerSP)`, `4(CallerSP)`...
-        // Then yes, offset is `total_frame_size`.
+            self.instructions.push(Instruction::Label(label));
+            if !payable {
+                self.generate_reject_value(&revert_label);
+            }
 
-        // But wait, the original code used `offset = 8`.
-        // `frame_size` (which was `offset`) = 8 + params*4.
+            // Call data is the selector followed by the arguments
+            self.generate_decode_args(args, 4, &revert_label);
+            self.instructions
+                .push(Instruction::JumpAndLink(Register::X1, function_label));
 
-        // If I change the stack layout, I must ensure `Expr::Variable` uses correct offsets.
+            if *fallible {
+                let ok = self.generate_label("dispatch_ok");
+                self.instructions
+                    .push(Instruction::Load(Register::X6, Register::X10, 0));
+                self.instructions.push(Instruction::BranchEq(
+                    Register::X6,
+                    Register::X0,
+                    ok.clone(),
+                ));
+                self.instructions
+                    .push(Instruction::Load(Register::X5, Register::X10, 4));
+                self.generate_revert_error_value();
+                self.instructions.push(Instruction::Label(ok));
+                self.instructions
+                    .push(Instruction::Load(Register::X10, Register::X10, 4));
+            }
 
-        let mut offset = total_frame_size;
-        for param in params {
-            self.locals.insert(param.name.clone(), offset);
-            offset += 4;
+            // Encode the result words returned in a0.., reusing the argument area
+            if *result == Some(AbiValue::Bytes) {
+                self.generate_dispatch_encode_bytes();
+            } else if *result == Some(AbiValue::Bool) {
+                self.instructions
+                    .push(Instruction::AddImm(Register::X2, Register::X2, -4));
+                self.instructions
+                    .push(Instruction::StoreByte(Register::X10, Register::X2, 0));
+                self.instructions
+                    .push(Instruction::Mv(Register::X10, Register::X2));
+                self.instructions.push(Instruction::Li(Register::X11, 1));
+            } else if let Some(AbiValue::Words(result_words)) = result {
+                let result_size = (*result_words * 4) as i32;
+                if args_size < result_size {
+                    self.instructions.push(Instruction::AddImm(
+                        Register::X2,
+                        Register::X2,
+                        args_size - result_size,
+                    ));
+                }
+                for (i, reg) in Register::arg_registers()
+                    .into_iter()
+                    .take(*result_words)
+                    .enumerate()
+                {
+                    self.instructions
+                        .push(Instruction::Store(reg, Register::X2, (i * 4) as i32));
+                }
+                self.instructions
+                    .push(Instruction::Mv(Register::X10, Register::X2));
+                self.instructions
+                    .push(Instruction::Li(Register::X11, result_size));
+            } else {
+                self.instructions.push(Instruction::Li(Register::X10, 0));
+                self.instructions.push(Instruction::Li(Register::X11, 0));
+            }
+            self.instructions
+                .push(Instruction::Li(Register::X17, HostFunction::Return as i32));
+            self.instructions.push(Instruction::Ecall);
         }
 
-        self.frame_size = total_frame_size; // Maybe unused, but keep it correct
+        self.instructions.push(Instruction::Label(revert_label));
+        self.instructions.push(Instruction::Li(Register::X10, 0));
+        self.instructions.push(Instruction::Li(Register::X11, 0));
+        self.instructions
+            .push(Instruction::Li(Register::X17, HostFunction::Revert as i32));
+        self.instructions.push(Instruction::Ecall);
 
-        // Generate code for the function body
-        self.generate_block(body)?;
+        Ok(())
+    }
 
-        // Function epilogue: restore return address and return
-        self.instructions.push(Instruction::Comment(format!(
-            "Function epilogue for {}",
-            name
-        )));
+    /// Revert if the call transfers any value
+    ///
+    /// The 16-byte call value is fetched onto the stack, keeping
+    /// the input pointer and length in `t3` and `t4`.
+    fn generate_reject_value(&mut self, revert_label: &str) {
         self.instructions
-            .push(Instruction::Load(Register::X1, Register::X2, locals_size)); // Restore return address
-        self.instructions.push(Instruction::AddImm(
-            Register::X2,
-            Register::X2,
-            total_frame_size,
+            .push(Instruction::Mv(Register::X28, Register::X10));
+        self.instructions
+            .push(Instruction::Mv(Register::X29, Register::X11));
+        self.instructions
+            .push(Instruction::AddImm(Register::X2, Register::X2, -16));
+        self.instructions
+            .push(Instruction::Mv(Register::X10, Register::X2));
+        self.instructions.push(Instruction::Li(
+            Register::X17,
+            HostFunction::GetCallValue as i32,
         ));
+        self.instructions.push(Instruction::Ecall);
 
-        // Return from function
         self.instructions
-            .push(Instruction::JumpAndLinkReg(Register::X0, Register::X1, 0)); // Return
+            .push(Instruction::Load(Register::X30, Register::X2, 0));
+        for offset in [4, 8, 12] {
+            self.instructions
+                .push(Instruction::Load(Register::X31, Register::X2, offset));
+            self.instructions
+                .push(Instruction::Or(Register::X30, Register::X30, Register::X31));
+        }
 
-        Ok(())
+        self.instructions
+            .push(Instruction::AddImm(Register::X2, Register::X2, 16));
+        self.instructions
+            .push(Instruction::Mv(Register::X10, Register::X28));
+        self.instructions
+            .push(Instruction::Mv(Register::X11, Register::X29));
+        self.instructions.push(Instruction::BranchNe(
+            Register::X30,
+            Register::X0,
+            revert_label.to_string(),
+        ));
     }
 
-    /// Generate code for a block
-    fn generate_block(&mut self, block: &Block) -> Result<Register, CodegenError> {
-        let mut result_reg = Register::X0;
+    /// Number of stack words taken by message arguments; `Bytes` and `Bool`
+    /// arguments are passed as a word
+    fn abi_words(args: &[AbiValue]) -> usize {
+        args.iter()
+            .map(|arg| match arg {
+                AbiValue::Words(words) => *words,
+                AbiValue::Bytes | AbiValue::Bool => 1,
+            })
+            .sum()
+    }
 
-        for statement in &block.statements {
-            result_reg = self.generate_statement(statement)?;
+    /// Decode the arguments in the input at `a0`, of `a1` bytes, after its
+    /// first `skip` bytes, onto the stack, reverting when it does not hold
+    /// exactly them
+    fn generate_decode_args(&mut self, args: &[AbiValue], skip: i32, revert_label: &str) {
+        if args.iter().any(|arg| !matches!(arg, AbiValue::Words(_))) {
+            self.generate_dispatch_decode(args, skip, revert_label);
+            return;
         }
 
-        Ok(result_reg)
-    }
+        // The little-endian words of every argument
+        let arg_words = Self::abi_words(args) as i32;
+        self.instructions
+            .push(Instruction::Li(Register::X6, skip + arg_words * 4));
+        self.instructions.push(Instruction::BranchNe(
+            Register::X11,
+            Register::X6,
+            revert_label.to_string(),
+        ));
 
-    /// Generate code for a statement
-    fn generate_statement(&mut self, statement: &Statement) -> Result<Register, CodegenError> {
-        match statement {
-            Statement::Return { value, .. } => {
-                let result_reg = self.generate_expr(value)?;
+        // Arguments are passed on the caller's stack
+        if arg_words > 0 {
+            self.instructions.push(Instruction::AddImm(
+                Register::X2,
+                Register::X2,
+                -arg_words * 4,
+            ));
+            for i in 0..arg_words {
+                self.instructions.push(Instruction::Load(
+                    Register::X5,
+                    Register::X10,
+                    skip + i * 4,
+                ));
                 self.instructions
-                    .push(Instruction::Mv(Register::X10, result_reg)); // Move result to a0 (return value)
-                Ok(Register::X10)
-            }
-            Statement::Assignment { pattern, value, .. } => {
-                let value_reg = self.generate_expr(value)?;
-                self.generate_assignment(pattern, value_reg)?;
-                Ok(value_reg)
+                    .push(Instruction::Store(Register::X5, Register::X2, i * 4));
             }
-            Statement::If {
-                condition,
-                then_branch,
-                else_branch,
-                ..
-            } => {
-                let condition_reg = self.generate_expr(condition)?;
+        }
+    }
 
-                let then_label = self.generate_label("if_then");
-                let else_label = self.generate_label("if_else");
-                let end_label = self.generate_label("if_end");
+    /// Decode arguments including `Bytes` or `Bool` values onto the stack
+    ///
+    /// The input is read with a cursor, since `Bytes` arguments have a
+    /// compact length prefix in the one, two or four byte mode followed by
+    /// their data, which is copied to the heap, and `Bool` arguments are a
+    /// single byte. The cursor and the end of the input are kept in two words
+    /// below the arguments while decoding. Input that ends early, has
+    /// trailing bytes or a `Bool` other than 0 or 1 reverts.
+    fn generate_dispatch_decode(&mut self, args: &[AbiValue], skip: i32, revert_label: &str) {
+        let args_size = (Self::abi_words(args) * 4) as i32;
+        let revert = || revert_label.to_string();
 
-                // Branch to then_label if condition is true (non-zero)
-                self.instructions.push(Instruction::BranchNe(
-                    condition_reg,
-                    Register::X0,
-                    then_label.clone(),
-                ));
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            -(args_size + 8),
+        ));
+        self.instructions
+            .push(Instruction::AddImm(Register::X5, Register::X10, skip));
+        self.instructions
+            .push(Instruction::Store(Register::X5, Register::X2, 0));
+        self.instructions
+            .push(Instruction::Add(Register::X6, Register::X10, Register::X11));
+        self.instructions
+            .push(Instruction::Store(Register::X6, Register::X2, 4));
 
-                // Else branch
+        let (cursor, end) = (Register::X5, Register::X7);
+        let mut slot = 8;
+        for arg in args {
+            self.instructions
+                .push(Instruction::Load(cursor, Register::X2, 0));
+            self.instructions
+                .push(Instruction::Load(end, Register::X2, 4));
+
+            let words = match arg {
+                AbiValue::Words(words) => *words as i32,
+                AbiValue::Bytes => {
+                    self.generate_decode_bytes_arg(slot, revert_label);
+                    slot += 4;
+                    continue;
+                }
+                AbiValue::Bool => {
+                    self.instructions
+                        .push(Instruction::BranchGeU(cursor, end, revert()));
+                    self.instructions
+                        .push(Instruction::LoadByteU(Register::X6, cursor, 0));
+                    self.instructions.push(Instruction::SetLessThanImmU(
+                        Register::X28,
+                        Register::X6,
+                        2,
+                    ));
+                    self.instructions.push(Instruction::BranchEq(
+                        Register::X28,
+                        Register::X0,
+                        revert(),
+                    ));
+                    self.instructions
+                        .push(Instruction::Store(Register::X6, Register::X2, slot));
+                    self.instructions
+                        .push(Instruction::AddImm(cursor, cursor, 1));
+                    self.instructions
+                        .push(Instruction::Store(cursor, Register::X2, 0));
+                    slot += 4;
+                    continue;
+                }
+            };
+
+            self.instructions
+                .push(Instruction::AddImm(Register::X6, cursor, words * 4));
+            self.instructions
+                .push(Instruction::BranchLtU(end, Register::X6, revert()));
+            for i in 0..words {
                 self.instructions
-                    .push(Instruction::Jump(else_label.clone()));
+                    .push(Instruction::Load(Register::X28, cursor, i * 4));
+                self.instructions.push(Instruction::Store(
+                    Register::X28,
+                    Register::X2,
+                    slot + i * 4,
+                ));
+            }
+            self.instructions
+                .push(Instruction::Store(Register::X6, Register::X2, 0));
+            slot += words * 4;
+        }
 
-                // Then branch
-                self.instructions.push(Instruction::Label(then_label));
-                let then_result = self.generate_block(then_branch)?;
-                self.instructions.push(Instruction::Jump(end_label.clone()));
+        self.instructions
+            .push(Instruction::Load(cursor, Register::X2, 0));
+        self.instructions
+            .push(Instruction::Load(end, Register::X2, 4));
+        self.instructions
+            .push(Instruction::BranchNe(cursor, end, revert()));
+        self.instructions
+            .push(Instruction::AddImm(Register::X2, Register::X2, 8));
+    }
 
-                // Else branch
-                self.instructions.push(Instruction::Label(else_label));
-                let _else_result = self.generate_block(else_branch)?;
+    /// Decode the `Bytes` argument at the cursor in X5, with the end of the
+    /// input in X7, into a heap block whose pointer is stored at `slot`
+    fn generate_decode_bytes_arg(&mut self, slot: i32, revert_label: &str) {
+        let (cursor, len, end, mode, prefix, byte) = (
+            Register::X5,
+            Register::X6,
+            Register::X7,
+            Register::X28,
+            Register::X29,
+            Register::X30,
+        );
+        let single = self.generate_label("decode_compact_single");
+        let double = self.generate_label("decode_compact_double");
+        let decoded = self.generate_label("decode_compact_done");
+        let revert = || revert_label.to_string();
 
-                // End of if
-                self.instructions.push(Instruction::Label(end_label));
+        self.instructions
+            .push(Instruction::BranchGeU(cursor, end, revert()));
+        self.instructions
+            .push(Instruction::LoadByteU(len, cursor, 0));
+        self.instructions.push(Instruction::AndImm(mode, len, 3));
+        self.instructions
+            .push(Instruction::BranchEq(mode, Register::X0, single.clone()));
+        self.instructions.push(Instruction::Li(prefix, 1));
+        self.instructions
+            .push(Instruction::BranchEq(mode, prefix, double.clone()));
+        // Lengths in the big-integer mode cannot fit in memory
+        self.instructions.push(Instruction::Li(prefix, 2));
+        self.instructions
+            .push(Instruction::BranchNe(mode, prefix, revert()));
 
-                // Result of the if statement is in then_result or else_result (depending on the branch taken)
-                // In a real compiler, we would need to merge the results
-                Ok(then_result)
-            }
-            Statement::Use { name, value, .. } => {
-                let val_reg = self.generate_expr(value)?;
+        // Four byte mode
+        self.instructions
+            .push(Instruction::AddImm(prefix, cursor, 4));
+        self.instructions
+            .push(Instruction::BranchLtU(end, prefix, revert()));
+        for i in 1..4 {
+            self.instructions
+                .push(Instruction::LoadByteU(byte, cursor, i));
+            self.instructions
+                .push(Instruction::ShiftLeftImm(byte, byte, i * 8));
+            self.instructions.push(Instruction::Or(len, len, byte));
+        }
+        self.instructions.push(Instruction::Li(prefix, 4));
+        self.instructions.push(Instruction::Jump(decoded.clone()));
 
-                // Get pre-assigned offset
-                let offset = self.current_local_offset;
-                self.current_local_offset += 4;
+        // Two byte mode
+        self.instructions.push(Instruction::Label(double));
+        self.instructions
+            .push(Instruction::AddImm(prefix, cursor, 2));
+        self.instructions
+            .push(Instruction::BranchLtU(end, prefix, revert()));
+        self.instructions
+            .push(Instruction::LoadByteU(byte, cursor, 1));
+        self.instructions
+            .push(Instruction::ShiftLeftImm(byte, byte, 8));
+        self.instructions.push(Instruction::Or(len, len, byte));
+        self.instructions.push(Instruction::Li(prefix, 2));
+        self.instructions.push(Instruction::Jump(decoded.clone()));
 
-                // Store to stack
-                self.instructions
-                    .push(Instruction::Store(val_reg, Register::X2, offset));
+        // Single byte mode
+        self.instructions.push(Instruction::Label(single));
+        self.instructions.push(Instruction::Li(prefix, 1));
 
-                // Register in locals map
-                self.locals.insert(name.clone(), offset);
+        self.instructions.push(Instruction::Label(decoded));
+        self.instructions
+            .push(Instruction::ShiftRightImm(len, len, 2));
+        self.instructions
+            .push(Instruction::Add(cursor, cursor, prefix));
+        self.instructions
+            .push(Instruction::Add(prefix, cursor, len));
+        self.instructions
+            .push(Instruction::BranchLtU(end, prefix, revert()));
+        self.instructions
+            .push(Instruction::Store(prefix, Register::X2, 0));
 
-                Ok(val_reg)
-            }
-            Statement::Expr { expr, .. } => self.generate_expr(expr),
-            // For brevity, not implementing all statement types
-            _ => Err(CodegenError::UnsupportedFeature(
-                "Statement type not yet implemented".to_string(),
-            )),
-        }
+        // Copy the data, keeping its address in the slot across the
+        // allocation
+        self.instructions
+            .push(Instruction::Store(cursor, Register::X2, slot));
+        self.generate_bytes_alloc(len);
+        self.instructions.push(Instruction::AddImm(
+            Register::X28,
+            Register::X10,
+            BYTES_HEADER_SIZE,
+        ));
+        self.instructions
+            .push(Instruction::Load(Register::X6, Register::X2, slot));
+        self.instructions
+            .push(Instruction::Load(Register::X7, Register::X10, 0));
+        self.generate_copy_bytes();
+        self.instructions
+            .push(Instruction::Store(Register::X10, Register::X2, slot));
     }
 
-    /// Generate code for an expression
-    fn generate_expr(&mut self, expr: &Expr) -> Result<Register, CodegenError> {
-        match expr {
-            Expr::Variable { name, .. } => {
-                // Load variable from stack frame or global storage
-                if let Some(&offset) = self.locals.get(name) {
-                    let reg = Register::X5; // Temporary register
+    /// Return the `Bytes` result in a0 SCALE-encoded: a compact length
+    /// prefix followed by the data
+    fn generate_dispatch_encode_bytes(&mut self) {
+        let (len, header) = (Register::X7, Register::X6);
+        let single = self.generate_label("encode_compact_single");
+        let double = self.generate_label("encode_compact_double");
+        let encoded = self.generate_label("encode_compact_done");
+
+        self.instructions
+            .push(Instruction::AddImm(Register::X2, Register::X2, -4));
+        self.instructions
+            .push(Instruction::Store(Register::X10, Register::X2, 0));
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X10, 0));
+        self.instructions.push(Instruction::AddImm(
+            Register::X10,
+            Register::X5,
+            BYTES_HEADER_SIZE,
+        ));
+        self.generate_alloc_dynamic();
+
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, 0));
+        self.instructions
+            .push(Instruction::Load(len, Register::X5, 0));
+        self.instructions
+            .push(Instruction::ShiftLeftImm(header, len, 2));
+        self.instructions.push(Instruction::Li(Register::X28, 0x40));
+        self.instructions
+            .push(Instruction::BranchLtU(len, Register::X28, single.clone()));
+        self.instructions
+            .push(Instruction::Li(Register::X28, 0x4000));
+        self.instructions
+            .push(Instruction::BranchLtU(len, Register::X28, double.clone()));
+
+        // Four byte mode
+        self.instructions
+            .push(Instruction::OrImm(header, header, 0b10));
+        self.instructions
+            .push(Instruction::Store(header, Register::X10, 0));
+        self.instructions.push(Instruction::Li(Register::X29, 4));
+        self.instructions.push(Instruction::Jump(encoded.clone()));
+
+        // Two byte mode
+        self.instructions.push(Instruction::Label(double));
+        self.instructions
+            .push(Instruction::OrImm(header, header, 0b01));
+        self.instructions
+            .push(Instruction::StoreByte(header, Register::X10, 0));
+        self.instructions
+            .push(Instruction::ShiftRightImm(header, header, 8));
+        self.instructions
+            .push(Instruction::StoreByte(header, Register::X10, 1));
+        self.instructions.push(Instruction::Li(Register::X29, 2));
+        self.instructions.push(Instruction::Jump(encoded.clone()));
+
+        // Single byte mode
+        self.instructions.push(Instruction::Label(single));
+        self.instructions
+            .push(Instruction::StoreByte(header, Register::X10, 0));
+        self.instructions.push(Instruction::Li(Register::X29, 1));
+
+        self.instructions.push(Instruction::Label(encoded));
+        self.instructions
+            .push(Instruction::Add(Register::X11, Register::X29, len));
+        self.instructions.push(Instruction::Add(
+            Register::X28,
+            Register::X10,
+            Register::X29,
+        ));
+        self.instructions.push(Instruction::AddImm(
+            Register::X6,
+            Register::X5,
+            BYTES_HEADER_SIZE,
+        ));
+        self.generate_copy_bytes();
+    }
+
+    /// Classify the type of a storage field
+    ///
+    /// Values, map keys, map entries and vector elements may be wide. A
+    /// `Map<K, V>` field is stored like a `StorageMap<K, V>`.
+    fn storage_kind(ty: &Type) -> Option<StorageKind> {
+        if Self::value_words(ty).is_some() {
+            return Some(StorageKind::Value);
+        }
+
+        match ty {
+            Type::Named { name, params, .. } => match (name.as_str(), params.as_slice()) {
+                ("StorageValue", [value]) if Self::value_words(value).is_some() => {
+                    Some(StorageKind::Value)
+                }
+                ("StorageMap" | "Map", [key, value])
+                    if Self::key_parts(key).is_some() && Self::value_words(value).is_some() =>
+                {
+                    Some(StorageKind::Map)
+                }
+                ("StorageVec", [element]) if Self::value_words(element).is_some() => {
+                    Some(StorageKind::Vec)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Type of the values held by a storage field
+    fn storage_value_type(ty: &Type) -> &Type {
+        match ty {
+            Type::Named { name, params, .. } if name.starts_with("Storage") || name == "Map" => {
+                params.last().unwrap_or(ty)
+            }
+            _ => ty,
+        }
+    }
+
+    /// Number of words of each part of a storage map key: of the key, or
+    /// of each element of a tuple key
+    fn key_parts(ty: &Type) -> Option<Vec<usize>> {
+        match ty {
+            Type::Tuple { elements, .. } => elements.iter().map(Self::value_words).collect(),
+            _ => Self::value_words(ty).map(|words| vec![words]),
+        }
+    }
+
+    /// Type of the keys of a storage map
+    fn storage_key_type(ty: &Type) -> Option<&Type> {
+        match ty {
+            Type::Named { name, params, .. } if matches!(name.as_str(), "StorageMap" | "Map") => {
+                params.first()
+            }
+            _ => None,
+        }
+    }
+
+    /// Checked integer type of a word-sized type
+    fn word_int(ty: &Type) -> Option<CheckedInt> {
+        match ty {
+            Type::U24 { .. } => Some(CheckedInt::U24),
+            Type::I24 { .. } => Some(CheckedInt::I24),
+            Type::Named { name, params, .. } if params.is_empty() => {
+                CheckedInt::from_type_name(name)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether values of a type occupy a single SCALE-encoded word
+    fn is_word_type(ty: &Type) -> bool {
+        match ty {
+            Type::U24 { .. } | Type::I24 { .. } | Type::F24 { .. } => true,
+            Type::Named { name, params, .. } => {
+                params.is_empty()
+                    && matches!(
+                        name.as_str(),
+                        "u24" | "i24" | "f24" | "u32" | "i32" | "Bool" | "bool"
+                    )
+            }
+            _ => false,
+        }
+    }
+
+    /// Number of words of a wide integer, `Address` or `Hash` type, or
+    /// `None` for other types
+    fn wide_words(ty: &Type) -> Option<usize> {
+        match ty {
+            Type::Named { name, params, .. } if params.is_empty() => match name.as_str() {
+                "Address" | "Hash" => Some(BYTES32_LEN / 4),
+                _ => wide_type_bits(name).map(|bits| bits as usize / 32),
+            },
+            _ => None,
+        }
+    }
+
+    /// Number of SCALE-encoded words of a word or wide integer type
+    fn value_words(ty: &Type) -> Option<usize> {
+        if Self::is_word_type(ty) {
+            Some(1)
+        } else {
+            Self::wide_words(ty)
+        }
+    }
+
+    /// Whether a type is `Bool`, held as a word that is 0 or 1
+    fn is_bool_type(ty: &Type) -> bool {
+        matches!(ty, Type::Named { name, params, .. } if matches!(name.as_str(), "Bool" | "bool") && params.is_empty())
+    }
+
+    /// Whether a type is `Bytes`, held as a pointer to its length and data
+    fn is_bytes_type(ty: &Type) -> bool {
+        matches!(ty, Type::Named { name, params, .. } if name == "Bytes" && params.is_empty())
+    }
+
+    /// The `Ok` type of a `Result` type
+    fn result_value_type(ty: &Type) -> Option<&Type> {
+        match ty {
+            Type::Named { name, params, .. } if name == "Result" && params.len() == 2 => {
+                params.first()
+            }
+            _ => None,
+        }
+    }
+
+    /// Encoding of a message argument or result of type `ty`
+    fn abi_value(ty: &Type) -> Option<AbiValue> {
+        if Self::is_bytes_type(ty) {
+            Some(AbiValue::Bytes)
+        } else if Self::is_bool_type(ty) {
+            Some(AbiValue::Bool)
+        } else {
+            Self::value_words(ty).map(AbiValue::Words)
+        }
+    }
+
+    /// Generate a unique label
+    fn generate_label(&mut self, prefix: &str) -> String {
+        let label = format!("{}.{}", prefix, self.next_label_id);
+        self.next_label_id += 1;
+        label
+    }
+
+    /// Generate a function label
+    fn generate_function_label(&mut self, name: &str) -> String {
+        Self::function_label(name)
+    }
+
+    /// The label the code of the function `name` starts at
+    pub fn function_label(name: &str) -> String {
+        if name == "main" {
+            "main".to_string()
+        } else {
+            format!("function.{}", name.replace('/', "_"))
+        }
+    }
+
+    /// Helper to count local variables
+    fn collect_locals(&self, block: &Block) -> usize {
+        let mut count = 0;
+        for stmt in &block.statements {
+            match stmt {
+                Statement::Use { .. }
+                | Statement::Assignment {
+                    pattern: Pattern::Variable { .. },
+                    ..
+                } => count += 1,
+                Statement::If {
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    count += self.collect_locals(then_branch);
+                    count += self.collect_locals(else_branch);
+                }
+                // The value matched, and the fields a case binds
+                Statement::Match { cases, .. } => {
+                    count += 1;
+                    for case in cases {
+                        count += Self::pattern_fields(&case.pattern);
+                        count += self.collect_locals(&case.body);
+                    }
+                }
+                Statement::Switch { cases, .. } => {
+                    for case in cases {
+                        count += self.collect_locals(&case.body);
+                    }
+                }
+                Statement::Fold { cases, .. } => {
+                    for case in cases {
+                        count += self.collect_locals(&case.body);
+                    }
+                }
+                Statement::Bend {
+                    body, else_body, ..
+                } => {
+                    count += self.collect_locals(body);
+                    if let Some(else_b) = else_body {
+                        count += self.collect_locals(else_b);
+                    }
+                }
+                Statement::LocalDef { function_def, .. } => {
+                    // If we support local functions, we might need to recurse if they capture?
+                    // For now, ignore.
+                    if let Definition::FunctionDef { .. } = function_def.as_ref() {
+                        // But local functions are separate frames?
+                        // Yes, usually. So don't count their locals here.
+                    }
+                }
+                Statement::With { body, .. } | Statement::Unchecked { body, .. } => {
+                    count += self.collect_locals(body);
+                }
+                // The iteration counter, plus the variable and end of a range
+                Statement::While { body, .. } => count += 1 + self.collect_locals(body),
+                Statement::For { body, .. } => count += 3 + self.collect_locals(body),
+                Statement::Emit { event, .. } => {
+                    // Slots for the topic and data buffers passed to the host
+                    if let Some((fields, _)) = self.events.get(event) {
+                        let indexed = fields.iter().filter(|field| field.indexed).count();
+                        let data: usize = fields
+                            .iter()
+                            .filter(|field| !field.indexed)
+                            .map(|field| Self::wide_words(&field.ty).unwrap_or(1))
+                            .sum();
+                        count += (1 + indexed) * 8 + data;
+                    }
+                }
+                _ => {}
+            }
+        }
+        count
+    }
+
+    /// Collect the wide integer locals of a block with their number of words
+    ///
+    /// A local is wide when the value first bound to it is.
+    fn collect_wide_locals(&mut self, block: &Block, wide_locals: &mut Vec<(String, usize)>) {
+        for stmt in &block.statements {
+            match stmt {
+                Statement::Use { name, value, .. }
+                | Statement::Assignment {
+                    pattern: Pattern::Variable { name, .. },
+                    value,
+                    ..
+                } => {
+                    if self.local_widths.contains_key(name)
+                        || (self.storage.contains_key(name) && !self.locals.contains_key(name))
+                    {
+                        continue;
+                    }
+                    let words = self.expr_width(value);
+                    if words > 1 {
+                        self.local_widths.insert(name.into(), words);
+                        wide_locals.push((name.to_string(), words));
+                    }
+                }
+                Statement::If {
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    self.collect_wide_locals(then_branch, wide_locals);
+                    self.collect_wide_locals(else_branch, wide_locals);
+                }
+                Statement::Unchecked { body, .. }
+                | Statement::While { body, .. }
+                | Statement::For { body, .. } => {
+                    self.collect_wide_locals(body, wide_locals);
+                }
+                Statement::Match { cases, .. } => {
+                    for case in cases {
+                        self.collect_wide_locals(&case.body, wide_locals);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Generate code for a function
+    fn generate_function(
+        &mut self,
+        name: Symbol,
+        params: &[Parameter],
+        body: &Block,
+    ) -> Result<(), CodegenError> {
+        // Reset local variables and frame size
+        self.locals.clear();
+        self.local_widths.clear();
+        self.local_kinds.clear();
+        self.bytes_locals.clear();
+        self.function_args.clear();
+        self.current_local_offset = 0;
+        self.return_words = self.function_widths.get(&name).copied().unwrap_or(1);
+
+        // Calculate frame size
+        // Locals: collect_locals(body) * 4
+        // Params: passed by caller, but we map them to stack offsets if needed?
+        // Wait, current logic maps params to [8, 12, ...].
+        // The implementation assumes params are ALREADY on the stack above the return address.
+        // Or it assumes we need to spill them?
+        // "Allocate space for local variables and function arguments" - the comment said.
+        // But usually arguments are in registers a0-a7.
+        // The previous code seemed to assume we put them on stack?
+        // But it didn't generate store instructions for them!
+        // It just inserted them into self.locals!
+        // This implies the compiler expects params to BE at those offsets.
+        // This is only true if the CALLER put them there.
+        // Or if we spill them in prologue.
+        // Since `generate_function` prologue didn't spill a0-a7,
+        // AND `Expr::Variable` loads from `offset(SP)`,
+        // The only logical conclusion is that the calling convention used by this simple compiler
+        // expects arguments to be passed on the stack.
+
+        // Let's preserve this behavior.
+        // Arguments are at `CallerSP + 0`, `CallerSP + 4`...
+        // `SP = CallerSP - 8` (in original). So `args at SP + 8`.
+
+        for param in params {
+            if let Some(words) = Self::wide_words(&param.ty) {
+                self.local_widths.insert(param.name, words);
+            }
+            if let Some(kind) = Self::word_int(&param.ty) {
+                self.local_kinds.insert(param.name, kind);
+            }
+            if Self::is_bytes_type(&param.ty) {
+                self.bytes_locals.insert(param.name);
+            }
+            if matches!(param.ty, Type::Function { .. }) {
+                self.function_args.insert(param.name);
+            }
+        }
+
+        // Wide integer locals get their words after the word-sized locals
+        let mut wide_locals = Vec::new();
+        self.collect_wide_locals(body, &mut wide_locals);
+        let mut wide_offset = (self.collect_locals(body) * 4) as i32;
+        for (local, words) in wide_locals {
+            self.locals.insert(local.into(), wide_offset);
+            wide_offset += (words * 4) as i32;
+        }
+
+        // The return address slot is aligned to its size
+        let ra_size = self.isa.word_size();
+        let locals_size = (wide_offset + ra_size - 1) / ra_size * ra_size;
+        let total_frame_size = locals_size + 8; // RA + alignment/padding + locals
+
+        // Function label
+        let function_label = self.function_labels.get(&name).unwrap().clone();
+        self.return_label = format!("{}.return", function_label);
+        self.instructions.push(Instruction::Label(function_label));
+
+        // Function prologue: save return address
+        self.instructions.push(Instruction::Comment(format!(
+            "Function prologue for {}",
+            name
+        )));
+
+        // Allocate stack frame
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            -total_frame_size,
+        ));
+
+        // Save RA at `locals_size` (just below caller args)
+        self.instructions
+            .push(self.save_return_address(locals_size));
+
+        // Map params (Caller args start at `total_frame_size + 8` relative to new SP??)
+        // Original: `AddImm -8`. Args at `8`.
+        // `CallerSP = NewSP + 8`.
+        // Args at `CallerSP + 0` = `NewSP + 8`.
+
+        // New: `AddImm -total`.
+        // `CallerSP = NewSP + total`.
+        // Args at `CallerSP + 0` = `NewSP + total`.
+
+        // Actually, if original args were at `0(CallerSP)`, `4(CallerSP)`...
+        // Then yes, offset is `total_frame_size`.
+
+        // But wait, the original code used `offset = 8`.
+        // `frame_size` (which was `offset`) = 8 + params*4.
+
+        // If I change the stack layout, I must ensure `Expr::Variable` uses correct offsets.
+
+        let mut offset = total_frame_size;
+        for param in params {
+            self.locals.insert(param.name, offset);
+            offset += (self.local_width(param.name) * 4) as i32;
+        }
+
+        self.frame_size = total_frame_size; // Maybe unused, but keep it correct
+
+        // Generate code for the function body
+        self.generate_block(body)?;
+
+        // Function epilogue: restore return address and return
+        self.instructions
+            .push(Instruction::Label(self.return_label.clone()));
+        self.instructions.push(Instruction::Comment(format!(
+            "Function epilogue for {}",
+            name
+        )));
+        self.instructions
+            .push(self.restore_return_address(locals_size));
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            total_frame_size,
+        ));
+
+        // Return from function
+        self.instructions
+            .push(Instruction::JumpAndLinkReg(Register::X0, Register::X1, 0)); // Return
+
+        Ok(())
+    }
+
+    /// Store `ra` at `offset` from the stack pointer, whole on 64-bit ISAs
+    fn save_return_address(&self, offset: i32) -> Instruction {
+        if self.isa.is_64_bit() {
+            Instruction::Store64(Register::X1, Register::X2, offset)
+        } else {
+            Instruction::Store(Register::X1, Register::X2, offset)
+        }
+    }
+
+    /// Load `ra` back from `offset` from the stack pointer
+    fn restore_return_address(&self, offset: i32) -> Instruction {
+        if self.isa.is_64_bit() {
+            Instruction::Load64(Register::X1, Register::X2, offset)
+        } else {
+            Instruction::Load(Register::X1, Register::X2, offset)
+        }
+    }
+
+    /// Generate code for a block
+    fn generate_block(&mut self, block: &Block) -> Result<Register, CodegenError> {
+        let mut result_reg = Register::X0;
+
+        for statement in &block.statements {
+            result_reg = self.generate_statement(statement)?;
+        }
+
+        Ok(result_reg)
+    }
+
+    /// Generate code for a statement
+    fn generate_statement(&mut self, statement: &Statement) -> Result<Register, CodegenError> {
+        match statement {
+            Statement::Return { value, .. } if self.return_words > 1 => {
+                // Wide results are returned in a0..
+                let words = self.return_words;
+                self.generate_wide_expr(value, words)?;
+                for (i, reg) in Register::arg_registers()
+                    .into_iter()
+                    .take(words)
+                    .enumerate()
+                {
+                    self.instructions
+                        .push(Instruction::Load(reg, Register::X2, (i * 4) as i32));
+                }
+                self.pop_wide(words);
+                self.instructions
+                    .push(Instruction::Jump(self.return_label.clone()));
+                Ok(Register::X10)
+            }
+            Statement::Return { value, .. } => {
+                let result_reg = self.generate_expr(value)?;
+                self.instructions
+                    .push(Instruction::Mv(Register::X10, result_reg)); // Move result to a0 (return value)
+                self.instructions
+                    .push(Instruction::Jump(self.return_label.clone()));
+                Ok(Register::X10)
+            }
+            Statement::Assignment {
+                pattern: Pattern::Variable { name, .. },
+                value,
+                ..
+            } if self.variable_width(*name) > 1 => {
+                self.generate_wide_assignment(*name, value)?;
+                Ok(Register::X0)
+            }
+            Statement::Assignment {
+                pattern: Pattern::MapAccess { map, key, location },
+                value,
+                ..
+            } => {
+                if let Some(call) = self.storage_map_call(
+                    map,
+                    "insert",
+                    vec![(**key).clone(), value.clone()],
+                    location,
+                ) {
+                    return self.generate_expr(&call);
+                }
+                self.generate_map_insert(map, key, value)?;
+                Ok(Register::X0)
+            }
+            Statement::Assignment { pattern, value, .. } => {
+                if let Pattern::Variable { name, .. } = pattern {
+                    self.record_local_kind(*name, value);
+                }
+                let value_reg = self.generate_expr(value)?;
+                self.generate_assignment(pattern, value_reg)?;
+                Ok(value_reg)
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let condition_reg = self.generate_expr(condition)?;
+
+                let then_label = self.generate_label("if_then");
+                let else_label = self.generate_label("if_else");
+                let end_label = self.generate_label("if_end");
+
+                // Branch to then_label if condition is true (non-zero)
+                self.instructions.push(Instruction::BranchNe(
+                    condition_reg,
+                    Register::X0,
+                    then_label.clone(),
+                ));
+
+                // Else branch
+                self.instructions
+                    .push(Instruction::Jump(else_label.clone()));
+
+                // Then branch
+                self.instructions.push(Instruction::Label(then_label));
+                let then_result = self.generate_block(then_branch)?;
+                self.instructions.push(Instruction::Jump(end_label.clone()));
+
+                // Else branch
+                self.instructions.push(Instruction::Label(else_label));
+                let _else_result = self.generate_block(else_branch)?;
+
+                // End of if
+                self.instructions.push(Instruction::Label(end_label));
+
+                // Result of the if statement is in then_result or else_result (depending on the branch taken)
+                // In a real compiler, we would need to merge the results
+                Ok(then_result)
+            }
+            Statement::Use { name, value, .. } if self.local_width(*name) > 1 => {
+                self.generate_wide_assignment(*name, value)?;
+                Ok(Register::X0)
+            }
+            Statement::Use { name, value, .. } => {
+                self.record_local_kind(*name, value);
+                let val_reg = self.generate_expr(value)?;
+
+                // Get pre-assigned offset
+                let offset = self.current_local_offset;
+                self.current_local_offset += 4;
+
+                // Store to stack
+                self.instructions
+                    .push(Instruction::Store(val_reg, Register::X2, offset));
+
+                // Register in locals map
+                self.locals.insert(name.into(), offset);
+
+                Ok(val_reg)
+            }
+            Statement::Expr { expr, .. } if self.expr_width(expr) > 1 => {
+                let words = self.expr_width(expr);
+                self.generate_wide_expr(expr, words)?;
+                self.pop_wide(words);
+                Ok(Register::X0)
+            }
+            Statement::Expr { expr, .. } => self.generate_expr(expr),
+            Statement::Unchecked { body, .. } => {
+                let checked = std::mem::replace(&mut self.checked, false);
+                let result = self.generate_block(body);
+                self.checked = checked;
+                result
+            }
+            Statement::Emit { event, args, .. } => self.generate_emit(*event, args),
+            Statement::While {
+                condition,
+                body,
+                bound,
+                ..
+            } => self.generate_while(condition, body, *bound),
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                bound,
+                ..
+            } => self.generate_for(*variable, start, end, body, *bound),
+            Statement::Revert {
+                kind,
+                condition,
+                reason,
+                ..
+            } => self.generate_revert_statement(*kind, condition.as_ref(), reason.as_ref()),
+            Statement::Match { value, cases, .. } => self.generate_match(value, cases),
+            Statement::Fold { location, .. } => Err(unsupported("Pattern matching is", location)),
+            // For brevity, not implementing all statement types
+            _ => Err(unsupported("This statement is", statement.location())),
+        }
+    }
+
+    /// Generate code for an emit statement
+    ///
+    /// Topics and data are laid out in the stack frame and passed to the
+    /// deposit event (`Log`) host call. The first topic is the keccak256 hash of
+    /// the event signature, followed by one 32-byte topic per indexed field
+    /// holding the little-endian value zero-padded on the right. Non-indexed
+    /// fields are concatenated as 4-byte little-endian words in the data.
+    fn generate_emit(&mut self, event: Symbol, args: &[Expr]) -> Result<Register, CodegenError> {
+        let (fields, topic) = self
+            .events
+            .get(&event)
+            .cloned()
+            .ok_or_else(|| CodegenError::UndefinedEvent(event.to_string()))?;
+
+        if fields.len() != args.len() {
+            return Err(CodegenError::InvalidOperation(format!(
+                "Event {} expects {} arguments, found {}",
+                event,
+                fields.len(),
+                args.len()
+            )));
+        }
+
+        let indexed = fields.iter().filter(|field| field.indexed).count() as i32;
+        let topics_offset = self.current_local_offset;
+        let data_offset = topics_offset + 32 * (1 + indexed);
+        let data_len: i32 = fields
+            .iter()
+            .filter(|field| !field.indexed)
+            .map(|field| 4 * Self::wide_words(&field.ty).unwrap_or(1) as i32)
+            .sum();
+        self.current_local_offset = data_offset + data_len;
+
+        self.instructions
+            .push(Instruction::Comment(format!("emit {}", event)));
+
+        // Signature topic
+        for (i, word) in topic.chunks(4).enumerate() {
+            let word = i32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            self.instructions.push(Instruction::Li(Register::X5, word));
+            self.instructions.push(Instruction::Store(
+                Register::X5,
+                Register::X2,
+                topics_offset + 4 * i as i32,
+            ));
+        }
+
+        let (mut topic_offset, mut field_offset) = (topics_offset + 32, data_offset);
+        for (field, arg) in fields.iter().zip(args) {
+            if field.indexed {
+                // A word is zero-extended to the topic, an `Address` or
+                // `Hash` fills it
+                self.generate_account_arg(arg, topic_offset)?;
+                topic_offset += 32;
+            } else if let Some(words) = Self::wide_words(&field.ty) {
+                // A wide value takes as many words as its type
+                self.generate_wide_expr(arg, words)?;
+                self.generate_copy_words(0, field_offset + 4 * words as i32, words);
+                self.pop_wide(words);
+                field_offset += 4 * words as i32;
+            } else {
+                let value_reg = self.generate_expr(arg)?;
+                self.instructions
+                    .push(Instruction::Store(value_reg, Register::X2, field_offset));
+                field_offset += 4;
+            }
+        }
+
+        self.instructions.push(Instruction::AddImm(
+            Register::X10,
+            Register::X2,
+            topics_offset,
+        ));
+        self.instructions
+            .push(Instruction::Li(Register::X11, 1 + indexed));
+        self.instructions.push(Instruction::AddImm(
+            Register::X12,
+            Register::X2,
+            data_offset,
+        ));
+        self.instructions
+            .push(Instruction::Li(Register::X13, data_len));
+        self.instructions
+            .push(Instruction::Li(Register::X17, HostFunction::Log as i32));
+        self.instructions.push(Instruction::Ecall);
+
+        Ok(Register::X0)
+    }
+
+    /// Generate a `while` loop
+    fn generate_while(
+        &mut self,
+        condition: &Expr,
+        body: &Block,
+        bound: Option<u32>,
+    ) -> Result<Register, CodegenError> {
+        let start_label = self.generate_label("while_start");
+        let end_label = self.generate_label("while_end");
+        let counter = self.generate_loop_counter(bound);
+
+        self.instructions
+            .push(Instruction::Label(start_label.clone()));
+        let condition_reg = self.generate_expr(condition)?;
+        self.instructions.push(Instruction::BranchEq(
+            condition_reg,
+            Register::X0,
+            end_label.clone(),
+        ));
+        self.generate_loop_iteration(counter, bound);
+        self.generate_block(body)?;
+        self.instructions.push(Instruction::Jump(start_label));
+        self.instructions.push(Instruction::Label(end_label));
+
+        Ok(Register::X0)
+    }
+
+    /// Generate a `for i in range(start, end)` loop
+    ///
+    /// The end is evaluated once, before the first iteration.
+    fn generate_for(
+        &mut self,
+        variable: Symbol,
+        start: &Expr,
+        end: &Expr,
+        body: &Block,
+        bound: Option<u32>,
+    ) -> Result<Register, CodegenError> {
+        let signed = self
+            .expr_kind(start)
+            .combine(self.expr_kind(end))
+            .is_signed();
+
+        self.record_local_kind(variable, start);
+        let start_reg = self.generate_expr(start)?;
+        let pattern = Pattern::Variable {
+            name: variable,
+            location: start.location().clone(),
+        };
+        self.generate_assignment(&pattern, start_reg)?;
+        let variable_offset = self.locals[&variable];
+
+        let end_offset = self.current_local_offset;
+        self.current_local_offset += 4;
+        let end_reg = self.generate_expr(end)?;
+        self.instructions
+            .push(Instruction::Store(end_reg, Register::X2, end_offset));
+
+        let start_label = self.generate_label("for_start");
+        let end_label = self.generate_label("for_end");
+        let counter = self.generate_loop_counter(bound);
+
+        self.instructions
+            .push(Instruction::Label(start_label.clone()));
+        self.instructions.push(Instruction::Load(
+            Register::X5,
+            Register::X2,
+            variable_offset,
+        ));
+        self.instructions
+            .push(Instruction::Load(Register::X6, Register::X2, end_offset));
+        self.instructions.push(if signed {
+            Instruction::BranchGe(Register::X5, Register::X6, end_label.clone())
+        } else {
+            Instruction::BranchGeU(Register::X5, Register::X6, end_label.clone())
+        });
+        self.generate_loop_iteration(counter, bound);
+        self.generate_block(body)?;
+
+        self.instructions.push(Instruction::Load(
+            Register::X5,
+            Register::X2,
+            variable_offset,
+        ));
+        self.instructions
+            .push(Instruction::AddImm(Register::X5, Register::X5, 1));
+        self.instructions.push(Instruction::Store(
+            Register::X5,
+            Register::X2,
+            variable_offset,
+        ));
+        self.instructions.push(Instruction::Jump(start_label));
+        self.instructions.push(Instruction::Label(end_label));
+
+        Ok(Register::X0)
+    }
+
+    /// Allocate and clear the iteration counter of a loop, returning its
+    /// offset
+    fn generate_loop_counter(&mut self, bound: Option<u32>) -> i32 {
+        let offset = self.current_local_offset;
+        self.current_local_offset += 4;
+        if bound.is_some() {
+            self.instructions
+                .push(Instruction::Store(Register::X0, Register::X2, offset));
+        }
+        offset
+    }
+
+    /// Start a loop iteration
+    ///
+    /// Every iteration charges `LOOP_ITERATION_GAS`, so a long-running loop
+    /// runs out of gas instead of the block. A bounded loop panics once it
+    /// would run more iterations than its bound.
+    fn generate_loop_iteration(&mut self, counter: i32, bound: Option<u32>) {
+        self.instructions
+            .push(Instruction::Li(Register::X10, LOOP_ITERATION_GAS as i32));
+        self.instructions.push(Instruction::Li(
+            Register::X17,
+            HostFunction::ChargeGas as i32,
+        ));
+        self.instructions.push(Instruction::Ecall);
+
+        if let Some(bound) = bound {
+            let within = self.generate_label("loop_within_bound");
+            self.instructions
+                .push(Instruction::Load(Register::X5, Register::X2, counter));
+            self.instructions
+                .push(Instruction::AddImm(Register::X5, Register::X5, 1));
+            self.instructions
+                .push(Instruction::Store(Register::X5, Register::X2, counter));
+            self.instructions
+                .push(Instruction::Li(Register::X6, bound as i32));
+            self.instructions.push(Instruction::BranchGeU(
+                Register::X6,
+                Register::X5,
+                within.clone(),
+            ));
+            self.generate_message_revert(PANIC_SIGNATURE, "loop bound exceeded");
+            self.instructions.push(Instruction::Label(within));
+        }
+    }
+
+    /// Generate `assert`, `require` or `revert`
+    ///
+    /// Unless the condition holds, the call reverts through the `Revert`
+    /// host call, which rolls back its state changes. The revert data is the
+    /// 4-byte selector of the error signature followed by the SCALE-encoded
+    /// fields: a message for `Error(String)` and `Panic(String)`, or the
+    /// arguments of an error variant. `require` and `revert` without a
+    /// reason revert with empty data.
+    fn generate_revert_statement(
+        &mut self,
+        kind: RevertKind,
+        condition: Option<&Expr>,
+        reason: Option<&Expr>,
+    ) -> Result<Register, CodegenError> {
+        let passed = self.generate_label("revert_passed");
+        if let Some(condition) = condition {
+            let reg = self.generate_expr(condition)?;
+            self.instructions
+                .push(Instruction::BranchNe(reg, Register::X0, passed.clone()));
+        }
+
+        match reason {
+            Some(Expr::Literal {
+                kind: LiteralKind::String(message),
+                ..
+            }) => {
+                let signature = match kind {
+                    RevertKind::Assert => PANIC_SIGNATURE,
+                    _ => ERROR_SIGNATURE,
+                };
+                self.generate_message_revert(signature, message);
+            }
+            Some(reason) => {
+                let (name, args) = reason.as_variant_constructor().ok_or_else(|| {
+                    CodegenError::InvalidOperation(
+                        "A revert reason must be a string or an error variant".to_string(),
+                    )
+                })?;
+                self.generate_error_revert(name, args)?;
+            }
+            None if kind == RevertKind::Assert => {
+                self.generate_message_revert(PANIC_SIGNATURE, "assertion failed");
+            }
+            None => self.generate_revert(),
+        }
+
+        self.instructions.push(Instruction::Label(passed));
+        Ok(Register::X0)
+    }
+
+    /// Revert with a constant message encoded as `signature`
+    fn generate_message_revert(&mut self, signature: &str, message: &str) {
+        let mut data = selector_for(signature).to_vec();
+        data.extend(encode_compact(message.len() as u32));
+        data.extend_from_slice(message.as_bytes());
+
+        let words = data.len().div_ceil(4);
+        self.push_wide(words);
+        for (i, chunk) in data.chunks(4).enumerate() {
+            let mut word = [0u8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.instructions
+                .push(Instruction::Li(Register::X5, i32::from_le_bytes(word)));
+            self.instructions.push(Instruction::Store(
+                Register::X5,
+                Register::X2,
+                (i * 4) as i32,
+            ));
+        }
+        self.generate_revert_with(data.len() as i32);
+        self.pop_wide(words);
+    }
+
+    /// Revert with an error variant, e.g. `Error/InsufficientBalance(a, b)`
+    fn generate_error_revert(&mut self, name: Symbol, args: &[Expr]) -> Result<(), CodegenError> {
+        self.generate_error_value(name, args)?;
+        self.generate_revert_error_value();
+        Ok(())
+    }
+
+    /// Revert the call with the data of the error value in X5
+    fn generate_revert_error_value(&mut self) {
+        self.instructions.push(Instruction::AddImm(
+            Register::X10,
+            Register::X5,
+            BYTES_HEADER_SIZE,
+        ));
+        self.instructions
+            .push(Instruction::Load(Register::X11, Register::X5, 0));
+        self.instructions
+            .push(Instruction::Li(Register::X17, HostFunction::Revert as i32));
+        self.instructions.push(Instruction::Ecall);
+    }
+
+    /// Generate the value of an error variant into X5
+    ///
+    /// An error value has the layout of `Bytes` holding its revert data: the
+    /// selector of the variant followed by its fields, word-sized ones as 4
+    /// little-endian bytes and `Bool` ones as a single byte.
+    fn generate_error_value(
+        &mut self,
+        name: Symbol,
+        args: &[Expr],
+    ) -> Result<Register, CodegenError> {
+        let (fields, selector) = self
+            .errors
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| CodegenError::UndefinedVariable(name.to_string()))?;
+
+        if fields.len() != args.len() {
+            return Err(CodegenError::InvalidOperation(format!(
+                "Error {} expects {} arguments, found {}",
+                name,
+                fields.len(),
+                args.len()
+            )));
+        }
+
+        let mut sizes = Vec::new();
+        for field in &fields {
+            sizes.push(match Self::abi_value(field) {
+                Some(AbiValue::Words(1)) => 4,
+                Some(AbiValue::Bool) => 1,
+                _ => {
+                    return Err(CodegenError::UnsupportedFeature(format!(
+                        "Field of error {} must be word-sized or a Bool",
+                        name
+                    )))
+                }
+            });
+        }
+
+        let size = 4 + sizes.iter().sum::<i32>();
+        self.generate_alloc(BYTES_HEADER_SIZE + (size as usize).div_ceil(4) as i32 * 4);
+        self.instructions.push(Instruction::Li(Register::X5, size));
+        self.instructions
+            .push(Instruction::Store(Register::X5, Register::X10, 0));
+        self.instructions
+            .push(Instruction::Li(Register::X5, i32::from_le_bytes(selector)));
+        self.instructions.push(Instruction::Store(
+            Register::X5,
+            Register::X10,
+            BYTES_HEADER_SIZE,
+        ));
+
+        // Keep the value on the stack while the fields are evaluated
+        self.push_wide(1);
+        self.instructions
+            .push(Instruction::Store(Register::X10, Register::X2, 0));
+
+        let mut offset = BYTES_HEADER_SIZE + 4;
+        for (arg, size) in args.iter().zip(sizes) {
+            let reg = self.generate_expr(arg)?;
+            self.instructions.push(Instruction::Mv(Register::X5, reg));
+            self.instructions
+                .push(Instruction::Load(Register::X7, Register::X2, 0));
+            // Fields after a `Bool` are unaligned, so store byte by byte
+            for byte in 0..size {
+                if byte > 0 {
+                    self.instructions.push(Instruction::ShiftRightImm(
+                        Register::X6,
+                        Register::X5,
+                        byte * 8,
+                    ));
+                }
+                let value = if byte > 0 { Register::X6 } else { Register::X5 };
+                self.instructions
+                    .push(Instruction::StoreByte(value, Register::X7, offset + byte));
+            }
+            offset += size;
+        }
+
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, 0));
+        self.pop_wide(1);
+        Ok(Register::X5)
+    }
+
+    /// Whether `name` is a variant of a type declared with `error`
+    fn is_error_value(&self, name: Symbol) -> bool {
+        name.rsplit_once('/')
+            .is_some_and(|(type_name, _)| self.error_types.contains(&Symbol::from(type_name)))
+            && self.errors.contains_key(&name)
+    }
+
+    /// Generate `Result/Ok(value)` or `Result/Err(error)` into X5
+    ///
+    /// A `Result` is a pointer to a tag word, 0 for `Ok` and 1 for `Err`,
+    /// followed by the word-sized value or the error value.
+    fn generate_result(&mut self, variant: &str, args: &[Expr]) -> Result<Register, CodegenError> {
+        let tag = match (variant, args) {
+            ("Result/Ok", [_]) => 0,
+            ("Result/Err", [_]) => 1,
+            _ => {
+                return Err(CodegenError::InvalidOperation(format!(
+                    "{} expects 1 argument, found {}",
+                    variant,
+                    args.len()
+                )))
+            }
+        };
+
+        self.generate_tagged(tag, args)
+    }
+
+    /// Generate the variant `name` of a type declared with `type` into X5
+    ///
+    /// A value of such a type is a pointer to a tag word, the index of its
+    /// variant, followed by a word for each field, the layout `Result`
+    /// values have too.
+    fn generate_variant(&mut self, name: Symbol, args: &[Expr]) -> Result<Register, CodegenError> {
+        let (tag, fields) = &self.variants[&name];
+        if args.len() != fields.len() {
+            return Err(CodegenError::InvalidOperation(format!(
+                "{} expects {} arguments, found {}",
+                name,
+                fields.len(),
+                args.len()
+            )));
+        }
+        self.generate_tagged(*tag, args)
+    }
+
+    /// Allocate a tag word followed by the word of each of `args`, returning
+    /// a pointer to it in X5
+    fn generate_tagged(&mut self, tag: i32, args: &[Expr]) -> Result<Register, CodegenError> {
+        let words = args.len();
+        if words > 0 {
+            self.push_wide(words);
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let reg = self.generate_expr(arg)?;
+            self.instructions
+                .push(Instruction::Store(reg, Register::X2, (i * 4) as i32));
+        }
+        self.generate_alloc(((words + 1) * 4) as i32);
+        self.instructions.push(Instruction::Li(Register::X6, tag));
+        self.instructions
+            .push(Instruction::Store(Register::X6, Register::X10, 0));
+        for i in 0..words {
+            self.instructions.push(Instruction::Load(
+                Register::X5,
+                Register::X2,
+                (i * 4) as i32,
+            ));
+            self.instructions.push(Instruction::Store(
+                Register::X5,
+                Register::X10,
+                ((i + 1) * 4) as i32,
+            ));
+        }
+        if words > 0 {
+            self.pop_wide(words);
+        }
+        self.instructions
+            .push(Instruction::Mv(Register::X5, Register::X10));
+        Ok(Register::X5)
+    }
+
+    /// Generate a `match` on a value of a type declared with `type`
+    ///
+    /// The cases are tried in order. A case for a variant compares the tag
+    /// of the value and binds the fields it names to locals, a variable
+    /// binds the whole value and `_` matches anything. Patterns nested in
+    /// the fields are not supported.
+    fn generate_match(
+        &mut self,
+        value: &Expr,
+        cases: &[MatchCase],
+    ) -> Result<Register, CodegenError> {
+        let reg = self.generate_expr(value)?;
+        let slot = self.current_local_offset;
+        self.current_local_offset += 4;
+        self.instructions
+            .push(Instruction::Store(reg, Register::X2, slot));
+
+        let end = self.generate_label("match_end");
+        for case in cases {
+            let next = self.generate_label("match_next");
+            match &case.pattern {
+                Pattern::Variable { name, .. }
+                | Pattern::TupleConstructor { name, .. }
+                | Pattern::Constructor { name, .. }
+                    if self.variants.contains_key(name) =>
+                {
+                    let (tag, fields) = self.variants[name].clone();
+                    self.instructions
+                        .push(Instruction::Load(Register::X5, Register::X2, slot));
+                    self.instructions
+                        .push(Instruction::Load(Register::X5, Register::X5, 0));
+                    self.instructions.push(Instruction::Li(Register::X6, tag));
+                    self.instructions.push(Instruction::BranchNe(
+                        Register::X5,
+                        Register::X6,
+                        next.clone(),
+                    ));
+                    for (binding, index) in Self::field_bindings(&case.pattern, *name, &fields)? {
+                        let offset = self.current_local_offset;
+                        self.current_local_offset += 4;
+                        self.instructions
+                            .push(Instruction::Load(Register::X5, Register::X2, slot));
+                        self.instructions.push(Instruction::Load(
+                            Register::X5,
+                            Register::X5,
+                            ((index + 1) * 4) as i32,
+                        ));
+                        self.instructions.push(Instruction::Store(
+                            Register::X5,
+                            Register::X2,
+                            offset,
+                        ));
+                        self.locals.insert(binding, offset);
+                    }
+                }
+                Pattern::Variable { name, .. } => {
+                    self.locals.insert(*name, slot);
+                }
+                Pattern::Wildcard { .. } => {}
+                pattern => return Err(unsupported("This pattern is", pattern.location())),
+            }
+            self.generate_block(&case.body)?;
+            self.instructions.push(Instruction::Jump(end.clone()));
+            self.instructions.push(Instruction::Label(next));
+        }
+        self.instructions.push(Instruction::Label(end));
+        Ok(Register::X0)
+    }
+
+    /// The variables the case pattern of the variant `name`, whose fields
+    /// are `fields`, binds, with the index of the field each one is bound to
+    fn field_bindings(
+        pattern: &Pattern,
+        name: Symbol,
+        fields: &[Symbol],
+    ) -> Result<Vec<(Symbol, usize)>, CodegenError> {
+        let patterns: Vec<(usize, &Pattern)> = match pattern {
+            Pattern::TupleConstructor { args, location, .. } => {
+                if args.len() != fields.len() {
+                    return Err(CodegenError::InvalidOperation(format!(
+                        "{} has {} fields, the pattern at line {}, column {} names {}",
+                        name,
+                        fields.len(),
+                        location.line,
+                        location.column,
+                        args.len()
+                    )));
+                }
+                args.iter().enumerate().collect()
+            }
+            Pattern::Constructor {
+                fields: named,
+                location,
+                ..
+            } => {
+                let mut named: Vec<_> = named.iter().collect();
+                named.sort_by(|a, b| a.0.cmp(b.0));
+                let mut patterns = Vec::new();
+                for (field, pattern) in named {
+                    let index = fields
+                        .iter()
+                        .position(|name| name == field)
+                        .ok_or_else(|| {
+                            CodegenError::InvalidOperation(format!(
+                                "{} has no field {} (line {}, column {})",
+                                name, field, location.line, location.column
+                            ))
+                        })?;
+                    patterns.push((index, pattern));
+                }
+                patterns
+            }
+            _ => Vec::new(),
+        };
+
+        let mut bindings = Vec::new();
+        for (index, pattern) in patterns {
+            match pattern {
+                Pattern::Variable { name, .. } => bindings.push((*name, index)),
+                Pattern::Wildcard { .. } => {}
+                pattern => return Err(unsupported("A nested pattern is", pattern.location())),
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// Number of locals the fields bound by a case pattern take
+    fn pattern_fields(pattern: &Pattern) -> usize {
+        let fields: Vec<&Pattern> = match pattern {
+            Pattern::TupleConstructor { args, .. } => args.iter().collect(),
+            Pattern::Constructor { fields, .. } => fields.values().collect(),
+            _ => Vec::new(),
+        };
+        fields
+            .into_iter()
+            .filter(|field| matches!(field, Pattern::Variable { .. }))
+            .count()
+    }
+
+    /// Generate `result?` into X5: the value of an `Ok`, or for an `Err` an
+    /// early return of the `Result` itself, whose error type the enclosing
+    /// function shares
+    fn generate_try(&mut self, expr: &Expr) -> Result<Register, CodegenError> {
+        let reg = self.generate_expr(expr)?;
+        let ok = self.generate_label("try_ok");
+        self.instructions.push(Instruction::Mv(Register::X5, reg));
+        self.instructions
+            .push(Instruction::Load(Register::X6, Register::X5, 0));
+        self.instructions.push(Instruction::BranchEq(
+            Register::X6,
+            Register::X0,
+            ok.clone(),
+        ));
+
+        // Release any scratch words of the enclosing expression first
+        self.instructions
+            .push(Instruction::Mv(Register::X10, Register::X5));
+        if self.stack_adjust != 0 {
+            self.instructions.push(Instruction::AddImm(
+                Register::X2,
+                Register::X2,
+                self.stack_adjust,
+            ));
+        }
+        self.instructions
+            .push(Instruction::Jump(self.return_label.clone()));
+
+        self.instructions.push(Instruction::Label(ok));
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X5, 4));
+        Ok(Register::X5)
+    }
+
+    /// Revert the call with the `len` bytes at the top of the stack
+    fn generate_revert_with(&mut self, len: i32) {
+        self.instructions
+            .push(Instruction::Mv(Register::X10, Register::X2));
+        self.instructions.push(Instruction::Li(Register::X11, len));
+        self.instructions
+            .push(Instruction::Li(Register::X17, HostFunction::Revert as i32));
+        self.instructions.push(Instruction::Ecall);
+    }
+
+    /// Generate a read of a storage field
+    ///
+    /// A scratch area holding the key, the value and its length is reserved
+    /// below the frame for the duration of the host call. Missing entries
+    /// read as zero.
+    fn generate_storage_load(&mut self, key: &[u8; 32]) -> Result<Register, CodegenError> {
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            -STORAGE_SCRATCH_SIZE,
+        ));
+        self.generate_storage_key(key);
+        self.instructions
+            .push(Instruction::Store(Register::X0, Register::X2, 32));
+
+        self.instructions
+            .push(Instruction::Mv(Register::X10, Register::X2));
+        self.instructions.push(Instruction::Li(Register::X11, 32));
+        self.instructions
+            .push(Instruction::AddImm(Register::X12, Register::X2, 32));
+        self.instructions
+            .push(Instruction::AddImm(Register::X13, Register::X2, 36));
+        self.instructions.push(Instruction::Li(
+            Register::X17,
+            HostFunction::StorageGet as i32,
+        ));
+        self.instructions.push(Instruction::Ecall);
+
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, 32));
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            STORAGE_SCRATCH_SIZE,
+        ));
+
+        Ok(Register::X5)
+    }
+
+    /// Generate a write of `value_reg` to a storage field
+    fn generate_storage_store(&mut self, key: &[u8; 32], value_reg: Register) {
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            -STORAGE_SCRATCH_SIZE,
+        ));
+        // Save the value before the key words clobber temporaries
+        self.instructions
+            .push(Instruction::Store(value_reg, Register::X2, 32));
+        self.generate_storage_key(key);
+
+        self.instructions
+            .push(Instruction::Mv(Register::X10, Register::X2));
+        self.instructions.push(Instruction::Li(Register::X11, 32));
+        self.instructions
+            .push(Instruction::AddImm(Register::X12, Register::X2, 32));
+        self.instructions.push(Instruction::Li(Register::X13, 4));
+        self.instructions.push(Instruction::Li(
+            Register::X17,
+            HostFunction::StorageSet as i32,
+        ));
+        self.instructions.push(Instruction::Ecall);
+
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            STORAGE_SCRATCH_SIZE,
+        ));
+    }
+
+    /// Generate a call to a storage field method
+    ///
+    /// Collection entries are addressed by `keccak256(root ++ key)`, where
+    /// `key` is the little-endian map key (one word, or several for wide
+    /// keys such as addresses) or vector index. Arguments are
+    /// evaluated into a scratch area below the frame; out-of-bounds vector
+    /// accesses revert.
+    fn generate_storage_method(
+        &mut self,
+        name: &str,
+        key: &[u8; 32],
+        kind: StorageKind,
+        method: &str,
+        args: &[Expr],
+    ) -> Result<Register, CodegenError> {
+        let arity = match (kind, method) {
+            (StorageKind::Value, "get") => 0,
+            (StorageKind::Value, "set") => 1,
+            (StorageKind::Map, "get" | "remove" | "contains") => 1,
+            (StorageKind::Map, "insert") => 2,
+            (StorageKind::Vec, "len" | "pop") => 0,
+            (StorageKind::Vec, "get" | "push") => 1,
+            (StorageKind::Vec, "set") => 2,
+            _ => {
+                return Err(CodegenError::InvalidOperation(format!(
+                    "Unknown storage method {}.{}",
+                    name, method
+                )))
+            }
+        };
+        if args.len() != arity {
+            return Err(CodegenError::InvalidOperation(format!(
+                "{}.{} expects {} arguments, found {}",
+                name,
+                method,
+                arity,
+                args.len()
+            )));
+        }
+
+        if self.variable_width(name.into()) > 1 && !matches!(method, "remove" | "len") {
+            return self.generate_wide_storage_method(name, key, kind, method, args);
+        }
+
+        if kind == StorageKind::Value {
+            return if method == "get" {
+                self.generate_storage_load(key)
+            } else {
+                let value_reg = self.generate_expr(&args[0])?;
+                self.generate_storage_store(key, value_reg);
+                Ok(Register::X0)
+            };
+        }
+
+        // Wide map keys are hashed into an entry key before the scratch
+        // area is reserved
+        let entry_parts = self.storage_key_parts(name.into());
+        let wide_entry = kind == StorageKind::Map && self.storage_key_width(name) > 1;
+        if wide_entry {
+            self.generate_wide_entry_key(key, &args[0], &entry_parts)?;
+        }
+
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            -COLLECTION_SCRATCH_SIZE,
+        ));
+        self.stack_adjust += COLLECTION_SCRATCH_SIZE;
+
+        for (i, arg) in args.iter().enumerate().skip(wide_entry as usize) {
+            let arg_reg = self.generate_expr(arg)?;
+            self.instructions.push(Instruction::Store(
+                arg_reg,
+                Register::X2,
+                COLLECTION_ARGS_OFFSET + (i as i32) * 4,
+            ));
+        }
+
+        let first_arg = COLLECTION_ARGS_OFFSET;
+        let second_arg = COLLECTION_ARGS_OFFSET + 4;
+        // Spare word for vector lengths
+        let length = COLLECTION_ARGS_OFFSET + 8;
+        let mut result = Register::X0;
+
+        if wide_entry {
+            self.generate_copy_words(COLLECTION_SCRATCH_SIZE, 0, BYTES32_LEN / 4);
+        } else {
+            self.generate_storage_key(key);
+            if kind == StorageKind::Map {
+                self.generate_derive_entry_key(first_arg);
+            }
+        }
+        match (kind, method) {
+            (StorageKind::Map, "get") => {
+                result = self.generate_collection_get();
+            }
+            (StorageKind::Map, "insert") => {
+                self.generate_collection_set(second_arg);
+            }
+            (StorageKind::Map, "remove") => {
+                self.generate_collection_host_call(HostFunction::StorageClear);
+            }
+            (StorageKind::Map, "contains") => {
+                self.generate_collection_get();
+                // StorageGet returns 0 in a0 when the entry exists
+                self.instructions.push(Instruction::SetLessThanImmU(
+                    Register::X5,
+                    Register::X10,
+                    1,
+                ));
+                result = Register::X5;
+            }
+            (StorageKind::Vec, "len") => {
+                result = self.generate_collection_get();
+            }
+            (StorageKind::Vec, "get") | (StorageKind::Vec, "set") => {
+                self.generate_collection_get();
+                self.generate_bounds_check(first_arg);
+                self.generate_derive_entry_key(first_arg);
+                if method == "get" {
+                    result = self.generate_collection_get();
+                } else {
+                    self.generate_collection_set(second_arg);
+                }
+            }
+            (StorageKind::Vec, "push") => {
+                self.generate_collection_get();
+                self.instructions
+                    .push(Instruction::Store(Register::X5, Register::X2, length));
+                self.generate_derive_entry_key(length);
+                self.generate_collection_set(first_arg);
+
+                // Store the new length under the root
+                self.generate_storage_key(key);
+                self.instructions
+                    .push(Instruction::Load(Register::X5, Register::X2, length));
+                self.instructions
+                    .push(Instruction::AddImm(Register::X5, Register::X5, 1));
+                self.instructions
+                    .push(Instruction::Store(Register::X5, Register::X2, length));
+                self.generate_collection_set(length);
+            }
+            (StorageKind::Vec, "pop") => {
+                self.generate_collection_get();
+                self.instructions.push(Instruction::Li(Register::X6, 0));
+                self.instructions
+                    .push(Instruction::Store(Register::X6, Register::X2, first_arg));
+                // Reverts unless 0 < len
+                self.generate_bounds_check(first_arg);
+
+                self.instructions
+                    .push(Instruction::AddImm(Register::X5, Register::X5, -1));
+                self.instructions
+                    .push(Instruction::Store(Register::X5, Register::X2, length));
+                self.generate_derive_entry_key(length);
+                self.generate_collection_get();
+                self.instructions
+                    .push(Instruction::Store(Register::X5, Register::X2, first_arg));
+                self.generate_collection_host_call(HostFunction::StorageClear);
+
+                self.generate_storage_key(key);
+                self.generate_collection_set(length);
+                self.instructions
+                    .push(Instruction::Load(Register::X5, Register::X2, first_arg));
+                result = Register::X5;
+            }
+            _ => unreachable!(),
+        }
+
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            COLLECTION_SCRATCH_SIZE,
+        ));
+        self.stack_adjust -= COLLECTION_SCRATCH_SIZE;
+        if wide_entry {
+            self.pop_wide(BYTES32_LEN / 4);
+        }
+
+        Ok(result)
+    }
+
+    /// Replace the key at the start of the scratch area with
+    /// `keccak256(key ++ word)`, reading the word from `suffix_offset`
+    fn generate_derive_entry_key(&mut self, suffix_offset: i32) {
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, suffix_offset));
+        self.instructions
+            .push(Instruction::Store(Register::X5, Register::X2, 32));
+        self.instructions
+            .push(Instruction::Mv(Register::X10, Register::X2));
+        self.instructions.push(Instruction::Li(Register::X11, 36));
+        self.instructions
+            .push(Instruction::Mv(Register::X12, Register::X2));
+        self.instructions.push(Instruction::Li(
+            Register::X17,
+            HostFunction::Keccak256 as i32,
+        ));
+        self.instructions.push(Instruction::Ecall);
+    }
+
+    /// Read the word under the scratch key into X5; missing entries read as zero
+    fn generate_collection_get(&mut self) -> Register {
+        self.instructions
+            .push(Instruction::Store(Register::X0, Register::X2, 36));
+        self.instructions
+            .push(Instruction::AddImm(Register::X12, Register::X2, 36));
+        self.instructions
+            .push(Instruction::AddImm(Register::X13, Register::X2, 40));
+        self.generate_collection_host_call(HostFunction::StorageGet);
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, 36));
+        Register::X5
+    }
+
+    /// Write the word at `value_offset` under the scratch key
+    fn generate_collection_set(&mut self, value_offset: i32) {
+        self.instructions.push(Instruction::AddImm(
+            Register::X12,
+            Register::X2,
+            value_offset,
+        ));
+        self.instructions.push(Instruction::Li(Register::X13, 4));
+        self.generate_collection_host_call(HostFunction::StorageSet);
+    }
+
+    /// Call a storage host function on the scratch key
+    fn generate_collection_host_call(&mut self, function: HostFunction) {
+        self.instructions
+            .push(Instruction::Mv(Register::X10, Register::X2));
+        self.instructions.push(Instruction::Li(Register::X11, 32));
+        self.instructions
+            .push(Instruction::Li(Register::X17, function as i32));
+        self.instructions.push(Instruction::Ecall);
+    }
+
+    /// Revert unless the index at `index_offset` is below the length in X5
+    fn generate_bounds_check(&mut self, index_offset: i32) {
+        let in_bounds = self.generate_label("storage_in_bounds");
+        self.instructions
+            .push(Instruction::Load(Register::X6, Register::X2, index_offset));
+        self.instructions.push(Instruction::BranchLtU(
+            Register::X6,
+            Register::X5,
+            in_bounds.clone(),
+        ));
+        self.generate_revert();
+        self.instructions.push(Instruction::Label(in_bounds));
+    }
+
+    /// Revert the call with empty output
+    fn generate_revert(&mut self) {
+        self.instructions.push(Instruction::Li(Register::X10, 0));
+        self.instructions.push(Instruction::Li(Register::X11, 0));
+        self.instructions
+            .push(Instruction::Li(Register::X17, HostFunction::Revert as i32));
+        self.instructions.push(Instruction::Ecall);
+    }
+
+    /// Generate `call`, `delegate_call` or `instantiate`
+    ///
+    /// The leading arguments are the target (an `Address` or `Hash`, or a
+    /// word-sized account id or code hash zero-padded to 32 bytes), the value for `call`/`instantiate` and
+    /// the gas limit (0 forwards all remaining gas). They are followed by a
+    /// string literal message signature, whose selector starts the input, and
+    /// the arguments of the message, in the words of the types the signature
+    /// declares. A failing callee reverts the caller.
+    /// `instantiate` returns the new account id, the others the callee's
+    /// return word.
+    fn generate_contract_call(
+        &mut self,
+        builtin: &str,
+        leading: usize,
+        args: &[Expr],
+    ) -> Result<Register, CodegenError> {
+        let signature = match args.get(leading) {
+            Some(Expr::Literal {
+                kind: LiteralKind::String(signature),
+                ..
+            }) => signature.clone(),
+            _ => {
+                return Err(CodegenError::InvalidOperation(format!(
+                    "{} expects {} arguments followed by a message signature string",
+                    builtin, leading
+                )))
+            }
+        };
+        let message_args = &args[leading + 1..];
+        // Arguments take the words of the types the signature declares, if
+        // it declares one for each, and a word otherwise
+        let widths = match signature_params(&signature) {
+            Some(params) if params.len() == message_args.len() => params
+                .iter()
+                .map(|param| match Self::abi_value(param) {
+                    Some(AbiValue::Words(words)) => Ok(words),
+                    _ => Err(CodegenError::UnsupportedFeature(format!(
+                        "{} cannot pass a {} to another contract",
+                        builtin,
+                        type_name(param)
+                    ))),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => vec![1; message_args.len()],
+        };
+        let input_len = 4 + 4 * widths.iter().sum::<usize>() as i32;
+        let scratch_size = (CONTRACT_CALL_SCRATCH_SIZE + input_len + 15) & !15;
+
+        self.instructions
+            .push(Instruction::Comment(format!("{} {}", builtin, signature)));
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            -scratch_size,
+        ));
+        self.stack_adjust += scratch_size;
+
+        // Target, value and gas
+        self.generate_account_arg(&args[0], 0)?;
+        let has_value = builtin != "delegate_call";
+        if has_value {
+            let value_reg = self.generate_expr(&args[1])?;
+            self.instructions
+                .push(Instruction::Store(value_reg, Register::X2, 32));
+        } else {
+            self.instructions
+                .push(Instruction::Store(Register::X0, Register::X2, 32));
+        }
+        for word in 1..4 {
+            self.instructions.push(Instruction::Store(
+                Register::X0,
+                Register::X2,
+                32 + 4 * word,
+            ));
+        }
+        let gas_reg = self.generate_expr(&args[leading - 1])?;
+        self.instructions
+            .push(Instruction::Store(gas_reg, Register::X2, 48));
+
+        // Output buffer
+        let output_capacity = if builtin == "instantiate" { 32 } else { 4 };
+        self.instructions
+            .push(Instruction::Li(Register::X5, output_capacity));
+        self.instructions
+            .push(Instruction::Store(Register::X5, Register::X2, 52));
+        self.instructions
+            .push(Instruction::Store(Register::X0, Register::X2, 56));
+
+        // Input: selector followed by the message arguments
+        let selector = selector_for(&signature);
+        self.instructions.push(Instruction::Li(
+            Register::X5,
+            u32::from_le_bytes(selector) as i32,
+        ));
+        self.instructions.push(Instruction::Store(
+            Register::X5,
+            Register::X2,
+            CONTRACT_CALL_SCRATCH_SIZE,
+        ));
+        let mut offset = CONTRACT_CALL_SCRATCH_SIZE + 4;
+        for (arg, &words) in message_args.iter().zip(&widths) {
+            if words > 1 {
+                self.generate_wide_expr(arg, words)?;
+                self.generate_copy_words(0, offset + (words * 4) as i32, words);
+                self.pop_wide(words);
+            } else {
+                let arg_reg = self.generate_expr(arg)?;
+                self.instructions
+                    .push(Instruction::Store(arg_reg, Register::X2, offset));
+            }
+            offset += (words * 4) as i32;
+        }
+
+        // Host arguments in a0.. order
+        enum HostArg {
+            Pointer(i32),
+            Word(i32),
+            Immediate(i32),
+        }
+        let mut host_args = vec![HostArg::Pointer(0)];
+        if has_value {
+            host_args.push(HostArg::Pointer(32));
+        }
+        host_args.push(HostArg::Word(48));
+        host_args.push(HostArg::Pointer(CONTRACT_CALL_SCRATCH_SIZE));
+        host_args.push(HostArg::Immediate(input_len));
+        host_args.push(HostArg::Pointer(56));
+        if builtin != "instantiate" {
+            host_args.push(HostArg::Pointer(52));
+        }
+        for (register, arg) in Register::arg_registers().into_iter().zip(host_args) {
+            self.instructions.push(match arg {
+                HostArg::Pointer(offset) => Instruction::AddImm(register, Register::X2, offset),
+                HostArg::Word(offset) => Instruction::Load(register, Register::X2, offset),
+                HostArg::Immediate(value) => Instruction::Li(register, value),
+            });
+        }
+        let host_function = match builtin {
+            "call" => HostFunction::Call,
+            "delegate_call" => HostFunction::DelegateCall,
+            _ => HostFunction::Create,
+        };
+        self.instructions
+            .push(Instruction::Li(Register::X17, host_function as i32));
+        self.instructions.push(Instruction::Ecall);
+
+        // A non-zero status means the callee reverted or failed
+        let succeeded = self.generate_label("call_succeeded");
+        self.instructions.push(Instruction::BranchEq(
+            Register::X10,
+            Register::X0,
+            succeeded.clone(),
+        ));
+        self.instructions.push(Instruction::Li(Register::X10, 0));
+        self.instructions.push(Instruction::Li(Register::X11, 0));
+        self.instructions
+            .push(Instruction::Li(Register::X17, HostFunction::Revert as i32));
+        self.instructions.push(Instruction::Ecall);
+        self.instructions.push(Instruction::Label(succeeded));
+
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, 56));
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            scratch_size,
+        ));
+        self.stack_adjust -= scratch_size;
+
+        Ok(Register::X5)
+    }
+
+    /// Generate `balance`, `transfer` or `terminate`
+    ///
+    /// Uses a 48-byte scratch area: the account id at 0 and the
+    /// 16-byte value at 32. A failed transfer reverts the caller.
+    /// `balance()` returns the low word of the balance, `transfer` returns 0
+    /// and `terminate` does not return.
+    fn generate_balance_builtin(
+        &mut self,
+        builtin: &str,
+        arity: usize,
+        args: &[Expr],
+    ) -> Result<Register, CodegenError> {
+        if args.len() != arity {
+            return Err(CodegenError::InvalidOperation(format!(
+                "{} expects {} arguments, found {}",
+                builtin,
+                arity,
+                args.len()
+            )));
+        }
+
+        let scratch_size = 48;
+        self.instructions
+            .push(Instruction::Comment(builtin.to_string()));
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            -scratch_size,
+        ));
+        self.stack_adjust += scratch_size;
+
+        if let Some(account) = args.first() {
+            self.generate_account_arg(account, 0)?;
+        }
+        if let Some(value) = args.get(1) {
+            let value_reg = self.generate_expr(value)?;
+            self.instructions
+                .push(Instruction::Store(value_reg, Register::X2, 32));
+            for word in 1..4 {
+                self.instructions.push(Instruction::Store(
+                    Register::X0,
+                    Register::X2,
+                    32 + 4 * word,
+                ));
+            }
+        }
+
+        let host_function = match builtin {
+            "balance" => {
+                self.instructions
+                    .push(Instruction::AddImm(Register::X10, Register::X2, 32));
+                HostFunction::GetBalance
+            }
+            "transfer" => {
+                self.instructions
+                    .push(Instruction::AddImm(Register::X10, Register::X2, 0));
+                self.instructions
+                    .push(Instruction::AddImm(Register::X11, Register::X2, 32));
+                HostFunction::Transfer
+            }
+            _ => {
+                self.instructions
+                    .push(Instruction::AddImm(Register::X10, Register::X2, 0));
+                HostFunction::Terminate
+            }
+        };
+        self.instructions
+            .push(Instruction::Li(Register::X17, host_function as i32));
+        self.instructions.push(Instruction::Ecall);
+
+        if builtin == "transfer" {
+            let succeeded = self.generate_label("transfer_succeeded");
+            self.instructions.push(Instruction::BranchEq(
+                Register::X10,
+                Register::X0,
+                succeeded.clone(),
+            ));
+            self.instructions.push(Instruction::Li(Register::X10, 0));
+            self.instructions.push(Instruction::Li(Register::X11, 0));
+            self.instructions
+                .push(Instruction::Li(Register::X17, HostFunction::Revert as i32));
+            self.instructions.push(Instruction::Ecall);
+            self.instructions.push(Instruction::Label(succeeded));
+        }
+
+        if builtin == "balance" {
+            self.instructions
+                .push(Instruction::Load(Register::X5, Register::X2, 32));
+        } else {
+            self.instructions.push(Instruction::Li(Register::X5, 0));
+        }
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            scratch_size,
+        ));
+        self.stack_adjust -= scratch_size;
+
+        Ok(Register::X5)
+    }
+
+    /// Generate `set_code_hash(code_hash)`, which returns 0 and reverts the
+    /// caller when no code was uploaded with the hash
+    ///
+    /// Uses a 32-byte scratch area holding the code hash.
+    fn generate_set_code_hash(&mut self, args: &[Expr]) -> Result<Register, CodegenError> {
+        let [code_hash] = args else {
+            return Err(CodegenError::InvalidOperation(format!(
+                "set_code_hash expects 1 argument, found {}",
+                args.len()
+            )));
+        };
+
+        let scratch_size = BYTES32_LEN as i32;
+        self.instructions
+            .push(Instruction::Comment(SET_CODE_HASH.to_string()));
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            -scratch_size,
+        ));
+        self.stack_adjust += scratch_size;
+
+        self.generate_account_arg(code_hash, 0)?;
+        self.instructions
+            .push(Instruction::AddImm(Register::X10, Register::X2, 0));
+        self.instructions.push(Instruction::Li(
+            Register::X17,
+            HostFunction::SetCodeHash as i32,
+        ));
+        self.instructions.push(Instruction::Ecall);
+
+        let succeeded = self.generate_label("set_code_hash_succeeded");
+        self.instructions.push(Instruction::BranchEq(
+            Register::X10,
+            Register::X0,
+            succeeded.clone(),
+        ));
+        self.instructions.push(Instruction::Li(Register::X10, 0));
+        self.instructions.push(Instruction::Li(Register::X11, 0));
+        self.instructions
+            .push(Instruction::Li(Register::X17, HostFunction::Revert as i32));
+        self.instructions.push(Instruction::Ecall);
+        self.instructions.push(Instruction::Label(succeeded));
+
+        self.instructions.push(Instruction::Li(Register::X5, 0));
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            scratch_size,
+        ));
+        self.stack_adjust -= scratch_size;
+
+        Ok(Register::X5)
+    }
+
+    /// Store a 32-byte account id or code hash at `offset(sp)`: an
+    /// `Address` or `Hash` value, or a zero-extended word
+    fn generate_account_arg(&mut self, account: &Expr, offset: i32) -> Result<(), CodegenError> {
+        let words = BYTES32_LEN / 4;
+        if self.expr_width(account) > 1 {
+            self.generate_wide_expr(account, words)?;
+            self.generate_copy_words(0, offset + BYTES32_LEN as i32, words);
+            self.pop_wide(words);
+        } else {
+            let account_reg = self.generate_expr(account)?;
+            self.instructions
+                .push(Instruction::Store(account_reg, Register::X2, offset));
+            for word in 1..words as i32 {
+                self.instructions.push(Instruction::Store(
+                    Register::X0,
+                    Register::X2,
+                    offset + 4 * word,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Generate a call to a chain extension: the arguments go in `a0`-`a5`
+    /// and the result comes back in `a0`
+    fn generate_chain_extension_call(
+        &mut self,
+        extension: &ChainExtension,
+        args: &[Expr],
+    ) -> Result<Register, CodegenError> {
+        if args.len() != extension.params.len() {
+            return Err(CodegenError::InvalidOperation(format!(
+                "Chain extension {} expects {} arguments, found {}",
+                extension.name,
+                extension.params.len(),
+                args.len()
+            )));
+        }
+
+        self.instructions.push(Instruction::Comment(format!(
+            "Chain extension {}",
+            extension.name
+        )));
+
+        // Evaluate every argument before loading the argument registers
+        let scratch_size = (4 * args.len() as i32 + 15) & !15;
+        if scratch_size > 0 {
+            self.instructions.push(Instruction::AddImm(
+                Register::X2,
+                Register::X2,
+                -scratch_size,
+            ));
+            self.stack_adjust += scratch_size;
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let arg_reg = self.generate_expr(arg)?;
+            self.instructions
+                .push(Instruction::Store(arg_reg, Register::X2, 4 * i as i32));
+        }
+        for (i, register) in Register::arg_registers()
+            .into_iter()
+            .take(args.len())
+            .enumerate()
+        {
+            self.instructions
+                .push(Instruction::Load(register, Register::X2, 4 * i as i32));
+        }
+
+        self.instructions
+            .push(Instruction::Li(Register::X17, extension.id as i32));
+        self.instructions.push(Instruction::Ecall);
+        if extension.returns == ExtensionType::Unit {
+            self.instructions.push(Instruction::Li(Register::X5, 0));
+        } else {
+            self.instructions
+                .push(Instruction::Mv(Register::X5, Register::X10));
+        }
+
+        if scratch_size > 0 {
+            self.instructions.push(Instruction::AddImm(
+                Register::X2,
+                Register::X2,
+                scratch_size,
+            ));
+            self.stack_adjust -= scratch_size;
+        }
+
+        Ok(Register::X5)
+    }
+
+    /// Write a storage key to the start of the scratch area
+    fn generate_storage_key(&mut self, key: &[u8; 32]) {
+        for (i, chunk) in key.chunks(4).enumerate() {
+            let word = i32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            self.instructions.push(Instruction::Li(Register::X5, word));
+            self.instructions.push(Instruction::Store(
+                Register::X5,
+                Register::X2,
+                (i * 4) as i32,
+            ));
+        }
+    }
+
+    /// Generate code for an expression
+    fn generate_expr(&mut self, expr: &Expr) -> Result<Register, CodegenError> {
+        let words = self.expr_width(expr);
+        if words > 1 {
+            return Err(CodegenError::InvalidOperation(format!(
+                "{}-bit value used where a word is expected",
+                words * 32
+            )));
+        }
+
+        match expr {
+            Expr::Variable { name, .. } => {
+                // Load variable from stack frame or global storage
+                if let Some(&offset) = self.locals.get(name) {
+                    let reg = Register::X5; // Temporary register
+                    self.instructions.push(Instruction::Load(
+                        reg,
+                        Register::X2,
+                        offset + self.stack_adjust,
+                    ));
+                    Ok(reg)
+                } else if let Some((key, kind)) = self.storage.get(name).copied() {
+                    if kind != StorageKind::Value {
+                        return Err(CodegenError::InvalidOperation(format!(
+                            "Storage collection {} can only be accessed through its methods",
+                            name
+                        )));
+                    }
+                    self.generate_storage_load(&key)
+                } else if let Some(function_label) = self.function_labels.get(name) {
+                    // Function pointer
+                    let reg = Register::X5; // Temporary register
+                    self.instructions
+                        .push(Instruction::La(reg, function_label.clone()));
+                    Ok(reg)
+                } else if self.is_error_value(*name) {
+                    self.generate_error_value(*name, &[])
+                } else if self.variants.contains_key(name) {
+                    self.generate_variant(*name, &[])
+                } else {
+                    Err(CodegenError::UndefinedVariable(name.to_string()))
+                }
+            }
+            Expr::Literal { kind, .. } => {
+                let reg = Register::X5; // Temporary register
+                match kind {
+                    LiteralKind::Uint(value) => {
+                        if *value <= i32::MAX as u32 {
+                            self.instructions.push(Instruction::Li(reg, *value as i32));
+                            Ok(reg)
+                        } else {
+                            Err(CodegenError::InvalidOperation(format!(
+                                "Literal value too large: {}",
+                                value
+                            )))
+                        }
+                    }
+                    LiteralKind::Int(value) => {
+                        self.instructions.push(Instruction::Li(reg, *value));
+                        Ok(reg)
+                    }
+                    LiteralKind::Bool(value) => {
+                        self.instructions
+                            .push(Instruction::Li(reg, if *value { 1 } else { 0 }));
+                        Ok(reg)
+                    }
+                    LiteralKind::Bytes(bytes) => Ok(self.generate_bytes_literal(bytes)),
+                    // For brevity, not implementing all literal types
+                    _ => Err(CodegenError::UnsupportedFeature(
+                        "Literal type not yet implemented".to_string(),
+                    )),
+                }
+            }
+            Expr::BinaryOp {
+                left,
+                operator,
+                right,
+                ..
+            } => {
+                let operand_words = self.expr_width(left).max(self.expr_width(right));
+                if operand_words > 1 {
+                    return self.generate_wide_comparison(operator, left, right, operand_words);
+                }
+                if self.is_bytes_expr(left) || self.is_bytes_expr(right) {
+                    return self.generate_bytes_equality(operator, left, right);
+                }
+                if matches!(operator, BinaryOperator::And | BinaryOperator::Or) {
+                    return self.generate_logical(operator, left, right);
+                }
+
+                let kind = self.expr_kind(left).combine(self.expr_kind(right));
+                let (left_reg, right_reg) = self.generate_operands(left, right)?;
+                let result_reg = Register::X5; // Temporary register
+
+                if self.checked
+                    && matches!(
+                        operator,
+                        BinaryOperator::Add
+                            | BinaryOperator::Sub
+                            | BinaryOperator::Mul
+                            | BinaryOperator::Div
+                            | BinaryOperator::Mod
+                    )
+                {
+                    self.generate_checked_arithmetic(operator, kind);
+                    return Ok(result_reg);
+                }
+
+                match operator {
+                    BinaryOperator::Add => {
+                        self.instructions
+                            .push(Instruction::Add(result_reg, left_reg, right_reg));
+                        self.generate_wrap(kind);
+                        Ok(result_reg)
+                    }
+                    BinaryOperator::Sub => {
+                        self.instructions
+                            .push(Instruction::Sub(result_reg, left_reg, right_reg));
+                        self.generate_wrap(kind);
+                        Ok(result_reg)
+                    }
+                    BinaryOperator::Mul => {
+                        self.instructions
+                            .push(Instruction::Mul(result_reg, left_reg, right_reg));
+                        self.generate_wrap(kind);
+                        Ok(result_reg)
+                    }
+                    BinaryOperator::Div => {
+                        self.instructions
+                            .push(Instruction::Div(result_reg, left_reg, right_reg));
+                        self.generate_wrap(kind);
+                        Ok(result_reg)
+                    }
+                    BinaryOperator::Mod => {
+                        self.instructions
+                            .push(Instruction::Rem(result_reg, left_reg, right_reg));
+                        self.generate_wrap(kind);
+                        Ok(result_reg)
+                    }
+                    BinaryOperator::BitAnd => {
+                        self.instructions
+                            .push(Instruction::And(result_reg, left_reg, right_reg));
+                        Ok(result_reg)
+                    }
+                    BinaryOperator::BitOr => {
+                        self.instructions
+                            .push(Instruction::Or(result_reg, left_reg, right_reg));
+                        Ok(result_reg)
+                    }
+                    BinaryOperator::BitXor => {
+                        self.instructions
+                            .push(Instruction::Xor(result_reg, left_reg, right_reg));
+                        Ok(result_reg)
+                    }
+                    BinaryOperator::Equal => {
+                        // x == y is (x - y) <u 1, i.e. 1 when the difference is 0
+                        self.instructions
+                            .push(Instruction::Sub(result_reg, left_reg, right_reg));
+                        self.instructions
+                            .push(Instruction::SetLessThanImmU(result_reg, result_reg, 1));
+                        Ok(result_reg)
+                    }
+                    BinaryOperator::NotEqual => {
+                        // x != y is 0 <u (x - y), i.e. 1 when the difference is not 0
+                        self.instructions
+                            .push(Instruction::Sub(result_reg, left_reg, right_reg));
+                        self.instructions.push(Instruction::SetLessThanU(
+                            result_reg,
+                            Register::X0,
+                            result_reg,
+                        ));
+                        Ok(result_reg)
+                    }
+                    BinaryOperator::Less => {
+                        self.instructions
+                            .push(Instruction::SetLessThan(result_reg, left_reg, right_reg));
+                        Ok(result_reg)
+                    }
+                    BinaryOperator::LessEqual => {
+                        // x <= y can be implemented as !(y < x)
+                        self.instructions
+                            .push(Instruction::SetLessThan(result_reg, right_reg, left_reg));
+                        self.instructions.push(Instruction::SetLessThanImm(
+                            Register::X6,
+                            Register::X0,
+                            1,
+                        )); // 1 if 0 < 1 (always true)
+                        self.instructions.push(Instruction::Xor(
+                            result_reg,
+                            result_reg,
+                            Register::X6,
+                        )); // Invert the result
+                        Ok(result_reg)
+                    }
+                    BinaryOperator::Greater => {
+                        self.instructions
+                            .push(Instruction::SetLessThan(result_reg, right_reg, left_reg));
+                        Ok(result_reg)
+                    }
+                    BinaryOperator::GreaterEqual => {
+                        // x >= y can be implemented as !(x < y)
+                        self.instructions
+                            .push(Instruction::SetLessThan(result_reg, left_reg, right_reg));
+                        self.instructions.push(Instruction::SetLessThanImm(
+                            Register::X6,
+                            Register::X0,
+                            1,
+                        )); // 1 if 0 < 1 (always true)
+                        self.instructions.push(Instruction::Xor(
+                            result_reg,
+                            result_reg,
+                            Register::X6,
+                        )); // Invert the result
+                        Ok(result_reg)
+                    }
+                    // For brevity, not implementing all operators
+                    _ => Err(CodegenError::UnsupportedFeature(
+                        "Binary operator not yet implemented".to_string(),
+                    )),
+                }
+            }
+            Expr::FunctionCall { function, args, .. } => {
+                // Methods of storage fields, e.g. `balances.get(owner)`
+                if let Some((name, method)) = function.as_method_target() {
+                    if !self.locals.contains_key(&name) {
+                        if let Some((key, kind)) = self.storage.get(&name).copied() {
+                            return self.generate_storage_method(&name, &key, kind, method, args);
+                        }
+                    }
+                }
+
+                // Calls through a function passed as an argument
+                if let Expr::Variable { name, .. } = &**function {
+                    if self.function_args.contains(name) {
+                        return self.generate_indirect_call(self.locals[name], args);
+                    }
+                }
+
+                // Cross-contract calls, unless shadowed by a user function
+                if let Expr::Variable { name, .. } = &**function {
+                    if !self.function_labels.contains_key(name) {
+                        if let Some(&(builtin, leading)) = CONTRACT_CALL_BUILTINS
+                            .iter()
+                            .find(|(builtin, _)| builtin == name)
+                        {
+                            return self.generate_contract_call(builtin, leading, args);
+                        }
+                        if let Some(&(builtin, arity)) =
+                            BALANCE_BUILTINS.iter().find(|(builtin, _)| builtin == name)
+                        {
+                            return self.generate_balance_builtin(builtin, arity, args);
+                        }
+                        if name == SET_CODE_HASH {
+                            return self.generate_set_code_hash(args);
+                        }
+                        if let Some(extension) = self.chain_extensions.get(name).cloned() {
+                            return self.generate_chain_extension_call(&extension, args);
+                        }
+                        if name == "Map::new" && args.is_empty() {
+                            return self.generate_map_literal(&[]);
+                        }
+                        if name == linearity::DUP && args.len() == 1 {
+                            return self.generate_map_dup(&args[0]);
+                        }
+                        if let Some(&(builtin, arity)) =
+                            BYTES_BUILTINS.iter().find(|(builtin, _)| builtin == name)
+                        {
+                            return self.generate_bytes_builtin(builtin, arity, args);
+                        }
+                        if let (TO_BYTES, [value]) = (name.as_str(), args.as_slice()) {
+                            return self.generate_to_bytes(value);
+                        }
+                        if self.variants.contains_key(name) {
+                            return self.generate_variant(*name, args);
+                        }
+                        if matches!(name.as_str(), "Result/Ok" | "Result/Err") {
+                            return self.generate_result(name, args);
+                        }
+                        if self.is_error_value(*name) {
+                            return self.generate_error_value(*name, args);
+                        }
+                    }
+                }
+
+                // For simplicity, only handle direct function calls
+                if let Expr::Variable { name, .. } = &**function {
+                    if self.function_labels.contains_key(name) {
+                        self.generate_direct_call(*name, args)
+                    } else {
+                        Err(CodegenError::UndefinedVariable(name.to_string()))
+                    }
+                } else {
+                    Err(CodegenError::InvalidOperation(
+                        "Function call with non-variable target".to_string(),
+                    ))
+                }
+            }
+            Expr::UnaryOp {
+                operator: UnaryOperator::Not,
+                operand,
+                ..
+            } => {
+                let reg = self.generate_expr(operand)?;
+                self.instructions
+                    .push(Instruction::SetLessThanImmU(Register::X5, reg, 1));
+                Ok(Register::X5)
+            }
+            Expr::Map { entries, .. } => self.generate_map_literal(entries),
+            Expr::Try { expr, .. } => self.generate_try(expr),
+            Expr::MapAccess { map, key, location } => {
+                match self.storage_map_call(map, "get", vec![(**key).clone()], location) {
+                    Some(call) => self.generate_expr(&call),
+                    None => self.generate_map_get(map, key),
+                }
+            }
+            Expr::InlineAsm {
+                inputs,
+                output,
+                instructions,
+                ..
+            } => self.generate_inline_asm(inputs, output.as_deref(), instructions),
+            Expr::List { location, .. } | Expr::ListComprehension { location, .. } => {
+                Err(unsupported("Lists are", location))
+            }
+            // Comprehensions not assigned or returned whole are lowered to folds
+            Expr::Block { block, location }
+                if block
+                    .statements
+                    .iter()
+                    .any(|statement| matches!(statement, Statement::Fold { .. })) =>
+            {
+                Err(unsupported(
+                    "A comprehension that is not the whole value assigned or returned is",
+                    location,
+                ))
+            }
+            Expr::MapComprehension { location, .. } => Err(unsupported(
+                "A comprehension over a range that is not the whole value assigned or returned is",
+                location,
+            )),
+            // For brevity, not implementing all expression types
+            _ => Err(unsupported("This expression is", expr.location())),
+        }
+    }
+
+    /// Generate a short-circuiting `&&` or `||` into X5
+    ///
+    /// The right operand is only evaluated when the left one does not
+    /// decide the result. Any non-zero operand counts as true; the result is
+    /// 0 or 1.
+    fn generate_logical(
+        &mut self,
+        operator: &BinaryOperator,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<Register, CodegenError> {
+        let end = self.generate_label("logical_end");
+
+        let reg = self.generate_expr(left)?;
+        self.instructions
+            .push(Instruction::SetLessThanU(Register::X5, Register::X0, reg));
+        self.instructions.push(match operator {
+            BinaryOperator::And => Instruction::BranchEq(Register::X5, Register::X0, end.clone()),
+            _ => Instruction::BranchNe(Register::X5, Register::X0, end.clone()),
+        });
+
+        let reg = self.generate_expr(right)?;
+        self.instructions
+            .push(Instruction::SetLessThanU(Register::X5, Register::X0, reg));
+        self.instructions.push(Instruction::Label(end));
+        Ok(Register::X5)
+    }
+
+    /// Rewrite an access to an entry of a storage map field into the
+    /// equivalent method call, e.g. `balances[owner]` into
+    /// `balances.get(owner)`
+    ///
+    /// Returns `None` when `map` is not a storage map.
+    fn storage_map_call(
+        &self,
+        map: &Expr,
+        method: &str,
+        args: Vec<Expr>,
+        location: &Location,
+    ) -> Option<Expr> {
+        match map {
+            Expr::Variable { name, .. }
+                if !self.locals.contains_key(name)
+                    && matches!(self.storage.get(name), Some((_, StorageKind::Map))) =>
+            {
+                Some(Expr::FunctionCall {
+                    function: Box::new(Expr::Variable {
+                        name: Symbol::intern(&format!("{}.{}", name, method)),
+                        location: location.clone(),
+                    }),
+                    args,
+                    named_args: HashMap::new(),
+                    location: location.clone(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Generate an in-memory map literal, returning a pointer to the map
+    /// in X5
+    ///
+    /// A map is a one-word header holding the root of an unbalanced binary
+    /// search tree, ordered by the unsigned key word. Every node is four
+    /// words: key, value, left and right child. Headers and nodes are
+    /// allocated with the `MemoryAlloc` host function and never freed; the
+    /// call reverts when memory runs out. Keys and values are words.
+    fn generate_map_literal(&mut self, entries: &[(Expr, Expr)]) -> Result<Register, CodegenError> {
+        self.generate_alloc(MAP_HEADER_SIZE);
+        self.instructions
+            .push(Instruction::Store(Register::X0, Register::X10, 0));
+
+        self.push_wide(MAP_SCRATCH_WORDS);
+        self.instructions
+            .push(Instruction::Store(Register::X10, Register::X2, 0));
+        for (key, value) in entries {
+            self.generate_map_operands(None, key, Some(value))?;
+            self.generate_map_set();
+        }
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, 0));
+        self.pop_wide(MAP_SCRATCH_WORDS);
+
+        Ok(Register::X5)
+    }
+
+    /// Generate a copy of an in-memory map, returning a pointer to the copy
+    /// in X5
+    ///
+    /// The tree is copied node by node, with the same shape. Pairs of a
+    /// node to copy and the address of the link to point at its copy are
+    /// kept below the stack pointer, above a pair with a null link marking
+    /// the end.
+    fn generate_map_dup(&mut self, map: &Expr) -> Result<Register, CodegenError> {
+        let (node, link, word) = (Register::X6, Register::X7, Register::X28);
+        let next = self.generate_label("map_dup");
+        let end = self.generate_label("map_dup_end");
+
+        // The new header at 0 and the root of the source at 4
+        self.push_wide(2);
+        let reg = self.generate_expr(map)?;
+        self.instructions.push(Instruction::Load(node, reg, 0));
+        self.instructions
+            .push(Instruction::Store(node, Register::X2, 4));
+        self.generate_alloc(MAP_HEADER_SIZE);
+        self.instructions
+            .push(Instruction::Store(Register::X0, Register::X10, 0));
+        self.instructions
+            .push(Instruction::Store(Register::X10, Register::X2, 0));
+
+        self.instructions
+            .push(Instruction::Load(node, Register::X2, 4));
+        self.instructions
+            .push(Instruction::AddImm(Register::X2, Register::X2, -16));
+        self.instructions
+            .push(Instruction::Store(Register::X0, Register::X2, 8));
+        self.instructions
+            .push(Instruction::Store(Register::X0, Register::X2, 12));
+        self.instructions
+            .push(Instruction::Store(node, Register::X2, 0));
+        self.instructions
+            .push(Instruction::Store(Register::X10, Register::X2, 4));
+
+        self.instructions.push(Instruction::Label(next.clone()));
+        self.instructions
+            .push(Instruction::Load(node, Register::X2, 0));
+        self.instructions
+            .push(Instruction::Load(link, Register::X2, 4));
+        self.instructions
+            .push(Instruction::AddImm(Register::X2, Register::X2, 8));
+        self.instructions
+            .push(Instruction::BranchEq(link, Register::X0, end.clone()));
+        self.instructions
+            .push(Instruction::BranchEq(node, Register::X0, next.clone()));
+
+        // Keep the pair across the allocation
+        self.instructions
+            .push(Instruction::AddImm(Register::X2, Register::X2, -8));
+        self.instructions
+            .push(Instruction::Store(node, Register::X2, 0));
+        self.instructions
+            .push(Instruction::Store(link, Register::X2, 4));
+        self.generate_alloc(MAP_NODE_SIZE);
+        self.instructions
+            .push(Instruction::Load(node, Register::X2, 0));
+        self.instructions
+            .push(Instruction::Load(link, Register::X2, 4));
+        self.instructions
+            .push(Instruction::Store(Register::X10, link, 0));
+        for offset in [0, 4] {
+            self.instructions
+                .push(Instruction::Load(word, node, offset));
+            self.instructions
+                .push(Instruction::Store(word, Register::X10, offset));
+        }
+        self.instructions
+            .push(Instruction::Store(Register::X0, Register::X10, 8));
+        self.instructions
+            .push(Instruction::Store(Register::X0, Register::X10, 12));
+
+        // The children replace the pair, the left one on top
+        self.instructions
+            .push(Instruction::AddImm(Register::X2, Register::X2, -8));
+        for (child, slot) in [(8, 0), (12, 8)] {
+            self.instructions.push(Instruction::Load(word, node, child));
+            self.instructions
+                .push(Instruction::Store(word, Register::X2, slot));
+            self.instructions
+                .push(Instruction::AddImm(link, Register::X10, child));
+            self.instructions
+                .push(Instruction::Store(link, Register::X2, slot + 4));
+        }
+        self.instructions.push(Instruction::Jump(next));
+
+        self.instructions.push(Instruction::Label(end));
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, 0));
+        self.pop_wide(2);
+        Ok(Register::X5)
+    }
+
+    /// Generate `map[key]` on an in-memory map into X5; missing keys read as
+    /// zero
+    fn generate_map_get(&mut self, map: &Expr, key: &Expr) -> Result<Register, CodegenError> {
+        self.push_wide(MAP_SCRATCH_WORDS);
+        self.generate_map_operands(Some(map), key, None)?;
+
+        let (node, wanted, node_key) = (Register::X6, Register::X7, Register::X28);
+        let search = self.generate_label("map_get");
+        let left = self.generate_label("map_get_left");
+        let found = self.generate_label("map_get_found");
+        let end = self.generate_label("map_get_end");
+
+        self.instructions
+            .push(Instruction::Load(node, Register::X2, 0));
+        self.instructions.push(Instruction::Load(node, node, 0));
+        self.instructions
+            .push(Instruction::Load(wanted, Register::X2, 4));
+        self.instructions.push(Instruction::Li(Register::X5, 0));
+
+        self.instructions.push(Instruction::Label(search.clone()));
+        self.instructions
+            .push(Instruction::BranchEq(node, Register::X0, end.clone()));
+        self.instructions.push(Instruction::Load(node_key, node, 0));
+        self.instructions
+            .push(Instruction::BranchEq(node_key, wanted, found.clone()));
+        self.instructions
+            .push(Instruction::BranchLtU(wanted, node_key, left.clone()));
+        self.instructions.push(Instruction::Load(node, node, 12));
+        self.instructions.push(Instruction::Jump(search.clone()));
+        self.instructions.push(Instruction::Label(left));
+        self.instructions.push(Instruction::Load(node, node, 8));
+        self.instructions.push(Instruction::Jump(search));
+        self.instructions.push(Instruction::Label(found));
+        self.instructions
+            .push(Instruction::Load(Register::X5, node, 4));
+        self.instructions.push(Instruction::Label(end));
+
+        self.pop_wide(MAP_SCRATCH_WORDS);
+        Ok(Register::X5)
+    }
+
+    /// Generate `map[key] = value` on an in-memory map
+    fn generate_map_insert(
+        &mut self,
+        map: &Expr,
+        key: &Expr,
+        value: &Expr,
+    ) -> Result<(), CodegenError> {
+        self.push_wide(MAP_SCRATCH_WORDS);
+        self.generate_map_operands(Some(map), key, Some(value))?;
+        self.generate_map_set();
+        self.pop_wide(MAP_SCRATCH_WORDS);
+        Ok(())
+    }
+
+    /// Evaluate the map pointer, key and value of a map operation into the
+    /// map scratch words at 0, 4 and 8 of the stack
+    fn generate_map_operands(
+        &mut self,
+        map: Option<&Expr>,
+        key: &Expr,
+        value: Option<&Expr>,
+    ) -> Result<(), CodegenError> {
+        let operands = [(map, 0), (Some(key), 4), (value, 8)];
+        for (operand, offset) in operands {
+            let Some(operand) = operand else {
+                continue;
+            };
+            if self.expr_width(operand) > 1 {
+                return Err(CodegenError::UnsupportedFeature(
+                    "In-memory map keys and values must be words".to_string(),
+                ));
+            }
+            let reg = self.generate_expr(operand)?;
+            self.instructions
+                .push(Instruction::Store(reg, Register::X2, offset));
+        }
+        Ok(())
+    }
+
+    /// Insert or overwrite the entry in the map scratch words
+    fn generate_map_set(&mut self) {
+        let (slot, wanted, node, node_key) =
+            (Register::X6, Register::X7, Register::X28, Register::X29);
+        let search = self.generate_label("map_set");
+        let update = self.generate_label("map_set_update");
+        let insert = self.generate_label("map_set_insert");
+        let end = self.generate_label("map_set_end");
+
+        // The slot is the address of the link to the next node, starting at
+        // the root in the header
+        self.instructions
+            .push(Instruction::Load(slot, Register::X2, 0));
+        self.instructions
+            .push(Instruction::Load(wanted, Register::X2, 4));
+
+        self.instructions.push(Instruction::Label(search.clone()));
+        self.instructions.push(Instruction::Load(node, slot, 0));
+        self.instructions
+            .push(Instruction::BranchEq(node, Register::X0, insert.clone()));
+        self.instructions.push(Instruction::Load(node_key, node, 0));
+        self.instructions
+            .push(Instruction::BranchEq(node_key, wanted, update.clone()));
+        self.instructions.push(Instruction::AddImm(slot, node, 12));
+        self.instructions
+            .push(Instruction::BranchLtU(node_key, wanted, search.clone()));
+        self.instructions.push(Instruction::AddImm(slot, node, 8));
+        self.instructions.push(Instruction::Jump(search));
+
+        self.instructions.push(Instruction::Label(update));
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, 8));
+        self.instructions
+            .push(Instruction::Store(Register::X5, node, 4));
+        self.instructions.push(Instruction::Jump(end.clone()));
+
+        self.instructions.push(Instruction::Label(insert));
+        self.instructions
+            .push(Instruction::Store(slot, Register::X2, 12));
+        self.generate_alloc(MAP_NODE_SIZE);
+        for (from, to) in [(4, 0), (8, 4)] {
+            self.instructions
+                .push(Instruction::Load(Register::X5, Register::X2, from));
+            self.instructions
+                .push(Instruction::Store(Register::X5, Register::X10, to));
+        }
+        self.instructions
+            .push(Instruction::Store(Register::X0, Register::X10, 8));
+        self.instructions
+            .push(Instruction::Store(Register::X0, Register::X10, 12));
+        self.instructions
+            .push(Instruction::Load(slot, Register::X2, 12));
+        self.instructions
+            .push(Instruction::Store(Register::X10, slot, 0));
+        self.instructions.push(Instruction::Label(end));
+    }
+
+    /// Allocate `size` bytes into X10, reverting when memory runs out
+    fn generate_alloc(&mut self, size: i32) {
+        self.instructions.push(Instruction::Li(Register::X10, size));
+        self.generate_alloc_dynamic();
+    }
+
+    /// Allocate the number of bytes in X10 into X10, reverting when memory
+    /// runs out
+    fn generate_alloc_dynamic(&mut self) {
+        let allocated = self.generate_label("alloc_ok");
+        self.instructions.push(Instruction::Li(
+            Register::X17,
+            HostFunction::MemoryAlloc as i32,
+        ));
+        self.instructions.push(Instruction::Ecall);
+        self.instructions.push(Instruction::BranchNe(
+            Register::X10,
+            Register::X0,
+            allocated.clone(),
+        ));
+        self.generate_revert();
+        self.instructions.push(Instruction::Label(allocated));
+    }
+
+    /// Generate a `Bytes` literal, returning a pointer to it in X5
+    ///
+    /// A `Bytes` value is a pointer to a heap block holding the length word
+    /// followed by the data, padded to whole words. Values are immutable;
+    /// `concat` and `slice` allocate new blocks.
+    fn generate_bytes_literal(&mut self, bytes: &[u8]) -> Register {
+        self.generate_alloc(BYTES_HEADER_SIZE + (bytes.len().div_ceil(4) * 4) as i32);
+        self.instructions
+            .push(Instruction::Li(Register::X5, bytes.len() as i32));
+        self.instructions
+            .push(Instruction::Store(Register::X5, Register::X10, 0));
+
+        // Freshly allocated memory is zeroed
+        for (i, chunk) in bytes.chunks(4).enumerate() {
+            let mut word = [0u8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            let word = u32::from_le_bytes(word);
+            if word != 0 {
+                self.instructions
+                    .push(Instruction::Li(Register::X5, word as i32));
+                self.instructions.push(Instruction::Store(
+                    Register::X5,
+                    Register::X10,
+                    BYTES_HEADER_SIZE + (i * 4) as i32,
+                ));
+            }
+        }
+
+        self.instructions
+            .push(Instruction::Mv(Register::X5, Register::X10));
+        Register::X5
+    }
+
+    /// Allocate a `Bytes` block for the length in `len` into X10, storing
+    /// the length
+    fn generate_bytes_alloc(&mut self, len: Register) {
+        self.push_wide(1);
+        self.instructions
+            .push(Instruction::Store(len, Register::X2, 0));
+        self.instructions.push(Instruction::AddImm(
+            Register::X10,
+            len,
+            BYTES_HEADER_SIZE + 3,
+        ));
+        self.instructions
+            .push(Instruction::AndImm(Register::X10, Register::X10, -4));
+        self.generate_alloc_dynamic();
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, 0));
+        self.instructions
+            .push(Instruction::Store(Register::X5, Register::X10, 0));
+        self.pop_wide(1);
+    }
+
+    /// Copy X7 bytes from the address in X6 to the address in X28
+    ///
+    /// Clobbers X6, X7, X28 and X29; X28 ends just past the copied bytes.
+    fn generate_copy_bytes(&mut self) {
+        let (src, len, dst, byte) = (Register::X6, Register::X7, Register::X28, Register::X29);
+        let copy = self.generate_label("bytes_copy");
+        let end = self.generate_label("bytes_copy_end");
+
+        self.instructions.push(Instruction::Label(copy.clone()));
+        self.instructions
+            .push(Instruction::BranchEq(len, Register::X0, end.clone()));
+        self.instructions.push(Instruction::LoadByteU(byte, src, 0));
+        self.instructions.push(Instruction::StoreByte(byte, dst, 0));
+        self.instructions.push(Instruction::AddImm(src, src, 1));
+        self.instructions.push(Instruction::AddImm(dst, dst, 1));
+        self.instructions.push(Instruction::AddImm(len, len, -1));
+        self.instructions.push(Instruction::Jump(copy));
+        self.instructions.push(Instruction::Label(end));
+    }
+
+    /// Generate `len(bytes)`, `concat(a, b)` or `slice(bytes, start, end)`
+    /// into X5
+    ///
+    /// `slice` copies the bytes in [start, end) and reverts when the range
+    /// is out of bounds.
+    fn generate_bytes_builtin(
+        &mut self,
+        builtin: &str,
+        arity: usize,
+        args: &[Expr],
+    ) -> Result<Register, CodegenError> {
+        if args.len() != arity {
+            return Err(CodegenError::InvalidOperation(format!(
+                "{} expects {} arguments, found {}",
+                builtin,
+                arity,
+                args.len()
+            )));
+        }
+
+        if builtin == "len" {
+            let reg = self.generate_expr(&args[0])?;
+            self.instructions
+                .push(Instruction::Load(Register::X5, reg, 0));
+            return Ok(Register::X5);
+        }
+
+        // Arguments at 0.., the result pointer in the last word
+        let result_offset = (arity * 4) as i32;
+        self.push_wide(arity + 1);
+        for (i, arg) in args.iter().enumerate() {
+            let reg = self.generate_expr(arg)?;
+            self.instructions
+                .push(Instruction::Store(reg, Register::X2, (i * 4) as i32));
+        }
+
+        if builtin == "concat" {
+            self.instructions
+                .push(Instruction::Load(Register::X6, Register::X2, 0));
+            self.instructions
+                .push(Instruction::Load(Register::X6, Register::X6, 0));
+            self.instructions
+                .push(Instruction::Load(Register::X7, Register::X2, 4));
+            self.instructions
+                .push(Instruction::Load(Register::X7, Register::X7, 0));
+            self.instructions
+                .push(Instruction::Add(Register::X5, Register::X6, Register::X7));
+            self.generate_bytes_alloc(Register::X5);
+            self.instructions.push(Instruction::Store(
+                Register::X10,
+                Register::X2,
+                result_offset,
+            ));
+
+            self.instructions.push(Instruction::AddImm(
+                Register::X28,
+                Register::X10,
+                BYTES_HEADER_SIZE,
+            ));
+            for offset in [0, 4] {
+                self.instructions
+                    .push(Instruction::Load(Register::X6, Register::X2, offset));
+                self.instructions
+                    .push(Instruction::Load(Register::X7, Register::X6, 0));
+                self.instructions.push(Instruction::AddImm(
+                    Register::X6,
+                    Register::X6,
+                    BYTES_HEADER_SIZE,
+                ));
+                self.generate_copy_bytes();
+            }
+        } else {
+            let (start, end, len) = (Register::X6, Register::X7, Register::X28);
+            let out_of_bounds = self.generate_label("slice_out_of_bounds");
+            let in_bounds = self.generate_label("slice_in_bounds");
+
+            self.instructions
+                .push(Instruction::Load(start, Register::X2, 4));
+            self.instructions
+                .push(Instruction::Load(end, Register::X2, 8));
+            self.instructions
+                .push(Instruction::Load(Register::X5, Register::X2, 0));
+            self.instructions
+                .push(Instruction::Load(len, Register::X5, 0));
+            self.instructions
+                .push(Instruction::BranchLtU(end, start, out_of_bounds.clone()));
+            self.instructions
+                .push(Instruction::BranchGeU(len, end, in_bounds.clone()));
+            self.instructions.push(Instruction::Label(out_of_bounds));
+            self.generate_revert();
+            self.instructions.push(Instruction::Label(in_bounds));
+
+            self.instructions
+                .push(Instruction::Sub(Register::X5, end, start));
+            self.generate_bytes_alloc(Register::X5);
+            self.instructions.push(Instruction::Store(
+                Register::X10,
+                Register::X2,
+                result_offset,
+            ));
+
+            self.instructions.push(Instruction::AddImm(
+                Register::X28,
+                Register::X10,
+                BYTES_HEADER_SIZE,
+            ));
+            self.instructions
+                .push(Instruction::Load(Register::X7, Register::X10, 0));
+            self.instructions
+                .push(Instruction::Load(Register::X5, Register::X2, 0));
+            self.instructions
+                .push(Instruction::Load(Register::X6, Register::X2, 4));
+            self.instructions
+                .push(Instruction::Add(Register::X6, Register::X5, Register::X6));
+            self.instructions.push(Instruction::AddImm(
+                Register::X6,
+                Register::X6,
+                BYTES_HEADER_SIZE,
+            ));
+            self.generate_copy_bytes();
+        }
+
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, result_offset));
+        self.pop_wide(arity + 1);
+        Ok(Register::X5)
+    }
+
+    /// Generate `keccak256(bytes)` or `random(bytes)` as a `words`-word
+    /// value pushed onto the stack
+    fn generate_bytes_hash(
+        &mut self,
+        name: &str,
+        value: &Expr,
+        words: usize,
+    ) -> Result<(), CodegenError> {
+        let host_function = match name {
+            "random" => HostFunction::Random,
+            _ => HostFunction::Keccak256,
+        };
+        let reg = self.generate_expr(value)?;
+        self.push_wide(words);
+        self.instructions
+            .push(Instruction::Load(Register::X11, reg, 0));
+        self.instructions
+            .push(Instruction::AddImm(Register::X10, reg, BYTES_HEADER_SIZE));
+        self.instructions
+            .push(Instruction::Mv(Register::X12, Register::X2));
+        self.instructions
+            .push(Instruction::Li(Register::X17, host_function as i32));
+        self.instructions.push(Instruction::Ecall);
+        self.generate_zero_words(BYTES32_LEN / 4, words - BYTES32_LEN / 4);
+        Ok(())
+    }
+
+    /// Generate `to_bytes(value)` into X5: the little-endian bytes of a
+    /// word or wide value, all of its words
+    fn generate_to_bytes(&mut self, value: &Expr) -> Result<Register, CodegenError> {
+        let words = self.expr_width(value);
+        self.generate_wide_expr(value, words)?;
+        let len = (words * 4) as i32;
+        self.generate_alloc(BYTES_HEADER_SIZE + len);
+        self.instructions.push(Instruction::Li(Register::X5, len));
+        self.instructions
+            .push(Instruction::Store(Register::X5, Register::X10, 0));
+        for i in 0..words as i32 {
+            self.instructions
+                .push(Instruction::Load(Register::X5, Register::X2, i * 4));
+            self.instructions.push(Instruction::Store(
+                Register::X5,
+                Register::X10,
+                BYTES_HEADER_SIZE + i * 4,
+            ));
+        }
+        self.pop_wide(words);
+        self.instructions
+            .push(Instruction::Mv(Register::X5, Register::X10));
+        Ok(Register::X5)
+    }
+
+    /// Push `u256_at(data, offset)`, the `u256` in the 32 little-endian
+    /// bytes of `data` from `offset`, zero-extended to `words`
+    ///
+    /// Reverts unless all 32 bytes are in `data`.
+    fn generate_u256_at(
+        &mut self,
+        data: &Expr,
+        offset: &Expr,
+        words: usize,
+    ) -> Result<(), CodegenError> {
+        self.push_wide(words);
+        self.generate_zero_words(BYTES32_LEN / 4, words - BYTES32_LEN / 4);
+
+        // The data pointer and offset at 0 and 4
+        self.push_wide(2);
+        for (i, arg) in [data, offset].into_iter().enumerate() {
+            let reg = self.generate_expr(arg)?;
+            self.instructions
+                .push(Instruction::Store(reg, Register::X2, (i * 4) as i32));
+        }
+
+        let (src, len, dst, end) = (Register::X6, Register::X7, Register::X28, Register::X29);
+        let out_of_bounds = self.generate_label("u256_at_out_of_bounds");
+        let in_bounds = self.generate_label("u256_at_in_bounds");
+        self.instructions
+            .push(Instruction::Load(src, Register::X2, 0));
+        self.instructions.push(Instruction::Load(len, src, 0));
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, 4));
+        self.instructions
+            .push(Instruction::AddImm(end, Register::X5, BYTES32_LEN as i32));
+        self.instructions.push(Instruction::BranchLtU(
+            end,
+            Register::X5,
+            out_of_bounds.clone(),
+        ));
+        self.instructions
+            .push(Instruction::BranchGeU(len, end, in_bounds.clone()));
+        self.instructions.push(Instruction::Label(out_of_bounds));
+        self.generate_revert();
+        self.instructions.push(Instruction::Label(in_bounds));
+
+        self.instructions
+            .push(Instruction::Add(src, src, Register::X5));
+        self.instructions
+            .push(Instruction::AddImm(src, src, BYTES_HEADER_SIZE));
+        self.instructions
+            .push(Instruction::Li(len, BYTES32_LEN as i32));
+        self.instructions
+            .push(Instruction::AddImm(dst, Register::X2, 8));
+        self.generate_copy_bytes();
+        self.pop_wide(2);
+        Ok(())
+    }
+
+    /// Push the 32-byte account the host `function` reads, the caller or
+    /// the contract itself, zero-extended to `words`
+    fn generate_account(&mut self, function: HostFunction, words: usize) {
+        self.push_wide(words);
+        self.instructions
+            .push(Instruction::Mv(Register::X10, Register::X2));
+        self.instructions
+            .push(Instruction::Li(Register::X17, function as i32));
+        self.instructions.push(Instruction::Ecall);
+        self.generate_zero_words(BYTES32_LEN / 4, words - BYTES32_LEN / 4);
+    }
+
+    /// Generate `==` or `!=` on `Bytes` values into X5, comparing their
+    /// lengths and then their data
+    fn generate_bytes_equality(
+        &mut self,
+        operator: &BinaryOperator,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<Register, CodegenError> {
+        let negate = match operator {
+            BinaryOperator::Equal => false,
+            BinaryOperator::NotEqual => true,
+            _ => {
+                return Err(CodegenError::InvalidOperation(format!(
+                    "Bytes values only support == and !=, found {}",
+                    operator
+                )))
+            }
+        };
+
+        self.push_wide(2);
+        for (operand, offset) in [(left, 0), (right, 4)] {
+            let reg = self.generate_expr(operand)?;
+            self.instructions
+                .push(Instruction::Store(reg, Register::X2, offset));
+        }
+
+        let (a, b, len, byte_a, byte_b) = (
+            Register::X6,
+            Register::X7,
+            Register::X28,
+            Register::X29,
+            Register::X30,
+        );
+        let compare = self.generate_label("bytes_eq");
+        let equal = self.generate_label("bytes_eq_true");
+        let end = self.generate_label("bytes_eq_end");
+
+        self.instructions
+            .push(Instruction::Load(a, Register::X2, 0));
+        self.instructions
+            .push(Instruction::Load(b, Register::X2, 4));
+        self.instructions.push(Instruction::Load(len, a, 0));
+        self.instructions.push(Instruction::Load(byte_a, b, 0));
+        self.instructions.push(Instruction::Li(Register::X5, 0));
+        self.instructions
+            .push(Instruction::BranchNe(len, byte_a, end.clone()));
+        self.instructions
+            .push(Instruction::AddImm(a, a, BYTES_HEADER_SIZE));
+        self.instructions
+            .push(Instruction::AddImm(b, b, BYTES_HEADER_SIZE));
+
+        self.instructions.push(Instruction::Label(compare.clone()));
+        self.instructions
+            .push(Instruction::BranchEq(len, Register::X0, equal.clone()));
+        self.instructions.push(Instruction::LoadByteU(byte_a, a, 0));
+        self.instructions.push(Instruction::LoadByteU(byte_b, b, 0));
+        self.instructions
+            .push(Instruction::BranchNe(byte_a, byte_b, end.clone()));
+        self.instructions.push(Instruction::AddImm(a, a, 1));
+        self.instructions.push(Instruction::AddImm(b, b, 1));
+        self.instructions.push(Instruction::AddImm(len, len, -1));
+        self.instructions.push(Instruction::Jump(compare));
+        self.instructions.push(Instruction::Label(equal));
+        self.instructions.push(Instruction::Li(Register::X5, 1));
+        self.instructions.push(Instruction::Label(end));
+
+        if negate {
+            self.instructions
+                .push(Instruction::XorImm(Register::X5, Register::X5, 1));
+        }
+        self.pop_wide(2);
+        Ok(Register::X5)
+    }
+
+    /// Remember the integer type of a new word-sized local, and whether it
+    /// holds `Bytes`
+    fn record_local_kind(&mut self, name: Symbol, value: &Expr) {
+        if !self.locals.contains_key(&name) && !self.storage.contains_key(&name) {
+            let kind = self.expr_kind(value);
+            self.local_kinds.insert(name, kind);
+            if self.is_bytes_expr(value) {
+                self.bytes_locals.insert(name);
+            }
+        }
+    }
+
+    /// Integer type of a word-sized expression, `u24` unless it involves
+    /// signed or 32-bit values
+    fn expr_kind(&self, expr: &Expr) -> CheckedInt {
+        match expr {
+            Expr::Literal {
+                kind: LiteralKind::Int(_),
+                ..
+            } => CheckedInt::I24,
+            Expr::Variable { name, .. } => self
+                .local_kinds
+                .get(name)
+                .or_else(|| {
+                    (!self.locals.contains_key(name))
+                        .then(|| self.storage_kinds.get(name))
+                        .flatten()
+                })
+                .copied()
+                .unwrap_or(CheckedInt::U24),
+            Expr::BinaryOp {
+                left,
+                operator,
+                right,
+                ..
+            } => match operator {
+                BinaryOperator::Add
+                | BinaryOperator::Sub
+                | BinaryOperator::Mul
+                | BinaryOperator::Div
+                | BinaryOperator::Mod
+                | BinaryOperator::BitAnd
+                | BinaryOperator::BitOr
+                | BinaryOperator::BitXor => self.expr_kind(left).combine(self.expr_kind(right)),
+                BinaryOperator::BitShiftLeft | BinaryOperator::BitShiftRight => {
+                    self.expr_kind(left)
+                }
+                _ => CheckedInt::U24,
+            },
+            Expr::FunctionCall { function, .. } => {
+                if let Some((name, "get" | "pop")) = function.as_method_target() {
+                    if !self.locals.contains_key(&name) {
+                        if let Some(&kind) = self.storage_kinds.get(&name) {
+                            return kind;
+                        }
+                    }
+                }
+                match &**function {
+                    Expr::Variable { name, .. } => self
+                        .function_kinds
+                        .get(name)
+                        .copied()
+                        .unwrap_or(CheckedInt::U24),
+                    _ => CheckedInt::U24,
+                }
+            }
+            _ => CheckedInt::U24,
+        }
+    }
+
+    /// Evaluate the operands of a binary operation into X5 and X6
+    ///
+    /// The left operand is kept on the stack while the right one is
+    /// evaluated.
+    fn generate_operands(
+        &mut self,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<(Register, Register), CodegenError> {
+        let left_reg = self.generate_expr(left)?;
+        self.push_wide(1);
+        self.instructions
+            .push(Instruction::Store(left_reg, Register::X2, 0));
+
+        let right_reg = self.generate_expr(right)?;
+        self.instructions
+            .push(Instruction::Mv(Register::X6, right_reg));
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, 0));
+        self.pop_wide(1);
+
+        Ok((Register::X5, Register::X6))
+    }
+
+    /// Generate `X5 = X5 op X6`, reverting when the result leaves the range
+    /// of `kind` or on division by zero
+    fn generate_checked_arithmetic(&mut self, operator: &BinaryOperator, kind: CheckedInt) {
+        let (left, right, flag) = (Register::X5, Register::X6, Register::X7);
+        let full_word = kind.bits() == 32;
+
+        match (operator, kind.is_signed()) {
+            (BinaryOperator::Add, false) => {
+                self.instructions.push(Instruction::Add(left, left, right));
+                if full_word {
+                    // Carry out when the sum wraps below an operand
                     self.instructions
-                        .push(Instruction::Load(reg, Register::X2, offset));
-                    Ok(reg)
-                } else if let Some(function_label) = self.function_labels.get(name) {
-                    // Function pointer
-                    let reg = Register::X5; // Temporary register
+                        .push(Instruction::SetLessThanU(flag, left, right));
+                    self.generate_overflow_trap(flag);
+                } else {
+                    self.generate_range_trap(kind);
+                }
+            }
+            (BinaryOperator::Sub, false) => {
+                self.instructions
+                    .push(Instruction::SetLessThanU(flag, left, right));
+                self.generate_overflow_trap(flag);
+                self.instructions.push(Instruction::Sub(left, left, right));
+            }
+            (BinaryOperator::Mul, false) => {
+                self.instructions
+                    .push(Instruction::MulHighU(flag, left, right));
+                self.instructions.push(Instruction::Mul(left, left, right));
+                self.generate_overflow_trap(flag);
+                if !full_word {
+                    self.generate_range_trap(kind);
+                }
+            }
+            (BinaryOperator::Add | BinaryOperator::Sub, true) => {
+                self.instructions.push(match operator {
+                    BinaryOperator::Add => Instruction::Add(Register::X28, left, right),
+                    _ => Instruction::Sub(Register::X28, left, right),
+                });
+                if full_word {
+                    // Overflow when the result's sign differs from the left
+                    // operand's and from the (possibly negated) right one
                     self.instructions
-                        .push(Instruction::La(reg, function_label.clone()));
-                    Ok(reg)
+                        .push(Instruction::Xor(flag, left, Register::X28));
+                    if *operator == BinaryOperator::Add {
+                        self.instructions.push(Instruction::Xor(
+                            Register::X29,
+                            right,
+                            Register::X28,
+                        ));
+                    } else {
+                        self.instructions
+                            .push(Instruction::Xor(Register::X29, left, right));
+                    }
+                    self.instructions
+                        .push(Instruction::And(flag, flag, Register::X29));
+                    self.instructions
+                        .push(Instruction::ShiftRightImm(flag, flag, 31));
+                    self.generate_overflow_trap(flag);
+                    self.instructions.push(Instruction::Mv(left, Register::X28));
+                } else {
+                    self.instructions.push(Instruction::Mv(left, Register::X28));
+                    self.generate_range_trap(kind);
+                }
+            }
+            (BinaryOperator::Mul, true) => {
+                // The product fits a word when the upper word is the sign
+                // extension of the lower one
+                self.instructions
+                    .push(Instruction::MulHigh(flag, left, right));
+                self.instructions.push(Instruction::Mul(left, left, right));
+                self.instructions
+                    .push(Instruction::ShiftRightArithImm(Register::X28, left, 31));
+                self.instructions
+                    .push(Instruction::Xor(flag, flag, Register::X28));
+                self.generate_overflow_trap(flag);
+                if !full_word {
+                    self.generate_range_trap(kind);
+                }
+            }
+            (BinaryOperator::Div | BinaryOperator::Mod, signed) => {
+                self.instructions
+                    .push(Instruction::SetLessThanImmU(flag, right, 1));
+                self.generate_overflow_trap(flag);
+                if *operator == BinaryOperator::Mod {
+                    self.instructions.push(Instruction::Rem(left, left, right));
+                } else if signed && full_word {
+                    // MIN / -1 does not fit
+                    self.instructions
+                        .push(Instruction::Li(Register::X28, i32::MIN));
+                    self.instructions
+                        .push(Instruction::Xor(flag, left, Register::X28));
+                    self.instructions
+                        .push(Instruction::AddImm(Register::X28, right, 1));
+                    self.instructions
+                        .push(Instruction::Or(flag, flag, Register::X28));
+                    self.instructions
+                        .push(Instruction::SetLessThanImmU(flag, flag, 1));
+                    self.generate_overflow_trap(flag);
+                    self.instructions.push(Instruction::Div(left, left, right));
                 } else {
-                    Err(CodegenError::UndefinedVariable(name.clone()))
+                    self.instructions.push(Instruction::Div(left, left, right));
+                    if signed {
+                        self.generate_range_trap(kind);
+                    }
                 }
             }
-            Expr::Literal { kind, .. } => {
-                let reg = Register::X5; // Temporary register
-                match kind {
-                    LiteralKind::Uint(value) => {
-                        if *value <= i32::MAX as u32 {
-                            self.instructions.push(Instruction::Li(reg, *value as i32));
-                            Ok(reg)
-                        } else {
-                            Err(CodegenError::InvalidOperation(format!(
-                                "Literal value too large: {}",
-                                value
+            _ => unreachable!(),
+        }
+    }
+
+    /// Revert when the 24-bit result in X5 leaves the range of `kind`
+    fn generate_range_trap(&mut self, kind: CheckedInt) {
+        let spare_bits = 32 - kind.bits() as i32;
+        let flag = Register::X7;
+        if kind.is_signed() {
+            // In range when sign-extending the low bits gives the same value
+            self.instructions
+                .push(Instruction::ShiftLeftImm(flag, Register::X5, spare_bits));
+            self.instructions
+                .push(Instruction::ShiftRightArithImm(flag, flag, spare_bits));
+            self.instructions
+                .push(Instruction::Xor(flag, flag, Register::X5));
+        } else {
+            self.instructions.push(Instruction::ShiftRightImm(
+                flag,
+                Register::X5,
+                kind.bits() as i32,
+            ));
+        }
+        self.generate_overflow_trap(flag);
+    }
+
+    /// Wrap the unchecked result in X5 to the range of `kind`: 24-bit
+    /// results are zero-extended when unsigned and sign-extended when signed
+    fn generate_wrap(&mut self, kind: CheckedInt) {
+        let spare_bits = 32 - kind.bits() as i32;
+        if spare_bits == 0 {
+            return;
+        }
+        self.instructions.push(Instruction::ShiftLeftImm(
+            Register::X5,
+            Register::X5,
+            spare_bits,
+        ));
+        self.instructions.push(if kind.is_signed() {
+            Instruction::ShiftRightArithImm(Register::X5, Register::X5, spare_bits)
+        } else {
+            Instruction::ShiftRightImm(Register::X5, Register::X5, spare_bits)
+        });
+    }
+
+    /// Revert unless `flag` is zero
+    fn generate_overflow_trap(&mut self, flag: Register) {
+        let no_overflow = self.generate_label("no_overflow");
+        self.instructions.push(Instruction::BranchEq(
+            flag,
+            Register::X0,
+            no_overflow.clone(),
+        ));
+        self.generate_revert();
+        self.instructions.push(Instruction::Label(no_overflow));
+    }
+
+    /// Little-endian words of a wide integer, address or hash literal
+    fn literal_words(kind: &LiteralKind) -> Option<Vec<u32>> {
+        match kind {
+            LiteralKind::WideUint(value) => Some(value.limbs().to_vec()),
+            LiteralKind::Address(address) => Some(address.words().to_vec()),
+            LiteralKind::Hash(hash) => Some(hash.words().to_vec()),
+            _ => None,
+        }
+    }
+
+    /// Whether `name` is the `keccak256` or `random` builtin on `Bytes`
+    fn is_bytes_hash(&self, name: Symbol) -> bool {
+        matches!(name.as_str(), "keccak256" | "random") && !self.function_labels.contains_key(&name)
+    }
+
+    /// Whether an expression evaluates to a `Bytes` pointer
+    fn is_bytes_expr(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Literal {
+                kind: LiteralKind::Bytes(_),
+                ..
+            } => true,
+            Expr::Variable { name, .. } => self.bytes_locals.contains(name),
+            Expr::FunctionCall { function, .. } => match &**function {
+                Expr::Variable { name, .. } if self.function_labels.contains_key(name) => {
+                    self.bytes_functions.contains(name)
+                }
+                Expr::Variable { name, .. } => {
+                    matches!(name.as_str(), "concat" | "slice" | TO_BYTES)
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether `name` refers to the `ZERO_ADDRESS` constant
+    fn is_zero_address(&self, name: Symbol) -> bool {
+        name == ZERO_ADDRESS
+            && !self.locals.contains_key(&name)
+            && !self.local_widths.contains_key(&name)
+            && !self.storage.contains_key(&name)
+    }
+
+    /// The host function behind `name` if it is the `caller` or `address`
+    /// builtin, reading an account
+    fn account_builtin(&self, name: Symbol) -> Option<HostFunction> {
+        let function = match name.as_str() {
+            "caller" => HostFunction::GetCaller,
+            "address" => HostFunction::GetAddress,
+            _ => return None,
+        };
+        (!self.function_labels.contains_key(&name)).then_some(function)
+    }
+
+    /// Whether `name` is the `u256_at` builtin
+    fn is_u256_at(&self, name: Symbol) -> bool {
+        name == U256_AT && !self.function_labels.contains_key(&name)
+    }
+
+    /// Whether `name` is the `to_address` or `to_hash` builtin
+    fn is_conversion(&self, name: Symbol) -> bool {
+        matches!(name.as_str(), "to_address" | "to_hash")
+            && !self.function_labels.contains_key(&name)
+    }
+
+    /// Number of words of the keys of a storage map
+    fn storage_key_width(&self, name: &str) -> usize {
+        self.storage_key_parts(name.into()).iter().sum()
+    }
+
+    /// Number of words of each part of the keys of a storage map
+    fn storage_key_parts(&self, name: Symbol) -> Vec<usize> {
+        self.storage_key_parts
+            .get(&name)
+            .cloned()
+            .unwrap_or_else(|| vec![1])
+    }
+
+    /// Number of words of a local variable
+    fn local_width(&self, name: Symbol) -> usize {
+        self.local_widths.get(&name).copied().unwrap_or(1)
+    }
+
+    /// Number of words of a variable, which may be a storage value
+    fn variable_width(&self, name: Symbol) -> usize {
+        if self.locals.contains_key(&name) || self.local_widths.contains_key(&name) {
+            self.local_width(name)
+        } else {
+            self.storage_widths.get(&name).copied().unwrap_or(1)
+        }
+    }
+
+    /// Number of words of the value of an expression
+    ///
+    /// Arithmetic and bitwise operations take the width of their wider
+    /// operand; comparisons and everything else produce a word.
+    fn expr_width(&self, expr: &Expr) -> usize {
+        match expr {
+            Expr::Literal { kind, .. } => Self::literal_words(kind).map_or(1, |words| words.len()),
+            Expr::Variable { name, .. } if self.is_zero_address(*name) => BYTES32_LEN / 4,
+            Expr::Variable { name, .. } => self.variable_width(*name),
+            Expr::BinaryOp {
+                left,
+                operator,
+                right,
+                ..
+            } => match operator {
+                BinaryOperator::Add
+                | BinaryOperator::Sub
+                | BinaryOperator::Mul
+                | BinaryOperator::Div
+                | BinaryOperator::Mod
+                | BinaryOperator::BitAnd
+                | BinaryOperator::BitOr
+                | BinaryOperator::BitXor => self.expr_width(left).max(self.expr_width(right)),
+                BinaryOperator::BitShiftLeft | BinaryOperator::BitShiftRight => {
+                    self.expr_width(left)
+                }
+                _ => 1,
+            },
+            Expr::MapAccess { map, key, location } => self
+                .storage_map_call(map, "get", vec![(**key).clone()], location)
+                .map_or(1, |call| self.expr_width(&call)),
+            Expr::FunctionCall { function, .. } => {
+                if let Some((name, "get" | "pop")) = function.as_method_target() {
+                    if !self.locals.contains_key(&name) {
+                        return self.storage_widths.get(&name).copied().unwrap_or(1);
+                    }
+                }
+                match &**function {
+                    Expr::Variable { name, .. } if self.is_conversion(*name) => BYTES32_LEN / 4,
+                    Expr::Variable { name, .. } if self.is_bytes_hash(*name) => BYTES32_LEN / 4,
+                    Expr::Variable { name, .. } if self.is_u256_at(*name) => BYTES32_LEN / 4,
+                    Expr::Variable { name, .. } if self.account_builtin(*name).is_some() => {
+                        BYTES32_LEN / 4
+                    }
+                    Expr::Variable { name, .. } => {
+                        self.function_widths.get(name).copied().unwrap_or(1)
+                    }
+                    _ => 1,
+                }
+            }
+            _ => 1,
+        }
+    }
+
+    /// Reserve `words` words below the stack pointer
+    fn push_wide(&mut self, words: usize) {
+        let size = (words * 4) as i32;
+        self.instructions
+            .push(Instruction::AddImm(Register::X2, Register::X2, -size));
+        self.stack_adjust += size;
+    }
+
+    /// Release `words` words reserved by `push_wide`
+    fn pop_wide(&mut self, words: usize) {
+        let size = (words * 4) as i32;
+        self.instructions
+            .push(Instruction::AddImm(Register::X2, Register::X2, size));
+        self.stack_adjust -= size;
+    }
+
+    /// Zero `count` words starting at word `from` of the top of the stack
+    fn generate_zero_words(&mut self, from: usize, count: usize) {
+        for i in from..from + count {
+            self.instructions.push(Instruction::Store(
+                Register::X0,
+                Register::X2,
+                (i * 4) as i32,
+            ));
+        }
+    }
+
+    /// Evaluate an expression as a `words`-word integer pushed onto the stack
+    ///
+    /// The little-endian words start at `0(sp)`; narrower values are
+    /// zero-extended. The caller releases them with `pop_wide`.
+    fn generate_wide_expr(&mut self, expr: &Expr, words: usize) -> Result<(), CodegenError> {
+        let width = self.expr_width(expr);
+        if width > words {
+            return Err(CodegenError::InvalidOperation(format!(
+                "Cannot narrow a {}-bit value to {} bits",
+                width * 32,
+                words * 32
+            )));
+        }
+
+        match expr {
+            Expr::Literal { kind, .. } if width > 1 => {
+                let value = Self::literal_words(kind).unwrap_or_default();
+                self.push_wide(words);
+                for i in 0..words {
+                    match value.get(i) {
+                        Some(&limb) if limb != 0 => {
+                            self.instructions
+                                .push(Instruction::Li(Register::X5, limb as i32));
+                            self.instructions.push(Instruction::Store(
+                                Register::X5,
+                                Register::X2,
+                                (i * 4) as i32,
+                            ));
+                        }
+                        _ => self.generate_zero_words(i, 1),
+                    }
+                }
+                Ok(())
+            }
+            Expr::Variable { name, .. } if self.is_zero_address(*name) => {
+                self.push_wide(words);
+                self.generate_zero_words(0, words);
+                Ok(())
+            }
+            Expr::Variable { name, .. } if width > 1 => {
+                self.push_wide(words);
+                if let Some(&offset) = self.locals.get(name) {
+                    for i in 0..width as i32 {
+                        self.instructions.push(Instruction::Load(
+                            Register::X5,
+                            Register::X2,
+                            offset + self.stack_adjust + i * 4,
+                        ));
+                        self.instructions.push(Instruction::Store(
+                            Register::X5,
+                            Register::X2,
+                            i * 4,
+                        ));
+                    }
+                    self.generate_zero_words(width, words - width);
+                } else {
+                    let (key, _) = self.storage[name];
+                    self.generate_zero_words(0, words);
+                    self.generate_wide_storage_access(
+                        &key,
+                        StorageKind::Value,
+                        None,
+                        width,
+                        false,
+                    )?;
+                }
+                Ok(())
+            }
+            Expr::BinaryOp {
+                left,
+                operator,
+                right,
+                ..
+            } if width > 1 => self.generate_wide_binary_op(operator, left, right, words),
+            Expr::MapAccess { map, key, location } if width > 1 => {
+                let call = self
+                    .storage_map_call(map, "get", vec![(**key).clone()], location)
+                    .unwrap();
+                self.generate_wide_expr(&call, words)
+            }
+            Expr::FunctionCall { function, args, .. } if width > 1 => {
+                if let Some((name, "pop")) = function.as_method_target() {
+                    let (key, kind) = self.storage[&name];
+                    if kind != StorageKind::Vec || !args.is_empty() {
+                        return Err(CodegenError::InvalidOperation(format!(
+                            "Unknown storage method {}.pop with {} arguments",
+                            name,
+                            args.len()
+                        )));
+                    }
+                    self.push_wide(words);
+                    self.generate_zero_words(0, words);
+                    self.generate_wide_vec_end(&key, width, true);
+                    return Ok(());
+                }
+                if let Some((name, "get")) = function.as_method_target() {
+                    let (key, kind) = self.storage[&name];
+                    let entry = match (kind, args.as_slice()) {
+                        (StorageKind::Value, []) => None,
+                        (StorageKind::Map | StorageKind::Vec, [entry]) => Some(entry),
+                        _ => {
+                            return Err(CodegenError::InvalidOperation(format!(
+                                "{}.get expects {} arguments, found {}",
+                                name,
+                                (kind != StorageKind::Value) as usize,
+                                args.len()
                             )))
                         }
+                    };
+                    let parts = self.storage_key_parts(name);
+                    let entry = entry.map(|entry| (entry, parts.as_slice()));
+                    self.push_wide(words);
+                    self.generate_zero_words(0, words);
+                    return self.generate_wide_storage_access(&key, kind, entry, width, false);
+                }
+
+                // Conversions between addresses, hashes and words
+                if let (Expr::Variable { name, .. }, [value]) = (&**function, args.as_slice()) {
+                    if self.is_conversion(*name) {
+                        return self.generate_wide_expr(value, words);
                     }
-                    LiteralKind::Int(value) => {
-                        self.instructions.push(Instruction::Li(reg, *value));
-                        Ok(reg)
+                    if self.is_bytes_hash(*name) {
+                        self.generate_bytes_hash(name, value, words)?;
+                        return Ok(());
                     }
-                    LiteralKind::Bool(value) => {
-                        self.instructions
-                            .push(Instruction::Li(reg, if *value { 1 } else { 0 }));
-                        Ok(reg)
+                }
+                if let (Expr::Variable { name, .. }, [data, offset]) =
+                    (&**function, args.as_slice())
+                {
+                    if self.is_u256_at(*name) {
+                        return self.generate_u256_at(data, offset, words);
                     }
-                    // For brevity, not implementing all literal types
-                    _ => Err(CodegenError::UnsupportedFeature(
-                        "Literal type not yet implemented".to_string(),
-                    )),
+                }
+                if let (Expr::Variable { name, .. }, []) = (&**function, args.as_slice()) {
+                    if let Some(function) = self.account_builtin(*name) {
+                        self.generate_account(function, words);
+                        return Ok(());
+                    }
+                }
+
+                // Wide results are returned in a0..
+                let name = match &**function {
+                    Expr::Variable { name, .. } => name,
+                    _ => unreachable!(),
+                };
+                self.generate_direct_call(*name, args)?;
+                self.push_wide(words);
+                for (i, reg) in Register::arg_registers()
+                    .into_iter()
+                    .take(width)
+                    .enumerate()
+                {
+                    self.instructions
+                        .push(Instruction::Store(reg, Register::X2, (i * 4) as i32));
+                }
+                self.generate_zero_words(width, words - width);
+                Ok(())
+            }
+            _ => {
+                let reg = self.generate_expr(expr)?;
+                self.push_wide(words);
+                self.instructions
+                    .push(Instruction::Store(reg, Register::X2, 0));
+                self.generate_zero_words(1, words - 1);
+                Ok(())
+            }
+        }
+    }
+
+    /// Assign a wide value to a local or a storage value
+    fn generate_wide_assignment(&mut self, name: Symbol, value: &Expr) -> Result<(), CodegenError> {
+        let words = self.variable_width(name);
+        self.generate_wide_expr(value, words)?;
+
+        if let Some(&offset) = self.locals.get(&name) {
+            for i in 0..words as i32 {
+                self.instructions
+                    .push(Instruction::Load(Register::X5, Register::X2, i * 4));
+                self.instructions.push(Instruction::Store(
+                    Register::X5,
+                    Register::X2,
+                    offset + self.stack_adjust + i * 4,
+                ));
+            }
+        } else {
+            let (key, kind) = self.storage[&name];
+            if kind != StorageKind::Value {
+                return Err(CodegenError::InvalidOperation(format!(
+                    "Storage collection {} can only be modified through its methods",
+                    name
+                )));
+            }
+            self.generate_wide_storage_access(&key, StorageKind::Value, None, words, true)?;
+        }
+
+        self.pop_wide(words);
+        Ok(())
+    }
+
+    /// Generate a wide arithmetic or bitwise operation
+    ///
+    /// Both operands are pushed and combined into the left one, so the
+    /// result is left on the stack like any other wide expression. Addition
+    /// and subtraction propagate carries and borrows between words with
+    /// `sltu`, multiplication is the truncated schoolbook product using
+    /// `mul`/`mulhu`, and division and remainder use shift-subtract long
+    /// division, reverting on a zero divisor.
+    fn generate_wide_binary_op(
+        &mut self,
+        operator: &BinaryOperator,
+        left: &Expr,
+        right: &Expr,
+        words: usize,
+    ) -> Result<(), CodegenError> {
+        self.generate_wide_expr(left, words)?;
+        self.generate_wide_expr(right, words)?;
+
+        // Right operand at 0(sp), left operand and result above it
+        let size = (words * 4) as i32;
+        let left_word = |i: usize| size + (i * 4) as i32;
+        let right_word = |i: usize| (i * 4) as i32;
+
+        match operator {
+            BinaryOperator::Add => {
+                for i in 0..words {
+                    self.instructions.push(Instruction::Load(
+                        Register::X5,
+                        Register::X2,
+                        left_word(i),
+                    ));
+                    self.instructions.push(Instruction::Load(
+                        Register::X6,
+                        Register::X2,
+                        right_word(i),
+                    ));
+                    self.generate_add_with_carry(i == 0);
+                    self.instructions.push(Instruction::Store(
+                        Register::X5,
+                        Register::X2,
+                        left_word(i),
+                    ));
+                }
+                if self.checked {
+                    self.generate_overflow_trap(Register::X7);
+                }
+            }
+            BinaryOperator::Sub => {
+                self.instructions.push(Instruction::Li(Register::X7, 0));
+                for i in 0..words {
+                    self.instructions.push(Instruction::Load(
+                        Register::X5,
+                        Register::X2,
+                        left_word(i),
+                    ));
+                    self.instructions.push(Instruction::Load(
+                        Register::X6,
+                        Register::X2,
+                        right_word(i),
+                    ));
+                    self.generate_sub_with_borrow();
+                    self.instructions.push(Instruction::Store(
+                        Register::X5,
+                        Register::X2,
+                        left_word(i),
+                    ));
+                }
+                if self.checked {
+                    self.generate_overflow_trap(Register::X7);
+                }
+            }
+            BinaryOperator::Mul => self.generate_wide_mul(words),
+            BinaryOperator::Div | BinaryOperator::Mod => {
+                self.generate_wide_divmod(words, *operator == BinaryOperator::Mod)
+            }
+            BinaryOperator::BitAnd | BinaryOperator::BitOr | BinaryOperator::BitXor => {
+                for i in 0..words {
+                    self.instructions.push(Instruction::Load(
+                        Register::X5,
+                        Register::X2,
+                        left_word(i),
+                    ));
+                    self.instructions.push(Instruction::Load(
+                        Register::X6,
+                        Register::X2,
+                        right_word(i),
+                    ));
+                    self.instructions.push(match operator {
+                        BinaryOperator::BitAnd => {
+                            Instruction::And(Register::X5, Register::X5, Register::X6)
+                        }
+                        BinaryOperator::BitOr => {
+                            Instruction::Or(Register::X5, Register::X5, Register::X6)
+                        }
+                        _ => Instruction::Xor(Register::X5, Register::X5, Register::X6),
+                    });
+                    self.instructions.push(Instruction::Store(
+                        Register::X5,
+                        Register::X2,
+                        left_word(i),
+                    ));
+                }
+            }
+            _ => {
+                return Err(CodegenError::UnsupportedFeature(format!(
+                    "Operator {:?} on {}-bit values",
+                    operator,
+                    words * 32
+                )))
+            }
+        }
+
+        self.pop_wide(words);
+        Ok(())
+    }
+
+    /// X5 = X5 + X6 + carry (X7), leaving the carry out in X7
+    fn generate_add_with_carry(&mut self, first: bool) {
+        self.instructions
+            .push(Instruction::Add(Register::X5, Register::X5, Register::X6));
+        if first {
+            self.instructions.push(Instruction::SetLessThanU(
+                Register::X7,
+                Register::X5,
+                Register::X6,
+            ));
+            return;
+        }
+        self.instructions.push(Instruction::SetLessThanU(
+            Register::X28,
+            Register::X5,
+            Register::X6,
+        ));
+        self.instructions
+            .push(Instruction::Add(Register::X5, Register::X5, Register::X7));
+        self.instructions.push(Instruction::SetLessThanU(
+            Register::X29,
+            Register::X5,
+            Register::X7,
+        ));
+        self.instructions
+            .push(Instruction::Or(Register::X7, Register::X28, Register::X29));
+    }
+
+    /// X5 = X5 - X6 - borrow (X7), leaving the borrow out in X7
+    fn generate_sub_with_borrow(&mut self) {
+        self.instructions.push(Instruction::SetLessThanU(
+            Register::X28,
+            Register::X5,
+            Register::X6,
+        ));
+        self.instructions
+            .push(Instruction::Sub(Register::X5, Register::X5, Register::X6));
+        self.instructions.push(Instruction::SetLessThanU(
+            Register::X29,
+            Register::X5,
+            Register::X7,
+        ));
+        self.instructions
+            .push(Instruction::Sub(Register::X5, Register::X5, Register::X7));
+        self.instructions
+            .push(Instruction::Or(Register::X7, Register::X28, Register::X29));
+    }
+
+    /// Set X7 to the borrow of `a - b` for the wide values at the given
+    /// stack offsets, without storing the difference
+    fn generate_wide_borrow(&mut self, a: i32, b: i32, words: usize) {
+        self.instructions.push(Instruction::Li(Register::X7, 0));
+        for i in 0..words as i32 {
+            self.instructions
+                .push(Instruction::Load(Register::X5, Register::X2, a + i * 4));
+            self.instructions
+                .push(Instruction::Load(Register::X6, Register::X2, b + i * 4));
+            self.generate_sub_with_borrow();
+        }
+    }
+
+    /// Multiply the two wide operands on the stack into the left one
+    ///
+    /// When checked, the product overflows if a row carries past the top
+    /// word or two nonzero words meet at or above it.
+    fn generate_wide_mul(&mut self, words: usize) {
+        // Product at 0(sp), right operand above it, left operand on top
+        self.push_wide(words);
+        self.generate_zero_words(0, words);
+        let size = (words * 4) as i32;
+        let word = |i: usize| (i * 4) as i32;
+
+        // Overflow flag
+        self.instructions.push(Instruction::Li(Register::X31, 0));
+        for i in 0..words {
+            self.instructions.push(Instruction::Load(
+                Register::X30,
+                Register::X2,
+                2 * size + word(i),
+            ));
+            self.instructions.push(Instruction::Li(Register::X7, 0));
+            for j in 0..words - i {
+                // (hi, lo) = left[i] * right[j] + product[i + j] + carry
+                self.instructions.push(Instruction::Load(
+                    Register::X6,
+                    Register::X2,
+                    size + word(j),
+                ));
+                self.instructions
+                    .push(Instruction::Mul(Register::X5, Register::X30, Register::X6));
+                self.instructions.push(Instruction::MulHighU(
+                    Register::X28,
+                    Register::X30,
+                    Register::X6,
+                ));
+                self.instructions
+                    .push(Instruction::Load(Register::X29, Register::X2, word(i + j)));
+                self.instructions
+                    .push(Instruction::Add(Register::X5, Register::X5, Register::X29));
+                self.instructions.push(Instruction::SetLessThanU(
+                    Register::X29,
+                    Register::X5,
+                    Register::X29,
+                ));
+                self.instructions.push(Instruction::Add(
+                    Register::X28,
+                    Register::X28,
+                    Register::X29,
+                ));
+                self.instructions
+                    .push(Instruction::Add(Register::X5, Register::X5, Register::X7));
+                self.instructions.push(Instruction::SetLessThanU(
+                    Register::X29,
+                    Register::X5,
+                    Register::X7,
+                ));
+                self.instructions.push(Instruction::Add(
+                    Register::X7,
+                    Register::X28,
+                    Register::X29,
+                ));
+                self.instructions
+                    .push(Instruction::Store(Register::X5, Register::X2, word(i + j)));
+            }
+            self.instructions
+                .push(Instruction::Or(Register::X31, Register::X31, Register::X7));
+
+            // left[i] * right[j] for i + j >= words
+            if i > 0 {
+                self.instructions.push(Instruction::Li(Register::X6, 0));
+                for j in words - i..words {
+                    self.instructions.push(Instruction::Load(
+                        Register::X28,
+                        Register::X2,
+                        size + word(j),
+                    ));
+                    self.instructions.push(Instruction::Or(
+                        Register::X6,
+                        Register::X6,
+                        Register::X28,
+                    ));
+                }
+                self.instructions.push(Instruction::SetLessThanU(
+                    Register::X6,
+                    Register::X0,
+                    Register::X6,
+                ));
+                self.instructions.push(Instruction::SetLessThanU(
+                    Register::X5,
+                    Register::X0,
+                    Register::X30,
+                ));
+                self.instructions
+                    .push(Instruction::And(Register::X5, Register::X5, Register::X6));
+                self.instructions
+                    .push(Instruction::Or(Register::X31, Register::X31, Register::X5));
+            }
+        }
+        if self.checked {
+            self.generate_overflow_trap(Register::X31);
+        }
+
+        self.generate_copy_words(0, 2 * size, words);
+        self.pop_wide(words);
+    }
+
+    /// Divide the left wide operand on the stack by the right one, leaving
+    /// the quotient or the remainder in the left operand
+    fn generate_wide_divmod(&mut self, words: usize, remainder: bool) {
+        let size = (words * 4) as i32;
+
+        // Revert on a zero divisor
+        let nonzero = self.generate_label("wide_div_nonzero");
+        self.instructions.push(Instruction::Li(Register::X7, 0));
+        for i in 0..words as i32 {
+            self.instructions
+                .push(Instruction::Load(Register::X5, Register::X2, i * 4));
+            self.instructions
+                .push(Instruction::Or(Register::X7, Register::X7, Register::X5));
+        }
+        self.instructions.push(Instruction::BranchNe(
+            Register::X7,
+            Register::X0,
+            nonzero.clone(),
+        ));
+        self.generate_revert();
+        self.instructions.push(Instruction::Label(nonzero));
+
+        // Remainder at 0(sp), divisor above it, dividend on top. The dividend
+        // is shifted into the remainder one bit at a time while the quotient
+        // bits are shifted into the freed low bits of the dividend.
+        self.push_wide(words);
+        self.generate_zero_words(0, words);
+        let remainder_at = 0;
+        let divisor_at = size;
+        let dividend_at = 2 * size;
+
+        let loop_label = self.generate_label("wide_div_loop");
+        let skip_label = self.generate_label("wide_div_skip");
+        self.instructions
+            .push(Instruction::Li(Register::X31, (words * 32) as i32));
+        self.instructions
+            .push(Instruction::Label(loop_label.clone()));
+
+        // (remainder, dividend) <<= 1
+        self.instructions.push(Instruction::Li(Register::X7, 0));
+        for base in [dividend_at, remainder_at] {
+            for i in 0..words as i32 {
+                self.instructions
+                    .push(Instruction::Load(Register::X5, Register::X2, base + i * 4));
+                self.instructions
+                    .push(Instruction::ShiftRightImm(Register::X6, Register::X5, 31));
+                self.instructions
+                    .push(Instruction::ShiftLeftImm(Register::X5, Register::X5, 1));
+                self.instructions
+                    .push(Instruction::Or(Register::X5, Register::X5, Register::X7));
+                self.instructions
+                    .push(Instruction::Mv(Register::X7, Register::X6));
+                self.instructions.push(Instruction::Store(
+                    Register::X5,
+                    Register::X2,
+                    base + i * 4,
+                ));
+            }
+        }
+
+        // if remainder >= divisor { remainder -= divisor; quotient |= 1 }
+        self.generate_wide_borrow(remainder_at, divisor_at, words);
+        self.instructions.push(Instruction::BranchNe(
+            Register::X7,
+            Register::X0,
+            skip_label.clone(),
+        ));
+        for i in 0..words as i32 {
+            self.instructions.push(Instruction::Load(
+                Register::X5,
+                Register::X2,
+                remainder_at + i * 4,
+            ));
+            self.instructions.push(Instruction::Load(
+                Register::X6,
+                Register::X2,
+                divisor_at + i * 4,
+            ));
+            self.generate_sub_with_borrow();
+            self.instructions.push(Instruction::Store(
+                Register::X5,
+                Register::X2,
+                remainder_at + i * 4,
+            ));
+        }
+        self.instructions
+            .push(Instruction::Load(Register::X5, Register::X2, dividend_at));
+        self.instructions
+            .push(Instruction::OrImm(Register::X5, Register::X5, 1));
+        self.instructions
+            .push(Instruction::Store(Register::X5, Register::X2, dividend_at));
+        self.instructions.push(Instruction::Label(skip_label));
+
+        self.instructions
+            .push(Instruction::AddImm(Register::X31, Register::X31, -1));
+        self.instructions.push(Instruction::BranchNe(
+            Register::X31,
+            Register::X0,
+            loop_label,
+        ));
+
+        if remainder {
+            self.generate_copy_words(remainder_at, dividend_at, words);
+        }
+        self.pop_wide(words);
+    }
+
+    /// Copy `words` words between stack offsets
+    fn generate_copy_words(&mut self, from: i32, to: i32, words: usize) {
+        for i in 0..words as i32 {
+            self.instructions
+                .push(Instruction::Load(Register::X5, Register::X2, from + i * 4));
+            self.instructions
+                .push(Instruction::Store(Register::X5, Register::X2, to + i * 4));
+        }
+    }
+
+    /// Compare two wide operands, leaving 1 or 0 in X5
+    fn generate_wide_comparison(
+        &mut self,
+        operator: &BinaryOperator,
+        left: &Expr,
+        right: &Expr,
+        words: usize,
+    ) -> Result<Register, CodegenError> {
+        self.generate_wide_expr(left, words)?;
+        self.generate_wide_expr(right, words)?;
+        let (left_at, right_at) = ((words * 4) as i32, 0);
+
+        match operator {
+            BinaryOperator::Equal | BinaryOperator::NotEqual => {
+                // OR together the XOR of every pair of words
+                self.instructions.push(Instruction::Li(Register::X7, 0));
+                for i in 0..words as i32 {
+                    self.instructions.push(Instruction::Load(
+                        Register::X5,
+                        Register::X2,
+                        left_at + i * 4,
+                    ));
+                    self.instructions.push(Instruction::Load(
+                        Register::X6,
+                        Register::X2,
+                        right_at + i * 4,
+                    ));
+                    self.instructions.push(Instruction::Xor(
+                        Register::X5,
+                        Register::X5,
+                        Register::X6,
+                    ));
+                    self.instructions.push(Instruction::Or(
+                        Register::X7,
+                        Register::X7,
+                        Register::X5,
+                    ));
+                }
+                if *operator == BinaryOperator::Equal {
+                    self.instructions.push(Instruction::SetLessThanImmU(
+                        Register::X5,
+                        Register::X7,
+                        1,
+                    ));
+                } else {
+                    self.instructions.push(Instruction::SetLessThanU(
+                        Register::X5,
+                        Register::X0,
+                        Register::X7,
+                    ));
                 }
             }
-            Expr::BinaryOp {
-                left,
-                operator,
-                right,
-                ..
-            } => {
-                let left_reg = self.generate_expr(left)?;
-                let right_reg = self.generate_expr(right)?;
-                let result_reg = Register::X5; // Temporary register
-
+            BinaryOperator::Less
+            | BinaryOperator::Greater
+            | BinaryOperator::LessEqual
+            | BinaryOperator::GreaterEqual => {
+                // a < b exactly when a - b borrows
+                let (a, b) = match operator {
+                    BinaryOperator::Less | BinaryOperator::GreaterEqual => (left_at, right_at),
+                    _ => (right_at, left_at),
+                };
+                self.generate_wide_borrow(a, b, words);
                 match operator {
-                    BinaryOperator::Add => {
-                        self.instructions
-                            .push(Instruction::Add(result_reg, left_reg, right_reg));
-                        Ok(result_reg)
-                    }
-                    BinaryOperator::Sub => {
-                        self.instructions
-                            .push(Instruction::Sub(result_reg, left_reg, right_reg));
-                        Ok(result_reg)
-                    }
-                    BinaryOperator::Mul => {
-                        self.instructions
-                            .push(Instruction::Mul(result_reg, left_reg, right_reg));
-                        Ok(result_reg)
-                    }
-                    BinaryOperator::Div => {
-                        self.instructions
-                            .push(Instruction::Div(result_reg, left_reg, right_reg));
-                        Ok(result_reg)
-                    }
-                    BinaryOperator::Mod => {
-                        self.instructions
-                            .push(Instruction::Rem(result_reg, left_reg, right_reg));
-                        Ok(result_reg)
-                    }
-                    BinaryOperator::BitAnd => {
-                        self.instructions
-                            .push(Instruction::And(result_reg, left_reg, right_reg));
-                        Ok(result_reg)
-                    }
-                    BinaryOperator::BitOr => {
-                        self.instructions
-                            .push(Instruction::Or(result_reg, left_reg, right_reg));
-                        Ok(result_reg)
-                    }
-                    BinaryOperator::BitXor => {
-                        self.instructions
-                            .push(Instruction::Xor(result_reg, left_reg, right_reg));
-                        Ok(result_reg)
-                    }
-                    BinaryOperator::Equal => {
-                        // x == y can be implemented as !(x - y)
-                        self.instructions
-                            .push(Instruction::Sub(result_reg, left_reg, right_reg));
-                        self.instructions
-                            .push(Instruction::SetLessThanImm(result_reg, result_reg, 1)); // 1 if x - y < 1 (i.e., x - y <= 0)
-                        self.instructions.push(Instruction::SetLessThanImm(
-                            Register::X6,
-                            Register::X0,
-                            1,
-                        )); // 1 if 0 < 1 (always true)
-                        self.instructions.push(Instruction::Xor(
-                            result_reg,
-                            result_reg,
-                            Register::X6,
-                        )); // Invert the result
-                        Ok(result_reg)
-                    }
-                    BinaryOperator::NotEqual => {
-                        // x != y can be implemented as (x - y) != 0
-                        self.instructions
-                            .push(Instruction::Sub(result_reg, left_reg, right_reg));
-                        self.instructions.push(Instruction::SetLessThanImm(
-                            result_reg,
-                            Register::X0,
-                            1,
-                        )); // 1 if 0 < 1 (always true)
-                        self.instructions
-                            .push(Instruction::And(result_reg, result_reg, right_reg)); // 1 if x - y != 0
-                        Ok(result_reg)
-                    }
-                    BinaryOperator::Less => {
-                        self.instructions
-                            .push(Instruction::SetLessThan(result_reg, left_reg, right_reg));
-                        Ok(result_reg)
-                    }
-                    BinaryOperator::LessEqual => {
-                        // x <= y can be implemented as !(y < x)
-                        self.instructions
-                            .push(Instruction::SetLessThan(result_reg, right_reg, left_reg));
-                        self.instructions.push(Instruction::SetLessThanImm(
-                            Register::X6,
-                            Register::X0,
-                            1,
-                        )); // 1 if 0 < 1 (always true)
-                        self.instructions.push(Instruction::Xor(
-                            result_reg,
-                            result_reg,
-                            Register::X6,
-                        )); // Invert the result
-                        Ok(result_reg)
-                    }
-                    BinaryOperator::Greater => {
-                        self.instructions
-                            .push(Instruction::SetLessThan(result_reg, right_reg, left_reg));
-                        Ok(result_reg)
-                    }
-                    BinaryOperator::GreaterEqual => {
-                        // x >= y can be implemented as !(x < y)
-                        self.instructions
-                            .push(Instruction::SetLessThan(result_reg, left_reg, right_reg));
-                        self.instructions.push(Instruction::SetLessThanImm(
-                            Register::X6,
-                            Register::X0,
-                            1,
-                        )); // 1 if 0 < 1 (always true)
-                        self.instructions.push(Instruction::Xor(
-                            result_reg,
-                            result_reg,
-                            Register::X6,
-                        )); // Invert the result
-                        Ok(result_reg)
-                    }
-                    // For brevity, not implementing all operators
-                    _ => Err(CodegenError::UnsupportedFeature(
-                        "Binary operator not yet implemented".to_string(),
-                    )),
+                    BinaryOperator::Less | BinaryOperator::Greater => self
+                        .instructions
+                        .push(Instruction::Mv(Register::X5, Register::X7)),
+                    _ => self
+                        .instructions
+                        .push(Instruction::XorImm(Register::X5, Register::X7, 1)),
                 }
             }
-            Expr::FunctionCall { function, args, .. } => {
-                // For simplicity, only handle direct function calls
-                if let Expr::Variable { name, .. } = &**function {
-                    let function_label = self.function_labels.get(name).cloned();
-                    if let Some(function_label) = function_label {
-                        // Load arguments into argument registers
-                        let arg_registers = Register::arg_registers();
-                        for (i, arg) in args.iter().enumerate() {
-                            if i >= arg_registers.len() {
-                                return Err(CodegenError::InvalidOperation(
-                                    "Too many arguments in function call".to_string(),
-                                ));
-                            }
+            _ => {
+                return Err(CodegenError::UnsupportedFeature(format!(
+                    "Operator {:?} on {}-bit values",
+                    operator,
+                    words * 32
+                )))
+            }
+        }
 
-                            let arg_reg = self.generate_expr(arg)?;
-                            self.instructions
-                                .push(Instruction::Mv(arg_registers[i], arg_reg));
-                        }
+        self.pop_wide(2 * words);
+        Ok(Register::X5)
+    }
 
-                        // Call the function
-                        self.instructions.push(Instruction::JumpAndLink(
-                            Register::X1,
-                            function_label.clone(),
-                        ));
+    /// Generate a method call on a storage field holding wide integers,
+    /// where the method produces a word or nothing
+    fn generate_wide_storage_method(
+        &mut self,
+        name: &str,
+        key: &[u8; 32],
+        kind: StorageKind,
+        method: &str,
+        args: &[Expr],
+    ) -> Result<Register, CodegenError> {
+        let words = self.variable_width(name.into());
+        match (kind, method, args) {
+            (StorageKind::Value, "set", [value]) => {
+                self.generate_wide_expr(value, words)?;
+                self.generate_wide_storage_access(key, kind, None, words, true)?;
+                self.pop_wide(words);
+                Ok(Register::X0)
+            }
+            (StorageKind::Map, "insert", [entry, value]) => {
+                let parts = self.storage_key_parts(name.into());
+                self.generate_wide_expr(value, words)?;
+                self.generate_wide_storage_access(key, kind, Some((entry, &parts)), words, true)?;
+                self.pop_wide(words);
+                Ok(Register::X0)
+            }
+            (StorageKind::Map, "contains", [entry]) => {
+                let parts = self.storage_key_parts(name.into());
+                self.push_wide(words);
+                self.generate_wide_storage_access(key, kind, Some((entry, &parts)), words, false)?;
+                // StorageGet returns 0 in a0 when the entry exists
+                self.instructions.push(Instruction::SetLessThanImmU(
+                    Register::X5,
+                    Register::X10,
+                    1,
+                ));
+                self.pop_wide(words);
+                Ok(Register::X5)
+            }
+            (StorageKind::Vec, "set", [index, value]) => {
+                self.generate_wide_expr(value, words)?;
+                self.generate_wide_storage_access(key, kind, Some((index, &[1])), words, true)?;
+                self.pop_wide(words);
+                Ok(Register::X0)
+            }
+            (StorageKind::Vec, "push", [value]) => {
+                self.generate_wide_expr(value, words)?;
+                self.generate_wide_vec_end(key, words, false);
+                self.pop_wide(words);
+                Ok(Register::X0)
+            }
+            (StorageKind::Vec, "pop", []) => {
+                self.push_wide(words);
+                self.generate_wide_vec_end(key, words, true);
+                self.pop_wide(words);
+                Ok(Register::X0)
+            }
+            _ => Err(CodegenError::InvalidOperation(format!(
+                "Unknown storage method {}.{} with {} arguments",
+                name,
+                method,
+                args.len()
+            ))),
+        }
+    }
 
-                        // Result is in a0 (x10)
-                        Ok(Register::X10)
-                    } else {
-                        Err(CodegenError::UndefinedVariable(name.clone()))
-                    }
-                } else {
-                    Err(CodegenError::InvalidOperation(
-                        "Function call with non-variable target".to_string(),
-                    ))
+    /// Read or write the `words`-word value at `0(sp)` under a storage
+    /// value, or under the entry of a storage map for an entry key with
+    /// parts of the given numbers of words, or of a storage vector for an
+    /// index
+    ///
+    /// Reads leave the value untouched when the entry is missing; indices
+    /// past the end of a vector revert.
+    fn generate_wide_storage_access(
+        &mut self,
+        key: &[u8; 32],
+        kind: StorageKind,
+        entry: Option<(&Expr, &[usize])>,
+        words: usize,
+        write: bool,
+    ) -> Result<(), CodegenError> {
+        let wide_entry = matches!(entry, Some((_, parts)) if parts.iter().sum::<usize>() > 1);
+        if let Some((entry, parts)) = entry.filter(|_| wide_entry) {
+            self.generate_wide_entry_key(key, entry, parts)?;
+        }
+
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            -COLLECTION_SCRATCH_SIZE,
+        ));
+        self.stack_adjust += COLLECTION_SCRATCH_SIZE;
+
+        if wide_entry {
+            self.generate_copy_words(COLLECTION_SCRATCH_SIZE, 0, BYTES32_LEN / 4);
+        } else {
+            if let Some((entry, _)) = entry {
+                let entry_reg = self.generate_expr(entry)?;
+                self.instructions.push(Instruction::Store(
+                    entry_reg,
+                    Register::X2,
+                    COLLECTION_ARGS_OFFSET,
+                ));
+            }
+            self.generate_storage_key(key);
+            if entry.is_some() {
+                if kind == StorageKind::Vec {
+                    self.generate_collection_get();
+                    self.generate_bounds_check(COLLECTION_ARGS_OFFSET);
                 }
+                self.generate_derive_entry_key(COLLECTION_ARGS_OFFSET);
             }
-            // For brevity, not implementing all expression types
-            _ => Err(CodegenError::UnsupportedFeature(
-                "Expression type not yet implemented".to_string(),
-            )),
         }
+
+        // The value follows the scratch area and the entry key, if any
+        let value_at = COLLECTION_SCRATCH_SIZE + if wide_entry { BYTES32_LEN as i32 } else { 0 };
+        self.instructions
+            .push(Instruction::AddImm(Register::X12, Register::X2, value_at));
+        if write {
+            self.instructions
+                .push(Instruction::Li(Register::X13, (words * 4) as i32));
+            self.generate_collection_host_call(HostFunction::StorageSet);
+        } else {
+            self.instructions
+                .push(Instruction::AddImm(Register::X13, Register::X2, 40));
+            self.generate_collection_host_call(HostFunction::StorageGet);
+        }
+
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            COLLECTION_SCRATCH_SIZE,
+        ));
+        self.stack_adjust -= COLLECTION_SCRATCH_SIZE;
+        if wide_entry {
+            self.pop_wide(BYTES32_LEN / 4);
+        }
+        Ok(())
+    }
+
+    /// Append the `words`-word value at `0(sp)` to a storage vector, or
+    /// with `pop`, move its last element there, reverting when it is empty
+    ///
+    /// The length is a word under the root, as for vectors of words.
+    fn generate_wide_vec_end(&mut self, key: &[u8; 32], words: usize, pop: bool) {
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            -COLLECTION_SCRATCH_SIZE,
+        ));
+        self.stack_adjust += COLLECTION_SCRATCH_SIZE;
+        let length = COLLECTION_ARGS_OFFSET + 8;
+
+        self.generate_storage_key(key);
+        self.generate_collection_get();
+        if pop {
+            self.instructions.push(Instruction::Store(
+                Register::X0,
+                Register::X2,
+                COLLECTION_ARGS_OFFSET,
+            ));
+            // Reverts unless 0 < len
+            self.generate_bounds_check(COLLECTION_ARGS_OFFSET);
+            self.instructions
+                .push(Instruction::AddImm(Register::X5, Register::X5, -1));
+        }
+        self.instructions
+            .push(Instruction::Store(Register::X5, Register::X2, length));
+        self.generate_derive_entry_key(length);
+
+        self.instructions.push(Instruction::AddImm(
+            Register::X12,
+            Register::X2,
+            COLLECTION_SCRATCH_SIZE,
+        ));
+        if pop {
+            self.instructions
+                .push(Instruction::AddImm(Register::X13, Register::X2, 40));
+            self.generate_collection_host_call(HostFunction::StorageGet);
+            self.generate_collection_host_call(HostFunction::StorageClear);
+        } else {
+            self.instructions
+                .push(Instruction::Li(Register::X13, (words * 4) as i32));
+            self.generate_collection_host_call(HostFunction::StorageSet);
+            self.instructions
+                .push(Instruction::Load(Register::X5, Register::X2, length));
+            self.instructions
+                .push(Instruction::AddImm(Register::X5, Register::X5, 1));
+            self.instructions
+                .push(Instruction::Store(Register::X5, Register::X2, length));
+        }
+
+        // Store the new length under the root
+        self.generate_storage_key(key);
+        self.generate_collection_set(length);
+
+        self.instructions.push(Instruction::AddImm(
+            Register::X2,
+            Register::X2,
+            COLLECTION_SCRATCH_SIZE,
+        ));
+        self.stack_adjust -= COLLECTION_SCRATCH_SIZE;
+    }
+
+    /// Push the storage key of the map entry for an entry key with parts
+    /// of the given numbers of words: `keccak256(root ++ entry)`, as for
+    /// word keys
+    ///
+    /// A slot for the result is reserved first, then the entry key and the
+    /// root are pushed and hashed into the slot, leaving only the result.
+    /// The elements of a tuple key follow each other in the entry key.
+    fn generate_wide_entry_key(
+        &mut self,
+        root: &[u8; 32],
+        entry: &Expr,
+        parts: &[usize],
+    ) -> Result<(), CodegenError> {
+        let key_words = BYTES32_LEN / 4;
+        let words = parts.iter().sum();
+        self.push_wide(key_words);
+        match (entry, parts) {
+            (_, [_]) => self.generate_wide_expr(entry, words)?,
+            (Expr::Tuple { elements, .. }, _) if elements.len() == parts.len() => {
+                self.push_wide(words);
+                let mut offset = 0;
+                for (element, &part) in elements.iter().zip(parts) {
+                    self.generate_wide_expr(element, part)?;
+                    self.generate_copy_words(0, offset + (part * 4) as i32, part);
+                    self.pop_wide(part);
+                    offset += (part * 4) as i32;
+                }
+            }
+            _ => {
+                return Err(CodegenError::InvalidOperation(format!(
+                    "A key of {} elements is expected",
+                    parts.len()
+                )))
+            }
+        }
+        self.push_wide(key_words);
+        self.generate_storage_key(root);
+
+        let preimage_len = (BYTES32_LEN + words * 4) as i32;
+        self.instructions
+            .push(Instruction::Mv(Register::X10, Register::X2));
+        self.instructions
+            .push(Instruction::Li(Register::X11, preimage_len));
+        self.instructions.push(Instruction::AddImm(
+            Register::X12,
+            Register::X2,
+            preimage_len,
+        ));
+        self.instructions.push(Instruction::Li(
+            Register::X17,
+            HostFunction::Keccak256 as i32,
+        ));
+        self.instructions.push(Instruction::Ecall);
+
+        self.pop_wide(key_words + words);
+        Ok(())
+    }
+
+    /// Generate a call to a function of the program
+    fn generate_direct_call(
+        &mut self,
+        name: Symbol,
+        args: &[Expr],
+    ) -> Result<Register, CodegenError> {
+        let function_label = self.function_labels[&name].clone();
+
+        // Arguments are passed on the caller's stack, where the callee's
+        // frame finds its parameters, wide ones with all their words
+        let widths: Vec<usize> = (0..args.len())
+            .map(|i| {
+                self.function_params
+                    .get(&name)
+                    .and_then(|params| params.get(i))
+                    .copied()
+                    .unwrap_or(1)
+            })
+            .collect();
+        let total: usize = widths.iter().sum();
+        if total > 0 {
+            self.push_wide(total);
+        }
+        let mut offset = 0;
+        for (arg, &words) in args.iter().zip(&widths) {
+            if words > 1 {
+                self.generate_wide_expr(arg, words)?;
+                self.generate_copy_words(0, ((words + offset) * 4) as i32, words);
+                self.pop_wide(words);
+            } else {
+                let arg_reg = self.generate_expr(arg)?;
+                self.instructions.push(Instruction::Store(
+                    arg_reg,
+                    Register::X2,
+                    (offset * 4) as i32,
+                ));
+            }
+            offset += words;
+        }
+
+        // Call the function
+        self.instructions
+            .push(Instruction::JumpAndLink(Register::X1, function_label));
+        if total > 0 {
+            self.pop_wide(total);
+        }
+
+        // Result is in a0 (x10)
+        Ok(Register::X10)
+    }
+
+    /// Call the function whose address the local at `offset` holds, with
+    /// the arguments on the stack as for a direct call
+    fn generate_indirect_call(
+        &mut self,
+        offset: i32,
+        args: &[Expr],
+    ) -> Result<Register, CodegenError> {
+        if !args.is_empty() {
+            self.push_wide(args.len());
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let arg_reg = self.generate_expr(arg)?;
+            self.instructions
+                .push(Instruction::Store(arg_reg, Register::X2, (i * 4) as i32));
+        }
+        self.instructions.push(Instruction::Load(
+            Register::X5,
+            Register::X2,
+            offset + self.stack_adjust,
+        ));
+        self.instructions
+            .push(Instruction::JumpAndLinkReg(Register::X1, Register::X5, 0));
+        if !args.is_empty() {
+            self.pop_wide(args.len());
+        }
+        Ok(Register::X10)
+    }
+
+    /// Generate code for an `asm` block: the inputs are evaluated onto the
+    /// stack, loaded into their registers, then the instructions follow as
+    /// they are written
+    fn generate_inline_asm(
+        &mut self,
+        inputs: &[(String, Expr)],
+        output: Option<&str>,
+        instructions: &[String],
+    ) -> Result<Register, CodegenError> {
+        let asm = InlineAsm::parse(inputs, output, instructions)?;
+        if !self.isa.is_64_bit() {
+            if let Some(instruction) = asm.instructions.iter().find(|i| i.is_rv64_only()) {
+                let text = instruction.to_string();
+                return Err(CodegenError::InvalidAssembly(format!(
+                    "`{}` requires rv64, the target is {}",
+                    text.split_whitespace().next().unwrap_or_default(),
+                    self.isa
+                )));
+            }
+        }
+        if !inputs.is_empty() {
+            self.push_wide(inputs.len());
+        }
+        for (i, (_, value)) in inputs.iter().enumerate() {
+            let reg = self.generate_expr(value)?;
+            self.instructions
+                .push(Instruction::Store(reg, Register::X2, (i * 4) as i32));
+        }
+        for (i, register) in asm.inputs.iter().enumerate() {
+            self.instructions
+                .push(Instruction::Load(*register, Register::X2, (i * 4) as i32));
+        }
+        if !inputs.is_empty() {
+            self.pop_wide(inputs.len());
+        }
+        self.instructions.extend(asm.instructions);
+        Ok(asm.output)
     }
 
     /// Generate code for an assignment
@@ -913,11 +6576,21 @@ impl RiscVCodegen {
                     self.instructions
                         .push(Instruction::Store(value_reg, Register::X2, offset));
                     Ok(())
+                } else if let Some((key, kind)) = self.storage.get(name).copied() {
+                    if kind != StorageKind::Value {
+                        return Err(CodegenError::InvalidOperation(format!(
+                            "Storage collection {} can only be modified through its methods",
+                            name
+                        )));
+                    }
+                    self.generate_storage_store(&key, value_reg);
+                    Ok(())
                 } else {
-                    // Allocate a new local variable
-                    self.frame_size += 4; // Assuming 4-byte (32-bit) values
-                    let offset = self.frame_size;
-                    self.locals.insert(name.clone(), offset);
+                    // Allocate a new local variable in a slot counted by
+                    // `collect_locals`
+                    let offset = self.current_local_offset;
+                    self.current_local_offset += 4;
+                    self.locals.insert(name.into(), offset);
                     self.instructions
                         .push(Instruction::Store(value_reg, Register::X2, offset));
                     Ok(())
@@ -0,0 +1,125 @@
+//! Word-size lowering for RV64 targets
+//!
+//! The code generator works on 32-bit words. On RV64 those words are kept in
+//! registers sign-extended to 64 bits, the canonical form of 32-bit values on
+//! 64-bit RISC-V, and data in memory keeps its 32-bit layout. [`widen`]
+//! rewrites the arithmetic that would otherwise carry into the upper half of
+//! a register into its `W`-suffixed form, which operates on the low word and
+//! sign-extends the result, so code behaves the same on every ISA.
+//!
+//! Bitwise operations, comparisons, branches, loads and stores already
+//! preserve sign-extended words and are kept as they are, as are stack
+//! pointer adjustments. The high halves of 32-bit products are taken from a
+//! full 64-bit multiplication, using `gp` and `tp` as scratch registers for
+//! unsigned ones. `asm` blocks go through the same rewriting.
+
+use super::risc_v::{Instruction, Register};
+
+/// Rewrite 32-bit code generated for RV32 to run on RV64
+pub fn widen(instructions: &[Instruction]) -> Vec<Instruction> {
+    let mut widened = Vec::with_capacity(instructions.len());
+    for instruction in instructions {
+        match instruction.clone() {
+            Instruction::Add(rd, rs1, rs2) => widened.push(Instruction::AddW(rd, rs1, rs2)),
+            Instruction::AddImm(rd, rs1, imm) if rd != Register::X2 || rs1 != Register::X2 => {
+                widened.push(Instruction::AddImmW(rd, rs1, imm))
+            }
+            Instruction::Sub(rd, rs1, rs2) => widened.push(Instruction::SubW(rd, rs1, rs2)),
+            Instruction::Neg(rd, rs1) => widened.push(Instruction::SubW(rd, Register::X0, rs1)),
+            Instruction::Mul(rd, rs1, rs2) => widened.push(Instruction::MulW(rd, rs1, rs2)),
+            Instruction::Div(rd, rs1, rs2) => widened.push(Instruction::DivW(rd, rs1, rs2)),
+            Instruction::Rem(rd, rs1, rs2) => widened.push(Instruction::RemW(rd, rs1, rs2)),
+            Instruction::MulHigh(rd, rs1, rs2) => {
+                // The product of two sign-extended words fits in 64 bits
+                widened.push(Instruction::Mul(rd, rs1, rs2));
+                widened.push(Instruction::ShiftRightArithImm(rd, rd, 32));
+            }
+            Instruction::MulHighU(rd, rs1, rs2) => {
+                // Moving both words to the upper halves leaves their unsigned
+                // 64-bit product in the upper half of the 128-bit one
+                widened.push(Instruction::ShiftLeftImm(Register::X3, rs1, 32));
+                widened.push(Instruction::ShiftLeftImm(Register::X4, rs2, 32));
+                widened.push(Instruction::MulHighU(rd, Register::X3, Register::X4));
+                widened.push(Instruction::ShiftRightArithImm(rd, rd, 32));
+            }
+            Instruction::ShiftLeft(rd, rs1, rs2) => {
+                widened.push(Instruction::ShiftLeftW(rd, rs1, rs2))
+            }
+            Instruction::ShiftRight(rd, rs1, rs2) => {
+                widened.push(Instruction::ShiftRightW(rd, rs1, rs2))
+            }
+            Instruction::ShiftRightArith(rd, rs1, rs2) => {
+                widened.push(Instruction::ShiftRightArithW(rd, rs1, rs2))
+            }
+            Instruction::ShiftLeftImm(rd, rs1, imm) => {
+                widened.push(Instruction::ShiftLeftImmW(rd, rs1, imm))
+            }
+            Instruction::ShiftRightImm(rd, rs1, imm) => {
+                widened.push(Instruction::ShiftRightImmW(rd, rs1, imm))
+            }
+            Instruction::ShiftRightArithImm(rd, rs1, imm) => {
+                widened.push(Instruction::ShiftRightArithImmW(rd, rs1, imm))
+            }
+            instruction => widened.push(instruction),
+        }
+    }
+    widened
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(instructions: &[Instruction]) -> Vec<String> {
+        instructions
+            .iter()
+            .map(|instruction| instruction.to_string().trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_arithmetic_uses_word_forms() {
+        let code = [
+            Instruction::Add(Register::X10, Register::X10, Register::X11),
+            Instruction::AddImm(Register::X10, Register::X10, 1),
+            Instruction::Neg(Register::X10, Register::X10),
+            Instruction::ShiftRightImm(Register::X10, Register::X10, 3),
+            Instruction::Xor(Register::X10, Register::X10, Register::X11),
+        ];
+        assert_eq!(
+            render(&widen(&code)),
+            [
+                "addw a0, a0, a1",
+                "addiw a0, a0, 1",
+                "subw a0, zero, a0",
+                "srliw a0, a0, 3",
+                "xor a0, a0, a1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stack_pointer_keeps_full_width() {
+        let code = [Instruction::AddImm(Register::X2, Register::X2, -16)];
+        assert_eq!(render(&widen(&code)), ["addi sp, sp, -16"]);
+    }
+
+    #[test]
+    fn test_high_products() {
+        let code = [
+            Instruction::MulHigh(Register::X10, Register::X11, Register::X12),
+            Instruction::MulHighU(Register::X10, Register::X11, Register::X12),
+        ];
+        assert_eq!(
+            render(&widen(&code)),
+            [
+                "mul a0, a1, a2",
+                "srai a0, a0, 32",
+                "slli gp, a1, 32",
+                "slli tp, a2, 32",
+                "mulhu a0, gp, tp",
+                "srai a0, a0, 32",
+            ]
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use super::*;
+use crate::compiler::codegen::encoder::Isa;
 use crate::compiler::codegen::risc_v::{CodegenError, Instruction, Register, RiscVCodegen};
 use crate::compiler::parser::ast::*;
 use crate::compiler::parser::parser::Parser;
@@ -407,3 +407,281 @@ fn test_unsupported_features_error() {
     let result = generate_code(source);
     assert!(result.is_ok(), "Basic features should be supported");
 }
+
+#[test]
+fn test_emit_lowers_to_deposit_event() {
+    let source = r#"
+            event Transfer {
+                indexed sender: u24,
+                amount: u24,
+            }
+
+            fn main() -> u24 {
+                emit Transfer(1, 2);
+                return 0;
+            }
+        "#;
+
+    let instructions = generate_code(source).unwrap();
+
+    let ecall = instructions
+        .iter()
+        .position(|inst| matches!(inst, Instruction::Ecall))
+        .expect("Should generate an ecall for emit");
+    assert!(matches!(
+        instructions[ecall - 1],
+        Instruction::Li(Register::X17, 40)
+    ));
+    assert!(instructions[..ecall]
+        .iter()
+        .any(|inst| matches!(inst, Instruction::Li(Register::X11, 2))));
+    assert!(instructions[..ecall]
+        .iter()
+        .any(|inst| matches!(inst, Instruction::Li(Register::X13, 4))));
+}
+
+#[test]
+fn test_emit_undefined_event() {
+    let source = r#"
+            fn main() -> u24 {
+                emit Missing(1);
+                return 0;
+            }
+        "#;
+
+    let result = generate_code(source);
+    assert!(matches!(result, Err(CodegenError::UndefinedEvent(name)) if name == "Missing"));
+}
+
+#[test]
+fn test_dispatcher_generation() {
+    let source = r#"
+            #[message]
+            fn get() -> u24 {
+                return helper();
+            }
+
+            #[message]
+            fn set(value: u24) {
+                return value;
+            }
+
+            fn helper() -> u24 {
+                return 1;
+            }
+        "#;
+
+    let program = parse_program(source);
+    let instructions = RiscVCodegen::new()
+        .with_dispatcher()
+        .generate(&program)
+        .unwrap();
+    let has_label = |label: &str| {
+        instructions
+            .iter()
+            .any(|inst| matches!(inst, Instruction::Label(l) if l == label))
+    };
+
+    assert!(matches!(&instructions[0], Instruction::Label(label) if label == "call"));
+    for label in ["call.get", "call.set", "call.revert"] {
+        assert!(has_label(label), "Should generate {} label", label);
+    }
+    // Only messages are dispatched
+    assert!(!has_label("call.helper"));
+
+    // Without the builder option no dispatcher is generated
+    let instructions = generate_code(source).unwrap();
+    assert!(!instructions
+        .iter()
+        .any(|inst| matches!(inst, Instruction::Label(l) if l == "call")));
+}
+
+#[test]
+fn test_dispatcher_rejects_undecodable_parameters() {
+    let source = r#"
+            #[message]
+            fn takes_any(value: Any) -> u24 {
+                return 1;
+            }
+        "#;
+
+    let program = parse_program(source);
+    let result = RiscVCodegen::new().with_dispatcher().generate(&program);
+    assert!(matches!(result, Err(CodegenError::UnsupportedFeature(_))));
+}
+
+#[test]
+fn test_dispatcher_skips_helpers() {
+    // Helpers may take what call data cannot hold
+    let source = r#"
+            type Pair {
+                Pair(u24, u24),
+            }
+
+            fn count(xs: List<u24>) -> u24 {
+                return 0;
+            }
+
+            fn first(p: Pair) -> u24 {
+                return 0;
+            }
+
+            fn apply(f: u24 -> u24, x: u24) -> u24 {
+                return f(x);
+            }
+
+            #[message]
+            fn three() -> u24 {
+                return 3;
+            }
+        "#;
+
+    let program = parse_program(source);
+    let instructions = RiscVCodegen::new()
+        .with_dispatcher()
+        .generate(&program)
+        .unwrap();
+    assert!(instructions
+        .iter()
+        .any(|inst| matches!(inst, Instruction::Label(l) if l == "call.three")));
+
+    // A library, without messages, gets no dispatcher
+    let library = parse_program(&source.replace("#[message]", ""));
+    let instructions = RiscVCodegen::new()
+        .with_dispatcher()
+        .generate(&library)
+        .unwrap();
+    assert!(!instructions
+        .iter()
+        .any(|inst| matches!(inst, Instruction::Label(l) if l == "call")));
+}
+
+#[test]
+fn test_wide_multiply_uses_mulhu() {
+    let source = r#"
+            fn square(value: u64) -> u64 {
+                return value * value;
+            }
+        "#;
+
+    let instructions = generate_code(source).unwrap();
+    assert!(instructions
+        .iter()
+        .any(|inst| matches!(inst, Instruction::MulHighU(..))));
+    // The two result words are returned in a0 and a1
+    assert!(instructions
+        .iter()
+        .any(|inst| matches!(inst, Instruction::Load(Register::X11, Register::X2, 4))));
+}
+
+#[test]
+fn test_wide_value_in_word_context_is_rejected() {
+    let source = r#"
+            fn truncate(value: u256) -> u24 {
+                return value;
+            }
+        "#;
+
+    assert!(matches!(
+        generate_code(source),
+        Err(CodegenError::InvalidOperation(_))
+    ));
+}
+
+#[test]
+fn test_instructions_read_back_from_listing() {
+    let listing = [
+        "lw a0, -4(sp)",
+        "sb t0, 0(a1)",
+        "addi sp, sp, 16",
+        "mulhu s10, t6, zero",
+        "srai a0, a0, 0x3",
+        "bgeu a0, a1, loop.end",
+        "jal ra, main",
+        "jalr zero, ra, 0",
+        "li a7, -2147483648",
+        "ecall",
+        "loop.end:",
+        "# done",
+    ];
+    for line in listing {
+        let instruction: Instruction = line.parse().unwrap();
+        let written = instruction.to_string();
+        assert_eq!(written.trim(), line.replace("0x3", "3"));
+        assert_eq!(written.parse::<Instruction>().unwrap().to_string(), written);
+    }
+    assert!(matches!("x10".parse(), Ok(Register::X10)));
+    assert!(matches!("fp".parse(), Ok(Register::X8)));
+
+    for line in [
+        "addx a0, a0, a1",
+        "add a0, a1",
+        "add a0, a1, a32",
+        "addi a0, a0, 2147483648",
+        "lw a0, 4[sp]",
+        "j 1loop",
+    ] {
+        assert!(
+            matches!(
+                line.parse::<Instruction>(),
+                Err(CodegenError::InvalidAssembly(_))
+            ),
+            "{}",
+            line
+        );
+    }
+}
+
+#[test]
+fn test_inline_asm_passes_through() {
+    let source = r#"
+            fn double(x: u24) -> u24 {
+                return asm(in a1 = x, out a2) {
+                    "add a2, a1, a1"
+                };
+            }
+        "#;
+
+    let instructions = generate_code(source).unwrap();
+    let listing: Vec<String> = instructions.iter().map(|inst| inst.to_string()).collect();
+    let add = listing
+        .iter()
+        .position(|line| line == "    add a2, a1, a1")
+        .unwrap();
+    assert_eq!(listing[add - 2], "    lw a1, 0(sp)");
+    assert_eq!(listing[add + 1], "    mv a0, a2");
+
+    let source = r#"
+            fn main() -> u24 {
+                return asm(in sp = 1, out a0) { };
+            }
+        "#;
+    assert!(matches!(
+        generate_code(source),
+        Err(CodegenError::InvalidAssembly(_))
+    ));
+}
+
+#[test]
+fn test_rv64_codegen() {
+    let source = r#"
+            fn double(x: u24) -> u24 {
+                return asm(in a1 = x, out a0) {
+                    "addw a0, a1, a1"
+                } + x;
+            }
+        "#;
+    let Err(CodegenError::InvalidAssembly(reason)) = generate_code(source) else {
+        panic!("expected an RV64 instruction to be rejected");
+    };
+    assert_eq!(reason, "`addw` requires rv64, the target is RV32EM");
+
+    let instructions = RiscVCodegen::new()
+        .with_isa(Isa::Rv64Im)
+        .generate(&parse_program(source))
+        .unwrap();
+    let listing: Vec<String> = instructions.iter().map(|inst| inst.to_string()).collect();
+    assert!(listing.contains(&"    sd ra, 0(sp)".to_string()));
+    assert!(listing.contains(&"    ld ra, 0(sp)".to_string()));
+    assert!(listing.iter().all(|line| !line.starts_with("    add ")));
+}
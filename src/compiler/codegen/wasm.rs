@@ -0,0 +1,1898 @@
+//! WebAssembly code generation
+//!
+//! A second backend next to [`RiscVCodegen`](super::risc_v::RiscVCodegen),
+//! for chains running pallet-contracts (ink!) and for running contracts in
+//! a browser. It reads the same lowered and optimized program and produces
+//! a [`WasmModule`], which encodes to a binary module importing its memory
+//! from `env` and the host functions from `seal0`, and exporting `deploy`
+//! and `call`.
+//!
+//! The backend covers word-sized contracts: `u24`, `i24`, `u32`, `i32` and
+//! `Bool` values with their arithmetic, control flow, calls between
+//! functions, storage values, events and reverts with a message. Anything
+//! else is a [`CodegenError::UnsupportedFeature`].
+//!
+//! Contracts behave the same on both backends. `call` is the message
+//! dispatcher, with the selectors of the RISC-V one, arguments as
+//! little-endian words and results as 4 little-endian bytes, or one for
+//! `Bool`. Unknown selectors, malformed input, value sent to functions not
+//! marked `#[payable]` and overflowing arithmetic revert with empty data.
+//! Storage values are kept as 4 little-endian bytes under the same keys,
+//! and events carry the same topics and data.
+//!
+//! Memory is laid out as:
+//!
+//! - `0..16`: values exchanged with the host, such as storage values, the
+//!   call value and results
+//! - `16..20`: lengths exchanged with the host
+//! - `32..DATA_OFFSET`: the topics and data of the event being emitted
+//! - `DATA_OFFSET..`: constant data (storage keys and revert messages),
+//!   then the call input
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::compiler::analyzer::attributes::FunctionAttributes;
+use crate::compiler::codegen::metadata::{
+    compute_event_signature, compute_event_topic, compute_selector_for_params, compute_storage_key,
+    selector_for, ERROR_SIGNATURE, PANIC_SIGNATURE,
+};
+use crate::compiler::codegen::risc_v::CodegenError;
+use crate::compiler::parser::ast::*;
+use crate::compiler::polkavm::host::HostFunction;
+use crate::security::safe_math::CheckedInt;
+use crate::stdlib::bytes::encode_compact;
+
+/// Size of a memory page
+pub const PAGE_SIZE: u32 = 64 * 1024;
+
+/// Pages of memory the module imports
+pub const MEMORY_PAGES: u32 = 1;
+
+/// Where values are exchanged with the host
+const VALUE_OFFSET: u32 = 0;
+
+/// Where lengths are exchanged with the host
+const LENGTH_OFFSET: u32 = 16;
+
+/// Where the event being emitted is laid out
+const EVENT_OFFSET: u32 = 32;
+
+/// Where constant data starts
+const DATA_OFFSET: u32 = 1024;
+
+/// Flag of `seal_return` reverting the call
+const REVERT_FLAG: i32 = 1;
+
+/// A value type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    I64,
+}
+
+impl ValType {
+    fn code(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7F,
+            ValType::I64 => 0x7E,
+        }
+    }
+}
+
+impl fmt::Display for ValType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValType::I32 => write!(f, "i32"),
+            ValType::I64 => write!(f, "i64"),
+        }
+    }
+}
+
+/// The parameters and results of a function
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+impl FuncType {
+    pub fn new(params: &[ValType], results: &[ValType]) -> Self {
+        FuncType {
+            params: params.to_vec(),
+            results: results.to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for FuncType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |types: &[ValType]| {
+            types
+                .iter()
+                .map(|ty| ty.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let mut parts = Vec::new();
+        if !self.params.is_empty() {
+            parts.push(format!("(param {})", list(&self.params)));
+        }
+        if !self.results.is_empty() {
+            parts.push(format!("(result {})", list(&self.results)));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// A host function imported from the `seal0` module of pallet-contracts
+///
+/// Every module imports all of them, in this order, so their function
+/// indices are fixed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WasmImport {
+    /// `seal_input(buf_ptr, buf_len_ptr)`: copy the call input to
+    /// `buf_ptr`, whose capacity is read from and length written to
+    /// `buf_len_ptr`
+    Input,
+    /// `seal_return(flags, data_ptr, data_len)`: end the call, reverting
+    /// it when bit 0 of `flags` is set
+    Return,
+    /// `seal_get_storage(key_ptr, out_ptr, out_len_ptr) -> status`: read
+    /// the value under a 32-byte key, returning 0 if found and 3 if not
+    GetStorage,
+    /// `seal_set_storage(key_ptr, value_ptr, value_len)`
+    SetStorage,
+    /// `seal_clear_storage(key_ptr)`
+    ClearStorage,
+    /// `seal_value_transferred(out_ptr, out_len_ptr)`: write the 16-byte
+    /// call value
+    ValueTransferred,
+    /// `seal_deposit_event(topics_ptr, topics_len, data_ptr, data_len)`,
+    /// with the topics SCALE-encoded as a `Vec` of 32-byte hashes
+    DepositEvent,
+}
+
+impl WasmImport {
+    pub const ALL: [WasmImport; 7] = [
+        WasmImport::Input,
+        WasmImport::Return,
+        WasmImport::GetStorage,
+        WasmImport::SetStorage,
+        WasmImport::ClearStorage,
+        WasmImport::ValueTransferred,
+        WasmImport::DepositEvent,
+    ];
+
+    /// Module the function is imported from
+    pub fn module(self) -> &'static str {
+        "seal0"
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WasmImport::Input => "seal_input",
+            WasmImport::Return => "seal_return",
+            WasmImport::GetStorage => "seal_get_storage",
+            WasmImport::SetStorage => "seal_set_storage",
+            WasmImport::ClearStorage => "seal_clear_storage",
+            WasmImport::ValueTransferred => "seal_value_transferred",
+            WasmImport::DepositEvent => "seal_deposit_event",
+        }
+    }
+
+    pub fn ty(self) -> FuncType {
+        use ValType::*;
+        match self {
+            WasmImport::Input | WasmImport::ValueTransferred => FuncType::new(&[I32, I32], &[]),
+            WasmImport::Return | WasmImport::SetStorage => FuncType::new(&[I32, I32, I32], &[]),
+            WasmImport::GetStorage => FuncType::new(&[I32, I32, I32], &[I32]),
+            WasmImport::ClearStorage => FuncType::new(&[I32], &[]),
+            WasmImport::DepositEvent => FuncType::new(&[I32, I32, I32, I32], &[]),
+        }
+    }
+
+    /// The RISC-V host function with the same effect. The input has none,
+    /// as RISC-V contracts are entered with it in memory.
+    pub fn host_function(self) -> Option<HostFunction> {
+        match self {
+            WasmImport::Input => None,
+            WasmImport::Return => Some(HostFunction::Return),
+            WasmImport::GetStorage => Some(HostFunction::StorageGet),
+            WasmImport::SetStorage => Some(HostFunction::StorageSet),
+            WasmImport::ClearStorage => Some(HostFunction::StorageClear),
+            WasmImport::ValueTransferred => Some(HostFunction::GetCallValue),
+            WasmImport::DepositEvent => Some(HostFunction::Log),
+        }
+    }
+
+    /// Function index of the import
+    pub fn index(self) -> u32 {
+        WasmImport::ALL
+            .iter()
+            .position(|import| *import == self)
+            .unwrap() as u32
+    }
+}
+
+/// An instruction without immediates operating on the value stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericOp {
+    I32Eqz,
+    I32Eq,
+    I32Ne,
+    I32LtS,
+    I32LtU,
+    I32GtS,
+    I32GtU,
+    I32LeS,
+    I32GeS,
+    I32GeU,
+    I64LtS,
+    I64GtS,
+    I64GtU,
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I32DivS,
+    I32RemS,
+    I32And,
+    I32Or,
+    I32Xor,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64DivS,
+    I64DivU,
+    I64RemS,
+    I64RemU,
+    I32WrapI64,
+    I64ExtendI32S,
+    I64ExtendI32U,
+}
+
+impl NumericOp {
+    /// Opcode and text format name
+    fn encoding(self) -> (u8, &'static str) {
+        match self {
+            NumericOp::I32Eqz => (0x45, "i32.eqz"),
+            NumericOp::I32Eq => (0x46, "i32.eq"),
+            NumericOp::I32Ne => (0x47, "i32.ne"),
+            NumericOp::I32LtS => (0x48, "i32.lt_s"),
+            NumericOp::I32LtU => (0x49, "i32.lt_u"),
+            NumericOp::I32GtS => (0x4A, "i32.gt_s"),
+            NumericOp::I32GtU => (0x4B, "i32.gt_u"),
+            NumericOp::I32LeS => (0x4C, "i32.le_s"),
+            NumericOp::I32GeS => (0x4E, "i32.ge_s"),
+            NumericOp::I32GeU => (0x4F, "i32.ge_u"),
+            NumericOp::I64LtS => (0x53, "i64.lt_s"),
+            NumericOp::I64GtS => (0x55, "i64.gt_s"),
+            NumericOp::I64GtU => (0x56, "i64.gt_u"),
+            NumericOp::I32Add => (0x6A, "i32.add"),
+            NumericOp::I32Sub => (0x6B, "i32.sub"),
+            NumericOp::I32Mul => (0x6C, "i32.mul"),
+            NumericOp::I32DivS => (0x6D, "i32.div_s"),
+            NumericOp::I32RemS => (0x6F, "i32.rem_s"),
+            NumericOp::I32And => (0x71, "i32.and"),
+            NumericOp::I32Or => (0x72, "i32.or"),
+            NumericOp::I32Xor => (0x73, "i32.xor"),
+            NumericOp::I64Add => (0x7C, "i64.add"),
+            NumericOp::I64Sub => (0x7D, "i64.sub"),
+            NumericOp::I64Mul => (0x7E, "i64.mul"),
+            NumericOp::I64DivS => (0x7F, "i64.div_s"),
+            NumericOp::I64DivU => (0x80, "i64.div_u"),
+            NumericOp::I64RemS => (0x81, "i64.rem_s"),
+            NumericOp::I64RemU => (0x82, "i64.rem_u"),
+            NumericOp::I32WrapI64 => (0xA7, "i32.wrap_i64"),
+            NumericOp::I64ExtendI32S => (0xAC, "i64.extend_i32_s"),
+            NumericOp::I64ExtendI32U => (0xAD, "i64.extend_i32_u"),
+        }
+    }
+}
+
+/// A WebAssembly instruction
+///
+/// Blocks, loops and ifs have no parameters or results. Memory accesses
+/// carry their static offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WasmInstruction {
+    Unreachable,
+    Block,
+    Loop,
+    If,
+    Else,
+    End,
+    /// Branch to the `n`th enclosing block, loop or if
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(u32),
+    Drop,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    I32Load(u32),
+    I32Store(u32),
+    I32Store8(u32),
+    I32Const(i32),
+    I64Const(i64),
+    Numeric(NumericOp),
+}
+
+impl WasmInstruction {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match *self {
+            WasmInstruction::Unreachable => out.push(0x00),
+            WasmInstruction::Block => out.extend([0x02, 0x40]),
+            WasmInstruction::Loop => out.extend([0x03, 0x40]),
+            WasmInstruction::If => out.extend([0x04, 0x40]),
+            WasmInstruction::Else => out.push(0x05),
+            WasmInstruction::End => out.push(0x0B),
+            WasmInstruction::Br(depth) => {
+                out.push(0x0C);
+                write_u32(out, depth);
+            }
+            WasmInstruction::BrIf(depth) => {
+                out.push(0x0D);
+                write_u32(out, depth);
+            }
+            WasmInstruction::Return => out.push(0x0F),
+            WasmInstruction::Call(index) => {
+                out.push(0x10);
+                write_u32(out, index);
+            }
+            WasmInstruction::Drop => out.push(0x1A),
+            WasmInstruction::LocalGet(index) => {
+                out.push(0x20);
+                write_u32(out, index);
+            }
+            WasmInstruction::LocalSet(index) => {
+                out.push(0x21);
+                write_u32(out, index);
+            }
+            WasmInstruction::LocalTee(index) => {
+                out.push(0x22);
+                write_u32(out, index);
+            }
+            // Memory accesses are followed by the alignment exponent and
+            // the offset
+            WasmInstruction::I32Load(offset) => {
+                out.extend([0x28, 2]);
+                write_u32(out, offset);
+            }
+            WasmInstruction::I32Store(offset) => {
+                out.extend([0x36, 2]);
+                write_u32(out, offset);
+            }
+            WasmInstruction::I32Store8(offset) => {
+                out.extend([0x3A, 0]);
+                write_u32(out, offset);
+            }
+            WasmInstruction::I32Const(value) => {
+                out.push(0x41);
+                write_i64(out, value as i64);
+            }
+            WasmInstruction::I64Const(value) => {
+                out.push(0x42);
+                write_i64(out, value);
+            }
+            WasmInstruction::Numeric(op) => out.push(op.encoding().0),
+        }
+    }
+}
+
+impl fmt::Display for WasmInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let offset = |f: &mut fmt::Formatter<'_>, name: &str, offset: u32| {
+            if offset == 0 {
+                write!(f, "{}", name)
+            } else {
+                write!(f, "{} offset={}", name, offset)
+            }
+        };
+        match *self {
+            WasmInstruction::Unreachable => write!(f, "unreachable"),
+            WasmInstruction::Block => write!(f, "block"),
+            WasmInstruction::Loop => write!(f, "loop"),
+            WasmInstruction::If => write!(f, "if"),
+            WasmInstruction::Else => write!(f, "else"),
+            WasmInstruction::End => write!(f, "end"),
+            WasmInstruction::Br(depth) => write!(f, "br {}", depth),
+            WasmInstruction::BrIf(depth) => write!(f, "br_if {}", depth),
+            WasmInstruction::Return => write!(f, "return"),
+            WasmInstruction::Call(index) => write!(f, "call {}", index),
+            WasmInstruction::Drop => write!(f, "drop"),
+            WasmInstruction::LocalGet(index) => write!(f, "local.get {}", index),
+            WasmInstruction::LocalSet(index) => write!(f, "local.set {}", index),
+            WasmInstruction::LocalTee(index) => write!(f, "local.tee {}", index),
+            WasmInstruction::I32Load(o) => offset(f, "i32.load", o),
+            WasmInstruction::I32Store(o) => offset(f, "i32.store", o),
+            WasmInstruction::I32Store8(o) => offset(f, "i32.store8", o),
+            WasmInstruction::I32Const(value) => write!(f, "i32.const {}", value),
+            WasmInstruction::I64Const(value) => write!(f, "i64.const {}", value),
+            WasmInstruction::Numeric(op) => write!(f, "{}", op.encoding().1),
+        }
+    }
+}
+
+/// A function defined by the module
+#[derive(Debug, Clone, PartialEq)]
+pub struct WasmFunction {
+    pub name: String,
+    pub ty: FuncType,
+    /// Types of the locals after the parameters
+    pub locals: Vec<ValType>,
+    /// The instructions, without the `end` closing the body
+    pub body: Vec<WasmInstruction>,
+}
+
+/// A module generated by [`WasmCodegen`]
+///
+/// Functions are indexed after the [imports](WasmImport::ALL).
+#[derive(Debug, Clone, PartialEq)]
+pub struct WasmModule {
+    /// Pages of the memory imported from `env`
+    pub memory_pages: u32,
+    pub functions: Vec<WasmFunction>,
+    /// Exported functions by name
+    pub exports: Vec<(String, u32)>,
+    /// Constant data and the address it is loaded at
+    pub data: Vec<(u32, Vec<u8>)>,
+}
+
+impl WasmModule {
+    /// The function with `index`, unless it is an import
+    pub fn function(&self, index: u32) -> Option<&WasmFunction> {
+        let index = index.checked_sub(WasmImport::ALL.len() as u32)?;
+        self.functions.get(index as usize)
+    }
+
+    /// Index of the function exported as `name`
+    pub fn export(&self, name: &str) -> Option<u32> {
+        self.exports
+            .iter()
+            .find(|(export, _)| export == name)
+            .map(|(_, index)| *index)
+    }
+
+    /// The binary module
+    pub fn encode(&self) -> Vec<u8> {
+        let mut types: Vec<FuncType> = Vec::new();
+        let mut type_index = |ty: FuncType| match types.iter().position(|t| *t == ty) {
+            Some(index) => index as u32,
+            None => {
+                types.push(ty);
+                types.len() as u32 - 1
+            }
+        };
+        let import_types: Vec<u32> = WasmImport::ALL
+            .iter()
+            .map(|import| type_index(import.ty()))
+            .collect();
+        let function_types: Vec<u32> = self
+            .functions
+            .iter()
+            .map(|function| type_index(function.ty.clone()))
+            .collect();
+
+        let mut module = b"\0asm".to_vec();
+        module.extend(1u32.to_le_bytes());
+
+        write_section(&mut module, 1, types.len(), |out| {
+            for ty in &types {
+                out.push(0x60);
+                write_u32(out, ty.params.len() as u32);
+                out.extend(ty.params.iter().map(|param| param.code()));
+                write_u32(out, ty.results.len() as u32);
+                out.extend(ty.results.iter().map(|result| result.code()));
+            }
+        });
+
+        write_section(&mut module, 2, 1 + WasmImport::ALL.len(), |out| {
+            write_name(out, "env");
+            write_name(out, "memory");
+            out.extend([0x02, 0x00]);
+            write_u32(out, self.memory_pages);
+            for (import, ty) in WasmImport::ALL.iter().zip(&import_types) {
+                write_name(out, import.module());
+                write_name(out, import.name());
+                out.push(0x00);
+                write_u32(out, *ty);
+            }
+        });
+
+        write_section(&mut module, 3, function_types.len(), |out| {
+            for ty in &function_types {
+                write_u32(out, *ty);
+            }
+        });
+
+        write_section(&mut module, 7, self.exports.len(), |out| {
+            for (name, index) in &self.exports {
+                write_name(out, name);
+                out.push(0x00);
+                write_u32(out, *index);
+            }
+        });
+
+        write_section(&mut module, 10, self.functions.len(), |out| {
+            for function in &self.functions {
+                let mut body = Vec::new();
+                // Locals are declared as runs of the same type
+                let mut runs: Vec<(u32, ValType)> = Vec::new();
+                for local in &function.locals {
+                    match runs.last_mut() {
+                        Some((count, ty)) if ty == local => *count += 1,
+                        _ => runs.push((1, *local)),
+                    }
+                }
+                write_u32(&mut body, runs.len() as u32);
+                for (count, ty) in runs {
+                    write_u32(&mut body, count);
+                    body.push(ty.code());
+                }
+                for instruction in &function.body {
+                    instruction.encode(&mut body);
+                }
+                body.push(0x0B);
+                write_u32(out, body.len() as u32);
+                out.extend(body);
+            }
+        });
+
+        write_section(&mut module, 11, self.data.len(), |out| {
+            for (address, bytes) in &self.data {
+                out.push(0x00);
+                WasmInstruction::I32Const(*address as i32).encode(out);
+                out.push(0x0B);
+                write_u32(out, bytes.len() as u32);
+                out.extend(bytes);
+            }
+        });
+
+        module
+    }
+}
+
+/// The module in the text format
+impl fmt::Display for WasmModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "(module")?;
+        writeln!(
+            f,
+            "  (import \"env\" \"memory\" (memory {}))",
+            self.memory_pages
+        )?;
+        for (index, import) in WasmImport::ALL.iter().enumerate() {
+            writeln!(
+                f,
+                "  (import \"{}\" \"{}\" (func ${} (;{};) {}))",
+                import.module(),
+                import.name(),
+                import.name(),
+                index,
+                import.ty()
+            )?;
+        }
+
+        for (index, function) in self.functions.iter().enumerate() {
+            let index = index + WasmImport::ALL.len();
+            write!(
+                f,
+                "  (func ${} (;{};) {}",
+                function.name, index, function.ty
+            )?;
+            if !function.locals.is_empty() {
+                let locals: Vec<String> = function.locals.iter().map(|l| l.to_string()).collect();
+                write!(f, " (local {})", locals.join(" "))?;
+            }
+            writeln!(f)?;
+            let mut depth = 2;
+            for instruction in &function.body {
+                if matches!(instruction, WasmInstruction::End | WasmInstruction::Else) {
+                    depth -= 1;
+                }
+                writeln!(f, "{:width$}{}", "", instruction, width = depth * 2)?;
+                if matches!(
+                    instruction,
+                    WasmInstruction::Block
+                        | WasmInstruction::Loop
+                        | WasmInstruction::If
+                        | WasmInstruction::Else
+                ) {
+                    depth += 1;
+                }
+            }
+            writeln!(f, "  )")?;
+        }
+
+        for (name, index) in &self.exports {
+            writeln!(f, "  (export \"{}\" (func {}))", name, index)?;
+        }
+        for (address, bytes) in &self.data {
+            let escaped: String = bytes.iter().map(|b| format!("\\{:02x}", b)).collect();
+            writeln!(f, "  (data (i32.const {}) \"{}\")", address, escaped)?;
+        }
+        write!(f, ")")
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_i64(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    write_u32(out, name.len() as u32);
+    out.extend(name.as_bytes());
+}
+
+/// Append section `id` holding a vector of `count` entries written by
+/// `entries`
+fn write_section(module: &mut Vec<u8>, id: u8, count: usize, entries: impl FnOnce(&mut Vec<u8>)) {
+    if count == 0 {
+        return;
+    }
+    let mut contents = Vec::new();
+    write_u32(&mut contents, count as u32);
+    entries(&mut contents);
+    module.push(id);
+    write_u32(module, contents.len() as u32);
+    module.extend(contents);
+}
+
+/// A word-sized value of a parameter, result, storage field or event field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum WordType {
+    Int(CheckedInt),
+    Bool,
+}
+
+impl WordType {
+    pub(super) fn of(ty: &Type) -> Option<WordType> {
+        match ty {
+            Type::U24 { .. } => Some(WordType::Int(CheckedInt::U24)),
+            Type::I24 { .. } => Some(WordType::Int(CheckedInt::I24)),
+            Type::Named { name, params, .. } if params.is_empty() => match name.as_str() {
+                "Bool" | "bool" => Some(WordType::Bool),
+                name => CheckedInt::from_type_name(name).map(WordType::Int),
+            },
+            _ => None,
+        }
+    }
+
+    /// Integer type arithmetic on the value uses
+    pub(super) fn kind(self) -> CheckedInt {
+        match self {
+            WordType::Int(kind) => kind,
+            WordType::Bool => CheckedInt::U24,
+        }
+    }
+}
+
+/// A function of the program
+#[derive(Debug, Clone)]
+struct Signature {
+    index: u32,
+    params: Vec<WordType>,
+    result: Option<WordType>,
+}
+
+/// Code generator for the WebAssembly target
+pub struct WasmCodegen {
+    functions: HashMap<Symbol, Signature>,
+    /// Address of the key and type of every storage field
+    storage: HashMap<Symbol, (u32, WordType)>,
+    events: HashMap<Symbol, (Vec<EventField>, [u8; 32])>,
+    data: Vec<(u32, Vec<u8>)>,
+    data_end: u32,
+
+    // The function being generated
+    checked: bool,
+    params: u32,
+    locals: HashMap<Symbol, u32>,
+    local_kinds: HashMap<Symbol, CheckedInt>,
+    local_types: Vec<ValType>,
+    body: Vec<WasmInstruction>,
+}
+
+impl Default for WasmCodegen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WasmCodegen {
+    pub fn new() -> Self {
+        WasmCodegen {
+            functions: HashMap::new(),
+            storage: HashMap::new(),
+            events: HashMap::new(),
+            data: Vec::new(),
+            data_end: DATA_OFFSET,
+            checked: true,
+            params: 0,
+            locals: HashMap::new(),
+            local_kinds: HashMap::new(),
+            local_types: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Generate the module of a program
+    pub fn generate(&mut self, program: &Program) -> Result<WasmModule, CodegenError> {
+        let first_function = WasmImport::ALL.len() as u32;
+        for definition in &program.definitions {
+            match definition {
+                Definition::FunctionDef {
+                    name,
+                    params,
+                    return_type,
+                    location,
+                    ..
+                } => {
+                    let params = params
+                        .iter()
+                        .map(|param| {
+                            WordType::of(&param.ty).ok_or_else(|| {
+                                unsupported(
+                                    &format!(
+                                        "Parameter {} of {} of type {}",
+                                        param.name, name, param.ty
+                                    ),
+                                    &param.location,
+                                )
+                            })
+                        })
+                        .collect::<Result<_, _>>()?;
+                    let result = match return_type {
+                        Some(Type::None { .. }) | None => None,
+                        Some(ty) => Some(WordType::of(ty).ok_or_else(|| {
+                            unsupported(&format!("Return type {} of {}", ty, name), location)
+                        })?),
+                    };
+                    let index = first_function + self.functions.len() as u32;
+                    self.functions.insert(
+                        *name,
+                        Signature {
+                            index,
+                            params,
+                            result,
+                        },
+                    );
+                }
+                Definition::StorageDef { fields, .. } => {
+                    for field in fields {
+                        let ty = Self::storage_value_type(&field.ty).ok_or_else(|| {
+                            unsupported(
+                                &format!("Storage field {} of type {}", field.name, field.ty),
+                                &field.location,
+                            )
+                        })?;
+                        let key = self.constant(&compute_storage_key(&field.name));
+                        self.storage.insert(field.name, (key, ty));
+                    }
+                }
+                Definition::EventDef { name, fields, .. } => {
+                    let topic = compute_event_topic(&compute_event_signature(name, fields));
+                    self.events.insert(*name, (fields.clone(), topic));
+                }
+                _ => {}
+            }
+        }
+
+        let mut functions = Vec::new();
+        for definition in &program.definitions {
+            if let Definition::FunctionDef {
+                name,
+                params,
+                body,
+                checked,
+                ..
+            } = definition
+            {
+                self.checked = *checked != Some(false);
+                functions.push(self.generate_function(name, params, body)?);
+            }
+        }
+
+        let call = first_function + functions.len() as u32;
+        functions.push(self.generate_dispatcher(program)?);
+        functions.push(WasmFunction {
+            name: "deploy".to_string(),
+            ty: FuncType::new(&[], &[]),
+            locals: Vec::new(),
+            body: Vec::new(),
+        });
+
+        Ok(WasmModule {
+            memory_pages: MEMORY_PAGES,
+            functions,
+            exports: vec![("call".to_string(), call), ("deploy".to_string(), call + 1)],
+            data: self.data.clone(),
+        })
+    }
+
+    /// Type of the value of a storage field
+    fn storage_value_type(ty: &Type) -> Option<WordType> {
+        match ty {
+            Type::Named { name, params, .. } if name == "StorageValue" && params.len() == 1 => {
+                WordType::of(&params[0])
+            }
+            ty => WordType::of(ty),
+        }
+    }
+
+    /// Place `bytes` in constant data, returning their address
+    fn constant(&mut self, bytes: &[u8]) -> u32 {
+        if let Some((address, _)) = self.data.iter().find(|(_, data)| data == bytes) {
+            return *address;
+        }
+        let address = self.data_end;
+        self.data.push((address, bytes.to_vec()));
+        self.data_end = (address + bytes.len() as u32).next_multiple_of(4);
+        address
+    }
+
+    fn emit(&mut self, instruction: WasmInstruction) {
+        self.body.push(instruction);
+    }
+
+    fn numeric(&mut self, op: NumericOp) {
+        self.body.push(WasmInstruction::Numeric(op));
+    }
+
+    fn call_import(&mut self, import: WasmImport) {
+        self.body.push(WasmInstruction::Call(import.index()));
+    }
+
+    /// Allocate a local of type `ty`
+    fn temp(&mut self, ty: ValType) -> u32 {
+        self.local_types.push(ty);
+        self.params + self.local_types.len() as u32 - 1
+    }
+
+    /// Start a function taking `params` word parameters
+    fn start_function(&mut self, params: u32) {
+        self.params = params;
+        self.locals.clear();
+        self.local_kinds.clear();
+        self.local_types.clear();
+        self.body.clear();
+    }
+
+    fn finish_function(&mut self, name: &str, ty: FuncType) -> WasmFunction {
+        WasmFunction {
+            name: name.to_string(),
+            ty,
+            locals: std::mem::take(&mut self.local_types),
+            body: std::mem::take(&mut self.body),
+        }
+    }
+
+    /// Generate a function of the program
+    ///
+    /// Every function returns a word, zero when it ends without `return`,
+    /// so calls are expressions whatever the function returns.
+    fn generate_function(
+        &mut self,
+        name: &str,
+        params: &[Parameter],
+        body: &Block,
+    ) -> Result<WasmFunction, CodegenError> {
+        self.start_function(params.len() as u32);
+        for (index, param) in params.iter().enumerate() {
+            self.locals.insert(param.name, index as u32);
+            if let Some(ty) = WordType::of(&param.ty) {
+                self.local_kinds.insert(param.name, ty.kind());
+            }
+        }
+
+        self.generate_block(body)?;
+        self.emit(WasmInstruction::I32Const(0));
+
+        let ty = FuncType::new(&vec![ValType::I32; params.len()], &[ValType::I32]);
+        Ok(self.finish_function(name, ty))
+    }
+
+    /// Generate `call`, the message dispatcher
+    ///
+    /// It reads the call input after the constant data, compares its
+    /// selector against the selector of every function, checks the call
+    /// value and the input length, calls the target with the argument
+    /// words and returns its result through `seal_return`. Only
+    /// `#[message]` functions are dispatched.
+    fn generate_dispatcher(&mut self, program: &Program) -> Result<WasmFunction, CodegenError> {
+        let mut targets = Vec::new();
+        let mut selectors: HashMap<[u8; 4], &str> = HashMap::new();
+        for definition in &program.definitions {
+            if let Definition::FunctionDef {
+                name,
+                params,
+                location,
+                ..
+            } = definition
+            {
+                let attributes = FunctionAttributes::of(definition)
+                    .map_err(|e| CodegenError::Generic(e.to_string()))?;
+                if attributes.export.is_some() {
+                    return Err(unsupported(&format!("Exporting {}", name), location));
+                }
+                if !attributes.message {
+                    continue;
+                }
+                let signature = &self.functions[name];
+                if signature.params.contains(&WordType::Bool) {
+                    return Err(unsupported(
+                        &format!("Dispatching Bool arguments of {}", name),
+                        location,
+                    ));
+                }
+                let selector = attributes
+                    .selector
+                    .unwrap_or_else(|| compute_selector_for_params(name, params));
+                if let Some(existing) = selectors.insert(selector, name) {
+                    return Err(CodegenError::Generic(format!(
+                        "Selector 0x{} of {} collides with {}",
+                        hex::encode(selector),
+                        name,
+                        existing
+                    )));
+                }
+                targets.push((signature.clone(), selector, attributes.payable));
+            }
+        }
+
+        self.start_function(0);
+        let input = self.data_end;
+        let capacity = MEMORY_PAGES * PAGE_SIZE - input;
+        let (length, selector, result) = (
+            self.temp(ValType::I32),
+            self.temp(ValType::I32),
+            self.temp(ValType::I32),
+        );
+
+        self.store_const(LENGTH_OFFSET, capacity as i32);
+        self.emit(WasmInstruction::I32Const(input as i32));
+        self.emit(WasmInstruction::I32Const(LENGTH_OFFSET as i32));
+        self.call_import(WasmImport::Input);
+        self.load(LENGTH_OFFSET, 0);
+        self.emit(WasmInstruction::LocalSet(length));
+
+        // Input must at least contain the selector
+        self.emit(WasmInstruction::LocalGet(length));
+        self.emit(WasmInstruction::I32Const(4));
+        self.numeric(NumericOp::I32LtU);
+        self.revert_if();
+        self.load(input, 0);
+        self.emit(WasmInstruction::LocalSet(selector));
+
+        for (signature, target, payable) in targets {
+            self.emit(WasmInstruction::LocalGet(selector));
+            self.emit(WasmInstruction::I32Const(u32::from_le_bytes(target) as i32));
+            self.numeric(NumericOp::I32Eq);
+            self.emit(WasmInstruction::If);
+
+            if !payable {
+                self.generate_reject_value();
+            }
+
+            // Call data is the selector followed by the little-endian words
+            // of every argument
+            let args = signature.params.len() as u32;
+            self.emit(WasmInstruction::LocalGet(length));
+            self.emit(WasmInstruction::I32Const((4 + 4 * args) as i32));
+            self.numeric(NumericOp::I32Ne);
+            self.revert_if();
+            for i in 0..args {
+                self.load(input, 4 + 4 * i);
+            }
+            self.emit(WasmInstruction::Call(signature.index));
+
+            let len = match signature.result {
+                Some(WordType::Bool) => {
+                    self.emit(WasmInstruction::LocalSet(result));
+                    self.emit(WasmInstruction::I32Const(VALUE_OFFSET as i32));
+                    self.emit(WasmInstruction::LocalGet(result));
+                    self.emit(WasmInstruction::I32Store8(0));
+                    1
+                }
+                Some(WordType::Int(_)) => {
+                    self.emit(WasmInstruction::LocalSet(result));
+                    self.emit(WasmInstruction::I32Const(VALUE_OFFSET as i32));
+                    self.emit(WasmInstruction::LocalGet(result));
+                    self.emit(WasmInstruction::I32Store(0));
+                    4
+                }
+                None => {
+                    self.emit(WasmInstruction::Drop);
+                    0
+                }
+            };
+            self.generate_return(0, VALUE_OFFSET, len);
+            self.emit(WasmInstruction::End);
+        }
+        self.generate_return(REVERT_FLAG, 0, 0);
+
+        Ok(self.finish_function("call", FuncType::new(&[], &[])))
+    }
+
+    /// Revert if the call transfers any value
+    fn generate_reject_value(&mut self) {
+        self.store_const(LENGTH_OFFSET, 16);
+        self.emit(WasmInstruction::I32Const(VALUE_OFFSET as i32));
+        self.emit(WasmInstruction::I32Const(LENGTH_OFFSET as i32));
+        self.call_import(WasmImport::ValueTransferred);
+        self.load(VALUE_OFFSET, 0);
+        for offset in [4, 8, 12] {
+            self.load(VALUE_OFFSET, offset);
+            self.numeric(NumericOp::I32Or);
+        }
+        self.revert_if();
+    }
+
+    /// Store `value` at `address`
+    fn store_const(&mut self, address: u32, value: i32) {
+        self.emit(WasmInstruction::I32Const(address as i32));
+        self.emit(WasmInstruction::I32Const(value));
+        self.emit(WasmInstruction::I32Store(0));
+    }
+
+    /// Load the word at `address + offset`
+    fn load(&mut self, address: u32, offset: u32) {
+        self.emit(WasmInstruction::I32Const(address as i32));
+        self.emit(WasmInstruction::I32Load(offset));
+    }
+
+    /// End the call through `seal_return`
+    fn generate_return(&mut self, flags: i32, address: u32, len: u32) {
+        self.emit(WasmInstruction::I32Const(flags));
+        self.emit(WasmInstruction::I32Const(address as i32));
+        self.emit(WasmInstruction::I32Const(len as i32));
+        self.call_import(WasmImport::Return);
+        self.emit(WasmInstruction::Unreachable);
+    }
+
+    /// Revert with empty data if the word on the stack is not zero
+    fn revert_if(&mut self) {
+        self.emit(WasmInstruction::If);
+        self.generate_return(REVERT_FLAG, 0, 0);
+        self.emit(WasmInstruction::End);
+    }
+
+    /// Revert with a constant message encoded as `signature`
+    fn generate_message_revert(&mut self, signature: &str, message: &str) {
+        let mut data = selector_for(signature).to_vec();
+        data.extend(encode_compact(message.len() as u32));
+        data.extend_from_slice(message.as_bytes());
+        let address = self.constant(&data);
+        self.generate_return(REVERT_FLAG, address, data.len() as u32);
+    }
+
+    fn generate_block(&mut self, block: &Block) -> Result<(), CodegenError> {
+        for statement in &block.statements {
+            self.generate_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn generate_statement(&mut self, statement: &Statement) -> Result<(), CodegenError> {
+        match statement {
+            Statement::Return { value, .. } => {
+                self.generate_expr(value)?;
+                self.emit(WasmInstruction::Return);
+            }
+            Statement::Assignment {
+                pattern: Pattern::Variable { name, .. },
+                value,
+                ..
+            } => {
+                self.record_local_kind(*name, value);
+                self.generate_expr(value)?;
+                self.generate_assignment(*name);
+            }
+            Statement::Use { name, value, .. } => {
+                self.record_local_kind(*name, value);
+                self.generate_expr(value)?;
+                let local = self.temp(ValType::I32);
+                self.locals.insert(*name, local);
+                self.emit(WasmInstruction::LocalSet(local));
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.generate_expr(condition)?;
+                self.emit(WasmInstruction::If);
+                self.generate_block(then_branch)?;
+                self.emit(WasmInstruction::Else);
+                self.generate_block(else_branch)?;
+                self.emit(WasmInstruction::End);
+            }
+            Statement::While {
+                condition,
+                body,
+                bound,
+                ..
+            } => {
+                let counter = self.generate_loop_counter(*bound);
+                self.emit(WasmInstruction::Block);
+                self.emit(WasmInstruction::Loop);
+                self.generate_expr(condition)?;
+                self.numeric(NumericOp::I32Eqz);
+                self.emit(WasmInstruction::BrIf(1));
+                self.generate_loop_iteration(counter, *bound);
+                self.generate_block(body)?;
+                self.emit(WasmInstruction::Br(0));
+                self.emit(WasmInstruction::End);
+                self.emit(WasmInstruction::End);
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                bound,
+                ..
+            } => self.generate_for(*variable, start, end, body, *bound)?,
+            Statement::Expr { expr, .. } => {
+                self.generate_expr(expr)?;
+                self.emit(WasmInstruction::Drop);
+            }
+            Statement::Unchecked { body, .. } => {
+                let checked = std::mem::replace(&mut self.checked, false);
+                let result = self.generate_block(body);
+                self.checked = checked;
+                result?;
+            }
+            Statement::Emit {
+                event,
+                args,
+                location,
+            } => self.generate_emit(*event, args, location)?,
+            Statement::Revert {
+                kind,
+                condition,
+                reason,
+                ..
+            } => {
+                if let Some(condition) = condition {
+                    self.generate_expr(condition)?;
+                    self.numeric(NumericOp::I32Eqz);
+                    self.emit(WasmInstruction::If);
+                }
+                match reason {
+                    Some(Expr::Literal {
+                        kind: LiteralKind::String(message),
+                        ..
+                    }) => {
+                        let signature = match kind {
+                            RevertKind::Assert => PANIC_SIGNATURE,
+                            _ => ERROR_SIGNATURE,
+                        };
+                        self.generate_message_revert(signature, message);
+                    }
+                    Some(reason) => {
+                        return Err(unsupported(
+                            "Reverting with an error variant",
+                            reason.location(),
+                        ))
+                    }
+                    None if *kind == RevertKind::Assert => {
+                        self.generate_message_revert(PANIC_SIGNATURE, "assertion failed");
+                    }
+                    None => self.generate_return(REVERT_FLAG, 0, 0),
+                }
+                if condition.is_some() {
+                    self.emit(WasmInstruction::End);
+                }
+            }
+            _ => return Err(unsupported("Statement type", statement.location())),
+        }
+        Ok(())
+    }
+
+    /// Store the word on the stack in a local or storage field, declaring
+    /// the local on first assignment
+    fn generate_assignment(&mut self, name: Symbol) {
+        if let Some(&local) = self.locals.get(&name) {
+            self.emit(WasmInstruction::LocalSet(local));
+        } else if let Some(&(key, _)) = self.storage.get(&name) {
+            self.generate_storage_store(key);
+        } else {
+            let local = self.temp(ValType::I32);
+            self.locals.insert(name, local);
+            self.emit(WasmInstruction::LocalSet(local));
+        }
+    }
+
+    /// Allocate and clear the iteration counter of a bounded loop
+    fn generate_loop_counter(&mut self, bound: Option<u32>) -> Option<u32> {
+        bound.map(|_| {
+            let counter = self.temp(ValType::I32);
+            self.emit(WasmInstruction::I32Const(0));
+            self.emit(WasmInstruction::LocalSet(counter));
+            counter
+        })
+    }
+
+    /// Start a loop iteration, panicking once a bounded loop would run
+    /// more iterations than its bound
+    ///
+    /// Iterations are not charged here: pallet-contracts meters modules by
+    /// instrumenting them when they are uploaded, and rejects modules
+    /// importing its `gas` function themselves.
+    fn generate_loop_iteration(&mut self, counter: Option<u32>, bound: Option<u32>) {
+        if let (Some(counter), Some(bound)) = (counter, bound) {
+            self.emit(WasmInstruction::LocalGet(counter));
+            self.emit(WasmInstruction::I32Const(1));
+            self.numeric(NumericOp::I32Add);
+            self.emit(WasmInstruction::LocalTee(counter));
+            self.emit(WasmInstruction::I32Const(bound as i32));
+            self.numeric(NumericOp::I32GtU);
+            self.emit(WasmInstruction::If);
+            self.generate_message_revert(PANIC_SIGNATURE, "loop bound exceeded");
+            self.emit(WasmInstruction::End);
+        }
+    }
+
+    /// Generate a `for i in range(start, end)` loop
+    ///
+    /// The end is evaluated once, before the first iteration.
+    fn generate_for(
+        &mut self,
+        variable: Symbol,
+        start: &Expr,
+        end: &Expr,
+        body: &Block,
+        bound: Option<u32>,
+    ) -> Result<(), CodegenError> {
+        let signed = self
+            .expr_kind(start)
+            .combine(self.expr_kind(end))
+            .is_signed();
+
+        self.record_local_kind(variable, start);
+        self.generate_expr(start)?;
+        self.generate_assignment(variable);
+        let end_local = self.temp(ValType::I32);
+        self.generate_expr(end)?;
+        self.emit(WasmInstruction::LocalSet(end_local));
+        let counter = self.generate_loop_counter(bound);
+
+        let read = Expr::Variable {
+            name: variable,
+            location: start.location().clone(),
+        };
+        self.emit(WasmInstruction::Block);
+        self.emit(WasmInstruction::Loop);
+        self.generate_expr(&read)?;
+        self.emit(WasmInstruction::LocalGet(end_local));
+        self.numeric(if signed {
+            NumericOp::I32GeS
+        } else {
+            NumericOp::I32GeU
+        });
+        self.emit(WasmInstruction::BrIf(1));
+        self.generate_loop_iteration(counter, bound);
+        self.generate_block(body)?;
+        self.generate_expr(&read)?;
+        self.emit(WasmInstruction::I32Const(1));
+        self.numeric(NumericOp::I32Add);
+        self.generate_assignment(variable);
+        self.emit(WasmInstruction::Br(0));
+        self.emit(WasmInstruction::End);
+        self.emit(WasmInstruction::End);
+        Ok(())
+    }
+
+    /// Generate an emit statement
+    ///
+    /// The arguments are evaluated first, then the topics and data laid
+    /// out like on RISC-V: the hash of the event signature and a 32-byte
+    /// topic per indexed field holding its little-endian value, and the
+    /// 4-byte little-endian words of the other fields.
+    fn generate_emit(
+        &mut self,
+        event: Symbol,
+        args: &[Expr],
+        location: &Location,
+    ) -> Result<(), CodegenError> {
+        let (fields, topic) = self
+            .events
+            .get(&event)
+            .cloned()
+            .ok_or_else(|| CodegenError::UndefinedEvent(event.to_string()))?;
+
+        if fields.len() != args.len() {
+            return Err(CodegenError::InvalidOperation(format!(
+                "Event {} expects {} arguments, found {}",
+                event,
+                fields.len(),
+                args.len()
+            )));
+        }
+        if let Some(field) = fields
+            .iter()
+            .find(|field| WordType::of(&field.ty).is_none())
+        {
+            return Err(unsupported(
+                &format!(
+                    "Field {} of event {} of type {}",
+                    field.name, event, field.ty
+                ),
+                &field.location,
+            ));
+        }
+
+        let indexed = fields.iter().filter(|field| field.indexed).count() as u32;
+        // The compact length of the topics goes right before them, so the
+        // topics are word-aligned
+        let topics = EVENT_OFFSET + 4;
+        let data = topics + 32 * (1 + indexed);
+        let data_len = 4 * (fields.len() as u32 - indexed);
+        if data + data_len > DATA_OFFSET {
+            return Err(unsupported(
+                &format!("Event {} with this many fields", event),
+                location,
+            ));
+        }
+
+        let mut values = Vec::new();
+        for arg in args {
+            self.generate_expr(arg)?;
+            let value = self.temp(ValType::I32);
+            self.emit(WasmInstruction::LocalSet(value));
+            values.push(value);
+        }
+
+        self.emit(WasmInstruction::I32Const(topics as i32 - 1));
+        self.emit(WasmInstruction::I32Const(
+            encode_compact(1 + indexed)[0] as i32,
+        ));
+        self.emit(WasmInstruction::I32Store8(0));
+        for (i, word) in topic.chunks(4).enumerate() {
+            let word = i32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            self.store_const(topics + 4 * i as u32, word);
+        }
+
+        let (mut topic_address, mut field_address) = (topics + 32, data);
+        for (field, value) in fields.iter().zip(values) {
+            let address = if field.indexed {
+                for word in 1..8 {
+                    self.store_const(topic_address + 4 * word, 0);
+                }
+                topic_address += 32;
+                topic_address - 32
+            } else {
+                field_address += 4;
+                field_address - 4
+            };
+            self.emit(WasmInstruction::I32Const(address as i32));
+            self.emit(WasmInstruction::LocalGet(value));
+            self.emit(WasmInstruction::I32Store(0));
+        }
+
+        self.emit(WasmInstruction::I32Const(topics as i32 - 1));
+        self.emit(WasmInstruction::I32Const((data - topics + 1) as i32));
+        self.emit(WasmInstruction::I32Const(data as i32));
+        self.emit(WasmInstruction::I32Const(data_len as i32));
+        self.call_import(WasmImport::DepositEvent);
+        Ok(())
+    }
+
+    /// Read a storage field onto the stack; missing entries read as zero
+    fn generate_storage_load(&mut self, key: u32) {
+        self.store_const(VALUE_OFFSET, 0);
+        self.store_const(LENGTH_OFFSET, 4);
+        self.emit(WasmInstruction::I32Const(key as i32));
+        self.emit(WasmInstruction::I32Const(VALUE_OFFSET as i32));
+        self.emit(WasmInstruction::I32Const(LENGTH_OFFSET as i32));
+        self.call_import(WasmImport::GetStorage);
+        self.emit(WasmInstruction::Drop);
+        self.load(VALUE_OFFSET, 0);
+    }
+
+    /// Write the word on the stack to a storage field
+    fn generate_storage_store(&mut self, key: u32) {
+        let value = self.temp(ValType::I32);
+        self.emit(WasmInstruction::LocalSet(value));
+        self.emit(WasmInstruction::I32Const(VALUE_OFFSET as i32));
+        self.emit(WasmInstruction::LocalGet(value));
+        self.emit(WasmInstruction::I32Store(0));
+        self.emit(WasmInstruction::I32Const(key as i32));
+        self.emit(WasmInstruction::I32Const(VALUE_OFFSET as i32));
+        self.emit(WasmInstruction::I32Const(4));
+        self.call_import(WasmImport::SetStorage);
+    }
+
+    /// Generate an expression, leaving its word on the stack
+    fn generate_expr(&mut self, expr: &Expr) -> Result<(), CodegenError> {
+        match expr {
+            Expr::Literal { kind, location } => {
+                let value = match kind {
+                    LiteralKind::Uint(value) => i32::try_from(*value).map_err(|_| {
+                        CodegenError::InvalidOperation(format!(
+                            "Literal value too large: {}",
+                            value
+                        ))
+                    })?,
+                    LiteralKind::Int(value) => *value,
+                    LiteralKind::Bool(value) => *value as i32,
+                    _ => return Err(unsupported("Literal type", location)),
+                };
+                self.emit(WasmInstruction::I32Const(value));
+            }
+            Expr::Variable { name, location } => {
+                if let Some(&local) = self.locals.get(name) {
+                    self.emit(WasmInstruction::LocalGet(local));
+                } else if let Some(&(key, _)) = self.storage.get(name) {
+                    self.generate_storage_load(key);
+                } else if self.functions.contains_key(name) {
+                    return Err(unsupported("Function values", location));
+                } else {
+                    return Err(CodegenError::UndefinedVariable(name.to_string()));
+                }
+            }
+            Expr::BinaryOp {
+                left,
+                operator,
+                right,
+                location,
+            } => self.generate_binary(operator, left, right, location)?,
+            Expr::UnaryOp {
+                operator: UnaryOperator::Not,
+                operand,
+                ..
+            } => {
+                self.generate_expr(operand)?;
+                self.numeric(NumericOp::I32Eqz);
+            }
+            Expr::FunctionCall {
+                function,
+                args,
+                location,
+                ..
+            } => self.generate_call(function, args, location)?,
+            _ => return Err(unsupported("Expression type", expr.location())),
+        }
+        Ok(())
+    }
+
+    /// Generate a call to a function or to a method of a storage value
+    fn generate_call(
+        &mut self,
+        function: &Expr,
+        args: &[Expr],
+        location: &Location,
+    ) -> Result<(), CodegenError> {
+        if let Some((name, method)) = function.as_method_target() {
+            if !self.locals.contains_key(&name) {
+                if let Some(&(key, _)) = self.storage.get(&name) {
+                    match (method, args) {
+                        ("get", []) => self.generate_storage_load(key),
+                        ("set", [value]) => {
+                            self.generate_expr(value)?;
+                            self.generate_storage_store(key);
+                            self.emit(WasmInstruction::I32Const(0));
+                        }
+                        _ => {
+                            return Err(CodegenError::InvalidOperation(format!(
+                                "Unknown storage method {}.{} with {} arguments",
+                                name,
+                                method,
+                                args.len()
+                            )))
+                        }
+                    }
+                    return Ok(());
+                }
+            }
+        }
+
+        let Expr::Variable { name, .. } = function else {
+            return Err(CodegenError::InvalidOperation(
+                "Function call with non-variable target".to_string(),
+            ));
+        };
+        let Some(signature) = self.functions.get(name).cloned() else {
+            return Err(unsupported(&format!("The builtin {}", name), location));
+        };
+        if signature.params.len() != args.len() {
+            return Err(CodegenError::InvalidOperation(format!(
+                "{} expects {} arguments, found {}",
+                name,
+                signature.params.len(),
+                args.len()
+            )));
+        }
+        for arg in args {
+            self.generate_expr(arg)?;
+        }
+        self.emit(WasmInstruction::Call(signature.index));
+        Ok(())
+    }
+
+    fn generate_binary(
+        &mut self,
+        operator: &BinaryOperator,
+        left: &Expr,
+        right: &Expr,
+        location: &Location,
+    ) -> Result<(), CodegenError> {
+        if matches!(operator, BinaryOperator::And | BinaryOperator::Or) {
+            return self.generate_logical(operator, left, right);
+        }
+
+        let kind = self.expr_kind(left).combine(self.expr_kind(right));
+        let arithmetic = matches!(
+            operator,
+            BinaryOperator::Add
+                | BinaryOperator::Sub
+                | BinaryOperator::Mul
+                | BinaryOperator::Div
+                | BinaryOperator::Mod
+        );
+        if self.checked && arithmetic {
+            return self.generate_checked_arithmetic(operator, kind, left, right);
+        }
+
+        let op = match operator {
+            BinaryOperator::Add => NumericOp::I32Add,
+            BinaryOperator::Sub => NumericOp::I32Sub,
+            BinaryOperator::Mul => NumericOp::I32Mul,
+            BinaryOperator::Div => NumericOp::I32DivS,
+            BinaryOperator::Mod => NumericOp::I32RemS,
+            BinaryOperator::BitAnd => NumericOp::I32And,
+            BinaryOperator::BitOr => NumericOp::I32Or,
+            BinaryOperator::BitXor => NumericOp::I32Xor,
+            // Comparisons are signed, as on RISC-V
+            BinaryOperator::Equal => NumericOp::I32Eq,
+            BinaryOperator::NotEqual => NumericOp::I32Ne,
+            BinaryOperator::Less => NumericOp::I32LtS,
+            BinaryOperator::LessEqual => NumericOp::I32LeS,
+            BinaryOperator::Greater => NumericOp::I32GtS,
+            BinaryOperator::GreaterEqual => NumericOp::I32GeS,
+            _ => {
+                return Err(unsupported(
+                    &format!("The operator {:?}", operator),
+                    location,
+                ))
+            }
+        };
+        self.generate_expr(left)?;
+        self.generate_expr(right)?;
+        self.numeric(op);
+        if arithmetic {
+            self.generate_wrap(kind);
+        }
+        Ok(())
+    }
+
+    /// Wrap the unchecked result on the stack to the range of `kind`, as on
+    /// RISC-V: 24-bit results are zero-extended when unsigned and
+    /// sign-extended when signed
+    fn generate_wrap(&mut self, kind: CheckedInt) {
+        if kind.bits() == 32 {
+            return;
+        }
+        self.emit(WasmInstruction::I32Const((1 << kind.bits()) - 1));
+        self.numeric(NumericOp::I32And);
+        if kind.is_signed() {
+            // (x ^ sign) - sign carries the sign bit into the upper bits
+            let sign = 1 << (kind.bits() - 1);
+            self.emit(WasmInstruction::I32Const(sign));
+            self.numeric(NumericOp::I32Xor);
+            self.emit(WasmInstruction::I32Const(sign));
+            self.numeric(NumericOp::I32Sub);
+        }
+    }
+
+    /// Generate `&&` or `||`, which only evaluate their right operand when
+    /// the left one does not decide the result, as 0 or 1
+    fn generate_logical(
+        &mut self,
+        operator: &BinaryOperator,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<(), CodegenError> {
+        let result = self.temp(ValType::I32);
+        self.generate_expr(left)?;
+        self.emit(WasmInstruction::I32Const(0));
+        self.numeric(NumericOp::I32Ne);
+        self.emit(WasmInstruction::LocalTee(result));
+        if *operator == BinaryOperator::Or {
+            self.numeric(NumericOp::I32Eqz);
+        }
+        self.emit(WasmInstruction::If);
+        self.generate_expr(right)?;
+        self.emit(WasmInstruction::I32Const(0));
+        self.numeric(NumericOp::I32Ne);
+        self.emit(WasmInstruction::LocalSet(result));
+        self.emit(WasmInstruction::End);
+        self.emit(WasmInstruction::LocalGet(result));
+        Ok(())
+    }
+
+    /// Generate arithmetic reverting when the result leaves the range of
+    /// `kind` or on division by zero
+    ///
+    /// The operands are extended to 64 bits, where no operation on words
+    /// can overflow, and the result is checked against the bounds of
+    /// `kind` before it is wrapped back to a word.
+    fn generate_checked_arithmetic(
+        &mut self,
+        operator: &BinaryOperator,
+        kind: CheckedInt,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<(), CodegenError> {
+        let signed = kind.is_signed();
+        let extend = if signed {
+            NumericOp::I64ExtendI32S
+        } else {
+            NumericOp::I64ExtendI32U
+        };
+
+        self.generate_expr(left)?;
+        self.numeric(extend);
+        self.generate_expr(right)?;
+        if matches!(operator, BinaryOperator::Div | BinaryOperator::Mod) {
+            let divisor = self.temp(ValType::I32);
+            self.emit(WasmInstruction::LocalTee(divisor));
+            self.numeric(extend);
+            self.emit(WasmInstruction::LocalGet(divisor));
+            self.numeric(NumericOp::I32Eqz);
+            self.revert_if();
+        } else {
+            self.numeric(extend);
+        }
+
+        self.numeric(match (operator, signed) {
+            (BinaryOperator::Add, _) => NumericOp::I64Add,
+            (BinaryOperator::Sub, _) => NumericOp::I64Sub,
+            (BinaryOperator::Mul, _) => NumericOp::I64Mul,
+            (BinaryOperator::Div, true) => NumericOp::I64DivS,
+            (BinaryOperator::Div, false) => NumericOp::I64DivU,
+            (BinaryOperator::Mod, true) => NumericOp::I64RemS,
+            _ => NumericOp::I64RemU,
+        });
+
+        let result = self.temp(ValType::I64);
+        self.emit(WasmInstruction::LocalTee(result));
+        if signed {
+            self.emit(WasmInstruction::I64Const(kind.min()));
+            self.numeric(NumericOp::I64LtS);
+            self.emit(WasmInstruction::LocalGet(result));
+            self.emit(WasmInstruction::I64Const(kind.max()));
+            self.numeric(NumericOp::I64GtS);
+            self.numeric(NumericOp::I32Or);
+        } else {
+            // Results below zero are above the maximum when unsigned
+            self.emit(WasmInstruction::I64Const(kind.max()));
+            self.numeric(NumericOp::I64GtU);
+        }
+        self.revert_if();
+        self.emit(WasmInstruction::LocalGet(result));
+        self.numeric(NumericOp::I32WrapI64);
+        Ok(())
+    }
+
+    fn record_local_kind(&mut self, name: Symbol, value: &Expr) {
+        if !self.locals.contains_key(&name) && !self.storage.contains_key(&name) {
+            let kind = self.expr_kind(value);
+            self.local_kinds.insert(name, kind);
+        }
+    }
+
+    /// Integer type of an expression, `u24` unless it involves signed or
+    /// 32-bit values, as on RISC-V
+    fn expr_kind(&self, expr: &Expr) -> CheckedInt {
+        match expr {
+            Expr::Literal {
+                kind: LiteralKind::Int(_),
+                ..
+            } => CheckedInt::I24,
+            Expr::Variable { name, .. } => self
+                .local_kinds
+                .get(name)
+                .copied()
+                .or_else(|| {
+                    (!self.locals.contains_key(name))
+                        .then(|| self.storage.get(name).map(|(_, ty)| ty.kind()))
+                        .flatten()
+                })
+                .unwrap_or(CheckedInt::U24),
+            Expr::BinaryOp {
+                left,
+                operator:
+                    BinaryOperator::Add
+                    | BinaryOperator::Sub
+                    | BinaryOperator::Mul
+                    | BinaryOperator::Div
+                    | BinaryOperator::Mod
+                    | BinaryOperator::BitAnd
+                    | BinaryOperator::BitOr
+                    | BinaryOperator::BitXor,
+                right,
+                ..
+            } => self.expr_kind(left).combine(self.expr_kind(right)),
+            Expr::FunctionCall { function, .. } => {
+                if let Some((name, "get")) = function.as_method_target() {
+                    if !self.locals.contains_key(&name) {
+                        if let Some((_, ty)) = self.storage.get(&name) {
+                            return ty.kind();
+                        }
+                    }
+                }
+                match &**function {
+                    Expr::Variable { name, .. } => self
+                        .functions
+                        .get(name)
+                        .and_then(|signature| signature.result)
+                        .map_or(CheckedInt::U24, WordType::kind),
+                    _ => CheckedInt::U24,
+                }
+            }
+            _ => CheckedInt::U24,
+        }
+    }
+}
+
+fn unsupported(what: &str, location: &Location) -> CodegenError {
+    CodegenError::UnsupportedFeature(format!(
+        "{} is not supported on the wasm32 target at line {}, column {}",
+        what, location.line, location.column
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parser::Parser;
+
+    fn generate(source: &str) -> Result<WasmModule, CodegenError> {
+        let program = Parser::new(source).parse_program().unwrap();
+        WasmCodegen::new().generate(&program)
+    }
+
+    #[test]
+    fn test_leb128() {
+        let mut out = Vec::new();
+        write_u32(&mut out, 624485);
+        assert_eq!(out, [0xE5, 0x8E, 0x26]);
+
+        for (value, expected) in [
+            (0i64, vec![0x00]),
+            (63, vec![0x3F]),
+            (64, vec![0xC0, 0x00]),
+            (-1, vec![0x7F]),
+            (-64, vec![0x40]),
+            (-65, vec![0xBF, 0x7F]),
+            (-123456, vec![0xC0, 0xBB, 0x78]),
+        ] {
+            let mut out = Vec::new();
+            write_i64(&mut out, value);
+            assert_eq!(out, expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_encode_module_sections() {
+        let module = generate(
+            r#"
+            storage {
+                total: u24,
+            }
+
+            fn add(a: u24, b: u24) -> u24 {
+                total = total + a;
+                return a + b;
+            }
+        "#,
+        )
+        .unwrap();
+
+        let binary = module.encode();
+        assert_eq!(&binary[..8], b"\0asm\x01\0\0\0");
+
+        // Sections follow each other in increasing order of their ids
+        let mut ids = Vec::new();
+        let mut at = 8;
+        while at < binary.len() {
+            ids.push(binary[at]);
+            let (mut size, mut shift) = (0usize, 0);
+            loop {
+                at += 1;
+                size |= ((binary[at] & 0x7F) as usize) << shift;
+                shift += 7;
+                if binary[at] & 0x80 == 0 {
+                    break;
+                }
+            }
+            at += 1 + size;
+        }
+        assert_eq!(at, binary.len());
+        assert_eq!(ids, [1, 2, 3, 7, 10, 11]);
+
+        let call = module.export("call").unwrap();
+        assert_eq!(module.function(call).unwrap().name, "call");
+        assert_eq!(
+            module
+                .function(module.export("deploy").unwrap())
+                .unwrap()
+                .name,
+            "deploy"
+        );
+        assert_eq!(
+            module.data[0],
+            (DATA_OFFSET, compute_storage_key("total").to_vec())
+        );
+    }
+
+    #[test]
+    fn test_text_format() {
+        let module = generate("fn inc(x: u24) -> u24 { unchecked { return x + 1; } }").unwrap();
+        let text = module.to_string();
+        assert!(text.starts_with("(module\n  (import \"env\" \"memory\" (memory 1))"));
+        assert!(text.contains(
+            "(import \"seal0\" \"seal_return\" (func $seal_return (;1;) (param i32 i32 i32)))"
+        ));
+        assert!(text.contains(
+            "  (func $inc (;7;) (param i32) (result i32)\n    local.get 0\n    i32.const 1\n    i32.add\n    i32.const 16777215\n    i32.and\n    return\n"
+        ));
+        assert!(text.contains("(export \"call\" (func 8))"));
+        // pallet-contracts rejects modules importing its gas function
+        assert!(!text.contains("\"gas\""));
+    }
+
+    #[test]
+    fn test_checked_arithmetic_widens() {
+        let module = generate("fn mul(a: i24, b: i24) -> i24 { return a * b; }").unwrap();
+        let body = &module.functions[0].body;
+        assert!(body.contains(&WasmInstruction::Numeric(NumericOp::I64ExtendI32S)));
+        assert!(body.contains(&WasmInstruction::I64Const(CheckedInt::I24.min())));
+        assert!(body.contains(&WasmInstruction::Numeric(NumericOp::I64Mul)));
+    }
+
+    #[test]
+    fn test_unsupported_features() {
+        let error = generate("fn hash(data: Bytes) -> u24 { return 0; }").unwrap_err();
+        assert!(matches!(error, CodegenError::UnsupportedFeature(_)));
+        assert!(error
+            .to_string()
+            .contains("on the wasm32 target at line 1, column 9"));
+
+        let error = generate(
+            r#"
+            storage {
+                balances: StorageMap<u24, u24>,
+            }
+        "#,
+        )
+        .unwrap_err();
+        assert!(matches!(error, CodegenError::UnsupportedFeature(_)));
+    }
+}
@@ -0,0 +1,898 @@
+//! Yul objects and their assembly to EVM bytecode
+//!
+//! The EVM backend ([`EvmCodegen`](super::evm::EvmCodegen)) lowers programs
+//! to a [`YulObject`]. It prints as Yul source, which `solc
+//! --strict-assembly` also accepts, and assembles to EVM bytecode here,
+//! without solc.
+//!
+//! The assembler covers the Yul the backend generates: functions with at
+//! most one result, defined at the top of the code of an object, and
+//! objects with sub-objects reached through `dataoffset`, `datasize` and
+//! `datacopy`. Variables live on the EVM stack, so a variable more than 16
+//! slots down is an error, as with solc. Functions are called by pushing
+//! the return address, then the arguments from the last to the first, and
+//! return with their result in place of both.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::compiler::codegen::risc_v::CodegenError;
+
+/// A 256-bit EVM word, big-endian
+pub type Word = [u8; 32];
+
+/// The word of an unsigned value
+pub fn word(value: u128) -> Word {
+    let mut word = [0; 32];
+    word[16..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+/// The two's complement word of a signed value
+pub fn signed_word(value: i64) -> Word {
+    let mut word = if value < 0 { [0xFF; 32] } else { [0; 32] };
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+/// A Yul expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum YulExpr {
+    Literal(Word),
+    /// A string literal, the name of an object for `dataoffset` and
+    /// `datasize`
+    String(String),
+    Identifier(String),
+    Call(String, Vec<YulExpr>),
+}
+
+impl YulExpr {
+    pub fn num(value: u128) -> Self {
+        YulExpr::Literal(word(value))
+    }
+
+    pub fn id(name: impl Into<String>) -> Self {
+        YulExpr::Identifier(name.into())
+    }
+
+    pub fn call(function: impl Into<String>, args: Vec<YulExpr>) -> Self {
+        YulExpr::Call(function.into(), args)
+    }
+}
+
+impl fmt::Display for YulExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            YulExpr::Literal(word) => {
+                let digits = hex::encode(word);
+                let digits = digits.trim_start_matches('0');
+                match u16::from_str_radix(digits, 16) {
+                    Ok(value) => write!(f, "{}", value),
+                    Err(_) if digits.is_empty() => write!(f, "0"),
+                    Err(_) => write!(f, "0x{}", digits),
+                }
+            }
+            YulExpr::String(value) => write!(f, "{:?}", value),
+            YulExpr::Identifier(name) => write!(f, "{}", name),
+            YulExpr::Call(function, args) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({})", function, args.join(", "))
+            }
+        }
+    }
+}
+
+/// A Yul statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum YulStatement {
+    Block(Vec<YulStatement>),
+    Function(YulFunction),
+    /// `let name := value`, or zero without a value
+    Let(String, Option<YulExpr>),
+    Assign(String, YulExpr),
+    Expr(YulExpr),
+    If(YulExpr, Vec<YulStatement>),
+    Switch {
+        expr: YulExpr,
+        cases: Vec<(Word, Vec<YulStatement>)>,
+        default: Option<Vec<YulStatement>>,
+    },
+    For {
+        init: Vec<YulStatement>,
+        condition: YulExpr,
+        post: Vec<YulStatement>,
+        body: Vec<YulStatement>,
+    },
+    Break,
+    Continue,
+    Leave,
+}
+
+/// A Yul function definition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YulFunction {
+    pub name: String,
+    pub params: Vec<String>,
+    pub result: Option<String>,
+    pub body: Vec<YulStatement>,
+}
+
+/// A Yul object: code, and the objects it deploys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YulObject {
+    pub name: String,
+    pub code: Vec<YulStatement>,
+    pub objects: Vec<YulObject>,
+}
+
+impl YulObject {
+    /// The sub-object named `name`
+    pub fn object(&self, name: &str) -> Option<&YulObject> {
+        self.objects.iter().find(|object| object.name == name)
+    }
+
+    /// Assemble the object to EVM bytecode: its code, followed by the
+    /// bytecode of its sub-objects
+    pub fn assemble(&self) -> Result<Vec<u8>, CodegenError> {
+        let objects = self
+            .objects
+            .iter()
+            .map(|object| Ok((object.name.as_str(), object.assemble()?)))
+            .collect::<Result<Vec<_>, CodegenError>>()?;
+        Assembler::new(&objects).assemble(&self.code)
+    }
+}
+
+impl fmt::Display for YulObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer {
+            out: String::new(),
+            indent: 0,
+        };
+        printer.object(self);
+        write!(f, "{}", printer.out)
+    }
+}
+
+/// Writes Yul source, indented by four spaces
+struct Printer {
+    out: String,
+    indent: usize,
+}
+
+impl Printer {
+    fn line(&mut self, text: &str) {
+        self.out.push_str(&"    ".repeat(self.indent));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn object(&mut self, object: &YulObject) {
+        self.line(&format!("object {:?} {{", object.name));
+        self.indent += 1;
+        self.block("code", &object.code);
+        for sub in &object.objects {
+            self.object(sub);
+        }
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    /// Write `head {`, the statements and the closing brace
+    fn block(&mut self, head: &str, statements: &[YulStatement]) {
+        let open = match head {
+            "" => "{".to_string(),
+            head => format!("{} {{", head),
+        };
+        if statements.is_empty() {
+            self.line(&format!("{} }}", open));
+            return;
+        }
+        self.line(&open);
+        self.indent += 1;
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn statement(&mut self, statement: &YulStatement) {
+        match statement {
+            YulStatement::Block(body) => self.block("", body),
+            YulStatement::Function(function) => {
+                let mut head =
+                    format!("function {}({})", function.name, function.params.join(", "));
+                if let Some(result) = &function.result {
+                    head.push_str(&format!(" -> {}", result));
+                }
+                self.block(&head, &function.body);
+            }
+            YulStatement::Let(name, Some(value)) => {
+                self.line(&format!("let {} := {}", name, value))
+            }
+            YulStatement::Let(name, None) => self.line(&format!("let {}", name)),
+            YulStatement::Assign(name, value) => self.line(&format!("{} := {}", name, value)),
+            YulStatement::Expr(expr) => self.line(&expr.to_string()),
+            YulStatement::If(condition, body) => self.block(&format!("if {}", condition), body),
+            YulStatement::Switch {
+                expr,
+                cases,
+                default,
+            } => {
+                self.line(&format!("switch {}", expr));
+                for (value, body) in cases {
+                    self.block(&format!("case {}", YulExpr::Literal(*value)), body);
+                }
+                if let Some(body) = default {
+                    self.block("default", body);
+                }
+            }
+            YulStatement::For {
+                init,
+                condition,
+                post,
+                body,
+            } => {
+                let inline = |statements: &[YulStatement]| {
+                    let mut printer = Printer {
+                        out: String::new(),
+                        indent: 0,
+                    };
+                    for statement in statements {
+                        printer.statement(statement);
+                    }
+                    match printer.out.trim() {
+                        "" => "{ }".to_string(),
+                        text => format!("{{ {} }}", text.replace('\n', " ")),
+                    }
+                };
+                let head = format!("for {} {} {}", inline(init), condition, inline(post));
+                self.block(&head, body);
+            }
+            YulStatement::Break => self.line("break"),
+            YulStatement::Continue => self.line("continue"),
+            YulStatement::Leave => self.line("leave"),
+        }
+    }
+}
+
+const STOP: u8 = 0x00;
+const ISZERO: u8 = 0x15;
+const EQ: u8 = 0x14;
+const CODECOPY: u8 = 0x39;
+const POP: u8 = 0x50;
+const JUMP: u8 = 0x56;
+const JUMPI: u8 = 0x57;
+const JUMPDEST: u8 = 0x5B;
+const PUSH1: u8 = 0x60;
+const PUSH2: u8 = 0x61;
+const DUP1: u8 = 0x80;
+const SWAP1: u8 = 0x90;
+
+/// Opcode, number of arguments and of results of a Yul builtin
+fn builtin(name: &str) -> Option<(u8, usize, usize)> {
+    Some(match name {
+        "stop" => (STOP, 0, 0),
+        "add" => (0x01, 2, 1),
+        "mul" => (0x02, 2, 1),
+        "sub" => (0x03, 2, 1),
+        "div" => (0x04, 2, 1),
+        "sdiv" => (0x05, 2, 1),
+        "mod" => (0x06, 2, 1),
+        "smod" => (0x07, 2, 1),
+        "exp" => (0x0A, 2, 1),
+        "signextend" => (0x0B, 2, 1),
+        "lt" => (0x10, 2, 1),
+        "gt" => (0x11, 2, 1),
+        "slt" => (0x12, 2, 1),
+        "sgt" => (0x13, 2, 1),
+        "eq" => (EQ, 2, 1),
+        "iszero" => (ISZERO, 1, 1),
+        "and" => (0x16, 2, 1),
+        "or" => (0x17, 2, 1),
+        "xor" => (0x18, 2, 1),
+        "not" => (0x19, 1, 1),
+        "byte" => (0x1A, 2, 1),
+        "shl" => (0x1B, 2, 1),
+        "shr" => (0x1C, 2, 1),
+        "sar" => (0x1D, 2, 1),
+        "keccak256" => (0x20, 2, 1),
+        "address" => (0x30, 0, 1),
+        "caller" => (0x33, 0, 1),
+        "callvalue" => (0x34, 0, 1),
+        "calldataload" => (0x35, 1, 1),
+        "calldatasize" => (0x36, 0, 1),
+        "calldatacopy" => (0x37, 3, 0),
+        "codecopy" | "datacopy" => (CODECOPY, 3, 0),
+        "pop" => (POP, 1, 0),
+        "mload" => (0x51, 1, 1),
+        "mstore" => (0x52, 2, 0),
+        "mstore8" => (0x53, 2, 0),
+        "sload" => (0x54, 1, 1),
+        "sstore" => (0x55, 2, 0),
+        "gas" => (0x5A, 0, 1),
+        "log0" => (0xA0, 2, 0),
+        "log1" => (0xA1, 3, 0),
+        "log2" => (0xA2, 4, 0),
+        "log3" => (0xA3, 5, 0),
+        "log4" => (0xA4, 6, 0),
+        "return" => (0xF3, 2, 0),
+        "revert" => (0xFD, 2, 0),
+        "invalid" => (0xFE, 0, 0),
+        _ => return None,
+    })
+}
+
+/// A function of the object being assembled
+#[derive(Debug, Clone, Copy)]
+struct FunctionLabel {
+    label: usize,
+    params: usize,
+    results: usize,
+}
+
+/// Where `break` and `continue` go, and the stack height they restore
+struct LoopLabels {
+    height: usize,
+    post: usize,
+    end: usize,
+}
+
+/// Where `leave` goes, and the stack height it restores
+struct ExitLabel {
+    height: usize,
+    label: usize,
+}
+
+/// Fixed-size placeholders patched once addresses are known
+enum Fixup {
+    Label(usize),
+    DataOffset(usize),
+}
+
+/// Compiles the code of one object
+struct Assembler<'a> {
+    code: Vec<u8>,
+    labels: Vec<Option<usize>>,
+    fixups: Vec<(usize, Fixup)>,
+    objects: &'a [(&'a str, Vec<u8>)],
+    functions: HashMap<String, FunctionLabel>,
+    /// The names of the stack slots, bottom first; `None` for temporaries
+    stack: Vec<Option<String>>,
+    loops: Vec<LoopLabels>,
+    exit: Option<ExitLabel>,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(objects: &'a [(&'a str, Vec<u8>)]) -> Self {
+        Assembler {
+            code: Vec::new(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+            objects,
+            functions: HashMap::new(),
+            stack: Vec::new(),
+            loops: Vec::new(),
+            exit: None,
+        }
+    }
+
+    fn assemble(mut self, code: &[YulStatement]) -> Result<Vec<u8>, CodegenError> {
+        let mut functions = Vec::new();
+        for statement in code {
+            if let YulStatement::Function(function) = statement {
+                let label = self.new_label();
+                self.functions.insert(
+                    function.name.clone(),
+                    FunctionLabel {
+                        label,
+                        params: function.params.len(),
+                        results: function.result.is_some() as usize,
+                    },
+                );
+                functions.push((label, function));
+            }
+        }
+
+        let main: Vec<YulStatement> = code
+            .iter()
+            .filter(|statement| !matches!(statement, YulStatement::Function(_)))
+            .cloned()
+            .collect();
+        self.block(&main)?;
+        self.code.push(STOP);
+        for (label, function) in functions {
+            self.function(label, function)?;
+        }
+
+        let code_len = self.code.len();
+        for (position, fixup) in std::mem::take(&mut self.fixups) {
+            let target = match fixup {
+                Fixup::Label(label) => self.labels[label].ok_or_else(|| {
+                    CodegenError::InvalidAssembly(format!("Unplaced label {}", label))
+                })?,
+                Fixup::DataOffset(index) => {
+                    code_len
+                        + self.objects[..index]
+                            .iter()
+                            .map(|(_, code)| code.len())
+                            .sum::<usize>()
+                }
+            };
+            let target = u16::try_from(target).map_err(|_| {
+                CodegenError::InvalidAssembly("Bytecode larger than 64 KiB".to_string())
+            })?;
+            self.code[position..position + 2].copy_from_slice(&target.to_be_bytes());
+        }
+
+        for (_, code) in self.objects {
+            self.code.extend_from_slice(code);
+        }
+        Ok(self.code)
+    }
+
+    fn new_label(&mut self) -> usize {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn place(&mut self, label: usize) {
+        self.labels[label] = Some(self.code.len());
+        self.code.push(JUMPDEST);
+    }
+
+    /// Push the address of `label`
+    fn push_label(&mut self, label: usize) {
+        self.code.push(PUSH2);
+        self.fixups.push((self.code.len(), Fixup::Label(label)));
+        self.code.extend_from_slice(&[0, 0]);
+    }
+
+    fn jump(&mut self, label: usize) {
+        self.push_label(label);
+        self.code.push(JUMP);
+    }
+
+    fn push_word(&mut self, word: &Word) {
+        let bytes = match word.iter().position(|&byte| byte != 0) {
+            Some(start) => &word[start..],
+            None => &word[31..],
+        };
+        self.code.push(PUSH1 + bytes.len() as u8 - 1);
+        self.code.extend_from_slice(bytes);
+    }
+
+    /// Emit `count` pops without changing the tracked stack, before
+    /// jumping away
+    fn pop_slots(&mut self, count: usize) {
+        self.code.extend(std::iter::repeat_n(POP, count));
+    }
+
+    /// Pop the slots above `height`
+    fn truncate(&mut self, height: usize) {
+        self.pop_slots(self.stack.len() - height);
+        self.stack.truncate(height);
+    }
+
+    /// How far below the top the slot at `index` is, checking `DUP` and
+    /// `SWAP` reach it
+    fn depth(&self, index: usize, name: &str) -> Result<u8, CodegenError> {
+        let depth = self.stack.len() - index;
+        if depth > 16 {
+            return Err(CodegenError::InvalidAssembly(format!(
+                "Stack too deep: variable {} is {} slots down",
+                name, depth
+            )));
+        }
+        Ok(depth as u8)
+    }
+
+    fn slot(&self, name: &str) -> Result<usize, CodegenError> {
+        self.stack
+            .iter()
+            .rposition(|slot| slot.as_deref() == Some(name))
+            .ok_or_else(|| CodegenError::UndefinedVariable(name.to_string()))
+    }
+
+    fn function(&mut self, label: usize, function: &YulFunction) -> Result<(), CodegenError> {
+        self.place(label);
+        self.stack.clear();
+        self.stack.push(None);
+        for param in function.params.iter().rev() {
+            self.stack.push(Some(param.clone()));
+        }
+        if let Some(result) = &function.result {
+            self.push_word(&word(0));
+            self.stack.push(Some(result.clone()));
+        }
+
+        let exit = self.new_label();
+        self.exit = Some(ExitLabel {
+            height: self.stack.len(),
+            label: exit,
+        });
+        self.block(&function.body)?;
+        self.exit = None;
+        self.place(exit);
+
+        // Drop the parameters below the result, then move the result
+        // below the return address
+        for _ in &function.params {
+            if function.result.is_some() {
+                self.code.push(SWAP1);
+            }
+            self.code.push(POP);
+        }
+        if function.result.is_some() {
+            self.code.push(SWAP1);
+        }
+        self.code.push(JUMP);
+        Ok(())
+    }
+
+    fn block(&mut self, statements: &[YulStatement]) -> Result<(), CodegenError> {
+        let height = self.stack.len();
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        self.truncate(height);
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &YulStatement) -> Result<(), CodegenError> {
+        match statement {
+            YulStatement::Block(body) => self.block(body)?,
+            YulStatement::Function(function) => {
+                return Err(CodegenError::InvalidAssembly(format!(
+                    "Function {} must be defined at the top of the code",
+                    function.name
+                )))
+            }
+            YulStatement::Let(name, value) => {
+                match value {
+                    Some(value) => self.expr(value, 1)?,
+                    None => {
+                        self.push_word(&word(0));
+                        self.stack.push(None);
+                    }
+                }
+                *self.stack.last_mut().unwrap() = Some(name.clone());
+            }
+            YulStatement::Assign(name, value) => {
+                let index = self.slot(name)?;
+                self.expr(value, 1)?;
+                let depth = self.depth(index + 1, name)?;
+                self.code.push(SWAP1 + depth - 1);
+                self.code.push(POP);
+                self.stack.pop();
+            }
+            YulStatement::Expr(expr) => self.expr(expr, 0)?,
+            YulStatement::If(condition, body) => {
+                let end = self.new_label();
+                self.expr(condition, 1)?;
+                self.code.push(ISZERO);
+                self.push_label(end);
+                self.code.push(JUMPI);
+                self.stack.pop();
+                self.block(body)?;
+                self.place(end);
+            }
+            YulStatement::Switch {
+                expr,
+                cases,
+                default,
+            } => {
+                let end = self.new_label();
+                self.expr(expr, 1)?;
+                let labels: Vec<usize> = cases.iter().map(|_| self.new_label()).collect();
+                for ((value, _), &label) in cases.iter().zip(&labels) {
+                    self.code.push(DUP1);
+                    self.push_word(value);
+                    self.code.push(EQ);
+                    self.push_label(label);
+                    self.code.push(JUMPI);
+                }
+
+                let height = self.stack.len() - 1;
+                self.truncate(height);
+                self.block(default.as_deref().unwrap_or_default())?;
+                self.jump(end);
+                for ((_, body), label) in cases.iter().zip(labels) {
+                    self.place(label);
+                    self.stack.push(None);
+                    self.truncate(height);
+                    self.block(body)?;
+                    self.jump(end);
+                }
+                self.place(end);
+            }
+            YulStatement::For {
+                init,
+                condition,
+                post,
+                body,
+            } => {
+                let height = self.stack.len();
+                for statement in init {
+                    self.statement(statement)?;
+                }
+                let (start, continue_at, end) =
+                    (self.new_label(), self.new_label(), self.new_label());
+                self.place(start);
+                self.expr(condition, 1)?;
+                self.code.push(ISZERO);
+                self.push_label(end);
+                self.code.push(JUMPI);
+                self.stack.pop();
+
+                self.loops.push(LoopLabels {
+                    height: self.stack.len(),
+                    post: continue_at,
+                    end,
+                });
+                self.block(body)?;
+                self.loops.pop();
+                self.place(continue_at);
+                self.block(post)?;
+                self.jump(start);
+                self.place(end);
+                self.truncate(height);
+            }
+            YulStatement::Break | YulStatement::Continue => {
+                let labels = self.loops.last().ok_or_else(|| {
+                    CodegenError::InvalidAssembly("break or continue outside a loop".to_string())
+                })?;
+                let (height, target) = match statement {
+                    YulStatement::Break => (labels.height, labels.end),
+                    _ => (labels.height, labels.post),
+                };
+                self.pop_slots(self.stack.len() - height);
+                self.jump(target);
+            }
+            YulStatement::Leave => {
+                let exit = self.exit.as_ref().ok_or_else(|| {
+                    CodegenError::InvalidAssembly("leave outside a function".to_string())
+                })?;
+                let (height, label) = (exit.height, exit.label);
+                self.pop_slots(self.stack.len() - height);
+                self.jump(label);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile an expression leaving `results` values on the stack
+    fn expr(&mut self, expr: &YulExpr, results: usize) -> Result<(), CodegenError> {
+        let produced = match expr {
+            YulExpr::Literal(value) => {
+                self.push_word(value);
+                self.stack.push(None);
+                1
+            }
+            YulExpr::String(value) => {
+                return Err(CodegenError::InvalidAssembly(format!(
+                    "String literal {:?} outside dataoffset or datasize",
+                    value
+                )))
+            }
+            YulExpr::Identifier(name) => {
+                let index = self.slot(name)?;
+                let depth = self.depth(index, name)?;
+                self.code.push(DUP1 + depth - 1);
+                self.stack.push(None);
+                1
+            }
+            YulExpr::Call(function, args) => self.call(function, args)?,
+        };
+        if produced != results {
+            return Err(CodegenError::InvalidAssembly(format!(
+                "{} gives {} values where {} are expected",
+                expr, produced, results
+            )));
+        }
+        Ok(())
+    }
+
+    /// Compile a call, returning how many values it leaves
+    fn call(&mut self, function: &str, args: &[YulExpr]) -> Result<usize, CodegenError> {
+        if let ("dataoffset" | "datasize", [YulExpr::String(name)]) = (function, args) {
+            let index = self
+                .objects
+                .iter()
+                .position(|(object, _)| object == name)
+                .ok_or_else(|| {
+                    CodegenError::InvalidAssembly(format!("Unknown object {:?}", name))
+                })?;
+            self.code.push(PUSH2);
+            if function == "dataoffset" {
+                self.fixups
+                    .push((self.code.len(), Fixup::DataOffset(index)));
+                self.code.extend_from_slice(&[0, 0]);
+            } else {
+                let size = u16::try_from(self.objects[index].1.len()).map_err(|_| {
+                    CodegenError::InvalidAssembly("Bytecode larger than 64 KiB".to_string())
+                })?;
+                self.code.extend_from_slice(&size.to_be_bytes());
+            }
+            self.stack.push(None);
+            return Ok(1);
+        }
+
+        let (params, results) = match (builtin(function), self.functions.get(function)) {
+            (Some((_, params, results)), _) => (params, results),
+            (None, Some(target)) => (target.params, target.results),
+            (None, None) => {
+                return Err(CodegenError::InvalidAssembly(format!(
+                    "Unknown Yul function {}",
+                    function
+                )))
+            }
+        };
+        if args.len() != params {
+            return Err(CodegenError::InvalidAssembly(format!(
+                "{} takes {} arguments, found {}",
+                function,
+                params,
+                args.len()
+            )));
+        }
+
+        let height = self.stack.len();
+        match builtin(function) {
+            Some((opcode, _, _)) => {
+                for arg in args.iter().rev() {
+                    self.expr(arg, 1)?;
+                }
+                self.code.push(opcode);
+            }
+            None => {
+                let target = self.functions[function];
+                let back = self.new_label();
+                self.push_label(back);
+                self.stack.push(None);
+                for arg in args.iter().rev() {
+                    self.expr(arg, 1)?;
+                }
+                self.jump(target.label);
+                self.place(back);
+            }
+        }
+        self.stack.truncate(height);
+        self.stack.extend(std::iter::repeat_n(None, results));
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(
+        name: &str,
+        params: &[&str],
+        result: Option<&str>,
+        body: Vec<YulStatement>,
+    ) -> YulStatement {
+        YulStatement::Function(YulFunction {
+            name: name.to_string(),
+            params: params.iter().map(|param| param.to_string()).collect(),
+            result: result.map(str::to_string),
+            body,
+        })
+    }
+
+    fn object() -> YulObject {
+        let runtime = YulObject {
+            name: "Counter_deployed".to_string(),
+            code: vec![
+                YulStatement::Expr(YulExpr::call(
+                    "mstore",
+                    vec![
+                        YulExpr::num(0),
+                        YulExpr::call("double", vec![YulExpr::num(21)]),
+                    ],
+                )),
+                YulStatement::Expr(YulExpr::call(
+                    "return",
+                    vec![YulExpr::num(0), YulExpr::num(32)],
+                )),
+                function(
+                    "double",
+                    &["x"],
+                    Some("r"),
+                    vec![YulStatement::Assign(
+                        "r".to_string(),
+                        YulExpr::call("add", vec![YulExpr::id("x"), YulExpr::id("x")]),
+                    )],
+                ),
+            ],
+            objects: Vec::new(),
+        };
+        let name = || YulExpr::String("Counter_deployed".to_string());
+        YulObject {
+            name: "Counter".to_string(),
+            code: vec![
+                YulStatement::Expr(YulExpr::call(
+                    "datacopy",
+                    vec![
+                        YulExpr::num(0),
+                        YulExpr::call("dataoffset", vec![name()]),
+                        YulExpr::call("datasize", vec![name()]),
+                    ],
+                )),
+                YulStatement::Expr(YulExpr::call(
+                    "return",
+                    vec![YulExpr::num(0), YulExpr::call("datasize", vec![name()])],
+                )),
+            ],
+            objects: vec![runtime],
+        }
+    }
+
+    #[test]
+    fn test_print_object() {
+        let text = object().to_string();
+        assert!(text.starts_with("object \"Counter\" {\n    code {\n"));
+        assert!(text.contains(
+            "        datacopy(0, dataoffset(\"Counter_deployed\"), datasize(\"Counter_deployed\"))\n"
+        ));
+        assert!(text
+            .contains("            function double(x) -> r {\n                r := add(x, x)\n"));
+        assert_eq!(YulExpr::num(0xFFFFFF).to_string(), "0xffffff");
+        assert_eq!(
+            YulExpr::Literal(signed_word(-1)).to_string(),
+            format!("0x{}", "f".repeat(64))
+        );
+    }
+
+    #[test]
+    fn test_assemble_sub_objects() {
+        let object = object();
+        let runtime = object
+            .object("Counter_deployed")
+            .unwrap()
+            .assemble()
+            .unwrap();
+        let deploy = object.assemble().unwrap();
+
+        assert!(deploy.ends_with(&runtime));
+        let offset = deploy.len() - runtime.len();
+        // datasize, then dataoffset, pushed as two-byte immediates
+        assert_eq!(deploy[0], PUSH2);
+        assert_eq!(
+            u16::from_be_bytes([deploy[1], deploy[2]]) as usize,
+            runtime.len()
+        );
+        assert_eq!(deploy[3], PUSH2);
+        assert_eq!(u16::from_be_bytes([deploy[4], deploy[5]]) as usize, offset);
+    }
+
+    #[test]
+    fn test_stack_too_deep() {
+        let params: Vec<String> = (0..17).map(|i| format!("p{}", i)).collect();
+        let params: Vec<&str> = params.iter().map(String::as_str).collect();
+        let read = |name: &str| {
+            function(
+                "f",
+                &params,
+                Some("r"),
+                vec![YulStatement::Assign("r".to_string(), YulExpr::id(name))],
+            )
+        };
+        let assemble = |statement| {
+            YulObject {
+                name: "Deep".to_string(),
+                code: vec![statement],
+                objects: Vec::new(),
+            }
+            .assemble()
+        };
+
+        assert!(assemble(read("p0")).is_ok());
+        assert!(matches!(
+            assemble(read("p16")),
+            Err(CodegenError::InvalidAssembly(message)) if message.contains("Stack too deep")
+        ));
+    }
+}
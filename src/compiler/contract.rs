@@ -0,0 +1,347 @@
+//! Contract declarations
+//!
+//! A contract declares its state, events and messages in one block:
+//!
+//! ```text
+//! #[version(2)]
+//! contract Counter {
+//!     pub let count: u24;
+//!     let owners: StorageMap<Address, Bool>;
+//!
+//!     event Incremented { by: u24 }
+//!
+//!     fn constructor() {
+//!         count = 1;
+//!     }
+//!
+//!     pub fn increment(by: u24) -> u24 {
+//!         count = count + by;
+//!         emit Incremented(by);
+//!         return count;
+//!     }
+//! }
+//! ```
+//!
+//! Right after parsing, a contract becomes the definitions it stands for:
+//! its `let` fields and attributes a `storage` block, and its members
+//! top-level definitions. The function named `constructor` is exported as
+//! `deploy`, so it runs once when the contract is instantiated, with its
+//! parameters decoded from the instantiation input as message arguments are
+//! from call data, without a selector. The other `pub` functions are
+//! messages, marked `#[message]` and dispatched by selector; functions
+//! without `pub` are helpers only the contract calls.
+//!
+//! A `pub` field also gets a `#[view]` getter message of the same name,
+//! which returns its value. The getter of a `StorageMap` takes a key and
+//! returns the entry at it, with one parameter per element of a tuple key,
+//! and the getter of a `StorageVec` takes the index of an element:
+//!
+//! ```text
+//! pub let balances: StorageMap<Address, u128>;
+//! # stands for
+//! #[view]
+//! #[message]
+//! pub fn balances(key: Address) -> u128 {
+//!     return balances.get(key);
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use crate::compiler::parser::ast::*;
+use crate::compiler::parser::parser::ParseError;
+use crate::compiler::polkavm::blob::DEPLOY_EXPORT;
+
+/// Name of the function run when a contract is instantiated
+pub const CONSTRUCTOR: &str = "constructor";
+
+/// The definitions a contract declaration stands for; other definitions
+/// are returned as they are
+pub fn lower(definition: Definition) -> Result<Vec<Definition>, ParseError> {
+    let Definition::ContractDef {
+        name,
+        fields,
+        definitions,
+        attributes,
+        location,
+    } = definition
+    else {
+        return Ok(vec![definition]);
+    };
+
+    let public: Vec<&StorageField> = fields
+        .iter()
+        .filter(|field| field.visibility == Visibility::Public)
+        .collect();
+    for field in &public {
+        let clash = definitions.iter().find(|definition| {
+            matches!(definition, Definition::FunctionDef { name, .. } if *name == field.name)
+        });
+        if let Some(function) = clash {
+            let location = function.location();
+            return Err(ParseError::Generic(format!(
+                "Function {} of contract {} has the name of the getter of a `pub` field (line {}, column {})",
+                field.name, name, location.line, location.column
+            )));
+        }
+    }
+    let getters: Vec<Definition> = public.into_iter().map(getter).collect();
+
+    let mut lowered = Vec::with_capacity(definitions.len() + getters.len() + 1);
+    if !fields.is_empty() || !attributes.is_empty() {
+        lowered.push(Definition::StorageDef {
+            fields,
+            attributes,
+            location,
+        });
+    }
+    lowered.extend(getters);
+    for mut definition in definitions {
+        if let Definition::FunctionDef {
+            name: function,
+            attributes,
+            location,
+            visibility,
+            ..
+        } = &mut definition
+        {
+            let marked = |name: &str| attributes.iter().any(|attribute| attribute.name == name);
+            if function == CONSTRUCTOR && !marked("export") {
+                attributes.push(Attribute {
+                    name: "export".into(),
+                    args: vec![Expr::Literal {
+                        kind: LiteralKind::String(DEPLOY_EXPORT.to_string()),
+                        location: location.clone(),
+                    }],
+                    location: location.clone(),
+                });
+            } else if *visibility == Visibility::Public
+                && !["message", "test", "extern", "internal", "export"]
+                    .into_iter()
+                    .any(marked)
+            {
+                attributes.push(Attribute {
+                    name: "message".into(),
+                    args: Vec::new(),
+                    location: location.clone(),
+                });
+            }
+        }
+        lowered.push(definition);
+    }
+    Ok(lowered)
+}
+
+/// The public view function returning the value of a `pub` field, or an
+/// entry of it for a storage collection
+fn getter(field: &StorageField) -> Definition {
+    let location = &field.location;
+    let variable = |name: &str| Expr::Variable {
+        name: name.into(),
+        location: location.clone(),
+    };
+    let parameter = |name: String, ty: &Type| Parameter {
+        name: name.into(),
+        ty: ty.clone(),
+        location: location.clone(),
+    };
+    let get = |key: Expr| Expr::FunctionCall {
+        function: Box::new(Expr::FieldAccess {
+            object: Box::new(variable(&field.name)),
+            field: "get".to_string(),
+            location: location.clone(),
+        }),
+        args: vec![key],
+        named_args: HashMap::new(),
+        location: location.clone(),
+    };
+
+    let (params, value, return_type) = match &field.ty {
+        Type::Named { name, params, .. } if name == "StorageMap" && params.len() == 2 => {
+            let (keys, key) = match &params[0] {
+                Type::Tuple { elements, .. } => {
+                    let keys: Vec<Parameter> = elements
+                        .iter()
+                        .enumerate()
+                        .map(|(i, ty)| parameter(format!("key{}", i), ty))
+                        .collect();
+                    let key = Expr::Tuple {
+                        elements: keys.iter().map(|key| variable(&key.name)).collect(),
+                        location: location.clone(),
+                    };
+                    (keys, key)
+                }
+                ty => (vec![parameter("key".to_string(), ty)], variable("key")),
+            };
+            (keys, get(key), params[1].clone())
+        }
+        Type::Named { name, params, .. } if name == "StorageVec" && params.len() == 1 => {
+            let index = Type::U24 {
+                location: location.clone(),
+            };
+            (
+                vec![parameter("index".to_string(), &index)],
+                get(variable("index")),
+                params[0].clone(),
+            )
+        }
+        ty => (Vec::new(), variable(&field.name), ty.clone()),
+    };
+
+    Definition::FunctionDef {
+        name: field.name,
+        params,
+        return_type: Some(return_type),
+        body: Block {
+            statements: vec![Statement::Return {
+                value,
+                location: location.clone(),
+            }],
+            location: location.clone(),
+        },
+        checked: None,
+        attributes: ["view", "message"]
+            .into_iter()
+            .map(|name| Attribute {
+                name: name.into(),
+                args: Vec::new(),
+                location: location.clone(),
+            })
+            .collect(),
+        visibility: Visibility::Public,
+        location: location.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::codegen::metadata::selector_for;
+    use crate::compiler::codegen::risc_v::RiscVCodegen;
+    use crate::compiler::parser::parser::Parser;
+    use crate::compiler::pipeline::{CompilerPipeline, Source};
+    use crate::compiler::polkavm::abi::StateMutability;
+    use crate::runtime::env::{ExecutionContext, ExecutionResult};
+    use crate::runtime::interpreter::Interpreter;
+    use crate::CompilerOptions;
+
+    const TOKEN: &str = r#"
+contract Token {
+    pub let supply: u24;
+    pub let balances: StorageMap<u24, u128>;
+    pub let holders: StorageVec<u24>;
+    let owner: u24;
+
+    fn constructor() {
+        supply = 50;
+    }
+}
+"#;
+
+    #[test]
+    fn test_public_field_getters() {
+        let program = Parser::new(TOKEN).parse_program().unwrap();
+        let getters: Vec<(&str, Vec<&str>)> = program
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                Definition::FunctionDef {
+                    name,
+                    params,
+                    attributes,
+                    visibility: Visibility::Public,
+                    ..
+                } if attributes[0].name == "view" => Some((
+                    name.as_str(),
+                    params.iter().map(|param| param.name.as_str()).collect(),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            getters,
+            [
+                ("supply", vec![]),
+                ("balances", vec!["key"]),
+                ("holders", vec!["index"]),
+            ]
+        );
+
+        let options = CompilerOptions {
+            abi: true,
+            ..Default::default()
+        };
+        let result = CompilerPipeline::new(&options)
+            .with_dispatcher()
+            .run(&Source::new("token", TOKEN))
+            .unwrap();
+        let abi = result.abi.unwrap();
+        let balances = abi
+            .methods
+            .iter()
+            .find(|method| method.name == "balances")
+            .unwrap();
+        assert_eq!(balances.inputs[0].type_, "u24");
+        assert_eq!(balances.outputs[0].type_, "u128");
+        assert!(matches!(balances.state_mutability, StateMutability::View));
+        assert!(!abi.methods.iter().any(|method| method.name == "owner"));
+
+        // One parameter per element of a tuple key
+        let source = "contract Token {\n    pub let allowances: StorageMap<(u24, u24), u128>;\n}\n";
+        let program = Parser::new(source).parse_program().unwrap();
+        match &program.definitions[1] {
+            Definition::FunctionDef { params, .. } => {
+                let names: Vec<&str> = params.iter().map(|param| param.name.as_str()).collect();
+                assert_eq!(names, ["key0", "key1"]);
+            }
+            other => panic!("Expected a getter, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_constructor_parameters() {
+        let source = r#"
+contract Counter {
+    pub let count: u24;
+    pub let active: Bool;
+
+    fn constructor(start: u24, opened: Bool) {
+        count = start;
+        active = opened;
+    }
+}
+"#;
+        let program = Parser::new(source).parse_program().unwrap();
+        let code = RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap();
+
+        // The arguments are encoded as in call data, without the selector
+        let mut context = ExecutionContext::new_default();
+        context.input = [&5u32.to_le_bytes()[..], &[1]].concat();
+        let mut interpreter = Interpreter::new(context);
+        assert!(matches!(
+            interpreter.execute_export(&code, DEPLOY_EXPORT).unwrap(),
+            ExecutionResult::Success { .. }
+        ));
+        interpreter.environment_mut().context.input = selector_for("count()").to_vec();
+        match interpreter.execute(&code).unwrap() {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 5u32.to_le_bytes()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Malformed input reverts
+        interpreter.environment_mut().context.input = [&5u32.to_le_bytes()[..], &[2]].concat();
+        assert!(matches!(
+            interpreter.execute_export(&code, DEPLOY_EXPORT).unwrap(),
+            ExecutionResult::Revert { .. }
+        ));
+    }
+
+    #[test]
+    fn test_getter_name_clash() {
+        let source = "contract Token {\n    pub let supply: u24;\n    fn supply() -> u24 {\n        return 1;\n    }\n}\n";
+        assert!(Parser::new(source).parse_program().is_err());
+    }
+}
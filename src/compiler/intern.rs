@@ -0,0 +1,443 @@
+//! Interned identifiers
+//!
+//! A [`Symbol`] is a `u32` naming one string in an [`Interner`]. Identifier
+//! tokens, the names the syntax tree binds and refers to, and the names the
+//! symbol tables of the type checker, the module system and the code
+//! generator are keyed on are symbols, so every occurrence of such a name
+//! shares the one copy of its text, copying one copies four bytes, and
+//! comparing or hashing one costs the same whatever the length of the name.
+//!
+//! Names are interned into the interner of the current session. A session
+//! is entered with [`Interner::enter`], and a thread that never enters one
+//! interns into an interner of its own. The table numbering the names of
+//! an interner is freed with it, so a long-running process such as the
+//! language server bounds it by starting a new session. The text of a name
+//! is never freed: it is kept once per process, whatever the sessions and
+//! threads interning it, so what [`Symbol::as_str`] returns lives as long
+//! as the process. That memory grows with the distinct names the process
+//! ever sees, not with how often it sees them.
+//!
+//! A symbol only means something in the session it was interned in: its
+//! number is looked up in the current interner, so it must not be kept
+//! past the end of that session. Nor does a symbol mean anything on
+//! another thread, which interns into an interner of its own, so the
+//! parallel build and the formatter hand their threads paths rather than
+//! syntax trees.
+//!
+//! Symbols compare equal exactly when their text does. They are ordered by
+//! their text, not by when they were interned, so sorting them gives the
+//! same order on every build. Hashes depend on the interning order, which
+//! matters only to code that iterates a hash map without sorting, as
+//! iterating one keyed by strings never had a stable order either.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::{LazyLock, Mutex};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// An interned identifier
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// Hashes names the way rustc's FxHash does: a multiply per eight bytes,
+/// far cheaper than SipHash for short keys that no one picks to collide
+#[derive(Default)]
+struct NameHasher(u64);
+
+impl Hasher for NameHasher {
+    fn write(&mut self, mut bytes: &[u8]) {
+        while let Some((word, rest)) = bytes.split_first_chunk() {
+            self.add(u64::from_le_bytes(*word));
+            bytes = rest;
+        }
+        if let Some((word, rest)) = bytes.split_first_chunk() {
+            self.add(u32::from_le_bytes(*word).into());
+            bytes = rest;
+        }
+        for &byte in bytes {
+            self.add(byte.into());
+        }
+    }
+
+    fn write_u8(&mut self, byte: u8) {
+        self.add(byte.into());
+    }
+
+    fn finish(&self) -> u64 {
+        // The multiplies leave the best mixed bits at the top, and hash
+        // tables pick buckets with the bottom ones
+        self.0.rotate_left(26)
+    }
+}
+
+impl NameHasher {
+    fn add(&mut self, word: u64) {
+        self.0 = self
+            .0
+            .wrapping_add(word)
+            .wrapping_mul(0xf1_35_7a_ea_2e_62_a9_c5);
+    }
+}
+
+/// The text of every name interned by the process, each kept once and
+/// never freed
+static TEXTS: LazyLock<Mutex<HashSet<&'static str>>> = LazyLock::new(Mutex::default);
+
+/// The text of `string` kept for the rest of the process
+fn keep(string: &str) -> &'static str {
+    let mut texts = TEXTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    match texts.get(string) {
+        Some(text) => text,
+        None => {
+            let text: &'static str = Box::leak(string.into());
+            texts.insert(text);
+            text
+        }
+    }
+}
+
+/// The names of an interner and the text of each symbol, by number
+#[derive(Default)]
+struct Tables {
+    symbols: HashMap<&'static str, u32, BuildHasherDefault<NameHasher>>,
+    texts: Vec<&'static str>,
+}
+
+/// The numbers of the strings symbols name, for one session
+///
+/// Cloning an interner gives another handle to the same one; it is freed
+/// with its last handle, though not the texts it numbered.
+#[derive(Clone, Default)]
+pub struct Interner {
+    tables: Rc<RefCell<Tables>>,
+}
+
+impl fmt::Debug for Interner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interner")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+thread_local! {
+    /// The interner of the session entered on this thread, if any
+    static SESSION: RefCell<Option<Interner>> = const { RefCell::new(None) };
+
+    /// The interner of the thread outside of sessions
+    static DEFAULT: Interner = Interner::new();
+}
+
+/// Leaves a session, entering the one it was entered from, even when the
+/// session unwinds
+struct Leave(Option<Interner>);
+
+impl Drop for Leave {
+    fn drop(&mut self) {
+        SESSION.with(|session| *session.borrow_mut() = self.0.take());
+    }
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `f` in the session of this interner, interning names into it
+    pub fn enter<T>(&self, f: impl FnOnce() -> T) -> T {
+        let outer = SESSION.with(|session| session.replace(Some(self.clone())));
+        let _leave = Leave(outer);
+        f()
+    }
+
+    /// Number of distinct names interned
+    pub fn len(&self) -> usize {
+        self.tables.borrow().texts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The interner of the current session
+    pub fn current() -> Self {
+        with_current(Interner::clone)
+    }
+
+    /// The symbol for `string` in this interner, interning it on first use
+    pub fn intern(&self, string: &str) -> Symbol {
+        let mut tables = self.tables.borrow_mut();
+        let index = match tables.symbols.get(string) {
+            Some(&index) => index,
+            None => {
+                let index = u32::try_from(tables.texts.len()).expect("too many symbols");
+                let text = keep(string);
+                tables.texts.push(text);
+                tables.symbols.insert(text, index);
+                index
+            }
+        };
+        Symbol(index)
+    }
+
+    /// The text of the symbol numbered `index`
+    fn text(&self, index: u32) -> &'static str {
+        self.tables
+            .borrow()
+            .texts
+            .get(index as usize)
+            .expect("symbols are only made by interning in the current session")
+    }
+}
+
+/// Run `f` with the interner of the current session
+fn with_current<T>(f: impl FnOnce(&Interner) -> T) -> T {
+    SESSION.with(|session| match &*session.borrow() {
+        Some(interner) => f(interner),
+        None => DEFAULT.with(f),
+    })
+}
+
+impl Symbol {
+    /// The symbol for `string` in the current session, interning it on
+    /// first use
+    pub fn intern(string: &str) -> Self {
+        with_current(|interner| interner.intern(string))
+    }
+
+    /// The text of the symbol, which is never freed
+    pub fn as_str(&self) -> &'static str {
+        with_current(|interner| interner.text(self.0))
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Default for Symbol {
+    fn default() -> Self {
+        Symbol::intern("")
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self == other {
+            return Ordering::Equal;
+        }
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(string: &str) -> Self {
+        Symbol::intern(string)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(string: &String) -> Self {
+        Symbol::intern(string)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(string: String) -> Self {
+        Symbol::intern(&string)
+    }
+}
+
+impl From<&Symbol> for Symbol {
+    fn from(symbol: &Symbol) -> Self {
+        *symbol
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> Self {
+        symbol.as_str().to_string()
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<Symbol> for str {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Symbol> for &str {
+    fn eq(&self, other: &Symbol) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl PartialEq<Symbol> for String {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = Cow::<'de, str>::deserialize(deserializer)?;
+        Ok(Symbol::intern(&string))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbols_share_their_text() {
+        let a = Symbol::intern("balance");
+        let b = Symbol::from(String::from("balance"));
+        assert_eq!(a, b);
+        assert!(std::ptr::eq(a.as_str(), b.as_str()));
+        assert_ne!(a, Symbol::intern("balances"));
+
+        assert_eq!(a, "balance");
+        assert_eq!(a.len(), 7);
+        assert_eq!(format!("{} {:?}", a, a), "balance \"balance\"");
+    }
+
+    #[test]
+    fn test_symbols_sort_by_text() {
+        let mut symbols: Vec<Symbol> = ["zeta", "alpha", "mid"].map(Symbol::intern).to_vec();
+        symbols.sort();
+        assert_eq!(symbols, ["alpha", "mid", "zeta"].map(Symbol::intern));
+    }
+
+    #[test]
+    fn test_symbols_serialize_as_strings() {
+        let json = serde_json::to_string(&Symbol::intern("owner")).unwrap();
+        assert_eq!(json, "\"owner\"");
+        let symbol: Symbol = serde_json::from_str(&json).unwrap();
+        assert_eq!(symbol, "owner");
+    }
+
+    #[test]
+    fn test_sessions_intern_into_their_own_interner() {
+        let outside = Symbol::intern("outside");
+        let interner = Interner::new();
+        let (inside, nested) = interner.enter(|| {
+            let inside = Symbol::intern("inside");
+            assert_eq!(Symbol::intern("inside"), inside);
+            let nested = Interner::new().enter(|| Symbol::intern("nested").to_string());
+            (inside.to_string(), nested)
+        });
+        assert_eq!((inside.as_str(), nested.as_str()), ("inside", "nested"));
+        assert_eq!(interner.len(), 1);
+        assert_eq!(outside, "outside");
+
+        // Entering the session again finds what it interned
+        interner.enter(|| {
+            assert_eq!(Symbol::intern("other"), "other");
+            assert_eq!(Symbol::intern("inside"), "inside");
+        });
+        assert_eq!(interner.len(), 2);
+
+        // A session that unwinds is left
+        let unwound = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            interner.enter(|| panic!("in session"))
+        }));
+        assert!(unwound.is_err());
+        assert_eq!(Symbol::intern("outside"), outside);
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn test_texts_outlive_their_session() {
+        let text = Interner::new().enter(|| Symbol::intern("session_local").as_str());
+        assert_eq!(text, "session_local");
+        // Every session and thread shares the one copy of a name
+        let again = Interner::new().enter(|| Symbol::intern("session_local").as_str());
+        assert!(std::ptr::eq(text, again));
+        let elsewhere = std::thread::spawn(|| Symbol::intern("session_local").as_str())
+            .join()
+            .unwrap();
+        assert!(std::ptr::eq(text, elsewhere));
+    }
+
+    #[test]
+    fn test_threads_intern_into_their_own_interner() {
+        let names: Vec<String> = (0..64).map(|i| format!("thread_{}", i)).collect();
+        let texts: Vec<String> = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    names
+                        .iter()
+                        .map(|name| Symbol::intern(name).to_string())
+                        .collect()
+                })
+                .join()
+                .unwrap()
+        });
+        assert_eq!(texts, names);
+        for name in &names {
+            assert_eq!(Symbol::intern(name), name.as_str());
+        }
+    }
+}
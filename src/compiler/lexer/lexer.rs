@@ -1,385 +1,1070 @@
 use super::token::Token;
-use logos::{Lexer, Logos};
-use std::collections::HashMap;
-
-/// Define tokens using the Logos derive macro for efficient lexing
-#[derive(Logos, Debug, PartialEq, Clone)]
-#[logos(skip r"[ \t\n\f]+")]
-enum LogosToken {
-    #[regex("[A-Za-z][A-Za-z0-9_./]*")]
+use crate::compiler::address::{Address, Hash};
+use crate::compiler::intern::Interner;
+use crate::compiler::wide::{wide_type_bits, WideUint};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+/// What the bytes of a token spell, before its text is read into a
+/// [`Token`]. Every token starts with an ASCII character, so every token
+/// starts and ends on a character boundary and can slice the `str` the
+/// bytes came from. Lexemes hold no data, so that each token is built
+/// once, where it is returned.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Lexeme {
+    // A leading underscore marks a name as intentionally unused; `_` on
+    // its own is the wildcard. Keywords are told apart from names later.
     Identifier,
 
-    // Keywords are handled in the callback for Identifier
-    #[regex("0|[1-9][0-9]*")]
+    // Digits of any integer or float may be separated by underscores, as in
+    // `1_000_000`. A base 2, 8 or 16 integer starts with `0b`, `0o` or `0x`.
     UintLiteral,
 
-    #[regex("[+-][0-9]+")]
+    SuffixedUintLiteral,
+
+    AddressLiteral,
+
+    // `0x` and 64 hex digits, without underscores, is a Hash
+    HashLiteral,
+
+    // `hex"..."`, with an even number of hex digits
+    BytesLiteral,
+
     IntLiteral,
 
-    #[regex("[+-]?[0-9]+\\.[0-9]+")]
     FloatLiteral,
 
-    #[regex("\"[^\"]*\"")]
     StringLiteral,
 
-    #[regex("'[^']'")]
+    // `r"..."`, or `r#"..."#` with as many `#` as it takes to hold quotes
+    RawStringLiteral,
+
+    TripleStringLiteral,
+
     CharLiteral,
 
-    #[regex("`[^`]*`")]
     SymbolLiteral,
 
-    #[token("(")]
-    LParen,
+    /// Punctuation and operators, spelled by their first byte or two
+    Punctuation,
 
-    #[token(")")]
-    RParen,
+    /// The end of the source
+    End,
 
-    #[token("{")]
-    LBrace,
+    /// Characters that start no token, or a string missing its closing
+    /// quote
+    Error,
+}
 
-    #[token("}")]
-    RBrace,
+impl Lexeme {
+    /// Whether the text of the lexeme is always ASCII on one line
+    fn is_ascii(&self) -> bool {
+        !matches!(
+            self,
+            Lexeme::StringLiteral
+                | Lexeme::RawStringLiteral
+                | Lexeme::TripleStringLiteral
+                | Lexeme::CharLiteral
+                | Lexeme::SymbolLiteral
+                | Lexeme::Error
+        )
+    }
+}
 
-    #[token("[")]
-    LBracket,
+/// Represents a token with its position in the source code
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TokenWithPosition {
+    pub token: Token,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    /// Comments between the previous token's line and this token, kept by
+    /// `BendLexer::with_comments`
+    pub leading_comments: Vec<Comment>,
+    /// Comments after this token on the line it ends on, kept by
+    /// `BendLexer::with_comments`
+    pub trailing_comments: Vec<Comment>,
+}
 
-    #[token("]")]
-    RBracket,
+/// A comment the lexer skipped, kept so that tools like the formatter can
+/// write it back
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Comment {
+    pub start: usize,
+    pub end: usize,
+    /// The comment with its `#` or `#{ }#`, without trailing whitespace
+    pub text: String,
+}
 
-    #[token(":")]
-    Colon,
+/// The main lexer for the Bend-PVM language. It scans the bytes of the
+/// source once and allocates nothing for names: keywords are matched on
+/// their bytes and identifiers are interned. Comments are only copied out
+/// for the lexers that keep them.
+///
+/// Lines are counted as line breaks are passed, and columns are worked out
+/// from where the line starts, so names, numbers and punctuation, which
+/// hold no line break or non-ASCII character, are passed without looking
+/// at their bytes again.
+pub struct BendLexer<'a> {
+    /// Current line number (1-based)
+    line: usize,
+    /// Where the current line starts
+    line_start: usize,
+    /// Bytes continuing a UTF-8 sequence on the current line so far, which
+    /// columns do not count
+    continuations: usize,
+    /// Source code for error reporting
+    source: &'a str,
+    /// Where the next token is looked for, past the trailing comments of
+    /// the last one
+    offset: usize,
+    /// The end of the last token, with the line and column there
+    last_end: (usize, usize, usize),
+    /// Whether whitespace and comments are produced as tokens
+    trivia: bool,
+    /// Whether comments are kept with the tokens around them
+    comments: bool,
+    /// Tokens lexed but not returned yet
+    pending: VecDeque<TokenWithPosition>,
+    /// The interner of the session, looked up once rather than per name
+    interner: Interner,
+}
 
-    #[token("::")]
-    DoubleColon,
+impl<'a> BendLexer<'a> {
+    /// Create a new lexer for the given source code
+    pub fn new(source: &'a str) -> Self {
+        BendLexer {
+            line: 1,
+            line_start: 0,
+            continuations: 0,
+            source,
+            offset: 0,
+            last_end: (0, 1, 1),
+            trivia: false,
+            comments: false,
+            pending: VecDeque::new(),
+            interner: Interner::current(),
+        }
+    }
 
-    #[token(";")]
-    Semicolon,
+    /// Create a lexer that keeps the comments before each token and after
+    /// it on its line, for tools that write them back
+    pub fn with_comments(source: &'a str) -> Self {
+        BendLexer {
+            comments: true,
+            ..BendLexer::new(source)
+        }
+    }
 
-    #[token(",")]
-    Comma,
+    /// Create a lexer that also produces the whitespace and comments
+    /// between tokens, as `Whitespace` and `Comment` tokens, so that the
+    /// tokens cover the whole source
+    pub fn with_trivia(source: &'a str) -> Self {
+        BendLexer {
+            trivia: true,
+            ..BendLexer::new(source)
+        }
+    }
 
-    #[token(".")]
-    Dot,
+    /// Get the next token from the source
+    pub fn next_token(&mut self) -> TokenWithPosition {
+        if !self.trivia {
+            return self.lex();
+        }
+        if let Some(token) = self.pending.pop_front() {
+            return token;
+        }
 
-    #[token("->")]
-    Arrow,
+        let (from, line, column) = self.last_end;
+        let token = self.lex();
+        self.pending = trivia_tokens(self.source, from..token.start, line, column);
+        self.pending.push_back(token);
+        self.pending.pop_front().unwrap()
+    }
 
-    #[token("=>")]
-    FatArrow,
+    #[inline]
+    fn lex(&mut self) -> TokenWithPosition {
+        let (start, leading_comments) = self.skip_trivia(self.offset, false);
+        let (start_line, start_column) = (self.line, self.column(start));
+        let (lexeme, end) = self.lexeme_at(start);
+        self.last_end = (end, self.line, self.column(end));
+
+        let trailing_comments;
+        (self.offset, trailing_comments) = if lexeme == Lexeme::End {
+            (end, Vec::new())
+        } else {
+            self.skip_trivia(end, true)
+        };
 
-    #[token("<-")]
-    LeftArrow,
+        TokenWithPosition {
+            token: self.read(lexeme, start, end),
+            start,
+            end,
+            line: start_line,
+            column: start_column,
+            leading_comments,
+            trailing_comments,
+        }
+    }
 
-    #[token("=")]
-    Equal,
+    /// The lexeme at `start`, where no whitespace or comment starts, and
+    /// where it ends, passing the lines it spans
+    #[inline(always)]
+    fn lexeme_at(&mut self, start: usize) -> (Lexeme, usize) {
+        let bytes = self.source.as_bytes();
+        let (lexeme, end) = match scan(bytes, start) {
+            Some(found) => found,
+            None if start == bytes.len() => (Lexeme::End, start),
+            None => (Lexeme::Error, self.error_end(start)),
+        };
+        // Names, numbers and punctuation hold no line break or non-ASCII
+        // character, so the line and its continuations stay as they are
+        if !lexeme.is_ascii() {
+            self.pass(start, end);
+        }
+        (lexeme, end)
+    }
 
-    #[token("~")]
-    Tilde,
+    /// The token from `start` to `end`, which `scan` found to be a `lexeme`
+    #[inline(always)]
+    fn read(&self, lexeme: Lexeme, start: usize, end: usize) -> Token {
+        read(&self.interner, self.source, lexeme, start, end)
+    }
 
-    #[token("+")]
-    Plus,
+    /// Where the error at `start`, where no token starts, ends
+    #[cold]
+    fn error_end(&self, start: usize) -> usize {
+        let bytes = self.source.as_bytes();
+        if raw_opening(&bytes[start..]).is_some() || bytes[start] == b'"' {
+            // A string missing its closing quote runs to the end of its
+            // line, and lexing goes on from the next one
+            return memchr(b'\n', &bytes[start..]).map_or(bytes.len(), |n| start + n);
+        }
+        // A run of characters that start no token is one error, and lexing
+        // goes on after it
+        let mut end = start;
+        loop {
+            end += self.source[end..].chars().next().map_or(1, char::len_utf8);
+            match bytes.get(end) {
+                None | Some(b' ' | b'\t' | b'\n' | b'\x0c') => break,
+                Some(b'#') if bytes.get(end + 1) != Some(&b'[') => break,
+                _ if scan(bytes, end).is_some() => break,
+                _ => {}
+            }
+        }
+        end
+    }
 
-    #[token("-")]
-    Minus,
+    /// Move past the whitespace and comments from `offset`, up to the next
+    /// token or, for the trailing comments of a token, up to the end of its
+    /// line. Returns where that is along with the comments passed.
+    #[inline(always)]
+    fn skip_trivia(&mut self, mut offset: usize, trailing: bool) -> (usize, Vec<Comment>) {
+        let bytes = self.source.as_bytes();
+        let mut comments = Vec::new();
+        while let Some(&byte) = bytes.get(offset) {
+            match byte {
+                b' ' | b'\t' | b'\x0c' => {}
+                b'\n' if !trailing => {
+                    self.line += 1;
+                    self.line_start = offset + 1;
+                    self.continuations = 0;
+                }
+                // `#[` opens an attribute rather than a comment
+                b'#' if bytes.get(offset + 1) != Some(&b'[') => {
+                    let end = comment_end(self.source, offset);
+                    self.pass(offset, end);
+                    if self.comments {
+                        comments.push(Comment {
+                            start: offset,
+                            end,
+                            text: self.source[offset..end].trim_end().to_string(),
+                        });
+                    }
+                    offset = end;
+                    continue;
+                }
+                _ => break,
+            }
+            offset += 1;
+        }
+        (offset, comments)
+    }
 
-    #[token("*")]
-    Star,
+    /// Count the line breaks and continuation bytes from `start` to `end`
+    fn pass(&mut self, start: usize, end: usize) {
+        let bytes = &self.source.as_bytes()[start..end];
+        for (i, &byte) in bytes.iter().enumerate() {
+            if byte == b'\n' {
+                self.line += 1;
+                self.line_start = start + i + 1;
+                self.continuations = 0;
+            } else if (byte as i8) < -0x40 {
+                self.continuations += 1;
+            }
+        }
+    }
 
-    #[token("/")]
-    Slash,
+    /// The column of `offset` on the current line, counting characters
+    fn column(&self, offset: usize) -> usize {
+        offset - self.line_start - self.continuations + 1
+    }
 
-    #[token("%")]
-    Percent,
+    /// Helper method to collect all tokens from source
+    #[cfg(test)]
+    pub fn collect_all_tokens(&mut self) -> Vec<TokenWithPosition> {
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next_token();
+            tokens.push(token.clone());
+            if let Token::EOF = token.token {
+                break;
+            }
+        }
+        tokens
+    }
+}
 
-    #[token("^")]
-    Caret,
+/// Where a token of a [`TokenBuffer`] is, and what kind of lexeme its
+/// bytes spell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenSpan {
+    pub start: u32,
+    pub end: u32,
+    pub line: u32,
+    pub column: u32,
+    lexeme: Lexeme,
+}
 
-    #[token("&")]
-    Ampersand,
+/// The tokens of a whole source, as spans of it, ending with `EOF`
+///
+/// Lexing into the buffer only finds where each token is and which lexeme
+/// it is, so it copies nothing out of the source. A token is read from its
+/// text when it is asked for: names are interned and literals parsed only
+/// for the tokens a caller reads. Comments are skipped.
+pub struct TokenBuffer<'a> {
+    source: &'a str,
+    spans: Vec<TokenSpan>,
+    interner: Interner,
+}
 
-    #[token("|")]
-    Pipe,
+impl<'a> TokenBuffer<'a> {
+    /// Lex all of `source`, which must be under 4 GiB
+    pub fn new(source: &'a str) -> Self {
+        assert!(u32::try_from(source.len()).is_ok(), "sources are under 4 GiB");
+        let mut lexer = BendLexer::new(source);
+        // Tokens take a few bytes of source each
+        let mut spans = Vec::with_capacity(source.len() / 4 + 1);
+        loop {
+            let (start, _) = lexer.skip_trivia(lexer.offset, false);
+            let (line, column) = (lexer.line, lexer.column(start));
+            let (lexeme, end) = lexer.lexeme_at(start);
+            lexer.offset = end;
+            spans.push(TokenSpan {
+                start: start as u32,
+                end: end as u32,
+                line: line as u32,
+                column: column as u32,
+                lexeme,
+            });
+            if lexeme == Lexeme::End {
+                break;
+            }
+        }
+        TokenBuffer {
+            source,
+            spans,
+            interner: lexer.interner,
+        }
+    }
 
-    #[token(">")]
-    GreaterThan,
+    /// The number of tokens, `EOF` included
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
 
-    #[token("<")]
-    LessThan,
+    /// Whether there are no tokens, which never holds, as there is `EOF`
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
 
-    #[token(">=")]
-    GreaterEqual,
+    /// Where each token is, in source order
+    pub fn spans(&self) -> &[TokenSpan] {
+        &self.spans
+    }
 
-    #[token("<=")]
-    LessEqual,
+    /// The source text of the token at `index`
+    pub fn text(&self, index: usize) -> &'a str {
+        let span = &self.spans[index];
+        &self.source[span.start as usize..span.end as usize]
+    }
 
-    #[token("==")]
-    EqualEqual,
+    /// The token at `index`, read from its text
+    pub fn token(&self, index: usize) -> Token {
+        let span = &self.spans[index];
+        read(
+            &self.interner,
+            self.source,
+            span.lexeme,
+            span.start as usize,
+            span.end as usize,
+        )
+    }
 
-    #[token("!=")]
-    NotEqual,
+    /// The token at `index` with its position, without comments
+    pub fn token_with_position(&self, index: usize) -> TokenWithPosition {
+        let span = &self.spans[index];
+        TokenWithPosition {
+            token: self.token(index),
+            start: span.start as usize,
+            end: span.end as usize,
+            line: span.line as usize,
+            column: span.column as usize,
+            leading_comments: Vec::new(),
+            trailing_comments: Vec::new(),
+        }
+    }
+}
 
-    #[token("+=")]
-    PlusEqual,
+/// The token of `source` from `start` to `end`, which `scan` found to be a
+/// `lexeme`, with names interned in `interner`
+#[inline(always)]
+fn read(interner: &Interner, source: &str, lexeme: Lexeme, start: usize, end: usize) -> Token {
+    let text = &source[start..end];
+    match lexeme {
+        Lexeme::Identifier => {
+            keyword(text).unwrap_or_else(|| Token::Identifier(interner.intern(text)))
+        }
+        Lexeme::Punctuation => {
+            let bytes = text.as_bytes();
+            let second = bytes.get(1).copied().unwrap_or(0);
+            match punctuation(bytes[0], second) {
+                Some((token, _)) => token,
+                None => unreachable!("scanned as punctuation"),
+            }
+        }
+        Lexeme::End => Token::EOF,
+        Lexeme::Error => error(text, start),
+        lexeme => literal(lexeme, text),
+    }
+}
 
-    #[token("-=")]
-    MinusEqual,
+/// The error token for `text`, which starts at `start` and starts no token
+#[cold]
+fn error(text: &str, start: usize) -> Token {
+    if raw_opening(text.as_bytes()).is_some() || text.starts_with('"') {
+        Token::Error(format!("Unterminated string literal: {}", text))
+    } else {
+        Token::Error(format!("Lexer error at position {}: {}", start, text))
+    }
+}
 
-    #[token("*=")]
-    StarEqual,
+/// The literal `text` spells, which `scan` found to be a `lexeme`
+#[inline(never)]
+fn literal(lexeme: Lexeme, text: &str) -> Token {
+    match lexeme {
+        // Without a suffix, the literal is a u24 if it fits, else the
+        // narrowest wide integer holding it; whether that fits where it
+        // goes is up to the type checker
+        Lexeme::UintLiteral => {
+            let (digits, radix) = integer_digits(text);
+            match WideUint::from_str_radix(&digits, radix, 256) {
+                Ok(value) => match value.limbs() {
+                    [word, rest @ ..] if *word <= 0xFF_FFFF && rest.iter().all(|&l| l == 0) => {
+                        Token::UintLiteral(*word)
+                    }
+                    _ => Token::WideUintLiteral(Box::new(narrowest(&value))),
+                },
+                Err(_) => Token::Error(format!(
+                    "Integer literal is too large: {} (more than 256 bits)",
+                    text
+                )),
+            }
+        }
+        Lexeme::SuffixedUintLiteral => {
+            let suffix = text.rfind('u').unwrap();
+            let (digits, radix) = integer_digits(&text[..suffix]);
+            match wide_type_bits(&text[suffix..]) {
+                Some(bits) => match WideUint::from_str_radix(&digits, radix, bits) {
+                    Ok(value) => Token::WideUintLiteral(Box::new(value)),
+                    Err(error) => Token::Error(error.to_string()),
+                },
+                // Whether a u24 fits is up to the type checker
+                None => match u32::from_str_radix(&digits, radix) {
+                    Ok(value) => Token::UintLiteral(value),
+                    Err(_) => Token::Error(format!(
+                        "Integer literal exceeds u24 maximum value: {}",
+                        text
+                    )),
+                },
+            }
+        }
+        Lexeme::AddressLiteral => match text[1..].parse::<Address>() {
+            Ok(address) => Token::AddressLiteral(Box::new(address)),
+            Err(error) => Token::Error(format!("Invalid address literal: {}", error)),
+        },
+        Lexeme::HashLiteral => match Hash::from_hex(text) {
+            Ok(hash) => Token::HashLiteral(Box::new(hash)),
+            Err(error) => Token::Error(format!("Invalid hash literal: {}", error)),
+        },
+        Lexeme::BytesLiteral => match hex::decode(&text[4..text.len() - 1]) {
+            Ok(bytes) => Token::BytesLiteral(bytes),
+            Err(error) => Token::Error(format!("Invalid bytes literal: {}", error)),
+        },
+        Lexeme::IntLiteral => match without_separators(text).parse::<i32>() {
+            Ok(value) => Token::IntLiteral(value),
+            Err(_) => Token::Error(format!("Signed integer literal is too large: {}", text)),
+        },
+        Lexeme::FloatLiteral => match without_separators(text).parse::<f32>() {
+            Ok(value) if value.is_finite() => Token::FloatLiteral(value.to_bits()),
+            _ => Token::Error(format!("Invalid float literal: {}", text)),
+        },
+        Lexeme::StringLiteral => match unescape(&text[1..text.len() - 1]) {
+            Ok(value) => Token::StringLiteral(value),
+            Err(error) => Token::Error(error),
+        },
+        Lexeme::RawStringLiteral => {
+            // Remove the `r`, the quotes and the `#` around them
+            let hashes = text[1..].find('"').unwrap();
+            let value = &text[2 + hashes..text.len() - 1 - hashes];
+            Token::StringLiteral(value.to_string())
+        }
+        Lexeme::TripleStringLiteral => match unescape(&dedent(&text[3..text.len() - 3])) {
+            Ok(value) => Token::StringLiteral(value),
+            Err(error) => Token::Error(error),
+        },
+        Lexeme::CharLiteral => {
+            // Remove the quotes and parse as char
+            let char_str = &text[1..text.len() - 1];
+            let c = char_str.chars().next().unwrap_or('\0');
+            Token::CharLiteral(c)
+        }
+        Lexeme::SymbolLiteral => {
+            // Remove backticks
+            let value = &text[1..text.len() - 1];
+            Token::SymbolLiteral(value.to_string())
+        }
+        Lexeme::Identifier | Lexeme::Punctuation | Lexeme::End | Lexeme::Error => {
+            unreachable!("not a literal")
+        }
+    }
+}
 
-    #[token("/=")]
-    SlashEqual,
+/// The lexeme at `start`, where no whitespace or comment starts, and where
+/// it ends. Of the lexemes starting there, the longest is taken. `None`
+/// when none does, or at the end of the source.
+#[inline(always)]
+fn scan(bytes: &[u8], start: usize) -> Option<(Lexeme, usize)> {
+    let rest = &bytes[start..];
+    let at = |n: usize| rest.get(n).copied().unwrap_or(0);
+    let (lexeme, length) = match *rest.first()? {
+        b'a'..=b'z' | b'A'..=b'Z' => match raw_opening(rest) {
+            Some(open) => (Lexeme::RawStringLiteral, raw_string(rest, open)?),
+            None if rest.starts_with(b"hex\"") => {
+                let digits = count(&rest[4..], |b| b.is_ascii_hexdigit());
+                if at(4 + digits) != b'"' {
+                    return None;
+                }
+                (Lexeme::BytesLiteral, 5 + digits)
+            }
+            None => (Lexeme::Identifier, 1 + name_length(&rest[1..])),
+        },
+        b'_' if at(1).is_ascii_alphanumeric() || at(1) == b'_' => {
+            (Lexeme::Identifier, 2 + name_length(&rest[2..]))
+        }
+        b'0'..=b'9' => number(rest),
+        b'+' | b'-' if at(1).is_ascii_digit() => {
+            let int = 2 + count(&rest[2..], |b| b.is_ascii_digit() || b == b'_');
+            match float_length(&rest[1..]) {
+                Some(float) => (Lexeme::FloatLiteral, 1 + float),
+                None => (Lexeme::IntLiteral, int),
+            }
+        }
+        b'@' => (Lexeme::AddressLiteral, 1 + address_length(&rest[1..])?),
+        b'"' if rest.starts_with(b"\"\"\"") => {
+            (Lexeme::TripleStringLiteral, 3 + triple_string(&rest[3..])?)
+        }
+        b'"' => (Lexeme::StringLiteral, string_length(rest)?),
+        b'\'' => {
+            let first = *rest.get(1)?;
+            let close = 1 + utf8_length(first);
+            if first == b'\'' || at(close) != b'\'' {
+                return None;
+            }
+            (Lexeme::CharLiteral, close + 1)
+        }
+        b'`' => (Lexeme::SymbolLiteral, 2 + memchr(b'`', &rest[1..])?),
+        _ => {
+            let (_, length) = punctuation(rest[0], at(1))?;
+            (Lexeme::Punctuation, length)
+        }
+    };
+    Some((lexeme, start + length))
+}
 
-    #[token("%=")]
-    PercentEqual,
+/// The punctuation or operator spelled by `first`, or by `first` and
+/// `second`, and how many of the two it takes
+#[inline(always)]
+fn punctuation(first: u8, second: u8) -> Option<(Token, usize)> {
+    let pair = match (first, second) {
+        (b'#', b'[') => Some(Token::HashBracket),
+        (b':', b':') => Some(Token::DoubleColon),
+        (b'-', b'>') => Some(Token::Arrow),
+        (b'=', b'>') => Some(Token::FatArrow),
+        (b'<', b'-') => Some(Token::LeftArrow),
+        (b'>', b'=') => Some(Token::GreaterEqual),
+        (b'<', b'=') => Some(Token::LessEqual),
+        (b'=', b'=') => Some(Token::EqualEqual),
+        (b'!', b'=') => Some(Token::NotEqual),
+        (b'&', b'&') => Some(Token::AndAnd),
+        (b'|', b'|') => Some(Token::OrOr),
+        (b'+', b'=') => Some(Token::PlusEqual),
+        (b'-', b'=') => Some(Token::MinusEqual),
+        (b'*', b'=') => Some(Token::StarEqual),
+        (b'/', b'=') => Some(Token::SlashEqual),
+        (b'%', b'=') => Some(Token::PercentEqual),
+        (b'^', b'=') => Some(Token::CaretEqual),
+        (b'&', b'=') => Some(Token::AmpersandEqual),
+        (b'|', b'=') => Some(Token::PipeEqual),
+        _ => None,
+    };
+    if let Some(token) = pair {
+        return Some((token, 2));
+    }
+    let token = match first {
+        b'(' => Token::LParen,
+        b')' => Token::RParen,
+        b'{' => Token::LBrace,
+        b'}' => Token::RBrace,
+        b'[' => Token::LBracket,
+        b']' => Token::RBracket,
+        b':' => Token::Colon,
+        b';' => Token::Semicolon,
+        b'?' => Token::Question,
+        b'_' => Token::Underscore,
+        b',' => Token::Comma,
+        b'.' => Token::Dot,
+        b'=' => Token::Equal,
+        b'~' => Token::Tilde,
+        b'+' => Token::Plus,
+        b'-' => Token::Minus,
+        b'*' => Token::Star,
+        b'/' => Token::Slash,
+        b'%' => Token::Percent,
+        b'^' => Token::Caret,
+        b'&' => Token::Ampersand,
+        b'|' => Token::Pipe,
+        b'>' => Token::GreaterThan,
+        b'<' => Token::LessThan,
+        b'!' => Token::Bang,
+        _ => return None,
+    };
+    Some((token, 1))
+}
 
-    #[token("^=")]
-    CaretEqual,
+/// Whether each byte can follow the first character of a name
+const NAME_BYTES: [bool; 256] = {
+    let mut table = [false; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let b = byte as u8;
+        table[byte] = b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.' | b'/');
+        byte += 1;
+    }
+    table
+};
 
-    #[token("&=")]
-    AmpersandEqual,
+/// How many of the bytes at the start of `bytes` continue a name
+fn name_length(bytes: &[u8]) -> usize {
+    count(bytes, |b| NAME_BYTES[b as usize])
+}
 
-    #[token("|=")]
-    PipeEqual,
+/// How many of the bytes at the start of `bytes` are `wanted`
+fn count(bytes: &[u8], wanted: impl Fn(u8) -> bool) -> usize {
+    bytes
+        .iter()
+        .position(|&b| !wanted(b))
+        .unwrap_or(bytes.len())
+}
 
-    // Comments
-    #[regex("#\\{[^}]*\\}#", logos::skip)]
-    MultiLineComment,
+/// Where `byte` first is in `bytes`
+fn memchr(byte: u8, bytes: &[u8]) -> Option<usize> {
+    bytes.iter().position(|&b| b == byte)
+}
 
-    #[regex("#[^\\n]*", logos::skip)]
-    SingleLineComment,
+/// The integer or float starting with the digit `bytes` starts with
+fn number(bytes: &[u8]) -> (Lexeme, usize) {
+    let at = |n: usize| bytes.get(n).copied().unwrap_or(0);
+    let decimal = |b: u8| b.is_ascii_digit() || b == b'_';
+    // `0`, or digits not starting with it
+    let plain = if bytes[0] == b'0' {
+        1
+    } else {
+        1 + count(&bytes[1..], decimal)
+    };
+    let radix = |digit: fn(u8) -> bool| {
+        let underscores = count(&bytes[2..], |b| b == b'_');
+        let first = 2 + underscores;
+        digit(at(first)).then(|| first + 1 + count(&bytes[first + 1..], |b| digit(b) || b == b'_'))
+    };
+    let based = match (bytes[0], at(1)) {
+        (b'0', b'b') => radix(|b| matches!(b, b'0' | b'1')),
+        (b'0', b'o') => radix(|b| matches!(b, b'0'..=b'7')),
+        (b'0', b'x') => radix(|b| b.is_ascii_hexdigit()),
+        _ => None,
+    };
+
+    // A suffix follows the longest digits it can
+    let digits = based.unwrap_or(plain);
+    if let Some(suffix) = [&b"u128"[..], b"u256", b"u24", b"u64"]
+        .into_iter()
+        .find(|suffix| bytes[digits..].starts_with(suffix))
+    {
+        return (Lexeme::SuffixedUintLiteral, digits + suffix.len());
+    }
+    if at(1) == b'x' {
+        match based {
+            Some(66) if count(&bytes[2..], |b| b.is_ascii_hexdigit()) == 64 => {
+                return (Lexeme::HashLiteral, 66)
+            }
+            None => return (Lexeme::Error, 2),
+            _ => {}
+        }
+    }
+    match (based, float_length(bytes)) {
+        (Some(based), _) => (Lexeme::UintLiteral, based),
+        (None, Some(float)) => (Lexeme::FloatLiteral, float),
+        (None, None) => (Lexeme::UintLiteral, plain),
+    }
 }
 
-/// Represents a token with its position in the source code
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct TokenWithPosition {
-    pub token: Token,
-    pub start: usize,
-    pub end: usize,
-    pub line: usize,
-    pub column: usize,
+/// The length of the float at the start of `bytes`, which starts with a
+/// digit: digits then a fraction, an exponent or both
+fn float_length(bytes: &[u8]) -> Option<usize> {
+    let at = |n: usize| bytes.get(n).copied().unwrap_or(0);
+    let decimal = |b: u8| b.is_ascii_digit() || b == b'_';
+    let mut length = 1 + count(&bytes[1..], decimal);
+    let fraction = at(length) == b'.' && at(length + 1).is_ascii_digit();
+    if fraction {
+        length += 2 + count(&bytes[length + 2..], decimal);
+    }
+    if matches!(at(length), b'e' | b'E') {
+        let sign = matches!(at(length + 1), b'+' | b'-') as usize;
+        let digits = count(&bytes[length + 1 + sign..], |b| b.is_ascii_digit());
+        if digits > 0 {
+            return Some(length + 1 + sign + digits);
+        }
+    }
+    fraction.then_some(length)
 }
 
-/// The main lexer for the Bend-PVM language
-pub struct BendLexer<'a> {
-    logos_lexer: Lexer<'a, LogosToken>,
-    /// Mapping from identifier strings to keyword tokens
-    keywords: HashMap<&'static str, Token>,
-    /// Current line number (1-based)
-    line: usize,
-    /// Current column number (1-based)
-    column: usize,
-    /// Source code for error reporting
-    source: &'a str,
+/// The length of an address after its `@`: `0x` and 64 hex digits, or
+/// base 58 digits
+fn address_length(bytes: &[u8]) -> Option<usize> {
+    if bytes.starts_with(b"0x") && count(&bytes[2..], |b| b.is_ascii_hexdigit()) >= 64 {
+        return Some(66);
+    }
+    let base58 = |b: u8| b.is_ascii_alphanumeric() && !matches!(b, b'0' | b'O' | b'I' | b'l');
+    Some(count(bytes, base58)).filter(|&length| length > 0)
 }
 
-impl<'a> BendLexer<'a> {
-    /// Create a new lexer for the given source code
-    pub fn new(source: &'a str) -> Self {
-        let mut keywords = HashMap::new();
-        keywords.insert("fn", Token::Fn);
-        keywords.insert("def", Token::Def);
-        keywords.insert("type", Token::Type);
-        keywords.insert("object", Token::Object);
-        keywords.insert("return", Token::Return);
-        keywords.insert("if", Token::If);
-        keywords.insert("else", Token::Else);
-        keywords.insert("match", Token::Match);
-        keywords.insert("case", Token::Case);
-        keywords.insert("fold", Token::Fold);
-        keywords.insert("bend", Token::Bend);
-        keywords.insert("when", Token::When);
-        keywords.insert("fork", Token::Fork);
-        keywords.insert("open", Token::Open);
-        keywords.insert("with", Token::With);
-        keywords.insert("use", Token::Use);
-        keywords.insert("lambda", Token::Lambda);
-        keywords.insert("in", Token::In);
-        keywords.insert("let", Token::Let);
-        keywords.insert("switch", Token::Switch);
-        keywords.insert("import", Token::Import);
-        keywords.insert("from", Token::From);
-        keywords.insert("as", Token::As);
-        keywords.insert("true", Token::True);
-        keywords.insert("false", Token::False);
+/// The length of the `"` string `bytes` starts with, escapes included
+fn string_length(bytes: &[u8]) -> Option<usize> {
+    let mut i = 1;
+    loop {
+        match *bytes.get(i)? {
+            b'"' => return Some(i + 1),
+            // An escape takes the whole character after the backslash, but
+            // no line break
+            b'\\' => match *bytes.get(i + 1)? {
+                b'\n' => return None,
+                byte => i += 1 + utf8_length(byte),
+            },
+            _ => i += 1,
+        }
+    }
+}
 
-        BendLexer {
-            logos_lexer: LogosToken::lexer(source),
-            keywords,
-            line: 1,
-            column: 1,
-            source,
+/// The length of the UTF-8 encoding of the character starting with `byte`
+fn utf8_length(byte: u8) -> usize {
+    match byte {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    }
+}
+
+/// Whether `text` is a keyword, which can't name anything
+pub fn is_keyword(text: &str) -> bool {
+    keyword(text).is_some()
+}
+
+/// The keyword token spelled `text`, if it is one
+#[inline(always)]
+fn keyword(text: &str) -> Option<Token> {
+    Some(match text.as_bytes() {
+        b"fn" => Token::Fn,
+        b"def" => Token::Def,
+        b"type" => Token::Type,
+        b"object" => Token::Object,
+        b"return" => Token::Return,
+        b"if" => Token::If,
+        b"else" => Token::Else,
+        b"while" => Token::While,
+        b"for" => Token::For,
+        b"match" => Token::Match,
+        b"case" => Token::Case,
+        b"fold" => Token::Fold,
+        b"bend" => Token::Bend,
+        b"when" => Token::When,
+        b"fork" => Token::Fork,
+        b"open" => Token::Open,
+        b"with" => Token::With,
+        b"use" => Token::Use,
+        b"lambda" => Token::Lambda,
+        b"in" => Token::In,
+        b"let" => Token::Let,
+        b"switch" => Token::Switch,
+        b"import" => Token::Import,
+        b"from" => Token::From,
+        b"as" => Token::As,
+        b"event" => Token::Event,
+        b"emit" => Token::Emit,
+        b"error" => Token::ErrorType,
+        b"storage" => Token::Storage,
+        b"checked" => Token::Checked,
+        b"unchecked" => Token::Unchecked,
+        b"assert" => Token::Assert,
+        b"require" => Token::Require,
+        b"revert" => Token::Revert,
+        b"guard" => Token::Guard,
+        b"asm" => Token::Asm,
+        b"contract" => Token::Contract,
+        b"interface" => Token::Interface,
+        b"pub" => Token::Pub,
+        b"true" => Token::True,
+        b"false" => Token::False,
+        b"and" => Token::AndAnd,
+        b"or" => Token::OrOr,
+        b"not" => Token::Bang,
+        _ => return None,
+    })
+}
+
+/// The line and column after `bytes`, from `line` and `column` before
+/// them. Columns count characters, so the bytes that continue a UTF-8
+/// sequence are skipped.
+fn position_after(bytes: &[u8], mut line: usize, mut column: usize) -> (usize, usize) {
+    for &byte in bytes {
+        if byte == b'\n' {
+            line += 1;
+            column = 1;
+        } else if (byte as i8) >= -0x40 {
+            column += 1;
         }
     }
+    (line, column)
+}
 
-    /// Get the next token from the source
-    pub fn next_token(&mut self) -> TokenWithPosition {
-        let start_pos = self.logos_lexer.span().end;
+/// The length of the opening of a raw string, `r` then any `#` and a
+/// quote, if `bytes` starts with one
+fn raw_opening(bytes: &[u8]) -> Option<usize> {
+    let rest = bytes.strip_prefix(b"r")?;
+    let hashes = count(rest, |b| b == b'#');
+    (rest.get(hashes) == Some(&b'"')).then_some(hashes + 2)
+}
 
-        let token_result = self.logos_lexer.next();
+/// The length of the raw string `bytes` starts with, whose opening is
+/// `open` long, up to a quote followed by as many `#` as it opened with
+fn raw_string(bytes: &[u8], open: usize) -> Option<usize> {
+    let hashes = &bytes[1..open - 1];
+    let mut from = open;
+    loop {
+        let quote = from + memchr(b'"', &bytes[from..])?;
+        if bytes[quote + 1..].starts_with(hashes) {
+            return Some(quote + 1 + hashes.len());
+        }
+        from = quote + 1;
+    }
+}
 
-        // The logos lexer skip patterns don't update our line/column count.
-        // We need to check the text between the end of the last token and the start of this one.
-        let skipped_text = &self.source[start_pos..self.logos_lexer.span().start];
-        for c in skipped_text.chars() {
-            if c == '\n' {
-                self.line += 1;
-                self.column = 1;
-            } else {
-                self.column += 1;
-            }
+/// The length of the rest of a `"""` string, up to and with the first
+/// unescaped `"""`
+fn triple_string(rest: &[u8]) -> Option<usize> {
+    let mut i = 0;
+    while i < rest.len() {
+        match rest[i] {
+            b'\\' => i += 1,
+            b'"' if rest[i..].starts_with(b"\"\"\"") => return Some(i + 3),
+            _ => {}
         }
+        i += 1;
+    }
+    None
+}
+
+/// `value` as the narrowest of u64, u128 and u256 that holds it
+fn narrowest(value: &WideUint) -> WideUint {
+    let words = value
+        .limbs()
+        .iter()
+        .rposition(|&limb| limb != 0)
+        .map_or(0, |top| top + 1);
+    let bits = [64, 128, 256]
+        .into_iter()
+        .find(|bits| words <= *bits as usize / 32)
+        .unwrap_or(256);
+    WideUint::from_le_bytes(&value.to_le_bytes()[..bits as usize / 8]).expect("wide integer bits")
+}
 
-        let actual_start_pos = self.logos_lexer.span().start;
-        let start_column = self.column;
-        let start_line = self.line;
+/// The digits of an integer literal, without its base prefix and the
+/// underscores between them, and its base
+fn integer_digits(text: &str) -> (Cow<'_, str>, u32) {
+    let (digits, radix) = match text.get(..2) {
+        Some("0b") => (&text[2..], 2),
+        Some("0o") => (&text[2..], 8),
+        Some("0x") => (&text[2..], 16),
+        _ => (text, 10),
+    };
+    (without_separators(digits), radix)
+}
 
-        let token = match token_result {
-            Some(Ok(logos_token)) => {
-                let span = self.logos_lexer.span();
-                let text = &self.source[span.clone()];
+/// `text` without the underscores separating its digits
+fn without_separators(text: &str) -> Cow<'_, str> {
+    if text.contains('_') {
+        Cow::Owned(text.replace('_', ""))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
 
-                match logos_token {
-                    LogosToken::Identifier => {
-                        // Check if it's a keyword
-                        if let Some(keyword) = self.keywords.get(text) {
-                            keyword.clone()
-                        } else {
-                            Token::Identifier(text.to_string())
-                        }
-                    }
-                    LogosToken::UintLiteral => {
-                        if let Ok(value) = text.parse::<u32>() {
-                            if value > 0xFFFFFF {
-                                Token::Error(format!(
-                                    "Unsigned integer literal exceeds u24 maximum value: {}",
-                                    value
-                                ))
-                            } else {
-                                Token::UintLiteral(value)
-                            }
-                        } else {
-                            Token::Error(format!("Invalid unsigned integer literal: {}", text))
-                        }
-                    }
-                    LogosToken::IntLiteral => {
-                        if let Ok(value) = text.parse::<i32>() {
-                            if !(-0x800000..=0x7FFFFF).contains(&value) {
-                                Token::Error(format!(
-                                    "Signed integer literal exceeds i24 range: {}",
-                                    value
-                                ))
-                            } else {
-                                Token::IntLiteral(value)
-                            }
-                        } else {
-                            Token::Error(format!("Invalid signed integer literal: {}", text))
-                        }
-                    }
-                    LogosToken::FloatLiteral => {
-                        if let Ok(value) = text.parse::<f32>() {
-                            Token::FloatLiteral(value.to_bits())
-                        } else {
-                            Token::Error(format!("Invalid float literal: {}", text))
-                        }
-                    }
-                    LogosToken::StringLiteral => {
-                        // Remove the quotes
-                        let value = &text[1..text.len() - 1];
-                        Token::StringLiteral(value.to_string())
-                    }
-                    LogosToken::CharLiteral => {
-                        // Remove the quotes and parse as char
-                        let char_str = &text[1..text.len() - 1];
-                        let c = char_str.chars().next().unwrap_or('\0');
-                        Token::CharLiteral(c)
-                    }
-                    LogosToken::SymbolLiteral => {
-                        // Remove backticks
-                        let value = &text[1..text.len() - 1];
-                        Token::SymbolLiteral(value.to_string())
+/// The value of a string with its escapes replaced: `\n`, `\t`, `\r`,
+/// `\0`, `\\`, `\"`, `\'` and `\u{...}` with up to six hex digits
+fn unescape(text: &str) -> Result<String, String> {
+    let mut value = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('"') => '"',
+            Some('\'') => '\'',
+            Some('u') => {
+                let rest = chars.as_str();
+                let code = rest
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.find('}').map(|end| &rest[..end]))
+                    .filter(|code| (1..=6).contains(&code.len()));
+                match code
+                    .and_then(|code| u32::from_str_radix(code, 16).ok())
+                    .and_then(char::from_u32)
+                {
+                    Some(c) => {
+                        chars = rest[code.unwrap().len() + 2..].chars();
+                        c
                     }
-                    LogosToken::LParen => Token::LParen,
-                    LogosToken::RParen => Token::RParen,
-                    LogosToken::LBrace => Token::LBrace,
-                    LogosToken::RBrace => Token::RBrace,
-                    LogosToken::LBracket => Token::LBracket,
-                    LogosToken::RBracket => Token::RBracket,
-                    LogosToken::Colon => Token::Colon,
-                    LogosToken::DoubleColon => Token::DoubleColon,
-                    LogosToken::Semicolon => Token::Semicolon,
-                    LogosToken::Comma => Token::Comma,
-                    LogosToken::Dot => Token::Dot,
-                    LogosToken::Arrow => Token::Arrow,
-                    LogosToken::FatArrow => Token::FatArrow,
-                    LogosToken::LeftArrow => Token::LeftArrow,
-                    LogosToken::Equal => Token::Equal,
-                    LogosToken::Tilde => Token::Tilde,
-                    LogosToken::Plus => Token::Plus,
-                    LogosToken::Minus => Token::Minus,
-                    LogosToken::Star => Token::Star,
-                    LogosToken::Slash => Token::Slash,
-                    LogosToken::Percent => Token::Percent,
-                    LogosToken::Caret => Token::Caret,
-                    LogosToken::Ampersand => Token::Ampersand,
-                    LogosToken::Pipe => Token::Pipe,
-                    LogosToken::GreaterThan => Token::GreaterThan,
-                    LogosToken::LessThan => Token::LessThan,
-                    LogosToken::GreaterEqual => Token::GreaterEqual,
-                    LogosToken::LessEqual => Token::LessEqual,
-                    LogosToken::EqualEqual => Token::EqualEqual,
-                    LogosToken::NotEqual => Token::NotEqual,
-                    LogosToken::PlusEqual => Token::PlusEqual,
-                    LogosToken::MinusEqual => Token::MinusEqual,
-                    LogosToken::StarEqual => Token::StarEqual,
-                    LogosToken::SlashEqual => Token::SlashEqual,
-                    LogosToken::PercentEqual => Token::PercentEqual,
-                    LogosToken::CaretEqual => Token::CaretEqual,
-                    LogosToken::AmpersandEqual => Token::AmpersandEqual,
-                    LogosToken::PipeEqual => Token::PipeEqual,
-                    _ => Token::Error(format!("Unexpected token: {}", text)),
+                    None => return Err("Invalid \\u{...} escape in string".to_string()),
                 }
             }
-            Some(Err(_)) => {
-                let span = self.logos_lexer.span();
-                let text = &self.source[span.clone()];
-                Token::Error(format!("Lexer error at position {}: {}", span.start, text))
-            }
-            None => Token::EOF,
+            Some(other) => return Err(format!("Unknown escape sequence in string: \\{}", other)),
+            None => return Err("String ends with a lone backslash".to_string()),
         };
+        value.push(escaped);
+    }
+    Ok(value)
+}
 
-        // Update line and column count for next token (the actual token text)
-        for c in self.source[self.logos_lexer.span().clone()].chars() {
-            if c == '\n' {
-                self.line += 1;
-                self.column = 1;
+/// The body of a `"""` string without the line break after the opening
+/// quotes, the indentation of the closing ones, and the indentation its
+/// lines share
+fn dedent(body: &str) -> String {
+    let body = body
+        .strip_prefix("\r\n")
+        .or_else(|| body.strip_prefix('\n'))
+        .unwrap_or(body);
+    let body = match body.rfind('\n') {
+        Some(n) if body[n + 1..].trim().is_empty() => &body[..n + 1],
+        _ => body,
+    };
+    let indent = body
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    body.split_inclusive('\n')
+        .map(|line| {
+            if line.trim().is_empty() {
+                line.trim_start_matches([' ', '\t'])
             } else {
-                self.column += 1;
+                &line[indent..]
             }
-        }
+        })
+        .collect()
+}
 
-        TokenWithPosition {
+/// The whitespace and comments in a `range` of skipped text as tokens,
+/// starting at `line` and `column`
+fn trivia_tokens(
+    source: &str,
+    range: std::ops::Range<usize>,
+    mut line: usize,
+    mut column: usize,
+) -> VecDeque<TokenWithPosition> {
+    let mut tokens = VecDeque::new();
+    let mut offset = range.start;
+    while offset < range.end {
+        let end = if source[offset..].starts_with('#') {
+            comment_end(source, offset)
+        } else {
+            source[offset..range.end]
+                .find('#')
+                .map_or(range.end, |i| offset + i)
+        };
+        let text = &source[offset..end];
+        let token = if text.starts_with('#') {
+            Token::Comment(text.to_string())
+        } else {
+            Token::Whitespace(text.to_string())
+        };
+        tokens.push_back(TokenWithPosition {
             token,
-            start: actual_start_pos,
-            end: self.logos_lexer.span().end,
-            line: start_line,
-            column: start_column,
-        }
+            start: offset,
+            end,
+            line,
+            column,
+            leading_comments: Vec::new(),
+            trailing_comments: Vec::new(),
+        });
+        (line, column) = position_after(text.as_bytes(), line, column);
+        offset = end;
     }
+    tokens
+}
 
-    /// Helper method to collect all tokens from source
-    #[cfg(test)]
-    pub fn collect_all_tokens(&mut self) -> Vec<TokenWithPosition> {
-        let mut tokens = Vec::new();
-        loop {
-            let token = self.next_token();
-            tokens.push(token.clone());
-            if let Token::EOF = token.token {
-                break;
-            }
-        }
-        tokens
-    }
+/// The end of the comment starting with the `#` at `start`. Like the lexer,
+/// this takes the longer of a line comment and a `#{ ... }#` block, which
+/// may span lines.
+fn comment_end(source: &str, start: usize) -> usize {
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |n| start + n);
+    let block_end = source[start..]
+        .strip_prefix("#{")
+        .and_then(|body| body.find('}'))
+        .map(|close| start + 2 + close)
+        .filter(|&close| source[close..].starts_with("}#"))
+        .map_or(0, |close| close + 2);
+    line_end.max(block_end)
 }
 
 #[cfg(test)]
@@ -395,10 +1080,24 @@ mod tests {
             ("return", Token::Return),
             ("if", Token::If),
             ("else", Token::Else),
+            ("while", Token::While),
+            ("for", Token::For),
             ("match", Token::Match),
             ("case", Token::Case),
             ("with", Token::With),
             ("use", Token::Use),
+            ("event", Token::Event),
+            ("emit", Token::Emit),
+            ("error", Token::ErrorType),
+            ("storage", Token::Storage),
+            ("assert", Token::Assert),
+            ("require", Token::Require),
+            ("revert", Token::Revert),
+            ("guard", Token::Guard),
+            ("asm", Token::Asm),
+            ("contract", Token::Contract),
+            ("interface", Token::Interface),
+            ("pub", Token::Pub),
         ];
 
         for (text, expected) in keywords {
@@ -418,12 +1117,13 @@ mod tests {
             "x",
             "a123",
             "test_123",
+            "_unused",
         ];
 
         for ident in identifiers {
             let mut lexer = BendLexer::new(ident);
             let token = lexer.next_token();
-            assert_eq!(token.token, Token::Identifier(ident.to_string()));
+            assert_eq!(token.token, Token::Identifier(ident.into()));
         }
     }
 
@@ -443,16 +1143,144 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_wide_unsigned_integers() {
+        let mut lexer = BendLexer::new("18446744073709551615u64 5u256");
+        match lexer.next_token().token {
+            Token::WideUintLiteral(value) => {
+                assert_eq!(value.bits(), 64);
+                assert_eq!(value.to_string(), "18446744073709551615");
+            }
+            other => panic!("Expected a wide literal, got {:?}", other),
+        }
+        assert_eq!(
+            lexer.next_token().token,
+            Token::WideUintLiteral(Box::new(WideUint::from_u128(5, 256).unwrap()))
+        );
+
+        let mut lexer = BendLexer::new("18446744073709551616u64");
+        match lexer.next_token().token {
+            Token::Error(msg) => assert!(msg.contains("exceeds u64 maximum")),
+            other => panic!("Expected error for overflow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_address_and_hash_literals() {
+        let alice_hex = "0xd43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
+        let alice = Address::from_hex(alice_hex).unwrap();
+        let source = format!(
+            "@5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY @{} {}",
+            alice_hex, alice_hex
+        );
+        let mut lexer = BendLexer::new(&source);
+        assert_eq!(
+            lexer.next_token().token,
+            Token::AddressLiteral(Box::new(alice))
+        );
+        assert_eq!(
+            lexer.next_token().token,
+            Token::AddressLiteral(Box::new(alice))
+        );
+        assert_eq!(
+            lexer.next_token().token,
+            Token::HashLiteral(Box::new(alice.to_hash()))
+        );
+
+        let mut lexer = BendLexer::new("@5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQZ");
+        match lexer.next_token().token {
+            Token::Error(msg) => assert!(msg.contains("checksum")),
+            other => panic!("Expected error for bad checksum, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bytes_literals() {
+        let mut lexer = BendLexer::new(r#"hex"deadBEEF" hex"" hexadecimal"#);
+        assert_eq!(
+            lexer.next_token().token,
+            Token::BytesLiteral(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(lexer.next_token().token, Token::BytesLiteral(vec![]));
+        assert_eq!(
+            lexer.next_token().token,
+            Token::Identifier("hexadecimal".into())
+        );
+
+        let mut lexer = BendLexer::new(r#"hex"abc""#);
+        match lexer.next_token().token {
+            Token::Error(msg) => assert!(msg.contains("Invalid bytes literal")),
+            other => panic!("Expected error for odd length, got {:?}", other),
+        }
+
+        // `0x` without digits is neither bytes nor an integer
+        let mut lexer = BendLexer::new("0x");
+        assert!(matches!(lexer.next_token().token, Token::Error(_)));
+    }
+
     #[test]
     fn test_unsigned_integer_overflow() {
-        let mut lexer = BendLexer::new("16777216"); // 2^24 (too big for u24)
-        let token = lexer.next_token();
-        match token.token {
-            Token::Error(msg) => assert!(msg.contains("exceeds u24 maximum")),
-            _ => panic!("Expected error for overflow"),
+        // Past u24, a literal takes the narrowest wide type holding it, and
+        // whether that fits its target is checked by the type checker
+        let wide = |value: u128, bits| {
+            Token::WideUintLiteral(Box::new(WideUint::from_u128(value, bits).unwrap()))
+        };
+        for (text, expected) in [
+            ("16777215", Token::UintLiteral(0xFF_FFFF)),
+            ("16777216", wide(16_777_216, 64)),
+            ("5000000000", wide(5_000_000_000, 64)),
+            ("0x1_0000_0000_0000_0000", wide(1 << 64, 128)),
+        ] {
+            let mut lexer = BendLexer::new(text);
+            assert_eq!(lexer.next_token().token, expected, "Failed for: {}", text);
+        }
+
+        for text in [
+            format!("0x1{}", "0".repeat(64)),
+            format!("1{}", "0".repeat(80)),
+        ] {
+            let mut lexer = BendLexer::new(&text);
+            match lexer.next_token().token {
+                Token::Error(msg) => assert!(msg.contains("too large")),
+                other => panic!("Expected error for overflow, got {:?}", other),
+            }
         }
     }
 
+    #[test]
+    fn test_integer_bases_and_separators() {
+        let test_cases = vec![
+            ("1_000_000", Token::UintLiteral(1_000_000)),
+            ("0b1010", Token::UintLiteral(10)),
+            ("0b_1111_0000", Token::UintLiteral(0xF0)),
+            ("0o755", Token::UintLiteral(0o755)),
+            ("0xffu24", Token::UintLiteral(255)),
+            ("0b1u24", Token::UintLiteral(1)),
+            ("-1_000", Token::IntLiteral(-1000)),
+            (
+                "0xdead_beefu64",
+                Token::WideUintLiteral(Box::new(WideUint::from_u128(0xdead_beef, 64).unwrap())),
+            ),
+            (
+                "1_000u128",
+                Token::WideUintLiteral(Box::new(WideUint::from_u128(1000, 128).unwrap())),
+            ),
+        ];
+
+        for (text, expected) in test_cases {
+            let mut lexer = BendLexer::new(text);
+            assert_eq!(lexer.next_token().token, expected, "Failed for: {}", text);
+        }
+
+        // Bare hex is an integer, like any other base
+        let mut lexer = BendLexer::new("0xff 0xdeadbeef");
+        assert_eq!(lexer.next_token().token, Token::UintLiteral(0xff));
+        assert_eq!(
+            lexer.next_token().token,
+            Token::WideUintLiteral(Box::new(WideUint::from_u128(0xdead_beef, 64).unwrap()))
+        );
+    }
+
     #[test]
     fn test_signed_integers() {
         let test_cases = vec![
@@ -472,25 +1300,33 @@ mod tests {
 
     #[test]
     fn test_signed_integer_overflow() {
-        let test_cases = vec!["-8388609", "+8388608"]; // Outside i24 range
+        // Beyond i24 is the type checker's to report
+        let mut lexer = BendLexer::new("+8388608");
+        assert_eq!(lexer.next_token().token, Token::IntLiteral(8388608));
+
+        let test_cases = vec!["-2147483649", "+2147483648"];
 
         for text in test_cases {
             let mut lexer = BendLexer::new(text);
             let token = lexer.next_token();
             match token.token {
-                Token::Error(msg) => assert!(msg.contains("exceeds i24 range")),
+                Token::Error(msg) => assert!(msg.contains("too large")),
                 _ => panic!("Expected error for overflow: {}", text),
             }
         }
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_float_literals() {
         let test_cases = vec![
             ("3.14", Token::FloatLiteral(3.14_f32.to_bits())),
             ("-2.5", Token::FloatLiteral((-2.5_f32).to_bits())),
             ("+0.0", Token::FloatLiteral(0.0_f32.to_bits())),
             ("123.456", Token::FloatLiteral(123.456_f32.to_bits())),
+            ("1e3", Token::FloatLiteral(1000.0_f32.to_bits())),
+            ("2.5E-2", Token::FloatLiteral(0.025_f32.to_bits())),
+            ("-1_000.5e+1", Token::FloatLiteral((-10005.0_f32).to_bits())),
         ];
 
         for (text, expected) in test_cases {
@@ -530,6 +1366,68 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_string_escapes() {
+        let mut lexer = BendLexer::new(r#""a\tb\n\"c\" \\ \u{1F600}""#);
+        assert_eq!(
+            lexer.next_token().token,
+            Token::StringLiteral("a\tb\n\"c\" \\ \u{1F600}".to_string())
+        );
+
+        for text in [r#""\q""#, r#""\u{110000}""#, r#""\u{}""#] {
+            let mut lexer = BendLexer::new(text);
+            match lexer.next_token().token {
+                Token::Error(msg) => assert!(msg.contains("escape"), "{}", msg),
+                other => panic!("Expected an escape error for {}, got {:?}", text, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_raw_and_multi_line_strings() {
+        let source = r####"r"C:\path" r#"say "hi""# r##"a "# b"## r #x"####;
+        let kinds: Vec<Token> = BendLexer::new(source)
+            .collect_all_tokens()
+            .into_iter()
+            .map(|token| token.token)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                Token::StringLiteral("C:\\path".to_string()),
+                Token::StringLiteral("say \"hi\"".to_string()),
+                Token::StringLiteral("a \"# b".to_string()),
+                Token::Identifier("r".into()),
+                Token::EOF,
+            ]
+        );
+
+        let source = "x = \"\"\"\n    Dear {name},\n\n      Thanks!\\t\"\"\n    \"\"\";";
+        let mut lexer = BendLexer::new(source);
+        lexer.next_token();
+        lexer.next_token();
+        let string = lexer.next_token();
+        assert_eq!(
+            string.token,
+            Token::StringLiteral("Dear {name},\n\n  Thanks!\t\"\"\n".to_string())
+        );
+        let semicolon = lexer.next_token();
+        assert_eq!(semicolon.token, Token::Semicolon);
+        assert_eq!((semicolon.line, semicolon.column), (5, 8));
+
+        // A comment right after a name called `r` is still a comment
+        let mut lexer = BendLexer::new("r#note\n1");
+        assert_eq!(lexer.next_token().token, Token::Identifier("r".into()));
+        assert_eq!(lexer.next_token().token, Token::UintLiteral(1));
+
+        let mut lexer = BendLexer::new("r#\"open\n1");
+        match lexer.next_token().token {
+            Token::Error(msg) => assert!(msg.contains("Unterminated string")),
+            other => panic!("Expected an unterminated string, got {:?}", other),
+        }
+        assert_eq!(lexer.next_token().token, Token::UintLiteral(1));
+    }
+
     #[test]
     fn test_operators_and_symbols() {
         let test_cases = vec![
@@ -554,6 +1452,7 @@ mod tests {
             ("]", Token::RBracket),
             (":", Token::Colon),
             (";", Token::Semicolon),
+            ("?", Token::Question),
             (",", Token::Comma),
             (".", Token::Dot),
         ];
@@ -572,7 +1471,44 @@ mod tests {
 
         // Should skip comment and return def token
         assert_eq!(tokens[0].token, Token::Def);
-        assert_eq!(tokens[1].token, Token::Identifier("test".to_string()));
+        assert_eq!(tokens[1].token, Token::Identifier("test".into()));
+    }
+
+    #[test]
+    fn test_comments_are_attached_to_tokens() {
+        let source = "# Leading\n#{ block }#\nfn main() { # after the brace\n    return 1; #{ a }# # b\n}\n# End\n";
+        assert!(BendLexer::new(source)
+            .collect_all_tokens()
+            .iter()
+            .all(|token| token.leading_comments.is_empty() && token.trailing_comments.is_empty()));
+
+        let tokens = BendLexer::with_comments(source).collect_all_tokens();
+        let texts = |comments: &[Comment]| -> Vec<String> {
+            comments
+                .iter()
+                .map(|comment| comment.text.clone())
+                .collect()
+        };
+
+        assert_eq!(tokens[0].token, Token::Fn);
+        assert_eq!(
+            texts(&tokens[0].leading_comments),
+            vec!["# Leading", "#{ block }#"]
+        );
+        assert_eq!(tokens[4].token, Token::LBrace);
+        assert_eq!(
+            texts(&tokens[4].trailing_comments),
+            vec!["# after the brace"]
+        );
+        assert!(tokens[5].leading_comments.is_empty());
+        assert_eq!(tokens[7].token, Token::Semicolon);
+        assert_eq!(texts(&tokens[7].trailing_comments), vec!["#{ a }# # b"]);
+
+        let eof = tokens.last().unwrap();
+        assert_eq!(eof.token, Token::EOF);
+        assert_eq!(texts(&eof.leading_comments), vec!["# End"]);
+        let comment = &eof.leading_comments[0];
+        assert_eq!(&source[comment.start..comment.end], "# End");
     }
 
     #[test]
@@ -582,7 +1518,35 @@ mod tests {
 
         // Should skip multiline comment and return def token
         assert_eq!(tokens[0].token, Token::Def);
-        assert_eq!(tokens[1].token, Token::Identifier("test".to_string()));
+        assert_eq!(tokens[1].token, Token::Identifier("test".into()));
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut lexer = BendLexer::new("#\n#[view]\ndef test");
+        let tokens = lexer.collect_all_tokens();
+
+        // A bare `#` is still a comment, `#[` opens an attribute
+        assert_eq!(tokens[0].token, Token::HashBracket);
+        assert_eq!(tokens[1].token, Token::Identifier("view".into()));
+        assert_eq!(tokens[2].token, Token::RBracket);
+        assert_eq!(tokens[3].token, Token::Def);
+    }
+
+    #[test]
+    fn test_guard_placeholder() {
+        let mut lexer = BendLexer::new("guard only_owner():\n    _;");
+        let tokens = lexer.collect_all_tokens();
+
+        assert_eq!(tokens[0].token, Token::Guard);
+        assert_eq!(tokens[1].token, Token::Identifier("only_owner".into()));
+        assert_eq!(tokens[5].token, Token::Underscore);
+        assert_eq!(tokens[6].token, Token::Semicolon);
+
+        let mut lexer = BendLexer::new("_ _x");
+        let tokens = lexer.collect_all_tokens();
+        assert_eq!(tokens[0].token, Token::Underscore);
+        assert_eq!(tokens[1].token, Token::Identifier("_x".into()));
     }
 
     #[test]
@@ -605,23 +1569,23 @@ mod tests {
 
         let expected_tokens = vec![
             Token::Def,
-            Token::Identifier("add".to_string()),
+            Token::Identifier("add".into()),
             Token::LParen,
-            Token::Identifier("a".to_string()),
+            Token::Identifier("a".into()),
             Token::Colon,
-            Token::Identifier("u24".to_string()),
+            Token::Identifier("u24".into()),
             Token::Comma,
-            Token::Identifier("b".to_string()),
+            Token::Identifier("b".into()),
             Token::Colon,
-            Token::Identifier("u24".to_string()),
+            Token::Identifier("u24".into()),
             Token::RParen,
             Token::Arrow,
-            Token::Identifier("u24".to_string()),
+            Token::Identifier("u24".into()),
             Token::Colon,
             Token::Return,
-            Token::Identifier("a".to_string()),
+            Token::Identifier("a".into()),
             Token::Plus,
-            Token::Identifier("b".to_string()),
+            Token::Identifier("b".into()),
             Token::EOF,
         ];
 
@@ -640,4 +1604,124 @@ mod tests {
             _ => panic!("Expected error token for invalid input"),
         }
     }
+
+    #[test]
+    fn test_lexing_recovers_from_invalid_characters() {
+        let tokens = BendLexer::new("x = $$$ 1;\ny = \"open\nz").collect_all_tokens();
+        let kinds: Vec<Token> = tokens.iter().map(|token| token.token.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                Token::Identifier("x".into()),
+                Token::Equal,
+                Token::Error("Lexer error at position 4: $$$".to_string()),
+                Token::UintLiteral(1),
+                Token::Semicolon,
+                Token::Identifier("y".into()),
+                Token::Equal,
+                Token::Error("Unterminated string literal: \"open".to_string()),
+                Token::Identifier("z".into()),
+                Token::EOF,
+            ]
+        );
+        assert_eq!((tokens[2].start, tokens[2].end), (4, 7));
+        assert_eq!((tokens[8].line, tokens[8].column), (3, 1));
+    }
+
+    #[test]
+    fn test_non_ascii_text() {
+        let source = "s = \"héllo → ✓\" # café\nλµ x\n\"\"\"\n  naïve\n\"\"\" y";
+        let tokens = BendLexer::with_comments(source).collect_all_tokens();
+        let kinds: Vec<Token> = tokens.iter().map(|token| token.token.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                Token::Identifier("s".into()),
+                Token::Equal,
+                Token::StringLiteral("héllo → ✓".to_string()),
+                Token::Error("Lexer error at position 29: λµ".to_string()),
+                Token::Identifier("x".into()),
+                Token::StringLiteral("naïve\n".to_string()),
+                Token::Identifier("y".into()),
+                Token::EOF,
+            ]
+        );
+        assert_eq!(tokens[2].trailing_comments[0].text, "# café");
+        // Columns count characters rather than bytes
+        assert_eq!((tokens[4].line, tokens[4].column), (2, 4));
+        assert_eq!((tokens[6].line, tokens[6].column), (5, 5));
+    }
+
+    #[test]
+    fn test_tokens_back_off_to_the_longest_match() {
+        let kinds = |source: &str| -> Vec<Token> {
+            BendLexer::new(source)
+                .collect_all_tokens()
+                .into_iter()
+                .map(|token| token.token)
+                .collect()
+        };
+
+        assert_eq!(
+            kinds("0x_f 0x_fu24 1.e3"),
+            vec![
+                Token::UintLiteral(15),
+                Token::UintLiteral(15),
+                Token::UintLiteral(1),
+                Token::Dot,
+                Token::Identifier("e3".into()),
+                Token::EOF,
+            ]
+        );
+        assert_eq!(
+            kinds("'λ' `a b`"),
+            vec![
+                Token::CharLiteral('λ'),
+                Token::SymbolLiteral("a b".to_string()),
+                Token::EOF,
+            ]
+        );
+
+        // A `#{` never closed by `}#` is a line comment
+        let tokens = BendLexer::with_comments(
+            "#{ open
+}
+x",
+        )
+        .collect_all_tokens();
+        assert_eq!(tokens[0].token, Token::RBrace);
+        assert_eq!(tokens[0].leading_comments[0].text, "#{ open");
+        assert_eq!(tokens[1].token, Token::Identifier("x".into()));
+    }
+
+    #[test]
+    fn test_trivia_tokens_cover_the_source() {
+        let source = "# Note\nfn main() { #{ a\nb }#\n  return 1; # done\n}\n";
+        let tokens = BendLexer::with_trivia(source).collect_all_tokens();
+
+        let mut offset = 0;
+        for token in &tokens {
+            assert_eq!(token.start, offset, "{:?}", token);
+            offset = token.end;
+        }
+        assert_eq!(offset, source.len());
+
+        let comments: Vec<(String, usize, usize)> = tokens
+            .iter()
+            .filter_map(|token| match &token.token {
+                Token::Comment(text) => Some((text.clone(), token.line, token.column)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            comments,
+            vec![
+                ("# Note".to_string(), 1, 1),
+                ("#{ a\nb }#".to_string(), 2, 13),
+                ("# done".to_string(), 4, 13),
+            ]
+        );
+        assert_eq!(tokens[1].token, Token::Whitespace("\n".to_string()));
+        assert_eq!((tokens[2].token.clone(), tokens[2].line), (Token::Fn, 2));
+    }
 }
@@ -1,6 +1,10 @@
 use std::fmt;
 use std::hash::Hash;
 
+use crate::compiler::address::{self, Address};
+use crate::compiler::intern;
+use crate::compiler::wide::WideUint;
+
 /// Represents a token in the Bend-PVM language
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Token {
@@ -11,6 +15,8 @@ pub enum Token {
     Return,
     If,
     Else,
+    While,
+    For,
     Match,
     Case,
     Fold,
@@ -33,7 +39,19 @@ pub enum Token {
     Contract,
     Interface,
     Library,
-    Underscore, // For pattern matching
+    Event,
+    Emit,
+    ErrorType, // `error` definitions; `Error` holds lexer errors
+    Storage,
+    Checked,
+    Unchecked,
+    Assert,
+    Require,
+    Revert,
+    Guard,
+    Asm,
+    Pub,
+    Underscore, // For pattern matching and the body placeholder of guards
     True,
     False,
 
@@ -44,9 +62,11 @@ pub enum Token {
     RBrace,
     LBracket,
     RBracket,
+    HashBracket, // #[ (attribute)
     Colon,
     DoubleColon, // ::
     Semicolon,
+    Question, // ? (error propagation)
     Comma,
     Dot,
     Arrow,
@@ -77,8 +97,9 @@ pub enum Token {
     EqualEqual,
     NotEqual,
     BangEqual, // !=
-    AndAnd,    // &&
-    OrOr,      // ||
+    AndAnd,    // && or `and`
+    OrOr,      // || or `or`
+    Bang,      // ! or `not`
     PlusEqual,
     MinusEqual,
     StarEqual,
@@ -103,14 +124,23 @@ pub enum Token {
     F24,    // Alias for FloatLiteral
 
     // Literals
-    Identifier(String),
-    UintLiteral(u32),  // For u24
-    IntLiteral(i32),   // For i24
-    FloatLiteral(u32), // For f24 (stored as bits to enable Eq/Hash)
+    Identifier(intern::Symbol), // Interned, so copying one allocates nothing
+    UintLiteral(u32),           // For u24, e.g. `42` or `0xff`
+    // The wide literals are boxed so that every token fits in 32 bytes
+    WideUintLiteral(Box<WideUint>), // For u64, u128 and u256, e.g. `1000u128` or `5000000000`
+    AddressLiteral(Box<Address>),   // SS58 or hex after `@`, e.g. `@5Grw...`
+    HashLiteral(Box<address::Hash>), // 32 hex bytes, e.g. `0x00...01`
+    BytesLiteral(Vec<u8>),          // An even number of hex digits, e.g. `hex"deadbeef"`
+    IntLiteral(i32),                // For i24
+    FloatLiteral(u32),              // For f24 (stored as bits to enable Eq/Hash)
     StringLiteral(String),
     CharLiteral(char),
     SymbolLiteral(String),
 
+    // Trivia, only produced by `BendLexer::with_trivia`
+    Comment(String),
+    Whitespace(String),
+
     // Special
     EOF,
     Error(String),
@@ -125,6 +155,8 @@ impl fmt::Display for Token {
             Token::Return => write!(f, "return"),
             Token::If => write!(f, "if"),
             Token::Else => write!(f, "else"),
+            Token::While => write!(f, "while"),
+            Token::For => write!(f, "for"),
             Token::Match => write!(f, "match"),
             Token::Case => write!(f, "case"),
             Token::Fold => write!(f, "fold"),
@@ -146,7 +178,19 @@ impl fmt::Display for Token {
             Token::Fn => write!(f, "fn"),
             Token::Contract => write!(f, "contract"),
             Token::Interface => write!(f, "interface"),
+            Token::Pub => write!(f, "pub"),
             Token::Library => write!(f, "library"),
+            Token::Event => write!(f, "event"),
+            Token::Emit => write!(f, "emit"),
+            Token::ErrorType => write!(f, "error"),
+            Token::Storage => write!(f, "storage"),
+            Token::Checked => write!(f, "checked"),
+            Token::Unchecked => write!(f, "unchecked"),
+            Token::Assert => write!(f, "assert"),
+            Token::Require => write!(f, "require"),
+            Token::Revert => write!(f, "revert"),
+            Token::Guard => write!(f, "guard"),
+            Token::Asm => write!(f, "asm"),
             Token::Underscore => write!(f, "_"),
             Token::True => write!(f, "true"),
             Token::False => write!(f, "false"),
@@ -155,10 +199,12 @@ impl fmt::Display for Token {
             Token::LBrace => write!(f, "{{"),
             Token::RBrace => write!(f, "}}"),
             Token::LBracket => write!(f, "["),
+            Token::HashBracket => write!(f, "#["),
             Token::RBracket => write!(f, "]"),
             Token::Colon => write!(f, ":"),
             Token::DoubleColon => write!(f, "::"),
             Token::Semicolon => write!(f, ";"),
+            Token::Question => write!(f, "?"),
             Token::Comma => write!(f, ","),
             Token::Dot => write!(f, "."),
             Token::Arrow => write!(f, "->"),
@@ -189,6 +235,7 @@ impl fmt::Display for Token {
             Token::BangEqual => write!(f, "!="),
             Token::AndAnd => write!(f, "&&"),
             Token::OrOr => write!(f, "||"),
+            Token::Bang => write!(f, "!"),
             Token::PlusEqual => write!(f, "+="),
             Token::MinusEqual => write!(f, "-="),
             Token::StarEqual => write!(f, "*="),
@@ -211,11 +258,16 @@ impl fmt::Display for Token {
             Token::F24 => write!(f, "F24"),
             Token::Identifier(s) => write!(f, "{}", s),
             Token::UintLiteral(n) => write!(f, "{}", n),
+            Token::WideUintLiteral(n) => write!(f, "{}u{}", n, n.bits()),
+            Token::AddressLiteral(address) => write!(f, "@{}", address),
+            Token::HashLiteral(hash) => write!(f, "{}", hash),
+            Token::BytesLiteral(bytes) => write!(f, "0x{}", hex::encode(bytes)),
             Token::IntLiteral(n) => write!(f, "{}", n),
             Token::FloatLiteral(bits) => write!(f, "{}", f32::from_bits(*bits)),
             Token::StringLiteral(s) => write!(f, "\"{}\"", s),
             Token::CharLiteral(c) => write!(f, "'{}'", c),
             Token::SymbolLiteral(s) => write!(f, "`{}`", s),
+            Token::Comment(text) | Token::Whitespace(text) => write!(f, "{}", text),
             Token::EOF => write!(f, "EOF"),
             Token::Error(e) => write!(f, "Error: {}", e),
         }
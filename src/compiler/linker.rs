@@ -0,0 +1,346 @@
+//! Linking object files into one program
+//!
+//! The [`Linker`] merges [`ObjectFile`]s into the instructions of one
+//! program and its binary. Global symbols are shared by every object and
+//! must be defined once; the labels local to an object are renamed after
+//! it, except in the first. Sections are then garbage collected: the
+//! sections of the first object, the entry, are kept along with every
+//! section a kept one refers to, and the rest is left out. A symbol is only
+//! reported as undefined when a kept section refers to it.
+
+use std::collections::{HashMap, HashSet};
+
+use thiserror::Error;
+
+use crate::compiler::codegen::encoder::{assemble, Isa};
+use crate::compiler::codegen::risc_v::Instruction;
+use crate::compiler::object::{Binding, ObjectFile};
+use crate::compiler::polkavm::bridge::compile_to_polkavm;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum LinkError {
+    #[error("Nothing to link")]
+    Empty,
+
+    #[error("Undefined symbol {symbol}, referred to by {module}")]
+    UndefinedSymbol { symbol: String, module: String },
+
+    #[error("Symbol {symbol} is defined by both {first} and {second}")]
+    DuplicateSymbol {
+        symbol: String,
+        first: String,
+        second: String,
+    },
+
+    #[error("Object {module} was compiled for {found}, expected {expected}")]
+    IsaMismatch {
+        module: String,
+        found: Isa,
+        expected: Isa,
+    },
+
+    #[error("Failed to encode the linked program: {0}")]
+    Encode(String),
+}
+
+/// A section of one of the objects linked
+type SectionId = (usize, usize);
+
+/// Links objects into one program
+#[derive(Debug, Clone)]
+pub struct Linker {
+    objects: Vec<ObjectFile>,
+    gc_sections: bool,
+}
+
+impl Default for Linker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Linker {
+            objects: Vec::new(),
+            gc_sections: true,
+        }
+    }
+
+    /// Add an object; the first one added is the entry
+    pub fn with_object(mut self, object: ObjectFile) -> Self {
+        self.objects.push(object);
+        self
+    }
+
+    /// Keep every section, even those no kept section refers to
+    pub fn keep_unused(mut self) -> Self {
+        self.gc_sections = false;
+        self
+    }
+
+    /// The instructions of the linked program
+    pub fn link(&self) -> Result<Vec<Instruction>, LinkError> {
+        let entry = self.objects.first().ok_or(LinkError::Empty)?;
+        for object in &self.objects {
+            if object.isa != entry.isa {
+                return Err(LinkError::IsaMismatch {
+                    module: object.module.clone(),
+                    found: object.isa,
+                    expected: entry.isa,
+                });
+            }
+        }
+
+        let globals = self.globals()?;
+        let resolve = |index: usize, symbol: &str| -> Result<SectionId, LinkError> {
+            let object = &self.objects[index];
+            object
+                .symbols
+                .iter()
+                .find(|defined| defined.name == symbol)
+                .and_then(|defined| defined.section)
+                .map(|section| (index, section))
+                .or_else(|| globals.get(symbol).copied())
+                .ok_or_else(|| LinkError::UndefinedSymbol {
+                    symbol: symbol.to_string(),
+                    module: object.module.clone(),
+                })
+        };
+
+        let mut pending: Vec<SectionId> = self
+            .objects
+            .iter()
+            .enumerate()
+            .take(if self.gc_sections { 1 } else { usize::MAX })
+            .flat_map(|(index, object)| (0..object.sections.len()).map(move |s| (index, s)))
+            .collect();
+        let mut kept: HashSet<SectionId> = pending.iter().copied().collect();
+        while let Some((index, section)) = pending.pop() {
+            for relocation in &self.objects[index].relocations {
+                if relocation.section != section {
+                    continue;
+                }
+                let target = resolve(index, &relocation.symbol)?;
+                if kept.insert(target) {
+                    pending.push(target);
+                }
+            }
+        }
+
+        let mut code = Vec::new();
+        for (index, object) in self.objects.iter().enumerate() {
+            let rename = local_labels(object, index);
+            for (section, contents) in object.sections.iter().enumerate() {
+                if !kept.contains(&(index, section)) {
+                    continue;
+                }
+                for instruction in &contents.code {
+                    let mut instruction = instruction.clone();
+                    let label = match &mut instruction {
+                        Instruction::Label(label) => Some(label),
+                        instruction => instruction.target_mut(),
+                    };
+                    if let Some(label) = label {
+                        if let Some(renamed) = rename.get(label.as_str()) {
+                            *label = renamed.clone();
+                        }
+                    }
+                    code.push(instruction);
+                }
+            }
+        }
+        Ok(code)
+    }
+
+    /// The binary of the linked program: a PolkaVM blob, or machine code
+    /// for 64-bit ISAs
+    pub fn link_binary(&self) -> Result<Vec<u8>, LinkError> {
+        let code = self.link()?;
+        let isa = self.objects[0].isa;
+        if isa.is_64_bit() {
+            return assemble(&code, isa)
+                .map(|machine_code| machine_code.code)
+                .map_err(|e| LinkError::Encode(e.to_string()));
+        }
+        compile_to_polkavm(&code, None)
+            .map_err(|e| LinkError::Encode(e.to_string()))?
+            .binary
+            .ok_or_else(|| LinkError::Encode("no binary generated".to_string()))
+    }
+
+    /// Where each global symbol is defined
+    fn globals(&self) -> Result<HashMap<&str, SectionId>, LinkError> {
+        let mut globals: HashMap<&str, SectionId> = HashMap::new();
+        for (index, object) in self.objects.iter().enumerate() {
+            for symbol in &object.symbols {
+                let (Binding::Global, Some(section)) = (symbol.binding, symbol.section) else {
+                    continue;
+                };
+                if let Some(&(first, _)) = globals.get(symbol.name.as_str()) {
+                    return Err(LinkError::DuplicateSymbol {
+                        symbol: symbol.name.clone(),
+                        first: self.objects[first].module.clone(),
+                        second: object.module.clone(),
+                    });
+                }
+                globals.insert(&symbol.name, (index, section));
+            }
+        }
+        Ok(globals)
+    }
+}
+
+/// New names of the labels local to the object at `index`, prefixed with
+/// its index so objects cannot clash. Those of the entry keep their name.
+fn local_labels(object: &ObjectFile, index: usize) -> HashMap<&str, String> {
+    if index == 0 {
+        return HashMap::new();
+    }
+    let globals: HashSet<&str> = object
+        .symbols
+        .iter()
+        .filter(|symbol| symbol.binding == Binding::Global)
+        .map(|symbol| symbol.name.as_str())
+        .collect();
+    object
+        .sections
+        .iter()
+        .flat_map(|section| &section.code)
+        .filter_map(|instruction| match instruction {
+            Instruction::Label(label) if !globals.contains(label.as_str()) => {
+                Some((label.as_str(), format!("o{}.{}", index, label)))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::codegen::risc_v::Register;
+
+    fn object(module: &str, code: Vec<Instruction>, functions: &[&str]) -> ObjectFile {
+        let functions = functions.iter().map(|name| name.to_string()).collect();
+        ObjectFile::from_code(module, Isa::default(), &code, &functions, |_| {
+            Some(Binding::Global)
+        })
+    }
+
+    fn labels(code: &[Instruction]) -> Vec<String> {
+        code.iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Label(label) => Some(label.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn library() -> ObjectFile {
+        object(
+            "lib",
+            vec![
+                Instruction::Label("function.used".to_string()),
+                Instruction::Label("loop".to_string()),
+                Instruction::BranchNe(Register::X10, Register::X0, "loop".to_string()),
+                Instruction::JumpAndLinkReg(Register::X0, Register::X1, 0),
+                Instruction::Label("function.unused".to_string()),
+                Instruction::JumpAndLinkReg(Register::X0, Register::X1, 0),
+            ],
+            &["function.used", "function.unused"],
+        )
+    }
+
+    fn app() -> ObjectFile {
+        object(
+            "app",
+            vec![
+                Instruction::Label("main".to_string()),
+                Instruction::Label("loop".to_string()),
+                Instruction::JumpAndLink(Register::X1, "function.used".to_string()),
+                Instruction::Jump("loop".to_string()),
+            ],
+            &["main"],
+        )
+    }
+
+    #[test]
+    fn test_object_sections_and_relocations() {
+        let app = app();
+        assert_eq!(app.sections.len(), 1);
+        assert_eq!(app.relocations.len(), 1);
+        assert_eq!(app.relocations[0].symbol, "function.used");
+        assert_eq!(
+            app.undefined()
+                .map(|symbol| &symbol.name)
+                .collect::<Vec<_>>(),
+            ["function.used"]
+        );
+
+        let library = ObjectFile::from_bytes(&library().to_bytes()).unwrap();
+        assert_eq!(library.sections.len(), 2);
+        assert!(library.relocations.is_empty());
+        assert_eq!(library.undefined().count(), 0);
+    }
+
+    #[test]
+    fn test_link_resolves_renames_and_collects() {
+        let code = Linker::new()
+            .with_object(app())
+            .with_object(library())
+            .link()
+            .unwrap();
+        assert_eq!(labels(&code), ["main", "loop", "function.used", "o1.loop"]);
+        assert!(matches!(
+            &code[6],
+            Instruction::BranchNe(_, _, label) if label == "o1.loop"
+        ));
+
+        let code = Linker::new()
+            .with_object(app())
+            .with_object(library())
+            .keep_unused()
+            .link()
+            .unwrap();
+        assert!(labels(&code).contains(&"function.unused".to_string()));
+
+        assert!(!Linker::new()
+            .with_object(app())
+            .with_object(library())
+            .link_binary()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_link_errors() {
+        assert_eq!(Linker::new().link().unwrap_err(), LinkError::Empty);
+        assert_eq!(
+            Linker::new().with_object(app()).link().unwrap_err(),
+            LinkError::UndefinedSymbol {
+                symbol: "function.used".to_string(),
+                module: "app".to_string(),
+            }
+        );
+        assert_eq!(
+            Linker::new()
+                .with_object(library())
+                .with_object(library())
+                .link()
+                .unwrap_err(),
+            LinkError::DuplicateSymbol {
+                symbol: "function.used".to_string(),
+                first: "lib".to_string(),
+                second: "lib".to_string(),
+            }
+        );
+
+        let mut wide = library();
+        wide.isa = Isa::Rv64Im;
+        assert!(matches!(
+            Linker::new().with_object(app()).with_object(wide).link(),
+            Err(LinkError::IsaMismatch { .. })
+        ));
+    }
+}
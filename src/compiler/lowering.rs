@@ -0,0 +1,2110 @@
+//! AST Lowering Pass
+//!
+//! This pass rewrites surface syntax into the core constructs the later
+//! passes understand. It runs after type checking, so errors are still
+//! reported against the code as written.
+//!
+//! List and map comprehensions become folds over the iterated list. Inside a
+//! fold the `tail` of a `List/Cons` is bound to the already folded rest of
+//! the list, so each case only has to handle one element. A comprehension
+//! assigned or returned as a whole instead becomes loops, which backends
+//! without folds can run: a list comprehension conses its elements onto a
+//! list, walking the iterated list with `match`, and a map comprehension
+//! over `range(...)` fills the map.
+//!
+//! Calls through an interface, `IToken(token).transfer(to, amount)`, become
+//! `call` builtins with the canonical signature of the message, so the
+//! callee's dispatcher receives the selector it expects.
+//!
+//! Guards listed in `#[guard(...)]` are expanded into the functions they
+//! wrap, outermost first. The arguments are bound to fresh temporaries and
+//! the locals of the guard are renamed, so they cannot clash with the
+//! locals of the function. When a guard runs code after the placeholder,
+//! the final `return` of the body stores its value, the code of the guard
+//! runs and the stored value is returned.
+//!
+//! A `#[non_reentrant]` function reverts unless the reentrancy lock, a
+//! storage field added to the program, is free, holds it while its body and
+//! guards run, and releases it before returning.
+//!
+//! With a storage declared `#[version(n)]`, the schema version the storage
+//! was last migrated to is kept in another added storage field, which
+//! `storage_version()` reads. The `#[migrate]` function reverts unless that
+//! version is lower than `n`, and records `n` before returning.
+//!
+//! With specifications checked, as debug builds do, the `#[requires(...)]`
+//! of a function become `require`s run first. Its `#[ensures(...)]`, and
+//! the `#[invariant(...)]`s of storage when it may change state, become
+//! `assert`s run where the body returns; `result` reads the returned value
+//! and `old(...)` a value captured before anything else runs.
+//!
+//! A superposition `{a, b}` is duplicated at compile time, as HVM would
+//! duplicate the term consuming it: the statement holding it, an
+//! expression statement, an `emit` or a write to a map entry, becomes one
+//! copy per alternative, run in the order written. PolkaVM runs them one
+//! after the other, so they cost the gas of every copy together, and there
+//! is no parallel speedup. The type checker rejects superpositions anywhere
+//! else, and statements of more than [`MAX_SUPERPOSITION_COPIES`] copies.
+//!
+//! # Examples
+//!
+//! ```text
+//! // Before lowering:
+//! ys = [f(x) for x in xs if p(x)];
+//!
+//! // After lowering:
+//! ys = {
+//!     %list.0 = xs;
+//!     fold %list.0 {
+//!         case List/Cons { head: x, tail: %tail.0 }:
+//!             if p(x) { return List/Cons(f(x), %tail.0); } else { return %tail.0; }
+//!         case List/Nil:
+//!             return List/Nil;
+//!     }
+//! };
+//!
+//! // Before lowering:
+//! squares = {x: x * x for x in range(n)};
+//!
+//! // After lowering:
+//! %start.2 = 0;
+//! %index.1 = n;
+//! %map.0 = {};
+//! while %index.1 > %start.2 {
+//!     %index.1 = %index.1 - 1;
+//!     %map.0[%index.1] = %index.1 * %index.1;
+//! }
+//! squares = %map.0;
+//!
+//! // Before lowering:
+//! guard at_least(amount: u24, min: u24) {
+//!     require(amount >= min);
+//!     _;
+//!     total = total + amount;
+//! }
+//!
+//! #[guard(at_least(value, 10))]
+//! fn deposit(value: u24) -> u24 { return value * 2; }
+//!
+//! // After lowering:
+//! fn deposit(value: u24) -> u24 {
+//!     %guard.0.amount = value;
+//!     %guard.0.min = 10;
+//!     require(%guard.0.amount >= %guard.0.min);
+//!     %guard.0.result = value * 2;
+//!     total = total + %guard.0.amount;
+//!     return %guard.0.result;
+//! }
+//!
+//! // Before lowering:
+//! emit Approval({alice, bob}, {0, limit});
+//!
+//! // After lowering:
+//! emit Approval(alice, 0);
+//! emit Approval(alice, limit);
+//! emit Approval(bob, 0);
+//! emit Approval(bob, limit);
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::analyzer::attributes::{
+    FunctionAttributes, GuardCall, Mutability, StorageAttributes,
+};
+use crate::compiler::analyzer::type_checker::{OLD, RESULT, STORAGE_VERSION};
+use crate::compiler::codegen::metadata::type_name;
+use crate::compiler::intern::Symbol;
+use crate::compiler::parser::ast::{
+    named_args_in_order_mut, BinaryOperator, Block, Definition, Expr, LiteralKind, Location,
+    LocationProvider, MatchCase, Pattern, Program, RevertKind, Statement, StorageField, Type,
+    Visibility,
+};
+
+/// Storage field holding the lock of the `#[non_reentrant]` functions,
+/// named so that no source identifier can clash with it
+pub const REENTRANCY_LOCK: &str = "%reentrancy.lock";
+
+/// Storage field holding the schema version the storage was last migrated
+/// to, named so that no source identifier can clash with it
+pub const STORAGE_VERSION_FIELD: &str = "%storage.version";
+
+/// `require` message of a migration run on storage already migrated
+pub const ALREADY_MIGRATED: &str = "storage already migrated";
+
+/// Start of the `require` message of a failed precondition
+pub const PRECONDITION_FAILED: &str = "precondition failed";
+
+/// Start of the `assert` message of a failed postcondition
+pub const POSTCONDITION_FAILED: &str = "postcondition failed";
+
+/// Start of the `assert` message of a failed invariant
+pub const INVARIANT_FAILED: &str = "invariant failed";
+
+/// Most copies the superpositions of one statement may expand it into
+pub const MAX_SUPERPOSITION_COPIES: usize = 64;
+
+/// Lowering pass over a whole program
+pub struct Lowering {
+    /// Counter for the names of the temporaries introduced by the pass
+    next_temporary: usize,
+    /// Number of comprehensions lowered so far
+    pub lowered_comprehensions: u32,
+    /// Number of guards expanded into function bodies so far
+    pub expanded_guards: u32,
+    /// Number of `#[non_reentrant]` functions wrapped in the lock so far
+    pub locked_functions: u32,
+    /// Number of statements duplicated for their superpositions so far
+    pub expanded_superpositions: u32,
+    /// Guard definitions of the program by name
+    guards: HashMap<Symbol, Definition>,
+    /// Canonical signatures of the messages of every interface
+    interfaces: HashMap<Symbol, HashMap<Symbol, String>>,
+    /// Storage fields, which keep their names inside guards
+    storage: HashSet<Symbol>,
+    /// Prefix of the renamed variables of the guard being expanded
+    guard_prefix: Option<String>,
+    /// Renamed variables of the guard being expanded
+    renames: HashMap<Symbol, Symbol>,
+    /// Whether to check the specifications of the functions
+    specifications: bool,
+    /// Invariants of the contract
+    invariants: Vec<Expr>,
+    /// Version of the storage schema, unless a function named
+    /// `storage_version` shadows the builtin reading it
+    storage_version: Option<u32>,
+    /// Whether a function is named `storage_version`
+    shadowed_storage_version: bool,
+    /// Prefix of the values captured by the `old(...)`s of the
+    /// postcondition being lowered, and the assignments capturing them
+    old_values: Option<(String, Vec<Statement>)>,
+}
+
+impl Default for Lowering {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lowering {
+    pub fn new() -> Self {
+        Self {
+            next_temporary: 0,
+            lowered_comprehensions: 0,
+            expanded_guards: 0,
+            locked_functions: 0,
+            expanded_superpositions: 0,
+            guards: HashMap::new(),
+            interfaces: HashMap::new(),
+            storage: HashSet::new(),
+            guard_prefix: None,
+            renames: HashMap::new(),
+            specifications: false,
+            invariants: Vec::new(),
+            storage_version: None,
+            shadowed_storage_version: false,
+            old_values: None,
+        }
+    }
+
+    /// Check the preconditions, postconditions and invariants of the
+    /// functions when `enabled`
+    pub fn with_specifications(mut self, enabled: bool) -> Self {
+        self.specifications = enabled;
+        self
+    }
+
+    /// Lower every definition of a program in place
+    pub fn lower_program(&mut self, program: &mut Program) {
+        for definition in &program.definitions {
+            match definition {
+                Definition::GuardDef { name, .. } => {
+                    self.guards.insert(*name, definition.clone());
+                }
+                Definition::InterfaceDef {
+                    name, functions, ..
+                } => {
+                    let messages = functions
+                        .iter()
+                        .filter_map(|function| match function {
+                            Definition::FunctionDef { name, params, .. } => {
+                                let types: Vec<String> =
+                                    params.iter().map(|param| type_name(&param.ty)).collect();
+                                Some((*name, format!("{}({})", name, types.join(","))))
+                            }
+                            _ => None,
+                        })
+                        .collect();
+                    self.interfaces.insert(*name, messages);
+                }
+                Definition::StorageDef {
+                    fields, attributes, ..
+                } => {
+                    self.storage.extend(fields.iter().map(|field| field.name));
+                    if let Ok(storage) = StorageAttributes::from_attributes(attributes) {
+                        self.invariants.extend(storage.invariants);
+                        self.storage_version = self.storage_version.or(storage.version);
+                    }
+                }
+                Definition::FunctionDef { name, .. } if name == STORAGE_VERSION => {
+                    self.shadowed_storage_version = true;
+                }
+                _ => {}
+            }
+        }
+
+        for definition in &mut program.definitions {
+            self.lower_definition(definition);
+        }
+
+        if self.locked_functions > 0 {
+            add_storage_field(program, REENTRANCY_LOCK);
+        }
+        if self.storage_version.is_some() {
+            add_storage_field(program, STORAGE_VERSION_FIELD);
+        }
+    }
+
+    fn lower_definition(&mut self, definition: &mut Definition) {
+        match definition {
+            Definition::FunctionDef {
+                name,
+                params,
+                body,
+                attributes,
+                ..
+            } => {
+                self.lower_block(body);
+                if let Ok(attributes) = FunctionAttributes::from_attributes(attributes, params) {
+                    for call in attributes.guards.iter().rev() {
+                        self.expand_guard(call, body);
+                    }
+                    if let (true, Some(version)) = (attributes.migrate, self.storage_version) {
+                        self.expand_migration(version, body);
+                    }
+                    // Outermost, so the guards run under the lock too
+                    if attributes.non_reentrant {
+                        self.expand_lock(body);
+                    }
+                    if self.specifications && !attributes.external && !attributes.test {
+                        self.expand_specification(name, &attributes, body);
+                    }
+                }
+            }
+            Definition::ObjectDef { functions, .. } => {
+                for function in functions {
+                    self.lower_definition(function);
+                }
+            }
+            Definition::Module { definitions, .. } => {
+                for definition in definitions {
+                    self.lower_definition(definition);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn lower_block(&mut self, block: &mut Block) {
+        // Superpositions first, so every copy is lowered as if written out
+        let statements = std::mem::take(&mut block.statements);
+        for statement in statements {
+            let copies = expand_superpositions(statement);
+            if copies.len() > 1 {
+                self.expanded_superpositions += 1;
+            }
+            for mut statement in copies {
+                if let Some(statements) = self.lower_range_comprehension(&mut statement) {
+                    block.statements.extend(statements);
+                    continue;
+                }
+                if let Some(statements) = self.lower_list_loop(&mut statement) {
+                    block.statements.extend(statements);
+                    continue;
+                }
+                self.lower_statement(&mut statement);
+                block.statements.push(statement);
+            }
+        }
+    }
+
+    fn lower_statement(&mut self, statement: &mut Statement) {
+        match statement {
+            Statement::Assignment { pattern, value, .. } => {
+                self.lower_expr(value);
+                self.lower_pattern(pattern);
+            }
+            Statement::Use { name, value, .. } => {
+                self.lower_expr(value);
+                self.rename_binding(name);
+            }
+            Statement::InPlaceOp { target, value, .. } => {
+                self.lower_expr(value);
+                self.rename_binding(target);
+            }
+            Statement::Return { value, .. }
+            | Statement::Open { value, .. }
+            | Statement::Expr { expr: value, .. } => self.lower_expr(value),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.lower_expr(condition);
+                self.lower_block(then_branch);
+                self.lower_block(else_branch);
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.lower_expr(condition);
+                self.lower_block(body);
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                ..
+            } => {
+                self.lower_expr(start);
+                self.lower_expr(end);
+                self.rename_binding(variable);
+                self.lower_block(body);
+            }
+            Statement::Switch { value, cases, .. } => {
+                self.lower_expr(value);
+                for case in cases {
+                    self.lower_block(&mut case.body);
+                }
+            }
+            Statement::Match { value, cases, .. } | Statement::Fold { value, cases, .. } => {
+                self.lower_expr(value);
+                for case in cases {
+                    self.lower_block(&mut case.body);
+                }
+            }
+            Statement::Bend {
+                initial_states,
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                for (_, expr) in initial_states {
+                    self.lower_expr(expr);
+                }
+                self.lower_expr(condition);
+                self.lower_block(body);
+                if let Some(else_body) = else_body {
+                    self.lower_block(else_body);
+                }
+            }
+            Statement::With { body, .. } | Statement::Unchecked { body, .. } => {
+                self.lower_block(body);
+            }
+            Statement::LocalDef { function_def, .. } => self.lower_definition(function_def),
+            Statement::TryCatch {
+                try_block,
+                catch_blocks,
+                ..
+            } => {
+                self.lower_block(try_block);
+                for catch in catch_blocks {
+                    self.lower_block(&mut catch.body);
+                }
+            }
+            Statement::Emit { args, .. } => {
+                for arg in args {
+                    self.lower_expr(arg);
+                }
+            }
+            Statement::Revert {
+                condition, reason, ..
+            } => {
+                for expr in condition.iter_mut().chain(reason) {
+                    self.lower_expr(expr);
+                }
+            }
+        }
+    }
+
+    fn lower_expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Tuple { elements, .. }
+            | Expr::List { elements, .. }
+            | Expr::Array { elements, .. }
+            | Expr::Superposition { elements, .. } => {
+                for element in elements {
+                    self.lower_expr(element);
+                }
+            }
+            Expr::Constructor {
+                args, named_args, ..
+            } => {
+                for arg in args.iter_mut().chain(named_args_in_order_mut(named_args)) {
+                    self.lower_expr(arg);
+                }
+            }
+            Expr::FunctionCall { function, args, .. }
+                if self.old_values.is_some()
+                    && args.len() == 1
+                    && matches!(&**function, Expr::Variable { name, .. } if name == OLD) =>
+            {
+                let mut value = args.remove(0);
+                // Captured before the body runs, where `result` is not
+                // bound yet
+                let result = self.renames.remove(&Symbol::intern(RESULT));
+                self.lower_expr(&mut value);
+                self.renames
+                    .extend(result.map(|result| (RESULT.into(), result)));
+
+                let (prefix, captures) = self.old_values.as_mut().expect("capturing old values");
+                let name = format!("{}.old.{}", prefix, captures.len());
+                let location = value.location().clone();
+                captures.push(Statement::Assignment {
+                    pattern: Pattern::Variable {
+                        name: name.clone().into(),
+                        location: location.clone(),
+                    },
+                    value,
+                    location: location.clone(),
+                });
+                *expr = variable_expr(&name, &location);
+            }
+            Expr::FunctionCall { function, args, .. }
+                if args.is_empty()
+                    && self.storage_version.is_some()
+                    && !self.shadowed_storage_version
+                    && matches!(&**function, Expr::Variable { name, .. } if name == STORAGE_VERSION) =>
+            {
+                *expr = variable_expr(STORAGE_VERSION_FIELD, expr.location());
+            }
+            Expr::FunctionCall {
+                function,
+                args,
+                named_args,
+                location,
+            } => {
+                self.lower_expr(function);
+                for arg in args.iter_mut().chain(named_args_in_order_mut(named_args)) {
+                    self.lower_expr(arg);
+                }
+                if let Some(call) = self.lower_interface_call(function, args, location) {
+                    *expr = call;
+                }
+            }
+            Expr::Lambda { body, .. }
+            | Expr::UnsccopedLambda { body, .. }
+            | Expr::UnaryOp { operand: body, .. }
+            | Expr::FieldAccess { object: body, .. }
+            | Expr::TreeLeaf { value: body, .. }
+            | Expr::Try { expr: body, .. } => self.lower_expr(body),
+            Expr::BinaryOp { left, right, .. }
+            | Expr::TreeNode { left, right, .. }
+            | Expr::MapAccess {
+                map: left,
+                key: right,
+                ..
+            } => {
+                self.lower_expr(left);
+                self.lower_expr(right);
+            }
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.lower_expr(key);
+                    self.lower_expr(value);
+                }
+            }
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.lower_expr(condition);
+                self.lower_expr(then_branch);
+                self.lower_expr(else_branch);
+            }
+            Expr::Block { block, .. } => self.lower_block(block),
+            Expr::InlineAsm { inputs, .. } => {
+                for (_, input) in inputs {
+                    self.lower_expr(input);
+                }
+            }
+            Expr::ListComprehension {
+                element,
+                variable,
+                iterable,
+                condition,
+                location,
+            } => {
+                for expr in [&mut **element, &mut **iterable]
+                    .into_iter()
+                    .chain(condition.as_deref_mut())
+                {
+                    self.lower_expr(expr);
+                }
+                *expr = self.lower_list_comprehension(
+                    element,
+                    variable,
+                    iterable,
+                    condition.as_deref(),
+                    location,
+                );
+            }
+            // Only a whole assigned or returned value can become a loop
+            Expr::MapComprehension { iterable, .. } if iterable.as_range().is_some() => {}
+            Expr::MapComprehension {
+                key,
+                value,
+                variable,
+                iterable,
+                condition,
+                location,
+            } => {
+                for expr in [&mut **key, &mut **value, &mut **iterable]
+                    .into_iter()
+                    .chain(condition.as_deref_mut())
+                {
+                    self.lower_expr(expr);
+                }
+                *expr = self.lower_map_comprehension(
+                    (key, value),
+                    variable,
+                    iterable,
+                    condition.as_deref(),
+                    location,
+                );
+            }
+            Expr::Variable { name, .. } => {
+                if let Some(renamed) = self.renames.get(name) {
+                    *name = *renamed;
+                }
+            }
+            Expr::Literal { .. } | Expr::Eraser { .. } => {}
+        }
+    }
+
+    /// Lower the expressions of an assignment target and rename the
+    /// variables it binds
+    fn lower_pattern(&mut self, pattern: &mut Pattern) {
+        match pattern {
+            Pattern::Variable { name, .. } => self.rename_binding(name),
+            Pattern::Tuple { elements, .. } => {
+                for element in elements {
+                    self.lower_pattern(element);
+                }
+            }
+            Pattern::Member { parent, .. } => self.lower_pattern(parent),
+            Pattern::MapAccess { map, key, .. } => {
+                self.lower_expr(map);
+                self.lower_expr(key);
+            }
+            _ => {}
+        }
+    }
+
+    /// Rename a variable bound inside the guard being expanded
+    ///
+    /// Storage fields keep their names, and nothing is renamed outside of
+    /// guards.
+    fn rename_binding(&mut self, name: &mut Symbol) {
+        if let Some(renamed) = self.renames.get(name) {
+            *name = *renamed;
+        } else if let Some(prefix) = &self.guard_prefix {
+            if !self.storage.contains(name) {
+                let renamed = Symbol::from(format!("{}.{}", prefix, name));
+                self.renames.insert(*name, renamed);
+                *name = renamed;
+            }
+        }
+    }
+
+    /// Wrap the body of a function in one of its guards
+    fn expand_guard(&mut self, call: &GuardCall, body: &mut Block) {
+        let Some(Definition::GuardDef {
+            params,
+            mut before,
+            mut after,
+            ..
+        }) = self.guards.get(&Symbol::intern(&call.name)).cloned()
+        else {
+            return;
+        };
+        let prefix = self.temporary("guard");
+
+        // Arguments are evaluated in the scope of the function, before any
+        // variable of the guard is renamed
+        let mut statements = Vec::new();
+        for (param, arg) in params.iter().zip(&call.args) {
+            let name = Symbol::from(format!("{}.{}", prefix, param.name));
+            statements.push(Statement::Assignment {
+                pattern: Pattern::Variable {
+                    name,
+                    location: param.location.clone(),
+                },
+                value: arg.clone(),
+                location: arg.location().clone(),
+            });
+            self.renames.insert(param.name, name);
+        }
+
+        self.guard_prefix = Some(prefix.clone());
+        self.lower_block(&mut before);
+        self.lower_block(&mut after);
+        self.guard_prefix = None;
+        self.renames.clear();
+
+        statements.extend(before.statements);
+        wrap(&prefix, statements, body, after.statements);
+        self.expanded_guards += 1;
+    }
+
+    /// Take the reentrancy lock around the body of a function
+    ///
+    /// The lock is released where the body falls through to its final
+    /// `return`; the type checker rejects earlier returns. A revert rolls
+    /// the lock back with the rest of the state.
+    fn expand_lock(&mut self, body: &mut Block) {
+        let prefix = self.temporary("lock");
+        let location = body.location.clone();
+        let lock = |value: u32| Statement::Assignment {
+            pattern: Pattern::Variable {
+                name: REENTRANCY_LOCK.into(),
+                location: location.clone(),
+            },
+            value: Expr::Literal {
+                kind: LiteralKind::Uint(value),
+                location: location.clone(),
+            },
+            location: location.clone(),
+        };
+        let unlocked = Statement::Revert {
+            kind: RevertKind::Require,
+            condition: Some(Expr::BinaryOp {
+                left: Box::new(variable_expr(REENTRANCY_LOCK, &location)),
+                operator: BinaryOperator::Equal,
+                right: Box::new(Expr::Literal {
+                    kind: LiteralKind::Uint(0),
+                    location: location.clone(),
+                }),
+                location: location.clone(),
+            }),
+            reason: Some(Expr::Literal {
+                kind: LiteralKind::String("reentrant call".to_string()),
+                location: location.clone(),
+            }),
+            location: location.clone(),
+        };
+        wrap(&prefix, vec![unlocked, lock(1)], body, vec![lock(0)]);
+        self.locked_functions += 1;
+    }
+
+    /// Run a migration to `version` at most once
+    ///
+    /// Like the lock, the version is recorded where the body falls through
+    /// to its final `return`, and a revert rolls it back.
+    fn expand_migration(&mut self, version: u32, body: &mut Block) {
+        let prefix = self.temporary("migrate");
+        let location = body.location.clone();
+        let version_literal = Expr::Literal {
+            kind: LiteralKind::Uint(version),
+            location: location.clone(),
+        };
+        let outdated = Statement::Revert {
+            kind: RevertKind::Require,
+            condition: Some(Expr::BinaryOp {
+                left: Box::new(variable_expr(STORAGE_VERSION_FIELD, &location)),
+                operator: BinaryOperator::Less,
+                right: Box::new(version_literal.clone()),
+                location: location.clone(),
+            }),
+            reason: Some(Expr::Literal {
+                kind: LiteralKind::String(ALREADY_MIGRATED.to_string()),
+                location: location.clone(),
+            }),
+            location: location.clone(),
+        };
+        let migrated = Statement::Assignment {
+            pattern: Pattern::Variable {
+                name: STORAGE_VERSION_FIELD.into(),
+                location: location.clone(),
+            },
+            value: version_literal,
+            location: location.clone(),
+        };
+        wrap(&prefix, vec![outdated], body, vec![migrated]);
+    }
+
+    /// Check the specifications of the function `name` around its body
+    ///
+    /// Like the lock, the postconditions and invariants run where the body
+    /// falls through to its final `return`; the type checker rejects
+    /// earlier returns.
+    fn expand_specification(
+        &mut self,
+        name: &str,
+        attributes: &FunctionAttributes,
+        body: &mut Block,
+    ) {
+        let invariants = match attributes.mutability {
+            Mutability::Mutable => self.invariants.clone(),
+            _ => Vec::new(),
+        };
+        if attributes.requires.is_empty() && attributes.ensures.is_empty() && invariants.is_empty()
+        {
+            return;
+        }
+        let prefix = self.temporary("spec");
+
+        let mut before = Vec::new();
+        for condition in &attributes.requires {
+            let mut condition = condition.clone();
+            self.lower_expr(&mut condition);
+            let message = format!(
+                "{}: {} (line {})",
+                PRECONDITION_FAILED,
+                name,
+                condition.location().line
+            );
+            before.push(check(RevertKind::Require, condition, message));
+        }
+
+        let mut after = Vec::new();
+        self.renames
+            .insert(RESULT.into(), format!("{}.result", prefix).into());
+        self.old_values = Some((prefix.clone(), Vec::new()));
+        for condition in &attributes.ensures {
+            let mut condition = condition.clone();
+            self.lower_expr(&mut condition);
+            let message = format!(
+                "{}: {} (line {})",
+                POSTCONDITION_FAILED,
+                name,
+                condition.location().line
+            );
+            after.push(check(RevertKind::Assert, condition, message));
+        }
+        if let Some((_, captures)) = self.old_values.take() {
+            before.extend(captures);
+        }
+        self.renames.clear();
+
+        for mut invariant in invariants {
+            self.lower_expr(&mut invariant);
+            let message = format!("{} (line {})", INVARIANT_FAILED, invariant.location().line);
+            after.push(check(RevertKind::Assert, invariant, message));
+        }
+        wrap(&prefix, before, body, after);
+    }
+
+    /// Lower `Interface(account, value: v, gas: g).message(args)` to
+    /// `call(account, v, g, "message(types)", args)`, with a value and gas
+    /// of 0 when they are not given
+    fn lower_interface_call(
+        &self,
+        function: &Expr,
+        args: &[Expr],
+        location: &Location,
+    ) -> Option<Expr> {
+        let target = function.as_interface_message()?;
+        let signature = self
+            .interfaces
+            .get(&target.interface)?
+            .get(&Symbol::intern(target.message))?;
+        let option = |name: &str| {
+            target
+                .options
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| Expr::Literal {
+                    kind: LiteralKind::Uint(0),
+                    location: location.clone(),
+                })
+        };
+
+        let mut call_args = vec![
+            target.accounts.first()?.clone(),
+            option("value"),
+            option("gas"),
+        ];
+        call_args.push(Expr::Literal {
+            kind: LiteralKind::String(signature.clone()),
+            location: location.clone(),
+        });
+        call_args.extend(args.iter().cloned());
+
+        Some(Expr::FunctionCall {
+            function: Box::new(variable_expr("call", location)),
+            args: call_args,
+            named_args: HashMap::new(),
+            location: location.clone(),
+        })
+    }
+
+    /// Lower `[element for variable in iterable if condition]`
+    ///
+    /// An element that passes the filter is consed onto the folded tail,
+    /// any other element is dropped.
+    fn lower_list_comprehension(
+        &mut self,
+        element: &Expr,
+        variable: &str,
+        iterable: &Expr,
+        condition: Option<&Expr>,
+        location: &Location,
+    ) -> Expr {
+        let tail = self.temporary("tail");
+        let cons = Expr::FunctionCall {
+            function: Box::new(variable_expr("List/Cons", location)),
+            args: vec![element.clone(), variable_expr(&tail, location)],
+            named_args: HashMap::new(),
+            location: location.clone(),
+        };
+        let cons_body = match condition {
+            Some(condition) => vec![Statement::If {
+                condition: condition.clone(),
+                then_branch: block(vec![return_statement(cons, location)], location),
+                else_branch: block(
+                    vec![return_statement(variable_expr(&tail, location), location)],
+                    location,
+                ),
+                location: location.clone(),
+            }],
+            None => vec![return_statement(cons, location)],
+        };
+        let nil = variable_expr("List/Nil", location);
+
+        self.lower_comprehension(variable, &tail, iterable, cons_body, nil, location)
+    }
+
+    /// Lower `{key: value for variable in iterable if condition}`
+    ///
+    /// The entry of an element that passes the filter is inserted into the
+    /// map built from the folded tail, so earlier elements win on duplicate
+    /// keys.
+    fn lower_map_comprehension(
+        &mut self,
+        (key, value): (&Expr, &Expr),
+        variable: &str,
+        iterable: &Expr,
+        condition: Option<&Expr>,
+        location: &Location,
+    ) -> Expr {
+        let tail = self.temporary("tail");
+        let insert = Statement::Assignment {
+            pattern: Pattern::MapAccess {
+                map: Box::new(variable_expr(&tail, location)),
+                key: Box::new(key.clone()),
+                location: location.clone(),
+            },
+            value: value.clone(),
+            location: location.clone(),
+        };
+        let mut cons_body = match condition {
+            Some(condition) => vec![Statement::If {
+                condition: condition.clone(),
+                then_branch: block(vec![insert], location),
+                else_branch: block(Vec::new(), location),
+                location: location.clone(),
+            }],
+            None => vec![insert],
+        };
+        cons_body.push(return_statement(variable_expr(&tail, location), location));
+        let empty = Expr::Map {
+            entries: Vec::new(),
+            location: location.clone(),
+        };
+
+        self.lower_comprehension(variable, &tail, iterable, cons_body, empty, location)
+    }
+
+    /// Lower a statement assigning or returning a map comprehension over
+    /// `range(...)` into a loop filling the map, followed by the statement
+    /// with the map as its value
+    ///
+    /// The range is walked down from its end, so the elements are visited
+    /// in the order a fold visits a list, and earlier elements still win
+    /// on duplicate keys.
+    fn lower_range_comprehension(&mut self, statement: &mut Statement) -> Option<Vec<Statement>> {
+        let target = whole_value(statement)?;
+        let Expr::MapComprehension {
+            iterable, location, ..
+        } = target
+        else {
+            return None;
+        };
+        let mut bounds = iterable.as_range()?.to_vec();
+        let location = location.clone();
+        let map = self.temporary("map");
+        let Expr::MapComprehension {
+            mut key,
+            mut value,
+            variable,
+            mut condition,
+            ..
+        } = std::mem::replace(target, variable_expr(&map, &location))
+        else {
+            unreachable!("matched above");
+        };
+        self.lowered_comprehensions += 1;
+
+        for bound in &mut bounds {
+            self.lower_expr(bound);
+        }
+        let end = bounds.pop().expect("a range has an end");
+        let start = bounds.pop().unwrap_or(Expr::Literal {
+            kind: LiteralKind::Uint(0),
+            location: end.location().clone(),
+        });
+        // The variable reads the index, whatever it shadows
+        let index = self.temporary("index");
+        let shadowed = self.renames.insert(variable, index.clone().into());
+        for expr in [&mut *key, &mut *value]
+            .into_iter()
+            .chain(condition.as_deref_mut())
+        {
+            self.lower_expr(expr);
+        }
+        match shadowed {
+            Some(renamed) => self.renames.insert(variable, renamed),
+            None => self.renames.remove(&variable),
+        };
+
+        let lowest = self.temporary("start");
+        let assign = |name: &str, value: Expr| Statement::Assignment {
+            pattern: Pattern::Variable {
+                name: name.into(),
+                location: location.clone(),
+            },
+            value,
+            location: location.clone(),
+        };
+        let insert = Statement::Assignment {
+            pattern: Pattern::MapAccess {
+                map: Box::new(variable_expr(&map, &location)),
+                key,
+                location: location.clone(),
+            },
+            value: *value,
+            location: location.clone(),
+        };
+        let mut body = vec![assign(
+            &index,
+            Expr::BinaryOp {
+                left: Box::new(variable_expr(&index, &location)),
+                operator: BinaryOperator::Sub,
+                right: Box::new(Expr::Literal {
+                    kind: LiteralKind::Uint(1),
+                    location: location.clone(),
+                }),
+                location: location.clone(),
+            },
+        )];
+        body.push(match condition {
+            Some(condition) => Statement::If {
+                condition: *condition,
+                then_branch: block(vec![insert], &location),
+                else_branch: block(Vec::new(), &location),
+                location: location.clone(),
+            },
+            None => insert,
+        });
+
+        let mut statements = vec![
+            assign(&lowest, start),
+            assign(&index, end),
+            assign(
+                &map,
+                Expr::Map {
+                    entries: Vec::new(),
+                    location: location.clone(),
+                },
+            ),
+            Statement::While {
+                condition: Expr::BinaryOp {
+                    left: Box::new(variable_expr(&index, &location)),
+                    operator: BinaryOperator::Greater,
+                    right: Box::new(variable_expr(&lowest, &location)),
+                    location: location.clone(),
+                },
+                body: block(body, &location),
+                bound: None,
+                location: location.clone(),
+            },
+        ];
+        let mut statement = statement.clone();
+        self.lower_statement(&mut statement);
+        statements.push(statement);
+        Some(statements)
+    }
+
+    /// Lower a statement assigning or returning a list comprehension into
+    /// loops building the list, followed by the statement with the list as
+    /// its value
+    ///
+    /// Over `range(...)` the range is walked down from its end, consing
+    /// every element that passes the filter onto the list. Over a list, the
+    /// elements are consed onto a reversed list while walking it, which a
+    /// second loop turns around, so the order is the order of a fold.
+    fn lower_list_loop(&mut self, statement: &mut Statement) -> Option<Vec<Statement>> {
+        let target = whole_value(statement)?;
+        let Expr::ListComprehension { location, .. } = target else {
+            return None;
+        };
+        let location = location.clone();
+        let list = self.temporary("list");
+        let Expr::ListComprehension {
+            mut element,
+            variable,
+            iterable,
+            mut condition,
+            ..
+        } = std::mem::replace(target, variable_expr(&list, &location))
+        else {
+            unreachable!("matched above");
+        };
+        self.lowered_comprehensions += 1;
+
+        let assign = |name: &str, value: Expr| assignment(name, value, &location);
+        let nil = || variable_expr("List/Nil", &location);
+        let mut statements = Vec::new();
+        // The iterated list is built first, as it may be a comprehension too
+        let (item, lowest) = match iterable.as_range() {
+            Some(bounds) => {
+                let mut bounds = bounds.to_vec();
+                for bound in &mut bounds {
+                    self.lower_expr(bound);
+                }
+                let end = bounds.pop().expect("a range has an end");
+                let start = bounds.pop().unwrap_or(Expr::Literal {
+                    kind: LiteralKind::Uint(0),
+                    location: end.location().clone(),
+                });
+                let (index, lowest) = (self.temporary("index"), self.temporary("start"));
+                statements.push(assign(&lowest, start));
+                statements.push(assign(&index, end));
+                (index, Some(lowest))
+            }
+            None => {
+                let rest = self.temporary("rest");
+                let mut walked = assign(&rest, *iterable);
+                match self.lower_list_loop(&mut walked) {
+                    Some(built) => statements.extend(built),
+                    None => {
+                        self.lower_statement(&mut walked);
+                        statements.push(walked);
+                    }
+                }
+                (rest, None)
+            }
+        };
+
+        // The variable reads the element, whatever it shadows
+        let head = match lowest {
+            Some(_) => item.clone(),
+            None => self.temporary("item"),
+        };
+        let shadowed = self.renames.insert(variable, head.clone().into());
+        for expr in std::iter::once(&mut *element).chain(condition.as_deref_mut()) {
+            self.lower_expr(expr);
+        }
+        match shadowed {
+            Some(renamed) => self.renames.insert(variable, renamed),
+            None => self.renames.remove(&variable),
+        };
+
+        let cons = |value: Expr, onto: &str| Expr::FunctionCall {
+            function: Box::new(variable_expr("List/Cons", &location)),
+            args: vec![value, variable_expr(onto, &location)],
+            named_args: HashMap::new(),
+            location: location.clone(),
+        };
+        let push = |onto: &str| {
+            let push = assign(onto, cons(*element, onto));
+            match condition {
+                Some(condition) => Statement::If {
+                    condition: *condition,
+                    then_branch: block(vec![push], &location),
+                    else_branch: block(Vec::new(), &location),
+                    location: location.clone(),
+                },
+                None => push,
+            }
+        };
+
+        match lowest {
+            Some(lowest) => {
+                let decrement = assign(
+                    &item,
+                    Expr::BinaryOp {
+                        left: Box::new(variable_expr(&item, &location)),
+                        operator: BinaryOperator::Sub,
+                        right: Box::new(Expr::Literal {
+                            kind: LiteralKind::Uint(1),
+                            location: location.clone(),
+                        }),
+                        location: location.clone(),
+                    },
+                );
+                statements.push(assign(&list, nil()));
+                statements.push(Statement::While {
+                    condition: Expr::BinaryOp {
+                        left: Box::new(variable_expr(&item, &location)),
+                        operator: BinaryOperator::Greater,
+                        right: Box::new(variable_expr(&lowest, &location)),
+                        location: location.clone(),
+                    },
+                    body: block(vec![decrement, push(&list)], &location),
+                    bound: None,
+                    location: location.clone(),
+                });
+            }
+            None => {
+                let reversed = self.temporary("reversed");
+                statements.push(assign(&reversed, nil()));
+                let body = vec![push(&reversed)];
+                statements.extend(self.walk_list(&item, &head, body, &location));
+                statements.push(assign(&list, nil()));
+                let head = self.temporary("item");
+                let body = vec![assign(&list, cons(variable_expr(&head, &location), &list))];
+                statements.extend(self.walk_list(&reversed, &head, body, &location));
+            }
+        }
+
+        let mut statement = statement.clone();
+        self.lower_statement(&mut statement);
+        statements.push(statement);
+        Some(statements)
+    }
+
+    /// A loop running `body` for every element of the list in the variable
+    /// `list`, bound to `head`, consuming the list
+    fn walk_list(
+        &mut self,
+        list: &str,
+        head: &str,
+        mut body: Vec<Statement>,
+        location: &Location,
+    ) -> Vec<Statement> {
+        let (more, tail) = (self.temporary("more"), self.temporary("tail"));
+        let flag = |value: bool| {
+            assignment(
+                &more,
+                Expr::Literal {
+                    kind: LiteralKind::Bool(value),
+                    location: location.clone(),
+                },
+                location,
+            )
+        };
+        body.push(assignment(list, variable_expr(&tail, location), location));
+        let variable = |name: &str| Pattern::Variable {
+            name: name.into(),
+            location: location.clone(),
+        };
+        let cases = vec![
+            MatchCase {
+                pattern: Pattern::Constructor {
+                    name: "List/Cons".into(),
+                    fields: HashMap::from([
+                        ("head".to_string(), variable(head)),
+                        ("tail".to_string(), variable(&tail)),
+                    ]),
+                    location: location.clone(),
+                },
+                body: block(body, location),
+                location: location.clone(),
+            },
+            MatchCase {
+                pattern: Pattern::Constructor {
+                    name: "List/Nil".into(),
+                    fields: HashMap::new(),
+                    location: location.clone(),
+                },
+                body: block(vec![flag(false)], location),
+                location: location.clone(),
+            },
+        ];
+        vec![
+            flag(true),
+            Statement::While {
+                condition: variable_expr(&more, location),
+                body: block(
+                    vec![Statement::Match {
+                        value: variable_expr(list, location),
+                        cases,
+                        location: location.clone(),
+                    }],
+                    location,
+                ),
+                bound: None,
+                location: location.clone(),
+            },
+        ]
+    }
+
+    /// Build the block folding `iterable`, binding the head of each
+    /// `List/Cons` to `variable` and the folded tail to `tail`
+    fn lower_comprehension(
+        &mut self,
+        variable: &str,
+        tail: &str,
+        iterable: &Expr,
+        cons_body: Vec<Statement>,
+        nil_value: Expr,
+        location: &Location,
+    ) -> Expr {
+        self.lowered_comprehensions += 1;
+        let list = self.temporary("list");
+
+        let cons_fields = HashMap::from([
+            (
+                "head".to_string(),
+                Pattern::Variable {
+                    name: variable.into(),
+                    location: location.clone(),
+                },
+            ),
+            (
+                "tail".to_string(),
+                Pattern::Variable {
+                    name: tail.into(),
+                    location: location.clone(),
+                },
+            ),
+        ]);
+        let cases = vec![
+            MatchCase {
+                pattern: Pattern::Constructor {
+                    name: "List/Cons".into(),
+                    fields: cons_fields,
+                    location: location.clone(),
+                },
+                body: block(cons_body, location),
+                location: location.clone(),
+            },
+            MatchCase {
+                pattern: Pattern::Constructor {
+                    name: "List/Nil".into(),
+                    fields: HashMap::new(),
+                    location: location.clone(),
+                },
+                body: block(vec![return_statement(nil_value, location)], location),
+                location: location.clone(),
+            },
+        ];
+
+        let statements = vec![
+            Statement::Assignment {
+                pattern: Pattern::Variable {
+                    name: list.clone().into(),
+                    location: iterable.location().clone(),
+                },
+                value: iterable.clone(),
+                location: location.clone(),
+            },
+            Statement::Fold {
+                value: variable_expr(&list, location),
+                cases,
+                location: location.clone(),
+            },
+        ];
+        Expr::Block {
+            block: block(statements, location),
+            location: location.clone(),
+        }
+    }
+
+    /// A fresh name that cannot clash with a source identifier
+    fn temporary(&mut self, prefix: &str) -> String {
+        let name = format!("%{}.{}", prefix, self.next_temporary);
+        self.next_temporary += 1;
+        name
+    }
+}
+
+/// The copies of `statement`, one for each choice of an alternative in its
+/// superpositions, with the leftmost superposition varying slowest
+fn expand_superpositions(statement: Statement) -> Vec<Statement> {
+    let mut probe = statement.clone();
+    let found = superposition_operands(&mut probe)
+        .into_iter()
+        .enumerate()
+        .find_map(|(index, operand)| alternatives(operand).map(|choices| (index, choices)));
+    match found {
+        Some((index, choices)) => choices
+            .into_iter()
+            .flat_map(|choice| {
+                let mut copy = statement.clone();
+                *superposition_operands(&mut copy).swap_remove(index) = choice;
+                expand_superpositions(copy)
+            })
+            .collect(),
+        None => vec![statement],
+    }
+}
+
+/// The copies of `expr` with its first superposition replaced by each of
+/// its alternatives, unless it has none
+fn alternatives(expr: &Expr) -> Option<Vec<Expr>> {
+    if let Expr::Superposition { elements, .. } = expr {
+        return Some(elements.clone());
+    }
+    let mut probe = expr.clone();
+    let (index, choices) = eager_operands(&mut probe)
+        .into_iter()
+        .enumerate()
+        .find_map(|(index, operand)| alternatives(operand).map(|choices| (index, choices)))?;
+    Some(
+        choices
+            .into_iter()
+            .map(|choice| {
+                let mut copy = expr.clone();
+                *eager_operands(&mut copy).swap_remove(index) = choice;
+                copy
+            })
+            .collect(),
+    )
+}
+
+/// Number of copies the superpositions of `statement` expand it into
+pub(crate) fn superposition_copies(statement: &Statement) -> usize {
+    fn copies(expr: &mut Expr) -> usize {
+        match expr {
+            Expr::Superposition { elements, .. } => elements
+                .iter_mut()
+                .map(copies)
+                .fold(0, usize::saturating_add),
+            _ => eager_operands(expr)
+                .into_iter()
+                .map(copies)
+                .fold(1, usize::saturating_mul),
+        }
+    }
+    let mut probe = statement.clone();
+    superposition_operands(&mut probe)
+        .into_iter()
+        .map(copies)
+        .fold(1, usize::saturating_mul)
+}
+
+/// Whether `statement` holds a superposition lowering duplicates it for
+pub(crate) fn has_superpositions(statement: &Statement) -> bool {
+    fn contains(expr: &mut Expr) -> bool {
+        matches!(expr, Expr::Superposition { .. }) || eager_operands(expr).into_iter().any(contains)
+    }
+    let mut probe = statement.clone();
+    superposition_operands(&mut probe).into_iter().any(contains)
+}
+
+/// The expressions of the statements a superposition may be duplicated in:
+/// expression statements, `emit`s and writes to map entries
+fn superposition_operands(statement: &mut Statement) -> Vec<&mut Expr> {
+    match statement {
+        Statement::Expr { expr, .. } => vec![expr],
+        Statement::Emit { args, .. } => args.iter_mut().collect(),
+        Statement::Assignment {
+            pattern: Pattern::MapAccess { map, key, .. },
+            value,
+            ..
+        } => vec![map, key, value],
+        _ => Vec::new(),
+    }
+}
+
+/// The operands `expr` evaluates whenever it is evaluated, in order, so
+/// not the branches of an `if`, the right of `&&` and `||`, or the bodies
+/// of lambdas, blocks and comprehensions
+fn eager_operands(expr: &mut Expr) -> Vec<&mut Expr> {
+    match expr {
+        Expr::Tuple { elements, .. }
+        | Expr::List { elements, .. }
+        | Expr::Array { elements, .. } => elements.iter_mut().collect(),
+        Expr::Constructor {
+            args, named_args, ..
+        } => args
+            .iter_mut()
+            .chain(named_args_in_order_mut(named_args))
+            .collect(),
+        Expr::FunctionCall {
+            function,
+            args,
+            named_args,
+            ..
+        } => std::iter::once(&mut **function)
+            .chain(args.iter_mut())
+            .chain(named_args_in_order_mut(named_args))
+            .collect(),
+        Expr::BinaryOp {
+            left,
+            operator: BinaryOperator::And | BinaryOperator::Or,
+            ..
+        } => vec![left],
+        Expr::BinaryOp { left, right, .. }
+        | Expr::TreeNode { left, right, .. }
+        | Expr::MapAccess {
+            map: left,
+            key: right,
+            ..
+        } => vec![left, right],
+        Expr::UnaryOp { operand: inner, .. }
+        | Expr::FieldAccess { object: inner, .. }
+        | Expr::TreeLeaf { value: inner, .. }
+        | Expr::Try { expr: inner, .. }
+        | Expr::If {
+            condition: inner, ..
+        }
+        | Expr::ListComprehension {
+            iterable: inner, ..
+        }
+        | Expr::MapComprehension {
+            iterable: inner, ..
+        } => vec![inner],
+        Expr::Map { entries, .. } => entries
+            .iter_mut()
+            .flat_map(|(key, value)| [key, value])
+            .collect(),
+        Expr::InlineAsm { inputs, .. } => inputs.iter_mut().map(|(_, input)| input).collect(),
+        Expr::Superposition { .. }
+        | Expr::Lambda { .. }
+        | Expr::UnsccopedLambda { .. }
+        | Expr::Block { .. }
+        | Expr::Variable { .. }
+        | Expr::Literal { .. }
+        | Expr::Eraser { .. } => Vec::new(),
+    }
+}
+
+/// Declare a word storage field the pass uses, such as the reentrancy lock,
+/// after the fields of the program so their layout is unchanged
+fn add_storage_field(program: &mut Program, name: &str) {
+    let field = StorageField {
+        name: name.into(),
+        ty: Type::U24 {
+            location: Location::default(),
+        },
+        visibility: Visibility::Private,
+        location: Location::default(),
+    };
+    let storage = program
+        .definitions
+        .iter_mut()
+        .find_map(|definition| match definition {
+            Definition::StorageDef { fields, .. } => Some(fields),
+            _ => None,
+        });
+    match storage {
+        Some(fields) => {
+            if !fields.iter().any(|field| field.name == name) {
+                fields.push(field);
+            }
+        }
+        None => program.definitions.push(Definition::StorageDef {
+            fields: vec![field],
+            attributes: Vec::new(),
+            location: Location::default(),
+        }),
+    }
+}
+
+/// Lower the superpositions, comprehensions, interface calls, guards, locks and migrations
+/// of a program, without checking its specifications
+pub fn lower_program(mut program: Program) -> Program {
+    Lowering::new().lower_program(&mut program);
+    program
+}
+
+fn variable_expr(name: &str, location: &Location) -> Expr {
+    Expr::Variable {
+        name: name.into(),
+        location: location.clone(),
+    }
+}
+
+fn assignment(name: &str, value: Expr, location: &Location) -> Statement {
+    Statement::Assignment {
+        pattern: Pattern::Variable {
+            name: name.into(),
+            location: location.clone(),
+        },
+        value,
+        location: location.clone(),
+    }
+}
+
+/// The value a statement assigns to a variable or returns, which a
+/// comprehension can be lowered to loops computing beforehand
+fn whole_value(statement: &mut Statement) -> Option<&mut Expr> {
+    match statement {
+        Statement::Assignment {
+            pattern: Pattern::Variable { .. },
+            value,
+            ..
+        }
+        | Statement::Use { value, .. }
+        | Statement::Return { value, .. } => Some(value),
+        _ => None,
+    }
+}
+
+fn return_statement(value: Expr, location: &Location) -> Statement {
+    Statement::Return {
+        value,
+        location: location.clone(),
+    }
+}
+
+/// Run `before`, then the body, then `after`, with the result of the final
+/// `return` of the body kept in `{prefix}.result` while `after` runs
+fn wrap(prefix: &str, before: Vec<Statement>, body: &mut Block, after: Vec<Statement>) {
+    let location = body.location.clone();
+    let mut statements = before;
+    let mut inner = std::mem::take(&mut body.statements);
+    if after.is_empty() {
+        statements.extend(inner);
+    } else {
+        // The body may only leave through its final `return`, which the
+        // type checker enforces for guards with code after `_`
+        let result = match inner.pop() {
+            Some(Statement::Return { value, location }) => {
+                let result = format!("{}.result", prefix);
+                inner.push(Statement::Assignment {
+                    pattern: Pattern::Variable {
+                        name: result.clone().into(),
+                        location: location.clone(),
+                    },
+                    value,
+                    location: location.clone(),
+                });
+                Some(return_statement(
+                    variable_expr(&result, &location),
+                    &location,
+                ))
+            }
+            other => {
+                inner.extend(other);
+                None
+            }
+        };
+        statements.extend(inner);
+        statements.extend(after);
+        statements.extend(result);
+    }
+    *body = block(statements, &location);
+}
+
+/// `require` or `assert` `condition` with `message`
+fn check(kind: RevertKind, condition: Expr, message: String) -> Statement {
+    let location = condition.location().clone();
+    Statement::Revert {
+        kind,
+        condition: Some(condition),
+        reason: Some(Expr::Literal {
+            kind: LiteralKind::String(message),
+            location: location.clone(),
+        }),
+        location,
+    }
+}
+
+fn block(statements: Vec<Statement>, location: &Location) -> Block {
+    Block {
+        statements,
+        location: location.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parser::Parser;
+
+    fn lowered_value(source: &str) -> Expr {
+        let program = lower_program(Parser::new(source).parse_program().unwrap());
+        match &program.definitions[0] {
+            Definition::FunctionDef { body, .. } => match &body.statements[0] {
+                Statement::Assignment { value, .. } => value.clone(),
+                other => panic!("Expected assignment, got {:?}", other),
+            },
+            _ => panic!("Expected function definition"),
+        }
+    }
+
+    /// The cases of the fold a comprehension was lowered to
+    fn fold_cases(expr: &Expr) -> &[MatchCase] {
+        let statements = match expr {
+            Expr::Block { block, .. } => &block.statements,
+            other => panic!("Expected block, got {:?}", other),
+        };
+        assert!(matches!(
+            &statements[0],
+            Statement::Assignment {
+                value: Expr::Variable { name, .. },
+                ..
+            } if name == "xs"
+        ));
+        match &statements[1] {
+            Statement::Fold { cases, .. } => cases,
+            other => panic!("Expected fold, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_lower_list_comprehension() {
+        let lowered = lowered_value(
+            "fn main(xs: List<u24>) -> u24 { n = List/length([x + 1 for x in xs if x > 2]); return n; }",
+        );
+        let cases = match &lowered {
+            Expr::FunctionCall { args, .. } => fold_cases(&args[0]),
+            other => panic!("Expected call, got {:?}", other),
+        };
+
+        match &cases[0].pattern {
+            Pattern::Constructor { name, fields, .. } => {
+                assert_eq!(name, "List/Cons");
+                assert!(matches!(&fields["head"], Pattern::Variable { name, .. } if name == "x"));
+            }
+            other => panic!("Expected constructor pattern, got {:?}", other),
+        }
+        match &cases[0].body.statements[..] {
+            [Statement::If {
+                then_branch,
+                else_branch,
+                ..
+            }] => {
+                assert!(matches!(
+                    &then_branch.statements[0],
+                    Statement::Return {
+                        value: Expr::FunctionCall { .. },
+                        ..
+                    }
+                ));
+                assert!(matches!(
+                    &else_branch.statements[0],
+                    Statement::Return {
+                        value: Expr::Variable { .. },
+                        ..
+                    }
+                ));
+            }
+            other => panic!("Expected filter, got {:?}", other),
+        }
+        assert!(matches!(
+            &cases[1].body.statements[0],
+            Statement::Return { value: Expr::Variable { name, .. }, .. } if name == "List/Nil"
+        ));
+    }
+
+    #[test]
+    fn test_lower_list_loop() {
+        let mut lowering = Lowering::new();
+        let mut program = Parser::new(
+            "fn main(xs: List<u24>) -> u24 { x = 7; ys = [x + 1 for x in xs if x > 2]; return x; }",
+        )
+        .parse_program()
+        .unwrap();
+        lowering.lower_program(&mut program);
+        let statements = match &program.definitions[0] {
+            Definition::FunctionDef { body, .. } => &body.statements,
+            _ => panic!("Expected function definition"),
+        };
+
+        assert_eq!(lowering.lowered_comprehensions, 1);
+        let walks: Vec<&Block> = statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::While { body, .. } => Some(body),
+                _ => None,
+            })
+            .collect();
+        // The elements are consed onto a reversed list, then turned around
+        assert_eq!(walks.len(), 2);
+        let cases = match &walks[0].statements[..] {
+            [Statement::Match { cases, .. }] => cases,
+            other => panic!("Expected a match on the list, got {:?}", other),
+        };
+        let head = match &cases[0].pattern {
+            Pattern::Constructor { name, fields, .. } if name == "List/Cons" => {
+                match &fields["head"] {
+                    Pattern::Variable { name, .. } => name,
+                    other => panic!("Expected a variable, got {:?}", other),
+                }
+            }
+            other => panic!("Expected constructor pattern, got {:?}", other),
+        };
+        // The variable of the comprehension does not clobber `x`
+        assert!(head.starts_with("%item."));
+        assert!(matches!(&cases[0].body.statements[0], Statement::If { .. }));
+        match &statements[statements.len() - 2..] {
+            [Statement::Assignment {
+                pattern: Pattern::Variable { name, .. },
+                value: Expr::Variable { name: list, .. },
+                ..
+            }, Statement::Return {
+                value: Expr::Variable { name: x, .. },
+                ..
+            }] => {
+                assert_eq!(name, "ys");
+                assert!(list.starts_with("%list."));
+                assert_eq!(x, "x");
+            }
+            other => panic!("Expected the assignment of the list, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_lower_map_comprehension() {
+        let lowered = lowered_value(
+            "fn main(xs: List<u24>) -> u24 { m = {x: x * x for x in xs}; return 0; }",
+        );
+        let cases = fold_cases(&lowered);
+
+        assert!(matches!(
+            &cases[0].body.statements[..],
+            [
+                Statement::Assignment {
+                    pattern: Pattern::MapAccess { .. },
+                    ..
+                },
+                Statement::Return { .. }
+            ]
+        ));
+        assert!(matches!(
+            &cases[1].body.statements[0],
+            Statement::Return { value: Expr::Map { entries, .. }, .. } if entries.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_lower_range_comprehension() {
+        let mut lowering = Lowering::new();
+        let mut program = Parser::new(
+            "fn main(n: u24) -> u24 { x = 7; m = {x: x + 1 for x in range(2, n) if x > 3}; return x; }",
+        )
+        .parse_program()
+        .unwrap();
+        lowering.lower_program(&mut program);
+        let statements = match &program.definitions[0] {
+            Definition::FunctionDef { body, .. } => &body.statements,
+            _ => panic!("Expected function definition"),
+        };
+
+        assert_eq!(lowering.lowered_comprehensions, 1);
+        match &statements[..] {
+            [_, Statement::Assignment { .. }, Statement::Assignment { .. }, Statement::Assignment {
+                value: Expr::Map { entries, .. },
+                ..
+            }, Statement::While { body, .. }, Statement::Assignment {
+                pattern: Pattern::Variable { name, .. },
+                value: Expr::Variable { name: map, .. },
+                ..
+            }, Statement::Return {
+                value: Expr::Variable { name: x, .. },
+                ..
+            }] => {
+                assert!(entries.is_empty());
+                assert_eq!(name, "m");
+                assert!(map.starts_with("%map."));
+                // The variable of the comprehension does not clobber `x`
+                assert_eq!(x, "x");
+                match &body.statements[1] {
+                    Statement::If { then_branch, .. } => assert!(matches!(
+                        &then_branch.statements[0],
+                        Statement::Assignment {
+                            pattern: Pattern::MapAccess { key, .. },
+                            ..
+                        } if matches!(&**key, Expr::Variable { name, .. } if name.starts_with("%index."))
+                    )),
+                    other => panic!("Expected filter, got {:?}", other),
+                }
+            }
+            other => panic!("Expected the loop filling the map, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_expand_guards() {
+        let mut lowering = Lowering::new();
+        let mut program = Parser::new(
+            r#"
+            storage { total: u24 }
+
+            guard at_least(amount: u24, min: u24) {
+                seen = amount;
+                require(seen >= min);
+                _;
+                total = total + seen;
+            }
+
+            guard positive(amount: u24) {
+                require(amount > 0);
+                _;
+            }
+
+            #[guard(positive(value), at_least(value, 10))]
+            fn deposit(value: u24) -> u24 {
+                seen = value * 2;
+                return seen;
+            }
+            "#,
+        )
+        .parse_program()
+        .unwrap();
+        lowering.lower_program(&mut program);
+
+        assert_eq!(lowering.expanded_guards, 2);
+        let body = match &program.definitions[3] {
+            Definition::FunctionDef { body, .. } => body,
+            _ => panic!("Expected function definition"),
+        };
+        let assigned: Vec<String> = body
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Assignment {
+                    pattern: Pattern::Variable { name, .. },
+                    ..
+                } => Some(name.to_string()),
+                _ => None,
+            })
+            .collect();
+
+        // The outer guard binds its argument first, the locals of the
+        // inner guard no longer clash with those of the function, and the
+        // result is stored before the code after the placeholder runs
+        assert_eq!(
+            assigned,
+            vec![
+                "%guard.1.amount",
+                "%guard.0.amount",
+                "%guard.0.min",
+                "%guard.0.seen",
+                "seen",
+                "%guard.0.result",
+                "total",
+            ]
+        );
+        assert!(matches!(
+            body.statements.last(),
+            Some(Statement::Return { value: Expr::Variable { name, .. }, .. }) if name == "%guard.0.result"
+        ));
+    }
+
+    #[test]
+    fn test_expand_lock() {
+        let mut lowering = Lowering::new();
+        let mut program = Parser::new(
+            r#"
+            guard positive(amount: u24) {
+                require(amount > 0);
+                _;
+            }
+
+            #[non_reentrant, guard(positive(value))]
+            fn deposit(value: u24) -> u24 {
+                return value;
+            }
+            "#,
+        )
+        .parse_program()
+        .unwrap();
+        lowering.lower_program(&mut program);
+
+        assert_eq!(lowering.locked_functions, 1);
+        let body = match &program.definitions[1] {
+            Definition::FunctionDef { body, .. } => body,
+            _ => panic!("Expected function definition"),
+        };
+        // The lock is taken before the guard runs and released before the
+        // stored result is returned
+        let statements = &body.statements;
+        assert!(matches!(
+            &statements[0],
+            Statement::Revert { kind: RevertKind::Require, condition: Some(Expr::BinaryOp { left, .. }), .. }
+                if matches!(left.as_ref(), Expr::Variable { name, .. } if name == REENTRANCY_LOCK)
+        ));
+        let assigned: Vec<&str> = statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Assignment {
+                    pattern: Pattern::Variable { name, .. },
+                    ..
+                } => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            assigned,
+            [
+                REENTRANCY_LOCK,
+                "%guard.0.amount",
+                "%lock.1.result",
+                REENTRANCY_LOCK
+            ]
+        );
+
+        // The lock gets a storage field of its own
+        assert!(matches!(
+            program.definitions.last(),
+            Some(Definition::StorageDef { fields, .. }) if fields[0].name == REENTRANCY_LOCK
+        ));
+    }
+
+    #[test]
+    fn test_expand_specification() {
+        let source = r#"
+            #[invariant(total <= 1000)]
+            storage {
+                total: u24,
+            }
+
+            #[requires(amount > 0)]
+            #[ensures(result == old(total) + amount)]
+            fn deposit(amount: u24) -> u24 {
+                total = total + amount;
+                return total;
+            }
+
+            #[view]
+            fn current() -> u24 {
+                return total;
+            }
+        "#;
+        let lowered = |specifications| {
+            let mut program = Parser::new(source).parse_program().unwrap();
+            Lowering::new()
+                .with_specifications(specifications)
+                .lower_program(&mut program);
+            program
+        };
+        let body = |program: &Program, index: usize| match &program.definitions[index] {
+            Definition::FunctionDef { body, .. } => body.statements.clone(),
+            _ => panic!("Expected function definition"),
+        };
+
+        let unchecked = lowered(false);
+        assert_eq!(body(&unchecked, 1).len(), 2);
+
+        let checked = lowered(true);
+        let statements = body(&checked, 1);
+        let checks: Vec<(RevertKind, String)> = statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Revert {
+                    kind,
+                    reason:
+                        Some(Expr::Literal {
+                            kind: LiteralKind::String(message),
+                            ..
+                        }),
+                    ..
+                } => Some((*kind, message.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            checks,
+            [
+                (
+                    RevertKind::Require,
+                    "precondition failed: deposit (line 7)".to_string()
+                ),
+                (
+                    RevertKind::Assert,
+                    "postcondition failed: deposit (line 8)".to_string()
+                ),
+                (RevertKind::Assert, "invariant failed (line 2)".to_string()),
+            ]
+        );
+        // The old total is captured after the precondition, before the body
+        assert!(matches!(
+            &statements[1],
+            Statement::Assignment { pattern: Pattern::Variable { name, .. }, .. }
+                if name == "%spec.0.old.0"
+        ));
+        assert!(matches!(statements.last(), Some(Statement::Return { .. })));
+
+        // Views cannot break the invariants
+        assert_eq!(body(&checked, 2).len(), 1);
+    }
+
+    #[test]
+    fn test_lower_nested_comprehensions() {
+        let mut lowering = Lowering::new();
+        let mut program = Parser::new(
+            "fn main(xs: List<u24>) -> u24 { ys = [y for y in [x for x in xs]]; return 0; }",
+        )
+        .parse_program()
+        .unwrap();
+        lowering.lower_program(&mut program);
+
+        assert_eq!(lowering.lowered_comprehensions, 2);
+    }
+
+    #[test]
+    fn test_expand_superpositions() {
+        let mut lowering = Lowering::new();
+        let mut program = Parser::new(
+            r#"
+            fn main(alice: u24, bob: u24, limit: u24) -> u24 {
+                emit Approval({alice, bob}, {0, limit});
+                balances[{alice, {bob, 7}}] = 1;
+                ok = {alice, bob} > 0 && {1, 2} > 0;
+                return 0;
+            }
+            "#,
+        )
+        .parse_program()
+        .unwrap();
+        lowering.lower_program(&mut program);
+        let statements = match &program.definitions[0] {
+            Definition::FunctionDef { body, .. } => &body.statements,
+            _ => panic!("Expected function definition"),
+        };
+
+        let value = |expr: &Expr| match expr {
+            Expr::Variable { name, .. } => name.to_string(),
+            Expr::Literal {
+                kind: LiteralKind::Uint(value),
+                ..
+            } => value.to_string(),
+            other => panic!("Expected variable or literal, got {:?}", other),
+        };
+        let emitted: Vec<Vec<String>> = statements[..4]
+            .iter()
+            .map(|statement| match statement {
+                Statement::Emit { args, .. } => args.iter().map(value).collect(),
+                other => panic!("Expected emit, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            emitted,
+            [
+                ["alice", "0"],
+                ["alice", "limit"],
+                ["bob", "0"],
+                ["bob", "limit"]
+            ]
+        );
+
+        // Nested superpositions are flattened
+        let keys: Vec<String> = statements[4..7]
+            .iter()
+            .map(|statement| match statement {
+                Statement::Assignment {
+                    pattern: Pattern::MapAccess { key, .. },
+                    ..
+                } => value(key),
+                other => panic!("Expected map write, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(keys, ["alice", "bob", "7"]);
+
+        // A local is not duplicated, the type checker rejects it
+        assert!(matches!(&statements[7], Statement::Assignment { .. }));
+        assert!(matches!(&statements[8], Statement::Return { .. }));
+        assert_eq!(lowering.expanded_superpositions, 2);
+    }
+}
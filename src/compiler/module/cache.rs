@@ -0,0 +1,274 @@
+//! On-disk cache of parsed, type checked and compiled modules
+//!
+//! Entries are keyed on the module's path and are only used while the
+//! compiler version, the hash of the module's source and the hashes of
+//! every module it imports all still match, so editing a module also
+//! invalidates the modules that depend on it. The CLI and the language
+//! server share the cache of the package a file belongs to.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::compiler::analyzer::lints::Warning;
+use crate::compiler::cfg::Cfg;
+use crate::compiler::codegen::encoder::Isa;
+use crate::compiler::codegen::risc_v::Instruction;
+use crate::compiler::module::ModuleError;
+use crate::compiler::parser::ast::Program;
+use crate::compiler::parser::parser::{ParseError, Parser};
+use crate::package::Workspace;
+
+/// Directory holding the cache, next to a package's `bend.toml`
+pub const CACHE_DIR: &str = ".bend-cache";
+
+/// Everything cached for one module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedModule {
+    pub compiler_version: String,
+    pub path: PathBuf,
+    pub source_hash: String,
+    /// Source hashes of every module imported directly or indirectly
+    pub dependencies: BTreeMap<PathBuf, String>,
+    /// The module as parsed, before `#[cfg]` removed anything
+    pub ast: Program,
+    /// What `type_checked`, `warnings` and `code` were found with
+    #[serde(default)]
+    pub cfg: Cfg,
+    /// Whether the module passed type checking
+    pub type_checked: bool,
+    /// Warnings reported while type checking
+    pub warnings: Vec<Warning>,
+    /// Code generated for the module when compiled as a contract
+    pub code: Option<CachedCode>,
+}
+
+/// Generated code, together with the options it depends on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedCode {
+    pub optimized: bool,
+    /// Whether specifications were checked, as debug builds do
+    #[serde(default)]
+    pub debug: bool,
+    #[serde(default)]
+    pub isa: Isa,
+    /// Whether a message dispatcher was generated, as contract builds do
+    #[serde(default)]
+    pub dispatcher: bool,
+    pub instructions: Vec<Instruction>,
+}
+
+impl CachedModule {
+    /// A fresh entry for a module that has only been parsed
+    pub fn new(path: &Path, source: &str, ast: Program) -> Self {
+        CachedModule {
+            compiler_version: crate::version().to_string(),
+            path: canonical(path),
+            source_hash: source_hash(source),
+            dependencies: BTreeMap::new(),
+            ast,
+            cfg: Cfg::default(),
+            type_checked: false,
+            warnings: Vec::new(),
+            code: None,
+        }
+    }
+
+    /// Forget what was found with another `#[cfg]` configuration than
+    /// `cfg`, keeping the syntax tree
+    pub fn configured_for(&mut self, cfg: &Cfg) {
+        if self.cfg != *cfg {
+            self.cfg = cfg.clone();
+            self.type_checked = false;
+            self.warnings.clear();
+            self.code = None;
+        }
+    }
+}
+
+/// A cache directory shared by everything compiling one package
+#[derive(Debug, Clone)]
+pub struct ModuleCache {
+    dir: PathBuf,
+}
+
+impl ModuleCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        ModuleCache {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The cache of the package containing `path`, or one next to the file
+    /// when it is not part of a package
+    pub fn for_path(path: &Path) -> Self {
+        let root = Workspace::find_root(path).unwrap_or_else(|| {
+            path.parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| PathBuf::from("."))
+        });
+        Self::new(root.join(CACHE_DIR))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The entry for `path` if it is still valid for `source`
+    pub fn get(&self, path: &Path, source: &str) -> Option<CachedModule> {
+        let entry = self.read(path)?;
+        let valid = entry.compiler_version == crate::version()
+            && entry.source_hash == source_hash(source)
+            && entry.dependencies.iter().all(|(dependency, hash)| {
+                fs::read_to_string(dependency).is_ok_and(|source| source_hash(&source) == *hash)
+            });
+        valid.then_some(entry)
+    }
+
+    /// Store an entry, replacing any previous one for the same module
+    pub fn insert(&self, entry: &CachedModule) -> Result<(), ModuleError> {
+        fs::create_dir_all(&self.dir).map_err(|e| ModuleError::IO(e.to_string()))?;
+        let json = serde_json::to_string(entry).map_err(|e| ModuleError::IO(e.to_string()))?;
+        fs::write(self.entry_path(&entry.path), json).map_err(|e| ModuleError::IO(e.to_string()))
+    }
+
+    /// Drop the entry for `path` and for every module that imports it,
+    /// returning the paths of the dropped modules
+    pub fn invalidate(&self, path: &Path) -> Vec<PathBuf> {
+        let key = canonical(path);
+        let mut dropped = Vec::new();
+        if fs::remove_file(self.entry_path(&key)).is_ok() {
+            dropped.push(key.clone());
+        }
+
+        for entry in self.entries() {
+            if entry.dependencies.contains_key(&key)
+                && fs::remove_file(self.entry_path(&entry.path)).is_ok()
+            {
+                dropped.push(entry.path);
+            }
+        }
+        dropped
+    }
+
+    /// Remove every entry
+    pub fn clear(&self) -> Result<(), ModuleError> {
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir).map_err(|e| ModuleError::IO(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Parse `source`, the contents of `path`, reusing the cached syntax
+    /// tree when there is one
+    pub fn parse(&self, path: &Path, source: &str) -> Result<Program, ParseError> {
+        if let Some(entry) = self.get(path, source) {
+            return Ok(entry.ast);
+        }
+
+        let program = Parser::new(source).parse_program()?;
+        // A cache that cannot be written only costs a reparse next time
+        let _ = self.insert(&CachedModule::new(path, source, program.clone()));
+        Ok(program)
+    }
+
+    fn read(&self, path: &Path) -> Option<CachedModule> {
+        let json = fs::read_to_string(self.entry_path(path)).ok()?;
+        // Entries written by another compiler version may not deserialize
+        serde_json::from_str(&json).ok()
+    }
+
+    fn entries(&self) -> Vec<CachedModule> {
+        let Ok(files) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        files
+            .filter_map(|file| file.ok())
+            .filter_map(|file| fs::read_to_string(file.path()).ok())
+            .filter_map(|json| serde_json::from_str(&json).ok())
+            .collect()
+    }
+
+    fn entry_path(&self, path: &Path) -> PathBuf {
+        let key = canonical(path);
+        let hash = hex::encode(Sha256::digest(key.to_string_lossy().as_bytes()));
+        self.dir.join(format!("{}.json", &hash[..16]))
+    }
+}
+
+/// Hash of a module's source
+pub fn source_hash(source: &str) -> String {
+    hex::encode(Sha256::digest(source.as_bytes()))
+}
+
+/// The path modules are recorded under, absolute when the file exists
+pub fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("bend_module_cache_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_cache_hits_until_source_changes() {
+        let dir = scratch_dir("source");
+        let path = dir.join("main.bend");
+        let source = "fn main() -> u24 {\n    return 1;\n}\n";
+        fs::write(&path, source).unwrap();
+
+        let cache = ModuleCache::new(dir.join(CACHE_DIR));
+        assert!(cache.get(&path, source).is_none());
+        let program = cache.parse(&path, source).unwrap();
+
+        let entry = cache.get(&path, source).unwrap();
+        assert_eq!(entry.ast, program);
+        assert!(!entry.type_checked);
+        assert!(cache
+            .get(&path, "fn main() -> u24 {\n    return 2;\n}\n")
+            .is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_dependency_changes_invalidate_dependents() {
+        let dir = scratch_dir("dependents");
+        let utils = dir.join("utils.bend");
+        let main = dir.join("main.bend");
+        fs::write(&utils, "pub fn one() -> u24 {\n    return 1;\n}\n").unwrap();
+        let source = "from utils import one;\n\nfn main() -> u24 {\n    return one();\n}\n";
+        fs::write(&main, source).unwrap();
+
+        let cache = ModuleCache::new(dir.join(CACHE_DIR));
+        let program = Parser::new(source).parse_program().unwrap();
+        let mut entry = CachedModule::new(&main, source, program);
+        entry.dependencies.insert(
+            canonical(&utils),
+            source_hash(&fs::read_to_string(&utils).unwrap()),
+        );
+        cache.insert(&entry).unwrap();
+        assert!(cache.get(&main, source).is_some());
+
+        fs::write(&utils, "pub fn one() -> u24 {\n    return 2;\n}\n").unwrap();
+        assert!(cache.get(&main, source).is_none());
+
+        cache.insert(&entry).unwrap();
+        assert_eq!(cache.invalidate(&utils), vec![canonical(&main)]);
+        assert!(cache.read(&main).is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -3,8 +3,10 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 use crate::compiler::module::ModuleError;
+use crate::compiler::object::{is_object, ObjectFile};
 use crate::compiler::parser::ast::*;
 use crate::compiler::parser::parser::Parser;
+use crate::stdlib::modules;
 
 /// Module loader for loading modules from files
 pub struct ModuleLoader {
@@ -61,10 +63,15 @@ impl ModuleLoader {
         result
     }
 
-    /// Parse a file into a program
-    fn parse_file(&self, path: &PathBuf) -> Result<Program, ModuleError> {
+    /// Parse a file into a program, the interface of an object file
+    fn parse_file(&self, path: &Path) -> Result<Program, ModuleError> {
+        if is_object(path) {
+            let object = ObjectFile::read(path).map_err(|e| ModuleError::IO(e.to_string()))?;
+            return Ok(object.interface_program());
+        }
+
         // Read the file
-        let source = fs::read_to_string(path).map_err(|e| ModuleError::IO(e.to_string()))?;
+        let source = read_source(path)?;
 
         // Parse the source
         let mut parser = Parser::new(&source);
@@ -76,6 +83,14 @@ impl ModuleLoader {
     }
 }
 
+/// Source of the module at `path`, which may be embedded in the compiler
+pub fn read_source(path: &Path) -> Result<String, ModuleError> {
+    match modules::embedded(path) {
+        Some(source) => Ok(source.to_string()),
+        None => fs::read_to_string(path).map_err(|e| ModuleError::IO(e.to_string())),
+    }
+}
+
 /// Extension trait for Parser to load modules
 trait ParserExt {
     /// Load a module from a file
@@ -1,15 +1,25 @@
+pub mod cache;
 pub mod loader;
 pub mod namespace;
 pub mod resolver;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use thiserror::Error;
 
+use self::cache::{canonical, source_hash, CachedModule, ModuleCache};
 use self::loader::ModuleLoader;
 use self::namespace::Namespace;
 use self::resolver::NameResolver;
+use crate::compiler::access;
+use crate::compiler::cfg::Cfg;
+use crate::compiler::intern::Symbol as Name;
+use crate::compiler::object::OBJECT_EXTENSION;
 use crate::compiler::parser::ast::*;
+use crate::compiler::parser::parser::Parser;
+use crate::stdlib::modules;
 
 #[derive(Error, Debug, Clone)]
 pub enum ModuleError {
@@ -28,6 +38,9 @@ pub enum ModuleError {
     #[error("Symbol {0} not found in module {1}")]
     SymbolNotFound(String, String),
 
+    #[error("Symbol {0} is private to module {1}")]
+    PrivateSymbol(String, String),
+
     #[error("Duplicate symbol: {0}")]
     DuplicateSymbol(String),
 
@@ -35,26 +48,35 @@ pub enum ModuleError {
     IO(String),
 }
 
+/// Index of a definition in the definitions of the syntax tree of its
+/// module
+pub type DefinitionId = usize;
+
 /// Represents a module in the Bend-PVM language
+///
+/// The syntax tree and the modules imported are shared rather than copied:
+/// a module imported by many others, such as those of the standard library
+/// the prelude brings in, is held once however often it is imported, and
+/// its namespace and symbols refer to its definitions by index.
 #[derive(Debug, Clone)]
 pub struct Module {
     /// Module name
-    pub name: String,
+    pub name: Name,
 
     /// Module path
     pub path: PathBuf,
 
     /// AST for the module
-    pub ast: Program,
+    pub ast: Arc<Program>,
 
     /// Namespace for the module
     pub namespace: Namespace,
 
     /// Imported modules
-    pub imports: HashMap<String, Module>,
+    pub imports: HashMap<Name, Arc<Module>>,
 
     /// Exported symbols
-    pub exports: HashMap<String, Symbol>,
+    pub exports: HashMap<Name, Symbol>,
 }
 
 /// Represents a symbol in a module
@@ -66,7 +88,7 @@ pub enum Symbol {
         name: String,
 
         /// Function definition
-        definition: Box<Definition>,
+        definition: DefinitionId,
     },
 
     /// Type symbol
@@ -75,7 +97,7 @@ pub enum Symbol {
         name: String,
 
         /// Type definition
-        definition: Box<Definition>,
+        definition: DefinitionId,
     },
 
     /// Object symbol
@@ -84,7 +106,7 @@ pub enum Symbol {
         name: String,
 
         /// Object definition
-        definition: Box<Definition>,
+        definition: DefinitionId,
     },
 
     /// Module symbol
@@ -93,7 +115,16 @@ pub enum Symbol {
         name: String,
 
         /// Module definition
-        definition: Box<Definition>,
+        definition: DefinitionId,
+    },
+
+    /// Event symbol
+    Event {
+        /// Event name
+        name: String,
+
+        /// Event definition
+        definition: DefinitionId,
     },
 
     /// Value symbol
@@ -106,6 +137,44 @@ pub enum Symbol {
     },
 }
 
+impl Module {
+    /// Definitions of everything the module imports followed by its own,
+    /// so the module can be type checked on its own
+    pub fn program(&self) -> Program {
+        let mut program = Program {
+            imports: Vec::new(),
+            definitions: Vec::new(),
+            location: self.ast.location.clone(),
+        };
+        self.collect_definitions(&mut HashSet::new(), &mut program, true);
+        program
+    }
+
+    /// The definitions of [`Module::program`] without those of the standard
+    /// library, which compiled contracts do not carry
+    pub fn program_without_std(&self) -> Program {
+        let mut program = Program {
+            imports: Vec::new(),
+            definitions: Vec::new(),
+            location: self.ast.location.clone(),
+        };
+        self.collect_definitions(&mut HashSet::new(), &mut program, false);
+        program
+    }
+
+    fn collect_definitions(&self, seen: &mut HashSet<Name>, program: &mut Program, std: bool) {
+        if !seen.insert(self.name) || (!std && modules::embedded(&self.path).is_some()) {
+            return;
+        }
+        for imported in self.imports.values() {
+            imported.collect_definitions(seen, program, std);
+        }
+        program
+            .definitions
+            .extend(self.ast.definitions.iter().cloned());
+    }
+}
+
 /// Module system for managing modules and namespaces
 pub struct ModuleSystem {
     /// Module loader
@@ -115,10 +184,19 @@ pub struct ModuleSystem {
     resolver: NameResolver,
 
     /// Loaded modules
-    modules: HashMap<String, Module>,
+    modules: HashMap<Name, Arc<Module>>,
 
     /// Search paths for modules
     search_paths: Vec<PathBuf>,
+
+    /// Cache of parsed modules, if enabled
+    cache: Option<ModuleCache>,
+
+    /// Whether modules implicitly import the standard library prelude
+    prelude: bool,
+
+    /// What the `#[cfg]` attributes of the modules are evaluated against
+    cfg: Cfg,
 }
 
 impl Default for ModuleSystem {
@@ -135,65 +213,179 @@ impl ModuleSystem {
             resolver: NameResolver::new(),
             modules: HashMap::new(),
             search_paths: Vec::new(),
+            cache: None,
+            prelude: true,
+            cfg: Cfg::default(),
         }
     }
 
+    /// Whether modules outside the standard library implicitly import its
+    /// prelude, which they do by default
+    pub fn set_prelude(&mut self, enabled: bool) {
+        self.prelude = enabled;
+    }
+
+    /// Reuse modules parsed by earlier builds from `cache`, and record
+    /// newly parsed modules in it
+    pub fn set_cache(&mut self, cache: ModuleCache) {
+        self.cache = Some(cache);
+    }
+
+    /// Leave out the definitions whose `#[cfg]` does not hold with `cfg`,
+    /// by default those of features and tests
+    pub fn set_cfg(&mut self, cfg: Cfg) {
+        self.cfg = cfg;
+    }
+
+    pub fn cfg(&self) -> &Cfg {
+        &self.cfg
+    }
+
+    /// The module cache, if enabled
+    pub fn cache(&self) -> Option<&ModuleCache> {
+        self.cache.as_ref()
+    }
+
     /// Add a search path
     pub fn add_search_path<P: AsRef<Path>>(&mut self, path: P) {
         self.search_paths.push(path.as_ref().to_path_buf());
     }
 
     /// Load a module
-    pub fn load_module<P: AsRef<Path>>(&mut self, path: P) -> Result<Module, ModuleError> {
+    pub fn load_module<P: AsRef<Path>>(&mut self, path: P) -> Result<Arc<Module>, ModuleError> {
         let path_buf = path.as_ref().to_path_buf();
-        let module_name = path_buf
-            .file_stem()
-            .ok_or_else(|| ModuleError::Generic("Invalid module path".to_string()))?
-            .to_string_lossy()
-            .to_string();
+        let module_name = module_name(&path_buf)?;
 
         // Check if the module is already loaded
         if let Some(module) = self.modules.get(&module_name) {
-            return Ok(module.clone());
+            return Ok(Arc::clone(module));
         }
 
-        // Load the module
-        let ast = self
-            .loader
-            .load_module(&path_buf)
+        // Load the module, reusing the cached syntax tree while the module
+        // and everything it imports are unchanged
+        let source = match &self.cache {
+            Some(_) => Some(loader::read_source(&path_buf)?),
+            None => None,
+        };
+        let cached = self
+            .cache
+            .as_ref()
+            .zip(source.as_deref())
+            .and_then(|(cache, source)| cache.get(&path_buf, source));
+        let parsed = match &cached {
+            Some(entry) => entry.ast.clone(),
+            None => self
+                .loader
+                .load_module(&path_buf)
+                .map_err(|e| ModuleError::LoadFailure(e.to_string()))?,
+        };
+
+        let ast = self.configure(parsed.clone())?;
+        let module = self.link_module(module_name, path_buf, ast)?;
+
+        // Record the freshly parsed module along with the sources it depends on
+        if let (Some(cache), Some(source), None) = (&self.cache, &source, &cached) {
+            let mut entry = CachedModule::new(&module.path, source, parsed);
+            record_dependencies(&module, &mut entry.dependencies);
+            // A cache that cannot be written only costs a reparse next time
+            let _ = cache.insert(&entry);
+        }
+
+        Ok(module)
+    }
+
+    /// Load a module from `source` instead of the file at `path`, such as
+    /// an editor buffer with unsaved changes. The modules it imports are
+    /// still read from disk, and nothing is cached for the module itself.
+    pub fn load_source<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        source: &str,
+    ) -> Result<Arc<Module>, ModuleError> {
+        let ast = Parser::new(source)
+            .parse_program()
             .map_err(|e| ModuleError::LoadFailure(e.to_string()))?;
+        self.load_program(path, ast)
+    }
 
-        // Create a namespace for the module
-        let namespace = Namespace::new(module_name.clone());
+    /// Load a module that is already parsed. The modules it imports are
+    /// read from disk unless they were added with [`Self::insert_module`].
+    pub fn load_program<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        program: Program,
+    ) -> Result<Arc<Module>, ModuleError> {
+        let path_buf = path.as_ref().to_path_buf();
+        let module_name = module_name(&path_buf)?;
+        let ast = self.configure(program)?;
+        self.link_module(module_name, path_buf, ast)
+    }
+
+    /// Use `module` for the imports of its name rather than loading it
+    pub fn insert_module(&mut self, module: Arc<Module>) {
+        self.modules.insert(module.name, module);
+    }
+
+    /// A module system finding modules the way this one does, with none
+    /// loaded yet and without the module cache
+    pub fn fork(&self) -> ModuleSystem {
+        ModuleSystem {
+            search_paths: self.search_paths.clone(),
+            prelude: self.prelude,
+            cfg: self.cfg.clone(),
+            ..ModuleSystem::new()
+        }
+    }
+
+    /// `program` without the definitions whose `#[cfg]` does not hold, with
+    /// its access control generated
+    fn configure(&self, mut program: Program) -> Result<Program, ModuleError> {
+        self.cfg
+            .configure(&mut program)
+            .map_err(|e| ModuleError::LoadFailure(e.to_string()))?;
+        access::expand(&mut program).map_err(|e| ModuleError::LoadFailure(e.to_string()))?;
+        Ok(program)
+    }
+
+    /// Load the imports of a parsed module and collect its definitions
+    fn link_module(
+        &mut self,
+        module_name: Name,
+        path_buf: PathBuf,
+        ast: Program,
+    ) -> Result<Arc<Module>, ModuleError> {
+        let ast = Arc::new(ast);
 
         // Create a placeholder module to handle circular dependencies
+        let empty = Arc::new(Program {
+            imports: Vec::new(),
+            definitions: Vec::new(),
+            location: Location {
+                line: 0,
+                column: 0,
+                start: 0,
+                end: 0,
+            },
+        });
         let placeholder_module = Module {
-            name: module_name.clone(),
+            name: module_name,
             path: path_buf.clone(),
-            ast: Program {
-                imports: Vec::new(),
-                definitions: Vec::new(),
-                location: Location {
-                    line: 0,
-                    column: 0,
-                    start: 0,
-                    end: 0,
-                },
-            },
-            namespace: namespace.clone(),
+            namespace: Namespace::new(module_name.to_string(), Arc::clone(&empty)),
+            ast: empty,
             imports: HashMap::new(),
             exports: HashMap::new(),
         };
 
         // Add the placeholder to the loaded modules
-        self.modules.insert(module_name.clone(), placeholder_module);
+        self.modules
+            .insert(module_name, Arc::new(placeholder_module));
 
-        // Create a new module
+        // Create a new module, with a namespace for its definitions
         let mut module = Module {
-            name: module_name.clone(),
+            name: module_name,
             path: path_buf,
+            namespace: Namespace::new(module_name.to_string(), Arc::clone(&ast)),
             ast,
-            namespace,
             imports: HashMap::new(),
             exports: HashMap::new(),
         };
@@ -205,14 +397,33 @@ impl ModuleSystem {
         self.process_definitions(&mut module)?;
 
         // Update the module in the loaded modules
-        self.modules.insert(module_name, module.clone());
+        let module = Arc::new(module);
+        self.modules.insert(module_name, Arc::clone(&module));
 
         Ok(module)
     }
 
+    /// The imports of the module at `path`, whose syntax tree is `ast`,
+    /// along with those of the prelude it implicitly imports
+    pub fn imports(&self, path: &Path, ast: &Program) -> Vec<Import> {
+        // The prelude comes first, except for modules it imports itself
+        let mut imports = Vec::new();
+        if self.prelude && modules::embedded(path).is_none() {
+            let explicit: Vec<&str> = ast.imports.iter().flat_map(import_paths).collect();
+            imports.extend(
+                modules::prelude_imports()
+                    .into_iter()
+                    .filter(|import| import_paths(import).all(|path| !explicit.contains(&path))),
+            );
+        }
+        imports.extend(ast.imports.iter().cloned());
+        imports
+    }
+
     /// Process imports in a module
     fn process_imports(&mut self, module: &mut Module) -> Result<(), ModuleError> {
-        for import in &module.ast.imports {
+        let imports = self.imports(&module.path, &module.ast);
+        for import in &imports {
             match import {
                 Import::FromImport {
                     path,
@@ -234,43 +445,50 @@ impl ModuleSystem {
                             for export_name in imported_module.exports.keys() {
                                 let alias = format!("{}/{}", imported_module.name, export_name);
                                 module.namespace.add_import(
-                                    export_name.clone(),
+                                    export_name.to_string(),
                                     alias.clone(),
-                                    imported_module.name.clone(),
+                                    imported_module.name.to_string(),
                                 )?;
                             }
                             continue;
                         } else {
-                            name.name.clone()
+                            name.name
                         };
 
                         // Get the symbol from the imported module
-                        let _symbol =
-                            imported_module.exports.get(&import_name).ok_or_else(|| {
-                                ModuleError::SymbolNotFound(
-                                    import_name.clone(),
-                                    imported_module.name.clone(),
-                                )
-                            })?;
+                        let key = import_name;
+                        if !imported_module.exports.contains_key(&key) {
+                            return Err(
+                                if imported_module.namespace.definitions.contains_key(&key) {
+                                    ModuleError::PrivateSymbol(
+                                        import_name.to_string(),
+                                        imported_module.name.to_string(),
+                                    )
+                                } else {
+                                    ModuleError::SymbolNotFound(
+                                        import_name.to_string(),
+                                        imported_module.name.to_string(),
+                                    )
+                                },
+                            );
+                        }
 
                         // Add the import to the namespace
                         let alias = if let Some(alias) = &name.alias {
-                            alias.clone()
+                            *alias
                         } else {
-                            import_name.clone()
+                            import_name
                         };
 
                         module.namespace.add_import(
-                            import_name,
-                            alias,
-                            imported_module.name.clone(),
+                            import_name.to_string(),
+                            alias.to_string(),
+                            imported_module.name.to_string(),
                         )?;
                     }
 
                     // Add the imported module
-                    module
-                        .imports
-                        .insert(imported_module.name.clone(), imported_module);
+                    module.imports.insert(imported_module.name, imported_module);
                 }
                 Import::DirectImport { names, location: _ } => {
                     for name in names {
@@ -286,16 +504,14 @@ impl ModuleSystem {
                         for export_name in imported_module.exports.keys() {
                             let alias = format!("{}/{}", imported_module.name, export_name);
                             module.namespace.add_import(
-                                export_name.clone(),
+                                export_name.to_string(),
                                 alias,
-                                imported_module.name.clone(),
+                                imported_module.name.to_string(),
                             )?;
                         }
 
                         // Add the imported module
-                        module
-                            .imports
-                            .insert(imported_module.name.clone(), imported_module);
+                        module.imports.insert(imported_module.name, imported_module);
                     }
                 }
             }
@@ -306,86 +522,139 @@ impl ModuleSystem {
 
     /// Process definitions in a module
     fn process_definitions(&mut self, module: &mut Module) -> Result<(), ModuleError> {
-        for definition in &module.ast.definitions {
+        for (id, definition) in module.ast.definitions.iter().enumerate() {
             match definition {
                 Definition::FunctionDef { name, .. } => {
-                    // Add the function to the namespace and exports
-                    module
-                        .namespace
-                        .add_definition(name.clone(), definition.clone())?;
+                    // Add the function to the namespace, and to the exports if public
+                    module.namespace.add_definition(name.to_string(), id)?;
 
-                    module.exports.insert(
-                        name.clone(),
+                    export(
+                        &mut module.exports,
+                        definition,
+                        name.to_string(),
                         Symbol::Function {
-                            name: name.clone(),
-                            definition: Box::new(definition.clone()),
+                            name: name.to_string(),
+                            definition: id,
                         },
                     );
                 }
                 Definition::TypeDef { name, .. } => {
-                    // Add the type to the namespace and exports
-                    module
-                        .namespace
-                        .add_definition(name.clone(), definition.clone())?;
+                    // Add the type to the namespace, and to the exports if public
+                    module.namespace.add_definition(name.to_string(), id)?;
 
-                    module.exports.insert(
-                        name.clone(),
+                    export(
+                        &mut module.exports,
+                        definition,
+                        name.to_string(),
+                        Symbol::Type {
+                            name: name.to_string(),
+                            definition: id,
+                        },
+                    );
+                }
+                Definition::ErrorDef { name, .. } => {
+                    // Add the error type to the namespace, and to the exports if public
+                    module.namespace.add_definition(name.to_string(), id)?;
+
+                    export(
+                        &mut module.exports,
+                        definition,
+                        name.to_string(),
                         Symbol::Type {
-                            name: name.clone(),
-                            definition: Box::new(definition.clone()),
+                            name: name.to_string(),
+                            definition: id,
                         },
                     );
                 }
                 Definition::ObjectDef { name, .. } => {
-                    // Add the object to the namespace and exports
-                    module
-                        .namespace
-                        .add_definition(name.clone(), definition.clone())?;
+                    // Add the object to the namespace, and to the exports if public
+                    module.namespace.add_definition(name.to_string(), id)?;
 
-                    module.exports.insert(
-                        name.clone(),
+                    export(
+                        &mut module.exports,
+                        definition,
+                        name.to_string(),
                         Symbol::Object {
-                            name: name.clone(),
-                            definition: Box::new(definition.clone()),
+                            name: name.to_string(),
+                            definition: id,
                         },
                     );
                 }
                 Definition::TypeAlias { name, .. } => {
-                    // Add the type alias to the namespace and exports
-                    module
-                        .namespace
-                        .add_definition(name.clone(), definition.clone())?;
+                    // Add the type alias to the namespace, and to the exports if public
+                    module.namespace.add_definition(name.to_string(), id)?;
 
-                    module.exports.insert(
-                        name.clone(),
+                    export(
+                        &mut module.exports,
+                        definition,
+                        name.to_string(),
                         Symbol::Type {
-                            name: name.clone(),
-                            definition: Box::new(definition.clone()),
+                            name: name.to_string(),
+                            definition: id,
                         },
                     );
                 }
                 Definition::Module { name, .. } => {
-                    // Add the module to the namespace and exports
-                    module
-                        .namespace
-                        .add_definition(name.clone(), definition.clone())?;
+                    // Add the module to the namespace, and to the exports if public
+                    module.namespace.add_definition(name.to_string(), id)?;
 
-                    module.exports.insert(
-                        name.clone(),
+                    export(
+                        &mut module.exports,
+                        definition,
+                        name.to_string(),
                         Symbol::Module {
-                            name: name.clone(),
-                            definition: Box::new(definition.clone()),
+                            name: name.to_string(),
+                            definition: id,
                         },
                     );
                 }
+                Definition::EventDef { name, .. } => {
+                    // Add the event to the namespace, and to the exports if public
+                    module.namespace.add_definition(name.to_string(), id)?;
+
+                    export(
+                        &mut module.exports,
+                        definition,
+                        name.to_string(),
+                        Symbol::Event {
+                            name: name.to_string(),
+                            definition: id,
+                        },
+                    );
+                }
+                Definition::StorageDef { .. } | Definition::ContractDef { .. } => {
+                    // Storage is private to the contract and never exported,
+                    // and the parser lowers contracts to it
+                }
+                Definition::InterfaceDef { name, .. } => {
+                    // Add the interface to the namespace, and to the exports if public
+                    module.namespace.add_definition(name.to_string(), id)?;
+
+                    export(
+                        &mut module.exports,
+                        definition,
+                        name.to_string(),
+                        Symbol::Type {
+                            name: name.to_string(),
+                            definition: id,
+                        },
+                    );
+                }
+                Definition::GuardDef { name, .. } => {
+                    // Guards are expanded into the functions of their own
+                    // module, so they are only added to the namespace
+                    module.namespace.add_definition(name.to_string(), id)?;
+                }
             }
         }
 
         Ok(())
     }
 
-    /// Resolve a module path to a file path
-    fn resolve_module_path(&self, module_name: &str) -> Option<PathBuf> {
+    /// Resolve a module path to a file path, its source or else the object
+    /// of a prebuilt library. Standard library modules resolve to paths in
+    /// [`modules::STD_DIR`], which do not exist on disk.
+    pub fn resolve_module_path(&self, module_name: &str) -> Option<PathBuf> {
         // First, check if the module name is a direct path
         let direct_path = PathBuf::from(module_name);
         if direct_path.exists() {
@@ -400,9 +669,14 @@ impl ModuleSystem {
             if path.exists() {
                 return Some(path);
             }
+            path.set_extension(OBJECT_EXTENSION);
+            if path.exists() {
+                return Some(path);
+            }
         }
 
-        None
+        // Fall back to the standard library
+        modules::module_path(module_name)
     }
 
     /// Resolve names in a module
@@ -413,12 +687,58 @@ impl ModuleSystem {
 
         // Add imported namespaces
         for (name, imported_module) in &module.imports {
-            resolver.add_import(name.clone(), imported_module.namespace.clone());
+            resolver.add_import(name.to_string(), imported_module.namespace.clone());
         }
 
         // Resolve names in the module
-        resolver.resolve_program(&mut module.ast)?;
+        resolver.resolve_program(Arc::make_mut(&mut module.ast))?;
 
         Ok(())
     }
 }
+
+/// Name of the module at `path`
+fn module_name(path: &Path) -> Result<Name, ModuleError> {
+    Ok(Name::intern(
+        &path
+            .file_stem()
+            .ok_or_else(|| ModuleError::Generic("Invalid module path".to_string()))?
+            .to_string_lossy(),
+    ))
+}
+
+/// Paths of the modules an import names
+pub fn import_paths(import: &Import) -> Box<dyn Iterator<Item = &str> + '_> {
+    match import {
+        Import::FromImport { path, .. } => Box::new(std::iter::once(path.as_str())),
+        Import::DirectImport { names, .. } => Box::new(names.iter().map(String::as_str)),
+    }
+}
+
+/// Record the source hash of every module `module` imports, directly or
+/// indirectly
+pub(crate) fn record_dependencies(module: &Module, dependencies: &mut BTreeMap<PathBuf, String>) {
+    for imported in module.imports.values() {
+        // The standard library only changes with the compiler version
+        let path = canonical(&imported.path);
+        if dependencies.contains_key(&path) || modules::embedded(&imported.path).is_some() {
+            continue;
+        }
+        if let Ok(source) = fs::read_to_string(&path) {
+            dependencies.insert(path, source_hash(&source));
+        }
+        record_dependencies(imported, dependencies);
+    }
+}
+
+/// Export a symbol if the definition it comes from is public
+fn export(
+    exports: &mut HashMap<Name, Symbol>,
+    definition: &Definition,
+    name: String,
+    symbol: Symbol,
+) {
+    if definition.visibility() == Visibility::Public {
+        exports.insert(Name::intern(&name), symbol);
+    }
+}
@@ -1,6 +1,8 @@
-use crate::compiler::module::ModuleError;
+use crate::compiler::intern::Symbol as Name;
+use crate::compiler::module::{DefinitionId, ModuleError};
 use crate::compiler::parser::ast::*;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 /// Represents an import in a namespace
 #[derive(Debug, Clone)]
@@ -21,36 +23,41 @@ pub struct Namespace {
     /// Namespace name (usually the module name)
     pub name: String,
 
-    /// Defined symbols (name -> definition)
-    pub definitions: HashMap<String, Definition>,
+    /// Syntax tree the definitions are in, shared with the module
+    pub program: Arc<Program>,
+
+    /// Defined symbols (name -> index in the definitions of the program)
+    pub definitions: HashMap<Name, DefinitionId>,
 
     /// Imported symbols (alias -> import)
-    pub imports: HashMap<String, Import>,
+    pub imports: HashMap<Name, Import>,
 }
 
 impl Namespace {
-    /// Create a new namespace
-    pub fn new(name: String) -> Self {
+    /// Create a new namespace for the definitions of `program`
+    pub fn new(name: String, program: Arc<Program>) -> Self {
         Namespace {
             name,
+            program,
             definitions: HashMap::new(),
             imports: HashMap::new(),
         }
     }
 
-    /// Add a definition to the namespace
+    /// Add a definition of the program to the namespace
     pub fn add_definition(
         &mut self,
         name: String,
-        definition: Definition,
+        definition: DefinitionId,
     ) -> Result<(), ModuleError> {
         // Check if the definition already exists
-        if self.definitions.contains_key(&name) {
+        let key = Name::intern(&name);
+        if self.definitions.contains_key(&key) {
             return Err(ModuleError::DuplicateSymbol(name));
         }
 
         // Add the definition
-        self.definitions.insert(name, definition);
+        self.definitions.insert(key, definition);
 
         Ok(())
     }
@@ -63,13 +70,14 @@ impl Namespace {
         source_module: String,
     ) -> Result<(), ModuleError> {
         // Check if the alias already exists
-        if self.imports.contains_key(&alias) {
+        let key = Name::intern(&alias);
+        if self.imports.contains_key(&key) {
             return Err(ModuleError::DuplicateSymbol(alias));
         }
 
         // Add the import
         self.imports.insert(
-            alias.clone(),
+            key,
             Import {
                 original_name,
                 alias,
@@ -82,13 +90,15 @@ impl Namespace {
 
     /// Look up a symbol in the namespace
     pub fn lookup(&self, name: &str) -> Option<&Definition> {
+        let name = Name::intern(name);
+
         // First, check local definitions
-        if let Some(definition) = self.definitions.get(name) {
-            return Some(definition);
+        if let Some(&id) = self.definitions.get(&name) {
+            return self.program.definitions.get(id);
         }
 
         // Then, check imports
-        if let Some(_import) = self.imports.get(name) {
+        if let Some(_import) = self.imports.get(&name) {
             // For imports, we just return None since we need to look up
             // the definition in the source module, which we don't have here
             return None;
@@ -99,16 +109,17 @@ impl Namespace {
 
     /// Check if a name is already defined in this namespace
     pub fn contains(&self, name: &str) -> bool {
-        self.definitions.contains_key(name) || self.imports.contains_key(name)
+        let name = Name::intern(name);
+        self.definitions.contains_key(&name) || self.imports.contains_key(&name)
     }
 
     /// Get all defined names in this namespace
     pub fn defined_names(&self) -> Vec<String> {
-        self.definitions.keys().cloned().collect()
+        self.definitions.keys().map(Name::to_string).collect()
     }
 
     /// Get all imported names in this namespace
     pub fn imported_names(&self) -> Vec<String> {
-        self.imports.keys().cloned().collect()
+        self.imports.keys().map(Name::to_string).collect()
     }
 }
@@ -79,12 +79,26 @@ impl NameResolver {
                         original_name
                     };
 
+                    // Only public definitions of the imported module are
+                    // visible here
+                    let private = self
+                        .namespaces
+                        .get(path)
+                        .and_then(|namespace| namespace.lookup(original_name))
+                        .is_some_and(|definition| definition.visibility() != Visibility::Public);
+                    if private {
+                        return Err(ModuleError::PrivateSymbol(
+                            original_name.to_string(),
+                            path.clone(),
+                        ));
+                    }
+
                     // Map the alias to the fully qualified name
                     let qualified_name = format!("{}/{}", path, original_name);
-                    self.name_mapping.insert(alias.clone(), qualified_name);
+                    self.name_mapping.insert(alias.to_string(), qualified_name);
 
                     // Add the name to the set of defined names
-                    self.defined_names.insert(alias.clone());
+                    self.defined_names.insert(alias.to_string());
                 }
             }
             Import::DirectImport { names, .. } => {
@@ -105,14 +119,14 @@ impl NameResolver {
                 name, params, body, ..
             } => {
                 // Add the function name to the set of defined names
-                self.defined_names.insert(name.clone());
+                self.defined_names.insert(name.to_string());
 
                 // Create a new scope for the function
                 let mut scope = self.defined_names.clone();
 
                 // Add parameters to the scope
                 for param in params {
-                    scope.insert(param.name.clone());
+                    scope.insert(param.name.to_string());
                 }
 
                 // Save the current scope
@@ -126,19 +140,68 @@ impl NameResolver {
             }
             Definition::TypeDef { name, .. } => {
                 // Add the type name to the set of defined names
-                self.defined_names.insert(name.clone());
+                self.defined_names.insert(name.to_string());
+            }
+            Definition::ErrorDef { name, .. } => {
+                // Add the error type name to the set of defined names
+                self.defined_names.insert(name.to_string());
             }
             Definition::ObjectDef { name, .. } => {
                 // Add the object name to the set of defined names
-                self.defined_names.insert(name.clone());
+                self.defined_names.insert(name.to_string());
             }
             Definition::TypeAlias { name, .. } => {
                 // Add the type alias name to the set of defined names
-                self.defined_names.insert(name.clone());
+                self.defined_names.insert(name.to_string());
             }
             Definition::Module { name, .. } => {
                 // Add the module name to the set of defined names
-                self.defined_names.insert(name.clone());
+                self.defined_names.insert(name.to_string());
+            }
+            Definition::EventDef { name, .. } => {
+                // Add the event name to the set of defined names
+                self.defined_names.insert(name.to_string());
+            }
+            Definition::InterfaceDef { name, .. } => {
+                // Add the interface name to the set of defined names
+                self.defined_names.insert(name.to_string());
+            }
+            Definition::StorageDef { fields, .. } => {
+                // Storage fields are visible in every function
+                for field in fields {
+                    self.defined_names.insert(field.name.to_string());
+                }
+            }
+            Definition::ContractDef { .. } => {
+                // Contracts are lowered to storage and definitions by the
+                // parser
+            }
+            Definition::GuardDef {
+                name,
+                params,
+                before,
+                after,
+                ..
+            } => {
+                // Add the guard name to the set of defined names
+                self.defined_names.insert(name.to_string());
+
+                // Both halves of the guard share one scope, so locals bound
+                // before the placeholder stay visible after it
+                let mut scope = self.defined_names.clone();
+                for param in params {
+                    scope.insert(param.name.to_string());
+                }
+
+                let old_scope = std::mem::replace(&mut self.defined_names, scope);
+                for statement in before
+                    .statements
+                    .iter_mut()
+                    .chain(after.statements.iter_mut())
+                {
+                    self.resolve_statement(statement)?;
+                }
+                self.defined_names = old_scope;
             }
         }
 
@@ -187,6 +250,24 @@ impl NameResolver {
                 self.resolve_block(then_branch)?;
                 self.resolve_block(else_branch)?;
             }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.resolve_expr(condition)?;
+                self.resolve_block(body)?;
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                ..
+            } => {
+                self.resolve_expr(start)?;
+                self.resolve_expr(end)?;
+                self.defined_names.insert(variable.to_string());
+                self.resolve_block(body)?;
+            }
             Statement::Expr { expr, .. } => {
                 self.resolve_expr(expr)?;
             }
@@ -202,10 +283,10 @@ impl NameResolver {
         match expr {
             Expr::Variable { name, .. } => {
                 // Resolve the variable name
-                if let Some(qualified_name) = self.name_mapping.get(name) {
+                if let Some(qualified_name) = self.name_mapping.get(name.as_str()) {
                     // Replace the name with the fully qualified name
-                    *name = qualified_name.clone();
-                } else if !self.defined_names.contains(name) {
+                    *name = Symbol::intern(qualified_name);
+                } else if !self.defined_names.contains(name.as_str()) {
                     // The name is not defined locally and is not imported
                     // Check if it's defined in the current namespace
                     let current_namespace = self
@@ -217,11 +298,11 @@ impl NameResolver {
                         if namespace.contains(name) {
                             // The name is defined in the current namespace
                             let qualified_name = format!("{}/{}", current_namespace, name);
-                            *name = qualified_name;
+                            *name = qualified_name.into();
                         } else {
                             // The name is not defined anywhere we can see
                             return Err(ModuleError::SymbolNotFound(
-                                name.clone(),
+                                name.to_string(),
                                 current_namespace.clone(),
                             ));
                         }
@@ -257,7 +338,7 @@ impl NameResolver {
 
                 // Add parameters to the scope
                 for param in params {
-                    scope.insert(param.name.clone());
+                    scope.insert(param.name.to_string());
                 }
 
                 // Save the current scope
@@ -302,7 +383,7 @@ impl NameResolver {
         match pattern {
             Pattern::Variable { name, .. } => {
                 // Add the variable name to the set of defined names
-                self.defined_names.insert(name.clone());
+                self.defined_names.insert(name.to_string());
             }
             Pattern::Tuple { elements, .. } => {
                 // Resolve names in tuple elements
@@ -0,0 +1,391 @@
+//! Object files for separate compilation
+//!
+//! An [`ObjectFile`] holds the code one module compiles to, split into a
+//! section per function, with the symbols the sections define and the
+//! relocations, the instructions referring to labels outside their own
+//! section. The [`Linker`](super::linker::Linker) merges objects into one
+//! program.
+//!
+//! An object also carries the interface of its module: the public
+//! definitions, with functions declared `#[extern]` and without their body.
+//! A module whose object sits where its source would, as `<name>.o` on a
+//! search path, can be imported like its source, so libraries can be
+//! distributed prebuilt.
+//!
+//! Objects are stored as JSON, like the module cache.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::compiler::analyzer::linearity::Linearity;
+use crate::compiler::codegen::encoder::Isa;
+use crate::compiler::codegen::risc_v::{CodegenError, Instruction, RiscVCodegen};
+use crate::compiler::lowering::Lowering;
+use crate::compiler::module::Module;
+use crate::compiler::optimizer::passes::{create_default_manager, OptimizationLevel};
+use crate::compiler::parser::ast::*;
+use crate::stdlib::modules;
+
+/// Extension of object files
+pub const OBJECT_EXTENSION: &str = "o";
+
+/// Version of the object format, bumped on incompatible changes
+pub const OBJECT_FORMAT: u32 = 1;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ObjectError {
+    #[error("Failed to compile module {module}: {message}")]
+    Compile { module: String, message: String },
+
+    /// Code the backend could not generate, kept to point at its source
+    #[error("Failed to compile module {module}: {error}")]
+    Codegen { module: String, error: CodegenError },
+
+    #[error("Invalid object file: {0}")]
+    Invalid(String),
+
+    #[error("Object format {found} is not supported, expected {expected}")]
+    Format { found: u32, expected: u32 },
+
+    #[error("IO error: {0}")]
+    Io(String),
+}
+
+/// Whether a symbol can be referred to from other objects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Binding {
+    /// Only visible within its object, renamed when linking
+    Local,
+    /// Shared by every object linked together
+    Global,
+}
+
+/// A label an object defines or refers to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectSymbol {
+    pub name: String,
+    pub binding: Binding,
+    /// Section defining the symbol, `None` when another object must
+    pub section: Option<usize>,
+}
+
+/// Code that is kept or left out of a linked program as a whole
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Section {
+    /// Label the section starts at, empty for code before any function
+    pub name: String,
+    pub code: Vec<Instruction>,
+}
+
+/// An instruction referring to a symbol outside its section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Relocation {
+    pub section: usize,
+    /// Index of the instruction in the section
+    pub instruction: usize,
+    pub symbol: String,
+}
+
+/// The compiled code of a module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectFile {
+    pub format: u32,
+    pub compiler_version: String,
+    /// Name of the module compiled
+    pub module: String,
+    pub isa: Isa,
+    pub sections: Vec<Section>,
+    pub symbols: Vec<ObjectSymbol>,
+    pub relocations: Vec<Relocation>,
+    /// Public definitions of the module, functions without their body
+    pub interface: Vec<Definition>,
+}
+
+impl ObjectFile {
+    /// Split `code` into sections at the labels of `functions`
+    ///
+    /// `binding` tells how the section of each function is bound, or
+    /// `None` for sections to leave out, such as those of functions other
+    /// objects define. Code before the first function is kept as a local
+    /// section.
+    pub fn from_code(
+        module: &str,
+        isa: Isa,
+        code: &[Instruction],
+        functions: &HashSet<String>,
+        binding: impl Fn(&str) -> Option<Binding>,
+    ) -> Self {
+        let mut sections = vec![(
+            Binding::Local,
+            Section {
+                name: String::new(),
+                code: Vec::new(),
+            },
+        )];
+        let mut kept = true;
+        for instruction in code {
+            if let Instruction::Label(label) = instruction {
+                if functions.contains(label) {
+                    let section_binding = binding(label);
+                    kept = section_binding.is_some();
+                    if let Some(section_binding) = section_binding {
+                        sections.push((
+                            section_binding,
+                            Section {
+                                name: label.clone(),
+                                code: Vec::new(),
+                            },
+                        ));
+                    }
+                }
+            }
+            if kept {
+                sections
+                    .last_mut()
+                    .unwrap()
+                    .1
+                    .code
+                    .push(instruction.clone());
+            }
+        }
+        if sections[0].1.code.is_empty() {
+            sections.remove(0);
+        }
+
+        let mut defined = HashMap::new();
+        for (index, (_, section)) in sections.iter().enumerate() {
+            for instruction in &section.code {
+                if let Instruction::Label(label) = instruction {
+                    defined.insert(label.clone(), index);
+                }
+            }
+        }
+
+        let mut symbols: Vec<ObjectSymbol> = sections
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, section))| !section.name.is_empty())
+            .map(|(index, (binding, section))| ObjectSymbol {
+                name: section.name.clone(),
+                binding: *binding,
+                section: Some(index),
+            })
+            .collect();
+        let mut relocations = Vec::new();
+        for (index, (_, section)) in sections.iter().enumerate() {
+            for (position, instruction) in section.code.iter().enumerate() {
+                let Some(target) = instruction.target() else {
+                    continue;
+                };
+                let target_section = defined.get(target).copied();
+                if target_section == Some(index) {
+                    continue;
+                }
+                relocations.push(Relocation {
+                    section: index,
+                    instruction: position,
+                    symbol: target.to_string(),
+                });
+                if !symbols.iter().any(|symbol| symbol.name == target) {
+                    // Labels inside other sections stay local, undefined
+                    // ones come from other objects
+                    symbols.push(ObjectSymbol {
+                        name: target.to_string(),
+                        binding: match target_section {
+                            Some(_) => Binding::Local,
+                            None => Binding::Global,
+                        },
+                        section: target_section,
+                    });
+                }
+            }
+        }
+
+        ObjectFile {
+            format: OBJECT_FORMAT,
+            compiler_version: crate::version().to_string(),
+            module: module.to_string(),
+            isa,
+            sections: sections.into_iter().map(|(_, section)| section).collect(),
+            symbols,
+            relocations,
+            interface: Vec::new(),
+        }
+    }
+
+    /// Compile `module` on its own, optimized at `level`, checking the
+    /// specifications of its functions with `specifications`
+    ///
+    /// The module is compiled with what it imports, keeping only the code
+    /// of its own functions and of those the compiler generated for it.
+    /// The standard library functions it and its imports use are linked
+    /// into it, local to its object.
+    /// Calls to imported functions are left to the linker. The entry
+    /// module of a contract is compiled with `dispatcher`, generating the
+    /// message dispatcher routing calls to the messages it links with.
+    pub fn compile(
+        module: &Module,
+        level: OptimizationLevel,
+        isa: Isa,
+        specifications: bool,
+        dispatcher: bool,
+    ) -> Result<Self, ObjectError> {
+        let failed = |message: String| ObjectError::Compile {
+            module: module.name.to_string(),
+            message,
+        };
+        let mut manager = create_default_manager();
+        manager.set_level(level);
+        let mut program = module.program_without_std();
+        program.definitions.extend(modules::link(&program));
+        Lowering::new()
+            .with_specifications(specifications)
+            .lower_program(&mut program);
+        let mut program = manager
+            .optimize(program)
+            .map_err(|e| failed(e.to_string()))?;
+        Linearity::new().lower_program(&mut program);
+        let mut generator = RiscVCodegen::new().with_isa(isa);
+        if dispatcher {
+            generator = generator.with_dispatcher();
+        }
+        let code = generator
+            .generate(&program)
+            .map_err(|error| ObjectError::Codegen {
+                module: module.name.to_string(),
+                error,
+            })?;
+
+        let own = function_labels(&module.ast);
+        let mut imported = HashSet::new();
+        for import in module.imports.values() {
+            collect_imported(import, &mut imported, &mut HashSet::new());
+        }
+        let mut object = Self::from_code(
+            &module.name,
+            isa,
+            &code,
+            &function_labels(&program),
+            |label| {
+                if own.contains(label) {
+                    Some(Binding::Global)
+                } else if imported.contains(label) {
+                    None
+                } else {
+                    Some(Binding::Local)
+                }
+            },
+        );
+        object.interface = interface(&module.ast);
+        Ok(object)
+    }
+
+    /// The symbols other objects must define
+    pub fn undefined(&self) -> impl Iterator<Item = &ObjectSymbol> {
+        self.symbols
+            .iter()
+            .filter(|symbol| symbol.section.is_none())
+    }
+
+    /// The interface as a program, to import the module from
+    pub fn interface_program(&self) -> Program {
+        Program {
+            imports: Vec::new(),
+            definitions: self.interface.clone(),
+            location: Location::default(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Objects only hold types serde_json can represent
+        serde_json::to_vec(self).unwrap()
+    }
+
+    /// Read an object, failing on formats this compiler cannot link
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ObjectError> {
+        let value: serde_json::Value =
+            serde_json::from_slice(bytes).map_err(|e| ObjectError::Invalid(e.to_string()))?;
+        let format = value.get("format").and_then(|format| format.as_u64());
+        if format != Some(OBJECT_FORMAT as u64) {
+            return Err(ObjectError::Format {
+                found: format.unwrap_or_default() as u32,
+                expected: OBJECT_FORMAT,
+            });
+        }
+        serde_json::from_value(value).map_err(|e| ObjectError::Invalid(e.to_string()))
+    }
+
+    pub fn read(path: &Path) -> Result<Self, ObjectError> {
+        let bytes =
+            fs::read(path).map_err(|e| ObjectError::Io(format!("{}: {}", path.display(), e)))?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), ObjectError> {
+        fs::write(path, self.to_bytes())
+            .map_err(|e| ObjectError::Io(format!("{}: {}", path.display(), e)))
+    }
+}
+
+/// Whether `path` names an object file
+pub fn is_object(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == OBJECT_EXTENSION)
+}
+
+/// Labels of the functions `program` defines
+fn function_labels(program: &Program) -> HashSet<String> {
+    program
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            Definition::FunctionDef { name, .. } => Some(RiscVCodegen::function_label(name)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Labels of the functions `module` and what it imports define, but for
+/// the standard library, which each object links on its own
+fn collect_imported(module: &Module, labels: &mut HashSet<String>, seen: &mut HashSet<String>) {
+    if !seen.insert(module.name.to_string()) || modules::embedded(&module.path).is_some() {
+        return;
+    }
+    labels.extend(function_labels(&module.ast));
+    for import in module.imports.values() {
+        collect_imported(import, labels, seen);
+    }
+}
+
+/// Attributes of a function its callers are checked against
+const CALLER_ATTRIBUTES: &[&str] = &["payable", "view", "pure", "selector", "deprecated"];
+
+/// The public definitions of `program`, functions declared `#[extern]`
+fn interface(program: &Program) -> Vec<Definition> {
+    program
+        .definitions
+        .iter()
+        .filter(|definition| definition.visibility() == Visibility::Public)
+        .cloned()
+        .map(|mut definition| {
+            if let Definition::FunctionDef {
+                body, attributes, ..
+            } = &mut definition
+            {
+                body.statements.clear();
+                attributes.retain(|attribute| CALLER_ATTRIBUTES.contains(&attribute.name.as_str()));
+                attributes.push(Attribute {
+                    name: "extern".into(),
+                    args: Vec::new(),
+                    location: body.location.clone(),
+                });
+            }
+            definition
+        })
+        .collect()
+}
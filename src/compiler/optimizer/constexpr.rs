@@ -0,0 +1,857 @@
+//! Compile-time evaluation of pure functions
+//!
+//! Calls to functions declared `#[pure]` whose arguments are all literals
+//! are run by a small interpreter and replaced by the literal they return,
+//! e.g. a lookup table indexed with a constant or the hash of a static
+//! string. `keccak256` of literal bytes is computed the same way.
+//!
+//! The interpreter covers integer and boolean arithmetic, locals, `if`,
+//! loops and calls to other pure functions. Arithmetic is checked as the
+//! generated code checks it, so a call that would revert, overflow or
+//! exceed its loop bound is left for run time, as is a call needing more
+//! than the step or depth limit.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::address::Hash;
+use crate::compiler::analyzer::attributes::{FunctionAttributes, Mutability};
+use crate::compiler::intern::Symbol;
+use crate::compiler::optimizer::passes::{OptimizationError, OptimizationPass, OptimizationResult};
+use crate::compiler::parser::ast::*;
+use crate::security::safe_math::CheckedInt;
+use crate::stdlib::crypto::CryptoFunctions;
+
+/// Most statements and expressions evaluated for a single call
+pub const DEFAULT_STEP_LIMIT: usize = 10_000;
+
+/// Deepest nesting of calls evaluated for a single call
+pub const DEFAULT_DEPTH_LIMIT: usize = 64;
+
+/// A value the interpreter computes
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    /// A word-sized integer, with the type the generated code checks it as
+    Int(i64, CheckedInt),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    Hash(Hash),
+}
+
+/// A function whose calls may be evaluated
+struct PureFunction {
+    params: Vec<Parameter>,
+    return_type: Type,
+    body: Block,
+}
+
+/// Compile-time evaluation pass
+pub struct ConstEvalPass {
+    functions: HashMap<Symbol, PureFunction>,
+    /// Whether `keccak256` is the builtin, no function shadowing it
+    builtin_hash: bool,
+    step_limit: usize,
+    depth_limit: usize,
+    /// Statistics
+    evaluated_calls: usize,
+}
+
+impl Default for ConstEvalPass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConstEvalPass {
+    pub fn new() -> Self {
+        ConstEvalPass {
+            functions: HashMap::new(),
+            builtin_hash: true,
+            step_limit: DEFAULT_STEP_LIMIT,
+            depth_limit: DEFAULT_DEPTH_LIMIT,
+            evaluated_calls: 0,
+        }
+    }
+
+    /// Give up on calls taking more than `steps` steps or nesting more
+    /// than `depth` calls
+    pub fn with_limits(mut self, steps: usize, depth: usize) -> Self {
+        self.step_limit = steps;
+        self.depth_limit = depth;
+        self
+    }
+
+    /// Number of calls replaced by their result so far
+    pub fn evaluated_calls(&self) -> usize {
+        self.evaluated_calls
+    }
+}
+
+impl OptimizationPass for ConstEvalPass {
+    fn name(&self) -> &'static str {
+        "constexpr"
+    }
+
+    fn description(&self) -> &'static str {
+        "Evaluates calls to pure functions with constant arguments at compile time"
+    }
+
+    fn run(&mut self, program: Program) -> Result<OptimizationResult, OptimizationError> {
+        self.collect_functions(&program);
+
+        let before = self.evaluated_calls;
+        let mut program = program;
+        for definition in &mut program.definitions {
+            self.fold_definition(definition);
+        }
+
+        if self.evaluated_calls > before {
+            Ok(OptimizationResult::Modified(program))
+        } else {
+            Ok(OptimizationResult::Unchanged(program))
+        }
+    }
+}
+
+impl ConstEvalPass {
+    /// Collect the functions declared `#[pure]` that run no checks of their
+    /// own and whose arithmetic is checked
+    fn collect_functions(&mut self, program: &Program) {
+        self.functions.clear();
+        self.builtin_hash = true;
+        for definition in &program.definitions {
+            if matches!(definition, Definition::FunctionDef { name, .. } if name == "keccak256") {
+                self.builtin_hash = false;
+            }
+            let Definition::FunctionDef {
+                name,
+                params,
+                return_type: Some(return_type),
+                body,
+                checked,
+                ..
+            } = definition
+            else {
+                continue;
+            };
+            let Ok(attributes) = FunctionAttributes::of(definition) else {
+                continue;
+            };
+            let plain = attributes.guards.is_empty()
+                && attributes.access.is_none()
+                && attributes.requires.is_empty()
+                && attributes.ensures.is_empty()
+                && !attributes.non_reentrant
+                && !attributes.external;
+            if attributes.mutability == Mutability::Pure && plain && *checked != Some(false) {
+                self.functions.insert(
+                    *name,
+                    PureFunction {
+                        params: params.clone(),
+                        return_type: return_type.clone(),
+                        body: body.clone(),
+                    },
+                );
+            }
+        }
+    }
+
+    fn fold_definition(&mut self, definition: &mut Definition) {
+        match definition {
+            Definition::FunctionDef { params, body, .. } => {
+                let mut bound = params.iter().map(|param| param.name).collect();
+                self.fold_block(body, &mut bound);
+            }
+            Definition::ObjectDef { functions, .. } => {
+                for function in functions {
+                    self.fold_definition(function);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Fold the calls in a block; `bound` holds the names of the locals
+    /// seen so far, which shadow functions
+    fn fold_block(&mut self, block: &mut Block, bound: &mut HashSet<Symbol>) {
+        for statement in &mut block.statements {
+            self.fold_statement(statement, bound);
+        }
+    }
+
+    fn fold_statement(&mut self, statement: &mut Statement, bound: &mut HashSet<Symbol>) {
+        match statement {
+            Statement::Assignment { pattern, value, .. } => {
+                self.fold_expr(value, bound);
+                if let Pattern::Variable { name, .. } = pattern {
+                    bound.insert(*name);
+                }
+            }
+            Statement::Use { name, value, .. } => {
+                self.fold_expr(value, bound);
+                bound.insert(*name);
+            }
+            Statement::InPlaceOp { value, .. }
+            | Statement::Return { value, .. }
+            | Statement::Open { value, .. }
+            | Statement::Expr { expr: value, .. } => self.fold_expr(value, bound),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.fold_expr(condition, bound);
+                self.fold_block(then_branch, bound);
+                self.fold_block(else_branch, bound);
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.fold_expr(condition, bound);
+                self.fold_block(body, bound);
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                ..
+            } => {
+                self.fold_expr(start, bound);
+                self.fold_expr(end, bound);
+                bound.insert(*variable);
+                self.fold_block(body, bound);
+            }
+            Statement::Switch { value, cases, .. } => {
+                self.fold_expr(value, bound);
+                for case in cases {
+                    self.fold_block(&mut case.body, bound);
+                }
+            }
+            Statement::Match { value, cases, .. } | Statement::Fold { value, cases, .. } => {
+                self.fold_expr(value, bound);
+                for case in cases {
+                    self.fold_block(&mut case.body, bound);
+                }
+            }
+            Statement::Bend {
+                initial_states,
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                for (name, expr) in initial_states {
+                    self.fold_expr(expr, bound);
+                    bound.insert(*name);
+                }
+                self.fold_expr(condition, bound);
+                self.fold_block(body, bound);
+                if let Some(else_body) = else_body {
+                    self.fold_block(else_body, bound);
+                }
+            }
+            Statement::With { body, .. } | Statement::Unchecked { body, .. } => {
+                self.fold_block(body, bound);
+            }
+            Statement::LocalDef { function_def, .. } => {
+                if let Definition::FunctionDef { name, .. } = &**function_def {
+                    bound.insert(*name);
+                }
+                self.fold_definition(function_def);
+            }
+            Statement::TryCatch {
+                try_block,
+                catch_blocks,
+                ..
+            } => {
+                self.fold_block(try_block, bound);
+                for catch in catch_blocks {
+                    self.fold_block(&mut catch.body, bound);
+                }
+            }
+            Statement::Emit { args, .. } => {
+                for arg in args {
+                    self.fold_expr(arg, bound);
+                }
+            }
+            Statement::Revert {
+                condition, reason, ..
+            } => {
+                for expr in condition.iter_mut().chain(reason) {
+                    self.fold_expr(expr, bound);
+                }
+            }
+        }
+    }
+
+    fn fold_expr(&mut self, expr: &mut Expr, bound: &HashSet<Symbol>) {
+        match expr {
+            Expr::Tuple { elements, .. }
+            | Expr::List { elements, .. }
+            | Expr::Array { elements, .. }
+            | Expr::Superposition { elements, .. } => {
+                for element in elements {
+                    self.fold_expr(element, bound);
+                }
+            }
+            Expr::Constructor {
+                args, named_args, ..
+            } => {
+                for arg in args.iter_mut().chain(named_args_in_order_mut(named_args)) {
+                    self.fold_expr(arg, bound);
+                }
+            }
+            Expr::FunctionCall {
+                function,
+                args,
+                named_args,
+                location,
+            } => {
+                for arg in args.iter_mut().chain(named_args_in_order_mut(named_args)) {
+                    self.fold_expr(arg, bound);
+                }
+                let Expr::Variable { name, .. } = &**function else {
+                    self.fold_expr(function, bound);
+                    return;
+                };
+                if bound.contains(name) || !named_args.is_empty() {
+                    return;
+                }
+                if let Some(result) = self.evaluate(name, args) {
+                    *expr = Expr::Literal {
+                        kind: result,
+                        location: location.clone(),
+                    };
+                    self.evaluated_calls += 1;
+                }
+            }
+            // Lambda parameters may shadow functions
+            Expr::Lambda { .. } | Expr::UnsccopedLambda { .. } => {}
+            Expr::UnaryOp { operand: body, .. }
+            | Expr::FieldAccess { object: body, .. }
+            | Expr::TreeLeaf { value: body, .. }
+            | Expr::Try { expr: body, .. } => self.fold_expr(body, bound),
+            Expr::BinaryOp { left, right, .. }
+            | Expr::TreeNode { left, right, .. }
+            | Expr::MapAccess {
+                map: left,
+                key: right,
+                ..
+            } => {
+                self.fold_expr(left, bound);
+                self.fold_expr(right, bound);
+            }
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.fold_expr(key, bound);
+                    self.fold_expr(value, bound);
+                }
+            }
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.fold_expr(condition, bound);
+                self.fold_expr(then_branch, bound);
+                self.fold_expr(else_branch, bound);
+            }
+            Expr::Block { block, .. } => self.fold_block(block, &mut bound.clone()),
+            Expr::InlineAsm { inputs, .. } => {
+                for (_, input) in inputs {
+                    self.fold_expr(input, bound);
+                }
+            }
+            Expr::ListComprehension { .. } | Expr::MapComprehension { .. } => {}
+            Expr::Variable { .. } | Expr::Literal { .. } | Expr::Eraser { .. } => {}
+        }
+    }
+
+    /// The literal a call of `name` with `args` returns, if every argument
+    /// is a literal and the call can be evaluated
+    fn evaluate(&self, name: &Symbol, args: &[Expr]) -> Option<LiteralKind> {
+        let builtin = name == "keccak256" && self.builtin_hash;
+        if !builtin && !self.functions.contains_key(name) {
+            return None;
+        }
+        let args = args
+            .iter()
+            .map(|arg| match arg {
+                Expr::Literal { kind, .. } => literal_value(kind),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let mut interpreter = Interpreter {
+            functions: &self.functions,
+            builtin_hash: self.builtin_hash,
+            steps: self.step_limit,
+            depth: self.depth_limit,
+        };
+        interpreter.call(name, args).and_then(value_literal)
+    }
+}
+
+/// What evaluating a statement leads to
+enum Flow {
+    Next,
+    Return(Value),
+}
+
+/// Evaluates calls to pure functions, with the steps and call depth left
+struct Interpreter<'a> {
+    functions: &'a HashMap<Symbol, PureFunction>,
+    builtin_hash: bool,
+    steps: usize,
+    depth: usize,
+}
+
+impl Interpreter<'_> {
+    /// Count a step, failing when none are left
+    fn step(&mut self) -> Option<()> {
+        self.steps = self.steps.checked_sub(1)?;
+        Some(())
+    }
+
+    fn call(&mut self, name: &Symbol, args: Vec<Value>) -> Option<Value> {
+        self.step()?;
+        let Some(function) = self.functions.get(name) else {
+            return match args.as_slice() {
+                [Value::Bytes(bytes)] if name == "keccak256" && self.builtin_hash => {
+                    Some(Value::Hash(CryptoFunctions::keccak256(bytes).into()))
+                }
+                _ => None,
+            };
+        };
+        if args.len() != function.params.len() {
+            return None;
+        }
+        let mut locals = HashMap::new();
+        for (param, arg) in function.params.iter().zip(args) {
+            locals.insert(param.name, conform(arg, &param.ty)?);
+        }
+
+        self.depth = self.depth.checked_sub(1)?;
+        let flow = self.block(&function.body, &mut locals);
+        self.depth += 1;
+        match flow? {
+            Flow::Return(value) => conform(value, &function.return_type),
+            Flow::Next => None,
+        }
+    }
+
+    fn block(&mut self, block: &Block, locals: &mut HashMap<Symbol, Value>) -> Option<Flow> {
+        for statement in &block.statements {
+            if let Flow::Return(value) = self.statement(statement, locals)? {
+                return Some(Flow::Return(value));
+            }
+        }
+        Some(Flow::Next)
+    }
+
+    fn statement(
+        &mut self,
+        statement: &Statement,
+        locals: &mut HashMap<Symbol, Value>,
+    ) -> Option<Flow> {
+        self.step()?;
+        match statement {
+            Statement::Assignment {
+                pattern: Pattern::Variable { name, .. },
+                value,
+                ..
+            }
+            | Statement::Use { name, value, .. } => {
+                let value = self.expr(value, locals)?;
+                locals.insert(*name, value);
+                Some(Flow::Next)
+            }
+            Statement::Return { value, .. } => Some(Flow::Return(self.expr(value, locals)?)),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => match self.condition(condition, locals)? {
+                true => self.block(then_branch, locals),
+                false => self.block(else_branch, locals),
+            },
+            Statement::While {
+                condition,
+                body,
+                bound,
+                ..
+            } => {
+                let mut iterations = 0;
+                while self.condition(condition, locals)? {
+                    iterations += 1;
+                    // Exceeding the bound reverts
+                    if bound.is_some_and(|bound| iterations > bound) {
+                        return None;
+                    }
+                    if let Flow::Return(value) = self.block(body, locals)? {
+                        return Some(Flow::Return(value));
+                    }
+                }
+                Some(Flow::Next)
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                bound,
+                ..
+            } => {
+                let (Value::Int(mut index, kind), Value::Int(end, _)) =
+                    (self.expr(start, locals)?, self.expr(end, locals)?)
+                else {
+                    return None;
+                };
+                let mut iterations = 0;
+                while index < end {
+                    iterations += 1;
+                    if bound.is_some_and(|bound| iterations > bound) {
+                        return None;
+                    }
+                    locals.insert(*variable, Value::Int(index, kind));
+                    if let Flow::Return(value) = self.block(body, locals)? {
+                        return Some(Flow::Return(value));
+                    }
+                    let Value::Int(current, _) = locals[variable] else {
+                        return None;
+                    };
+                    index = kind.check("add", current, 1).ok()?;
+                }
+                Some(Flow::Next)
+            }
+            _ => None,
+        }
+    }
+
+    fn condition(&mut self, expr: &Expr, locals: &HashMap<Symbol, Value>) -> Option<bool> {
+        match self.expr(expr, locals)? {
+            Value::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, locals: &HashMap<Symbol, Value>) -> Option<Value> {
+        self.step()?;
+        match expr {
+            Expr::Literal { kind, .. } => literal_value(kind),
+            Expr::Variable { name, .. } => locals.get(name).cloned(),
+            Expr::BinaryOp {
+                left,
+                operator: operator @ (BinaryOperator::And | BinaryOperator::Or),
+                right,
+                ..
+            } => match (self.expr(left, locals)?, operator) {
+                (Value::Bool(false), BinaryOperator::And) => Some(Value::Bool(false)),
+                (Value::Bool(true), BinaryOperator::Or) => Some(Value::Bool(true)),
+                (Value::Bool(_), _) => match self.expr(right, locals)? {
+                    Value::Bool(right) => Some(Value::Bool(right)),
+                    _ => None,
+                },
+                _ => None,
+            },
+            Expr::BinaryOp {
+                left,
+                operator,
+                right,
+                ..
+            } => {
+                let left = self.expr(left, locals)?;
+                let right = self.expr(right, locals)?;
+                binary(operator, left, right)
+            }
+            Expr::UnaryOp { operand, .. } => match self.expr(operand, locals)? {
+                Value::Bool(value) => Some(Value::Bool(!value)),
+                _ => None,
+            },
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => match self.condition(condition, locals)? {
+                true => self.expr(then_branch, locals),
+                false => self.expr(else_branch, locals),
+            },
+            Expr::FunctionCall {
+                function,
+                args,
+                named_args,
+                ..
+            } if named_args.is_empty() => {
+                let Expr::Variable { name, .. } = &**function else {
+                    return None;
+                };
+                if locals.contains_key(name) {
+                    return None;
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| self.expr(arg, locals))
+                    .collect::<Option<Vec<_>>>()?;
+                self.call(name, args)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The value of a literal the interpreter handles
+fn literal_value(kind: &LiteralKind) -> Option<Value> {
+    match kind {
+        LiteralKind::Uint(value) => Some(Value::Int(*value as i64, CheckedInt::U24)),
+        LiteralKind::Int(value) => Some(Value::Int(*value as i64, CheckedInt::I24)),
+        LiteralKind::Bool(value) => Some(Value::Bool(*value)),
+        LiteralKind::Bytes(bytes) => Some(Value::Bytes(bytes.clone())),
+        LiteralKind::Hash(hash) => Some(Value::Hash(*hash)),
+        _ => None,
+    }
+}
+
+/// The literal standing for a value
+fn value_literal(value: Value) -> Option<LiteralKind> {
+    match value {
+        Value::Int(value, CheckedInt::U24) => Some(LiteralKind::Uint(value as u32)),
+        Value::Int(value, CheckedInt::I24) => Some(LiteralKind::Int(value as i32)),
+        Value::Int(..) => None,
+        Value::Bool(value) => Some(LiteralKind::Bool(value)),
+        Value::Bytes(bytes) => Some(LiteralKind::Bytes(bytes)),
+        Value::Hash(hash) => Some(LiteralKind::Hash(hash)),
+    }
+}
+
+/// A value passed or returned as `ty`, which must hold it
+///
+/// Only 24-bit integers are evaluated: a literal stands for a `u24` or an
+/// `i24`, so replacing a 32-bit result would change how the code around
+/// it is checked.
+fn conform(value: Value, ty: &Type) -> Option<Value> {
+    let name = match ty {
+        Type::U24 { .. } => "u24",
+        Type::I24 { .. } => "i24",
+        Type::Named { name, params, .. } if params.is_empty() => name,
+        _ => return None,
+    };
+    match (value, name) {
+        (Value::Int(value, _), "u24" | "i24") => {
+            let kind = CheckedInt::from_type_name(name)?;
+            (kind.min()..=kind.max())
+                .contains(&value)
+                .then_some(Value::Int(value, kind))
+        }
+        (value @ Value::Bool(_), "Bool" | "bool")
+        | (value @ Value::Bytes(_), "Bytes")
+        | (value @ Value::Hash(_), "Hash") => Some(value),
+        _ => None,
+    }
+}
+
+/// The result of a binary operation, as the generated code computes it
+fn binary(operator: &BinaryOperator, left: Value, right: Value) -> Option<Value> {
+    use BinaryOperator::*;
+
+    let equal = match (&left, &right) {
+        (Value::Int(left, left_kind), Value::Int(right, right_kind)) => {
+            let kind = left_kind.combine(*right_kind);
+            let (left, right) = (*left, *right);
+            let result = match operator {
+                Add => kind.check("add", left, right).ok()?,
+                Sub => kind.check("sub", left, right).ok()?,
+                Mul => kind.check("mul", left, right).ok()?,
+                Div => kind.check("div", left, right).ok()?,
+                Mod => kind.check("mod", left, right).ok()?,
+                BitAnd => left & right,
+                BitOr => left | right,
+                BitXor => left ^ right,
+                _ => return compare(operator, left.cmp(&right)),
+            };
+            return (kind.min()..=kind.max())
+                .contains(&result)
+                .then_some(Value::Int(result, kind));
+        }
+        (Value::Bool(left), Value::Bool(right)) => left == right,
+        (Value::Bytes(left), Value::Bytes(right)) => left == right,
+        (Value::Hash(left), Value::Hash(right)) => left == right,
+        _ => return None,
+    };
+    // Only equality is defined on the other values
+    match operator {
+        Equal => Some(Value::Bool(equal)),
+        NotEqual => Some(Value::Bool(!equal)),
+        _ => None,
+    }
+}
+
+/// The result of a comparison whose operands compare as `ordering`
+fn compare(operator: &BinaryOperator, ordering: std::cmp::Ordering) -> Option<Value> {
+    use std::cmp::Ordering;
+
+    Some(Value::Bool(match operator {
+        BinaryOperator::Equal => ordering == Ordering::Equal,
+        BinaryOperator::NotEqual => ordering != Ordering::Equal,
+        BinaryOperator::Less => ordering == Ordering::Less,
+        BinaryOperator::LessEqual => ordering != Ordering::Greater,
+        BinaryOperator::Greater => ordering == Ordering::Greater,
+        BinaryOperator::GreaterEqual => ordering != Ordering::Less,
+        _ => return None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parser::Parser;
+
+    const FUNCTIONS: &str = r#"
+        #[pure]
+        fn factorial(n: u24) -> u24 {
+            result = 1;
+            while n > 1 {
+                result = result * n;
+                n = n - 1;
+            }
+            return result;
+        }
+
+        #[pure]
+        fn fib(n: u24) -> u24 {
+            if n < 2 {
+                return n;
+            } else {
+                return fib(n - 1) + fib(n - 2);
+            }
+        }
+
+        #[pure]
+        fn sum(end: u24) -> u24 {
+            total = 0;
+            for i in range(0, end) bound 8 {
+                total = total + i;
+            }
+            return total;
+        }
+
+        #[pure]
+        fn is_even(n: u24) -> Bool {
+            return n % 2 == 0;
+        }
+
+        fn impure(n: u24) -> u24 {
+            return n;
+        }
+    "#;
+
+    fn evaluate(pass: ConstEvalPass, body: &str) -> (Vec<Expr>, usize) {
+        let source = format!("{}\nfn main(a: u24) -> u24 {{\n{}\n}}", FUNCTIONS, body);
+        let program = Parser::new(&source).parse_program().unwrap();
+        let mut pass = pass;
+        let program = pass.run(program).unwrap().program();
+        let Some(Definition::FunctionDef { body, .. }) = program.definitions.last() else {
+            panic!("expected main");
+        };
+        let values = body
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Assignment { value, .. } | Statement::Return { value, .. } => {
+                    Some(value.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        (values, pass.evaluated_calls())
+    }
+
+    fn literal(expr: &Expr) -> Option<&LiteralKind> {
+        match expr {
+            Expr::Literal { kind, .. } => Some(kind),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_evaluates_pure_calls() {
+        let (values, evaluated) = evaluate(
+            ConstEvalPass::new(),
+            r#"
+            x = factorial(5);
+            y = fib(10) + a;
+            z = sum(sum(4));
+            even = is_even(factorial(4));
+            return x;
+            "#,
+        );
+
+        assert_eq!(evaluated, 6);
+        assert_eq!(literal(&values[0]), Some(&LiteralKind::Uint(120)));
+        match &values[1] {
+            Expr::BinaryOp { left, .. } => assert_eq!(literal(left), Some(&LiteralKind::Uint(55))),
+            other => panic!("expected an addition, found {:?}", other),
+        }
+        // sum(4) is 6, and sum(6) is 15
+        assert_eq!(literal(&values[2]), Some(&LiteralKind::Uint(15)));
+        assert_eq!(literal(&values[3]), Some(&LiteralKind::Bool(true)));
+    }
+
+    #[test]
+    fn test_leaves_calls_it_cannot_evaluate() {
+        let (values, evaluated) = evaluate(
+            ConstEvalPass::new(),
+            r#"
+            overflow = factorial(20);
+            past_bound = sum(9);
+            not_pure = impure(1);
+            not_constant = factorial(a);
+            return a;
+            "#,
+        );
+
+        assert_eq!(evaluated, 0);
+        assert!(values.iter().all(|value| literal(value).is_none()));
+
+        // fib(20) takes tens of thousands of steps
+        let (values, evaluated) = evaluate(ConstEvalPass::new(), "return fib(20);");
+        assert_eq!((evaluated, literal(&values[0])), (0, None));
+        let (values, evaluated) = evaluate(
+            ConstEvalPass::new().with_limits(usize::MAX, 4),
+            "return fib(6);",
+        );
+        assert_eq!((evaluated, literal(&values[0])), (0, None));
+    }
+
+    #[test]
+    fn test_hashes_literal_bytes() {
+        let source = r#"
+            #[pure]
+            fn transfer_topic() -> Hash {
+                return keccak256(hex"deadbeef");
+            }
+
+            fn main() -> Hash {
+                return transfer_topic();
+            }
+        "#;
+        let program = Parser::new(source).parse_program().unwrap();
+        let mut pass = ConstEvalPass::new();
+        let program = pass.run(program).unwrap().program();
+
+        let expected = Hash::from(CryptoFunctions::keccak256(&[0xde, 0xad, 0xbe, 0xef]));
+        for definition in &program.definitions {
+            let Definition::FunctionDef { body, .. } = definition else {
+                continue;
+            };
+            match body.statements.as_slice() {
+                [Statement::Return { value, .. }] => {
+                    assert_eq!(literal(value), Some(&LiteralKind::Hash(expected)))
+                }
+                other => panic!("expected a return, found {:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,782 @@
+//! Devirtualization of higher-order calls
+//!
+//! Code generation only emits direct calls, so a call through a
+//! function-typed parameter or a local holding a function cannot be
+//! compiled as written. When the function called is known statically this
+//! pass turns such calls into direct ones:
+//!
+//! - a local bound once to a function, `g = double`, is replaced by the
+//!   function, so `g(x)` becomes `double(x)`;
+//! - a call of a lambda, `(|y| y + 1)(x)`, is replaced by its body with
+//!   the arguments substituted, under the rules of the inlining pass;
+//! - a call passing known functions or closed lambdas to function-typed
+//!   parameters, `apply(double, x)`, calls a copy of the callee specialized
+//!   for them, `apply%double(x)`, in which those calls are direct.
+//!
+//! A function with function-typed parameters cannot be called from outside
+//! the contract, so once every call of it has been specialized it is
+//! removed.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::optimizer::inline::{evaluated_once, substitute};
+use crate::compiler::optimizer::passes::{OptimizationError, OptimizationPass, OptimizationResult};
+use crate::compiler::parser::ast::*;
+
+/// Devirtualization pass
+pub struct DevirtualizePass {
+    /// Functions of the program, before this run's specializations
+    functions: HashMap<Symbol, Definition>,
+    /// Specialized copies created in this run
+    specializations: Vec<Definition>,
+    /// Functions some call was specialized away from
+    specialized: HashSet<Symbol>,
+    /// Statistics
+    devirtualized_calls: usize,
+}
+
+impl Default for DevirtualizePass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DevirtualizePass {
+    pub fn new() -> Self {
+        DevirtualizePass {
+            functions: HashMap::new(),
+            specializations: Vec::new(),
+            specialized: HashSet::new(),
+            devirtualized_calls: 0,
+        }
+    }
+
+    /// Number of calls made direct so far
+    pub fn devirtualized_calls(&self) -> usize {
+        self.devirtualized_calls
+    }
+}
+
+impl OptimizationPass for DevirtualizePass {
+    fn name(&self) -> &'static str {
+        "devirtualize"
+    }
+
+    fn description(&self) -> &'static str {
+        "Turns calls through function values known at compile time into direct calls"
+    }
+
+    fn run(&mut self, program: Program) -> Result<OptimizationResult, OptimizationError> {
+        self.functions = program
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                Definition::FunctionDef { name, .. } => Some((*name, definition.clone())),
+                _ => None,
+            })
+            .collect();
+        self.specializations.clear();
+        self.specialized.clear();
+
+        let before = self.devirtualized_calls;
+        let mut program = program;
+        for definition in &mut program.definitions {
+            if let Definition::FunctionDef { params, body, .. } = definition {
+                self.devirtualize_body(params, body);
+            }
+        }
+        if self.devirtualized_calls == before {
+            return Ok(OptimizationResult::Unchanged(program));
+        }
+
+        program
+            .definitions
+            .append(&mut self.specializations.split_off(0));
+        self.remove_specialized(&mut program);
+        Ok(OptimizationResult::Modified(program))
+    }
+}
+
+/// A function value known at compile time
+#[derive(Debug, Clone)]
+enum Callee {
+    /// A function of the program
+    Function(Symbol),
+    /// A lambda referring to nothing but its parameters and functions
+    Lambda(Expr),
+}
+
+impl Callee {
+    /// The expression standing for the function value
+    fn value(&self, location: &Location) -> Expr {
+        match self {
+            Callee::Function(name) => Expr::Variable {
+                name: *name,
+                location: location.clone(),
+            },
+            Callee::Lambda(lambda) => lambda.clone(),
+        }
+    }
+
+    /// How the value shows in the names of specializations, lambdas
+    /// being told apart by where they are written
+    fn label(&self) -> String {
+        match self {
+            Callee::Function(name) => name.to_string(),
+            Callee::Lambda(lambda) => format!("lambda{}", lambda.location().start),
+        }
+    }
+}
+
+impl DevirtualizePass {
+    /// Devirtualize the calls in the body of a function
+    fn devirtualize_body(&mut self, params: &[Parameter], body: &mut Block) {
+        // Names bound in the function shadow the program's functions
+        let mut bound: HashSet<Symbol> = params.iter().map(|param| param.name).collect();
+        let mut assignments = HashMap::new();
+        walk_block(body, &mut |_| {}, &mut |statement| {
+            if let Some(name) = bound_name(statement) {
+                bound.insert(name);
+                *assignments.entry(name).or_insert(0) += 1;
+            }
+        });
+
+        // Locals bound once, to a known function value
+        let mut aliases = HashMap::new();
+        for statement in &body.statements {
+            if let Statement::Assignment {
+                pattern: Pattern::Variable { name, .. },
+                value,
+                ..
+            } = statement
+            {
+                let once =
+                    assignments.get(name) == Some(&1) && !params.iter().any(|p| &p.name == name);
+                if let Some(callee) = self.callee(value, &bound).filter(|_| once) {
+                    aliases.insert(*name, callee);
+                }
+            }
+        }
+
+        let mut devirtualized = 0;
+        walk_block(
+            body,
+            &mut |expr| match expr {
+                Expr::Variable { name, location } => {
+                    if let Some(callee) = aliases.get(name) {
+                        *expr = callee.value(location);
+                    }
+                }
+                Expr::FunctionCall { .. } => {
+                    if let Some(call) = self.devirtualize_call(expr, &bound) {
+                        *expr = call;
+                        devirtualized += 1;
+                    }
+                }
+                _ => {}
+            },
+            &mut |_| {},
+        );
+
+        // The aliases are no longer used
+        body.statements.retain(|statement| {
+            !matches!(statement, Statement::Assignment {
+                pattern: Pattern::Variable { name, .. },
+                ..
+            } if aliases.contains_key(name))
+        });
+        self.devirtualized_calls += devirtualized + aliases.len();
+    }
+
+    /// The function value `expr` stands for, if known at compile time
+    fn callee(&self, expr: &Expr, bound: &HashSet<Symbol>) -> Option<Callee> {
+        match expr {
+            Expr::Variable { name, .. } if !bound.contains(name) => self
+                .functions
+                .contains_key(name)
+                .then_some(Callee::Function(*name)),
+            Expr::Lambda { params, body, .. } => {
+                let params: HashSet<&str> = params.iter().map(|p| p.name.as_str()).collect();
+                let mut closed = true;
+                visit(body, &mut |expr| {
+                    if let Expr::Variable { name, .. } = expr {
+                        let root = name.split('.').next().unwrap_or(name);
+                        closed &= params.contains(root)
+                            || self.functions.contains_key(&Symbol::intern(root));
+                    }
+                });
+                closed.then(|| Callee::Lambda(expr.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// The direct call replacing a call, if it can be made direct
+    fn devirtualize_call(&mut self, call: &Expr, bound: &HashSet<Symbol>) -> Option<Expr> {
+        let Expr::FunctionCall {
+            function,
+            args,
+            named_args,
+            location,
+        } = call
+        else {
+            return None;
+        };
+        if !named_args.is_empty() {
+            return None;
+        }
+        match &**function {
+            Expr::Lambda { .. } => apply_lambda(function, args),
+            Expr::Variable { name, .. } if !bound.contains(name) => {
+                self.specialize(name, args, bound, location)
+            }
+            _ => None,
+        }
+    }
+
+    /// Call a copy of `name` specialized for the known functions among
+    /// `args`, if any are passed to function-typed parameters
+    fn specialize(
+        &mut self,
+        name: &Symbol,
+        args: &[Expr],
+        bound: &HashSet<Symbol>,
+        location: &Location,
+    ) -> Option<Expr> {
+        let Some(Definition::FunctionDef { params, .. }) = self.functions.get(name) else {
+            return None;
+        };
+        if params.len() != args.len() {
+            return None;
+        }
+        let known: Vec<Option<Callee>> = params
+            .iter()
+            .zip(args)
+            .map(|(param, arg)| match param.ty {
+                Type::Function { .. } => self.callee(arg, bound),
+                _ => None,
+            })
+            .collect();
+        if known.iter().all(Option::is_none) {
+            return None;
+        }
+
+        // Unknown values of function-typed parameters show as `_`
+        let labels: Vec<String> = params
+            .iter()
+            .zip(&known)
+            .filter(|(param, _)| matches!(param.ty, Type::Function { .. }))
+            .map(|(_, callee)| callee.as_ref().map_or("_".to_string(), Callee::label))
+            .collect();
+        let specialized = format!("{}%{}", name, labels.join("%"));
+        let exists = self.functions.contains_key(&Symbol::intern(&specialized))
+            || self
+                .specializations
+                .iter()
+                .any(|definition| matches!(definition, Definition::FunctionDef { name, .. } if *name == specialized));
+        if !exists {
+            let definition = self.specialized_copy(name, &specialized, &known)?;
+            self.specializations.push(definition);
+        }
+        self.specialized.insert(*name);
+
+        let args = args
+            .iter()
+            .zip(&known)
+            .filter(|(_, callee)| callee.is_none())
+            .map(|(arg, _)| arg.clone())
+            .collect();
+        Some(Expr::FunctionCall {
+            function: Box::new(Expr::Variable {
+                name: specialized.into(),
+                location: location.clone(),
+            }),
+            args,
+            named_args: HashMap::new(),
+            location: location.clone(),
+        })
+    }
+
+    /// A copy of the function `name` with the parameters of the known
+    /// function values replaced by them, if every use of those parameters
+    /// can be replaced
+    fn specialized_copy(
+        &self,
+        name: &Symbol,
+        specialized: &str,
+        known: &[Option<Callee>],
+    ) -> Option<Definition> {
+        let Some(Definition::FunctionDef {
+            params,
+            return_type,
+            body,
+            checked,
+            attributes,
+            location,
+            ..
+        }) = self.functions.get(name)
+        else {
+            return None;
+        };
+        let replaced: HashMap<&str, &Callee> = params
+            .iter()
+            .zip(known)
+            .filter_map(|(param, callee)| Some((param.name.as_str(), callee.as_ref()?)))
+            .collect();
+
+        let mut body = body.clone();
+        let (mut applied, mut assigned) = (true, false);
+        walk_block(
+            &mut body,
+            &mut |expr| match expr {
+                Expr::Variable { name, location } => {
+                    if let Some(callee) = replaced.get(name.as_str()) {
+                        *expr = callee.value(location);
+                    }
+                }
+                Expr::FunctionCall { function, args, .. }
+                    if matches!(**function, Expr::Lambda { .. }) =>
+                {
+                    match apply_lambda(function, args) {
+                        Some(applied) => *expr = applied,
+                        None => applied = false,
+                    }
+                }
+                _ => {}
+            },
+            &mut |statement| {
+                // A parameter assigned to cannot be replaced
+                assigned |=
+                    bound_name(statement).is_some_and(|name| replaced.contains_key(name.as_str()));
+            },
+        );
+        if !applied || assigned {
+            return None;
+        }
+
+        Some(Definition::FunctionDef {
+            name: specialized.into(),
+            params: params
+                .iter()
+                .zip(known)
+                .filter(|(_, callee)| callee.is_none())
+                .map(|(param, _)| param.clone())
+                .collect(),
+            return_type: return_type.clone(),
+            body,
+            checked: *checked,
+            // Selectors and guards belong to the original
+            attributes: attributes
+                .iter()
+                .filter(|attribute| attribute.name == "pure" || attribute.name == "view")
+                .cloned()
+                .collect(),
+            visibility: Visibility::Private,
+            location: location.clone(),
+        })
+    }
+
+    /// Remove the functions whose calls were all specialized
+    fn remove_specialized(&self, program: &mut Program) {
+        let mut referenced = HashSet::new();
+        let mut functions: Vec<&mut Definition> = program.definitions.iter_mut().collect();
+        while let Some(definition) = functions.pop() {
+            match definition {
+                Definition::FunctionDef { body, .. } => walk_block(
+                    body,
+                    &mut |expr| {
+                        if let Expr::Variable { name, .. } = expr {
+                            referenced.insert(*name);
+                        }
+                    },
+                    &mut |_| {},
+                ),
+                Definition::ObjectDef {
+                    functions: methods, ..
+                } => functions.extend(methods.iter_mut()),
+                _ => {}
+            }
+        }
+        program.definitions.retain(|definition| match definition {
+            Definition::FunctionDef { name, .. } => {
+                !self.specialized.contains(name) || referenced.contains(name)
+            }
+            _ => true,
+        });
+    }
+}
+
+/// The body of a called lambda with the arguments substituted, if that
+/// neither repeats, drops nor reorders any work
+fn apply_lambda(lambda: &Expr, args: &[Expr]) -> Option<Expr> {
+    let Expr::Lambda { params, body, .. } = lambda else {
+        return None;
+    };
+    if params.len() != args.len() {
+        return None;
+    }
+    let params: Vec<Symbol> = params.iter().map(|param| param.name).collect();
+    let mut computed = args
+        .iter()
+        .zip(&params)
+        .filter(|(arg, _)| !matches!(arg, Expr::Variable { .. } | Expr::Literal { .. }));
+    if let Some((_, param)) = computed.next() {
+        if computed.next().is_some() || !evaluated_once(body, param) {
+            return None;
+        }
+    }
+    let bindings = params.iter().cloned().zip(args.iter().cloned()).collect();
+    substitute(body, &params, &bindings)
+}
+
+/// The local a statement binds, if it binds one
+fn bound_name(statement: &Statement) -> Option<Symbol> {
+    match statement {
+        Statement::Assignment {
+            pattern: Pattern::Variable { name, .. },
+            ..
+        }
+        | Statement::Use { name, .. }
+        | Statement::InPlaceOp { target: name, .. }
+        | Statement::For { variable: name, .. } => Some(*name),
+        _ => None,
+    }
+}
+
+/// Visit an expression and its subexpressions, outside of lambdas
+fn visit(expr: &Expr, f: &mut dyn FnMut(&Expr)) {
+    let mut expr = expr.clone();
+    walk_expr(&mut expr, &mut |expr| f(expr), &mut |_| {});
+}
+
+/// Walk the statements of a block and their expressions, calling `on_expr`
+/// on each expression after its subexpressions
+///
+/// Lambdas and comprehensions bind names of their own, so their bodies are
+/// not walked.
+fn walk_block(
+    block: &mut Block,
+    on_expr: &mut dyn FnMut(&mut Expr),
+    on_statement: &mut dyn FnMut(&Statement),
+) {
+    for statement in &mut block.statements {
+        walk_statement(statement, on_expr, on_statement);
+    }
+}
+
+fn walk_statement(
+    statement: &mut Statement,
+    on_expr: &mut dyn FnMut(&mut Expr),
+    on_statement: &mut dyn FnMut(&Statement),
+) {
+    on_statement(statement);
+    match statement {
+        Statement::Assignment { value, .. }
+        | Statement::Use { value, .. }
+        | Statement::InPlaceOp { value, .. }
+        | Statement::Return { value, .. }
+        | Statement::Open { value, .. }
+        | Statement::Expr { expr: value, .. } => walk_expr(value, on_expr, on_statement),
+        Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            walk_expr(condition, on_expr, on_statement);
+            walk_block(then_branch, on_expr, on_statement);
+            walk_block(else_branch, on_expr, on_statement);
+        }
+        Statement::While {
+            condition, body, ..
+        } => {
+            walk_expr(condition, on_expr, on_statement);
+            walk_block(body, on_expr, on_statement);
+        }
+        Statement::For {
+            start, end, body, ..
+        } => {
+            walk_expr(start, on_expr, on_statement);
+            walk_expr(end, on_expr, on_statement);
+            walk_block(body, on_expr, on_statement);
+        }
+        Statement::Switch { value, cases, .. } => {
+            walk_expr(value, on_expr, on_statement);
+            for case in cases {
+                walk_block(&mut case.body, on_expr, on_statement);
+            }
+        }
+        Statement::Match { value, cases, .. } | Statement::Fold { value, cases, .. } => {
+            walk_expr(value, on_expr, on_statement);
+            for case in cases {
+                walk_block(&mut case.body, on_expr, on_statement);
+            }
+        }
+        Statement::Bend {
+            initial_states,
+            condition,
+            body,
+            else_body,
+            ..
+        } => {
+            for (_, expr) in initial_states {
+                walk_expr(expr, on_expr, on_statement);
+            }
+            walk_expr(condition, on_expr, on_statement);
+            walk_block(body, on_expr, on_statement);
+            if let Some(else_body) = else_body {
+                walk_block(else_body, on_expr, on_statement);
+            }
+        }
+        Statement::With { body, .. } | Statement::Unchecked { body, .. } => {
+            walk_block(body, on_expr, on_statement);
+        }
+        Statement::LocalDef { .. } => {}
+        Statement::TryCatch {
+            try_block,
+            catch_blocks,
+            ..
+        } => {
+            walk_block(try_block, on_expr, on_statement);
+            for catch in catch_blocks {
+                walk_block(&mut catch.body, on_expr, on_statement);
+            }
+        }
+        Statement::Emit { args, .. } => {
+            for arg in args {
+                walk_expr(arg, on_expr, on_statement);
+            }
+        }
+        Statement::Revert {
+            condition, reason, ..
+        } => {
+            for expr in condition.iter_mut().chain(reason) {
+                walk_expr(expr, on_expr, on_statement);
+            }
+        }
+    }
+}
+
+fn walk_expr(
+    expr: &mut Expr,
+    on_expr: &mut dyn FnMut(&mut Expr),
+    on_statement: &mut dyn FnMut(&Statement),
+) {
+    match expr {
+        Expr::Tuple { elements, .. }
+        | Expr::List { elements, .. }
+        | Expr::Array { elements, .. }
+        | Expr::Superposition { elements, .. } => {
+            for element in elements {
+                walk_expr(element, on_expr, on_statement);
+            }
+        }
+        Expr::Constructor {
+            args, named_args, ..
+        }
+        | Expr::FunctionCall {
+            args, named_args, ..
+        } => {
+            for arg in args.iter_mut().chain(named_args_in_order_mut(named_args)) {
+                walk_expr(arg, on_expr, on_statement);
+            }
+        }
+        Expr::UnaryOp { operand: body, .. }
+        | Expr::FieldAccess { object: body, .. }
+        | Expr::TreeLeaf { value: body, .. }
+        | Expr::Try { expr: body, .. } => walk_expr(body, on_expr, on_statement),
+        Expr::BinaryOp { left, right, .. }
+        | Expr::TreeNode { left, right, .. }
+        | Expr::MapAccess {
+            map: left,
+            key: right,
+            ..
+        } => {
+            walk_expr(left, on_expr, on_statement);
+            walk_expr(right, on_expr, on_statement);
+        }
+        Expr::Map { entries, .. } => {
+            for (key, value) in entries {
+                walk_expr(key, on_expr, on_statement);
+                walk_expr(value, on_expr, on_statement);
+            }
+        }
+        Expr::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            walk_expr(condition, on_expr, on_statement);
+            walk_expr(then_branch, on_expr, on_statement);
+            walk_expr(else_branch, on_expr, on_statement);
+        }
+        Expr::Block { block, .. } => walk_block(block, on_expr, on_statement),
+        Expr::InlineAsm { inputs, .. } => {
+            for (_, input) in inputs {
+                walk_expr(input, on_expr, on_statement);
+            }
+        }
+        Expr::Lambda { .. }
+        | Expr::UnsccopedLambda { .. }
+        | Expr::ListComprehension { .. }
+        | Expr::MapComprehension { .. }
+        | Expr::Variable { .. }
+        | Expr::Literal { .. }
+        | Expr::Eraser { .. } => {}
+    }
+    // The callee last, so a call sees what it calls once rewritten
+    if let Expr::FunctionCall { function, .. } = expr {
+        walk_expr(function, on_expr, on_statement);
+    }
+    on_expr(expr);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parser::Parser;
+
+    fn devirtualize(source: &str) -> (Program, usize) {
+        let mut program = Parser::new(source).parse_program().unwrap();
+        let mut pass = DevirtualizePass::new();
+        // Specialized copies are devirtualized in the next run
+        loop {
+            match pass.run(program).unwrap() {
+                OptimizationResult::Modified(modified) => program = modified,
+                OptimizationResult::Unchanged(unchanged) => {
+                    return (unchanged, pass.devirtualized_calls())
+                }
+            }
+        }
+    }
+
+    fn function<'a>(program: &'a Program, function: &str) -> &'a Block {
+        program
+            .definitions
+            .iter()
+            .find_map(|definition| match definition {
+                Definition::FunctionDef { name, body, .. } if name == function => Some(body),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("no function {}", function))
+    }
+
+    fn callees(block: &Block) -> Vec<String> {
+        let mut block = block.clone();
+        let mut callees = Vec::new();
+        walk_block(
+            &mut block,
+            &mut |expr| {
+                if let Expr::FunctionCall { function, .. } = expr {
+                    match &**function {
+                        Expr::Variable { name, .. } => callees.push(name.to_string()),
+                        other => callees.push(format!("{:?}", other)),
+                    }
+                }
+            },
+            &mut |_| {},
+        );
+        callees
+    }
+
+    const HIGHER_ORDER: &str = r#"
+        fn double(x: u24) -> u24 {
+            return x * 2;
+        }
+
+        fn apply(f: u24 -> u24, x: u24) -> u24 {
+            return f(x);
+        }
+
+        fn twice(f: u24 -> u24, x: u24) -> u24 {
+            return apply(f, apply(f, x));
+        }
+
+        fn main(a: u24) -> u24 {
+            g = double;
+            return apply(double, a) + g(a) + twice(g, a);
+        }
+    "#;
+
+    #[test]
+    fn test_specializes_known_functions() {
+        let (program, devirtualized) = devirtualize(HIGHER_ORDER);
+
+        assert!(devirtualized >= 4);
+        let main = function(&program, "main");
+        assert_eq!(main.statements.len(), 1);
+        assert_eq!(callees(main), ["apply%double", "double", "twice%double"]);
+        assert_eq!(callees(function(&program, "apply%double")), ["double"]);
+        assert_eq!(
+            callees(function(&program, "twice%double")),
+            ["apply%double", "apply%double"]
+        );
+        // Nothing calls the generic versions any more
+        assert!(!program.definitions.iter().any(|definition| matches!(
+            definition,
+            Definition::FunctionDef { name, .. } if name == "apply" || name == "twice"
+        )));
+
+        let options = crate::CompilerOptions::default();
+        assert!(crate::compile_to_artifacts("higher_order", HIGHER_ORDER, &options).is_ok());
+    }
+
+    #[test]
+    fn test_applies_closed_lambdas() {
+        let (program, _) = devirtualize(
+            r#"
+            fn apply(f: u24 -> u24, x: u24) -> u24 {
+                return f(x);
+            }
+
+            fn main(a: u24) -> u24 {
+                h = |y: u24| y + 1;
+                return h(a) + apply(|z: u24| z * 3, a) + apply(h, a);
+            }
+            "#,
+        );
+
+        // `h(a)` is now `a + 1`, and each lambda got its own copy of apply
+        let main = function(&program, "main");
+        let callees = callees(main);
+        assert_eq!(callees.len(), 2);
+        assert!(callees.iter().all(|name| name.starts_with("apply%lambda")));
+        assert_ne!(callees[0], callees[1]);
+        match &main.statements[..] {
+            [Statement::Return {
+                value: Expr::BinaryOp { left, .. },
+                ..
+            }] => assert!(matches!(
+                &**left,
+                Expr::BinaryOp { left, .. } if matches!(**left, Expr::BinaryOp { .. })
+            )),
+            other => panic!("expected a sum, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_leaves_unknown_callees() {
+        let source = r#"
+            fn double(x: u24) -> u24 {
+                return x * 2;
+            }
+
+            fn apply(f: u24 -> u24, x: u24) -> u24 {
+                return f(x);
+            }
+
+            fn main(a: u24) -> u24 {
+                g = double;
+                g = apply;
+                n = 1;
+                return apply(|y: u24| y + n, a) + g(a);
+            }
+        "#;
+        let (program, devirtualized) = devirtualize(source);
+
+        // `g` is bound twice, and the lambda refers to a local
+        assert_eq!(devirtualized, 0);
+        assert_eq!(program, Parser::new(source).parse_program().unwrap());
+    }
+}
@@ -14,97 +14,45 @@ impl FloatCombPass {
         FloatCombPass
     }
 
-    /// Optimize a block by combining floating-point operations
-    fn optimize_block(&self, block: &Block) -> (Block, bool) {
-        let mut new_statements = Vec::new();
+    /// Optimize a block by combining floating-point operations, in place
+    fn optimize_block(&self, block: &mut Block) -> bool {
         let mut modified = false;
 
-        for statement in &block.statements {
-            let (optimized_statement, stmt_modified) = self.optimize_statement(statement);
-            new_statements.push(optimized_statement);
-            modified = modified || stmt_modified;
+        for statement in &mut block.statements {
+            modified |= self.optimize_statement(statement);
         }
 
-        (
-            Block {
-                statements: new_statements,
-                location: block.location.clone(),
-            },
-            modified,
-        )
+        modified
     }
 
-    /// Optimize a statement by combining floating-point operations
-    fn optimize_statement(&self, statement: &Statement) -> (Statement, bool) {
+    /// Optimize a statement by combining floating-point operations, in place
+    fn optimize_statement(&self, statement: &mut Statement) -> bool {
         match statement {
-            Statement::Assignment {
-                pattern,
-                value,
-                location,
-            } => {
-                let (optimized_expr, expr_modified) = self.optimize_expr(value);
-
-                (
-                    Statement::Assignment {
-                        pattern: pattern.clone(),
-                        value: optimized_expr,
-                        location: location.clone(),
-                    },
-                    expr_modified,
-                )
-            }
-            Statement::Return { value, location } => {
-                let (optimized_expr, expr_modified) = self.optimize_expr(value);
-
-                (
-                    Statement::Return {
-                        value: optimized_expr,
-                        location: location.clone(),
-                    },
-                    expr_modified,
-                )
+            Statement::Assignment { value, .. } | Statement::Return { value, .. } => {
+                self.optimize_expr(value)
             }
             Statement::If {
                 condition,
                 then_branch,
                 else_branch,
-                location,
+                ..
             } => {
-                let (optimized_condition, condition_modified) = self.optimize_expr(condition);
-                let (optimized_then, then_modified) = self.optimize_block(then_branch);
-                let (optimized_else, else_modified) = self.optimize_block(else_branch);
-
-                let modified = condition_modified || then_modified || else_modified;
-
-                (
-                    Statement::If {
-                        condition: optimized_condition,
-                        then_branch: optimized_then,
-                        else_branch: optimized_else,
-                        location: location.clone(),
-                    },
-                    modified,
-                )
-            }
-            Statement::Expr { expr, location } => {
-                let (optimized_expr, expr_modified) = self.optimize_expr(expr);
+                let condition_modified = self.optimize_expr(condition);
+                let then_modified = self.optimize_block(then_branch);
+                let else_modified = self.optimize_block(else_branch);
 
-                (
-                    Statement::Expr {
-                        expr: optimized_expr,
-                        location: location.clone(),
-                    },
-                    expr_modified,
-                )
+                condition_modified || then_modified || else_modified
             }
+            Statement::Expr { expr, .. } => self.optimize_expr(expr),
             // For other statement types, implement optimization logic
             // This is a simplified implementation
-            _ => (statement.clone(), false),
+            _ => false,
         }
     }
 
-    /// Optimize an expression by combining floating-point operations
-    fn optimize_expr(&self, expr: &Expr) -> (Expr, bool) {
+    /// Optimize an expression by combining floating-point operations, in
+    /// place
+    fn optimize_expr(&self, expr: &mut Expr) -> bool {
         match expr {
             Expr::BinaryOp {
                 left,
@@ -112,77 +60,46 @@ impl FloatCombPass {
                 right,
                 location,
             } => {
-                let (optimized_left, left_modified) = self.optimize_expr(left);
-                let (optimized_right, right_modified) = self.optimize_expr(right);
+                let left_modified = self.optimize_expr(left);
+                let right_modified = self.optimize_expr(right);
 
-                // Try to fold constants
-                if let Some(folded) =
-                    self.fold_constants(&optimized_left, operator, &optimized_right, location)
-                {
-                    return (folded, true);
+                // Try to fold constants, then algebraic simplifications
+                let combined = self
+                    .fold_constants(left, operator, right, location)
+                    .or_else(|| self.simplify_algebra(left, operator, right, location));
+                if let Some(combined) = combined {
+                    *expr = combined;
+                    return true;
                 }
 
-                // Try algebraic simplifications
-                if let Some(simplified) =
-                    self.simplify_algebra(&optimized_left, operator, &optimized_right, location)
-                {
-                    return (simplified, true);
-                }
-
-                let modified = left_modified || right_modified;
-
-                (
-                    Expr::BinaryOp {
-                        left: Box::new(optimized_left),
-                        operator: operator.clone(),
-                        right: Box::new(optimized_right),
-                        location: location.clone(),
-                    },
-                    modified,
-                )
+                left_modified || right_modified
             }
             Expr::FunctionCall {
                 function,
                 args,
                 named_args,
-                location,
+                ..
             } => {
-                let (optimized_function, function_modified) = self.optimize_expr(function);
-
-                let mut optimized_args = Vec::new();
-                let mut args_modified = false;
+                let mut modified = self.optimize_expr(function);
 
-                for arg in args {
-                    let (optimized_arg, arg_modified) = self.optimize_expr(arg);
-                    optimized_args.push(optimized_arg);
-                    args_modified = args_modified || arg_modified;
+                for arg in args.iter_mut().chain(named_args.values_mut()) {
+                    modified |= self.optimize_expr(arg);
                 }
 
-                let mut optimized_named_args = named_args.clone();
-                let mut named_args_modified = false;
+                modified
+            }
+            Expr::Superposition { elements, .. } => {
+                // Each alternative folds on its own, they are never combined
+                let mut modified = false;
 
-                for (name, arg) in named_args {
-                    let (optimized_arg, arg_modified) = self.optimize_expr(arg);
-                    if arg_modified {
-                        optimized_named_args.insert(name.clone(), optimized_arg);
-                        named_args_modified = true;
-                    }
+                for element in elements {
+                    modified |= self.optimize_expr(element);
                 }
 
-                let modified = function_modified || args_modified || named_args_modified;
-
-                (
-                    Expr::FunctionCall {
-                        function: Box::new(optimized_function),
-                        args: optimized_args,
-                        named_args: optimized_named_args.clone(),
-                        location: location.clone(),
-                    },
-                    modified,
-                )
+                modified
             }
             // For other expression types, no optimization needed
-            _ => (expr.clone(), false),
+            _ => false,
         }
     }
 
@@ -522,44 +439,19 @@ impl OptimizationPass for FloatCombPass {
 
     fn run(&mut self, program: Program) -> Result<OptimizationResult, OptimizationError> {
         let mut modified = false;
-        let mut new_definitions = Vec::new();
+        let mut program = program;
 
-        // Optimize each definition
-        for definition in &program.definitions {
-            match definition {
-                Definition::FunctionDef {
-                    name,
-                    params,
-                    return_type,
-                    body,
-                    checked,
-                    location,
-                } => {
-                    // Optimize the function body
-                    let (optimized_body, body_modified) = self.optimize_block(body);
-                    modified = modified || body_modified;
-
-                    new_definitions.push(Definition::FunctionDef {
-                        name: name.clone(),
-                        params: params.clone(),
-                        return_type: return_type.clone(),
-                        body: optimized_body,
-                        checked: *checked,
-                        location: location.clone(),
-                    });
-                }
-                // Other definition types don't need optimization
-                _ => new_definitions.push(definition.clone()),
+        // Optimize each function body in place; other definition types
+        // don't need optimization
+        for definition in &mut program.definitions {
+            if let Definition::FunctionDef { body, .. } = definition {
+                modified |= self.optimize_block(body);
             }
         }
 
         // Return the result
         if modified {
-            Ok(OptimizationResult::Modified(Program {
-                imports: program.imports.clone(),
-                definitions: new_definitions,
-                location: program.location.clone(),
-            }))
+            Ok(OptimizationResult::Modified(program))
         } else {
             Ok(OptimizationResult::Unchanged(program))
         }
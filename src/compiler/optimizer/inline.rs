@@ -1,18 +1,19 @@
-// FUNCTION INLINING OPTIMIZATION - MINIMAL VERSION
-// This is a simplified version that compiles with the current AST structure
+// FUNCTION INLINING OPTIMIZATION
+//
+// Calls to functions marked `#[inline]` or `#[inline(always)]` whose body is
+// a single `return` are replaced by the returned expression, with the
+// arguments substituted for the parameters. Functions marked
+// `#[inline(never)]` are never inlined.
 
+use crate::compiler::analyzer::attributes::{FunctionAttributes, InlineHint};
 use crate::compiler::optimizer::passes::{OptimizationError, OptimizationResult};
 use crate::compiler::parser::ast::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 /// Function inlining optimization pass
 pub struct InlinePass {
-    /// Function definitions indexed by name
-    functions: HashMap<String, Definition>,
-    /// Maximum inline depth to prevent infinite recursion
-    max_depth: usize,
-    /// Current inline depth
-    current_depth: usize,
+    /// Inlinable functions: parameter names and the returned expression
+    functions: HashMap<Symbol, (Vec<Symbol>, Expr)>,
     /// Statistics
     inlined_calls: usize,
 }
@@ -27,11 +28,14 @@ impl InlinePass {
     pub fn new() -> Self {
         InlinePass {
             functions: HashMap::new(),
-            max_depth: 5,
-            current_depth: 0,
             inlined_calls: 0,
         }
     }
+
+    /// Number of calls inlined so far
+    pub fn inlined_calls(&self) -> usize {
+        self.inlined_calls
+    }
 }
 
 impl crate::compiler::optimizer::passes::OptimizationPass for InlinePass {
@@ -40,39 +44,597 @@ impl crate::compiler::optimizer::passes::OptimizationPass for InlinePass {
     }
 
     fn description(&self) -> &'static str {
-        "Inlines small functions to reduce call overhead"
+        "Inlines functions marked #[inline] to reduce call overhead"
     }
 
     fn run(&mut self, program: Program) -> Result<OptimizationResult, OptimizationError> {
-        // Collect all function definitions
-        for def in &program.definitions {
-            if let Definition::FunctionDef { name, .. } = def {
-                self.functions.insert(name.clone(), def.clone());
+        self.collect_functions(&program);
+        if self.functions.is_empty() {
+            return Ok(OptimizationResult::Unchanged(program));
+        }
+
+        let before = self.inlined_calls;
+        let mut program = program;
+        for definition in &mut program.definitions {
+            self.inline_definition(definition);
+        }
+
+        if self.inlined_calls > before {
+            Ok(OptimizationResult::Modified(program))
+        } else {
+            Ok(OptimizationResult::Unchanged(program))
+        }
+    }
+}
+
+impl InlinePass {
+    /// Collect the functions whose calls can be inlined
+    fn collect_functions(&mut self, program: &Program) {
+        self.functions.clear();
+        for definition in &program.definitions {
+            let Definition::FunctionDef {
+                name, params, body, ..
+            } = definition
+            else {
+                continue;
+            };
+            let hint = FunctionAttributes::of(definition)
+                .ok()
+                .and_then(|attributes| attributes.inline);
+            if hint != Some(InlineHint::Always) {
+                continue;
+            }
+            let [Statement::Return { value, .. }] = body.statements.as_slice() else {
+                continue;
+            };
+            let params: Vec<Symbol> = params.iter().map(|param| param.name).collect();
+            if substitute(value, &params, &HashMap::new()).is_some() {
+                self.functions.insert(*name, (params, value.clone()));
             }
         }
 
-        // Inline functions in program
-        let inlined_definitions: Vec<Definition> = program
-            .definitions
+        // Recursive functions would be expanded forever
+        let calls: HashMap<Symbol, HashSet<Symbol>> = self
+            .functions
             .iter()
-            .map(|def| self.inline_definition(def))
+            .map(|(name, (_, value))| {
+                let mut callees = HashSet::new();
+                called_functions(value, &mut callees);
+                (*name, callees)
+            })
             .collect();
+        self.functions
+            .retain(|name, _| !reaches(&calls, name, name, &mut HashSet::new()));
+    }
 
-        let _report = format!("Inlined {} function calls", self.inlined_calls);
+    /// Inline calls within a definition
+    fn inline_definition(&mut self, definition: &mut Definition) {
+        match definition {
+            Definition::FunctionDef { body, .. } => self.inline_block(body),
+            Definition::ObjectDef { functions, .. } => {
+                for function in functions {
+                    self.inline_definition(function);
+                }
+            }
+            _ => {}
+        }
+    }
 
-        Ok(OptimizationResult::Unchanged(Program {
-            imports: program.imports.clone(),
-            definitions: inlined_definitions,
-            location: program.location.clone(),
-        }))
+    fn inline_block(&mut self, block: &mut Block) {
+        for statement in &mut block.statements {
+            self.inline_statement(statement);
+        }
+    }
+
+    fn inline_statement(&mut self, statement: &mut Statement) {
+        match statement {
+            Statement::Assignment { value, .. }
+            | Statement::Use { value, .. }
+            | Statement::InPlaceOp { value, .. }
+            | Statement::Return { value, .. }
+            | Statement::Open { value, .. }
+            | Statement::Expr { expr: value, .. } => self.inline_expr(value),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.inline_expr(condition);
+                self.inline_block(then_branch);
+                self.inline_block(else_branch);
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.inline_expr(condition);
+                self.inline_block(body);
+            }
+            Statement::For {
+                start, end, body, ..
+            } => {
+                self.inline_expr(start);
+                self.inline_expr(end);
+                self.inline_block(body);
+            }
+            Statement::Switch { value, cases, .. } => {
+                self.inline_expr(value);
+                for case in cases {
+                    self.inline_block(&mut case.body);
+                }
+            }
+            Statement::Match { value, cases, .. } | Statement::Fold { value, cases, .. } => {
+                self.inline_expr(value);
+                for case in cases {
+                    self.inline_block(&mut case.body);
+                }
+            }
+            Statement::Bend {
+                initial_states,
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                for (_, expr) in initial_states {
+                    self.inline_expr(expr);
+                }
+                self.inline_expr(condition);
+                self.inline_block(body);
+                if let Some(else_body) = else_body {
+                    self.inline_block(else_body);
+                }
+            }
+            Statement::With { body, .. } | Statement::Unchecked { body, .. } => {
+                self.inline_block(body);
+            }
+            Statement::LocalDef { function_def, .. } => self.inline_definition(function_def),
+            Statement::TryCatch {
+                try_block,
+                catch_blocks,
+                ..
+            } => {
+                self.inline_block(try_block);
+                for catch in catch_blocks {
+                    self.inline_block(&mut catch.body);
+                }
+            }
+            Statement::Emit { args, .. } => {
+                for arg in args {
+                    self.inline_expr(arg);
+                }
+            }
+            Statement::Revert {
+                condition, reason, ..
+            } => {
+                for expr in condition.iter_mut().chain(reason) {
+                    self.inline_expr(expr);
+                }
+            }
+        }
+    }
+
+    fn inline_expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Tuple { elements, .. }
+            | Expr::List { elements, .. }
+            | Expr::Array { elements, .. }
+            | Expr::Superposition { elements, .. } => {
+                for element in elements {
+                    self.inline_expr(element);
+                }
+            }
+            Expr::Constructor {
+                args, named_args, ..
+            } => {
+                for arg in args.iter_mut().chain(named_args_in_order_mut(named_args)) {
+                    self.inline_expr(arg);
+                }
+            }
+            Expr::FunctionCall {
+                function,
+                args,
+                named_args,
+                ..
+            } => {
+                self.inline_expr(function);
+                for arg in args.iter_mut().chain(named_args_in_order_mut(named_args)) {
+                    self.inline_expr(arg);
+                }
+                if let Some(inlined) = self.inline_call(function, args, named_args) {
+                    *expr = inlined;
+                    self.inlined_calls += 1;
+                    // The inlined body may call other inline functions
+                    self.inline_expr(expr);
+                }
+            }
+            Expr::Lambda { body, .. }
+            | Expr::UnsccopedLambda { body, .. }
+            | Expr::UnaryOp { operand: body, .. }
+            | Expr::FieldAccess { object: body, .. }
+            | Expr::TreeLeaf { value: body, .. }
+            | Expr::Try { expr: body, .. } => self.inline_expr(body),
+            Expr::BinaryOp { left, right, .. }
+            | Expr::TreeNode { left, right, .. }
+            | Expr::MapAccess {
+                map: left,
+                key: right,
+                ..
+            } => {
+                self.inline_expr(left);
+                self.inline_expr(right);
+            }
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.inline_expr(key);
+                    self.inline_expr(value);
+                }
+            }
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.inline_expr(condition);
+                self.inline_expr(then_branch);
+                self.inline_expr(else_branch);
+            }
+            Expr::Block { block, .. } => self.inline_block(block),
+            Expr::InlineAsm { inputs, .. } => {
+                for (_, input) in inputs {
+                    self.inline_expr(input);
+                }
+            }
+            Expr::ListComprehension {
+                element,
+                iterable,
+                condition,
+                ..
+            } => {
+                for expr in [&mut **element, &mut **iterable]
+                    .into_iter()
+                    .chain(condition.as_deref_mut())
+                {
+                    self.inline_expr(expr);
+                }
+            }
+            Expr::MapComprehension {
+                key,
+                value,
+                iterable,
+                condition,
+                ..
+            } => {
+                for expr in [&mut **key, &mut **value, &mut **iterable]
+                    .into_iter()
+                    .chain(condition.as_deref_mut())
+                {
+                    self.inline_expr(expr);
+                }
+            }
+            Expr::Variable { .. } | Expr::Literal { .. } | Expr::Eraser { .. } => {}
+        }
+    }
+
+    /// The inlined body of a call, if the callee can be inlined there
+    ///
+    /// Substituting an argument must neither repeat, drop nor reorder any
+    /// work, so every argument but one must be a variable or a literal, and
+    /// that one must be bound to a parameter evaluated exactly once.
+    fn inline_call(
+        &self,
+        function: &Expr,
+        args: &[Expr],
+        named_args: &HashMap<String, Expr>,
+    ) -> Option<Expr> {
+        let Expr::Variable { name, .. } = function else {
+            return None;
+        };
+        let (params, value) = self.functions.get(name)?;
+        if params.len() != args.len() || !named_args.is_empty() {
+            return None;
+        }
+        let mut computed = args
+            .iter()
+            .zip(params)
+            .filter(|(arg, _)| !matches!(arg, Expr::Variable { .. } | Expr::Literal { .. }));
+        if let Some((_, param)) = computed.next() {
+            if computed.next().is_some() || !evaluated_once(value, param) {
+                return None;
+            }
+        }
+
+        let bindings = params.iter().cloned().zip(args.iter().cloned()).collect();
+        substitute(value, params, &bindings)
     }
 }
 
-impl InlinePass {
-    /// Inline functions within a definition
-    fn inline_definition(&mut self, def: &Definition) -> Definition {
-        // For now, just return the definition as-is
-        // Full inlining implementation would require more complex AST transformation
-        def.clone()
+/// Substitute arguments for the parameters of an inlined expression
+///
+/// Returns `None` for expressions that cannot be moved into another function,
+/// e.g. `?`, which returns from the function it appears in, or expressions
+/// binding names that could capture the caller's variables.
+pub(super) fn substitute(
+    expr: &Expr,
+    params: &[Symbol],
+    bindings: &HashMap<Symbol, Expr>,
+) -> Option<Expr> {
+    let all = |exprs: &[Expr]| -> Option<Vec<Expr>> {
+        exprs
+            .iter()
+            .map(|expr| substitute(expr, params, bindings))
+            .collect()
+    };
+    let boxed = |expr: &Expr| substitute(expr, params, bindings).map(Box::new);
+
+    Some(match expr {
+        Expr::Variable { name, .. } => {
+            // Methods of parameters, e.g. `values.len`, are not renamed
+            if let Some((receiver, _)) = name.split_once('.') {
+                if params.iter().any(|param| param == receiver) {
+                    return None;
+                }
+            }
+            bindings.get(name).cloned().unwrap_or_else(|| expr.clone())
+        }
+        Expr::Literal { .. } | Expr::Eraser { .. } => expr.clone(),
+        Expr::Tuple { elements, location } => Expr::Tuple {
+            elements: all(elements)?,
+            location: location.clone(),
+        },
+        Expr::List { elements, location } => Expr::List {
+            elements: all(elements)?,
+            location: location.clone(),
+        },
+        Expr::Constructor {
+            name,
+            args,
+            named_args,
+            location,
+        } if named_args.is_empty() => Expr::Constructor {
+            name: *name,
+            args: all(args)?,
+            named_args: HashMap::new(),
+            location: location.clone(),
+        },
+        Expr::FunctionCall {
+            function,
+            args,
+            named_args,
+            location,
+        } if named_args.is_empty() => Expr::FunctionCall {
+            function: boxed(function)?,
+            args: all(args)?,
+            named_args: HashMap::new(),
+            location: location.clone(),
+        },
+        Expr::BinaryOp {
+            left,
+            operator,
+            right,
+            location,
+        } => Expr::BinaryOp {
+            left: boxed(left)?,
+            operator: operator.clone(),
+            right: boxed(right)?,
+            location: location.clone(),
+        },
+        Expr::UnaryOp {
+            operator,
+            operand,
+            location,
+        } => Expr::UnaryOp {
+            operator: operator.clone(),
+            operand: boxed(operand)?,
+            location: location.clone(),
+        },
+        Expr::FieldAccess {
+            object,
+            field,
+            location,
+        } => Expr::FieldAccess {
+            object: boxed(object)?,
+            field: field.clone(),
+            location: location.clone(),
+        },
+        Expr::MapAccess { map, key, location } => Expr::MapAccess {
+            map: boxed(map)?,
+            key: boxed(key)?,
+            location: location.clone(),
+        },
+        Expr::If {
+            condition,
+            then_branch,
+            else_branch,
+            location,
+        } => Expr::If {
+            condition: boxed(condition)?,
+            then_branch: boxed(then_branch)?,
+            else_branch: boxed(else_branch)?,
+            location: location.clone(),
+        },
+        _ => return None,
+    })
+}
+
+/// Visit an inlinable expression and all of its subexpressions
+fn visit(expr: &Expr, f: &mut dyn FnMut(&Expr)) {
+    f(expr);
+    match expr {
+        Expr::Tuple { elements, .. } | Expr::List { elements, .. } => {
+            for element in elements {
+                visit(element, f);
+            }
+        }
+        Expr::Constructor { args, .. } => {
+            for arg in args {
+                visit(arg, f);
+            }
+        }
+        Expr::FunctionCall { function, args, .. } => {
+            visit(function, f);
+            for arg in args {
+                visit(arg, f);
+            }
+        }
+        Expr::BinaryOp { left, right, .. }
+        | Expr::MapAccess {
+            map: left,
+            key: right,
+            ..
+        } => {
+            visit(left, f);
+            visit(right, f);
+        }
+        Expr::UnaryOp { operand, .. }
+        | Expr::FieldAccess {
+            object: operand, ..
+        } => {
+            visit(operand, f);
+        }
+        Expr::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            visit(condition, f);
+            visit(then_branch, f);
+            visit(else_branch, f);
+        }
+        _ => {}
+    }
+}
+
+/// Collect the names of the functions an expression calls
+fn called_functions(expr: &Expr, callees: &mut HashSet<Symbol>) {
+    visit(expr, &mut |expr| {
+        if let Expr::FunctionCall { function, .. } = expr {
+            if let Expr::Variable { name, .. } = &**function {
+                callees.insert(*name);
+            }
+        }
+    });
+}
+
+/// Whether a parameter is evaluated exactly once by an expression
+pub(super) fn evaluated_once(expr: &Expr, param: &str) -> bool {
+    let (mut uses, mut conditional) = (0, false);
+    visit(expr, &mut |expr| match expr {
+        Expr::Variable { name, .. } if name == param => uses += 1,
+        Expr::If { .. } => conditional = true,
+        _ => {}
+    });
+    uses == 1 && !conditional
+}
+
+/// Whether `target` is reachable from `from` in the call graph
+fn reaches(
+    calls: &HashMap<Symbol, HashSet<Symbol>>,
+    from: &Symbol,
+    target: &Symbol,
+    visited: &mut HashSet<Symbol>,
+) -> bool {
+    let Some(callees) = calls.get(from) else {
+        return false;
+    };
+    for callee in callees {
+        if callee == target {
+            return true;
+        }
+        if visited.insert(*callee) && reaches(calls, callee, target, visited) {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::optimizer::passes::OptimizationPass;
+    use crate::compiler::parser::parser::Parser;
+
+    fn inline(source: &str) -> (Program, usize) {
+        let program = Parser::new(source).parse_program().unwrap();
+        let mut pass = InlinePass::new();
+        let program = pass.run(program).unwrap().program();
+        (program, pass.inlined_calls())
+    }
+
+    fn returned(program: &Program, function: &str) -> Expr {
+        program
+            .definitions
+            .iter()
+            .find_map(|definition| match definition {
+                Definition::FunctionDef { name, body, .. } if name == function => {
+                    match body.statements.last() {
+                        Some(Statement::Return { value, .. }) => Some(value.clone()),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_inlines_hinted_functions() {
+        let (program, inlined) = inline(
+            r#"
+            #[inline]
+            fn double(x: u24) -> u24 {
+                return x + x;
+            }
+
+            #[inline(always)]
+            fn quadruple(x: u24) -> u24 {
+                return double(double(x));
+            }
+
+            fn main(a: u24) -> u24 {
+                return quadruple(a);
+            }
+            "#,
+        );
+
+        // `double` evaluates its parameter twice, so in `double(double(x))`
+        // only the inner call is inlined, both in `quadruple` and in `main`
+        assert_eq!(inlined, 3);
+        match returned(&program, "main") {
+            Expr::FunctionCall { function, args, .. } => {
+                assert!(matches!(*function, Expr::Variable { ref name, .. } if name == "double"));
+                assert!(matches!(args[0], Expr::BinaryOp { .. }));
+            }
+            other => panic!("expected a call of double, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_respects_hints_and_recursion() {
+        let source = r#"
+            #[inline(never)]
+            fn kept(x: u24) -> u24 {
+                return x + 1;
+            }
+
+            fn plain(x: u24) -> u24 {
+                return x + 1;
+            }
+
+            #[inline]
+            fn forever(x: u24) -> u24 {
+                return forever(x);
+            }
+
+            fn main(a: u24) -> u24 {
+                return kept(a) + plain(a) + forever(a);
+            }
+            "#;
+        let (program, inlined) = inline(source);
+
+        assert_eq!(inlined, 0);
+        assert_eq!(program, Parser::new(source).parse_program().unwrap());
     }
 }
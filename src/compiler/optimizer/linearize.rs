@@ -1,3 +1,4 @@
+use crate::compiler::lowering::has_superpositions;
 use crate::compiler::optimizer::passes::{OptimizationError, OptimizationPass, OptimizationResult};
 use crate::compiler::parser::ast::*;
 use std::collections::HashMap;
@@ -30,102 +31,82 @@ impl LinearizePass {
         format!("__lin_{}", self.counter)
     }
 
-    /// Linearizes a block by extracting complex subexpressions
-    fn linearize_block(&mut self, block: &Block) -> (Block, bool) {
-        let mut new_statements = Vec::new();
+    /// Linearizes a block by extracting complex subexpressions, in place
+    fn linearize_block(&mut self, block: &mut Block) -> bool {
         let mut modified = false;
 
-        for statement in &block.statements {
-            let (linearized_statements, stmt_modified) = self.linearize_statement(statement);
-            new_statements.extend(linearized_statements);
-            modified = modified || stmt_modified;
+        for statement in std::mem::take(&mut block.statements) {
+            modified |= self.linearize_statement(statement, &mut block.statements);
         }
 
-        (
-            Block {
-                statements: new_statements,
-                location: block.location.clone(),
-            },
-            modified,
-        )
+        modified
     }
 
-    /// Linearizes a statement by extracting complex subexpressions
-    fn linearize_statement(&mut self, statement: &Statement) -> (Vec<Statement>, bool) {
+    /// Linearizes a statement by extracting complex subexpressions, pushing
+    /// the extracted statements and then the statement itself to `out`
+    fn linearize_statement(&mut self, statement: Statement, out: &mut Vec<Statement>) -> bool {
+        // A superposition is duplicated with the whole statement holding it,
+        // so nothing may be extracted out of that statement
+        if has_superpositions(&statement) {
+            out.push(statement);
+            return false;
+        }
+
         match statement {
             Statement::Assignment {
                 pattern,
                 value,
                 location,
             } => {
-                let (linearized_expr, pre_statements, expr_modified) = self.linearize_expr(value);
-
-                let mut statements = pre_statements;
-                statements.push(Statement::Assignment {
-                    pattern: pattern.clone(),
-                    value: linearized_expr,
-                    location: location.clone(),
+                let (value, modified) = self.linearize_expr(value, out);
+                out.push(Statement::Assignment {
+                    pattern,
+                    value,
+                    location,
                 });
-
-                (statements, expr_modified)
+                modified
             }
             Statement::Return { value, location } => {
-                let (linearized_expr, pre_statements, expr_modified) = self.linearize_expr(value);
-
-                let mut statements = pre_statements;
-                statements.push(Statement::Return {
-                    value: linearized_expr,
-                    location: location.clone(),
-                });
-
-                (statements, expr_modified)
+                let (value, modified) = self.linearize_expr(value, out);
+                out.push(Statement::Return { value, location });
+                modified
             }
             Statement::If {
                 condition,
-                then_branch,
-                else_branch,
+                mut then_branch,
+                mut else_branch,
                 location,
             } => {
-                // Linearize the condition
-                let (linearized_condition, mut pre_statements, condition_modified) =
-                    self.linearize_expr(condition);
-
-                // Linearize the branches
-                let (linearized_then, then_modified) = self.linearize_block(then_branch);
-                let (linearized_else, else_modified) = self.linearize_block(else_branch);
-
-                let modified = condition_modified || then_modified || else_modified;
-
-                let if_statement = Statement::If {
-                    condition: linearized_condition,
-                    then_branch: linearized_then,
-                    else_branch: linearized_else,
-                    location: location.clone(),
-                };
-
-                pre_statements.push(if_statement);
-
-                (pre_statements, modified)
+                // Linearize the condition, then the branches
+                let (condition, condition_modified) = self.linearize_expr(condition, out);
+                let then_modified = self.linearize_block(&mut then_branch);
+                let else_modified = self.linearize_block(&mut else_branch);
+
+                out.push(Statement::If {
+                    condition,
+                    then_branch,
+                    else_branch,
+                    location,
+                });
+                condition_modified || then_modified || else_modified
             }
             Statement::Expr { expr, location } => {
-                let (linearized_expr, mut pre_statements, expr_modified) =
-                    self.linearize_expr(expr);
-
-                pre_statements.push(Statement::Expr {
-                    expr: linearized_expr,
-                    location: location.clone(),
-                });
-
-                (pre_statements, expr_modified)
+                let (expr, modified) = self.linearize_expr(expr, out);
+                out.push(Statement::Expr { expr, location });
+                modified
             }
             // For other statement types, we would need to implement linearization logic
             // This is a simplified implementation
-            _ => (vec![statement.clone()], false),
+            statement => {
+                out.push(statement);
+                false
+            }
         }
     }
 
-    /// Linearizes an expression by extracting complex subexpressions
-    fn linearize_expr(&mut self, expr: &Expr) -> (Expr, Vec<Statement>, bool) {
+    /// Linearizes an expression by extracting complex subexpressions into
+    /// statements pushed to `pre_statements`
+    fn linearize_expr(&mut self, expr: Expr, pre_statements: &mut Vec<Statement>) -> (Expr, bool) {
         match expr {
             Expr::BinaryOp {
                 left,
@@ -134,138 +115,120 @@ impl LinearizePass {
                 location,
             } => {
                 // Linearize the operands
-                let (linearized_left, mut left_statements, left_modified) =
-                    self.linearize_expr(left);
-                let (linearized_right, mut right_statements, right_modified) =
-                    self.linearize_expr(right);
-
-                let mut pre_statements = Vec::new();
-                pre_statements.append(&mut left_statements);
-                pre_statements.append(&mut right_statements);
+                let (left, left_modified) = self.linearize_expr(*left, pre_statements);
+                let (right, right_modified) = self.linearize_expr(*right, pre_statements);
 
                 // If the operands are complex, extract them into temporary variables
-                let (final_left, mut left_temp_stmts) = self.maybe_extract_expr(&linearized_left);
-                let (final_right, mut right_temp_stmts) =
-                    self.maybe_extract_expr(&linearized_right);
-
-                pre_statements.append(&mut left_temp_stmts);
-                pre_statements.append(&mut right_temp_stmts);
-
-                let modified = left_modified
-                    || right_modified
-                    || final_left != linearized_left
-                    || final_right != linearized_right;
+                let (left, left_extracted) = self.maybe_extract_expr(left, pre_statements);
+                let (right, right_extracted) = self.maybe_extract_expr(right, pre_statements);
 
                 let result = Expr::BinaryOp {
-                    left: Box::new(final_left),
-                    operator: operator.clone(),
-                    right: Box::new(final_right),
-                    location: location.clone(),
+                    left: Box::new(left),
+                    operator,
+                    right: Box::new(right),
+                    location,
                 };
+                let modified = left_modified || right_modified || left_extracted || right_extracted;
 
-                (result, pre_statements, modified)
+                (result, modified)
             }
             Expr::FunctionCall {
                 function,
                 args,
-                named_args,
+                mut named_args,
                 location,
             } => {
                 // Linearize the function
-                let (linearized_function, function_statements, function_modified) =
-                    self.linearize_expr(function);
+                let (function, function_modified) = self.linearize_expr(*function, pre_statements);
+                let mut modified = function_modified;
 
                 // Linearize the arguments
-                let mut linearized_args = Vec::new();
-                let mut args_modified = false;
-                let mut pre_statements = function_statements;
-
+                let mut linearized_args = Vec::with_capacity(args.len());
                 for arg in args {
-                    let (linearized_arg, mut arg_statements, arg_modified) =
-                        self.linearize_expr(arg);
-                    pre_statements.append(&mut arg_statements);
-                    linearized_args.push(linearized_arg);
-                    args_modified = args_modified || arg_modified;
+                    let (arg, arg_modified) = self.linearize_expr(arg, pre_statements);
+                    linearized_args.push(arg);
+                    modified |= arg_modified;
                 }
 
                 // Linearize named arguments
-                let mut linearized_named_args = HashMap::new();
-                let mut named_args_modified = false;
-
-                for (name, arg) in named_args {
-                    let (linearized_arg, mut arg_statements, arg_modified) =
-                        self.linearize_expr(arg);
-                    pre_statements.append(&mut arg_statements);
-                    linearized_named_args.insert(name.clone(), linearized_arg);
-                    named_args_modified = named_args_modified || arg_modified;
+                let names: Vec<String> = named_args_in_order(&named_args)
+                    .into_iter()
+                    .map(|(name, _)| name.clone())
+                    .collect();
+                let mut linearized_named_args = Vec::with_capacity(names.len());
+                for name in names {
+                    let arg = named_args.remove(&name).expect("named argument");
+                    let (arg, arg_modified) = self.linearize_expr(arg, pre_statements);
+                    linearized_named_args.push((name, arg));
+                    modified |= arg_modified;
                 }
 
                 // If the function is complex, extract it into a temporary variable
-                let (final_function, mut function_temp_stmts) =
-                    self.maybe_extract_expr(&linearized_function);
-                pre_statements.append(&mut function_temp_stmts);
+                let (function, function_extracted) =
+                    self.maybe_extract_expr(function, pre_statements);
+                modified |= function_extracted;
 
                 // If any arguments are complex, extract them into temporary variables
-                let mut final_args = Vec::new();
+                let mut final_args = Vec::with_capacity(linearized_args.len());
                 for arg in linearized_args {
-                    let (final_arg, mut arg_temp_stmts) = self.maybe_extract_expr(&arg);
-                    pre_statements.append(&mut arg_temp_stmts);
-                    final_args.push(final_arg);
+                    let (arg, extracted) = self.maybe_extract_expr(arg, pre_statements);
+                    final_args.push(arg);
+                    modified |= extracted;
                 }
-
                 let mut final_named_args = HashMap::new();
                 for (name, arg) in linearized_named_args {
-                    let (final_arg, mut arg_temp_stmts) = self.maybe_extract_expr(&arg);
-                    pre_statements.append(&mut arg_temp_stmts);
-                    final_named_args.insert(name, final_arg);
+                    let (arg, extracted) = self.maybe_extract_expr(arg, pre_statements);
+                    final_named_args.insert(name, arg);
+                    modified |= extracted;
                 }
 
-                let modified = function_modified
-                    || args_modified
-                    || named_args_modified
-                    || final_function != linearized_function;
-
                 let result = Expr::FunctionCall {
-                    function: Box::new(final_function),
+                    function: Box::new(function),
                     args: final_args,
                     named_args: final_named_args,
-                    location: location.clone(),
+                    location,
                 };
 
-                (result, pre_statements, modified)
+                (result, modified)
             }
             // For other expression types, we would need to implement linearization logic
             // This is a simplified implementation
-            _ => (expr.clone(), Vec::new(), false),
+            expr => (expr, false),
         }
     }
 
-    /// Extracts a complex expression into a temporary variable
-    fn maybe_extract_expr(&mut self, expr: &Expr) -> (Expr, Vec<Statement>) {
-        if self.is_complex(expr) {
-            // Generate a fresh variable name
-            let var_name = self.fresh_var();
-
-            // Create a new variable expression
-            let var_expr = Expr::Variable {
-                name: var_name.clone(),
-                location: expr.location().clone(),
-            };
+    /// Extracts a complex expression into a temporary variable assigned by
+    /// a statement pushed to `pre_statements`, returning whether it did
+    fn maybe_extract_expr(
+        &mut self,
+        expr: Expr,
+        pre_statements: &mut Vec<Statement>,
+    ) -> (Expr, bool) {
+        if !self.is_complex(&expr) {
+            return (expr, false);
+        }
 
-            // Create an assignment statement
-            let assignment = Statement::Assignment {
-                pattern: Pattern::Variable {
-                    name: var_name,
-                    location: expr.location().clone(),
-                },
-                value: expr.clone(),
-                location: expr.location().clone(),
-            };
+        // Generate a fresh variable name
+        let var_name = self.fresh_var();
+        let location = expr.location().clone();
+
+        // Create a new variable expression
+        let var_expr = Expr::Variable {
+            name: Symbol::intern(&var_name),
+            location: location.clone(),
+        };
+
+        // Create an assignment statement
+        pre_statements.push(Statement::Assignment {
+            pattern: Pattern::Variable {
+                name: var_name.into(),
+                location: location.clone(),
+            },
+            value: expr,
+            location,
+        });
 
-            (var_expr, vec![assignment])
-        } else {
-            (expr.clone(), Vec::new())
-        }
+        (var_expr, true)
     }
 
     /// Checks if an expression is complex and should be extracted
@@ -291,44 +254,19 @@ impl OptimizationPass for LinearizePass {
     fn run(&mut self, program: Program) -> Result<OptimizationResult, OptimizationError> {
         let mut linearizer = LinearizePass::new();
         let mut modified = false;
-        let mut new_definitions = Vec::new();
-
-        // Linearize each definition
-        for definition in &program.definitions {
-            match definition {
-                Definition::FunctionDef {
-                    name,
-                    params,
-                    return_type,
-                    body,
-                    checked,
-                    location,
-                } => {
-                    // Linearize the function body
-                    let (linearized_body, body_modified) = linearizer.linearize_block(body);
-                    modified = modified || body_modified;
+        let mut program = program;
 
-                    new_definitions.push(Definition::FunctionDef {
-                        name: name.clone(),
-                        params: params.clone(),
-                        return_type: return_type.clone(),
-                        body: linearized_body,
-                        checked: *checked,
-                        location: location.clone(),
-                    });
-                }
-                // Other definition types don't need linearization
-                _ => new_definitions.push(definition.clone()),
+        // Linearize each function body in place; other definition types
+        // don't need linearization
+        for definition in &mut program.definitions {
+            if let Definition::FunctionDef { body, .. } = definition {
+                modified |= linearizer.linearize_block(body);
             }
         }
 
         // Return the result
         if modified {
-            Ok(OptimizationResult::Modified(Program {
-                imports: program.imports.clone(),
-                definitions: new_definitions,
-                location: program.location.clone(),
-            }))
+            Ok(OptimizationResult::Modified(program))
         } else {
             Ok(OptimizationResult::Unchanged(program))
         }
@@ -1,6 +1,7 @@
 use std::collections::HashSet;
 
 use crate::compiler::parser::ast::*;
+use crate::compiler::timing::Timings;
 use thiserror::Error;
 
 /// Errors that can occur during optimization
@@ -130,6 +131,9 @@ impl OptimizationManager {
                 self.enable_pass("dead_code_elimination");
                 self.enable_pass("linearize");
                 self.enable_pass("prune");
+                self.enable_pass("devirtualize");
+                self.enable_pass("inline");
+                self.enable_pass("constexpr");
             }
             OptimizationLevel::Aggressive => {
                 // Enable all passes
@@ -158,13 +162,24 @@ impl OptimizationManager {
 
     /// Runs all enabled optimization passes on the program
     pub fn optimize(&mut self, program: Program) -> Result<Program, OptimizationError> {
+        self.optimize_timed(program, &mut Timings::new())
+    }
+
+    /// Like [`optimize`](Self::optimize), recording each run of a pass in
+    /// `timings`
+    pub fn optimize_timed(
+        &mut self,
+        program: Program,
+        timings: &mut Timings,
+    ) -> Result<Program, OptimizationError> {
         let mut current_program = program;
         let mut modified = false;
 
         // Run all enabled passes
         for pass in &mut self.passes {
             if self.enabled_passes.contains(pass.name()) {
-                let result = pass.run(current_program)?;
+                let name = pass.name();
+                let result = timings.time(name, |_| pass.run(current_program))?;
 
                 if result.was_modified() {
                     modified = true;
@@ -177,7 +192,7 @@ impl OptimizationManager {
         // If any pass modified the program, run the optimization again
         // until no more changes are made
         if modified {
-            self.optimize(current_program)
+            self.optimize_timed(current_program, timings)
         } else {
             Ok(current_program)
         }
@@ -186,8 +201,11 @@ impl OptimizationManager {
 
 /// Creates an optimization manager with the default set of passes
 pub fn create_default_manager() -> OptimizationManager {
+    use crate::compiler::optimizer::constexpr::ConstEvalPass;
+    use crate::compiler::optimizer::devirtualize::DevirtualizePass;
     use crate::compiler::optimizer::eta_reduction::EtaReductionPass;
     use crate::compiler::optimizer::float_comb::FloatCombPass;
+    use crate::compiler::optimizer::inline::InlinePass;
     use crate::compiler::optimizer::linearize::LinearizePass;
     use crate::compiler::optimizer::pruner::PrunePass;
 
@@ -198,6 +216,9 @@ pub fn create_default_manager() -> OptimizationManager {
     manager.register_pass(Box::new(FloatCombPass::new()));
     manager.register_pass(Box::new(PrunePass::new()));
     manager.register_pass(Box::new(EtaReductionPass::new()));
+    manager.register_pass(Box::new(DevirtualizePass::new()));
+    manager.register_pass(Box::new(InlinePass::new()));
+    manager.register_pass(Box::new(ConstEvalPass::new()));
 
     // Set default level
     manager.set_level(OptimizationLevel::Standard);
@@ -208,7 +229,6 @@ pub fn create_default_manager() -> OptimizationManager {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::compiler::parser::ast::*;
     use crate::compiler::parser::parser::Parser;
 
     fn parse_program(source: &str) -> Program {
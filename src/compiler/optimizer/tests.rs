@@ -1,4 +1,3 @@
-use super::*;
 use crate::compiler::optimizer::eta_reduction::EtaReductionPass;
 use crate::compiler::optimizer::float_comb::FloatCombPass;
 use crate::compiler::optimizer::linearize::LinearizePass;
@@ -64,6 +63,39 @@ fn test_prune_pass_basic() {
     assert!(duration.as_millis() < 50, "PrunePass should be fast");
 }
 
+#[test]
+fn test_prune_pass_keeps_messages() {
+    let program = parse_from_source(
+        r#"
+            fn double(x: u24) -> u24 {
+                return x * 2;
+            }
+
+            fn balance_of(account: u24) -> u24 {
+                return account;
+            }
+
+            #[test]
+            fn test_double() -> u24 {
+                assert(double(2) == 4, "doubled");
+                return 0;
+            }
+        "#,
+    )
+    .unwrap();
+
+    let program = PrunePass::new().run(program).unwrap().program();
+    let names: Vec<&str> = program
+        .definitions
+        .iter()
+        .filter_map(|def| match def {
+            Definition::FunctionDef { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(names, ["double", "balance_of"]);
+}
+
 #[test]
 fn test_eta_reduction_pass_basic() {
     let program = create_simple_program();
@@ -176,8 +208,8 @@ fn test_optimization_pass_isolation() {
 
     // Results should be different (passes do different things)
     // but both should be valid
-    assert!(program_after_1.definitions.len() > 0);
-    assert!(program_after_2.definitions.len() > 0);
+    assert!(!program_after_1.definitions.is_empty());
+    assert!(!program_after_2.definitions.is_empty());
 }
 
 #[test]
@@ -360,3 +392,53 @@ fn test_preserve_non_foldable() {
         }
     }
 }
+
+#[test]
+fn test_passes_keep_superpositions_whole() {
+    let program = parse_from_source(
+        r#"
+            fn main(a: u24) -> u24 {
+                pay({a * 2, 1.5 + 2.5} + 1);
+                return 0;
+            }
+        "#,
+    )
+    .unwrap();
+
+    // Linearizing would bind the superposition to a local, which lowering
+    // cannot duplicate
+    let unchanged = match LinearizePass::new().run(program).unwrap() {
+        OptimizationResult::Unchanged(program) => program,
+        OptimizationResult::Modified(_) => panic!("LinearizePass split a superposition"),
+    };
+
+    // Each alternative folds on its own
+    let folded = match FloatCombPass::new().run(unchanged).unwrap() {
+        OptimizationResult::Modified(program) => program,
+        OptimizationResult::Unchanged(_) => panic!("FloatCombPass left 1.5 + 2.5"),
+    };
+    let Definition::FunctionDef { body, .. } = &folded.definitions[0] else {
+        panic!("Expected function definition");
+    };
+    let Statement::Expr {
+        expr: Expr::FunctionCall { args, .. },
+        ..
+    } = &body.statements[0]
+    else {
+        panic!("Expected call statement");
+    };
+    let Expr::BinaryOp { left, .. } = &args[0] else {
+        panic!("Expected addition");
+    };
+    let Expr::Superposition { elements, .. } = &**left else {
+        panic!("Expected superposition");
+    };
+    assert!(matches!(elements[0], Expr::BinaryOp { .. }));
+    assert!(matches!(
+        elements[1],
+        Expr::Literal {
+            kind: LiteralKind::Float(value),
+            ..
+        } if value == 4.0
+    ));
+}
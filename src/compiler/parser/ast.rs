@@ -2,8 +2,14 @@
 
 use std::collections::HashMap;
 
+use serde::{Deserialize, Serialize};
+
+use crate::compiler::address::{Address, Hash};
+pub use crate::compiler::intern::Symbol;
+use crate::compiler::wide::WideUint;
+
 /// Represents a source location for AST nodes
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 pub struct Location {
     pub line: usize,
     pub column: usize,
@@ -34,7 +40,7 @@ impl Location {
 }
 
 /// Represents a complete Bend-PVM program
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Program {
     pub imports: Vec<Import>,
     pub definitions: Vec<Definition>,
@@ -42,7 +48,7 @@ pub struct Program {
 }
 
 /// Represents an import statement
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Import {
     FromImport {
         path: String,
@@ -56,81 +62,175 @@ pub enum Import {
 }
 
 /// Represents an imported name, optionally aliased
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ImportName {
-    pub name: String,
-    pub alias: Option<String>,
+    pub name: Symbol,
+    pub alias: Option<Symbol>,
     pub location: Location,
 }
 
 /// Represents a top-level definition
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Definition {
     FunctionDef {
-        name: String,
+        name: Symbol,
         params: Vec<Parameter>,
         return_type: Option<Type>,
         body: Block,
         checked: Option<bool>, // None = default, Some(true) = checked, Some(false) = unchecked
+        attributes: Vec<Attribute>,
+        visibility: Visibility,
         location: Location,
     },
     TypeDef {
-        name: String,
+        name: Symbol,
         type_params: Vec<String>,
         variants: Vec<TypeVariant>,
+        attributes: Vec<Attribute>,
+        visibility: Visibility,
         location: Location,
     },
     ObjectDef {
-        name: String,
+        name: Symbol,
         type_params: Vec<String>,
         fields: Vec<Field>,
         functions: Vec<Definition>,
+        attributes: Vec<Attribute>,
+        visibility: Visibility,
         location: Location,
     },
     TypeAlias {
-        name: String,
+        name: Symbol,
         type_params: Vec<String>,
         target_type: Type,
+        visibility: Visibility,
         location: Location,
     },
     Module {
-        name: String,
+        name: Symbol,
         definitions: Vec<Definition>,
         exports: Vec<String>,
         location: Location,
     },
+    EventDef {
+        name: Symbol,
+        fields: Vec<EventField>,
+        visibility: Visibility,
+        location: Location,
+    },
+    /// An error type, whose variants calls can fail with
+    ErrorDef {
+        name: Symbol,
+        variants: Vec<TypeVariant>,
+        visibility: Visibility,
+        location: Location,
+    },
+    StorageDef {
+        fields: Vec<StorageField>,
+        /// The `#[invariant(...)]`s of the contract
+        attributes: Vec<Attribute>,
+        location: Location,
+    },
+    /// A reusable guard wrapped around the bodies of the functions that
+    /// list it in `#[guard(...)]`; the `_;` placeholder of its body splits
+    /// it into the code run before and after the guarded body
+    GuardDef {
+        name: Symbol,
+        params: Vec<Parameter>,
+        before: Block,
+        after: Block,
+        location: Location,
+    },
+    /// The messages of an external contract, called through
+    /// `Name(address).message(args)`; the functions have no bodies
+    InterfaceDef {
+        name: Symbol,
+        functions: Vec<Definition>,
+        visibility: Visibility,
+        location: Location,
+    },
+    /// A contract declaring its state, events and messages together, which
+    /// the parser lowers with [`crate::compiler::contract::lower`] unless
+    /// asked to keep it
+    ContractDef {
+        name: Symbol,
+        /// State fields, declared with `let`, or `pub let` to get a getter
+        fields: Vec<StorageField>,
+        /// Functions, events, errors, guards and types, in source order
+        definitions: Vec<Definition>,
+        /// Attributes of the contract's storage, e.g. `#[version(n)]`
+        attributes: Vec<Attribute>,
+        location: Location,
+    },
+}
+
+/// Whether a definition can be imported by other modules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Visibility {
+    /// Only usable within its own module
+    #[default]
+    Private,
+    /// Exported, marked with `pub`
+    Public,
+}
+
+/// Represents an attribute attached to a definition, e.g. `#[selector(0x12345678)]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attribute {
+    pub name: Symbol,
+    pub args: Vec<Expr>,
+    pub location: Location,
 }
 
 /// Represents a parameter in a function definition
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Parameter {
-    pub name: String,
+    pub name: Symbol,
     pub ty: Type,
     pub location: Location,
 }
 
 /// Represents a function or constructor parameter field
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Field {
-    pub name: String,
+    pub name: Symbol,
     pub type_annotation: Option<Type>,
     pub is_recursive: bool, // Marked with ~
     pub location: Location,
 }
 
+/// Represents a field of an event definition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventField {
+    pub name: Symbol,
+    pub ty: Type,
+    pub indexed: bool, // Indexed fields become event topics
+    pub location: Location,
+}
+
+/// Represents a persistent field declared in a storage block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageField {
+    pub name: Symbol,
+    pub ty: Type,
+    /// Public for contract fields declared `pub let`, which get a getter
+    pub visibility: Visibility,
+    pub location: Location,
+}
+
 /// Represents a variant in a type definition
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TypeVariant {
-    pub name: String,
+    pub name: Symbol,
     pub fields: Vec<Field>,
     pub location: Location,
 }
 
 /// Represents a type in the Bend-PVM language
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Type {
     Named {
-        name: String,
+        name: Symbol,
         params: Vec<Type>,
         location: Location,
     },
@@ -165,7 +265,7 @@ pub enum Type {
         location: Location,
     },
     Generic {
-        name: String,
+        name: Symbol,
         bounds: Vec<TypeBound>,
         location: Location,
     },
@@ -181,8 +281,54 @@ pub enum Type {
     },
 }
 
+/// Types print the way they are written in source, e.g. `Map<u24, Bool>`
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn list(f: &mut std::fmt::Formatter<'_>, types: &[Type]) -> std::fmt::Result {
+            for (i, ty) in types.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", ty)?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Type::Named { name, params, .. } => {
+                write!(f, "{}", name)?;
+                if !params.is_empty() {
+                    write!(f, "<")?;
+                    list(f, params)?;
+                    write!(f, ">")?;
+                }
+                Ok(())
+            }
+            Type::Function { param, result, .. }
+            | Type::Effect {
+                input: param,
+                output: result,
+                ..
+            } => write!(f, "{} -> {}", param, result),
+            Type::Tuple { elements, .. } => {
+                write!(f, "(")?;
+                list(f, elements)?;
+                write!(f, ")")
+            }
+            Type::Any { .. } => write!(f, "Any"),
+            Type::None { .. } => write!(f, "None"),
+            Type::Hole { .. } | Type::Unknown { .. } => write!(f, "_"),
+            Type::U24 { .. } => write!(f, "u24"),
+            Type::I24 { .. } => write!(f, "i24"),
+            Type::F24 { .. } => write!(f, "f24"),
+            Type::Generic { name, .. } => write!(f, "{}", name),
+            Type::Constrained { base, .. } => write!(f, "{}", base),
+        }
+    }
+}
+
 /// Represents a type bound for generics
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TypeBound {
     pub trait_name: String,
     pub args: Vec<Type>,
@@ -190,14 +336,14 @@ pub struct TypeBound {
 }
 
 /// Represents a block of statements
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Block {
     pub statements: Vec<Statement>,
     pub location: Location,
 }
 
 /// Represents a statement in the Bend-PVM language
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Statement {
     Assignment {
         pattern: Pattern,
@@ -205,12 +351,12 @@ pub enum Statement {
         location: Location,
     },
     Use {
-        name: String,
+        name: Symbol,
         value: Expr,
         location: Location,
     },
     InPlaceOp {
-        target: String,
+        target: Symbol,
         operator: InPlaceOperator,
         value: Expr,
         location: Location,
@@ -225,6 +371,23 @@ pub enum Statement {
         else_branch: Block,
         location: Location,
     },
+    /// `while cond { ... }`; `while cond bound 10 { ... }` caps the number
+    /// of iterations
+    While {
+        condition: Expr,
+        body: Block,
+        bound: Option<u32>,
+        location: Location,
+    },
+    /// `for i in range(start, end) { ... }`, optionally with a bound
+    For {
+        variable: Symbol,
+        start: Expr,
+        end: Expr, // Exclusive
+        body: Block,
+        bound: Option<u32>,
+        location: Location,
+    },
     Switch {
         value: Expr,
         cases: Vec<SwitchCase>,
@@ -241,7 +404,7 @@ pub enum Statement {
         location: Location,
     },
     Bend {
-        initial_states: Vec<(String, Expr)>,
+        initial_states: Vec<(Symbol, Expr)>,
         condition: Expr,
         body: Block,
         else_body: Option<Block>,
@@ -257,6 +420,11 @@ pub enum Statement {
         body: Block,
         location: Location,
     },
+    /// A block whose arithmetic is not checked for overflow
+    Unchecked {
+        body: Block,
+        location: Location,
+    },
     LocalDef {
         function_def: Box<Definition>,
         location: Location,
@@ -270,10 +438,30 @@ pub enum Statement {
         catch_blocks: Vec<CatchBlock>,
         location: Location,
     },
+    Emit {
+        event: Symbol,
+        args: Vec<Expr>,
+        location: Location,
+    },
+    /// `assert(cond, "msg")`, `require(cond, reason)` or `revert(reason)`
+    Revert {
+        kind: RevertKind,
+        condition: Option<Expr>, // None for `revert`
+        reason: Option<Expr>,    // A string literal or an error variant
+        location: Location,
+    },
+}
+
+/// Represents the construct a revert statement was written with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RevertKind {
+    Assert,  // Reverts with `Panic(String)` when the condition is false
+    Require, // Reverts with the reason when the condition is false
+    Revert,  // Always reverts with the reason
 }
 
 /// Represents an in-place operation like +=, -=, etc.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum InPlaceOperator {
     Add,
     Sub,
@@ -287,7 +475,7 @@ pub enum InPlaceOperator {
 }
 
 /// Represents a switch case
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SwitchCase {
     pub value: Option<u32>, // None means default case (_)
     pub body: Block,
@@ -295,7 +483,7 @@ pub struct SwitchCase {
 }
 
 /// Represents a catch block in try-catch
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CatchBlock {
     pub error_type: Option<String>,
     pub error_var: Option<String>,
@@ -304,7 +492,7 @@ pub struct CatchBlock {
 }
 
 /// Represents a match case
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MatchCase {
     pub pattern: Pattern,
     pub body: Block,
@@ -312,10 +500,10 @@ pub struct MatchCase {
 }
 
 /// Represents a pattern in pattern matching
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Pattern {
     Variable {
-        name: String,
+        name: Symbol,
         location: Location,
     },
     Tuple {
@@ -323,12 +511,12 @@ pub enum Pattern {
         location: Location,
     },
     Constructor {
-        name: String,
+        name: Symbol,
         fields: HashMap<String, Pattern>, // For object/type constructors
         location: Location,
     },
     TupleConstructor {
-        name: String,
+        name: Symbol,
         args: Vec<Pattern>,
         location: Location,
     },
@@ -341,16 +529,21 @@ pub enum Pattern {
         member: String,
         location: Location,
     },
+    MapAccess {
+        map: Box<Expr>,
+        key: Box<Expr>,
+        location: Location,
+    },
     Wildcard {
         location: Location,
     },
 }
 
 /// Represents an expression in the Bend-PVM language
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Expr {
     Variable {
-        name: String,
+        name: Symbol,
         location: Location,
     },
     Literal {
@@ -370,7 +563,7 @@ pub enum Expr {
         location: Location,
     },
     Constructor {
-        name: String,
+        name: Symbol,
         args: Vec<Expr>,
         named_args: HashMap<String, Expr>,
         location: Location,
@@ -397,6 +590,11 @@ pub enum Expr {
         right: Box<Expr>,
         location: Location,
     },
+    UnaryOp {
+        operator: UnaryOperator,
+        operand: Box<Expr>,
+        location: Location,
+    },
     FieldAccess {
         object: Box<Expr>,
         field: String,
@@ -406,6 +604,10 @@ pub enum Expr {
         elements: Vec<Expr>,
         location: Location,
     },
+    Map {
+        entries: Vec<(Expr, Expr)>,
+        location: Location,
+    },
     MapAccess {
         map: Box<Expr>,
         key: Box<Expr>,
@@ -433,14 +635,51 @@ pub enum Expr {
     Eraser {
         location: Location,
     },
+    /// `expr?`: the `Ok` value of a `Result`, or an early return of its `Err`
+    Try {
+        expr: Box<Expr>,
+        location: Location,
+    },
+    /// `[element for variable in iterable if condition]`, lowered to a fold
+    ListComprehension {
+        element: Box<Expr>,
+        variable: Symbol,
+        iterable: Box<Expr>,
+        condition: Option<Box<Expr>>,
+        location: Location,
+    },
+    /// `{key: value for variable in iterable if condition}`, lowered to a fold
+    MapComprehension {
+        key: Box<Expr>,
+        value: Box<Expr>,
+        variable: Symbol,
+        iterable: Box<Expr>,
+        condition: Option<Box<Expr>>,
+        location: Location,
+    },
+    /// `asm(in a0 = x, out a0) { "addi a0, a0, 1" }`: RISC-V instructions
+    /// passed through to the generated code as they are
+    InlineAsm {
+        /// Registers loaded with the value of an expression before the block
+        inputs: Vec<(String, Expr)>,
+        /// Register the value of the expression is in after the block
+        output: Option<String>,
+        /// One instruction, `label:` or `# comment` each
+        instructions: Vec<String>,
+        location: Location,
+    },
 }
 
 /// Represents a literal value
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum LiteralKind {
-    Uint(u32),  // For u24
-    Int(i32),   // For i24
-    Float(f32), // For f24
+    Uint(u32),          // For u24
+    WideUint(WideUint), // For u64, u128 and u256
+    Address(Address),   // For Address, e.g. `@5Grw...`
+    Hash(Hash),         // For Hash, e.g. `0x00...01`
+    Bytes(Vec<u8>),     // For Bytes, e.g. `hex"deadbeef"`
+    Int(i32),           // For i24
+    Float(f32),         // For f24
     Bool(bool),
     String(String),
     Char(char),
@@ -448,7 +687,7 @@ pub enum LiteralKind {
 }
 
 /// Represents a binary operator
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BinaryOperator {
     Add,
     Sub,
@@ -467,6 +706,26 @@ pub enum BinaryOperator {
     GreaterEqual,
     BitShiftLeft,
     BitShiftRight,
+    And, // Short-circuiting `&&` or `and`
+    Or,  // Short-circuiting `||` or `or`
+}
+
+/// Represents a unary operator
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum UnaryOperator {
+    Not, // `!` or `not`
+}
+
+/// The callee of a call through an interface, see
+/// [`Expr::as_interface_message`]
+#[derive(Debug, Clone, Copy)]
+pub struct InterfaceMessageTarget<'a> {
+    pub interface: Symbol,
+    /// Arguments of the binding, the account of the contract
+    pub accounts: &'a [Expr],
+    /// Named arguments of the binding, `value` and `gas`
+    pub options: &'a HashMap<String, Expr>,
+    pub message: &'a str,
 }
 
 /// Helper trait to get the location of an AST node
@@ -482,6 +741,166 @@ impl LocationProvider for Definition {
             Definition::ObjectDef { location, .. } => location,
             Definition::TypeAlias { location, .. } => location,
             Definition::Module { location, .. } => location,
+            Definition::EventDef { location, .. } => location,
+            Definition::ErrorDef { location, .. } => location,
+            Definition::StorageDef { location, .. } => location,
+            Definition::GuardDef { location, .. } => location,
+            Definition::InterfaceDef { location, .. } => location,
+            Definition::ContractDef { location, .. } => location,
+        }
+    }
+}
+
+impl Definition {
+    /// Attributes attached to the definition, empty for definitions that
+    /// cannot carry any
+    pub fn attributes(&self) -> &[Attribute] {
+        match self {
+            Definition::FunctionDef { attributes, .. }
+            | Definition::TypeDef { attributes, .. }
+            | Definition::ObjectDef { attributes, .. }
+            | Definition::StorageDef { attributes, .. }
+            | Definition::ContractDef { attributes, .. } => attributes,
+            _ => &[],
+        }
+    }
+
+    /// Visibility of the definition; storage, guards and contracts are
+    /// always private and nested modules list their exports themselves
+    pub fn visibility(&self) -> Visibility {
+        match self {
+            Definition::FunctionDef { visibility, .. }
+            | Definition::TypeDef { visibility, .. }
+            | Definition::ObjectDef { visibility, .. }
+            | Definition::TypeAlias { visibility, .. }
+            | Definition::EventDef { visibility, .. }
+            | Definition::ErrorDef { visibility, .. }
+            | Definition::InterfaceDef { visibility, .. } => *visibility,
+            Definition::Module { .. } => Visibility::Public,
+            Definition::StorageDef { .. }
+            | Definition::GuardDef { .. }
+            | Definition::ContractDef { .. } => Visibility::Private,
+        }
+    }
+}
+
+impl Expr {
+    /// Split the callee of a method call into receiver and method name
+    ///
+    /// The lexer keeps dotted names such as `balances.get` together, so
+    /// both a dotted variable and an explicit field access are accepted.
+    pub fn as_method_target(&self) -> Option<(Symbol, &str)> {
+        match self {
+            Expr::Variable { name, .. } => {
+                let (object, method) = name.as_str().rsplit_once('.')?;
+                Some((Symbol::intern(object), method))
+            }
+            Expr::FieldAccess { object, field, .. } => match &**object {
+                Expr::Variable { name, .. } => Some((*name, field.as_str())),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The arguments of `range(end)` or `range(start, end)`, which a `for`
+    /// loop or a comprehension iterates over
+    pub fn as_range(&self) -> Option<&[Expr]> {
+        match self {
+            Expr::FunctionCall {
+                function,
+                args,
+                named_args,
+                ..
+            } if named_args.is_empty() && matches!(args.len(), 1 | 2) => match &**function {
+                Expr::Variable { name, .. } if name == "range" => Some(args),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Split the callee of a call through an interface, e.g.
+    /// `IToken(token, value: 5).transfer`
+    pub fn as_interface_message(&self) -> Option<InterfaceMessageTarget<'_>> {
+        match self {
+            Expr::FieldAccess { object, field, .. } => match &**object {
+                Expr::FunctionCall {
+                    function,
+                    args,
+                    named_args,
+                    ..
+                } => match &**function {
+                    Expr::Variable { name, .. } => Some(InterfaceMessageTarget {
+                        interface: *name,
+                        accounts: args,
+                        options: named_args,
+                        message: field,
+                    }),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Split a constructor of a type variant, e.g. `Error/Paused` or
+    /// `Error/InsufficientBalance(needed, available)`, into the qualified
+    /// variant name and its arguments
+    pub fn as_variant_constructor(&self) -> Option<(Symbol, &[Expr])> {
+        match self {
+            Expr::Variable { name, .. } if name.contains('/') => Some((*name, &[])),
+            Expr::FunctionCall { function, args, .. } => match &**function {
+                Expr::Variable { name, .. } if name.contains('/') => Some((*name, args.as_slice())),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Named arguments of a call or constructor in name order, so passes that
+/// number temporaries as they go produce the same code on every build
+pub fn named_args_in_order(named_args: &HashMap<String, Expr>) -> Vec<(&String, &Expr)> {
+    let mut named: Vec<_> = named_args.iter().collect();
+    named.sort_by(|a, b| a.0.cmp(b.0));
+    named
+}
+
+/// Mutable named arguments in name order, see [`named_args_in_order`]
+pub fn named_args_in_order_mut(named_args: &mut HashMap<String, Expr>) -> Vec<&mut Expr> {
+    let mut named: Vec<_> = named_args.iter_mut().collect();
+    named.sort_by(|a, b| a.0.cmp(b.0));
+    named.into_iter().map(|(_, arg)| arg).collect()
+}
+
+impl Statement {
+    /// Blocks directly nested in a statement, e.g. the branches of an `if`
+    pub fn nested_blocks(&self) -> Vec<&Block> {
+        match self {
+            Statement::If {
+                then_branch,
+                else_branch,
+                ..
+            } => vec![then_branch, else_branch],
+            Statement::While { body, .. } | Statement::For { body, .. } => vec![body],
+            Statement::Switch { cases, .. } => cases.iter().map(|case| &case.body).collect(),
+            Statement::Match { cases, .. } | Statement::Fold { cases, .. } => {
+                cases.iter().map(|case| &case.body).collect()
+            }
+            Statement::Bend {
+                body, else_body, ..
+            } => std::iter::once(body).chain(else_body).collect(),
+            Statement::With { body, .. } | Statement::Unchecked { body, .. } => vec![body],
+            Statement::TryCatch {
+                try_block,
+                catch_blocks,
+                ..
+            } => std::iter::once(try_block)
+                .chain(catch_blocks.iter().map(|catch| &catch.body))
+                .collect(),
+            _ => Vec::new(),
         }
     }
 }
@@ -499,14 +918,20 @@ impl LocationProvider for Expr {
             Expr::Lambda { location, .. } => location,
             Expr::UnsccopedLambda { location, .. } => location,
             Expr::BinaryOp { location, .. } => location,
+            Expr::UnaryOp { location, .. } => location,
             Expr::FieldAccess { location, .. } => location,
             Expr::Superposition { location, .. } => location,
+            Expr::Map { location, .. } => location,
             Expr::MapAccess { location, .. } => location,
             Expr::TreeLeaf { location, .. } => location,
             Expr::TreeNode { location, .. } => location,
             Expr::If { location, .. } => location,
             Expr::Block { location, .. } => location,
             Expr::Eraser { location } => location,
+            Expr::Try { location, .. } => location,
+            Expr::ListComprehension { location, .. } => location,
+            Expr::MapComprehension { location, .. } => location,
+            Expr::InlineAsm { location, .. } => location,
         }
     }
 }
@@ -519,15 +944,35 @@ impl LocationProvider for Statement {
             Statement::InPlaceOp { location, .. } => location,
             Statement::Return { location, .. } => location,
             Statement::If { location, .. } => location,
+            Statement::While { location, .. } => location,
+            Statement::For { location, .. } => location,
             Statement::Switch { location, .. } => location,
             Statement::Match { location, .. } => location,
             Statement::Fold { location, .. } => location,
             Statement::Bend { location, .. } => location,
             Statement::Open { location, .. } => location,
             Statement::With { location, .. } => location,
+            Statement::Unchecked { location, .. } => location,
             Statement::LocalDef { location, .. } => location,
             Statement::Expr { location, .. } => location,
             Statement::TryCatch { location, .. } => location,
+            Statement::Emit { location, .. } => location,
+            Statement::Revert { location, .. } => location,
+        }
+    }
+}
+
+impl LocationProvider for Pattern {
+    fn location(&self) -> &Location {
+        match self {
+            Pattern::Variable { location, .. } => location,
+            Pattern::Tuple { location, .. } => location,
+            Pattern::Constructor { location, .. } => location,
+            Pattern::TupleConstructor { location, .. } => location,
+            Pattern::Literal { location, .. } => location,
+            Pattern::Member { location, .. } => location,
+            Pattern::MapAccess { location, .. } => location,
+            Pattern::Wildcard { location } => location,
         }
     }
 }
@@ -595,11 +1040,11 @@ impl AstVisitor for BasicAstVisitor {
 #[derive(Debug, Clone, PartialEq)]
 pub enum AstValidationError {
     DuplicateDefinition {
-        name: String,
+        name: Symbol,
         location: Location,
     },
     UndefinedVariable {
-        name: String,
+        name: Symbol,
         location: Location,
     },
     TypeMismatch {
@@ -616,11 +1061,11 @@ pub enum AstValidationError {
         location: Location,
     },
     DuplicateField {
-        name: String,
+        name: Symbol,
         location: Location,
     },
     MissingField {
-        name: String,
+        name: Symbol,
         location: Location,
     },
 }
@@ -667,12 +1112,29 @@ impl AstValidator {
             Definition::FunctionDef { body, .. } => {
                 self.validate_block(body, errors);
             }
-            Definition::TypeDef { variants, .. } => {
+            Definition::GuardDef { before, after, .. } => {
+                self.validate_block(before, errors);
+                self.validate_block(after, errors);
+            }
+            Definition::InterfaceDef { functions, .. } => {
+                let mut function_names = std::collections::HashSet::new();
+                for function in functions {
+                    if let Definition::FunctionDef { name, location, .. } = function {
+                        if !function_names.insert(*name) {
+                            errors.push(AstValidationError::DuplicateDefinition {
+                                name: *name,
+                                location: location.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+            Definition::TypeDef { variants, .. } | Definition::ErrorDef { variants, .. } => {
                 let mut variant_names = std::collections::HashSet::new();
                 for variant in variants {
-                    if !variant_names.insert(variant.name.clone()) {
+                    if !variant_names.insert(variant.name) {
                         errors.push(AstValidationError::DuplicateDefinition {
-                            name: variant.name.clone(),
+                            name: variant.name,
                             location: variant.location.clone(),
                         });
                     }
@@ -681,9 +1143,9 @@ impl AstValidator {
             Definition::ObjectDef { fields, .. } => {
                 let mut field_names = std::collections::HashSet::new();
                 for field in fields {
-                    if !field_names.insert(field.name.clone()) {
+                    if !field_names.insert(field.name) {
                         errors.push(AstValidationError::DuplicateField {
-                            name: field.name.clone(),
+                            name: field.name,
                             location: field.location.clone(),
                         });
                     }
@@ -692,17 +1154,65 @@ impl AstValidator {
             Definition::TypeAlias { target_type, .. } => {
                 self.validate_type(target_type, errors);
             }
+            Definition::EventDef { fields, .. } => {
+                let mut field_names = std::collections::HashSet::new();
+                for field in fields {
+                    if !field_names.insert(field.name) {
+                        errors.push(AstValidationError::DuplicateField {
+                            name: field.name,
+                            location: field.location.clone(),
+                        });
+                    }
+                    self.validate_type(&field.ty, errors);
+                }
+            }
+            Definition::StorageDef { fields, .. } => {
+                let mut field_names = std::collections::HashSet::new();
+                for field in fields {
+                    if !field_names.insert(field.name) {
+                        errors.push(AstValidationError::DuplicateField {
+                            name: field.name,
+                            location: field.location.clone(),
+                        });
+                    }
+                    self.validate_type(&field.ty, errors);
+                }
+            }
+            Definition::ContractDef {
+                fields,
+                definitions,
+                ..
+            } => {
+                let mut field_names = std::collections::HashSet::new();
+                for field in fields {
+                    if !field_names.insert(field.name) {
+                        errors.push(AstValidationError::DuplicateField {
+                            name: field.name,
+                            location: field.location.clone(),
+                        });
+                    }
+                    self.validate_type(&field.ty, errors);
+                }
+                for def in definitions {
+                    self.validate_definition(def, _definitions, errors);
+                }
+            }
             Definition::Module { definitions, .. } => {
                 let mut def_names = std::collections::HashSet::new();
                 for def in definitions {
                     let def_name = match def {
-                        Definition::FunctionDef { name, .. } => name.clone(),
-                        Definition::TypeDef { name, .. } => name.clone(),
-                        Definition::ObjectDef { name, .. } => name.clone(),
-                        Definition::TypeAlias { name, .. } => name.clone(),
-                        Definition::Module { .. } => continue,
+                        Definition::FunctionDef { name, .. } => *name,
+                        Definition::TypeDef { name, .. } => *name,
+                        Definition::ObjectDef { name, .. } => *name,
+                        Definition::TypeAlias { name, .. } => *name,
+                        Definition::EventDef { name, .. } => *name,
+                        Definition::ErrorDef { name, .. } => *name,
+                        Definition::GuardDef { name, .. } => *name,
+                        Definition::InterfaceDef { name, .. } => *name,
+                        Definition::ContractDef { name, .. } => *name,
+                        Definition::Module { .. } | Definition::StorageDef { .. } => continue,
                     };
-                    if !def_names.insert(def_name.clone()) {
+                    if !def_names.insert(def_name) {
                         errors.push(AstValidationError::DuplicateDefinition {
                             name: def_name,
                             location: def.location().clone(),
@@ -746,6 +1256,19 @@ impl AstValidator {
                 self.validate_block(then_branch, errors);
                 self.validate_block(else_branch, errors);
             }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.validate_expression(condition, errors);
+                self.validate_block(body, errors);
+            }
+            Statement::For {
+                start, end, body, ..
+            } => {
+                self.validate_expression(start, errors);
+                self.validate_expression(end, errors);
+                self.validate_block(body, errors);
+            }
             Statement::Match {
                 value,
                 cases,
@@ -777,6 +1300,9 @@ impl AstValidator {
             } => {
                 self.validate_block(body, errors);
             }
+            Statement::Unchecked { body, .. } => {
+                self.validate_block(body, errors);
+            }
             Statement::LocalDef {
                 function_def,
                 location: _,
@@ -790,6 +1316,18 @@ impl AstValidator {
             Statement::Expr { expr, location: _ } => {
                 self.validate_expression(expr, errors);
             }
+            Statement::Emit { args, .. } => {
+                for arg in args {
+                    self.validate_expression(arg, errors);
+                }
+            }
+            Statement::Revert {
+                condition, reason, ..
+            } => {
+                for expr in condition.iter().chain(reason) {
+                    self.validate_expression(expr, errors);
+                }
+            }
             _ => {}
         }
     }
@@ -821,6 +1359,35 @@ impl AstValidator {
             } => {
                 self.validate_expression(body, errors);
             }
+            Expr::Try { expr, .. } => {
+                self.validate_expression(expr, errors);
+            }
+            Expr::ListComprehension {
+                element,
+                iterable,
+                condition,
+                ..
+            } => {
+                for expr in [element, iterable].into_iter().chain(condition) {
+                    self.validate_expression(expr, errors);
+                }
+            }
+            Expr::MapComprehension {
+                key,
+                value,
+                iterable,
+                condition,
+                ..
+            } => {
+                for expr in [key, value, iterable].into_iter().chain(condition) {
+                    self.validate_expression(expr, errors);
+                }
+            }
+            Expr::InlineAsm { inputs, .. } => {
+                for (_, expr) in inputs {
+                    self.validate_expression(expr, errors);
+                }
+            }
             _ => {}
         }
     }
@@ -4,7 +4,8 @@ use std::collections::HashMap;
 
 use super::ast::*;
 
-use crate::compiler::lexer::lexer::{BendLexer, TokenWithPosition};
+use crate::compiler::contract;
+use crate::compiler::lexer::lexer::{TokenBuffer, TokenWithPosition};
 use crate::compiler::lexer::token::Token;
 use thiserror::Error;
 
@@ -21,8 +22,12 @@ pub enum ParseError {
     #[error("Unexpected end of input, expected {expected}")]
     UnexpectedEOF { expected: String },
 
-    #[error("Lexical error: {0}")]
-    LexicalError(String),
+    #[error("Lexical error: {message} at line {line}, column {column}")]
+    LexicalError {
+        message: String,
+        line: usize,
+        column: usize,
+    },
 
     #[error("Invalid pattern: {0}")]
     InvalidPattern(String),
@@ -32,28 +37,45 @@ pub enum ParseError {
 }
 
 pub struct Parser<'a> {
-    lexer: BendLexer<'a>,
+    tokens: TokenBuffer<'a>,
+    /// Index in `tokens` of the token after the peek token
+    next: usize,
     current_token: TokenWithPosition,
     peek_token: TokenWithPosition,
+    /// Whether `contract` declarations are kept instead of lowered
+    keep_contracts: bool,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(source: &'a str) -> Self {
-        let mut lexer = BendLexer::new(source);
-        let current_token = lexer.next_token();
-        let peek_token = lexer.next_token();
+        let tokens = TokenBuffer::new(source);
+        let last = tokens.len() - 1;
+        let current_token = tokens.token_with_position(0);
+        let peek_token = tokens.token_with_position(last.min(1));
 
         Parser {
-            lexer,
+            tokens,
+            next: last.min(2),
             current_token,
             peek_token,
+            keep_contracts: false,
         }
     }
 
-    /// Advance to the next token
-    fn advance(&mut self) {
-        self.current_token = self.peek_token.clone();
-        self.peek_token = self.lexer.next_token();
+    /// Keep `contract` declarations as written instead of lowering them to
+    /// storage and top-level definitions, for tools printing the source back
+    pub fn keep_contracts(mut self) -> Self {
+        self.keep_contracts = true;
+        self
+    }
+
+    /// Advance to the next token, returning the one advanced past
+    fn advance(&mut self) -> TokenWithPosition {
+        // Past the end, the last token, `EOF`, is read again
+        let token = self.tokens.token_with_position(self.next);
+        self.next = (self.next + 1).min(self.tokens.len() - 1);
+        let next = std::mem::replace(&mut self.peek_token, token);
+        std::mem::replace(&mut self.current_token, next)
     }
 
     /// Check if the current token matches the expected token
@@ -74,16 +96,49 @@ impl<'a> Parser<'a> {
     /// Expect and consume a token, or return an error
     fn expect(&mut self, expected: Token) -> Result<TokenWithPosition, ParseError> {
         if self.check(&expected) {
-            let token = self.current_token.clone();
-            self.advance();
-            Ok(token)
+            Ok(self.advance())
+        } else {
+            Err(self.unexpected(&Self::describe(&expected)))
+        }
+    }
+
+    /// How an error names the token `expect` was given. The identifier it is
+    /// given is only a placeholder, so it is named by its kind.
+    fn describe(expected: &Token) -> String {
+        match expected {
+            Token::Identifier(_) => "identifier".to_string(),
+            token => token.to_string(),
+        }
+    }
+
+    /// Expect and consume the name of a field, or return an error
+    fn expect_field(&mut self) -> Result<TokenWithPosition, ParseError> {
+        if self.check(&Token::Identifier(Symbol::default())) {
+            Ok(self.advance())
         } else {
-            Err(ParseError::UnexpectedToken {
-                found: self.current_token.token.to_string(),
+            Err(self.unexpected("field name"))
+        }
+    }
+
+    /// The error for finding the current token where `expected` should be.
+    /// A token the lexer could not read is reported as what it is.
+    fn unexpected(&self, expected: &str) -> ParseError {
+        let (line, column) = (self.current_token.line, self.current_token.column);
+        match &self.current_token.token {
+            Token::Error(message) => ParseError::LexicalError {
+                message: message.clone(),
+                line,
+                column,
+            },
+            Token::EOF => ParseError::UnexpectedEOF {
                 expected: expected.to_string(),
-                line: self.current_token.line,
-                column: self.current_token.column,
-            })
+            },
+            found => ParseError::UnexpectedToken {
+                found: found.to_string(),
+                expected: expected.to_string(),
+                line,
+                column,
+            },
         }
     }
 
@@ -103,7 +158,12 @@ impl<'a> Parser<'a> {
 
         // Parse top-level definitions
         while !self.check(&Token::EOF) {
-            definitions.push(self.parse_definition()?);
+            let definition = self.parse_definition()?;
+            if self.keep_contracts {
+                definitions.push(definition);
+            } else {
+                definitions.extend(contract::lower(definition)?);
+            }
         }
 
         let end = self.current_token.end;
@@ -137,9 +197,9 @@ impl<'a> Parser<'a> {
         let start_column = token.column;
 
         // Parse the module path
-        let path_token = self.expect(Token::Identifier(String::new()))?;
+        let path_token = self.expect(Token::Identifier(Symbol::default()))?;
         let path = match &path_token.token {
-            Token::Identifier(s) => s.clone(),
+            Token::Identifier(s) => s.to_string(),
             _ => unreachable!(),
         };
 
@@ -151,7 +211,7 @@ impl<'a> Parser<'a> {
             // From X import *
             self.advance();
             vec![ImportName {
-                name: "*".to_string(),
+                name: "*".into(),
                 alias: None,
                 location: Location {
                     line: self.current_token.line,
@@ -166,9 +226,9 @@ impl<'a> Parser<'a> {
             let mut names = Vec::new();
 
             loop {
-                let name_token = self.expect(Token::Identifier(String::new()))?;
+                let name_token = self.expect(Token::Identifier(Symbol::default()))?;
                 let name = match &name_token.token {
-                    Token::Identifier(s) => s.clone(),
+                    Token::Identifier(s) => *s,
                     _ => unreachable!(),
                 };
 
@@ -177,9 +237,9 @@ impl<'a> Parser<'a> {
                 // Check for alias
                 if self.check(&Token::As) {
                     self.advance();
-                    let alias_token = self.expect(Token::Identifier(String::new()))?;
+                    let alias_token = self.expect(Token::Identifier(Symbol::default()))?;
                     alias = match &alias_token.token {
-                        Token::Identifier(s) => Some(s.clone()),
+                        Token::Identifier(s) => Some(*s),
                         _ => unreachable!(),
                     };
                 }
@@ -214,9 +274,9 @@ impl<'a> Parser<'a> {
             let mut names = Vec::new();
 
             loop {
-                let name_token = self.expect(Token::Identifier(String::new()))?;
+                let name_token = self.expect(Token::Identifier(Symbol::default()))?;
                 let name = match &name_token.token {
-                    Token::Identifier(s) => s.clone(),
+                    Token::Identifier(s) => *s,
                     _ => unreachable!(),
                 };
 
@@ -225,9 +285,9 @@ impl<'a> Parser<'a> {
                 // Check for alias
                 if self.check(&Token::As) {
                     self.advance();
-                    let alias_token = self.expect(Token::Identifier(String::new()))?;
+                    let alias_token = self.expect(Token::Identifier(Symbol::default()))?;
                     alias = match &alias_token.token {
-                        Token::Identifier(s) => Some(s.clone()),
+                        Token::Identifier(s) => Some(*s),
                         _ => unreachable!(),
                     };
                 }
@@ -279,9 +339,9 @@ impl<'a> Parser<'a> {
         let mut names = Vec::new();
 
         loop {
-            let name_token = self.expect(Token::Identifier(String::new()))?;
+            let name_token = self.expect(Token::Identifier(Symbol::default()))?;
             let name = match &name_token.token {
-                Token::Identifier(s) => s.clone(),
+                Token::Identifier(s) => s.to_string(),
                 _ => unreachable!(),
             };
 
@@ -310,45 +370,289 @@ impl<'a> Parser<'a> {
 
     /// Parse a top-level definition
     fn parse_definition(&mut self) -> Result<Definition, ParseError> {
+        if self.check(&Token::HashBracket) {
+            let attributes = self.parse_attributes()?;
+            let definition = self.parse_definition()?;
+            return self.attach_attributes(definition, attributes);
+        }
+
+        if self.check(&Token::Pub) {
+            self.advance();
+            let definition = self.parse_definition()?;
+            return self.make_public(definition);
+        }
+
         let token = self.current_token.token.clone();
         match token {
-            Token::Fn => self.parse_function_def(),
+            Token::Fn | Token::Checked | Token::Unchecked => self.parse_function_def(),
             Token::Type => self.parse_type_def(),
             Token::Object => self.parse_object_def(),
             Token::Contract => self.parse_contract_def(),
             Token::Interface => self.parse_interface_def(),
             Token::Library => self.parse_library_def(),
-            _ => Err(ParseError::UnexpectedToken {
-                found: self.current_token.token.to_string(),
-                expected: "definition keyword".to_string(),
-                line: self.current_token.line,
-                column: self.current_token.column,
-            }),
+            Token::Event => self.parse_event_def(),
+            Token::ErrorType => self.parse_error_def(),
+            Token::Storage => self.parse_storage_def(),
+            Token::Guard => self.parse_guard_def(),
+            _ => Err(self.unexpected("definition keyword")),
+        }
+    }
+
+    /// Parse the attributes preceding a definition
+    ///
+    /// ```text
+    /// #[payable]
+    /// #[selector(0xa9059cbb), inline(always)]
+    /// ```
+    fn parse_attributes(&mut self) -> Result<Vec<Attribute>, ParseError> {
+        let mut attributes = Vec::new();
+
+        while self.check(&Token::HashBracket) {
+            self.advance();
+
+            loop {
+                // `guard` is a keyword, but also names the attribute
+                // applying guards
+                let name_token = if self.check(&Token::Guard) {
+                    self.expect(Token::Guard)?
+                } else {
+                    self.expect(Token::Identifier(Symbol::default()))?
+                };
+                let name = match &name_token.token {
+                    Token::Identifier(s) => *s,
+                    Token::Guard => "guard".into(),
+                    _ => unreachable!(),
+                };
+
+                let mut args = Vec::new();
+                if name == "cfg" {
+                    args = self.parse_cfg_predicates()?;
+                } else if self.check(&Token::LParen) {
+                    self.advance();
+                    while !self.check(&Token::RParen) {
+                        args.push(self.parse_expression()?);
+                        if !self.check(&Token::RParen) {
+                            self.expect(Token::Comma)?;
+                        }
+                    }
+                    self.expect(Token::RParen)?;
+                }
+
+                attributes.push(Attribute {
+                    name,
+                    args,
+                    location: Location {
+                        line: name_token.line,
+                        column: name_token.column,
+                        start: name_token.start,
+                        end: self.current_token.end,
+                    },
+                });
+
+                if !self.check(&Token::Comma) {
+                    break;
+                }
+                self.advance();
+            }
+
+            self.expect(Token::RBracket)?;
+        }
+
+        Ok(attributes)
+    }
+
+    /// Parse the parenthesized predicates of `#[cfg(...)]`: `test`,
+    /// `feature = "name"`, and `not`, `any` and `all` of other predicates.
+    /// `name = value` becomes an `==` comparison and the combinators calls.
+    fn parse_cfg_predicates(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut predicates = Vec::new();
+        if self.check(&Token::LParen) {
+            self.advance();
+            while !self.check(&Token::RParen) {
+                predicates.push(self.parse_cfg_predicate()?);
+                if !self.check(&Token::RParen) {
+                    self.expect(Token::Comma)?;
+                }
+            }
+            self.expect(Token::RParen)?;
+        }
+        Ok(predicates)
+    }
+
+    fn parse_cfg_predicate(&mut self) -> Result<Expr, ParseError> {
+        let token = self.current_token.clone();
+        let name = match &token.token {
+            Token::Identifier(name) => name.to_string(),
+            // `not` is lexed as `!`
+            Token::Bang => "not".to_string(),
+            _ => return Err(self.unexpected("cfg predicate")),
+        };
+        self.advance();
+        let location = |end| Location {
+            line: token.line,
+            column: token.column,
+            start: token.start,
+            end,
+        };
+        let variable = Expr::Variable {
+            name: name.into(),
+            location: location(token.end),
+        };
+
+        if self.check(&Token::Equal) {
+            self.advance();
+            let value = self.parse_primary_expression()?;
+            return Ok(Expr::BinaryOp {
+                left: Box::new(variable),
+                operator: BinaryOperator::Equal,
+                location: location(value.location().end),
+                right: Box::new(value),
+            });
+        }
+        if self.check(&Token::LParen) {
+            let args = self.parse_cfg_predicates()?;
+            return Ok(Expr::FunctionCall {
+                function: Box::new(variable),
+                args,
+                named_args: HashMap::new(),
+                location: location(self.current_token.start),
+            });
+        }
+        Ok(variable)
+    }
+
+    /// Attach parsed attributes to the definition that follows them
+    fn attach_attributes(
+        &self,
+        mut definition: Definition,
+        parsed: Vec<Attribute>,
+    ) -> Result<Definition, ParseError> {
+        match &mut definition {
+            Definition::FunctionDef { attributes, .. }
+            | Definition::TypeDef { attributes, .. }
+            | Definition::ObjectDef { attributes, .. }
+            | Definition::StorageDef { attributes, .. }
+            | Definition::ContractDef { attributes, .. } => {
+                attributes.extend(parsed);
+                Ok(definition)
+            }
+            _ => {
+                let location = definition.location();
+                Err(ParseError::Generic(format!(
+                    "Attributes are only allowed on functions, types, objects, storage and contracts (line {}, column {})",
+                    location.line, location.column
+                )))
+            }
+        }
+    }
+
+    /// Mark the definition following `pub` as exported
+    fn make_public(&self, mut definition: Definition) -> Result<Definition, ParseError> {
+        match &mut definition {
+            Definition::FunctionDef { visibility, .. }
+            | Definition::TypeDef { visibility, .. }
+            | Definition::ObjectDef { visibility, .. }
+            | Definition::TypeAlias { visibility, .. }
+            | Definition::EventDef { visibility, .. }
+            | Definition::ErrorDef { visibility, .. }
+            | Definition::InterfaceDef { visibility, .. } => {
+                if *visibility == Visibility::Public {
+                    let location = definition.location();
+                    return Err(ParseError::Generic(format!(
+                        "Duplicate `pub` (line {}, column {})",
+                        location.line, location.column
+                    )));
+                }
+                *visibility = Visibility::Public;
+                Ok(definition)
+            }
+            _ => {
+                let location = definition.location();
+                Err(ParseError::Generic(format!(
+                    "Storage, guards and contracts cannot be public (line {}, column {})",
+                    location.line, location.column
+                )))
+            }
         }
     }
 
-    /// Parse a function definition
+    /// Parse a function definition, optionally preceded by a `checked` or
+    /// `unchecked` modifier
     fn parse_function_def(&mut self) -> Result<Definition, ParseError> {
-        let token = self.expect(Token::Fn)?;
-        let start = token.start;
-        let start_line = token.line;
-        let start_column = token.column;
+        let start = self.current_token.start;
+        let start_line = self.current_token.line;
+        let start_column = self.current_token.column;
+
+        let checked = match self.current_token.token {
+            Token::Checked => Some(true),
+            Token::Unchecked => Some(false),
+            _ => None,
+        };
+        if checked.is_some() {
+            self.advance();
+        }
+        self.expect(Token::Fn)?;
 
         // Parse function name
-        let name_token = self.expect(Token::Identifier(String::new()))?;
+        let name_token = self.expect(Token::Identifier(Symbol::default()))?;
         let name = match &name_token.token {
-            Token::Identifier(s) => s.clone(),
+            Token::Identifier(s) => *s,
             _ => unreachable!(),
         };
 
         // Parse parameters
+        let params = self.parse_parameters()?;
+
+        // Parse return type (optional)
+        let return_type = if self.check(&Token::Arrow) {
+            self.advance();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        // Parse function body
+        let body = if self.check(&Token::LBrace) {
+            self.parse_block()?
+        } else {
+            // External function declaration
+            Block {
+                statements: Vec::new(),
+                location: Location {
+                    line: self.current_token.line,
+                    column: self.current_token.column,
+                    start: self.current_token.start,
+                    end: self.current_token.end,
+                },
+            }
+        };
+
+        Ok(Definition::FunctionDef {
+            name,
+            params,
+            return_type,
+            body,
+            checked,
+            attributes: Vec::new(),
+            visibility: Visibility::Private,
+            location: Location {
+                line: start_line,
+                column: start_column,
+                start,
+                end: self.current_token.end,
+            },
+        })
+    }
+
+    /// Parse a parenthesized list of typed parameters
+    fn parse_parameters(&mut self) -> Result<Vec<Parameter>, ParseError> {
         self.expect(Token::LParen)?;
         let mut params = Vec::new();
 
         while !self.check(&Token::RParen) {
-            let param_name_token = self.expect(Token::Identifier(String::new()))?;
+            let param_name_token = self.expect(Token::Identifier(Symbol::default()))?;
             let param_name = match &param_name_token.token {
-                Token::Identifier(s) => s.clone(),
+                Token::Identifier(s) => *s,
                 _ => unreachable!(),
             };
 
@@ -372,37 +676,90 @@ impl<'a> Parser<'a> {
         }
 
         self.expect(Token::RParen)?;
+        Ok(params)
+    }
 
-        // Parse return type (optional)
-        let return_type = if self.check(&Token::Arrow) {
-            self.advance();
-            Some(self.parse_type()?)
-        } else {
-            None
+    /// Parse a guard definition
+    ///
+    /// The `_;` placeholder marks where the body of a guarded function
+    /// runs and must appear exactly once, at the top level of the guard.
+    ///
+    /// ```text
+    /// guard only_owner() {
+    ///     require(caller() == owner, "not the owner");
+    ///     _;
+    /// }
+    /// ```
+    fn parse_guard_def(&mut self) -> Result<Definition, ParseError> {
+        let token = self.expect(Token::Guard)?;
+        let start = token.start;
+        let start_line = token.line;
+        let start_column = token.column;
+
+        let name_token = self.expect(Token::Identifier(Symbol::default()))?;
+        let name = match &name_token.token {
+            Token::Identifier(s) => *s,
+            _ => unreachable!(),
         };
 
-        // Parse function body
-        let body = if self.check(&Token::LBrace) {
-            self.parse_block()?
-        } else {
-            // External function declaration
-            Block {
-                statements: Vec::new(),
-                location: Location {
-                    line: self.current_token.line,
-                    column: self.current_token.column,
-                    start: self.current_token.start,
-                    end: self.current_token.end,
-                },
+        let params = self.parse_parameters()?;
+
+        let body_start = self.expect(Token::LBrace)?;
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        let mut placeholder = None;
+
+        while !self.check(&Token::RBrace) && !self.check(&Token::EOF) {
+            if self.check(&Token::Underscore) {
+                let token = self.current_token.clone();
+                if placeholder.is_some() {
+                    return Err(ParseError::Generic(format!(
+                        "Guard '{}' has more than one `_` placeholder (line {}, column {})",
+                        name, token.line, token.column
+                    )));
+                }
+                self.advance();
+                if self.check(&Token::Semicolon) {
+                    self.advance();
+                }
+                placeholder = Some(token);
+            } else if placeholder.is_some() {
+                after.push(self.parse_statement()?);
+            } else {
+                before.push(self.parse_statement()?);
             }
-        };
+        }
 
-        Ok(Definition::FunctionDef {
+        self.expect(Token::RBrace)?;
+
+        let placeholder = placeholder.ok_or_else(|| {
+            ParseError::Generic(format!(
+                "Guard '{}' is missing the `_` placeholder for the guarded body (line {}, column {})",
+                name, start_line, start_column
+            ))
+        })?;
+
+        Ok(Definition::GuardDef {
             name,
             params,
-            return_type,
-            body,
-            checked: None,
+            before: Block {
+                statements: before,
+                location: Location {
+                    line: body_start.line,
+                    column: body_start.column,
+                    start: body_start.start,
+                    end: placeholder.start,
+                },
+            },
+            after: Block {
+                statements: after,
+                location: Location {
+                    line: placeholder.line,
+                    column: placeholder.column,
+                    start: placeholder.end,
+                    end: self.current_token.end,
+                },
+            },
             location: Location {
                 line: start_line,
                 column: start_column,
@@ -420,9 +777,9 @@ impl<'a> Parser<'a> {
         let start_column = token.column;
 
         // Parse type name
-        let name_token = self.expect(Token::Identifier(String::new()))?;
+        let name_token = self.expect(Token::Identifier(Symbol::default()))?;
         let name = match &name_token.token {
-            Token::Identifier(s) => s.clone(),
+            Token::Identifier(s) => *s,
             _ => unreachable!(),
         };
 
@@ -434,13 +791,33 @@ impl<'a> Parser<'a> {
         };
 
         // Parse type body
+        let variants = self.parse_type_variants()?;
+
+        Ok(Definition::TypeDef {
+            name,
+            type_params,
+            variants,
+            attributes: Vec::new(),
+            visibility: Visibility::Private,
+            location: Location {
+                line: start_line,
+                column: start_column,
+                start,
+                end: self.current_token.end,
+            },
+        })
+    }
+
+    /// Parse the braced variants of a type or error definition, e.g.
+    /// `{ Paused, InsufficientBalance(needed: u24, available: u24) }`
+    fn parse_type_variants(&mut self) -> Result<Vec<TypeVariant>, ParseError> {
         self.expect(Token::LBrace)?;
         let mut variants = Vec::new();
 
         while !self.check(&Token::RBrace) && !self.check(&Token::EOF) {
-            let variant_name_token = self.expect(Token::Identifier(String::new()))?;
+            let variant_name_token = self.expect(Token::Identifier(Symbol::default()))?;
             let variant_name = match &variant_name_token.token {
-                Token::Identifier(s) => s.clone(),
+                Token::Identifier(s) => *s,
                 _ => unreachable!(),
             };
 
@@ -452,11 +829,11 @@ impl<'a> Parser<'a> {
                 while !self.check(&Token::RParen) {
                     // Check if we have a field name followed by colon (field: Type)
                     // or just a type directly (Type)
-                    if self.check(&Token::Identifier(String::new())) && self.peek_is_colon() {
+                    if self.check(&Token::Identifier(Symbol::default())) && self.peek_is_colon() {
                         // Field name with type annotation: field: Type
-                        let field_name_token = self.expect(Token::Identifier(String::new()))?;
+                        let field_name_token = self.expect(Token::Identifier(Symbol::default()))?;
                         let field_name = match &field_name_token.token {
-                            Token::Identifier(s) => s.clone(),
+                            Token::Identifier(s) => *s,
                             _ => unreachable!(),
                         };
 
@@ -468,17 +845,17 @@ impl<'a> Parser<'a> {
                             type_annotation: Some(field_type),
                             is_recursive: false,
                             location: Location {
-                                line: self.current_token.line,
-                                column: self.current_token.column,
-                                start: self.current_token.start,
-                                end: self.current_token.end,
+                                line: field_name_token.line,
+                                column: field_name_token.column,
+                                start: field_name_token.start,
+                                end: field_name_token.end,
                             },
                         });
                     } else {
                         // Just type without field name: Type
                         let field_type = self.parse_type()?;
                         fields.push(Field {
-                            name: "_".to_string(),
+                            name: "_".into(),
                             type_annotation: Some(field_type),
                             is_recursive: false,
                             location: Location {
@@ -515,10 +892,32 @@ impl<'a> Parser<'a> {
 
         self.expect(Token::RBrace)?;
 
-        Ok(Definition::TypeDef {
+        Ok(variants)
+    }
+
+    /// Parse an error definition
+    ///
+    /// ```text
+    /// error TokenError { Paused, InsufficientBalance(needed: u24, available: u24) }
+    /// ```
+    fn parse_error_def(&mut self) -> Result<Definition, ParseError> {
+        let token = self.expect(Token::ErrorType)?;
+        let start = token.start;
+        let start_line = token.line;
+        let start_column = token.column;
+
+        let name_token = self.expect(Token::Identifier(Symbol::default()))?;
+        let name = match &name_token.token {
+            Token::Identifier(s) => *s,
+            _ => unreachable!(),
+        };
+
+        let variants = self.parse_type_variants()?;
+
+        Ok(Definition::ErrorDef {
             name,
-            type_params,
             variants,
+            visibility: Visibility::Private,
             location: Location {
                 line: start_line,
                 column: start_column,
@@ -536,9 +935,9 @@ impl<'a> Parser<'a> {
         let start_column = token.column;
 
         // Parse object name
-        let name_token = self.expect(Token::Identifier(String::new()))?;
+        let name_token = self.expect(Token::Identifier(Symbol::default()))?;
         let name = match &name_token.token {
-            Token::Identifier(s) => s.clone(),
+            Token::Identifier(s) => *s,
             _ => unreachable!(),
         };
 
@@ -557,15 +956,14 @@ impl<'a> Parser<'a> {
         while !self.check(&Token::RBrace) && !self.check(&Token::EOF) {
             if self.check(&Token::Let) {
                 fields.push(self.parse_field()?);
+            } else if self.check(&Token::HashBracket) {
+                let attributes = self.parse_attributes()?;
+                let function = self.parse_function_def()?;
+                functions.push(self.attach_attributes(function, attributes)?);
             } else if self.check(&Token::Fn) {
                 functions.push(self.parse_function_def()?);
             } else {
-                return Err(ParseError::UnexpectedToken {
-                    found: self.current_token.token.to_string(),
-                    expected: "field or function".to_string(),
-                    line: self.current_token.line,
-                    column: self.current_token.column,
-                });
+                return Err(self.unexpected("field or function"));
             }
         }
 
@@ -576,6 +974,81 @@ impl<'a> Parser<'a> {
             type_params,
             fields,
             functions,
+            attributes: Vec::new(),
+            visibility: Visibility::Private,
+            location: Location {
+                line: start_line,
+                column: start_column,
+                start,
+                end: self.current_token.end,
+            },
+        })
+    }
+
+    /// Parse an event definition
+    ///
+    /// ```text
+    /// event Transfer { indexed sender: u24, indexed receiver: u24, amount: u24 }
+    /// ```
+    fn parse_event_def(&mut self) -> Result<Definition, ParseError> {
+        let token = self.expect(Token::Event)?;
+        let start = token.start;
+        let start_line = token.line;
+        let start_column = token.column;
+
+        // Parse event name
+        let name_token = self.expect(Token::Identifier(Symbol::default()))?;
+        let name = match &name_token.token {
+            Token::Identifier(s) => *s,
+            _ => unreachable!(),
+        };
+
+        // Parse event fields
+        self.expect(Token::LBrace)?;
+        let mut fields = Vec::new();
+
+        while !self.check(&Token::RBrace) && !self.check(&Token::EOF) {
+            let field_start = self.current_token.clone();
+
+            // `indexed` is only a keyword when followed by a field name
+            let indexed = matches!(&self.current_token.token, Token::Identifier(s) if s == "indexed")
+                && matches!(self.peek_token.token, Token::Identifier(_));
+            if indexed {
+                self.advance();
+            }
+
+            let field_token = self.expect(Token::Identifier(Symbol::default()))?;
+            let field_name = match &field_token.token {
+                Token::Identifier(s) => *s,
+                _ => unreachable!(),
+            };
+
+            self.expect(Token::Colon)?;
+            let ty = self.parse_type()?;
+
+            fields.push(EventField {
+                name: field_name,
+                ty,
+                indexed,
+                location: Location {
+                    line: field_start.line,
+                    column: field_start.column,
+                    start: field_start.start,
+                    end: self.current_token.end,
+                },
+            });
+
+            if !self.check(&Token::RBrace) {
+                self.expect(Token::Comma)?;
+            }
+        }
+
+        self.expect(Token::RBrace)?;
+
+        Ok(Definition::EventDef {
+            name,
+            fields,
+            visibility: Visibility::Private,
             location: Location {
                 line: start_line,
                 column: start_column,
@@ -593,9 +1066,9 @@ impl<'a> Parser<'a> {
         let start_column = token.column;
 
         // Parse field name
-        let name_token = self.expect(Token::Identifier(String::new()))?;
+        let name_token = self.expect(Token::Identifier(Symbol::default()))?;
         let name = match &name_token.token {
-            Token::Identifier(s) => s.clone(),
+            Token::Identifier(s) => *s,
             _ => unreachable!(),
         };
 
@@ -628,7 +1101,7 @@ impl<'a> Parser<'a> {
     }
 
     /// Parse a type
-    fn parse_type(&mut self) -> Result<Type, ParseError> {
+    pub fn parse_type(&mut self) -> Result<Type, ParseError> {
         let start = self.current_token.start;
         let start_line = self.current_token.line;
         let start_column = self.current_token.column;
@@ -652,7 +1125,7 @@ impl<'a> Parser<'a> {
 
                     Ok(Type::Function {
                         param: Box::new(Type::Named {
-                            name: name.clone(),
+                            name,
                             params,
                             location: Location {
                                 line: start_line,
@@ -671,7 +1144,7 @@ impl<'a> Parser<'a> {
                     })
                 } else {
                     Ok(Type::Named {
-                        name: name.clone(),
+                        name,
                         params,
                         location: Location {
                             line: start_line,
@@ -750,12 +1223,7 @@ impl<'a> Parser<'a> {
                     },
                 })
             }
-            _ => Err(ParseError::UnexpectedToken {
-                found: self.current_token.token.to_string(),
-                expected: "type".to_string(),
-                line: self.current_token.line,
-                column: self.current_token.column,
-            }),
+            _ => Err(self.unexpected("type")),
         }
     }
 
@@ -765,9 +1233,9 @@ impl<'a> Parser<'a> {
         let mut params = Vec::new();
 
         while !self.check(&Token::GreaterThan) {
-            let param_token = self.expect(Token::Identifier(String::new()))?;
+            let param_token = self.expect(Token::Identifier(Symbol::default()))?;
             let param = match &param_token.token {
-                Token::Identifier(s) => s.clone(),
+                Token::Identifier(s) => s.to_string(),
                 _ => unreachable!(),
             };
 
@@ -840,6 +1308,7 @@ impl<'a> Parser<'a> {
                     location,
                 })
             }
+            Expr::MapAccess { map, key, location } => Ok(Pattern::MapAccess { map, key, location }),
             _ => Err(ParseError::Generic(format!(
                 "Invalid assignment target: {:?}",
                 expr
@@ -853,6 +1322,8 @@ impl<'a> Parser<'a> {
         match token {
             Token::Return => self.parse_return_statement(),
             Token::If => self.parse_if_statement(),
+            Token::While => self.parse_while_statement(),
+            Token::For => self.parse_for_statement(),
             Token::Switch => self.parse_switch_statement(),
             Token::Match => self.parse_match_statement(),
             Token::Fold => self.parse_fold_statement(),
@@ -862,6 +1333,11 @@ impl<'a> Parser<'a> {
             Token::Use => self.parse_use_statement(),
             Token::Let => self.parse_let_statement(),
             Token::Try => self.parse_try_catch_statement(),
+            Token::Emit => self.parse_emit_statement(),
+            Token::Assert => self.parse_revert_statement(RevertKind::Assert),
+            Token::Require => self.parse_revert_statement(RevertKind::Require),
+            Token::Revert => self.parse_revert_statement(RevertKind::Revert),
+            Token::Unchecked => self.parse_unchecked_statement(),
             Token::Def => {
                 let def = self.parse_function_def()?;
                 let location = def.location().clone();
@@ -954,6 +1430,95 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Parse an emit statement: `emit Transfer(sender, receiver, amount);`
+    fn parse_emit_statement(&mut self) -> Result<Statement, ParseError> {
+        let token = self.expect(Token::Emit)?;
+        let start = token.start;
+        let start_line = token.line;
+        let start_column = token.column;
+
+        let name_token = self.expect(Token::Identifier(Symbol::default()))?;
+        let event = match &name_token.token {
+            Token::Identifier(s) => *s,
+            _ => unreachable!(),
+        };
+
+        self.expect(Token::LParen)?;
+        let mut args = Vec::new();
+
+        while !self.check(&Token::RParen) {
+            args.push(self.parse_expression()?);
+            if !self.check(&Token::RParen) {
+                self.expect(Token::Comma)?;
+            }
+        }
+
+        self.expect(Token::RParen)?;
+
+        if self.check(&Token::Semicolon) {
+            self.advance();
+        }
+
+        Ok(Statement::Emit {
+            event,
+            args,
+            location: Location {
+                line: start_line,
+                column: start_column,
+                start,
+                end: self.current_token.end,
+            },
+        })
+    }
+
+    /// Parse `assert(cond, "msg")`, `require(cond, reason)` or
+    /// `revert(reason)`; the reason is optional
+    fn parse_revert_statement(&mut self, kind: RevertKind) -> Result<Statement, ParseError> {
+        let token = self.expect(match kind {
+            RevertKind::Assert => Token::Assert,
+            RevertKind::Require => Token::Require,
+            RevertKind::Revert => Token::Revert,
+        })?;
+        let start = token.start;
+        let start_line = token.line;
+        let start_column = token.column;
+
+        self.expect(Token::LParen)?;
+
+        let condition = if kind == RevertKind::Revert {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+
+        let reason = if self.check(&Token::RParen) {
+            None
+        } else {
+            if condition.is_some() {
+                self.expect(Token::Comma)?;
+            }
+            Some(self.parse_expression()?)
+        };
+
+        self.expect(Token::RParen)?;
+
+        if self.check(&Token::Semicolon) {
+            self.advance();
+        }
+
+        Ok(Statement::Revert {
+            kind,
+            condition,
+            reason,
+            location: Location {
+                line: start_line,
+                column: start_column,
+                start,
+                end: self.current_token.end,
+            },
+        })
+    }
+
     /// Parse a let statement
     fn parse_let_statement(&mut self) -> Result<Statement, ParseError> {
         let token = self.expect(Token::Let)?;
@@ -962,31 +1527,23 @@ impl<'a> Parser<'a> {
         let start_column = token.column;
 
         // Parse pattern (for now, just variable name)
-        let name_token = self.expect(Token::Identifier(String::new()))?;
+        let name_token = self.expect(Token::Identifier(Symbol::default()))?;
         let name = match &name_token.token {
-            Token::Identifier(s) => s.clone(),
+            Token::Identifier(s) => *s,
             _ => unreachable!(),
         };
 
         // Parse optional type annotation
         let _ty = if self.check(&Token::Colon) {
             self.advance();
-            let ty = self.parse_type()?;
-            eprintln!("DEBUG parse_let: parsed type annotation successfully");
-            Some(ty)
+            Some(self.parse_type()?)
         } else {
-            eprintln!("DEBUG parse_let: no type annotation found");
             None
         };
 
         // Parse assignment
-        eprintln!(
-            "DEBUG parse_let: checking for = token, current token is: {:?}",
-            self.current_token.token
-        );
         self.expect(Token::Equal)?;
         let value = self.parse_expression()?;
-        eprintln!("DEBUG parse_let: parsed value expression successfully");
 
         if self.check(&Token::Semicolon) {
             self.advance();
@@ -1043,7 +1600,7 @@ impl<'a> Parser<'a> {
             Token::Identifier(name) => {
                 self.advance();
                 Ok(Expr::Variable {
-                    name: name.clone(),
+                    name: Symbol::intern(&name),
                     location: Location {
                         line: start_line,
                         column: start_column,
@@ -1064,6 +1621,54 @@ impl<'a> Parser<'a> {
                     },
                 })
             }
+            Token::WideUintLiteral(value) => {
+                self.advance();
+                Ok(Expr::Literal {
+                    kind: LiteralKind::WideUint(*value),
+                    location: Location {
+                        line: start_line,
+                        column: start_column,
+                        start,
+                        end: self.current_token.end,
+                    },
+                })
+            }
+            Token::AddressLiteral(value) => {
+                self.advance();
+                Ok(Expr::Literal {
+                    kind: LiteralKind::Address(*value),
+                    location: Location {
+                        line: start_line,
+                        column: start_column,
+                        start,
+                        end: self.current_token.end,
+                    },
+                })
+            }
+            Token::HashLiteral(value) => {
+                self.advance();
+                Ok(Expr::Literal {
+                    kind: LiteralKind::Hash(*value),
+                    location: Location {
+                        line: start_line,
+                        column: start_column,
+                        start,
+                        end: self.current_token.end,
+                    },
+                })
+            }
+            Token::BytesLiteral(value) => {
+                self.advance();
+                Ok(Expr::Literal {
+                    kind: LiteralKind::Bytes(value),
+                    location: Location {
+                        line: start_line,
+                        column: start_column,
+                        start,
+                        end: self.current_token.end,
+                    },
+                })
+            }
             Token::IntLiteral(value) => {
                 self.advance();
                 Ok(Expr::Literal {
@@ -1179,19 +1784,79 @@ impl<'a> Parser<'a> {
                     self.expect(Token::RParen)?;
                     Ok(first_expr)
                 }
-            }
-            Token::LBrace => {
-                let block = self.parse_block()?;
+            }
+            Token::LBrace => {
+                // `{}` and `{key: value, ...}` are map literals, `{a, b, ...}`
+                // a superposition, anything else is a block
+                self.advance(); // consume '{'
+                let mut statements = Vec::new();
+                if !self.check(&Token::RBrace) {
+                    let first = self.parse_statement()?;
+                    if self.check(&Token::Colon) {
+                        if let Statement::Expr { expr, .. } = first {
+                            let location = Location {
+                                line: start_line,
+                                column: start_column,
+                                start,
+                                end: self.current_token.end,
+                            };
+                            return self.parse_map_entries(expr, location);
+                        }
+                    } else if self.check(&Token::Comma) {
+                        if let Statement::Expr { expr, .. } = first {
+                            let mut elements = vec![expr];
+                            while self.check(&Token::Comma) {
+                                self.advance(); // consume ','
+                                if self.check(&Token::RBrace) {
+                                    break;
+                                }
+                                elements.push(self.parse_expression()?);
+                            }
+                            let end_token = self.expect(Token::RBrace)?;
+                            return Ok(Expr::Superposition {
+                                elements,
+                                location: Location {
+                                    line: start_line,
+                                    column: start_column,
+                                    start,
+                                    end: end_token.end,
+                                },
+                            });
+                        }
+                    }
+                    statements.push(first);
+                    while !self.check(&Token::RBrace) && !self.check(&Token::EOF) {
+                        statements.push(self.parse_statement()?);
+                    }
+                    self.expect(Token::RBrace)?;
+                } else {
+                    self.advance(); // consume '}'
+                    return Ok(Expr::Map {
+                        entries: Vec::new(),
+                        location: Location {
+                            line: start_line,
+                            column: start_column,
+                            start,
+                            end: self.current_token.end,
+                        },
+                    });
+                }
+
+                let location = Location {
+                    line: start_line,
+                    column: start_column,
+                    start,
+                    end: self.current_token.end,
+                };
                 Ok(Expr::Block {
-                    block,
-                    location: Location {
-                        line: start_line,
-                        column: start_column,
-                        start,
-                        end: self.current_token.end,
+                    block: Block {
+                        statements,
+                        location: location.clone(),
                     },
+                    location,
                 })
             }
+            Token::Asm => self.parse_inline_asm(),
             Token::If => {
                 self.advance();
                 let condition = self.parse_expression()?;
@@ -1216,6 +1881,23 @@ impl<'a> Parser<'a> {
                 if !self.check(&Token::RBracket) {
                     loop {
                         elements.push(self.parse_expression()?);
+                        if elements.len() == 1 && self.check(&Token::For) {
+                            let (variable, iterable, condition) =
+                                self.parse_comprehension_clauses()?;
+                            self.expect(Token::RBracket)?;
+                            return Ok(Expr::ListComprehension {
+                                element: Box::new(elements.remove(0)),
+                                variable: variable.into(),
+                                iterable: Box::new(iterable),
+                                condition: condition.map(Box::new),
+                                location: Location {
+                                    line: start_line,
+                                    column: start_column,
+                                    start,
+                                    end: self.current_token.end,
+                                },
+                            });
+                        }
                         if !self.check(&Token::Comma) {
                             break;
                         }
@@ -1241,9 +1923,9 @@ impl<'a> Parser<'a> {
 
                 loop {
                     // Parse parameter name
-                    let name_token = self.expect(Token::Identifier(String::new()))?;
+                    let name_token = self.expect(Token::Identifier(Symbol::default()))?;
                     let name = match &name_token.token {
-                        Token::Identifier(s) => s.clone(),
+                        Token::Identifier(s) => *s,
                         _ => unreachable!(),
                     };
 
@@ -1297,18 +1979,13 @@ impl<'a> Parser<'a> {
                     },
                 })
             }
-            _ => Err(ParseError::UnexpectedToken {
-                found: self.current_token.token.to_string(),
-                expected: "expression".to_string(),
-                line: self.current_token.line,
-                column: self.current_token.column,
-            }),
+            _ => Err(self.unexpected("expression")),
         }
     }
 
     /// Parse a binary expression with precedence
     fn parse_binary_expression(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
-        let mut left = self.parse_postfix_expression()?;
+        let mut left = self.parse_unary_expression()?;
 
         loop {
             let operator = match self.current_token.token {
@@ -1323,6 +2000,8 @@ impl<'a> Parser<'a> {
                 Token::LessEqual => BinaryOperator::LessEqual,
                 Token::EqualEqual => BinaryOperator::Equal,
                 Token::NotEqual => BinaryOperator::NotEqual,
+                Token::AndAnd => BinaryOperator::And,
+                Token::OrOr => BinaryOperator::Or,
                 _ => break,
             };
 
@@ -1350,18 +2029,58 @@ impl<'a> Parser<'a> {
         Ok(left)
     }
 
+    /// Parse a unary expression, e.g. `!paused` or `not paused`
+    fn parse_unary_expression(&mut self) -> Result<Expr, ParseError> {
+        if !self.check(&Token::Bang) {
+            return self.parse_postfix_expression();
+        }
+
+        let token = self.expect(Token::Bang)?;
+        let operand = self.parse_unary_expression()?;
+        Ok(Expr::UnaryOp {
+            operator: UnaryOperator::Not,
+            operand: Box::new(operand),
+            location: Location {
+                line: token.line,
+                column: token.column,
+                start: token.start,
+                end: self.current_token.end,
+            },
+        })
+    }
+
     /// Parse a postfix expression (function calls, etc.)
     fn parse_postfix_expression(&mut self) -> Result<Expr, ParseError> {
         let mut left = self.parse_primary_expression()?;
 
         loop {
             if self.check(&Token::LParen) {
-                // Function call
+                // Function call, with optional `name: value` arguments
                 self.advance();
                 let mut args = Vec::new();
+                let mut named_args = HashMap::new();
                 if !self.check(&Token::RParen) {
                     loop {
-                        args.push(self.parse_expression()?);
+                        if let Token::Identifier(name) = &self.current_token.token {
+                            if self.peek_is_colon() {
+                                let name = name.to_string();
+                                let (line, column) =
+                                    (self.current_token.line, self.current_token.column);
+                                self.advance();
+                                self.advance();
+                                let value = self.parse_expression()?;
+                                if named_args.insert(name.clone(), value).is_some() {
+                                    return Err(ParseError::Generic(format!(
+                                        "Argument '{}' is given more than once (line {}, column {})",
+                                        name, line, column
+                                    )));
+                                }
+                            } else {
+                                args.push(self.parse_expression()?);
+                            }
+                        } else {
+                            args.push(self.parse_expression()?);
+                        }
                         if !self.check(&Token::Comma) {
                             break;
                         }
@@ -1374,7 +2093,7 @@ impl<'a> Parser<'a> {
                 left = Expr::FunctionCall {
                     function: Box::new(left),
                     args,
-                    named_args: HashMap::new(),
+                    named_args,
                     location: Location {
                         line: self.current_token.line, // Approx
                         column: self.current_token.column,
@@ -1385,9 +2104,9 @@ impl<'a> Parser<'a> {
             } else if self.check(&Token::Dot) {
                 // Field access (e.g., self.value)
                 self.advance();
-                let field_token = self.expect(Token::Identifier(String::new()))?;
+                let field_token = self.expect_field()?;
                 let field_name = match &field_token.token {
-                    Token::Identifier(s) => s.clone(),
+                    Token::Identifier(s) => s.to_string(),
                     _ => unreachable!(),
                 };
 
@@ -1402,12 +2121,29 @@ impl<'a> Parser<'a> {
                         end: field_token.end,
                     },
                 };
+            } else if self.check(&Token::LBracket) {
+                // Map access (e.g., balances[owner])
+                self.advance();
+                let key = self.parse_expression()?;
+                let end_token = self.expect(Token::RBracket)?;
+
+                let location_start = left.location().start;
+                left = Expr::MapAccess {
+                    map: Box::new(left),
+                    key: Box::new(key),
+                    location: Location {
+                        line: self.current_token.line,
+                        column: self.current_token.column,
+                        start: location_start,
+                        end: end_token.end,
+                    },
+                };
             } else if self.check(&Token::DoubleColon) {
                 // Static access (e.g., Map::new)
                 self.advance();
-                let field_token = self.expect(Token::Identifier(String::new()))?;
+                let field_token = self.expect(Token::Identifier(Symbol::default()))?;
                 let field_name = match &field_token.token {
-                    Token::Identifier(s) => s.clone(),
+                    Token::Identifier(s) => *s,
                     _ => unreachable!(),
                 };
 
@@ -1415,7 +2151,7 @@ impl<'a> Parser<'a> {
                 if let Expr::Variable { name, location } = left {
                     let new_name = format!("{}::{}", name, field_name);
                     left = Expr::Variable {
-                        name: new_name,
+                        name: new_name.into(),
                         location: Location {
                             line: location.line,
                             column: location.column,
@@ -1428,6 +2164,17 @@ impl<'a> Parser<'a> {
                         "Expected identifier before ::".to_string(),
                     ));
                 }
+            } else if self.check(&Token::Question) {
+                // Error propagation (e.g., transfer(to, amount)?)
+                let end_token = self.expect(Token::Question)?;
+                let location = left.location().clone();
+                left = Expr::Try {
+                    expr: Box::new(left),
+                    location: Location {
+                        end: end_token.end,
+                        ..location
+                    },
+                };
             } else {
                 break;
             }
@@ -1435,8 +2182,131 @@ impl<'a> Parser<'a> {
         Ok(left)
     }
 
+    /// Parse the remaining entries of a map literal whose first key has
+    /// been parsed, up to and including the closing brace
+    fn parse_map_entries(
+        &mut self,
+        first_key: Expr,
+        mut location: Location,
+    ) -> Result<Expr, ParseError> {
+        let mut entries = Vec::new();
+        let mut key = first_key;
+        loop {
+            self.expect(Token::Colon)?;
+            let value = self.parse_expression()?;
+            if entries.is_empty() && self.check(&Token::For) {
+                let (variable, iterable, condition) = self.parse_comprehension_clauses()?;
+                let end_token = self.expect(Token::RBrace)?;
+                location.end = end_token.end;
+                return Ok(Expr::MapComprehension {
+                    key: Box::new(key),
+                    value: Box::new(value),
+                    variable: variable.into(),
+                    iterable: Box::new(iterable),
+                    condition: condition.map(Box::new),
+                    location,
+                });
+            }
+            entries.push((key, value));
+            if !self.check(&Token::Comma) {
+                break;
+            }
+            self.advance(); // consume ','
+            if self.check(&Token::RBrace) {
+                break;
+            }
+            key = self.parse_expression()?;
+        }
+        let end_token = self.expect(Token::RBrace)?;
+        location.end = end_token.end;
+
+        Ok(Expr::Map { entries, location })
+    }
+
+    /// Parse the `for variable in iterable` clause of a comprehension,
+    /// with an optional `if condition` filter
+    fn parse_comprehension_clauses(&mut self) -> Result<(String, Expr, Option<Expr>), ParseError> {
+        self.expect(Token::For)?;
+        let variable = match self.expect(Token::Identifier(Symbol::default()))?.token {
+            Token::Identifier(name) => name.to_string(),
+            _ => unreachable!(),
+        };
+        self.expect(Token::In)?;
+        let iterable = self.parse_expression()?;
+        let condition = if self.check(&Token::If) {
+            self.advance(); // consume 'if'
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+        Ok((variable, iterable, condition))
+    }
+
+    /// `asm(in a0 = x, out a0) { "addi a0, a0, 1" }`, with one string per
+    /// instruction. The bindings may be left out with their parentheses.
+    fn parse_inline_asm(&mut self) -> Result<Expr, ParseError> {
+        let token = self.expect(Token::Asm)?;
+        let mut inputs = Vec::new();
+        let mut output = None;
+        if self.check(&Token::LParen) {
+            self.advance(); // consume '('
+            while !self.check(&Token::RParen) {
+                let out =
+                    matches!(&self.current_token.token, Token::Identifier(name) if name == "out");
+                if !out && !self.check(&Token::In) {
+                    return Err(self.unexpected("`in` or `out`"));
+                }
+                let binding = self.current_token.clone();
+                self.advance(); // consume 'in' or 'out'
+                let register = match self.expect(Token::Identifier(Symbol::default()))?.token {
+                    Token::Identifier(name) => name.to_string(),
+                    _ => unreachable!(),
+                };
+                if out {
+                    if output.is_some() {
+                        return Err(ParseError::Generic(format!(
+                            "asm has more than one `out` register (line {}, column {})",
+                            binding.line, binding.column
+                        )));
+                    }
+                    output = Some(register);
+                } else {
+                    self.expect(Token::Equal)?;
+                    inputs.push((register, self.parse_expression()?));
+                }
+                if !self.check(&Token::Comma) {
+                    break;
+                }
+                self.advance(); // consume ','
+            }
+            self.expect(Token::RParen)?;
+        }
+
+        self.expect(Token::LBrace)?;
+        let mut instructions = Vec::new();
+        while let Token::StringLiteral(instruction) = &self.current_token.token {
+            instructions.push(instruction.clone());
+            self.advance();
+        }
+        self.expect(Token::RBrace)?;
+
+        Ok(Expr::InlineAsm {
+            inputs,
+            output,
+            instructions,
+            location: Location {
+                line: token.line,
+                column: token.column,
+                start: token.start,
+                end: self.current_token.end,
+            },
+        })
+    }
+
     fn get_precedence(operator: &BinaryOperator) -> u8 {
         match operator {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
             BinaryOperator::Equal | BinaryOperator::NotEqual => 3,
             BinaryOperator::Less
             | BinaryOperator::LessEqual
@@ -1474,6 +2344,106 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Parse `while cond { ... }`, with an optional `bound N` after the
+    /// condition
+    fn parse_while_statement(&mut self) -> Result<Statement, ParseError> {
+        let token = self.expect(Token::While)?;
+
+        let condition = self.parse_expression()?;
+        let bound = self.parse_loop_bound()?;
+        let body = self.parse_block()?;
+
+        Ok(Statement::While {
+            condition,
+            body,
+            bound,
+            location: Location {
+                line: token.line,
+                column: token.column,
+                start: token.start,
+                end: self.current_token.end,
+            },
+        })
+    }
+
+    /// Parse `for i in range(start, end) { ... }` or `for i in range(end)`,
+    /// with an optional `bound N` after the range
+    fn parse_for_statement(&mut self) -> Result<Statement, ParseError> {
+        let token = self.expect(Token::For)?;
+
+        let variable = match self.expect(Token::Identifier(Symbol::default()))?.token {
+            Token::Identifier(name) => name.to_string(),
+            _ => unreachable!(),
+        };
+        self.expect(Token::In)?;
+
+        let range_token = self.current_token.clone();
+        let range = self.parse_expression()?;
+        let (start, end) = match range {
+            Expr::FunctionCall { function, args, .. } if matches!(&*function, Expr::Variable { name, .. } if name == "range") =>
+            {
+                let mut args = args.into_iter();
+                match (args.next(), args.next(), args.next()) {
+                    (Some(end), None, None) => (
+                        Expr::Literal {
+                            kind: LiteralKind::Uint(0),
+                            location: end.location().clone(),
+                        },
+                        end,
+                    ),
+                    (Some(start), Some(end), None) => (start, end),
+                    _ => {
+                        return Err(ParseError::Generic(format!(
+                            "range takes 1 or 2 arguments at line {}, column {}",
+                            range_token.line, range_token.column
+                        )))
+                    }
+                }
+            }
+            _ => {
+                return Err(ParseError::UnexpectedToken {
+                    found: range_token.token.to_string(),
+                    expected: "range(...)".to_string(),
+                    line: range_token.line,
+                    column: range_token.column,
+                })
+            }
+        };
+
+        let bound = self.parse_loop_bound()?;
+        let body = self.parse_block()?;
+
+        Ok(Statement::For {
+            variable: variable.into(),
+            start,
+            end,
+            body,
+            bound,
+            location: Location {
+                line: token.line,
+                column: token.column,
+                start: token.start,
+                end: self.current_token.end,
+            },
+        })
+    }
+
+    /// Parse the optional `bound N` of a loop, the most iterations it may run
+    fn parse_loop_bound(&mut self) -> Result<Option<u32>, ParseError> {
+        if !matches!(&self.current_token.token, Token::Identifier(name) if name == "bound") {
+            return Ok(None);
+        }
+        self.advance();
+
+        match self.current_token.token {
+            Token::UintLiteral(bound) => {
+                self.advance();
+                Ok(Some(bound))
+            }
+            _ => Err(self.unexpected("iteration bound")),
+        }
+    }
+
     fn parse_switch_statement(&mut self) -> Result<Statement, ParseError> {
         Err(ParseError::Generic(
             "Switch statements not implemented yet".to_string(),
@@ -1559,10 +2529,6 @@ impl<'a> Parser<'a> {
 
         // Parse content inside the block
         while !self.check(&Token::RBrace) && !self.check(&Token::EOF) {
-            println!(
-                "DEBUG: Current: {:?}, Peek: {:?}",
-                self.current_token.token, self.peek_token.token
-            );
             // Check for initializer syntax 1: Identifier <- Expression
             let is_arrow_init = if let Token::Identifier(_) = &self.current_token.token {
                 matches!(self.peek_token.token, Token::LeftArrow)
@@ -1571,9 +2537,9 @@ impl<'a> Parser<'a> {
             };
 
             if is_arrow_init {
-                let var_token = self.expect(Token::Identifier(String::new()))?;
+                let var_token = self.expect(Token::Identifier(Symbol::default()))?;
                 let var = match &var_token.token {
-                    Token::Identifier(s) => s.clone(),
+                    Token::Identifier(s) => *s,
                     _ => unreachable!(),
                 };
 
@@ -1645,6 +2611,25 @@ impl<'a> Parser<'a> {
         ))
     }
 
+    /// Parse an `unchecked` block, e.g. `unchecked: { i = i + 1; }`
+    fn parse_unchecked_statement(&mut self) -> Result<Statement, ParseError> {
+        let token = self.expect(Token::Unchecked)?;
+        if self.check(&Token::Colon) {
+            self.advance();
+        }
+        let body = self.parse_block()?;
+
+        Ok(Statement::Unchecked {
+            body,
+            location: Location {
+                line: token.line,
+                column: token.column,
+                start: token.start,
+                end: self.current_token.end,
+            },
+        })
+    }
+
     fn parse_with_statement(&mut self) -> Result<Statement, ParseError> {
         Err(ParseError::Generic(
             "With statements not implemented yet".to_string(),
@@ -1668,7 +2653,7 @@ impl<'a> Parser<'a> {
             self.advance();
 
             // Parse optional error type and variable
-            let error_type = if self.check(&Token::Identifier("".to_string())) {
+            let error_type = if self.check(&Token::Identifier(Symbol::default())) {
                 None
             } else {
                 Some("Error".to_string()) // Default error type
@@ -1713,16 +2698,204 @@ impl<'a> Parser<'a> {
         ))
     }
 
+    /// Parse a storage block declaring persistent contract state
+    ///
+    /// ```text
+    /// storage { total_supply: u24, owner: u24 }
+    /// ```
+    fn parse_storage_def(&mut self) -> Result<Definition, ParseError> {
+        let token = self.expect(Token::Storage)?;
+        let start = token.start;
+        let start_line = token.line;
+        let start_column = token.column;
+
+        self.expect(Token::LBrace)?;
+        let mut fields = Vec::new();
+
+        while !self.check(&Token::RBrace) && !self.check(&Token::EOF) {
+            let field_token = self.expect(Token::Identifier(Symbol::default()))?;
+            let field_name = match &field_token.token {
+                Token::Identifier(s) => *s,
+                _ => unreachable!(),
+            };
+
+            self.expect(Token::Colon)?;
+            let ty = self.parse_type()?;
+
+            fields.push(StorageField {
+                name: field_name,
+                ty,
+                visibility: Visibility::Private,
+                location: Location {
+                    line: field_token.line,
+                    column: field_token.column,
+                    start: field_token.start,
+                    end: self.current_token.end,
+                },
+            });
+
+            if !self.check(&Token::RBrace) {
+                self.expect(Token::Comma)?;
+            }
+        }
+
+        self.expect(Token::RBrace)?;
+
+        Ok(Definition::StorageDef {
+            fields,
+            attributes: Vec::new(),
+            location: Location {
+                line: start_line,
+                column: start_column,
+                start,
+                end: self.current_token.end,
+            },
+        })
+    }
+
+    /// Parse a contract declaring its state fields along with its events,
+    /// functions and other definitions. `pub` fields get a getter.
+    ///
+    /// ```text
+    /// contract Counter {
+    ///     pub let count: u24;
+    ///     event Incremented { by: u24 }
+    ///     fn constructor() { count = 1; }
+    ///     pub fn increment(by: u24) -> u24 { ... }
+    /// }
+    /// ```
     fn parse_contract_def(&mut self) -> Result<Definition, ParseError> {
-        Err(ParseError::Generic(
-            "Contract definitions not implemented yet".to_string(),
-        ))
+        let token = self.expect(Token::Contract)?;
+        let start = token.start;
+        let start_line = token.line;
+        let start_column = token.column;
+
+        let name_token = self.expect(Token::Identifier(Symbol::default()))?;
+        let name = match &name_token.token {
+            Token::Identifier(s) => *s,
+            _ => unreachable!(),
+        };
+
+        self.expect(Token::LBrace)?;
+        let mut fields = Vec::new();
+        let mut definitions = Vec::new();
+
+        while !self.check(&Token::RBrace) && !self.check(&Token::EOF) {
+            let public = self.check(&Token::Pub) && matches!(self.peek_token.token, Token::Let);
+            if public || self.check(&Token::Let) {
+                let field_token = self.advance();
+                if public {
+                    self.advance();
+                }
+                let name_token = self.expect(Token::Identifier(Symbol::default()))?;
+                let field_name = match &name_token.token {
+                    Token::Identifier(s) => *s,
+                    _ => unreachable!(),
+                };
+
+                self.expect(Token::Colon)?;
+                let ty = self.parse_type()?;
+                self.expect(Token::Semicolon)?;
+
+                fields.push(StorageField {
+                    name: field_name,
+                    ty,
+                    visibility: if public {
+                        Visibility::Public
+                    } else {
+                        Visibility::Private
+                    },
+                    location: Location {
+                        line: field_token.line,
+                        column: field_token.column,
+                        start: field_token.start,
+                        end: self.current_token.end,
+                    },
+                });
+                continue;
+            }
+
+            let definition = self.parse_definition()?;
+            if let Definition::StorageDef { location, .. }
+            | Definition::ContractDef { location, .. } = &definition
+            {
+                return Err(ParseError::Generic(format!(
+                    "Contracts declare their state with `let` and cannot contain storage or other contracts (line {}, column {})",
+                    location.line, location.column
+                )));
+            }
+            definitions.push(definition);
+        }
+
+        self.expect(Token::RBrace)?;
+
+        Ok(Definition::ContractDef {
+            name,
+            fields,
+            definitions,
+            attributes: Vec::new(),
+            location: Location {
+                line: start_line,
+                column: start_column,
+                start,
+                end: self.current_token.end,
+            },
+        })
     }
 
+    /// Parse an interface definition
+    ///
+    /// ```text
+    /// interface IToken {
+    ///     fn transfer(to: u24, amount: u24) -> u24;
+    ///     #[payable]
+    ///     fn deposit() -> u24;
+    /// }
+    /// ```
     fn parse_interface_def(&mut self) -> Result<Definition, ParseError> {
-        Err(ParseError::Generic(
-            "Interface definitions not implemented yet".to_string(),
-        ))
+        let token = self.expect(Token::Interface)?;
+        let start = token.start;
+        let start_line = token.line;
+        let start_column = token.column;
+
+        let name_token = self.expect(Token::Identifier(Symbol::default()))?;
+        let name = match &name_token.token {
+            Token::Identifier(s) => *s,
+            _ => unreachable!(),
+        };
+
+        self.expect(Token::LBrace)?;
+        let mut functions = Vec::new();
+
+        while !self.check(&Token::RBrace) && !self.check(&Token::EOF) {
+            if self.check(&Token::HashBracket) {
+                let attributes = self.parse_attributes()?;
+                let function = self.parse_function_def()?;
+                functions.push(self.attach_attributes(function, attributes)?);
+            } else if self.check(&Token::Fn) {
+                functions.push(self.parse_function_def()?);
+            } else {
+                return Err(self.unexpected("function declaration"));
+            }
+
+            if self.check(&Token::Semicolon) {
+                self.advance();
+            }
+        }
+
+        self.expect(Token::RBrace)?;
+
+        Ok(Definition::InterfaceDef {
+            name,
+            functions,
+            visibility: Visibility::Private,
+            location: Location {
+                line: start_line,
+                column: start_column,
+                start,
+                end: self.current_token.end,
+            },
+        })
     }
 
     fn parse_library_def(&mut self) -> Result<Definition, ParseError> {
@@ -1751,9 +2924,9 @@ impl<'a> Parser<'a> {
                     let mut fields = HashMap::new();
 
                     while !self.check(&Token::RBrace) {
-                        let field_name_token = self.expect(Token::Identifier(String::new()))?;
+                        let field_name_token = self.expect(Token::Identifier(Symbol::default()))?;
                         let field_name = match &field_name_token.token {
-                            Token::Identifier(s) => s.clone(),
+                            Token::Identifier(s) => s.to_string(),
                             _ => unreachable!(),
                         };
                         self.expect(Token::Colon)?;
@@ -1775,7 +2948,7 @@ impl<'a> Parser<'a> {
                     let location = Location::span(&start_location, &end_location);
 
                     Ok(Pattern::Constructor {
-                        name: name.clone(),
+                        name,
                         fields,
                         location,
                     })
@@ -1801,14 +2974,14 @@ impl<'a> Parser<'a> {
                     let location = Location::span(&start_location, &end_location);
 
                     Ok(Pattern::TupleConstructor {
-                        name: name.clone(),
+                        name,
                         args,
                         location,
                     })
                 } else {
                     // Simple variable pattern
                     Ok(Pattern::Variable {
-                        name: name.clone(),
+                        name,
                         location: start_location,
                     })
                 }
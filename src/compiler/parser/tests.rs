@@ -3,9 +3,11 @@
 //! Tests for the Bend-PVM parser implementation.
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use crate::compiler::parser::ast::*;
     use crate::compiler::parser::parser::Parser;
+    use crate::compiler::wide::WideUint;
 
     #[test]
     fn test_parser_basic_function() {
@@ -331,4 +333,721 @@ fn factorial(n: u24) -> u24 {
             _ => panic!("Expected function definition"),
         }
     }
+
+    #[test]
+    fn test_parser_event_definition() {
+        let source = r#"
+event Transfer {
+    indexed sender: u24,
+    indexed receiver: u24,
+    amount: u24,
+}
+"#;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        match &program.definitions[0] {
+            Definition::EventDef { name, fields, .. } => {
+                assert_eq!(name, "Transfer");
+                let summary: Vec<(&str, bool)> = fields
+                    .iter()
+                    .map(|field| (field.name.as_str(), field.indexed))
+                    .collect();
+                assert_eq!(
+                    summary,
+                    vec![("sender", true), ("receiver", true), ("amount", false)]
+                );
+            }
+            _ => panic!("Expected event definition"),
+        }
+    }
+
+    #[test]
+    fn test_parser_emit_statement() {
+        let source = r#"
+fn main() -> u24 {
+    emit Transfer(1, 2, 3);
+    return 0;
+}
+"#;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        match &program.definitions[0] {
+            Definition::FunctionDef { body, .. } => match &body.statements[0] {
+                Statement::Emit { event, args, .. } => {
+                    assert_eq!(event, "Transfer");
+                    assert_eq!(args.len(), 3);
+                }
+                other => panic!("Expected emit statement, got {:?}", other),
+            },
+            _ => panic!("Expected function definition"),
+        }
+    }
+
+    #[test]
+    fn test_parser_revert_statements() {
+        let source = r#"
+fn main(amount: u24) -> u24 {
+    assert(amount < 100, "amount too large");
+    require(amount > 0);
+    revert(Error/InsufficientBalance(amount, 0));
+}
+"#;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        let body = match &program.definitions[0] {
+            Definition::FunctionDef { body, .. } => body,
+            _ => panic!("Expected function definition"),
+        };
+        let statements: Vec<(RevertKind, bool, bool)> = body
+            .statements
+            .iter()
+            .map(|statement| match statement {
+                Statement::Revert {
+                    kind,
+                    condition,
+                    reason,
+                    ..
+                } => (*kind, condition.is_some(), reason.is_some()),
+                other => panic!("Expected revert statement, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            statements,
+            vec![
+                (RevertKind::Assert, true, true),
+                (RevertKind::Require, true, false),
+                (RevertKind::Revert, false, true),
+            ]
+        );
+
+        match &body.statements[2] {
+            Statement::Revert {
+                reason: Some(reason),
+                ..
+            } => {
+                let (variant, args) = reason.as_variant_constructor().unwrap();
+                assert_eq!(variant, "Error/InsufficientBalance");
+                assert_eq!(args.len(), 2);
+            }
+            other => panic!("Expected revert with a reason, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parser_error_definition_and_try() {
+        let source = r#"
+error TokenError {
+    InsufficientBalance(needed: u24, available: u24),
+    Paused,
+}
+
+fn transfer(amount: u24) -> Result<u24, TokenError> {
+    left = withdraw(amount)?;
+    return Result/Ok(left);
+}
+"#;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        match &program.definitions[0] {
+            Definition::ErrorDef { name, variants, .. } => {
+                assert_eq!(name, "TokenError");
+                let names: Vec<&str> = variants.iter().map(|v| v.name.as_str()).collect();
+                assert_eq!(names, vec!["InsufficientBalance", "Paused"]);
+                assert_eq!(variants[0].fields.len(), 2);
+            }
+            other => panic!("Expected error definition, got {:?}", other),
+        }
+
+        let body = match &program.definitions[1] {
+            Definition::FunctionDef { body, .. } => body,
+            _ => panic!("Expected function definition"),
+        };
+        match &body.statements[0] {
+            Statement::Assignment {
+                value: Expr::Try { expr, .. },
+                ..
+            } => assert!(matches!(**expr, Expr::FunctionCall { .. })),
+            other => panic!("Expected assignment of a try expression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parser_loops() {
+        let source = r#"
+fn main(n: u24) -> u24 {
+    total = 0;
+    while total < n {
+        total = total + 1;
+    }
+    while total > 0 bound 100 {
+        total = total - 1;
+    }
+    for i in range(n) {
+        total = total + i;
+    }
+    for j in range(1, n + 1) bound 64 {
+        total = total + j;
+    }
+    return total;
+}
+"#;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        let body = match &program.definitions[0] {
+            Definition::FunctionDef { body, .. } => body,
+            _ => panic!("Expected function definition"),
+        };
+        match &body.statements[1] {
+            Statement::While { bound, body, .. } => {
+                assert_eq!(*bound, None);
+                assert_eq!(body.statements.len(), 1);
+            }
+            other => panic!("Expected while loop, got {:?}", other),
+        }
+        assert!(matches!(
+            &body.statements[2],
+            Statement::While {
+                bound: Some(100),
+                ..
+            }
+        ));
+        match &body.statements[3] {
+            Statement::For {
+                variable,
+                start,
+                bound,
+                ..
+            } => {
+                assert_eq!(variable, "i");
+                assert!(matches!(
+                    start,
+                    Expr::Literal {
+                        kind: LiteralKind::Uint(0),
+                        ..
+                    }
+                ));
+                assert_eq!(*bound, None);
+            }
+            other => panic!("Expected for loop, got {:?}", other),
+        }
+        match &body.statements[4] {
+            Statement::For {
+                variable,
+                end,
+                bound,
+                ..
+            } => {
+                assert_eq!(variable, "j");
+                assert!(matches!(end, Expr::BinaryOp { .. }));
+                assert_eq!(*bound, Some(64));
+            }
+            other => panic!("Expected for loop, got {:?}", other),
+        }
+
+        let not_a_range = "fn main(n: u24) -> u24 { for i in n { } return 0; }";
+        assert!(Parser::new(not_a_range).parse_program().is_err());
+    }
+
+    #[test]
+    fn test_parser_comprehensions() {
+        let source = r#"
+fn main(xs: List<u24>) -> u24 {
+    doubled = [x * 2 for x in xs];
+    small = [x for x in xs if x < 10];
+    squares = {x: x * x for x in xs if x > 0};
+    pair = [1, 2];
+    return 0;
+}
+"#;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        let body = match &program.definitions[0] {
+            Definition::FunctionDef { body, .. } => body,
+            _ => panic!("Expected function definition"),
+        };
+        let value = |index: usize| match &body.statements[index] {
+            Statement::Assignment { value, .. } => value,
+            other => panic!("Expected assignment, got {:?}", other),
+        };
+
+        match value(0) {
+            Expr::ListComprehension {
+                element,
+                variable,
+                iterable,
+                condition,
+                ..
+            } => {
+                assert!(matches!(**element, Expr::BinaryOp { .. }));
+                assert_eq!(variable, "x");
+                assert!(matches!(&**iterable, Expr::Variable { name, .. } if name == "xs"));
+                assert!(condition.is_none());
+            }
+            other => panic!("Expected list comprehension, got {:?}", other),
+        }
+        assert!(matches!(
+            value(1),
+            Expr::ListComprehension {
+                condition: Some(_),
+                ..
+            }
+        ));
+        match value(2) {
+            Expr::MapComprehension {
+                key,
+                value,
+                condition,
+                ..
+            } => {
+                assert!(matches!(**key, Expr::Variable { .. }));
+                assert!(matches!(**value, Expr::BinaryOp { .. }));
+                assert!(condition.is_some());
+            }
+            other => panic!("Expected map comprehension, got {:?}", other),
+        }
+        assert!(matches!(value(3), Expr::Array { elements, .. } if elements.len() == 2));
+    }
+
+    #[test]
+    fn test_parser_attributes() {
+        let source = r#"
+# A comment
+#[payable]
+#[selector(0xa9059cbb), deprecated("use send")]
+fn transfer(to: u24) -> u24 {
+    return to;
+}
+
+#[deprecated]
+type Flag { On, Off }
+"#;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        let attributes = program.definitions[0].attributes();
+        let names: Vec<&str> = attributes.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["payable", "selector", "deprecated"]);
+        assert!(attributes[0].args.is_empty());
+        assert!(matches!(
+            &attributes[1].args[..],
+            [Expr::Literal { kind: LiteralKind::WideUint(value), .. }]
+                if *value == WideUint::from_u128(0xa9059cbb, 64).unwrap()
+        ));
+        assert_eq!(program.definitions[1].attributes()[0].name, "deprecated");
+
+        // Only functions, types and objects take attributes
+        let mut parser = Parser::new("#[payable] event Paid { amount: u24 }");
+        assert!(parser.parse_program().is_err());
+    }
+
+    #[test]
+    fn test_parser_guard_definition() {
+        let source = r#"
+guard at_least(amount: u24, min: u24) {
+    require(amount >= min, "too small");
+    _;
+    total = total + amount;
+}
+
+#[guard(at_least(value, 10))]
+fn deposit(value: u24) -> u24 {
+    return value;
+}
+"#;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        match &program.definitions[0] {
+            Definition::GuardDef {
+                name,
+                params,
+                before,
+                after,
+                ..
+            } => {
+                assert_eq!(name, "at_least");
+                assert_eq!(params.len(), 2);
+                assert!(matches!(&before.statements[..], [Statement::Revert { .. }]));
+                assert!(matches!(
+                    &after.statements[..],
+                    [Statement::Assignment { .. }]
+                ));
+            }
+            _ => panic!("Expected guard definition"),
+        }
+        assert_eq!(program.definitions[1].attributes()[0].name, "guard");
+
+        // The placeholder must appear exactly once
+        let mut parser = Parser::new("guard missing() { require(true); }");
+        assert!(parser.parse_program().is_err());
+        let mut parser = Parser::new("guard twice() { _; _; }");
+        assert!(parser.parse_program().is_err());
+    }
+
+    #[test]
+    fn test_parser_interface_definition() {
+        let source = r#"
+interface IToken {
+    fn transfer(to: u24, amount: u24) -> u24;
+    #[payable]
+    fn deposit() -> u24;
+}
+
+fn main() -> u24 {
+    return IToken(2, value: 5).deposit();
+}
+"#;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        match &program.definitions[0] {
+            Definition::InterfaceDef {
+                name, functions, ..
+            } => {
+                assert_eq!(name, "IToken");
+                assert_eq!(functions.len(), 2);
+                assert_eq!(functions[1].attributes()[0].name, "payable");
+            }
+            _ => panic!("Expected interface definition"),
+        }
+        match &program.definitions[1] {
+            Definition::FunctionDef { body, .. } => match &body.statements[0] {
+                Statement::Return {
+                    value: Expr::FunctionCall { function, .. },
+                    ..
+                } => {
+                    let target = function.as_interface_message().unwrap();
+                    assert_eq!(
+                        (target.interface.as_str(), target.message),
+                        ("IToken", "deposit")
+                    );
+                    assert_eq!(target.accounts.len(), 1);
+                    assert!(target.options.contains_key("value"));
+                }
+                other => panic!("Expected interface call, got {:?}", other),
+            },
+            _ => panic!("Expected function definition"),
+        }
+    }
+
+    #[test]
+    fn test_parser_visibility() {
+        let source = r#"
+pub type Shape {
+    Circle(u24)
+}
+
+#[view]
+pub fn area(shape: Shape) -> u24 {
+    return 0;
+}
+
+fn helper() -> u24 {
+    return 1;
+}
+"#;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        let visibilities: Vec<_> = program
+            .definitions
+            .iter()
+            .map(|definition| definition.visibility())
+            .collect();
+        assert_eq!(
+            visibilities,
+            [Visibility::Public, Visibility::Public, Visibility::Private]
+        );
+        assert_eq!(program.definitions[1].attributes().len(), 1);
+
+        for source in [
+            "pub storage { count: u24 }",
+            "pub pub fn f() -> u24 { return 1; }",
+        ] {
+            let mut parser = Parser::new(source);
+            assert!(parser.parse_program().is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_parser_storage_definition() {
+        let source = r#"
+storage {
+    total_supply: u24,
+    owner: u24,
+}
+"#;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        match &program.definitions[0] {
+            Definition::StorageDef { fields, .. } => {
+                let names: Vec<&str> = fields.iter().map(|field| field.name.as_str()).collect();
+                assert_eq!(names, vec!["total_supply", "owner"]);
+            }
+            _ => panic!("Expected storage definition"),
+        }
+    }
+
+    #[test]
+    fn test_parser_contract_definition() {
+        let source = r#"
+#[version(2)]
+contract Counter {
+    let count: u24;
+    event Incremented { by: u24 }
+
+    fn constructor() {
+        count = 1;
+    }
+
+    pub fn increment(by: u24) -> u24 {
+        count = count + by;
+        return count;
+    }
+}
+"#;
+        let program = Parser::new(source)
+            .keep_contracts()
+            .parse_program()
+            .unwrap();
+        match &program.definitions[..] {
+            [Definition::ContractDef {
+                name,
+                fields,
+                definitions,
+                attributes,
+                ..
+            }] => {
+                assert_eq!(name, "Counter");
+                assert_eq!(fields[0].name, "count");
+                assert_eq!(definitions.len(), 3);
+                assert_eq!(attributes[0].name, "version");
+            }
+            other => panic!("Expected a contract definition, got {:?}", other),
+        }
+
+        // Lowered to storage, the events and the functions, with the
+        // constructor exported as `deploy`
+        let program = Parser::new(source).parse_program().unwrap();
+        match &program.definitions[0] {
+            Definition::StorageDef {
+                fields, attributes, ..
+            } => {
+                assert_eq!(fields[0].name, "count");
+                assert_eq!(attributes[0].name, "version");
+            }
+            other => panic!("Expected storage, got {:?}", other),
+        }
+        assert!(matches!(
+            &program.definitions[1],
+            Definition::EventDef { .. }
+        ));
+        let constructor = program.definitions[2].attributes();
+        assert_eq!(constructor[0].name, "export");
+        assert_eq!(program.definitions[3].visibility(), Visibility::Public);
+
+        for source in [
+            "contract A { storage { x: u24 } }",
+            "contract A { contract B {} }",
+            "contract A { let x: u24 = 1; }",
+            "pub contract A {}",
+        ] {
+            assert!(Parser::new(source).parse_program().is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_parser_unchecked_arithmetic() {
+        let source = r#"
+unchecked fn wrap(a: u24) -> u24 {
+    return a + 1;
+}
+
+fn count(n: u24) -> u24 {
+    unchecked: {
+        n = n + 1;
+    }
+    return n;
+}
+"#;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        match &program.definitions[0] {
+            Definition::FunctionDef { name, checked, .. } => {
+                assert_eq!(name, "wrap");
+                assert_eq!(*checked, Some(false));
+            }
+            _ => panic!("Expected function definition"),
+        }
+        match &program.definitions[1] {
+            Definition::FunctionDef { checked, body, .. } => {
+                assert_eq!(*checked, None);
+                match &body.statements[0] {
+                    Statement::Unchecked { body, .. } => assert_eq!(body.statements.len(), 1),
+                    other => panic!("Expected unchecked block, got {:?}", other),
+                }
+            }
+            _ => panic!("Expected function definition"),
+        }
+    }
+
+    #[test]
+    fn test_parser_map_literals_and_access() {
+        let source = r#"
+fn prices() -> u24 {
+    empty = {};
+    prices = {1: 10, 2: 20,};
+    prices[3] = prices[1] + 5;
+    return prices[3];
+}
+"#;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        let body = match &program.definitions[0] {
+            Definition::FunctionDef { body, .. } => body,
+            _ => panic!("Expected function definition"),
+        };
+        match &body.statements[0] {
+            Statement::Assignment {
+                value: Expr::Map { entries, .. },
+                ..
+            } => assert!(entries.is_empty()),
+            other => panic!("Expected empty map literal, got {:?}", other),
+        }
+        match &body.statements[1] {
+            Statement::Assignment {
+                value: Expr::Map { entries, .. },
+                ..
+            } => {
+                assert_eq!(entries.len(), 2);
+                assert!(matches!(
+                    entries[1],
+                    (
+                        Expr::Literal {
+                            kind: LiteralKind::Uint(2),
+                            ..
+                        },
+                        Expr::Literal {
+                            kind: LiteralKind::Uint(20),
+                            ..
+                        }
+                    )
+                ));
+            }
+            other => panic!("Expected map literal, got {:?}", other),
+        }
+        match &body.statements[2] {
+            Statement::Assignment {
+                pattern: Pattern::MapAccess { map, .. },
+                value: Expr::BinaryOp { left, .. },
+                ..
+            } => {
+                assert!(matches!(&**map, Expr::Variable { name, .. } if name == "prices"));
+                assert!(matches!(&**left, Expr::MapAccess { .. }));
+            }
+            other => panic!("Expected map entry assignment, got {:?}", other),
+        }
+
+        // Braces around statements are still a block
+        let mut parser = Parser::new("fn main() -> u24 { x = { y = 1; y }; return x; }");
+        let program = parser.parse_program().unwrap();
+        match &program.definitions[0] {
+            Definition::FunctionDef { body, .. } => assert!(matches!(
+                &body.statements[0],
+                Statement::Assignment {
+                    value: Expr::Block { .. },
+                    ..
+                }
+            )),
+            _ => panic!("Expected function definition"),
+        }
+    }
+
+    #[test]
+    fn test_parser_inline_asm() {
+        let source = r##"
+fn main(x: u24) -> u24 {
+    y = asm(in a0 = x + 1, in a1 = 2, out a2) {
+        "add a2, a0, a1"
+        "# doubled"
+        "slli a2, a2, 1"
+    };
+    asm { "ebreak" };
+    return y;
+}
+"##;
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program().unwrap();
+
+        let body = match &program.definitions[0] {
+            Definition::FunctionDef { body, .. } => body,
+            _ => panic!("Expected function definition"),
+        };
+        match &body.statements[0] {
+            Statement::Assignment {
+                value:
+                    Expr::InlineAsm {
+                        inputs,
+                        output,
+                        instructions,
+                        ..
+                    },
+                ..
+            } => {
+                let registers: Vec<&str> = inputs.iter().map(|(r, _)| r.as_str()).collect();
+                assert_eq!(registers, ["a0", "a1"]);
+                assert!(matches!(inputs[0].1, Expr::BinaryOp { .. }));
+                assert_eq!(output.as_deref(), Some("a2"));
+                assert_eq!(instructions.len(), 3);
+            }
+            other => panic!("Expected inline assembly, got {:?}", other),
+        }
+        assert!(matches!(
+            &body.statements[1],
+            Statement::Expr {
+                expr: Expr::InlineAsm { inputs, output: None, .. },
+                ..
+            } if inputs.is_empty()
+        ));
+
+        for source in [
+            "fn main() -> u24 { return asm(out a0, out a1) { }; }",
+            "fn main() -> u24 { return asm(a0 = 1) { }; }",
+            "fn main() -> u24 { return asm { add }; }",
+        ] {
+            assert!(Parser::new(source).parse_program().is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_parser_errors_name_what_was_expected() {
+        let errors = [
+            (
+                "fn main() -> u24 {\n    for i in 0..10 {\n    }\n}",
+                "Unexpected token . at line 2, column 16, expected field name",
+            ),
+            (
+                "type Point {\n    fn: u24,\n}",
+                "Unexpected token fn at line 2, column 5, expected identifier",
+            ),
+            (
+                "storage {\n    let: u24,\n}",
+                "Unexpected token let at line 2, column 5, expected identifier",
+            ),
+            ("fn main(", "Unexpected end of input, expected identifier"),
+        ];
+        for (source, message) in errors {
+            let error = Parser::new(source).parse_program().unwrap_err();
+            assert_eq!(error.to_string(), message, "{}", source);
+        }
+    }
 }
@@ -0,0 +1,940 @@
+//! The stages of a compilation
+//!
+//! A [`CompilerPipeline`] takes a [`Source`] through the stages of the
+//! compiler, each producing what the next one reads:
+//!
+//! - `parse`: the [`Ast`], without the definitions `#[cfg]` leaves out and
+//!   with the generated access control
+//! - `check`: the [`TypedAst`], type checked along with the definitions of
+//!   the standard library it uses, with the warnings of the lints that are
+//!   not allowed
+//! - `lower`: the [`Ir`], linked with the standard library, lowered and
+//!   optimized, with shared maps copied before they are written
+//! - `codegen`: the RISC-V instructions, the WebAssembly module for
+//!   [`Target::Wasm32`], or the Yul object for [`Target::Evm`]
+//! - `encode`: the PolkaVM blob, the binary WebAssembly module, or the EVM
+//!   deploy bytecode
+//!
+//! [`CompilerPipeline::run`] runs them all and gathers what they produced
+//! into a [`CompilationResult`]. Tools that only need the first stages,
+//! such as the test runner, which only parses, call them one by one.
+//! Hooks added with [`CompilerPipeline::inspect`] see the output of every
+//! stage as it is produced.
+
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::analyzer::gas_profiler::{GasProfile, GasProfiler};
+use crate::compiler::access;
+use crate::compiler::analyzer::linearity::Linearity;
+use crate::compiler::analyzer::lints::{lint_program, Level, LintLevels, Warning};
+use crate::compiler::analyzer::type_checker::{TypeChecker, TypeError};
+use crate::compiler::cfg::Cfg;
+use crate::compiler::codegen::encoder::{assemble, Isa};
+use crate::compiler::codegen::evm::EvmCodegen;
+use crate::compiler::codegen::metadata::{
+    build_metadata, collect_constructor, collect_error_metadata, collect_event_metadata,
+    collect_exports, collect_function_metadata, collect_migration, collect_storage_layout,
+    collect_storage_version, ContractMetadata,
+};
+use crate::compiler::codegen::risc_v::CodegenError;
+use crate::compiler::codegen::risc_v::{Instruction, RiscVCodegen};
+use crate::compiler::codegen::wasm::{WasmCodegen, WasmModule};
+use crate::compiler::codegen::yul::YulObject;
+use crate::compiler::lexer::lexer::BendLexer;
+use crate::compiler::lexer::token::Token;
+use crate::compiler::lowering::Lowering;
+use crate::compiler::module::cache::{CachedCode, CachedModule, ModuleCache};
+use crate::compiler::optimizer::passes::{create_default_manager, OptimizationLevel};
+use crate::compiler::parser::ast::{Definition, Program};
+use crate::compiler::parser::parser::Parser;
+use crate::compiler::polkavm::abi::{generate_abi, ContractABI};
+use crate::compiler::polkavm::bridge::compile_to_polkavm;
+use crate::compiler::timing::Timings;
+use crate::compiler::upgrade;
+use crate::debugger::DebugInfo;
+use crate::diagnostics::Diagnostic;
+use crate::stdlib::modules;
+use crate::{CompileError, CompilerOptions};
+
+/// The text a compilation starts from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    /// Name of the contract, from the file name when read from one
+    pub name: String,
+    /// The file the text was read from, if any
+    pub path: Option<PathBuf>,
+    pub text: String,
+}
+
+impl Source {
+    pub fn new(name: &str, text: &str) -> Self {
+        Source {
+            name: name.to_string(),
+            path: None,
+            text: text.to_string(),
+        }
+    }
+
+    /// The contents of the file at `path`
+    pub fn from_path(path: &Path) -> std::io::Result<Self> {
+        Ok(Source {
+            name: path.file_stem().map_or("contract".to_string(), |stem| {
+                stem.to_string_lossy().to_string()
+            }),
+            path: Some(path.to_path_buf()),
+            text: std::fs::read_to_string(path)?,
+        })
+    }
+
+    /// Everything on standard input, as the contract `stdin`
+    pub fn from_stdin() -> std::io::Result<Self> {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        Ok(Source {
+            name: "stdin".to_string(),
+            path: None,
+            text,
+        })
+    }
+}
+
+/// What code is generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    /// RISC-V, linked into a PolkaVM blob
+    #[default]
+    RiscV,
+    /// WebAssembly, for pallet-contracts and browsers
+    Wasm32,
+    /// EVM bytecode, through Yul, for Ethereum-compatible chains.
+    /// Experimental: only word-sized values and storage are supported
+    Evm,
+}
+
+impl Target {
+    /// Extension of the binary
+    pub fn extension(self) -> &'static str {
+        match self {
+            Target::RiscV => "bin",
+            Target::Wasm32 => "wasm",
+            Target::Evm => "evm",
+        }
+    }
+
+    /// Extension of the listing
+    pub fn listing_extension(self) -> &'static str {
+        match self {
+            Target::RiscV => "s",
+            Target::Wasm32 => "wat",
+            Target::Evm => "yul",
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "riscv" => Ok(Target::RiscV),
+            "wasm32" => Ok(Target::Wasm32),
+            "evm" => Ok(Target::Evm),
+            _ => Err(format!(
+                "Unknown target '{}', expected riscv, wasm32 or evm",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::RiscV => write!(f, "riscv"),
+            Target::Wasm32 => write!(f, "wasm32"),
+            Target::Evm => write!(f, "evm"),
+        }
+    }
+}
+
+/// A parsed source, without the definitions whose `#[cfg]` does not hold
+#[derive(Debug, Clone)]
+pub struct Ast {
+    pub program: Program,
+}
+
+/// A source that passed type checking, unless it was turned off
+#[derive(Debug, Clone)]
+pub struct TypedAst {
+    pub program: Program,
+    /// The definitions of the standard library the program uses, see
+    /// [`modules::link`]
+    pub library: Vec<Definition>,
+    /// The warnings of the lints that are not allowed
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// A lowered and optimized program, ready for code generation
+#[derive(Debug, Clone)]
+pub struct Ir {
+    pub program: Program,
+}
+
+/// The output of a stage, as hooks see it
+#[derive(Debug, Clone, Copy)]
+pub enum Stage<'a> {
+    Ast(&'a Program),
+    TypedAst(&'a Program),
+    Ir(&'a Program),
+    Instructions(&'a [Instruction]),
+    /// The generated module, for [`Target::Wasm32`]
+    WasmModule(&'a WasmModule),
+    /// The generated object, for [`Target::Evm`]
+    YulObject(&'a YulObject),
+    Module(&'a [u8]),
+}
+
+/// Everything a compilation produces, in memory
+#[derive(Debug, Clone)]
+pub struct CompilationResult {
+    /// The PolkaVM blob, the binary WebAssembly module, or the EVM deploy
+    /// bytecode
+    pub blob: Vec<u8>,
+    /// The assembly listing, with [`CompilerOptions::assembly`], in the
+    /// text format for WebAssembly and as Yul for the EVM
+    pub assembly: Option<String>,
+    /// With [`CompilerOptions::abi`]
+    pub abi: Option<ContractABI>,
+    /// With [`CompilerOptions::metadata`]
+    pub metadata: Option<ContractMetadata>,
+    /// Where the code of each function is, with [`CompilerOptions::debug`],
+    /// for RISC-V
+    pub debug_info: Option<DebugInfo>,
+    /// The warnings of the lints that are not allowed
+    pub diagnostics: Vec<Diagnostic>,
+    /// Static gas estimates of the functions
+    pub gas_report: Option<GasProfile>,
+    /// The phases that ran: `lex` with [`CompilerOptions::time_passes`],
+    /// `parse`, `type-check`, `lower`, `optimize` with a phase for each
+    /// run of a pass, `codegen` and `encode`. Phases the module cache
+    /// answered are left out.
+    pub timings: Timings,
+}
+
+type Hook<'a> = Box<dyn FnMut(Stage<'_>) + 'a>;
+
+/// Runs sources through the stages of the compiler with one set of options
+pub struct CompilerPipeline<'a> {
+    options: &'a CompilerOptions,
+    /// Overrides [`CompilerOptions::optimize`]
+    level: Option<OptimizationLevel>,
+    dispatcher: bool,
+    cache: Option<ModuleCache>,
+    hooks: Vec<Hook<'a>>,
+    timings: Timings,
+}
+
+impl<'a> CompilerPipeline<'a> {
+    pub fn new(options: &'a CompilerOptions) -> Self {
+        CompilerPipeline {
+            options,
+            level: None,
+            dispatcher: false,
+            cache: None,
+            hooks: Vec::new(),
+            timings: Timings::new(),
+        }
+    }
+
+    /// Generate a dispatcher routing calls to the functions by selector,
+    /// as deployed contracts need
+    pub fn with_dispatcher(mut self) -> Self {
+        self.dispatcher = true;
+        self
+    }
+
+    /// Optimize at `level` rather than as [`CompilerOptions::optimize`]
+    /// says
+    pub fn with_optimization_level(mut self, level: OptimizationLevel) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// Reuse and update what `cache` holds for sources read from files.
+    /// Generated code is only reused without a level.
+    pub fn with_cache(mut self, cache: ModuleCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Call `hook` with the output of every stage
+    pub fn inspect(mut self, hook: impl FnMut(Stage<'_>) + 'a) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// The phases the stages run so far took
+    pub fn timings(&self) -> &Timings {
+        &self.timings
+    }
+
+    /// Parse `source` and remove the definitions whose `#[cfg]` does not
+    /// hold
+    pub fn parse(&mut self, source: &Source) -> Result<Ast, CompileError> {
+        let program = self.parse_unconfigured(source)?;
+        self.configure(source, program)
+    }
+
+    fn parse_unconfigured(&mut self, source: &Source) -> Result<Program, CompileError> {
+        // The parser lexes as it goes, so lexing is only timed on its own
+        // when asked to
+        if self.options.time_passes {
+            self.timings.time("lex", |_| {
+                let mut lexer = BendLexer::new(&source.text);
+                while lexer.next_token().token != Token::EOF {}
+            });
+        }
+        self.timings
+            .time("parse", |_| Parser::new(&source.text).parse_program())
+            .map_err(|e| {
+                CompileError::Diagnostic(Box::new(Diagnostic::from_parse_error(&e, &source.text)))
+            })
+    }
+
+    fn configure(&mut self, source: &Source, mut program: Program) -> Result<Ast, CompileError> {
+        self.options
+            .cfg
+            .configure(&mut program)
+            .map_err(|e| type_error(&e, source))?;
+        access::expand(&mut program).map_err(|e| type_error(&e, source))?;
+        self.hook(Stage::Ast(&program));
+        Ok(Ast { program })
+    }
+
+    /// Type check `ast`, unless [`CompilerOptions::type_check`] is off,
+    /// check that it can upgrade [`CompilerOptions::upgrade_from`], and lint
+    /// it. Fails with the warnings when a lint is denied.
+    pub fn check(&mut self, source: &Source, ast: Ast) -> Result<TypedAst, CompileError> {
+        self.check_with(source, ast, None).map(|(typed, _)| typed)
+    }
+
+    /// Like [`check`](Self::check), taking the warnings of an earlier type
+    /// check instead of checking again. Returns the type checker's
+    /// warnings too.
+    fn check_with(
+        &mut self,
+        source: &Source,
+        ast: Ast,
+        checked: Option<Vec<Warning>>,
+    ) -> Result<(TypedAst, Vec<Warning>), CompileError> {
+        let program = ast.program;
+        let library = modules::link(&program);
+        let mut type_warnings = Vec::new();
+        if self.options.type_check {
+            type_warnings = match checked {
+                Some(warnings) => warnings,
+                None => {
+                    let mut type_checker = TypeChecker::new();
+                    self.timings
+                        .time("type-check", |_| {
+                            type_checker.check_linked(&program, &library)
+                        })
+                        .map_err(|e| type_error(&e, source))?;
+                    type_checker.warnings().to_vec()
+                }
+            };
+        }
+
+        if let Some(deployed) = &self.options.upgrade_from {
+            upgrade::check_program(deployed, &program).map_err(|e| type_error(&e, source))?;
+        }
+
+        let mut warnings = lint_program(&program);
+        warnings.extend(type_warnings.iter().cloned());
+        let (diagnostics, denied) =
+            lint_diagnostics(&source.text, &program, warnings, &self.options.lint_levels);
+        if denied {
+            return Err(CompileError::DeniedWarnings(diagnostics));
+        }
+        self.hook(Stage::TypedAst(&program));
+        Ok((
+            TypedAst {
+                program,
+                library,
+                diagnostics,
+            },
+            type_warnings,
+        ))
+    }
+
+    /// Lower the program and optimize it, checking its specifications with
+    /// [`CompilerOptions::debug`], then copy the maps it writes while shared
+    pub fn lower(&mut self, typed: &TypedAst) -> Result<Ir, CompileError> {
+        let specifications = self.options.debug;
+        let program = self.timings.time("lower", |_| {
+            let mut program = typed.program.clone();
+            program.definitions.extend(typed.library.iter().cloned());
+            Lowering::new()
+                .with_specifications(specifications)
+                .lower_program(&mut program);
+            program
+        });
+        let program = match self.optimization_level() {
+            OptimizationLevel::None => program,
+            level => {
+                let mut manager = create_default_manager();
+                if self.level.is_some() {
+                    manager.set_level(level);
+                }
+                self.timings
+                    .time("optimize", |timings| {
+                        manager.optimize_timed(program, timings)
+                    })
+                    .map_err(|e| CompileError::Optimization(e.to_string()))?
+            }
+        };
+        let program = self.timings.time("linearity", |_| {
+            let mut program = program;
+            Linearity::new().lower_program(&mut program);
+            program
+        });
+        self.hook(Stage::Ir(&program));
+        Ok(Ir { program })
+    }
+
+    /// Generate the RISC-V code of the contract
+    ///
+    /// Features the backend does not support are reported as diagnostics
+    /// pointing at them.
+    pub fn codegen(&mut self, source: &Source, ir: &Ir) -> Result<Vec<Instruction>, CompileError> {
+        let mut generator = RiscVCodegen::new().with_isa(self.options.isa);
+        if self.dispatcher {
+            generator = generator.with_dispatcher();
+        }
+        let code = self
+            .timings
+            .time("codegen", |_| generator.generate(&ir.program))
+            .map_err(|e| codegen_error(e, source))?;
+        self.hook(Stage::Instructions(&code));
+        Ok(code)
+    }
+
+    /// Generate the WebAssembly module, whose `call` export always
+    /// dispatches messages
+    ///
+    /// Features the wasm32 target does not support are reported as
+    /// diagnostics pointing at them.
+    pub fn codegen_wasm(&mut self, source: &Source, ir: &Ir) -> Result<WasmModule, CompileError> {
+        let module = self
+            .timings
+            .time("codegen", |_| WasmCodegen::new().generate(&ir.program))
+            .map_err(|e| codegen_error(e, source))?;
+        self.hook(Stage::WasmModule(&module));
+        Ok(module)
+    }
+
+    /// Generate the Yul object of the contract, named after the source
+    ///
+    /// Features the EVM target does not support are reported as
+    /// diagnostics pointing at them.
+    pub fn codegen_evm(&mut self, source: &Source, ir: &Ir) -> Result<YulObject, CompileError> {
+        let object = self
+            .timings
+            .time("codegen", |_| {
+                EvmCodegen::new()
+                    .with_name(&source.name)
+                    .generate(&ir.program)
+            })
+            .map_err(|e| codegen_error(e, source))?;
+        self.hook(Stage::YulObject(&object));
+        Ok(object)
+    }
+
+    /// Assemble the deploy bytecode of a Yul object
+    pub fn encode_evm(&mut self, object: &YulObject) -> Result<Vec<u8>, CompileError> {
+        let bytecode = self
+            .timings
+            .time("encode", |_| object.assemble())
+            .map_err(|e| CompileError::Codegen(e.to_string()))?;
+        self.hook(Stage::Module(&bytecode));
+        Ok(bytecode)
+    }
+
+    pub fn encode_wasm(&mut self, module: &WasmModule) -> Vec<u8> {
+        let binary = self.timings.time("encode", |_| module.encode());
+        self.hook(Stage::Module(&binary));
+        binary
+    }
+
+    /// Link the code into a PolkaVM blob, or assemble it to raw machine
+    /// code for 64-bit ISAs, which PolkaVM blobs cannot hold
+    pub fn encode(&mut self, code: &[Instruction]) -> Result<Vec<u8>, CompileError> {
+        let isa = self.options.isa;
+        let blob = if isa.is_64_bit() {
+            self.timings
+                .time("encode", |_| assemble(code, isa))
+                .map_err(|e| CompileError::Codegen(e.to_string()))?
+                .code
+        } else {
+            self.timings
+                .time("encode", |_| compile_to_polkavm(code, None))
+                .map_err(|e| CompileError::PolkaVM(e.to_string()))?
+                .binary
+                .ok_or_else(|| CompileError::Codegen("No binary generated".to_string()))?
+        };
+        self.hook(Stage::Module(&blob));
+        Ok(blob)
+    }
+
+    /// Run every stage on `source`, along with whatever the options ask
+    /// for besides the blob
+    pub fn run(&mut self, source: &Source) -> Result<CompilationResult, CompileError> {
+        let cache = self.cache.take();
+        let result = self.run_cached(source, cache.as_ref());
+        self.cache = cache;
+        result
+    }
+
+    fn run_cached(
+        &mut self,
+        source: &Source,
+        cache: Option<&ModuleCache>,
+    ) -> Result<CompilationResult, CompileError> {
+        let cache = cache.zip(source.path.as_deref());
+        let cached = cache.and_then(|(cache, path)| cache.get(path, &source.text));
+
+        let mut entry = match cached {
+            Some(entry) => entry,
+            None => {
+                let program = self.parse_unconfigured(source)?;
+                let path = cache.map_or(Path::new(&source.name), |(_, path)| path);
+                CachedModule::new(path, &source.text, program)
+            }
+        };
+        entry.configured_for(&self.options.cfg);
+        let ast = self.configure(source, entry.ast.clone())?;
+
+        let checked = entry.type_checked.then(|| entry.warnings.clone());
+        let (typed, type_warnings) = self.check_with(source, ast, checked)?;
+        if self.options.type_check {
+            entry.type_checked = true;
+            entry.warnings = type_warnings;
+        }
+
+        if self.options.target == Target::Wasm32 {
+            if let Some((cache, _)) = cache {
+                let _ = cache.insert(&entry);
+            }
+            let ir = self.lower(&typed)?;
+            let module = self.codegen_wasm(source, &ir)?;
+            let blob = self.encode_wasm(&module);
+            let listing = module.to_string();
+            return Ok(self.result(source, typed, blob, listing, None));
+        }
+        if self.options.target == Target::Evm {
+            if let Some((cache, _)) = cache {
+                let _ = cache.insert(&entry);
+            }
+            let ir = self.lower(&typed)?;
+            let object = self.codegen_evm(source, &ir)?;
+            let blob = self.encode_evm(&object)?;
+            let listing = object.to_string();
+            return Ok(self.result(source, typed, blob, listing, None));
+        }
+
+        let optimized = self.optimization_level() != OptimizationLevel::None;
+        let debug = self.options.debug;
+        let reusable = self.level.is_none();
+        let code = match entry.code.as_ref().filter(|code| {
+            reusable
+                && code.optimized == optimized
+                && code.debug == debug
+                && code.isa == self.options.isa
+                && code.dispatcher == self.dispatcher
+        }) {
+            Some(code) => {
+                self.hook(Stage::Instructions(&code.instructions));
+                code.instructions.clone()
+            }
+            None => {
+                let ir = self.lower(&typed)?;
+                let code = self.codegen(source, &ir)?;
+                if reusable {
+                    entry.code = Some(CachedCode {
+                        optimized,
+                        debug,
+                        isa: self.options.isa,
+                        dispatcher: self.dispatcher,
+                        instructions: code.clone(),
+                    });
+                }
+                code
+            }
+        };
+
+        if let Some((cache, _)) = cache {
+            // A cache that cannot be written only costs a rebuild next time
+            let _ = cache.insert(&entry);
+        }
+
+        let blob = self.encode(&code)?;
+        let listing = format!("; Assembly for {}\n{:?}", source.name, code);
+        let debug_info = self.options.debug.then(|| {
+            let path = match &source.path {
+                Some(path) => self.options.remap_path(path),
+                None => PathBuf::from(&source.name),
+            };
+            DebugInfo::new(path, &source.text, &typed.program, &code)
+        });
+        Ok(self.result(source, typed, blob, listing, debug_info))
+    }
+
+    /// Gather what a compilation produced, along with the ABI and metadata
+    fn result(
+        &mut self,
+        source: &Source,
+        typed: TypedAst,
+        blob: Vec<u8>,
+        listing: String,
+        debug_info: Option<DebugInfo>,
+    ) -> CompilationResult {
+        let options = self.options;
+        let (name, text, program) = (&source.name, &source.text, &typed.program);
+        let metadata = (options.abi || options.metadata).then(|| {
+            let isa = (options.target == Target::RiscV).then_some(options.isa);
+            contract_metadata(name, text, program, &options.cfg, isa)
+        });
+
+        CompilationResult {
+            blob,
+            assembly: options.assembly.then_some(listing),
+            abi: metadata.as_ref().filter(|_| options.abi).map(generate_abi),
+            metadata: metadata.filter(|_| options.metadata),
+            debug_info,
+            diagnostics: typed.diagnostics,
+            gas_report: GasProfiler::new().profile_source(text, name).ok(),
+            timings: std::mem::take(&mut self.timings),
+        }
+    }
+
+    fn optimization_level(&self) -> OptimizationLevel {
+        match self.level {
+            Some(level) => level,
+            None if self.options.optimize => OptimizationLevel::Standard,
+            None => OptimizationLevel::None,
+        }
+    }
+
+    fn hook(&mut self, stage: Stage<'_>) {
+        for hook in &mut self.hooks {
+            hook(stage);
+        }
+    }
+}
+
+/// The metadata of the contract `name` whose source `text` compiled to
+/// `program`, configured with `cfg`, for RISC-V code of `isa` if any
+pub fn contract_metadata(
+    name: &str,
+    text: &str,
+    program: &Program,
+    cfg: &Cfg,
+    isa: Option<Isa>,
+) -> ContractMetadata {
+    let mut metadata = build_metadata(
+        name,
+        env!("CARGO_PKG_VERSION"),
+        &[(name, text)],
+        collect_function_metadata(program),
+        Default::default(),
+        Default::default(),
+    );
+    metadata.events = collect_event_metadata(program);
+    metadata.errors = collect_error_metadata(program);
+    metadata.storage_layout = collect_storage_layout(program);
+    metadata.storage_version = collect_storage_version(program);
+    metadata.migration = collect_migration(program);
+    metadata.exports = collect_exports(program);
+    metadata.constructor = collect_constructor(program);
+    metadata.features = cfg.features.iter().cloned().collect();
+    metadata.isa = isa;
+    metadata
+}
+
+fn type_error(error: &TypeError, source: &Source) -> CompileError {
+    CompileError::Diagnostic(Box::new(Diagnostic::from_type_error(error, &source.text)))
+}
+
+/// A code generation error, as a diagnostic when it names a feature the
+/// backend does not support
+fn codegen_error(error: CodegenError, source: &Source) -> CompileError {
+    match error {
+        CodegenError::UnsupportedFeature(_) => CompileError::Diagnostic(Box::new(
+            Diagnostic::from_codegen_error(&error, &source.text),
+        )),
+        error => CompileError::Codegen(error.to_string()),
+    }
+}
+
+/// The diagnostics of the `warnings` about `program` whose lints are not
+/// allowed at `levels`, sorted by position, and whether any is denied
+pub fn lint_diagnostics(
+    source: &str,
+    program: &Program,
+    mut warnings: Vec<Warning>,
+    levels: &LintLevels,
+) -> (Vec<Diagnostic>, bool) {
+    warnings.sort_by_key(|warning| warning.location.start);
+    let mut diagnostics = Vec::new();
+    let mut denied = false;
+    for warning in &warnings {
+        let level = levels.level(program, warning);
+        match level {
+            Level::Allow => continue,
+            Level::Warn => {}
+            Level::Deny => denied = true,
+        }
+        diagnostics.push(Diagnostic::from_warning(warning, level, source));
+    }
+    (diagnostics, denied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+        fn double(x: u24) -> u24 {
+            return x * 2;
+        }
+
+        #[message]
+        fn quadruple(x: u24) -> u24 {
+            return double(double(x));
+        }
+    "#;
+
+    #[test]
+    fn test_stages_one_by_one() {
+        let options = CompilerOptions::default();
+        let source = Source::new("math", SOURCE);
+        let mut pipeline = CompilerPipeline::new(&options);
+
+        let ast = pipeline.parse(&source).unwrap();
+        assert_eq!(ast.program.definitions.len(), 2);
+        let typed = pipeline.check(&source, ast).unwrap();
+        assert!(typed.diagnostics.is_empty());
+        let ir = pipeline.lower(&typed).unwrap();
+        let code = pipeline.codegen(&source, &ir).unwrap();
+        let blob = pipeline.encode(&code).unwrap();
+
+        let result = CompilerPipeline::new(&options).run(&source).unwrap();
+        assert_eq!(blob, result.blob);
+        let phases: Vec<&str> = pipeline
+            .timings()
+            .top_level()
+            .map(|phase| phase.name.as_str())
+            .collect();
+        assert_eq!(
+            phases,
+            [
+                "parse",
+                "type-check",
+                "lower",
+                "optimize",
+                "linearity",
+                "codegen",
+                "encode"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hooks_see_every_stage() {
+        let options = CompilerOptions::default();
+        let mut stages = Vec::new();
+        let mut pipeline = CompilerPipeline::new(&options).inspect(|stage| {
+            stages.push(match stage {
+                Stage::Ast(_) => "ast",
+                Stage::TypedAst(_) => "typed",
+                Stage::Ir(_) => "ir",
+                Stage::Instructions(_) => "instructions",
+                Stage::WasmModule(_) => "wasm",
+                Stage::YulObject(_) => "yul",
+                Stage::Module(_) => "module",
+            })
+        });
+        pipeline.run(&Source::new("math", SOURCE)).unwrap();
+        drop(pipeline);
+        assert_eq!(stages, ["ast", "typed", "ir", "instructions", "module"]);
+    }
+
+    #[test]
+    fn test_dispatcher_and_levels() {
+        let options = CompilerOptions::default();
+        let source = Source::new("math", SOURCE);
+        let plain = CompilerPipeline::new(&options).run(&source).unwrap();
+        let dispatched = CompilerPipeline::new(&options)
+            .with_dispatcher()
+            .run(&source)
+            .unwrap();
+        assert_ne!(plain.blob, dispatched.blob);
+
+        // A helper taking a list is not a message, so it needs no decoding
+        let source = Source::new(
+            "lists",
+            "fn count(xs: List<u24>) -> u24 {\n    return List/length(xs);\n}\n\n#[message]\nfn size() -> u24 {\n    return count(List/range(0, 3));\n}\n",
+        );
+        assert!(CompilerPipeline::new(&options)
+            .with_dispatcher()
+            .run(&source)
+            .is_ok());
+
+        let unoptimized = CompilerPipeline::new(&options)
+            .with_optimization_level(OptimizationLevel::None)
+            .run(&source)
+            .unwrap();
+        assert!(!unoptimized
+            .timings
+            .top_level()
+            .any(|phase| phase.name == "optimize"));
+    }
+
+    #[test]
+    fn test_cached_code_follows_debug() {
+        let dir = std::env::temp_dir().join(format!("bend_pipeline_debug_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("vault.bend");
+        std::fs::write(
+            &path,
+            "storage {\n    total: u24,\n}\n\n#[message]\n#[requires(amount > 0)]\nfn deposit(amount: u24) -> u24 {\n    total = total + amount;\n    return total;\n}\n",
+        )
+        .unwrap();
+        let source = Source::from_path(&path).unwrap();
+
+        let release = CompilerOptions::default();
+        let debug = CompilerOptions {
+            debug: true,
+            ..Default::default()
+        };
+        let fresh =
+            |options: &CompilerOptions| CompilerPipeline::new(options).run(&source).unwrap().blob;
+        let (release_blob, debug_blob) = (fresh(&release), fresh(&debug));
+        assert_ne!(release_blob, debug_blob);
+
+        let cache = ModuleCache::new(dir.join("cache"));
+        for options in [&debug, &release, &debug, &release] {
+            let blob = CompilerPipeline::new(options)
+                .with_cache(cache.clone())
+                .run(&source)
+                .unwrap()
+                .blob;
+            let expected = if options.debug {
+                &debug_blob
+            } else {
+                &release_blob
+            };
+            assert_eq!(&blob, expected);
+        }
+
+        // Code cached without a dispatcher is not reused for a contract
+        let contract = CompilerPipeline::new(&release)
+            .with_dispatcher()
+            .with_cache(cache.clone())
+            .run(&source)
+            .unwrap()
+            .blob;
+        assert_ne!(contract, release_blob);
+        assert_eq!(
+            contract,
+            CompilerPipeline::new(&release)
+                .with_dispatcher()
+                .run(&source)
+                .unwrap()
+                .blob
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_errors_are_diagnostics() {
+        let options = CompilerOptions::default();
+        let mut pipeline = CompilerPipeline::new(&options);
+        assert!(matches!(
+            pipeline.parse(&Source::new("broken", "fn main( {")),
+            Err(CompileError::Diagnostic(_))
+        ));
+        let source = Source::new("untyped", "fn main() -> u24 {\n    return missing(1);\n}\n");
+        let ast = pipeline.parse(&source).unwrap();
+        assert!(matches!(
+            pipeline.check(&source, ast),
+            Err(CompileError::Diagnostic(diagnostic)) if diagnostic.code.starts_with('E')
+        ));
+    }
+
+    #[test]
+    fn test_upgrade_from_deployed_metadata() {
+        let deployed = Source::new("vault", "storage {\n    total: u24,\n}\n");
+        let with_metadata = CompilerOptions {
+            metadata: true,
+            ..Default::default()
+        };
+        let metadata = CompilerPipeline::new(&with_metadata)
+            .run(&deployed)
+            .unwrap()
+            .metadata
+            .unwrap();
+        assert_eq!(metadata.storage_layout.len(), 1);
+
+        let options = CompilerOptions {
+            upgrade_from: Some(metadata),
+            ..with_metadata
+        };
+        let appended = Source::new("vault", "storage {\n    total: u24,\n    fee: u24,\n}\n");
+        CompilerPipeline::new(&options).run(&appended).unwrap();
+
+        let retyped = Source::new("vault", "storage {\n    total: u128,\n}\n");
+        assert!(CompilerPipeline::new(&options).run(&retyped).is_err());
+        let migrated = Source::new(
+            "vault",
+            "#[version(1)]\nstorage {\n    total: u128,\n}\n\n#[migrate]\nfn migrate() -> u24 {\n    total = 0;\n    return 0;\n}\n",
+        );
+        let result = CompilerPipeline::new(&options).run(&migrated).unwrap();
+        let metadata = result.metadata.unwrap();
+        assert_eq!(metadata.storage_version, 1);
+        assert_eq!(metadata.migration.as_deref(), Some("migrate"));
+    }
+
+    #[test]
+    fn test_exports_in_blob_and_metadata() {
+        let options = CompilerOptions {
+            metadata: true,
+            ..Default::default()
+        };
+        let source = Source::new(
+            "vault",
+            "#[export(\"deploy\")]\nfn init() {\n}\n\n#[export]\nfn ping() -> u24 {\n    return 1;\n}\n\n#[message]\nfn get() -> u24 {\n    return 2;\n}\n",
+        );
+        let result = CompilerPipeline::new(&options)
+            .with_dispatcher()
+            .run(&source)
+            .unwrap();
+
+        let program = polkavm_common::program::ProgramBlob::parse(&result.blob[..]).unwrap();
+        let exports: Vec<String> = program
+            .exports()
+            .map(|export| export.unwrap().prototype().name().to_string())
+            .collect();
+        assert_eq!(exports, ["call", "deploy", "ping"]);
+
+        let metadata = result.metadata.unwrap();
+        assert_eq!(metadata.exports["deploy"], "init");
+        assert_eq!(metadata.exports["ping"], "ping");
+        assert_eq!(metadata.functions.keys().collect::<Vec<_>>(), ["get"]);
+    }
+}
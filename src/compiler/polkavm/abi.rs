@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 
-use crate::compiler::codegen::metadata::{ContractMetadata, FunctionMetadata};
+use crate::compiler::analyzer::attributes::{Access, Mutability};
+use crate::compiler::codegen::metadata::{
+    ContractMetadata, ErrorMetadata, EventMetadata, FunctionMetadata,
+};
 
 /// Represents the ABI for a contract
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,6 +17,11 @@ pub struct ContractABI {
     /// Contract methods
     pub methods: Vec<MethodABI>,
 
+    /// The function run when the contract is instantiated, whose arguments
+    /// make up the instantiation input, without a selector
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constructor: Option<MethodABI>,
+
     /// Contract events
     pub events: Vec<EventABI>,
 
@@ -50,6 +58,10 @@ pub struct MethodABI {
 
     /// Whether the method is payable
     pub payable: bool,
+
+    /// Accounts allowed to call the method, when it is restricted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<Access>,
 }
 
 /// Represents a parameter in the contract ABI
@@ -81,12 +93,29 @@ pub struct EventABI {
     pub anonymous: bool,
 }
 
+impl EventABI {
+    /// The signature the first topic of the event hashes, like
+    /// `Transfer(Address,Address,u128)`
+    pub fn signature(&self) -> String {
+        let types: Vec<&str> = self
+            .inputs
+            .iter()
+            .map(|input| input.type_.as_str())
+            .collect();
+        format!("{}({})", self.name, types.join(","))
+    }
+}
+
 /// Represents an error in the contract ABI
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ErrorABI {
     /// Error name
     pub name: String,
 
+    /// Error selector (first 4 bytes of the revert data)
+    #[serde(default)]
+    pub selector: String,
+
     /// Error inputs
     pub inputs: Vec<ParameterABI>,
 }
@@ -180,20 +209,45 @@ pub enum TypeKind {
 pub fn generate_abi(metadata: &ContractMetadata) -> ContractABI {
     let mut methods = Vec::new();
 
-    // Add methods from the metadata
+    // Add methods from the metadata, sorted by name so the ABI is deterministic
     for (name, function) in &metadata.functions {
         methods.push(function_to_method_abi(name, function));
     }
-
-    // For this example, we're not implementing events, errors, state variables, or types
+    methods.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Add events, sorted by name so the ABI is deterministic
+    let mut events: Vec<EventABI> = metadata.events.values().map(event_to_abi).collect();
+    events.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Add state variables in storage layout order
+    let state_variables = metadata
+        .storage_layout
+        .iter()
+        .map(|field| StateVariableABI {
+            name: field.name.clone(),
+            type_: field.type_name.clone(),
+            public: false,
+            constant: false,
+        })
+        .collect();
+
+    // Add errors, sorted by name so the ABI is deterministic
+    let mut errors: Vec<ErrorABI> = metadata.errors.values().map(error_to_abi).collect();
+    errors.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // For this example, we're not implementing types
 
     ContractABI {
         name: metadata.name.clone(),
         version: metadata.version.clone(),
         methods,
-        events: Vec::new(),
-        errors: Vec::new(),
-        state_variables: Vec::new(),
+        constructor: metadata.constructor.as_ref().map(|function| MethodABI {
+            type_: MethodType::Constructor,
+            ..function_to_method_abi(&function.name, function)
+        }),
+        events,
+        errors,
+        state_variables,
         types: Vec::new(),
     }
 }
@@ -232,11 +286,61 @@ fn function_to_method_abi(name: &str, function: &FunctionMetadata) -> MethodABI
         type_: MethodType::Function,
         inputs,
         outputs,
-        state_mutability: StateMutability::NonPayable, // Default to non-payable
-        payable: false,
+        state_mutability: match (function.payable, function.mutability) {
+            (true, _) => StateMutability::Payable,
+            (false, Mutability::Pure) => StateMutability::Pure,
+            (false, Mutability::View) => StateMutability::View,
+            (false, Mutability::Mutable) => StateMutability::NonPayable,
+        },
+        payable: function.payable,
+        access: function.access.clone(),
     }
 }
 
+/// Convert event metadata to an event ABI
+fn event_to_abi(event: &EventMetadata) -> EventABI {
+    EventABI {
+        name: event.name.clone(),
+        inputs: event
+            .fields
+            .iter()
+            .map(|field| ParameterABI {
+                name: field.name.clone(),
+                type_: field.type_name.clone(),
+                components: None,
+                indexed: Some(field.indexed),
+            })
+            .collect(),
+        anonymous: false,
+    }
+}
+
+/// Convert error metadata to an error ABI
+fn error_to_abi(error: &ErrorMetadata) -> ErrorABI {
+    ErrorABI {
+        name: error.name.clone(),
+        selector: format!("0x{}", hex::encode(error.selector)),
+        inputs: error
+            .fields
+            .iter()
+            .map(|field| ParameterABI {
+                name: field.name.clone(),
+                type_: field.type_name.clone(),
+                components: None,
+                indexed: None,
+            })
+            .collect(),
+    }
+}
+
+/// The `Ok` type of a `Result` type, which the dispatcher returns on
+/// success, or the type itself
+pub fn ok_type(ty: &str) -> &str {
+    ty.strip_prefix("Result<")
+        .and_then(|params| params.split(',').next())
+        .unwrap_or(ty)
+}
+
 /// Parse an ABI from JSON
 pub fn parse_abi(json: &str) -> Result<ContractABI, serde_json::Error> {
     serde_json::from_str(json)
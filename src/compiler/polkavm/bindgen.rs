@@ -0,0 +1,874 @@
+//! Typed client bindings of a contract, generated from its ABI
+//!
+//! Rust bindings have a struct per message whose fields encode with
+//! `parity-scale-codec` into the call data the dispatcher reads, and an
+//! async client over a `Transport` the application implements, e.g. with
+//! subxt. TypeScript bindings encode call data themselves and call the
+//! contract through polkadot-js.
+//!
+//! Every argument type the dispatcher decodes is supported. Return values
+//! of other types come back as the raw bytes the dispatcher wrote.
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::compiler::polkavm::abi::{ok_type, ContractABI, MethodABI, StateMutability};
+use crate::compiler::wide::wide_type_bits;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    TypeScript,
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rust" => Ok(Language::Rust),
+            "ts" | "typescript" => Ok(Language::TypeScript),
+            _ => Err(format!("Unknown language '{}', expected rust or ts", s)),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BindgenError {
+    /// An argument of a type the dispatcher cannot decode
+    #[error("Argument `{param}` of `{method}` has type {ty}, which call data cannot carry")]
+    UnsupportedType {
+        method: String,
+        param: String,
+        ty: String,
+    },
+    /// An indexer asked for a contract without events
+    #[error("Contract `{0}` declares no events to index")]
+    NoEvents(String),
+}
+
+/// How a value crosses the dispatcher
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Value {
+    U32,
+    I32,
+    Bool,
+    /// `Address` and `Hash`
+    Bytes32,
+    Bytes,
+    /// Unsigned integers of 64 bits or more, little endian
+    Uint(u16),
+    /// Words of a type the dispatcher writes as they are
+    Raw,
+}
+
+impl Value {
+    pub(super) fn of(ty: &str) -> Value {
+        match ty {
+            "u24" | "u32" => Value::U32,
+            "i24" | "i32" => Value::I32,
+            "Bool" | "bool" => Value::Bool,
+            "Address" | "Hash" => Value::Bytes32,
+            "Bytes" => Value::Bytes,
+            _ => wide_type_bits(ty).map_or(Value::Raw, Value::Uint),
+        }
+    }
+
+    fn rust_type(self) -> String {
+        match self {
+            Value::U32 => "u32".to_string(),
+            Value::I32 => "i32".to_string(),
+            Value::Bool => "bool".to_string(),
+            Value::Bytes32 => "[u8; 32]".to_string(),
+            Value::Bytes | Value::Raw => "Vec<u8>".to_string(),
+            Value::Uint(bits @ (64 | 128)) => format!("u{}", bits),
+            Value::Uint(bits) => format!("[u8; {}]", bits / 8),
+        }
+    }
+
+    fn ts_type(self) -> &'static str {
+        match self {
+            Value::U32 | Value::I32 => "number",
+            Value::Bool => "boolean",
+            Value::Uint(_) => "bigint",
+            Value::Bytes32 | Value::Bytes | Value::Raw => "Uint8Array",
+        }
+    }
+
+    /// The `Writer` call encoding `name`
+    fn ts_write(self, name: &str) -> String {
+        match self {
+            Value::U32 | Value::I32 => format!("writer.u32({})", name),
+            Value::Bool => format!("writer.bool({})", name),
+            Value::Bytes32 => format!("writer.fixed({}, 32)", name),
+            Value::Bytes | Value::Raw => format!("writer.vec({})", name),
+            Value::Uint(bits) => format!("writer.uint({}, {})", name, bits / 8),
+        }
+    }
+
+    /// The `Reader` call decoding a value
+    fn ts_read(self) -> String {
+        match self {
+            Value::U32 => "reader.u32()".to_string(),
+            Value::I32 => "reader.i32()".to_string(),
+            Value::Bool => "reader.bool()".to_string(),
+            Value::Bytes32 => "reader.fixed(32)".to_string(),
+            Value::Bytes => "reader.vec()".to_string(),
+            Value::Uint(bits) => format!("reader.uint({})", bits / 8),
+            Value::Raw => "reader.rest()".to_string(),
+        }
+    }
+}
+
+/// A message with its arguments and return value resolved
+struct Message<'a> {
+    method: &'a MethodABI,
+    args: Vec<(String, Value)>,
+    output: Option<Value>,
+}
+
+impl<'a> Message<'a> {
+    fn resolve(method: &'a MethodABI, escape: fn(&str) -> String) -> Result<Self, BindgenError> {
+        let mut args = Vec::new();
+        for input in &method.inputs {
+            let value = Value::of(&input.type_);
+            if value == Value::Raw {
+                return Err(BindgenError::UnsupportedType {
+                    method: method.name.clone(),
+                    param: input.name.clone(),
+                    ty: input.type_.clone(),
+                });
+            }
+            args.push((escape(&identifier(&input.name)), value));
+        }
+        let output = method
+            .outputs
+            .first()
+            .map(|output| Value::of(ok_type(&output.type_)));
+        Ok(Message {
+            method,
+            args,
+            output,
+        })
+    }
+
+    /// Whether calls only read, so only a query makes sense
+    fn read_only(&self) -> bool {
+        matches!(
+            self.method.state_mutability,
+            StateMutability::Pure | StateMutability::View
+        )
+    }
+
+    /// The message as written in source, for doc comments
+    fn signature(&self) -> String {
+        let params: Vec<String> = self
+            .method
+            .inputs
+            .iter()
+            .map(|input| format!("{}: {}", input.name, input.type_))
+            .collect();
+        let output = match self.method.outputs.first() {
+            Some(output) => format!(" -> {}", output.type_),
+            None => String::new(),
+        };
+        format!("{}({}){}", self.method.name, params.join(", "), output)
+    }
+
+    /// Name of the client method querying the message in Rust bindings,
+    /// clear of the client's own methods
+    fn rust_function(&self) -> String {
+        let name = snake_case(&self.method.name);
+        match ["new", "query", "send"].contains(&name.as_str()) {
+            true => format!("{}_message", name),
+            false => rust_identifier(&name),
+        }
+    }
+
+    /// Name of the client method querying the message in TypeScript
+    /// bindings, clear of the client's own members
+    fn ts_method(&self) -> String {
+        let name = camel_case(&self.method.name);
+        let members = [
+            "constructor",
+            "api",
+            "address",
+            "pallet",
+            "dryRun",
+            "query",
+            "send",
+        ];
+        match members.contains(&name.as_str()) {
+            true => format!("{}_", name),
+            false => name,
+        }
+    }
+
+    /// Name of the struct of the message in Rust bindings, clear of the
+    /// names the bindings define themselves
+    fn rust_struct(&self, client: &str) -> String {
+        let name = pascal_case(&self.method.name);
+        let taken = [
+            "Message",
+            "Transport",
+            "Encode",
+            "Decode",
+            "Send",
+            "Sync",
+            "Sized",
+            "Option",
+            "Result",
+            "Vec",
+            "Box",
+            "String",
+            client,
+        ];
+        match taken.contains(&name.as_str()) {
+            true => format!("{}Message", name),
+            false => name,
+        }
+    }
+}
+
+/// Bindings of the contract with `abi` in `language`
+pub fn generate_bindings(abi: &ContractABI, language: Language) -> Result<String, BindgenError> {
+    match language {
+        Language::Rust => generate_rust(abi),
+        Language::TypeScript => generate_typescript(abi),
+    }
+}
+
+fn generate_rust(abi: &ContractABI) -> Result<String, BindgenError> {
+    let messages = abi
+        .methods
+        .iter()
+        .map(|method| Message::resolve(method, rust_identifier))
+        .collect::<Result<Vec<_>, _>>()?;
+    let client = format!("{}Client", pascal_case(&abi.name));
+    let mut out = String::new();
+
+    let _ = writeln!(
+        out,
+        "//! Client of the `{}` contract (version {}), generated by `bend-pvm\n\
+         //! bindgen` from its ABI. Do not edit.",
+        abi.name, abi.version
+    );
+    out.push_str(RUST_PRELUDE);
+
+    for message in &messages {
+        let name = message.rust_struct(&client);
+        let _ = writeln!(out, "\n/// `{}`", message.signature());
+        let _ = writeln!(out, "#[derive(Debug, Clone, PartialEq, Eq, Encode)]");
+        if message.args.is_empty() {
+            let _ = writeln!(out, "pub struct {};", name);
+        } else {
+            let _ = writeln!(out, "pub struct {} {{", name);
+            for (arg, value) in &message.args {
+                let _ = writeln!(out, "    pub {}: {},", arg, value.rust_type());
+            }
+            let _ = writeln!(out, "}}");
+        }
+        let output = message.output.map_or("()".to_string(), Value::rust_type);
+        let _ = writeln!(out, "\nimpl Message for {} {{", name);
+        let _ = writeln!(
+            out,
+            "    const NAME: &'static str = \"{}\";",
+            message.method.name
+        );
+        let _ = writeln!(
+            out,
+            "    const SELECTOR: [u8; 4] = [{}];",
+            byte_list(&message.method.selector)
+        );
+        let _ = writeln!(out, "    const PAYABLE: bool = {};", message.method.payable);
+        let _ = writeln!(out, "    type Output = {};\n", output);
+        let decode = match message.output {
+            None => "Ok(())".to_string(),
+            Some(Value::Raw) => "Ok(data.to_vec())".to_string(),
+            Some(value) => format!("<{}>::decode(&mut data)", value.rust_type()),
+        };
+        let data = match message.output {
+            Some(Value::Raw) => "data",
+            Some(_) => "mut data",
+            None => "_data",
+        };
+        let _ = writeln!(
+            out,
+            "    fn decode_output({}: &[u8]) -> Result<Self::Output, parity_scale_codec::Error> {{\n        {}\n    }}\n}}",
+            data, decode
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "\n/// Errors a call can revert with, by the selector the revert data\n\
+         /// starts with\n\
+         pub const ERRORS: &[([u8; 4], &str)] = &["
+    );
+    for error in &abi.errors {
+        let _ = writeln!(
+            out,
+            "    ([{}], \"{}\"),",
+            byte_list(&error.selector),
+            error.name
+        );
+    }
+    let _ = writeln!(out, "];");
+    out.push_str(RUST_REVERT_REASON);
+
+    let _ = writeln!(
+        out,
+        "\n/// The `{}` contract deployed at `address`\n\
+         pub struct {}<T> {{\n    pub transport: T,\n    pub address: Vec<u8>,\n}}\n\n\
+         impl<T: Transport> {}<T> {{",
+        abi.name, client, client
+    );
+    out.push_str(RUST_CLIENT_METHODS);
+    for message in &messages {
+        let name = message.rust_struct(&client);
+        let function = message.rust_function();
+        let mut params: Vec<String> = message
+            .args
+            .iter()
+            .map(|(arg, value)| format!("{}: {}", arg, value.rust_type()))
+            .collect();
+        let construct = match message.args.is_empty() {
+            true => name.clone(),
+            false => {
+                let fields: Vec<&str> = message.args.iter().map(|(arg, _)| arg.as_str()).collect();
+                format!("{} {{ {} }}", name, fields.join(", "))
+            }
+        };
+        let value = match message.method.payable {
+            true => {
+                params.push("value: u128".to_string());
+                "value"
+            }
+            false => "0",
+        };
+        let params = match params.is_empty() {
+            true => String::new(),
+            false => format!(", {}", params.join(", ")),
+        };
+        let output = message.output.map_or("()".to_string(), Value::rust_type);
+        let _ = writeln!(
+            out,
+            "\n    /// Query `{}`\n    pub async fn {}(&self{}) -> Result<{}, T::Error> {{\n        self.query(&{}, {}).await\n    }}",
+            message.signature(),
+            function,
+            params,
+            output,
+            construct,
+            value
+        );
+        if !message.read_only() {
+            let _ = writeln!(
+                out,
+                "\n    /// Submit a call of `{}`\n    pub async fn call_{}(&self{}) -> Result<(), T::Error> {{\n        self.send(&{}, {}).await\n    }}",
+                message.signature(),
+                snake_case(&message.method.name),
+                params,
+                construct,
+                value
+            );
+        }
+    }
+    let _ = writeln!(out, "}}");
+    Ok(out)
+}
+
+fn generate_typescript(abi: &ContractABI) -> Result<String, BindgenError> {
+    let messages = abi
+        .methods
+        .iter()
+        .map(|method| Message::resolve(method, ts_identifier))
+        .collect::<Result<Vec<_>, _>>()?;
+    let client = format!("{}Client", pascal_case(&abi.name));
+    let mut out = String::new();
+
+    let _ = writeln!(
+        out,
+        "// Client of the `{}` contract (version {}), generated by `bend-pvm\n\
+         // bindgen` from its ABI. Do not edit.",
+        abi.name, abi.version
+    );
+    out.push_str(TS_PRELUDE);
+
+    let _ = writeln!(
+        out,
+        "\n/** Errors a call can revert with, by the selector the revert data starts with */\n\
+         export const ERRORS: Record<string, string> = {{"
+    );
+    for error in &abi.errors {
+        let _ = writeln!(out, "  \"{}\": \"{}\",", error.selector, error.name);
+    }
+    let _ = writeln!(out, "}};");
+    out.push_str(TS_REVERT_REASON);
+
+    let _ = writeln!(
+        out,
+        "\n/** Call data and return values of the contract's messages */\nexport const messages = {{"
+    );
+    for message in &messages {
+        let key = camel_case(&message.method.name);
+        let params: Vec<String> = message
+            .args
+            .iter()
+            .map(|(arg, value)| format!("{}: {}", arg, value.ts_type()))
+            .collect();
+        let _ = writeln!(out, "  /** `{}` */", message.signature());
+        let _ = writeln!(out, "  {}: {{", key);
+        let _ = writeln!(out, "    selector: \"{}\",", message.method.selector);
+        let _ = writeln!(out, "    payable: {},", message.method.payable);
+        let _ = writeln!(
+            out,
+            "    encode({}): Uint8Array {{\n      const writer = new Writer(\"{}\");",
+            params.join(", "),
+            message.method.selector
+        );
+        for (arg, value) in &message.args {
+            let _ = writeln!(out, "      {};", value.ts_write(arg));
+        }
+        let _ = writeln!(out, "      return writer.finish();\n    }},");
+        match message.output {
+            Some(value) => {
+                let _ = writeln!(
+                    out,
+                    "    decode(data: Uint8Array): {} {{\n      const reader = new Reader(data);\n      return {};\n    }},",
+                    value.ts_type(),
+                    value.ts_read()
+                );
+            }
+            None => {
+                let _ = writeln!(out, "    decode(_data: Uint8Array): void {{}},");
+            }
+        }
+        let _ = writeln!(out, "  }},");
+    }
+    let _ = writeln!(out, "}};");
+
+    let _ = writeln!(
+        out,
+        "\n/** The `{}` contract deployed at `address` */\nexport class {} {{",
+        abi.name, client
+    );
+    out.push_str(TS_CLIENT_METHODS);
+    for message in &messages {
+        let key = camel_case(&message.method.name);
+        let method = message.ts_method();
+        let mut params: Vec<String> = message
+            .args
+            .iter()
+            .map(|(arg, value)| format!("{}: {}", arg, value.ts_type()))
+            .collect();
+        let args: Vec<&str> = message.args.iter().map(|(arg, _)| arg.as_str()).collect();
+        let value = match message.method.payable {
+            true => {
+                params.push("value: bigint = 0n".to_string());
+                "value"
+            }
+            false => "0n",
+        };
+        let output = message.output.map_or("void", Value::ts_type);
+        let query_params = std::iter::once("origin: string".to_string())
+            .chain(params.iter().cloned())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = writeln!(
+            out,
+            "\n  /** Query `{}` */\n  async {}({}): Promise<{}> {{\n    const data = messages.{}.encode({});\n    return messages.{}.decode(await this.query(origin, data, {}));\n  }}",
+            message.signature(),
+            method,
+            query_params,
+            output,
+            key,
+            args.join(", "),
+            key,
+            value
+        );
+        if !message.read_only() {
+            let send_params = std::iter::once("signer: KeyringPair".to_string())
+                .chain(params.iter().cloned())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let _ = writeln!(
+                out,
+                "\n  /** Submit a call of `{}` */\n  async send{}({}): Promise<string> {{\n    return this.send(signer, messages.{}.encode({}), {});\n  }}",
+                message.signature(),
+                pascal_case(&message.method.name),
+                send_params,
+                key,
+                args.join(", "),
+                value
+            );
+        }
+    }
+    let _ = writeln!(out, "}}");
+    Ok(out)
+}
+
+/// The bytes of a `0x` hex selector as the elements of a Rust array
+fn byte_list(selector: &str) -> String {
+    let digits = selector.trim_start_matches("0x");
+    let bytes: Vec<String> = (0..digits.len())
+        .step_by(2)
+        .map(|i| format!("0x{}", &digits[i..(i + 2).min(digits.len())]))
+        .collect();
+    bytes.join(", ")
+}
+
+/// `name` with every character an identifier cannot have replaced
+fn identifier(name: &str) -> String {
+    let mut identifier: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if identifier.is_empty() || identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+    identifier
+}
+
+fn snake_case(name: &str) -> String {
+    identifier(name)
+}
+
+pub(super) fn pascal_case(name: &str) -> String {
+    let pascal: String = identifier(name)
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect();
+    match pascal.starts_with(|c: char| c.is_ascii_digit()) || pascal.is_empty() {
+        true => format!("_{}", pascal),
+        false => pascal,
+    }
+}
+
+pub(super) fn camel_case(name: &str) -> String {
+    let pascal = pascal_case(name);
+    let mut chars = pascal.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => pascal,
+    }
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where",
+    "while", "yield",
+];
+
+const TS_RESERVED: &[&str] = &[
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "new",
+    "null",
+    "return",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "data",
+    "value",
+    "origin",
+    "signer",
+];
+
+fn rust_identifier(name: &str) -> String {
+    match RUST_KEYWORDS.contains(&name) {
+        true => format!("r#{}", name),
+        false => name.to_string(),
+    }
+}
+
+/// Parameters named like the ones the client methods add get a trailing
+/// underscore too
+fn ts_identifier(name: &str) -> String {
+    match TS_RESERVED.contains(&name) {
+        true => format!("{}_", name),
+        false => name.to_string(),
+    }
+}
+
+const RUST_PRELUDE: &str = r#"
+#![allow(dead_code)]
+
+use parity_scale_codec::{Decode, Encode};
+
+/// A message of the contract, holding the arguments of one call
+pub trait Message: Encode {
+    const NAME: &'static str;
+    const SELECTOR: [u8; 4];
+    const PAYABLE: bool;
+    /// What the message returns
+    type Output;
+
+    /// The selector followed by the encoded arguments
+    fn call_data(&self) -> Vec<u8> {
+        let mut data = Self::SELECTOR.to_vec();
+        self.encode_to(&mut data);
+        data
+    }
+
+    fn decode_output(data: &[u8]) -> Result<Self::Output, parity_scale_codec::Error>;
+}
+
+/// How the client reaches the node the contract is deployed on, e.g. with
+/// subxt through the `ReviveApi_call` runtime API and `Revive::call`
+#[allow(async_fn_in_trait)]
+pub trait Transport {
+    type Error: From<parity_scale_codec::Error>;
+
+    /// Dry-run a call of the contract at `address` and return the data it
+    /// returned
+    async fn query(&self, address: &[u8], data: Vec<u8>, value: u128) -> Result<Vec<u8>, Self::Error>;
+
+    /// Submit a call of the contract at `address`
+    async fn call(&self, address: &[u8], data: Vec<u8>, value: u128) -> Result<(), Self::Error>;
+}
+"#;
+
+const RUST_REVERT_REASON: &str = r#"
+/// The error revert data stands for, if the contract declares it
+pub fn revert_reason(data: &[u8]) -> Option<&'static str> {
+    ERRORS
+        .iter()
+        .find(|(selector, _)| data.starts_with(selector))
+        .map(|(_, name)| *name)
+}
+"#;
+
+const RUST_CLIENT_METHODS: &str = r#"
+    pub fn new(transport: T, address: impl Into<Vec<u8>>) -> Self {
+        Self {
+            transport,
+            address: address.into(),
+        }
+    }
+
+    /// Dry-run `message` and decode what it returns
+    pub async fn query<M: Message>(&self, message: &M, value: u128) -> Result<M::Output, T::Error> {
+        let output = self
+            .transport
+            .query(&self.address, message.call_data(), value)
+            .await?;
+        Ok(M::decode_output(&output)?)
+    }
+
+    /// Submit `message`, transferring `value` to the contract
+    pub async fn send<M: Message>(&self, message: &M, value: u128) -> Result<(), T::Error> {
+        self.transport
+            .call(&self.address, message.call_data(), value)
+            .await
+    }
+"#;
+
+const TS_PRELUDE: &str = r#"
+import type { ApiPromise } from "@polkadot/api";
+import type { KeyringPair } from "@polkadot/keyring/types";
+
+export type Pallet = "revive" | "contracts";
+
+function toHex(data: Uint8Array): string {
+  return "0x" + Array.from(data, (byte) => byte.toString(16).padStart(2, "0")).join("");
+}
+
+function fromHex(text: string): Uint8Array {
+  const digits = text.startsWith("0x") ? text.slice(2) : text;
+  const bytes = new Uint8Array(digits.length / 2);
+  for (let i = 0; i < bytes.length; i++) {
+    bytes[i] = parseInt(digits.slice(2 * i, 2 * i + 2), 16);
+  }
+  return bytes;
+}
+
+/** Writes call data the way the contract's dispatcher reads it */
+class Writer {
+  private bytes: number[] = [];
+
+  constructor(selector: string) {
+    this.raw(fromHex(selector));
+  }
+
+  raw(data: Uint8Array): void {
+    this.bytes.push(...data);
+  }
+
+  u32(value: number): void {
+    this.uint(BigInt(value >>> 0), 4);
+  }
+
+  bool(value: boolean): void {
+    this.bytes.push(value ? 1 : 0);
+  }
+
+  uint(value: bigint, size: number): void {
+    for (let i = 0; i < size; i++) {
+      this.bytes.push(Number(value & 0xffn));
+      value >>= 8n;
+    }
+  }
+
+  fixed(data: Uint8Array, size: number): void {
+    if (data.length !== size) {
+      throw new Error(`expected ${size} bytes, found ${data.length}`);
+    }
+    this.raw(data);
+  }
+
+  vec(data: Uint8Array): void {
+    const length = BigInt(data.length);
+    if (length < 1n << 6n) {
+      this.uint(length << 2n, 1);
+    } else if (length < 1n << 14n) {
+      this.uint((length << 2n) | 1n, 2);
+    } else if (length < 1n << 30n) {
+      this.uint((length << 2n) | 2n, 4);
+    } else {
+      throw new Error(`${data.length} bytes are too many`);
+    }
+    this.raw(data);
+  }
+
+  finish(): Uint8Array {
+    return Uint8Array.from(this.bytes);
+  }
+}
+
+/** Reads the values the contract's dispatcher returns */
+class Reader {
+  private offset = 0;
+
+  constructor(private data: Uint8Array) {}
+
+  take(size: number): Uint8Array {
+    if (this.offset + size > this.data.length) {
+      throw new Error("the contract returned too little data");
+    }
+    const bytes = this.data.subarray(this.offset, this.offset + size);
+    this.offset += size;
+    return bytes;
+  }
+
+  uint(size: number): bigint {
+    const bytes = this.take(size);
+    let value = 0n;
+    for (let i = size - 1; i >= 0; i--) {
+      value = (value << 8n) | BigInt(bytes[i]);
+    }
+    return value;
+  }
+
+  u32(): number {
+    return Number(this.uint(4));
+  }
+
+  i32(): number {
+    return Number(BigInt.asIntN(32, this.uint(4)));
+  }
+
+  bool(): boolean {
+    return this.take(1)[0] !== 0;
+  }
+
+  fixed(size: number): Uint8Array {
+    return this.take(size).slice();
+  }
+
+  vec(): Uint8Array {
+    const mode = this.data[this.offset] & 3;
+    if (mode === 3) {
+      throw new Error("the contract returned too many bytes");
+    }
+    const length = Number(this.uint([1, 2, 4][mode]) >> 2n);
+    return this.fixed(length);
+  }
+
+  rest(): Uint8Array {
+    return this.fixed(this.data.length - this.offset);
+  }
+}
+"#;
+
+const TS_REVERT_REASON: &str = r#"
+/** The error revert data stands for, if the contract declares it */
+export function revertReason(data: Uint8Array): string | undefined {
+  return ERRORS[toHex(data.subarray(0, 4))];
+}
+"#;
+
+const TS_CLIENT_METHODS: &str = r#"  constructor(
+    readonly api: ApiPromise,
+    readonly address: string,
+    readonly pallet: Pallet = "revive",
+  ) {}
+
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  private dryRun(origin: string, data: Uint8Array, value: bigint): Promise<any> {
+    const runtime = this.pallet === "revive" ? this.api.call.reviveApi : this.api.call.contractsApi;
+    return runtime.call(origin, this.address, value, null, null, data);
+  }
+
+  /** Dry-run a call and return the data the contract returned */
+  async query(origin: string, data: Uint8Array, value: bigint = 0n): Promise<Uint8Array> {
+    const { result } = await this.dryRun(origin, data, value);
+    if (result.isErr) {
+      throw new Error(`the call failed: ${result.asErr.toString()}`);
+    }
+    const output: Uint8Array = result.asOk.data.toU8a(true);
+    if (result.asOk.flags.toNumber() & 1) {
+      const reason = revertReason(output) ?? toHex(output);
+      throw new Error(`the contract reverted with ${reason}`);
+    }
+    return output;
+  }
+
+  /** Submit a call with the weight and deposit its dry run requires, and
+   * return the hash of the extrinsic */
+  async send(signer: KeyringPair, data: Uint8Array, value: bigint = 0n): Promise<string> {
+    const { gasRequired, storageDeposit } = await this.dryRun(signer.address, data, value);
+    const deposit = storageDeposit.isCharge ? storageDeposit.asCharge : 0;
+    const pallet = this.pallet === "revive" ? this.api.tx.revive : this.api.tx.contracts;
+    const hash = await pallet
+      .call(this.address, value, gasRequired, deposit, data)
+      .signAndSend(signer);
+    return hash.toHex();
+  }
+"#;
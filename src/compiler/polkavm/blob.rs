@@ -0,0 +1,683 @@
+//! PolkaVM program blob writer
+//!
+//! Translates the `Instruction` stream into the PolkaVM 0.1 instruction set
+//! and serializes it as a program blob. The blob keeps the calling convention
+//! of the reference interpreter, so the same code runs on both:
+//!
+//! - Guest addresses are the interpreter's: a flat 64 KiB memory starting at
+//!   0, which the blob places at `MEMORY_BASE`. Loads and stores add the base
+//!   to their offset and the host adds it to every pointer it is passed.
+//! - PolkaVM has 13 registers. `ra`, `sp`, `t0`, `t1` and `a0`-`a5` map to
+//!   their RISC-V counterparts, `t2`, `s0` and `s1` are scratch, and the
+//!   remaining RISC-V registers live in a spill area after guest memory.
+//! - Labels become jump targets and code addresses (`la`, return addresses)
+//!   are jump target numbers times 4, which is what `jalr` expects.
+//! - `ecall` becomes `ecalli` with the host function id when `a7` holds a
+//!   known constant, and a dynamic host call reading `a7` otherwise.
+//!
+//! PolkaVM 0.1 is a 32-bit machine, so RV64 code is rejected.
+//!
+//! The exported `call` entry point loads the call input through a host call,
+//! sets up the stack and starts at the function exported as `call`, the
+//! dispatcher, `main` or the first instruction, like the interpreter. Every
+//! other `#[export]` function gets an entry point of its own, e.g. `deploy`,
+//! doing the same before it enters the function.
+
+use std::collections::{BTreeSet, HashMap};
+
+use polkavm_common::abi::{VM_ADDR_RETURN_TO_HOST, VM_ADDR_USER_MEMORY, VM_PAGE_SIZE};
+use polkavm_common::program::{
+    ExternTy, Opcode, RawInstruction, Reg, BLOB_MAGIC, BLOB_VERSION_V1, MAX_INSTRUCTION_LENGTH,
+    SECTION_CODE, SECTION_END_OF_FILE, SECTION_EXPORTS, SECTION_IMPORTS, SECTION_MEMORY_CONFIG,
+};
+use polkavm_common::varint::{write_varint, MAX_VARINT_LENGTH};
+
+use super::bridge::PolkaVMError;
+use crate::compiler::codegen::risc_v::{
+    export_label, Instruction, Register, DISPATCH_LABEL, EXPORT_PREFIX,
+};
+
+/// Address at which guest address 0 is placed in the PolkaVM address space
+pub const MEMORY_BASE: u32 = VM_ADDR_USER_MEMORY;
+
+/// Size of guest memory, the same as the interpreter's default
+pub const GUEST_MEMORY_SIZE: u32 = 64 * 1024;
+
+/// Name of the entry point every program exports
+pub const ENTRY_POINT: &str = "call";
+
+/// Name of the export run when a contract is instantiated, if it has one
+pub const DEPLOY_EXPORT: &str = "deploy";
+
+/// Host call copying the call input to guest address 0, returning 0 in `a0`
+/// and the input length in `a1`
+pub const LOAD_INPUT: u32 = 0xFFFF_FFFE;
+
+/// Host call dispatching on the host function id held in `a7`
+pub const DYNAMIC_HOST_CALL: u32 = 0xFFFF_FFFF;
+
+/// Scratch registers, never holding a RISC-V register
+const SCRATCH: Reg = Reg::T2;
+const SCRATCH2: Reg = Reg::S1;
+const SCRATCH3: Reg = Reg::S0;
+
+/// Jump target of the `call` entry point
+const ENTRY_TARGET: u32 = 0;
+
+/// PolkaVM register holding `register`, if it is not spilled
+pub fn native_register(register: Register) -> Option<Reg> {
+    Some(match register {
+        Register::X0 => Reg::Zero,
+        Register::X1 => Reg::RA,
+        Register::X2 => Reg::SP,
+        Register::X5 => Reg::T0,
+        Register::X6 => Reg::T1,
+        Register::X10 => Reg::A0,
+        Register::X11 => Reg::A1,
+        Register::X12 => Reg::A2,
+        Register::X13 => Reg::A3,
+        Register::X14 => Reg::A4,
+        Register::X15 => Reg::A5,
+        _ => return None,
+    })
+}
+
+/// PolkaVM address of the spill slot of `register`
+pub fn spill_address(register: Register) -> u32 {
+    MEMORY_BASE + GUEST_MEMORY_SIZE + 4 * register as u32
+}
+
+/// Assemble instructions into a PolkaVM program blob
+pub fn assemble(instructions: &[Instruction]) -> Result<Vec<u8>, PolkaVMError> {
+    let mut writer = BlobWriter::new(instructions)?;
+    writer.translate(instructions)?;
+    Ok(writer.finish())
+}
+
+/// Translation state of a single program
+struct BlobWriter<'a> {
+    code: Vec<RawInstruction>,
+    labels: HashMap<&'a str, u32>,
+    next_target: u32,
+    imports: BTreeSet<u32>,
+    /// Names of the exported entry points with their jump target
+    exports: Vec<(&'a str, u32)>,
+}
+
+impl<'a> BlobWriter<'a> {
+    fn new(instructions: &'a [Instruction]) -> Result<Self, PolkaVMError> {
+        let mut labels = HashMap::new();
+        for instruction in instructions {
+            if let Instruction::Label(name) = instruction {
+                let target = labels.len() as u32 + 1;
+                if labels.insert(name.as_str(), target).is_some() {
+                    return Err(PolkaVMError::BinaryGenerationError(format!(
+                        "Duplicate label: {}",
+                        name
+                    )));
+                }
+            }
+        }
+
+        Ok(BlobWriter {
+            code: Vec::new(),
+            next_target: labels.len() as u32 + 1,
+            labels,
+            imports: BTreeSet::from([LOAD_INPUT]),
+            exports: Vec::new(),
+        })
+    }
+
+    fn translate(&mut self, instructions: &'a [Instruction]) -> Result<(), PolkaVMError> {
+        let mut exports: Vec<(&'a str, u32)> = self
+            .labels
+            .iter()
+            .filter_map(|(&label, &target)| Some((label.strip_prefix(EXPORT_PREFIX)?, target)))
+            .filter(|(export, _)| *export != ENTRY_POINT)
+            .collect();
+        exports.sort();
+        for (export, start) in exports {
+            let target = self.fresh_target();
+            self.entry(target, Some(start));
+            self.exports.push((export, target));
+        }
+
+        // The `call` entry point comes last, falling through to the first
+        // instruction when there is nowhere else to start
+        let start = [export_label(ENTRY_POINT).as_str(), DISPATCH_LABEL, "main"]
+            .into_iter()
+            .find_map(|label| self.labels.get(label).copied());
+        self.entry(ENTRY_TARGET, start);
+        self.exports.insert(0, (ENTRY_POINT, ENTRY_TARGET));
+
+        for (index, instruction) in instructions.iter().enumerate() {
+            self.instruction(instruction, &instructions[..index])?;
+        }
+
+        // Falling off the end returns to the host, which returns `a0`
+        self.imm(Opcode::add_imm, SCRATCH, Reg::Zero, VM_ADDR_RETURN_TO_HOST);
+        self.imm(Opcode::jump_and_link_register, Reg::Zero, SCRATCH, 0);
+        Ok(())
+    }
+
+    fn instruction(
+        &mut self,
+        instruction: &Instruction,
+        preceding: &[Instruction],
+    ) -> Result<(), PolkaVMError> {
+        match instruction {
+            Instruction::Load(rd, rs1, offset) => self.load(Opcode::load_u32, *rd, *rs1, *offset),
+            Instruction::LoadByteU(rd, rs1, offset) => {
+                self.load(Opcode::load_u8, *rd, *rs1, *offset)
+            }
+            Instruction::Store(rs2, rs1, offset) => {
+                self.store(Opcode::store_u32, *rs2, *rs1, *offset)
+            }
+            Instruction::StoreByte(rs2, rs1, offset) => {
+                self.store(Opcode::store_u8, *rs2, *rs1, *offset)
+            }
+            Instruction::Add(rd, rs1, rs2) => self.op3(Opcode::add, *rd, *rs1, *rs2),
+            Instruction::Sub(rd, rs1, rs2) => self.op3(Opcode::sub, *rd, *rs1, *rs2),
+            Instruction::Mul(rd, rs1, rs2) => self.op3(Opcode::mul, *rd, *rs1, *rs2),
+            Instruction::MulHigh(rd, rs1, rs2) => {
+                self.op3(Opcode::mul_upper_signed_signed, *rd, *rs1, *rs2)
+            }
+            Instruction::MulHighU(rd, rs1, rs2) => {
+                self.op3(Opcode::mul_upper_unsigned_unsigned, *rd, *rs1, *rs2)
+            }
+            Instruction::Div(rd, rs1, rs2) => self.division(Opcode::div_signed, *rd, *rs1, *rs2),
+            Instruction::Rem(rd, rs1, rs2) => self.division(Opcode::rem_signed, *rd, *rs1, *rs2),
+            Instruction::And(rd, rs1, rs2) => self.op3(Opcode::and, *rd, *rs1, *rs2),
+            Instruction::Or(rd, rs1, rs2) => self.op3(Opcode::or, *rd, *rs1, *rs2),
+            Instruction::Xor(rd, rs1, rs2) => self.op3(Opcode::xor, *rd, *rs1, *rs2),
+            Instruction::ShiftLeft(rd, rs1, rs2) => {
+                self.op3(Opcode::shift_logical_left, *rd, *rs1, *rs2)
+            }
+            Instruction::ShiftRight(rd, rs1, rs2) => {
+                self.op3(Opcode::shift_logical_right, *rd, *rs1, *rs2)
+            }
+            Instruction::ShiftRightArith(rd, rs1, rs2) => {
+                // PolkaVM does not mask arithmetic shift amounts
+                let amount = self.read(*rs2, SCRATCH2);
+                self.imm(Opcode::and_imm, SCRATCH3, amount, 31);
+                let value = self.read(*rs1, SCRATCH);
+                let dest = self.dest(*rd);
+                self.op(Opcode::shift_arithmetic_right, dest, value, SCRATCH3);
+                self.write_back(*rd);
+            }
+            Instruction::SetLessThan(rd, rs1, rs2) => {
+                self.op3(Opcode::set_less_than_signed, *rd, *rs1, *rs2)
+            }
+            Instruction::SetLessThanU(rd, rs1, rs2) => {
+                self.op3(Opcode::set_less_than_unsigned, *rd, *rs1, *rs2)
+            }
+            Instruction::AddImm(rd, rs1, imm) => self.op_imm(Opcode::add_imm, *rd, *rs1, *imm),
+            Instruction::AndImm(rd, rs1, imm) => self.op_imm(Opcode::and_imm, *rd, *rs1, *imm),
+            Instruction::OrImm(rd, rs1, imm) => self.op_imm(Opcode::or_imm, *rd, *rs1, *imm),
+            Instruction::XorImm(rd, rs1, imm) => self.op_imm(Opcode::xor_imm, *rd, *rs1, *imm),
+            Instruction::ShiftLeftImm(rd, rs1, imm) => {
+                self.op_imm(Opcode::shift_logical_left_imm, *rd, *rs1, imm & 31)
+            }
+            Instruction::ShiftRightImm(rd, rs1, imm) => {
+                self.op_imm(Opcode::shift_logical_right_imm, *rd, *rs1, imm & 31)
+            }
+            Instruction::ShiftRightArithImm(rd, rs1, imm) => {
+                self.op_imm(Opcode::shift_arithmetic_right_imm, *rd, *rs1, imm & 31)
+            }
+            Instruction::SetLessThanImm(rd, rs1, imm) => {
+                self.op_imm(Opcode::set_less_than_signed_imm, *rd, *rs1, *imm)
+            }
+            Instruction::SetLessThanImmU(rd, rs1, imm) => {
+                self.op_imm(Opcode::set_less_than_unsigned_imm, *rd, *rs1, *imm)
+            }
+            Instruction::BranchEq(rs1, rs2, label) => {
+                self.branch(Opcode::branch_eq, *rs1, *rs2, label)?
+            }
+            Instruction::BranchNe(rs1, rs2, label) => {
+                self.branch(Opcode::branch_not_eq, *rs1, *rs2, label)?
+            }
+            Instruction::BranchLt(rs1, rs2, label) => {
+                self.branch(Opcode::branch_less_signed, *rs1, *rs2, label)?
+            }
+            Instruction::BranchLe(rs1, rs2, label) => {
+                self.branch(Opcode::branch_greater_or_equal_signed, *rs2, *rs1, label)?
+            }
+            Instruction::BranchGe(rs1, rs2, label) => {
+                self.branch(Opcode::branch_greater_or_equal_signed, *rs1, *rs2, label)?
+            }
+            Instruction::BranchLtU(rs1, rs2, label) => {
+                self.branch(Opcode::branch_less_unsigned, *rs1, *rs2, label)?
+            }
+            Instruction::BranchGeU(rs1, rs2, label) => {
+                self.branch(Opcode::branch_greater_or_equal_unsigned, *rs1, *rs2, label)?
+            }
+            Instruction::Jump(label) => {
+                let target = self.label(label)?;
+                self.jump(target);
+            }
+            Instruction::JumpAndLink(rd, label) => {
+                let target = self.label(label)?;
+                let return_target = self.fresh_target();
+                self.load_code_address(*rd, return_target);
+                self.jump(target);
+                self.jump_target(return_target);
+            }
+            Instruction::JumpAndLinkReg(rd, rs1, offset) => {
+                let mut base = self.read(*rs1, SCRATCH2);
+                let return_target = self.fresh_target();
+                if *rd != Register::X0 {
+                    if native_register(*rd) == Some(base) {
+                        self.imm(Opcode::add_imm, SCRATCH3, base, 0);
+                        base = SCRATCH3;
+                    }
+                    self.load_code_address(*rd, return_target);
+                }
+                self.imm(
+                    Opcode::jump_and_link_register,
+                    Reg::Zero,
+                    base,
+                    *offset as u32,
+                );
+                self.jump_target(return_target);
+            }
+            Instruction::Ecall => {
+                let id = host_function_id(preceding).unwrap_or(DYNAMIC_HOST_CALL);
+                self.ecalli(id);
+            }
+            Instruction::Ebreak => self.code.push(RawInstruction::new_argless(Opcode::trap)),
+            Instruction::Li(rd, imm) => self.op_imm(Opcode::add_imm, *rd, Register::X0, *imm),
+            Instruction::La(rd, label) => {
+                let target = self.label(label)?;
+                self.load_code_address(*rd, target);
+            }
+            Instruction::Mv(rd, rs1) => self.op_imm(Opcode::add_imm, *rd, *rs1, 0),
+            Instruction::Not(rd, rs1) => self.op_imm(Opcode::xor_imm, *rd, *rs1, -1),
+            Instruction::Neg(rd, rs1) => self.op3(Opcode::sub, *rd, Register::X0, *rs1),
+            Instruction::Label(name) => {
+                let target = self.label(name)?;
+                self.jump_target(target);
+            }
+            Instruction::Comment(_) => {}
+            Instruction::Load64(..)
+            | Instruction::Store64(..)
+            | Instruction::AddW(..)
+            | Instruction::AddImmW(..)
+            | Instruction::SubW(..)
+            | Instruction::MulW(..)
+            | Instruction::DivW(..)
+            | Instruction::RemW(..)
+            | Instruction::ShiftLeftW(..)
+            | Instruction::ShiftRightW(..)
+            | Instruction::ShiftRightArithW(..)
+            | Instruction::ShiftLeftImmW(..)
+            | Instruction::ShiftRightImmW(..)
+            | Instruction::ShiftRightArithImmW(..) => {
+                return Err(PolkaVMError::BinaryGenerationError(format!(
+                    "`{}` is an RV64 instruction and PolkaVM blobs are 32-bit",
+                    instruction.to_string().trim()
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// Signed division or remainder with RISC-V results for a zero divisor
+    /// and for overflow, which PolkaVM leaves to the host language
+    fn division(&mut self, opcode: Opcode, rd: Register, rs1: Register, rs2: Register) {
+        let dividend = self.read(rs1, SCRATCH);
+        let divisor = self.read(rs2, SCRATCH2);
+        let dest = self.dest(rd);
+        let (by_zero, by_minus_one, done) = (
+            self.fresh_target(),
+            self.fresh_target(),
+            self.fresh_target(),
+        );
+
+        self.imm(Opcode::branch_eq, divisor, Reg::Zero, by_zero);
+        self.imm(Opcode::add_imm, SCRATCH3, Reg::Zero, u32::MAX);
+        self.imm(Opcode::branch_eq, divisor, SCRATCH3, by_minus_one);
+        self.op(opcode, dest, dividend, divisor);
+        self.jump(done);
+
+        // x / 0 = -1 and x % 0 = x
+        self.jump_target(by_zero);
+        if opcode == Opcode::div_signed {
+            self.imm(Opcode::add_imm, dest, Reg::Zero, u32::MAX);
+        } else {
+            self.imm(Opcode::add_imm, dest, dividend, 0);
+        }
+        self.jump(done);
+
+        // x / -1 = -x, wrapping, and x % -1 = 0
+        self.jump_target(by_minus_one);
+        if opcode == Opcode::div_signed {
+            self.op(Opcode::sub, dest, Reg::Zero, dividend);
+        } else {
+            self.imm(Opcode::add_imm, dest, Reg::Zero, 0);
+        }
+
+        self.jump_target(done);
+        self.write_back(rd);
+    }
+
+    fn load(&mut self, opcode: Opcode, rd: Register, rs1: Register, offset: i32) {
+        let base = self.read(rs1, SCRATCH2);
+        let dest = self.dest(rd);
+        self.imm(opcode, dest, base, MEMORY_BASE.wrapping_add(offset as u32));
+        self.write_back(rd);
+    }
+
+    fn store(&mut self, opcode: Opcode, rs2: Register, rs1: Register, offset: i32) {
+        let value = self.read(rs2, SCRATCH);
+        let base = self.read(rs1, SCRATCH2);
+        self.imm(opcode, value, base, MEMORY_BASE.wrapping_add(offset as u32));
+    }
+
+    fn op3(&mut self, opcode: Opcode, rd: Register, rs1: Register, rs2: Register) {
+        let lhs = self.read(rs1, SCRATCH);
+        let rhs = self.read(rs2, SCRATCH2);
+        let dest = self.dest(rd);
+        self.op(opcode, dest, lhs, rhs);
+        self.write_back(rd);
+    }
+
+    fn op_imm(&mut self, opcode: Opcode, rd: Register, rs1: Register, imm: i32) {
+        let source = self.read(rs1, SCRATCH);
+        let dest = self.dest(rd);
+        self.imm(opcode, dest, source, imm as u32);
+        self.write_back(rd);
+    }
+
+    fn branch(
+        &mut self,
+        opcode: Opcode,
+        rs1: Register,
+        rs2: Register,
+        label: &str,
+    ) -> Result<(), PolkaVMError> {
+        let target = self.label(label)?;
+        let lhs = self.read(rs1, SCRATCH);
+        let rhs = self.read(rs2, SCRATCH2);
+        self.imm(opcode, lhs, rhs, target);
+        Ok(())
+    }
+
+    /// Set `rd` to the code address of a jump target
+    fn load_code_address(&mut self, rd: Register, target: u32) {
+        let dest = self.dest(rd);
+        self.imm(Opcode::add_imm, dest, Reg::Zero, target * 4);
+        self.write_back(rd);
+    }
+
+    /// Register holding the value of `register`, loading spilled registers
+    /// into `scratch`
+    fn read(&mut self, register: Register, scratch: Reg) -> Reg {
+        match native_register(register) {
+            Some(reg) => reg,
+            None => {
+                self.imm(
+                    Opcode::load_u32,
+                    scratch,
+                    Reg::Zero,
+                    spill_address(register),
+                );
+                scratch
+            }
+        }
+    }
+
+    /// Register to compute the new value of `register` in
+    fn dest(&self, register: Register) -> Reg {
+        native_register(register).unwrap_or(SCRATCH)
+    }
+
+    /// Store the value computed for a spilled `register`
+    fn write_back(&mut self, register: Register) {
+        if native_register(register).is_none() {
+            self.imm(
+                Opcode::store_u32,
+                SCRATCH,
+                Reg::Zero,
+                spill_address(register),
+            );
+        }
+    }
+
+    fn label(&self, name: &str) -> Result<u32, PolkaVMError> {
+        self.labels.get(name).copied().ok_or_else(|| {
+            PolkaVMError::BinaryGenerationError(format!("Undefined label: {}", name))
+        })
+    }
+
+    /// Start an entry point at `target`, loading the input and setting up
+    /// the stack before it jumps to `start`
+    fn entry(&mut self, target: u32, start: Option<u32>) {
+        self.jump_target(target);
+        self.ecalli(LOAD_INPUT);
+        self.imm(Opcode::add_imm, Reg::SP, Reg::Zero, GUEST_MEMORY_SIZE);
+        if let Some(start) = start {
+            self.jump(start);
+        }
+    }
+
+    fn fresh_target(&mut self) -> u32 {
+        self.next_target += 1;
+        self.next_target - 1
+    }
+
+    fn jump(&mut self, target: u32) {
+        self.imm(Opcode::jump_and_link_register, Reg::Zero, Reg::Zero, target);
+    }
+
+    fn jump_target(&mut self, target: u32) {
+        self.code
+            .push(RawInstruction::new_with_imm(Opcode::jump_target, target));
+    }
+
+    fn ecalli(&mut self, id: u32) {
+        self.imports.insert(id);
+        self.code
+            .push(RawInstruction::new_with_imm(Opcode::ecalli, id));
+    }
+
+    fn op(&mut self, opcode: Opcode, dest: Reg, lhs: Reg, rhs: Reg) {
+        self.code
+            .push(RawInstruction::new_with_regs3(opcode, dest, lhs, rhs));
+    }
+
+    fn imm(&mut self, opcode: Opcode, reg1: Reg, reg2: Reg, imm: u32) {
+        self.code
+            .push(RawInstruction::new_with_regs2_imm(opcode, reg1, reg2, imm));
+    }
+
+    /// Serialize the translated program
+    fn finish(self) -> Vec<u8> {
+        let mut blob = BLOB_MAGIC.to_vec();
+        blob.push(BLOB_VERSION_V1);
+
+        let mut memory_config = Vec::new();
+        write_u32(&mut memory_config, GUEST_MEMORY_SIZE + VM_PAGE_SIZE);
+        write_u32(&mut memory_config, 0);
+        write_section(&mut blob, SECTION_MEMORY_CONFIG, &memory_config);
+
+        let mut imports = Vec::new();
+        write_u32(&mut imports, self.imports.len() as u32);
+        for id in &self.imports {
+            write_u32(&mut imports, *id);
+            write_prototype(&mut imports, &format!("bend_host_{}", id), None);
+        }
+        write_section(&mut blob, SECTION_IMPORTS, &imports);
+
+        let mut exports = Vec::new();
+        write_u32(&mut exports, self.exports.len() as u32);
+        for (name, target) in &self.exports {
+            write_u32(&mut exports, *target);
+            write_prototype(&mut exports, name, Some(ExternTy::I32));
+        }
+        write_section(&mut blob, SECTION_EXPORTS, &exports);
+
+        let mut code = Vec::new();
+        let mut buffer = [0; MAX_INSTRUCTION_LENGTH];
+        for instruction in self.code {
+            let length = instruction.serialize_into(&mut buffer);
+            code.extend_from_slice(&buffer[..length]);
+        }
+        write_section(&mut blob, SECTION_CODE, &code);
+
+        blob.push(SECTION_END_OF_FILE);
+        blob
+    }
+}
+
+/// Host function selected by the `li a7, id` that reaches an `ecall`
+/// without passing a label or another write to `a7`
+fn host_function_id(preceding: &[Instruction]) -> Option<u32> {
+    for instruction in preceding.iter().rev() {
+        match instruction {
+            Instruction::Li(Register::X17, id) => return Some(*id as u32),
+            Instruction::Label(_) => return None,
+            instruction if writes(instruction, Register::X17) => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Whether `instruction` may change `register`
+fn writes(instruction: &Instruction, register: Register) -> bool {
+    match instruction {
+        Instruction::Load(rd, ..)
+        | Instruction::LoadByteU(rd, ..)
+        | Instruction::Add(rd, ..)
+        | Instruction::AddImm(rd, ..)
+        | Instruction::Sub(rd, ..)
+        | Instruction::Mul(rd, ..)
+        | Instruction::MulHigh(rd, ..)
+        | Instruction::MulHighU(rd, ..)
+        | Instruction::Div(rd, ..)
+        | Instruction::Rem(rd, ..)
+        | Instruction::And(rd, ..)
+        | Instruction::Or(rd, ..)
+        | Instruction::Xor(rd, ..)
+        | Instruction::AndImm(rd, ..)
+        | Instruction::OrImm(rd, ..)
+        | Instruction::XorImm(rd, ..)
+        | Instruction::ShiftLeft(rd, ..)
+        | Instruction::ShiftRight(rd, ..)
+        | Instruction::ShiftRightArith(rd, ..)
+        | Instruction::ShiftLeftImm(rd, ..)
+        | Instruction::ShiftRightImm(rd, ..)
+        | Instruction::ShiftRightArithImm(rd, ..)
+        | Instruction::SetLessThan(rd, ..)
+        | Instruction::SetLessThanU(rd, ..)
+        | Instruction::SetLessThanImm(rd, ..)
+        | Instruction::SetLessThanImmU(rd, ..)
+        | Instruction::JumpAndLink(rd, _)
+        | Instruction::JumpAndLinkReg(rd, ..)
+        | Instruction::Li(rd, _)
+        | Instruction::La(rd, _)
+        | Instruction::Mv(rd, _)
+        | Instruction::Not(rd, _)
+        | Instruction::Neg(rd, _) => *rd == register,
+        // A host call may return values in argument registers
+        Instruction::Ecall => true,
+        _ => false,
+    }
+}
+
+fn write_u32(output: &mut Vec<u8>, value: u32) {
+    let mut buffer = [0; MAX_VARINT_LENGTH];
+    let length = write_varint(value, &mut buffer);
+    output.extend_from_slice(&buffer[..length]);
+}
+
+fn write_section(output: &mut Vec<u8>, section: u8, contents: &[u8]) {
+    output.push(section);
+    write_u32(output, contents.len() as u32);
+    output.extend_from_slice(contents);
+}
+
+/// Write a function prototype without arguments
+fn write_prototype(output: &mut Vec<u8>, name: &str, return_ty: Option<ExternTy>) {
+    write_u32(output, name.len() as u32);
+    output.extend_from_slice(name.as_bytes());
+    write_u32(output, 0);
+    output.push(return_ty.map_or(0, |ty| ty as u8));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polkavm_common::program::ProgramBlob;
+
+    #[test]
+    fn test_assemble_parses() {
+        let instructions = vec![
+            Instruction::Label("main".to_string()),
+            Instruction::Li(Register::X20, 40),
+            Instruction::AddImm(Register::X10, Register::X20, 2),
+            Instruction::Li(Register::X17, 61),
+            Instruction::Ecall,
+            Instruction::JumpAndLinkReg(Register::X0, Register::X1, 0),
+        ];
+        let blob = assemble(&instructions).unwrap();
+        let program = ProgramBlob::parse(&blob[..]).unwrap();
+
+        let imports: Vec<u32> = program.imports().map(|i| i.unwrap().index()).collect();
+        assert_eq!(imports, [61, LOAD_INPUT]);
+        let export = program.exports().next().unwrap().unwrap();
+        assert_eq!(export.prototype().name(), ENTRY_POINT);
+        assert!(program.instructions().all(|i| i.is_ok()));
+        assert_eq!(program.bss_size(), GUEST_MEMORY_SIZE + VM_PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_exports() {
+        let instructions = vec![
+            Instruction::Label("main".to_string()),
+            Instruction::Li(Register::X10, 1),
+            Instruction::JumpAndLinkReg(Register::X0, Register::X1, 0),
+            Instruction::Label("init".to_string()),
+            Instruction::Li(Register::X10, 2),
+            Instruction::JumpAndLinkReg(Register::X0, Register::X1, 0),
+            Instruction::Label(export_label("deploy")),
+            Instruction::Jump("init".to_string()),
+            Instruction::Label(export_label("ping")),
+            Instruction::Jump("main".to_string()),
+        ];
+        let blob = assemble(&instructions).unwrap();
+        let program = ProgramBlob::parse(&blob[..]).unwrap();
+
+        let exports: Vec<String> = program
+            .exports()
+            .map(|export| export.unwrap().prototype().name().to_string())
+            .collect();
+        assert_eq!(exports, [ENTRY_POINT, "deploy", "ping"]);
+        assert!(program.instructions().all(|i| i.is_ok()));
+    }
+
+    #[test]
+    fn test_host_function_id() {
+        let li = Instruction::Li(Register::X17, 1);
+        assert_eq!(host_function_id(std::slice::from_ref(&li)), Some(1));
+        assert_eq!(
+            host_function_id(&[li.clone(), Instruction::Li(Register::X10, 2)]),
+            Some(1)
+        );
+        assert_eq!(
+            host_function_id(&[li.clone(), Instruction::Label("l".to_string())]),
+            None
+        );
+        assert_eq!(host_function_id(&[li, Instruction::Ecall]), None);
+        assert_eq!(host_function_id(&[]), None);
+    }
+
+    #[test]
+    fn test_undefined_label() {
+        let error = assemble(&[Instruction::Jump("missing".to_string())]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to generate binary: Undefined label: missing"
+        );
+    }
+}
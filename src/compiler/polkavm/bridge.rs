@@ -1,6 +1,8 @@
 use std::path::Path;
 use thiserror::Error;
 
+use super::blob;
+use crate::compiler::codegen::encoder::{self, EncodeError, Isa, MachineCode};
 use crate::compiler::codegen::risc_v::Instruction;
 
 #[derive(Error, Debug, Clone)]
@@ -18,6 +20,12 @@ pub enum PolkaVMError {
     WriteError(String),
 }
 
+impl From<EncodeError> for PolkaVMError {
+    fn from(error: EncodeError) -> Self {
+        PolkaVMError::BinaryGenerationError(error.to_string())
+    }
+}
+
 /// Represents a PolkaVM module
 pub struct PolkaVMModule {
     /// Assembly code
@@ -26,6 +34,9 @@ pub struct PolkaVMModule {
     /// File path for the module
     pub file_path: Option<String>,
 
+    /// Instructions the binary is assembled from
+    pub instructions: Vec<Instruction>,
+
     /// Binary data (after compilation)
     pub binary: Option<Vec<u8>>,
 }
@@ -35,6 +46,7 @@ impl PolkaVMModule {
         PolkaVMModule {
             assembly,
             file_path: None,
+            instructions: Vec::new(),
             binary: None,
         }
     }
@@ -55,6 +67,7 @@ impl PolkaVMModule {
         PolkaVMModule {
             assembly,
             file_path: None,
+            instructions: instructions.to_vec(),
             binary: None,
         }
     }
@@ -71,46 +84,23 @@ impl PolkaVMModule {
         Ok(())
     }
 
-    /// Compile assembly to binary using PolkaVM toolchain
+    /// Assemble the instructions into a PolkaVM program blob
     pub fn compile(&mut self) -> Result<&[u8], PolkaVMError> {
-        // For this example, we'll simulate compilation with a mock function
-        // In a real implementation, this would use the PolkaVM toolchain
-
         // If we already have binary data, return it
         if let Some(ref binary) = self.binary {
             return Ok(binary);
         }
 
-        // Use an external assembler to generate binary
-        let binary = self.mock_assemble()?;
+        let binary = blob::assemble(&self.instructions)?;
 
         self.binary = Some(binary);
 
         Ok(self.binary.as_ref().expect("Binary was just set above"))
     }
 
-    /// Mock function to simulate assembling
-    fn mock_assemble(&self) -> Result<Vec<u8>, PolkaVMError> {
-        // In a real implementation, this would use the PolkaVM assembler
-        // For now, we'll just return a simple binary with instructions
-
-        // Start with a simple header (simulated)
-        let mut binary = vec![
-            0x7f, 0x45, 0x4c, 0x46, // Magic bytes for ELF
-            0x01, // 32-bit
-            0x01, // Little endian
-            0x01, // ELF version
-            0x00, // System V ABI
-        ];
-
-        // Add a placeholder for the assembly code
-        binary.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
-
-        // Add the assembly code's length as a simple way to include it
-        let len_bytes = (self.assembly.len() as u32).to_le_bytes();
-        binary.extend_from_slice(&len_bytes);
-
-        Ok(binary)
+    /// Encode the instructions as RISC-V machine code for `isa`
+    pub fn machine_code(&self, isa: Isa) -> Result<MachineCode, PolkaVMError> {
+        Ok(encoder::assemble(&self.instructions, isa)?)
     }
 
     /// Write binary to a file
@@ -196,7 +186,7 @@ mod tests {
         let result = module.compile();
         assert!(result.is_ok());
         let binary = result.unwrap();
-        assert!(!binary.is_empty());
+        assert!(binary.starts_with(b"PVM\0"));
         assert!(module.binary.is_some());
     }
 
@@ -216,4 +206,19 @@ mod tests {
 
         assert_eq!(len1, len2);
     }
+
+    #[test]
+    fn test_machine_code_of_generated_code() {
+        let source = "fn add(a: u24, b: u24) -> u24 {\n    return a + b;\n}\n";
+        let instructions = crate::generate_riscv_from_source(source, true).unwrap();
+        let module = PolkaVMModule::from_instructions(&instructions);
+
+        let machine_code = module.machine_code(Isa::Rv32Im).unwrap();
+        assert_eq!(machine_code.addresses.len(), instructions.len());
+        let decoded = encoder::disassemble(&machine_code.code).unwrap();
+        assert_eq!(
+            encoder::assemble(&decoded, Isa::Rv32Im).unwrap().code,
+            machine_code.code
+        );
+    }
 }
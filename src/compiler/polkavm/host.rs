@@ -1,6 +1,8 @@
 /// This module defines the host functions that the PolkaVM runtime provides to
 /// contract executables. These functions allow the contract to interact with the
 /// blockchain environment.
+use thiserror::Error;
+
 // Standard host functions provided to all contracts
 #[repr(u32)]
 pub enum HostFunction {
@@ -14,6 +16,9 @@ pub enum HostFunction {
     GetCallValue = 11,
     GetBlockNumber = 12,
     GetBlockTimestamp = 13,
+    GetBalance = 14,
+    Random = 15, // subject_ptr, subject_len, output_ptr
+    GetAddress = 16,
 
     // Contract interactions
     Call = 20,
@@ -21,6 +26,7 @@ pub enum HostFunction {
     DelegateCall = 22,
     Create = 23,
     Create2 = 24,
+    Transfer = 25,
 
     // Crypto operations
     Keccak256 = 30,
@@ -28,9 +34,10 @@ pub enum HostFunction {
     Sha256 = 32,
     Ripemd160 = 33,
     EcdsaRecover = 34,
+    Sr25519Verify = 35,
 
     // Debugging and logging
-    Log = 40,
+    Log = 40, // deposit_event: topics_ptr, topics_count, data_ptr, data_len
     Debug = 41,
 
     // Memory operations (for handling dynamic memory)
@@ -41,6 +48,11 @@ pub enum HostFunction {
     Abort = 60,
     Return = 61,
     Revert = 62,
+    Terminate = 63,
+    SetCodeHash = 64,
+
+    // Metering
+    ChargeGas = 70, // amount
 }
 
 /// Generates bindings for host functions
@@ -86,6 +98,12 @@ pub fn generate_host_bindings() -> String {
     bindings.push_str("    ecall\n");
     bindings.push_str(".endm\n\n");
 
+    bindings.push_str(".macro get_address result_ptr\n");
+    bindings.push_str("    li a7, 16  # GetAddress\n");
+    bindings.push_str("    mv a0, \\result_ptr\n");
+    bindings.push_str("    ecall\n");
+    bindings.push_str(".endm\n\n");
+
     bindings.push_str(".macro get_call_value result_ptr\n");
     bindings.push_str("    li a7, 11  # GetCallValue\n");
     bindings.push_str("    mv a0, \\result_ptr\n");
@@ -104,6 +122,20 @@ pub fn generate_host_bindings() -> String {
     bindings.push_str("    ecall\n");
     bindings.push_str(".endm\n\n");
 
+    bindings.push_str(".macro get_balance result_ptr\n");
+    bindings.push_str("    li a7, 14  # GetBalance\n");
+    bindings.push_str("    mv a0, \\result_ptr\n");
+    bindings.push_str("    ecall\n");
+    bindings.push_str(".endm\n\n");
+
+    bindings.push_str(".macro random subject_ptr subject_len output_ptr\n");
+    bindings.push_str("    li a7, 15  # Random\n");
+    bindings.push_str("    mv a0, \\subject_ptr\n");
+    bindings.push_str("    mv a1, \\subject_len\n");
+    bindings.push_str("    mv a2, \\output_ptr\n");
+    bindings.push_str("    ecall\n");
+    bindings.push_str(".endm\n\n");
+
     // Add contract interactions
     bindings.push_str(
         ".macro call address_ptr value_ptr gas input_ptr input_len output_ptr output_len_ptr\n",
@@ -132,6 +164,39 @@ pub fn generate_host_bindings() -> String {
     bindings.push_str("    ecall\n");
     bindings.push_str(".endm\n\n");
 
+    bindings.push_str(
+        ".macro delegate_call address_ptr gas input_ptr input_len output_ptr output_len_ptr\n",
+    );
+    bindings.push_str("    li a7, 22  # DelegateCall\n");
+    bindings.push_str("    mv a0, \\address_ptr\n");
+    bindings.push_str("    mv a1, \\gas\n");
+    bindings.push_str("    mv a2, \\input_ptr\n");
+    bindings.push_str("    mv a3, \\input_len\n");
+    bindings.push_str("    mv a4, \\output_ptr\n");
+    bindings.push_str("    mv a5, \\output_len_ptr\n");
+    bindings.push_str("    ecall\n");
+    bindings.push_str(".endm\n\n");
+
+    bindings.push_str(
+        ".macro instantiate code_hash_ptr value_ptr gas input_ptr input_len address_ptr\n",
+    );
+    bindings.push_str("    li a7, 23  # Create\n");
+    bindings.push_str("    mv a0, \\code_hash_ptr\n");
+    bindings.push_str("    mv a1, \\value_ptr\n");
+    bindings.push_str("    mv a2, \\gas\n");
+    bindings.push_str("    mv a3, \\input_ptr\n");
+    bindings.push_str("    mv a4, \\input_len\n");
+    bindings.push_str("    mv a5, \\address_ptr\n");
+    bindings.push_str("    ecall\n");
+    bindings.push_str(".endm\n\n");
+
+    bindings.push_str(".macro transfer address_ptr value_ptr\n");
+    bindings.push_str("    li a7, 25  # Transfer\n");
+    bindings.push_str("    mv a0, \\address_ptr\n");
+    bindings.push_str("    mv a1, \\value_ptr\n");
+    bindings.push_str("    ecall\n");
+    bindings.push_str(".endm\n\n");
+
     // Add crypto operations
     bindings.push_str(".macro keccak256 input_ptr input_len output_ptr\n");
     bindings.push_str("    li a7, 30  # Keccak256\n");
@@ -141,6 +206,34 @@ pub fn generate_host_bindings() -> String {
     bindings.push_str("    ecall\n");
     bindings.push_str(".endm\n\n");
 
+    for (name, id) in [("blake2b_256", 31), ("sha256", 32), ("ripemd160", 33)] {
+        bindings.push_str(&format!(".macro {} input_ptr input_len output_ptr\n", name));
+        bindings.push_str(&format!("    li a7, {}\n", id));
+        bindings.push_str("    mv a0, \\input_ptr\n");
+        bindings.push_str("    mv a1, \\input_len\n");
+        bindings.push_str("    mv a2, \\output_ptr\n");
+        bindings.push_str("    ecall\n");
+        bindings.push_str(".endm\n\n");
+    }
+
+    bindings.push_str(".macro ecdsa_recover signature_ptr hash_ptr output_ptr\n");
+    bindings.push_str("    li a7, 34  # EcdsaRecover\n");
+    bindings.push_str("    mv a0, \\signature_ptr\n");
+    bindings.push_str("    mv a1, \\hash_ptr\n");
+    bindings.push_str("    mv a2, \\output_ptr\n");
+    bindings.push_str("    ecall\n");
+    bindings.push_str(".endm\n\n");
+
+    bindings
+        .push_str(".macro sr25519_verify signature_ptr public_key_ptr message_ptr message_len\n");
+    bindings.push_str("    li a7, 35  # Sr25519Verify\n");
+    bindings.push_str("    mv a0, \\signature_ptr\n");
+    bindings.push_str("    mv a1, \\public_key_ptr\n");
+    bindings.push_str("    mv a2, \\message_ptr\n");
+    bindings.push_str("    mv a3, \\message_len\n");
+    bindings.push_str("    ecall\n");
+    bindings.push_str(".endm\n\n");
+
     // Add logging and debugging
     bindings.push_str(".macro log topics_ptr topics_count data_ptr data_len\n");
     bindings.push_str("    li a7, 40  # Log\n");
@@ -173,6 +266,25 @@ pub fn generate_host_bindings() -> String {
     bindings.push_str("    ecall\n");
     bindings.push_str(".endm\n\n");
 
+    bindings.push_str(".macro terminate beneficiary_ptr\n");
+    bindings.push_str("    li a7, 63  # Terminate\n");
+    bindings.push_str("    mv a0, \\beneficiary_ptr\n");
+    bindings.push_str("    ecall\n");
+    bindings.push_str(".endm\n\n");
+
+    bindings.push_str(".macro set_code_hash code_hash_ptr\n");
+    bindings.push_str("    li a7, 64  # SetCodeHash\n");
+    bindings.push_str("    mv a0, \\code_hash_ptr\n");
+    bindings.push_str("    ecall\n");
+    bindings.push_str(".endm\n\n");
+
+    // Add metering
+    bindings.push_str(".macro charge_gas amount\n");
+    bindings.push_str("    li a7, 70  # ChargeGas\n");
+    bindings.push_str("    mv a0, \\amount\n");
+    bindings.push_str("    ecall\n");
+    bindings.push_str(".endm\n\n");
+
     bindings
 }
 
@@ -301,3 +413,227 @@ pub fn generate_prelude() -> String {
 
     prelude
 }
+
+/// First host call id assigned to chain extensions
+pub const CHAIN_EXTENSION_BASE: u32 = 1000;
+
+/// Most arguments a chain extension takes, passed in `a0`-`a5`
+pub const MAX_CHAIN_EXTENSION_ARGS: usize = 6;
+
+/// Errors raised while registering chain extensions
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum HostError {
+    #[error("Chain extension {0} is already registered")]
+    DuplicateExtension(String),
+
+    #[error("Chain extension {name} takes {count} arguments, at most {max} are supported", max = MAX_CHAIN_EXTENSION_ARGS)]
+    TooManyArguments { name: String, count: usize },
+
+    #[error("Invalid chain extension name: {0}")]
+    InvalidName(String),
+
+    #[error("Invalid chain extension parameter: {0}")]
+    InvalidParameter(String),
+}
+
+/// Word types chain extensions exchange with contracts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtensionType {
+    U24,
+    I24,
+    /// No value (only valid as a return type)
+    Unit,
+}
+
+impl ExtensionType {
+    /// Name of the type in Bend source
+    pub fn bend_name(&self) -> &'static str {
+        match self {
+            ExtensionType::U24 => "u24",
+            ExtensionType::I24 => "i24",
+            ExtensionType::Unit => "None",
+        }
+    }
+}
+
+/// A custom host function provided by the chain, e.g. XCM send or an
+/// oracle read
+///
+/// Arguments are passed in `a0`-`a5` and the result is returned in `a0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainExtension {
+    /// Name contracts call the extension by
+    pub name: String,
+    /// Host call id, assigned on registration
+    pub id: u32,
+    /// Named parameters
+    pub params: Vec<(String, ExtensionType)>,
+    /// Return type
+    pub returns: ExtensionType,
+    /// Gas charged per call
+    pub gas: u64,
+}
+
+impl ChainExtension {
+    /// Create an extension without parameters returning `u24`
+    pub fn new(name: &str) -> Self {
+        ChainExtension {
+            name: name.to_string(),
+            id: 0,
+            params: Vec::new(),
+            returns: ExtensionType::U24,
+            gas: 0,
+        }
+    }
+
+    /// Add a parameter
+    pub fn with_param(mut self, name: &str, ty: ExtensionType) -> Self {
+        self.params.push((name.to_string(), ty));
+        self
+    }
+
+    /// Set the return type
+    pub fn with_return(mut self, ty: ExtensionType) -> Self {
+        self.returns = ty;
+        self
+    }
+
+    /// Set the gas charged per call
+    pub fn with_gas(mut self, gas: u64) -> Self {
+        self.gas = gas;
+        self
+    }
+
+    /// Body-less Bend declaration of the extension
+    pub fn extern_declaration(&self) -> String {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty.bend_name()))
+            .collect();
+        let mut declaration = format!("fn {}({})", self.name, params.join(", "));
+        if self.returns != ExtensionType::Unit {
+            declaration.push_str(&format!(" -> {}", self.returns.bend_name()));
+        }
+        declaration
+    }
+}
+
+/// Chain extensions available to contracts, keyed by name
+#[derive(Debug, Clone, Default)]
+pub struct ChainExtensionRegistry {
+    extensions: Vec<ChainExtension>,
+}
+
+impl ChainExtensionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an extension and return its host call id
+    pub fn register(&mut self, mut extension: ChainExtension) -> Result<u32, HostError> {
+        let valid_name = extension
+            .name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && extension
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(HostError::InvalidName(extension.name));
+        }
+        if self.get(&extension.name).is_some() {
+            return Err(HostError::DuplicateExtension(extension.name));
+        }
+        let unit_param = extension
+            .params
+            .iter()
+            .any(|(_, ty)| *ty == ExtensionType::Unit);
+        if unit_param {
+            return Err(HostError::InvalidParameter(format!(
+                "{} has a parameter of type None",
+                extension.name
+            )));
+        }
+        if extension.params.len() > MAX_CHAIN_EXTENSION_ARGS {
+            return Err(HostError::TooManyArguments {
+                count: extension.params.len(),
+                name: extension.name,
+            });
+        }
+
+        extension.id = CHAIN_EXTENSION_BASE + self.extensions.len() as u32;
+        let id = extension.id;
+        self.extensions.push(extension);
+        Ok(id)
+    }
+
+    /// Extension registered under `name`
+    pub fn get(&self, name: &str) -> Option<&ChainExtension> {
+        self.extensions.iter().find(|ext| ext.name == name)
+    }
+
+    /// Extension with host call id `id`
+    pub fn by_id(&self, id: u32) -> Option<&ChainExtension> {
+        self.extensions.iter().find(|ext| ext.id == id)
+    }
+
+    /// Registered extensions in id order
+    pub fn iter(&self) -> impl Iterator<Item = &ChainExtension> {
+        self.extensions.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.extensions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.extensions.is_empty()
+    }
+
+    /// Assembly macros for every extension, in the style of
+    /// [`generate_host_bindings`]
+    pub fn generate_bindings(&self) -> String {
+        let mut bindings = String::new();
+        bindings.push_str("# Chain extension bindings\n\n");
+
+        for extension in &self.extensions {
+            let params: Vec<&str> = extension
+                .params
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect();
+            bindings.push_str(&format!(".macro {} {}\n", extension.name, params.join(" ")));
+            bindings.push_str(&format!(
+                "    li a7, {}  # {}\n",
+                extension.id, extension.name
+            ));
+            for (i, param) in params.iter().enumerate() {
+                bindings.push_str(&format!("    mv a{}, \\{}\n", i, param));
+            }
+            bindings.push_str("    ecall\n");
+            bindings.push_str(".endm\n\n");
+        }
+
+        bindings
+    }
+
+    /// Bend declarations contracts include to call the extensions
+    pub fn generate_extern_declarations(&self) -> String {
+        let mut declarations = String::new();
+        declarations.push_str("# Chain extensions provided by the host\n\n");
+
+        for extension in &self.extensions {
+            declarations.push_str(&format!(
+                "# Host call {}, {} gas\n{}\n\n",
+                extension.id,
+                extension.gas,
+                extension.extern_declaration()
+            ));
+        }
+
+        declarations
+    }
+}
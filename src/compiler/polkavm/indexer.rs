@@ -0,0 +1,399 @@
+//! Off-chain indexer projects for the events of a contract, generated
+//! from its ABI
+//!
+//! The GraphQL schema has an entity per event, holding its fields along
+//! with the contract, block and extrinsic that emitted it; indexed fields
+//! are indexed in the database too. The TypeScript mappings recognize
+//! the contract's events among the `ContractEmitted` events of the
+//! contracts pallet by their first topic, the keccak256 hash of the event
+//! signature, and decode them as the code generator lays them out: an
+//! indexed field fills a 32-byte topic from its first byte, little endian,
+//! and the other fields follow each other in the data, as 4-byte words or,
+//! for wider integers, addresses and hashes, as all their bytes.
+//!
+//! Handlers are written for Subsquid processors and SubQuery projects;
+//! the decoders they share depend on neither.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::compiler::codegen::metadata::compute_event_topic;
+use crate::compiler::polkavm::abi::{ContractABI, EventABI};
+use crate::compiler::polkavm::bindgen::{camel_case, pascal_case, BindgenError, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indexer {
+    Subsquid,
+    SubQuery,
+}
+
+impl FromStr for Indexer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "subsquid" | "squid" => Ok(Indexer::Subsquid),
+            "subquery" | "subql" => Ok(Indexer::SubQuery),
+            _ => Err(format!(
+                "Unknown indexer '{}', expected subsquid or subquery",
+                s
+            )),
+        }
+    }
+}
+
+/// A file of an indexer project, at its path in the project
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexerFile {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+/// Columns every entity has, which event fields are renamed around
+const ENTITY_COLUMNS: &[&str] = &[
+    "id",
+    "contract",
+    "blockNumber",
+    "timestamp",
+    "extrinsicHash",
+];
+
+/// An event with its fields resolved
+struct Event<'a> {
+    abi: &'a EventABI,
+    entity: String,
+    /// Topic as `0x` hex
+    topic: String,
+    /// Name in the schema, value and whether it is a topic, per field
+    fields: Vec<(String, Value, bool)>,
+}
+
+impl<'a> Event<'a> {
+    fn resolve(abi: &'a EventABI) -> Self {
+        let fields = abi
+            .inputs
+            .iter()
+            .map(|input| {
+                let mut name = camel_case(&input.name);
+                if ENTITY_COLUMNS.contains(&name.as_str()) {
+                    name.push('_');
+                }
+                let value = Value::of(&input.type_);
+                (name, value, input.indexed.unwrap_or(false))
+            })
+            .collect();
+        Event {
+            abi,
+            entity: pascal_case(&abi.name),
+            topic: format!("0x{}", hex::encode(compute_event_topic(&abi.signature()))),
+            fields,
+        }
+    }
+
+    /// Name of the constant holding the topic
+    fn topic_constant(&self) -> String {
+        format!("{}_TOPIC", camel_to_upper(&self.entity))
+    }
+}
+
+/// Schema and mappings indexing the events of the contract with `abi`
+pub fn generate_indexer(
+    abi: &ContractABI,
+    indexer: Indexer,
+) -> Result<Vec<IndexerFile>, BindgenError> {
+    if abi.events.is_empty() {
+        return Err(BindgenError::NoEvents(abi.name.clone()));
+    }
+    let events: Vec<Event> = abi.events.iter().map(Event::resolve).collect();
+    let mapping = match indexer {
+        Indexer::Subsquid => format!("src/{}.ts", camel_case(&abi.name)),
+        Indexer::SubQuery => format!("src/mappings/{}.ts", camel_case(&abi.name)),
+    };
+    Ok(vec![
+        IndexerFile {
+            path: PathBuf::from("schema.graphql"),
+            contents: generate_schema(abi, &events, indexer),
+        },
+        IndexerFile {
+            path: PathBuf::from(mapping),
+            contents: generate_mapping(abi, &events, indexer),
+        },
+    ])
+}
+
+fn generate_schema(abi: &ContractABI, events: &[Event], indexer: Indexer) -> String {
+    let timestamp = match indexer {
+        Indexer::Subsquid => "DateTime",
+        Indexer::SubQuery => "Date",
+    };
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# Events of the `{}` contract (version {}), generated by `bend-pvm\n\
+         # bindgen --indexer` from its ABI. Do not edit.",
+        abi.name, abi.version
+    );
+    for event in events {
+        let _ = writeln!(out, "\n# `{}`", event.abi.signature());
+        let _ = writeln!(out, "type {} @entity {{", event.entity);
+        let _ = writeln!(out, "  id: ID!");
+        let _ = writeln!(out, "  contract: String! @index");
+        let _ = writeln!(out, "  blockNumber: Int! @index");
+        let _ = writeln!(out, "  timestamp: {}!", timestamp);
+        let _ = writeln!(out, "  extrinsicHash: String");
+        for (name, value, indexed) in &event.fields {
+            let index = if *indexed { " @index" } else { "" };
+            let _ = writeln!(out, "  {}: {}!{}", name, graphql_type(*value), index);
+        }
+        let _ = writeln!(out, "}}");
+    }
+    out
+}
+
+fn generate_mapping(abi: &ContractABI, events: &[Event], indexer: Indexer) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "// Mappings of the `{}` contract's events (version {}), generated by\n\
+         // `bend-pvm bindgen --indexer` from its ABI. Do not edit.",
+        abi.name, abi.version
+    );
+    let entities: Vec<&str> = events.iter().map(|event| event.entity.as_str()).collect();
+    match indexer {
+        Indexer::Subsquid => {
+            let _ = writeln!(
+                out,
+                "\nimport type {{ Store }} from \"@subsquid/typeorm-store\";\n\
+                 import {{ {} }} from \"./model\";",
+                entities.join(", ")
+            );
+        }
+        Indexer::SubQuery => {
+            let _ = writeln!(
+                out,
+                "\nimport type {{ SubstrateEvent }} from \"@subql/types\";\n\
+                 import type {{ Codec }} from \"@polkadot/types/types\";\n\
+                 import {{ {} }} from \"../types\";",
+                entities.join(", ")
+            );
+        }
+    }
+    out.push_str(TS_DECODING);
+
+    for event in events {
+        let _ = writeln!(
+            out,
+            "\n/** Topic of `{}` */\nexport const {} = \"{}\";",
+            event.abi.signature(),
+            event.topic_constant(),
+            event.topic
+        );
+        let _ = writeln!(out, "\nexport interface {}Event {{", event.entity);
+        for (name, value, _) in &event.fields {
+            let _ = writeln!(out, "  {}: {};", name, ts_type(*value));
+        }
+        let _ = writeln!(out, "}}");
+        let _ = writeln!(
+            out,
+            "\nexport function decode{}(topics: Uint8Array[], data: Uint8Array): {}Event {{\n  return {{",
+            event.entity, event.entity
+        );
+        let (mut topic, mut offset) = (1, 0);
+        for (name, value, indexed) in &event.fields {
+            let decoded = match indexed {
+                true => {
+                    topic += 1;
+                    decode_topic(*value, topic - 1)
+                }
+                false => {
+                    offset += data_len(*value);
+                    decode_data(*value, offset - data_len(*value))
+                }
+            };
+            let _ = writeln!(out, "    {}: {},", name, decoded);
+        }
+        let _ = writeln!(out, "  }};\n}}");
+    }
+
+    match indexer {
+        Indexer::Subsquid => generate_subsquid_handler(&mut out, events),
+        Indexer::SubQuery => generate_subquery_handler(&mut out, events),
+    }
+    out
+}
+
+fn generate_subsquid_handler(out: &mut String, events: &[Event]) {
+    out.push_str(SUBSQUID_EVENT);
+    let _ = writeln!(
+        out,
+        "\n/** Store the events the contract at `address` emitted among `events` */\n\
+         export async function handleContractEmitted(\n  store: Store,\n  address: string,\n  events: ContractEmitted[],\n): Promise<void> {{"
+    );
+    for event in events {
+        let _ = writeln!(
+            out,
+            "  const {}: {}[] = [];",
+            camel_case(&event.abi.name),
+            event.entity
+        );
+    }
+    let _ = writeln!(
+        out,
+        "  for (const event of events) {{\n    \
+         if (event.args.contract.toLowerCase() !== address.toLowerCase()) {{\n      continue;\n    }}\n    \
+         const topics = event.args.topics.map(fromHex);\n    \
+         const data = fromHex(event.args.data);\n    \
+         const columns = {{\n      id: event.id,\n      contract: event.args.contract,\n      \
+         blockNumber: event.block.height,\n      timestamp: new Date(event.block.timestamp ?? 0),\n      \
+         extrinsicHash: event.extrinsic?.hash,\n    }};\n    \
+         switch (event.args.topics[0]) {{"
+    );
+    for event in events {
+        let _ = writeln!(
+            out,
+            "      case {}:\n        {}.push(new {}({{ ...columns, ...decode{}(topics, data) }}));\n        break;",
+            event.topic_constant(),
+            camel_case(&event.abi.name),
+            event.entity,
+            event.entity
+        );
+    }
+    let _ = writeln!(out, "    }}\n  }}");
+    for event in events {
+        let _ = writeln!(
+            out,
+            "  await store.insert({});",
+            camel_case(&event.abi.name)
+        );
+    }
+    let _ = writeln!(out, "}}");
+}
+
+fn generate_subquery_handler(out: &mut String, events: &[Event]) {
+    let _ = writeln!(
+        out,
+        "\n/** Address of the contract to index; empty indexes every contract\n \
+         * emitting these events */\n\
+         export const CONTRACT_ADDRESS = \"\";\n\n\
+         /** Handler of `Revive.ContractEmitted` events */\n\
+         export async function handleContractEmitted(event: SubstrateEvent): Promise<void> {{\n  \
+         const [contract, data, topics] = event.event.data;\n  \
+         if (CONTRACT_ADDRESS && contract.toString().toLowerCase() !== CONTRACT_ADDRESS.toLowerCase()) {{\n    return;\n  }}\n  \
+         const topicBytes = (topics as unknown as Codec[]).map((topic) => topic.toU8a(true));\n  \
+         const dataBytes = data.toU8a(true);\n  \
+         const columns = {{\n    id: `${{event.block.block.header.number.toString()}}-${{event.idx}}`,\n    \
+         contract: contract.toString(),\n    blockNumber: event.block.block.header.number.toNumber(),\n    \
+         timestamp: event.block.timestamp ?? new Date(0),\n    \
+         extrinsicHash: event.extrinsic?.extrinsic.hash.toHex(),\n  }};\n  \
+         switch (toHex(topicBytes[0])) {{"
+    );
+    for event in events {
+        let _ = writeln!(
+            out,
+            "    case {}:\n      await {}.create({{ ...columns, ...decode{}(topicBytes, dataBytes) }}).save();\n      break;",
+            event.topic_constant(),
+            event.entity,
+            event.entity
+        );
+    }
+    let _ = writeln!(out, "  }}\n}}");
+}
+
+fn graphql_type(value: Value) -> &'static str {
+    match value {
+        Value::U32 | Value::I32 => "Int",
+        Value::Bool => "Boolean",
+        Value::Uint(_) => "BigInt",
+        Value::Bytes32 | Value::Bytes | Value::Raw => "String",
+    }
+}
+
+fn ts_type(value: Value) -> &'static str {
+    match value {
+        Value::U32 | Value::I32 => "number",
+        Value::Bool => "boolean",
+        Value::Uint(_) => "bigint",
+        Value::Bytes32 | Value::Bytes | Value::Raw => "string",
+    }
+}
+
+/// The decoding of a field filling topic `index`
+fn decode_topic(value: Value, index: usize) -> String {
+    let topic = format!("topics[{}]", index);
+    match value {
+        Value::U32 => format!("Number(uint({}.subarray(0, 4)))", topic),
+        Value::I32 => format!("Number(BigInt.asIntN(32, uint({}.subarray(0, 4))))", topic),
+        Value::Bool => format!("{}[0] !== 0", topic),
+        Value::Uint(bits) => format!("uint({}.subarray(0, {}))", topic, bits / 8),
+        Value::Bytes32 | Value::Bytes | Value::Raw => format!("toHex({})", topic),
+    }
+}
+
+/// Number of bytes a field takes in the data
+fn data_len(value: Value) -> usize {
+    match value {
+        Value::Uint(bits) => bits as usize / 8,
+        Value::Bytes32 => 32,
+        _ => 4,
+    }
+}
+
+/// The decoding of a field held in the data from `offset`
+fn decode_data(value: Value, offset: usize) -> String {
+    let word = format!("data.subarray({}, {})", offset, offset + data_len(value));
+    match value {
+        Value::U32 => format!("Number(uint({}))", word),
+        Value::I32 => format!("Number(BigInt.asIntN(32, uint({})))", word),
+        Value::Bool => format!("data[{}] !== 0", offset),
+        Value::Uint(_) => format!("uint({})", word),
+        Value::Bytes32 | Value::Bytes | Value::Raw => format!("toHex({})", word),
+    }
+}
+
+/// `PascalCase` as `UPPER_SNAKE_CASE`
+fn camel_to_upper(name: &str) -> String {
+    let mut upper = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            upper.push('_');
+        }
+        upper.push(c.to_ascii_uppercase());
+    }
+    upper
+}
+
+const TS_DECODING: &str = r#"
+/** Bytes of a `0x` hex string */
+function fromHex(hex: string): Uint8Array {
+  const digits = hex.startsWith("0x") ? hex.slice(2) : hex;
+  const bytes = new Uint8Array(digits.length / 2);
+  for (let i = 0; i < bytes.length; i++) {
+    bytes[i] = parseInt(digits.slice(2 * i, 2 * i + 2), 16);
+  }
+  return bytes;
+}
+
+function toHex(bytes: Uint8Array): string {
+  return "0x" + Array.from(bytes, (byte) => byte.toString(16).padStart(2, "0")).join("");
+}
+
+/** Little-endian unsigned integer of `bytes` */
+function uint(bytes: Uint8Array): bigint {
+  let value = 0n;
+  for (let i = bytes.length - 1; i >= 0; i--) {
+    value = (value << 8n) | BigInt(bytes[i]);
+  }
+  return value;
+}
+"#;
+
+const SUBSQUID_EVENT: &str = r#"
+/** A `Revive.ContractEmitted` event, as the processor delivers it */
+export interface ContractEmitted {
+  id: string;
+  block: { height: number; timestamp?: number };
+  extrinsic?: { hash: string };
+  args: { contract: string; data: string; topics: string[] };
+}
+"#;
@@ -0,0 +1,659 @@
+//! Incremental analysis through memoized queries
+//!
+//! A [`Database`] answers questions about the modules of a program: the
+//! syntax tree of a file ([`Database::parse`]), the names a module defines
+//! ([`Database::symbols`]), the module linked with the modules it imports
+//! ([`Database::module`]) and what type checking it found
+//! ([`Database::check`]). The command line and the language server share
+//! it, so that checking again after an edit, or answering the next request
+//! of an editor, only redoes what the edit could have changed.
+//!
+//! The sources of the modules are the inputs. A source is the text of an
+//! editor buffer, set with [`Database::set_source`], or else the file on
+//! disk, read the first time it is needed and again when
+//! [`Database::reload`] is told it changed. Every change to an input
+//! starts a new revision.
+//!
+//! Every answer is remembered with the queries it asked on the way. The
+//! first time it is asked for in a revision, the queries it depends on are
+//! brought up to date, and when none of them changed since it was found it
+//! is reused as it is; otherwise it is found again. An answer found again
+//! equal to the previous one counts as unchanged, so what depends on it is
+//! still reused: editing the body of a function changes the syntax tree of
+//! its module but not its symbols, and undoing an edit before the next
+//! query changes nothing at all.
+//!
+//! Which file an import refers to depends on the files that exist, so
+//! linking a module also depends on the layout of the files, which changes
+//! whenever a file appears or disappears.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use bend_pvm::compiler::module::ModuleSystem;
+//! use bend_pvm::compiler::query::Database;
+//!
+//! let mut database = Database::new(ModuleSystem::new());
+//! let checked = database.check("token.bend".as_ref());
+//!
+//! // Only the modules importing token.bend are checked again
+//! database.set_source("token.bend".as_ref(), "fn main() -> u24 {\n    return 2;\n}\n");
+//! let again = database.check("token.bend".as_ref());
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::compiler::analyzer::lints::Warning;
+use crate::compiler::analyzer::type_checker::{TypeChecker, TypeError, TypeInfo};
+use crate::compiler::module::cache::canonical;
+use crate::compiler::module::loader::read_source;
+use crate::compiler::module::{import_paths, Module, ModuleError, ModuleSystem};
+use crate::compiler::object::{is_object, ObjectFile};
+use crate::compiler::parser::ast::{Definition, Location, Program, Visibility};
+use crate::compiler::parser::parser::{ParseError, Parser};
+
+/// A point in the history of the inputs, counting their changes
+pub type Revision = u64;
+
+/// A name a module defines at the top level
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Whether other modules can import it
+    pub public: bool,
+}
+
+/// What a [`ModuleSymbol`] names
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    /// Types, errors, aliases and interfaces
+    Type,
+    Object,
+    Module,
+    Event,
+    Guard,
+}
+
+/// What type checking a module along with the modules it imports found
+#[derive(Debug, Clone)]
+pub struct Checked {
+    /// The error type checking stopped at, if any
+    pub error: Option<TypeError>,
+    /// Warnings of the type checker, not of the lints
+    pub warnings: Vec<Warning>,
+    /// See [`TypeChecker::name_types`]
+    pub name_types: Vec<(String, Location, TypeInfo)>,
+    /// See [`TypeChecker::inferred_results`]
+    pub inferred_results: Vec<(String, TypeInfo)>,
+}
+
+/// A question the database answers, along with what it is about
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Query {
+    /// Which files exist
+    Layout,
+    Source(PathBuf),
+    Parse(PathBuf),
+    Symbols(PathBuf),
+    Module(PathBuf),
+    Check(PathBuf),
+}
+
+/// A remembered answer
+struct Memo<T> {
+    value: T,
+    /// The last revision the answer was known to be up to date in
+    verified_at: Revision,
+    /// The revision the answer last changed in
+    changed_at: Revision,
+    /// The queries asked to find the answer
+    dependencies: Vec<Query>,
+}
+
+/// The source of one module
+struct Input {
+    /// `None` when the file cannot be read
+    text: Option<Arc<str>>,
+    /// Whether the text is that of an editor buffer rather than the file
+    open: bool,
+    changed_at: Revision,
+}
+
+/// A query being answered, with the queries it asked so far
+struct Frame {
+    query: Query,
+    dependencies: Vec<Query>,
+}
+
+type Memos<T> = HashMap<PathBuf, Memo<T>>;
+
+/// Remembered answers about the modules found by one module system
+pub struct Database {
+    /// Finds the modules imports refer to, and configures them
+    modules: ModuleSystem,
+    revision: Revision,
+    layout_changed_at: Revision,
+    sources: HashMap<PathBuf, Input>,
+    parsed: Memos<Arc<Result<Program, ParseError>>>,
+    symbols: Memos<Arc<Vec<ModuleSymbol>>>,
+    linked: Memos<Result<Arc<Module>, ModuleError>>,
+    checked: Memos<Result<Arc<Checked>, ModuleError>>,
+    /// The queries being answered, innermost last
+    active: Vec<Frame>,
+}
+
+impl fmt::Debug for Database {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Database")
+            .field("revision", &self.revision)
+            .field("sources", &self.sources.len())
+            .field("parsed", &self.parsed.len())
+            .field("linked", &self.linked.len())
+            .field("checked", &self.checked.len())
+            .finish()
+    }
+}
+
+impl Database {
+    /// A database finding and configuring modules the way `modules` does.
+    /// Modules `modules` already loaded are not reused.
+    pub fn new(modules: ModuleSystem) -> Self {
+        Database {
+            modules: modules.fork(),
+            revision: 0,
+            layout_changed_at: 0,
+            sources: HashMap::new(),
+            parsed: HashMap::new(),
+            symbols: HashMap::new(),
+            linked: HashMap::new(),
+            checked: HashMap::new(),
+            active: Vec::new(),
+        }
+    }
+
+    /// The module system modules are found and configured with
+    pub fn module_system(&self) -> &ModuleSystem {
+        &self.modules
+    }
+
+    /// The current revision
+    pub fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    /// Use `text` as the source of the module at `path`, such as an editor
+    /// buffer with unsaved changes, until [`Self::close_source`]
+    pub fn set_source(&mut self, path: &Path, text: &str) {
+        let input = self
+            .sources
+            .entry(canonical(path))
+            .or_insert_with(|| Input {
+                text: None,
+                open: false,
+                changed_at: 0,
+            });
+        input.open = true;
+        if input.text.as_deref() != Some(text) {
+            self.revision += 1;
+            input.text = Some(text.into());
+            input.changed_at = self.revision;
+        }
+    }
+
+    /// Go back to reading the module at `path` from disk
+    pub fn close_source(&mut self, path: &Path) {
+        if let Some(input) = self.sources.get_mut(&canonical(path)) {
+            input.open = false;
+        }
+        self.reload(path);
+    }
+
+    /// Read the file at `path` again, after it changed on disk. Nothing
+    /// changes while an editor buffer stands for it.
+    pub fn reload(&mut self, path: &Path) {
+        let path = canonical(path);
+        let text = read(&path);
+        let Some(input) = self.sources.get_mut(&path) else {
+            // A new file may be what an import was looking for
+            if text.is_some() {
+                self.revision += 1;
+                self.layout_changed_at = self.revision;
+            }
+            return;
+        };
+        if input.open || input.text == text {
+            return;
+        }
+
+        self.revision += 1;
+        if input.text.is_some() != text.is_some() {
+            self.layout_changed_at = self.revision;
+        }
+        input.text = text;
+        input.changed_at = self.revision;
+    }
+
+    /// The source of the module at `path`, if it can be read
+    pub fn source(&mut self, path: &Path) -> Option<Arc<str>> {
+        let path = canonical(path);
+        self.depend_on(Query::Source(path.clone()));
+        let revision = self.revision;
+        self.sources
+            .entry(path)
+            .or_insert_with_key(|path| Input {
+                text: read(path),
+                open: false,
+                changed_at: revision,
+            })
+            .text
+            .clone()
+    }
+
+    /// The syntax tree of the module at `path`, or the interface of an
+    /// object file
+    pub fn parse(&mut self, path: &Path) -> Arc<Result<Program, ParseError>> {
+        self.fetch(
+            Query::Parse(canonical(path)),
+            |database| &mut database.parsed,
+            |database, path| {
+                let Some(source) = database.source(path) else {
+                    let message = format!("cannot read {}", path.display());
+                    return Arc::new(Err(ParseError::Generic(message)));
+                };
+                Arc::new(if is_object(path) {
+                    ObjectFile::from_bytes(source.as_bytes())
+                        .map(|object| object.interface_program())
+                        .map_err(|e| ParseError::Generic(e.to_string()))
+                } else {
+                    Parser::new(&source).parse_program()
+                })
+            },
+            |old, new| matches!((&**old, &**new), (Ok(old), Ok(new)) if old == new),
+        )
+    }
+
+    /// The names the module at `path` defines at the top level, leaving
+    /// out those whose `#[cfg]` does not hold, in the order they are
+    /// defined. Empty when the module does not parse.
+    pub fn symbols(&mut self, path: &Path) -> Arc<Vec<ModuleSymbol>> {
+        self.fetch(
+            Query::Symbols(canonical(path)),
+            |database| &mut database.symbols,
+            |database, path| {
+                let parsed = database.parse(path);
+                let Ok(program) = &*parsed else {
+                    return Arc::default();
+                };
+                let cfg = database.modules.cfg();
+                Arc::new(
+                    program
+                        .definitions
+                        .iter()
+                        .filter(|definition| cfg.enabled(definition.attributes()).unwrap_or(true))
+                        .filter_map(module_symbol)
+                        .collect(),
+                )
+            },
+            |old, new| old == new,
+        )
+    }
+
+    /// The module at `path`, configured and linked with the modules it
+    /// imports
+    pub fn module(&mut self, path: &Path) -> Result<Arc<Module>, ModuleError> {
+        let path = canonical(path);
+        if self.is_active(&Query::Module(path.clone())) {
+            // Whether the cycle remains depends on what the module imports
+            self.depend_on(Query::Parse(path.clone()));
+            return Err(ModuleError::CircularDependency(
+                path.to_string_lossy().to_string(),
+            ));
+        }
+        self.fetch(
+            Query::Module(path),
+            |database| &mut database.linked,
+            Self::link,
+            |_, _| false,
+        )
+    }
+
+    /// Type check the module at `path` along with the modules it imports
+    pub fn check(&mut self, path: &Path) -> Result<Arc<Checked>, ModuleError> {
+        self.fetch(
+            Query::Check(canonical(path)),
+            |database| &mut database.checked,
+            |database, path| {
+                let module = database.module(path)?;
+                let mut checker = TypeChecker::new();
+                let error = checker.check_program(&module.program()).err();
+                Ok(Arc::new(Checked {
+                    error,
+                    warnings: checker.warnings().to_vec(),
+                    name_types: checker.name_types().to_vec(),
+                    inferred_results: checker.inferred_results().to_vec(),
+                }))
+            },
+            |_, _| false,
+        )
+    }
+
+    /// Link the module at `path` with the modules it imports, which are
+    /// queried first so the module system finds them rather than loading
+    /// them again
+    fn link(&mut self, path: &Path) -> Result<Arc<Module>, ModuleError> {
+        self.depend_on(Query::Layout);
+        let program = match &*self.parse(path) {
+            Ok(program) => program.clone(),
+            Err(error) => return Err(ModuleError::LoadFailure(error.to_string())),
+        };
+
+        let mut modules = self.modules.fork();
+        for import in modules.imports(path, &program) {
+            for name in import_paths(&import) {
+                // Imports that cannot be found are reported when linking
+                if let Some(imported) = modules.resolve_module_path(name) {
+                    modules.insert_module(self.module(&imported)?);
+                }
+            }
+        }
+        modules.load_program(path, program)
+    }
+
+    /// Answer `query`, reusing the answer in `memos` while it is up to
+    /// date and finding it with `find` otherwise. `same` tells whether a
+    /// new answer is equal to the previous one.
+    fn fetch<T: Clone>(
+        &mut self,
+        query: Query,
+        memos: fn(&mut Self) -> &mut Memos<T>,
+        find: fn(&mut Self, &Path) -> T,
+        same: fn(&T, &T) -> bool,
+    ) -> T {
+        self.depend_on(query.clone());
+        let path = query.path().to_path_buf();
+        self.active.push(Frame {
+            query,
+            dependencies: Vec::new(),
+        });
+
+        let value = match self.verify(&path, memos) {
+            Some(value) => value,
+            None => {
+                // Verifying may have asked queries the new answer does not
+                self.active.last_mut().unwrap().dependencies.clear();
+                let value = find(self, &path);
+                let dependencies =
+                    std::mem::take(&mut self.active.last_mut().unwrap().dependencies);
+                let revision = self.revision;
+                match memos(self).get_mut(&path) {
+                    Some(memo) if same(&memo.value, &value) => {
+                        memo.verified_at = revision;
+                        memo.dependencies = dependencies;
+                        memo.value.clone()
+                    }
+                    _ => {
+                        let memo = Memo {
+                            value: value.clone(),
+                            verified_at: revision,
+                            changed_at: revision,
+                            dependencies,
+                        };
+                        memos(self).insert(path, memo);
+                        value
+                    }
+                }
+            }
+        };
+        self.active.pop();
+        value
+    }
+
+    /// The remembered answer for `path` in `memos`, if nothing it depends
+    /// on changed since it was last known to be up to date
+    fn verify<T: Clone>(
+        &mut self,
+        path: &Path,
+        memos: fn(&mut Self) -> &mut Memos<T>,
+    ) -> Option<T> {
+        let revision = self.revision;
+        let memo = memos(self).get(path)?;
+        if memo.verified_at == revision {
+            return Some(memo.value.clone());
+        }
+
+        let verified_at = memo.verified_at;
+        for dependency in memo.dependencies.clone() {
+            if self.refresh(&dependency) > verified_at {
+                return None;
+            }
+        }
+        let memo = memos(self).get_mut(path)?;
+        memo.verified_at = revision;
+        Some(memo.value.clone())
+    }
+
+    /// Bring `query` up to date, returning the revision it last changed in
+    fn refresh(&mut self, query: &Query) -> Revision {
+        fn changed_at<T>(memos: &Memos<T>, path: &Path, revision: Revision) -> Revision {
+            // A query not remembered was a cycle, which may have changed
+            memos.get(path).map_or(revision, |memo| memo.changed_at)
+        }
+
+        match query {
+            Query::Layout => self.layout_changed_at,
+            Query::Source(path) => {
+                self.source(path);
+                self.sources[path].changed_at
+            }
+            Query::Parse(path) => {
+                self.parse(path);
+                changed_at(&self.parsed, path, self.revision)
+            }
+            Query::Symbols(path) => {
+                self.symbols(path);
+                changed_at(&self.symbols, path, self.revision)
+            }
+            Query::Module(path) => {
+                let _ = self.module(path);
+                changed_at(&self.linked, path, self.revision)
+            }
+            Query::Check(path) => {
+                let _ = self.check(path);
+                changed_at(&self.checked, path, self.revision)
+            }
+        }
+    }
+
+    /// Record that the query being answered asked `query`
+    fn depend_on(&mut self, query: Query) {
+        if let Some(frame) = self.active.last_mut() {
+            if !frame.dependencies.contains(&query) {
+                frame.dependencies.push(query);
+            }
+        }
+    }
+
+    fn is_active(&self, query: &Query) -> bool {
+        self.active.iter().any(|frame| frame.query == *query)
+    }
+}
+
+impl Query {
+    fn path(&self) -> &Path {
+        match self {
+            Query::Layout => Path::new(""),
+            Query::Source(path)
+            | Query::Parse(path)
+            | Query::Symbols(path)
+            | Query::Module(path)
+            | Query::Check(path) => path,
+        }
+    }
+}
+
+/// The text of the file at `path`, which may be embedded in the compiler
+fn read(path: &Path) -> Option<Arc<str>> {
+    read_source(path).ok().map(Arc::from)
+}
+
+fn module_symbol(definition: &Definition) -> Option<ModuleSymbol> {
+    let (name, kind) = match definition {
+        Definition::FunctionDef { name, .. } => (name, SymbolKind::Function),
+        Definition::TypeDef { name, .. }
+        | Definition::ErrorDef { name, .. }
+        | Definition::TypeAlias { name, .. }
+        | Definition::InterfaceDef { name, .. } => (name, SymbolKind::Type),
+        Definition::ObjectDef { name, .. } => (name, SymbolKind::Object),
+        Definition::Module { name, .. } => (name, SymbolKind::Module),
+        Definition::EventDef { name, .. } => (name, SymbolKind::Event),
+        Definition::GuardDef { name, .. } => (name, SymbolKind::Guard),
+        Definition::StorageDef { .. } | Definition::ContractDef { .. } => return None,
+    };
+    Some(ModuleSymbol {
+        name: name.to_string(),
+        kind,
+        // Guards are expanded into the functions of their own module
+        public: kind != SymbolKind::Guard && definition.visibility() == Visibility::Public,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bend_query_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn database(dir: &Path) -> Database {
+        let mut modules = ModuleSystem::new();
+        modules.add_search_path(dir);
+        Database::new(modules)
+    }
+
+    const UTILS: &str = "pub fn one() -> u24 {\n    return 1;\n}\n";
+    const MAIN: &str = "from utils import one;\n\nfn main() -> u24 {\n    return one();\n}\n";
+    const OTHER: &str = "fn main() -> u24 {\n    return 2;\n}\n";
+
+    #[test]
+    fn test_edits_only_affect_dependents() {
+        let dir = scratch_dir("dependents");
+        let (utils, main, other) = (
+            dir.join("utils.bend"),
+            dir.join("main.bend"),
+            dir.join("other.bend"),
+        );
+        fs::write(&utils, UTILS).unwrap();
+        fs::write(&main, MAIN).unwrap();
+        fs::write(&other, OTHER).unwrap();
+
+        let mut database = database(&dir);
+        let main_checked = database.check(&main).unwrap();
+        let other_checked = database.check(&other).unwrap();
+        assert!(main_checked.error.is_none());
+        assert!(Arc::ptr_eq(&main_checked, &database.check(&main).unwrap()));
+
+        database.set_source(&utils, "pub fn one() -> u24 {\n    return true;\n}\n");
+        let again = database.check(&main).unwrap();
+        assert!(!Arc::ptr_eq(&main_checked, &again));
+        assert!(again.error.is_some());
+        assert!(Arc::ptr_eq(
+            &other_checked,
+            &database.check(&other).unwrap()
+        ));
+
+        // Closing the buffer goes back to the file
+        database.close_source(&utils);
+        assert!(database.check(&main).unwrap().error.is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_equal_answers_keep_dependents() {
+        let dir = scratch_dir("equal");
+        let (utils, main) = (dir.join("utils.bend"), dir.join("main.bend"));
+        fs::write(&utils, UTILS).unwrap();
+        fs::write(&main, MAIN).unwrap();
+
+        let mut database = database(&dir);
+        let checked = database.check(&main).unwrap();
+        let symbols = database.symbols(&utils);
+        assert_eq!(
+            *symbols,
+            vec![ModuleSymbol {
+                name: "one".to_string(),
+                kind: SymbolKind::Function,
+                public: true,
+            }]
+        );
+
+        // An edit undone before the next query changes nothing
+        database.set_source(&utils, "pub fn one() -> u24 {\n    return 2;\n}\n");
+        database.set_source(&utils, UTILS);
+        assert!(Arc::ptr_eq(&checked, &database.check(&main).unwrap()));
+
+        // Editing a body leaves the symbols as they were
+        database.set_source(&utils, "pub fn one() -> u24 {\n    return 3;\n}\n");
+        assert!(Arc::ptr_eq(&symbols, &database.symbols(&utils)));
+        assert!(!Arc::ptr_eq(&checked, &database.check(&main).unwrap()));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_new_files_resolve_imports() {
+        let dir = scratch_dir("layout");
+        let (utils, main) = (dir.join("utils.bend"), dir.join("main.bend"));
+        fs::write(&main, MAIN).unwrap();
+
+        let mut database = database(&dir);
+        assert!(matches!(
+            database.check(&main),
+            Err(ModuleError::NotFound(name)) if name == "utils"
+        ));
+
+        fs::write(&utils, UTILS).unwrap();
+        database.reload(&utils);
+        assert!(database.check(&main).unwrap().error.is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_circular_imports() {
+        let dir = scratch_dir("cycle");
+        let (a, b) = (dir.join("a.bend"), dir.join("b.bend"));
+        fs::write(
+            &a,
+            "from b import two;\n\npub fn one() -> u24 {\n    return 1;\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            &b,
+            "from a import one;\n\npub fn two() -> u24 {\n    return 2;\n}\n",
+        )
+        .unwrap();
+
+        let mut database = database(&dir);
+        assert!(matches!(
+            database.module(&a),
+            Err(ModuleError::CircularDependency(_))
+        ));
+
+        database.set_source(&b, "pub fn two() -> u24 {\n    return 2;\n}\n");
+        assert!(database.module(&a).is_ok());
+        assert!(database.module(&b).is_ok());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,1417 @@
+//! Bounded symbolic execution of lowered programs
+//!
+//! Each function is run on symbolic arguments, forking at every branch and
+//! unrolling loops a bounded number of times. Wherever the generated code
+//! would trap, an `assert`, checked arithmetic, a division or a bounded
+//! loop, the path constraints go to a [`Solver`] along with the condition
+//! of the trap. A model of them is a counterexample: arguments reaching
+//! the trap, with the storage values and call results the path assumes.
+//!
+//! Specifications are checked statically: the `#[requires(...)]` of a
+//! function and the `#[invariant(...)]`s of storage are assumed on entry,
+//! and its `#[ensures(...)]`, with the invariants when it may change state,
+//! are checked like asserts where it returns.
+//!
+//! Storage is read once per path and calls to other contracts, host
+//! functions and functions past the call depth return unconstrained
+//! values. Paths through constructs the executor does not model, `match`,
+//! `fold`, `bend` and the like, are cut and the function is reported as
+//! truncated.
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::Serialize;
+
+use crate::compiler::analyzer::attributes::{FunctionAttributes, Mutability};
+use crate::compiler::analyzer::type_checker::{OLD, RESULT};
+use crate::compiler::intern::Symbol;
+use crate::compiler::lowering::{INVARIANT_FAILED, POSTCONDITION_FAILED};
+use crate::compiler::parser::ast::{
+    Attribute, BinaryOperator, Block, Definition, Expr, InPlaceOperator, LiteralKind, Location,
+    LocationProvider, Pattern, Program, RevertKind, Statement, Type, UnaryOperator,
+};
+
+use super::solver::{default_solver, satisfies, BinaryOp, Model, Solution, Solver, Term, Variable};
+
+/// How far the executor explores
+#[derive(Debug, Clone)]
+pub struct SymbolicOptions {
+    /// Iterations of a loop explored before the path is cut
+    pub loop_bound: u32,
+    /// Depth of calls to functions of the program inlined
+    pub call_depth: usize,
+    /// Branches forked in a function before paths are cut
+    pub max_paths: usize,
+}
+
+impl Default for SymbolicOptions {
+    fn default() -> Self {
+        SymbolicOptions {
+            loop_bound: 8,
+            call_depth: 4,
+            max_paths: 1024,
+        }
+    }
+}
+
+/// Why the code traps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureKind {
+    Assertion,
+    Overflow,
+    DivisionByZero,
+    /// A bounded loop would run more iterations than its bound
+    LoopBound,
+    /// An `#[ensures(...)]` would not hold on return
+    Postcondition,
+    /// An `#[invariant(...)]` would not hold after a state-changing call
+    Invariant,
+}
+
+impl std::fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FailureKind::Assertion => "assertion failure",
+            FailureKind::Overflow => "arithmetic overflow",
+            FailureKind::DivisionByZero => "division by zero",
+            FailureKind::LoopBound => "loop bound exceeded",
+            FailureKind::Postcondition => "postcondition violation",
+            FailureKind::Invariant => "invariant violation",
+        })
+    }
+}
+
+/// A trap and the values reaching it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Counterexample {
+    pub function: String,
+    pub kind: FailureKind,
+    pub location: Location,
+    pub message: String,
+    /// Arguments of the function
+    pub inputs: Model,
+    /// Storage values and call results the path depends on
+    pub environment: Model,
+}
+
+impl std::fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} in `{}`: {}",
+            self.location.line, self.location.column, self.kind, self.function, self.message
+        )?;
+        let values: Vec<String> = self
+            .inputs
+            .iter()
+            .chain(&self.environment)
+            .map(|(name, value)| format!("{} = {}", name.trim_start_matches('%'), value))
+            .collect();
+        if !values.is_empty() {
+            write!(f, " when {}", values.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// What exploring a function found
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FunctionReport {
+    pub function: String,
+    /// Paths explored to their end
+    pub paths: usize,
+    pub counterexamples: Vec<Counterexample>,
+    /// Traps shown to be unreachable
+    pub proved: usize,
+    /// Traps the solver could not decide
+    pub undecided: usize,
+    /// Whether paths were cut by the bounds or unsupported constructs
+    pub truncated: bool,
+}
+
+impl FunctionReport {
+    /// Whether no trap is reachable on any path
+    pub fn is_verified(&self) -> bool {
+        self.counterexamples.is_empty() && self.undecided == 0 && !self.truncated
+    }
+}
+
+/// Explores the functions of a lowered program
+pub struct SymbolicExecutor<'a> {
+    functions: Vec<&'a Definition>,
+    by_name: HashMap<&'a str, &'a Definition>,
+    storage: HashMap<&'a str, &'a Type>,
+    invariants: Vec<&'a Expr>,
+    options: SymbolicOptions,
+    solver: Box<dyn Solver>,
+    run: Run,
+}
+
+impl<'a> SymbolicExecutor<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        let mut functions = Vec::new();
+        let mut storage = HashMap::new();
+        let mut invariants = Vec::new();
+        for definition in flatten(&program.definitions) {
+            match definition {
+                Definition::FunctionDef { .. } => functions.push(definition),
+                Definition::StorageDef {
+                    fields, attributes, ..
+                } => {
+                    storage.extend(fields.iter().map(|field| (field.name.as_str(), &field.ty)));
+                    invariants.extend(conditions(attributes, "invariant"));
+                }
+                _ => {}
+            }
+        }
+        let by_name = functions
+            .iter()
+            .map(|definition| (function_name(definition), *definition))
+            .collect();
+        SymbolicExecutor {
+            functions,
+            by_name,
+            storage,
+            invariants,
+            options: SymbolicOptions::default(),
+            solver: default_solver(),
+            run: Run::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: SymbolicOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_solver(mut self, solver: impl Solver + 'static) -> Self {
+        self.solver = Box::new(solver);
+        self
+    }
+
+    /// Explore the function `name`, if the program has it
+    pub fn verify_function(&mut self, name: &str) -> Option<FunctionReport> {
+        let function = *self.by_name.get(name)?;
+        Some(self.explore(function))
+    }
+
+    /// Explore every function of the program but its tests
+    pub fn verify_program(&mut self) -> Vec<FunctionReport> {
+        let functions: Vec<&'a Definition> = self
+            .functions
+            .iter()
+            .copied()
+            .filter(|function| match function {
+                Definition::FunctionDef { attributes, .. } => {
+                    !attributes.iter().any(|attribute| attribute.name == "test")
+                }
+                _ => false,
+            })
+            .collect();
+        functions
+            .into_iter()
+            .map(|function| self.explore(function))
+            .collect()
+    }
+
+    fn explore(&mut self, function: &'a Definition) -> FunctionReport {
+        let Definition::FunctionDef {
+            name,
+            params,
+            body,
+            checked,
+            attributes,
+            ..
+        } = function
+        else {
+            unreachable!("only functions are explored");
+        };
+        let mutable = FunctionAttributes::from_attributes(attributes, params)
+            .is_ok_and(|attributes| attributes.mutability == Mutability::Mutable);
+        let invariants = self.invariants.clone();
+
+        self.run = Run {
+            function: name.to_string(),
+            ..Run::default()
+        };
+        let mut state = State {
+            unchecked: *checked == Some(false),
+            ..State::default()
+        };
+        for param in params {
+            let kind = Kind::of(&param.ty);
+            let term = self.declare(&param.name, kind);
+            self.run.parameters.insert(param.name.to_string());
+            state.locals.insert(param.name, Value { term, kind });
+        }
+        let entry = state.locals.clone();
+
+        // The invariants hold between calls
+        let mut states = vec![state];
+        for condition in conditions(attributes, "requires").chain(invariants.iter().copied()) {
+            states = self.assume_condition(states, condition);
+        }
+        let mut outcomes = Vec::new();
+        for state in states {
+            outcomes.extend(self.block(state, &body.statements));
+        }
+        self.run.paths += outcomes.len();
+
+        self.run.entry = Some(entry);
+        for (mut state, flow) in outcomes {
+            if let Flow::Return(value) = flow {
+                state.locals.insert(RESULT.into(), value);
+            }
+            let mut states = vec![state];
+            for condition in conditions(attributes, "ensures") {
+                let message = format!(
+                    "{}: {} (line {})",
+                    POSTCONDITION_FAILED,
+                    name,
+                    condition.location().line
+                );
+                states =
+                    self.check_condition(states, condition, FailureKind::Postcondition, &message);
+            }
+            for condition in invariants.iter().filter(|_| mutable) {
+                let message = format!("{} (line {})", INVARIANT_FAILED, condition.location().line);
+                states = self.check_condition(states, condition, FailureKind::Invariant, &message);
+            }
+        }
+
+        let run = std::mem::take(&mut self.run);
+        let count = |status| run.checks.values().filter(|s| **s == status).count();
+        FunctionReport {
+            function: run.function.clone(),
+            paths: run.paths,
+            proved: count(Status::Proved),
+            undecided: count(Status::Undecided),
+            counterexamples: run.counterexamples,
+            truncated: run.truncated,
+        }
+    }
+
+    /// Add the input `name` to the constraints
+    fn declare(&mut self, name: &str, kind: Kind) -> Term {
+        if !self
+            .run
+            .variables
+            .iter()
+            .any(|variable| variable.name == name)
+        {
+            let (min, max) = kind.range();
+            self.run.variables.push(Variable::new(name, min, max));
+        }
+        Term::var(name)
+    }
+
+    /// A value nothing is known of but its kind
+    fn fresh(&mut self, origin: &str, kind: Kind) -> Value {
+        let name = format!("%{}.{}", origin, self.run.fresh);
+        self.run.fresh += 1;
+        Value {
+            term: self.declare(&name, kind),
+            kind,
+        }
+    }
+
+    /// Take one more branch, unless the paths are used up
+    fn fork(&mut self) -> bool {
+        if self.run.forks >= self.options.max_paths {
+            self.run.truncated = true;
+            return false;
+        }
+        self.run.forks += 1;
+        true
+    }
+
+    /// Look for a path where `failure` holds, then continue assuming it
+    /// does not. Returns whether the path can continue.
+    fn check(
+        &mut self,
+        state: &mut State,
+        kind: FailureKind,
+        failure: Term,
+        location: &Location,
+        message: &str,
+    ) -> bool {
+        let key = (location.clone(), kind);
+        let known = self.run.checks.get(&key).copied();
+        let status = if failure == Term::Const(0) {
+            Status::Proved
+        } else if known == Some(Status::Reached) {
+            Status::Reached
+        } else {
+            let mut constraints = state.constraints.clone();
+            constraints.extend(state.guards.iter().cloned());
+            constraints.push(failure.clone());
+            match self.solver.solve(&self.run.variables, &constraints) {
+                Solution::Sat(model) if satisfies(&model, &self.run.variables, &constraints) => {
+                    self.report(kind, location, message, &model, &constraints);
+                    Status::Reached
+                }
+                Solution::Unsat => Status::Proved,
+                _ => Status::Undecided,
+            }
+        };
+        let entry = self.run.checks.entry(key).or_insert(status);
+        *entry = (*entry).max(status);
+
+        let holds = match state.guards.iter().cloned().reduce(and) {
+            Some(guard) => Term::binary(BinaryOp::Or, !guard, !failure),
+            None => !failure,
+        };
+        state.assume(holds)
+    }
+
+    /// The paths of `states` where `condition` holds
+    fn assume_condition(&mut self, states: Vec<State>, condition: &'a Expr) -> Vec<State> {
+        let mut holding = Vec::new();
+        for state in states {
+            for (mut state, value) in self.eval(state, condition) {
+                if state.assume(value.truth()) {
+                    holding.push(state);
+                }
+            }
+        }
+        holding
+    }
+
+    /// Look for a path of `states` where `condition` does not hold, then
+    /// continue on those where it does
+    fn check_condition(
+        &mut self,
+        states: Vec<State>,
+        condition: &'a Expr,
+        kind: FailureKind,
+        message: &str,
+    ) -> Vec<State> {
+        let mut holding = Vec::new();
+        for state in states {
+            for (mut state, value) in self.eval(state, condition) {
+                if self.check(
+                    &mut state,
+                    kind,
+                    !value.truth(),
+                    condition.location(),
+                    message,
+                ) {
+                    holding.push(state);
+                }
+            }
+        }
+        holding
+    }
+
+    fn report(
+        &mut self,
+        kind: FailureKind,
+        location: &Location,
+        message: &str,
+        model: &Model,
+        constraints: &[Term],
+    ) {
+        let mut used = BTreeSet::new();
+        for constraint in constraints {
+            used.extend(constraint.variables());
+        }
+        let (inputs, environment) = model
+            .iter()
+            .filter(|(name, _)| self.run.parameters.contains(*name) || used.contains(name.as_str()))
+            .map(|(name, value)| (name.clone(), *value))
+            .partition(|(name, _)| self.run.parameters.contains(name));
+        self.run.counterexamples.push(Counterexample {
+            function: self.run.function.clone(),
+            kind,
+            location: location.clone(),
+            message: message.to_string(),
+            inputs,
+            environment,
+        });
+    }
+
+    fn block(&mut self, state: State, statements: &'a [Statement]) -> Vec<(State, Flow)> {
+        let mut live = vec![state];
+        let mut done = Vec::new();
+        for statement in statements {
+            let mut next = Vec::new();
+            for state in live {
+                for (state, flow) in self.statement(state, statement) {
+                    match flow {
+                        Flow::Next => next.push(state),
+                        flow => done.push((state, flow)),
+                    }
+                }
+            }
+            live = next;
+            if live.is_empty() {
+                break;
+            }
+        }
+        done.extend(live.into_iter().map(|state| (state, Flow::Next)));
+        done
+    }
+
+    fn statement(&mut self, state: State, statement: &'a Statement) -> Vec<(State, Flow)> {
+        match statement {
+            Statement::Assignment { pattern, value, .. } => {
+                let mut outcomes = Vec::new();
+                for (mut state, value) in self.eval(state, value) {
+                    self.bind(&mut state, pattern, value);
+                    outcomes.push((state, Flow::Next));
+                }
+                outcomes
+            }
+            Statement::Use { name, value, .. } => self
+                .eval(state, value)
+                .into_iter()
+                .map(|(mut state, value)| {
+                    state.locals.insert(*name, value);
+                    (state, Flow::Next)
+                })
+                .collect(),
+            Statement::InPlaceOp {
+                target,
+                operator,
+                value,
+                location,
+            } => {
+                let Some(operator) = in_place_operator(operator) else {
+                    self.run.truncated = true;
+                    return Vec::new();
+                };
+                let mut outcomes = Vec::new();
+                for (mut state, value) in self.eval(state, value) {
+                    let current = self.read(&mut state, *target);
+                    if let Some(result) =
+                        self.binary(&mut state, &operator, current, value, location)
+                    {
+                        self.assign(&mut state, *target, result);
+                        outcomes.push((state, Flow::Next));
+                    }
+                }
+                outcomes
+            }
+            Statement::Return { value, .. } => self
+                .eval(state, value)
+                .into_iter()
+                .map(|(state, value)| (state, Flow::Return(value)))
+                .collect(),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let mut outcomes = Vec::new();
+                for (state, condition) in self.eval(state, condition) {
+                    let holds = condition.truth();
+                    let mut then_state = state.clone();
+                    let mut else_state = state;
+                    let then_feasible = then_state.assume(holds.clone());
+                    let else_feasible = else_state.assume(!holds);
+                    if then_feasible && else_feasible && !self.fork() {
+                        continue;
+                    }
+                    if then_feasible {
+                        outcomes.extend(self.block(then_state, &then_branch.statements));
+                    }
+                    if else_feasible {
+                        outcomes.extend(self.block(else_state, &else_branch.statements));
+                    }
+                }
+                outcomes
+            }
+            Statement::While {
+                condition,
+                body,
+                bound,
+                location,
+            } => self.repeat(
+                state,
+                body,
+                *bound,
+                location,
+                |executor, state| {
+                    executor
+                        .eval(state, condition)
+                        .into_iter()
+                        .map(|(state, value)| (state, value.truth()))
+                        .collect()
+                },
+                |_, _| {},
+            ),
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                bound,
+                location,
+            } => {
+                let mut outcomes = Vec::new();
+                for (state, start) in self.eval(state, start) {
+                    for (mut state, end) in self.eval(state, end) {
+                        state.locals.insert(*variable, start.clone());
+                        outcomes.extend(self.repeat(
+                            state,
+                            body,
+                            *bound,
+                            location,
+                            |_, state| {
+                                let index = state.locals[variable].term.clone();
+                                let holds = Term::binary(BinaryOp::Lt, index, end.term.clone());
+                                vec![(state, holds)]
+                            },
+                            |_, state| {
+                                let index = state.locals.get_mut(variable).expect("loop variable");
+                                index.term =
+                                    Term::binary(BinaryOp::Add, index.term.clone(), Term::Const(1));
+                            },
+                        ));
+                    }
+                }
+                outcomes
+            }
+            Statement::Unchecked { body, .. } => {
+                let mut state = state;
+                let unchecked = std::mem::replace(&mut state.unchecked, true);
+                let mut outcomes = self.block(state, &body.statements);
+                for (state, _) in &mut outcomes {
+                    state.unchecked = unchecked;
+                }
+                outcomes
+            }
+            Statement::Expr { expr, .. } => self
+                .eval(state, expr)
+                .into_iter()
+                .map(|(state, _)| (state, Flow::Next))
+                .collect(),
+            Statement::Emit { args, .. } => self
+                .eval_all(state, args)
+                .into_iter()
+                .map(|(state, _)| (state, Flow::Next))
+                .collect(),
+            Statement::Revert {
+                kind,
+                condition,
+                reason,
+                location,
+            } => {
+                let Some(condition) = condition else {
+                    // The path ends here, rolled back
+                    self.run.paths += 1;
+                    return Vec::new();
+                };
+                let message = match reason {
+                    Some(Expr::Literal {
+                        kind: LiteralKind::String(message),
+                        ..
+                    }) => message.as_str(),
+                    _ => "assertion failed",
+                };
+                let mut outcomes = Vec::new();
+                for (mut state, condition) in self.eval(state, condition) {
+                    let holds = condition.truth();
+                    let feasible = match kind {
+                        RevertKind::Assert => self.check(
+                            &mut state,
+                            FailureKind::Assertion,
+                            !holds,
+                            location,
+                            message,
+                        ),
+                        _ => state.assume(holds),
+                    };
+                    if feasible {
+                        outcomes.push((state, Flow::Next));
+                    }
+                }
+                outcomes
+            }
+            Statement::Switch { .. }
+            | Statement::Match { .. }
+            | Statement::Fold { .. }
+            | Statement::Bend { .. }
+            | Statement::Open { .. }
+            | Statement::With { .. }
+            | Statement::LocalDef { .. }
+            | Statement::TryCatch { .. } => {
+                self.run.truncated = true;
+                Vec::new()
+            }
+        }
+    }
+
+    /// Unroll a loop whose `condition` is checked before every iteration
+    /// and `step` run after it
+    #[allow(clippy::too_many_arguments)]
+    fn repeat(
+        &mut self,
+        state: State,
+        body: &'a Block,
+        bound: Option<u32>,
+        location: &Location,
+        condition: impl Fn(&mut Self, State) -> Vec<(State, Term)>,
+        step: impl Fn(&mut Self, &mut State),
+    ) -> Vec<(State, Flow)> {
+        let mut live = vec![state];
+        let mut done = Vec::new();
+        for iteration in 0.. {
+            let mut next = Vec::new();
+            for state in live {
+                for (mut state, holds) in condition(self, state) {
+                    if Some(iteration) == bound {
+                        // Running another iteration panics
+                        if self.check(
+                            &mut state,
+                            FailureKind::LoopBound,
+                            holds,
+                            location,
+                            "loop bound exceeded",
+                        ) {
+                            done.push((state, Flow::Next));
+                        }
+                        continue;
+                    }
+                    let mut exit = state.clone();
+                    let exits = exit.assume(!holds.clone());
+                    let enters = state.assume(holds);
+                    if exits && enters && !self.fork() {
+                        continue;
+                    }
+                    if exits {
+                        done.push((exit, Flow::Next));
+                    }
+                    if !enters {
+                        continue;
+                    }
+                    if iteration >= self.options.loop_bound {
+                        self.run.truncated = true;
+                        continue;
+                    }
+                    for (mut state, flow) in self.block(state, &body.statements) {
+                        match flow {
+                            Flow::Next => {
+                                step(self, &mut state);
+                                next.push(state);
+                            }
+                            flow => done.push((state, flow)),
+                        }
+                    }
+                }
+            }
+            live = next;
+            if live.is_empty() {
+                break;
+            }
+        }
+        done
+    }
+
+    fn bind(&mut self, state: &mut State, pattern: &Pattern, value: Value) {
+        match pattern {
+            Pattern::Variable { name, .. } => self.assign(state, *name, value),
+            Pattern::Tuple { elements, .. } => {
+                for element in elements {
+                    let value = self.fresh("element", Kind::Opaque);
+                    self.bind(state, element, value);
+                }
+            }
+            // Writes to maps and fields are not modelled
+            _ => {}
+        }
+    }
+
+    fn assign(&mut self, state: &mut State, name: Symbol, value: Value) {
+        match self.storage.get(name.as_str()) {
+            Some(ty) if !state.locals.contains_key(&name) => {
+                let kind = Kind::of(ty);
+                state.storage.insert(
+                    name,
+                    Value {
+                        term: value.term,
+                        kind,
+                    },
+                );
+            }
+            _ => {
+                state.locals.insert(name, value);
+            }
+        }
+    }
+
+    fn read(&mut self, state: &mut State, name: Symbol) -> Value {
+        if let Some(value) = state.locals.get(&name).or_else(|| state.storage.get(&name)) {
+            return value.clone();
+        }
+        let value = match self.storage.get(name.as_str()) {
+            Some(ty) => {
+                let kind = Kind::of(ty);
+                let term = self.declare(&format!("%storage.{}", name), kind);
+                Value { term, kind }
+            }
+            None => self.fresh(&name, Kind::Opaque),
+        };
+        if self.storage.contains_key(name.as_str()) {
+            state.storage.insert(name, value.clone());
+        }
+        value
+    }
+
+    fn eval(&mut self, state: State, expr: &'a Expr) -> Vec<(State, Value)> {
+        let mut state = state;
+        match expr {
+            Expr::Variable { name, .. } => {
+                let value = self.read(&mut state, *name);
+                vec![(state, value)]
+            }
+            Expr::Literal { kind, .. } => {
+                let value = match kind {
+                    LiteralKind::Uint(value) => Value::constant(*value as i64, Kind::U24),
+                    LiteralKind::Int(value) => Value::constant(*value as i64, Kind::I24),
+                    LiteralKind::Bool(value) => Value::constant(*value as i64, Kind::Bool),
+                    LiteralKind::Char(value) => Value::constant(*value as i64, Kind::U24),
+                    _ => self.fresh("literal", Kind::Opaque),
+                };
+                vec![(state, value)]
+            }
+            Expr::BinaryOp {
+                left,
+                operator,
+                right,
+                location,
+            } => {
+                let mut results = Vec::new();
+                for (state, left) in self.eval(state, left) {
+                    if matches!(operator, BinaryOperator::And | BinaryOperator::Or) {
+                        // The right operand only runs when the left one does
+                        // not decide the result
+                        let mut state = state;
+                        let left = left.truth();
+                        let (guard, op) = match operator {
+                            BinaryOperator::And => (left.clone(), BinaryOp::And),
+                            _ => (!left.clone(), BinaryOp::Or),
+                        };
+                        state.guards.push(guard);
+                        for (mut state, right) in self.eval(state, right) {
+                            state.guards.pop();
+                            let term = Term::binary(op, left.clone(), right.truth());
+                            results.push((
+                                state,
+                                Value {
+                                    term,
+                                    kind: Kind::Bool,
+                                },
+                            ));
+                        }
+                        continue;
+                    }
+                    for (mut state, right) in self.eval(state, right) {
+                        if let Some(value) =
+                            self.binary(&mut state, operator, left.clone(), right, location)
+                        {
+                            results.push((state, value));
+                        }
+                    }
+                }
+                results
+            }
+            Expr::UnaryOp {
+                operator: UnaryOperator::Not,
+                operand,
+                ..
+            } => self
+                .eval(state, operand)
+                .into_iter()
+                .map(|(state, value)| {
+                    let term = !value.truth();
+                    (
+                        state,
+                        Value {
+                            term,
+                            kind: Kind::Bool,
+                        },
+                    )
+                })
+                .collect(),
+            Expr::FunctionCall { function, args, .. } => self.eval_call(state, function, args),
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let mut results = Vec::new();
+                for (mut state, condition) in self.eval(state, condition) {
+                    let holds = condition.truth();
+                    state.guards.push(holds.clone());
+                    for (mut state, then_value) in self.eval(state, then_branch) {
+                        state.guards.pop();
+                        state.guards.push(!holds.clone());
+                        for (mut state, else_value) in self.eval(state, else_branch) {
+                            state.guards.pop();
+                            // else + holds * (then - else), `holds` being 0 or 1
+                            let difference = Term::binary(
+                                BinaryOp::Sub,
+                                then_value.term.clone(),
+                                else_value.term.clone(),
+                            );
+                            let term = Term::binary(
+                                BinaryOp::Add,
+                                else_value.term,
+                                Term::binary(BinaryOp::Mul, holds.clone(), difference),
+                            );
+                            results.push((
+                                state,
+                                Value {
+                                    term,
+                                    kind: then_value.kind,
+                                },
+                            ));
+                        }
+                    }
+                }
+                results
+            }
+            Expr::Block { block, .. } => self
+                .block(state, &block.statements)
+                .into_iter()
+                .map(|(state, flow)| match flow {
+                    Flow::Return(value) => (state, value),
+                    Flow::Next => (state, Value::unit()),
+                })
+                .collect(),
+            _ => {
+                let value = self.fresh("value", Kind::Opaque);
+                vec![(state, value)]
+            }
+        }
+    }
+
+    /// Evaluate `exprs` in order
+    fn eval_all(&mut self, state: State, exprs: &'a [Expr]) -> Vec<(State, Vec<Value>)> {
+        let mut results = vec![(state, Vec::new())];
+        for expr in exprs {
+            let mut next = Vec::new();
+            for (state, values) in results {
+                for (state, value) in self.eval(state, expr) {
+                    let mut values = values.clone();
+                    values.push(value);
+                    next.push((state, values));
+                }
+            }
+            results = next;
+        }
+        results
+    }
+
+    fn eval_call(
+        &mut self,
+        state: State,
+        function: &'a Expr,
+        args: &'a [Expr],
+    ) -> Vec<(State, Value)> {
+        if let (Expr::Variable { name, .. }, [value]) = (function, args) {
+            if name == OLD && self.run.entry.is_some() && !self.by_name.contains_key(OLD) {
+                return self.eval_old(state, value);
+            }
+        }
+        let callee = match function {
+            Expr::Variable { name, .. } => self.by_name.get(name.as_str()).copied(),
+            _ => None,
+        };
+        let result_kind = match function.as_method_target() {
+            Some((field, method)) => match self.storage.get(field.as_str()) {
+                Some(_) if method == "len" => Kind::U24,
+                Some(ty) if method == "get" => Kind::element_of(ty),
+                _ => Kind::Opaque,
+            },
+            None => Kind::Opaque,
+        };
+        let origin = match function {
+            Expr::Variable { name, .. } => format!("call.{}", name),
+            _ => "call".to_string(),
+        };
+
+        let mut results = Vec::new();
+        for (state, values) in self.eval_all(state, args) {
+            match callee {
+                Some(callee) => results.extend(self.inline(state, callee, values)),
+                None => {
+                    let value = self.fresh(&origin, result_kind);
+                    results.push((state, value));
+                }
+            }
+        }
+        results
+    }
+
+    /// Evaluate `value` as on entry to the function, in a postcondition
+    fn eval_old(&mut self, state: State, value: &'a Expr) -> Vec<(State, Value)> {
+        let mut entry = state.clone();
+        entry.locals = self.run.entry.clone().unwrap_or_default();
+        // Storage read afresh is the storage on entry
+        entry.storage.clear();
+        self.eval(entry, value)
+            .into_iter()
+            .map(|(mut after, value)| {
+                after.locals = state.locals.clone();
+                after.storage = state.storage.clone();
+                (after, value)
+            })
+            .collect()
+    }
+
+    /// Run `function` on `args` within the path of the caller
+    fn inline(
+        &mut self,
+        mut state: State,
+        function: &'a Definition,
+        args: Vec<Value>,
+    ) -> Vec<(State, Value)> {
+        let Definition::FunctionDef {
+            name,
+            params,
+            return_type,
+            body,
+            checked,
+            ..
+        } = function
+        else {
+            unreachable!("only functions are called");
+        };
+        if state.depth >= self.options.call_depth {
+            let kind = return_type.as_ref().map_or(Kind::Opaque, Kind::of);
+            let value = self.fresh(&format!("call.{}", name), kind);
+            return vec![(state, value)];
+        }
+
+        let locals = std::mem::take(&mut state.locals);
+        let unchecked = std::mem::replace(&mut state.unchecked, *checked == Some(false));
+        state.depth += 1;
+        for (param, value) in params.iter().zip(args) {
+            state.locals.insert(param.name, value);
+        }
+        self.block(state, &body.statements)
+            .into_iter()
+            .map(|(mut state, flow)| {
+                state.locals = locals.clone();
+                state.unchecked = unchecked;
+                state.depth -= 1;
+                let value = match flow {
+                    Flow::Return(value) => value,
+                    Flow::Next => Value::unit(),
+                };
+                (state, value)
+            })
+            .collect()
+    }
+
+    /// `left operator right`, checking for the traps of checked arithmetic.
+    /// `None` when the operation always traps.
+    fn binary(
+        &mut self,
+        state: &mut State,
+        operator: &BinaryOperator,
+        left: Value,
+        right: Value,
+        location: &Location,
+    ) -> Option<Value> {
+        let comparison = match operator {
+            BinaryOperator::Equal => Some((BinaryOp::Eq, false)),
+            BinaryOperator::NotEqual => Some((BinaryOp::Ne, false)),
+            BinaryOperator::Less => Some((BinaryOp::Lt, false)),
+            BinaryOperator::LessEqual => Some((BinaryOp::Le, false)),
+            BinaryOperator::Greater => Some((BinaryOp::Lt, true)),
+            BinaryOperator::GreaterEqual => Some((BinaryOp::Le, true)),
+            _ => None,
+        };
+        if let Some((op, swapped)) = comparison {
+            let (left, right) = match swapped {
+                true => (right.term, left.term),
+                false => (left.term, right.term),
+            };
+            return Some(Value {
+                term: Term::binary(op, left, right),
+                kind: Kind::Bool,
+            });
+        }
+
+        let kind = left.kind.combine(right.kind);
+        if kind == Kind::Opaque {
+            return Some(self.fresh("value", Kind::Opaque));
+        }
+        let op = match operator {
+            BinaryOperator::Add => BinaryOp::Add,
+            BinaryOperator::Sub => BinaryOp::Sub,
+            BinaryOperator::Mul => BinaryOp::Mul,
+            BinaryOperator::Div => BinaryOp::Div,
+            BinaryOperator::Mod => BinaryOp::Rem,
+            BinaryOperator::BitAnd => BinaryOp::BitAnd,
+            BinaryOperator::BitOr => BinaryOp::BitOr,
+            BinaryOperator::BitXor => BinaryOp::BitXor,
+            BinaryOperator::BitShiftRight => BinaryOp::Shr,
+            // Shifted out bits and powers are not modelled
+            _ => return Some(self.fresh("value", kind)),
+        };
+        let arithmetic = matches!(
+            op,
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem
+        );
+        if !arithmetic {
+            return Some(Value {
+                term: Term::binary(op, left.term, right.term),
+                kind,
+            });
+        }
+        if state.unchecked {
+            // Wrapping results are not modelled
+            return Some(self.fresh("value", kind));
+        }
+
+        if matches!(op, BinaryOp::Div | BinaryOp::Rem) {
+            let zero = Term::binary(BinaryOp::Eq, right.term.clone(), Term::Const(0));
+            if !self.check(
+                state,
+                FailureKind::DivisionByZero,
+                zero,
+                location,
+                "division by zero",
+            ) {
+                return None;
+            }
+        }
+        let result = Term::binary(op, left.term, right.term);
+        // A quotient of u24 and a remainder always fit
+        let fits = op == BinaryOp::Rem || (op == BinaryOp::Div && kind == Kind::U24);
+        if !fits {
+            let (min, max) = kind.range();
+            let overflow = Term::binary(
+                BinaryOp::Or,
+                Term::binary(BinaryOp::Lt, result.clone(), Term::Const(min)),
+                Term::binary(BinaryOp::Lt, Term::Const(max), result.clone()),
+            );
+            let message = format!("{} overflows {}", operator_name(operator), kind);
+            if !self.check(state, FailureKind::Overflow, overflow, location, &message) {
+                return None;
+            }
+        }
+        Some(Value { term: result, kind })
+    }
+}
+
+/// What is tracked of a function while it is explored
+#[derive(Default)]
+struct Run {
+    function: String,
+    parameters: BTreeSet<String>,
+    variables: Vec<Variable>,
+    fresh: usize,
+    forks: usize,
+    paths: usize,
+    truncated: bool,
+    checks: HashMap<(Location, FailureKind), Status>,
+    counterexamples: Vec<Counterexample>,
+    /// Arguments on entry, while postconditions are checked
+    entry: Option<HashMap<Symbol, Value>>,
+}
+
+/// What is known of a trap, from least to most informative
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Status {
+    Proved,
+    Undecided,
+    Reached,
+}
+
+/// One path through a function
+#[derive(Debug, Clone, Default)]
+struct State {
+    locals: HashMap<Symbol, Value>,
+    storage: HashMap<Symbol, Value>,
+    constraints: Vec<Term>,
+    /// Conditions under which the expression being evaluated runs
+    guards: Vec<Term>,
+    unchecked: bool,
+    depth: usize,
+}
+
+impl State {
+    /// Constrain the path by `condition`, returning whether it can still
+    /// hold
+    fn assume(&mut self, condition: Term) -> bool {
+        match condition {
+            Term::Const(0) => false,
+            Term::Const(_) => true,
+            condition => {
+                self.constraints.push(condition);
+                true
+            }
+        }
+    }
+}
+
+enum Flow {
+    Next,
+    Return(Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Value {
+    term: Term,
+    kind: Kind,
+}
+
+impl Value {
+    fn constant(value: i64, kind: Kind) -> Self {
+        Value {
+            term: Term::Const(value),
+            kind,
+        }
+    }
+
+    fn unit() -> Self {
+        Value::constant(0, Kind::Opaque)
+    }
+
+    /// 1 when the value is not 0, else 0
+    fn truth(&self) -> Term {
+        match self.kind {
+            Kind::Bool => self.term.clone(),
+            _ => Term::binary(BinaryOp::Ne, self.term.clone(), Term::Const(0)),
+        }
+    }
+}
+
+/// What the executor knows of the type of a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    U24,
+    I24,
+    Bool,
+    /// Addresses, strings and the like, only compared
+    Opaque,
+}
+
+impl Kind {
+    fn of(ty: &Type) -> Self {
+        match ty {
+            Type::U24 { .. } => Kind::U24,
+            Type::I24 { .. } => Kind::I24,
+            Type::Named { name, params, .. } => match name.as_str() {
+                "u24" => Kind::U24,
+                "i24" => Kind::I24,
+                "Bool" | "bool" => Kind::Bool,
+                "StorageValue" => params.first().map_or(Kind::Opaque, Kind::of),
+                _ => Kind::Opaque,
+            },
+            _ => Kind::Opaque,
+        }
+    }
+
+    /// The kind of what `get` returns from a storage collection
+    fn element_of(ty: &Type) -> Self {
+        match ty {
+            Type::Named { params, .. } => params.last().map_or(Kind::Opaque, Kind::of),
+            _ => Kind::Opaque,
+        }
+    }
+
+    /// The kind of the result of arithmetic on the two
+    fn combine(self, other: Kind) -> Self {
+        match (self, other) {
+            (Kind::Opaque, _) | (_, Kind::Opaque) => Kind::Opaque,
+            (Kind::I24, _) | (_, Kind::I24) => Kind::I24,
+            _ => Kind::U24,
+        }
+    }
+
+    fn range(self) -> (i64, i64) {
+        match self {
+            Kind::U24 => (0, (1 << 24) - 1),
+            Kind::I24 => (-(1 << 23), (1 << 23) - 1),
+            Kind::Bool => (0, 1),
+            Kind::Opaque => (0, u32::MAX as i64),
+        }
+    }
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Kind::U24 => "u24",
+            Kind::I24 => "i24",
+            Kind::Bool => "Bool",
+            Kind::Opaque => "_",
+        })
+    }
+}
+
+fn and(left: Term, right: Term) -> Term {
+    Term::binary(BinaryOp::And, left, right)
+}
+
+fn in_place_operator(operator: &InPlaceOperator) -> Option<BinaryOperator> {
+    Some(match operator {
+        InPlaceOperator::Add => BinaryOperator::Add,
+        InPlaceOperator::Sub => BinaryOperator::Sub,
+        InPlaceOperator::Mul => BinaryOperator::Mul,
+        InPlaceOperator::Div => BinaryOperator::Div,
+        InPlaceOperator::Mod => BinaryOperator::Mod,
+        InPlaceOperator::BitAnd => BinaryOperator::BitAnd,
+        InPlaceOperator::BitOr => BinaryOperator::BitOr,
+        InPlaceOperator::BitXor => BinaryOperator::BitXor,
+        InPlaceOperator::Map => return None,
+    })
+}
+
+fn operator_name(operator: &BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "addition",
+        BinaryOperator::Sub => "subtraction",
+        BinaryOperator::Mul => "multiplication",
+        BinaryOperator::Div => "division",
+        _ => "operation",
+    }
+}
+
+/// The conditions of the `name` attributes, e.g. `requires`
+fn conditions<'a>(attributes: &'a [Attribute], name: &'a str) -> impl Iterator<Item = &'a Expr> {
+    attributes
+        .iter()
+        .filter(move |attribute| attribute.name == name)
+        .flat_map(|attribute| &attribute.args)
+}
+
+fn function_name(definition: &Definition) -> &str {
+    match definition {
+        Definition::FunctionDef { name, .. } => name,
+        _ => "",
+    }
+}
+
+/// The definitions of the program and of its modules
+fn flatten(definitions: &[Definition]) -> Vec<&Definition> {
+    let mut flat = Vec::new();
+    for definition in definitions {
+        match definition {
+            Definition::Module { definitions, .. } => flat.extend(flatten(definitions)),
+            definition => flat.push(definition),
+        }
+    }
+    flat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::symbolic::solver::SearchSolver;
+
+    fn verify(source: &str, function: &str) -> FunctionReport {
+        let program = crate::parse_source(source).expect("parses");
+        let program = crate::compiler::lowering::lower_program(program);
+        SymbolicExecutor::new(&program)
+            .with_solver(SearchSolver::default())
+            .verify_function(function)
+            .expect("function exists")
+    }
+
+    #[test]
+    fn test_reachable_assert() {
+        let report = verify(
+            "fn check_price(price: u24, quantity: u24) -> u24 {\n    total = price * quantity;\n    if quantity > 10 {\n        assert(total != 999, \"unlucky total\");\n    } else {\n        total = total + 1;\n    }\n    return total;\n}\n",
+            "check_price",
+        );
+        let failures: Vec<&Counterexample> = report
+            .counterexamples
+            .iter()
+            .filter(|counterexample| counterexample.kind == FailureKind::Assertion)
+            .collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].message, "unlucky total");
+        assert_eq!(failures[0].location.line, 4);
+        let inputs = &failures[0].inputs;
+        assert!(inputs["quantity"] > 10);
+        assert_eq!(inputs["price"] * inputs["quantity"], 999);
+        assert_eq!(report.paths, 2);
+    }
+
+    #[test]
+    fn test_overflow_and_division_by_zero() {
+        let report = verify(
+            "fn split(amount: u24, parts: u24) -> u24 {\n    share = amount / parts;\n    return share + amount;\n}\n",
+            "split",
+        );
+        let kinds: Vec<FailureKind> = report
+            .counterexamples
+            .iter()
+            .map(|counterexample| counterexample.kind)
+            .collect();
+        assert_eq!(kinds, [FailureKind::DivisionByZero, FailureKind::Overflow]);
+        assert_eq!(report.counterexamples[0].inputs["parts"], 0);
+        let overflow = &report.counterexamples[1].inputs;
+        assert!(overflow["amount"] / overflow["parts"] + overflow["amount"] >= 1 << 24);
+        assert!(!report.is_verified());
+    }
+
+    #[test]
+    fn test_require_prevents_failure() {
+        let report = verify(
+            "fn half(x: u24) -> u24 {\n    require(x < 100, \"too large\");\n    y = x + x;\n    assert(y != 300);\n    return y / 2;\n}\n",
+            "half",
+        );
+        assert!(report.counterexamples.is_empty());
+        // Only the division by 2 is constant; search cannot rule out the rest
+        assert_eq!(report.proved, 1);
+        assert_eq!(report.undecided, 2);
+        assert!(!report.truncated);
+    }
+
+    #[test]
+    fn test_loops_and_calls() {
+        let report = verify(
+            "fn step(i: u24) -> u24 {\n    return i - 3;\n}\n\nfn walk(n: u24) -> u24 {\n    i = 0;\n    while i < n bound 4 {\n        i = i + 1;\n    }\n    return step(i);\n}\n",
+            "walk",
+        );
+        let kinds: BTreeSet<FailureKind> = report
+            .counterexamples
+            .iter()
+            .map(|counterexample| counterexample.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            BTreeSet::from([FailureKind::Overflow, FailureKind::LoopBound])
+        );
+        let bound = report
+            .counterexamples
+            .iter()
+            .find(|counterexample| counterexample.kind == FailureKind::LoopBound)
+            .unwrap();
+        assert!(bound.inputs["n"] > 4);
+        assert!(!report.truncated);
+    }
+
+    #[test]
+    fn test_specifications() {
+        let source = "#[invariant(total <= 1000)]\nstorage {\n    total: u24,\n}\n\n#[requires(amount <= 10)]\n#[ensures(result == old(total) + amount)]\nfn deposit(amount: u24) -> u24 {\n    require(total + amount <= 1000);\n    total = total + amount;\n    return total;\n}\n\n#[ensures(result > old(total))]\nfn withdraw(amount: u24) -> u24 {\n    require(amount <= total);\n    total = total - amount;\n    return total;\n}\n\nfn bump() -> u24 {\n    total = total + 1;\n    return total;\n}\n";
+
+        // The precondition and invariant rule out overflows, which search
+        // cannot prove but does not reach either
+        let deposit = verify(source, "deposit");
+        assert!(deposit.counterexamples.is_empty());
+
+        let withdraw = verify(source, "withdraw");
+        assert_eq!(withdraw.counterexamples.len(), 1);
+        let broken = &withdraw.counterexamples[0];
+        assert_eq!(broken.kind, FailureKind::Postcondition);
+        assert_eq!(broken.message, "postcondition failed: withdraw (line 14)");
+
+        let bump = verify(source, "bump");
+        assert_eq!(bump.counterexamples.len(), 1);
+        let broken = &bump.counterexamples[0];
+        assert_eq!(broken.kind, FailureKind::Invariant);
+        assert_eq!(broken.environment["%storage.total"], 1000);
+    }
+}
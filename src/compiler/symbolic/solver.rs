@@ -0,0 +1,629 @@
+//! Constraints and the solvers deciding them
+//!
+//! The symbolic executor describes paths as [`Term`]s over the integer
+//! inputs of a function, each a [`Variable`] with its range, and asks a
+//! [`Solver`] for a model satisfying them all. Terms evaluate like 64-bit
+//! integers, which holds every word the executor models without wrapping.
+//!
+//! [`SearchSolver`] tries values derived from the bounds of the variables
+//! and the constants of the constraints, then pseudo-random ones. It finds
+//! models but cannot show there is none, answering [`Solution::Unknown`].
+//! With the `smt` feature, `SmtSolver` hands the constraints as 64-bit
+//! vectors to an SMT solver reading SMT-LIB 2, which can.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Values of the variables of a model, by name
+pub type Model = BTreeMap<String, i64>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    /// 1 when the operand is 0, else 0
+    Not,
+    Neg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    /// Truncating division
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    /// Arithmetic shift right
+    Shr,
+}
+
+/// An integer expression over variables; conditions are 0 or 1
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Term {
+    Const(i64),
+    Var(String),
+    Unary(UnaryOp, Box<Term>),
+    Binary(BinaryOp, Box<Term>, Box<Term>),
+}
+
+impl Term {
+    pub fn var(name: &str) -> Self {
+        Term::Var(name.to_string())
+    }
+
+    /// `op` applied to `operand`, folded when it is constant
+    pub fn unary(op: UnaryOp, operand: Term) -> Self {
+        match operand {
+            Term::Const(value) => Term::Const(apply_unary(op, value)),
+            operand => Term::Unary(op, Box::new(operand)),
+        }
+    }
+
+    /// `op` applied to `left` and `right`, folded when both are constant
+    pub fn binary(op: BinaryOp, left: Term, right: Term) -> Self {
+        if let (Term::Const(left), Term::Const(right)) = (&left, &right) {
+            if let Some(value) = apply_binary(op, *left, *right) {
+                return Term::Const(value);
+            }
+        }
+        Term::Binary(op, Box::new(left), Box::new(right))
+    }
+
+    /// The value of the term under `model`, `None` when a variable has no
+    /// value or it divides by zero
+    pub fn eval(&self, model: &Model) -> Option<i64> {
+        match self {
+            Term::Const(value) => Some(*value),
+            Term::Var(name) => model.get(name).copied(),
+            Term::Unary(op, operand) => Some(apply_unary(*op, operand.eval(model)?)),
+            Term::Binary(op, left, right) => {
+                apply_binary(*op, left.eval(model)?, right.eval(model)?)
+            }
+        }
+    }
+
+    fn constants(&self, constants: &mut BTreeSet<i64>) {
+        match self {
+            Term::Const(value) => {
+                constants.insert(*value);
+            }
+            Term::Var(_) => {}
+            Term::Unary(_, operand) => operand.constants(constants),
+            Term::Binary(_, left, right) => {
+                left.constants(constants);
+                right.constants(constants);
+            }
+        }
+    }
+
+    /// The names of the variables the term uses
+    pub fn variables(&self) -> BTreeSet<&str> {
+        let mut variables = BTreeSet::new();
+        self.collect_variables(&mut variables);
+        variables
+    }
+
+    fn collect_variables<'a>(&'a self, variables: &mut BTreeSet<&'a str>) {
+        match self {
+            Term::Const(_) => {}
+            Term::Var(name) => {
+                variables.insert(name);
+            }
+            Term::Unary(_, operand) => operand.collect_variables(variables),
+            Term::Binary(_, left, right) => {
+                left.collect_variables(variables);
+                right.collect_variables(variables);
+            }
+        }
+    }
+}
+
+impl std::ops::Not for Term {
+    type Output = Term;
+
+    fn not(self) -> Term {
+        Term::unary(UnaryOp::Not, self)
+    }
+}
+
+fn apply_unary(op: UnaryOp, value: i64) -> i64 {
+    match op {
+        UnaryOp::Not => (value == 0) as i64,
+        UnaryOp::Neg => value.wrapping_neg(),
+    }
+}
+
+fn apply_binary(op: BinaryOp, left: i64, right: i64) -> Option<i64> {
+    Some(match op {
+        BinaryOp::Add => left.wrapping_add(right),
+        BinaryOp::Sub => left.wrapping_sub(right),
+        BinaryOp::Mul => left.wrapping_mul(right),
+        BinaryOp::Div => left.checked_div(right)?,
+        BinaryOp::Rem => left.checked_rem(right)?,
+        BinaryOp::Eq => (left == right) as i64,
+        BinaryOp::Ne => (left != right) as i64,
+        BinaryOp::Lt => (left < right) as i64,
+        BinaryOp::Le => (left <= right) as i64,
+        BinaryOp::And => (left != 0 && right != 0) as i64,
+        BinaryOp::Or => (left != 0 || right != 0) as i64,
+        BinaryOp::BitAnd => left & right,
+        BinaryOp::BitOr => left | right,
+        BinaryOp::BitXor => left ^ right,
+        BinaryOp::Shl => left.wrapping_shl((right & 63) as u32),
+        BinaryOp::Shr => left.wrapping_shr((right & 63) as u32),
+    })
+}
+
+/// An input of the constraints with the values it can take
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub min: i64,
+    pub max: i64,
+}
+
+impl Variable {
+    pub fn new(name: &str, min: i64, max: i64) -> Self {
+        Variable {
+            name: name.to_string(),
+            min,
+            max,
+        }
+    }
+
+    /// The value closest to 0 the variable can take
+    fn default_value(&self) -> i64 {
+        0.clamp(self.min, self.max)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Solution {
+    /// Values of every variable satisfying the constraints
+    Sat(Model),
+    /// No values satisfy the constraints
+    Unsat,
+    Unknown,
+}
+
+/// Decides whether constraints, each a term that must not be 0, can hold
+pub trait Solver {
+    fn solve(&mut self, variables: &[Variable], constraints: &[Term]) -> Solution;
+}
+
+/// Whether `model` gives every variable a value in its range satisfying
+/// every constraint
+pub fn satisfies(model: &Model, variables: &[Variable], constraints: &[Term]) -> bool {
+    variables.iter().all(|variable| {
+        model
+            .get(&variable.name)
+            .is_some_and(|value| (variable.min..=variable.max).contains(value))
+    }) && constraints
+        .iter()
+        .all(|constraint| constraint.eval(model).is_some_and(|value| value != 0))
+}
+
+/// Constant constraints decide on their own: `Some(false)` when one is 0
+fn trivial(constraints: &[Term]) -> Option<bool> {
+    let mut all_constant = true;
+    for constraint in constraints {
+        match constraint {
+            Term::Const(0) => return Some(false),
+            Term::Const(_) => {}
+            _ => all_constant = false,
+        }
+    }
+    all_constant.then_some(true)
+}
+
+/// Looks for a model among values likely to matter
+#[derive(Debug, Clone)]
+pub struct SearchSolver {
+    /// Number of assignments tried before giving up
+    pub budget: usize,
+    seed: u64,
+}
+
+impl Default for SearchSolver {
+    fn default() -> Self {
+        SearchSolver {
+            budget: 20_000,
+            seed: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+}
+
+/// Most values tried for one variable before sampling
+const MAX_CANDIDATES: usize = 48;
+
+impl SearchSolver {
+    /// Values worth trying for `variable`: its bounds, small numbers and
+    /// the constants of the constraints, their neighbours and quotients
+    fn candidates(variable: &Variable, constants: &BTreeSet<i64>) -> Vec<i64> {
+        let mut values = vec![
+            variable.default_value(),
+            variable.min,
+            variable.max,
+            1,
+            -1,
+            2,
+            variable.min.saturating_add(1),
+            variable.max.saturating_sub(1),
+        ];
+        for &constant in constants {
+            values.extend([
+                constant,
+                constant.saturating_add(1),
+                constant.saturating_sub(1),
+            ]);
+        }
+        for &dividend in constants {
+            for &divisor in constants {
+                if divisor > 1 {
+                    let quotient = dividend / divisor;
+                    values.extend([quotient, quotient.saturating_add(1)]);
+                }
+            }
+        }
+
+        let mut seen = BTreeSet::new();
+        values
+            .into_iter()
+            .filter(|value| (variable.min..=variable.max).contains(value))
+            .filter(|value| seen.insert(*value))
+            .take(MAX_CANDIDATES)
+            .collect()
+    }
+
+    fn next_random(&mut self) -> u64 {
+        // xorshift64
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+}
+
+impl Solver for SearchSolver {
+    fn solve(&mut self, variables: &[Variable], constraints: &[Term]) -> Solution {
+        match trivial(constraints) {
+            Some(false) => return Solution::Unsat,
+            Some(true) => {
+                let model = variables
+                    .iter()
+                    .map(|variable| (variable.name.clone(), variable.default_value()))
+                    .collect();
+                return Solution::Sat(model);
+            }
+            None => {}
+        }
+
+        let mut constants = BTreeSet::new();
+        let mut used = BTreeSet::new();
+        for constraint in constraints {
+            constraint.constants(&mut constants);
+            used.extend(constraint.variables());
+        }
+        let mut model: Model = variables
+            .iter()
+            .map(|variable| (variable.name.clone(), variable.default_value()))
+            .collect();
+        let free: Vec<&Variable> = variables
+            .iter()
+            .filter(|variable| used.contains(variable.name.as_str()))
+            .collect();
+        let candidates: Vec<Vec<i64>> = free
+            .iter()
+            .map(|variable| Self::candidates(variable, &constants))
+            .collect();
+
+        // Every combination of candidates, in order, then random values
+        let mut indices = vec![0; free.len()];
+        let mut tried = 0;
+        loop {
+            for ((variable, values), &index) in free.iter().zip(&candidates).zip(&indices) {
+                model.insert(variable.name.clone(), values[index]);
+            }
+            if satisfies(&model, variables, constraints) {
+                return Solution::Sat(model);
+            }
+            tried += 1;
+            if tried >= self.budget {
+                return Solution::Unknown;
+            }
+            let mut position = 0;
+            while position < indices.len() {
+                indices[position] += 1;
+                if indices[position] < candidates[position].len() {
+                    break;
+                }
+                indices[position] = 0;
+                position += 1;
+            }
+            if position == indices.len() {
+                break;
+            }
+        }
+        while tried < self.budget {
+            for variable in &free {
+                let span = (variable.max - variable.min) as u64 + 1;
+                let value = variable.min + (self.next_random() % span) as i64;
+                model.insert(variable.name.clone(), value);
+            }
+            if satisfies(&model, variables, constraints) {
+                return Solution::Sat(model);
+            }
+            tried += 1;
+        }
+        Solution::Unknown
+    }
+}
+
+/// The solver used when none is given: an SMT solver with the `smt`
+/// feature, falling back to search when it cannot be run
+pub fn default_solver() -> Box<dyn Solver> {
+    #[cfg(feature = "smt")]
+    {
+        Box::new(SmtSolver::from_env())
+    }
+    #[cfg(not(feature = "smt"))]
+    {
+        Box::new(SearchSolver::default())
+    }
+}
+
+/// An SMT solver run as a process, reading SMT-LIB 2 on stdin
+///
+/// The command is taken from `BEND_SMT_SOLVER`, `z3 -in` by default, and
+/// `cvc5 --lang smt2` works as well. Constraints are 64-bit vectors, so
+/// they mean what they evaluate to.
+#[cfg(feature = "smt")]
+#[derive(Debug, Clone)]
+pub struct SmtSolver {
+    pub command: String,
+    pub args: Vec<String>,
+    /// Used when the solver cannot be run
+    fallback: SearchSolver,
+}
+
+#[cfg(feature = "smt")]
+impl SmtSolver {
+    pub fn new(command: &str, args: &[&str]) -> Self {
+        SmtSolver {
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            fallback: SearchSolver::default(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let command = std::env::var("BEND_SMT_SOLVER").unwrap_or_else(|_| "z3 -in".to_string());
+        let mut words = command.split_whitespace();
+        let program = words.next().unwrap_or("z3");
+        let args: Vec<&str> = words.collect();
+        Self::new(program, &args)
+    }
+
+    /// The SMT-LIB 2 script asking for a model of `constraints`
+    pub fn script(variables: &[Variable], constraints: &[Term]) -> String {
+        let mut script = String::from("(set-logic QF_BV)\n");
+        for variable in variables {
+            let name = quote(&variable.name);
+            script.push_str(&format!("(declare-const {} (_ BitVec 64))\n", name));
+            script.push_str(&format!(
+                "(assert (and (bvsle {} {}) (bvsle {} {})))\n",
+                bitvector(variable.min),
+                name,
+                name,
+                bitvector(variable.max)
+            ));
+        }
+        for constraint in constraints {
+            script.push_str(&format!(
+                "(assert (distinct {} {}))\n",
+                smt_term(constraint),
+                bitvector(0)
+            ));
+        }
+        script.push_str("(check-sat)\n");
+        if !variables.is_empty() {
+            let names: Vec<String> = variables
+                .iter()
+                .map(|variable| quote(&variable.name))
+                .collect();
+            script.push_str(&format!("(get-value ({}))\n", names.join(" ")));
+        }
+        script
+    }
+
+    /// Read the answer of the solver to [`script`](Self::script)
+    pub fn parse_output(output: &str, variables: &[Variable]) -> Solution {
+        match output.lines().next().map(str::trim) {
+            Some("sat") => {}
+            Some("unsat") => return Solution::Unsat,
+            _ => return Solution::Unknown,
+        }
+        let mut model = Model::new();
+        for variable in variables {
+            let name = quote(&variable.name);
+            let Some(start) = output.find(&format!("({} ", name)) else {
+                return Solution::Unknown;
+            };
+            let rest = output[start + name.len() + 2..].trim_start();
+            let token: String = rest
+                .chars()
+                .take_while(|c| !c.is_whitespace() && *c != ')')
+                .collect();
+            let bits = if let Some(hex) = token.strip_prefix("#x") {
+                u64::from_str_radix(hex, 16).ok()
+            } else if let Some(binary) = token.strip_prefix("#b") {
+                u64::from_str_radix(binary, 2).ok()
+            } else {
+                None
+            };
+            let Some(bits) = bits else {
+                return Solution::Unknown;
+            };
+            model.insert(variable.name.clone(), bits as i64);
+        }
+        Solution::Sat(model)
+    }
+}
+
+#[cfg(feature = "smt")]
+impl Solver for SmtSolver {
+    fn solve(&mut self, variables: &[Variable], constraints: &[Term]) -> Solution {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        if let Some(holds) = trivial(constraints) {
+            return match holds {
+                false => Solution::Unsat,
+                true => self.fallback.solve(variables, constraints),
+            };
+        }
+        let child = Command::new(&self.command)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let Ok(mut child) = child else {
+            return self.fallback.solve(variables, constraints);
+        };
+        let script = Self::script(variables, constraints);
+        if let Some(mut stdin) = child.stdin.take() {
+            if stdin.write_all(script.as_bytes()).is_err() {
+                return Solution::Unknown;
+            }
+        }
+        match child.wait_with_output() {
+            Ok(output) => Self::parse_output(&String::from_utf8_lossy(&output.stdout), variables),
+            Err(_) => Solution::Unknown,
+        }
+    }
+}
+
+#[cfg(feature = "smt")]
+fn quote(name: &str) -> String {
+    format!("|{}|", name.replace('|', "_"))
+}
+
+#[cfg(feature = "smt")]
+fn bitvector(value: i64) -> String {
+    format!("(_ bv{} 64)", value as u64)
+}
+
+#[cfg(feature = "smt")]
+fn smt_term(term: &Term) -> String {
+    let boolean =
+        |condition: String| format!("(ite {} {} {})", condition, bitvector(1), bitvector(0));
+    let truthy = |term: &Term| format!("(distinct {} {})", smt_term(term), bitvector(0));
+    match term {
+        Term::Const(value) => bitvector(*value),
+        Term::Var(name) => quote(name),
+        Term::Unary(UnaryOp::Not, operand) => {
+            boolean(format!("(= {} {})", smt_term(operand), bitvector(0)))
+        }
+        Term::Unary(UnaryOp::Neg, operand) => format!("(bvneg {})", smt_term(operand)),
+        Term::Binary(op, left, right) => {
+            let (l, r) = (smt_term(left), smt_term(right));
+            match op {
+                BinaryOp::Add => format!("(bvadd {} {})", l, r),
+                BinaryOp::Sub => format!("(bvsub {} {})", l, r),
+                BinaryOp::Mul => format!("(bvmul {} {})", l, r),
+                BinaryOp::Div => format!("(bvsdiv {} {})", l, r),
+                BinaryOp::Rem => format!("(bvsrem {} {})", l, r),
+                BinaryOp::Eq => boolean(format!("(= {} {})", l, r)),
+                BinaryOp::Ne => boolean(format!("(distinct {} {})", l, r)),
+                BinaryOp::Lt => boolean(format!("(bvslt {} {})", l, r)),
+                BinaryOp::Le => boolean(format!("(bvsle {} {})", l, r)),
+                BinaryOp::And => boolean(format!("(and {} {})", truthy(left), truthy(right))),
+                BinaryOp::Or => boolean(format!("(or {} {})", truthy(left), truthy(right))),
+                BinaryOp::BitAnd => format!("(bvand {} {})", l, r),
+                BinaryOp::BitOr => format!("(bvor {} {})", l, r),
+                BinaryOp::BitXor => format!("(bvxor {} {})", l, r),
+                BinaryOp::Shl => format!("(bvshl {} (bvand {} {}))", l, r, bitvector(63)),
+                BinaryOp::Shr => format!("(bvashr {} (bvand {} {}))", l, r, bitvector(63)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn x_times_3_is_999() -> (Vec<Variable>, Vec<Term>) {
+        let product = Term::binary(BinaryOp::Mul, Term::var("x"), Term::Const(3));
+        (
+            vec![
+                Variable::new("x", 0, (1 << 24) - 1),
+                Variable::new("y", 0, 1),
+            ],
+            vec![Term::binary(BinaryOp::Eq, product, Term::Const(999))],
+        )
+    }
+
+    #[test]
+    fn test_terms_fold_and_evaluate() {
+        let sum = Term::binary(BinaryOp::Add, Term::Const(2), Term::Const(3));
+        assert_eq!(sum, Term::Const(5));
+        let quotient = Term::binary(BinaryOp::Div, Term::var("a"), Term::var("b"));
+        let model: Model = [("a".to_string(), 7), ("b".to_string(), 0)].into();
+        assert_eq!(quotient.eval(&model), None);
+        assert_eq!(
+            Term::binary(BinaryOp::Lt, Term::Const(-1), Term::Const(0)),
+            Term::Const(1)
+        );
+    }
+
+    #[test]
+    fn test_search_solver() {
+        let (variables, constraints) = x_times_3_is_999();
+        let Solution::Sat(model) = SearchSolver::default().solve(&variables, &constraints) else {
+            panic!("expected a model");
+        };
+        assert_eq!(model["x"], 333);
+        assert_eq!(model["y"], 0);
+
+        assert_eq!(
+            SearchSolver::default().solve(&variables, &[Term::Const(0)]),
+            Solution::Unsat
+        );
+        let impossible = Term::binary(BinaryOp::Lt, Term::var("x"), Term::Const(0));
+        assert_eq!(
+            SearchSolver::default().solve(&variables, &[impossible]),
+            Solution::Unknown
+        );
+    }
+
+    #[cfg(feature = "smt")]
+    #[test]
+    fn test_smt_script_and_output() {
+        let (variables, constraints) = x_times_3_is_999();
+        let script = SmtSolver::script(&variables, &constraints);
+        assert!(script.contains("(declare-const |x| (_ BitVec 64))"));
+        assert!(script.contains("(bvmul |x| (_ bv3 64))"));
+        assert!(script.ends_with("(get-value (|x| |y|))\n"));
+
+        let output = "sat\n((|x| #x000000000000014d)\n (|y| #b0000000000000000000000000000000000000000000000000000000000000000))\n";
+        let Solution::Sat(model) = SmtSolver::parse_output(output, &variables) else {
+            panic!("expected a model");
+        };
+        assert_eq!(model["x"], 333);
+        assert_eq!(model["y"], 0);
+        assert_eq!(
+            SmtSolver::parse_output("unsat\n", &variables),
+            Solution::Unsat
+        );
+    }
+}
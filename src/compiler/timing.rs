@@ -0,0 +1,266 @@
+//! How long the phases of a compilation take
+//!
+//! A [`Timings`] records each phase as it runs: how long it took and the
+//! resident memory of the process before and after it. Phases nest, so
+//! the runs of each optimizer pass sit below the `optimize` phase. With
+//! `-Z time-passes` the compiler prints them as a table, and with
+//! `-Z trace-passes=<file>` it writes them as a Chrome trace, which
+//! `chrome://tracing` and Perfetto open.
+
+use std::fs;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+/// One run of a phase
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phase {
+    pub name: String,
+    /// How many phases this one runs within
+    pub depth: usize,
+    /// When the phase started, from the start of the compilation
+    pub start: Duration,
+    pub duration: Duration,
+    /// Resident memory in bytes, where the platform tells it
+    pub memory_before: Option<u64>,
+    pub memory_after: Option<u64>,
+}
+
+impl Phase {
+    /// How much the resident memory grew during the phase, in bytes
+    pub fn memory_delta(&self) -> Option<i64> {
+        Some(self.memory_after? as i64 - self.memory_before? as i64)
+    }
+}
+
+/// The phases of a compilation, in the order they started
+#[derive(Debug, Clone)]
+pub struct Timings {
+    origin: Instant,
+    depth: usize,
+    phases: Vec<Phase>,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Timings {
+            origin: Instant::now(),
+            depth: 0,
+            phases: Vec::new(),
+        }
+    }
+
+    /// Run `f` as the phase `name`. The phases `f` runs through the
+    /// timings it is given nest below this one.
+    pub fn time<T>(&mut self, name: &str, f: impl FnOnce(&mut Self) -> T) -> T {
+        let index = self.phases.len();
+        let start = Instant::now();
+        self.phases.push(Phase {
+            name: name.to_string(),
+            depth: self.depth,
+            start: start - self.origin,
+            duration: Duration::ZERO,
+            memory_before: resident_memory(),
+            memory_after: None,
+        });
+
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+
+        let phase = &mut self.phases[index];
+        phase.duration = start.elapsed();
+        phase.memory_after = resident_memory();
+        result
+    }
+
+    pub fn phases(&self) -> &[Phase] {
+        &self.phases
+    }
+
+    /// The outermost phases, which nothing else runs within
+    pub fn top_level(&self) -> impl Iterator<Item = &Phase> {
+        self.phases.iter().filter(|phase| phase.depth == 0)
+    }
+
+    /// The time the outermost phases took together
+    pub fn total(&self) -> Duration {
+        self.top_level().map(|phase| phase.duration).sum()
+    }
+
+    /// The phases as a table, with the runs of a phase at the same place
+    /// added up: optimizer passes run until the program stops changing
+    pub fn table(&self) -> String {
+        // (name, depth, runs, duration, memory delta)
+        let mut rows: Vec<(&str, usize, usize, Duration, Option<i64>)> = Vec::new();
+        let mut parents: Vec<usize> = Vec::new();
+        let mut keys: Vec<(Option<usize>, &str)> = Vec::new();
+        for phase in &self.phases {
+            parents.truncate(phase.depth);
+            let key = (parents.last().copied(), phase.name.as_str());
+            let row = match keys.iter().position(|k| *k == key) {
+                Some(row) => {
+                    let (_, _, runs, duration, delta) = &mut rows[row];
+                    *runs += 1;
+                    *duration += phase.duration;
+                    *delta = delta.zip(phase.memory_delta()).map(|(a, b)| a + b);
+                    row
+                }
+                None => {
+                    keys.push(key);
+                    rows.push((
+                        &phase.name,
+                        phase.depth,
+                        1,
+                        phase.duration,
+                        phase.memory_delta(),
+                    ));
+                    rows.len() - 1
+                }
+            };
+            parents.push(row);
+        }
+
+        let total = self.total().as_secs_f64();
+        let mut table = format!(
+            "{:<28} {:>5} {:>11} {:>7} {:>11}\n",
+            "phase", "runs", "time (ms)", "%", "rss delta"
+        );
+        for (name, depth, runs, duration, delta) in rows {
+            let seconds = duration.as_secs_f64();
+            let share = if total > 0.0 {
+                seconds / total * 100.0
+            } else {
+                0.0
+            };
+            table.push_str(&format!(
+                "{:<28} {:>5} {:>11.3} {:>7.1} {:>11}\n",
+                format!("{}{}", "  ".repeat(depth), name),
+                runs,
+                seconds * 1000.0,
+                share,
+                delta.map_or("-".to_string(), format_bytes)
+            ));
+        }
+        table.push_str(&format!(
+            "{:<28} {:>5} {:>11.3}\n",
+            "total",
+            "",
+            total * 1000.0
+        ));
+        table
+    }
+
+    /// The phases in the Chrome trace event format, as complete events in
+    /// microseconds
+    pub fn chrome_trace(&self) -> Value {
+        let events: Vec<Value> = self
+            .phases
+            .iter()
+            .map(|phase| {
+                json!({
+                    "name": phase.name,
+                    "cat": "compile",
+                    "ph": "X",
+                    "ts": phase.start.as_secs_f64() * 1e6,
+                    "dur": phase.duration.as_secs_f64() * 1e6,
+                    "pid": std::process::id(),
+                    "tid": 0,
+                    "args": {
+                        "rss_before": phase.memory_before,
+                        "rss_after": phase.memory_after,
+                    },
+                })
+            })
+            .collect();
+        json!({
+            "traceEvents": events,
+            "displayTimeUnit": "ms",
+        })
+    }
+}
+
+/// The resident memory of the process in bytes, on Linux
+fn resident_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+fn format_bytes(bytes: i64) -> String {
+    match bytes.unsigned_abs() {
+        n if n >= 1 << 20 => format!("{:+.1} MiB", bytes as f64 / (1 << 20) as f64),
+        n if n >= 1 << 10 => format!("{:+.1} KiB", bytes as f64 / (1 << 10) as f64),
+        _ => format!("{:+} B", bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phases_nest() {
+        let mut timings = Timings::new();
+        timings.time("parse", |_| ());
+        let value = timings.time("optimize", |timings| {
+            for _ in 0..2 {
+                timings.time("linearize", |_| ());
+                timings.time("prune", |_| ());
+            }
+            42
+        });
+        assert_eq!(value, 42);
+
+        let names: Vec<(&str, usize)> = timings
+            .phases()
+            .iter()
+            .map(|phase| (phase.name.as_str(), phase.depth))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("parse", 0),
+                ("optimize", 0),
+                ("linearize", 1),
+                ("prune", 1),
+                ("linearize", 1),
+                ("prune", 1),
+            ]
+        );
+        assert_eq!(timings.top_level().count(), 2);
+        let optimize = &timings.phases()[1];
+        let passes: Duration = timings.phases()[2..].iter().map(|p| p.duration).sum();
+        assert!(optimize.duration >= passes);
+
+        // Runs of the same pass are added up
+        let table = timings.table();
+        assert_eq!(table.lines().count(), 6);
+        assert!(table.contains("  linearize"));
+        let runs: Vec<&str> = table
+            .lines()
+            .filter(|line| line.trim_start().starts_with("prune"))
+            .map(|line| line.split_whitespace().nth(1).unwrap())
+            .collect();
+        assert_eq!(runs, ["2"]);
+    }
+
+    #[test]
+    fn test_chrome_trace() {
+        let mut timings = Timings::new();
+        timings.time("codegen", |timings| timings.time("encode", |_| ()));
+        let trace = timings.chrome_trace();
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["name"], "codegen");
+        assert_eq!(events[0]["ph"], "X");
+        assert!(events[1]["ts"].as_f64().unwrap() >= events[0]["ts"].as_f64().unwrap());
+    }
+}
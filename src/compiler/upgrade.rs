@@ -0,0 +1,517 @@
+//! Whether a new version of a contract can take over the storage of the
+//! deployed one
+//!
+//! A contract replaces its own code with `set_code_hash(code_hash)`; the
+//! new code runs from the next call on, over the storage the old code left.
+//! Storage keys are derived from field names, so fields can be reordered
+//! and new ones added freely: they read as zero until written. A field that
+//! is removed, or whose type changes, leaves data the new code cannot read
+//! the way it was written.
+//!
+//! Such breaking changes need a new storage schema version, declared with
+//! `#[version(n)]` on the storage, and a `#[migrate]` function moving the
+//! data over:
+//!
+//! ```text
+//! #[version(2)]
+//! storage {
+//!     balance: u24,
+//!     fee: u24,
+//! }
+//!
+//! #[migrate]
+//! fn migrate() -> u24 {
+//!     fee = 3;
+//!     return 0;
+//! }
+//! ```
+//!
+//! [`check_upgrade`] compares the storage schemas of two versions, as their
+//! metadata records them, and [`check_program`] does so for a program being
+//! compiled against the metadata of the deployed version.
+//! [`migration_skeleton`] starts the `#[migrate]` function for what changed.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compiler::analyzer::type_checker::TypeError;
+use crate::compiler::codegen::metadata::{
+    collect_migration, collect_storage_layout, collect_storage_version, ContractMetadata,
+    StorageFieldMetadata,
+};
+use crate::compiler::parser::ast::{Definition, Program};
+
+/// The storage a version of a contract reads and writes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageSchema {
+    /// Schema version, 0 when the storage is not versioned
+    #[serde(default)]
+    pub version: u32,
+    /// Fields in declaration order
+    pub fields: Vec<StorageFieldMetadata>,
+    /// The `#[migrate]` function moving storage of earlier versions over
+    #[serde(default)]
+    pub migration: Option<String>,
+}
+
+/// The JSON files a storage schema is read from
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SchemaFile {
+    Metadata(Box<ContractMetadata>),
+    Schema(StorageSchema),
+    Layout(Vec<StorageFieldMetadata>),
+}
+
+impl StorageSchema {
+    /// The storage schema recorded in the metadata of a contract
+    pub fn of_metadata(metadata: &ContractMetadata) -> Self {
+        StorageSchema {
+            version: metadata.storage_version,
+            fields: metadata.storage_layout.clone(),
+            migration: metadata.migration.clone(),
+        }
+    }
+
+    /// The storage schema a program declares
+    pub fn of_program(program: &Program) -> Self {
+        StorageSchema {
+            version: collect_storage_version(program),
+            fields: collect_storage_layout(program),
+            migration: collect_migration(program),
+        }
+    }
+
+    /// Read a storage schema from the metadata of a contract, a schema as
+    /// `serde` writes it, or a bare storage layout, which is unversioned
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        Ok(match serde_json::from_str(json)? {
+            SchemaFile::Metadata(metadata) => StorageSchema::of_metadata(&metadata),
+            SchemaFile::Schema(schema) => schema,
+            SchemaFile::Layout(fields) => StorageSchema {
+                version: 0,
+                fields,
+                migration: None,
+            },
+        })
+    }
+}
+
+/// How a storage field changed between two versions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum StorageChange {
+    /// A new field, which reads as zero until written
+    Added { field: String, type_name: String },
+    /// A field the new version no longer declares
+    Removed { field: String, type_name: String },
+    /// A field whose type changed
+    Retyped {
+        field: String,
+        from: String,
+        to: String,
+    },
+    /// A field declared at another position among the fields both versions
+    /// declare, which keeps its key
+    Reordered {
+        field: String,
+        from: usize,
+        to: usize,
+    },
+}
+
+impl StorageChange {
+    /// Whether the new version may misread what the old one stored
+    pub fn is_breaking(&self) -> bool {
+        matches!(
+            self,
+            StorageChange::Removed { .. } | StorageChange::Retyped { .. }
+        )
+    }
+
+    /// The field that changed
+    pub fn field(&self) -> &str {
+        match self {
+            StorageChange::Added { field, .. }
+            | StorageChange::Removed { field, .. }
+            | StorageChange::Retyped { field, .. }
+            | StorageChange::Reordered { field, .. } => field,
+        }
+    }
+}
+
+impl fmt::Display for StorageChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageChange::Added { field, type_name } => {
+                write!(f, "+ {}: {} (added)", field, type_name)
+            }
+            StorageChange::Removed { field, type_name } => {
+                write!(f, "- {}: {} (removed)", field, type_name)
+            }
+            StorageChange::Retyped { field, from, to } => {
+                write!(f, "~ {}: {} -> {} (type changed)", field, from, to)
+            }
+            StorageChange::Reordered { field, from, to } => {
+                write!(f, "  {}: position {} -> {} (reordered)", field, from, to)
+            }
+        }
+    }
+}
+
+/// What changes between the storage of two versions and why upgrading from
+/// one to the other is unsafe, if it is
+#[derive(Debug, Clone, Serialize)]
+pub struct UpgradeReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    /// The `#[migrate]` function of the new version
+    pub migration: Option<String>,
+    pub changes: Vec<StorageChange>,
+    /// Why the new version cannot take over the storage, empty when it can
+    pub problems: Vec<String>,
+}
+
+impl UpgradeReport {
+    /// Whether the new version can take over the storage of the old one
+    pub fn is_compatible(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for UpgradeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Storage version {} -> {}",
+            self.from_version, self.to_version
+        )?;
+        if let Some(migration) = &self.migration {
+            write!(f, ", migrated by `{}`", migration)?;
+        }
+        writeln!(f)?;
+        if self.changes.is_empty() {
+            writeln!(f, "  storage layout unchanged")?;
+        }
+        for change in &self.changes {
+            writeln!(f, "  {}", change)?;
+        }
+        if self.is_compatible() {
+            write!(f, "Compatible")
+        } else {
+            write!(f, "Incompatible:")?;
+            for problem in &self.problems {
+                write!(f, "\n  {}", problem)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Compare the storage of the deployed version, `old`, with that of the
+/// version replacing it, `new`
+///
+/// Fields are matched by name, as their keys are. Appending and reordering
+/// fields is compatible, while breaking changes are only allowed with a
+/// higher storage version and a `#[migrate]` function, and the version never
+/// goes back.
+pub fn check_upgrade(old: &StorageSchema, new: &StorageSchema) -> UpgradeReport {
+    let mut changes = Vec::new();
+    for field in &old.fields {
+        match new.fields.iter().find(|other| other.name == field.name) {
+            None => changes.push(StorageChange::Removed {
+                field: field.name.clone(),
+                type_name: field.type_name.clone(),
+            }),
+            Some(other) if other.type_name != field.type_name => {
+                changes.push(StorageChange::Retyped {
+                    field: field.name.clone(),
+                    from: field.type_name.clone(),
+                    to: other.type_name.clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    for field in &new.fields {
+        if !old.fields.iter().any(|other| other.name == field.name) {
+            changes.push(StorageChange::Added {
+                field: field.name.clone(),
+                type_name: field.type_name.clone(),
+            });
+        }
+    }
+    let kept = |fields: &[StorageFieldMetadata], others: &[StorageFieldMetadata]| {
+        fields
+            .iter()
+            .filter(|field| others.iter().any(|other| other.name == field.name))
+            .map(|field| field.name.clone())
+            .collect::<Vec<_>>()
+    };
+    let (old_order, new_order) = (
+        kept(&old.fields, &new.fields),
+        kept(&new.fields, &old.fields),
+    );
+    for (to, field) in new_order.iter().enumerate() {
+        let from = old_order
+            .iter()
+            .position(|other| other == field)
+            .unwrap_or(to);
+        if from != to {
+            changes.push(StorageChange::Reordered {
+                field: field.clone(),
+                from,
+                to,
+            });
+        }
+    }
+
+    let mut problems = Vec::new();
+    if new.version < old.version {
+        problems.push(format!(
+            "the storage version goes back from {} to {}",
+            old.version, new.version
+        ));
+    }
+    if let Some(breaking) = changes.iter().find(|change| change.is_breaking()) {
+        let field = breaking.field();
+        if new.version <= old.version {
+            problems.push(format!(
+                "`{}` changes incompatibly without a new storage version; declare the storage #[version({})]",
+                field,
+                old.version + 1
+            ));
+        } else if new.migration.is_none() {
+            problems.push(format!(
+                "`{}` changes incompatibly, but no #[migrate] function moves the storage of version {} to version {}",
+                field, old.version, new.version
+            ));
+        }
+    }
+
+    UpgradeReport {
+        from_version: old.version,
+        to_version: new.version,
+        migration: new.migration.clone(),
+        changes,
+        problems,
+    }
+}
+
+/// Start the `#[migrate]` function moving the storage of `old` over to
+/// `new`, as Bend source
+///
+/// Fields are only written where the change needs it: retyped fields are
+/// read and written back for the conversion to be filled in, added ones
+/// are left to be initialized and removed ones are pointed out with the
+/// key their data stays under.
+pub fn migration_skeleton(old: &StorageSchema, new: &StorageSchema) -> String {
+    let report = check_upgrade(old, new);
+    let version = new.version.max(old.version + 1);
+    let mut source = String::new();
+    if new.version < version {
+        source.push_str(&format!(
+            "# Declare the storage #[version({})] for this migration to run\n",
+            version
+        ));
+    }
+    source.push_str(&format!(
+        "# Moves the storage of version {} over to version {}\n#[migrate]\nfn {}() -> u24 {{\n",
+        old.version,
+        version,
+        new.migration.as_deref().unwrap_or("migrate")
+    ));
+    for change in &report.changes {
+        match change {
+            StorageChange::Retyped { field, from, to } => source.push_str(&format!(
+                "    # TODO: `{0}` was stored as {1} and is now read as {2}; convert it\n    {0} = {0};\n",
+                field, from, to
+            )),
+            StorageChange::Added { field, type_name } => source.push_str(&format!(
+                "    # TODO: `{}: {}` reads as zero until initialized\n",
+                field, type_name
+            )),
+            StorageChange::Removed { field, type_name } => {
+                let key = old
+                    .fields
+                    .iter()
+                    .find(|other| other.name == *field)
+                    .map(|other| hex::encode(other.key))
+                    .unwrap_or_default();
+                source.push_str(&format!(
+                    "    # TODO: `{}: {}` is no longer declared; its data stays under key 0x{}\n",
+                    field, type_name, key
+                ));
+            }
+            StorageChange::Reordered { .. } => {}
+        }
+    }
+    source.push_str("    return 0;\n}\n");
+    source
+}
+
+/// Check that `program` can take over the storage of the deployed version
+/// described by `deployed`, failing at its storage declaration otherwise
+pub fn check_program(
+    deployed: &ContractMetadata,
+    program: &Program,
+) -> Result<UpgradeReport, TypeError> {
+    let report = check_upgrade(
+        &StorageSchema::of_metadata(deployed),
+        &StorageSchema::of_program(program),
+    );
+    let Some(problem) = report.problems.first() else {
+        return Ok(report);
+    };
+
+    let message = format!(
+        "Cannot upgrade {} {}: {}",
+        deployed.name, deployed.version, problem
+    );
+    let storage = program
+        .definitions
+        .iter()
+        .find_map(|definition| match definition {
+            Definition::StorageDef { location, .. } => Some(location),
+            _ => None,
+        });
+    Err(TypeError::Generic(match storage {
+        Some(location) => format!(
+            "{} (line {}, column {})",
+            message, location.line, location.column
+        ),
+        None => message,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::analyzer::type_checker::TypeChecker;
+    use crate::compiler::codegen::metadata::compute_storage_key;
+    use crate::compiler::parser::parser::Parser;
+
+    fn schema(source: &str) -> StorageSchema {
+        StorageSchema::of_program(&Parser::new(source).parse_program().unwrap())
+    }
+
+    const DEPLOYED: &str = "storage {\n    balance: u24,\n    owner: Address,\n}\n";
+
+    #[test]
+    fn test_appending_and_reordering_are_compatible() {
+        let report = check_upgrade(
+            &schema(DEPLOYED),
+            &schema("storage {\n    owner: Address,\n    fee: u24,\n    balance: u24,\n}\n"),
+        );
+        assert!(report.is_compatible(), "{}", report);
+        assert_eq!(
+            report.changes,
+            [
+                StorageChange::Added {
+                    field: "fee".to_string(),
+                    type_name: "u24".to_string()
+                },
+                StorageChange::Reordered {
+                    field: "owner".to_string(),
+                    from: 1,
+                    to: 0
+                },
+                StorageChange::Reordered {
+                    field: "balance".to_string(),
+                    from: 0,
+                    to: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_schema_files() {
+        let layout = serde_json::to_string(&schema(DEPLOYED).fields).unwrap();
+        let from_layout = StorageSchema::from_json(&layout).unwrap();
+        assert_eq!(from_layout.version, 0);
+        assert_eq!(from_layout.fields.len(), 2);
+
+        let versioned = schema("#[version(3)]\nstorage {\n    balance: u24,\n}\n");
+        let from_schema =
+            StorageSchema::from_json(&serde_json::to_string(&versioned).unwrap()).unwrap();
+        assert_eq!(from_schema.version, 3);
+        assert!(StorageSchema::from_json("{\"name\": \"vault\"}").is_err());
+    }
+
+    #[test]
+    fn test_migration_skeleton() {
+        let new = "storage {\n    balance: u128,\n    fee: u24,\n}\n";
+        let skeleton = migration_skeleton(&schema(DEPLOYED), &schema(new));
+        assert!(skeleton.starts_with("# Declare the storage #[version(1)]"));
+        assert!(skeleton.contains("    balance = balance;\n"));
+        assert!(skeleton.contains("`fee: u24` reads as zero"));
+        assert!(skeleton.contains(&format!(
+            "`owner: Address` is no longer declared; its data stays under key 0x{}",
+            hex::encode(compute_storage_key("owner"))
+        )));
+
+        // With the version declared, the skeleton checks as the migration
+        let versioned = format!("#[version(1)]\n{}\n{}", new, skeleton);
+        let program = Parser::new(&versioned).parse_program().unwrap();
+        TypeChecker::new().check_program(&program).unwrap();
+        let report = check_upgrade(&schema(DEPLOYED), &StorageSchema::of_program(&program));
+        assert!(report.is_compatible(), "{}", report);
+    }
+
+    #[test]
+    fn test_breaking_changes_need_a_version_and_migration() {
+        let retyped = "storage {\n    balance: u128,\n    owner: Address,\n}\n";
+        let report = check_upgrade(&schema(DEPLOYED), &schema(retyped));
+        assert_eq!(
+            report.changes,
+            [StorageChange::Retyped {
+                field: "balance".to_string(),
+                from: "u24".to_string(),
+                to: "u128".to_string()
+            }]
+        );
+        assert!(report.problems[0].contains("#[version(1)]"));
+
+        let versioned = format!("#[version(1)]\n{}", retyped);
+        let report = check_upgrade(&schema(DEPLOYED), &schema(&versioned));
+        assert!(report.problems[0].contains("no #[migrate] function"));
+
+        let migrated = format!(
+            "{}#[migrate]\nfn migrate() -> u24 {{\n    return 0;\n}}\n",
+            versioned
+        );
+        let report = check_upgrade(&schema(DEPLOYED), &schema(&migrated));
+        assert!(report.is_compatible(), "{}", report);
+        assert_eq!(report.migration.as_deref(), Some("migrate"));
+
+        // Removing a field breaks too, and versions never go back
+        let report = check_upgrade(
+            &schema(&migrated),
+            &schema("storage {\n    owner: Address,\n}\n"),
+        );
+        assert_eq!(report.problems.len(), 2);
+        assert!(report.problems[0].contains("goes back from 1 to 0"));
+    }
+
+    #[test]
+    fn test_check_program_fails_at_the_storage() {
+        let program = Parser::new("\nstorage {\n    owner: Address,\n}\n")
+            .parse_program()
+            .unwrap();
+        let mut deployed = crate::compiler::codegen::metadata::build_metadata(
+            "vault",
+            "1.0.0",
+            &[],
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        );
+        deployed.storage_layout = schema(DEPLOYED).fields;
+
+        let error = check_program(&deployed, &program).unwrap_err().to_string();
+        assert!(error.contains("Cannot upgrade vault 1.0.0: `balance` changes incompatibly"));
+        assert!(error.contains("(line 2, column 1)"));
+    }
+}
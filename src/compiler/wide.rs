@@ -0,0 +1,210 @@
+//! Wide unsigned integers (`u64`, `u128` and `u256`)
+//!
+//! Values wider than a machine word are kept as little-endian 32-bit limbs,
+//! both at compile time (literals) and in generated code, where a value of
+//! `bits / 32` words occupies consecutive stack slots with the least
+//! significant word first. Their SCALE encoding is the same little-endian
+//! byte string, so arguments, results and storage entries are copied
+//! without conversion.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Maximum number of 32-bit limbs of a wide integer
+pub const MAX_WIDE_LIMBS: usize = 8;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum WideIntError {
+    #[error("Unsupported integer width: {0}")]
+    UnsupportedWidth(u16),
+
+    #[error("Invalid integer literal: {0}")]
+    InvalidDigits(String),
+
+    #[error("Integer literal exceeds u{bits} maximum value: {value}")]
+    Overflow { value: String, bits: u16 },
+}
+
+/// Width in bits of a wide integer type name, e.g. `u128`
+pub fn wide_type_bits(name: &str) -> Option<u16> {
+    match name {
+        "u64" => Some(64),
+        "u128" => Some(128),
+        "u256" => Some(256),
+        _ => None,
+    }
+}
+
+/// An unsigned integer of 64, 128 or 256 bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WideUint {
+    bits: u16,
+    limbs: [u32; MAX_WIDE_LIMBS],
+}
+
+impl WideUint {
+    /// Zero of the given width
+    pub fn zero(bits: u16) -> Result<Self, WideIntError> {
+        if !matches!(bits, 64 | 128 | 256) {
+            return Err(WideIntError::UnsupportedWidth(bits));
+        }
+        Ok(WideUint {
+            bits,
+            limbs: [0; MAX_WIDE_LIMBS],
+        })
+    }
+
+    /// Create a value from a `u128`, failing if it does not fit
+    pub fn from_u128(value: u128, bits: u16) -> Result<Self, WideIntError> {
+        let mut result = Self::zero(bits)?;
+        for (i, limb) in result.limbs.iter_mut().take(4).enumerate() {
+            *limb = (value >> (32 * i)) as u32;
+        }
+        if result.limbs[result.words()..].iter().any(|&limb| limb != 0) {
+            return Err(WideIntError::Overflow {
+                value: value.to_string(),
+                bits,
+            });
+        }
+        Ok(result)
+    }
+
+    /// Parse a decimal literal, failing if it does not fit
+    pub fn from_dec_str(digits: &str, bits: u16) -> Result<Self, WideIntError> {
+        Self::from_str_radix(digits, 10, bits)
+    }
+
+    /// Parse the digits of a literal in base `radix` (2 to 36), failing if
+    /// it does not fit
+    pub fn from_str_radix(digits: &str, radix: u32, bits: u16) -> Result<Self, WideIntError> {
+        let mut result = Self::zero(bits)?;
+        if digits.is_empty() {
+            return Err(WideIntError::InvalidDigits(digits.to_string()));
+        }
+
+        for c in digits.chars() {
+            let digit = c
+                .to_digit(radix)
+                .ok_or_else(|| WideIntError::InvalidDigits(digits.to_string()))?;
+
+            // result = result * radix + digit
+            let mut carry = digit as u64;
+            for limb in result.limbs.iter_mut() {
+                let product = *limb as u64 * radix as u64 + carry;
+                *limb = product as u32;
+                carry = product >> 32;
+            }
+            if carry != 0 || result.limbs[result.words()..].iter().any(|&limb| limb != 0) {
+                return Err(WideIntError::Overflow {
+                    value: digits.to_string(),
+                    bits,
+                });
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Decode a little-endian value of 8, 16 or 32 bytes
+    pub fn from_le_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut result = Self::zero((bytes.len() * 8) as u16).ok()?;
+        for (limb, chunk) in result.limbs.iter_mut().zip(bytes.chunks(4)) {
+            *limb = u32::from_le_bytes(chunk.try_into().ok()?);
+        }
+        Some(result)
+    }
+
+    /// Width in bits
+    pub fn bits(&self) -> u16 {
+        self.bits
+    }
+
+    /// Number of 32-bit words
+    pub fn words(&self) -> usize {
+        self.bits as usize / 32
+    }
+
+    /// Little-endian limbs, one per word
+    pub fn limbs(&self) -> &[u32] {
+        &self.limbs[..self.words()]
+    }
+
+    /// Little-endian encoding of `bits / 8` bytes
+    pub fn to_le_bytes(&self) -> Vec<u8> {
+        self.limbs()
+            .iter()
+            .flat_map(|limb| limb.to_le_bytes())
+            .collect()
+    }
+
+    /// Whether the value is zero
+    pub fn is_zero(&self) -> bool {
+        self.limbs.iter().all(|&limb| limb == 0)
+    }
+}
+
+impl fmt::Display for WideUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        // Repeatedly divide by 10, collecting the remainders
+        let mut limbs = self.limbs;
+        let mut digits = Vec::new();
+        while limbs.iter().any(|&limb| limb != 0) {
+            let mut remainder = 0u64;
+            for limb in limbs.iter_mut().rev() {
+                let value = (remainder << 32) | *limb as u64;
+                *limb = (value / 10) as u32;
+                remainder = value % 10;
+            }
+            digits.push(char::from(b'0' + remainder as u8));
+        }
+        let digits: String = digits.into_iter().rev().collect();
+        write!(f, "{}", digits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        let max = "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+        let value = WideUint::from_dec_str(max, 256).unwrap();
+        assert!(value.limbs().iter().all(|&limb| limb == u32::MAX));
+        assert_eq!(value.to_string(), max);
+
+        let value = WideUint::from_dec_str("18446744073709551616", 128).unwrap();
+        assert_eq!(value.limbs(), &[0, 0, 1, 0]);
+        assert_eq!(value, WideUint::from_u128(1 << 64, 128).unwrap());
+        assert_eq!(WideUint::zero(64).unwrap().to_string(), "0");
+
+        let value = WideUint::from_str_radix("ffffffffffffffff", 16, 64).unwrap();
+        assert_eq!(value.to_string(), u64::MAX.to_string());
+        assert!(WideUint::from_str_radix("12", 2, 64).is_err());
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert!(matches!(
+            WideUint::from_dec_str("18446744073709551616", 64),
+            Err(WideIntError::Overflow { bits: 64, .. })
+        ));
+        assert!(WideUint::from_u128(u64::MAX as u128 + 1, 64).is_err());
+        assert_eq!(WideUint::zero(96), Err(WideIntError::UnsupportedWidth(96)));
+    }
+
+    #[test]
+    fn test_le_bytes_round_trip() {
+        let value = WideUint::from_u128(0x0102_0304_0506_0708, 64).unwrap();
+        let bytes = value.to_le_bytes();
+        assert_eq!(bytes, 0x0102_0304_0506_0708u64.to_le_bytes().to_vec());
+        assert_eq!(WideUint::from_le_bytes(&bytes), Some(value));
+        assert_eq!(WideUint::from_le_bytes(&[0; 12]), None);
+    }
+}
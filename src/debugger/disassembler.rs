@@ -1,3 +1,4 @@
+use crate::compiler::codegen::encoder::{assemble, Isa, MachineCode};
 use crate::compiler::codegen::risc_v::Instruction;
 use crate::debugger::DebugInfo;
 
@@ -8,14 +9,19 @@ pub struct Disassembler {
 
     /// Instructions
     instructions: Vec<Instruction>,
+
+    /// Machine code of the instructions, if they assemble
+    machine_code: Option<MachineCode>,
 }
 
 impl Disassembler {
     /// Create a new disassembler
     pub fn new(debug_info: DebugInfo, instructions: Vec<Instruction>) -> Self {
+        let machine_code = assemble(&instructions, Isa::Rv32Im).ok();
         Disassembler {
             debug_info,
             instructions,
+            machine_code,
         }
     }
 
@@ -28,11 +34,21 @@ impl Disassembler {
         let instruction = &self.instructions[index];
         let source_line = self.debug_info.instruction_to_line.get(&index).cloned();
 
+        // Without machine code, assume 4 bytes per instruction
+        let (address, bytes) = match &self.machine_code {
+            Some(machine_code) => (
+                machine_code.addresses[index] as usize,
+                machine_code.bytes(index).to_vec(),
+            ),
+            None => (index * 4, Vec::new()),
+        };
+
         Some(DisassembledInstruction {
             index,
             instruction: instruction.clone(),
             source_line,
-            address: index * 4, // Assuming 4 bytes per instruction
+            address,
+            bytes,
         })
     }
 
@@ -112,24 +128,30 @@ pub struct DisassembledInstruction {
 
     /// Instruction address
     pub address: usize,
+
+    /// Machine code bytes, empty for labels, comments and code that does
+    /// not assemble
+    pub bytes: Vec<u8>,
 }
 
 impl DisassembledInstruction {
     /// Convert to string representation
     #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
-        format!("{:08x}: {}", self.address, self.instruction)
+        format!(
+            "{:08x}: {:<17} {}",
+            self.address,
+            hex::encode(&self.bytes),
+            self.instruction
+        )
     }
 
     /// Get a human-readable representation with source line
     pub fn to_string_with_source(&self) -> String {
         if let Some(line) = self.source_line {
-            format!(
-                "{:08x}: {} // line {}",
-                self.address, self.instruction, line
-            )
+            format!("{} // line {}", self.to_string(), line)
         } else {
-            format!("{:08x}: {}", self.address, self.instruction)
+            self.to_string()
         }
     }
 }
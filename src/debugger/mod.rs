@@ -14,7 +14,9 @@ use thiserror::Error;
 
 pub use self::breakpoint::Breakpoint;
 use self::state::{DebuggerState, ExecutionState};
-use crate::compiler::codegen::risc_v::Instruction;
+use crate::compiler::codegen::risc_v::{Instruction, RiscVCodegen};
+use crate::compiler::parser::ast::{Definition, Location, Program};
+use crate::diagnostics::line_column;
 use crate::runtime::env::{Environment, ExecutionContext};
 
 /// Debugger errors
@@ -114,6 +116,74 @@ pub struct DebugInfo {
     pub functions: HashMap<String, FunctionRange>,
 }
 
+impl DebugInfo {
+    /// The debug information of `code`, generated from `program`, the
+    /// source `source_code`: where each function's code starts and ends,
+    /// with the first instruction of each function's first line
+    pub fn new(
+        source_path: PathBuf,
+        source_code: &str,
+        program: &Program,
+        code: &[Instruction],
+    ) -> Self {
+        let labels: HashMap<&str, usize> = code
+            .iter()
+            .enumerate()
+            .filter_map(|(index, instruction)| match instruction {
+                Instruction::Label(label) => Some((label.as_str(), index)),
+                _ => None,
+            })
+            .collect();
+        let mut starts: Vec<(usize, &str, &Location)> = program
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                Definition::FunctionDef { name, location, .. } => {
+                    let label = RiscVCodegen::function_label(name);
+                    labels
+                        .get(label.as_str())
+                        .map(|&start| (start, name.as_str(), location))
+                }
+                _ => None,
+            })
+            .collect();
+        starts.sort_by_key(|(start, _, _)| *start);
+
+        let mut info = DebugInfo {
+            source_path,
+            source_code: source_code.to_string(),
+            line_to_instruction: HashMap::new(),
+            instruction_to_line: HashMap::new(),
+            locals: HashMap::new(),
+            functions: HashMap::new(),
+        };
+        for (i, (start, name, location)) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).map_or(code.len(), |(next, _, _)| *next);
+            // The location of a definition ends past the token after it, so
+            // the function ends at the last brace before that
+            let text = &source_code[..location.end.min(source_code.len())];
+            let last = text.rfind('}').unwrap_or(text.trim_end().len());
+            let (end_line, _) = line_column(source_code, last);
+            info.line_to_instruction
+                .entry(location.line)
+                .or_default()
+                .push(*start);
+            info.instruction_to_line.insert(*start, location.line);
+            info.functions.insert(
+                name.to_string(),
+                FunctionRange {
+                    name: name.to_string(),
+                    start: *start,
+                    end,
+                    start_line: location.line,
+                    end_line,
+                },
+            );
+        }
+        info
+    }
+}
+
 /// Variable location in memory or registers
 #[derive(Debug, Clone)]
 pub enum VariableLocation {
@@ -199,6 +269,11 @@ impl Debugger {
         &mut self.state
     }
 
+    /// Get the runtime environment (storage, events and open checkpoints)
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
     /// Add a breakpoint
     pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> Result<(), DebuggerError> {
         // Validate the breakpoint
@@ -254,6 +329,12 @@ impl Debugger {
         // Emit the started event
         self.emit_event(DebuggerEvent::Started);
 
+        // The session is one transaction, committed when the program finishes
+        // and rolled back if it crashes
+        if self.environment.checkpoint_depth() == 0 {
+            self.environment.checkpoint();
+        }
+
         // Set the initial state
         self.state.execution_state = ExecutionState::Running;
 
@@ -291,6 +372,11 @@ impl Debugger {
 
         if pc >= self.instructions.len() {
             self.state.execution_state = ExecutionState::Stopped;
+            if self.environment.checkpoint_depth() > 0 {
+                self.environment
+                    .commit()
+                    .map_err(|e| DebuggerError::Environment(e.to_string()))?;
+            }
             self.emit_event(DebuggerEvent::Finished);
             return Ok(());
         }
@@ -310,6 +396,11 @@ impl Debugger {
             }
             Err(err) => {
                 self.state.execution_state = ExecutionState::Stopped;
+                if self.environment.checkpoint_depth() > 0 {
+                    self.environment
+                        .rollback()
+                        .map_err(|e| DebuggerError::Environment(e.to_string()))?;
+                }
                 self.emit_event(DebuggerEvent::Crashed(err.to_string()));
 
                 Err(err)
@@ -554,6 +645,33 @@ impl Debugger {
                 Ok(())
             }
 
+            // Load a zero-extended byte (lbu)
+            Instruction::LoadByteU(rd, rs1, offset) => {
+                let base_addr = self.get_reg_value(rs1)? as i32;
+                let addr = (base_addr + offset) as u32;
+
+                match self.state.memory.get(&addr) {
+                    Some(byte) => {
+                        self.set_reg_value(rd, *byte as u32);
+                        Ok(())
+                    }
+                    None => Err(DebuggerError::Execution(format!(
+                        "Memory read error: address 0x{:08x}",
+                        addr
+                    ))),
+                }
+            }
+
+            // Store the low byte (sb)
+            Instruction::StoreByte(rs2, rs1, offset) => {
+                let value = self.get_reg_value(rs2)?;
+                let base_addr = self.get_reg_value(rs1)? as i32;
+                let addr = (base_addr + offset) as u32;
+
+                self.state.memory.insert(addr, (value & 0xFF) as u8);
+                Ok(())
+            }
+
             // Branch if equal (beq)
             Instruction::BranchEq(rs1, rs2, label) => {
                 let val1 = self.get_reg_value(rs1)?;
@@ -769,4 +887,34 @@ mod tests {
             panic!("Expected SetBreakpoint with Line variant");
         }
     }
+
+    #[test]
+    fn test_session_checkpoint_closes_on_finish_and_crash() {
+        use crate::compiler::codegen::risc_v::Register;
+
+        let debug_info = DebugInfo {
+            source_path: PathBuf::from("test.bend"),
+            source_code: String::new(),
+            line_to_instruction: HashMap::new(),
+            instruction_to_line: HashMap::new(),
+            locals: HashMap::new(),
+            functions: HashMap::new(),
+        };
+
+        let mut debugger = Debugger::new(
+            debug_info.clone(),
+            vec![Instruction::Comment("nop".to_string())],
+            ExecutionContext::new_default(),
+        );
+        debugger.run().unwrap();
+        assert_eq!(debugger.environment().checkpoint_depth(), 0);
+
+        let mut debugger = Debugger::new(
+            debug_info,
+            vec![Instruction::Load(Register::X5, Register::X6, 0)],
+            ExecutionContext::new_default(),
+        );
+        assert!(debugger.run().is_err());
+        assert_eq!(debugger.environment().checkpoint_depth(), 0);
+    }
 }
@@ -64,7 +64,7 @@ pub struct DeploymentConfig {
 impl DeploymentConfig {
     pub fn new(environment: Environment) -> Self {
         let network = match environment {
-            Environment::Development => NetworkConfig::new("local", "http://localhost:9933", 0),
+            Environment::Development => NetworkConfig::new("local", "http://localhost:9944", 0),
             Environment::Testnet => {
                 NetworkConfig::new("testnet", "https://rpc.testnet.polkadot.io", 42)
             }
@@ -0,0 +1,265 @@
+//! A contract compiled for a node: its PolkaVM blob and ABI, and the call
+//! data of its messages
+//!
+//! Messages are the contract's functions. Call data is the selector of the
+//! message followed by its arguments in the encoding the dispatcher reads.
+//! A contract is instantiated with the arguments of its constructor, in the
+//! same encoding but without a selector, or with the call data of the
+//! function chosen as its constructor.
+
+use std::str::FromStr;
+
+use super::error::DeployError;
+use super::scale::{encode_bytes, Input};
+use crate::compiler::address::{Address, Hash};
+use crate::compiler::pipeline::{CompilerPipeline, Source};
+use crate::compiler::polkavm::abi::{ok_type, ContractABI, MethodABI};
+use crate::compiler::wide::{wide_type_bits, WideUint};
+use crate::{CompileError, CompilerOptions};
+
+pub struct Contract {
+    pub binary: Vec<u8>,
+    pub abi: ContractABI,
+}
+
+impl Contract {
+    /// Type check and compile the contract in `source`, with a dispatcher
+    /// routing calls to its messages by selector
+    pub fn compile(name: &str, source: &str) -> Result<Self, CompileError> {
+        let options = CompilerOptions {
+            metadata: false,
+            deterministic: true,
+            ..CompilerOptions::default()
+        };
+        let result = CompilerPipeline::new(&options)
+            .with_dispatcher()
+            .run(&Source::new(name, source))?;
+        Ok(Contract {
+            binary: result.blob,
+            abi: result
+                .abi
+                .ok_or_else(|| CompileError::Codegen("No ABI generated".to_string()))?,
+        })
+    }
+
+    pub fn message(&self, name: &str) -> Result<&MethodABI, DeployError> {
+        self.abi
+            .methods
+            .iter()
+            .find(|method| method.name == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = self.abi.methods.iter().map(|m| m.name.as_str()).collect();
+                DeployError::Argument(format!(
+                    "the contract has no message `{}`, only {}",
+                    name,
+                    match names.is_empty() {
+                        true => "none".to_string(),
+                        false => names.join(", "),
+                    }
+                ))
+            })
+    }
+
+    /// The call data of `message` with `args`, each written the way it
+    /// would be in source
+    pub fn call_data(&self, message: &str, args: &[String]) -> Result<Vec<u8>, DeployError> {
+        let method = self.message(message)?;
+        let mut data = hex::decode(method.selector.trim_start_matches("0x"))
+            .map_err(|_| DeployError::Encode(format!("selector {}", method.selector)))?;
+        encode_args(method, args, &mut data)?;
+        Ok(data)
+    }
+
+    /// The input instantiating the contract with `args` to its constructor,
+    /// empty for a contract without one
+    pub fn deploy_data(&self, args: &[String]) -> Result<Vec<u8>, DeployError> {
+        let mut data = Vec::new();
+        match &self.abi.constructor {
+            Some(constructor) => encode_args(constructor, args, &mut data)?,
+            None if args.is_empty() => {}
+            None => {
+                return Err(DeployError::Argument(
+                    "the contract has no constructor taking arguments, give --constructor".into(),
+                ))
+            }
+        }
+        Ok(data)
+    }
+
+    /// The value `message` returned in `data`, written the way it would be
+    /// in source, or `None` when it returns nothing
+    pub fn decode_output(&self, message: &str, data: &[u8]) -> Result<Option<String>, DeployError> {
+        let method = self.message(message)?;
+        let Some(output) = method.outputs.first() else {
+            return Ok(None);
+        };
+        let mut input = Input::new(data);
+        let value = decode_value(ok_type(&output.type_), &mut input)?;
+        Ok(Some(value))
+    }
+}
+
+/// Append the arguments `args` of `method`, each written the way it would be in source
+fn encode_args(method: &MethodABI, args: &[String], out: &mut Vec<u8>) -> Result<(), DeployError> {
+    if args.len() != method.inputs.len() {
+        return Err(DeployError::Argument(format!(
+            "`{}` takes {} argument(s), {} given",
+            method.name,
+            method.inputs.len(),
+            args.len()
+        )));
+    }
+    for (input, arg) in method.inputs.iter().zip(args) {
+        encode_arg(&input.type_, arg, out).map_err(|e| match e {
+            DeployError::Argument(message) => {
+                DeployError::Argument(format!("{}: {}", input.name, message))
+            }
+            e => e,
+        })?;
+    }
+    Ok(())
+}
+
+fn encode_arg(ty: &str, arg: &str, out: &mut Vec<u8>) -> Result<(), DeployError> {
+    let invalid = || DeployError::Argument(format!("`{}` is not a valid {}", arg, ty));
+    match ty {
+        "u24" | "u32" => {
+            let value: u32 = arg.parse().map_err(|_| invalid())?;
+            if ty == "u24" && value >= 1 << 24 {
+                return Err(invalid());
+            }
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        "i24" | "i32" => {
+            let value: i32 = arg.parse().map_err(|_| invalid())?;
+            if ty == "i24" && !(-(1 << 23)..1 << 23).contains(&value) {
+                return Err(invalid());
+            }
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        "Bool" | "bool" => out.push(arg.parse::<bool>().map_err(|_| invalid())? as u8),
+        "Address" => {
+            out.extend_from_slice(Address::from_str(arg).map_err(|_| invalid())?.as_bytes())
+        }
+        "Hash" => out.extend_from_slice(Hash::from_str(arg).map_err(|_| invalid())?.as_bytes()),
+        "Bytes" => {
+            let digits = arg.strip_prefix("0x").ok_or_else(invalid)?;
+            encode_bytes(&hex::decode(digits).map_err(|_| invalid())?, out);
+        }
+        _ => {
+            let bits = wide_type_bits(ty).ok_or_else(|| {
+                DeployError::Argument(format!("arguments of type {} are not supported", ty))
+            })?;
+            let value = match arg.strip_prefix("0x") {
+                Some(digits) => WideUint::from_str_radix(digits, 16, bits),
+                None => WideUint::from_dec_str(arg, bits),
+            }
+            .map_err(|_| invalid())?;
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    Ok(())
+}
+
+fn decode_value(ty: &str, input: &mut Input) -> Result<String, DeployError> {
+    let word = |input: &mut Input| -> Result<[u8; 4], DeployError> {
+        Ok(input.read_bytes(4)?.try_into().unwrap())
+    };
+    Ok(match ty {
+        "u24" | "u32" => u32::from_le_bytes(word(input)?).to_string(),
+        "i24" | "i32" => i32::from_le_bytes(word(input)?).to_string(),
+        "Bool" | "bool" => input.read_bool()?.to_string(),
+        "Address" | "Hash" => format!("0x{}", hex::encode(input.read_bytes(32)?)),
+        "Bytes" => format!("0x{}", hex::encode(input.read_vec()?)),
+        _ => match wide_type_bits(ty) {
+            Some(bits) => WideUint::from_le_bytes(input.read_bytes(bits as usize / 8)?)
+                .ok_or_else(|| DeployError::Decode(format!("invalid {}", ty)))?
+                .to_string(),
+            // Other types come back as the words the dispatcher wrote
+            None => format!("0x{}", hex::encode(input.remaining())),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+        #[message]
+        fn add(a: u24, b: u24) -> u24 {
+            return a + b;
+        }
+
+        #[message]
+        fn owner_of(id: u128, active: Bool) -> Address {
+            return @0x0101010101010101010101010101010101010101010101010101010101010101;
+        }
+    "#;
+
+    #[test]
+    fn test_compile_contract() {
+        let contract = Contract::compile("adder", SOURCE).unwrap();
+        assert!(!contract.binary.is_empty());
+        assert_eq!(contract.abi.name, "adder");
+        assert!(contract.message("add").is_ok());
+        assert!(contract.message("sub").is_err());
+    }
+
+    #[test]
+    fn test_call_data() {
+        let contract = Contract::compile("adder", SOURCE).unwrap();
+        let data = contract
+            .call_data("add", &["2".to_string(), "40".to_string()])
+            .unwrap();
+        let selector = contract.message("add").unwrap().selector.clone();
+        assert_eq!(
+            format!("0x{}", hex::encode(&data)),
+            format!("{}0200000028000000", selector)
+        );
+
+        let data = contract
+            .call_data("owner_of", &["0x10".to_string(), "true".to_string()])
+            .unwrap();
+        assert_eq!(data.len(), 4 + 16 + 1);
+        assert_eq!(data[4], 0x10);
+        assert_eq!(data[20], 1);
+
+        assert!(contract.call_data("add", &["1".to_string()]).is_err());
+        assert!(contract
+            .call_data("add", &["16777216".to_string(), "0".to_string()])
+            .is_err());
+    }
+
+    #[test]
+    fn test_deploy_data() {
+        let contract = Contract::compile("adder", SOURCE).unwrap();
+        assert!(contract.abi.constructor.is_none());
+        assert_eq!(contract.deploy_data(&[]).unwrap(), Vec::<u8>::new());
+        assert!(contract.deploy_data(&["1".to_string()]).is_err());
+
+        // The constructor's arguments without a selector
+        let source = "contract Counter {\n    let count: u24;\n    fn constructor(start: u24, active: Bool) {\n        count = start;\n    }\n}\n";
+        let contract = Contract::compile("counter", source).unwrap();
+        let data = contract
+            .deploy_data(&["7".to_string(), "true".to_string()])
+            .unwrap();
+        assert_eq!(data, [7, 0, 0, 0, 1]);
+        assert!(contract.deploy_data(&[]).is_err());
+    }
+
+    #[test]
+    fn test_decode_output() {
+        let contract = Contract::compile("adder", SOURCE).unwrap();
+        assert_eq!(
+            contract.decode_output("add", &42u32.to_le_bytes()).unwrap(),
+            Some("42".to_string())
+        );
+        assert_eq!(
+            contract.decode_output("owner_of", &[1; 32]).unwrap(),
+            Some(format!("0x{}", "01".repeat(32)))
+        );
+        assert!(contract.decode_output("add", &[1]).is_err());
+        assert_eq!(ok_type("Result<u128,Error>"), "u128");
+    }
+}
@@ -0,0 +1,40 @@
+//! Errors of deploying and calling contracts on a node
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DeployError {
+    /// The node could not be reached or did not answer over HTTP
+    #[error("RPC error: {0}")]
+    Rpc(String),
+
+    /// The node answered a request with a JSON-RPC error
+    #[error("Node error {code}: {message}")]
+    Node { code: i64, message: String },
+
+    /// Data from the node that does not decode as expected
+    #[error("Invalid data from the node: {0}")]
+    Decode(String),
+
+    /// A value that does not fit the type the node expects
+    #[error("Cannot encode {0}")]
+    Encode(String),
+
+    #[error("Invalid signer: {0}")]
+    Signer(String),
+
+    /// A contract argument that does not fit its ABI type
+    #[error("Invalid argument: {0}")]
+    Argument(String),
+
+    /// The contract reverted or the extrinsic failed
+    #[error("Contract error: {0}")]
+    Contract(String),
+
+    #[error("Compile error: {0}")]
+    Compile(String),
+
+    /// An extrinsic that was not included in a block in time
+    #[error("Timed out waiting for {0}")]
+    Timeout(String),
+}
@@ -0,0 +1,226 @@
+//! What a deployment or call costs on a node, next to what the static gas
+//! profile of the contract predicts
+//!
+//! The node's dry run reports weight as `ref_time` (picoseconds of
+//! execution) and `proof_size` (bytes of proof), plus the storage deposit.
+//! The static profile counts gas in the compiler's own units, converted to
+//! `ref_time` at the rate of its cost table, the proof size of the host
+//! calls it makes, and the worst-case storage deposit. Any
+//! figure the node needs more of than the profile predicts is an
+//! underestimate: limits set from the profile would make the extrinsic
+//! fail.
+
+use serde_json::{json, Value};
+
+use super::error::DeployError;
+use super::node::DryRun;
+use crate::analyzer::costs::CostTable;
+use crate::analyzer::gas_profiler::{GasEstimate, GasProfiler};
+
+/// What the static gas profile predicts for one function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticEstimate {
+    pub function: String,
+    /// Worst-case gas
+    pub gas: u64,
+    /// Storage proof, in bytes
+    pub proof_size: u64,
+    /// Worst-case storage deposit
+    pub storage_deposit: u128,
+}
+
+impl StaticEstimate {
+    /// The estimate of `function` in the contract `source`, charging the
+    /// weights of `costs`
+    pub fn of(source: &str, function: &str, costs: &CostTable) -> Result<Self, DeployError> {
+        let profile = GasProfiler::with_costs(costs.clone())
+            .profile_source(source, "")
+            .map_err(|e| DeployError::Compile(e.to_string()))?;
+        let estimate = profile
+            .estimates
+            .iter()
+            .find(|estimate| estimate.name == function)
+            .ok_or_else(|| {
+                DeployError::Argument(format!("no static estimate of `{}`", function))
+            })?;
+        Ok(Self::from(estimate))
+    }
+}
+
+impl From<&GasEstimate> for StaticEstimate {
+    fn from(estimate: &GasEstimate) -> Self {
+        StaticEstimate {
+            function: estimate.name.clone(),
+            gas: estimate.max_cost,
+            proof_size: estimate.proof_size,
+            storage_deposit: estimate.max_storage_deposit,
+        }
+    }
+}
+
+/// A figure the node needs more of than the static estimate predicts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Underestimate {
+    pub resource: &'static str,
+    pub estimated: u128,
+    pub required: u128,
+}
+
+impl Underestimate {
+    /// How much more is required than estimated, in percent of the
+    /// estimate
+    pub fn shortfall_percent(&self) -> Option<u128> {
+        let excess = self.required - self.estimated;
+        (self.estimated > 0).then(|| excess * 100 / self.estimated)
+    }
+}
+
+/// A dry run with the static estimate of the function it ran, if any
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub dry_run: DryRun,
+    pub estimate: Option<StaticEstimate>,
+    pub ref_time_per_gas: u64,
+}
+
+impl Estimate {
+    /// `ref_time` the static estimate stands for
+    pub fn estimated_ref_time(&self) -> Option<u128> {
+        let estimate = self.estimate.as_ref()?;
+        Some(estimate.gas as u128 * self.ref_time_per_gas as u128)
+    }
+
+    /// The figures the static estimate falls short of
+    pub fn underestimates(&self) -> Vec<Underestimate> {
+        let Some(estimate) = &self.estimate else {
+            return Vec::new();
+        };
+        let mut underestimates = Vec::new();
+        let mut compare = |resource, estimated: u128, required: u128| {
+            if required > estimated {
+                underestimates.push(Underestimate {
+                    resource,
+                    estimated,
+                    required,
+                });
+            }
+        };
+        if let (Some(estimated), Some(required)) =
+            (self.estimated_ref_time(), self.dry_run.ref_time())
+        {
+            compare("ref_time", estimated, required as u128);
+        }
+        if let Some(required) = self.dry_run.proof_size() {
+            compare("proof_size", estimate.proof_size as u128, required as u128);
+        }
+        if let Some(charge) = self.dry_run.storage_charge() {
+            compare("storage deposit", estimate.storage_deposit, charge);
+        }
+        underestimates
+    }
+
+    /// The estimate as JSON, for `estimate --json`
+    pub fn to_json(&self) -> Value {
+        json!({
+            "dry_run": {
+                "ref_time": self.dry_run.ref_time(),
+                "proof_size": self.dry_run.proof_size(),
+                "storage_deposit": self.dry_run.storage_deposit,
+                "reverted": self.dry_run.reverted,
+            },
+            "static": self.estimate.as_ref().map(|estimate| json!({
+                "function": estimate.function,
+                "gas": estimate.gas,
+                "ref_time": self.estimated_ref_time().map(|ref_time| ref_time.to_string()),
+                "proof_size": estimate.proof_size,
+                "storage_deposit": estimate.storage_deposit.to_string(),
+            })),
+            "ref_time_per_gas": self.ref_time_per_gas,
+            "underestimates": self.underestimates().iter().map(|underestimate| json!({
+                "resource": underestimate.resource,
+                "estimated": underestimate.estimated.to_string(),
+                "required": underestimate.required.to_string(),
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dry_run(ref_time: u64, deposit: Value) -> DryRun {
+        DryRun {
+            data: Vec::new(),
+            reverted: false,
+            address: None,
+            gas_required: json!({ "ref_time": ref_time, "proof_size": 4096 }),
+            storage_deposit: deposit,
+        }
+    }
+
+    #[test]
+    fn test_static_estimate() {
+        let source = r#"
+            fn add(a: u24, b: u24) -> u24 {
+                return a + b;
+            }
+        "#;
+        let costs = CostTable::default();
+        let estimate = StaticEstimate::of(source, "add", &costs).unwrap();
+        assert_eq!(estimate.function, "add");
+        assert!(estimate.gas > 0);
+        assert_eq!(estimate.proof_size, 0);
+        assert_eq!(estimate.storage_deposit, 0);
+        assert!(StaticEstimate::of(source, "sub", &costs).is_err());
+
+        // Dearer instructions make a dearer estimate
+        let exact = CostTable {
+            ref_time_per_gas: 1,
+            ..costs.clone()
+        };
+        let mut dearer = exact.clone();
+        for weight in dearer.instructions.values_mut() {
+            weight.ref_time *= 2;
+        }
+        let exact = StaticEstimate::of(source, "add", &exact).unwrap();
+        let dearer = StaticEstimate::of(source, "add", &dearer).unwrap();
+        assert_eq!(dearer.gas, exact.gas * 2);
+    }
+
+    #[test]
+    fn test_underestimates() {
+        let estimate = StaticEstimate {
+            function: "add".to_string(),
+            gas: 100,
+            proof_size: 4096,
+            storage_deposit: 1_000,
+        };
+        let mut result = Estimate {
+            dry_run: dry_run(1_500_000, json!({ "Charge": "5000" })),
+            estimate: Some(estimate),
+            ref_time_per_gas: 10_000,
+        };
+        assert_eq!(result.estimated_ref_time(), Some(1_000_000));
+        let underestimates = result.underestimates();
+        assert_eq!(underestimates.len(), 2);
+        assert_eq!(underestimates[0].resource, "ref_time");
+        assert_eq!(underestimates[0].shortfall_percent(), Some(50));
+        assert_eq!(underestimates[1].required, 5000);
+        assert_eq!(underestimates[1].shortfall_percent(), Some(400));
+
+        result.dry_run = dry_run(900_000, json!({ "Refund": 7 }));
+        assert!(result.underestimates().is_empty());
+        assert_eq!(result.to_json()["underestimates"], json!([]));
+
+        result.estimate.as_mut().unwrap().proof_size = 1024;
+        let underestimates = result.underestimates();
+        assert_eq!(underestimates.len(), 1);
+        assert_eq!(underestimates[0].resource, "proof_size");
+        assert_eq!(underestimates[0].shortfall_percent(), Some(300));
+
+        result.estimate = None;
+        assert!(result.underestimates().is_empty());
+        assert_eq!(result.to_json()["static"], Value::Null);
+    }
+}
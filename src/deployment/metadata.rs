@@ -0,0 +1,591 @@
+//! Runtime metadata of a node: the types of its calls, events, storage and
+//! runtime APIs
+//!
+//! Versions 14 and 15 are read. Only version 15 describes the runtime
+//! APIs, which dry runs of contract calls need.
+
+use std::collections::BTreeMap;
+
+use super::error::DeployError;
+use super::scale::{twox128, twox256, twox64, Input};
+use crate::stdlib::crypto::CryptoFunctions;
+
+/// `meta` as a little-endian integer, in front of every metadata
+const MAGIC: u32 = 0x6174_656d;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Primitive {
+    Bool,
+    Char,
+    Str,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    U256,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    I256,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: Option<String>,
+    pub ty: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    pub name: String,
+    pub index: u8,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeDef {
+    Composite(Vec<Field>),
+    Variant(Vec<Variant>),
+    Sequence(u32),
+    Array(u32, u32),
+    Tuple(Vec<u32>),
+    Primitive(Primitive),
+    Compact(u32),
+    BitSequence,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeInfo {
+    /// Path of the Rust type, e.g. `["Option"]`
+    pub path: Vec<String>,
+    /// Names of the generic parameters and the types they stand for
+    pub params: Vec<(String, Option<u32>)>,
+    pub def: TypeDef,
+}
+
+/// How a storage map hashes its keys into storage keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageHasher {
+    Blake2_128,
+    Blake2_256,
+    Blake2_128Concat,
+    Twox128,
+    Twox256,
+    Twox64Concat,
+    Identity,
+}
+
+impl StorageHasher {
+    fn from_index(index: u8) -> Result<Self, DeployError> {
+        Ok(match index {
+            0 => StorageHasher::Blake2_128,
+            1 => StorageHasher::Blake2_256,
+            2 => StorageHasher::Blake2_128Concat,
+            3 => StorageHasher::Twox128,
+            4 => StorageHasher::Twox256,
+            5 => StorageHasher::Twox64Concat,
+            6 => StorageHasher::Identity,
+            other => {
+                return Err(DeployError::Decode(format!(
+                    "unknown storage hasher {}",
+                    other
+                )))
+            }
+        })
+    }
+
+    /// The part of a storage key standing for the encoded map key `key`
+    pub fn hash(self, key: &[u8]) -> Vec<u8> {
+        let blake2_128 = || CryptoFunctions::blake2b(key, 16).unwrap();
+        match self {
+            StorageHasher::Blake2_128 => blake2_128(),
+            StorageHasher::Blake2_256 => CryptoFunctions::blake2b_256(key).to_vec(),
+            StorageHasher::Blake2_128Concat => [blake2_128(), key.to_vec()].concat(),
+            StorageHasher::Twox128 => twox128(key).to_vec(),
+            StorageHasher::Twox256 => twox256(key).to_vec(),
+            StorageHasher::Twox64Concat => [twox64(key).as_slice(), key].concat(),
+            StorageHasher::Identity => key.to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageEntry {
+    pub name: String,
+    /// Whether the entry is a single value rather than a map
+    pub plain: bool,
+    /// Hashers of the keys of a map, one per key
+    pub hashers: Vec<StorageHasher>,
+    /// Type of the value
+    pub ty: u32,
+}
+
+impl StorageEntry {
+    /// The storage key of the entry of `pallet` under the encoded map
+    /// key `key`, or of the value itself when the entry is plain
+    pub fn key(&self, pallet: &str, key: &[u8]) -> Vec<u8> {
+        let mut storage_key = twox128(pallet.as_bytes()).to_vec();
+        storage_key.extend_from_slice(&twox128(self.name.as_bytes()));
+        if let Some(hasher) = self.hashers.first() {
+            storage_key.extend_from_slice(&hasher.hash(key));
+        }
+        storage_key
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pallet {
+    pub name: String,
+    pub index: u8,
+    pub storage: Vec<StorageEntry>,
+    /// Variant type of the pallet's calls
+    pub calls: Option<u32>,
+    /// Variant type of the pallet's errors
+    pub error: Option<u32>,
+}
+
+/// Data signed along with every extrinsic, such as the nonce or the tip
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedExtension {
+    pub identifier: String,
+    /// Type of the part included in the extrinsic
+    pub ty: u32,
+    /// Type of the part only included in the signed payload
+    pub additional: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extrinsic {
+    pub version: u8,
+    pub address: u32,
+    pub signature: u32,
+    pub signed_extensions: Vec<SignedExtension>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiMethod {
+    pub name: String,
+    pub inputs: Vec<(String, u32)>,
+    pub output: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeApi {
+    pub name: String,
+    pub methods: Vec<ApiMethod>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub version: u8,
+    pub types: BTreeMap<u32, TypeInfo>,
+    pub pallets: Vec<Pallet>,
+    pub extrinsic: Extrinsic,
+    pub apis: Vec<RuntimeApi>,
+}
+
+impl Metadata {
+    /// Decode metadata as `state_getMetadata` returns it, starting with
+    /// the `meta` magic number
+    pub fn decode(bytes: &[u8]) -> Result<Self, DeployError> {
+        let mut input = Input::new(bytes);
+        if input.read_u32()? != MAGIC {
+            return Err(DeployError::Decode("metadata without magic number".into()));
+        }
+        let version = input.read_u8()?;
+        if !matches!(version, 14 | 15) {
+            return Err(DeployError::Decode(format!(
+                "metadata version {} is not supported, expected 14 or 15",
+                version
+            )));
+        }
+
+        let types = input
+            .read_list(|input| Ok((input.read_compact_u32()?, read_type(input)?)))?
+            .into_iter()
+            .collect();
+        let pallets = input.read_list(|input| read_pallet(input, version))?;
+        let mut metadata = Metadata {
+            version,
+            types,
+            pallets,
+            extrinsic: Extrinsic {
+                version: 0,
+                address: 0,
+                signature: 0,
+                signed_extensions: Vec::new(),
+            },
+            apis: Vec::new(),
+        };
+
+        metadata.extrinsic = if version == 14 {
+            let ty = input.read_compact_u32()?;
+            let version = input.read_u8()?;
+            let signed_extensions = input.read_list(read_signed_extension)?;
+            // The address and signature are parameters of the extrinsic type
+            let param = |name: &str| {
+                metadata
+                    .types
+                    .get(&ty)
+                    .and_then(|info| info.params.iter().find(|(param, _)| param == name))
+                    .and_then(|(_, ty)| *ty)
+                    .ok_or_else(|| DeployError::Decode(format!("extrinsic without {}", name)))
+            };
+            Extrinsic {
+                version,
+                address: param("Address")?,
+                signature: param("Signature")?,
+                signed_extensions,
+            }
+        } else {
+            let version = input.read_u8()?;
+            let address = input.read_compact_u32()?;
+            let _call = input.read_compact_u32()?;
+            let signature = input.read_compact_u32()?;
+            let _extra = input.read_compact_u32()?;
+            Extrinsic {
+                version,
+                address,
+                signature,
+                signed_extensions: input.read_list(read_signed_extension)?,
+            }
+        };
+        let _runtime = input.read_compact_u32()?;
+
+        if version == 15 {
+            metadata.apis = input.read_list(|input| {
+                let name = input.read_string()?;
+                let methods = input.read_list(|input| {
+                    let name = input.read_string()?;
+                    let inputs = input
+                        .read_list(|input| Ok((input.read_string()?, input.read_compact_u32()?)))?;
+                    let output = input.read_compact_u32()?;
+                    read_docs(input)?;
+                    Ok(ApiMethod {
+                        name,
+                        inputs,
+                        output,
+                    })
+                })?;
+                read_docs(input)?;
+                Ok(RuntimeApi { name, methods })
+            })?;
+        }
+        Ok(metadata)
+    }
+
+    pub fn type_info(&self, ty: u32) -> Result<&TypeInfo, DeployError> {
+        self.types
+            .get(&ty)
+            .ok_or_else(|| DeployError::Decode(format!("unknown type id {}", ty)))
+    }
+
+    pub fn pallet(&self, name: &str) -> Option<&Pallet> {
+        self.pallets.iter().find(|pallet| pallet.name == name)
+    }
+
+    /// The method `method` of the runtime API `api`, e.g. `ReviveApi` and
+    /// `call`
+    pub fn api_method(&self, api: &str, method: &str) -> Option<&ApiMethod> {
+        self.apis
+            .iter()
+            .find(|candidate| candidate.name == api)?
+            .methods
+            .iter()
+            .find(|candidate| candidate.name == method)
+    }
+
+    /// The variants of a variant type
+    pub fn variants(&self, ty: u32) -> Result<&[Variant], DeployError> {
+        match &self.type_info(ty)?.def {
+            TypeDef::Variant(variants) => Ok(variants),
+            _ => Err(DeployError::Decode(format!("type {} is not an enum", ty))),
+        }
+    }
+
+    /// The name of the error with `index` in the pallet with
+    /// `pallet_index`, as `Pallet::Error`
+    pub fn module_error(&self, pallet_index: u8, index: u8) -> Option<String> {
+        let pallet = self.pallets.iter().find(|p| p.index == pallet_index)?;
+        let variant = self
+            .variants(pallet.error?)
+            .ok()?
+            .iter()
+            .find(|variant| variant.index == index)?;
+        Some(format!("{}::{}", pallet.name, variant.name))
+    }
+}
+
+fn read_docs(input: &mut Input) -> Result<Vec<String>, DeployError> {
+    input.read_list(Input::read_string)
+}
+
+fn read_fields(input: &mut Input) -> Result<Vec<Field>, DeployError> {
+    input.read_list(|input| {
+        let name = input.read_option(Input::read_string)?;
+        let ty = input.read_compact_u32()?;
+        let _type_name = input.read_option(Input::read_string)?;
+        read_docs(input)?;
+        Ok(Field { name, ty })
+    })
+}
+
+fn read_type(input: &mut Input) -> Result<TypeInfo, DeployError> {
+    let path = input.read_list(Input::read_string)?;
+    let params = input.read_list(|input| {
+        let name = input.read_string()?;
+        let ty = input.read_option(Input::read_compact_u32)?;
+        Ok((name, ty))
+    })?;
+    let def = match input.read_u8()? {
+        0 => TypeDef::Composite(read_fields(input)?),
+        1 => TypeDef::Variant(input.read_list(|input| {
+            let name = input.read_string()?;
+            let fields = read_fields(input)?;
+            let index = input.read_u8()?;
+            read_docs(input)?;
+            Ok(Variant {
+                name,
+                index,
+                fields,
+            })
+        })?),
+        2 => TypeDef::Sequence(input.read_compact_u32()?),
+        3 => {
+            let len = input.read_u32()?;
+            TypeDef::Array(len, input.read_compact_u32()?)
+        }
+        4 => TypeDef::Tuple(input.read_list(Input::read_compact_u32)?),
+        5 => TypeDef::Primitive(match input.read_u8()? {
+            0 => Primitive::Bool,
+            1 => Primitive::Char,
+            2 => Primitive::Str,
+            3 => Primitive::U8,
+            4 => Primitive::U16,
+            5 => Primitive::U32,
+            6 => Primitive::U64,
+            7 => Primitive::U128,
+            8 => Primitive::U256,
+            9 => Primitive::I8,
+            10 => Primitive::I16,
+            11 => Primitive::I32,
+            12 => Primitive::I64,
+            13 => Primitive::I128,
+            14 => Primitive::I256,
+            other => {
+                return Err(DeployError::Decode(format!(
+                    "unknown primitive type {}",
+                    other
+                )))
+            }
+        }),
+        6 => TypeDef::Compact(input.read_compact_u32()?),
+        7 => {
+            input.read_compact_u32()?;
+            input.read_compact_u32()?;
+            TypeDef::BitSequence
+        }
+        other => return Err(DeployError::Decode(format!("unknown type kind {}", other))),
+    };
+    read_docs(input)?;
+    Ok(TypeInfo { path, params, def })
+}
+
+fn read_pallet(input: &mut Input, version: u8) -> Result<Pallet, DeployError> {
+    let name = input.read_string()?;
+    let storage = input
+        .read_option(|input| {
+            let _prefix = input.read_string()?;
+            input.read_list(|input| {
+                let name = input.read_string()?;
+                let _modifier = input.read_u8()?;
+                let (plain, hashers, ty) = match input.read_u8()? {
+                    0 => (true, Vec::new(), input.read_compact_u32()?),
+                    1 => {
+                        let hashers =
+                            input.read_list(|input| StorageHasher::from_index(input.read_u8()?))?;
+                        let _key = input.read_compact_u32()?;
+                        (false, hashers, input.read_compact_u32()?)
+                    }
+                    other => {
+                        return Err(DeployError::Decode(format!(
+                            "unknown storage entry kind {}",
+                            other
+                        )))
+                    }
+                };
+                let _default = input.read_vec()?;
+                read_docs(input)?;
+                Ok(StorageEntry {
+                    name,
+                    plain,
+                    hashers,
+                    ty,
+                })
+            })
+        })?
+        .unwrap_or_default();
+    let calls = input.read_option(Input::read_compact_u32)?;
+    let _event = input.read_option(Input::read_compact_u32)?;
+    let _constants = input.read_list(|input| {
+        input.read_string()?;
+        input.read_compact_u32()?;
+        input.read_vec()?;
+        read_docs(input)
+    })?;
+    let error = input.read_option(Input::read_compact_u32)?;
+    let index = input.read_u8()?;
+    if version >= 15 {
+        read_docs(input)?;
+    }
+    Ok(Pallet {
+        name,
+        index,
+        storage,
+        calls,
+        error,
+    })
+}
+
+fn read_signed_extension(input: &mut Input) -> Result<SignedExtension, DeployError> {
+    Ok(SignedExtension {
+        identifier: input.read_string()?,
+        ty: input.read_compact_u32()?,
+        additional: input.read_compact_u32()?,
+    })
+}
+
+#[cfg(test)]
+impl Metadata {
+    /// Metadata with the types `types`, numbered from 0, and nothing else
+    pub(crate) fn with_types(types: Vec<(&str, TypeDef)>) -> Self {
+        Metadata {
+            version: 15,
+            types: types
+                .into_iter()
+                .enumerate()
+                .map(|(id, (name, def))| {
+                    let path = name.split("::").filter(|s| !s.is_empty());
+                    let info = TypeInfo {
+                        path: path.map(str::to_string).collect(),
+                        params: Vec::new(),
+                        def,
+                    };
+                    (id as u32, info)
+                })
+                .collect(),
+            pallets: Vec::new(),
+            extrinsic: Extrinsic {
+                version: 4,
+                address: 0,
+                signature: 0,
+                signed_extensions: Vec::new(),
+            },
+            apis: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deployment::scale::{encode_bytes, encode_compact};
+
+    fn string(text: &str, out: &mut Vec<u8>) {
+        encode_bytes(text.as_bytes(), out);
+    }
+
+    /// Version 14 metadata of a runtime with a `Contracts` pallet at index
+    /// 8 that has a single error
+    fn metadata_v14() -> Vec<u8> {
+        let mut out = MAGIC.to_le_bytes().to_vec();
+        out.push(14);
+
+        // Types: u8, the extrinsic and the pallet's errors. Lengths and
+        // type ids are compact, four times their value.
+        encode_compact(3, &mut out);
+        out.extend([0, 0, 0, 5, 3, 0]);
+        out.extend([4, 4]);
+        string("UncheckedExtrinsic", &mut out);
+        out.push(8);
+        for param in ["Address", "Signature"] {
+            string(param, &mut out);
+            out.extend([1, 0]);
+        }
+        out.extend([0, 0, 0]);
+        out.extend([8, 4]);
+        string("Error", &mut out);
+        out.extend([0, 1, 4]);
+        string("TooBig", &mut out);
+        out.extend([0, 3, 0, 0]);
+
+        // Pallets
+        out.push(4);
+        string("Contracts", &mut out);
+        out.extend([0, 0, 0, 0, 1, 8, 8]);
+
+        // Extrinsic and runtime type
+        out.extend([4, 4, 4]);
+        string("CheckNonce", &mut out);
+        out.extend([0, 0, 0]);
+        out
+    }
+
+    #[test]
+    fn test_decode_v14() {
+        let metadata = Metadata::decode(&metadata_v14()).unwrap();
+        assert_eq!(metadata.version, 14);
+        assert_eq!(metadata.types.len(), 3);
+        assert_eq!(metadata.type_info(1).unwrap().path, ["UncheckedExtrinsic"]);
+        assert_eq!(metadata.pallet("Contracts").unwrap().index, 8);
+        assert_eq!(
+            metadata.module_error(8, 3).as_deref(),
+            Some("Contracts::TooBig")
+        );
+        assert_eq!(metadata.module_error(8, 4), None);
+        assert_eq!(metadata.extrinsic.version, 4);
+        assert_eq!(
+            metadata.extrinsic.signed_extensions[0].identifier,
+            "CheckNonce"
+        );
+        assert!(metadata.apis.is_empty());
+        assert!(metadata.variants(0).is_err());
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let mut bytes = metadata_v14();
+        bytes[4] = 13;
+        assert!(Metadata::decode(&bytes).is_err());
+        bytes[0] = 0;
+        assert!(Metadata::decode(&bytes).is_err());
+        let bytes = metadata_v14();
+        assert!(Metadata::decode(&bytes[..bytes.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn test_storage_key() {
+        // `System.Account` of the development account Alice
+        let alice = "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
+        let entry = StorageEntry {
+            name: "Account".to_string(),
+            plain: false,
+            hashers: vec![StorageHasher::Blake2_128Concat],
+            ty: 0,
+        };
+        assert_eq!(
+            hex::encode(entry.key("System", &hex::decode(alice).unwrap())),
+            format!(
+                "26aa394eea5630e07c48ae0c9558cef7b99d880ec681799c0cf30e8886371da9\
+                 de1e86a9a8c739864cf3cc5ec2bea59f{}",
+                alice
+            )
+        );
+        assert_eq!(StorageHasher::Twox64Concat.hash(b"key")[8..], *b"key");
+        assert_eq!(StorageHasher::Identity.hash(b"key"), b"key");
+        assert!(StorageHasher::from_index(7).is_err());
+    }
+}
@@ -2,12 +2,32 @@
 //! Issue #23 - Contract Deployment and Management Tools
 
 mod config;
+mod contract;
 mod deployer;
+mod error;
+mod estimate;
+mod metadata;
+mod node;
+mod rpc;
+mod scale;
+mod signer;
 mod state;
+mod value;
+mod verify;
 
 pub use config::{DeploymentConfig, Environment, NetworkConfig};
+pub use contract::Contract;
 pub use deployer::ContractDeployer;
+pub use error::DeployError;
+pub use estimate::{Estimate, StaticEstimate, Underestimate};
+pub use metadata::Metadata;
+pub use node::{ChainParams, ContractsPallet, DryRun, Node};
+pub use rpc::RpcClient;
+pub use signer::Signer;
 pub use state::{DeploymentState, DeploymentStatus};
+pub use verify::{
+    package_contract, parse_code_hash, BuildSettings, Rebuild, Reference, Verification,
+};
 
 /// Initialize deployment system with environment
 pub fn init_deployment(env: Environment) -> DeploymentConfig {
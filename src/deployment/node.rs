@@ -0,0 +1,914 @@
+//! A live Substrate node with a contracts pallet: uploading and
+//! instantiating contracts, calling them and querying them
+//!
+//! `pallet-revive` is used when the runtime has it, `pallet-contracts`
+//! otherwise. Every instantiation or call is dry-run first through the
+//! pallet's runtime API, which also tells the weight and storage deposit
+//! the extrinsic needs.
+
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use super::error::DeployError;
+use super::metadata::Metadata;
+use super::rpc::RpcClient;
+use super::scale::{encode_compact, Input};
+use super::signer::Signer;
+use super::state::{DeploymentState, DeploymentStatus};
+use crate::compiler::address::{Address, DEFAULT_SS58_PREFIX};
+use crate::stdlib::crypto::CryptoFunctions;
+
+/// Version of the signed extrinsics sent, with the signed bit set
+const SIGNED_EXTRINSIC_V4: u8 = 0x84;
+
+/// Signed payloads longer than this are signed by their hash
+const MAX_UNHASHED_PAYLOAD: usize = 256;
+
+/// How long to wait for an extrinsic to be included in a block
+const INCLUSION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How often to look for new blocks while waiting
+const BLOCK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Return flag of a contract that reverted
+const FLAG_REVERT: u64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContractsPallet {
+    Revive,
+    Contracts,
+}
+
+impl ContractsPallet {
+    pub fn name(self) -> &'static str {
+        match self {
+            ContractsPallet::Revive => "Revive",
+            ContractsPallet::Contracts => "Contracts",
+        }
+    }
+
+    fn api(self) -> &'static str {
+        match self {
+            ContractsPallet::Revive => "ReviveApi",
+            ContractsPallet::Contracts => "ContractsApi",
+        }
+    }
+
+    /// The hash the pallet stores `code` under: Keccak-256 for
+    /// `pallet-revive`, BLAKE2b-256 for `pallet-contracts`
+    pub fn code_hash(self, code: &[u8]) -> [u8; 32] {
+        match self {
+            ContractsPallet::Revive => CryptoFunctions::keccak256(code),
+            ContractsPallet::Contracts => CryptoFunctions::blake2b_256(code),
+        }
+    }
+}
+
+impl FromStr for ContractsPallet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "revive" => Ok(ContractsPallet::Revive),
+            "contracts" => Ok(ContractsPallet::Contracts),
+            _ => Err(format!(
+                "Unknown contracts pallet '{}', expected revive or contracts",
+                s
+            )),
+        }
+    }
+}
+
+/// What the chain checks each signed extrinsic against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainParams {
+    pub genesis_hash: [u8; 32],
+    pub spec_version: u32,
+    pub tx_version: u32,
+}
+
+/// The outcome of a dry run
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRun {
+    /// What the contract returned
+    pub data: Vec<u8>,
+    pub reverted: bool,
+    /// Address of the instantiated contract
+    pub address: Option<String>,
+    /// Weight the extrinsic needs, as `{ref_time, proof_size}`
+    pub gas_required: Value,
+    /// Storage deposit the extrinsic is charged
+    pub storage_deposit: Value,
+}
+
+impl DryRun {
+    pub fn ref_time(&self) -> Option<u64> {
+        weight_part(&self.gas_required, "ref_time")
+    }
+
+    pub fn proof_size(&self) -> Option<u64> {
+        weight_part(&self.gas_required, "proof_size")
+    }
+
+    /// The storage deposit charged, or `None` when the dry run refunds
+    /// deposit instead
+    pub fn storage_charge(&self) -> Option<u128> {
+        match self.storage_deposit.get("Charge")? {
+            Value::Number(amount) => amount.as_u64().map(u128::from),
+            Value::String(amount) => amount.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+fn weight_part(weight: &Value, name: &str) -> Option<u64> {
+    match weight.get(name)? {
+        Value::Number(amount) => amount.as_u64(),
+        Value::String(amount) => amount.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Where a submitted extrinsic ended up
+struct Inclusion {
+    hash: String,
+    block_hash: String,
+    block_number: u64,
+    index: u32,
+}
+
+pub struct Node {
+    rpc: RpcClient,
+    metadata: Metadata,
+    pallet: ContractsPallet,
+    params: ChainParams,
+    ss58_prefix: u16,
+}
+
+impl Node {
+    /// Connect to the node at `url` and read its runtime metadata
+    pub fn connect(url: &str) -> Result<Self, DeployError> {
+        let rpc = RpcClient::new(url)?;
+        let metadata = Metadata::decode(&fetch_metadata(&rpc)?)?;
+        let pallet = if metadata.pallet("Revive").is_some() {
+            ContractsPallet::Revive
+        } else if metadata.pallet("Contracts").is_some() {
+            ContractsPallet::Contracts
+        } else {
+            return Err(DeployError::Rpc(format!(
+                "{} has neither pallet-revive nor pallet-contracts",
+                url
+            )));
+        };
+        if metadata.version < 15 {
+            return Err(DeployError::Decode(
+                "the node's metadata does not describe its runtime APIs, version 15 is required"
+                    .into(),
+            ));
+        }
+
+        let version = rpc.request("state_getRuntimeVersion", json!([]))?;
+        let version_field = |name: &str| {
+            version
+                .get(name)
+                .and_then(Value::as_u64)
+                .and_then(|value| u32::try_from(value).ok())
+                .ok_or_else(|| DeployError::Decode(format!("runtime version without {}", name)))
+        };
+        let genesis_hash = hex_value(&rpc.request("chain_getBlockHash", json!([0]))?)?;
+        let params = ChainParams {
+            genesis_hash: genesis_hash
+                .try_into()
+                .map_err(|_| DeployError::Decode("genesis hash of the wrong length".into()))?,
+            spec_version: version_field("specVersion")?,
+            tx_version: version_field("transactionVersion")?,
+        };
+        let ss58_prefix = rpc
+            .request("system_properties", json!([]))?
+            .get("ss58Format")
+            .and_then(Value::as_u64)
+            .and_then(|prefix| u16::try_from(prefix).ok())
+            .unwrap_or(DEFAULT_SS58_PREFIX);
+
+        Ok(Node {
+            rpc,
+            metadata,
+            pallet,
+            params,
+            ss58_prefix,
+        })
+    }
+
+    pub fn pallet(&self) -> ContractsPallet {
+        self.pallet
+    }
+
+    /// The hash of the code the contract at `address` runs
+    pub fn code_hash_of(&self, address: &str) -> Result<[u8; 32], DeployError> {
+        let pallet = self.pallet.name();
+        let entry = self
+            .metadata
+            .pallet(pallet)
+            .and_then(|pallet| {
+                pallet
+                    .storage
+                    .iter()
+                    .find(|entry| entry.name == "ContractInfoOf" && !entry.plain)
+            })
+            .ok_or_else(|| {
+                DeployError::Decode(format!("the runtime has no {}.ContractInfoOf", pallet))
+            })?;
+        let account = hex_value(&json!(self.contract_address(address)?))?;
+        let key = entry.key(pallet, &account);
+        let info = match self.rpc.request("state_getStorage", json!([hex(&key)]))? {
+            Value::Null => {
+                return Err(DeployError::Argument(format!(
+                    "there is no contract at {}",
+                    address
+                )))
+            }
+            stored => self
+                .metadata
+                .decode_value(entry.ty, &mut Input::new(&hex_value(&stored)?))?,
+        };
+        let code_hash = info
+            .get("code_hash")
+            .ok_or_else(|| DeployError::Decode("contract info without a code hash".into()))?;
+        hex_value(code_hash)?
+            .try_into()
+            .map_err(|_| DeployError::Decode("code hash of the wrong length".into()))
+    }
+
+    /// Dry-run uploading `code` and instantiating it with `data`
+    pub fn dry_run_instantiate(
+        &self,
+        signer: &Signer,
+        code: &[u8],
+        data: &[u8],
+        value: u128,
+        salt: &[u8; 32],
+    ) -> Result<DryRun, DeployError> {
+        let result = self.runtime_call(
+            "instantiate",
+            &json!({
+                "origin": hex(signer.account().as_bytes()),
+                "value": value.to_string(),
+                "gas_limit": null,
+                "storage_deposit_limit": null,
+                "code": { "Upload": hex(code) },
+                "data": hex(data),
+                "salt": hex(salt),
+            }),
+        )?;
+        let output = dry_run_output(&self.metadata, &result)?;
+        let address = output
+            .get("addr")
+            .or_else(|| output.get("account_id"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let mut dry_run = read_dry_run(&result, output.get("result").unwrap_or(&Value::Null))?;
+        dry_run.address = address;
+        Ok(dry_run)
+    }
+
+    /// Dry-run calling the contract at `dest` with `data`
+    pub fn dry_run_call(
+        &self,
+        signer: &Signer,
+        dest: &str,
+        data: &[u8],
+        value: u128,
+    ) -> Result<DryRun, DeployError> {
+        let result = self.runtime_call(
+            "call",
+            &json!({
+                "origin": hex(signer.account().as_bytes()),
+                "dest": self.contract_address(dest)?,
+                "value": value.to_string(),
+                "gas_limit": null,
+                "storage_deposit_limit": null,
+                "input_data": hex(data),
+            }),
+        )?;
+        let output = dry_run_output(&self.metadata, &result)?;
+        read_dry_run(&result, &output)
+    }
+
+    /// Upload `code` and instantiate it with `data`, waiting for the
+    /// extrinsic to be included in a block
+    pub fn instantiate(
+        &self,
+        signer: &Signer,
+        code: &[u8],
+        data: &[u8],
+        value: u128,
+        salt: &[u8; 32],
+    ) -> Result<DeploymentState, DeployError> {
+        let dry_run = self.mapped(signer, || {
+            self.dry_run_instantiate(signer, code, data, value, salt)
+        })?;
+        if dry_run.reverted {
+            return Err(DeployError::Contract(format!(
+                "instantiation reverted with 0x{}",
+                hex::encode(&dry_run.data)
+            )));
+        }
+
+        let mut state = DeploymentState::new();
+        state.set_status(DeploymentStatus::Deploying);
+        let inclusion = self.submit(
+            signer,
+            "instantiate_with_code",
+            &json!({
+                "value": value.to_string(),
+                "gas_limit": dry_run.gas_required,
+                "storage_deposit_limit": deposit_limit(&dry_run.storage_deposit),
+                "code": hex(code),
+                "data": hex(data),
+                "salt": hex(salt),
+            }),
+        )?;
+        let events = self.events(&inclusion)?;
+        let address = events
+            .iter()
+            .filter_map(|event| event.get(self.pallet.name())?.get("Instantiated"))
+            .find_map(|instantiated| instantiated.get("contract")?.as_str())
+            .map(str::to_string)
+            .or_else(|| dry_run.address.clone())
+            .ok_or_else(|| DeployError::Contract("no contract was instantiated".into()))?;
+
+        state.set_contract_address(&address);
+        self.record(&mut state, &inclusion, &dry_run);
+        Ok(state)
+    }
+
+    /// Call the contract at `dest` with `data`, waiting for the extrinsic
+    /// to be included in a block
+    pub fn call(
+        &self,
+        signer: &Signer,
+        dest: &str,
+        data: &[u8],
+        value: u128,
+    ) -> Result<(DeploymentState, DryRun), DeployError> {
+        let dry_run = self.mapped(signer, || self.dry_run_call(signer, dest, data, value))?;
+        if dry_run.reverted {
+            return Err(DeployError::Contract(format!(
+                "call reverted with 0x{}",
+                hex::encode(&dry_run.data)
+            )));
+        }
+
+        let mut state = DeploymentState::new();
+        state.set_status(DeploymentStatus::Confirming);
+        state.set_contract_address(dest);
+        let inclusion = self.submit(
+            signer,
+            "call",
+            &json!({
+                "dest": self.contract_address(dest)?,
+                "value": value.to_string(),
+                "gas_limit": dry_run.gas_required,
+                "storage_deposit_limit": deposit_limit(&dry_run.storage_deposit),
+                "data": hex(data),
+            }),
+        )?;
+        self.events(&inclusion)?;
+        self.record(&mut state, &inclusion, &dry_run);
+        Ok((state, dry_run))
+    }
+
+    /// Run `dry_run`, first mapping the signer's account to an address
+    /// when `pallet-revive` needs it to be
+    fn mapped(
+        &self,
+        signer: &Signer,
+        dry_run: impl Fn() -> Result<DryRun, DeployError>,
+    ) -> Result<DryRun, DeployError> {
+        match dry_run() {
+            Err(DeployError::Contract(error)) if error == "Revive::AccountUnmapped" => {
+                let inclusion = self.submit(signer, "map_account", &json!({}))?;
+                self.events(&inclusion)?;
+                dry_run()
+            }
+            result => result,
+        }
+    }
+
+    fn record(&self, state: &mut DeploymentState, inclusion: &Inclusion, dry_run: &DryRun) {
+        state.set_transaction_hash(&inclusion.hash);
+        state.set_block_number(inclusion.block_number);
+        if let Some(ref_time) = dry_run.ref_time() {
+            state.set_gas_used(ref_time);
+        }
+        state.complete();
+    }
+
+    /// A contract address as the pallet's calls take it: a `0x` hex
+    /// address, or for `pallet-contracts` an SS58 account too
+    fn contract_address(&self, address: &str) -> Result<String, DeployError> {
+        if address.starts_with("0x") {
+            return Ok(address.to_string());
+        }
+        match self.pallet {
+            ContractsPallet::Contracts => Address::from_ss58(address)
+                .map(|address| hex(address.as_bytes()))
+                .map_err(|e| DeployError::Argument(format!("{}: {}", address, e))),
+            ContractsPallet::Revive => Err(DeployError::Argument(format!(
+                "{} is not a 0x-prefixed contract address",
+                address
+            ))),
+        }
+    }
+
+    /// Call a method of the pallet's runtime API with its inputs taken by
+    /// name from `args`
+    fn runtime_call(&self, method: &str, args: &Value) -> Result<Value, DeployError> {
+        let api = self.pallet.api();
+        let method_info = self.metadata.api_method(api, method).ok_or_else(|| {
+            DeployError::Decode(format!("the runtime has no {}::{}", api, method))
+        })?;
+        let mut input = Vec::new();
+        for (name, ty) in &method_info.inputs {
+            let arg = args.get(name).unwrap_or(&Value::Null);
+            self.metadata.encode_value(*ty, arg, &mut input)?;
+        }
+        let output = self.rpc.request(
+            "state_call",
+            json!([format!("{}_{}", api, method), hex(&input)]),
+        )?;
+        let output = hex_value(&output)?;
+        self.metadata
+            .decode_value(method_info.output, &mut Input::new(&output))
+    }
+
+    /// Sign and submit a call of the contracts pallet, then wait for it to
+    /// be included in a block
+    fn submit(
+        &self,
+        signer: &Signer,
+        name: &str,
+        fields: &Value,
+    ) -> Result<Inclusion, DeployError> {
+        let call = encode_call(&self.metadata, self.pallet.name(), name, fields)?;
+        let account = signer
+            .account()
+            .to_ss58(self.ss58_prefix)
+            .map_err(|e| DeployError::Signer(e.to_string()))?;
+        let nonce = self
+            .rpc
+            .request("system_accountNextIndex", json!([account]))?
+            .as_u64()
+            .ok_or_else(|| DeployError::Decode("account nonce".into()))?;
+        let extrinsic = sign_extrinsic(&self.metadata, &self.params, signer, &call, nonce)?;
+        let extrinsic = hex(&extrinsic);
+
+        let mut next_block = self.best_block()? + 1;
+        let hash = self
+            .rpc
+            .request("author_submitExtrinsic", json!([extrinsic]))?
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+
+        let start = Instant::now();
+        loop {
+            let best_block = self.best_block()?;
+            while next_block <= best_block {
+                let number = next_block;
+                let block_hash = self.rpc.request("chain_getBlockHash", json!([number]))?;
+                let block = self.rpc.request("chain_getBlock", json!([block_hash]))?;
+                let extrinsics = block
+                    .pointer("/block/extrinsics")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| DeployError::Decode(format!("block {}", number)))?;
+                if let Some(index) = extrinsics
+                    .iter()
+                    .position(|candidate| candidate.as_str() == Some(extrinsic.as_str()))
+                {
+                    return Ok(Inclusion {
+                        hash,
+                        block_hash: block_hash.as_str().unwrap_or_default().to_string(),
+                        block_number: number,
+                        index: index as u32,
+                    });
+                }
+                next_block += 1;
+            }
+            if start.elapsed() > INCLUSION_TIMEOUT {
+                return Err(DeployError::Timeout(format!("extrinsic {}", hash)));
+            }
+            thread::sleep(BLOCK_POLL_INTERVAL);
+        }
+    }
+
+    fn best_block(&self) -> Result<u64, DeployError> {
+        let header = self.rpc.request("chain_getHeader", json!([]))?;
+        header
+            .get("number")
+            .and_then(Value::as_str)
+            .and_then(|number| u64::from_str_radix(number.trim_start_matches("0x"), 16).ok())
+            .ok_or_else(|| DeployError::Decode("block header without a number".into()))
+    }
+
+    /// The events of an included extrinsic, failing when it failed
+    fn events(&self, inclusion: &Inclusion) -> Result<Vec<Value>, DeployError> {
+        let system = self
+            .metadata
+            .pallet("System")
+            .ok_or_else(|| DeployError::Decode("the runtime has no System pallet".into()))?;
+        let entry = system
+            .storage
+            .iter()
+            .find(|entry| entry.name == "Events" && entry.plain)
+            .ok_or_else(|| DeployError::Decode("the runtime has no System.Events".into()))?;
+        let key = entry.key("System", &[]);
+        let stored = self
+            .rpc
+            .request("state_getStorage", json!([hex(&key), inclusion.block_hash]))?;
+        let records = match stored {
+            Value::Null => json!([]),
+            stored => self
+                .metadata
+                .decode_value(entry.ty, &mut Input::new(&hex_value(&stored)?))?,
+        };
+
+        let mut events = Vec::new();
+        for record in records.as_array().into_iter().flatten() {
+            let index = record
+                .get("phase")
+                .and_then(|phase| phase.get("ApplyExtrinsic"))
+                .and_then(Value::as_u64);
+            if index != Some(inclusion.index as u64) {
+                continue;
+            }
+            let event = record.get("event").cloned().unwrap_or(Value::Null);
+            if let Some(failed) = event.pointer("/System/ExtrinsicFailed") {
+                let error = failed.get("dispatch_error").unwrap_or(failed);
+                return Err(DeployError::Contract(describe_error(&self.metadata, error)));
+            }
+            events.push(event);
+        }
+        Ok(events)
+    }
+}
+
+/// The `Ok` value of the result of a dry run
+fn dry_run_output(metadata: &Metadata, result: &Value) -> Result<Value, DeployError> {
+    match result.get("result") {
+        Some(Value::Object(outcome)) if outcome.contains_key("Ok") => Ok(outcome["Ok"].clone()),
+        Some(Value::Object(outcome)) if outcome.contains_key("Err") => Err(DeployError::Contract(
+            describe_error(metadata, &outcome["Err"]),
+        )),
+        _ => Err(DeployError::Decode(format!("dry run result {}", result))),
+    }
+}
+
+/// A dry run from the result of a runtime API call and its `Ok` value
+fn read_dry_run(result: &Value, output: &Value) -> Result<DryRun, DeployError> {
+    let flags = output.get("flags").unwrap_or(&Value::Null);
+    let flags = flags.get("bits").unwrap_or(flags).as_u64().unwrap_or(0);
+    Ok(DryRun {
+        data: output
+            .get("data")
+            .map(hex_value)
+            .transpose()?
+            .unwrap_or_default(),
+        reverted: flags & FLAG_REVERT != 0,
+        address: None,
+        gas_required: result.get("gas_required").cloned().unwrap_or(Value::Null),
+        storage_deposit: result
+            .get("storage_deposit")
+            .cloned()
+            .unwrap_or(Value::Null),
+    })
+}
+
+/// A dispatch error as `Pallet::Error` when a pallet raised it
+fn describe_error(metadata: &Metadata, error: &Value) -> String {
+    let module_error = error.get("Module").and_then(|module| {
+        let pallet = u8::try_from(module.get("index")?.as_u64()?).ok()?;
+        let index = match module.get("error")? {
+            Value::String(bytes) => *hex::decode(bytes.trim_start_matches("0x")).ok()?.first()?,
+            number => u8::try_from(number.as_u64()?).ok()?,
+        };
+        metadata.module_error(pallet, index)
+    });
+    module_error.unwrap_or_else(|| match error {
+        Value::String(name) => name.clone(),
+        error => error.to_string(),
+    })
+}
+
+/// The metadata of the node's runtime, version 15 when it has it
+fn fetch_metadata(rpc: &RpcClient) -> Result<Vec<u8>, DeployError> {
+    let version = hex(&15u32.to_le_bytes());
+    if let Ok(output) = rpc.request(
+        "state_call",
+        json!(["Metadata_metadata_at_version", version]),
+    ) {
+        let output = hex_value(&output)?;
+        let mut input = Input::new(&output);
+        if let Some(metadata) = input.read_option(|input| input.read_vec())? {
+            return Ok(metadata.to_vec());
+        }
+    }
+    hex_value(&rpc.request("state_getMetadata", json!([]))?)
+}
+
+/// The call `name` of `pallet` with `fields`
+pub fn encode_call(
+    metadata: &Metadata,
+    pallet: &str,
+    name: &str,
+    fields: &Value,
+) -> Result<Vec<u8>, DeployError> {
+    let pallet = metadata
+        .pallet(pallet)
+        .ok_or_else(|| DeployError::Encode(format!("calls of the missing pallet {}", pallet)))?;
+    let calls = pallet
+        .calls
+        .ok_or_else(|| DeployError::Encode(format!("calls of {}, which has none", pallet.name)))?;
+    let variant = metadata
+        .variants(calls)?
+        .iter()
+        .find(|variant| variant.name == name)
+        .ok_or_else(|| {
+            DeployError::Encode(format!("{}::{}, which does not exist", pallet.name, name))
+        })?;
+    let mut call = vec![pallet.index, variant.index];
+    metadata.encode_fields(&variant.fields, fields, &mut call)?;
+    Ok(call)
+}
+
+/// A signed extrinsic of `call`, with its length in front
+pub fn sign_extrinsic(
+    metadata: &Metadata,
+    params: &ChainParams,
+    signer: &Signer,
+    call: &[u8],
+    nonce: u64,
+) -> Result<Vec<u8>, DeployError> {
+    let genesis = hex(&params.genesis_hash);
+    let mut extra = Vec::new();
+    let mut additional = Vec::new();
+    for extension in &metadata.extrinsic.signed_extensions {
+        let (extra_value, additional_value) = match extension.identifier.as_str() {
+            "CheckSpecVersion" => (Value::Null, json!(params.spec_version)),
+            "CheckTxVersion" => (Value::Null, json!(params.tx_version)),
+            "CheckGenesis" => (Value::Null, json!(genesis)),
+            "CheckMortality" | "CheckEra" => (json!("Immortal"), json!(genesis)),
+            "CheckNonce" => (json!(nonce), Value::Null),
+            "ChargeTransactionPayment" => (json!(0), Value::Null),
+            "ChargeAssetTxPayment" => (json!({ "tip": 0, "asset_id": null }), Value::Null),
+            "CheckMetadataHash" => (json!({ "mode": "Disabled" }), Value::Null),
+            // Other extensions are checked by the runtime alone
+            _ => (Value::Null, Value::Null),
+        };
+        let context = |e: DeployError| match e {
+            DeployError::Encode(message) => DeployError::Encode(format!(
+                "{} (signed extension {})",
+                message, extension.identifier
+            )),
+            e => e,
+        };
+        metadata
+            .encode_value(extension.ty, &extra_value, &mut extra)
+            .map_err(context)?;
+        metadata
+            .encode_value(extension.additional, &additional_value, &mut additional)
+            .map_err(context)?;
+    }
+
+    let mut payload = call.to_vec();
+    payload.extend_from_slice(&extra);
+    payload.extend_from_slice(&additional);
+    let signature = if payload.len() > MAX_UNHASHED_PAYLOAD {
+        signer.sign(&CryptoFunctions::blake2b_256(&payload))
+    } else {
+        signer.sign(&payload)
+    };
+
+    let mut body = vec![SIGNED_EXTRINSIC_V4];
+    metadata.encode_value(
+        metadata.extrinsic.address,
+        &json!(hex(signer.account().as_bytes())),
+        &mut body,
+    )?;
+    metadata.encode_value(
+        metadata.extrinsic.signature,
+        &json!({ "Sr25519": hex(&signature) }),
+        &mut body,
+    )?;
+    body.extend_from_slice(&extra);
+    body.extend_from_slice(call);
+
+    let mut extrinsic = Vec::new();
+    encode_compact(body.len() as u128, &mut extrinsic);
+    extrinsic.extend_from_slice(&body);
+    Ok(extrinsic)
+}
+
+/// The storage deposit limit covering a dry run's deposit
+fn deposit_limit(storage_deposit: &Value) -> Value {
+    storage_deposit.get("Charge").cloned().unwrap_or(json!(0))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn hex_value(value: &Value) -> Result<Vec<u8>, DeployError> {
+    value
+        .as_str()
+        .and_then(|text| text.strip_prefix("0x"))
+        .and_then(|digits| hex::decode(digits).ok())
+        .ok_or_else(|| DeployError::Decode(format!("{} is not hex", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deployment::metadata::{
+        Field, Pallet, Primitive, SignedExtension, TypeDef, Variant,
+    };
+
+    fn field(name: Option<&str>, ty: u32) -> Field {
+        Field {
+            name: name.map(str::to_string),
+            ty,
+        }
+    }
+
+    fn variant(name: &str, index: u8, fields: Vec<Field>) -> Variant {
+        Variant {
+            name: name.to_string(),
+            index,
+            fields,
+        }
+    }
+
+    fn extension(identifier: &str, ty: u32, additional: u32) -> SignedExtension {
+        SignedExtension {
+            identifier: identifier.to_string(),
+            ty,
+            additional,
+        }
+    }
+
+    /// Metadata of a runtime with `System::remark` and the usual signed
+    /// extensions
+    fn metadata() -> Metadata {
+        let mut metadata = Metadata::with_types(vec![
+            ("", TypeDef::Primitive(Primitive::U8)),
+            ("", TypeDef::Array(32, 0)),
+            ("AccountId32", TypeDef::Composite(vec![field(None, 1)])),
+            (
+                "MultiAddress",
+                TypeDef::Variant(vec![variant("Id", 0, vec![field(None, 2)])]),
+            ),
+            ("", TypeDef::Array(64, 0)),
+            ("Signature", TypeDef::Composite(vec![field(None, 4)])),
+            (
+                "MultiSignature",
+                TypeDef::Variant(vec![
+                    variant("Ed25519", 0, vec![field(None, 5)]),
+                    variant("Sr25519", 1, vec![field(None, 5)]),
+                ]),
+            ),
+            ("", TypeDef::Primitive(Primitive::U32)),
+            ("", TypeDef::Compact(7)),
+            (
+                "Era",
+                TypeDef::Variant(vec![variant("Immortal", 0, vec![])]),
+            ),
+            ("CheckMortality", TypeDef::Composite(vec![field(None, 9)])),
+            ("H256", TypeDef::Composite(vec![field(None, 1)])),
+            ("CheckNonce", TypeDef::Composite(vec![field(None, 8)])),
+            ("", TypeDef::Tuple(vec![])),
+            ("", TypeDef::Sequence(0)),
+            (
+                "Call",
+                TypeDef::Variant(vec![variant("remark", 0, vec![field(Some("remark"), 14)])]),
+            ),
+            (
+                "Error",
+                TypeDef::Variant(vec![variant("CallFiltered", 5, vec![])]),
+            ),
+        ]);
+        metadata.pallets.push(Pallet {
+            name: "System".to_string(),
+            index: 0,
+            storage: Vec::new(),
+            calls: Some(15),
+            error: Some(16),
+        });
+        metadata.extrinsic.address = 3;
+        metadata.extrinsic.signature = 6;
+        metadata.extrinsic.signed_extensions = vec![
+            extension("CheckSpecVersion", 13, 7),
+            extension("CheckGenesis", 13, 11),
+            extension("CheckMortality", 10, 11),
+            extension("CheckNonce", 12, 13),
+            extension("CheckWeight", 13, 13),
+        ];
+        metadata
+    }
+
+    #[test]
+    fn test_encode_call() {
+        let metadata = metadata();
+        let call = encode_call(
+            &metadata,
+            "System",
+            "remark",
+            &json!({ "remark": "0xdead" }),
+        )
+        .unwrap();
+        assert_eq!(hex::encode(call), "000008dead");
+        assert!(encode_call(&metadata, "System", "kill", &json!({})).is_err());
+        assert!(encode_call(&metadata, "Revive", "call", &json!({})).is_err());
+    }
+
+    #[test]
+    fn test_sign_extrinsic() {
+        let metadata = metadata();
+        let params = ChainParams {
+            genesis_hash: [7; 32],
+            spec_version: 100,
+            tx_version: 1,
+        };
+        let signer = Signer::dev("Alice").unwrap();
+        let call = [0, 0, 8, 0xde, 0xad];
+        let extrinsic = sign_extrinsic(&metadata, &params, &signer, &call, 5).unwrap();
+
+        let mut input = Input::new(&extrinsic);
+        let len = input.read_compact().unwrap() as usize;
+        assert_eq!(len, input.remaining().len());
+        assert_eq!(input.read_u8().unwrap(), SIGNED_EXTRINSIC_V4);
+        assert_eq!(input.read_u8().unwrap(), 0);
+        assert_eq!(input.read_bytes(32).unwrap(), signer.account().as_bytes());
+        assert_eq!(input.read_u8().unwrap(), 1);
+        let signature = input.read_bytes(64).unwrap();
+        // Immortal, then the nonce, then the call
+        assert_eq!(input.remaining(), [&[0, 5 << 2][..], &call].concat());
+
+        let mut payload = call.to_vec();
+        payload.extend_from_slice(&[0, 5 << 2]);
+        payload.extend_from_slice(&100u32.to_le_bytes());
+        payload.extend_from_slice(&[7; 32]);
+        payload.extend_from_slice(&[7; 32]);
+        let public = schnorrkel::PublicKey::from_bytes(signer.account().as_bytes()).unwrap();
+        let signature = schnorrkel::Signature::from_bytes(signature).unwrap();
+        assert!(public
+            .verify_simple(
+                crate::stdlib::crypto::SR25519_SIGNING_CONTEXT,
+                &payload,
+                &signature
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_unknown_extension_with_data() {
+        let mut metadata = metadata();
+        metadata
+            .extrinsic
+            .signed_extensions
+            .push(extension("ChargeSomething", 7, 13));
+        let params = ChainParams {
+            genesis_hash: [0; 32],
+            spec_version: 1,
+            tx_version: 1,
+        };
+        let signer = Signer::dev("Bob").unwrap();
+        let error = sign_extrinsic(&metadata, &params, &signer, &[0, 0, 0], 0).unwrap_err();
+        assert!(error.to_string().contains("ChargeSomething"), "{}", error);
+    }
+
+    #[test]
+    fn test_dry_run_result() {
+        let metadata = metadata();
+        let result = json!({
+            "gas_required": { "ref_time": 1000, "proof_size": 20 },
+            "storage_deposit": { "Charge": "5000" },
+            "result": { "Ok": { "flags": { "bits": 1 }, "data": "0x2a000000" } },
+        });
+        let output = dry_run_output(&metadata, &result).unwrap();
+        let dry_run = read_dry_run(&result, &output).unwrap();
+        assert!(dry_run.reverted);
+        assert_eq!(dry_run.data, 42u32.to_le_bytes());
+        assert_eq!(deposit_limit(&dry_run.storage_deposit), json!("5000"));
+        assert_eq!(deposit_limit(&json!({ "Refund": 3 })), json!(0));
+
+        let failed = json!({
+            "result": { "Err": { "Module": { "index": 0, "error": "0x05000000" } } },
+        });
+        let error = dry_run_output(&metadata, &failed).unwrap_err();
+        assert_eq!(error.to_string(), "Contract error: System::CallFiltered");
+        assert_eq!(describe_error(&metadata, &json!("BadOrigin")), "BadOrigin");
+    }
+}
@@ -0,0 +1,338 @@
+//! JSON-RPC over HTTP to a Substrate node
+//!
+//! Each request opens a connection of its own and closes it once answered,
+//! so no connection state needs managing. Nodes serve HTTP and WebSocket
+//! on the same port, so a `ws://` URL is reached over HTTP too, and a
+//! `wss://` one over HTTPS. HTTPS is spoken with rustls, trusting the
+//! Mozilla root certificates.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use serde_json::{json, Value};
+
+use super::error::DeployError;
+
+/// How long to wait for the node to connect or answer
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct RpcClient {
+    /// `host:port`
+    authority: String,
+    /// The name the node's certificate must be for, when it is reached
+    /// over TLS
+    server_name: Option<ServerName<'static>>,
+    path: String,
+}
+
+impl RpcClient {
+    /// A client of the node at `url`, such as `http://localhost:9944` or
+    /// `wss://rpc.polkadot.io`
+    pub fn new(url: &str) -> Result<Self, DeployError> {
+        let (rest, tls) = if let Some(rest) = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("wss://"))
+        {
+            (rest, true)
+        } else if let Some(rest) = url
+            .strip_prefix("http://")
+            .or_else(|| url.strip_prefix("ws://"))
+        {
+            (rest, false)
+        } else {
+            return Err(DeployError::Rpc(format!(
+                "unsupported URL `{}`, expected http://, https://, ws:// or wss://",
+                url
+            )));
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+        // The port follows the last colon, unless that colon is inside an
+        // IPv6 address in brackets
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (host, Some(port)),
+            _ => (authority, None),
+        };
+        if host.is_empty() {
+            return Err(DeployError::Rpc(format!("URL `{}` without a host", url)));
+        }
+        let server_name = if tls {
+            let name = host.trim_start_matches('[').trim_end_matches(']');
+            let name = ServerName::try_from(name.to_string())
+                .map_err(|e| DeployError::Rpc(format!("invalid host in URL `{}`: {}", url, e)))?;
+            Some(name)
+        } else {
+            None
+        };
+        let port = port.unwrap_or(if tls { "443" } else { "80" });
+        Ok(RpcClient {
+            authority: format!("{}:{}", host, port),
+            server_name,
+            path: path.to_string(),
+        })
+    }
+
+    /// Call `method` with `params` and return its result
+    pub fn request(&self, method: &str, params: Value) -> Result<Value, DeployError> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+        let response = self.post(&body)?;
+        let mut response: Value = serde_json::from_slice(&response)
+            .map_err(|e| DeployError::Rpc(format!("invalid response to {}: {}", method, e)))?;
+        if let Some(error) = response.get("error") {
+            return Err(DeployError::Node {
+                code: error
+                    .get("code")
+                    .and_then(Value::as_i64)
+                    .unwrap_or_default(),
+                message: error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("unknown error")
+                    .to_string(),
+            });
+        }
+        Ok(response
+            .get_mut("result")
+            .map(Value::take)
+            .unwrap_or(Value::Null))
+    }
+
+    fn post(&self, body: &str) -> Result<Vec<u8>, DeployError> {
+        let io = |e: std::io::Error| DeployError::Rpc(format!("{}: {}", self.authority, e));
+        let stream = TcpStream::connect(&self.authority).map_err(io)?;
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(io)?;
+        stream.set_write_timeout(Some(TIMEOUT)).map_err(io)?;
+        match &self.server_name {
+            Some(name) => {
+                let connection = ClientConnection::new(tls_config(), name.clone())
+                    .map_err(|e| DeployError::Rpc(format!("{}: {}", self.authority, e)))?;
+                self.exchange(StreamOwned::new(connection, stream), body)
+            }
+            None => self.exchange(stream, body),
+        }
+    }
+
+    /// Send the request with `body` over `stream` and read the body of the
+    /// response
+    fn exchange(&self, mut stream: impl Read + Write, body: &str) -> Result<Vec<u8>, DeployError> {
+        let io = |e: std::io::Error| DeployError::Rpc(format!("{}: {}", self.authority, e));
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.path,
+            self.authority,
+            body.len(),
+            body
+        )
+        .map_err(io)?;
+
+        let mut reader = BufReader::new(stream);
+        let mut status = String::new();
+        reader.read_line(&mut status).map_err(io)?;
+        let code = status.split_whitespace().nth(1).unwrap_or_default();
+        if code != "200" {
+            return Err(DeployError::Rpc(format!(
+                "{} answered `{}`",
+                self.authority,
+                status.trim()
+            )));
+        }
+
+        let mut length = None;
+        let mut chunked = false;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).map_err(io)?;
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                let value = value.trim();
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.parse::<usize>().ok();
+                } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                    chunked = value.eq_ignore_ascii_case("chunked");
+                }
+            }
+        }
+
+        let mut body = Vec::new();
+        if chunked {
+            loop {
+                let mut size = String::new();
+                reader.read_line(&mut size).map_err(io)?;
+                let size = size.trim().split(';').next().unwrap_or_default();
+                let size = usize::from_str_radix(size, 16)
+                    .map_err(|_| DeployError::Rpc(format!("invalid chunk size `{}`", size)))?;
+                if size == 0 {
+                    break;
+                }
+                let start = body.len();
+                body.resize(start + size, 0);
+                reader.read_exact(&mut body[start..]).map_err(io)?;
+                let mut end = String::new();
+                reader.read_line(&mut end).map_err(io)?;
+            }
+        } else if let Some(length) = length {
+            body.resize(length, 0);
+            reader.read_exact(&mut body).map_err(io)?;
+        } else {
+            reader.read_to_end(&mut body).map_err(io)?;
+        }
+        Ok(body)
+    }
+}
+
+/// The TLS settings of every client, made once
+fn tls_config() -> Arc<ClientConfig> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let roots = RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            };
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let config = ClientConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .expect("ring supports the default protocol versions")
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            Arc::new(config)
+        })
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deployment::config::{DeploymentConfig, Environment};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Answer one request with `response` and return the request
+    fn serve(response: String) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.strip_prefix("Content-Length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            request
+        });
+        (url, handle)
+    }
+
+    fn response(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    #[test]
+    fn test_request() {
+        let (url, server) = serve(response(r#"{"jsonrpc":"2.0","id":1,"result":42}"#));
+        let client = RpcClient::new(&url).unwrap();
+        let result = client.request("system_name", json!([])).unwrap();
+        assert_eq!(result, json!(42));
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST / HTTP/1.1\r\n"));
+        assert!(request.contains(r#""method":"system_name""#));
+    }
+
+    #[test]
+    fn test_chunked_response() {
+        let mut response = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_string();
+        for chunk in [r#"{"jsonrpc":"2.0","#, r#""id":1,"result":"ok"}"#] {
+            response.push_str(&format!("{:x}\r\n{}\r\n", chunk.len(), chunk));
+        }
+        response.push_str("0\r\n\r\n");
+        let (url, server) = serve(response);
+        let client = RpcClient::new(&url.replace("http", "ws")).unwrap();
+        assert_eq!(client.request("x", json!([])).unwrap(), json!("ok"));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_node_error() {
+        let (url, server) = serve(response(
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32601,"message":"Method not found"}}"#,
+        ));
+        let error = RpcClient::new(&url)
+            .unwrap()
+            .request("nope", json!([]))
+            .unwrap_err();
+        assert!(matches!(error, DeployError::Node { code: -32601, .. }));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_urls() {
+        assert!(RpcClient::new("ftp://example.com").is_err());
+        assert!(RpcClient::new("http://").is_err());
+        assert!(RpcClient::new("https://:443").is_err());
+
+        let client = RpcClient::new("ws://node:9944/rpc").unwrap();
+        assert_eq!(client.authority, "node:9944");
+        assert_eq!(client.path, "/rpc");
+        assert!(client.server_name.is_none());
+
+        let client = RpcClient::new("wss://rpc.polkadot.io").unwrap();
+        assert_eq!(client.authority, "rpc.polkadot.io:443");
+        assert_eq!(client.path, "/");
+        assert_eq!(
+            client.server_name,
+            Some(ServerName::try_from("rpc.polkadot.io").unwrap())
+        );
+
+        let client = RpcClient::new("https://[::1]:9944").unwrap();
+        assert_eq!(client.authority, "[::1]:9944");
+        assert!(matches!(client.server_name, Some(ServerName::IpAddress(_))));
+    }
+
+    #[test]
+    fn test_default_network_urls() {
+        for environment in [
+            Environment::Development,
+            Environment::Testnet,
+            Environment::Mainnet,
+        ] {
+            let url = DeploymentConfig::new(environment).network.rpc_url;
+            let client = RpcClient::new(&url).unwrap();
+            assert_eq!(client.server_name.is_some(), url.starts_with("https://"));
+        }
+        // Building the TLS settings checks the root certificates load
+        tls_config();
+    }
+}
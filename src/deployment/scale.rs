@@ -0,0 +1,277 @@
+//! SCALE, the encoding Substrate nodes use for extrinsics, storage and
+//! runtime calls, and the hashes of storage keys
+
+use super::error::DeployError;
+
+/// Append `value` in the compact encoding
+pub fn encode_compact(value: u128, out: &mut Vec<u8>) {
+    match value {
+        0..=0x3f => out.push((value as u8) << 2),
+        0x40..=0x3fff => out.extend_from_slice(&(((value as u16) << 2) | 0b01).to_le_bytes()),
+        0x4000..=0x3fff_ffff => {
+            out.extend_from_slice(&(((value as u32) << 2) | 0b10).to_le_bytes())
+        }
+        _ => {
+            let bytes = value.to_le_bytes();
+            let len = 16 - value.leading_zeros() as usize / 8;
+            out.push((((len - 4) as u8) << 2) | 0b11);
+            out.extend_from_slice(&bytes[..len]);
+        }
+    }
+}
+
+/// Append `bytes` with their compact length in front, as a `Vec<u8>`
+pub fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    encode_compact(bytes.len() as u128, out);
+    out.extend_from_slice(bytes);
+}
+
+/// SCALE data being decoded from the front
+pub struct Input<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Input { data }
+    }
+
+    /// Bytes not decoded yet
+    pub fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DeployError> {
+        if self.data.len() < len {
+            return Err(DeployError::Decode(format!(
+                "expected {} more byte(s), found {}",
+                len,
+                self.data.len()
+            )));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, DeployError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, DeployError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn read_compact(&mut self) -> Result<u128, DeployError> {
+        let first = self.read_u8()?;
+        Ok(match first & 0b11 {
+            0b00 => (first >> 2) as u128,
+            0b01 => (u16::from_le_bytes([first, self.read_u8()?]) >> 2) as u128,
+            0b10 => {
+                let mut bytes = [first, 0, 0, 0];
+                bytes[1..].copy_from_slice(self.read_bytes(3)?);
+                (u32::from_le_bytes(bytes) >> 2) as u128
+            }
+            _ => {
+                let len = (first >> 2) as usize + 4;
+                if len > 16 {
+                    return Err(DeployError::Decode(format!(
+                        "compact integer of {} bytes",
+                        len
+                    )));
+                }
+                let mut bytes = [0u8; 16];
+                bytes[..len].copy_from_slice(self.read_bytes(len)?);
+                u128::from_le_bytes(bytes)
+            }
+        })
+    }
+
+    /// A compact integer that must fit in `u32`, such as a length or a
+    /// type id
+    pub fn read_compact_u32(&mut self) -> Result<u32, DeployError> {
+        let value = self.read_compact()?;
+        u32::try_from(value).map_err(|_| DeployError::Decode(format!("{} is too large", value)))
+    }
+
+    /// A `Vec<u8>`
+    pub fn read_vec(&mut self) -> Result<&'a [u8], DeployError> {
+        let len = self.read_compact_u32()? as usize;
+        self.read_bytes(len)
+    }
+
+    pub fn read_string(&mut self) -> Result<String, DeployError> {
+        let bytes = self.read_vec()?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| DeployError::Decode("string is not UTF-8".to_string()))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, DeployError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(DeployError::Decode(format!("invalid bool {}", byte))),
+        }
+    }
+
+    /// A `Vec<T>`, each element read by `read`
+    pub fn read_list<T>(
+        &mut self,
+        mut read: impl FnMut(&mut Self) -> Result<T, DeployError>,
+    ) -> Result<Vec<T>, DeployError> {
+        let len = self.read_compact_u32()?;
+        (0..len).map(|_| read(self)).collect()
+    }
+
+    /// An `Option<T>`, the value read by `read`
+    pub fn read_option<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, DeployError>,
+    ) -> Result<Option<T>, DeployError> {
+        match self.read_u8()? {
+            0 => Ok(None),
+            1 => read(self).map(Some),
+            byte => Err(DeployError::Decode(format!("invalid option {}", byte))),
+        }
+    }
+}
+
+const PRIME1: u64 = 11_400_714_785_074_694_791;
+const PRIME2: u64 = 14_029_467_366_897_019_727;
+const PRIME3: u64 = 1_609_587_929_392_839_161;
+const PRIME4: u64 = 9_650_029_242_287_828_579;
+const PRIME5: u64 = 2_870_177_450_012_600_261;
+
+/// The 64-bit xxHash of `data`
+fn xxhash64(data: &[u8], seed: u64) -> u64 {
+    fn round(acc: u64, input: u64) -> u64 {
+        acc.wrapping_add(input.wrapping_mul(PRIME2))
+            .rotate_left(31)
+            .wrapping_mul(PRIME1)
+    }
+    fn merge(acc: u64, value: u64) -> u64 {
+        (acc ^ round(0, value))
+            .wrapping_mul(PRIME1)
+            .wrapping_add(PRIME4)
+    }
+    let u64_at = |bytes: &[u8]| u64::from_le_bytes(bytes[..8].try_into().unwrap());
+
+    let mut rest = data;
+    let mut hash = if data.len() >= 32 {
+        let mut lanes = [
+            seed.wrapping_add(PRIME1).wrapping_add(PRIME2),
+            seed.wrapping_add(PRIME2),
+            seed,
+            seed.wrapping_sub(PRIME1),
+        ];
+        while rest.len() >= 32 {
+            for (i, lane) in lanes.iter_mut().enumerate() {
+                *lane = round(*lane, u64_at(&rest[i * 8..]));
+            }
+            rest = &rest[32..];
+        }
+        let hash = lanes[0]
+            .rotate_left(1)
+            .wrapping_add(lanes[1].rotate_left(7))
+            .wrapping_add(lanes[2].rotate_left(12))
+            .wrapping_add(lanes[3].rotate_left(18));
+        lanes.iter().fold(hash, |hash, &lane| merge(hash, lane))
+    } else {
+        seed.wrapping_add(PRIME5)
+    };
+    hash = hash.wrapping_add(data.len() as u64);
+
+    while rest.len() >= 8 {
+        hash = (hash ^ round(0, u64_at(rest)))
+            .rotate_left(27)
+            .wrapping_mul(PRIME1)
+            .wrapping_add(PRIME4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        let word = u32::from_le_bytes(rest[..4].try_into().unwrap()) as u64;
+        hash = (hash ^ word.wrapping_mul(PRIME1))
+            .rotate_left(23)
+            .wrapping_mul(PRIME2)
+            .wrapping_add(PRIME3);
+        rest = &rest[4..];
+    }
+    for &byte in rest {
+        hash = (hash ^ (byte as u64).wrapping_mul(PRIME5))
+            .rotate_left(11)
+            .wrapping_mul(PRIME1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(PRIME2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(PRIME3);
+    hash ^ (hash >> 32)
+}
+
+/// The 64-bit xxHash of `Twox64Concat` storage map keys
+pub fn twox64(data: &[u8]) -> [u8; 8] {
+    xxhash64(data, 0).to_le_bytes()
+}
+
+/// The 128-bit xxHash storage keys start with, one for the pallet prefix
+/// and one for the entry name
+pub fn twox128(data: &[u8]) -> [u8; 16] {
+    let mut hash = [0u8; 16];
+    hash[..8].copy_from_slice(&xxhash64(data, 0).to_le_bytes());
+    hash[8..].copy_from_slice(&xxhash64(data, 1).to_le_bytes());
+    hash
+}
+
+/// The 256-bit xxHash of `Twox256` storage map keys
+pub fn twox256(data: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    for (seed, chunk) in hash.chunks_mut(8).enumerate() {
+        chunk.copy_from_slice(&xxhash64(data, seed as u64).to_le_bytes());
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_round_trip() {
+        for (value, encoded) in [
+            (0u128, "00"),
+            (1, "04"),
+            (63, "fc"),
+            (64, "0101"),
+            (16383, "fdff"),
+            (16384, "02000100"),
+            (1 << 30, "0300000040"),
+            (u64::MAX as u128, "13ffffffffffffffff"),
+        ] {
+            let mut out = Vec::new();
+            encode_compact(value, &mut out);
+            assert_eq!(hex::encode(&out), encoded, "{}", value);
+            assert_eq!(Input::new(&out).read_compact().unwrap(), value);
+        }
+        assert!(Input::new(&[0x01]).read_compact().is_err());
+    }
+
+    #[test]
+    fn test_twox128() {
+        // The storage key of `System.Events`
+        assert_eq!(
+            hex::encode(twox128(b"System")),
+            "26aa394eea5630e07c48ae0c9558cef7"
+        );
+        assert_eq!(
+            hex::encode(twox128(b"Events")),
+            "80d41e5e16056765bc8461851072c9d7"
+        );
+        assert_eq!(xxhash64(b"", 0), 0xef46db3751d8e999);
+        assert_eq!(
+            xxhash64(b"Nobody inspects the spammish repetition", 0),
+            0xfbcea83c8a378bf1
+        );
+    }
+}
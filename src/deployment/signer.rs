@@ -0,0 +1,184 @@
+//! Accounts that sign extrinsics
+//!
+//! A signer is an sr25519 key given as one of:
+//! - a development account, `//Alice` to `//Ferdie`, funded on dev chains
+//! - a `0x`-prefixed 32-byte hex seed
+//! - the path of a keystore file, a JSON object with a `seed` field
+
+use std::fs;
+use std::path::Path;
+
+use schnorrkel::{ExpansionMode, Keypair, MiniSecretKey};
+
+use super::error::DeployError;
+use crate::compiler::address::Address;
+use crate::stdlib::crypto::SR25519_SIGNING_CONTEXT;
+
+/// Seeds of the development accounts, derived from the development phrase
+const DEV_SEEDS: &[(&str, &str)] = &[
+    (
+        "Alice",
+        "e5be9a5092b81bca64be81d212e7f2f9eba183bb7a90954f7b76361f6edb5c0a",
+    ),
+    (
+        "Bob",
+        "398f0c28f98885e046333d4a41c19cee4c37368a9832c6502f6cfd182e2aef89",
+    ),
+    (
+        "Charlie",
+        "bc1ede780f784bb6991a585e4f6e61522c14e1cae6ad0895fb57b9a205a8f938",
+    ),
+    (
+        "Dave",
+        "868020ae0687dda7d57565093a69090211449845a7e11453612800b663307246",
+    ),
+    (
+        "Eve",
+        "786ad0e2df456fe43dd1f91ebca22e235bc162e0bb8d53c633e8c85b2af68b7a",
+    ),
+    (
+        "Ferdie",
+        "42438b7883391c05512a938e36c2df0131e088b3756d6aa7a755fbff19d2f842",
+    ),
+];
+
+/// An sr25519 key pair signing extrinsics
+pub struct Signer {
+    keypair: Keypair,
+}
+
+impl Signer {
+    /// The key pair of a 32-byte mini secret key
+    pub fn from_seed(seed: &[u8]) -> Result<Self, DeployError> {
+        let secret = MiniSecretKey::from_bytes(seed).map_err(|_| {
+            DeployError::Signer(format!("expected a 32-byte seed, found {}", seed.len()))
+        })?;
+        Ok(Signer {
+            keypair: secret.expand_to_keypair(ExpansionMode::Ed25519),
+        })
+    }
+
+    /// A development account such as `//Alice`
+    pub fn dev(name: &str) -> Result<Self, DeployError> {
+        let (_, seed) = DEV_SEEDS
+            .iter()
+            .find(|(dev, _)| dev.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                let names: Vec<String> = DEV_SEEDS
+                    .iter()
+                    .map(|(dev, _)| format!("//{}", dev))
+                    .collect();
+                DeployError::Signer(format!(
+                    "unknown development account `//{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                ))
+            })?;
+        Self::from_seed(&hex::decode(seed).unwrap())
+    }
+
+    /// A signer given on the command line: a development account, a hex
+    /// seed or a keystore file
+    pub fn parse(spec: &str) -> Result<Self, DeployError> {
+        if let Some(name) = spec.strip_prefix("//") {
+            return Self::dev(name);
+        }
+        if let Some(digits) = spec.strip_prefix("0x") {
+            let seed = hex::decode(digits)
+                .map_err(|_| DeployError::Signer("seed is not valid hex".into()))?;
+            return Self::from_seed(&seed);
+        }
+        Self::from_keystore(Path::new(spec))
+    }
+
+    /// The key in a keystore file
+    pub fn from_keystore(path: &Path) -> Result<Self, DeployError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| DeployError::Signer(format!("{}: {}", path.display(), e)))?;
+        let keystore: serde_json::Value = serde_json::from_str(&text)
+            .map_err(|e| DeployError::Signer(format!("{}: {}", path.display(), e)))?;
+        let seed = keystore
+            .get("seed")
+            .and_then(|seed| seed.as_str())
+            .and_then(|seed| seed.strip_prefix("0x"))
+            .ok_or_else(|| {
+                DeployError::Signer(format!(
+                    "{}: expected a `seed` field with a 0x-prefixed hex seed",
+                    path.display()
+                ))
+            })?;
+        let seed = hex::decode(seed).map_err(|_| {
+            DeployError::Signer(format!("{}: seed is not valid hex", path.display()))
+        })?;
+        Self::from_seed(&seed)
+    }
+
+    /// The account id, the public key
+    pub fn account(&self) -> Address {
+        Address::from_slice(&self.keypair.public.to_bytes()).unwrap()
+    }
+
+    /// Sign `message` in the substrate signing context
+    pub fn sign(&self, message: &[u8]) -> [u8; 64] {
+        self.keypair
+            .sign_simple(SR25519_SIGNING_CONTEXT, message)
+            .to_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dev_accounts() {
+        let alice = Signer::parse("//Alice").unwrap();
+        assert_eq!(
+            alice.account().to_ss58(42).unwrap(),
+            "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY"
+        );
+        let bob = Signer::parse("//bob").unwrap();
+        assert_eq!(
+            bob.account().to_ss58(42).unwrap(),
+            "5FHneW46xGXgs5mUiveU4sbTyGBzmstUspZC92UhjJM694ty"
+        );
+        for (name, address) in [
+            (
+                "Charlie",
+                "5FLSigC9HGRKVhB9FiEo4Y3koPsNmBmLJbpXg2mp1hXcS59Y",
+            ),
+            ("Dave", "5DAAnrj7VHTznn2AWBemMuyBwZWs6FNFjdyVXUeYum3PTXFy"),
+            ("Eve", "5HGjWAeFDfFCWPsjFQdVV2Msvz2XtMktvgocEZcCj68kUMaw"),
+            ("Ferdie", "5CiPPseXPECbkjWCa6MnjNokrgYjMqmKndv2rSnekmSK2DjL"),
+        ] {
+            let signer = Signer::dev(name).unwrap();
+            assert_eq!(signer.account().to_ss58(42).unwrap(), address, "{}", name);
+        }
+        assert!(Signer::parse("//Mallory").is_err());
+    }
+
+    #[test]
+    fn test_sign() {
+        let signer = Signer::parse(&format!("0x{}", "07".repeat(32))).unwrap();
+        let signature = signer.sign(b"payload");
+        let public = schnorrkel::PublicKey::from_bytes(signer.account().as_bytes()).unwrap();
+        let signature = schnorrkel::Signature::from_bytes(&signature).unwrap();
+        assert!(public
+            .verify_simple(SR25519_SIGNING_CONTEXT, b"payload", &signature)
+            .is_ok());
+        assert!(Signer::parse("0x1234").is_err());
+    }
+
+    #[test]
+    fn test_keystore() {
+        let dir = std::env::temp_dir().join(format!("bend-pvm-keystore-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("key.json");
+        fs::write(&path, format!("{{\"seed\": \"0x{}\"}}", DEV_SEEDS[0].1)).unwrap();
+        let signer = Signer::parse(path.to_str().unwrap()).unwrap();
+        assert_eq!(signer.account(), Signer::dev("Alice").unwrap().account());
+        fs::write(&path, "{}").unwrap();
+        assert!(Signer::parse(path.to_str().unwrap()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
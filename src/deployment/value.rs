@@ -0,0 +1,491 @@
+//! Values of the node's types as JSON, encoded and decoded by the types
+//! in its metadata
+//!
+//! - structs are objects by field name, or arrays when their fields have
+//!   no names; a struct with a single field is its field
+//! - enums are the name of a variant without fields, or an object with the
+//!   variant name as its only key; `Option` is `null` or the value
+//! - bytes (`Vec<u8>`, `[u8; N]`) are `0x` hex strings
+//! - integers are numbers, or decimal strings when larger than 64 bits
+//!
+//! A string given for an enum with an `Id` variant, such as `MultiAddress`,
+//! is its `Id`.
+
+use serde_json::{json, Map, Value};
+
+use super::error::DeployError;
+use super::metadata::{Field, Metadata, Primitive, TypeDef, Variant};
+use super::scale::{encode_bytes, encode_compact, Input};
+use crate::compiler::wide::WideUint;
+
+impl Metadata {
+    /// Append `value` encoded as the type `ty`
+    pub fn encode_value(
+        &self,
+        ty: u32,
+        value: &Value,
+        out: &mut Vec<u8>,
+    ) -> Result<(), DeployError> {
+        let info = self.type_info(ty)?;
+        let mismatch = || {
+            DeployError::Encode(format!(
+                "{} as {}",
+                value,
+                info.path.last().map_or("a value", String::as_str)
+            ))
+        };
+        match &info.def {
+            TypeDef::Composite(fields) => self.encode_fields(fields, value, out),
+            TypeDef::Variant(variants) => {
+                if info.path.last().is_some_and(|name| name == "Option") {
+                    let (name, inner) = match value {
+                        Value::Null => ("None", &Value::Null),
+                        value => ("Some", value),
+                    };
+                    let variant = find_variant(variants, name).ok_or_else(mismatch)?;
+                    out.push(variant.index);
+                    return self.encode_fields(&variant.fields, inner, out);
+                }
+                let (variant, inner) = match value {
+                    Value::String(name) => match find_variant(variants, name) {
+                        Some(variant) => (variant, &Value::Null),
+                        None => (find_variant(variants, "Id").ok_or_else(mismatch)?, value),
+                    },
+                    Value::Object(object) if object.len() == 1 => {
+                        let (name, inner) = object.iter().next().unwrap();
+                        (find_variant(variants, name).ok_or_else(mismatch)?, inner)
+                    }
+                    _ => return Err(mismatch()),
+                };
+                out.push(variant.index);
+                self.encode_fields(&variant.fields, inner, out)
+            }
+            TypeDef::Sequence(element) => {
+                if let (Some(bytes), true) = (hex_bytes(value), self.is_u8(*element)) {
+                    encode_bytes(&bytes?, out);
+                    return Ok(());
+                }
+                let items = value.as_array().ok_or_else(mismatch)?;
+                encode_compact(items.len() as u128, out);
+                items
+                    .iter()
+                    .try_for_each(|item| self.encode_value(*element, item, out))
+            }
+            TypeDef::Array(len, element) => {
+                if let (Some(bytes), true) = (hex_bytes(value), self.is_u8(*element)) {
+                    let bytes = bytes?;
+                    if bytes.len() != *len as usize {
+                        return Err(DeployError::Encode(format!("{} as {} bytes", value, len)));
+                    }
+                    out.extend_from_slice(&bytes);
+                    return Ok(());
+                }
+                match value.as_array() {
+                    Some(items) if items.len() == *len as usize => items
+                        .iter()
+                        .try_for_each(|item| self.encode_value(*element, item, out)),
+                    _ => Err(mismatch()),
+                }
+            }
+            TypeDef::Tuple(elements) => match (elements.len(), value) {
+                (0, _) => Ok(()),
+                (1, value) if !value.is_array() => self.encode_value(elements[0], value, out),
+                (_, Value::Array(items)) if items.len() == elements.len() => elements
+                    .iter()
+                    .zip(items)
+                    .try_for_each(|(ty, item)| self.encode_value(*ty, item, out)),
+                _ => Err(mismatch()),
+            },
+            TypeDef::Primitive(primitive) => encode_primitive(*primitive, value, out),
+            TypeDef::Compact(_) => {
+                encode_compact(integer(value).ok_or_else(mismatch)?, out);
+                Ok(())
+            }
+            TypeDef::BitSequence => Err(DeployError::Encode("bit sequences".into())),
+        }
+    }
+
+    /// Append the fields of a struct or enum variant
+    pub fn encode_fields(
+        &self,
+        fields: &[Field],
+        value: &Value,
+        out: &mut Vec<u8>,
+    ) -> Result<(), DeployError> {
+        match fields {
+            [] => Ok(()),
+            [field] if !has_field(value, field) => self.encode_value(field.ty, value, out),
+            _ if fields.iter().all(|field| field.name.is_some()) => {
+                for field in fields {
+                    let name = field.name.as_deref().unwrap_or_default();
+                    let field_value = value.get(name).unwrap_or(&Value::Null);
+                    self.encode_value(field.ty, field_value, out)
+                        .map_err(|e| match e {
+                            DeployError::Encode(message) => {
+                                DeployError::Encode(format!("{} (field `{}`)", message, name))
+                            }
+                            e => e,
+                        })?;
+                }
+                Ok(())
+            }
+            _ => match value.as_array() {
+                Some(items) if items.len() == fields.len() => fields
+                    .iter()
+                    .zip(items)
+                    .try_for_each(|(field, item)| self.encode_value(field.ty, item, out)),
+                _ => Err(DeployError::Encode(format!(
+                    "{} as {} fields",
+                    value,
+                    fields.len()
+                ))),
+            },
+        }
+    }
+
+    /// Decode a value of the type `ty`
+    pub fn decode_value(&self, ty: u32, input: &mut Input) -> Result<Value, DeployError> {
+        let info = self.type_info(ty)?;
+        match &info.def {
+            TypeDef::Composite(fields) => self.decode_fields(fields, input),
+            TypeDef::Variant(variants) => {
+                let index = input.read_u8()?;
+                let variant = variants
+                    .iter()
+                    .find(|variant| variant.index == index)
+                    .ok_or_else(|| {
+                        DeployError::Decode(format!("variant {} of type {}", index, ty))
+                    })?;
+                let fields = self.decode_fields(&variant.fields, input)?;
+                Ok(match info.path.last().map(String::as_str) {
+                    Some("Option") => fields,
+                    _ if variant.fields.is_empty() => json!(variant.name),
+                    _ => json!({ variant.name.clone(): fields }),
+                })
+            }
+            TypeDef::Sequence(element) => {
+                let len = input.read_compact_u32()?;
+                self.decode_items(*element, len, input)
+            }
+            TypeDef::Array(len, element) => self.decode_items(*element, *len, input),
+            TypeDef::Tuple(elements) => match elements.as_slice() {
+                [] => Ok(Value::Null),
+                [element] => self.decode_value(*element, input),
+                elements => elements
+                    .iter()
+                    .map(|ty| self.decode_value(*ty, input))
+                    .collect::<Result<_, _>>()
+                    .map(Value::Array),
+            },
+            TypeDef::Primitive(primitive) => decode_primitive(*primitive, input),
+            TypeDef::Compact(_) => Ok(number(input.read_compact()?)),
+            TypeDef::BitSequence => Err(DeployError::Decode("bit sequences".into())),
+        }
+    }
+
+    fn decode_fields(&self, fields: &[Field], input: &mut Input) -> Result<Value, DeployError> {
+        match fields {
+            [] => Ok(Value::Null),
+            [field] if field.name.is_none() => self.decode_value(field.ty, input),
+            _ if fields.iter().all(|field| field.name.is_some()) => {
+                let mut object = Map::new();
+                for field in fields {
+                    let name = field.name.clone().unwrap_or_default();
+                    object.insert(name, self.decode_value(field.ty, input)?);
+                }
+                Ok(Value::Object(object))
+            }
+            _ => fields
+                .iter()
+                .map(|field| self.decode_value(field.ty, input))
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+        }
+    }
+
+    fn decode_items(
+        &self,
+        element: u32,
+        len: u32,
+        input: &mut Input,
+    ) -> Result<Value, DeployError> {
+        if self.is_u8(element) {
+            let bytes = input.read_bytes(len as usize)?;
+            return Ok(json!(format!("0x{}", hex::encode(bytes))));
+        }
+        (0..len)
+            .map(|_| self.decode_value(element, input))
+            .collect::<Result<_, _>>()
+            .map(Value::Array)
+    }
+
+    fn is_u8(&self, ty: u32) -> bool {
+        self.type_info(ty)
+            .is_ok_and(|info| info.def == TypeDef::Primitive(Primitive::U8))
+    }
+}
+
+fn find_variant<'v>(variants: &'v [Variant], name: &str) -> Option<&'v Variant> {
+    variants.iter().find(|variant| variant.name == name)
+}
+
+/// Whether `value` is an object with the field's name as a key
+fn has_field(value: &Value, field: &Field) -> bool {
+    field
+        .name
+        .as_ref()
+        .is_some_and(|name| value.get(name).is_some())
+}
+
+/// The bytes of a `0x` hex string
+fn hex_bytes(value: &Value) -> Option<Result<Vec<u8>, DeployError>> {
+    let digits = value.as_str()?.strip_prefix("0x")?;
+    Some(hex::decode(digits).map_err(|_| DeployError::Encode(format!("{} as hex", value))))
+}
+
+/// A non-negative integer given as a number or a decimal string
+fn integer(value: &Value) -> Option<u128> {
+    match value {
+        Value::Number(number) => number.as_u64().map(u128::from),
+        Value::String(digits) => digits.parse().ok(),
+        _ => None,
+    }
+}
+
+/// A number, or a decimal string when it does not fit in 64 bits
+fn number(value: u128) -> Value {
+    match u64::try_from(value) {
+        Ok(value) => json!(value),
+        Err(_) => json!(value.to_string()),
+    }
+}
+
+fn encode_primitive(
+    primitive: Primitive,
+    value: &Value,
+    out: &mut Vec<u8>,
+) -> Result<(), DeployError> {
+    let mismatch = || DeployError::Encode(format!("{} as {:?}", value, primitive));
+    let signed = || -> Option<i128> {
+        match value {
+            Value::Number(number) => number.as_i64().map(i128::from),
+            Value::String(digits) => digits.parse().ok(),
+            _ => None,
+        }
+    };
+    macro_rules! int {
+        ($ty:ty, $value:expr) => {{
+            let value = <$ty>::try_from($value.ok_or_else(mismatch)?).map_err(|_| mismatch())?;
+            out.extend_from_slice(&value.to_le_bytes());
+        }};
+    }
+    match primitive {
+        Primitive::Bool => out.push(value.as_bool().ok_or_else(mismatch)? as u8),
+        Primitive::Char => {
+            let text = value.as_str().ok_or_else(mismatch)?;
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => out.extend_from_slice(&(c as u32).to_le_bytes()),
+                _ => return Err(mismatch()),
+            }
+        }
+        Primitive::Str => encode_bytes(value.as_str().ok_or_else(mismatch)?.as_bytes(), out),
+        Primitive::U8 => int!(u8, integer(value)),
+        Primitive::U16 => int!(u16, integer(value)),
+        Primitive::U32 => int!(u32, integer(value)),
+        Primitive::U64 => int!(u64, integer(value)),
+        Primitive::U128 => int!(u128, integer(value)),
+        Primitive::I8 => int!(i8, signed()),
+        Primitive::I16 => int!(i16, signed()),
+        Primitive::I32 => int!(i32, signed()),
+        Primitive::I64 => int!(i64, signed()),
+        Primitive::I128 => int!(i128, signed()),
+        Primitive::U256 => {
+            let digits = match value {
+                Value::Number(number) => number.to_string(),
+                Value::String(digits) => digits.clone(),
+                _ => return Err(mismatch()),
+            };
+            let value = WideUint::from_dec_str(&digits, 256).map_err(|_| mismatch())?;
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        Primitive::I256 => return Err(DeployError::Encode("i256 values".into())),
+    }
+    Ok(())
+}
+
+fn decode_primitive(primitive: Primitive, input: &mut Input) -> Result<Value, DeployError> {
+    macro_rules! int {
+        ($ty:ty) => {{
+            let bytes = input.read_bytes(std::mem::size_of::<$ty>())?;
+            <$ty>::from_le_bytes(bytes.try_into().unwrap())
+        }};
+    }
+    Ok(match primitive {
+        Primitive::Bool => json!(input.read_bool()?),
+        Primitive::Char => {
+            let code = input.read_u32()?;
+            let c = char::from_u32(code)
+                .ok_or_else(|| DeployError::Decode(format!("invalid char {}", code)))?;
+            json!(c.to_string())
+        }
+        Primitive::Str => json!(input.read_string()?),
+        Primitive::U8 => json!(int!(u8)),
+        Primitive::U16 => json!(int!(u16)),
+        Primitive::U32 => json!(int!(u32)),
+        Primitive::U64 => json!(int!(u64)),
+        Primitive::U128 => number(int!(u128)),
+        Primitive::I8 => json!(int!(i8)),
+        Primitive::I16 => json!(int!(i16)),
+        Primitive::I32 => json!(int!(i32)),
+        Primitive::I64 => json!(int!(i64)),
+        Primitive::I128 => {
+            let value = int!(i128);
+            match i64::try_from(value) {
+                Ok(value) => json!(value),
+                Err(_) => json!(value.to_string()),
+            }
+        }
+        Primitive::U256 => {
+            let value = WideUint::from_le_bytes(input.read_bytes(32)?)
+                .ok_or_else(|| DeployError::Decode("invalid u256".into()))?;
+            json!(value.to_string())
+        }
+        Primitive::I256 => json!(format!("0x{}", hex::encode(input.read_bytes(32)?))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: Option<&str>, ty: u32) -> Field {
+        Field {
+            name: name.map(str::to_string),
+            ty,
+        }
+    }
+
+    fn variant(name: &str, index: u8, fields: Vec<Field>) -> Variant {
+        Variant {
+            name: name.to_string(),
+            index,
+            fields,
+        }
+    }
+
+    fn metadata() -> Metadata {
+        Metadata::with_types(vec![
+            ("", TypeDef::Primitive(Primitive::U8)),
+            ("", TypeDef::Primitive(Primitive::U128)),
+            ("", TypeDef::Sequence(0)),
+            ("", TypeDef::Compact(1)),
+            (
+                "Weight",
+                TypeDef::Composite(vec![
+                    field(Some("ref_time"), 3),
+                    field(Some("proof_size"), 3),
+                ]),
+            ),
+            (
+                "Option",
+                TypeDef::Variant(vec![
+                    variant("None", 0, vec![]),
+                    variant("Some", 1, vec![field(None, 4)]),
+                ]),
+            ),
+            ("", TypeDef::Array(4, 0)),
+            ("AccountId32", TypeDef::Composite(vec![field(None, 6)])),
+            (
+                "MultiAddress",
+                TypeDef::Variant(vec![
+                    variant("Id", 0, vec![field(None, 7)]),
+                    variant("Index", 1, vec![field(None, 3)]),
+                ]),
+            ),
+            ("", TypeDef::Tuple(vec![0, 1])),
+            ("", TypeDef::Primitive(Primitive::Str)),
+            ("", TypeDef::Primitive(Primitive::U256)),
+            (
+                "Mode",
+                TypeDef::Variant(vec![
+                    variant("Disabled", 0, vec![]),
+                    variant("Enabled", 1, vec![]),
+                ]),
+            ),
+            ("", TypeDef::Primitive(Primitive::I32)),
+        ])
+    }
+
+    fn round_trip(ty: u32, value: Value, encoded: &str) {
+        let metadata = metadata();
+        let mut out = Vec::new();
+        metadata.encode_value(ty, &value, &mut out).unwrap();
+        assert_eq!(hex::encode(&out), encoded, "{}", value);
+        let mut input = Input::new(&out);
+        assert_eq!(metadata.decode_value(ty, &mut input).unwrap(), value);
+        assert!(input.remaining().is_empty());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        round_trip(0, json!(7), "07");
+        round_trip(
+            1,
+            json!("340282366920938463463374607431768211455"),
+            &"ff".repeat(16),
+        );
+        round_trip(2, json!("0xdead"), "08dead");
+        round_trip(3, json!(64), "0101");
+        round_trip(4, json!({ "ref_time": 1, "proof_size": 2 }), "0408");
+        round_trip(5, Value::Null, "00");
+        round_trip(5, json!({ "ref_time": 1, "proof_size": 2 }), "010408");
+        round_trip(8, json!({ "Index": 3 }), "010c");
+        round_trip(9, json!([1, 2]), &format!("0102{}", "00".repeat(15)));
+        round_trip(10, json!("hi"), "086869");
+        round_trip(11, json!("1"), &format!("01{}", "00".repeat(31)));
+        round_trip(12, json!("Enabled"), "01");
+        round_trip(13, json!(-2), "feffffff");
+    }
+
+    #[test]
+    fn test_lenient_encoding() {
+        let metadata = metadata();
+        let mut out = Vec::new();
+        // A string for an enum with an `Id` variant is its `Id`
+        metadata
+            .encode_value(8, &json!("0x01020304"), &mut out)
+            .unwrap();
+        assert_eq!(hex::encode(&out), "0001020304");
+        assert_eq!(
+            metadata.decode_value(8, &mut Input::new(&out)).unwrap(),
+            json!({ "Id": "0x01020304" })
+        );
+
+        // Integers may be decimal strings, and a struct with a single field
+        // may be given by name
+        out.clear();
+        metadata.encode_value(3, &json!("5"), &mut out).unwrap();
+        metadata
+            .encode_value(7, &json!("0x00000000"), &mut out)
+            .unwrap();
+        assert_eq!(hex::encode(&out), "1400000000");
+
+        for (ty, value) in [
+            (0, json!(256)),
+            (0, json!(-1)),
+            (6, json!("0x0102")),
+            (12, json!("Unknown")),
+            (4, json!({ "ref_time": 1 })),
+            (2, json!("0xzz")),
+        ] {
+            assert!(
+                metadata.encode_value(ty, &value, &mut out).is_err(),
+                "{}",
+                value
+            );
+        }
+        assert!(metadata.encode_value(99, &json!(0), &mut out).is_err());
+        assert!(metadata.decode_value(12, &mut Input::new(&[2])).is_err());
+    }
+}
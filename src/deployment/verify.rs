@@ -0,0 +1,277 @@
+//! Checking that a deployed contract was built from a given source
+//!
+//! The source is rebuilt with the settings every build of this compiler
+//! version uses, and the hash of the resulting blob is compared with the
+//! code hash of the deployed contract, a code hash given outright or the
+//! hash of a `.bin` file. Builds are deterministic, so the same source and
+//! compiler version always give the same blob.
+//!
+//! A source file outside a package is rebuilt the way `compile` builds it.
+//! A module of a package is rebuilt the way `build --release` builds it,
+//! with the package's release profile and default features, linked with
+//! the modules it imports; see [`package_contract`].
+
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use super::contract::Contract;
+use super::error::DeployError;
+use super::node::ContractsPallet;
+use crate::compiler::cfg::Cfg;
+use crate::package::profile::RELEASE;
+use crate::package::{build_contract, BuildError, Profile, Workspace};
+use crate::stdlib::crypto::CryptoFunctions;
+
+/// The settings a contract is built with, which a verification pins
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildSettings {
+    pub compiler_version: String,
+    /// Optimization passes run: the default pipeline, or those of a
+    /// package's build profile
+    pub optimization: String,
+    /// Whether a dispatcher routes calls by selector, always the case
+    pub dispatcher: bool,
+}
+
+impl BuildSettings {
+    /// The settings of this compiler, failing when `version` asks for
+    /// another one
+    pub fn pinned(version: Option<&str>) -> Result<Self, DeployError> {
+        let compiler_version = env!("CARGO_PKG_VERSION");
+        if let Some(version) = version {
+            if version.trim_start_matches('v') != compiler_version {
+                return Err(DeployError::Argument(format!(
+                    "the contract was built with compiler {}, this is {}; \
+                     verify it with that version",
+                    version, compiler_version
+                )));
+            }
+        }
+        Ok(BuildSettings {
+            compiler_version: compiler_version.to_string(),
+            optimization: "default".to_string(),
+            dispatcher: true,
+        })
+    }
+
+    /// The settings, for a contract built by a package with `profile`
+    pub fn with_profile(mut self, profile: &Profile) -> Self {
+        self.optimization = format!(
+            "{} profile, opt-level {}",
+            profile.name(),
+            profile.opt_level()
+        );
+        self
+    }
+}
+
+/// The contract `build --release` builds from the module at `path` of the
+/// package at `root`, with the package's default features, along with the
+/// profile it is built with
+pub fn package_contract(root: &Path, path: &Path) -> Result<(Contract, Profile), BuildError> {
+    let workspace = Workspace::load(root)?;
+    let manifest = workspace.root().manifest();
+    let profile = manifest.profile(RELEASE).unwrap_or_else(Profile::release);
+    let cfg = Cfg::new().with_features(manifest.resolve_features(&[], true)?);
+    let built = build_contract(&workspace, &profile, &cfg, path)?;
+    let contract = Contract {
+        binary: built.binary,
+        abi: built.abi,
+    };
+    Ok((contract, profile))
+}
+
+/// What the rebuilt blob is checked against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reference {
+    /// The code of the contract deployed at an address
+    Deployed {
+        address: String,
+        code_hash: [u8; 32],
+    },
+    /// A code hash given outright
+    CodeHash([u8; 32]),
+    /// A `.bin` file
+    Binary { path: String, code_hash: [u8; 32] },
+}
+
+impl Reference {
+    pub fn code_hash(&self) -> &[u8; 32] {
+        match self {
+            Reference::Deployed { code_hash, .. }
+            | Reference::CodeHash(code_hash)
+            | Reference::Binary { code_hash, .. } => code_hash,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Reference::Deployed { address, code_hash } => json!({
+                "kind": "deployed",
+                "address": address,
+                "code_hash": hex(code_hash),
+            }),
+            Reference::CodeHash(code_hash) => json!({
+                "kind": "code_hash",
+                "code_hash": hex(code_hash),
+            }),
+            Reference::Binary { path, code_hash } => json!({
+                "kind": "binary",
+                "path": path,
+                "code_hash": hex(code_hash),
+            }),
+        }
+    }
+}
+
+/// A contract rebuilt from source, with the code hash the contracts
+/// pallet would store its blob under
+pub struct Rebuild {
+    pub contract: Contract,
+    pub source_hash: [u8; 32],
+    pub settings: BuildSettings,
+    pub pallet: ContractsPallet,
+    pub code_hash: [u8; 32],
+}
+
+impl Rebuild {
+    /// `contract` as compiled from `source` with `settings`
+    pub fn new(
+        contract: Contract,
+        source: &str,
+        settings: BuildSettings,
+        pallet: ContractsPallet,
+    ) -> Self {
+        Rebuild {
+            code_hash: pallet.code_hash(&contract.binary),
+            source_hash: CryptoFunctions::blake2b_256(source.as_bytes()),
+            contract,
+            settings,
+            pallet,
+        }
+    }
+
+    /// The report of checking the rebuild against `reference`
+    pub fn verify(self, reference: Reference) -> Verification {
+        Verification {
+            rebuild: self,
+            reference,
+        }
+    }
+}
+
+/// The outcome of a verification, for people and for block explorers
+pub struct Verification {
+    pub rebuild: Rebuild,
+    pub reference: Reference,
+}
+
+impl Verification {
+    pub fn verified(&self) -> bool {
+        self.rebuild.code_hash == *self.reference.code_hash()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let rebuild = &self.rebuild;
+        json!({
+            "verified": self.verified(),
+            "contract": rebuild.contract.abi.name,
+            "source_hash": hex(&rebuild.source_hash),
+            "settings": {
+                "compiler": "bend-pvm",
+                "compiler_version": rebuild.settings.compiler_version,
+                "optimization": rebuild.settings.optimization,
+                "dispatcher": rebuild.settings.dispatcher,
+            },
+            "pallet": rebuild.pallet.name(),
+            "code_hash": hex(&rebuild.code_hash),
+            "code_size": rebuild.contract.binary.len(),
+            "reference": self.reference.to_json(),
+            "abi": serde_json::to_value(&rebuild.contract.abi).unwrap_or(Value::Null),
+        })
+    }
+}
+
+/// A 32-byte hash given as `0x`-prefixed hex
+pub fn parse_code_hash(text: &str) -> Result<[u8; 32], DeployError> {
+    text.strip_prefix("0x")
+        .and_then(|digits| hex::decode(digits).ok())
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            DeployError::Argument(format!("`{}` is not a 0x-prefixed 32-byte hash", text))
+        })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+        fn add(a: u24, b: u24) -> u24 {
+            return a + b;
+        }
+
+        fn scale(factor: u24) -> u24 {
+            if factor > 50 {
+                return 100;
+            } else {
+                return add(factor, factor);
+            }
+        }
+    "#;
+
+    fn rebuild(pallet: ContractsPallet) -> Rebuild {
+        let contract = Contract::compile("points", SOURCE).unwrap();
+        let settings = BuildSettings::pinned(Some(env!("CARGO_PKG_VERSION"))).unwrap();
+        Rebuild::new(contract, SOURCE, settings, pallet)
+    }
+
+    #[test]
+    fn test_rebuild_is_deterministic() {
+        let first = rebuild(ContractsPallet::Revive);
+        for _ in 0..8 {
+            let again = rebuild(ContractsPallet::Revive);
+            assert_eq!(again.contract.binary, first.contract.binary);
+            assert_eq!(again.code_hash, first.code_hash);
+        }
+        assert_eq!(
+            first.code_hash,
+            CryptoFunctions::keccak256(&first.contract.binary)
+        );
+    }
+
+    #[test]
+    fn test_verification() {
+        let rebuild = rebuild(ContractsPallet::Contracts);
+        let code_hash = CryptoFunctions::blake2b_256(&rebuild.contract.binary);
+        let verification = rebuild.verify(Reference::Binary {
+            path: "points.bin".to_string(),
+            code_hash,
+        });
+        assert!(verification.verified());
+        let report = verification.to_json();
+        assert_eq!(report["verified"], json!(true));
+        assert_eq!(report["pallet"], json!("Contracts"));
+        assert_eq!(report["reference"]["kind"], json!("binary"));
+        assert_eq!(report["code_hash"], json!(hex(&code_hash)));
+
+        let rebuild = verification.rebuild;
+        let verification = rebuild.verify(Reference::CodeHash([0; 32]));
+        assert!(!verification.verified());
+        assert_eq!(verification.to_json()["verified"], json!(false));
+    }
+
+    #[test]
+    fn test_pinned_settings() {
+        assert!(BuildSettings::pinned(None).is_ok());
+        assert!(BuildSettings::pinned(Some("0.0.0-other")).is_err());
+        assert!(parse_code_hash(&format!("0x{}", "ab".repeat(32))).is_ok());
+        assert!(parse_code_hash("0xabcd").is_err());
+        assert!(parse_code_hash(&"ab".repeat(32)).is_err());
+    }
+}
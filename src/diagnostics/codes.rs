@@ -0,0 +1,75 @@
+//! Diagnostic codes
+//!
+//! Codes never change meaning once released, so tools and documentation can
+//! rely on them. `E00xx` are found while reading the source, `E01xx` while
+//! type checking, `E02xx` while loading imported modules, `E03xx` while
+//! generating code, `E04xx` are not about the source, such as a file that
+//! cannot be written, and `W01xx` are the warnings of lints, which keep
+//! their code when denied.
+
+/// A token where another was expected
+pub const UNEXPECTED_TOKEN: &str = "E0001";
+/// The source ends in the middle of a definition
+pub const UNEXPECTED_END: &str = "E0002";
+/// Text that is not a token, or a literal that is not valid
+pub const LEXICAL: &str = "E0003";
+/// An expression used where a pattern is expected
+pub const INVALID_PATTERN: &str = "E0004";
+/// Any other syntax error
+pub const SYNTAX: &str = "E0005";
+
+/// A type error without a code of its own
+pub const TYPE: &str = "E0100";
+pub const UNDEFINED_VARIABLE: &str = "E0101";
+pub const TYPE_MISMATCH: &str = "E0102";
+pub const UNDEFINED_TYPE: &str = "E0103";
+pub const UNDEFINED_CONSTRUCTOR: &str = "E0104";
+pub const UNDEFINED_EVENT: &str = "E0105";
+/// An operator applied to operands it does not take
+pub const INCOMPATIBLE_OPERATION: &str = "E0106";
+pub const INVALID_ATTRIBUTE: &str = "E0107";
+/// A `view` or `pure` function changing what it may not
+pub const MUTABILITY: &str = "E0108";
+/// An integer literal too large for its type
+pub const LITERAL_OUT_OF_RANGE: &str = "E0109";
+/// An `asm` block with an unknown instruction or register
+pub const INVALID_ASSEMBLY: &str = "E0110";
+/// A superposition where it cannot be duplicated, or duplicated too often
+pub const INVALID_SUPERPOSITION: &str = "E0111";
+/// A write to a map not held by a variable, which could not be copied first
+pub const UNSUPPORTED_SHARING: &str = "E0112";
+
+/// A module error without a code of its own
+pub const MODULE: &str = "E0200";
+pub const MODULE_NOT_FOUND: &str = "E0201";
+pub const CIRCULAR_IMPORT: &str = "E0202";
+/// An imported name the module does not define, or does not export
+pub const IMPORTED_SYMBOL: &str = "E0203";
+
+/// A code generation error without a code of its own
+pub const CODEGEN: &str = "E0300";
+/// A feature the selected target cannot compile
+pub const UNSUPPORTED_FEATURE: &str = "E0301";
+/// An optimization pass that failed
+pub const OPTIMIZATION: &str = "E0302";
+/// Objects that could not be linked
+pub const LINK: &str = "E0303";
+/// Code PolkaVM could not make a program of
+pub const POLKAVM: &str = "E0304";
+
+/// A file that could not be read or written
+pub const IO: &str = "E0400";
+/// A check of the security of the build that failed
+pub const SECURITY: &str = "E0401";
+/// Anything else that stopped a command, such as an invalid manifest
+pub const COMMAND: &str = "E0402";
+
+/// A use of something marked `#[deprecated]`
+pub const DEPRECATED: &str = "W0101";
+/// A local or parameter that is never read
+pub const UNUSED_VARIABLE: &str = "W0102";
+pub const UNUSED_IMPORT: &str = "W0103";
+/// Statements after a `return` or `revert`
+pub const UNREACHABLE_CODE: &str = "W0104";
+/// A binding hiding a variable of an enclosing scope
+pub const SHADOWING: &str = "W0105";
@@ -0,0 +1,501 @@
+//! Diagnostics for the errors of each compiler phase
+//!
+//! Compiler errors only know the line and column they are at, which most
+//! of them also spell out in their message. The spans are found again by
+//! lexing the source from there, and the position is dropped from the
+//! message since the code frame shows it.
+
+use std::ops::Range;
+
+use regex::Regex;
+
+use crate::compiler::analyzer::lints::{Level, Lint, Warning};
+use crate::compiler::analyzer::type_checker::TypeError;
+use crate::compiler::codegen::risc_v::CodegenError;
+use crate::compiler::lexer::lexer::BendLexer;
+use crate::compiler::lexer::token::Token;
+use crate::compiler::module::ModuleError;
+use crate::compiler::parser::ast::{Import, Program};
+use crate::compiler::parser::parser::ParseError;
+
+use super::{codes, offset, Diagnostic};
+use crate::CompileError;
+
+/// Tokens that a suggestion may insert where the parser expected them
+const INSERTABLE: &[&str] = &[";", ")", "]", "}", ","];
+
+impl Diagnostic {
+    /// The diagnostic for a parse error in `source`
+    pub fn from_parse_error(error: &ParseError, source: &str) -> Self {
+        let message = strip_location(&error.to_string());
+        match error {
+            ParseError::UnexpectedToken {
+                expected,
+                line,
+                column,
+                ..
+            } => {
+                let start = offset(source, *line, *column);
+                let diagnostic = Diagnostic::error(codes::UNEXPECTED_TOKEN, message)
+                    .with_label(token_span(source, start), format!("expected {}", expected));
+                if !INSERTABLE.contains(&expected.as_str()) {
+                    return diagnostic;
+                }
+                let end = previous_token_end(source, start);
+                diagnostic.with_suggestion(format!("insert `{}`", expected), end..end, expected)
+            }
+            ParseError::UnexpectedEOF { expected } => {
+                Diagnostic::error(codes::UNEXPECTED_END, message)
+                    .with_label(source.len()..source.len(), format!("expected {}", expected))
+            }
+            ParseError::LexicalError {
+                message,
+                line,
+                column,
+            } => lexical_error(message, source, offset(source, *line, *column)),
+            ParseError::InvalidPattern(_) => located(codes::INVALID_PATTERN, &message, source),
+            ParseError::Generic(inner) => located(codes::SYNTAX, inner, source),
+        }
+    }
+
+    /// The diagnostic for a type error in `source`
+    pub fn from_type_error(error: &TypeError, source: &str) -> Self {
+        let code = match error {
+            TypeError::Generic(_) => codes::TYPE,
+            TypeError::UndefinedVariable { .. } => codes::UNDEFINED_VARIABLE,
+            TypeError::TypeMismatch { .. } => codes::TYPE_MISMATCH,
+            TypeError::UndefinedType { .. } => codes::UNDEFINED_TYPE,
+            TypeError::UndefinedConstructor { .. } => codes::UNDEFINED_CONSTRUCTOR,
+            TypeError::UndefinedEvent { .. } => codes::UNDEFINED_EVENT,
+            TypeError::IncompatibleOperation { .. } => codes::INCOMPATIBLE_OPERATION,
+            TypeError::InvalidAttribute { .. } => codes::INVALID_ATTRIBUTE,
+            TypeError::MutabilityViolation { .. } => codes::MUTABILITY,
+            TypeError::LiteralOutOfRange { .. } => codes::LITERAL_OUT_OF_RANGE,
+            TypeError::InvalidAssembly { .. } => codes::INVALID_ASSEMBLY,
+            TypeError::InvalidSuperposition { .. } => codes::INVALID_SUPERPOSITION,
+            TypeError::UnsupportedSharing { .. } => codes::UNSUPPORTED_SHARING,
+        };
+        let text = match error {
+            TypeError::Generic(inner) => inner.clone(),
+            _ => error.to_string(),
+        };
+        let diagnostic = Diagnostic::error(code, strip_location(&text));
+        let Some((line, column)) = location(&text) else {
+            return diagnostic;
+        };
+        let span = token_span(source, offset(source, line, column));
+
+        match error {
+            TypeError::UndefinedVariable { name, .. }
+            | TypeError::UndefinedType { name, .. }
+            | TypeError::UndefinedConstructor { name, .. }
+            | TypeError::UndefinedEvent { name, .. } => {
+                let diagnostic = diagnostic.with_label(span.clone(), "not found in this scope");
+                match similar_name(source, name) {
+                    Some(similar) => diagnostic.with_suggestion(
+                        format!("a name with a similar spelling exists: `{}`", similar),
+                        span,
+                        similar,
+                    ),
+                    None => diagnostic,
+                }
+            }
+            TypeError::TypeMismatch {
+                expected, found, ..
+            } => diagnostic.with_label(span, format!("expected {}, found {}", expected, found)),
+            TypeError::IncompatibleOperation {
+                left, op, right, ..
+            } => diagnostic.with_label(
+                span,
+                format!("`{}` does not apply to {} and {}", op, left, right),
+            ),
+            TypeError::LiteralOutOfRange { target, .. } => {
+                let literal = source[span.clone()].to_string();
+                let diagnostic = diagnostic
+                    .with_label(span.clone(), format!("does not fit in {}", target))
+                    .with_note(match target.as_str() {
+                        "i24" => "i24 holds values from -8388608 to +8388607",
+                        _ => "u24 holds values up to 16777215",
+                    });
+                let plain = literal.chars().all(|c| c.is_ascii_digit() || c == '_');
+                if target == "u24" && plain {
+                    diagnostic.with_suggestion(
+                        "make it a u64 literal",
+                        span,
+                        format!("{}u64", literal),
+                    )
+                } else {
+                    diagnostic
+                }
+            }
+            _ => diagnostic.with_label(span, ""),
+        }
+    }
+
+    /// The diagnostic for a lint warning in `source`, an error when the
+    /// lint is denied
+    pub fn from_warning(warning: &Warning, level: Level, source: &str) -> Self {
+        let code = match warning.lint {
+            Lint::UnusedVariables => codes::UNUSED_VARIABLE,
+            Lint::UnusedImports => codes::UNUSED_IMPORT,
+            Lint::UnreachableCode => codes::UNREACHABLE_CODE,
+            Lint::Shadowing => codes::SHADOWING,
+            Lint::Deprecated => codes::DEPRECATED,
+        };
+        let location = &warning.location;
+        let mut span = if location.start < location.end && location.end <= source.len() {
+            location.start..location.end
+        } else {
+            token_span(source, offset(source, location.line, location.column))
+        };
+        // Statements binding a name, like `for`, are narrowed to the name
+        if let Some(name) = warning.message.split('\'').nth(1) {
+            if let Some(found) = find_word(&source[span.clone()], name) {
+                span = span.start + found..span.start + found + name.len();
+            }
+        }
+        let lint = warning.lint;
+        let (diagnostic, note) = match level {
+            Level::Deny => (
+                Diagnostic::error(code, &warning.message),
+                format!("`{}` is denied", lint),
+            ),
+            _ if lint == Lint::UnusedImports => (
+                Diagnostic::warning(code, &warning.message),
+                format!("`--allow {}` silences this warning", lint),
+            ),
+            _ => (
+                Diagnostic::warning(code, &warning.message),
+                format!("`#[allow({})]` on the function silences this warning", lint),
+            ),
+        };
+        let diagnostic = diagnostic.with_label(span, "").with_note(note);
+        match lint {
+            Lint::UnusedVariables => diagnostic
+                .with_note("if it is unused on purpose, start its name with an underscore"),
+            _ => diagnostic,
+        }
+    }
+
+    /// The diagnostic for a module that could not be loaded for `program`.
+    /// Errors are reported on the import they come from, or on the first
+    /// import.
+    pub fn from_module_error(error: &ModuleError, program: &Program) -> Self {
+        let code = match error {
+            ModuleError::NotFound(_) => codes::MODULE_NOT_FOUND,
+            ModuleError::CircularDependency(_) => codes::CIRCULAR_IMPORT,
+            ModuleError::SymbolNotFound(..) | ModuleError::PrivateSymbol(..) => {
+                codes::IMPORTED_SYMBOL
+            }
+            _ => codes::MODULE,
+        };
+        let location = |import: &Import| match import {
+            Import::FromImport { location, .. } | Import::DirectImport { location, .. } => {
+                location.start..location.end
+            }
+        };
+        let import = match error {
+            ModuleError::NotFound(module) => program
+                .imports
+                .iter()
+                .find(|import| imports(import, module)),
+            _ => None,
+        };
+        let diagnostic = Diagnostic::error(code, error.to_string());
+        match import.or(program.imports.first()) {
+            Some(import) => diagnostic.with_label(location(import), ""),
+            None => diagnostic,
+        }
+    }
+
+    /// The diagnostic for a code generation error in `source`
+    pub fn from_codegen_error(error: &CodegenError, source: &str) -> Self {
+        match error {
+            CodegenError::UnsupportedFeature(message) => {
+                located(codes::UNSUPPORTED_FEATURE, message, source)
+            }
+            _ => located(codes::CODEGEN, &error.to_string(), source),
+        }
+    }
+
+    /// The diagnostic for a compilation of `source` that failed with
+    /// `error`, or none for denied warnings, whose diagnostics are the
+    /// warnings themselves
+    pub fn from_compile_error(error: &CompileError, source: &str) -> Option<Self> {
+        let diagnostic = match error {
+            CompileError::Diagnostic(diagnostic) => (**diagnostic).clone(),
+            CompileError::DeniedWarnings(_) => return None,
+            CompileError::Codegen(message) => located(codes::CODEGEN, message, source),
+            CompileError::Optimization(message) => located(codes::OPTIMIZATION, message, source),
+            CompileError::Module(message) => Diagnostic::error(codes::MODULE, message),
+            CompileError::Link(message) => Diagnostic::error(codes::LINK, message),
+            CompileError::PolkaVM(message) => Diagnostic::error(codes::POLKAVM, message),
+            CompileError::Security(message) => Diagnostic::error(codes::SECURITY, message),
+            CompileError::Io(error) => Diagnostic::error(codes::IO, error.to_string()),
+        };
+        Some(diagnostic)
+    }
+}
+
+/// The offset of the first whole-word occurrence of `word` in `text`
+fn find_word(text: &str, word: &str) -> Option<usize> {
+    let is_name = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(word)
+        .map(|(i, _)| i)
+        .find(|&i| !text[..i].ends_with(is_name) && !text[i + word.len()..].starts_with(is_name))
+}
+
+/// The diagnostic for the lexer error at `start`
+fn lexical_error(message: &str, source: &str, start: usize) -> Diagnostic {
+    let span = token_span(source, start);
+    let diagnostic = Diagnostic::error(codes::LEXICAL, message);
+    if message.starts_with("Unterminated string literal") {
+        diagnostic
+            .with_label(span.clone(), "string starts here")
+            .with_suggestion("close the string", span.end..span.end, "\"")
+    } else if message.contains("escape") {
+        diagnostic
+            .with_label(span, "")
+            .with_note("strings accept \\n, \\t, \\r, \\0, \\\\, \\\", \\' and \\u{...}")
+    } else if message.starts_with("Lexer error") {
+        diagnostic.with_label(span, "not part of any token")
+    } else {
+        diagnostic.with_label(span, "")
+    }
+}
+
+/// A diagnostic at the position its message mentions, if any
+fn located(code: &'static str, message: &str, source: &str) -> Diagnostic {
+    let diagnostic = Diagnostic::error(code, strip_location(message));
+    match location(message) {
+        Some((line, column)) => {
+            diagnostic.with_label(token_span(source, offset(source, line, column)), "")
+        }
+        None => diagnostic,
+    }
+}
+
+/// Matches the positions messages end with: `at line 3, column 5` or
+/// `(line 3, column 5)`
+fn location_pattern() -> Regex {
+    Regex::new(r" ?\(?(?:at )?line (\d+), column (\d+)\)?").unwrap()
+}
+
+/// The last line and column a message mentions
+pub(super) fn location(message: &str) -> Option<(usize, usize)> {
+    let captures = location_pattern().captures_iter(message).last()?;
+    Some((captures[1].parse().ok()?, captures[2].parse().ok()?))
+}
+
+fn strip_location(message: &str) -> String {
+    location_pattern().replace_all(message, "").into_owned()
+}
+
+/// The bytes of the token starting at `start`
+fn token_span(source: &str, start: usize) -> Range<usize> {
+    let token = BendLexer::new(&source[start..]).next_token();
+    if token.token == Token::EOF {
+        return start..start;
+    }
+    start + token.start..start + token.end
+}
+
+/// Where the last token before `offset` ends
+fn previous_token_end(source: &str, offset: usize) -> usize {
+    let mut lexer = BendLexer::new(source);
+    let mut end = 0;
+    loop {
+        let token = lexer.next_token();
+        if token.token == Token::EOF || token.end > offset {
+            return end;
+        }
+        end = token.end;
+    }
+}
+
+/// The name in `source` closest in spelling to `name`, when close enough to
+/// be a likely typo
+fn similar_name(source: &str, name: &str) -> Option<String> {
+    let mut lexer = BendLexer::new(source);
+    let mut best: Option<(usize, String)> = None;
+    loop {
+        match lexer.next_token().token {
+            Token::EOF => break,
+            Token::Identifier(candidate) if candidate != name => {
+                let distance = edit_distance(name, &candidate);
+                if distance <= (name.chars().count() / 3).max(1)
+                    && best.as_ref().is_none_or(|(best, _)| distance < *best)
+                {
+                    best = Some((distance, candidate.to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+    best.map(|(_, name)| name)
+}
+
+/// The number of characters to insert, remove or replace, or pairs of
+/// neighbours to swap, to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // distance[i][j] is the distance between a[..i] and b[..j]
+    let mut distance = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distance.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in distance[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let replace = distance[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut best = replace
+                .min(distance[i - 1][j] + 1)
+                .min(distance[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(distance[i - 2][j - 2] + 1);
+            }
+            distance[i][j] = best;
+        }
+    }
+    distance[a.len()][b.len()]
+}
+
+fn imports(import: &Import, module: &str) -> bool {
+    match import {
+        Import::FromImport { path, .. } => path == module,
+        Import::DirectImport { names, .. } => names.iter().any(|name| name == module),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::analyzer::lints::lint_program;
+    use crate::compiler::analyzer::type_checker::TypeChecker;
+    use crate::compiler::parser::parser::Parser;
+    use crate::diagnostics::Severity;
+
+    fn parse_error(source: &str) -> Diagnostic {
+        let error = Parser::new(source).parse_program().unwrap_err();
+        Diagnostic::from_parse_error(&error, source)
+    }
+
+    fn type_error(source: &str) -> Diagnostic {
+        let program = Parser::new(source).parse_program().unwrap();
+        let error = TypeChecker::new().check_program(&program).unwrap_err();
+        Diagnostic::from_type_error(&error, source)
+    }
+
+    #[test]
+    fn test_parse_error_spans_and_fixes() {
+        let source = "fn main() -> u24 {\n    x = f(1\n    return x;\n}\n";
+        let diagnostic = parse_error(source);
+        assert_eq!(diagnostic.code, codes::UNEXPECTED_TOKEN);
+        assert!(!diagnostic.message.contains("line"));
+        assert_eq!(&source[diagnostic.span().unwrap()], "return");
+        let fix = &diagnostic.suggestions[0];
+        assert_eq!((fix.span.clone(), fix.replacement.as_str()), (30..30, ")"));
+
+        let diagnostic = parse_error("fn main() -> u24 {\n    x = \"open;\n}\n");
+        assert_eq!(diagnostic.code, codes::LEXICAL);
+        assert_eq!(diagnostic.suggestions[0].span, 33..33);
+
+        let diagnostic = parse_error("fn main() -> u24 {");
+        assert_eq!(diagnostic.code, codes::UNEXPECTED_END);
+        assert_eq!(diagnostic.span(), Some(18..18));
+    }
+
+    #[test]
+    fn test_type_error_spans_and_fixes() {
+        let source = "fn main(count: u24) -> u24 {\n    return cuont + 1;\n}\n";
+        let diagnostic = type_error(source);
+        assert_eq!(diagnostic.code, codes::UNDEFINED_VARIABLE);
+        assert_eq!(&source[diagnostic.span().unwrap()], "cuont");
+        assert_eq!(diagnostic.suggestions[0].replacement, "count");
+
+        let source = "fn main() -> u24 {\n    return 20_000_000;\n}\n";
+        let diagnostic = type_error(source);
+        assert_eq!(diagnostic.code, codes::LITERAL_OUT_OF_RANGE);
+        assert_eq!(diagnostic.suggestions[0].replacement, "20_000_000u64");
+        assert_eq!(diagnostic.notes.len(), 1);
+    }
+
+    #[test]
+    fn test_lint_warnings() {
+        let source = "fn main(unused: u24) -> u24 {\n    return 1;\n}\n";
+        let program = Parser::new(source).parse_program().unwrap();
+        let warning = &lint_program(&program)[0];
+
+        let diagnostic = Diagnostic::from_warning(warning, Level::Warn, source);
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(diagnostic.code, codes::UNUSED_VARIABLE);
+        assert_eq!(&source[diagnostic.span().unwrap()], "unused");
+
+        let diagnostic = Diagnostic::from_warning(warning, Level::Deny, source);
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.code, codes::UNUSED_VARIABLE);
+
+        let source =
+            "fn main() -> u24 {\n    for index in range(0, 3) {\n    }\n    return 1;\n}\n";
+        let program = Parser::new(source).parse_program().unwrap();
+        let warning = &lint_program(&program)[0];
+        let diagnostic = Diagnostic::from_warning(warning, Level::Warn, source);
+        assert_eq!(&source[diagnostic.span().unwrap()], "index");
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let source = "fn main() -> u24 {\n    return [1];\n}\n";
+        let error = CompileError::Codegen("Bad jump at line 2, column 12".to_string());
+        let diagnostic = Diagnostic::from_compile_error(&error, source).unwrap();
+        assert_eq!(diagnostic.code, codes::CODEGEN);
+        assert_eq!(diagnostic.message, "Bad jump");
+        assert_eq!(&source[diagnostic.span().unwrap()], "[");
+
+        let errors = [
+            (
+                CompileError::Optimization("pass".into()),
+                codes::OPTIMIZATION,
+            ),
+            (CompileError::Module("missing".into()), codes::MODULE),
+            (CompileError::Link("duplicate".into()), codes::LINK),
+            (CompileError::PolkaVM("too large".into()), codes::POLKAVM),
+            (CompileError::Security("unsafe".into()), codes::SECURITY),
+            (
+                CompileError::Io(std::io::ErrorKind::NotFound.into()),
+                codes::IO,
+            ),
+        ];
+        for (error, code) in errors {
+            let diagnostic = Diagnostic::from_compile_error(&error, source).unwrap();
+            assert_eq!(diagnostic.code, code);
+            assert_eq!(diagnostic.span(), None);
+        }
+
+        let parse = parse_error("fn main() -> u24 {");
+        let error = CompileError::Diagnostic(Box::new(parse.clone()));
+        assert_eq!(Diagnostic::from_compile_error(&error, source), Some(parse));
+        let error = CompileError::DeniedWarnings(Vec::new());
+        assert_eq!(Diagnostic::from_compile_error(&error, source), None);
+    }
+
+    #[test]
+    fn test_locations_are_dropped_from_messages() {
+        assert_eq!(
+            strip_location("Unknown guard 'g' (line 3, column 5)"),
+            "Unknown guard 'g'"
+        );
+        assert_eq!(
+            strip_location("Unexpected token x at line 1, column 2, expected ;"),
+            "Unexpected token x, expected ;"
+        );
+        assert_eq!(
+            location("'x' is deprecated (line 4, column 9)"),
+            Some((4, 9))
+        );
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("cuont", "count"), 1);
+    }
+}
@@ -0,0 +1,83 @@
+//! Diagnostics as JSON for editors, CI and other tools
+//!
+//! Spans are given both as byte offsets and as 1-based lines and columns,
+//! with columns counted in characters. Each object also carries the code
+//! frame the command line would print, without colors, as `rendered`.
+
+use std::ops::Range;
+
+use serde_json::{json, Value};
+
+use super::{line_column, render, Diagnostic};
+
+/// `diagnostic`, about `source` in the file `name`, as a JSON object
+pub fn to_json(diagnostic: &Diagnostic, name: &str, source: &str) -> Value {
+    let labels: Vec<Value> = diagnostic
+        .labels
+        .iter()
+        .map(|label| {
+            let mut value = span(source, &label.span);
+            value["message"] = json!(label.message);
+            value
+        })
+        .collect();
+    let suggestions: Vec<Value> = diagnostic
+        .suggestions
+        .iter()
+        .map(|suggestion| {
+            let mut value = span(source, &suggestion.span);
+            value["message"] = json!(suggestion.message);
+            value["replacement"] = json!(suggestion.replacement);
+            value
+        })
+        .collect();
+
+    json!({
+        "severity": diagnostic.severity.to_string(),
+        "code": diagnostic.code,
+        "message": diagnostic.message,
+        "file": name,
+        "labels": labels,
+        "notes": diagnostic.notes,
+        "suggestions": suggestions,
+        "rendered": render(diagnostic, name, source, false),
+    })
+}
+
+fn span(source: &str, span: &Range<usize>) -> Value {
+    let (line, column) = line_column(source, span.start);
+    let (end_line, end_column) = line_column(source, span.end);
+    json!({
+        "start": span.start,
+        "end": span.end,
+        "line": line,
+        "column": column,
+        "end_line": end_line,
+        "end_column": end_column,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::codes;
+
+    #[test]
+    fn test_json_fields() {
+        let source = "fn main() -> u24 {\n    return x;\n}\n";
+        let diagnostic = Diagnostic::error(codes::UNDEFINED_VARIABLE, "Undefined variable 'x'")
+            .with_label(30..31, "not found in this scope")
+            .with_suggestion("did you mean `y`", 30..31, "y");
+        let value = to_json(&diagnostic, "main.bend", source);
+        assert_eq!(value["code"], "E0101");
+        assert_eq!(value["severity"], "error");
+        assert_eq!(value["labels"][0]["line"], 2);
+        assert_eq!(value["labels"][0]["column"], 12);
+        assert_eq!(value["labels"][0]["end_column"], 13);
+        assert_eq!(value["suggestions"][0]["replacement"], "y");
+        assert!(value["rendered"]
+            .as_str()
+            .unwrap()
+            .starts_with("error[E0101]"));
+    }
+}
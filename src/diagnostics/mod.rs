@@ -0,0 +1,225 @@
+//! Errors and warnings about source files, for people and for tools
+//!
+//! Every problem the compiler finds in a source becomes a [`Diagnostic`]:
+//! a severity, a stable code such as `E0001`, a message, the spans of the
+//! source it is about, notes and suggested fixes. The command line renders
+//! diagnostics as code frames ([`render`]) or as JSON lines ([`to_json`]),
+//! the language server turns them into LSP diagnostics and quick fixes, and
+//! the test runner reports test files that do not compile with them. With
+//! `--message-format=json`, diagnostics are streamed along with the other
+//! messages of a build ([`emit_message`]).
+//!
+//! The codes are listed in [`codes`].
+
+pub mod codes;
+mod convert;
+mod json;
+mod render;
+
+pub use json::to_json;
+pub use render::render;
+
+use std::fmt;
+use std::io::{IsTerminal, Write};
+use std::ops::Range;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Bytes of the source a diagnostic is about, with what is wrong there
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+/// A fix: the bytes `span` of the source replaced by `replacement`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub message: String,
+    pub span: Range<usize>,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    /// The spans the problem is about, the main one first
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(code, message)
+        }
+    }
+
+    pub fn with_label(mut self, span: Range<usize>, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_suggestion(
+        mut self,
+        message: impl Into<String>,
+        span: Range<usize>,
+        replacement: impl Into<String>,
+    ) -> Self {
+        self.suggestions.push(Suggestion {
+            message: message.into(),
+            span,
+            replacement: replacement.into(),
+        });
+        self
+    }
+
+    /// The main span, when the diagnostic points into the source
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.labels.first().map(|label| label.span.clone())
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// How the command line writes diagnostics out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageFormat {
+    /// Code frames on stderr
+    #[default]
+    Human,
+    /// One JSON object per line on stdout
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            _ => Err(format!(
+                "Unknown message format '{}', expected human or json",
+                s
+            )),
+        }
+    }
+}
+
+/// Write out a diagnostic about `source`, the file called `name`. Code
+/// frames are colored when stderr is a terminal and `NO_COLOR` is not set.
+pub fn emit(diagnostic: &Diagnostic, name: &str, source: &str, format: MessageFormat) {
+    match format {
+        MessageFormat::Human => {
+            let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+            eprint!("{}", render(diagnostic, name, source, color));
+        }
+        MessageFormat::Json => emit_message("diagnostic", to_json(diagnostic, name, source)),
+    }
+}
+
+/// Write out one line of `--message-format=json`: the fields of `message`
+/// along with a `reason` telling what it reports, such as `diagnostic`,
+/// `artifact`, `timing`, `gas-report` or `finished`
+pub fn emit_message(reason: &str, mut message: serde_json::Value) {
+    message["reason"] = reason.into();
+    let _ = writeln!(std::io::stdout(), "{}", message);
+}
+
+/// The byte offset of a 1-based line and column, counting the column in
+/// characters. Positions past the end of a line are its end.
+pub fn offset(source: &str, line: usize, column: usize) -> usize {
+    let start: usize = source
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    let text = source[start..].split('\n').next().unwrap_or("");
+    start
+        + text
+            .char_indices()
+            .nth(column.saturating_sub(1))
+            .map_or(text.len(), |(i, _)| i)
+}
+
+/// The 1-based line and column of a byte offset, counting the column in
+/// characters
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |n| n + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offsets_and_positions() {
+        let source = "fn main() {\n  x = \"é\";\n}";
+        assert_eq!(offset(source, 1, 1), 0);
+        assert_eq!(offset(source, 2, 8), 19);
+        assert_eq!(offset(source, 2, 100), 23);
+        assert_eq!(offset(source, 9, 1), source.len());
+        assert_eq!(line_column(source, 19), (2, 8));
+        assert_eq!(line_column(source, source.len()), (3, 2));
+    }
+
+    #[test]
+    fn test_message_formats() {
+        assert_eq!("json".parse(), Ok(MessageFormat::Json));
+        assert!("xml".parse::<MessageFormat>().is_err());
+        let diagnostic = Diagnostic::warning(codes::DEPRECATED, "'old' is deprecated");
+        assert_eq!(
+            diagnostic.to_string(),
+            "warning[W0101]: 'old' is deprecated"
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! Code frames: diagnostics the way the command line prints them
+//!
+//! ```text
+//! error[E0101]: Undefined variable 'cuont'
+//!  --> src/main.bend:2:12
+//!   |
+//! 2 |     return cuont + 1;
+//!   |            ^^^^^ not found in this scope
+//! help: a name with a similar spelling exists: `count`
+//!   |
+//! 2 |     return count + 1;
+//!   |            ~~~~~
+//! ```
+//!
+//! Insertions are marked with `+` instead of `~`.
+
+use super::{line_column, offset, Diagnostic, Severity};
+
+const RED: &str = "1;31";
+const YELLOW: &str = "1;33";
+const BLUE: &str = "1;34";
+const CYAN: &str = "1;36";
+const BOLD: &str = "1";
+
+/// `diagnostic` about `source`, the file `name`, as a code frame, with ANSI
+/// colors when `color` is set
+pub fn render(diagnostic: &Diagnostic, name: &str, source: &str, color: bool) -> String {
+    let style = Style { color };
+    let severity = match diagnostic.severity {
+        Severity::Error => RED,
+        Severity::Warning => YELLOW,
+    };
+    let mut out = format!(
+        "{}{}\n",
+        style.paint(
+            severity,
+            &format!("{}[{}]", diagnostic.severity, diagnostic.code)
+        ),
+        style.paint(BOLD, &format!(": {}", diagnostic.message))
+    );
+
+    let lines: Vec<usize> = diagnostic
+        .labels
+        .iter()
+        .map(|label| &label.span)
+        .chain(diagnostic.suggestions.iter().map(|fix| &fix.span))
+        .map(|span| line_column(source, span.start).0)
+        .collect();
+    let width = lines.iter().max().map_or(0, |line| line.to_string().len());
+    let frame = Frame {
+        style: &style,
+        source,
+        width,
+    };
+
+    if let Some(span) = diagnostic.span() {
+        let (line, column) = line_column(source, span.start);
+        out.push_str(&format!(
+            "{}{} {}:{}:{}\n",
+            " ".repeat(width),
+            style.paint(BLUE, "-->"),
+            name,
+            line,
+            column
+        ));
+        out.push_str(&frame.gutter());
+
+        // Labels in the order of their lines, each line shown once
+        let mut labels: Vec<(usize, usize)> = (0..diagnostic.labels.len())
+            .map(|i| (lines[i], i))
+            .collect();
+        labels.sort();
+        let mut shown = None;
+        for (line, i) in labels {
+            let label = &diagnostic.labels[i];
+            if shown != Some(line) {
+                out.push_str(&frame.line(line, frame.text(line)));
+                shown = Some(line);
+            }
+            let (mark, paint) = match i {
+                0 => ('^', severity),
+                _ => ('-', BLUE),
+            };
+            out.push_str(&frame.marks(&label.span, mark, paint, &label.message));
+        }
+    }
+
+    if !diagnostic.notes.is_empty() {
+        out.push_str(&frame.gutter());
+    }
+    for note in &diagnostic.notes {
+        out.push_str(&format!(
+            "{} {} note: {}\n",
+            " ".repeat(width),
+            style.paint(BLUE, "="),
+            note
+        ));
+    }
+
+    for fix in &diagnostic.suggestions {
+        out.push_str(&format!("{}: {}\n", style.paint(CYAN, "help"), fix.message));
+        let line = line_column(source, fix.span.start).0;
+        let start = offset(source, line, 1);
+        let text = frame.text(line);
+        // Fixes that span lines are only described
+        if fix.replacement.contains('\n') || fix.span.end > start + text.len() {
+            continue;
+        }
+        let patched = format!(
+            "{}{}{}",
+            &text[..fix.span.start - start],
+            fix.replacement,
+            &text[fix.span.end - start..]
+        );
+        let replaced = fix.span.start..fix.span.start + fix.replacement.len();
+        let mark = if fix.span.is_empty() { '+' } else { '~' };
+        out.push_str(&frame.gutter());
+        out.push_str(&frame.line(line, &patched));
+        out.push_str(
+            &Frame {
+                source: &patched,
+                ..frame
+            }
+            .marks(
+                &(replaced.start - start..replaced.end - start),
+                mark,
+                CYAN,
+                "",
+            ),
+        );
+    }
+    out
+}
+
+struct Style {
+    color: bool,
+}
+
+impl Style {
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Source lines with a gutter of line numbers `width` wide
+#[derive(Clone, Copy)]
+struct Frame<'a> {
+    style: &'a Style,
+    source: &'a str,
+    width: usize,
+}
+
+impl<'a> Frame<'a> {
+    fn gutter(&self) -> String {
+        format!(
+            "{}\n",
+            self.style
+                .paint(BLUE, &format!("{} |", " ".repeat(self.width)))
+        )
+    }
+
+    /// The 1-based line `line` of the source, without its line break
+    fn text(&self, line: usize) -> &'a str {
+        let text = self.source.split('\n').nth(line - 1).unwrap_or("");
+        text.strip_suffix('\r').unwrap_or(text)
+    }
+
+    fn line(&self, line: usize, text: &str) -> String {
+        let number = self
+            .style
+            .paint(BLUE, &format!("{:>width$} |", line, width = self.width));
+        match text {
+            "" => format!("{}\n", number),
+            _ => format!("{} {}\n", number, text),
+        }
+    }
+
+    /// Marks under the part of `span` on its first line. Tabs before it are
+    /// kept so the marks line up however wide the terminal shows them.
+    fn marks(
+        &self,
+        span: &std::ops::Range<usize>,
+        mark: char,
+        paint: &str,
+        message: &str,
+    ) -> String {
+        let (line, column) = line_column(self.source, span.start);
+        let text = self.text(line);
+        let before: String = text
+            .chars()
+            .take(column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let start = offset(self.source, line, 1);
+        let end = span.end.min(start + text.len()).max(span.start);
+        let count = self.source[span.start..end].chars().count().max(1);
+        let mut marks = mark.to_string().repeat(count);
+        if !message.is_empty() {
+            marks.push(' ');
+            marks.push_str(message);
+        }
+        format!(
+            "{} {}{}\n",
+            self.style
+                .paint(BLUE, &format!("{} |", " ".repeat(self.width))),
+            before,
+            self.style.paint(paint, &marks)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::codes;
+
+    #[test]
+    fn test_render_code_frame() {
+        let source = "fn main(count: u24) -> u24 {\n    return cuont + 1;\n}\n";
+        let diagnostic = Diagnostic::error(codes::UNDEFINED_VARIABLE, "Undefined variable 'cuont'")
+            .with_label(40..45, "not found in this scope")
+            .with_note("names are looked up in the enclosing functions")
+            .with_suggestion("did you mean `count`", 40..45, "count");
+        assert_eq!(
+            render(&diagnostic, "main.bend", source, false),
+            "error[E0101]: Undefined variable 'cuont'\n \
+             --> main.bend:2:12\n  \
+              |\n\
+             2 |     return cuont + 1;\n  \
+              |            ^^^^^ not found in this scope\n  \
+              |\n  \
+              = note: names are looked up in the enclosing functions\n\
+             help: did you mean `count`\n  \
+              |\n\
+             2 |     return count + 1;\n  \
+              |            ~~~~~\n"
+        );
+
+        let colored = render(&diagnostic, "main.bend", source, true);
+        assert!(colored.starts_with("\x1b[1;31merror[E0101]\x1b[0m"));
+    }
+
+    #[test]
+    fn test_render_insertion_and_end_of_input() {
+        let source = "fn main() -> u24 {\n\tx = f(1\n\treturn x;\n}";
+        let diagnostic = Diagnostic::error(codes::UNEXPECTED_TOKEN, "Unexpected token return")
+            .with_label(29..35, "expected )")
+            .with_suggestion("insert `)`", 27..27, ")");
+        let text = render(&diagnostic, "main.bend", source, false);
+        assert!(
+            text.contains("3 | \treturn x;\n  | \t^^^^^^ expected )\n"),
+            "{}",
+            text
+        );
+        assert!(
+            text.ends_with("2 | \tx = f(1)\n  | \t       +\n"),
+            "{}",
+            text
+        );
+
+        let diagnostic = Diagnostic::error(codes::UNEXPECTED_END, "Unexpected end of input")
+            .with_label(source.len()..source.len(), "");
+        assert!(render(&diagnostic, "main.bend", source, false).ends_with("4 | }\n  |  ^\n"));
+    }
+}
@@ -473,6 +473,19 @@ pub mod ffi_functions {
     }
 }
 
+// SECURITY FIX: Input validation constants
+const MAX_ARGS: usize = 16;
+const MAX_INPUT_SIZE: usize = 65536;
+const MAX_OUTPUT_SIZE: usize = 65536;
+
+fn is_valid_function_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 100
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -599,16 +612,3 @@ mod tests {
         assert!(matches!(result, Err(FFIError::PermissionDenied(_))));
     }
 }
-
-// SECURITY FIX: Input validation constants
-const MAX_ARGS: usize = 16;
-const MAX_INPUT_SIZE: usize = 65536;
-const MAX_OUTPUT_SIZE: usize = 65536;
-
-fn is_valid_function_name(name: &str) -> bool {
-    !name.is_empty()
-        && name.len() <= 100
-        && name
-            .chars()
-            .all(|c| c.is_alphanumeric() || c == '_' || c == '.')
-}
@@ -0,0 +1,222 @@
+//! Formatter settings and where a project keeps them
+//!
+//! Settings are read from a `.bendfmt.toml` next to the sources or in a
+//! directory above them, or from the `[fmt]` table of the package's
+//! `bend.toml`. The keys are the field names of [`FormatterConfig`]:
+//!
+//! ```toml
+//! indent_size = 2
+//! max_line_length = 100
+//! use_tabs = false
+//! blank_line_after_fn = true
+//! space_around_operators = true
+//! trailing_comma = "never"
+//! reflow_comments = true
+//! ```
+//!
+//! Keys left out keep their defaults.
+
+use std::fs;
+use std::path::Path;
+
+use crate::package::toml::{self, TomlTable, TomlValue};
+use crate::package::workspace::MANIFEST_FILE;
+
+/// Name of the formatter's own settings file
+pub const CONFIG_FILE: &str = ".bendfmt.toml";
+
+#[derive(Debug, Clone)]
+pub struct FormatterConfig {
+    pub indent_size: usize,
+    pub max_line_length: usize,
+    pub use_tabs: bool,
+    pub blank_line_after_fn: bool,
+    pub space_around_operators: bool,
+    pub trailing_comma: TrailingComma,
+    /// Rewrap comments with lines longer than `max_line_length`
+    pub reflow_comments: bool,
+}
+
+impl Default for FormatterConfig {
+    fn default() -> Self {
+        Self {
+            indent_size: 4,
+            max_line_length: 120,
+            use_tabs: false,
+            blank_line_after_fn: true,
+            space_around_operators: true,
+            trailing_comma: TrailingComma::Vertical,
+            reflow_comments: false,
+        }
+    }
+}
+
+/// When lists the parser accepts a trailing comma in end with one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingComma {
+    /// Only when the list is split one item per line
+    Vertical,
+    Always,
+    Never,
+}
+
+impl TrailingComma {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "vertical" => Some(TrailingComma::Vertical),
+            "always" => Some(TrailingComma::Always),
+            "never" => Some(TrailingComma::Never),
+            _ => None,
+        }
+    }
+}
+
+impl FormatterConfig {
+    /// Read settings from a parsed settings table, keeping the defaults for
+    /// anything left out
+    pub fn from_toml(table: &TomlTable) -> Result<Self, String> {
+        let mut config = FormatterConfig::default();
+        for (key, value) in table {
+            match key.as_str() {
+                "indent_size" => config.indent_size = size(key, value)?,
+                "max_line_length" => config.max_line_length = size(key, value)?,
+                "use_tabs" => config.use_tabs = flag(key, value)?,
+                "blank_line_after_fn" => config.blank_line_after_fn = flag(key, value)?,
+                "space_around_operators" => config.space_around_operators = flag(key, value)?,
+                "reflow_comments" => config.reflow_comments = flag(key, value)?,
+                "trailing_comma" => {
+                    config.trailing_comma = value
+                        .as_str()
+                        .and_then(TrailingComma::parse)
+                        .ok_or_else(|| {
+                            "'trailing_comma' must be \"vertical\", \"always\" or \"never\""
+                                .to_string()
+                        })?
+                }
+                _ => return Err(format!("Unknown formatter setting '{}'", key)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Find the settings for the file or directory at `path`: the closest
+    /// directory holding a `.bendfmt.toml`, or a `bend.toml` with a `[fmt]`
+    /// table, decides. A `.bendfmt.toml` wins over a `bend.toml` next to it.
+    pub fn discover(path: &Path) -> Result<Option<Self>, String> {
+        let start = if path.is_dir() {
+            path
+        } else {
+            path.parent().unwrap_or(Path::new(""))
+        };
+        // A relative file name has an empty parent
+        let start = if start.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            start
+        };
+        let start = start.canonicalize().unwrap_or_else(|_| start.to_path_buf());
+
+        for dir in start.ancestors() {
+            let config_file = dir.join(CONFIG_FILE);
+            if config_file.is_file() {
+                return read(&config_file, None);
+            }
+            let manifest = dir.join(MANIFEST_FILE);
+            if manifest.is_file() {
+                if let Some(config) = read(&manifest, Some("fmt"))? {
+                    return Ok(Some(config));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The settings in the file at `path`, or in its table `section`. Nothing
+/// when the file has no such table.
+fn read(path: &Path, section: Option<&str>) -> Result<Option<FormatterConfig>, String> {
+    let in_file = |e: String| format!("{}: {}", path.display(), e);
+    let source = fs::read_to_string(path).map_err(|e| in_file(e.to_string()))?;
+    let table = toml::parse(&source).map_err(|e| in_file(e.to_string()))?;
+    let table = match section {
+        Some(section) => match table.get(section) {
+            Some(value) => value
+                .as_table()
+                .ok_or_else(|| in_file(format!("'{}' must be a table", section)))?,
+            None => return Ok(None),
+        },
+        None => &table,
+    };
+    FormatterConfig::from_toml(table).map(Some).map_err(in_file)
+}
+
+fn size(key: &str, value: &TomlValue) -> Result<usize, String> {
+    match value {
+        TomlValue::Integer(n) if *n > 0 => Ok(*n as usize),
+        _ => Err(format!("'{}' must be a positive integer", key)),
+    }
+}
+
+fn flag(key: &str, value: &TomlValue) -> Result<bool, String> {
+    match value {
+        TomlValue::Boolean(b) => Ok(*b),
+        _ => Err(format!("'{}' must be true or false", key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_from_toml() {
+        let table =
+            toml::parse("indent_size = 2\nuse_tabs = true\ntrailing_comma = \"never\"\n").unwrap();
+        let config = FormatterConfig::from_toml(&table).unwrap();
+        assert_eq!(config.indent_size, 2);
+        assert!(config.use_tabs);
+        assert_eq!(config.trailing_comma, TrailingComma::Never);
+        assert_eq!(config.max_line_length, 120);
+
+        for bad in [
+            "indent = 2",
+            "indent_size = \"2\"",
+            "trailing_comma = \"sometimes\"",
+        ] {
+            let table = toml::parse(bad).unwrap();
+            assert!(FormatterConfig::from_toml(&table).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_discover_settings() {
+        let root = std::env::temp_dir().join(format!("bend-fmt-config-{}", std::process::id()));
+        let nested = root.join("src").join("nested");
+        fs::create_dir_all(&nested).unwrap();
+        let file = nested.join("main.bend");
+        fs::write(&file, "fn main() {}\n").unwrap();
+
+        // A manifest without a [fmt] table says nothing
+        fs::write(root.join(MANIFEST_FILE), "[package]\nname = \"demo\"\n").unwrap();
+        assert!(FormatterConfig::discover(&file).unwrap().is_none());
+
+        fs::write(
+            root.join(MANIFEST_FILE),
+            "[package]\nname = \"demo\"\n\n[fmt]\nmax_line_length = 80\n",
+        )
+        .unwrap();
+        let config = FormatterConfig::discover(&file).unwrap().unwrap();
+        assert_eq!(config.max_line_length, 80);
+
+        // The closer settings file wins
+        fs::write(root.join("src").join(CONFIG_FILE), "indent_size = 2\n").unwrap();
+        let config = FormatterConfig::discover(&file).unwrap().unwrap();
+        assert_eq!((config.indent_size, config.max_line_length), (2, 120));
+
+        fs::write(root.join("src").join(CONFIG_FILE), "indent_size = 0\n").unwrap();
+        let error = FormatterConfig::discover(&nested).unwrap_err();
+        assert!(error.contains(CONFIG_FILE), "{}", error);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
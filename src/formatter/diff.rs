@@ -0,0 +1,190 @@
+//! Line differences between a source and its formatted version
+//!
+//! Differences are shown as unified diffs, like `diff -u` and `git diff`
+//! print them, with three lines of context around each change.
+
+use std::ops::Range;
+
+/// A run of lines that changed: lines `old` of the source are replaced by
+/// lines `new` of the formatted text. Either may be empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// The lines of `text`, each with its line break
+pub fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// The changed runs of lines between `old` and `new`, in order. Lines are
+/// matched by a longest common subsequence after the common start and end
+/// are set aside.
+pub fn hunks(old: &[&str], new: &[&str]) -> Vec<Hunk> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    // common[i][j] is the length of the longest common subsequence of
+    // a[i..] and b[j..]
+    let mut common = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i][j] = if a[i] == b[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut start = None;
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            if let Some((old, new)) = start.take() {
+                hunks.push(Hunk {
+                    old: prefix + old..prefix + i,
+                    new: prefix + new..prefix + j,
+                });
+            }
+            i += 1;
+            j += 1;
+            continue;
+        }
+        start.get_or_insert((i, j));
+        if j == b.len() || (i < a.len() && common[i + 1][j] >= common[i][j + 1]) {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    if let Some((old, new)) = start {
+        hunks.push(Hunk {
+            old: prefix + old..prefix + i,
+            new: prefix + new..prefix + j,
+        });
+    }
+    hunks
+}
+
+/// Unchanged lines shown around each change
+const CONTEXT: usize = 3;
+
+/// The unified diff from `old` to `new`, both labelled `name`. Empty when
+/// the texts are the same.
+pub fn unified_diff(name: &str, old: &str, new: &str) -> String {
+    let old = lines(old);
+    let new = lines(new);
+    let hunks = hunks(&old, &new);
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    // Changes close enough to share context are shown together
+    let mut groups: Vec<&[Hunk]> = Vec::new();
+    let mut first = 0;
+    for i in 1..=hunks.len() {
+        if i == hunks.len() || hunks[i].old.start - hunks[i - 1].old.end > 2 * CONTEXT {
+            groups.push(&hunks[first..i]);
+            first = i;
+        }
+    }
+
+    let mut diff = format!("--- a/{}\n+++ b/{}\n", name, name);
+    for group in groups {
+        let (head, tail) = (&group[0], &group[group.len() - 1]);
+        let old_start = head.old.start.saturating_sub(CONTEXT);
+        let old_end = (tail.old.end + CONTEXT).min(old.len());
+        let new_start = head.new.start - (head.old.start - old_start);
+        let new_end = tail.new.end + (old_end - tail.old.end);
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            span(old_start, old_end),
+            span(new_start, new_end)
+        ));
+
+        let mut line = old_start;
+        for hunk in group {
+            push_lines(&mut diff, ' ', &old[line..hunk.old.start]);
+            push_lines(&mut diff, '-', &old[hunk.old.clone()]);
+            push_lines(&mut diff, '+', &new[hunk.new.clone()]);
+            line = hunk.old.end;
+        }
+        push_lines(&mut diff, ' ', &old[line..old_end]);
+    }
+    diff
+}
+
+/// The `start,count` of a range of lines. An empty range starts at the
+/// line before it.
+fn span(start: usize, end: usize) -> String {
+    match end - start {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        count => format!("{},{}", start + 1, count),
+    }
+}
+
+fn push_lines(diff: &mut String, prefix: char, lines: &[&str]) {
+    for line in lines {
+        diff.push(prefix);
+        diff.push_str(line);
+        if !line.ends_with('\n') {
+            diff.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hunks() {
+        let old = lines("a\nb\nc\nd\ne\n");
+        let new = lines("a\nB\nc\nd\nx\ne\n");
+        assert_eq!(
+            hunks(&old, &new),
+            vec![
+                Hunk {
+                    old: 1..2,
+                    new: 1..2
+                },
+                Hunk {
+                    old: 4..4,
+                    new: 4..5
+                },
+            ]
+        );
+        assert!(hunks(&old, &old).is_empty());
+        assert_eq!(
+            hunks(&old, &[]),
+            vec![Hunk {
+                old: 0..5,
+                new: 0..0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk";
+        assert_eq!(
+            unified_diff("x.bend", old, new),
+            "--- a/x.bend\n+++ b/x.bend\n\
+             @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -8,3 +8,4 @@\n h\n i\n j\n+k\n\\ No newline at end of file\n"
+        );
+        assert_eq!(unified_diff("x.bend", old, old), "");
+    }
+}
@@ -0,0 +1,355 @@
+//! Finding the files to format and formatting many at once
+//!
+//! Paths given to the formatter may be files, directories or glob patterns
+//! like `src/**/*.bend`. Directories and patterns are searched recursively
+//! for `.bend` files, skipping whatever the `.gitignore` files of the
+//! repository ignore. Files named directly are always formatted.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use super::{Formatter, FormatterConfig};
+
+/// Every file the paths name, in order and without duplicates
+pub fn collect_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for path in paths {
+        let text = path.to_string_lossy();
+        if text.contains(['*', '?']) {
+            let pattern = text.replace('\\', "/");
+            let base = glob_base(&pattern);
+            let mut found = Vec::new();
+            walk(&base, &mut Ignore::above(&base), &mut found)?;
+            let matched: Vec<PathBuf> = found
+                .into_iter()
+                .filter(|file| glob_match(&pattern, &file.to_string_lossy().replace('\\', "/")))
+                .collect();
+            if matched.is_empty() {
+                return Err(format!("No files match '{}'", text));
+            }
+            files.extend(matched);
+        } else if path.is_dir() {
+            let mut found = Vec::new();
+            walk(path, &mut Ignore::above(path), &mut found)?;
+            files.extend(
+                found
+                    .into_iter()
+                    .filter(|file| file.extension().is_some_and(|ext| ext == "bend")),
+            );
+        } else if path.is_file() {
+            files.push(path.clone());
+        } else {
+            return Err(format!("No such file or directory: {}", path.display()));
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    files.retain(|file| seen.insert(file.clone()));
+    Ok(files)
+}
+
+/// The outcome of formatting one file
+#[derive(Debug, Clone)]
+pub struct FileFormat {
+    pub path: PathBuf,
+    /// The file as it was read
+    pub source: String,
+    /// The formatted file, or why it could not be formatted
+    pub result: Result<String, String>,
+}
+
+impl FileFormat {
+    /// Whether formatting changes the file
+    pub fn changed(&self) -> bool {
+        self.result
+            .as_ref()
+            .is_ok_and(|formatted| *formatted != self.source)
+    }
+}
+
+/// Format files on up to `jobs` threads, each with the settings found for
+/// it and then changed by `adjust`. Nothing is written; the outcomes are
+/// in the order of the files.
+pub fn format_files(
+    files: &[PathBuf],
+    jobs: usize,
+    adjust: &(dyn Fn(&mut FormatterConfig) + Sync),
+) -> Vec<FileFormat> {
+    if files.is_empty() {
+        return Vec::new();
+    }
+    let per_thread = files.len().div_ceil(jobs.max(1));
+    thread::scope(|scope| {
+        let workers: Vec<_> = files
+            .chunks(per_thread)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|path| format_one(path, adjust))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("formatter thread panicked"))
+            .collect()
+    })
+}
+
+fn format_one(path: &Path, adjust: &(dyn Fn(&mut FormatterConfig) + Sync)) -> FileFormat {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            return FileFormat {
+                path: path.to_path_buf(),
+                source: String::new(),
+                result: Err(e.to_string()),
+            }
+        }
+    };
+    let result = FormatterConfig::discover(path).and_then(|config| {
+        let mut config = config.unwrap_or_default();
+        adjust(&mut config);
+        Formatter::with_config(config).format_source(&source)
+    });
+    FileFormat {
+        path: path.to_path_buf(),
+        source,
+        result,
+    }
+}
+
+/// Every file below `dir` that is not ignored, sorted by path
+fn walk(dir: &Path, ignore: &mut Ignore, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let rules = ignore.rules.len();
+    ignore.read(dir);
+
+    let entries = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    paths.sort();
+    for path in paths {
+        let is_dir = path.is_dir();
+        if path.file_name().is_some_and(|name| name == ".git") || ignore.ignores(&path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            walk(&path, ignore, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    ignore.rules.truncate(rules);
+    Ok(())
+}
+
+/// The leading directories of a pattern that hold no wildcards
+fn glob_base(pattern: &str) -> PathBuf {
+    let mut base = PathBuf::new();
+    let segments: Vec<&str> = pattern.split('/').collect();
+    for segment in &segments[..segments.len() - 1] {
+        if segment.contains(['*', '?']) {
+            break;
+        }
+        base.push(if segment.is_empty() { "/" } else { segment });
+    }
+    if base.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        base
+    }
+}
+
+/// Whether `text` matches a pattern where `*` is any run of characters
+/// within one path segment, `?` is one such character and `**` is any run
+/// of whole segments
+fn glob_match(pattern: &str, text: &str) -> bool {
+    // Paths found under `.` start with `./`, patterns usually do not
+    let text = text.strip_prefix("./").unwrap_or(text);
+    let pattern = pattern.strip_prefix("./").unwrap_or(pattern);
+    matches(pattern.as_bytes(), text.as_bytes())
+}
+
+fn matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            // Zero or more whole segments
+            matches(rest, text)
+                || text
+                    .iter()
+                    .enumerate()
+                    .any(|(i, c)| *c == b'/' && matches(rest, &text[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+        [b'*', rest @ ..] => {
+            let segment = text.iter().position(|c| *c == b'/').unwrap_or(text.len());
+            (0..=segment).any(|i| matches(rest, &text[i..]))
+        }
+        [b'?', rest @ ..] => text.first().is_some_and(|c| *c != b'/') && matches(rest, &text[1..]),
+        [c, rest @ ..] => text.first() == Some(c) && matches(rest, &text[1..]),
+    }
+}
+
+/// The `.gitignore` rules in force in a directory
+struct Ignore {
+    rules: Vec<IgnoreRule>,
+}
+
+struct IgnoreRule {
+    /// The directory of the `.gitignore` the rule is from
+    base: PathBuf,
+    pattern: String,
+    /// `!pattern`, which brings back what an earlier rule ignored
+    negated: bool,
+    /// `pattern/`, which only ignores directories
+    directories_only: bool,
+    /// Patterns with a `/` match from the base, others match any name
+    anchored: bool,
+}
+
+impl Ignore {
+    /// The rules of the repository `dir` is in, from the `.gitignore` files
+    /// of the directories above it
+    fn above(dir: &Path) -> Self {
+        let mut ignore = Ignore { rules: Vec::new() };
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        let Some(root) = dir.ancestors().find(|dir| dir.join(".git").exists()) else {
+            return ignore;
+        };
+        let above: Vec<&Path> = dir
+            .ancestors()
+            .skip(1)
+            .take_while(|ancestor| ancestor.starts_with(root))
+            .collect();
+        for ancestor in above.into_iter().rev() {
+            ignore.read(ancestor);
+        }
+        ignore
+    }
+
+    /// Add the rules of the `.gitignore` in `dir`. Rules keep the full path
+    /// of their directory, since walked paths may be relative.
+    fn read(&mut self, dir: &Path) {
+        let Ok(text) = fs::read_to_string(dir.join(".gitignore")) else {
+            return;
+        };
+        for line in text.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let (directories_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            self.rules.push(IgnoreRule {
+                base: dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf()),
+                pattern: line.trim_start_matches('/').to_string(),
+                negated,
+                directories_only,
+                anchored: line.contains('/'),
+            });
+        }
+    }
+
+    /// Whether the last rule matching `path` ignores it
+    fn ignores(&self, path: &Path, is_dir: bool) -> bool {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.directories_only && !is_dir {
+                continue;
+            }
+            let Ok(relative) = path.strip_prefix(&rule.base) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            let matched = if rule.anchored {
+                glob_match(&rule.pattern, &relative)
+            } else {
+                let name = relative.rsplit('/').next().unwrap_or(&relative);
+                glob_match(&rule.pattern, name)
+            };
+            if matched {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("src/**/*.bend", "src/a.bend"));
+        assert!(glob_match("src/**/*.bend", "./src/a/b/c.bend"));
+        assert!(!glob_match("src/*.bend", "src/a/b.bend"));
+        assert!(glob_match("?.bend", "a.bend"));
+        assert!(!glob_match("*.bend", "a.bend.bak"));
+    }
+
+    #[test]
+    fn test_collect_respects_gitignore() {
+        let root = std::env::temp_dir().join(format!("bend-fmt-files-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("src").join("generated")).unwrap();
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::write(root.join(".gitignore"), "build/\n*.tmp.bend\n").unwrap();
+        fs::write(
+            root.join("src").join(".gitignore"),
+            "generated/\n!keep.tmp.bend\n",
+        )
+        .unwrap();
+        for file in [
+            "src/main.bend",
+            "src/notes.txt",
+            "src/scratch.tmp.bend",
+            "src/keep.tmp.bend",
+            "src/generated/out.bend",
+            "build/out.bend",
+        ] {
+            fs::write(root.join(file), "fn main() {}\n").unwrap();
+        }
+
+        let names = |files: Vec<PathBuf>| -> Vec<String> {
+            files
+                .iter()
+                .map(|file| {
+                    let file = file.canonicalize().unwrap();
+                    let root = root.canonicalize().unwrap();
+                    file.strip_prefix(root)
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect()
+        };
+        let files = collect_files(std::slice::from_ref(&root)).unwrap();
+        assert_eq!(names(files), vec!["src/keep.tmp.bend", "src/main.bend"]);
+
+        // Rules of the directories above a walked one still apply
+        let files = collect_files(&[root.join("src")]).unwrap();
+        assert_eq!(names(files), vec!["src/keep.tmp.bend", "src/main.bend"]);
+
+        let pattern = root.join("**").join("m*.bend");
+        let files = collect_files(&[pattern, root.join("build/out.bend")]).unwrap();
+        assert_eq!(names(files), vec!["src/main.bend", "build/out.bend"]);
+
+        assert!(collect_files(&[root.join("missing.bend")]).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
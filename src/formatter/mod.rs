@@ -2,30 +2,23 @@
 //!
 //! Provides automatic formatting for Bend source files with configurable
 //! style options including indentation, line length, and spacing rules.
+//! Sources are parsed and printed back from the AST, so the result reads
+//! the same to the parser as the input did.
+
+mod config;
+mod diff;
+mod files;
+mod printer;
+
+pub use config::{FormatterConfig, TrailingComma, CONFIG_FILE};
+pub use diff::unified_diff;
+pub use files::{collect_files, format_files, FileFormat};
 
 use std::fs;
+use std::ops::Range;
 use std::path::Path;
 
-#[derive(Debug, Clone)]
-pub struct FormatterConfig {
-    pub indent_size: usize,
-    pub max_line_length: usize,
-    pub use_tabs: bool,
-    pub blank_line_after_fn: bool,
-    pub space_around_operators: bool,
-}
-
-impl Default for FormatterConfig {
-    fn default() -> Self {
-        Self {
-            indent_size: 4,
-            max_line_length: 120,
-            use_tabs: false,
-            blank_line_after_fn: true,
-            space_around_operators: true,
-        }
-    }
-}
+use crate::compiler::parser::parser::Parser;
 
 #[derive(Debug, Clone)]
 pub enum FormatResult {
@@ -35,276 +28,109 @@ pub enum FormatResult {
     Error(String),
 }
 
+/// A replacement for the bytes `range` of a source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatEdit {
+    pub range: Range<usize>,
+    pub new_text: String,
+}
+
 pub struct Formatter {
     config: FormatterConfig,
-    indent_stack: Vec<usize>,
 }
 
 impl Formatter {
     pub fn new() -> Self {
         Self {
             config: FormatterConfig::default(),
-            indent_stack: vec![0],
         }
     }
 
     pub fn with_config(config: FormatterConfig) -> Self {
-        Self {
-            config,
-            indent_stack: vec![0],
-        }
+        Self { config }
     }
 
+    /// Format a whole program: parse it and print the AST back, keeping its
+    /// comments. Fails on source that does not parse.
     pub fn format_source(&mut self, source: &str) -> Result<String, String> {
-        let lines: Vec<&str> = source.lines().collect();
-        let mut result: Vec<String> = Vec::new();
-        let mut i = 0;
-
-        while i < lines.len() {
-            let line = lines[i].trim_end();
-            let trimmed = line.trim_start();
-
-            if trimmed.is_empty() {
-                if !result.is_empty() && !result.last().unwrap().is_empty() {
-                    result.push(String::new());
-                }
-                i += 1;
-                continue;
-            }
-
-            let prev_ends_with_brace = result
-                .last()
-                .map(|l| l.trim_end().ends_with('}'))
-                .unwrap_or(false);
-            let prev_line_ends_with_brace_or_is_fn = if !result.is_empty() {
-                let prev = result.last().unwrap().trim();
-                prev.ends_with('}')
-            } else {
-                false
-            };
-
-            let (processed_line, indent_change) = self.process_line(trimmed, prev_ends_with_brace);
-
-            if self.config.blank_line_after_fn
-                && prev_line_ends_with_brace_or_is_fn
-                && !processed_line.is_empty()
-                && !result.is_empty()
-                && !result.last().unwrap().is_empty()
-            {
-                result.push(String::new());
-            }
-
-            if let Some(change) = indent_change {
-                if change > 0 {
-                    let current = *self.indent_stack.last().unwrap();
-                    self.indent_stack.push(current + change as usize);
-                } else {
-                    for _ in 0..change.abs() {
-                        if self.indent_stack.len() > 1 {
-                            self.indent_stack.pop();
-                        }
-                    }
-                }
-            }
-
-            let indent_level = *self.indent_stack.last().unwrap();
-            let indent_str = self.get_indent(indent_level);
-            result.push(format!("{}{}", indent_str, processed_line));
-
-            i += 1;
+        let program = Parser::new(source)
+            .keep_contracts()
+            .parse_program()
+            .map_err(|e| e.to_string())?;
+        let formatted = printer::print_program(source, &program, &self.config);
+
+        // Definitions the printer cannot reproduce are copied as written,
+        // so this only fails on a bug in the printer
+        let reparsed = Parser::new(&formatted)
+            .keep_contracts()
+            .parse_program()
+            .map_err(|e| format!("Formatting produced source that does not parse: {}", e))?;
+        if !printer::same_program(&program, &reparsed) {
+            return Err("Formatting would change the meaning of the program".to_string());
         }
 
-        let joined = result.join("\n");
-        Ok(self.normalize_whitespace(&joined))
+        Ok(formatted)
     }
 
-    fn process_line(&self, line: &str, _prev_ends_with_brace: bool) -> (String, Option<isize>) {
-        let body = line.trim_start();
-        let opens_block = body.contains('{');
-        let closes_block = body.contains('}');
-
-        let normalized_body = self.normalize_spaces(body);
-        let indent_change = if opens_block && !closes_block {
-            Some(1)
-        } else if closes_block && !opens_block {
-            Some(-1)
-        } else {
-            None
-        };
-
-        (normalized_body, indent_change)
-    }
-
-    fn normalize_spaces(&self, line: &str) -> String {
-        let mut result = line.to_string();
-
-        result = result.replace("->", " -> ");
-
-        let re_multi_space = regex::Regex::new(r"\s{2,}").unwrap();
-        result = re_multi_space.replace_all(&result, " ").to_string();
-
-        result = self.normalize_params(&result);
-
-        result = self.normalize_operators(&result);
-
-        result = result.replace("( ", "(");
-        result = result.replace(" )", ")");
-        result = result.replace("{ ", "{");
-        result = result.replace(" }", "}");
-
-        result.trim().to_string()
-    }
-
-    fn normalize_operators(&self, line: &str) -> String {
-        let mut result = line.to_string();
-        let operators = [
-            "+", "-", "*", "/", "%", "=", "==", "!=", "<", ">", "<=", ">=",
-        ];
-
-        for op in operators {
-            let with_spaces = format!(" {} ", op);
-            let without_spaces = format!("{}{}", op, op);
-            result = result.replace(&without_spaces, &with_spaces);
-
-            let pattern = regex::Regex::new(&format!(r"(\w)({})", regex::escape(op))).unwrap();
-            result = pattern
-                .replace_all(&result, &format!("$1{}", with_spaces))
-                .to_string();
-
-            let pattern = regex::Regex::new(&format!(r"({})(\w)", regex::escape(op),)).unwrap();
-            result = pattern
-                .replace_all(&result, &format!("{} $1$3", with_spaces))
-                .to_string();
-        }
-
-        result
-    }
-
-    fn normalize_params(&self, s: &str) -> String {
-        let re = regex::Regex::new(r"\(([^)]*)\)").unwrap();
-
-        re.replace_all(s, |caps: &regex::Captures| {
-            let inner = caps.get(1).map_or("", |m| m.as_str());
-            let normalized = self.normalize_param_inner(inner);
-            format!("({})", normalized)
-        })
-        .to_string()
-    }
-
-    fn normalize_param_inner(&self, inner: &str) -> String {
-        let trimmed = inner.trim();
-        if trimmed.is_empty() {
-            return String::new();
+    /// Format only the lines the bytes `range` of `source` touch. The whole
+    /// program still has to parse, but what the formatter would change on
+    /// other lines is left out, so the rest of the source stays as written.
+    pub fn format_range(
+        &mut self,
+        source: &str,
+        range: Range<usize>,
+    ) -> Result<Vec<FormatEdit>, String> {
+        let formatted = self.format_source(source)?;
+        let old = diff::lines(source);
+        let new = diff::lines(&formatted);
+
+        let mut starts = Vec::with_capacity(old.len() + 1);
+        let mut offset = 0;
+        for line in &old {
+            starts.push(offset);
+            offset += line.len();
         }
-
-        let parts: Vec<&str> = trimmed.split(',').collect();
-        let normalized_parts: Vec<String> = parts
-            .iter()
-            .map(|part| {
-                let p = part.trim();
-                if p.is_empty() {
-                    String::new()
+        let line_of = |offset: usize| {
+            starts
+                .partition_point(|&start| start <= offset)
+                .saturating_sub(1)
+        };
+        let first = line_of(range.start);
+        // A selection of whole lines ends at the start of the next one
+        let last = line_of(range.end.saturating_sub(1).max(range.start));
+        starts.push(source.len());
+
+        let edits = diff::hunks(&old, &new)
+            .into_iter()
+            // Lines rewritten one for one are edited one at a time, so
+            // a selection only picks up the lines in it
+            .flat_map(|hunk| {
+                if hunk.old.len() == hunk.new.len() {
+                    hunk.old
+                        .clone()
+                        .zip(hunk.new.clone())
+                        .map(|(old, new)| diff::Hunk {
+                            old: old..old + 1,
+                            new: new..new + 1,
+                        })
+                        .collect()
                 } else {
-                    let re_spaces = regex::Regex::new(r"\s+").unwrap();
-                    let normalized = re_spaces.replace_all(p, " ").to_string();
-                    if normalized.contains(':') && !normalized.contains(": ") {
-                        let normalized = normalized.replace(":", ": ");
-                        normalized.trim().to_string()
-                    } else {
-                        normalized.trim().to_string()
-                    }
+                    vec![hunk]
                 }
             })
-            .filter(|s| !s.is_empty())
-            .collect();
-
-        normalized_parts.join(", ")
-    }
-
-    fn normalize_parens(&self, line: &str) -> String {
-        let mut result = String::new();
-        let mut in_parens = false;
-        let chars: Vec<char> = line.chars().collect();
-
-        for i in 0..chars.len() {
-            let c = chars[i];
-
-            match c {
-                '(' | '[' => {
-                    in_parens = true;
-                    result.push(c);
-                    if i + 1 < chars.len() && chars[i + 1] == ' ' {
-                        continue;
-                    }
-                }
-                ')' | ']' => {
-                    in_parens = false;
-                    if result.ends_with(' ') {
-                        result.pop();
-                    }
-                    result.push(c);
-                    if i + 1 < chars.len() && chars[i + 1] == ' ' {
-                        continue;
-                    }
-                }
-                ',' if in_parens => {
-                    result.push(',');
-                    if i + 1 < chars.len() && chars[i + 1] != ' ' {
-                        result.push(' ');
-                    }
-                }
-                ' ' if i > 0 && i < chars.len() - 1 => {
-                    let prev = chars[i - 1];
-                    let next = chars[i + 1];
-                    if prev == ' ' || next == ' ' {
-                        continue;
-                    }
-                    if prev == '('
-                        || prev == '['
-                        || prev == ','
-                        || next == ')'
-                        || next == ']'
-                        || next == ','
-                    {
-                        continue;
-                    }
-                    result.push(c);
-                }
-                _ => result.push(c),
-            }
-        }
-
-        result.replace("  ", " ")
-    }
-
-    fn get_indent(&self, level: usize) -> String {
-        if self.config.use_tabs {
-            "\t".repeat(level)
-        } else {
-            " ".repeat(level * self.config.indent_size)
-        }
-    }
-
-    fn normalize_whitespace(&self, code: &str) -> String {
-        let mut result = String::new();
-        let mut blank_line_pending = false;
-
-        for line in code.lines() {
-            let trimmed = line.trim_end();
-
-            if trimmed.is_empty() {
-                blank_line_pending = true;
-            } else {
-                if blank_line_pending && !result.is_empty() {
-                    result.push('\n');
+            .filter(|hunk| {
+                if hunk.old.is_empty() {
+                    (first..=last).contains(&hunk.old.start)
+                } else {
+                    hunk.old.start <= last && hunk.old.end > first
                 }
-                result.push_str(trimmed);
-                result.push('\n');
-                blank_line_pending = false;
-            }
-        }
-
-        result.trim_end().to_string()
+            })
+            .map(|hunk| FormatEdit {
+                range: starts[hunk.old.start]..starts[hunk.old.end],
+                new_text: new[hunk.new].concat(),
+            })
+            .collect();
+        Ok(edits)
     }
 
     /// Check if code is already properly formatted
@@ -423,6 +249,43 @@ mod tests {
         assert!(formatter.is_formatted(formatted));
     }
 
+    #[test]
+    fn test_cfg_attributes() {
+        let mut formatter = Formatter::new();
+
+        let source =
+            "#[cfg( any(test,feature=\"debug\") )]\nfn check() -> u24 {\n    return 1;\n}\n";
+        let formatted = formatter.format_source(source).unwrap();
+        assert!(formatted.starts_with("#[cfg(any(test, feature = \"debug\"))]\n"));
+        assert!(formatter.is_formatted(&formatted));
+    }
+
+    #[test]
+    fn test_contract_is_kept() {
+        let mut formatter = Formatter::new();
+
+        let source = "#[version(2)]\ncontract Counter {\nlet count: u24;\n# Raised on every change\nevent Changed { to: u24 }\nfn constructor() { count = 1; }\npub  let step: u24;\n}\n";
+        let formatted = formatter.format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "#[version(2)]\ncontract Counter {\n    let count: u24;\n    # Raised on every change\n    event Changed {\n        to: u24,\n    }\n\n    fn constructor() {\n        count = 1;\n    }\n\n    pub let step: u24;\n}\n"
+        );
+        assert!(formatter.is_formatted(&formatted));
+    }
+
+    #[test]
+    fn test_inline_asm() {
+        let mut formatter = Formatter::new();
+
+        let source = "fn double(x: u24) -> u24 {\n    return asm( in a0=x,out a0 ) { \"add a0, a0, a0\"\n\"ebreak\" };\n}\n";
+        let formatted = formatter.format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "fn double(x: u24) -> u24 {\n    return asm(in a0 = x, out a0) {\n        \"add a0, a0, a0\"\n        \"ebreak\"\n    };\n}\n"
+        );
+        assert!(formatter.is_formatted(&formatted));
+    }
+
     #[test]
     fn test_indentation() {
         let mut formatter = Formatter::new();
@@ -449,4 +312,129 @@ mod tests {
         let result = formatter.format_source(unformatted).unwrap();
         assert_eq!(result.matches("\n\n").count(), 1);
     }
+
+    const PROGRAM: &str = r#"# A token
+from std/Option import Option;
+
+#[selector(0x12345678), payable]
+pub   fn transfer( to:u24 ,amount : u24)->u24{   # checked below
+  x=a+b*c;
+  y = (a+b)*c
+
+
+  if x>0 {return 1} else {
+      return f(x, amount: 2)
+  }
+  match shape {
+    Shape/Circle(r) => r*2,
+    _ => { return 0; }
+  }
+  for i in range(10) bound 5 { total = total + i; }
+  unchecked { i = i + 1; }
+  let r: u24 = x;
+  return;
+}
+type Shape { Circle(radius: u24),   Point }
+#{ The storage }#
+storage { total: u24 }
+"#;
+
+    #[test]
+    fn test_round_trip_and_idempotence() {
+        let mut formatter = Formatter::new();
+        let formatted = formatter.format_source(PROGRAM).unwrap();
+
+        let before = Parser::new(PROGRAM).parse_program().unwrap();
+        let after = Parser::new(&formatted).parse_program().unwrap();
+        assert!(printer::same_program(&before, &after));
+        assert_eq!(formatter.format_source(&formatted).unwrap(), formatted);
+
+        assert!(
+            formatted.contains("pub fn transfer(to: u24, amount: u24) -> u24 { # checked below\n")
+        );
+        assert!(formatted
+            .contains("    y = (a + b) * c;\n\n    if x > 0 {\n        return 1;\n    } else {"));
+        assert!(formatted.contains("        Shape/Circle(r) => r * 2,\n        _ => {\n"));
+        assert!(formatted.contains("    let r: u24 = x;\n    return;\n}\n"));
+        assert!(formatted.contains(
+            "type Shape {\n    Circle(radius: u24),\n    Point,\n}\n#{ The storage }#\nstorage {"
+        ));
+    }
+
+    #[test]
+    fn test_comments_are_kept() {
+        let mut formatter = Formatter::new();
+        let formatted = formatter.format_source(PROGRAM).unwrap();
+        for comment in ["# A token", "# checked below", "#{ The storage }#"] {
+            assert_eq!(formatted.matches(comment).count(), 1, "{}", formatted);
+        }
+    }
+
+    #[test]
+    fn test_source_that_does_not_parse_is_an_error() {
+        let mut formatter = Formatter::new();
+        assert!(formatter.format_source("fn broken( {").is_err());
+        assert!(!formatter.is_formatted("fn broken( {"));
+    }
+
+    #[test]
+    fn test_standard_library_is_formatted() {
+        let mut formatter = Formatter::new();
+        for module in crate::stdlib::modules::modules() {
+            let source = crate::stdlib::modules::source(module).unwrap();
+            assert!(
+                formatter.is_formatted(source),
+                "{} is not formatted",
+                module
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_range_leaves_other_lines_alone() {
+        let mut formatter = Formatter::new();
+        let source = "fn a() -> u24 {\nreturn 1;\n}\n\nfn b() -> u24 {\nx = 1+ 2;\nreturn x;\n}\n";
+        let start = source.find("x = ").unwrap();
+
+        let edits = formatter.format_range(source, start..start + 3).unwrap();
+        assert_eq!(
+            edits,
+            vec![FormatEdit {
+                range: start..start + "x = 1+ 2;\n".len(),
+                new_text: "    x = 1 + 2;\n".to_string(),
+            }]
+        );
+
+        let edits = formatter.format_range(source, 0..source.len()).unwrap();
+        assert_eq!(edits.len(), 3);
+        assert!(formatter.format_range("fn broken( {", 0..3).is_err());
+    }
+
+    #[test]
+    fn test_comments_inside_statements_stay_in_place() {
+        let mut formatter = Formatter::new();
+        let source = "fn main() -> u24 {\n  y = 1 +  2;\n      x = total(\n          1, # one\n          2\n      );\n  return x;\n}\n";
+        assert_eq!(
+            formatter.format_source(source).unwrap(),
+            "fn main() -> u24 {\n    y = 1 + 2;\n    x = total(\n        1, # one\n        2\n    );\n    return x;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_reflow_long_comments() {
+        let source = "# A comment that is far too long to fit on one line of this width\n# and goes on.\n#\n# - kept as written\nfn main() -> u24 {\n    # Short enough\n    # to keep.\n    return 1;\n}\n";
+        let config = FormatterConfig {
+            max_line_length: 30,
+            reflow_comments: true,
+            ..Default::default()
+        };
+        let formatted = Formatter::with_config(config)
+            .format_source(source)
+            .unwrap();
+        assert_eq!(
+            formatted,
+            "# A comment that is far too\n# long to fit on one line of\n# this width and goes on.\n#\n# - kept as written\nfn main() -> u24 {\n    # Short enough\n    # to keep.\n    return 1;\n}\n"
+        );
+        assert_eq!(Formatter::new().format_source(source).unwrap(), source);
+    }
 }
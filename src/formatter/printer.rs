@@ -0,0 +1,1579 @@
+//! Pretty-printing of parsed programs
+//!
+//! Every construct is written one way whatever its layout in the source, so
+//! formatting twice gives the same text. Comments are not part of the AST:
+//! they are read from the gaps between tokens and written before the item
+//! that follows them, or at the end of the line they trailed. Blank lines
+//! between items are kept, collapsed to one.
+//!
+//! Each definition is parsed again once printed; one that does not come
+//! back as the same definition, e.g. because it holds a `let` with a type
+//! annotation the AST does not keep, is copied from the source as written.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::compiler::lexer::lexer::{BendLexer, TokenWithPosition};
+use crate::compiler::lexer::token::Token;
+use crate::compiler::parser::ast::*;
+use crate::compiler::parser::parser::Parser;
+
+use super::{FormatterConfig, TrailingComma};
+
+/// Print `program`, parsed from `source`, the way `config` asks for
+pub(crate) fn print_program(source: &str, program: &Program, config: &FormatterConfig) -> String {
+    let mut printer = Printer::new(source, config);
+
+    for import in &program.imports {
+        let start = match import {
+            Import::FromImport { location, .. } | Import::DirectImport { location, .. } => {
+                location.start
+            }
+        };
+        printer.item(0, start, false);
+        printer.import(import);
+    }
+
+    for (i, definition) in program.definitions.iter().enumerate() {
+        let start = printer.leading_start(definition);
+        let spaced = match i.checked_sub(1).map(|i| &program.definitions[i]) {
+            Some(Definition::FunctionDef { .. }) => config.blank_line_after_fn,
+            Some(_) => false,
+            None => !program.imports.is_empty(),
+        };
+        printer.item(0, start, spaced);
+
+        let next = program
+            .definitions
+            .get(i + 1)
+            .map_or(source.len(), |next| printer.leading_start(next));
+        printer.top_level(definition, start, next);
+    }
+
+    printer.comments(0, usize::MAX, false);
+    if printer.lines.is_empty() {
+        return String::new();
+    }
+    let mut text = printer.lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Whether two programs are the same once their locations are left out
+pub(crate) fn same_program(a: &Program, b: &Program) -> bool {
+    same(&a.imports, &b.imports) && same(&a.definitions, &b.definitions)
+}
+
+fn same<T: Serialize + ?Sized>(a: &T, b: &T) -> bool {
+    shape(a) == shape(b)
+}
+
+/// The node as JSON, without the locations that differ between layouts
+fn shape<T: Serialize + ?Sized>(node: &T) -> Value {
+    fn strip(value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                fields.remove("location");
+                fields.values_mut().for_each(strip);
+            }
+            Value::Array(elements) => elements.iter_mut().for_each(strip),
+            _ => {}
+        }
+    }
+
+    let mut value = serde_json::to_value(node).unwrap_or(Value::Null);
+    strip(&mut value);
+    value
+}
+
+/// A comment and the byte range it spans
+struct Comment {
+    start: usize,
+    end: usize,
+    text: String,
+    /// Whether code comes before it on its line
+    trailing: bool,
+}
+
+struct Printer<'a> {
+    source: &'a str,
+    config: &'a FormatterConfig,
+    tokens: Vec<TokenWithPosition>,
+    comments: Vec<Comment>,
+    /// The first comment not written yet
+    next_comment: usize,
+    lines: Vec<String>,
+    /// The line a trailing comment was last added to
+    commented: Option<usize>,
+    /// Set when a construct the parser cannot read back was printed
+    unsupported: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn new(source: &'a str, config: &'a FormatterConfig) -> Self {
+        let mut lexer = BendLexer::with_comments(source);
+        let mut tokens = Vec::new();
+        let mut comments = Vec::new();
+        loop {
+            let mut token = lexer.next_token();
+            let leading = std::mem::take(&mut token.leading_comments);
+            let trailing = std::mem::take(&mut token.trailing_comments);
+            comments.extend(leading.into_iter().map(|comment| (comment, false)));
+            comments.extend(trailing.into_iter().map(|comment| (comment, true)));
+            if token.token == Token::EOF {
+                break;
+            }
+            tokens.push(token);
+        }
+        let comments = comments
+            .into_iter()
+            .map(|(comment, trailing)| Comment {
+                start: comment.start,
+                end: comment.end,
+                text: comment.text,
+                trailing,
+            })
+            .collect();
+
+        Printer {
+            source,
+            config,
+            tokens,
+            comments,
+            next_comment: 0,
+            lines: Vec::new(),
+            commented: None,
+            unsupported: false,
+        }
+    }
+
+    // Layout
+
+    fn indent(&self, level: usize) -> String {
+        if self.config.use_tabs {
+            "\t".repeat(level)
+        } else {
+            " ".repeat(level * self.config.indent_size)
+        }
+    }
+
+    /// Write `text` at `level`; lines after its first carry their own
+    /// indentation
+    fn line(&mut self, level: usize, text: &str) {
+        let mut parts = text.split('\n');
+        let first = parts.next().unwrap_or_default();
+        self.lines.push(if first.is_empty() {
+            String::new()
+        } else {
+            format!("{}{}", self.indent(level), first)
+        });
+        self.lines.extend(parts.map(str::to_string));
+    }
+
+    fn blank(&mut self) {
+        if self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+
+    /// `items` between `open` and `close`, on one line if it fits and one
+    /// item per line otherwise. The items are printed at `level + 1`.
+    fn list(&self, open: &str, items: &[String], close: &str, level: usize) -> String {
+        self.layout(open, items, close, level, false)
+    }
+
+    /// A list the parser accepts a trailing comma in, which gets one as the
+    /// trailing comma policy says
+    fn trailing_list(&self, open: &str, items: &[String], close: &str, level: usize) -> String {
+        self.layout(open, items, close, level, true)
+    }
+
+    fn layout(
+        &self,
+        open: &str,
+        items: &[String],
+        close: &str,
+        level: usize,
+        trailing: bool,
+    ) -> String {
+        let policy = if trailing {
+            self.config.trailing_comma
+        } else {
+            TrailingComma::Never
+        };
+        let mut flat = format!("{}{}", open, items.join(", "));
+        if policy == TrailingComma::Always && !items.is_empty() {
+            flat.push(',');
+        }
+        flat.push_str(close);
+        let width = level * self.config.indent_size + flat.len();
+        if items.is_empty() || (!flat.contains('\n') && width <= self.config.max_line_length) {
+            return flat;
+        }
+
+        let inner = self.indent(level + 1);
+        let mut text = format!("{}\n", open);
+        for (i, item) in items.iter().enumerate() {
+            text.push_str(&inner);
+            text.push_str(item);
+            if i + 1 < items.len() || policy != TrailingComma::Never {
+                text.push(',');
+            }
+            text.push('\n');
+        }
+        text.push_str(&self.indent(level));
+        text.push_str(close);
+        text
+    }
+
+    // Source positions
+
+    fn text(&self, token: &TokenWithPosition) -> &'a str {
+        &self.source[token.start..token.end]
+    }
+
+    fn token_index(&self, offset: usize) -> usize {
+        self.tokens.partition_point(|token| token.start < offset)
+    }
+
+    /// Whether the source has a blank line between `offset` and the token
+    /// or comment before it
+    fn blank_before(&self, offset: usize) -> bool {
+        let token_end = self
+            .tokens
+            .partition_point(|token| token.end <= offset)
+            .checked_sub(1)
+            .map(|i| self.tokens[i].end);
+        let comment_end = self
+            .comments
+            .partition_point(|comment| comment.end <= offset)
+            .checked_sub(1)
+            .map(|i| self.comments[i].end);
+        match token_end.max(comment_end) {
+            Some(end) => self.source[end..offset].matches('\n').count() >= 2,
+            None => false,
+        }
+    }
+
+    /// The end of the last token before `offset`
+    fn token_end_before(&self, offset: usize) -> usize {
+        self.tokens
+            .partition_point(|token| token.end <= offset)
+            .checked_sub(1)
+            .map_or(0, |i| self.tokens[i].end)
+    }
+
+    /// The offset of the `}` closing the first `{` at or after `offset`
+    fn closing(&self, offset: usize) -> usize {
+        let from = self.token_index(offset);
+        let open = from
+            + self.tokens[from..]
+                .iter()
+                .position(|token| token.token == Token::LBrace)
+                .unwrap_or(0);
+        let mut depth = 0;
+        for token in self.tokens.iter().skip(open) {
+            match token.token {
+                Token::LBrace => depth += 1,
+                Token::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return token.start;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.source.len()
+    }
+
+    /// The offset of the `}` closing the block `offset` is in
+    fn enclosing_close(&self, offset: usize) -> usize {
+        let mut depth = 0;
+        for token in &self.tokens[self.token_index(offset)..] {
+            match token.token {
+                Token::LBrace => depth += 1,
+                Token::RBrace if depth == 0 => return token.start,
+                Token::RBrace => depth -= 1,
+                _ => {}
+            }
+        }
+        self.source.len()
+    }
+
+    /// Where a definition starts, including its attributes and `pub`
+    fn leading_start(&self, definition: &Definition) -> usize {
+        let offset = definition.location().start;
+        let attributed = !definition.attributes().is_empty();
+
+        let mut i = self.token_index(offset);
+        while let Some(previous) = i.checked_sub(1) {
+            match self.tokens[previous].token {
+                Token::Pub => i = previous,
+                Token::RBracket if attributed => {
+                    match self.tokens[..previous]
+                        .iter()
+                        .rposition(|token| token.token == Token::HashBracket)
+                    {
+                        Some(open) => i = open,
+                        None => break,
+                    }
+                }
+                _ => break,
+            }
+        }
+        self.tokens.get(i).map_or(offset, |token| token.start)
+    }
+
+    // Comments and items
+
+    fn comment_before(&self, offset: usize) -> bool {
+        self.comments
+            .get(self.next_comment)
+            .is_some_and(|comment| comment.start < offset)
+    }
+
+    /// Write the comments before `offset`: those that trailed code at the
+    /// end of the last line, the others on lines of their own, after a
+    /// blank line when `spaced`. Returns whether any got a line.
+    fn comments(&mut self, level: usize, offset: usize, spaced: bool) -> bool {
+        let mut written = false;
+        while self.comment_before(offset) {
+            let comment = &self.comments[self.next_comment];
+            let (start, text, trailing) = (comment.start, comment.text.clone(), comment.trailing);
+            self.next_comment += 1;
+            match self.lines.len().checked_sub(1) {
+                Some(last)
+                    if trailing && self.commented != Some(last) && !self.lines[last].is_empty() =>
+                {
+                    self.lines[last].push(' ');
+                    self.lines[last].push_str(&text);
+                    self.commented = Some(last);
+                }
+                _ => {
+                    if (spaced && !written) || self.blank_before(start) {
+                        self.blank();
+                    }
+                    if self.config.reflow_comments && !text.starts_with("#{") {
+                        let run = self.comment_run(text, offset);
+                        let width = self
+                            .config
+                            .max_line_length
+                            .saturating_sub(level * self.config.indent_size);
+                        for line in reflow(&run, width) {
+                            self.line(level, &line);
+                        }
+                    } else {
+                        self.line(level, &text);
+                    }
+                    written = true;
+                }
+            }
+        }
+        written
+    }
+
+    /// `first` and the line comments on the lines right after it, before
+    /// `offset`, each on a line of its own
+    fn comment_run(&mut self, first: String, offset: usize) -> Vec<String> {
+        let mut run = vec![first];
+        let mut end = self.comments[self.next_comment - 1].end;
+        while let Some(next) = self.comments.get(self.next_comment) {
+            let adjacent = self.source[end..next.start].matches('\n').count() == 1;
+            if next.trailing || next.start >= offset || next.text.starts_with("#{") || !adjacent {
+                break;
+            }
+            run.push(next.text.clone());
+            end = next.end;
+            self.next_comment += 1;
+        }
+        run
+    }
+
+    /// Start an item found at `offset` in the source: write the comments
+    /// before it, and a blank line if the source had one or `spaced` asks
+    /// for it
+    fn item(&mut self, level: usize, offset: usize, spaced: bool) {
+        let commented = self.comments(level, offset, spaced);
+        if (spaced && !commented) || self.blank_before(offset) {
+            self.blank();
+        }
+    }
+
+    /// Write the comments left before the `}` at `offset`, then the brace
+    fn close(&mut self, level: usize, offset: usize) {
+        self.comments(level + 1, offset, false);
+        self.line(level, "}");
+    }
+
+    /// Write `head{`, the statements of `block` and its closing brace, or
+    /// `head{}` for an empty block. With `continues` the head goes at the
+    /// end of the last line, as in `} else {`.
+    fn block(&mut self, level: usize, head: &str, block: &Block, continues: bool) {
+        let close = self.closing(block.location.start);
+        let empty = block.statements.is_empty() && !self.comment_before(close);
+        let opening = format!("{}{}", head, if empty { "{}" } else { "{" });
+        match self.lines.last_mut() {
+            Some(last) if continues => last.push_str(&opening),
+            _ => self.line(level, &opening),
+        }
+        if !empty {
+            self.statements(level + 1, &block.statements);
+            self.close(level, close);
+        }
+    }
+
+    fn statements(&mut self, level: usize, statements: &[Statement]) {
+        for (i, statement) in statements.iter().enumerate() {
+            let start = statement.location().start;
+            self.item(level, start, false);
+            let end = statements
+                .get(i + 1)
+                .map_or_else(|| self.enclosing_close(start), |next| next.location().start);
+
+            let mark = (self.lines.len(), self.next_comment, self.commented);
+            self.statement(level, statement);
+            if !self.comments_in_place(start, end, mark.0) {
+                self.lines.truncate(mark.0);
+                (self.next_comment, self.commented) = (mark.1, mark.2);
+                self.verbatim(level, start, end);
+            }
+        }
+    }
+
+    /// Whether the comments inside the code from `start` up to the last
+    /// token before `end` are still where they were, relative to the code
+    /// around them, in the lines printed from `first_line` on
+    fn comments_in_place(&self, start: usize, end: usize, first_line: usize) -> bool {
+        let end = self.token_end_before(end);
+        let from = self
+            .comments
+            .partition_point(|comment| comment.start <= start);
+        let inside = self
+            .comments
+            .get(from)
+            .is_some_and(|comment| comment.start < end);
+        !inside
+            || anchors(&self.source[start..end]) == anchors(&self.lines[first_line..].join("\n"))
+    }
+
+    /// Copy the code from `start` up to the last token before `end` as it
+    /// is written, with its lines moved to `level`
+    fn verbatim(&mut self, level: usize, start: usize, end: usize) {
+        let end = self.token_end_before(end);
+        let line_start = self.source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let column = start - line_start;
+        let indent = self.indent(level);
+
+        let mut lines = self.source[start..end].split('\n');
+        let mut text = lines.next().unwrap_or_default().to_string();
+        for line in lines {
+            let written = line.len() - line.trim_start().len();
+            text.push('\n');
+            if !line.trim().is_empty() {
+                text.push_str(&indent);
+                text.push_str(&line[written.min(column)..]);
+            }
+        }
+        self.line(level, &text);
+        while self.comment_before(end) {
+            self.next_comment += 1;
+        }
+    }
+
+    // Imports and definitions
+
+    fn import(&mut self, import: &Import) {
+        let text = match import {
+            Import::FromImport { path, names, .. } => {
+                let names: Vec<String> = names
+                    .iter()
+                    .map(|name| match &name.alias {
+                        Some(alias) => format!("{} as {}", name.name, alias),
+                        None => name.name.to_string(),
+                    })
+                    .collect();
+                format!("from {} import {};", path, names.join(", "))
+            }
+            Import::DirectImport { names, .. } => format!("import {};", names.join(", ")),
+        };
+        self.line(0, &text);
+    }
+
+    /// Print a top-level definition spanning `start..next`, or copy it from
+    /// the source if the printed text does not parse back to it or moved a
+    /// comment within it
+    fn top_level(&mut self, definition: &Definition, start: usize, next: usize) {
+        let mark = (self.lines.len(), self.next_comment, self.commented);
+        self.unsupported = false;
+        self.definition(0, definition);
+
+        let printed = self.lines[mark.0..].join("\n");
+        let faithful = !self.unsupported
+            && self.comments_in_place(start, next, mark.0)
+            && Parser::new(&printed)
+                .keep_contracts()
+                .parse_program()
+                .is_ok_and(|program| {
+                    program.imports.is_empty()
+                        && program.definitions.len() == 1
+                        && same(&program.definitions[0], definition)
+                });
+        if faithful {
+            return;
+        }
+
+        self.lines.truncate(mark.0);
+        (self.next_comment, self.commented) = (mark.1, mark.2);
+        self.verbatim(0, start, next);
+    }
+
+    fn definition(&mut self, level: usize, definition: &Definition) {
+        match definition {
+            Definition::FunctionDef { .. } => self.function(level, definition, false),
+            Definition::TypeDef {
+                name,
+                type_params,
+                variants,
+                attributes,
+                visibility,
+                location,
+            } => {
+                self.attributes(level, attributes);
+                let head = format!(
+                    "{}type {}{} ",
+                    public(visibility),
+                    name,
+                    type_params_text(type_params)
+                );
+                self.variants(level, &head, variants, location.start);
+            }
+            Definition::ErrorDef {
+                name,
+                variants,
+                visibility,
+                location,
+            } => {
+                let head = format!("{}error {} ", public(visibility), name);
+                self.variants(level, &head, variants, location.start);
+            }
+            Definition::ObjectDef {
+                name,
+                type_params,
+                fields,
+                functions,
+                attributes,
+                visibility,
+                location,
+            } => {
+                self.attributes(level, attributes);
+                let head = format!(
+                    "{}object {}{} ",
+                    public(visibility),
+                    name,
+                    type_params_text(type_params)
+                );
+
+                // Fields and functions may be mixed, keep their order
+                let mut members: Vec<(usize, Result<&Field, &Definition>)> = fields
+                    .iter()
+                    .map(|field| (field.location.start, Ok(field)))
+                    .chain(
+                        functions
+                            .iter()
+                            .map(|function| (self.leading_start(function), Err(function))),
+                    )
+                    .collect();
+                members.sort_by_key(|(start, _)| *start);
+
+                let close = self.closing(location.start);
+                if members.is_empty() && !self.comment_before(close) {
+                    self.line(level, &format!("{}{{}}", head));
+                    return;
+                }
+                self.line(level, &format!("{}{{", head));
+                let mut after_function = false;
+                for (start, member) in members {
+                    self.item(
+                        level + 1,
+                        start,
+                        after_function && self.config.blank_line_after_fn,
+                    );
+                    match member {
+                        Ok(field) => {
+                            let ty = field
+                                .type_annotation
+                                .as_ref()
+                                .map(|ty| format!(": {}", ty))
+                                .unwrap_or_default();
+                            let recursive = if field.is_recursive { " ~" } else { "" };
+                            self.line(
+                                level + 1,
+                                &format!("let {}{}{};", field.name, ty, recursive),
+                            );
+                        }
+                        Err(function) => self.function(level + 1, function, false),
+                    }
+                    after_function = member.is_err();
+                }
+                self.close(level, close);
+            }
+            Definition::EventDef {
+                name,
+                fields,
+                visibility,
+                location,
+            } => {
+                let head = format!("{}event {} ", public(visibility), name);
+                let fields: Vec<(usize, String)> = fields
+                    .iter()
+                    .map(|field| {
+                        let indexed = if field.indexed { "indexed " } else { "" };
+                        let text = format!("{}{}: {}", indexed, field.name, field.ty);
+                        (field.location.start, text)
+                    })
+                    .collect();
+                self.members(level, &head, &fields, location.start);
+            }
+            Definition::StorageDef {
+                fields,
+                attributes,
+                location,
+            } => {
+                self.attributes(level, attributes);
+                let fields: Vec<(usize, String)> = fields
+                    .iter()
+                    .map(|field| {
+                        let text = format!("{}: {}", field.name, field.ty);
+                        (field.location.start, text)
+                    })
+                    .collect();
+                self.members(level, "storage ", &fields, location.start);
+            }
+            Definition::GuardDef {
+                name,
+                params,
+                before,
+                after,
+                ..
+            } => {
+                let params = self.parameters(params, level);
+                self.line(level, &format!("guard {}{} {{", name, params));
+                self.statements(level + 1, &before.statements);
+                // `before` ends where the `_` placeholder starts
+                self.item(level + 1, before.location.end, false);
+                self.line(level + 1, "_;");
+                self.statements(level + 1, &after.statements);
+                let close = self.closing(before.location.start);
+                self.close(level, close);
+            }
+            Definition::InterfaceDef {
+                name,
+                functions,
+                visibility,
+                location,
+            } => {
+                let head = format!("{}interface {} ", public(visibility), name);
+                let close = self.closing(location.start);
+                if functions.is_empty() && !self.comment_before(close) {
+                    self.line(level, &format!("{}{{}}", head));
+                    return;
+                }
+                self.line(level, &format!("{}{{", head));
+                for function in functions {
+                    let start = self.leading_start(function);
+                    self.item(level + 1, start, false);
+                    self.function(level + 1, function, true);
+                }
+                self.close(level, close);
+            }
+            Definition::ContractDef {
+                name,
+                fields,
+                definitions,
+                attributes,
+                location,
+            } => {
+                self.attributes(level, attributes);
+                let head = format!("contract {} ", name);
+
+                // State fields and definitions may be mixed, keep their order
+                let mut members: Vec<(usize, Result<&StorageField, &Definition>)> = fields
+                    .iter()
+                    .map(|field| (field.location.start, Ok(field)))
+                    .chain(
+                        definitions
+                            .iter()
+                            .map(|definition| (self.leading_start(definition), Err(definition))),
+                    )
+                    .collect();
+                members.sort_by_key(|(start, _)| *start);
+
+                let close = self.closing(location.start);
+                if members.is_empty() && !self.comment_before(close) {
+                    self.line(level, &format!("{}{{}}", head));
+                    return;
+                }
+                self.line(level, &format!("{}{{", head));
+                let mut after_definition = false;
+                for (start, member) in members {
+                    self.item(
+                        level + 1,
+                        start,
+                        after_definition && self.config.blank_line_after_fn,
+                    );
+                    match member {
+                        Ok(field) => self.line(
+                            level + 1,
+                            &format!(
+                                "{}let {}: {};",
+                                public(&field.visibility),
+                                field.name,
+                                field.ty
+                            ),
+                        ),
+                        Err(definition) => self.definition(level + 1, definition),
+                    }
+                    after_definition = member.is_err();
+                }
+                self.close(level, close);
+            }
+            Definition::TypeAlias { .. } | Definition::Module { .. } => {
+                self.unsupported = true;
+            }
+        }
+    }
+
+    /// Print a function; `declaration` ends it with `;` in place of a body
+    fn function(&mut self, level: usize, definition: &Definition, declaration: bool) {
+        let Definition::FunctionDef {
+            name,
+            params,
+            return_type,
+            body,
+            checked,
+            attributes,
+            visibility,
+            ..
+        } = definition
+        else {
+            return;
+        };
+
+        self.attributes(level, attributes);
+        let modifier = match checked {
+            Some(true) => "checked ",
+            Some(false) => "unchecked ",
+            None => "",
+        };
+        let mut head = format!(
+            "{}{}fn {}{}",
+            public(visibility),
+            modifier,
+            name,
+            self.parameters(params, level)
+        );
+        if let Some(return_type) = return_type {
+            head.push_str(&format!(" -> {}", return_type));
+        }
+
+        if declaration {
+            head.push(';');
+            self.line(level, &head);
+        } else if self.source[body.location.start..].starts_with('{') {
+            head.push(' ');
+            self.block(level, &head, body, false);
+        } else {
+            self.line(level, &head);
+        }
+    }
+
+    fn parameters(&mut self, params: &[Parameter], level: usize) -> String {
+        let params: Vec<String> = params
+            .iter()
+            .map(|param| format!("{}: {}", param.name, param.ty))
+            .collect();
+        self.trailing_list("(", &params, ")", level)
+    }
+
+    fn attributes(&mut self, level: usize, attributes: &[Attribute]) {
+        for attribute in attributes {
+            let mut text = format!("#[{}", attribute.name);
+            if !attribute.args.is_empty() {
+                let args: Vec<String> = attribute
+                    .args
+                    .iter()
+                    .map(|arg| match attribute.name.as_str() {
+                        "cfg" => cfg_predicate(arg),
+                        _ => self.expr(arg, level + 1),
+                    })
+                    .collect();
+                text.push_str(&self.trailing_list("(", &args, ")", level));
+            }
+            text.push(']');
+            self.line(level, &text);
+        }
+    }
+
+    /// The braced variants of a type or error
+    fn variants(&mut self, level: usize, head: &str, variants: &[TypeVariant], start: usize) {
+        let variants: Vec<(usize, String)> = variants
+            .iter()
+            .map(|variant| {
+                let fields: Vec<String> = variant
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        let ty = field
+                            .type_annotation
+                            .as_ref()
+                            .map(ToString::to_string)
+                            .unwrap_or_default();
+                        // Fields without a name are written as a bare type
+                        match field.name.as_str() {
+                            "_" => ty,
+                            name => format!("{}: {}", name, ty),
+                        }
+                    })
+                    .collect();
+                let mut text = variant.name.to_string();
+                if !fields.is_empty() {
+                    text.push_str(&self.trailing_list("(", &fields, ")", level + 1));
+                }
+                (variant.location.start, text)
+            })
+            .collect();
+        self.members(level, head, &variants, start);
+    }
+
+    /// `head{`, one member per line followed by a comma, and the closing
+    /// brace of the first block after `start`
+    fn members(&mut self, level: usize, head: &str, members: &[(usize, String)], start: usize) {
+        let close = self.closing(start);
+        if members.is_empty() && !self.comment_before(close) {
+            self.line(level, &format!("{}{{}}", head));
+            return;
+        }
+        self.line(level, &format!("{}{{", head));
+        for (i, (start, text)) in members.iter().enumerate() {
+            self.item(level + 1, *start, false);
+            let last = i + 1 == members.len();
+            if last && self.config.trailing_comma == TrailingComma::Never {
+                self.line(level + 1, text);
+            } else {
+                self.line(level + 1, &format!("{},", text));
+            }
+        }
+        self.close(level, close);
+    }
+
+    // Statements
+
+    fn statement(&mut self, level: usize, statement: &Statement) {
+        match statement {
+            Statement::Assignment { pattern, value, .. } => {
+                let text = format!(
+                    "{} = {};",
+                    self.pattern(pattern, level),
+                    self.expr(value, level)
+                );
+                self.line(level, &text);
+            }
+            Statement::Use {
+                name,
+                value,
+                location,
+            } => {
+                let annotation = self.let_annotation(location.start);
+                let text = format!("let {}{} = {};", name, annotation, self.expr(value, level));
+                self.line(level, &text);
+            }
+            Statement::Return { value, .. } => {
+                // A bare `return` returns a literal placed at the token
+                // after it
+                let bare = matches!(
+                    value,
+                    Expr::Literal {
+                        kind: LiteralKind::Uint(0),
+                        ..
+                    }
+                ) && matches!(
+                    self.source.as_bytes().get(value.location().start),
+                    Some(b';' | b'}')
+                );
+                let text = if bare {
+                    "return;".to_string()
+                } else {
+                    format!("return {};", self.expr(value, level))
+                };
+                self.line(level, &text);
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let head = format!("if {} ", self.expr(condition, level));
+                self.block(level, &head, then_branch, false);
+                self.block(level, " else ", else_branch, true);
+            }
+            Statement::While {
+                condition,
+                body,
+                bound,
+                ..
+            } => {
+                let head = format!(
+                    "while {}{} ",
+                    self.expr(condition, level),
+                    bound_text(bound)
+                );
+                self.block(level, &head, body, false);
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                bound,
+                ..
+            } => {
+                // `range(end)` starts from a zero placed at `end`
+                let implicit = matches!(
+                    start,
+                    Expr::Literal {
+                        kind: LiteralKind::Uint(0),
+                        ..
+                    }
+                ) && start.location() == end.location();
+                let mut range = Vec::new();
+                if !implicit {
+                    range.push(self.expr(start, level + 1));
+                }
+                range.push(self.expr(end, level + 1));
+                let head = format!(
+                    "for {} in range{}{} ",
+                    variable,
+                    self.list("(", &range, ")", level),
+                    bound_text(bound)
+                );
+                self.block(level, &head, body, false);
+            }
+            Statement::Match {
+                value,
+                cases,
+                location,
+            } => {
+                let head = format!("match {} ", self.expr(value, level));
+                let close = location.end.saturating_sub(1);
+                if cases.is_empty() && !self.comment_before(close) {
+                    self.line(level, &format!("{}{{}}", head));
+                    return;
+                }
+                self.line(level, &format!("{}{{", head));
+                for case in cases {
+                    self.item(level + 1, case.pattern.location().start, false);
+                    self.case(level + 1, case);
+                }
+                self.close(level, close);
+            }
+            Statement::Bend {
+                initial_states,
+                body,
+                location,
+                ..
+            } => {
+                self.line(level, "bend {");
+                for (name, value) in initial_states {
+                    // `name <- value` or `let name = value`
+                    let i = self.token_index(value.location().start);
+                    let arrow = i
+                        .checked_sub(1)
+                        .is_some_and(|i| self.tokens[i].token == Token::LeftArrow);
+                    let start = match i.checked_sub(3).map(|i| &self.tokens[i]) {
+                        Some(token) if !arrow && token.token == Token::Let => token.start,
+                        _ => i
+                            .checked_sub(2)
+                            .map_or(value.location().start, |i| self.tokens[i].start),
+                    };
+                    self.item(level + 1, start, false);
+                    let value = self.expr(value, level + 1);
+                    let text = if arrow {
+                        format!("{} <- {};", name, value)
+                    } else {
+                        format!("let {} = {};", name, value)
+                    };
+                    self.line(level + 1, &text);
+                }
+                self.statements(level + 1, &body.statements);
+                self.close(level, location.end.saturating_sub(1));
+            }
+            Statement::Unchecked { body, .. } => self.block(level, "unchecked: ", body, false),
+            Statement::Expr { expr, .. } => {
+                // A statement starting with `if` is an if statement
+                let text = match expr {
+                    Expr::If { .. } => format!("({});", self.expr(expr, level)),
+                    _ => format!("{};", self.expr(expr, level)),
+                };
+                self.line(level, &text);
+            }
+            Statement::TryCatch {
+                try_block,
+                catch_blocks,
+                ..
+            } => {
+                self.block(level, "try ", try_block, false);
+                for catch in catch_blocks {
+                    self.block(level, " catch ", &catch.body, true);
+                }
+            }
+            Statement::Emit { event, args, .. } => {
+                let args: Vec<String> = args.iter().map(|arg| self.expr(arg, level + 1)).collect();
+                let text = format!(
+                    "emit {}{};",
+                    event,
+                    self.trailing_list("(", &args, ")", level)
+                );
+                self.line(level, &text);
+            }
+            Statement::Revert {
+                kind,
+                condition,
+                reason,
+                ..
+            } => {
+                let name = match kind {
+                    RevertKind::Assert => "assert",
+                    RevertKind::Require => "require",
+                    RevertKind::Revert => "revert",
+                };
+                let args: Vec<String> = condition
+                    .iter()
+                    .chain(reason)
+                    .map(|arg| self.expr(arg, level + 1))
+                    .collect();
+                let text = format!("{}{};", name, self.list("(", &args, ")", level));
+                self.line(level, &text);
+            }
+            Statement::InPlaceOp { .. }
+            | Statement::Switch { .. }
+            | Statement::Fold { .. }
+            | Statement::Open { .. }
+            | Statement::With { .. }
+            | Statement::LocalDef { .. } => self.unsupported = true,
+        }
+    }
+
+    /// The type annotation of the `let` at `offset`, which the AST drops,
+    /// written back from its tokens
+    fn let_annotation(&self, offset: usize) -> String {
+        let i = self.token_index(offset) + 2;
+        if self.tokens.get(i).map(|token| &token.token) != Some(&Token::Colon) {
+            return String::new();
+        }
+        let mut text = String::from(": ");
+        for token in self.tokens[i + 1..]
+            .iter()
+            .take_while(|token| token.token != Token::Equal)
+        {
+            match token.token {
+                Token::Comma => text.push_str(", "),
+                Token::Arrow => text.push_str(" -> "),
+                _ => text.push_str(self.text(token)),
+            }
+        }
+        text
+    }
+
+    fn case(&mut self, level: usize, case: &MatchCase) {
+        let pattern = self.pattern(&case.pattern, level);
+        // An arm written without braces holds a single expression that
+        // spans the whole body
+        match case.body.statements.as_slice() {
+            [Statement::Expr { expr, location }] if *location == case.body.location => {
+                let text = format!("{} => {},", pattern, self.expr(expr, level));
+                self.line(level, &text);
+            }
+            _ => self.block(level, &format!("{} => ", pattern), &case.body, false),
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern, level: usize) -> String {
+        match pattern {
+            Pattern::Variable { name, .. } => name.to_string(),
+            Pattern::Wildcard { .. } => "_".to_string(),
+            Pattern::Tuple { elements, .. } => {
+                let elements: Vec<String> = elements
+                    .iter()
+                    .map(|element| self.pattern(element, level + 1))
+                    .collect();
+                self.list("(", &elements, ")", level)
+            }
+            Pattern::TupleConstructor { name, args, .. } => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|arg| self.pattern(arg, level + 1))
+                    .collect();
+                format!("{}{}", name, self.trailing_list("(", &args, ")", level))
+            }
+            Pattern::Constructor { name, fields, .. } => {
+                let mut fields: Vec<(&String, &Pattern)> = fields.iter().collect();
+                fields.sort_by_key(|(_, pattern)| pattern.location().start);
+                let fields: Vec<String> = fields
+                    .into_iter()
+                    .map(|(field, pattern)| {
+                        format!("{}: {}", field, self.pattern(pattern, level + 1))
+                    })
+                    .collect();
+                if fields.is_empty() {
+                    format!("{} {{}}", name)
+                } else {
+                    format!("{} {{ {} }}", name, fields.join(", "))
+                }
+            }
+            Pattern::Literal { value, .. } => self.expr(value, level),
+            Pattern::Member { parent, member, .. } => {
+                // `a.b` would read as a single name
+                let parent = match &**parent {
+                    Pattern::Variable { name, .. } => format!("({})", name),
+                    parent => self.pattern(parent, level),
+                };
+                format!("{}.{}", parent, member)
+            }
+            Pattern::MapAccess { map, key, .. } => {
+                format!("{}[{}]", self.operand(map, level), self.expr(key, level))
+            }
+        }
+    }
+
+    // Expressions
+
+    fn expr(&mut self, expr: &Expr, level: usize) -> String {
+        match expr {
+            Expr::Variable { name, .. } => name.to_string(),
+            Expr::Literal { kind, location } => self.literal(kind, location),
+            Expr::Tuple { elements, .. } => {
+                let elements = self.exprs(elements, level);
+                self.list("(", &elements, ")", level)
+            }
+            Expr::Array { elements, .. } => {
+                let elements = self.exprs(elements, level);
+                self.list("[", &elements, "]", level)
+            }
+            Expr::FunctionCall {
+                function,
+                args,
+                named_args,
+                ..
+            } => {
+                let function = self.operand(function, level);
+                // Named arguments keep their place among the others
+                let mut arguments: Vec<(usize, String)> = args
+                    .iter()
+                    .map(|arg| (arg.location().start, self.expr(arg, level + 1)))
+                    .collect();
+                for (name, value) in named_args {
+                    let text = format!("{}: {}", name, self.expr(value, level + 1));
+                    arguments.push((value.location().start, text));
+                }
+                arguments.sort_by_key(|(start, _)| *start);
+                let arguments: Vec<String> = arguments.into_iter().map(|(_, text)| text).collect();
+                format!("{}{}", function, self.list("(", &arguments, ")", level))
+            }
+            Expr::FieldAccess { object, field, .. } => {
+                // `a.b` would read as a single name
+                let object = match &**object {
+                    Expr::Variable { name, .. } => format!("({})", name),
+                    object => self.operand(object, level),
+                };
+                format!("{}.{}", object, field)
+            }
+            Expr::MapAccess { map, key, .. } => {
+                format!("{}[{}]", self.operand(map, level), self.expr(key, level))
+            }
+            Expr::Try { expr, .. } => format!("{}?", self.operand(expr, level)),
+            Expr::Lambda { params, body, .. } => {
+                let params: Vec<String> = params
+                    .iter()
+                    .map(|param| match param.ty {
+                        Type::Unknown { .. } => param.name.to_string(),
+                        ref ty => format!("{}: {}", param.name, ty),
+                    })
+                    .collect();
+                format!("|{}| {}", params.join(", "), self.expr(body, level))
+            }
+            Expr::BinaryOp {
+                left,
+                operator,
+                right,
+                ..
+            } => self.binary(left, operator, right, level),
+            Expr::UnaryOp { operand, .. } => {
+                let operand = match &**operand {
+                    Expr::BinaryOp { .. } => format!("({})", self.expr(operand, level)),
+                    operand => self.operand(operand, level),
+                };
+                format!("!{}", operand)
+            }
+            Expr::Map { entries, .. } => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| {
+                        format!(
+                            "{}: {}",
+                            self.expr(key, level + 1),
+                            self.expr(value, level + 1)
+                        )
+                    })
+                    .collect();
+                self.trailing_list("{", &entries, "}", level)
+            }
+            Expr::Superposition { elements, .. } => {
+                let elements = self.exprs(elements, level);
+                // Without its comma a single alternative reads as a block
+                if let [element] = elements.as_slice() {
+                    return format!("{{{},}}", element);
+                }
+                self.trailing_list("{", &elements, "}", level)
+            }
+            Expr::Block { block, .. } => {
+                let close = self.closing(block.location.start);
+                let lines = std::mem::take(&mut self.lines);
+                let commented = self.commented.take();
+                self.statements(level + 1, &block.statements);
+                self.close(level, close);
+                let inner = std::mem::replace(&mut self.lines, lines);
+                self.commented = commented;
+                format!("{{\n{}", inner.join("\n"))
+            }
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => format!(
+                "if {} {} else {}",
+                self.operand(condition, level),
+                self.expr(then_branch, level),
+                self.expr(else_branch, level)
+            ),
+            Expr::ListComprehension {
+                element,
+                variable,
+                iterable,
+                condition,
+                ..
+            } => {
+                let element = self.expr(element, level);
+                let clauses = self.clauses(variable, iterable, condition, level);
+                format!("[{}{}]", element, clauses)
+            }
+            Expr::MapComprehension {
+                key,
+                value,
+                variable,
+                iterable,
+                condition,
+                ..
+            } => {
+                let entry = format!("{}: {}", self.expr(key, level), self.expr(value, level));
+                let clauses = self.clauses(variable, iterable, condition, level);
+                format!("{{{}{}}}", entry, clauses)
+            }
+            Expr::InlineAsm {
+                inputs,
+                output,
+                instructions,
+                ..
+            } => {
+                let mut bindings: Vec<String> = inputs
+                    .iter()
+                    .map(|(register, value)| {
+                        format!("in {} = {}", register, self.expr(value, level + 1))
+                    })
+                    .collect();
+                bindings.extend(output.iter().map(|register| format!("out {}", register)));
+                let head = if bindings.is_empty() {
+                    "asm".to_string()
+                } else {
+                    format!("asm({})", bindings.join(", "))
+                };
+                if instructions.is_empty() {
+                    return format!("{} {{}}", head);
+                }
+                let body: Vec<String> = instructions
+                    .iter()
+                    .map(|instruction| format!("{}{:?}", self.indent(level + 1), instruction))
+                    .collect();
+                format!("{} {{\n{}\n{}}}", head, body.join("\n"), self.indent(level))
+            }
+            Expr::List { .. }
+            | Expr::Constructor { .. }
+            | Expr::UnsccopedLambda { .. }
+            | Expr::TreeLeaf { .. }
+            | Expr::TreeNode { .. }
+            | Expr::Eraser { .. } => {
+                self.unsupported = true;
+                String::new()
+            }
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr], level: usize) -> Vec<String> {
+        exprs
+            .iter()
+            .map(|expr| self.expr(expr, level + 1))
+            .collect()
+    }
+
+    /// An expression something is applied to, in parentheses unless it
+    /// binds tighter than the application
+    fn operand(&mut self, expr: &Expr, level: usize) -> String {
+        match expr {
+            Expr::BinaryOp { .. }
+            | Expr::UnaryOp { .. }
+            | Expr::If { .. }
+            | Expr::Lambda { .. } => {
+                format!("({})", self.expr(expr, level))
+            }
+            _ => self.expr(expr, level),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        left: &Expr,
+        operator: &BinaryOperator,
+        right: &Expr,
+        level: usize,
+    ) -> String {
+        let Some((symbol, precedence)) = operator_info(operator) else {
+            self.unsupported = true;
+            return String::new();
+        };
+
+        // Operators of the same precedence group to the left
+        let mut side = |expr: &Expr, tighter: fn(u8, u8) -> bool| match expr {
+            Expr::BinaryOp { operator, .. }
+                if operator_info(operator)
+                    .is_some_and(|(_, inner)| !tighter(inner, precedence)) =>
+            {
+                format!("({})", self.expr(expr, level))
+            }
+            Expr::If { .. } | Expr::Lambda { .. } => format!("({})", self.expr(expr, level)),
+            _ => self.expr(expr, level),
+        };
+        let left = side(left, |inner, outer| inner >= outer);
+        let right = side(right, |inner, outer| inner > outer);
+
+        // Without spaces `a+1` reads as `a` and `+1`, and `a/b` as a name
+        let spaced = self.config.space_around_operators
+            || *operator == BinaryOperator::Div
+            || right.starts_with(|c: char| c.is_ascii_digit() || c == '+' || c == '-');
+        if spaced {
+            format!("{} {} {}", left, symbol, right)
+        } else {
+            format!("{}{}{}", left, symbol, right)
+        }
+    }
+
+    fn clauses(
+        &mut self,
+        variable: &str,
+        iterable: &Expr,
+        condition: &Option<Box<Expr>>,
+        level: usize,
+    ) -> String {
+        let mut text = format!(" for {} in {}", variable, self.expr(iterable, level));
+        if let Some(condition) = condition {
+            text.push_str(&format!(" if {}", self.expr(condition, level)));
+        }
+        text
+    }
+
+    /// A literal as it was written, or from its value if it was not
+    fn literal(&mut self, kind: &LiteralKind, location: &Location) -> String {
+        let i = self.token_index(location.start);
+        if let Some(token) = self.tokens.get(i).filter(|token| {
+            token.start == location.start
+                && matches!(
+                    token.token,
+                    Token::UintLiteral(_)
+                        | Token::WideUintLiteral(_)
+                        | Token::AddressLiteral(_)
+                        | Token::HashLiteral(_)
+                        | Token::BytesLiteral(_)
+                        | Token::IntLiteral(_)
+                        | Token::FloatLiteral(_)
+                        | Token::StringLiteral(_)
+                        | Token::CharLiteral(_)
+                        | Token::SymbolLiteral(_)
+                        | Token::True
+                        | Token::False
+                )
+        }) {
+            return self.text(token).to_string();
+        }
+
+        match kind {
+            LiteralKind::Uint(value) => value.to_string(),
+            LiteralKind::Int(value) => format!("{:+}", value),
+            LiteralKind::Float(value) => format!("{:?}", value),
+            LiteralKind::Bool(value) => value.to_string(),
+            LiteralKind::String(value) => format!("{:?}", value),
+            LiteralKind::Char(value) => format!("'{}'", value),
+            LiteralKind::Symbol(value) => format!("`{}`", value),
+            LiteralKind::WideUint(_)
+            | LiteralKind::Address(_)
+            | LiteralKind::Hash(_)
+            | LiteralKind::Bytes(_) => {
+                self.unsupported = true;
+                String::new()
+            }
+        }
+    }
+}
+
+/// Every comment in `text` with the number of tokens before it and whether
+/// code comes before it on its line. Punctuation the printer adds or drops
+/// is not counted.
+fn anchors(text: &str) -> Vec<(usize, bool, String)> {
+    let mut lexer = BendLexer::with_comments(text);
+    let mut anchors = Vec::new();
+    let mut count = 0;
+    loop {
+        let token = lexer.next_token();
+        for comment in token.leading_comments {
+            anchors.push((count, false, comment.text));
+        }
+        if token.token == Token::EOF {
+            return anchors;
+        }
+        if !matches!(
+            token.token,
+            Token::Comma
+                | Token::Semicolon
+                | Token::Colon
+                | Token::LParen
+                | Token::RParen
+                | Token::LBracket
+                | Token::RBracket
+                | Token::HashBracket
+        ) {
+            count += 1;
+        }
+        for comment in token.trailing_comments {
+            anchors.push((count, true, comment.text));
+        }
+    }
+}
+
+/// Rewrap the paragraphs of a run of `#` comments that have a line longer
+/// than `width`. Empty comment lines end paragraphs, and lines that look
+/// like lists, code or indented text are kept as written.
+fn reflow(lines: &[String], width: usize) -> Vec<String> {
+    fn flush(paragraph: &mut Vec<&String>, width: usize, out: &mut Vec<String>) {
+        if paragraph.iter().all(|line| line.chars().count() <= width) {
+            out.extend(paragraph.drain(..).cloned());
+            return;
+        }
+        let mut line = String::from("#");
+        for word in paragraph
+            .drain(..)
+            .flat_map(|line| line[1..].split_whitespace())
+        {
+            if line.len() > 1 && line.chars().count() + 1 + word.chars().count() > width {
+                out.push(std::mem::replace(&mut line, String::from("#")));
+            }
+            line.push(' ');
+            line.push_str(word);
+        }
+        out.push(line);
+    }
+
+    let mut out = Vec::new();
+    let mut paragraph = Vec::new();
+    for line in lines {
+        let body = &line[1..];
+        let prose = body.starts_with(' ')
+            && body[1..]
+                .chars()
+                .next()
+                .is_some_and(|c| !c.is_whitespace() && !"-*#`|>".contains(c))
+            && !body[1..].starts_with(|c: char| c.is_ascii_digit());
+        if prose {
+            paragraph.push(line);
+        } else {
+            flush(&mut paragraph, width, &mut out);
+            out.push(line.clone());
+        }
+    }
+    flush(&mut paragraph, width, &mut out);
+    out
+}
+
+fn public(visibility: &Visibility) -> &'static str {
+    match visibility {
+        Visibility::Public => "pub ",
+        Visibility::Private => "",
+    }
+}
+
+/// A predicate of `#[cfg(...)]`, as the parser reads it back
+fn cfg_predicate(predicate: &Expr) -> String {
+    match predicate {
+        Expr::BinaryOp { left, right, .. } => {
+            format!("{} = {}", cfg_predicate(left), cfg_predicate(right))
+        }
+        Expr::FunctionCall { function, args, .. } => {
+            let args: Vec<String> = args.iter().map(cfg_predicate).collect();
+            format!("{}({})", cfg_predicate(function), args.join(", "))
+        }
+        Expr::Literal {
+            kind: LiteralKind::String(value),
+            ..
+        } => format!("{:?}", value),
+        Expr::Variable { name, .. } => name.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn type_params_text(type_params: &[String]) -> String {
+    if type_params.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", type_params.join(", "))
+    }
+}
+
+fn bound_text(bound: &Option<u32>) -> String {
+    bound
+        .map(|bound| format!(" bound {}", bound))
+        .unwrap_or_default()
+}
+
+/// The symbol and precedence of the operators the parser reads
+fn operator_info(operator: &BinaryOperator) -> Option<(&'static str, u8)> {
+    Some(match operator {
+        BinaryOperator::Or => ("||", 1),
+        BinaryOperator::And => ("&&", 2),
+        BinaryOperator::Equal => ("==", 3),
+        BinaryOperator::NotEqual => ("!=", 3),
+        BinaryOperator::Less => ("<", 4),
+        BinaryOperator::LessEqual => ("<=", 4),
+        BinaryOperator::Greater => (">", 4),
+        BinaryOperator::GreaterEqual => (">=", 4),
+        BinaryOperator::Add => ("+", 5),
+        BinaryOperator::Sub => ("-", 5),
+        BinaryOperator::Mul => ("*", 6),
+        BinaryOperator::Div => ("/", 6),
+        BinaryOperator::Mod => ("%", 6),
+        BinaryOperator::Pow
+        | BinaryOperator::BitAnd
+        | BinaryOperator::BitOr
+        | BinaryOperator::BitXor
+        | BinaryOperator::BitShiftLeft
+        | BinaryOperator::BitShiftRight => return None,
+    })
+}
@@ -17,11 +17,16 @@ pub mod compiler {
         mod tests;
     }
     pub mod analyzer {
+        pub mod attributes;
+        pub mod linearity;
+        pub mod lints;
         pub mod type_checker;
         pub mod type_inference;
     }
     pub mod optimizer {
         pub mod constant_folding;
+        pub mod constexpr;
+        pub mod devirtualize;
         pub mod eta_reduction;
         pub mod float_comb;
         pub mod inline;
@@ -32,29 +37,60 @@ pub mod compiler {
         mod tests;
     }
     pub mod codegen {
+        pub mod encoder;
+        pub mod evm;
         pub mod ir;
         pub mod metadata;
         pub mod risc_v;
+        pub mod rv64;
         #[cfg(test)]
         mod tests;
+        pub mod wasm;
+        pub mod yul;
     }
+    pub mod access;
+    pub mod address;
+    pub mod cfg;
+    pub mod contract;
+    pub mod intern;
+    pub mod linker;
+    pub mod lowering;
     pub mod module;
+    pub mod object;
+    pub mod pipeline;
+    pub mod query;
+    pub mod symbolic {
+        pub mod executor;
+        pub mod solver;
+    }
+    pub mod timing;
+    pub mod upgrade;
+    pub mod wide;
     pub mod polkavm {
         pub mod abi;
+        pub mod bindgen;
+        pub mod blob;
         pub mod bridge;
         pub mod host;
+        pub mod indexer;
     }
 }
 
 pub mod runtime {
     pub mod env;
+    pub mod evm;
+    pub mod interpreter;
     pub mod memory;
     pub mod metering;
+    #[cfg(feature = "polkavm-engine")]
+    pub mod polkavm;
     pub mod proxy;
     pub mod storage;
+    pub mod wasm;
 }
 
 pub mod debugger;
+pub mod diagnostics;
 pub mod formatter;
 pub mod migration;
 pub mod security;
@@ -76,15 +112,18 @@ pub mod logging;
 // Deployment tools
 pub mod deployment;
 
-use std::path::PathBuf;
+use compiler::analyzer::lints::LintLevels;
+use compiler::cfg::Cfg;
+use compiler::codegen::encoder::Isa;
+use compiler::codegen::metadata::ContractMetadata;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
-use compiler::analyzer::type_checker::TypeChecker;
-use compiler::codegen::risc_v::RiscVCodegen;
-use compiler::lexer::lexer::BendLexer;
-use compiler::optimizer::passes::create_default_manager;
-use compiler::parser::parser::Parser;
-use compiler::polkavm::bridge::compile_to_polkavm;
+use compiler::module::cache::ModuleCache;
+use compiler::pipeline::{CompilerPipeline, Source, Target};
+use diagnostics::{Diagnostic, MessageFormat, Severity};
+
+pub use compiler::pipeline::CompilationResult;
 
 /// Compiler error type
 #[derive(Error, Debug)]
@@ -92,11 +131,10 @@ pub enum CompileError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
-    #[error("Parse error: {0}")]
-    Parse(String),
-
-    #[error("Type error: {0}")]
-    Type(String),
+    /// A parse or type error in the source, with where it is and how to
+    /// fix it
+    #[error("{0}")]
+    Diagnostic(Box<Diagnostic>),
 
     #[error("Optimization error: {0}")]
     Optimization(String),
@@ -107,8 +145,27 @@ pub enum CompileError {
     #[error("PolkaVM error: {0}")]
     PolkaVM(String),
 
+    #[error("Module error: {0}")]
+    Module(String),
+
+    #[error("Link error: {0}")]
+    Link(String),
+
     #[error("Security error: {0}")]
     Security(String),
+
+    /// Lints denied with `--deny` or `--deny-warnings` found problems:
+    /// the warnings of the compilation, the denied ones as errors
+    #[error("{} denied warning(s)", denied_count(.0))]
+    DeniedWarnings(Vec<Diagnostic>),
+}
+
+/// How many of `diagnostics` are errors
+fn denied_count(diagnostics: &[Diagnostic]) -> usize {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count()
 }
 
 /// Options for the compiler
@@ -145,6 +202,41 @@ pub struct CompilerOptions {
 
     /// Security level (0=None, 1=Basic, 2=Enhanced, 3=Maximum)
     pub security_level: u8,
+
+    /// Whether to reuse and update the module cache
+    pub incremental: bool,
+
+    /// How to write out warnings
+    pub message_format: MessageFormat,
+
+    /// Levels of the lints, before the attributes of each function
+    pub lint_levels: LintLevels,
+
+    /// Whether to also time lexing, on its own (`-Z time-passes`)
+    pub time_passes: bool,
+
+    /// Features and whether tests run, for `#[cfg(...)]`
+    pub cfg: Cfg,
+
+    /// What code is generated for
+    pub target: Target,
+
+    /// Instruction set of the RISC-V target; RV64 code is emitted as raw
+    /// machine code for 64-bit PolkaVM
+    pub isa: Isa,
+
+    /// Metadata of the deployed version of the contract, whose storage the
+    /// compiled version must be able to take over
+    pub upgrade_from: Option<ContractMetadata>,
+
+    /// Whether every build of the same source must give the same bytes:
+    /// the module cache is not used and source paths left absolute after
+    /// [`CompilerOptions::remap_path_prefix`] are cut down to the file name
+    pub deterministic: bool,
+
+    /// Prefixes of source paths to replace in debug information, first
+    /// match first, as with `--remap-path-prefix FROM=TO`
+    pub remap_path_prefix: Vec<(PathBuf, PathBuf)>,
 }
 
 impl Default for CompilerOptions {
@@ -161,164 +253,247 @@ impl Default for CompilerOptions {
             static_analysis: false,
             fuzz_testing: false,
             security_level: 1,
+            incremental: false,
+            message_format: MessageFormat::Human,
+            lint_levels: LintLevels::default(),
+            time_passes: false,
+            cfg: Cfg::default(),
+            target: Target::RiscV,
+            isa: Isa::default(),
+            upgrade_from: None,
+            deterministic: false,
+            remap_path_prefix: Vec::new(),
         }
     }
 }
 
-/// Compile a Bend source file
-pub fn compile(source_path: &PathBuf, options: CompilerOptions) -> Result<(), CompileError> {
-    // Read source file
-    let source = std::fs::read_to_string(source_path)?;
-
-    // Parse
-    let _lexer = BendLexer::new(&source);
-    let mut parser = Parser::new(&source);
-    let program = parser
-        .parse_program()
-        .map_err(|e| CompileError::Parse(e.to_string()))?;
-
-    // Type Check
-    if options.type_check {
-        let mut type_checker = TypeChecker::new();
-        type_checker
-            .check_program(&program)
-            .map_err(|e| CompileError::Type(e.to_string()))?;
+impl CompilerOptions {
+    /// `path` as it is recorded in debug information
+    pub fn remap_path(&self, path: &Path) -> PathBuf {
+        let remapped = self
+            .remap_path_prefix
+            .iter()
+            .find_map(|(from, to)| Some(to.join(path.strip_prefix(from).ok()?)));
+        match remapped {
+            Some(path) => path,
+            None if self.deterministic && path.is_absolute() => {
+                path.file_name().map_or_else(PathBuf::new, PathBuf::from)
+            }
+            None => path.to_path_buf(),
+        }
     }
+}
 
-    // Optimize
-    let optimized_program = if options.optimize {
-        let mut manager = create_default_manager();
-        manager
-            .optimize(program)
-            .map_err(|e| CompileError::Optimization(e.to_string()))?
-    } else {
-        program
-    };
+/// The files [`compile`] wrote, along with what the compilation produced
+#[derive(Debug, Clone)]
+pub struct CompileOutput {
+    pub binary: PathBuf,
+    pub assembly: Option<PathBuf>,
+    pub abi: Option<PathBuf>,
+    pub metadata: Option<PathBuf>,
+    pub result: CompilationResult,
+}
 
-    // Generate Code
-    let mut generator = RiscVCodegen::new();
-    let code = generator
-        .generate(&optimized_program)
-        .map_err(|e| CompileError::Codegen(e.to_string()))?;
-
-    // Output Assembly
-    if options.assembly {
-        let asm_path = if let Some(output) = &options.output {
-            let mut p = output.clone();
-            p.set_extension("s");
-            p
-        } else {
-            let mut p = source_path.clone();
-            p.set_extension("s");
-            p
-        };
-
-        // Convert bytecode to assembly string (mock implementation)
-        let asm_content = format!("; Assembly for {}\n{:?}", source_path.display(), code);
-        std::fs::write(asm_path, asm_content)?;
-    }
+/// Compile the contract `name` from `source` without touching the file
+/// system, with a message dispatcher routing calls to its messages by
+/// selector. Warnings come back in the result; when a lint is denied, the
+/// error carries them instead.
+pub fn compile_to_artifacts(
+    name: &str,
+    source: &str,
+    options: &CompilerOptions,
+) -> Result<CompilationResult, CompileError> {
+    CompilerPipeline::new(options)
+        .with_dispatcher()
+        .run(&Source::new(name, source))
+}
 
-    // Compile to PolkaVM
-    let polkavm_module =
-        compile_to_polkavm(&code, None).map_err(|e| CompileError::PolkaVM(e.to_string()))?;
+/// Compile the contract in a Bend source file, with a message dispatcher
+/// like [`compile_to_artifacts`], writing the binary next to it or to
+/// [`CompilerOptions::output`], with the listing, ABI and metadata beside
+/// the binary as `.s` (`.wat` for WebAssembly), `.abi.json` and
+/// `.metadata.json`. Warnings are written out in
+/// [`CompilerOptions::message_format`].
+pub fn compile(
+    source_path: &Path,
+    options: CompilerOptions,
+) -> Result<CompileOutput, CompileError> {
+    let source = Source::from_path(source_path)?;
+    let mut pipeline = CompilerPipeline::new(&options).with_dispatcher();
+    // Reuse what earlier builds produced for this exact source
+    if options.incremental && !options.deterministic {
+        pipeline = pipeline.with_cache(ModuleCache::for_path(source_path));
+    }
+    let report = |diagnostics: &[Diagnostic]| {
+        let file = source_path.display().to_string();
+        for diagnostic in diagnostics {
+            diagnostics::emit(diagnostic, &file, &source.text, options.message_format);
+        }
+    };
+    let result = match pipeline.run(&source) {
+        Ok(result) => result,
+        Err(CompileError::DeniedWarnings(diagnostics)) => {
+            report(&diagnostics);
+            return Err(CompileError::DeniedWarnings(diagnostics));
+        }
+        Err(error) => return Err(error),
+    };
+    report(&result.diagnostics);
 
     // Output Binary
     let bin_path = if let Some(output) = &options.output {
         output.clone()
     } else {
-        let mut p = source_path.clone();
-        p.set_extension("bin");
-        p
+        source_path.with_extension(options.target.extension())
     };
+    std::fs::write(&bin_path, &result.blob)?;
+
+    // Output Assembly, ABI and Metadata
+    let beside = |extension: &str| bin_path.with_extension(extension);
+    let assembly = match &result.assembly {
+        Some(listing) => {
+            let path = beside(options.target.listing_extension());
+            std::fs::write(&path, listing)?;
+            Some(path)
+        }
+        None => None,
+    };
+    let abi = match &result.abi {
+        Some(abi) => {
+            let path = beside("abi.json");
+            std::fs::write(&path, to_json(abi)?)?;
+            Some(path)
+        }
+        None => None,
+    };
+    let metadata = match &result.metadata {
+        Some(metadata) => {
+            let path = beside("metadata.json");
+            std::fs::write(&path, to_json(metadata)?)?;
+            Some(path)
+        }
+        None => None,
+    };
+
+    Ok(CompileOutput {
+        binary: bin_path,
+        assembly,
+        abi,
+        metadata,
+        result,
+    })
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, CompileError> {
+    serde_json::to_string_pretty(value)
+        .map_err(|e| CompileError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))
+}
+
+/// Compile the module in a Bend source file to an object, to be linked
+/// with [`link`] and the objects of the modules it imports. Imports are
+/// looked up beside the file. Objects carry no message dispatcher, which
+/// only the entry module of a package build gets.
+pub fn compile_object(
+    source_path: &Path,
+    options: &CompilerOptions,
+) -> Result<compiler::object::ObjectFile, CompileError> {
+    let mut modules = compiler::module::ModuleSystem::new();
+    modules.set_cfg(options.cfg.clone());
+    if let Some(dir) = source_path.parent() {
+        modules.add_search_path(dir);
+    }
+    let module = modules
+        .load_module(source_path)
+        .map_err(|e| CompileError::Module(e.to_string()))?;
+    let level = match options.optimize {
+        true => compiler::optimizer::passes::OptimizationLevel::Standard,
+        false => compiler::optimizer::passes::OptimizationLevel::None,
+    };
+    compiler::object::ObjectFile::compile(&module, level, options.isa, options.debug, false)
+        .map_err(|e| match e {
+            compiler::object::ObjectError::Codegen { error, .. } => {
+                let source = std::fs::read_to_string(source_path).unwrap_or_default();
+                CompileError::Diagnostic(Box::new(Diagnostic::from_codegen_error(&error, &source)))
+            }
+            e => CompileError::Codegen(e.to_string()),
+        })
+}
 
-    let binary = polkavm_module
-        .binary
-        .ok_or_else(|| CompileError::Codegen("No binary generated".to_string()))?;
-    std::fs::write(bin_path, binary)?;
+/// Link the object files at `paths` into a binary, the first being the
+/// entry. Sections nothing refers to are left out unless `keep_unused`.
+pub fn link(paths: &[PathBuf], keep_unused: bool) -> Result<Vec<u8>, CompileError> {
+    let mut linker = compiler::linker::Linker::new();
+    if keep_unused {
+        linker = linker.keep_unused();
+    }
+    for path in paths {
+        let object = compiler::object::ObjectFile::read(path)
+            .map_err(|e| CompileError::Link(e.to_string()))?;
+        linker = linker.with_object(object);
+    }
+    linker
+        .link_binary()
+        .map_err(|e| CompileError::Link(e.to_string()))
+}
 
-    Ok(())
+/// Explore the functions of a Bend source file symbolically, looking for
+/// inputs reaching an assert, an overflow or another trap
+pub fn verify_symbolic(
+    source_path: &Path,
+    options: &CompilerOptions,
+    symbolic: &compiler::symbolic::executor::SymbolicOptions,
+) -> Result<Vec<compiler::symbolic::executor::FunctionReport>, CompileError> {
+    let source = Source::from_path(source_path)?;
+    // Unoptimized, so every check of the source is still there
+    let mut pipeline = CompilerPipeline::new(options)
+        .with_optimization_level(compiler::optimizer::passes::OptimizationLevel::None);
+    let ast = pipeline.parse(&source)?;
+    let typed = pipeline.check(&source, ast)?;
+    let ir = pipeline.lower(&typed)?;
+    Ok(
+        compiler::symbolic::executor::SymbolicExecutor::new(&ir.program)
+            .with_options(symbolic.clone())
+            .verify_program(),
+    )
 }
 
 /// Helper function to parse a Bend source string (for testing/tools)
 pub fn parse_source(source: &str) -> Result<compiler::parser::ast::Program, CompileError> {
-    let _lexer = BendLexer::new(source);
-    let mut parser = Parser::new(source);
-    parser
-        .parse_program()
-        .map_err(|e| CompileError::Parse(e.to_string()))
+    let options = CompilerOptions::default();
+    let ast = CompilerPipeline::new(&options).parse(&Source::new("contract", source))?;
+    Ok(ast.program)
 }
 
 /// Generate RISC-V instructions from a source file
 pub fn generate_riscv(
-    source_path: &PathBuf,
+    source_path: &Path,
     options: CompilerOptions,
 ) -> Result<Vec<compiler::codegen::risc_v::Instruction>, CompileError> {
-    // Read source file
-    let source = std::fs::read_to_string(source_path)?;
-
-    // Parse
-    let _lexer = BendLexer::new(&source);
-    let mut parser = Parser::new(&source);
-    let program = parser
-        .parse_program()
-        .map_err(|e| CompileError::Parse(e.to_string()))?;
-
-    // Type Check
-    if options.type_check {
-        let mut type_checker = TypeChecker::new();
-        type_checker
-            .check_program(&program)
-            .map_err(|e| CompileError::Type(e.to_string()))?;
-    }
-
-    // Optimize
-    let optimized_program = if options.optimize {
-        let mut manager = create_default_manager();
-        manager
-            .optimize(program)
-            .map_err(|e| CompileError::Optimization(e.to_string()))?
-    } else {
-        program
-    };
-
-    // Generate Code
-    let mut generator = RiscVCodegen::new();
-    let code = generator
-        .generate(&optimized_program)
-        .map_err(|e| CompileError::Codegen(e.to_string()))?;
-
-    Ok(code)
+    let source = Source::from_path(source_path)?;
+    let mut pipeline = CompilerPipeline::new(&options);
+    let ast = pipeline.parse(&source)?;
+    let typed = pipeline.check(&source, ast)?;
+    let ir = pipeline.lower(&typed)?;
+    pipeline.codegen(&source, &ir)
 }
 
-/// Generate RISC-V instructions from source code string
+/// Generate RISC-V instructions from source code string, without type
+/// checking it
 pub fn generate_riscv_from_source(
     source: &str,
     optimize: bool,
 ) -> Result<Vec<compiler::codegen::risc_v::Instruction>, CompileError> {
-    // Parse
-    let _lexer = BendLexer::new(source);
-    let mut parser = Parser::new(source);
-    let program = parser
-        .parse_program()
-        .map_err(|e| CompileError::Parse(e.to_string()))?;
-
-    // Optimize (optional)
-    let optimized_program = if optimize {
-        let mut manager = create_default_manager();
-        manager
-            .optimize(program)
-            .map_err(|e| CompileError::Optimization(e.to_string()))?
-    } else {
-        program
+    let options = CompilerOptions {
+        optimize,
+        type_check: false,
+        ..CompilerOptions::default()
     };
-
-    // Generate Code
-    let mut generator = RiscVCodegen::new();
-    let code = generator
-        .generate(&optimized_program)
-        .map_err(|e| CompileError::Codegen(e.to_string()))?;
-
-    Ok(code)
+    let source = Source::new("contract", source);
+    let mut pipeline = CompilerPipeline::new(&options);
+    let ast = pipeline.parse(&source)?;
+    let typed = pipeline.check(&source, ast)?;
+    let ir = pipeline.lower(&typed)?;
+    pipeline.codegen(&source, &ir)
 }
 
 /// Compile source code directly without writing to a file
@@ -327,44 +502,7 @@ pub fn compile_from_source(
     source: &str,
     options: CompilerOptions,
 ) -> Result<Vec<u8>, CompileError> {
-    // Parse
-    let _lexer = BendLexer::new(source);
-    let mut parser = Parser::new(source);
-    let program = parser
-        .parse_program()
-        .map_err(|e| CompileError::Parse(e.to_string()))?;
-
-    // Type Check
-    if options.type_check {
-        let mut type_checker = TypeChecker::new();
-        type_checker
-            .check_program(&program)
-            .map_err(|e| CompileError::Type(e.to_string()))?;
-    }
-
-    // Optimize
-    let optimized_program = if options.optimize {
-        let mut manager = create_default_manager();
-        manager
-            .optimize(program)
-            .map_err(|e| CompileError::Optimization(e.to_string()))?
-    } else {
-        program
-    };
-
-    // Generate Code
-    let mut generator = RiscVCodegen::new();
-    let code = generator
-        .generate(&optimized_program)
-        .map_err(|e| CompileError::Codegen(e.to_string()))?;
-
-    // Compile to PolkaVM
-    let polkavm_module =
-        compile_to_polkavm(&code, None).map_err(|e| CompileError::PolkaVM(e.to_string()))?;
-
-    polkavm_module
-        .binary
-        .ok_or_else(|| CompileError::Codegen("No binary generated".to_string()))
+    compile_to_artifacts("contract", source, &options).map(|result| result.blob)
 }
 
 /// Returns the current version of the compiler
@@ -1,10 +1,41 @@
 #![allow(dead_code)]
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use std::cell::RefCell;
 use std::path::{Path, PathBuf};
-
+use std::time::{Duration, Instant};
+
+use bend_pvm::analyzer::{CostTable, Graph, GraphFormat, GraphKind};
+use bend_pvm::compiler::analyzer::lints::{Level, Lint, LintLevels};
+use bend_pvm::compiler::cfg::Cfg;
+use bend_pvm::compiler::codegen::encoder::Isa;
+use bend_pvm::compiler::codegen::metadata::ContractMetadata;
+use bend_pvm::compiler::object::OBJECT_EXTENSION;
+use bend_pvm::compiler::pipeline::Target as CodegenTarget;
+use bend_pvm::compiler::polkavm::abi::parse_abi;
+use bend_pvm::compiler::polkavm::bindgen::{generate_bindings, Language};
+use bend_pvm::compiler::polkavm::indexer::{generate_indexer, Indexer};
+use bend_pvm::compiler::query::Database;
+use bend_pvm::compiler::symbolic::executor::SymbolicOptions;
+use bend_pvm::compiler::upgrade::{check_upgrade, migration_skeleton, StorageSchema};
 use bend_pvm::debugger::{DebugInfo, Debugger};
-use bend_pvm::formatter::Formatter;
-use bend_pvm::{compile, generate_riscv_from_source, CompilerOptions};
+use bend_pvm::deployment::{
+    package_contract, parse_code_hash, BuildSettings, Contract, ContractsPallet, DeployError,
+    DeploymentConfig, DeploymentState, DryRun, Environment, Estimate, Node, Rebuild, Reference,
+    Signer, StaticEstimate,
+};
+use bend_pvm::diagnostics::{codes, emit, emit_message, Diagnostic, MessageFormat};
+use bend_pvm::formatter::{collect_files, format_files, unified_diff};
+use bend_pvm::migration::{MigrationConfig, SolidityMigrator, SourceLanguage};
+use bend_pvm::package::artifacts::sha256;
+use bend_pvm::package::{
+    build_with, check_with, database, profile, rebuild, BuildError, BuildOutput, ModuleDiagnostic,
+    PackageManifest, Profile, TemplateSource, Watcher, Workspace, MANIFEST_FILE,
+};
+use bend_pvm::security::audit::FailOn;
+use bend_pvm::security::security_scanner::ReportFormat;
+use bend_pvm::{
+    compile, compile_object, generate_riscv_from_source, link, CompileError, CompilerOptions,
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "bend-pvm")]
@@ -55,6 +86,57 @@ enum Commands {
         /// Disable ABI generation
         #[arg(short = 'A', long)]
         no_abi: bool,
+
+        /// Rebuild every module instead of reusing the module cache
+        #[arg(long)]
+        no_cache: bool,
+
+        /// What to generate code for: riscv for PolkaVM, wasm32 for
+        /// pallet-contracts chains and browsers, or evm (experimental) for
+        /// Ethereum-compatible chains
+        #[arg(long, default_value = "riscv")]
+        target: CodegenTarget,
+
+        /// RISC-V instruction set: rv32e and rv32i produce PolkaVM blobs,
+        /// rv64 raw RV64IM machine code for 64-bit PolkaVM
+        #[arg(long, default_value = "rv32e")]
+        isa: Isa,
+
+        /// Compile the module to an object file, to link with `link`,
+        /// instead of a binary
+        #[arg(short = 'c', long, conflicts_with = "target")]
+        object: bool,
+
+        /// How to report errors, warnings and progress: human, or json to
+        /// stream one message per line on stdout
+        #[arg(long, default_value = "human")]
+        message_format: MessageFormat,
+
+        #[command(flatten)]
+        lints: LintArgs,
+
+        #[command(flatten)]
+        features: FeatureArgs,
+
+        /// Metadata of the deployed version, whose storage the contract
+        /// must be able to take over when upgraded with set_code_hash
+        #[arg(long, value_name = "METADATA")]
+        upgrade_from: Option<PathBuf>,
+
+        /// Give the same bytes on every build of the same source: skip
+        /// the module cache and keep absolute paths out of the output
+        #[arg(long)]
+        deterministic: bool,
+
+        /// Record source paths under FROM as under TO in debug information
+        #[arg(long, value_name = "FROM=TO", value_parser = parse_remap)]
+        remap_path_prefix: Vec<(PathBuf, PathBuf)>,
+
+        /// Unstable options: `time-passes` prints how long each phase took
+        /// and how much memory it used, `trace-passes=<file>` writes the
+        /// phases as a Chrome trace. Use with --no-cache to time them all.
+        #[arg(short = 'Z', value_name = "OPTION")]
+        unstable: Vec<Unstable>,
     },
 
     /// Check a Bend source file for errors
@@ -66,6 +148,86 @@ enum Commands {
         /// Disable type checking
         #[arg(short = 'T', long)]
         no_type_check: bool,
+
+        /// Recheck every module instead of reusing the module cache
+        #[arg(long)]
+        no_cache: bool,
+
+        /// How to report errors, warnings and progress: human, or json to
+        /// stream one message per line on stdout
+        #[arg(long, default_value = "human")]
+        message_format: MessageFormat,
+
+        #[command(flatten)]
+        lints: LintArgs,
+
+        #[command(flatten)]
+        features: FeatureArgs,
+    },
+
+    /// Build the package in the current directory, as bend.toml sets it up,
+    /// into target/<profile>
+    Build {
+        /// Build with the release profile
+        #[arg(long, conflicts_with = "debug")]
+        release: bool,
+
+        /// Build with the dev profile, the default
+        #[arg(long)]
+        debug: bool,
+
+        /// Keep rebuilding the modules affected by each change to the
+        /// sources, until interrupted
+        #[arg(short, long)]
+        watch: bool,
+
+        /// Only check the modules, without writing artifacts
+        #[arg(long)]
+        check: bool,
+
+        /// Run the tests of the package's modules after each build
+        #[arg(long)]
+        test: bool,
+
+        /// Rebuild every module instead of reusing the module cache
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Give the same bytes wherever the package is: skip the module
+        /// cache, record source paths relative to the package root and no
+        /// build time unless SOURCE_DATE_EPOCH is set
+        #[arg(long)]
+        deterministic: bool,
+
+        /// Record source paths under FROM as under TO in the artifacts
+        #[arg(long, value_name = "FROM=TO", value_parser = parse_remap)]
+        remap_path_prefix: Vec<(PathBuf, PathBuf)>,
+
+        /// How to report errors, warnings and progress: human, or json to
+        /// stream one message per line on stdout
+        #[arg(long, default_value = "human")]
+        message_format: MessageFormat,
+
+        #[command(flatten)]
+        lints: LintArgs,
+
+        #[command(flatten)]
+        features: FeatureArgs,
+    },
+
+    /// Link object files compiled with `compile --object` into a binary
+    Link {
+        /// Object files, the entry module first
+        #[arg(required = true)]
+        objects: Vec<PathBuf>,
+
+        /// Output file, the first object with the `bin` extension by default
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Keep the functions nothing calls
+        #[arg(long)]
+        keep_unused: bool,
     },
 
     /// Run a Bend source file
@@ -85,21 +247,36 @@ enum Commands {
         /// Set initial breakpoint at line
         #[arg(short, long)]
         breakpoint: Option<usize>,
+
+        /// Execute the produced blob on an embedded PolkaVM instance
+        #[cfg(feature = "polkavm-engine")]
+        #[arg(long, conflicts_with_all = ["step", "breakpoint"])]
+        polkavm: bool,
     },
 
-    /// Format a Bend source file
+    /// Format Bend source files, with the settings of the closest
+    /// `.bendfmt.toml` or the `[fmt]` table of `bend.toml`
     Format {
-        /// Bend source file
+        /// Files, directories or glob patterns; directories are searched
+        /// for .bend files that .gitignore does not ignore
         #[arg(required = true)]
-        file: PathBuf,
+        paths: Vec<PathBuf>,
 
-        /// Output file (defaults to overwriting the input file)
+        /// Output file for a single input (defaults to overwriting it)
         #[arg(short, long)]
         output: Option<PathBuf>,
 
-        /// Check if the file is formatted correctly
+        /// Check that the files are formatted, failing if any is not
         #[arg(short, long)]
         check: bool,
+
+        /// Print the changes formatting would make as unified diffs
+        #[arg(long, requires = "check")]
+        diff: bool,
+
+        /// Number of files formatted at once (defaults to the CPU count)
+        #[arg(short, long)]
+        jobs: Option<usize>,
     },
 
     /// Initialize a new Bend project
@@ -111,6 +288,26 @@ enum Commands {
         /// Project directory (defaults to a new directory with the project name)
         #[arg(short, long)]
         directory: Option<PathBuf>,
+
+        /// Template of the contract: minimal, erc20, erc721, dao, multisig,
+        /// escrow, or a package in a git repository as git+<url>[#<ref>]
+        #[arg(short, long, default_value = "minimal")]
+        template: TemplateSource,
+    },
+
+    /// Run the `#[test]` functions of a Bend source file and fuzz its
+    /// `#[invariant]` functions
+    Test {
+        /// Bend source file
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Only run the test with this name
+        #[arg(short, long)]
+        filter: Option<String>,
+
+        #[command(flatten)]
+        features: FeatureArgs,
     },
 
     /// Profile gas usage of a Bend source file
@@ -119,15 +316,463 @@ enum Commands {
         #[arg(required = true)]
         file: PathBuf,
 
+        /// Cost table to charge, instead of the one of the package
+        #[arg(long)]
+        costs: Option<PathBuf>,
+
         /// Output in JSON format
         #[arg(short, long)]
         json: bool,
     },
+
+    /// Draw the call graph or the module graph of a Bend source file,
+    /// annotated with gas estimates and storage access
+    Graph {
+        /// Bend source file
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Graph to draw: calls, between functions, or modules, between
+        /// the modules they import
+        #[arg(long, default_value = "calls")]
+        kind: GraphKind,
+
+        /// How to draw it: dot, for Graphviz, or mermaid
+        #[arg(long, default_value = "dot")]
+        format: GraphFormat,
+
+        /// Cost table to charge, instead of the one of the package
+        #[arg(long)]
+        costs: Option<PathBuf>,
+    },
+
+    /// Scan a Bend source file for vulnerabilities, failing when any is
+    /// high or critical
+    Scan {
+        /// Bend source file
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// How to write the findings out: human, json, or sarif for code
+        /// scanning services
+        #[arg(long, default_value = "human")]
+        format: ReportFormat,
+    },
+
+    /// Audit a project with the security scanner, the static analyzer and
+    /// the lints, failing on new findings at or above a severity
+    Audit {
+        /// Bend source files, or the modules of the package around the
+        /// current directory if none
+        files: Vec<PathBuf>,
+
+        /// Least severe new finding failing the audit: critical, high,
+        /// medium, low, info or none
+        #[arg(long, default_value = "high")]
+        fail_on: FailOn,
+
+        /// Write the findings to this file as SARIF
+        #[arg(long)]
+        sarif: Option<PathBuf>,
+
+        /// Accept the findings in this baseline
+        #[arg(long)]
+        baseline: Option<PathBuf>,
+
+        /// Write a baseline accepting the current findings to this file
+        #[arg(long)]
+        write_baseline: Option<PathBuf>,
+    },
+
+    /// Upload a contract to a node and instantiate it
+    Deploy {
+        /// Bend source file of the contract
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Arguments of the constructor, written as in source
+        #[arg(allow_negative_numbers = true)]
+        args: Vec<String>,
+
+        /// Message to instantiate the contract with, when it has no
+        /// `constructor` or `#[export("deploy")]` function
+        #[arg(long)]
+        constructor: Option<String>,
+
+        /// Balance transferred to the contract
+        #[arg(long, default_value_t = 0)]
+        value: u128,
+
+        /// 32-byte hex salt of the contract address (random by default)
+        #[arg(long)]
+        salt: Option<String>,
+
+        /// Only dry-run the instantiation, without submitting it
+        #[arg(long)]
+        dry_run: bool,
+
+        #[command(flatten)]
+        node: NodeArgs,
+    },
+
+    /// Call a message of a contract deployed on a node
+    Call {
+        /// Bend source file of the contract
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Address of the contract
+        #[arg(required = true)]
+        address: String,
+
+        /// Function of the contract to call
+        #[arg(required = true)]
+        message: String,
+
+        /// Arguments of the message, written as in source
+        #[arg(allow_negative_numbers = true)]
+        args: Vec<String>,
+
+        /// Balance transferred to the contract
+        #[arg(long, default_value_t = 0)]
+        value: u128,
+
+        /// Only dry-run the call and show what it returns, without
+        /// submitting it
+        #[arg(long)]
+        query: bool,
+
+        #[command(flatten)]
+        node: NodeArgs,
+    },
+
+    /// Dry-run deploying or calling a contract on a node and compare what
+    /// it costs with the static gas estimate
+    Estimate {
+        /// Bend source file of the contract
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Arguments of the constructor or message, written as in source
+        #[arg(allow_negative_numbers = true)]
+        args: Vec<String>,
+
+        /// Message to instantiate the contract with, when it has no
+        /// `constructor` or `#[export("deploy")]` function
+        #[arg(long, conflicts_with = "address")]
+        constructor: Option<String>,
+
+        /// Estimate calling the contract at this address instead of
+        /// deploying it
+        #[arg(long, requires = "message")]
+        address: Option<String>,
+
+        /// Function of the contract to call
+        #[arg(long, requires = "address")]
+        message: Option<String>,
+
+        /// Balance transferred to the contract
+        #[arg(long, default_value_t = 0)]
+        value: u128,
+
+        /// Cost table to charge, instead of the one of the package
+        #[arg(long)]
+        costs: Option<PathBuf>,
+
+        /// Picoseconds of ref_time one unit of static gas stands for,
+        /// instead of the rate of the cost table
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        ref_time_per_gas: Option<u64>,
+
+        /// Output in JSON format
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        node: NodeArgs,
+    },
+
+    /// Rebuild a contract from source and check it against the code of a
+    /// deployed contract, a code hash or a .bin file, or look for inputs
+    /// reaching its asserts and traps with --symbolic
+    #[command(group(
+        clap::ArgGroup::new("reference")
+            .required(true)
+            .args(["address", "code_hash", "bin", "symbolic"])
+    ))]
+    Verify {
+        /// Bend source file of the contract
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Address of the deployed contract, whose code hash is read from
+        /// the node
+        #[arg(long)]
+        address: Option<String>,
+
+        /// Code hash the contract was uploaded with
+        #[arg(long)]
+        code_hash: Option<String>,
+
+        /// Contract blob to compare with, as `deploy` uploads it
+        #[arg(long)]
+        bin: Option<PathBuf>,
+
+        /// Also write the rebuilt contract blob to this file
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Contracts pallet whose code hash to compute when there is no
+        /// node to ask: revive or contracts
+        #[arg(long, default_value = "revive", conflicts_with = "address")]
+        pallet: ContractsPallet,
+
+        /// Compiler version the contract was built with, which must be this
+        /// one
+        #[arg(long)]
+        compiler_version: Option<String>,
+
+        /// Output the verification report in JSON format
+        #[arg(long)]
+        json: bool,
+
+        /// Explore the functions symbolically instead, reporting inputs
+        /// that fail an assert, overflow or divide by zero
+        #[arg(long)]
+        symbolic: bool,
+
+        /// Iterations of each loop explored by --symbolic
+        #[arg(long, default_value_t = 8, requires = "symbolic")]
+        loop_bound: u32,
+
+        /// RPC URL of the node, for --address
+        #[arg(long, default_value_t = DeploymentConfig::new(Environment::Development).network.rpc_url)]
+        url: String,
+    },
+
+    /// Check from their metadata that a new version of a contract can take
+    /// over the storage of the deployed one, failing when it cannot
+    UpgradeCheck {
+        /// Metadata or storage layout of the deployed version
+        #[arg(required = true)]
+        old: PathBuf,
+
+        /// Metadata or storage layout of the new version
+        #[arg(required = true)]
+        new: PathBuf,
+
+        /// Output the report in JSON format
+        #[arg(long)]
+        json: bool,
+
+        /// Write a skeleton of the #[migrate] function to this file
+        #[arg(long)]
+        write_migration: Option<PathBuf>,
+    },
+
+    /// Generate a typed client of a contract from its ABI
+    Bindgen {
+        /// Bend source file of the contract, or its ABI as JSON
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Language of the client: rust or ts
+        #[arg(long, required_unless_present = "indexer", conflicts_with = "indexer")]
+        lang: Option<Language>,
+
+        /// Generate a GraphQL schema and event mappings for an indexer
+        /// instead: subsquid or subquery
+        #[arg(long)]
+        indexer: Option<Indexer>,
+
+        /// File to write the client to instead of stdout, or directory of
+        /// the indexer project
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Convert a Solidity, Vyper or ink! contract to Bend, reporting what
+    /// needs a hand
+    Migrate {
+        /// Contract source file: `.sol`, `.vy`, or `.rs` for ink!
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// File to write the Bend source to instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Directory to write the compatibility report to, as Markdown,
+        /// HTML and JSON
+        #[arg(long, value_name = "DIR")]
+        report: Option<PathBuf>,
+    },
+}
+
+/// The node `deploy`, `call` and `estimate` talk to and who signs for them
+#[derive(Args, Debug)]
+struct NodeArgs {
+    /// RPC URL of the node
+    #[arg(long, default_value_t = DeploymentConfig::new(Environment::Development).network.rpc_url)]
+    url: String,
+
+    /// Development account (e.g. //Alice), 0x-prefixed hex seed or
+    /// keystore file signing the extrinsics
+    #[arg(long, default_value = "//Alice")]
+    signer: String,
+}
+
+impl NodeArgs {
+    fn connect(&self) -> Result<(Node, Signer), DeployError> {
+        let signer = Signer::parse(&self.signer)?;
+        let node = Node::connect(&self.url)?;
+        Ok((node, signer))
+    }
+}
+
+/// Levels of the lints, for `compile`, `check` and `build`
+#[derive(Args, Debug)]
+struct LintArgs {
+    /// Silence the warnings of a lint
+    #[arg(long = "allow", value_name = "LINT")]
+    allow: Vec<Lint>,
+
+    /// Report the problems a lint finds as warnings
+    #[arg(long = "warn", value_name = "LINT")]
+    warn: Vec<Lint>,
+
+    /// Report the problems a lint finds as errors
+    #[arg(long = "deny", value_name = "LINT")]
+    deny: Vec<Lint>,
+
+    /// Fail on any warning, e.g. in CI
+    #[arg(long)]
+    deny_warnings: bool,
+}
+
+impl LintArgs {
+    fn levels(&self) -> LintLevels {
+        let mut levels = LintLevels::default();
+        for (lints, level) in [
+            (&self.allow, Level::Allow),
+            (&self.warn, Level::Warn),
+            (&self.deny, Level::Deny),
+        ] {
+            for lint in lints {
+                levels.set(*lint, level);
+            }
+        }
+        if self.deny_warnings {
+            levels.deny_warnings();
+        }
+        levels
+    }
+}
+
+/// Features for `#[cfg(feature = "...")]`, for `compile`, `check`, `build`
+/// and `test`
+#[derive(Args, Debug)]
+struct FeatureArgs {
+    /// Features to enable, separated by commas
+    #[arg(short = 'F', long, value_delimiter = ',', value_name = "FEATURES")]
+    features: Vec<String>,
+
+    /// Do not enable the features listed by `default` in bend.toml
+    #[arg(long)]
+    no_default_features: bool,
+}
+
+impl FeatureArgs {
+    /// The features enabled in the package of `path`, as its bend.toml
+    /// declares them, or the given ones for a file outside of any package
+    fn cfg(&self, path: &Path) -> Result<Cfg, Box<dyn std::error::Error>> {
+        match Workspace::find_root(path) {
+            Some(root) => resolve_features(&root, &self.features, !self.no_default_features),
+            None => Ok(Cfg::new().with_features(self.features.iter().cloned())),
+        }
+    }
+}
+
+/// The features of the package in `root` enabled by `features` and, with
+/// `default_features`, by its `default` feature
+fn resolve_features(
+    root: &Path,
+    features: &[String],
+    default_features: bool,
+) -> Result<Cfg, Box<dyn std::error::Error>> {
+    let manifest = PackageManifest::load(&root.join(MANIFEST_FILE))?;
+    Ok(Cfg::new().with_features(manifest.resolve_features(features, default_features)?))
+}
+
+/// Unstable options of `compile`, given as `-Z <option>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Unstable {
+    /// Print the phases of the compilation as a table on stderr
+    TimePasses,
+    /// Write the phases of the compilation to a file as a Chrome trace
+    TracePasses(PathBuf),
+}
+
+impl std::str::FromStr for Unstable {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            None if s == "time-passes" => Ok(Unstable::TimePasses),
+            Some(("trace-passes", path)) if !path.is_empty() => {
+                Ok(Unstable::TracePasses(PathBuf::from(path)))
+            }
+            _ => Err(format!(
+                "Unknown unstable option '{}', expected time-passes or trace-passes=<file>",
+                s
+            )),
+        }
+    }
+}
+
+/// A `--remap-path-prefix` of the form `FROM=TO`
+fn parse_remap(s: &str) -> Result<(PathBuf, PathBuf), String> {
+    match s.split_once('=') {
+        Some((from, to)) if !from.is_empty() => Ok((PathBuf::from(from), PathBuf::from(to))),
+        _ => Err(format!("Expected FROM=TO, found '{}'", s)),
+    }
+}
+
+impl Commands {
+    /// How the command writes out its errors, and the file they are about
+    /// unless they say otherwise
+    fn message_format(&self) -> (MessageFormat, PathBuf) {
+        match self {
+            Commands::Compile {
+                file,
+                message_format,
+                ..
+            }
+            | Commands::Check {
+                file,
+                message_format,
+                ..
+            } => (*message_format, file.clone()),
+            Commands::Build { message_format, .. } => (*message_format, PathBuf::new()),
+            _ => (MessageFormat::Human, PathBuf::new()),
+        }
+    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
+    let (format, file) = cli.command.message_format();
+    match run(cli) {
+        Err(error) if format == MessageFormat::Json => {
+            emit_error(&*error, &file);
+            emit_finished(false);
+            std::process::exit(1);
+        }
+        result => result,
+    }
+}
 
+fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
     match cli.command {
         Commands::Compile {
             file,
@@ -138,6 +783,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             assembly,
             no_metadata,
             no_abi,
+            no_cache,
+            target,
+            isa,
+            object,
+            message_format,
+            lints,
+            features,
+            upgrade_from,
+            deterministic,
+            remap_path_prefix,
+            unstable,
         } => {
             // Handle auto flag behavior
             let optimize = !no_optimize;
@@ -148,7 +804,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             let output = output.or_else(|| {
                 file.file_stem().map(|stem| {
                     let mut output = PathBuf::from(stem);
-                    output.set_extension("bin");
+                    output.set_extension(match object {
+                        true => OBJECT_EXTENSION,
+                        false => target.extension(),
+                    });
                     output
                 })
             });
@@ -166,17 +825,87 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 static_analysis: true,
                 fuzz_testing: false,
                 security_level: 2,
+                incremental: !no_cache,
+                message_format,
+                lint_levels: lints.levels(),
+                time_passes: unstable.contains(&Unstable::TimePasses),
+                cfg: features.cfg(&file)?,
+                target,
+                isa,
+                upgrade_from: upgrade_from.as_deref().map(read_metadata).transpose()?,
+                deterministic,
+                remap_path_prefix,
             };
 
+            // Resolve and compile the package's dependencies
+            prepare_dependencies(&file, !no_cache && !deterministic, message_format)?;
+
+            if object {
+                let path = options
+                    .output
+                    .clone()
+                    .unwrap_or_else(|| file.with_extension(OBJECT_EXTENSION));
+                compile_object(&file, &options)?.write(&path)?;
+                match message_format {
+                    MessageFormat::Human => println!("Wrote object {}.", path.display()),
+                    MessageFormat::Json => {
+                        emit_message(
+                            "artifact",
+                            serde_json::json!({ "module": file, "object": path }),
+                        );
+                        emit_finished(true);
+                    }
+                }
+                return Ok(());
+            }
+
             // Compile file
-            compile(&file, options)?;
+            let output = report(&file, compile(&file, options), message_format)?;
+
+            for option in &unstable {
+                match option {
+                    Unstable::TimePasses => eprint!("{}", output.result.timings.table()),
+                    Unstable::TracePasses(path) => std::fs::write(
+                        path,
+                        serde_json::to_string(&output.result.timings.chrome_trace())?,
+                    )?,
+                }
+            }
 
-            println!("Compilation successful.");
+            match message_format {
+                MessageFormat::Human => println!("Compilation successful."),
+                MessageFormat::Json => {
+                    let result = &output.result;
+                    emit_message(
+                        "artifact",
+                        serde_json::json!({
+                            "module": file,
+                            "binary": output.binary,
+                            "listing": output.assembly,
+                            "abi": output.abi,
+                            "metadata": output.metadata,
+                            "size": result.blob.len(),
+                            "sha256": sha256(&result.blob),
+                        }),
+                    );
+                    for phase in result.timings.top_level() {
+                        emit_timing(&phase.name, phase.duration);
+                    }
+                    if let Some(gas_report) = &result.gas_report {
+                        emit_message("gas-report", gas_report.to_json());
+                    }
+                    emit_finished(true);
+                }
+            }
         }
 
         Commands::Check {
             file,
             no_type_check,
+            no_cache,
+            message_format,
+            lints,
+            features,
         } => {
             // Handle auto flag behavior
             let type_check = !no_type_check;
@@ -194,12 +923,95 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 static_analysis: true,
                 fuzz_testing: false,
                 security_level: 2,
+                incremental: !no_cache,
+                message_format,
+                lint_levels: lints.levels(),
+                time_passes: false,
+                cfg: features.cfg(&file)?,
+                target: CodegenTarget::RiscV,
+                isa: Isa::default(),
+                upgrade_from: None,
+                deterministic: false,
+                remap_path_prefix: Vec::new(),
             };
 
+            // Resolve and check the package's dependencies
+            prepare_dependencies(&file, !no_cache, message_format)?;
+
             // Check file
-            compile(&file, options)?;
+            let output = report(&file, compile(&file, options), message_format)?;
+
+            match message_format {
+                MessageFormat::Human => println!("No errors found."),
+                MessageFormat::Json => {
+                    for phase in output.result.timings.top_level() {
+                        emit_timing(&phase.name, phase.duration);
+                    }
+                    emit_finished(true);
+                }
+            }
+        }
+
+        Commands::Build {
+            release,
+            debug: _,
+            watch,
+            check,
+            test,
+            no_cache,
+            deterministic,
+            remap_path_prefix,
+            message_format,
+            lints,
+            features,
+        } => {
+            let cwd = std::env::current_dir()?;
+            let Some(root) = Workspace::find_root(&cwd) else {
+                eprintln!(
+                    "error: no {} found in {} or above",
+                    MANIFEST_FILE,
+                    cwd.display()
+                );
+                std::process::exit(1);
+            };
+            prepare_dependencies(&root, !no_cache && !deterministic, message_format)?;
 
-            println!("No errors found.");
+            let session = BuildSession {
+                root,
+                profile: if release {
+                    profile::RELEASE
+                } else {
+                    profile::DEV
+                },
+                deterministic,
+                remap_path_prefix,
+                check,
+                test,
+                format: message_format,
+                lint_levels: lints.levels(),
+                features,
+                database: RefCell::default(),
+            };
+            let succeeded = session.run(None);
+            if watch {
+                session.watch()?;
+            } else if !succeeded {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Link {
+            objects,
+            output,
+            keep_unused,
+        } => {
+            let output = output.unwrap_or_else(|| objects[0].with_extension("bin"));
+            std::fs::write(&output, link(&objects, keep_unused)?)?;
+            println!(
+                "Linked {} object(s) into {}.",
+                objects.len(),
+                output.display()
+            );
         }
 
         Commands::Run {
@@ -207,6 +1019,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             no_optimize,
             step,
             breakpoint,
+            #[cfg(feature = "polkavm-engine")]
+            polkavm,
         } => {
             // Read source file
             let source = std::fs::read_to_string(&file)
@@ -224,6 +1038,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 return Ok(());
             }
 
+            #[cfg(feature = "polkavm-engine")]
+            if polkavm {
+                return run_on_polkavm(&instructions);
+            }
+
             // Create debug info (basic)
             let debug_info = DebugInfo {
                 source_path: file.clone(),
@@ -307,85 +1126,75 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
 
         Commands::Format {
-            file,
+            paths,
             output,
             check,
+            diff,
+            jobs,
         } => {
-            let mut formatter = Formatter::new();
-
-            if check {
-                // Check if file is formatted
-                let source = std::fs::read_to_string(&file)
-                    .map_err(|e| format!("Failed to read file: {}", e))?;
+            let files = collect_files(&paths)?;
+            if output.is_some() && (files.len() != 1 || check) {
+                return Err("--output takes a single file and cannot be used with --check".into());
+            }
 
-                if formatter.is_formatted(&source) {
-                    println!("File is already formatted.");
-                } else {
-                    println!("File needs formatting.");
-                    return Err("File is not formatted".into());
+            let jobs = jobs.unwrap_or_else(num_cpus::get);
+            let mut failed = 0;
+            let mut changed = 0;
+            for file in format_files(&files, jobs, &|_| {}) {
+                let formatted = match &file.result {
+                    Ok(formatted) => formatted,
+                    Err(e) => {
+                        eprintln!("Failed to format {}: {}", file.path.display(), e);
+                        failed += 1;
+                        continue;
+                    }
+                };
+                if let Some(output) = &output {
+                    std::fs::write(output, formatted)
+                        .map_err(|e| format!("Failed to write output: {}", e))?;
+                    println!("Formatted: {} -> {}", file.path.display(), output.display());
+                    continue;
                 }
-            } else {
-                // Format the file
-                let output_path = output.as_ref().unwrap_or(&file);
-
-                if file == *output_path {
-                    // Format in-place
-                    match formatter.format_file_in_place(&file) {
-                        Ok(modified) => {
-                            if modified {
-                                println!("Formatted: {}", file.display());
-                            } else {
-                                println!("File is already formatted: {}", file.display());
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Failed to format file: {}", e);
-                            return Err(e);
-                        }
+                if !file.changed() {
+                    continue;
+                }
+                changed += 1;
+                if check {
+                    if diff {
+                        let name = file.path.display().to_string();
+                        print!("{}", unified_diff(&name, &file.source, formatted));
+                    } else {
+                        println!("Would reformat: {}", file.path.display());
                     }
                 } else {
-                    // Format to different output file
-                    match formatter.format_file(&file) {
-                        Ok(bend_pvm::formatter::FormatResult::Formatted(formatted)) => {
-                            std::fs::write(output_path, formatted)
-                                .map_err(|e| format!("Failed to write output: {}", e))?;
-                            println!("Formatted: {} -> {}", file.display(), output_path.display());
-                        }
-                        Ok(bend_pvm::formatter::FormatResult::AlreadyFormatted) => {
-                            println!("File is already formatted: {}", file.display());
-                            // Copy file to output if different
-                            if file != *output_path {
-                                std::fs::copy(&file, output_path)
-                                    .map_err(|e| format!("Failed to copy file: {}", e))?;
-                                println!("Copied: {} -> {}", file.display(), output_path.display());
-                            }
-                        }
-                        Ok(bend_pvm::formatter::FormatResult::NeedsFormatting) => {
-                            eprintln!("File needs formatting but was not processed");
-                            return Err("Formatting error".into());
-                        }
-                        Ok(bend_pvm::formatter::FormatResult::Error(e)) => {
-                            eprintln!("Failed to format file: {}", e);
-                            return Err(e.into());
-                        }
-                        Err(e) => {
-                            eprintln!("Failed to format file: {}", e);
-                            return Err(e);
-                        }
-                    }
+                    std::fs::write(&file.path, formatted)
+                        .map_err(|e| format!("Failed to write {}: {}", file.path.display(), e))?;
+                    println!("Formatted: {}", file.path.display());
                 }
             }
+
+            if check && changed > 0 {
+                eprintln!("{} of {} files need formatting", changed, files.len());
+            } else if output.is_none() && changed == 0 && failed == 0 {
+                println!("{} files already formatted", files.len());
+            }
+            if failed > 0 || (check && changed > 0) {
+                std::process::exit(1);
+            }
         }
 
-        Commands::Init { name, directory } => {
+        Commands::Init {
+            name,
+            directory,
+            template,
+        } => {
             // Determine project directory
             let project_dir = directory.unwrap_or_else(|| PathBuf::from(&name));
 
-            // Create project directory
-            std::fs::create_dir_all(&project_dir)?;
-
             // Create project structure
-            create_project_structure(&project_dir, &name)?;
+            for file in template.scaffold(&project_dir, &name)? {
+                println!("Created {}", project_dir.join(file).display());
+            }
 
             if cli.auto {
                 // In auto mode, also initialize with default dependencies
@@ -396,18 +1205,76 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 // TODO: Add default dependencies to bend.toml
             }
 
-            println!("Project '{}' initialized in {:?}.", name, project_dir);
+            println!(
+                "Project '{}' initialized in {:?} from the {} template.",
+                name, project_dir, template
+            );
+        }
+
+        Commands::Test {
+            file,
+            filter,
+            features,
+        } => {
+            use bend_pvm::testing::{TestError, TestResult, TestSuite};
+
+            let source = std::fs::read_to_string(&file)?;
+            let name = file.file_stem().unwrap_or_default().to_string_lossy();
+            let cfg = features.cfg(&file)?;
+            let mut suite = match TestSuite::from_source_with_cfg(&name, &source, &cfg) {
+                Ok(suite) => suite,
+                Err(TestError::Diagnostic(diagnostic)) => {
+                    let name = file.display().to_string();
+                    emit(&diagnostic, &name, &source, MessageFormat::Human);
+                    std::process::exit(1);
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if let Some(filter) = &filter {
+                suite.tests.retain(|test| &test.name == filter);
+                if suite.tests.is_empty() {
+                    eprintln!("No test named '{}' in {}", filter, file.display());
+                    std::process::exit(1);
+                }
+            }
+
+            let mut failed = 0;
+            for (name, result) in suite.run_all() {
+                match result {
+                    TestResult::Passed { gas_used, .. } => {
+                        println!("test {} ... ok ({} gas)", name, gas_used)
+                    }
+                    TestResult::Failed { error, .. } => {
+                        failed += 1;
+                        println!("test {} ... FAILED: {}", name, error);
+                    }
+                    TestResult::Skipped { reason } => {
+                        println!("test {} ... skipped: {}", name, reason)
+                    }
+                }
+            }
+            if failed > 0 {
+                std::process::exit(1);
+            }
         }
 
-        Commands::GasProfile { file, json } => {
+        Commands::GasProfile { file, costs, json } => {
             use bend_pvm::analyzer::gas_profiler::GasProfiler;
 
-            match GasProfiler::new().profile_file(&file) {
+            let costs = match cost_table(costs.as_deref(), &file) {
+                Ok(costs) => costs,
+                Err(e) => {
+                    eprintln!("Error reading the cost table: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            match GasProfiler::with_costs(costs).profile_file(&file) {
                 Ok(profile) => {
                     if json {
-                        println!("JSON output not yet implemented");
+                        println!("{}", serde_json::to_string_pretty(&profile.to_json())?);
+                    } else {
+                        bend_pvm::analyzer::gas_profiler::print_profile(&profile);
                     }
-                    bend_pvm::analyzer::gas_profiler::print_profile(&profile);
                 }
                 Err(e) => {
                     eprintln!("Error profiling gas: {}", e);
@@ -415,93 +1282,1137 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
         }
+
+        Commands::Graph {
+            file,
+            kind,
+            format,
+            costs,
+        } => {
+            use bend_pvm::analyzer::gas_profiler::GasProfiler;
+
+            let costs = match cost_table(costs.as_deref(), &file) {
+                Ok(costs) => costs,
+                Err(e) => {
+                    eprintln!("Error reading the cost table: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let mut modules = bend_pvm::compiler::module::ModuleSystem::new();
+            if let Some(dir) = file.parent() {
+                modules.add_search_path(dir);
+            }
+            let module = match modules.load_module(&file) {
+                Ok(module) => module,
+                Err(e) => {
+                    eprintln!("Error loading {}: {}", file.display(), e);
+                    std::process::exit(1);
+                }
+            };
+            let graph = match kind {
+                GraphKind::Calls => Graph::calls(&module, &GasProfiler::with_costs(costs)),
+                GraphKind::Modules => Graph::modules(&module),
+            };
+            print!("{}", graph.render(format));
+        }
+
+        Commands::Scan { file, format } => {
+            use bend_pvm::security::security_scanner::SecurityScanner;
+
+            let source = std::fs::read_to_string(&file)?;
+            let program = match bend_pvm::parse_source(&source) {
+                Ok(program) => program,
+                Err(e) => {
+                    eprintln!("Error parsing {}: {}", file.display(), e);
+                    std::process::exit(1);
+                }
+            };
+            let result = SecurityScanner::new().scan_program(&program)?;
+            match format {
+                ReportFormat::Human => {
+                    for vulnerability in &result.vulnerabilities {
+                        println!("{}\n", vulnerability);
+                    }
+                    println!(
+                        "{} finding(s): {} critical, {} high, {} medium, {} low.",
+                        result.total_vulnerabilities,
+                        result.critical_count,
+                        result.high_count,
+                        result.medium_count,
+                        result.low_count + result.info_count
+                    );
+                }
+                ReportFormat::Json => {
+                    println!("{}", serde_json::to_string_pretty(&result.to_json())?)
+                }
+                ReportFormat::Sarif => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&result.to_sarif(&file.display().to_string()))?
+                ),
+            }
+            if result.critical_count + result.high_count > 0 {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Audit {
+            files,
+            fail_on,
+            sarif,
+            baseline,
+            write_baseline,
+        } => {
+            use bend_pvm::security::audit::{AuditReport, Baseline};
+
+            let files = if files.is_empty() {
+                let cwd = std::env::current_dir()?;
+                let Some(root) = Workspace::find_root(&cwd) else {
+                    eprintln!(
+                        "error: no {} found in {} or above",
+                        MANIFEST_FILE,
+                        cwd.display()
+                    );
+                    std::process::exit(1);
+                };
+                let workspace = Workspace::load(&root)?;
+                workspace
+                    .root()
+                    .module_files()?
+                    .into_iter()
+                    .map(|path| {
+                        path.strip_prefix(&root)
+                            .map(Path::to_path_buf)
+                            .unwrap_or(path)
+                    })
+                    .collect()
+            } else {
+                files
+            };
+
+            let mut report = AuditReport::new();
+            for file in &files {
+                let source = std::fs::read_to_string(file)?;
+                let program = match bend_pvm::parse_source(&source) {
+                    Ok(program) => program,
+                    Err(e) => {
+                        eprintln!("Error parsing {}: {}", file.display(), e);
+                        std::process::exit(1);
+                    }
+                };
+                report.audit(&file.display().to_string(), &program)?;
+            }
+            if let Some(baseline) = baseline {
+                report.apply_baseline(&Baseline::read(&baseline)?);
+            }
+
+            for finding in report.new_findings() {
+                println!("{}\n", finding);
+            }
+            println!("{}", report.summary());
+            if let Some(sarif) = sarif {
+                std::fs::write(sarif, serde_json::to_string_pretty(&report.to_sarif())?)?;
+            }
+            if let Some(path) = write_baseline {
+                report.baseline().write(&path)?;
+            }
+            if report.fails(&fail_on) {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Deploy {
+            file,
+            args,
+            constructor,
+            value,
+            salt,
+            dry_run,
+            node,
+        } => {
+            let deployment = Deployment { file, node, value };
+            exit_on_error(deployment.deploy(
+                constructor.as_deref(),
+                &args,
+                salt.as_deref(),
+                dry_run,
+            ));
+        }
+
+        Commands::Call {
+            file,
+            address,
+            message,
+            args,
+            value,
+            query,
+            node,
+        } => {
+            let deployment = Deployment { file, node, value };
+            exit_on_error(deployment.call(&address, &message, &args, query));
+        }
+
+        Commands::Estimate {
+            file,
+            args,
+            constructor,
+            address,
+            message,
+            value,
+            costs,
+            ref_time_per_gas,
+            json,
+            node,
+        } => {
+            let deployment = Deployment { file, node, value };
+            let target = match (&address, &message) {
+                (Some(address), Some(message)) => Target::Call { address, message },
+                _ => Target::Deploy {
+                    constructor: constructor.as_deref(),
+                },
+            };
+            let result = cost_table(costs.as_deref(), &deployment.file)
+                .map_err(DeployError::Argument)
+                .and_then(|mut costs| {
+                    if let Some(ref_time_per_gas) = ref_time_per_gas {
+                        costs.ref_time_per_gas = ref_time_per_gas;
+                    }
+                    deployment.estimate(target, &args, &costs, json)
+                });
+            exit_on_error(result);
+        }
+
+        Commands::Verify {
+            file,
+            address,
+            code_hash,
+            bin,
+            output,
+            pallet,
+            compiler_version,
+            json,
+            loop_bound,
+            url,
+            ..
+        } => {
+            let reference = match (address, code_hash, bin) {
+                (Some(address), ..) => VerifyAgainst::Deployed { address, url },
+                (_, Some(code_hash), _) => VerifyAgainst::CodeHash(code_hash, pallet),
+                (.., Some(bin)) => VerifyAgainst::Binary(bin, pallet),
+                // Otherwise clap requires --symbolic
+                _ => {
+                    verify_symbolically(&file, loop_bound, json)?;
+                    return Ok(());
+                }
+            };
+            exit_on_error(verify(
+                &file,
+                reference,
+                output.as_deref(),
+                compiler_version.as_deref(),
+                json,
+            ));
+        }
+
+        Commands::UpgradeCheck {
+            old,
+            new,
+            json,
+            write_migration,
+        } => {
+            let (old, new) = (read_schema(&old)?, read_schema(&new)?);
+            let report = check_upgrade(&old, &new);
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&report)?),
+                false => println!("{}", report),
+            }
+            if let Some(path) = write_migration {
+                std::fs::write(&path, migration_skeleton(&old, &new))
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            }
+            if !report.is_compatible() {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Bindgen {
+            file,
+            lang,
+            indexer,
+            output,
+        } => {
+            let abi = if file
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                let json = std::fs::read_to_string(&file)
+                    .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+                parse_abi(&json).map_err(|e| format!("{}: {}", file.display(), e))?
+            } else {
+                let source = std::fs::read_to_string(&file)
+                    .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+                let name = file
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                report(
+                    &file,
+                    Contract::compile(&name, &source),
+                    MessageFormat::Human,
+                )?
+                .abi
+            };
+            if let Some(indexer) = indexer {
+                for generated in generate_indexer(&abi, indexer)? {
+                    let Some(dir) = &output else {
+                        println!(
+                            "==> {} <==\n{}",
+                            generated.path.display(),
+                            generated.contents
+                        );
+                        continue;
+                    };
+                    let path = dir.join(&generated.path);
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent)
+                            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+                    }
+                    std::fs::write(&path, generated.contents)
+                        .map_err(|e| format!("Failed to write output: {}", e))?;
+                    println!("Generated: {}", path.display());
+                }
+                return Ok(());
+            }
+            let bindings = generate_bindings(&abi, lang.unwrap_or(Language::Rust))?;
+            match &output {
+                Some(output) => {
+                    std::fs::write(output, bindings)
+                        .map_err(|e| format!("Failed to write output: {}", e))?;
+                    println!("Generated: {}", output.display());
+                }
+                None => print!("{}", bindings),
+            }
+        }
+
+        Commands::Migrate {
+            file,
+            output,
+            report,
+        } => {
+            let language = SourceLanguage::from_path(&file).ok_or_else(|| {
+                format!(
+                    "Cannot tell the language of {}: expected a .sol, .vy or .rs file",
+                    file.display()
+                )
+            })?;
+            let source = std::fs::read_to_string(&file)
+                .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+            let mut migrator = SolidityMigrator::with_config(MigrationConfig {
+                generate_report: report.is_some(),
+                output_dir: report.unwrap_or_default(),
+                ..MigrationConfig::default()
+            });
+            let bend = migrator.migrate(language, &source, &file.display().to_string())?;
+            match &output {
+                Some(output) => {
+                    std::fs::write(output, bend)
+                        .map_err(|e| format!("Failed to write output: {}", e))?;
+                    println!("Generated: {}", output.display());
+                }
+                None => print!("{}", bend),
+            }
+            for issue in &migrator.stats().issues {
+                eprintln!(
+                    "{}:{}: {}: {}",
+                    file.display(),
+                    issue.source_location,
+                    issue.severity,
+                    issue.description
+                );
+            }
+            for path in migrator.write_report()? {
+                println!("Report: {}", path.display());
+            }
+        }
     }
 
     Ok(())
 }
 
-fn create_project_structure(project_dir: &Path, name: &str) -> std::io::Result<()> {
-    // Create main source file
-    let main_file = project_dir.join("src").join("main.bend");
-    std::fs::create_dir_all(main_file.parent().unwrap())?;
+/// Read the metadata a compilation wrote to `path`
+fn read_metadata(path: &Path) -> Result<ContractMetadata, String> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))
+}
 
-    std::fs::write(
-        &main_file,
-        format!(
-            r#"
-#{{{name}}}
-# A smart contract written in Bend-PVM.
+/// Read the storage schema in the metadata or storage layout at `path`
+fn read_schema(path: &Path) -> Result<StorageSchema, String> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    StorageSchema::from_json(&json).map_err(|e| format!("{}: {}", path.display(), e))
+}
 
-def main() -> u24:
-    return 42
-"#
-        ),
-    )?;
-
-    // Create project configuration
-    let config_file = project_dir.join("bend.toml");
-    std::fs::write(
-        &config_file,
-        format!(
-            r#"
-[package]
-name = "{name}"
-version = "0.1.0"
-authors = ["Your Name <your.email@example.com>"]
-
-[dependencies]
-# Add your dependencies here
-"#
+/// Write out the diagnostic a compilation of `file` failed with, or the
+/// number of denied warnings, exiting with status 1
+fn report<T>(
+    file: &Path,
+    result: Result<T, CompileError>,
+    format: MessageFormat,
+) -> Result<T, Box<dyn std::error::Error>> {
+    let error = match result {
+        Ok(value) => return Ok(value),
+        Err(error) => error,
+    };
+    // A file that cannot be read has no code frame to show
+    let source = std::fs::read_to_string(file).unwrap_or_default();
+    match Diagnostic::from_compile_error(&error, &source) {
+        Some(diagnostic) => emit(&diagnostic, &file.display().to_string(), &source, format),
+        None if format == MessageFormat::Human => {
+            eprintln!("error: {} failed because of {}", file.display(), error)
+        }
+        None => {}
+    }
+    if format == MessageFormat::Json {
+        emit_finished(false);
+    }
+    std::process::exit(1);
+}
+
+/// A contract `deploy` and `call` work with, on a node
+struct Deployment {
+    file: PathBuf,
+    node: NodeArgs,
+    /// Balance transferred to the contract
+    value: u128,
+}
+
+impl Deployment {
+    /// Instantiate the contract with the message `constructor`, or with
+    /// the constructor of the contract, if it has one
+    fn deploy(
+        &self,
+        constructor: Option<&str>,
+        args: &[String],
+        salt: Option<&str>,
+        dry_run: bool,
+    ) -> Result<(), DeployError> {
+        let contract = self.compile()?;
+        let data = match constructor {
+            Some(constructor) => contract.call_data(constructor, args)?,
+            None => contract.deploy_data(args)?,
+        };
+        let salt = match salt {
+            Some(salt) => parse_salt(salt)?,
+            None => rand::random(),
+        };
+        let (node, signer) = self.node.connect()?;
+
+        if dry_run {
+            let result =
+                node.dry_run_instantiate(&signer, &contract.binary, &data, self.value, &salt)?;
+            print_dry_run(&result);
+            if let Some(address) = &result.address {
+                println!("Would deploy to {}", address);
+            }
+            return Ok(());
+        }
+        let state = node.instantiate(&signer, &contract.binary, &data, self.value, &salt)?;
+        println!(
+            "Deployed {} to {}",
+            self.file.display(),
+            state.contract_address.as_deref().unwrap_or_default()
+        );
+        print_inclusion(&state);
+        Ok(())
+    }
+
+    /// Call `message` of the contract at `address`, or only dry-run it
+    /// with `query`, and write out what it returned
+    fn call(
+        &self,
+        address: &str,
+        message: &str,
+        args: &[String],
+        query: bool,
+    ) -> Result<(), DeployError> {
+        let contract = self.compile()?;
+        let data = contract.call_data(message, args)?;
+        let (node, signer) = self.node.connect()?;
+
+        let result = if query {
+            let result = node.dry_run_call(&signer, address, &data, self.value)?;
+            print_dry_run(&result);
+            result
+        } else {
+            let (state, result) = node.call(&signer, address, &data, self.value)?;
+            println!("Called {} on {}", message, address);
+            print_inclusion(&state);
+            result
+        };
+        if result.reverted {
+            return Err(DeployError::Contract(format!(
+                "{} reverted with 0x{}",
+                message,
+                hex::encode(&result.data)
+            )));
+        }
+        if let Some(output) = contract.decode_output(message, &result.data)? {
+            println!("Result: {}", output);
+        }
+        Ok(())
+    }
+
+    /// Dry-run `target` and write out what it costs next to the static
+    /// estimate of the function it runs
+    fn estimate(
+        &self,
+        target: Target,
+        args: &[String],
+        costs: &CostTable,
+        json: bool,
+    ) -> Result<(), DeployError> {
+        let source = self.source()?;
+        let contract = self.compile()?;
+        let (function, data) = match target {
+            Target::Deploy {
+                constructor: Some(constructor),
+            } => (Some(constructor), contract.call_data(constructor, args)?),
+            Target::Deploy { constructor: None } => (
+                contract
+                    .abi
+                    .constructor
+                    .as_ref()
+                    .map(|method| method.name.as_str()),
+                contract.deploy_data(args)?,
+            ),
+            Target::Call { message, .. } => (Some(message), contract.call_data(message, args)?),
+        };
+        let estimate = function
+            .map(|function| StaticEstimate::of(&source, function, costs))
+            .transpose()?;
+        let (node, signer) = self.node.connect()?;
+        let dry_run = match target {
+            Target::Deploy { .. } => {
+                let salt = rand::random();
+                node.dry_run_instantiate(&signer, &contract.binary, &data, self.value, &salt)?
+            }
+            Target::Call { address, .. } => {
+                node.dry_run_call(&signer, address, &data, self.value)?
+            }
+        };
+        let estimate = Estimate {
+            dry_run,
+            estimate,
+            ref_time_per_gas: costs.ref_time_per_gas,
+        };
+
+        if json {
+            println!("{:#}", estimate.to_json());
+            return Ok(());
+        }
+        let action = match target {
+            Target::Deploy { .. } => format!("deploying {}", self.file.display()),
+            Target::Call { address, message } => format!("calling {} on {}", message, address),
+        };
+        println!("Dry run of {} on {}", action, self.node.url);
+        let figure = |value: Option<u64>| value.map_or("unknown".to_string(), |v| v.to_string());
+        println!("  ref_time         {}", figure(estimate.dry_run.ref_time()));
+        println!(
+            "  proof_size       {}",
+            figure(estimate.dry_run.proof_size())
+        );
+        println!("  storage deposit  {}", estimate.dry_run.storage_deposit);
+        if estimate.dry_run.reverted {
+            println!("  reverted with 0x{}", hex::encode(&estimate.dry_run.data));
+        }
+        match &estimate.estimate {
+            Some(estimated) => {
+                println!("Static estimate of `{}`", estimated.function);
+                println!(
+                    "  gas              {} (ref_time {} at {} per gas)",
+                    estimated.gas,
+                    estimate.estimated_ref_time().unwrap_or_default(),
+                    costs.ref_time_per_gas
+                );
+                println!("  proof_size       {}", estimated.proof_size);
+                println!("  storage deposit  {} (max)", estimated.storage_deposit);
+            }
+            None => println!("No function runs, so there is no static estimate to compare"),
+        }
+        for underestimate in estimate.underestimates() {
+            let shortfall = underestimate
+                .shortfall_percent()
+                .map_or(String::new(), |percent| format!(" ({}% more)", percent));
+            println!(
+                "warning: the node requires {} {}, the static estimate is {}{}",
+                underestimate.required, underestimate.resource, underestimate.estimated, shortfall
+            );
+        }
+        Ok(())
+    }
+
+    fn source(&self) -> Result<String, DeployError> {
+        std::fs::read_to_string(&self.file).map_err(|e| {
+            DeployError::Compile(format!("Failed to read {}: {}", self.file.display(), e))
+        })
+    }
+
+    /// Compile the contract, writing out the diagnostic of a source that
+    /// does not compile
+    fn compile(&self) -> Result<Contract, DeployError> {
+        let source = self.source()?;
+        let name = self
+            .file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        report(
+            &self.file,
+            Contract::compile(&name, &source),
+            MessageFormat::Human,
+        )
+        .map_err(|e| DeployError::Compile(e.to_string()))
+    }
+}
+
+/// What `estimate` dry-runs
+#[derive(Clone, Copy)]
+enum Target<'a> {
+    Deploy { constructor: Option<&'a str> },
+    Call { address: &'a str, message: &'a str },
+}
+
+/// What `verify` checks the rebuilt contract against
+enum VerifyAgainst {
+    Deployed { address: String, url: String },
+    CodeHash(String, ContractsPallet),
+    Binary(PathBuf, ContractsPallet),
+}
+
+/// Rebuild the contract in `file`, writing its blob to `output`, and
+/// check its code hash against `against`, exiting with status 1 when they
+/// differ
+fn verify(
+    file: &Path,
+    against: VerifyAgainst,
+    output: Option<&Path>,
+    compiler_version: Option<&str>,
+    json: bool,
+) -> Result<(), DeployError> {
+    let mut settings = BuildSettings::pinned(compiler_version)?;
+    let source = std::fs::read_to_string(file)
+        .map_err(|e| DeployError::Compile(format!("Failed to read {}: {}", file.display(), e)))?;
+    let name = file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // Modules of a package are rebuilt the way `build --release` built them
+    let contract = match Workspace::find_root(file) {
+        Some(root) => match package_contract(&root, file) {
+            Ok((contract, profile)) => {
+                settings = settings.with_profile(&profile);
+                contract
+            }
+            Err(BuildError::Diagnostics(diagnostics)) => {
+                emit_all(&diagnostics, MessageFormat::Human)
+                    .map_err(|e| DeployError::Compile(e.to_string()))?;
+                return Err(DeployError::Compile(format!(
+                    "could not build {}",
+                    file.display()
+                )));
+            }
+            Err(error) => return Err(DeployError::Compile(error.to_string())),
+        },
+        None => report(
+            file,
+            Contract::compile(&name, &source),
+            MessageFormat::Human,
+        )
+        .map_err(|e| DeployError::Compile(e.to_string()))?,
+    };
+    if let Some(output) = output {
+        std::fs::write(output, &contract.binary).map_err(|e| {
+            DeployError::Compile(format!("Failed to write {}: {}", output.display(), e))
+        })?;
+    }
+    let (pallet, reference) = match against {
+        VerifyAgainst::Deployed { address, url } => {
+            let node = Node::connect(&url)?;
+            let code_hash = node.code_hash_of(&address)?;
+            (node.pallet(), Reference::Deployed { address, code_hash })
+        }
+        VerifyAgainst::CodeHash(code_hash, pallet) => {
+            (pallet, Reference::CodeHash(parse_code_hash(&code_hash)?))
+        }
+        VerifyAgainst::Binary(path, pallet) => {
+            let binary = std::fs::read(&path).map_err(|e| {
+                DeployError::Argument(format!("Failed to read {}: {}", path.display(), e))
+            })?;
+            let code_hash = pallet.code_hash(&binary);
+            let path = path.display().to_string();
+            (pallet, Reference::Binary { path, code_hash })
+        }
+    };
+    let verification = Rebuild::new(contract, &source, settings, pallet).verify(reference);
+
+    if json {
+        println!("{:#}", verification.to_json());
+    } else {
+        let rebuild = &verification.rebuild;
+        println!(
+            "Rebuilt {} with bend-pvm {} ({} bytes)",
+            file.display(),
+            rebuild.settings.compiler_version,
+            rebuild.contract.binary.len()
+        );
+        println!("  source hash  0x{}", hex::encode(rebuild.source_hash));
+        println!(
+            "  code hash    0x{} ({})",
+            hex::encode(rebuild.code_hash),
+            rebuild.pallet.name()
+        );
+        let reference = match &verification.reference {
+            Reference::Deployed { address, .. } => format!("the contract at {}", address),
+            Reference::CodeHash(_) => "the given code hash".to_string(),
+            Reference::Binary { path, .. } => path.clone(),
+        };
+        println!(
+            "  expected     0x{} ({})",
+            hex::encode(verification.reference.code_hash()),
+            reference
+        );
+        match verification.verified() {
+            true => println!("Verified: the source builds to the code of {}", reference),
+            false => println!(
+                "Not verified: the source does not build to the code of {}",
+                reference
+            ),
+        }
+    }
+    if !verification.verified() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Explore the functions of the contract in `file` symbolically, exiting
+/// with status 1 when an input reaches a trap
+fn verify_symbolically(file: &Path, loop_bound: u32, json: bool) -> Result<(), String> {
+    let symbolic = SymbolicOptions {
+        loop_bound,
+        ..SymbolicOptions::default()
+    };
+    let reports = bend_pvm::verify_symbolic(file, &CompilerOptions::default(), &symbolic)
+        .map_err(|e| e.to_string())?;
+
+    if json {
+        let json = serde_json::to_string_pretty(&reports).map_err(|e| e.to_string())?;
+        println!("{}", json);
+    } else {
+        for report in &reports {
+            let status = if !report.counterexamples.is_empty() {
+                "FAILED"
+            } else if report.is_verified() {
+                "verified"
+            } else {
+                "unknown"
+            };
+            println!(
+                "{}: {} ({} paths, {} checks proved, {} undecided{})",
+                report.function,
+                status,
+                report.paths,
+                report.proved,
+                report.undecided,
+                if report.truncated { ", truncated" } else { "" }
+            );
+            for counterexample in &report.counterexamples {
+                println!("  {}:{}", file.display(), counterexample);
+            }
+        }
+    }
+    if reports
+        .iter()
+        .any(|report| !report.counterexamples.is_empty())
+    {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Write out `result`'s error and exit with status 1 if it failed
+fn exit_on_error(result: Result<(), DeployError>) {
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
+}
+
+fn parse_salt(salt: &str) -> Result<[u8; 32], DeployError> {
+    let bytes = salt
+        .strip_prefix("0x")
+        .and_then(|digits| hex::decode(digits).ok())
+        .ok_or_else(|| DeployError::Argument("--salt must be 0x-prefixed hex".into()))?;
+    let len = bytes.len();
+    bytes
+        .try_into()
+        .map_err(|_| DeployError::Argument(format!("--salt must be 32 bytes, found {}", len)))
+}
+
+fn print_dry_run(result: &DryRun) {
+    println!(
+        "Dry run: gas required {}, storage deposit {}",
+        result.gas_required, result.storage_deposit
+    );
+}
+
+fn print_inclusion(state: &DeploymentState) {
+    println!(
+        "  transaction {} in block #{}",
+        state.transaction_hash.as_deref().unwrap_or_default(),
+        state.block_number.unwrap_or_default()
+    );
+}
+
+/// How often `build --watch` looks for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// What `build` does each time it runs
+struct BuildSession {
+    root: PathBuf,
+    profile: &'static str,
+    deterministic: bool,
+    remap_path_prefix: Vec<(PathBuf, PathBuf)>,
+    check: bool,
+    test: bool,
+    format: MessageFormat,
+    lint_levels: LintLevels,
+    /// Resolved again on each build, as bend.toml may have changed
+    features: FeatureArgs,
+    /// What checking the modules found, kept from one run to the next
+    database: RefCell<Option<Database>>,
+}
+
+impl BuildSession {
+    /// Check or build the package, then run its tests if asked to, writing
+    /// out what happened. With `changed`, only the entry modules importing
+    /// the changed files are compiled. Returns whether everything succeeded.
+    fn run(&self, changed: Option<&[PathBuf]>) -> bool {
+        let start = Instant::now();
+        let succeeded = self.try_run(changed, start).unwrap_or_else(|error| {
+            match self.format {
+                MessageFormat::Human => eprintln!("error: {}", error),
+                MessageFormat::Json => emit_error(&*error, Path::new("")),
+            }
+            false
+        });
+        if self.format == MessageFormat::Json {
+            emit_finished(succeeded);
+        }
+        succeeded
+    }
+
+    fn try_run(
+        &self,
+        changed: Option<&[PathBuf]>,
+        start: Instant,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let workspace = Workspace::load(&self.root)?;
+        let name = workspace.root().name();
+        let profile = workspace
+            .root()
+            .manifest()
+            .profile(self.profile)
+            .ok_or("unknown profile")?
+            .with_deterministic(self.deterministic)
+            .with_remap_path_prefix(self.remap_path_prefix.clone());
+        let cfg = Cfg::new().with_features(
+            workspace
+                .root()
+                .manifest()
+                .resolve_features(&self.features.features, !self.features.no_default_features)?,
+        );
+
+        // Everything is checked again when the manifest or the features
+        // changed, as they decide how modules are found and configured
+        let mut kept = self.database.borrow_mut();
+        let database = match kept.as_mut() {
+            Some(kept) if changed.is_some() && kept.module_system().cfg() == &cfg => kept,
+            _ => kept.insert(database(&workspace, &cfg)),
+        };
+        let result = match changed {
+            _ if self.check => {
+                for path in changed.into_iter().flatten() {
+                    database.reload(path);
+                }
+                check_with(&workspace, database, &self.lint_levels).map(|warnings| BuildOutput {
+                    warnings,
+                    ..BuildOutput::default()
+                })
+            }
+            Some(changed) => rebuild(&workspace, database, &profile, &self.lint_levels, changed),
+            None => build_with(&workspace, database, &profile, &self.lint_levels),
+        };
+        let output = match result {
+            Ok(output) => output,
+            Err(BuildError::Diagnostics(diagnostics)) => {
+                emit_all(&diagnostics, self.format)?;
+                eprintln!("error: could not build {}", name);
+                return Ok(false);
+            }
+            Err(error) => return Err(error.into()),
+        };
+        emit_all(&output.warnings, self.format)?;
+
+        if self.format == MessageFormat::Json {
+            for artifact in &output.artifacts {
+                emit_message(
+                    "artifact",
+                    serde_json::json!({
+                        "module": artifact.module,
+                        "binary": artifact.binary,
+                        "listing": artifact.listing,
+                        "abi": artifact.abi,
+                        "metadata": artifact.metadata,
+                        "size": artifact.size,
+                        "sha256": artifact.sha256,
+                        "profile": profile.name(),
+                    }),
+                );
+            }
+            emit_timing(if self.check { "check" } else { "build" }, start.elapsed());
+            for artifact in &output.artifacts {
+                emit_gas_report(&artifact.module);
+            }
+        } else {
+            self.print_summary(name, &profile, &output, start);
+        }
+
+        Ok(!self.test || run_package_tests(&workspace, &cfg, self.format)?)
+    }
+
+    /// Write out the artifacts of a build and how long it took, for people
+    fn print_summary(&self, name: &str, profile: &Profile, output: &BuildOutput, start: Instant) {
+        for artifact in &output.artifacts {
+            println!(
+                "Built {} -> {}",
+                artifact.module.display(),
+                artifact.binary.display()
+            );
+        }
+        let seconds = start.elapsed().as_secs_f64();
+        if self.check {
+            println!("Checked {} in {:.2}s.", name, seconds);
+        } else {
+            println!(
+                "Finished {} profile [opt-level {}{}] for {} in {:.2}s{}.",
+                profile.name(),
+                profile.opt_level(),
+                if profile.debug() { ", debug" } else { "" },
+                name,
+                seconds,
+                match output.fresh.len() {
+                    0 => String::new(),
+                    fresh => format!(", {} module(s) up to date", fresh),
+                }
+            );
+        }
+    }
+
+    /// Run again whenever the sources of the workspace change, until
+    /// interrupted
+    fn watch(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut watcher = Watcher::new(&Workspace::load(&self.root)?)?;
+        loop {
+            println!(
+                "Watching {} file(s) for changes...",
+                watcher.files().count()
+            );
+            let changed = watcher.wait(POLL_INTERVAL)?;
+            let names: Vec<String> = changed
+                .iter()
+                .map(|path| {
+                    let path = path.strip_prefix(&self.root).unwrap_or(path);
+                    path.display().to_string()
+                })
+                .collect();
+            println!("\nChanged {}", names.join(", "));
+
+            // A new manifest may add or remove dependencies and change the
+            // profile, so everything is built again
+            if let Err(error) = prepare_dependencies(&self.root, true, self.format) {
+                eprintln!("error: {}", error);
+                continue;
+            }
+            let manifest = changed.iter().any(|path| path.ends_with(MANIFEST_FILE));
+            self.run((!manifest).then_some(&changed));
+            if manifest {
+                watcher = Watcher::new(&Workspace::load(&self.root)?)?;
+            }
+        }
+    }
+}
+
+/// Run the tests of every module of the package, printing the failures and
+/// a summary. Returns whether all of them passed.
+fn run_package_tests(
+    workspace: &Workspace,
+    cfg: &Cfg,
+    format: MessageFormat,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    use bend_pvm::testing::TestResult;
+
+    let output = bend_pvm::package::test(workspace, cfg)?;
+    emit_all(&output.diagnostics, format)?;
+    let (mut passed, mut failed) = (0, output.diagnostics.len());
+    for test in &output.tests {
+        match &test.result {
+            TestResult::Passed { .. } => passed += 1,
+            TestResult::Failed { error, .. } => {
+                failed += 1;
+                let module = test.path.file_stem().unwrap_or_default().to_string_lossy();
+                match format {
+                    MessageFormat::Human => {
+                        println!("test {}::{} ... FAILED: {}", module, test.name, error)
+                    }
+                    MessageFormat::Json => emit_message(
+                        "test-failed",
+                        serde_json::json!({ "module": test.path, "test": test.name, "error": error.to_string() }),
+                    ),
+                }
+            }
+            TestResult::Skipped { .. } => {}
+        }
+    }
+    match format {
+        MessageFormat::Human => println!("Tests: {} passed, {} failed.", passed, failed),
+        MessageFormat::Json => emit_message(
+            "tests",
+            serde_json::json!({ "passed": passed, "failed": failed }),
         ),
-    )?;
+    }
+    Ok(output.passed())
+}
 
-    // Create README.md
-    let readme_file = project_dir.join("README.md");
-    std::fs::write(
-        &readme_file,
-        format!(
-            r#"
-# {name}
+/// Assemble `instructions` into a blob and run it on the embedded PolkaVM engine
+#[cfg(feature = "polkavm-engine")]
+fn run_on_polkavm(
+    instructions: &[bend_pvm::compiler::codegen::risc_v::Instruction],
+) -> Result<(), Box<dyn std::error::Error>> {
+    use bend_pvm::compiler::polkavm::bridge::compile_to_polkavm;
+    use bend_pvm::runtime::env::ExecutionResult;
+    use bend_pvm::runtime::polkavm::PolkaVmEngine;
+
+    let mut module = compile_to_polkavm(instructions, None)
+        .map_err(|e| format!("Failed to assemble blob: {}", e))?;
+    let blob = module
+        .compile()
+        .map_err(|e| format!("Failed to assemble blob: {}", e))?;
+    println!("Running {} byte blob on PolkaVM...", blob.len());
+
+    let context = bend_pvm::runtime::env::ExecutionContext::new_default();
+    let mut engine = PolkaVmEngine::new(context);
+    match engine.execute(blob)? {
+        ExecutionResult::Success { data, gas_used, .. } => {
+            println!("Execution completed: {:02x?} ({} gas)", data, gas_used);
+        }
+        ExecutionResult::Revert { data, gas_used, .. } => {
+            return Err(format!("Execution reverted: {:02x?} ({} gas)", data, gas_used).into());
+        }
+        other => return Err(format!("Execution failed: {:?}", other).into()),
+    }
+    Ok(())
+}
 
-A smart contract written in Bend-PVM.
+/// Write out how long a phase of the compilation took, as a JSON message
+fn emit_timing(phase: &str, duration: Duration) {
+    emit_message(
+        "timing",
+        serde_json::json!({ "phase": phase, "seconds": duration.as_secs_f64() }),
+    );
+}
 
-## Building
+/// Write out the static gas estimates of the functions of `module`, as a
+/// JSON message. Modules the profiler cannot read are left out.
+fn emit_gas_report(module: &Path) {
+    use bend_pvm::analyzer::gas_profiler::GasProfiler;
 
-```
-bend-pvm compile src/main.bend
-```
+    let costs = CostTable::discover(module).unwrap_or_default();
+    if let Ok(profile) = GasProfiler::with_costs(costs).profile_file(module) {
+        emit_message("gas-report", profile.to_json());
+    }
+}
 
-## Testing
+/// The cost table at `path`, or the one of the package `source` is in
+fn cost_table(path: Option<&Path>, source: &Path) -> Result<CostTable, String> {
+    match path {
+        Some(path) => CostTable::load(path),
+        None => CostTable::discover(source),
+    }
+}
 
-```
-bend-pvm check src/main.bend
-```
-"#
+/// Write out an error that stopped a command as a JSON diagnostic, about
+/// `file` unless the error names another
+fn emit_error(error: &(dyn std::error::Error + 'static), file: &Path) {
+    let (file, diagnostic) = match error.downcast_ref::<BuildError>() {
+        Some(BuildError::Module { path, message }) => (
+            path.as_path(),
+            Diagnostic::error(codes::MODULE, message.clone()),
         ),
-    )?;
-
-    // Create .gitignore
-    let gitignore_file = project_dir.join(".gitignore");
-    std::fs::write(
-        &gitignore_file,
-        r#"
-# Build artifacts
-*.bin
-*.s
-*.metadata.json
-*.abi.json
-
-# Editor files
-.vscode/
-.idea/
-*.swp
-*.swo
-
-# OS files
-.DS_Store
-Thumbs.db
-"#,
-    )?;
+        _ => (file, Diagnostic::error(codes::COMMAND, error.to_string())),
+    };
+    let source = std::fs::read_to_string(file).unwrap_or_default();
+    let diagnostic = error
+        .downcast_ref::<CompileError>()
+        .and_then(|error| Diagnostic::from_compile_error(error, &source))
+        .unwrap_or(diagnostic);
+    emit(
+        &diagnostic,
+        &file.display().to_string(),
+        &source,
+        MessageFormat::Json,
+    );
+}
+
+/// Write out the last JSON message of a command
+fn emit_finished(success: bool) {
+    emit_message("finished", serde_json::json!({ "success": success }));
+}
 
+/// Write out diagnostics about the modules of a package
+fn emit_all(
+    diagnostics: &[ModuleDiagnostic],
+    format: MessageFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for ModuleDiagnostic { path, diagnostic } in diagnostics {
+        let source = std::fs::read_to_string(path)?;
+        emit(diagnostic, &path.display().to_string(), &source, format);
+    }
+    Ok(())
+}
+
+/// Resolve the dependencies of the package containing `file`, if it is in
+/// one, writing its lock file and compiling the dependencies in order,
+/// naming them in the human format. Without `incremental` the module cache
+/// is cleared first.
+fn prepare_dependencies(
+    file: &Path,
+    incremental: bool,
+    format: MessageFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(root) = Workspace::find_root(file) else {
+        return Ok(());
+    };
+
+    let workspace = Workspace::load(&root)?;
+    workspace.write_lock()?;
+    if !incremental {
+        workspace.cache().clear()?;
+    }
+    for name in workspace.compile_dependencies()? {
+        if format == MessageFormat::Human {
+            println!("Compiled dependency {}.", name);
+        }
+    }
     Ok(())
 }
@@ -238,13 +238,10 @@ impl SolidityAnalyzer {
         self.check_feature("modifiers", &modifier.location);
 
         self.issues.push(MigrationIssue {
-            description: format!(
-                "Modifier '{}' needs conversion to predicate function",
-                modifier.name
-            ),
+            description: format!("Modifier '{}' is converted to a guard", modifier.name),
             source_location: format!("{}:{}", modifier.location.line, modifier.location.column),
             severity: IssueSeverity::Partial,
-            suggestion: Some("Convert modifier to pre/post-condition check functions".to_string()),
+            suggestion: Some("Review the statements of the converted guard body".to_string()),
         });
     }
 
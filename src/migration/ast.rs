@@ -1,7 +1,9 @@
 //! # Solidity AST Representation
 //!
 //! This module provides AST structures for representing Solidity contracts,
-//! enabling parsing and analysis during migration.
+//! enabling parsing and analysis during migration. [`super::parser`] builds
+//! them from source, and the Vyper and ink! frontends build them from their
+//! languages for the converter to share.
 
 /// Represents a Solidity source location
 #[derive(Debug, Clone, PartialEq)]
@@ -30,7 +32,37 @@ pub struct SoliditySource {
     pub libraries: Vec<LibraryDefinition>,
     pub enums: Vec<EnumDefinition>,
     pub structs: Vec<StructDefinition>,
-    pub location: SolLocation,
+    /// Free functions, declared outside of any contract
+    pub functions: Vec<FunctionDefinition>,
+    /// File-level constants
+    pub constants: Vec<StateVariable>,
+    pub events: Vec<EventDefinition>,
+    pub errors: Vec<ErrorDefinition>,
+    pub type_definitions: Vec<TypeDefinition>,
+    pub using_directives: Vec<UsingDirective>,
+    pub location: SolLocation,
+}
+
+impl SoliditySource {
+    /// A source declaring nothing yet
+    pub fn new(location: SolLocation) -> Self {
+        SoliditySource {
+            version_pragma: None,
+            imports: Vec::new(),
+            contracts: Vec::new(),
+            interfaces: Vec::new(),
+            libraries: Vec::new(),
+            enums: Vec::new(),
+            structs: Vec::new(),
+            functions: Vec::new(),
+            constants: Vec::new(),
+            events: Vec::new(),
+            errors: Vec::new(),
+            type_definitions: Vec::new(),
+            using_directives: Vec::new(),
+            location,
+        }
+    }
 }
 
 /// Import directive
@@ -73,10 +105,33 @@ pub struct ContractDefinition {
     pub structs: Vec<StructDefinition>,
     pub enums: Vec<EnumDefinition>,
     pub type_definitions: Vec<TypeDefinition>,
+    pub using_directives: Vec<UsingDirective>,
     pub is_abstract: bool,
     pub location: SolLocation,
 }
 
+impl ContractDefinition {
+    /// A contract of `kind` without bases or members yet
+    pub fn new(name: String, kind: ContractKind, location: SolLocation) -> Self {
+        ContractDefinition {
+            name,
+            kind,
+            base_contracts: Vec::new(),
+            state_variables: Vec::new(),
+            functions: Vec::new(),
+            modifiers: Vec::new(),
+            events: Vec::new(),
+            errors: Vec::new(),
+            structs: Vec::new(),
+            enums: Vec::new(),
+            type_definitions: Vec::new(),
+            using_directives: Vec::new(),
+            is_abstract: false,
+            location,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ContractKind {
     Contract,
@@ -84,6 +139,16 @@ pub enum ContractKind {
     Library,
 }
 
+/// `using Library for Type;`, attaching the functions of a library, or a
+/// list of functions, to a type (`None` for `*`)
+#[derive(Debug, Clone)]
+pub struct UsingDirective {
+    pub library: Option<String>,
+    pub functions: Vec<String>,
+    pub type_name: Option<TypeName>,
+    pub location: SolLocation,
+}
+
 #[derive(Debug, Clone)]
 pub struct BaseContract {
     pub name: String,
@@ -187,6 +252,8 @@ pub struct VariableDeclaration {
     pub name: Option<String>,
     pub type_name: TypeName,
     pub storage_location: StorageLocation,
+    /// A parameter of an event its listeners can filter on
+    pub indexed: bool,
     pub location: SolLocation,
 }
 
@@ -281,6 +348,7 @@ pub struct Literal {
 #[derive(Debug, Clone, PartialEq)]
 pub enum SubDenomination {
     Wei,
+    Gwei,
     Ether,
     Seconds,
     Minutes,
@@ -336,6 +404,7 @@ pub enum UnaryOperator {
     BitNot,
     Inc,
     Dec,
+    Delete,
 }
 
 #[derive(Debug, Clone)]
@@ -365,7 +434,10 @@ pub enum AssignmentOperator {
 pub struct FunctionCall {
     pub expression: Box<Expression>,
     pub arguments: Vec<Expression>,
+    /// Names of the arguments when passed by name, `f({to: a})`
     pub names: Vec<String>,
+    /// Call options, `{value: v, gas: g}`
+    pub options: Vec<(String, Expression)>,
     pub location: SolLocation,
 }
 
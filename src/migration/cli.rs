@@ -3,10 +3,10 @@
 //! Command-line interface for the Solidity to Bend-PVM migration tool.
 
 use super::analyzer::SolidityAnalyzer;
-use super::ast::SoliditySource;
-use super::{MigrationConfig, SolidityMigrator};
+use super::parser::parse_solidity;
+use super::{MigrationConfig, MigrationError, SolidityMigrator, SourceLanguage};
 use clap::{Arg, ArgAction, Command};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 
 /// Run the migration CLI
@@ -17,11 +17,11 @@ pub fn run_cli() {
         .about("Migrate Solidity smart contracts to Bend-PVM")
         .subcommand(
             Command::new("convert")
-                .about("Convert Solidity file to Bend-PVM")
+                .about("Convert Solidity, Vyper or ink! files to Bend-PVM")
                 .arg(
                     Arg::new("input")
                         .required(true)
-                        .help("Input Solidity file or directory"),
+                        .help("Input contract file, or directory of Solidity and Vyper files"),
                 )
                 .arg(
                     Arg::new("output")
@@ -56,13 +56,19 @@ pub fn run_cli() {
                 .arg(
                     Arg::new("erc_type")
                         .required(true)
-                        .help("ERC template type (ERC20, ERC721, ERC1155)"),
+                        .help("ERC template type (ERC20, ERC721, ERC1155, ERC4626)"),
                 )
                 .arg(
                     Arg::new("output")
                         .short('o')
                         .long("output")
                         .help("Output file path"),
+                )
+                .arg(
+                    Arg::new("no-tests")
+                        .long("no-tests")
+                        .action(ArgAction::SetTrue)
+                        .help("Don't append a test suite calling each template message"),
                 ),
         )
         .get_matches();
@@ -87,15 +93,16 @@ pub fn run_cli() {
         Some(("template", sub_matches)) => {
             let erc_type = sub_matches.get_one::<String>("erc_type").unwrap();
             let output = sub_matches.get_one::<String>("output").map(String::from);
+            let tests = !sub_matches.get_flag("no-tests");
 
-            template_command(erc_type, output);
+            template_command(erc_type, output, tests);
         }
         _ => {
             println!("Bend-PVM Migration Tool");
             println!("Usage: bend-migrate <command> [options]");
             println!();
             println!("Commands:");
-            println!("  convert      Convert Solidity file to Bend-PVM");
+            println!("  convert      Convert Solidity, Vyper or ink! files to Bend-PVM");
             println!("  analyze      Analyze Solidity file for compatibility");
             println!("  list-erc     List available ERC templates");
             println!("  template     Generate ERC template");
@@ -106,66 +113,124 @@ pub fn run_cli() {
 }
 
 /// Convert command
-fn convert_command(input: &str, output: Option<String>, _recursive: bool) {
-    println!("Converting Solidity files from: {}", input);
-
-    let _migrator = SolidityMigrator::new();
+fn convert_command(input: &str, output: Option<String>, recursive: bool) {
     let mut config = MigrationConfig::default();
-
     if let Some(output_dir) = output {
         config.output_dir = PathBuf::from(output_dir);
     }
 
-    // In a real implementation, we would:
-    // 1. Find all .sol files
-    // 2. Parse each file
-    // 3. Convert to Bend-PVM
-    // 4. Write output files
+    let files = source_files(Path::new(input), recursive);
+    if files.is_empty() {
+        eprintln!("Error: no Solidity or Vyper files found in {}", input);
+        process::exit(1);
+    }
+    if let Err(e) = std::fs::create_dir_all(&config.output_dir) {
+        eprintln!(
+            "Error: failed to create {}: {}",
+            config.output_dir.display(),
+            e
+        );
+        process::exit(1);
+    }
 
-    println!("Configuration:");
-    println!("  Output directory: {}", config.output_dir.display());
-    println!("  Generate tests: {}", config.generate_tests);
-    println!("  Generate docs: {}", config.generate_docs);
-    println!();
+    let mut migrator = SolidityMigrator::with_config(config.clone());
+    let mut failed = false;
+    for file in files {
+        let converted = std::fs::read_to_string(&file)
+            .map_err(MigrationError::from)
+            .and_then(|source| {
+                let language = SourceLanguage::from_path(&file).unwrap_or(SourceLanguage::Solidity);
+                migrator.migrate(language, &source, &file.display().to_string())
+            });
+        match converted {
+            Ok(bend) => {
+                let target = config
+                    .output_dir
+                    .join(file.with_extension("bend").file_name().unwrap_or_default());
+                match std::fs::write(&target, bend) {
+                    Ok(()) => println!("{} -> {}", file.display(), target.display()),
+                    Err(e) => {
+                        eprintln!("Error: failed to write {}: {}", target.display(), e);
+                        failed = true;
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                failed = true;
+            }
+        }
+    }
 
-    println!("Migration features:");
-    println!("  ERC20: ✅ Included");
-    println!("  ERC721: ✅ Included");
-    println!("  ERC1155: ✅ Included");
+    let stats = migrator.stats();
+    println!(
+        "Converted {} contract(s), {} function(s), {} issue(s)",
+        stats.contracts_processed,
+        stats.functions_translated,
+        stats.issues.len()
+    );
+    match migrator.write_report() {
+        Ok(written) => {
+            for path in written {
+                println!("Report: {}", path.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("Error: failed to write the migration report: {}", e);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
 
-    println!();
-    println!("Ready to convert. (Full implementation pending file I/O)");
+/// The contract files at `input`: the file itself, or the Solidity and
+/// Vyper files of a directory
+fn source_files(input: &Path, recursive: bool) -> Vec<PathBuf> {
+    if !input.is_dir() {
+        return vec![input.to_path_buf()];
+    }
+    let mut files = Vec::new();
+    let Ok(entries) = std::fs::read_dir(input) else {
+        return files;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            if recursive {
+                files.extend(source_files(&path, recursive));
+            }
+        } else if matches!(
+            SourceLanguage::from_path(&path),
+            Some(SourceLanguage::Solidity | SourceLanguage::Vyper)
+        ) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
 }
 
 /// Analyze command
 fn analyze_command(input: &str, json: bool) {
-    println!("Analyzing Solidity file: {}", input);
-
-    let mut analyzer = SolidityAnalyzer::new();
-
-    // Create a dummy source for demonstration
-    let dummy_source = SoliditySource {
-        version_pragma: None,
-        imports: Vec::new(),
-        contracts: Vec::new(),
-        interfaces: Vec::new(),
-        libraries: Vec::new(),
-        enums: Vec::new(),
-        structs: Vec::new(),
-        location: super::ast::SolLocation {
-            file: input.to_string(),
-            line: 1,
-            column: 1,
-            start: 0,
-            end: 0,
-        },
+    let source = match std::fs::read_to_string(input)
+        .map_err(MigrationError::from)
+        .and_then(|source| parse_solidity(&source, input))
+    {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
     };
 
-    let result = analyzer.analyze(&dummy_source);
+    let mut analyzer = SolidityAnalyzer::new();
+    let result = analyzer.analyze(&source);
 
     if json {
         println!("{}", serde_json::to_string_pretty(&result).unwrap());
     } else {
+        println!("Analyzing Solidity file: {}", input);
         println!();
         println!("Analysis Results:");
         println!("  Compatibility Score: {:.1}%", result.compatibility_score);
@@ -205,178 +270,21 @@ fn list_erc_command() {
 }
 
 /// Template command
-fn template_command(erc_type: &str, output: Option<String>) {
-    let mut migrator = SolidityMigrator::new();
-
-    // Add ERC-1155 template if requested
-    if erc_type == "ERC1155" {
-        migrator.erc_templates.insert("ERC1155".to_string(), r#"
-/// ERC-1155 Multi-Token Implementation for Bend-PVM
-contract ERC1155 is BendContract {
-    /// Token URI
-    let uri: String
-
-    /// Mapping from token ID to balance
-    let balances: Map<u256, Map<Address, u256>>
-
-    /// Mapping from token ID to operator approvals
-    let operator_approvals: Map<u256, Map<Address, bool>>
-
-    /// Mapping from owner to operator approvals
-    let default_operator_approvals: Map<Address, Map<Address, bool>>
-
-    /// Event: Transfer Single
-    event TransferSingle(operator: Address, from: Address, to: Address, id: u256, value: u256)
-
-    /// Event: Transfer Batch
-    event TransferBatch(operator: Address, from: Address, to: Address, ids: Vec<u256>, values: Vec<u256>)
-
-    /// Event: Approval for All
-    event ApprovalForAll(account: Address, operator: Address, approved: bool)
-
-    /// Event: URI(value: String, id: u256)
-    event URI(value: String, id: u256)
-
-    /// Constructor
-    fn init(uri: String) {
-        self.uri = uri
-        self.balances = Map::new()
-        self.operator_approvals = Map::new()
-        self.default_operator_approvals = Map::new()
-    }
-
-    /// Get balance of account for token
-    fn balance_of(account: Address, id: u256) -> u256 {
-        if !self.balances.contains_key(id) {
-            return 0
-        }
-        let token_balances = self.balances[id]
-        if !token_balances.contains_key(account) {
-            return 0
-        }
-        token_balances[account]
-    }
-
-    /// Get balance of multiple accounts for multiple tokens
-    fn balance_of_batch(accounts: Vec<Address>, ids: Vec<u256>) -> Vec<u256> {
-        assert(accounts.len() == ids.len(), "Accounts and IDs length mismatch")
-        let results = Vec::new()
-        for i in 0..accounts.len() {
-            let balance = self.balance_of(accounts[i], ids[i])
-            results.push(balance)
-        }
-        results
-    }
-
-    /// Set approval for all
-    fn set_approval_for_all(operator: Address, approved: bool) {
-        self.default_operator_approvals[msg.sender][operator] = approved
-        emit ApprovalForAll(msg.sender, operator, approved)
-    }
-
-    /// Check if operator is approved for all
-    fn is_approved_for_all(account: Address, operator: Address) -> bool {
-        if !self.default_operator_approvals.contains_key(account) {
-            return false
-        }
-        self.default_operator_approvals[account][operator]
-    }
-
-    /// Safe transfer from single token
-    fn safe_transfer_from(from: Address, to: Address, id: u256, amount: u256, data: Vec<u8>) {
-        assert(from == msg.sender || self.is_approved_for_all(from, msg.sender), "Not authorized")
-        assert(to != Address::zero(), "Transfer to zero address")
-
-        let from_balance = self.balance_of(from, id)
-        assert(from_balance >= amount, "Insufficient balance")
-
-        self.balances[id][from] = from_balance - amount
-        self.balances[id][to] = self.balance_of(to, id) + amount
-
-        emit TransferSingle(msg.sender, from, to, id, amount)
-    }
-
-    /// Safe batch transfer from
-    fn safe_batch_transfer_from(from: Address, to: Address, ids: Vec<u256>, amounts: Vec<u256>, data: Vec<u8>) {
-        assert(from == msg.sender || self.is_approved_for_all(from, msg.sender), "Not authorized")
-        assert(to != Address::zero(), "Transfer to zero address")
-        assert(ids.len() == amounts.len(), "IDs and amounts length mismatch")
-
-        for i in 0..ids.len() {
-            let id = ids[i]
-            let amount = amounts[i]
-            let from_balance = self.balance_of(from, id)
-            assert(from_balance >= amount, "Insufficient balance")
-
-            self.balances[id][from] = from_balance - amount
-            self.balances[id][to] = self.balance_of(to, id) + amount
-        }
-
-        emit TransferBatch(msg.sender, from, to, ids, amounts)
-    }
-
-    /// Mint single token
-    fn mint(to: Address, id: u256, amount: u256, data: Vec<u8>) {
-        assert(to != Address::zero(), "Mint to zero address")
-
-        self.balances[id][to] = self.balance_of(to, id) + amount
-        emit TransferSingle(msg.sender, Address::zero(), to, id, amount)
-    }
-
-    /// Batch mint tokens
-    fn mint_batch(to: Address, ids: Vec<u256>, amounts: Vec<u256>, data: Vec<u8>) {
-        assert(to != Address::zero(), "Mint to zero address")
-        assert(ids.len() == amounts.len(), "IDs and amounts length mismatch")
-
-        for i in 0..ids.len() {
-            let id = ids[i]
-            let amount = amounts[i]
-            self.balances[id][to] = self.balance_of(to, id) + amount
-        }
-
-        emit TransferBatch(msg.sender, Address::zero(), to, ids, amounts)
-    }
-
-    /// Burn single token
-    fn burn(from: Address, id: u256, amount: u256) {
-        let from_balance = self.balance_of(from, id)
-        assert(from_balance >= amount, "Insufficient balance")
-
-        self.balances[id][from] = from_balance - amount
-        emit TransferSingle(msg.sender, from, Address::zero(), id, amount)
-    }
-
-    /// Batch burn tokens
-    fn burn_batch(from: Address, ids: Vec<u256>, amounts: Vec<u256>) {
-        assert(ids.len() == amounts.len(), "IDs and amounts length mismatch")
-
-        for i in 0..ids.len() {
-            let id = ids[i]
-            let amount = amounts[i]
-            let from_balance = self.balance_of(from, id)
-            assert(from_balance >= amount, "Insufficient balance")
-
-            self.balances[id][from] = from_balance - amount
-        }
-
-        emit TransferBatch(msg.sender, from, Address::zero(), ids, amounts)
-    }
-
-    /// Get URI for token
-    fn uri(id: u256) -> String {
-        self.uri
-    }
-}
-"#
-        .to_string());
-    }
+fn template_command(erc_type: &str, output: Option<String>, tests: bool) {
+    let migrator = SolidityMigrator::with_config(MigrationConfig {
+        generate_tests: tests,
+        ..MigrationConfig::default()
+    });
 
     match migrator.get_erc_template(erc_type) {
         Some(template) => {
-            let output_content = template.trim();
+            let mut output_content = template.trim().to_string();
+            if let Some(suite) = migrator.generate_template_tests(erc_type) {
+                output_content.push_str(&suite);
+            }
 
             if let Some(output_path) = output {
-                std::fs::write(&output_path, output_content)
+                std::fs::write(&output_path, &output_content)
                     .expect("Failed to write template file");
                 println!("Generated {} template: {}", erc_type, output_path);
             } else {
@@ -4,8 +4,22 @@
 //! to Bend-PVM source code.
 
 use super::ast::*;
-use super::{IssueSeverity, MigrationError, MigrationIssue, SolidityMigrator};
-use std::collections::HashMap;
+use super::inheritance::flatten;
+use super::ink::parse_ink;
+use super::parser::parse_solidity;
+use super::vyper::parse_vyper;
+use super::{
+    IssueSeverity, MigratedFile, MigrationError, MigrationIssue, SolidityMigrator, SourceLanguage,
+};
+use crate::compiler::analyzer::type_checker::ZERO_ADDRESS;
+use crate::compiler::codegen::risc_v::is_storable;
+use crate::compiler::lexer::lexer::is_keyword;
+use crate::compiler::parser::parser::Parser;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+/// Builtin returning the account of the caller, for `msg.sender`
+const CALLER: &str = "caller";
 
 /// Converter from Solidity AST to Bend-PVM source code
 pub struct SolidityToBendConverter {
@@ -19,10 +33,34 @@ pub struct SolidityToBendConverter {
     type_mappings: HashMap<String, String>,
     /// Built-in function mappings
     function_mappings: HashMap<String, String>,
+    /// Bend equivalents of Solidity globals such as `msg.sender`
+    global_mappings: HashMap<String, String>,
     /// Current contract context
     contract_context: Option<String>,
+    /// Whether the current definition is a contract, whose public and
+    /// external functions are its messages
+    in_contract: bool,
+    /// Functions of the libraries of the source, by library
+    libraries: HashMap<String, Vec<String>>,
+    /// Library whose functions are being converted
+    library: Option<String>,
+    /// `using for` directives of the source, outside of contracts
+    file_usings: Vec<UsingDirective>,
+    /// Functions attached to types by `using for` in the current contract,
+    /// with their library (`None` for free functions)
+    attached: HashMap<String, Option<String>>,
+    /// Modifiers of the current contract
+    modifier_names: HashSet<String>,
+    /// Mappings and dynamic arrays of the current contract, which become
+    /// `StorageMap` and `StorageVec` collections
+    collections: HashMap<String, TypeName>,
+    /// Assignments of the initial values of the current contract's state
+    /// variables, which its constructor runs first
+    initializers: Vec<String>,
     /// Issues found during conversion
     issues: Vec<MigrationIssue>,
+    /// The first expression with no Bend equivalent, failing the conversion
+    untranslatable: RefCell<Option<MigrationError>>,
 }
 
 impl SolidityToBendConverter {
@@ -34,8 +72,18 @@ impl SolidityToBendConverter {
             output: String::new(),
             type_mappings: HashMap::new(),
             function_mappings: HashMap::new(),
+            global_mappings: HashMap::new(),
             contract_context: None,
+            in_contract: false,
+            libraries: HashMap::new(),
+            library: None,
+            file_usings: Vec::new(),
+            attached: HashMap::new(),
+            modifier_names: HashSet::new(),
+            collections: HashMap::new(),
+            initializers: Vec::new(),
             issues: Vec::new(),
+            untranslatable: RefCell::new(None),
         };
         converter.initialize_mappings();
         converter
@@ -102,66 +150,58 @@ impl SolidityToBendConverter {
             .insert("ripemd160".to_string(), "crypto.ripemd160".to_string());
         self.function_mappings
             .insert("ecrecover".to_string(), "crypto.ecrecover".to_string());
-        self.function_mappings
-            .insert("block.timestamp".to_string(), "block.timestamp".to_string());
-        self.function_mappings
-            .insert("block.number".to_string(), "block.height".to_string());
-        self.function_mappings
-            .insert("msg.sender".to_string(), "ctx.caller".to_string());
-        self.function_mappings
-            .insert("msg.value".to_string(), "ctx.value".to_string());
-        self.function_mappings
-            .insert("msg.data".to_string(), "ctx.data".to_string());
-        self.function_mappings
-            .insert("tx.gasprice".to_string(), "ctx.gas_price".to_string());
-        self.function_mappings
-            .insert("block.coinbase".to_string(), "block.proposer".to_string());
-        self.function_mappings.insert(
-            "block.difficulty".to_string(),
-            "block.difficulty".to_string(),
-        );
-        self.function_mappings
-            .insert("block.gaslimit".to_string(), "block.gas_limit".to_string());
-        self.function_mappings
-            .insert("block.chainid".to_string(), "block.chain_id".to_string());
         self.function_mappings
             .insert("gasleft".to_string(), "ctx.gas".to_string());
-        self.function_mappings
-            .insert("this".to_string(), "ctx.self".to_string());
+
+        // Globals with a Bend builtin
+        self.global_mappings
+            .insert("msg.sender".to_string(), format!("{}()", CALLER));
     }
 
-    /// Convert a Solidity source to Bend-PVM source
-    pub fn convert(&mut self, source: &SoliditySource) -> String {
+    /// Convert a Solidity source to Bend-PVM source, failing on the first
+    /// expression Bend has no equivalent of
+    pub fn convert(&mut self, source: &SoliditySource) -> Result<String, MigrationError> {
         self.output.clear();
         self.indent = 0;
         self.issues.clear();
+        self.untranslatable.take();
 
         // Add header
-        self.add_line("/// Auto-generated Bend-PVM contract from Solidity");
-        self.add_line("/// Migration from Solidity smart contracts");
-        self.add_line("");
-        self.add_line("contract BendContract {");
-        self.indent += 1;
-        self.add_line("/// Contract context for system calls");
-        self.add_line("let ctx: Context");
-        self.add_line("");
-        self.indent -= 1;
-        self.add_line("}");
-        self.add_line("");
+        self.add_line("# Auto-generated Bend-PVM contract from Solidity");
+        self.add_line("# Migration from Solidity smart contracts");
 
-        // Convert contracts
-        for contract in &source.contracts {
+        self.libraries = source
+            .contracts
+            .iter()
+            .filter(|contract| contract.kind == ContractKind::Library)
+            .map(|library| {
+                let functions = library.functions.iter().map(|f| f.name.clone()).collect();
+                (library.name.clone(), functions)
+            })
+            .collect();
+        self.file_usings = source.using_directives.clone();
+
+        // Convert contracts, with their bases merged in
+        let (contracts, issues) = flatten(source);
+        self.issues.extend(issues);
+        for definition in &source.structs {
+            self.convert_struct(definition);
+        }
+        for contract in &contracts {
             self.convert_contract(contract);
         }
 
-        self.output.clone()
+        match self.untranslatable.take() {
+            Some(error) => Err(error),
+            None => Ok(self.output.clone()),
+        }
     }
 
     /// Convert a contract definition
     fn convert_contract(&mut self, contract: &ContractDefinition) {
         // Add contract comment
         self.add_line("");
-        self.add_line(&format!("/// Contract: {}", contract.name));
+        self.add_line(&format!("# Contract: {}", contract.name));
 
         // Add inheritance info
         if !contract.base_contracts.is_empty() {
@@ -170,8 +210,14 @@ impl SolidityToBendConverter {
                 .iter()
                 .map(|b| b.name.clone())
                 .collect();
-            self.add_line(&format!("/// Inherits from: {}", bases.join(", ")));
+            let merged = match contract.kind {
+                ContractKind::Contract => " (merged in)",
+                _ => "",
+            };
+            self.add_line(&format!("# Inherits from: {}{}", bases.join(", "), merged));
         }
+        self.attach_library_functions(contract);
+        self.modifier_names = contract.modifiers.iter().map(|m| m.name.clone()).collect();
 
         // Contract definition
         let kind_str = match contract.kind {
@@ -180,9 +226,34 @@ impl SolidityToBendConverter {
             ContractKind::Library => "library",
         };
 
+        // Structs become objects, declared ahead of the contract
+        for definition in &contract.structs {
+            self.convert_struct(definition);
+        }
+
+        // Libraries have no state: their functions become top-level
+        // functions named after the library, like `Math/add`
+        if contract.kind == ContractKind::Library {
+            self.library = Some(contract.name.clone());
+            for func in &contract.functions {
+                self.convert_function(func);
+            }
+            self.library = None;
+            self.attached.clear();
+            self.modifier_names.clear();
+            return;
+        }
+        self.collections = contract
+            .state_variables
+            .iter()
+            .filter(|var| var.mutability != Mutability::Constant && is_collection(&var.type_name))
+            .map(|var| (var.name.clone(), var.type_name.clone()))
+            .collect();
+
         self.add_line(&format!("{} {} {{", kind_str, contract.name));
         self.indent += 1;
         self.contract_context = Some(contract.name.clone());
+        self.in_contract = contract.kind == ContractKind::Contract;
 
         // Convert state variables
         for var in &contract.state_variables {
@@ -193,39 +264,169 @@ impl SolidityToBendConverter {
             self.add_line("");
         }
 
-        // Convert events to comments
+        // Convert events
         for event in &contract.events {
             self.convert_event(event);
         }
 
+        // Convert modifiers to guards
+        for modifier in &contract.modifiers {
+            self.convert_modifier(modifier);
+        }
+
         // Convert functions
         for func in &contract.functions {
             self.convert_function(func);
         }
 
+        // Initial values of state variables need a constructor to set them
+        if !self.initializers.is_empty() {
+            self.add_line("");
+            self.add_line("fn constructor() {");
+            self.indent += 1;
+            for line in std::mem::take(&mut self.initializers) {
+                self.add_line(&line);
+            }
+            self.indent -= 1;
+            self.add_line("}");
+        }
+
         self.indent -= 1;
         self.add_line("}");
         self.contract_context = None;
+        self.in_contract = false;
+        self.attached.clear();
+        self.modifier_names.clear();
+        self.collections.clear();
+        self.initializers.clear();
+    }
+
+    /// Attach the functions `using for` directives of the file and of
+    /// `contract` attach, for calls like `x.add(y)` to become `L.add(x, y)`
+    ///
+    /// The type of the receiver is not checked: every call of an attached
+    /// function name as a member is rewritten.
+    fn attach_library_functions(&mut self, contract: &ContractDefinition) {
+        let usings: Vec<UsingDirective> = self
+            .file_usings
+            .iter()
+            .chain(&contract.using_directives)
+            .cloned()
+            .collect();
+        for using in usings {
+            for function in &using.functions {
+                self.attached.insert(function.clone(), None);
+            }
+            let Some(library) = &using.library else {
+                continue;
+            };
+            match self.libraries.get(library) {
+                Some(functions) => {
+                    for function in functions {
+                        self.attached.insert(function.clone(), Some(library.clone()));
+                    }
+                }
+                None => self.add_issue(
+                    &format!(
+                        "Library `{}` of `using for` is not defined in this file; calls through it are left as they are",
+                        library
+                    ),
+                    &format!("{}:{}", using.location.line, using.location.column),
+                    IssueSeverity::Manual,
+                    Some(format!("Call the functions of `{}` directly", library)),
+                ),
+            }
+        }
+    }
+
+    /// Convert a struct to an object with the same fields
+    fn convert_struct(&mut self, definition: &StructDefinition) {
+        self.add_line("");
+        self.add_line(&format!("# Struct: {}", definition.name));
+        self.add_line(&format!("object {} {{", definition.name));
+        self.indent += 1;
+        for member in &definition.members {
+            if matches!(member.type_name, TypeName::Mapping(_)) {
+                self.add_issue(
+                    &format!(
+                        "Mapping member `{}` of struct `{}` can't be stored in an object",
+                        member.name.as_deref().unwrap_or("_"),
+                        definition.name
+                    ),
+                    &format!("{}:{}", member.location.line, member.location.column),
+                    IssueSeverity::Manual,
+                    Some(
+                        "Move the mapping to a contract-level StorageMap keyed by the struct's key"
+                            .to_string(),
+                    ),
+                );
+            }
+            let declaration = self.convert_variable_declaration(member);
+            self.add_line(&format!("let {};", declaration));
+        }
+        self.indent -= 1;
+        self.add_line("}");
     }
 
     /// Convert a state variable
     fn convert_state_variable(&mut self, var: &StateVariable) {
-        let bend_type = self.map_type(&var.type_name);
+        let collection = self.collections.contains_key(&var.name);
+        let bend_type = if collection {
+            self.map_storage_type(&var.type_name)
+        } else {
+            self.map_type(&var.type_name)
+        };
 
         // Add documentation
-        self.add_line(&format!("/// State variable: {}", var.name));
+        self.add_line(&format!("# State variable: {}", var.name));
 
         // Visibility comment
         let visibility_str = format!("{:?}", var.visibility).to_lowercase();
-        self.add_line(&format!("/// Visibility: {}", visibility_str));
+        self.add_line(&format!("# Visibility: {}", visibility_str));
 
-        // Variable declaration
-        let mut declaration = format!("let {}: {}", var.name, bend_type);
+        if collection {
+            self.add_line(&format!(
+                "# Entries are stored under keccak256(\"{}\") hashed with their key",
+                var.name
+            ));
+            self.add_issue(
+                &format!(
+                    "`{}` is stored as a `{}`, whose entries don't live at the storage slots Solidity assigns",
+                    var.name, bend_type
+                ),
+                &format!("{}:{}", var.location.line, var.location.column),
+                IssueSeverity::Partial,
+                Some("Copy existing entries over when migrating deployed state".to_string()),
+            );
+        }
 
-        // Add mutability
+        if !storable(&bend_type) {
+            self.add_issue(
+                &format!(
+                    "`{}` is a `{}`, which the RISC-V backend cannot store",
+                    var.name, bend_type
+                ),
+                &format!("{}:{}", var.location.line, var.location.column),
+                IssueSeverity::Unsupported,
+                Some(
+                    "Store integers, Bool, Address or Hash values, keeping each field of a struct in a storage field of its own"
+                        .to_string(),
+                ),
+            );
+        }
+
+        // Constants are stored like other state, set by the constructor
         match var.mutability {
             Mutability::Constant => {
-                declaration = format!("const {}", declaration);
+                self.add_issue(
+                    &format!(
+                        "Constant `{}` is stored as state, set by the constructor",
+                        var.name
+                    ),
+                    &format!("{}:{}", var.location.line, var.location.column),
+                    IssueSeverity::Partial,
+                    Some("Inline the value where the constant is used".to_string()),
+                );
             }
             Mutability::Immutable => {
                 self.add_issue(
@@ -238,32 +439,68 @@ impl SolidityToBendConverter {
             Mutability::Mutable => {}
         }
 
-        // Add initial value if present
+        // The constructor sets the initial value, if there is one
         if let Some(value) = &var.value {
             let bend_value = self.convert_expression(value);
-            declaration = format!("{} = {}", declaration, bend_value);
+            self.initializers
+                .push(format!("{} = {};", identifier(&var.name), bend_value));
         }
 
-        self.add_line(&format!("{};", declaration));
+        // Public state variables get the getters Solidity generates
+        let public = match var.visibility {
+            Visibility::Public => "pub ",
+            _ => "",
+        };
+        self.add_line(&format!(
+            "{}let {}: {};",
+            public,
+            identifier(&var.name),
+            bend_type
+        ));
     }
 
-    /// Convert an event
+    /// Convert an event, keeping its indexed parameters, and naming unnamed
+    /// ones after their position
     fn convert_event(&mut self, event: &EventDefinition) {
-        self.add_line(&format!("/// Event: {}", event.name));
-        let params: Vec<String> = event
+        let fields: Vec<String> = event
             .parameters
             .iter()
-            .map(|p| {
+            .enumerate()
+            .map(|(i, p)| {
                 let param_type = self.map_type(&p.type_name);
-                format!(
-                    "{}: {}",
-                    p.name.as_ref().unwrap_or(&String::new()),
-                    param_type
-                )
+                let name = match &p.name {
+                    Some(name) => identifier(name),
+                    None => format!("arg{}", i),
+                };
+                match p.indexed {
+                    true => format!("indexed {}: {}", name, param_type),
+                    false => format!("{}: {}", name, param_type),
+                }
             })
             .collect();
-        self.add_line(&format!("/// Parameters: {}", params.join(", ")));
-        self.add_line(&format!("// emit {}({});", event.name, params.join(", ")));
+        self.add_line(&format!("event {} {{ {} }}", event.name, fields.join(", ")));
+    }
+
+    /// Convert a modifier to a guard, whose `_;` placeholder matches the
+    /// one of the modifier
+    fn convert_modifier(&mut self, modifier: &ModifierDefinition) {
+        self.add_line("");
+        self.add_line(&format!("# Modifier: {}", modifier.name));
+
+        let params: Vec<String> = modifier
+            .parameters
+            .iter()
+            .map(|p| self.convert_variable_declaration(p))
+            .collect();
+        self.add_line(&format!(
+            "guard {}({}) {{",
+            identifier(&modifier.name),
+            params.join(", ")
+        ));
+        self.indent += 1;
+        self.convert_block(&modifier.body);
+        self.indent -= 1;
+        self.add_line("}");
     }
 
     /// Convert a function definition
@@ -281,15 +518,15 @@ impl SolidityToBendConverter {
 
         // Add documentation
         self.add_line("");
-        self.add_line(&format!("/// Function: {}", func.name));
+        self.add_line(&format!("# Function: {}", func.name));
 
         // Visibility comment
         let visibility_str = format!("{:?}", func.visibility).to_lowercase();
-        self.add_line(&format!("/// Visibility: {}", visibility_str));
+        self.add_line(&format!("# Visibility: {}", visibility_str));
 
         // State mutability
         let mutability_str = format!("{:?}", func.state_mutability).to_lowercase();
-        self.add_line(&format!("/// Mutability: {}", mutability_str));
+        self.add_line(&format!("# Mutability: {}", mutability_str));
 
         // Function signature
         let params: Vec<String> = func
@@ -298,7 +535,20 @@ impl SolidityToBendConverter {
             .map(|p| self.convert_variable_declaration(p))
             .collect();
 
-        let mut signature = format!("fn {}({})", func.name, params.join(", "));
+        let name = match &self.library {
+            Some(library) => format!("{}/{}", library, identifier(&func.name)),
+            None => identifier(&func.name),
+        };
+        // Public and external functions of a contract are its messages
+        let public = match func.visibility {
+            Visibility::Public | Visibility::External
+                if self.in_contract && !func.is_constructor =>
+            {
+                "pub "
+            }
+            _ => "",
+        };
+        let mut signature = format!("{}fn {}({})", public, name, params.join(", "));
 
         // Return type
         if !func.return_parameters.is_empty() {
@@ -314,21 +564,54 @@ impl SolidityToBendConverter {
             }
         }
 
-        // Function modifiers
-        if !func.modifiers.is_empty() {
-            let modifier_names: Vec<String> =
-                func.modifiers.iter().map(|m| m.name.clone()).collect();
-            self.add_line(&format!("/// Modifiers: {}", modifier_names.join(", ")));
+        // Function modifiers become guards, applied in the same order
+        let (modifiers, unknown): (Vec<_>, Vec<_>) = func
+            .modifiers
+            .iter()
+            .partition(|m| self.modifier_names.contains(&m.name));
+        for modifier in unknown {
+            self.add_issue(
+                &format!(
+                    "Modifier `{}` of `{}` is not defined in this file and is dropped",
+                    modifier.name, func.name
+                ),
+                &format!("{}:{}", modifier.location.line, modifier.location.column),
+                IssueSeverity::Manual,
+                Some("Add the contract defining the modifier to the file".to_string()),
+            );
+        }
+        if !modifiers.is_empty() {
+            let guards: Vec<String> = modifiers
+                .iter()
+                .map(|m| {
+                    if m.arguments.is_empty() {
+                        identifier(&m.name)
+                    } else {
+                        let args: Vec<String> = m
+                            .arguments
+                            .iter()
+                            .map(|arg| self.convert_expression(arg))
+                            .collect();
+                        format!("{}({})", identifier(&m.name), args.join(", "))
+                    }
+                })
+                .collect();
+            self.add_line(&format!("#[guard({})]", guards.join(", ")));
         }
 
         self.add_line(&format!("{} {{", signature));
         self.indent += 1;
+        if func.is_constructor {
+            for line in std::mem::take(&mut self.initializers) {
+                self.add_line(&line);
+            }
+        }
 
         // Convert function body
         if let Some(body) = &func.body {
             self.convert_block(body);
         } else {
-            self.add_line("/// External function - implementation delegated");
+            self.add_line("# External function - implementation delegated");
         }
 
         self.indent -= 1;
@@ -337,7 +620,11 @@ impl SolidityToBendConverter {
 
     /// Convert a variable declaration to Bend-PVM format
     fn convert_variable_declaration(&self, decl: &VariableDeclaration) -> String {
-        let name = decl.name.clone().unwrap_or_else(|| "_".to_string());
+        let name = decl.name.as_deref().map_or("_".to_string(), identifier);
+        // `var` declarations leave the type to inference
+        if matches!(&decl.type_name, TypeName::Elementary(t) if t.name == "var") {
+            return name;
+        }
         let bend_type = self.map_type(&decl.type_name);
         format!("{}: {}", name, bend_type)
     }
@@ -352,55 +639,89 @@ impl SolidityToBendConverter {
     /// Convert a statement
     fn convert_statement(&mut self, stmt: &Statement) {
         match stmt {
-            Statement::Block(block) => {
-                self.add_line("{");
-                self.indent += 1;
-                self.convert_block(block);
-                self.indent -= 1;
-                self.add_line("}");
-            }
+            // Bend has no block statements: the statements of a block,
+            // like the body of an `if`, join the enclosing ones
+            Statement::Block(block) => self.convert_block(block),
             Statement::VariableDeclaration(var_decl) => {
-                let decls: Vec<String> = var_decl
+                // Locals are declared by their first assignment
+                let names: Vec<String> = var_decl
                     .declarations
                     .iter()
-                    .filter(|d| d.name.is_some())
-                    .map(|d| self.convert_variable_declaration(d))
+                    .filter_map(|d| d.name.as_deref())
+                    .map(identifier)
                     .collect();
 
                 if let Some(init) = &var_decl.initial_value {
                     let init_value = self.convert_expression(init);
-                    self.add_line(&format!("let {} = {};", decls.join(", "), init_value));
-                } else {
-                    self.add_line(&format!("let {};", decls.join(", ")));
+                    self.add_line(&format!("{} = {};", key_tuple(&names), init_value));
+                    return;
+                }
+                for decl in var_decl.declarations.iter().filter(|d| d.name.is_some()) {
+                    match self.default_value(&decl.type_name) {
+                        Some(value) => {
+                            let name = identifier(decl.name.as_deref().unwrap_or_default());
+                            self.add_line(&format!("{} = {};", name, value));
+                        }
+                        None => {
+                            let declaration = self.convert_variable_declaration(decl);
+                            self.add_line(&format!("let {};", declaration));
+                            self.add_issue(
+                                &format!("Local `{}` has no zero value to start from", declaration),
+                                &format!("{}:{}", decl.location.line, decl.location.column),
+                                IssueSeverity::Manual,
+                                Some(
+                                    "Assign the local its first value where it is declared"
+                                        .to_string(),
+                                ),
+                            );
+                        }
+                    }
                 }
             }
             Statement::Expression(expr_stmt) => {
+                // `x++` is an assignment of `x + 1`
+                if let Expression::UnaryOperation(unop) = &expr_stmt.expression {
+                    let operator = match unop.operator {
+                        UnaryOperator::Inc => Some(AssignmentOperator::AddAssign),
+                        UnaryOperator::Dec => Some(AssignmentOperator::SubAssign),
+                        _ => None,
+                    };
+                    if let Some(operator) = operator {
+                        let assignment = Assignment {
+                            operator,
+                            left: unop.operand.clone(),
+                            right: Box::new(Expression::Literal(Literal {
+                                value: Some("1".to_string()),
+                                subdenomination: None,
+                                type_name: None,
+                                location: unop.location.clone(),
+                            })),
+                            location: unop.location.clone(),
+                        };
+                        self.convert_assignment_statement(&assignment);
+                        return;
+                    }
+                }
+                if let Expression::Assignment(assignment) = &expr_stmt.expression {
+                    self.convert_assignment_statement(assignment);
+                    return;
+                }
                 let expr = self.convert_expression(&expr_stmt.expression);
                 self.add_line(&format!("{};", expr));
             }
             Statement::Assignment(assign_stmt) => {
-                let left = self.convert_expression(&assign_stmt.assignment.left);
-                let right = self.convert_expression(&assign_stmt.assignment.right);
-
-                match assign_stmt.assignment.operator {
-                    AssignmentOperator::Assign => {
-                        self.add_line(&format!("{} = {};", left, right));
-                    }
-                    _ => {
-                        let op = self.map_assignment_operator(&assign_stmt.assignment.operator);
-                        self.add_line(&format!("{} {} {};", left, op, right));
-                    }
-                }
+                self.convert_assignment_statement(&assign_stmt.assignment);
             }
             Statement::If(if_stmt) => {
                 let condition = self.convert_expression(&if_stmt.condition);
                 self.add_line(&format!("if {} {{", condition));
                 self.indent += 1;
                 self.convert_statement(&if_stmt.true_body);
+                // Every `if` of Bend has an `else`
+                self.indent -= 1;
+                self.add_line("} else {");
+                self.indent += 1;
                 if let Some(false_body) = &if_stmt.false_body {
-                    self.indent -= 1;
-                    self.add_line("} else {");
-                    self.indent += 1;
                     self.convert_statement(false_body);
                 }
                 self.indent -= 1;
@@ -418,9 +739,24 @@ impl SolidityToBendConverter {
                 let event = self.convert_expression(&emit_stmt.event);
                 self.add_line(&format!("emit {};", event));
             }
-            Statement::For(_for_stmt) => {
-                self.add_line("// for loop - needs manual conversion");
-                self.add_line("// Original: for (...) { ... }");
+            // `for (init; condition; step) body` runs as
+            // `init; while condition { body; step; }`
+            Statement::For(for_stmt) => {
+                if let Some(initialization) = &for_stmt.initialization {
+                    self.convert_statement(initialization);
+                }
+                let condition = match &for_stmt.condition {
+                    Some(condition) => self.convert_expression(condition),
+                    None => "true".to_string(),
+                };
+                self.add_line(&format!("while {} {{", condition));
+                self.indent += 1;
+                self.convert_statement(&for_stmt.body);
+                if let Some(iteration) = &for_stmt.iteration {
+                    self.convert_statement(iteration);
+                }
+                self.indent -= 1;
+                self.add_line("}");
             }
             Statement::While(while_stmt) => {
                 let condition = self.convert_expression(&while_stmt.condition);
@@ -430,11 +766,19 @@ impl SolidityToBendConverter {
                 self.indent -= 1;
                 self.add_line("}");
             }
-            Statement::Continue(_) => {
-                self.add_line("continue;");
-            }
-            Statement::Break(_) => {
-                self.add_line("break;");
+            Statement::Continue(ContinueStatement { location })
+            | Statement::Break(BreakStatement { location }) => {
+                let keyword = match stmt {
+                    Statement::Continue(_) => "continue",
+                    _ => "break",
+                };
+                self.add_line(&format!("# {} - needs manual conversion", keyword));
+                self.add_issue(
+                    &format!("Bend loops have no `{}`", keyword),
+                    &format!("{}:{}", location.line, location.column),
+                    IssueSeverity::Manual,
+                    Some("Fold the condition into the loop's condition".to_string()),
+                );
             }
             Statement::Revert(revert_stmt) => {
                 if let Some(error_call) = &revert_stmt.error_call {
@@ -444,8 +788,11 @@ impl SolidityToBendConverter {
                     self.add_line("assert(false, \"revert\");");
                 }
             }
+            Statement::Placeholder(_) => {
+                self.add_line("_;");
+            }
             Statement::Assembly(assembly) => {
-                self.add_line(&format!("// Inline assembly: {}", assembly.operations));
+                self.add_line(&format!("# Inline assembly: {}", assembly.operations));
                 self.add_issue(
                     "Inline assembly requires manual conversion",
                     &format!("{}:{}", assembly.location.line, assembly.location.column),
@@ -454,22 +801,132 @@ impl SolidityToBendConverter {
                 );
             }
             _ => {
-                self.add_line("// Statement not fully supported");
+                self.add_line("# Statement not fully supported");
             }
         }
     }
 
+    /// Convert an assignment made as a statement
+    fn convert_assignment_statement(&mut self, assignment: &Assignment) {
+        if let Some(lines) = self.convert_storage_write(assignment) {
+            for line in lines {
+                self.add_line(&line);
+            }
+            return;
+        }
+        let left = self.convert_expression(&assignment.left);
+        let right = self.convert_expression(&assignment.right);
+        let line = self.convert_assignment(&left, &assignment.operator, &right);
+        self.add_line(&format!("{};", line));
+    }
+
+    /// The zero value of `type_name`, which Solidity locals start from
+    fn default_value(&self, type_name: &TypeName) -> Option<&'static str> {
+        match self.map_type(type_name).as_str() {
+            "Bool" => Some("false"),
+            "Address" => Some(ZERO_ADDRESS),
+            "u24" | "u64" | "u128" | "u256" | "i24" | "i64" | "i128" | "i256" => Some("0"),
+            _ => None,
+        }
+    }
+
+    /// Convert an assignment to an entry of a storage collection, or to a
+    /// field of one, into writes through `insert` or `set`
+    ///
+    /// Fields of struct entries are read, updated and written back.
+    fn convert_storage_write(&self, assignment: &Assignment) -> Option<Vec<String>> {
+        let mut members = Vec::new();
+        let mut target = &*assignment.left;
+        while let Expression::MemberAccess(member) = target {
+            members.push(member.member_name.as_str());
+            target = &member.expression;
+        }
+        let (name, keys) = self.storage_entry(target)?;
+        let key = self.convert_entry_key(&keys);
+        let setter = match self.collections.get(name) {
+            Some(TypeName::Mapping(_)) => "insert",
+            _ => "set",
+        };
+        let name = identifier(name);
+        let right = self.convert_expression(&assignment.right);
+
+        if members.is_empty() {
+            let value = match assignment.operator {
+                AssignmentOperator::Assign => right,
+                _ => format!(
+                    "({}.get({}) {} {})",
+                    name,
+                    key,
+                    self.map_compound_operator(&assignment.operator),
+                    right
+                ),
+            };
+            return Some(vec![format!("{}.{}({}, {});", name, setter, key, value)]);
+        }
+
+        members.reverse();
+        let members: Vec<String> = members.into_iter().map(identifier).collect();
+        let entry = format!("{}_entry", name);
+        let field = format!("{}.{}", entry, members.join("."));
+        Some(vec![
+            format!("{} = {}.get({});", entry, name, key),
+            format!(
+                "{};",
+                self.convert_assignment(&field, &assignment.operator, &right)
+            ),
+            format!("{}.{}({}, {});", name, setter, key, entry),
+        ])
+    }
+
+    /// The assignment of `right` to `left` with `operator`, which Bend
+    /// only has the plain form of: `x += y` becomes `x = (x + y)`
+    fn convert_assignment(&self, left: &str, operator: &AssignmentOperator, right: &str) -> String {
+        match operator {
+            AssignmentOperator::Assign => format!("{} = {}", left, right),
+            _ => format!(
+                "{} = ({} {} {})",
+                left,
+                left,
+                self.map_compound_operator(operator),
+                right
+            ),
+        }
+    }
+
+    /// Split `m[a][b]` into the storage collection `m` and the keys of one
+    /// of its entries, `[a, b]`
+    fn storage_entry<'a>(&self, expr: &'a Expression) -> Option<(&'a str, Vec<&'a Expression>)> {
+        let mut keys = Vec::new();
+        let mut current = expr;
+        while let Expression::IndexAccess(index) = current {
+            keys.push(&*index.index);
+            current = &index.base;
+        }
+        let Expression::Identifier(identifier) = current else {
+            return None;
+        };
+        let collection = self.collections.get(&identifier.name)?;
+        keys.reverse();
+        (keys.len() == key_count(collection)).then_some((identifier.name.as_str(), keys))
+    }
+
+    /// Convert the keys of a collection entry, as a tuple for nested
+    /// mappings
+    fn convert_entry_key(&self, keys: &[&Expression]) -> String {
+        let keys: Vec<String> = keys.iter().map(|k| self.convert_expression(k)).collect();
+        key_tuple(&keys)
+    }
+
     /// Convert an expression
     fn convert_expression(&self, expr: &Expression) -> String {
         match expr {
             Expression::Identifier(identifier) => {
                 // Map special identifiers
                 match identifier.name.as_str() {
-                    "super" => "super",
-                    "this" => "self",
-                    _ => &identifier.name,
+                    "super" => "super".to_string(),
+                    "this" => "self".to_string(),
+                    name => self::identifier(name),
                 }
-                .to_string()
             }
             Expression::Literal(literal) => {
                 if let Some(value) = &literal.value {
@@ -495,6 +952,14 @@ impl SolidityToBendConverter {
                 format!("({} {} {})", left, op, right)
             }
             Expression::UnaryOperation(unop) => {
+                if unop.operator == UnaryOperator::Delete {
+                    if let Some((name, keys)) = self.storage_entry(&unop.operand) {
+                        if matches!(self.collections.get(name), Some(TypeName::Mapping(_))) {
+                            let key = self.convert_entry_key(&keys);
+                            return format!("{}.remove({})", identifier(name), key);
+                        }
+                    }
+                }
                 let operand = self.convert_expression(&unop.operand);
                 let op = self.map_unary_operator(&unop.operator);
                 if unop.is_prefix {
@@ -504,6 +969,29 @@ impl SolidityToBendConverter {
                 }
             }
             Expression::FunctionCall(func_call) => {
+                // Functions attached with `using for` take their receiver
+                // as first argument
+                if let Expression::MemberAccess(member) = &*func_call.expression {
+                    let direct = matches!(
+                        &*member.expression,
+                        Expression::Identifier(id) if self.libraries.contains_key(&id.name)
+                    );
+                    if let (Some(library), false) = (self.attached.get(&member.member_name), direct)
+                    {
+                        let args: Vec<String> = std::iter::once(&*member.expression)
+                            .chain(&func_call.arguments)
+                            .map(|a| self.convert_expression(a))
+                            .collect();
+                        let function = match library {
+                            Some(library) => {
+                                format!("{}/{}", library, identifier(&member.member_name))
+                            }
+                            None => identifier(&member.member_name),
+                        };
+                        return format!("{}({})", function, args.join(", "));
+                    }
+                }
+
                 let func_expr = self.convert_expression(&func_call.expression);
 
                 // Check if it's a mapped function
@@ -526,10 +1014,34 @@ impl SolidityToBendConverter {
                 format!("{}({})", func_expr, args.join(", "))
             }
             Expression::MemberAccess(member) => {
+                if let Expression::Identifier(identifier) = &*member.expression {
+                    let global = format!("{}.{}", identifier.name, member.member_name);
+                    if let Some(mapped) = self.global_mappings.get(&global) {
+                        return mapped.clone();
+                    }
+                    if self.libraries.contains_key(&identifier.name) {
+                        let function = self::identifier(&member.member_name);
+                        return format!("{}/{}", identifier.name, function);
+                    }
+                }
                 let base = self.convert_expression(&member.expression);
-                format!("{}.{}", base, member.member_name)
+                if let (Expression::Identifier(identifier), "length") =
+                    (&*member.expression, member.member_name.as_str())
+                {
+                    if matches!(
+                        self.collections.get(&identifier.name),
+                        Some(TypeName::Array(_))
+                    ) {
+                        return format!("{}.len()", base);
+                    }
+                }
+                format!("{}.{}", base, self::identifier(&member.member_name))
             }
             Expression::IndexAccess(index) => {
+                if let Some((name, keys)) = self.storage_entry(expr) {
+                    let name = self::identifier(name);
+                    return format!("{}.get({})", name, self.convert_entry_key(&keys));
+                }
                 let base = self.convert_expression(&index.base);
                 let idx = self.convert_expression(&index.index);
                 format!("{}[{}]", base, idx)
@@ -537,8 +1049,7 @@ impl SolidityToBendConverter {
             Expression::Assignment(assign) => {
                 let left = self.convert_expression(&assign.left);
                 let right = self.convert_expression(&assign.right);
-                let op = self.map_assignment_operator(&assign.operator);
-                format!("{} {} {}", left, op, right)
+                self.convert_assignment(&left, &assign.operator, &right)
             }
             Expression::Conditional(conditional) => {
                 let condition = self.convert_expression(&conditional.condition);
@@ -560,12 +1071,35 @@ impl SolidityToBendConverter {
             Expression::TypeConversion(conv) => {
                 let type_str = self.map_type(&conv.type_name);
                 let expr = self.convert_expression(&conv.expression);
+                if type_str == "Address" && expr == "0" {
+                    return ZERO_ADDRESS.to_string();
+                }
                 format!("{}({})", type_str, expr)
             }
-            _ => "unknown_expression".to_string(),
+            // A component left out of a tuple being assigned, as in `(, b)`
+            Expression::Location(_) => "_".to_string(),
+            Expression::NewExpression(new) => self.untranslatable("`new`", &new.location),
+            Expression::ArrayLiteral(array) => {
+                self.untranslatable("array literals", &array.location)
+            }
+            Expression::StructLiteral(literal) => {
+                self.untranslatable("struct literals", &literal.location)
+            }
         }
     }
 
+    /// Fail the conversion on `what`, found at `location`, unless it already
+    /// failed, standing in for it with nothing meanwhile
+    fn untranslatable(&self, what: &str, location: &SolLocation) -> String {
+        self.untranslatable.borrow_mut().get_or_insert_with(|| {
+            MigrationError::UnsupportedFeature(format!(
+                "{} at {}:{}:{}",
+                what, location.file, location.line, location.column
+            ))
+        });
+        String::new()
+    }
+
     /// Map a Solidity type to Bend-PVM type
     fn map_type(&self, type_name: &TypeName) -> String {
         match type_name {
@@ -613,6 +1147,31 @@ impl SolidityToBendConverter {
         }
     }
 
+    /// Map the type of a mapping or dynamic array state variable to a
+    /// storage collection, keying nested mappings by a tuple of their keys
+    fn map_storage_type(&self, type_name: &TypeName) -> String {
+        match type_name {
+            TypeName::Mapping(mapping) => {
+                let (keys, value) = self.mapping_keys(mapping);
+                format!("StorageMap<{}, {}>", key_tuple(&keys), value)
+            }
+            TypeName::Array(array) => format!("StorageVec<{}>", self.map_type(&array.base_type)),
+            other => self.map_type(other),
+        }
+    }
+
+    /// Key types of a possibly nested mapping, outermost first, and the
+    /// type of its values
+    fn mapping_keys(&self, mapping: &MappingTypeName) -> (Vec<String>, String) {
+        let mut keys = vec![self.map_type(&mapping.key_type)];
+        let mut value = &*mapping.value_type;
+        while let TypeName::Mapping(inner) = value {
+            keys.push(self.map_type(&inner.key_type));
+            value = &inner.value_type;
+        }
+        (keys, self.map_type(value))
+    }
+
     /// Map binary operator
     fn map_binary_operator(&self, op: &BinaryOperator) -> String {
         match op {
@@ -647,24 +1206,25 @@ impl SolidityToBendConverter {
             UnaryOperator::BitNot => "~",
             UnaryOperator::Inc => "++",
             UnaryOperator::Dec => "--",
+            UnaryOperator::Delete => "delete ",
         }
         .to_string()
     }
 
-    /// Map assignment operator
-    fn map_assignment_operator(&self, op: &AssignmentOperator) -> String {
+    /// Map the operator a compound assignment applies
+    fn map_compound_operator(&self, op: &AssignmentOperator) -> String {
         match op {
-            AssignmentOperator::Assign => "=",
-            AssignmentOperator::AddAssign => "+=",
-            AssignmentOperator::SubAssign => "-=",
-            AssignmentOperator::MulAssign => "*=",
-            AssignmentOperator::DivAssign => "/=",
-            AssignmentOperator::ModAssign => "%=",
-            AssignmentOperator::BitAndAssign => "&=",
-            AssignmentOperator::BitOrAssign => "|=",
-            AssignmentOperator::BitXorAssign => "^=",
-            AssignmentOperator::BitShiftLeftAssign => "<<=",
-            AssignmentOperator::BitShiftRightAssign => ">>=",
+            AssignmentOperator::Assign => "",
+            AssignmentOperator::AddAssign => "+",
+            AssignmentOperator::SubAssign => "-",
+            AssignmentOperator::MulAssign => "*",
+            AssignmentOperator::DivAssign => "/",
+            AssignmentOperator::ModAssign => "%",
+            AssignmentOperator::BitAndAssign => "&",
+            AssignmentOperator::BitOrAssign => "|",
+            AssignmentOperator::BitXorAssign => "^",
+            AssignmentOperator::BitShiftLeftAssign => "<<",
+            AssignmentOperator::BitShiftRightAssign => ">>",
         }
         .to_string()
     }
@@ -698,6 +1258,54 @@ impl SolidityToBendConverter {
     }
 }
 
+/// `name` as a Bend identifier: a keyword, or the name of a builtin the
+/// converter calls, gets a trailing underscore
+fn identifier(name: &str) -> String {
+    match is_keyword(name) || name == CALLER {
+        true => format!("{}_", name),
+        false => name.to_string(),
+    }
+}
+
+/// Whether the RISC-V backend can keep a storage field of the Bend type
+/// `bend_type`
+fn storable(bend_type: &str) -> bool {
+    Parser::new(bend_type)
+        .parse_type()
+        .map_or(true, |ty| is_storable(&ty))
+}
+
+/// Whether a state variable of type `type_name` is kept in a storage
+/// collection: mappings and dynamic arrays
+fn is_collection(type_name: &TypeName) -> bool {
+    match type_name {
+        TypeName::Mapping(_) => true,
+        TypeName::Array(array) => array.length.is_none(),
+        _ => false,
+    }
+}
+
+/// Number of keys addressing an entry of a storage collection
+fn key_count(type_name: &TypeName) -> usize {
+    match type_name {
+        TypeName::Mapping(mapping) => {
+            1 + match &*mapping.value_type {
+                inner @ TypeName::Mapping(_) => key_count(inner),
+                _ => 0,
+            }
+        }
+        _ => 1,
+    }
+}
+
+/// Join keys into a tuple, or keep a single one as it is
+fn key_tuple(keys: &[String]) -> String {
+    match keys {
+        [key] => key.clone(),
+        _ => format!("({})", keys.join(", ")),
+    }
+}
+
 impl Default for SolidityToBendConverter {
     fn default() -> Self {
         Self::new()
@@ -709,7 +1317,7 @@ impl SolidityMigrator {
     /// Convert a Solidity source file to Bend-PVM source
     pub fn convert_solidity(&mut self, source: &SoliditySource) -> Result<String, MigrationError> {
         let mut converter = SolidityToBendConverter::new();
-        let result = converter.convert(source);
+        let result = converter.convert(source)?;
 
         // Log issues
         for issue in converter.get_issues() {
@@ -725,11 +1333,54 @@ impl SolidityMigrator {
 
         Ok(result)
     }
+
+    /// Parse Solidity `source`, read from `file`, and convert it to
+    /// Bend-PVM source
+    pub fn migrate_source(&mut self, source: &str, file: &str) -> Result<String, MigrationError> {
+        self.migrate(SourceLanguage::Solidity, source, file)
+    }
+
+    /// Parse `source` in `language`, read from `file`, and convert it to
+    /// Bend-PVM source
+    pub fn migrate(
+        &mut self,
+        language: SourceLanguage,
+        source: &str,
+        file: &str,
+    ) -> Result<String, MigrationError> {
+        let parsed = match language {
+            SourceLanguage::Solidity => parse_solidity(source, file)?,
+            SourceLanguage::Vyper => parse_vyper(source, file)?,
+            SourceLanguage::Ink => parse_ink(source, file)?,
+        };
+        self.stats.lines_of_code += source.lines().count();
+        let first_issue = self.stats.issues.len();
+        let converted = self.convert_solidity(&parsed)?;
+        self.stats.files.push(MigratedFile {
+            file: file.to_string(),
+            source: source.to_string(),
+            issues: first_issue..self.stats.issues.len(),
+        });
+        Ok(converted)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compiler::analyzer::type_checker::TypeChecker;
+    use crate::compiler::pipeline::{CompilerPipeline, Source};
+    use crate::CompilerOptions;
+
+    /// Parse and type check migrated Bend, as the compiler does
+    fn check(bend: &str) {
+        let program = Parser::new(bend)
+            .parse_program()
+            .unwrap_or_else(|error| panic!("{} in\n{}", error, bend));
+        TypeChecker::new()
+            .check_program(&program)
+            .unwrap_or_else(|error| panic!("{} in\n{}", error, bend));
+    }
 
     #[test]
     fn test_converter_creation() {
@@ -763,4 +1414,307 @@ mod tests {
             Some(&"crypto.keccak256".to_string())
         );
     }
+
+    #[test]
+    fn test_migrate_source() {
+        let mut migrator = SolidityMigrator::new();
+        let bend = migrator
+            .migrate_source(
+                "contract Counter {\n    uint256 count;\n    function inc(uint256 by) external returns (uint256) {\n        count += by;\n        return count;\n    }\n}\n",
+                "Counter.sol",
+            )
+            .unwrap();
+        assert!(bend.contains("let count: u256;"));
+        assert!(bend.contains(
+            "fn inc(by: u256) -> u256 {\n        count = (count + by);\n        return count;\n    }"
+        ));
+        assert_eq!(migrator.stats().functions_translated, 1);
+        assert_eq!(migrator.stats().lines_of_code, 7);
+        check(&bend);
+
+        assert!(matches!(
+            migrator.migrate_source("contract {", "Broken.sol"),
+            Err(MigrationError::ParseError(message)) if message.starts_with("Broken.sol:1:10:")
+        ));
+    }
+
+    #[test]
+    fn test_modifiers_inheritance_and_libraries() {
+        let source = r#"
+library Math {
+    function add(uint256 a, uint256 b) internal pure returns (uint256) { return a + b; }
+}
+
+contract Owned {
+    address owner;
+    modifier onlyOwner() { require(msg.sender == owner, "not the owner"); _; }
+    constructor() { owner = msg.sender; }
+}
+
+contract Counter is Owned {
+    using Math for uint256;
+    using SafeCast for uint256;
+    uint256 count;
+    function inc(uint256 by) external onlyOwner whenNotPaused {
+        count = count.add(by);
+        count = Math.add(count, 1);
+    }
+}
+"#;
+        let mut migrator = SolidityMigrator::new();
+        let bend = migrator.migrate_source(source, "Counter.sol").unwrap();
+        assert!(bend.contains("# Inherits from: Owned (merged in)\ncontract Counter {"));
+        assert!(!bend.contains("contract Owned"));
+        assert!(bend.contains("    let owner: Address;"));
+        assert!(bend.contains("    guard onlyOwner() {"));
+        assert!(bend.contains("    #[guard(onlyOwner)]\n    pub fn inc(by: u256) {"));
+        assert!(bend.contains("    fn constructor() {\n        owner = caller();\n    }"));
+        assert!(bend.contains("fn Math/add(a: u256, b: u256) -> u256 {"));
+        assert!(bend.contains("count = Math/add(count, by);"));
+        assert!(bend.contains("count = Math/add(count, 1);"));
+        check(&bend);
+
+        let issues: Vec<_> = migrator
+            .stats()
+            .issues
+            .iter()
+            .map(|issue| issue.description.as_str())
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                "Library `SafeCast` of `using for` is not defined in this file; calls through it are left as they are",
+                "Modifier `whenNotPaused` of `inc` is not defined in this file and is dropped",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_storage_collections_and_structs() {
+        let source = r#"
+struct Position {
+    uint256 amount;
+    bool open;
+}
+
+contract Ledger {
+    uint256 public total;
+    mapping(address => uint256) public balances;
+    mapping(address => mapping(uint256 => uint256)) public allowance;
+    mapping(uint256 => Position) positions;
+    address[] public holders;
+
+    function deposit(uint256 id, uint256 amount) external {
+        balances[msg.sender] += amount;
+        allowance[msg.sender][id] = amount;
+        positions[id].amount = amount;
+        holders.push(msg.sender);
+        total = holders.length + balances[msg.sender];
+        holders[0] = msg.sender;
+        delete balances[msg.sender];
+    }
+}
+"#;
+        let mut migrator = SolidityMigrator::new();
+        let bend = migrator.migrate_source(source, "Ledger.sol").unwrap();
+        assert!(bend.contains("object Position {\n    let amount: u256;\n    let open_: Bool;\n}"));
+        // Public state variables get the getters of `pub` fields
+        assert!(bend.contains("    pub let total: u256;"));
+        assert!(bend.contains(
+            "    # Entries are stored under keccak256(\"balances\") hashed with their key\n    pub let balances: StorageMap<Address, u256>;"
+        ));
+        assert!(bend.contains("    pub let allowance: StorageMap<(Address, u256), u256>;"));
+        assert!(bend.contains("    let positions: StorageMap<u256, Position>;"));
+        assert!(bend.contains("    pub let holders: StorageVec<Address>;"));
+
+        for line in [
+            "balances.insert(caller(), (balances.get(caller()) + amount));",
+            "allowance.insert((caller(), id), amount);",
+            "positions_entry = positions.get(id);\n        positions_entry.amount = amount;\n        positions.insert(id, positions_entry);",
+            "holders.push(caller());",
+            "total = (holders.len() + balances.get(caller()));",
+            "holders.set(0, caller());",
+            "balances.remove(caller());",
+        ] {
+            assert!(bend.contains(line), "missing `{}` in\n{}", line, bend);
+        }
+
+        assert!(!bend.contains("#[view]"));
+        let issues = &migrator.stats().issues;
+        assert_eq!(issues.len(), 5);
+        // Structs cannot be stored, so the map of them is reported
+        let unsupported: Vec<&str> = issues
+            .iter()
+            .filter(|issue| matches!(issue.severity, IssueSeverity::Unsupported))
+            .map(|issue| issue.description.as_str())
+            .collect();
+        assert_eq!(
+            unsupported,
+            ["`positions` is a `StorageMap<u256, Position>`, which the RISC-V backend cannot store"]
+        );
+        check(&bend);
+    }
+
+    #[test]
+    fn test_contract_output_compiles() {
+        let source = r#"
+contract Counter {
+    uint24 count = 1;
+    event Incremented(uint24 by);
+    function inc(uint24 by) external returns (uint24) {
+        count = count + by;
+        emit Incremented(by);
+        return count;
+    }
+}
+"#;
+        let mut migrator = SolidityMigrator::new();
+        let bend = migrator.migrate_source(source, "Counter.sol").unwrap();
+        assert!(bend.starts_with("# Auto-generated Bend-PVM contract from Solidity\n"));
+        assert!(bend.contains("    event Incremented { by: u24 }"));
+        // The initial value is set by a constructor made for it
+        assert!(bend.contains("    fn constructor() {\n        count = 1;\n    }"));
+
+        let options = CompilerOptions {
+            metadata: true,
+            ..Default::default()
+        };
+        let result = CompilerPipeline::new(&options)
+            .with_dispatcher()
+            .run(&Source::new("counter", &bend))
+            .unwrap();
+        let metadata = result.metadata.unwrap();
+        assert_eq!(metadata.exports["deploy"], "constructor");
+        assert!(metadata.functions.contains_key("inc"));
+    }
+
+    #[test]
+    fn test_erc20_output_type_checks() {
+        let source = r#"
+contract Token {
+    mapping(address => uint256) public balances;
+    mapping(address => mapping(address => uint256)) public allowance;
+    uint256 public totalSupply;
+    uint256 public transfers;
+
+    event Transfer(address indexed from, address indexed to, uint256 value);
+
+    constructor(uint256 supply) {
+        totalSupply = supply;
+        balances[msg.sender] = supply;
+    }
+
+    function transfer(address to, uint256 amount) public returns (bool) {
+        require(balances[msg.sender] >= amount, "insufficient");
+        balances[msg.sender] -= amount;
+        balances[to] += amount;
+        transfers += 1;
+        transfers++;
+        emit Transfer(msg.sender, to, amount);
+        return true;
+    }
+
+    function transferFrom(address from, address to, uint256 amount) public returns (bool) {
+        uint256 allowed = allowance[from][msg.sender];
+        bool open;
+        require(allowed >= amount, "allowance");
+        allowance[from][msg.sender] = allowed - amount;
+        balances[from] -= amount;
+        balances[to] += amount;
+        emit Transfer(from, to, amount);
+        return true;
+    }
+}
+"#;
+        let mut migrator = SolidityMigrator::new();
+        let bend = migrator.migrate_source(source, "Token.sol").unwrap();
+        for line in [
+            "    event Transfer { indexed from_: Address, indexed to: Address, value: u256 }",
+            "    fn constructor(supply: u256) {",
+            "        balances.insert(caller(), supply);",
+            "        transfers = (transfers + 1);\n        transfers = (transfers + 1);",
+            "    pub fn transferFrom(from_: Address, to: Address, amount: u256) -> Bool {",
+            "        allowed = allowance.get((from_, caller()));\n        open_ = false;",
+        ] {
+            assert!(bend.contains(line), "missing `{}` in\n{}", line, bend);
+        }
+        assert!(!bend.contains("msg.sender"));
+        assert!(!bend.contains("+="));
+
+        check(&bend);
+        let result = CompilerPipeline::new(&CompilerOptions::default())
+            .with_dispatcher()
+            .run(&Source::new("token", &bend));
+        assert!(result.is_ok(), "{:?}", result.err());
+    }
+
+    #[test]
+    fn test_control_flow_round_trips() {
+        let source = r#"
+contract Flow {
+    uint24 public count;
+    bool paused;
+    address owner;
+
+    function bump(uint24 by) external returns (uint24) {
+        require(owner != address(0), "no owner");
+        if (paused) {
+            revert("paused");
+        } else if (by == 0) {
+            return count;
+        }
+        uint24 i;
+        while (i < by) {
+            i++;
+            if (i > 100) {
+                break;
+            }
+        }
+        for (uint24 j = 0; j < by; j++) {
+            count += j;
+        }
+        return count;
+    }
+}
+"#;
+        let mut migrator = SolidityMigrator::new();
+        let bend = migrator.migrate_source(source, "Flow.sol").unwrap();
+        for lines in [
+            "        assert((owner != ZERO_ADDRESS), \"no owner\");",
+            "        if paused {\n            assert(false, \"paused\");\n        } else {\n            if (by == 0) {\n                return count;\n            } else {\n            }\n        }",
+            "        i = 0;\n        while (i < by) {\n            i = (i + 1);",
+            "                # break - needs manual conversion",
+            "        j = 0;\n        while (j < by) {\n            count = (count + j);\n            j = (j + 1);\n        }",
+        ] {
+            assert!(bend.contains(lines), "missing `{}` in\n{}", lines, bend);
+        }
+        assert_eq!(
+            migrator.stats().issues[0].description,
+            "Bend loops have no `break`"
+        );
+        check(&bend);
+        let result = CompilerPipeline::new(&CompilerOptions::default())
+            .with_dispatcher()
+            .run(&Source::new("flow", &bend));
+        assert!(result.is_ok(), "{:?} in\n{}", result.err(), bend);
+    }
+
+    #[test]
+    fn test_untranslatable_expressions_fail() {
+        let source = r#"
+contract Factory {
+    function make() external returns (uint24) {
+        uint24[] memory values = new uint24[](3);
+        return 1;
+    }
+}
+"#;
+        let mut migrator = SolidityMigrator::new();
+        match migrator.migrate_source(source, "Factory.sol") {
+            Err(MigrationError::UnsupportedFeature(message)) => {
+                assert_eq!(message, "`new` at Factory.sol:4:34")
+            }
+            other => panic!("expected an unsupported feature, got {:?}", other),
+        }
+    }
 }
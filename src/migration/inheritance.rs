@@ -0,0 +1,679 @@
+//! # Inheritance Flattening
+//!
+//! Bend has no inheritance, so a contract is converted with the members of
+//! its bases merged in, in the order Solidity linearizes them (C3):
+//!
+//! - state variables come most base first, as Solidity lays out storage;
+//! - of functions, modifiers, events, errors, structs and enums the most
+//!   derived definition wins, and overridden functions reached through
+//!   `super.f()` or `Base.f()` are kept as `Base_f`;
+//! - the constructors of the bases run before the contract's own, with the
+//!   arguments given in `is Base(...)` or the constructor's `Base(...)`.
+//!
+//! Contracts which are only bases of others are merged into them rather
+//! than converted on their own.
+
+use std::collections::{HashMap, HashSet};
+
+use super::ast::*;
+use super::{IssueSeverity, MigrationError, MigrationIssue};
+
+/// The C3 linearization of `name`, most derived first
+///
+/// Bases that `contracts` does not define are linearized on their own.
+pub fn linearize(
+    contracts: &[ContractDefinition],
+    name: &str,
+) -> Result<Vec<String>, MigrationError> {
+    linearize_with(contracts, name, &mut Vec::new())
+}
+
+fn linearize_with(
+    contracts: &[ContractDefinition],
+    name: &str,
+    visiting: &mut Vec<String>,
+) -> Result<Vec<String>, MigrationError> {
+    if visiting.iter().any(|visited| visited == name) {
+        return Err(MigrationError::TranslationError(format!(
+            "`{}` inherits from itself",
+            name
+        )));
+    }
+    let Some(contract) = contracts.iter().find(|contract| contract.name == name) else {
+        return Ok(vec![name.to_string()]);
+    };
+
+    // Bases are listed from the most base-like to the most derived
+    visiting.push(name.to_string());
+    let mut sequences = Vec::new();
+    for base in contract.base_contracts.iter().rev() {
+        sequences.push(linearize_with(contracts, &base.name, visiting)?);
+    }
+    sequences.push(
+        contract
+            .base_contracts
+            .iter()
+            .rev()
+            .map(|base| base.name.clone())
+            .collect(),
+    );
+    visiting.pop();
+
+    let mut linearization = vec![name.to_string()];
+    loop {
+        sequences.retain(|sequence| !sequence.is_empty());
+        if sequences.is_empty() {
+            return Ok(linearization);
+        }
+        let head = sequences
+            .iter()
+            .map(|sequence| &sequence[0])
+            .find(|head| {
+                !sequences
+                    .iter()
+                    .any(|sequence| sequence[1..].contains(head))
+            })
+            .cloned()
+            .ok_or_else(|| {
+                MigrationError::TranslationError(format!(
+                    "Linearization of the inheritance graph of `{}` is impossible",
+                    name
+                ))
+            })?;
+        for sequence in &mut sequences {
+            if sequence[0] == head {
+                sequence.remove(0);
+            }
+        }
+        linearization.push(head);
+    }
+}
+
+/// The contracts to convert, with the members of their bases merged in,
+/// and what could not be merged
+pub fn flatten(source: &SoliditySource) -> (Vec<ContractDefinition>, Vec<MigrationIssue>) {
+    let contracts = &source.contracts;
+    let bases: HashSet<&str> = contracts
+        .iter()
+        .filter(|contract| contract.kind == ContractKind::Contract)
+        .flat_map(|contract| &contract.base_contracts)
+        .map(|base| base.name.as_str())
+        .collect();
+
+    let mut flattened = Vec::new();
+    let mut issues = Vec::new();
+    for contract in contracts {
+        let is_contract = contract.kind == ContractKind::Contract;
+        if is_contract && bases.contains(contract.name.as_str()) {
+            continue;
+        }
+        if !is_contract || contract.base_contracts.is_empty() {
+            flattened.push(contract.clone());
+            continue;
+        }
+        match linearize(contracts, &contract.name) {
+            Ok(linearization) => {
+                flattened.push(merge(contracts, &linearization, &mut issues));
+            }
+            Err(e) => {
+                issues.push(issue(
+                    &e.to_string(),
+                    &contract.location,
+                    IssueSeverity::Unsupported,
+                    "Reorder the base contracts from the most base-like to the most derived",
+                ));
+                flattened.push(contract.clone());
+            }
+        }
+    }
+    (flattened, issues)
+}
+
+fn issue(
+    description: &str,
+    location: &SolLocation,
+    severity: IssueSeverity,
+    suggestion: &str,
+) -> MigrationIssue {
+    MigrationIssue {
+        description: description.to_string(),
+        source_location: format!("{}:{}", location.line, location.column),
+        severity,
+        suggestion: Some(suggestion.to_string()),
+    }
+}
+
+/// The members of the contracts, most base first, which no more derived
+/// contract redefines
+fn most_derived<T: Clone>(
+    base_first: &[&ContractDefinition],
+    members: impl Fn(&ContractDefinition) -> &Vec<T>,
+    name: impl Fn(&T) -> &str,
+) -> Vec<T> {
+    let mut kept = Vec::new();
+    for (index, contract) in base_first.iter().enumerate() {
+        for member in members(contract) {
+            let redefined = base_first[index + 1..].iter().any(|derived| {
+                members(derived)
+                    .iter()
+                    .any(|other| name(other) == name(member))
+            });
+            if !redefined {
+                kept.push(member.clone());
+            }
+        }
+    }
+    kept
+}
+
+/// Merge the contracts of a `linearization` into its first, most derived,
+/// contract
+fn merge(
+    contracts: &[ContractDefinition],
+    linearization: &[String],
+    issues: &mut Vec<MigrationIssue>,
+) -> ContractDefinition {
+    let derived = contracts
+        .iter()
+        .find(|contract| contract.name == linearization[0])
+        .expect("linearizations start with a defined contract");
+    let chain: Vec<&ContractDefinition> = linearization
+        .iter()
+        .filter_map(|name| {
+            let found = contracts.iter().find(|contract| contract.name == *name);
+            if found.is_none() {
+                issues.push(issue(
+                    &format!(
+                        "Base contract `{}` of `{}` is not defined in this file; its members are not merged",
+                        name, derived.name
+                    ),
+                    &derived.location,
+                    IssueSeverity::Manual,
+                    "Add the base contract to the file being migrated",
+                ));
+            }
+            found
+        })
+        .collect();
+    // Most derived first, with the reverse for most base first
+    let base_first: Vec<&ContractDefinition> = chain.iter().rev().copied().collect();
+
+    let mut merged = derived.clone();
+    merged.state_variables = base_first
+        .iter()
+        .flat_map(|contract| contract.state_variables.clone())
+        .collect();
+    merged.modifiers = most_derived(&base_first, |c| &c.modifiers, |m| &m.name);
+    merged.events = most_derived(&base_first, |c| &c.events, |e| &e.name);
+    merged.errors = most_derived(&base_first, |c| &c.errors, |e| &e.name);
+    merged.structs = most_derived(&base_first, |c| &c.structs, |s| &s.name);
+    merged.enums = most_derived(&base_first, |c| &c.enums, |e| &e.name);
+    merged.type_definitions = most_derived(&base_first, |c| &c.type_definitions, |t| &t.name);
+    merged.using_directives = base_first
+        .iter()
+        .flat_map(|contract| contract.using_directives.clone())
+        .collect();
+    merged.functions = merge_functions(&chain);
+    if let Some(constructor) = merge_constructors(&chain, issues) {
+        merged.functions.insert(0, constructor);
+    }
+    merged
+}
+
+/// The functions of `chain`, most derived first: the most derived
+/// implementation of each, and the overridden ones reached from `super`
+fn merge_functions(chain: &[&ContractDefinition]) -> Vec<FunctionDefinition> {
+    type Key = (String, usize);
+    let key = |function: &FunctionDefinition| (function.name.clone(), function.parameters.len());
+
+    // Implementations of each function, most derived first
+    let mut implementations: HashMap<Key, Vec<(usize, FunctionDefinition)>> = HashMap::new();
+    let mut order: Vec<Key> = Vec::new();
+    for (index, contract) in chain.iter().enumerate().rev() {
+        for function in &contract.functions {
+            if function.is_constructor {
+                continue;
+            }
+            let key = key(function);
+            if !implementations.contains_key(&key) {
+                order.push(key.clone());
+            }
+            implementations
+                .entry(key)
+                .or_default()
+                .insert(0, (index, function.clone()));
+        }
+    }
+    // The name each implementation is converted under
+    let emitted = |key: &Key, index: usize| match implementations[key]
+        .iter()
+        .position(|(owner, _)| *owner == index)
+    {
+        Some(0) => key.0.clone(),
+        _ => format!("{}_{}", chain[index].name, key.0),
+    };
+
+    let mut functions = Vec::new();
+    let mut referenced = HashSet::new();
+    for key in &order {
+        for (position, (owner, function)) in implementations[key].iter().enumerate() {
+            let mut function = function.clone();
+            function.name = emitted(key, *owner);
+            if position > 0 {
+                function.visibility = Visibility::Internal;
+            }
+            if let Some(body) = &mut function.body {
+                walk_block(body, &mut |expression: &mut Expression| {
+                    let Expression::FunctionCall(call) = expression else {
+                        return;
+                    };
+                    let Expression::MemberAccess(member) = &*call.expression else {
+                        return;
+                    };
+                    let Expression::Identifier(target) = &*member.expression else {
+                        return;
+                    };
+                    let called = (member.member_name.clone(), call.arguments.len());
+                    let Some(candidates) = implementations.get(&called) else {
+                        return;
+                    };
+                    // `super.f()` calls the next implementation after this
+                    // one, `Base.f()` the one of `Base` or inherited by it
+                    let from = match target.name.as_str() {
+                        "super" => owner + 1,
+                        base => match chain.iter().position(|contract| contract.name == base) {
+                            Some(index) => index,
+                            None => return,
+                        },
+                    };
+                    let Some((next, _)) = candidates
+                        .iter()
+                        .find(|(index, function)| *index >= from && function.body.is_some())
+                    else {
+                        return;
+                    };
+                    let name = emitted(&called, *next);
+                    let location = member.location.clone();
+                    referenced.insert(name.clone());
+                    *call.expression = Expression::Identifier(Identifier { name, location });
+                });
+            }
+            functions.push((position, function));
+        }
+    }
+
+    // Declarations without a body give way to an implementation, and
+    // overridden implementations are only kept when called
+    functions
+        .into_iter()
+        .filter(|(position, function)| {
+            *position == 0 || (function.body.is_some() && referenced.contains(&function.name))
+        })
+        .map(|(_, function)| function)
+        .collect()
+}
+
+/// One constructor running the constructors of `chain`, most base first
+fn merge_constructors(
+    chain: &[&ContractDefinition],
+    issues: &mut Vec<MigrationIssue>,
+) -> Option<FunctionDefinition> {
+    let constructor_of = |contract: &ContractDefinition| {
+        contract
+            .functions
+            .iter()
+            .find(|function| function.is_constructor)
+            .cloned()
+    };
+    let derived = chain[0];
+    let bases: Vec<&str> = chain[1..]
+        .iter()
+        .map(|contract| contract.name.as_str())
+        .collect();
+
+    // Arguments of base constructors, given with the inheritance or by the
+    // constructor of a derived contract
+    let mut arguments: HashMap<&str, &Vec<Expression>> = HashMap::new();
+    for contract in chain {
+        for base in &contract.base_contracts {
+            if !base.arguments.is_empty() {
+                arguments.insert(&base.name, &base.arguments);
+            }
+        }
+    }
+    let constructors: Vec<FunctionDefinition> = chain
+        .iter()
+        .filter_map(|contract| constructor_of(contract))
+        .collect();
+    for constructor in &constructors {
+        for invocation in &constructor.modifiers {
+            if bases.contains(&invocation.name.as_str()) {
+                arguments.insert(&invocation.name, &invocation.arguments);
+            }
+        }
+    }
+    if constructors.is_empty() {
+        return None;
+    }
+
+    let mut merged = constructor_of(derived).unwrap_or_else(|| FunctionDefinition {
+        name: "constructor".to_string(),
+        parameters: Vec::new(),
+        return_parameters: Vec::new(),
+        body: None,
+        visibility: Visibility::Public,
+        state_mutability: StateMutability::NonPayable,
+        virtual_flag: false,
+        override_specifiers: Vec::new(),
+        modifiers: Vec::new(),
+        is_constructor: true,
+        is_fallback: false,
+        is_receive: false,
+        location: derived.location.clone(),
+    });
+    let mut statements = Vec::new();
+    for contract in chain[1..].iter().rev() {
+        let Some(constructor) = constructor_of(contract) else {
+            continue;
+        };
+        let given = arguments.get(contract.name.as_str());
+        if given.is_none() && !constructor.parameters.is_empty() {
+            issues.push(issue(
+                &format!(
+                    "The arguments of the constructor of `{}` are not given by `{}`",
+                    contract.name, derived.name
+                ),
+                &derived.location,
+                IssueSeverity::Manual,
+                "Initialize the parameters of the base constructor",
+            ));
+        }
+        for (index, parameter) in constructor.parameters.iter().enumerate() {
+            statements.push(Statement::VariableDeclaration(
+                VariableDeclarationStatement {
+                    declarations: vec![parameter.clone()],
+                    initial_value: given.and_then(|arguments| arguments.get(index)).cloned(),
+                    location: parameter.location.clone(),
+                },
+            ));
+        }
+        merged.modifiers.extend(constructor.modifiers.clone());
+        if constructor.state_mutability == StateMutability::Payable {
+            merged.state_mutability = StateMutability::Payable;
+        }
+        if let Some(body) = constructor.body {
+            statements.extend(body.statements);
+        }
+    }
+    merged
+        .modifiers
+        .retain(|invocation| !bases.contains(&invocation.name.as_str()));
+
+    let location = merged.location.clone();
+    let body = merged.body.get_or_insert(Block {
+        statements: Vec::new(),
+        location,
+    });
+    statements.append(&mut body.statements);
+    body.statements = statements;
+    Some(merged)
+}
+
+/// Visit every expression of `block`, innermost first
+pub fn walk_block(block: &mut Block, visit: &mut dyn FnMut(&mut Expression)) {
+    for statement in &mut block.statements {
+        walk_statement(statement, visit);
+    }
+}
+
+fn walk_statement(statement: &mut Statement, visit: &mut dyn FnMut(&mut Expression)) {
+    match statement {
+        Statement::Block(block) => walk_block(block, visit),
+        Statement::Unchecked(unchecked) => walk_block(&mut unchecked.block, visit),
+        Statement::VariableDeclaration(declaration) => {
+            if let Some(value) = &mut declaration.initial_value {
+                walk_expression(value, visit);
+            }
+        }
+        Statement::Assignment(assignment) => {
+            walk_expression(&mut assignment.assignment.left, visit);
+            walk_expression(&mut assignment.assignment.right, visit);
+        }
+        Statement::Expression(statement) => walk_expression(&mut statement.expression, visit),
+        Statement::If(statement) => {
+            walk_expression(&mut statement.condition, visit);
+            walk_statement(&mut statement.true_body, visit);
+            if let Some(body) = &mut statement.false_body {
+                walk_statement(body, visit);
+            }
+        }
+        Statement::For(statement) => {
+            if let Some(initialization) = &mut statement.initialization {
+                walk_statement(initialization, visit);
+            }
+            if let Some(condition) = &mut statement.condition {
+                walk_expression(condition, visit);
+            }
+            if let Some(iteration) = &mut statement.iteration {
+                walk_statement(iteration, visit);
+            }
+            walk_statement(&mut statement.body, visit);
+        }
+        Statement::While(statement) => {
+            walk_expression(&mut statement.condition, visit);
+            walk_statement(&mut statement.body, visit);
+        }
+        Statement::DoWhile(statement) => {
+            walk_statement(&mut statement.body, visit);
+            walk_expression(&mut statement.condition, visit);
+        }
+        Statement::Return(statement) => {
+            if let Some(value) = &mut statement.expression {
+                walk_expression(value, visit);
+            }
+        }
+        Statement::Emit(statement) => walk_expression(&mut statement.event, visit),
+        Statement::Revert(statement) => {
+            if let Some(call) = &mut statement.error_call {
+                walk_expression(call, visit);
+            }
+        }
+        Statement::Continue(_)
+        | Statement::Break(_)
+        | Statement::Assembly(_)
+        | Statement::Placeholder(_)
+        | Statement::Location(_) => {}
+    }
+}
+
+fn walk_expression(expression: &mut Expression, visit: &mut dyn FnMut(&mut Expression)) {
+    match expression {
+        Expression::BinaryOperation(operation) => {
+            walk_expression(&mut operation.left, visit);
+            walk_expression(&mut operation.right, visit);
+        }
+        Expression::UnaryOperation(operation) => walk_expression(&mut operation.operand, visit),
+        Expression::Assignment(assignment) => {
+            walk_expression(&mut assignment.left, visit);
+            walk_expression(&mut assignment.right, visit);
+        }
+        Expression::FunctionCall(call) => {
+            walk_expression(&mut call.expression, visit);
+            for argument in &mut call.arguments {
+                walk_expression(argument, visit);
+            }
+            for (_, option) in &mut call.options {
+                walk_expression(option, visit);
+            }
+        }
+        Expression::MemberAccess(member) => walk_expression(&mut member.expression, visit),
+        Expression::IndexAccess(index) => {
+            walk_expression(&mut index.base, visit);
+            walk_expression(&mut index.index, visit);
+        }
+        Expression::Conditional(conditional) => {
+            walk_expression(&mut conditional.condition, visit);
+            walk_expression(&mut conditional.true_expression, visit);
+            walk_expression(&mut conditional.false_expression, visit);
+        }
+        Expression::Tuple(tuple) => {
+            for element in &mut tuple.elements {
+                walk_expression(element, visit);
+            }
+        }
+        Expression::ArrayLiteral(array) => {
+            for element in &mut array.elements {
+                walk_expression(element, visit);
+            }
+        }
+        Expression::StructLiteral(literal) => {
+            for argument in &mut literal.arguments {
+                walk_expression(argument, visit);
+            }
+        }
+        Expression::TypeConversion(conversion) => {
+            walk_expression(&mut conversion.expression, visit)
+        }
+        Expression::Identifier(_)
+        | Expression::Literal(_)
+        | Expression::NewExpression(_)
+        | Expression::Location(_) => {}
+    }
+    visit(expression);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration::parser::parse_solidity;
+
+    fn contracts(source: &str) -> Vec<ContractDefinition> {
+        parse_solidity(source, "Test.sol").unwrap().contracts
+    }
+
+    #[test]
+    fn test_linearize() {
+        let diamond = contracts(
+            "contract Base {}\ncontract A is Base {}\ncontract B is Base {}\ncontract D is A, B {}",
+        );
+        assert_eq!(linearize(&diamond, "D").unwrap(), ["D", "B", "A", "Base"]);
+        assert_eq!(linearize(&diamond, "Missing").unwrap(), ["Missing"]);
+
+        let impossible = contracts("contract X {}\ncontract A is X {}\ncontract C is A, X {}");
+        assert!(linearize(&impossible, "C")
+            .unwrap_err()
+            .to_string()
+            .contains("Linearization of the inheritance graph of `C` is impossible"));
+        let cyclic = contracts("contract A is B {}\ncontract B is A {}");
+        assert!(linearize(&cyclic, "A").is_err());
+    }
+
+    const VAULT: &str = r#"
+contract Owned {
+    address owner;
+    modifier onlyOwner() { require(msg.sender == owner, "not the owner"); _; }
+    constructor(address initial) { owner = initial; }
+    function version() public virtual returns (uint256) { return 1; }
+}
+
+contract Paused is Owned {
+    bool paused;
+    function version() public virtual override returns (uint256) { return super.version() + 1; }
+}
+
+contract Vault is Owned(msg.sender), Paused {
+    uint256 total;
+    constructor(uint256 start) { total = start; }
+    function version() public override returns (uint256) { return super.version() * 10; }
+    function pause() external onlyOwner { paused = true; }
+}
+"#;
+
+    #[test]
+    fn test_flatten() {
+        let source = parse_solidity(VAULT, "Vault.sol").unwrap();
+        let (contracts, issues) = flatten(&source);
+        assert!(issues.is_empty(), "{:?}", issues);
+        let [vault] = &contracts[..] else {
+            panic!("expected only the most derived contract");
+        };
+
+        let variables: Vec<_> = vault
+            .state_variables
+            .iter()
+            .map(|v| v.name.as_str())
+            .collect();
+        assert_eq!(variables, ["owner", "paused", "total"]);
+        assert_eq!(vault.modifiers[0].name, "onlyOwner");
+        let functions: Vec<_> = vault.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            functions,
+            [
+                "constructor",
+                "version",
+                "Paused_version",
+                "Owned_version",
+                "pause"
+            ]
+        );
+
+        // super calls go to the next implementation of the linearization
+        let callee = |function: &FunctionDefinition| {
+            let Statement::Return(ReturnStatement {
+                expression: Some(Expression::BinaryOperation(operation)),
+                ..
+            }) = &function.body.as_ref().unwrap().statements[0]
+            else {
+                panic!("expected a return");
+            };
+            let Expression::FunctionCall(call) = &*operation.left else {
+                panic!("expected a call");
+            };
+            let Expression::Identifier(callee) = &*call.expression else {
+                panic!("expected a renamed call");
+            };
+            callee.name.clone()
+        };
+        assert_eq!(callee(&vault.functions[1]), "Paused_version");
+        assert_eq!(callee(&vault.functions[2]), "Owned_version");
+        assert_eq!(vault.functions[2].visibility, Visibility::Internal);
+
+        // The base constructor runs first, with the argument of `is Owned(...)`
+        let constructor = &vault.functions[0];
+        assert_eq!(constructor.parameters.len(), 1);
+        let statements = &constructor.body.as_ref().unwrap().statements;
+        assert!(matches!(
+            &statements[..],
+            [
+                Statement::VariableDeclaration(VariableDeclarationStatement {
+                    initial_value: Some(Expression::MemberAccess(_)),
+                    ..
+                }),
+                Statement::Assignment(_),
+                Statement::Assignment(_),
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_missing_base_and_arguments() {
+        let source = parse_solidity(
+            "contract Owned {\n    constructor(address initial) {}\n}\ncontract Token is ERC20, Owned {}\n",
+            "Token.sol",
+        )
+        .unwrap();
+        let (contracts, issues) = flatten(&source);
+        assert_eq!(contracts.len(), 1);
+        let descriptions: Vec<_> = issues
+            .iter()
+            .map(|issue| issue.description.as_str())
+            .collect();
+        assert_eq!(
+            descriptions,
+            [
+                "Base contract `ERC20` of `Token` is not defined in this file; its members are not merged",
+                "The arguments of the constructor of `Owned` are not given by `Token`",
+            ]
+        );
+    }
+}
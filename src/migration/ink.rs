@@ -0,0 +1,1627 @@
+//! # ink! Frontend
+//!
+//! Parses ink! contracts into the migration AST, for the converter to
+//! generate Bend-PVM from them as it does from Solidity.
+//!
+//! The `#[ink(storage)]` struct is the contract: its name and fields become
+//! the contract name and state variables. `#[ink(event)]` structs are
+//! events, other structs and enums are kept as they are, and the functions
+//! of `impl` blocks are `#[ink(constructor)]`s, external `#[ink(message)]`s
+//! or internal helpers. ink! primitives map to their Solidity types
+//! (`AccountId` is `address`, `Balance` is `uint128`), `Mapping` and `Vec`
+//! to mappings and arrays, and `Option`, `Result` and `Lazy` to the type
+//! they wrap.
+//!
+//! Bodies are lowered to Solidity idioms: `get`, `insert` and `remove` on
+//! a storage collection are index reads, writes and `delete`s,
+//! `self.env()` calls are `msg` and `block` fields, `emit_event` is
+//! `emit`, `return Err(e)` is a revert and a constructor's trailing
+//! `Self { .. }` assigns the fields. `match`, `if let` and closures are
+//! reported as unsupported.
+
+use super::ast::*;
+use super::parser::{
+    assignment_operator, binary_operator, error_at, tokenize, Dialect, Parser, Token, TokenKind,
+};
+use super::MigrationError;
+use std::collections::HashSet;
+use std::ops::{Deref, DerefMut};
+
+/// Parse ink! `source`, read from `file`
+pub fn parse_ink(source: &str, file: &str) -> Result<SoliditySource, MigrationError> {
+    let tokens = tokenize(source, file, Dialect::Rust)?;
+    InkParser {
+        parser: Parser {
+            tokens,
+            position: 0,
+            source,
+            file,
+        },
+        collections: HashSet::new(),
+        tails: HashSet::new(),
+        no_struct: false,
+    }
+    .parse_crate()
+}
+
+/// The Solidity type of an ink! or Rust primitive type
+fn elementary(name: &str) -> Option<String> {
+    let mapped = match name {
+        "AccountId" | "Address" | "H160" => "address",
+        "Balance" => "uint128",
+        "U256" => "uint256",
+        "Hash" | "H256" => "bytes32",
+        "Timestamp" | "usize" => "uint64",
+        "BlockNumber" => "uint32",
+        "isize" => "int64",
+        "bool" => "bool",
+        "String" => "string",
+        _ => {
+            let (prefix, bits) = match (name.strip_prefix('u'), name.strip_prefix('i')) {
+                (Some(bits), _) => ("uint", bits),
+                (_, Some(bits)) => ("int", bits),
+                _ => return None,
+            };
+            return matches!(bits, "8" | "16" | "32" | "64" | "128")
+                .then(|| format!("{}{}", prefix, bits));
+        }
+    };
+    Some(mapped.to_string())
+}
+
+/// Whether the attributes hold `#[ink(word)]`, alone or among others, or
+/// `#[ink::word]`
+fn is_ink(attributes: &[String], word: &str) -> bool {
+    attributes.iter().any(|attribute| {
+        attribute == &format!("ink::{}", word)
+            || attribute
+                .strip_prefix("ink(")
+                .and_then(|arguments| arguments.strip_suffix(')'))
+                .is_some_and(|arguments| arguments.split(',').any(|w| w == word))
+    })
+}
+
+/// Whether `expression` creates an empty collection or a default value,
+/// like `Mapping::default()` or `Vec::new()`
+fn is_default(expression: &Expression) -> bool {
+    matches!(
+        expression,
+        Expression::FunctionCall(call) if call.arguments.is_empty() && matches!(
+            &*call.expression,
+            Expression::MemberAccess(member) if matches!(member.member_name.as_str(), "default" | "new")
+        )
+    )
+}
+
+/// The Solidity parser's token cursor, with the ink! grammar
+struct InkParser<'a> {
+    parser: Parser<'a>,
+    /// Storage fields holding a `Mapping` or `Vec`
+    collections: HashSet<String>,
+    /// Offsets of the expressions ending a block without a `;`
+    tails: HashSet<usize>,
+    /// Whether `Name {` is a block rather than a struct literal, in the
+    /// condition of an `if` or a loop
+    no_struct: bool,
+}
+
+impl<'a> Deref for InkParser<'a> {
+    type Target = Parser<'a>;
+
+    fn deref(&self) -> &Parser<'a> {
+        &self.parser
+    }
+}
+
+impl<'a> DerefMut for InkParser<'a> {
+    fn deref_mut(&mut self) -> &mut Parser<'a> {
+        &mut self.parser
+    }
+}
+
+impl<'a> InkParser<'a> {
+    /// Parse `item`s separated by commas up to the closing `close`
+    fn list<T>(
+        &mut self,
+        close: &str,
+        mut item: impl FnMut(&mut Self) -> Result<T, MigrationError>,
+    ) -> Result<Vec<T>, MigrationError> {
+        let mut items = Vec::new();
+        while !self.at(close) {
+            items.push(item(self)?);
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect(close)?;
+        Ok(items)
+    }
+
+    fn unsupported(&self, feature: &str) -> MigrationError {
+        let token = self.peek();
+        MigrationError::UnsupportedFeature(format!(
+            "{}:{}:{}: {}",
+            self.file, token.line, token.column, feature
+        ))
+    }
+
+    /// Skip the tokens up to the matching `close` of the `open` just passed
+    fn skip_balanced(&mut self, open: &str, close: &str) -> Result<(), MigrationError> {
+        let mut depth = 1;
+        while depth > 0 {
+            if self.peek().kind == TokenKind::Eof {
+                return Err(self.unexpected(&format!("`{}`", close)));
+            }
+            let token = self.advance();
+            if token.kind == TokenKind::Punctuation && token.text == open {
+                depth += 1;
+            } else if token.kind == TokenKind::Punctuation && token.text == close {
+                depth -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Skip an item up to its `;` or the end of its `{ ... }` body
+    fn skip_item(&mut self) -> Result<(), MigrationError> {
+        loop {
+            if self.eat(";") {
+                return Ok(());
+            }
+            if self.eat("{") {
+                return self.skip_balanced("{", "}");
+            }
+            if self.peek().kind == TokenKind::Eof {
+                return Err(self.unexpected("`;`"));
+            }
+            self.advance();
+        }
+    }
+
+    /// Outer and inner attributes, as their tokens without spaces, like
+    /// `ink(message,payable)`
+    fn parse_attributes(&mut self) -> Result<Vec<String>, MigrationError> {
+        let mut attributes = Vec::new();
+        while self.eat("#") {
+            self.eat("!");
+            self.expect("[")?;
+            let mut attribute = String::new();
+            let mut depth = 0;
+            while depth > 0 || !self.at("]") {
+                if self.peek().kind == TokenKind::Eof {
+                    return Err(self.unexpected("`]`"));
+                }
+                let token = self.advance();
+                match token.text.as_str() {
+                    "[" | "(" => depth += 1,
+                    "]" | ")" => depth -= 1,
+                    _ => {}
+                }
+                attribute.push_str(&token.text);
+            }
+            self.advance();
+            attributes.push(attribute);
+        }
+        Ok(attributes)
+    }
+
+    /// `pub`, `pub(crate)` and the like, which are dropped
+    fn skip_visibility(&mut self) -> Result<(), MigrationError> {
+        if self.eat("pub") && self.eat("(") {
+            self.skip_balanced("(", ")")?;
+        }
+        Ok(())
+    }
+
+    fn parse_crate(&mut self) -> Result<SoliditySource, MigrationError> {
+        let start = self.peek().clone();
+        let mut contract =
+            ContractDefinition::new(String::new(), ContractKind::Contract, self.location(&start));
+        self.parse_items(&mut contract, None)?;
+        if contract.name.is_empty() {
+            return Err(self.unexpected("an `#[ink(storage)]` struct"));
+        }
+        contract.location = self.location(&start);
+        let mut source = SoliditySource::new(self.location(&start));
+        source.contracts.push(contract);
+        Ok(source)
+    }
+
+    /// The items of a module, up to its closing `}` or the end of the file
+    fn parse_items(
+        &mut self,
+        contract: &mut ContractDefinition,
+        close: Option<&str>,
+    ) -> Result<(), MigrationError> {
+        loop {
+            let attributes = self.parse_attributes()?;
+            match close {
+                Some(close) if self.eat(close) => return Ok(()),
+                None if self.peek().kind == TokenKind::Eof => return Ok(()),
+                _ => {}
+            }
+            self.skip_visibility()?;
+            match self.peek().text.as_str() {
+                "mod" => {
+                    self.advance();
+                    self.identifier()?;
+                    if self.eat(";") {
+                        continue;
+                    }
+                    self.expect("{")?;
+                    match attributes.iter().any(|a| a.starts_with("cfg(")) {
+                        true => self.skip_balanced("{", "}")?,
+                        false => self.parse_items(contract, Some("}"))?,
+                    }
+                }
+                "struct" => self.parse_struct(&attributes, contract)?,
+                "enum" => contract.enums.push(self.parse_enum()?),
+                "impl" => self.parse_impl(contract)?,
+                "fn" => {
+                    let function = self.parse_function(&attributes)?;
+                    contract.functions.push(function);
+                }
+                "const" | "static" => contract.state_variables.push(self.parse_constant()?),
+                "use" | "type" | "extern" | "trait" | "macro_rules" => self.skip_item()?,
+                _ => return Err(self.unexpected("an item")),
+            }
+        }
+    }
+
+    fn parse_struct(
+        &mut self,
+        attributes: &[String],
+        contract: &mut ContractDefinition,
+    ) -> Result<(), MigrationError> {
+        let start = self.expect("struct")?;
+        let name = self.identifier()?;
+        if !self.at("{") {
+            // Unit and tuple structs carry no fields to migrate
+            return self.skip_item();
+        }
+        self.advance();
+        let fields = self.list("}", |parser| {
+            let attributes = parser.parse_attributes()?;
+            parser.skip_visibility()?;
+            let start = parser.peek().clone();
+            let name = parser.identifier()?;
+            parser.expect(":")?;
+            let type_name = parser.parse_type()?;
+            Ok(VariableDeclaration {
+                name: Some(name),
+                type_name,
+                storage_location: StorageLocation::Default,
+                indexed: is_ink(&attributes, "topic"),
+                location: parser.location(&start),
+            })
+        })?;
+        let location = self.location(&start);
+
+        if is_ink(attributes, "storage") {
+            contract.name = name;
+            for field in fields {
+                let name = field.name.unwrap_or_default();
+                let collection = match &field.type_name {
+                    TypeName::Mapping(_) => true,
+                    TypeName::Array(array) => array.length.is_none(),
+                    _ => false,
+                };
+                if collection {
+                    self.collections.insert(name.clone());
+                }
+                contract.state_variables.push(StateVariable {
+                    name,
+                    type_name: field.type_name,
+                    visibility: Visibility::Internal,
+                    mutability: Mutability::Mutable,
+                    value: None,
+                    is_constant: false,
+                    overrides: None,
+                    location: field.location,
+                });
+            }
+        } else if is_ink(attributes, "event") {
+            contract.events.push(EventDefinition {
+                name,
+                parameters: fields,
+                anonymous: false,
+                location,
+            });
+        } else {
+            contract.structs.push(StructDefinition {
+                name,
+                members: fields,
+                location,
+            });
+        }
+        Ok(())
+    }
+
+    /// An enum, whose variants are kept without their fields
+    fn parse_enum(&mut self) -> Result<EnumDefinition, MigrationError> {
+        let start = self.expect("enum")?;
+        let name = self.identifier()?;
+        self.expect("{")?;
+        let values = self.list("}", |parser| {
+            parser.parse_attributes()?;
+            let value = parser.identifier()?;
+            if parser.eat("(") {
+                parser.skip_balanced("(", ")")?;
+            } else if parser.eat("{") {
+                parser.skip_balanced("{", "}")?;
+            } else if parser.eat("=") {
+                parser.parse_expression()?;
+            }
+            Ok(value)
+        })?;
+        Ok(EnumDefinition {
+            name,
+            values,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_constant(&mut self) -> Result<StateVariable, MigrationError> {
+        let start = self.advance();
+        let name = self.identifier()?;
+        self.expect(":")?;
+        let type_name = self.parse_type()?;
+        self.expect("=")?;
+        let value = self.parse_expression()?;
+        self.expect(";")?;
+        Ok(StateVariable {
+            name,
+            type_name,
+            visibility: Visibility::Internal,
+            mutability: Mutability::Constant,
+            value: Some(value),
+            is_constant: true,
+            overrides: None,
+            location: self.location(&start),
+        })
+    }
+
+    /// The functions of an `impl` block; those of a trait implementation
+    /// only when they are constructors or messages
+    fn parse_impl(&mut self, contract: &mut ContractDefinition) -> Result<(), MigrationError> {
+        self.expect("impl")?;
+        self.parse_type()?;
+        let trait_impl = self.eat("for");
+        if trait_impl {
+            self.parse_type()?;
+        }
+        self.expect("{")?;
+        loop {
+            let attributes = self.parse_attributes()?;
+            if self.eat("}") {
+                return Ok(());
+            }
+            self.skip_visibility()?;
+            match self.peek().text.as_str() {
+                "fn" => {
+                    let function = self.parse_function(&attributes)?;
+                    let ink = is_ink(&attributes, "constructor") || is_ink(&attributes, "message");
+                    if ink || !trait_impl {
+                        contract.functions.push(function);
+                    }
+                }
+                "const" => contract.state_variables.push(self.parse_constant()?),
+                "type" => self.skip_item()?,
+                _ => return Err(self.unexpected("a function")),
+            }
+        }
+    }
+
+    fn parse_function(
+        &mut self,
+        attributes: &[String],
+    ) -> Result<FunctionDefinition, MigrationError> {
+        let start = self.expect("fn")?;
+        let name = self.identifier()?;
+        if self.at("<") {
+            return Err(self.unsupported("generic functions"));
+        }
+        self.expect("(")?;
+        // `Some(mutable)` for a `&self` or `&mut self` receiver
+        let mut receiver = None;
+        let parameters = self.list(")", |parser| {
+            let reference = parser.eat("&");
+            let mutable = parser.eat("mut");
+            if parser.eat("self") {
+                receiver = Some(mutable || !reference);
+                return Ok(None);
+            }
+            let start = parser.peek().clone();
+            let name = parser.identifier()?;
+            parser.expect(":")?;
+            let type_name = parser.parse_type()?;
+            Ok(Some(VariableDeclaration {
+                name: Some(name),
+                type_name,
+                storage_location: StorageLocation::Default,
+                indexed: false,
+                location: parser.location(&start),
+            }))
+        })?;
+        let return_start = self.peek().clone();
+        let return_types = match self.eat("->") {
+            true if self.eat("Self") => Vec::new(),
+            true => self.parse_types()?,
+            false => Vec::new(),
+        };
+        let return_parameters = return_types
+            .into_iter()
+            .map(|type_name| VariableDeclaration {
+                name: None,
+                type_name,
+                storage_location: StorageLocation::Default,
+                indexed: false,
+                location: self.location(&return_start),
+            })
+            .collect();
+
+        let is_constructor = is_ink(attributes, "constructor");
+        self.expect("{")?;
+        let body = self.parse_block(if is_constructor {
+            Tail::Fields
+        } else {
+            Tail::Return
+        })?;
+
+        let visibility = match is_constructor || is_ink(attributes, "message") {
+            true => Visibility::External,
+            false => Visibility::Internal,
+        };
+        let state_mutability = match receiver {
+            _ if is_ink(attributes, "payable") => StateMutability::Payable,
+            Some(false) => StateMutability::View,
+            Some(true) => StateMutability::NonPayable,
+            None if is_constructor => StateMutability::NonPayable,
+            None => StateMutability::Pure,
+        };
+        Ok(FunctionDefinition {
+            name,
+            parameters: parameters.into_iter().flatten().collect(),
+            return_parameters,
+            body: Some(body),
+            visibility,
+            state_mutability,
+            virtual_flag: false,
+            override_specifiers: Vec::new(),
+            modifiers: Vec::new(),
+            is_constructor,
+            is_fallback: false,
+            is_receive: false,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_type(&mut self) -> Result<TypeName, MigrationError> {
+        let start = self.peek().clone();
+        match self.parse_types()?.as_slice() {
+            [type_name] => Ok(type_name.clone()),
+            _ => Err(error_at(
+                self.file,
+                start.line,
+                start.column,
+                "expected a single type".into(),
+            )),
+        }
+    }
+
+    /// The close of a generic argument list, splitting a `>>`
+    fn close_generics(&mut self) -> Result<(), MigrationError> {
+        if self.at(">>") {
+            let position = self.position;
+            let token = &mut self.parser.tokens[position];
+            token.text = ">".to_string();
+            token.start += 1;
+            token.column += 1;
+            return Ok(());
+        }
+        self.expect(">").map(|_| ())
+    }
+
+    /// A type as the Solidity types it holds: none for `()`, the elements
+    /// of a tuple, and the wrapped type of `Option`, `Result` and `Lazy`
+    fn parse_types(&mut self) -> Result<Vec<TypeName>, MigrationError> {
+        let start = self.peek().clone();
+        if self.eat("&") {
+            self.eat("mut");
+        }
+        if self.eat("(") {
+            let elements = self.list(")", Self::parse_types)?;
+            return Ok(elements.into_iter().flatten().collect());
+        }
+        if self.eat("[") {
+            let base_type = Box::new(self.parse_type()?);
+            self.expect(";")?;
+            let length = self.parse_expression()?;
+            self.expect("]")?;
+            return Ok(vec![TypeName::Array(Box::new(ArrayTypeName {
+                base_type,
+                length: Some(Box::new(length)),
+                location: self.location(&start),
+            }))]);
+        }
+
+        let mut name = self.identifier()?;
+        while self.eat("::") {
+            name = self.identifier()?;
+        }
+        let mut arguments = Vec::new();
+        if self.eat("<") {
+            while !self.at(">") && !self.at(">>") {
+                arguments.push(self.parse_types()?);
+                if !self.eat(",") {
+                    break;
+                }
+            }
+            self.close_generics()?;
+        }
+        let location = self.location(&start);
+
+        let type_name = match name.as_str() {
+            "Option" | "Result" | "Lazy" | "Box" => {
+                return Ok(arguments.into_iter().next().unwrap_or_default());
+            }
+            "Mapping" | "StorageMap" | "HashMap" | "BTreeMap" if arguments.len() == 2 => {
+                let mut arguments = arguments.into_iter();
+                let keys = arguments.next().unwrap_or_default();
+                let value = arguments.next().unwrap_or_default();
+                let [value] = <[TypeName; 1]>::try_from(value).map_err(|_| {
+                    error_at(
+                        self.file,
+                        start.line,
+                        start.column,
+                        "expected a single value type".into(),
+                    )
+                })?;
+                // A tuple key is a mapping per element
+                keys.into_iter().rev().fold(value, |value_type, key_type| {
+                    TypeName::Mapping(MappingTypeName {
+                        key_type: Box::new(key_type),
+                        value_type: Box::new(value_type),
+                        location: location.clone(),
+                    })
+                })
+            }
+            "Vec" | "StorageVec" if arguments.len() == 1 => match arguments.remove(0).as_slice() {
+                [TypeName::Elementary(element)] if element.name == "uint8" => {
+                    TypeName::Elementary(ElementaryTypeName {
+                        name: "bytes".to_string(),
+                        location,
+                    })
+                }
+                [element] => TypeName::Array(Box::new(ArrayTypeName {
+                    base_type: Box::new(element.clone()),
+                    length: None,
+                    location,
+                })),
+                _ => return Err(self.unsupported("vectors of tuples")),
+            },
+            _ => match elementary(&name) {
+                Some(name) => TypeName::Elementary(ElementaryTypeName { name, location }),
+                None => TypeName::UserDefined(UserDefinedTypeName {
+                    name,
+                    type_arguments: arguments.into_iter().flatten().collect(),
+                    location,
+                }),
+            },
+        };
+        Ok(vec![type_name])
+    }
+
+    /// A `{ ... }` block, whose `{` was just passed, with its trailing
+    /// expression handled as `tail` says
+    fn parse_block(&mut self, tail: Tail) -> Result<Block, MigrationError> {
+        let start = self.peek().clone();
+        let mut statements = Vec::new();
+        while !self.eat("}") {
+            if self.peek().kind == TokenKind::Eof {
+                return Err(self.unexpected("`}`"));
+            }
+            if self.eat(";") {
+                continue;
+            }
+            statements.extend(self.parse_statement()?);
+        }
+        if tail != Tail::Keep {
+            if let Some(last) = statements.pop() {
+                statements.extend(self.tail_statements(last, tail));
+            }
+        }
+        Ok(Block {
+            statements,
+            location: self.location(&start),
+        })
+    }
+
+    /// The statements a block's last statement stands for when the block
+    /// ends a function: its trailing expression is the return value, or,
+    /// in a constructor, the fields to initialize
+    fn tail_statements(&self, statement: Statement, tail: Tail) -> Vec<Statement> {
+        match statement {
+            Statement::Expression(expression)
+                if self.tails.contains(&expression.location.start) =>
+            {
+                self.returned(expression.expression, tail)
+            }
+            Statement::If(mut branch) => {
+                for body in std::iter::once(&mut branch.true_body).chain(&mut branch.false_body) {
+                    let statements = match &mut **body {
+                        Statement::Block(block) => &mut block.statements,
+                        _ => continue,
+                    };
+                    if let Some(last) = statements.pop() {
+                        statements.extend(self.tail_statements(last, tail));
+                    }
+                }
+                vec![Statement::If(branch)]
+            }
+            statement => vec![statement],
+        }
+    }
+
+    /// The statements returning `value`: `Ok(v)` returns `v`, `Err(e)`
+    /// reverts with `e` and `()` returns nothing
+    fn returned(&self, value: Expression, tail: Tail) -> Vec<Statement> {
+        let location = match &value {
+            Expression::FunctionCall(call) => call.location.clone(),
+            Expression::Tuple(tuple) => tuple.location.clone(),
+            Expression::Identifier(identifier) => identifier.location.clone(),
+            Expression::Literal(literal) => literal.location.clone(),
+            Expression::BinaryOperation(operation) => operation.location.clone(),
+            Expression::IndexAccess(access) => access.location.clone(),
+            Expression::MemberAccess(access) => access.location.clone(),
+            _ => self.location(self.peek()),
+        };
+        match value {
+            Expression::Tuple(tuple) if tuple.elements.is_empty() => Vec::new(),
+            Expression::FunctionCall(mut call) => {
+                let callee = match &*call.expression {
+                    Expression::Identifier(identifier) => identifier.name.as_str(),
+                    _ => "",
+                };
+                match (callee, call.arguments.len()) {
+                    ("Ok", 1) => self.returned(call.arguments.remove(0), tail),
+                    ("Err", 1) => vec![Statement::Revert(RevertStatement {
+                        error_call: Some(call.arguments.remove(0)),
+                        location,
+                    })],
+                    ("Self", _) if tail == Tail::Fields => call
+                        .names
+                        .into_iter()
+                        .zip(call.arguments)
+                        .filter(|(field, value)| {
+                            // Collections filled through a local of the
+                            // same name are already in storage
+                            let filled = self.collections.contains(field)
+                                && matches!(value, Expression::Identifier(id) if &id.name == field);
+                            !is_default(value) && !filled
+                        })
+                        .map(|(field, value)| {
+                            Statement::Assignment(AssignmentStatement {
+                                assignment: Assignment {
+                                    operator: AssignmentOperator::Assign,
+                                    left: Box::new(Expression::Identifier(Identifier {
+                                        name: field,
+                                        location: location.clone(),
+                                    })),
+                                    right: Box::new(value),
+                                    location: location.clone(),
+                                },
+                                location: location.clone(),
+                            })
+                        })
+                        .collect(),
+                    _ => vec![Statement::Return(ReturnStatement {
+                        expression: Some(Expression::FunctionCall(call)),
+                        location,
+                    })],
+                }
+            }
+            value => vec![Statement::Return(ReturnStatement {
+                expression: Some(value),
+                location,
+            })],
+        }
+    }
+
+    /// A statement, or nothing for a `let` initializing a storage
+    /// collection the constructor then fills
+    fn parse_statement(&mut self) -> Result<Option<Statement>, MigrationError> {
+        let start = self.peek().clone();
+        let statement = match start.text.as_str() {
+            "let" => return self.parse_let(),
+            "if" => self.parse_if()?,
+            "while" => {
+                self.advance();
+                let condition = self.parse_condition()?;
+                self.expect("{")?;
+                let body = self.parse_block(Tail::Keep)?;
+                Statement::While(WhileStatement {
+                    condition,
+                    body: Box::new(Statement::Block(body)),
+                    location: self.location(&start),
+                })
+            }
+            "loop" => {
+                self.advance();
+                self.expect("{")?;
+                let body = self.parse_block(Tail::Keep)?;
+                Statement::While(WhileStatement {
+                    condition: self.literal(&start, "true".to_string(), "bool"),
+                    body: Box::new(Statement::Block(body)),
+                    location: self.location(&start),
+                })
+            }
+            "for" => self.parse_for()?,
+            "match" => return Err(self.unsupported("`match`")),
+            "return" => {
+                self.advance();
+                let value = match self.at(";") || self.at("}") {
+                    true => Expression::Tuple(TupleExpression {
+                        elements: Vec::new(),
+                        location: self.location(&start),
+                    }),
+                    false => self.parse_expression()?,
+                };
+                self.eat(";");
+                let mut statements = self.returned(value, Tail::Return);
+                return Ok(Some(match statements.len() {
+                    0 => Statement::Return(ReturnStatement {
+                        expression: None,
+                        location: self.location(&start),
+                    }),
+                    _ => statements.remove(0),
+                }));
+            }
+            "break" | "continue" => {
+                self.advance();
+                self.eat(";");
+                let location = self.location(&start);
+                return Ok(Some(match start.text.as_str() {
+                    "break" => Statement::Break(BreakStatement { location }),
+                    _ => Statement::Continue(ContinueStatement { location }),
+                }));
+            }
+            "{" => {
+                self.advance();
+                Statement::Block(self.parse_block(Tail::Keep)?)
+            }
+            _ => {
+                let left = self.parse_expression()?;
+                let operator = match self.peek().kind {
+                    TokenKind::Punctuation => assignment_operator(&self.peek().text),
+                    _ => None,
+                };
+                if let Some(operator) = operator {
+                    self.advance();
+                    let right = self.parse_expression()?;
+                    self.expect(";")?;
+                    return Ok(Some(Statement::Assignment(AssignmentStatement {
+                        assignment: Assignment {
+                            operator,
+                            left: Box::new(left),
+                            right: Box::new(right),
+                            location: self.location(&start),
+                        },
+                        location: self.location(&start),
+                    })));
+                }
+                if !self.eat(";") {
+                    if !self.at("}") {
+                        return Err(self.unexpected("`;`"));
+                    }
+                    self.tails.insert(start.start);
+                }
+                return Ok(Some(self.expression_statement(left, &start)));
+            }
+        };
+        Ok(Some(statement))
+    }
+
+    /// The statement of an expression, for the calls standing for one:
+    /// `insert` is an assignment, `emit_event` an `emit` and `panic!` a
+    /// revert
+    fn expression_statement(&self, expression: Expression, start: &Token) -> Statement {
+        let location = self.location(start);
+        match expression {
+            Expression::Assignment(assignment) => Statement::Assignment(AssignmentStatement {
+                assignment,
+                location,
+            }),
+            Expression::FunctionCall(mut call)
+                if call.arguments.len() == 1
+                    && matches!(
+                        &*call.expression,
+                        Expression::Identifier(id) if id.name == "emit_event" || id.name == "revert"
+                    ) =>
+            {
+                let argument = call.arguments.remove(0);
+                match &*call.expression {
+                    Expression::Identifier(id) if id.name == "emit_event" => {
+                        Statement::Emit(EmitStatement {
+                            event: argument,
+                            location,
+                        })
+                    }
+                    _ => Statement::Revert(RevertStatement {
+                        error_call: Some(argument),
+                        location,
+                    }),
+                }
+            }
+            expression => Statement::Expression(ExpressionStatement {
+                expression,
+                location,
+            }),
+        }
+    }
+
+    /// `let name: Type = value;`, with the type left to inference when it
+    /// isn't written
+    fn parse_let(&mut self) -> Result<Option<Statement>, MigrationError> {
+        let start = self.expect("let")?;
+        let names = match self.eat("(") {
+            true => self.list(")", |parser| {
+                parser.eat("mut");
+                parser.identifier()
+            })?,
+            false => {
+                self.eat("mut");
+                vec![self.identifier()?]
+            }
+        };
+        let type_name = match self.eat(":") {
+            true => self.parse_type()?,
+            false => TypeName::Elementary(ElementaryTypeName {
+                name: "var".to_string(),
+                location: self.location(&start),
+            }),
+        };
+        let initial_value = match self.eat("=") {
+            true => Some(self.parse_expression()?),
+            false => None,
+        };
+        if self.at("else") {
+            return Err(self.unsupported("`let`-`else`"));
+        }
+        self.expect(";")?;
+        // `let mut balances = Mapping::default();` in a constructor fills
+        // the storage field of the same name
+        if let ([name], Some(value)) = (names.as_slice(), &initial_value) {
+            if self.collections.contains(name) && is_default(value) {
+                return Ok(None);
+            }
+        }
+        let declarations = names
+            .into_iter()
+            .map(|name| VariableDeclaration {
+                name: Some(name),
+                type_name: type_name.clone(),
+                storage_location: StorageLocation::Default,
+                indexed: false,
+                location: self.location(&start),
+            })
+            .collect();
+        Ok(Some(Statement::VariableDeclaration(
+            VariableDeclarationStatement {
+                declarations,
+                initial_value,
+                location: self.location(&start),
+            },
+        )))
+    }
+
+    /// The condition of an `if` or a loop, where `Name {` opens the body
+    fn parse_condition(&mut self) -> Result<Expression, MigrationError> {
+        let no_struct = std::mem::replace(&mut self.no_struct, true);
+        let condition = self.parse_expression();
+        self.no_struct = no_struct;
+        condition
+    }
+
+    fn parse_if(&mut self) -> Result<Statement, MigrationError> {
+        let start = self.expect("if")?;
+        if self.at("let") {
+            return Err(self.unsupported("`if let`"));
+        }
+        let condition = self.parse_condition()?;
+        self.expect("{")?;
+        let true_body = Statement::Block(self.parse_block(Tail::Keep)?);
+        let false_body = match self.eat("else") {
+            true if self.at("if") => Some(Box::new(self.parse_if()?)),
+            true => {
+                self.expect("{")?;
+                Some(Box::new(Statement::Block(self.parse_block(Tail::Keep)?)))
+            }
+            false => None,
+        };
+        Ok(Statement::If(IfStatement {
+            condition,
+            true_body: Box::new(true_body),
+            false_body,
+            location: self.location(&start),
+        }))
+    }
+
+    /// `for i in start..end { ... }`, as a counting loop
+    fn parse_for(&mut self) -> Result<Statement, MigrationError> {
+        let start = self.expect("for")?;
+        let variable = self.identifier()?;
+        self.expect("in")?;
+        let no_struct = std::mem::replace(&mut self.no_struct, true);
+        let from = self.parse_binary(1);
+        self.no_struct = no_struct;
+        let from = from?;
+        let operator = match (self.eat(".."), self.eat("..=")) {
+            (true, _) => BinaryOperator::Less,
+            (_, true) => BinaryOperator::LessEqual,
+            _ => return Err(self.unsupported("`for` over an iterator")),
+        };
+        let end = self.parse_condition()?;
+        self.expect("{")?;
+        let body = self.parse_block(Tail::Keep)?;
+        let location = self.location(&start);
+        let counter = Expression::Identifier(Identifier {
+            name: variable.clone(),
+            location: location.clone(),
+        });
+        Ok(Statement::For(ForStatement {
+            initialization: Some(Box::new(Statement::VariableDeclaration(
+                VariableDeclarationStatement {
+                    declarations: vec![VariableDeclaration {
+                        name: Some(variable),
+                        type_name: TypeName::Elementary(ElementaryTypeName {
+                            name: "var".to_string(),
+                            location: location.clone(),
+                        }),
+                        storage_location: StorageLocation::Default,
+                        indexed: false,
+                        location: location.clone(),
+                    }],
+                    initial_value: Some(from),
+                    location: location.clone(),
+                },
+            ))),
+            condition: Some(Expression::BinaryOperation(BinaryOperation {
+                operator,
+                left: Box::new(counter.clone()),
+                right: Box::new(end),
+                location: location.clone(),
+            })),
+            iteration: Some(Box::new(Statement::Assignment(AssignmentStatement {
+                assignment: Assignment {
+                    operator: AssignmentOperator::AddAssign,
+                    left: Box::new(counter),
+                    right: Box::new(self.literal(&start, "1".to_string(), "number")),
+                    location: location.clone(),
+                },
+                location: location.clone(),
+            }))),
+            body: Box::new(Statement::Block(body)),
+            location,
+        }))
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression, MigrationError> {
+        self.parse_binary(1)
+    }
+
+    fn parse_binary(&mut self, precedence: u8) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let mut left = self.parse_cast()?;
+        while let Some((operator, level)) = binary_operator(&self.peek().text)
+            .filter(|(_, level)| *level >= precedence && self.peek().kind == TokenKind::Punctuation)
+        {
+            self.advance();
+            let right = self.parse_binary(level + 1)?;
+            left = Expression::BinaryOperation(BinaryOperation {
+                operator,
+                left: Box::new(left),
+                right: Box::new(right),
+                location: self.location(&start),
+            });
+        }
+        Ok(left)
+    }
+
+    /// `value as Type`
+    fn parse_cast(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let mut expression = self.parse_unary()?;
+        while self.eat("as") {
+            let type_name = self.parse_type()?;
+            expression = Expression::TypeConversion(Box::new(TypeConversion {
+                type_name: Box::new(type_name),
+                expression: Box::new(expression),
+                location: self.location(&start),
+            }));
+        }
+        Ok(expression)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let operator = match start.text.as_str() {
+            _ if start.kind != TokenKind::Punctuation => return self.parse_postfix(),
+            // References and dereferences are dropped
+            "&" | "*" => {
+                self.advance();
+                self.eat("mut");
+                return self.parse_unary();
+            }
+            "!" => UnaryOperator::Not,
+            "-" => UnaryOperator::Neg,
+            _ => return self.parse_postfix(),
+        };
+        self.advance();
+        let operand = self.parse_unary()?;
+        Ok(Expression::UnaryOperation(UnaryOperation {
+            operator,
+            operand: Box::new(operand),
+            is_prefix: true,
+            location: self.location(&start),
+        }))
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let mut expression = self.parse_primary()?;
+        loop {
+            if self.eat("?") {
+                // Errors propagate as reverts of the callee
+                continue;
+            }
+            if self.eat(".") {
+                let member_name = self.identifier()?;
+                if self.eat("(") {
+                    let arguments = self.list(")", Self::parse_expression)?;
+                    expression = self.method_call(&start, expression, member_name, arguments);
+                    continue;
+                }
+                expression = match expression {
+                    // `self.x` is the state variable `x`
+                    Expression::Identifier(identifier) if identifier.name == "this" => {
+                        Expression::Identifier(Identifier {
+                            name: member_name,
+                            location: self.location(&start),
+                        })
+                    }
+                    expression => Expression::MemberAccess(MemberAccess {
+                        expression: Box::new(expression),
+                        member_name,
+                        location: self.location(&start),
+                    }),
+                };
+            } else if self.eat("(") {
+                let arguments = self.list(")", Self::parse_expression)?;
+                expression = self.call(&start, expression, arguments, Vec::new());
+            } else if self.eat("[") {
+                let index = self.parse_expression()?;
+                self.expect("]")?;
+                expression = Expression::IndexAccess(IndexAccess {
+                    base: Box::new(expression),
+                    index: Box::new(index),
+                    location: self.location(&start),
+                });
+            } else {
+                return Ok(expression);
+            }
+        }
+    }
+
+    fn call(
+        &self,
+        start: &Token,
+        callee: Expression,
+        arguments: Vec<Expression>,
+        names: Vec<String>,
+    ) -> Expression {
+        Expression::FunctionCall(FunctionCall {
+            expression: Box::new(callee),
+            arguments,
+            names,
+            options: Vec::new(),
+            location: self.location(start),
+        })
+    }
+
+    /// `receiver.method(arguments)`, lowering storage collection accesses,
+    /// environment queries and `Option` adapters
+    fn method_call(
+        &self,
+        start: &Token,
+        receiver: Expression,
+        method: String,
+        mut arguments: Vec<Expression>,
+    ) -> Expression {
+        let location = self.location(start);
+        let member = |expression: Expression, member_name: &str| {
+            Expression::MemberAccess(MemberAccess {
+                expression: Box::new(expression),
+                member_name: member_name.to_string(),
+                location: location.clone(),
+            })
+        };
+        let identifier = |name: &str| {
+            Expression::Identifier(Identifier {
+                name: name.to_string(),
+                location: location.clone(),
+            })
+        };
+
+        // `self.env().caller()` and the like
+        let environment = matches!(
+            &receiver,
+            Expression::FunctionCall(call) if call.arguments.is_empty()
+                && matches!(&*call.expression, Expression::Identifier(id) if id.name == "env")
+        );
+        if environment {
+            match method.as_str() {
+                "caller" => return member(identifier("msg"), "sender"),
+                "transferred_value" => return member(identifier("msg"), "value"),
+                "block_timestamp" => return member(identifier("block"), "timestamp"),
+                "block_number" => return member(identifier("block"), "number"),
+                "account_id" | "address" => return identifier("this"),
+                "balance" => return member(identifier("this"), "balance"),
+                "emit_event" => {
+                    return self.call(start, identifier("emit_event"), arguments, Vec::new())
+                }
+                _ => {}
+            }
+        }
+
+        // `self.helper(..)` calls a function of the contract
+        if matches!(&receiver, Expression::Identifier(id) if id.name == "this") {
+            return self.call(start, identifier(&method), arguments, Vec::new());
+        }
+
+        let collection = matches!(
+            &receiver,
+            Expression::Identifier(id) if self.collections.contains(&id.name)
+        );
+        let entry = |receiver: Expression, index: Expression| {
+            Expression::IndexAccess(IndexAccess {
+                base: Box::new(receiver),
+                index: Box::new(index),
+                location: location.clone(),
+            })
+        };
+        match (method.as_str(), arguments.len()) {
+            ("get", 1) if collection => entry(receiver, arguments.remove(0)),
+            ("insert" | "set", 2) if collection => {
+                let index = arguments.remove(0);
+                Expression::Assignment(Assignment {
+                    operator: AssignmentOperator::Assign,
+                    left: Box::new(entry(receiver, index)),
+                    right: Box::new(arguments.remove(0)),
+                    location: location.clone(),
+                })
+            }
+            ("remove" | "take", 1) if collection => Expression::UnaryOperation(UnaryOperation {
+                operator: UnaryOperator::Delete,
+                operand: Box::new(entry(receiver, arguments.remove(0))),
+                is_prefix: true,
+                location: location.clone(),
+            }),
+            ("len", 0) => member(receiver, "length"),
+            ("unwrap" | "unwrap_or_default" | "expect" | "clone" | "into" | "to_owned", _) => {
+                receiver
+            }
+            ("unwrap_or", 1) if matches!(receiver, Expression::IndexAccess(_)) => receiver,
+            _ => self.call(start, member(receiver, &method), arguments, Vec::new()),
+        }
+    }
+
+    /// A macro invocation `name!(...)`, for the assertions and `vec!`
+    fn parse_macro(&mut self, start: &Token) -> Result<Expression, MigrationError> {
+        let name = start.text.as_str();
+        let close = match self.peek().text.as_str() {
+            "(" => ")",
+            "[" => "]",
+            _ => return Err(self.unexpected("`(`")),
+        };
+        self.advance();
+        let mut arguments = self.list(close, Self::parse_expression)?;
+        let location = self.location(start);
+        let identifier = |name: &str| {
+            Expression::Identifier(Identifier {
+                name: name.to_string(),
+                location: location.clone(),
+            })
+        };
+        let callee = match name {
+            "vec" => {
+                return Ok(Expression::ArrayLiteral(ArrayLiteral {
+                    elements: arguments,
+                    location,
+                }))
+            }
+            "assert" => "require",
+            "assert_eq" | "assert_ne" if arguments.len() >= 2 => {
+                let left = arguments.remove(0);
+                let right = arguments.remove(0);
+                let operator = match name {
+                    "assert_eq" => BinaryOperator::Equal,
+                    _ => BinaryOperator::NotEqual,
+                };
+                arguments.insert(
+                    0,
+                    Expression::BinaryOperation(BinaryOperation {
+                        operator,
+                        left: Box::new(left),
+                        right: Box::new(right),
+                        location: location.clone(),
+                    }),
+                );
+                arguments.truncate(2);
+                "require"
+            }
+            "panic" | "unreachable" | "todo" | "unimplemented" => {
+                arguments.truncate(1);
+                if arguments.is_empty() {
+                    arguments.push(self.literal(start, name.to_string(), "string"));
+                }
+                "revert"
+            }
+            _ => {
+                return Err(MigrationError::UnsupportedFeature(format!(
+                    "{}:{}:{}: the `{}!` macro",
+                    self.file, start.line, start.column, name
+                )))
+            }
+        };
+        Ok(self.call(start, identifier(callee), arguments, Vec::new()))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        match start.kind {
+            TokenKind::Number => {
+                self.advance();
+                // A type suffix, as in `1_000u128`
+                let suffix = self.peek();
+                if suffix.kind == TokenKind::Identifier
+                    && suffix.start == start.end
+                    && elementary(&suffix.text).is_some()
+                {
+                    self.advance();
+                }
+                return Ok(self.literal(&start, start.text.replace('_', ""), "number"));
+            }
+            TokenKind::String | TokenKind::HexString => {
+                self.advance();
+                return Ok(self.literal(&start, start.text.clone(), "string"));
+            }
+            TokenKind::Eof => return Err(self.unexpected("an expression")),
+            TokenKind::Identifier | TokenKind::Punctuation => {}
+        }
+
+        match start.text.as_str() {
+            "(" => {
+                self.advance();
+                let no_struct = std::mem::replace(&mut self.no_struct, false);
+                let elements = self.list(")", Self::parse_expression);
+                self.no_struct = no_struct;
+                let mut elements = elements?;
+                if elements.len() == 1 && !self.tokens[self.position - 2].text.eq(",") {
+                    return Ok(elements.remove(0));
+                }
+                Ok(Expression::Tuple(TupleExpression {
+                    elements,
+                    location: self.location(&start),
+                }))
+            }
+            "[" => {
+                self.advance();
+                let elements = self.list("]", Self::parse_expression)?;
+                Ok(Expression::ArrayLiteral(ArrayLiteral {
+                    elements,
+                    location: self.location(&start),
+                }))
+            }
+            "|" | "||" | "move" => Err(self.unsupported("closures")),
+            "match" => Err(self.unsupported("`match`")),
+            "true" | "false" if start.kind == TokenKind::Identifier => {
+                self.advance();
+                Ok(self.literal(&start, start.text.clone(), "bool"))
+            }
+            _ if start.kind == TokenKind::Identifier => {
+                self.advance();
+                if self.at("!") {
+                    self.advance();
+                    return self.parse_macro(&start);
+                }
+                let mut expression = Expression::Identifier(Identifier {
+                    name: match start.text.as_str() {
+                        "self" => "this".to_string(),
+                        name => name.to_string(),
+                    },
+                    location: self.location(&start),
+                });
+                // Paths: `Self::x` is the associated item `x`, and
+                // `Type::x` a member of the type
+                while self.eat("::") {
+                    if self.eat("<") {
+                        while !self.at(">") && !self.at(">>") {
+                            self.parse_types()?;
+                            if !self.eat(",") {
+                                break;
+                            }
+                        }
+                        self.close_generics()?;
+                        continue;
+                    }
+                    let member_name = self.identifier()?;
+                    expression = match expression {
+                        Expression::Identifier(identifier) if identifier.name == "Self" => {
+                            Expression::Identifier(Identifier {
+                                name: member_name,
+                                location: self.location(&start),
+                            })
+                        }
+                        expression => Expression::MemberAccess(MemberAccess {
+                            expression: Box::new(expression),
+                            member_name,
+                            location: self.location(&start),
+                        }),
+                    };
+                }
+                if self.at("{") && !self.no_struct {
+                    return self.parse_struct_literal(&start, expression);
+                }
+                Ok(expression)
+            }
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+
+    /// `Name { field: value, shorthand }`, as a call with named arguments
+    fn parse_struct_literal(
+        &mut self,
+        start: &Token,
+        name: Expression,
+    ) -> Result<Expression, MigrationError> {
+        self.expect("{")?;
+        let mut names = Vec::new();
+        let arguments = self.list("}", |parser| {
+            let field = parser.peek().clone();
+            let name = parser.identifier()?;
+            names.push(name.clone());
+            match parser.eat(":") {
+                true => parser.parse_expression(),
+                false => Ok(Expression::Identifier(Identifier {
+                    name,
+                    location: parser.location(&field),
+                })),
+            }
+        })?;
+        Ok(self.call(start, name, arguments, names))
+    }
+}
+
+/// How the trailing expression of a block is handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tail {
+    /// Kept as an expression statement, in a nested block
+    Keep,
+    /// Returned, at the end of a function
+    Return,
+    /// Assigned field by field, from the `Self { .. }` ending a constructor
+    Fields,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLIPPER: &str = r#"
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+#[ink::contract]
+mod token {
+    use ink::storage::Mapping;
+
+    /// A fungible token
+    #[ink(storage)]
+    #[derive(Default)]
+    pub struct Token {
+        total_supply: Balance,
+        balances: Mapping<AccountId, Balance>,
+        allowances: Mapping<(AccountId, AccountId), Balance>,
+        holders: Vec<AccountId>,
+    }
+
+    #[ink(event)]
+    pub struct Transfer {
+        #[ink(topic)]
+        from: Option<AccountId>,
+        #[ink(topic)]
+        to: Option<AccountId>,
+        value: Balance,
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum Error {
+        InsufficientBalance,
+        InsufficientAllowance,
+    }
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    impl Token {
+        #[ink(constructor)]
+        pub fn new(total_supply: Balance) -> Self {
+            let mut balances = Mapping::default();
+            let caller = Self::env().caller();
+            balances.insert(caller, &total_supply);
+            Self::env().emit_event(Transfer {
+                from: None,
+                to: Some(caller),
+                value: total_supply,
+            });
+            Self {
+                total_supply,
+                balances,
+                allowances: Default::default(),
+                holders: Vec::new(),
+            }
+        }
+
+        #[ink(message)]
+        pub fn balance_of(&self, owner: AccountId) -> Balance {
+            self.balances.get(owner).unwrap_or_default()
+        }
+
+        #[ink(message, payable)]
+        pub fn transfer(&mut self, to: AccountId, value: Balance) -> Result<()> {
+            let from = self.env().caller();
+            let from_balance = self.balance_of(from);
+            if from_balance < value {
+                return Err(Error::InsufficientBalance);
+            }
+            self.balances.insert(from, &(from_balance - value));
+            let to_balance: Balance = self.balance_of(to);
+            self.balances.insert(to, &(to_balance + value as u128));
+            for i in 0..self.holders.len() {
+                assert!(self.holders[i] != to, "holder");
+            }
+            self.holders.push(to);
+            Ok(())
+        }
+
+        fn is_large(value: Balance) -> bool {
+            if value > 1_000u128 {
+                true
+            } else {
+                false
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[ink::test]
+        fn works() {
+            let token = Token::new(100);
+            assert_eq!(token.balance_of(AccountId::from([1; 32])), 100);
+        }
+    }
+}
+"#;
+
+    #[test]
+    fn test_parse_contract() {
+        let source = parse_ink(FLIPPER, "lib.rs").unwrap();
+        let token = &source.contracts[0];
+        assert_eq!(token.name, "Token");
+        let variables: Vec<_> = token
+            .state_variables
+            .iter()
+            .map(|v| v.name.as_str())
+            .collect();
+        assert_eq!(
+            variables,
+            ["total_supply", "balances", "allowances", "holders"]
+        );
+        assert!(matches!(
+            &token.state_variables[2].type_name,
+            TypeName::Mapping(outer) if matches!(&*outer.value_type, TypeName::Mapping(_))
+        ));
+        assert_eq!(token.events[0].name, "Transfer");
+        let indexed: Vec<bool> = token.events[0]
+            .parameters
+            .iter()
+            .map(|parameter| parameter.indexed)
+            .collect();
+        assert_eq!(indexed, [true, true, false]);
+        assert_eq!(
+            token.enums[0].values,
+            ["InsufficientBalance", "InsufficientAllowance"]
+        );
+
+        let functions: Vec<_> = token
+            .functions
+            .iter()
+            .map(|f| {
+                (
+                    f.name.as_str(),
+                    f.visibility.clone(),
+                    f.state_mutability.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            functions,
+            [
+                ("new", Visibility::External, StateMutability::NonPayable),
+                ("balance_of", Visibility::External, StateMutability::View),
+                ("transfer", Visibility::External, StateMutability::Payable),
+                ("is_large", Visibility::Internal, StateMutability::Pure),
+            ]
+        );
+        assert!(token.functions[0].is_constructor);
+        assert!(token.functions[2].return_parameters.is_empty());
+
+        // The constructor fills `balances`, emits and assigns the supply
+        let body = &token.functions[0].body.as_ref().unwrap().statements;
+        assert_eq!(body.len(), 4);
+        assert!(matches!(&body[1], Statement::Assignment(_)));
+        assert!(matches!(&body[2], Statement::Emit(_)));
+        let Statement::Assignment(supply) = &body[3] else {
+            panic!("expected an assignment, found {:?}", body[3]);
+        };
+        assert!(
+            matches!(&*supply.assignment.left, Expression::Identifier(id) if id.name == "total_supply")
+        );
+    }
+
+    #[test]
+    fn test_migrate_ink() {
+        let source = parse_ink(FLIPPER, "lib.rs").unwrap();
+        let mut converter = super::super::converter::SolidityToBendConverter::new();
+        let bend = converter.convert(&source).unwrap();
+        for line in [
+            "    let balances: StorageMap<Address, u128>;",
+            "    let allowances: StorageMap<(Address, Address), u128>;",
+            "        balances.insert(caller_, total_supply);",
+            "        caller_ = caller();",
+            "        return balances.get(owner);",
+            "            assert(false, Error.InsufficientBalance);",
+            "        holders.push(to);",
+            "            return true;",
+        ] {
+            assert!(bend.contains(line), "missing `{}` in\n{}", line, bend);
+        }
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let error = |body: &str| {
+            let source = format!(
+                "#[ink(storage)] pub struct C {{ x: u32 }}\nimpl C {{ fn f(&self) {{ {} }} }}",
+                body
+            );
+            match parse_ink(&source, "lib.rs") {
+                Err(MigrationError::UnsupportedFeature(message)) => message,
+                other => panic!(
+                    "expected an unsupported feature, found {:?}",
+                    other.map(|_| ())
+                ),
+            }
+        };
+        assert_eq!(error("match self.x { _ => {} }"), "lib.rs:2:24: `match`");
+        assert_eq!(error("if let Some(x) = y {}"), "lib.rs:2:27: `if let`");
+        assert_eq!(error("let f = |x| x;"), "lib.rs:2:32: closures");
+        assert!(matches!(
+            parse_ink("struct C { x: u32 }", "lib.rs"),
+            Err(MigrationError::ParseError(_))
+        ));
+    }
+}
@@ -12,6 +12,11 @@ pub mod analyzer;
 pub mod ast;
 pub mod cli;
 pub mod converter;
+pub mod inheritance;
+pub mod ink;
+pub mod parser;
+pub mod report;
+pub mod vyper;
 
 /// Errors that can occur during migration
 #[derive(Error, Debug)]
@@ -76,6 +81,40 @@ pub struct MigrationStats {
     pub issues: Vec<MigrationIssue>,
     /// Estimated gas savings (if applicable)
     pub gas_savings_estimate: f64,
+    /// Files migrated from source, locating their issues
+    pub files: Vec<MigratedFile>,
+}
+
+/// Language of a contract source being migrated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceLanguage {
+    Solidity,
+    Vyper,
+    /// An ink! contract, in Rust
+    Ink,
+}
+
+impl SourceLanguage {
+    /// The language of a source file, from its extension
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "sol" => Some(SourceLanguage::Solidity),
+            "vy" => Some(SourceLanguage::Vyper),
+            "rs" => Some(SourceLanguage::Ink),
+            _ => None,
+        }
+    }
+}
+
+/// A source file migrated by `SolidityMigrator::migrate`
+#[derive(Debug, Clone)]
+pub struct MigratedFile {
+    /// Path of the file
+    pub file: String,
+    /// Source of the file
+    pub source: String,
+    /// Indices of the issues of the file in `MigrationStats::issues`
+    pub issues: std::ops::Range<usize>,
 }
 
 /// A single migration issue
@@ -122,6 +161,7 @@ impl Default for MigrationConfig {
                 "ERC20".to_string(),
                 "ERC721".to_string(),
                 "ERC1155".to_string(),
+                "ERC4626".to_string(),
             ],
             custom_mappings: HashMap::new(),
         }
@@ -133,6 +173,7 @@ pub struct SolidityMigrator {
     config: MigrationConfig,
     stats: MigrationStats,
     erc_templates: HashMap<String, String>,
+    template_tests: HashMap<String, String>,
 }
 
 impl Default for SolidityMigrator {
@@ -153,10 +194,12 @@ impl SolidityMigrator {
             config,
             stats: MigrationStats::default(),
             erc_templates: HashMap::new(),
+            template_tests: HashMap::new(),
         };
 
         // Initialize ERC templates
         migrator.initialize_erc_templates();
+        migrator.initialize_template_tests();
 
         migrator
     }
@@ -167,97 +210,80 @@ impl SolidityMigrator {
         self.erc_templates.insert(
             "ERC20".to_string(),
             r#"
-/// ERC-20 Token Implementation for Bend-PVM
-contract ERC20 is BendContract {
-    /// Token name
-    let name: String
-    
-    /// Token symbol  
-    let symbol: String
-    
-    /// Total supply
-    let total_supply: u256
-    
-    /// Balance mapping
-    let balances: Map<Address, u256>
-    
-    /// Allowance mapping
-    let allowances: Map<Address, Map<Address, u256>>
-    
-    /// Event: Transfer
-    event Transfer(from: Address, to: Address, value: u256)
-    
-    /// Event: Approval
-    event Approval(owner: Address, spender: Address, value: u256)
-    
-    /// Constructor
-    fn init(name: String, symbol: String, initial_supply: u256) {
-        self.name = name
-        self.symbol = symbol
-        self.total_supply = initial_supply
-        self.balances[msg.sender] = initial_supply
-    }
-    
-    /// Get token name
-    fn get_name() -> String {
-        self.name
-    }
-    
-    /// Get token symbol
-    fn get_symbol() -> String {
-        self.symbol
-    }
-    
-    /// Get decimals (default 18)
-    fn get_decimals() -> u8 {
-        18
-    }
-    
-    /// Get total supply
-    fn get_total_supply() -> u256 {
-        self.total_supply
-    }
-    
-    /// Get balance of account
-    fn balance_of(account: Address) -> u256 {
-        self.balances[account]
-    }
-    
-    /// Transfer tokens
-    fn transfer(to: Address, amount: u256) -> bool {
-        let from = msg.sender
-        assert(self.balances[from] >= amount, "Insufficient balance")
-        
-        self.balances[from] = self.balances[from] - amount
-        self.balances[to] = self.balances[to] + amount
-        
-        emit Transfer(from, to, amount)
-        true
-    }
-    
-    /// Approve spender
-    fn approve(spender: Address, amount: u256) -> bool {
-        self.allowances[msg.sender][spender] = amount
-        emit Approval(msg.sender, spender, amount)
-        true
-    }
-    
-    /// Transfer from (with allowance)
-    fn transfer_from(from: Address, to: Address, amount: u256) -> bool {
-        assert(self.allowances[from][msg.sender] >= amount, "Insufficient allowance")
-        assert(self.balances[from] >= amount, "Insufficient balance")
-        
-        self.allowances[from][msg.sender] = self.allowances[from][msg.sender] - amount
-        self.balances[from] = self.balances[from] - amount
-        self.balances[to] = self.balances[to] + amount
-        
-        emit Transfer(from, to, amount)
-        true
-    }
-    
-    /// Get allowance
-    fn allowance(owner: Address, spender: Address) -> u256 {
-        self.allowances[owner][spender]
+# ERC-20 token for Bend-PVM
+contract ERC20 {
+    # Total supply
+    pub let total_supply: u256;
+
+    # Balance of each account
+    let balances: StorageMap<Address, u256>;
+
+    # Allowance of each (owner, spender) pair
+    let allowances: StorageMap<(Address, Address), u256>;
+
+    # Whether `initialize` ran
+    let initialized: Bool;
+
+    event Transfer { indexed from_: Address, indexed to: Address, value: u256 }
+
+    event Approval { indexed owner: Address, indexed spender: Address, value: u256 }
+
+    # Mint the initial supply to the caller, once
+    pub fn initialize(initial_supply: u256) {
+        assert(!initialized, "already initialized");
+        initialized = true;
+        total_supply = initial_supply;
+        balances.insert(caller(), initial_supply);
+        emit Transfer(ZERO_ADDRESS, caller(), initial_supply);
+    }
+
+    # Decimals of the token amounts
+    #[view]
+    pub fn decimals() -> u24 {
+        return 18;
+    }
+
+    # Balance of `account`
+    #[view]
+    pub fn balance_of(account: Address) -> u256 {
+        return balances.get(account);
+    }
+
+    # Move `amount` from the caller to `to`
+    pub fn transfer(to: Address, amount: u256) -> Bool {
+        from_ = caller();
+        balance = balances.get(from_);
+        assert(balance >= amount, "insufficient balance");
+        balances.insert(from_, balance - amount);
+        balances.insert(to, balances.get(to) + amount);
+        emit Transfer(from_, to, amount);
+        return true;
+    }
+
+    # Let `spender` move up to `amount` of the caller's tokens
+    pub fn approve(spender: Address, amount: u256) -> Bool {
+        allowances.insert((caller(), spender), amount);
+        emit Approval(caller(), spender, amount);
+        return true;
+    }
+
+    # Move `amount` from `from_` to `to`, spending the caller's allowance
+    pub fn transfer_from(from_: Address, to: Address, amount: u256) -> Bool {
+        allowed = allowances.get((from_, caller()));
+        assert(allowed >= amount, "insufficient allowance");
+        balance = balances.get(from_);
+        assert(balance >= amount, "insufficient balance");
+        allowances.insert((from_, caller()), allowed - amount);
+        balances.insert(from_, balance - amount);
+        balances.insert(to, balances.get(to) + amount);
+        emit Transfer(from_, to, amount);
+        return true;
+    }
+
+    # Amount `spender` may move of `owner`'s tokens
+    #[view]
+    pub fn allowance(owner: Address, spender: Address) -> u256 {
+        return allowances.get((owner, spender));
     }
 }
 "#
@@ -268,127 +294,970 @@ contract ERC20 is BendContract {
         self.erc_templates.insert(
             "ERC721".to_string(),
             r#"
-/// ERC-721 Non-Fungible Token Implementation for Bend-PVM
-contract ERC721 is BendContract {
-    /// Token name
-    let name: String
-    
-    /// Token symbol
-    let symbol: String
-    
-    /// Mapping from token ID to owner
-    let owners: Map<u256, Address>
-    
-    /// Mapping from owner to token count
-    let balances: Map<Address, u256>
-    
-    /// Mapping from token ID to approved address
-    let token_approvals: Map<u256, Address>
-    
-    /// Mapping from owner to operator approvals
-    let operator_approvals: Map<Address, Map<Address, bool>>
-    
-    /// Event: Transfer
-    event Transfer(from: Address, to: Address, token_id: u256)
-    
-    /// Event: Approval
-    event Approval(owner: Address, approved: Address, token_id: u256)
-    
-    /// Event: Approval for all
-    event ApprovalForAll(owner: Address, operator: Address, approved: bool)
-    
-    /// Constructor
-    fn init(name: String, symbol: String) {
-        self.name = name
-        self.symbol = symbol
-    }
-    
-    /// Get token name
-    fn get_name() -> String {
-        self.name
-    }
-    
-    /// Get token symbol
-    fn get_symbol() -> String {
-        self.symbol
-    }
-    
-    /// Get balance of owner
-    fn balance_of(owner: Address) -> u256 {
-        self.balances[owner]
-    }
-    
-    /// Get owner of token
-    fn owner_of(token_id: u256) -> Address {
-        self.owners[token_id]
-    }
-    
-    /// Approve token
-    fn approve(to: Address, token_id: u256) {
-        let owner = self.owners[token_id]
-        assert(msg.sender == owner || self.operator_approvals[owner][msg.sender], "Not authorized")
-        self.token_approvals[token_id] = to
-        emit Approval(owner, to, token_id)
-    }
-    
-    /// Approve all
-    fn set_approval_for_all(operator: Address, approved: bool) {
-        self.operator_approvals[msg.sender][operator] = approved
-        emit ApprovalForAll(msg.sender, operator, approved)
-    }
-    
-    /// Get approved address
-    fn get_approved(token_id: u256) -> Address {
-        self.token_approvals[token_id]
-    }
-    
-    /// Check if operator is approved
-    fn is_approved_for_all(owner: Address, operator: Address) -> bool {
-        self.operator_approvals[owner][operator]
-    }
-    
-    /// Transfer token
-    fn transfer_from(from: Address, to: Address, token_id: u256) {
-        assert(
-            msg.sender == from || 
-            self.token_approvals[token_id] == msg.sender ||
-            self.operator_approvals[from][msg.sender],
-            "Not authorized"
-        )
-        assert(self.owners[token_id] == from, "Not token owner")
-        
-        self.owners[token_id] = to
-        self.balances[from] = self.balances[from] - 1
-        self.balances[to] = self.balances[to] + 1
-        
-        emit Transfer(from, to, token_id)
-    }
-    
-    /// Safe transfer from
-    fn safe_transfer_from(from: Address, to: Address, token_id: u256) {
-        self.transfer_from(from, to, token_id)
-        // Add receiver contract check if needed
-    }
-    
-    /// Mint token (should be protected)
-    fn mint(to: Address, token_id: u256) {
-        assert(self.owners[token_id] == Address::zero(), "Token already exists")
-        self.owners[token_id] = to
-        self.balances[to] = self.balances[to] + 1
-        emit Transfer(Address::zero(), to, token_id)
-    }
-    
-    /// Burn token (should be protected)
-    fn burn(token_id: u256) {
-        let owner = self.owners[token_id]
-        assert(owner != Address::zero(), "Invalid token")
-        
-        self.owners[token_id] = Address::zero()
-        self.balances[owner] = self.balances[owner] - 1
-        
-        emit Transfer(owner, Address::zero(), token_id)
+# ERC-721 non-fungible tokens for Bend-PVM
+contract ERC721 {
+    # Account allowed to mint, the deployer
+    pub let minter: Address;
+
+    # Owner of each token, the zero address for none
+    let owners: StorageMap<u256, Address>;
+
+    # Number of tokens of each owner
+    let balances: StorageMap<Address, u256>;
+
+    # Account approved to move each token
+    let token_approvals: StorageMap<u256, Address>;
+
+    # Whether an (owner, operator) pair may move all of the owner's tokens
+    let operator_approvals: StorageMap<(Address, Address), Bool>;
+
+    event Transfer { indexed from_: Address, indexed to: Address, indexed token_id: u256 }
+
+    event Approval { indexed owner: Address, indexed approved: Address, indexed token_id: u256 }
+
+    event ApprovalForAll { indexed owner: Address, indexed operator: Address, approved: Bool }
+
+    fn constructor() {
+        minter = caller();
+    }
+
+    # Number of tokens of `owner`
+    #[view]
+    pub fn balance_of(owner: Address) -> u256 {
+        return balances.get(owner);
+    }
+
+    # Owner of `token_id`, which must exist
+    #[view]
+    pub fn owner_of(token_id: u256) -> Address {
+        owner = owners.get(token_id);
+        assert(owner != ZERO_ADDRESS, "invalid token");
+        return owner;
+    }
+
+    # Let `to` move `token_id`
+    pub fn approve(to: Address, token_id: u256) {
+        owner = owner_of(token_id);
+        assert(caller() == owner || operator_approvals.get((owner, caller())), "not authorized");
+        token_approvals.insert(token_id, to);
+        emit Approval(owner, to, token_id);
+    }
+
+    # Let `operator` move all of the caller's tokens, or stop it
+    pub fn set_approval_for_all(operator: Address, approved: Bool) {
+        operator_approvals.insert((caller(), operator), approved);
+        emit ApprovalForAll(caller(), operator, approved);
+    }
+
+    # Account approved to move `token_id`
+    #[view]
+    pub fn get_approved(token_id: u256) -> Address {
+        return token_approvals.get(token_id);
+    }
+
+    # Whether `operator` may move all of `owner`'s tokens
+    #[view]
+    pub fn is_approved_for_all(owner: Address, operator: Address) -> Bool {
+        return operator_approvals.get((owner, operator));
+    }
+
+    # Move `token_id` from `from_` to `to`
+    pub fn transfer_from(from_: Address, to: Address, token_id: u256) {
+        assert(owner_of(token_id) == from_, "not token owner");
+        spender = caller();
+        allowed = spender == from_ || token_approvals.get(token_id) == spender;
+        assert(allowed || operator_approvals.get((from_, spender)), "not authorized");
+        token_approvals.insert(token_id, ZERO_ADDRESS);
+        owners.insert(token_id, to);
+        balances.insert(from_, balances.get(from_) - 1);
+        balances.insert(to, balances.get(to) + 1);
+        emit Transfer(from_, to, token_id);
+    }
+
+    # Mint `token_id` to `to`
+    pub fn mint(to: Address, token_id: u256) {
+        assert(caller() == minter, "not minter");
+        assert(owners.get(token_id) == ZERO_ADDRESS, "token already exists");
+        owners.insert(token_id, to);
+        balances.insert(to, balances.get(to) + 1);
+        emit Transfer(ZERO_ADDRESS, to, token_id);
+    }
+
+    # Burn `token_id`, which the caller owns
+    pub fn burn(token_id: u256) {
+        owner = owner_of(token_id);
+        assert(caller() == owner, "not token owner");
+        token_approvals.insert(token_id, ZERO_ADDRESS);
+        owners.insert(token_id, ZERO_ADDRESS);
+        balances.insert(owner, balances.get(owner) - 1);
+        emit Transfer(owner, ZERO_ADDRESS, token_id);
     }
 }
+"#
+            .to_string(),
+        );
+
+        // ERC-1155 template
+        self.erc_templates.insert(
+            "ERC1155".to_string(),
+            r#"
+# ERC-1155 multi-token for Bend-PVM
+#
+# Batch messages take their lists as Bytes, each token id, amount or
+# account in 32 little-endian bytes.
+contract ERC1155 {
+    # Account allowed to mint, the deployer
+    pub let minter: Address;
+
+    # Balance of each (token id, account) pair
+    let balances: StorageMap<(u256, Address), u256>;
+
+    # Whether an (account, operator) pair may move all of the account's tokens
+    let operator_approvals: StorageMap<(Address, Address), Bool>;
+
+    event TransferSingle { indexed operator: Address, indexed from_: Address, indexed to: Address, id: u256, value: u256 }
+
+    event TransferBatch { indexed operator: Address, indexed from_: Address, indexed to: Address, ids: Bytes, values: Bytes }
+
+    event ApprovalForAll { indexed account: Address, indexed operator: Address, approved: Bool }
+
+    fn constructor() {
+        minter = caller();
+    }
+
+    # Balance of `account` in token `id`
+    #[view]
+    pub fn balance_of(account: Address, id: u256) -> u256 {
+        return balances.get((id, account));
+    }
+
+    # Balance of each account of `accounts` in the token id at the same
+    # place of `ids`
+    #[view]
+    pub fn balance_of_batch(accounts: Bytes, ids: Bytes) -> Bytes {
+        assert(len(accounts) == len(ids), "accounts and ids length mismatch");
+        balances_ = hex"";
+        for i in range(0, len(ids) / 32) bound 256 {
+            account = to_address(u256_at(accounts, i * 32));
+            balances_ = concat(balances_, to_bytes(balances.get((u256_at(ids, i * 32), account))));
+        }
+        return balances_;
+    }
+
+    # Let `operator` move all of the caller's tokens, or stop it
+    pub fn set_approval_for_all(operator: Address, approved: Bool) {
+        assert(operator != caller(), "setting approval for self");
+        operator_approvals.insert((caller(), operator), approved);
+        emit ApprovalForAll(caller(), operator, approved);
+    }
+
+    # Whether `operator` may move all of `account`'s tokens
+    #[view]
+    pub fn is_approved_for_all(account: Address, operator: Address) -> Bool {
+        return operator_approvals.get((account, operator));
+    }
+
+    # Move `amount` of token `id` from `from_` to `to`
+    pub fn safe_transfer_from(from_: Address, to: Address, id: u256, amount: u256) {
+        assert(from_ == caller() || operator_approvals.get((from_, caller())), "not authorized");
+        assert(to != ZERO_ADDRESS, "transfer to the zero address");
+        balance = balances.get((id, from_));
+        assert(balance >= amount, "insufficient balance");
+        balances.insert((id, from_), balance - amount);
+        balances.insert((id, to), balances.get((id, to)) + amount);
+        emit TransferSingle(caller(), from_, to, id, amount);
+    }
+
+    # Move each amount of `amounts` of the token id at the same place of
+    # `ids` from `from_` to `to`
+    pub fn safe_batch_transfer_from(from_: Address, to: Address, ids: Bytes, amounts: Bytes) {
+        assert(from_ == caller() || operator_approvals.get((from_, caller())), "not authorized");
+        assert(to != ZERO_ADDRESS, "transfer to the zero address");
+        assert(len(ids) == len(amounts), "ids and amounts length mismatch");
+        for i in range(0, len(ids) / 32) bound 256 {
+            id = u256_at(ids, i * 32);
+            amount = u256_at(amounts, i * 32);
+            balance = balances.get((id, from_));
+            assert(balance >= amount, "insufficient balance");
+            balances.insert((id, from_), balance - amount);
+            balances.insert((id, to), balances.get((id, to)) + amount);
+        }
+        emit TransferBatch(caller(), from_, to, ids, amounts);
+    }
+
+    # Mint `amount` of token `id` to `to`
+    pub fn mint(to: Address, id: u256, amount: u256) {
+        assert(caller() == minter, "not minter");
+        assert(to != ZERO_ADDRESS, "mint to the zero address");
+        balances.insert((id, to), balances.get((id, to)) + amount);
+        emit TransferSingle(caller(), ZERO_ADDRESS, to, id, amount);
+    }
+
+    # Burn `amount` of the caller's token `id`
+    pub fn burn(id: u256, amount: u256) {
+        balance = balances.get((id, caller()));
+        assert(balance >= amount, "insufficient balance");
+        balances.insert((id, caller()), balance - amount);
+        emit TransferSingle(caller(), caller(), ZERO_ADDRESS, id, amount);
+    }
+}
+"#
+            .to_string(),
+        );
+
+        // ERC-4626 template
+        self.erc_templates.insert(
+            "ERC4626".to_string(),
+            r#"
+# ERC-4626 tokenized vault for Bend-PVM
+#
+# Shares are worth a part of the assets the vault holds. One virtual share
+# and one virtual asset keep the first depositor from inflating the share
+# price.
+#
+# Depositing pulls the asset from the caller with `transfer_from`, so the
+# caller approves the vault first.
+interface IERC20 {
+    fn transfer(to: Address, amount: u256) -> Bool;
+    fn transfer_from(from_: Address, to: Address, amount: u256) -> Bool;
+}
+
+contract ERC4626 {
+    # The ERC-20 asset deposited
+    pub let asset: Address;
+
+    # Total shares
+    pub let total_supply: u256;
+
+    # Assets held by the vault
+    pub let total_assets: u256;
+
+    # Shares of each account
+    let balances: StorageMap<Address, u256>;
+
+    # Allowance of shares of each (owner, spender) pair
+    let allowances: StorageMap<(Address, Address), u256>;
+
+    # Whether `initialize` ran
+    let initialized: Bool;
+
+    event Deposit { indexed sender: Address, indexed owner: Address, assets: u256, shares: u256 }
+
+    event Withdraw { indexed sender: Address, indexed receiver: Address, indexed owner: Address, assets: u256, shares: u256 }
+
+    event Transfer { indexed from_: Address, indexed to: Address, value: u256 }
+
+    event Approval { indexed owner: Address, indexed spender: Address, value: u256 }
+
+    # Set the asset deposited, once
+    pub fn initialize(asset_: Address) {
+        assert(!initialized, "already initialized");
+        initialized = true;
+        asset = asset_;
+    }
+
+    # Shares of `owner`
+    #[view]
+    pub fn balance_of(owner: Address) -> u256 {
+        return balances.get(owner);
+    }
+
+    # Shares worth `assets`, rounded down
+    #[view]
+    pub fn convert_to_shares(assets: u256) -> u256 {
+        return assets * (total_supply + 1) / (total_assets + 1);
+    }
+
+    # Assets worth `shares`, rounded down
+    #[view]
+    pub fn convert_to_assets(shares: u256) -> u256 {
+        return shares * (total_assets + 1) / (total_supply + 1);
+    }
+
+    # Shares minted by depositing `assets`
+    #[view]
+    pub fn preview_deposit(assets: u256) -> u256 {
+        return convert_to_shares(assets);
+    }
+
+    # Assets needed to mint `shares`, rounded up
+    #[view]
+    pub fn preview_mint(shares: u256) -> u256 {
+        return (shares * (total_assets + 1) + total_supply) / (total_supply + 1);
+    }
+
+    # Shares burned by withdrawing `assets`, rounded up
+    #[view]
+    pub fn preview_withdraw(assets: u256) -> u256 {
+        return (assets * (total_supply + 1) + total_assets) / (total_assets + 1);
+    }
+
+    # Assets received by redeeming `shares`
+    #[view]
+    pub fn preview_redeem(shares: u256) -> u256 {
+        return convert_to_assets(shares);
+    }
+
+    # Assets `owner` can withdraw
+    #[view]
+    pub fn max_withdraw(owner: Address) -> u256 {
+        return convert_to_assets(balances.get(owner));
+    }
+
+    # Shares `owner` can redeem
+    #[view]
+    pub fn max_redeem(owner: Address) -> u256 {
+        return balances.get(owner);
+    }
+
+    # Deposit `assets` of the caller, minting shares to `receiver`
+    pub fn deposit(assets: u256, receiver: Address) -> u256 {
+        shares = convert_to_shares(assets);
+        assert(shares > 0, "zero shares");
+        assert(IERC20(asset).transfer_from(caller(), address(), assets), "asset transfer failed");
+        total_assets = total_assets + assets;
+        total_supply = total_supply + shares;
+        balances.insert(receiver, balances.get(receiver) + shares);
+        emit Deposit(caller(), receiver, assets, shares);
+        return shares;
+    }
+
+    # Mint exactly `shares` to `receiver`, for the assets of the caller
+    # they are worth
+    pub fn mint(shares: u256, receiver: Address) -> u256 {
+        assets = preview_mint(shares);
+        assert(IERC20(asset).transfer_from(caller(), address(), assets), "asset transfer failed");
+        total_assets = total_assets + assets;
+        total_supply = total_supply + shares;
+        balances.insert(receiver, balances.get(receiver) + shares);
+        emit Deposit(caller(), receiver, assets, shares);
+        return assets;
+    }
+
+    # Withdraw exactly `assets` to `receiver`, burning the shares of
+    # `owner` they are worth
+    pub fn withdraw(assets: u256, receiver: Address, owner: Address) -> u256 {
+        shares = preview_withdraw(assets);
+        if caller() != owner {
+            allowed = allowances.get((owner, caller()));
+            assert(allowed >= shares, "insufficient allowance");
+            allowances.insert((owner, caller()), allowed - shares);
+        } else {
+        }
+        balance = balances.get(owner);
+        assert(balance >= shares, "insufficient shares");
+        balances.insert(owner, balance - shares);
+        total_supply = total_supply - shares;
+        total_assets = total_assets - assets;
+        assert(IERC20(asset).transfer(receiver, assets), "asset transfer failed");
+        emit Withdraw(caller(), receiver, owner, assets, shares);
+        return shares;
+    }
+
+    # Redeem `shares` of `owner`, sending the assets to `receiver`
+    pub fn redeem(shares: u256, receiver: Address, owner: Address) -> u256 {
+        assets = convert_to_assets(shares);
+        assert(assets > 0, "zero assets");
+        if caller() != owner {
+            allowed = allowances.get((owner, caller()));
+            assert(allowed >= shares, "insufficient allowance");
+            allowances.insert((owner, caller()), allowed - shares);
+        } else {
+        }
+        balance = balances.get(owner);
+        assert(balance >= shares, "insufficient shares");
+        balances.insert(owner, balance - shares);
+        total_supply = total_supply - shares;
+        total_assets = total_assets - assets;
+        assert(IERC20(asset).transfer(receiver, assets), "asset transfer failed");
+        emit Withdraw(caller(), receiver, owner, assets, shares);
+        return assets;
+    }
+
+    # Move `amount` shares from the caller to `to`
+    pub fn transfer(to: Address, amount: u256) -> Bool {
+        balance = balances.get(caller());
+        assert(balance >= amount, "insufficient balance");
+        balances.insert(caller(), balance - amount);
+        balances.insert(to, balances.get(to) + amount);
+        emit Transfer(caller(), to, amount);
+        return true;
+    }
+
+    # Let `spender` redeem up to `amount` of the caller's shares
+    pub fn approve(spender: Address, amount: u256) -> Bool {
+        allowances.insert((caller(), spender), amount);
+        emit Approval(caller(), spender, amount);
+        return true;
+    }
+
+    # Shares `spender` may redeem of `owner`'s
+    #[view]
+    pub fn allowance(owner: Address, spender: Address) -> u256 {
+        return allowances.get((owner, spender));
+    }
+}
+"#
+            .to_string(),
+        );
+    }
+
+    /// Initialize the test suites of the built-in ERC templates
+    ///
+    /// Tests run as `TEST_CALLER` in a contract of their own, so a test of
+    /// the vault cannot move an asset: the Rust tests deploy one for it.
+    fn initialize_template_tests(&mut self) {
+        self.template_tests.insert(
+            "ERC20".to_string(),
+            r#"
+# Tests of the ERC20 template
+
+#[test]
+fn test_initialize() -> u24 {
+    initialize(1000);
+    assert(total_supply == 1000, "total supply");
+    assert(balance_of(caller()) == 1000, "supply of the caller");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_initialize_twice() -> u24 {
+    initialize(1000);
+    initialize(1000);
+    return 0;
+}
+
+#[test]
+fn test_decimals() -> u24 {
+    assert(decimals() == 18, "decimals");
+    return 0;
+}
+
+#[test]
+fn test_balance_of() -> u24 {
+    assert(balance_of(caller()) == 0, "balance before initialize");
+    initialize(1000);
+    assert(balance_of(caller()) == 1000, "balance of the caller");
+    assert(balance_of(to_address(2)) == 0, "balance of another account");
+    return 0;
+}
+
+#[test]
+fn test_transfer() -> u24 {
+    initialize(1000);
+    assert(transfer(to_address(2), 300), "transfer");
+    assert(balance_of(caller()) == 700, "balance of the sender");
+    assert(balance_of(to_address(2)) == 300, "balance of the receiver");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_transfer_over_balance() -> u24 {
+    initialize(1000);
+    transfer(to_address(2), 1001);
+    return 0;
+}
+
+#[test]
+fn test_approve() -> u24 {
+    assert(approve(to_address(2), 50), "approve");
+    assert(allowance(caller(), to_address(2)) == 50, "allowance");
+    assert(approve(to_address(2), 20), "approve again");
+    assert(allowance(caller(), to_address(2)) == 20, "replaced allowance");
+    return 0;
+}
+
+#[test]
+fn test_transfer_from() -> u24 {
+    initialize(1000);
+    approve(caller(), 400);
+    assert(transfer_from(caller(), to_address(2), 300), "transfer_from");
+    assert(allowance(caller(), caller()) == 100, "spent allowance");
+    assert(balance_of(caller()) == 700, "balance of the owner");
+    assert(balance_of(to_address(2)) == 300, "balance of the receiver");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_transfer_from_over_allowance() -> u24 {
+    initialize(1000);
+    approve(caller(), 400);
+    transfer_from(caller(), to_address(2), 401);
+    return 0;
+}
+
+#[test]
+fn test_allowance() -> u24 {
+    assert(allowance(caller(), to_address(2)) == 0, "no allowance");
+    approve(to_address(2), 50);
+    assert(allowance(caller(), to_address(2)) == 50, "allowance of the spender");
+    assert(allowance(to_address(2), caller()) == 0, "allowance the other way");
+    return 0;
+}
+"#
+            .to_string(),
+        );
+
+        self.template_tests.insert(
+            "ERC721".to_string(),
+            r#"
+# Tests of the ERC721 template
+
+#[test]
+fn test_balance_of() -> u24 {
+    constructor();
+    assert(balance_of(to_address(2)) == 0, "no tokens");
+    mint(to_address(2), 1);
+    mint(to_address(2), 2);
+    assert(balance_of(to_address(2)) == 2, "tokens of the owner");
+    return 0;
+}
+
+#[test]
+fn test_owner_of() -> u24 {
+    constructor();
+    mint(to_address(2), 7);
+    assert(owner_of(7) == to_address(2), "owner");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_owner_of_missing_token() -> u24 {
+    owner_of(7);
+    return 0;
+}
+
+#[test]
+fn test_approve() -> u24 {
+    constructor();
+    mint(caller(), 1);
+    approve(to_address(2), 1);
+    assert(get_approved(1) == to_address(2), "approved account");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_approve_token_of_another() -> u24 {
+    constructor();
+    mint(to_address(2), 1);
+    approve(to_address(3), 1);
+    return 0;
+}
+
+#[test]
+fn test_set_approval_for_all() -> u24 {
+    set_approval_for_all(to_address(2), true);
+    assert(is_approved_for_all(caller(), to_address(2)), "approved");
+    set_approval_for_all(to_address(2), false);
+    assert(!is_approved_for_all(caller(), to_address(2)), "approval revoked");
+    return 0;
+}
+
+#[test]
+fn test_get_approved() -> u24 {
+    constructor();
+    mint(caller(), 1);
+    assert(get_approved(1) == ZERO_ADDRESS, "nobody approved");
+    return 0;
+}
+
+#[test]
+fn test_is_approved_for_all() -> u24 {
+    assert(!is_approved_for_all(caller(), to_address(2)), "not approved");
+    set_approval_for_all(to_address(2), true);
+    assert(!is_approved_for_all(to_address(2), caller()), "approved the other way");
+    return 0;
+}
+
+#[test]
+fn test_transfer_from() -> u24 {
+    constructor();
+    mint(caller(), 1);
+    approve(to_address(3), 1);
+    transfer_from(caller(), to_address(2), 1);
+    assert(owner_of(1) == to_address(2), "new owner");
+    assert(balance_of(caller()) == 0, "tokens of the sender");
+    assert(balance_of(to_address(2)) == 1, "tokens of the receiver");
+    assert(get_approved(1) == ZERO_ADDRESS, "approval cleared");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_transfer_from_unauthorized() -> u24 {
+    constructor();
+    mint(to_address(2), 1);
+    transfer_from(to_address(2), to_address(3), 1);
+    return 0;
+}
+
+#[test]
+fn test_mint() -> u24 {
+    constructor();
+    mint(to_address(2), 1);
+    assert(owner_of(1) == to_address(2), "owner");
+    assert(balance_of(to_address(2)) == 1, "tokens of the owner");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_mint_twice() -> u24 {
+    constructor();
+    mint(to_address(2), 1);
+    mint(to_address(3), 1);
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_mint_not_minter() -> u24 {
+    mint(to_address(2), 1);
+    return 0;
+}
+
+#[test]
+fn test_burn() -> u24 {
+    constructor();
+    mint(caller(), 1);
+    mint(caller(), 2);
+    burn(1);
+    assert(balance_of(caller()) == 1, "tokens left");
+    assert(owner_of(2) == caller(), "other token kept");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_burn_token_of_another() -> u24 {
+    constructor();
+    mint(to_address(2), 1);
+    burn(1);
+    return 0;
+}
+"#
+            .to_string(),
+        );
+
+        self.template_tests.insert(
+            "ERC1155".to_string(),
+            r#"
+# Tests of the ERC1155 template
+
+#[test]
+fn test_balance_of() -> u24 {
+    constructor();
+    mint(to_address(2), 1, 5);
+    assert(balance_of(to_address(2), 1) == 5, "balance in the token");
+    assert(balance_of(to_address(2), 2) == 0, "balance in another token");
+    return 0;
+}
+
+#[test]
+fn test_balance_of_batch() -> u24 {
+    constructor();
+    mint(to_address(2), 1, 5);
+    mint(to_address(3), 2, 7);
+    accounts = concat(concat(to_bytes(to_address(2)), to_bytes(to_address(3))), to_bytes(to_address(2)));
+    ids = concat(concat(to_bytes(1u256), to_bytes(2u256)), to_bytes(2u256));
+    balances_ = balance_of_batch(accounts, ids);
+    assert(len(balances_) == 96, "one balance per account");
+    assert(u256_at(balances_, 0) == 5, "first balance");
+    assert(u256_at(balances_, 32) == 7, "second balance");
+    assert(u256_at(balances_, 64) == 0, "third balance");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_balance_of_batch_length_mismatch() -> u24 {
+    balance_of_batch(to_bytes(to_address(2)), concat(to_bytes(1u256), to_bytes(2u256)));
+    return 0;
+}
+
+#[test]
+fn test_set_approval_for_all() -> u24 {
+    set_approval_for_all(to_address(2), true);
+    assert(is_approved_for_all(caller(), to_address(2)), "approved");
+    set_approval_for_all(to_address(2), false);
+    assert(!is_approved_for_all(caller(), to_address(2)), "approval revoked");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_set_approval_for_self() -> u24 {
+    set_approval_for_all(caller(), true);
+    return 0;
+}
+
+#[test]
+fn test_is_approved_for_all() -> u24 {
+    assert(!is_approved_for_all(caller(), to_address(2)), "not approved");
+    set_approval_for_all(to_address(2), true);
+    assert(!is_approved_for_all(to_address(2), caller()), "approved the other way");
+    return 0;
+}
+
+#[test]
+fn test_safe_transfer_from() -> u24 {
+    constructor();
+    mint(caller(), 1, 10);
+    safe_transfer_from(caller(), to_address(2), 1, 4);
+    assert(balance_of(caller(), 1) == 6, "balance of the sender");
+    assert(balance_of(to_address(2), 1) == 4, "balance of the receiver");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_safe_transfer_from_over_balance() -> u24 {
+    constructor();
+    mint(caller(), 1, 10);
+    safe_transfer_from(caller(), to_address(2), 1, 11);
+    return 0;
+}
+
+#[test]
+fn test_safe_batch_transfer_from() -> u24 {
+    constructor();
+    mint(caller(), 1, 10);
+    mint(caller(), 2, 20);
+    ids = concat(to_bytes(1u256), to_bytes(2u256));
+    amounts = concat(to_bytes(3u256), to_bytes(5u256));
+    safe_batch_transfer_from(caller(), to_address(2), ids, amounts);
+    assert(balance_of(caller(), 1) == 7, "first balance of the sender");
+    assert(balance_of(caller(), 2) == 15, "second balance of the sender");
+    assert(balance_of(to_address(2), 1) == 3, "first balance of the receiver");
+    assert(balance_of(to_address(2), 2) == 5, "second balance of the receiver");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_safe_batch_transfer_from_over_balance() -> u24 {
+    constructor();
+    mint(caller(), 1, 10);
+    ids = concat(to_bytes(1u256), to_bytes(2u256));
+    amounts = concat(to_bytes(3u256), to_bytes(5u256));
+    safe_batch_transfer_from(caller(), to_address(2), ids, amounts);
+    return 0;
+}
+
+#[test]
+fn test_mint() -> u24 {
+    constructor();
+    mint(to_address(2), 1, 5);
+    mint(to_address(2), 1, 2);
+    assert(balance_of(to_address(2), 1) == 7, "minted balance");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_mint_not_minter() -> u24 {
+    mint(to_address(2), 1, 5);
+    return 0;
+}
+
+#[test]
+fn test_burn() -> u24 {
+    constructor();
+    mint(caller(), 1, 5);
+    burn(1, 2);
+    assert(balance_of(caller(), 1) == 3, "balance left");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_burn_over_balance() -> u24 {
+    constructor();
+    mint(caller(), 1, 5);
+    burn(1, 6);
+    return 0;
+}
+"#
+            .to_string(),
+        );
+
+        self.template_tests.insert(
+            "ERC4626".to_string(),
+            r#"
+# Tests of the ERC4626 template
+#
+# The vault holds 300 assets for 100 shares, so a share is worth about 3
+# assets.
+
+#[cfg(test)]
+fn fill_vault() {
+    initialize(to_address(2));
+    total_assets = 300;
+    total_supply = 100;
+    balances.insert(caller(), 100);
+}
+
+#[test]
+fn test_initialize() -> u24 {
+    initialize(to_address(2));
+    assert(asset == to_address(2), "asset");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_initialize_twice() -> u24 {
+    initialize(to_address(2));
+    initialize(to_address(3));
+    return 0;
+}
+
+#[test]
+fn test_balance_of() -> u24 {
+    assert(balance_of(caller()) == 0, "no shares");
+    fill_vault();
+    assert(balance_of(caller()) == 100, "shares of the caller");
+    return 0;
+}
+
+#[test]
+fn test_convert_to_shares() -> u24 {
+    assert(convert_to_shares(100) == 100, "one share per asset when empty");
+    fill_vault();
+    assert(convert_to_shares(100) == 33, "shares rounded down");
+    return 0;
+}
+
+#[test]
+fn test_convert_to_assets() -> u24 {
+    assert(convert_to_assets(100) == 100, "one asset per share when empty");
+    fill_vault();
+    assert(convert_to_assets(33) == 98, "assets rounded down");
+    return 0;
+}
+
+#[test]
+fn test_preview_deposit() -> u24 {
+    fill_vault();
+    assert(preview_deposit(100) == convert_to_shares(100), "shares for the assets");
+    return 0;
+}
+
+#[test]
+fn test_preview_mint() -> u24 {
+    fill_vault();
+    assert(preview_mint(33) == 99, "assets rounded up");
+    return 0;
+}
+
+#[test]
+fn test_preview_withdraw() -> u24 {
+    fill_vault();
+    assert(preview_withdraw(98) == 33, "shares rounded up");
+    assert(preview_withdraw(99) == 34, "shares rounded up past a share");
+    return 0;
+}
+
+#[test]
+fn test_preview_redeem() -> u24 {
+    fill_vault();
+    assert(preview_redeem(33) == convert_to_assets(33), "assets for the shares");
+    return 0;
+}
+
+#[test]
+fn test_max_withdraw() -> u24 {
+    fill_vault();
+    assert(max_withdraw(caller()) == 298, "assets of the caller's shares");
+    assert(max_withdraw(to_address(3)) == 0, "nothing for another account");
+    return 0;
+}
+
+#[test]
+fn test_max_redeem() -> u24 {
+    fill_vault();
+    assert(max_redeem(caller()) == 100, "shares of the caller");
+    return 0;
+}
+
+# The asset is not deployed in a test, so the transfer of the asset
+# fails and nothing is minted
+#[test]
+#[should_revert]
+fn test_deposit() -> u24 {
+    fill_vault();
+    deposit(100, caller());
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_mint() -> u24 {
+    fill_vault();
+    mint(10, caller());
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_withdraw() -> u24 {
+    fill_vault();
+    withdraw(100, caller(), caller());
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_withdraw_over_shares() -> u24 {
+    fill_vault();
+    withdraw(400, caller(), caller());
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_redeem() -> u24 {
+    fill_vault();
+    redeem(10, caller(), caller());
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_redeem_over_allowance() -> u24 {
+    fill_vault();
+    redeem(10, caller(), to_address(3));
+    return 0;
+}
+
+#[test]
+fn test_transfer() -> u24 {
+    fill_vault();
+    assert(transfer(to_address(3), 40), "transfer");
+    assert(balance_of(caller()) == 60, "shares of the sender");
+    assert(balance_of(to_address(3)) == 40, "shares of the receiver");
+    return 0;
+}
+
+#[test]
+fn test_approve() -> u24 {
+    assert(approve(to_address(3), 50), "approve");
+    assert(allowance(caller(), to_address(3)) == 50, "allowance");
+    return 0;
+}
+
+#[test]
+fn test_allowance() -> u24 {
+    assert(allowance(caller(), to_address(3)) == 0, "no allowance");
+    approve(to_address(3), 50);
+    assert(allowance(to_address(3), caller()) == 0, "allowance the other way");
+    return 0;
+}
 "#
             .to_string(),
         );
@@ -403,6 +1272,34 @@ contract ERC721 is BendContract {
     pub fn list_erc_templates(&self) -> Vec<String> {
         self.erc_templates.keys().cloned().collect()
     }
+
+    /// Test suite of ERC template `name`, or `None` when the template is
+    /// unknown or `generate_tests` is off
+    ///
+    /// Every message has a `test_<message>` test asserting what it does,
+    /// and the checks it makes have tests expecting a revert.
+    pub fn generate_template_tests(&self, name: &str) -> Option<String> {
+        if !self.config.generate_tests {
+            return None;
+        }
+        self.template_tests.get(name).cloned()
+    }
+
+    /// What the migrator converted so far, and the issues it found
+    pub fn stats(&self) -> &MigrationStats {
+        &self.stats
+    }
+
+    /// Write the compatibility report of the migration so far to the
+    /// output directory, as Markdown, HTML and JSON
+    ///
+    /// Returns the files written, none when `generate_report` is off.
+    pub fn write_report(&self) -> Result<Vec<PathBuf>, MigrationError> {
+        if !self.config.generate_report {
+            return Ok(Vec::new());
+        }
+        report::MigrationReport::new(&self.stats).write(&self.config.output_dir)
+    }
 }
 
 /// Helper function to create a new migrator
@@ -413,6 +1310,12 @@ pub fn create_migrator() -> SolidityMigrator {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compiler::address::Address;
+    use crate::compiler::codegen::metadata::selector_for;
+    use crate::runtime::env::{Environment, ExecutionContext, ExecutionResult};
+    use crate::runtime::interpreter::Interpreter;
+    use crate::testing::differential::CompiledContract;
+    use crate::testing::{TestResult, TestSuite};
 
     #[test]
     fn test_migrator_creation() {
@@ -428,6 +1331,198 @@ mod tests {
         let migrator = SolidityMigrator::new();
         assert!(migrator.get_erc_template("ERC20").is_some());
         assert!(migrator.get_erc_template("ERC721").is_some());
-        assert!(migrator.get_erc_template("ERC1155").is_none()); // Not implemented yet
+        assert!(migrator.get_erc_template("ERC1155").is_some());
+        assert!(migrator.get_erc_template("ERC4626").is_some());
+    }
+
+    #[test]
+    fn test_template_tests() {
+        let migrator = SolidityMigrator::new();
+        let suite = migrator.generate_template_tests("ERC4626").unwrap();
+        assert!(suite.contains(
+            "#[test]\nfn test_initialize() -> u24 {\n    initialize(to_address(2));\n    assert(asset == to_address(2), \"asset\");\n    return 0;\n}"
+        ));
+        assert!(migrator.generate_template_tests("ERC777").is_none());
+
+        let migrator = SolidityMigrator::with_config(MigrationConfig {
+            generate_tests: false,
+            ..MigrationConfig::default()
+        });
+        assert!(migrator.generate_template_tests("ERC20").is_none());
+    }
+
+    #[test]
+    fn test_templates_compile_and_pass_their_tests() {
+        let migrator = SolidityMigrator::new();
+        let mut names = migrator.list_erc_templates();
+        names.sort();
+        assert_eq!(names, ["ERC1155", "ERC20", "ERC4626", "ERC721"]);
+        for name in names {
+            let template = migrator.get_erc_template(&name).unwrap();
+            let source = format!(
+                "{}{}",
+                template,
+                migrator.generate_template_tests(&name).unwrap()
+            );
+            let results = TestSuite::from_source(&name, &source)
+                .unwrap_or_else(|error| panic!("{}: {:?}", name, error))
+                .run_all();
+
+            for (test, result) in &results {
+                assert!(
+                    matches!(result, TestResult::Passed { .. }),
+                    "{} {}: {:?}",
+                    name,
+                    test,
+                    result
+                );
+            }
+
+            // Every message has a test
+            let tests: Vec<&str> = results.iter().map(|(test, _)| test.as_str()).collect();
+            let messages = template
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix("pub fn "))
+                .filter_map(|signature| signature.split_once('(').map(|(message, _)| message));
+            for message in messages {
+                let test = format!("test_{}", message);
+                assert!(tests.contains(&test.as_str()), "{}: no {}", name, test);
+            }
+        }
+    }
+
+    /// Send a message from `caller` to the contract at `to`, as a
+    /// transaction of its own
+    fn send(
+        environment: &mut Option<Environment>,
+        caller: Address,
+        to: Address,
+        signature: &str,
+        args: &[Vec<u8>],
+    ) -> ExecutionResult {
+        let mut context = ExecutionContext::new_default();
+        context.caller = caller;
+        context.address = to;
+        context.input = [selector_for(signature).to_vec(), args.concat()].concat();
+        let mut current = environment.take().unwrap();
+        current.enter_contract(context);
+        let code = current.code_at(&to).unwrap();
+        let mut interpreter = Interpreter::with_environment(current);
+        let result = interpreter.execute(&code).unwrap();
+        *environment = Some(interpreter.into_environment());
+        result
+    }
+
+    #[test]
+    fn test_vault_moves_its_asset() {
+        let migrator = SolidityMigrator::new();
+        let account = |id: u8| Address::new([id; 32]);
+        let amount = |value: u64| {
+            let mut bytes = [0u8; 32];
+            bytes[..8].copy_from_slice(&value.to_le_bytes());
+            bytes.to_vec()
+        };
+        let (user, receiver, token, vault) = (account(1), account(9), account(2), account(3));
+
+        let mut environment = Environment::new(ExecutionContext::new_default());
+        for (address, name) in [(token, "ERC20"), (vault, "ERC4626")] {
+            let template = migrator.get_erc_template(name).unwrap();
+            let contract = CompiledContract::with_dispatcher(template).unwrap();
+            environment.deploy(address, contract.instructions);
+        }
+        let mut environment = Some(environment);
+        let mut send = |to: Address, signature: &str, args: &[Vec<u8>]| match send(
+            &mut environment,
+            user,
+            to,
+            signature,
+            args,
+        ) {
+            ExecutionResult::Success { data, .. } => Some(data),
+            _ => None,
+        };
+        let address = |account: Address| account.as_bytes().to_vec();
+
+        send(token, "initialize(u256)", &[amount(1000)]).unwrap();
+        send(vault, "initialize(Address)", &[address(token)]).unwrap();
+        send(
+            token,
+            "approve(Address,u256)",
+            &[address(vault), amount(600)],
+        )
+        .unwrap();
+
+        // Depositing pulls the assets from the caller
+        let deposit = [amount(400), address(user)];
+        assert_eq!(
+            send(vault, "deposit(u256,Address)", &deposit),
+            Some(amount(400))
+        );
+        assert_eq!(
+            send(token, "balance_of(Address)", &[address(vault)]),
+            Some(amount(400))
+        );
+        assert_eq!(
+            send(token, "balance_of(Address)", &[address(user)]),
+            Some(amount(600))
+        );
+        let mint = [amount(100), address(user)];
+        assert_eq!(send(vault, "mint(u256,Address)", &mint), Some(amount(100)));
+        assert_eq!(
+            send(token, "balance_of(Address)", &[address(vault)]),
+            Some(amount(500))
+        );
+
+        // Redeeming and withdrawing send the assets to the receiver
+        let redeem = [amount(200), address(receiver), address(user)];
+        assert_eq!(
+            send(vault, "redeem(u256,Address,Address)", &redeem),
+            Some(amount(200))
+        );
+        assert_eq!(
+            send(token, "balance_of(Address)", &[address(receiver)]),
+            Some(amount(200))
+        );
+        let withdraw = [amount(100), address(user), address(user)];
+        assert_eq!(
+            send(vault, "withdraw(u256,Address,Address)", &withdraw),
+            Some(amount(100))
+        );
+        assert_eq!(
+            send(token, "balance_of(Address)", &[address(user)]),
+            Some(amount(600))
+        );
+        assert_eq!(
+            send(token, "balance_of(Address)", &[address(vault)]),
+            Some(amount(200))
+        );
+
+        // Past the allowance of the vault, nothing moves
+        let deposit = [amount(200), address(user)];
+        assert_eq!(send(vault, "deposit(u256,Address)", &deposit), None);
+        assert_eq!(
+            send(vault, "balance_of(Address)", &[address(user)]),
+            Some(amount(200))
+        );
+    }
+
+    #[test]
+    fn test_migrate_by_language() {
+        let language = |path: &str| SourceLanguage::from_path(std::path::Path::new(path));
+        assert_eq!(language("Token.sol"), Some(SourceLanguage::Solidity));
+        assert_eq!(language("contracts/Vault.vy"), Some(SourceLanguage::Vyper));
+        assert_eq!(language("flipper/lib.rs"), Some(SourceLanguage::Ink));
+        assert_eq!(language("README.md"), None);
+
+        let mut migrator = SolidityMigrator::new();
+        let bend = migrator
+            .migrate(
+                SourceLanguage::Vyper,
+                "count: public(uint256)\n\n@external\ndef bump():\n    self.count += 1\n",
+                "Counter.vy",
+            )
+            .unwrap();
+        assert!(bend.contains("        count = (count + 1);"), "{}", bend);
+        assert_eq!(migrator.stats().files[0].file, "Counter.vy");
     }
 }
@@ -0,0 +1,1891 @@
+//! # Solidity Parser
+//!
+//! A recursive descent parser building the [`SoliditySource`] AST from
+//! Solidity source, for the converter and analyzer to work on.
+//!
+//! It covers the language contracts are written in: pragmas and imports,
+//! contracts, interfaces and libraries with their bases, state variables,
+//! functions, modifiers, events, errors, structs, enums, user-defined value
+//! types and `using for`, and every statement and expression but
+//! `try`/`catch`. Inline assembly is kept as text. Interfaces and libraries
+//! are contracts of their [`ContractKind`], in `contracts`.
+//!
+//! The tokenizer and the token cursor are shared with the Vyper and ink!
+//! frontends, which read their own [`Dialect`].
+
+use super::ast::*;
+use super::MigrationError;
+
+/// Parse Solidity `source`, read from `file`
+pub fn parse_solidity(source: &str, file: &str) -> Result<SoliditySource, MigrationError> {
+    let tokens = tokenize(source, file, Dialect::Solidity)?;
+    Parser {
+        tokens,
+        position: 0,
+        source,
+        file,
+    }
+    .parse_source()
+}
+
+/// Source language being tokenized, for its comments and punctuation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Dialect {
+    Solidity,
+    /// `#` comments, `"""` docstrings, `//` floor division and `@`
+    /// decorators
+    Vyper,
+    /// `::` paths, `#[...]` attributes and `..` ranges
+    Rust,
+}
+
+impl Dialect {
+    /// Punctuation of the dialect beyond Solidity's, matched first
+    fn punctuation(self) -> &'static [&'static str] {
+        match self {
+            Dialect::Solidity => &[],
+            Dialect::Vyper => &["//=", "//", "->", "@"],
+            Dialect::Rust => &["..=", "::", "->", "..", "#"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum TokenKind {
+    Identifier,
+    Number,
+    /// A string literal, with its escapes as written
+    String,
+    /// A `hex"..."` literal, holding the hex digits
+    HexString,
+    Punctuation,
+    Eof,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Token {
+    pub(super) kind: TokenKind,
+    pub(super) text: String,
+    pub(super) start: usize,
+    pub(super) end: usize,
+    pub(super) line: usize,
+    pub(super) column: usize,
+}
+
+/// Keywords starting statements and expressions, which cannot name a type
+const KEYWORDS: &[&str] = &[
+    "break", "continue", "delete", "do", "else", "emit", "false", "for", "if", "new", "return",
+    "revert", "true", "while",
+];
+
+/// Punctuation, longest first so that the longest match wins
+const PUNCTUATION: &[&str] = &[
+    ">>>=", ">>>", "<<=", ">>=", "**", "==", "!=", "<=", ">=", "&&", "||", "++", "--", "+=", "-=",
+    "*=", "/=", "%=", "&=", "|=", "^=", "<<", ">>", "=>", "(", ")", "{", "}", "[", "]", ";", ",",
+    ".", "?", ":", "=", "+", "-", "*", "/", "%", "&", "|", "^", "!", "~", "<", ">",
+];
+
+pub(super) fn error_at(file: &str, line: usize, column: usize, message: String) -> MigrationError {
+    MigrationError::ParseError(format!("{}:{}:{}: {}", file, line, column, message))
+}
+
+pub(super) fn tokenize(
+    source: &str,
+    file: &str,
+    dialect: Dialect,
+) -> Result<Vec<Token>, MigrationError> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let (mut offset, mut line, mut line_start) = (0, 1, 0);
+
+    while offset < bytes.len() {
+        let c = bytes[offset];
+        let column = offset - line_start + 1;
+        if c == b'\n' {
+            offset += 1;
+            line += 1;
+            line_start = offset;
+            continue;
+        }
+        if c.is_ascii_whitespace() {
+            offset += 1;
+            continue;
+        }
+        let line_comment = match dialect {
+            Dialect::Vyper => "#",
+            _ => "//",
+        };
+        if source[offset..].starts_with(line_comment) {
+            offset += source[offset..].find('\n').unwrap_or(source.len() - offset);
+            continue;
+        }
+        if dialect == Dialect::Vyper && source[offset..].starts_with("\"\"\"") {
+            let Some(length) = source[offset + 3..].find("\"\"\"") else {
+                return Err(error_at(
+                    file,
+                    line,
+                    column,
+                    "unterminated docstring".into(),
+                ));
+            };
+            let text = &source[offset + 3..offset + 3 + length];
+            tokens.push(Token {
+                kind: TokenKind::String,
+                text: text.to_string(),
+                start: offset,
+                end: offset + length + 6,
+                line,
+                column,
+            });
+            if let Some(last) = text.rfind('\n') {
+                line += text.matches('\n').count();
+                line_start = offset + 3 + last + 1;
+            }
+            offset += length + 6;
+            continue;
+        }
+        if dialect != Dialect::Vyper && source[offset..].starts_with("/*") {
+            let Some(length) = source[offset + 2..].find("*/") else {
+                return Err(error_at(file, line, column, "unterminated comment".into()));
+            };
+            let comment = &source[offset..offset + length + 4];
+            if let Some(last) = comment.rfind('\n') {
+                line += comment.matches('\n').count();
+                line_start = offset + last + 1;
+            }
+            offset += length + 4;
+            continue;
+        }
+
+        let start = offset;
+        let kind = if c.is_ascii_alphabetic() || c == b'_' || c == b'$' {
+            while offset < bytes.len()
+                && (bytes[offset].is_ascii_alphanumeric() || matches!(bytes[offset], b'_' | b'$'))
+            {
+                offset += 1;
+            }
+            let word = &source[start..offset];
+            match bytes.get(offset) {
+                Some(b'"' | b'\'') if word == "hex" || word == "unicode" => {
+                    let (text, end) = string_literal(source, offset, file, line, column)?;
+                    offset = end;
+                    let kind = match word {
+                        "hex" => TokenKind::HexString,
+                        _ => TokenKind::String,
+                    };
+                    tokens.push(Token {
+                        kind,
+                        text,
+                        start,
+                        end,
+                        line,
+                        column,
+                    });
+                    continue;
+                }
+                _ => TokenKind::Identifier,
+            }
+        } else if c.is_ascii_digit()
+            || (c == b'.' && bytes.get(offset + 1).is_some_and(u8::is_ascii_digit))
+        {
+            if source[offset..].starts_with("0x") || source[offset..].starts_with("0X") {
+                offset += 2;
+                while offset < bytes.len()
+                    && (bytes[offset].is_ascii_hexdigit() || bytes[offset] == b'_')
+                {
+                    offset += 1;
+                }
+            } else {
+                while offset < bytes.len() {
+                    let digit = bytes[offset];
+                    let exponent_sign =
+                        matches!(digit, b'-' | b'+') && matches!(bytes[offset - 1], b'e' | b'E');
+                    let fraction =
+                        digit == b'.' && bytes.get(offset + 1).is_some_and(u8::is_ascii_digit);
+                    if digit.is_ascii_digit()
+                        || matches!(digit, b'_' | b'e' | b'E')
+                        || exponent_sign
+                        || fraction
+                    {
+                        offset += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+            TokenKind::Number
+        } else if c == b'"' || c == b'\'' {
+            let (text, end) = string_literal(source, offset, file, line, column)?;
+            tokens.push(Token {
+                kind: TokenKind::String,
+                text,
+                start,
+                end,
+                line,
+                column,
+            });
+            offset = end;
+            continue;
+        } else {
+            let Some(punctuation) = dialect
+                .punctuation()
+                .iter()
+                .chain(PUNCTUATION)
+                .find(|punctuation| source[offset..].starts_with(**punctuation))
+            else {
+                let found = source[offset..].chars().next().unwrap_or_default();
+                return Err(error_at(
+                    file,
+                    line,
+                    column,
+                    format!("unexpected character `{}`", found),
+                ));
+            };
+            offset += punctuation.len();
+            TokenKind::Punctuation
+        };
+        tokens.push(Token {
+            kind,
+            text: source[start..offset].to_string(),
+            start,
+            end: offset,
+            line,
+            column,
+        });
+    }
+
+    let (line, column) = (line, source.len() - line_start + 1);
+    tokens.push(Token {
+        kind: TokenKind::Eof,
+        text: String::new(),
+        start: source.len(),
+        end: source.len(),
+        line,
+        column,
+    });
+    Ok(tokens)
+}
+
+/// The contents of the string literal opening at `offset` and the offset
+/// after its closing quote
+fn string_literal(
+    source: &str,
+    offset: usize,
+    file: &str,
+    line: usize,
+    column: usize,
+) -> Result<(String, usize), MigrationError> {
+    let bytes = source.as_bytes();
+    let quote = bytes[offset];
+    let mut end = offset + 1;
+    while end < bytes.len() && bytes[end] != quote {
+        if bytes[end] == b'\n' {
+            break;
+        }
+        end += if bytes[end] == b'\\' { 2 } else { 1 };
+    }
+    if end >= bytes.len() || bytes[end] != quote {
+        return Err(error_at(file, line, column, "unterminated string".into()));
+    }
+    Ok((source[offset + 1..end].to_string(), end + 1))
+}
+
+/// Whether `name` is an elementary type, like `uint256` or `bytes32`
+pub(super) fn is_elementary(name: &str) -> bool {
+    let sized = |prefix: &str, extra: char| {
+        name.strip_prefix(prefix)
+            .is_some_and(|size| size.chars().all(|c| c.is_ascii_digit() || c == extra))
+    };
+    matches!(name, "address" | "bool" | "string" | "byte")
+        || sized("uint", '_')
+        || sized("int", '_')
+        || sized("bytes", '_')
+        || sized("ufixed", 'x')
+        || sized("fixed", 'x')
+}
+
+pub(super) fn binary_operator(text: &str) -> Option<(BinaryOperator, u8)> {
+    Some(match text {
+        "||" => (BinaryOperator::LogicalOr, 1),
+        "&&" => (BinaryOperator::LogicalAnd, 2),
+        "==" => (BinaryOperator::Equal, 3),
+        "!=" => (BinaryOperator::NotEqual, 3),
+        "<" => (BinaryOperator::Less, 4),
+        "<=" => (BinaryOperator::LessEqual, 4),
+        ">" => (BinaryOperator::Greater, 4),
+        ">=" => (BinaryOperator::GreaterEqual, 4),
+        "|" => (BinaryOperator::BitOr, 5),
+        "^" => (BinaryOperator::BitXor, 6),
+        "&" => (BinaryOperator::BitAnd, 7),
+        "<<" => (BinaryOperator::BitShiftLeft, 8),
+        ">>" | ">>>" => (BinaryOperator::BitShiftRight, 8),
+        "+" => (BinaryOperator::Add, 9),
+        "-" => (BinaryOperator::Sub, 9),
+        "*" => (BinaryOperator::Mul, 10),
+        "/" => (BinaryOperator::Div, 10),
+        "%" => (BinaryOperator::Mod, 10),
+        _ => return None,
+    })
+}
+
+pub(super) fn assignment_operator(text: &str) -> Option<AssignmentOperator> {
+    Some(match text {
+        "=" => AssignmentOperator::Assign,
+        "+=" => AssignmentOperator::AddAssign,
+        "-=" => AssignmentOperator::SubAssign,
+        "*=" => AssignmentOperator::MulAssign,
+        "/=" => AssignmentOperator::DivAssign,
+        "%=" => AssignmentOperator::ModAssign,
+        "&=" => AssignmentOperator::BitAndAssign,
+        "|=" => AssignmentOperator::BitOrAssign,
+        "^=" => AssignmentOperator::BitXorAssign,
+        "<<=" => AssignmentOperator::BitShiftLeftAssign,
+        ">>=" | ">>>=" => AssignmentOperator::BitShiftRightAssign,
+        _ => return None,
+    })
+}
+
+fn subdenomination(text: &str) -> Option<SubDenomination> {
+    Some(match text {
+        "wei" => SubDenomination::Wei,
+        "gwei" => SubDenomination::Gwei,
+        "ether" => SubDenomination::Ether,
+        "seconds" => SubDenomination::Seconds,
+        "minutes" => SubDenomination::Minutes,
+        "hours" => SubDenomination::Hours,
+        "days" => SubDenomination::Days,
+        "weeks" => SubDenomination::Weeks,
+        "years" => SubDenomination::Years,
+        _ => return None,
+    })
+}
+
+/// Cursor over the tokens of a source, with the Solidity grammar
+pub(super) struct Parser<'a> {
+    pub(super) tokens: Vec<Token>,
+    pub(super) position: usize,
+    pub(super) source: &'a str,
+    pub(super) file: &'a str,
+}
+
+/// The header of a function, modifier or state variable: what follows its
+/// name or type
+#[derive(Default)]
+struct Specifiers {
+    visibility: Option<Visibility>,
+    state_mutability: Option<StateMutability>,
+    is_virtual: bool,
+    overrides: Option<Vec<String>>,
+    is_constant: bool,
+    is_immutable: bool,
+    modifiers: Vec<ModifierInvocation>,
+}
+
+impl<'a> Parser<'a> {
+    pub(super) fn peek(&self) -> &Token {
+        self.peek_at(0)
+    }
+
+    pub(super) fn peek_at(&self, ahead: usize) -> &Token {
+        let last = self.tokens.len() - 1;
+        &self.tokens[(self.position + ahead).min(last)]
+    }
+
+    pub(super) fn advance(&mut self) -> Token {
+        let token = self.peek().clone();
+        if token.kind != TokenKind::Eof {
+            self.position += 1;
+        }
+        token
+    }
+
+    /// Whether the next token is the punctuation or keyword `text`
+    pub(super) fn at(&self, text: &str) -> bool {
+        self.at_ahead(0, text)
+    }
+
+    pub(super) fn at_ahead(&self, ahead: usize, text: &str) -> bool {
+        let token = self.peek_at(ahead);
+        matches!(token.kind, TokenKind::Punctuation | TokenKind::Identifier) && token.text == text
+    }
+
+    pub(super) fn eat(&mut self, text: &str) -> bool {
+        let found = self.at(text);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    pub(super) fn expect(&mut self, text: &str) -> Result<Token, MigrationError> {
+        match self.at(text) {
+            true => Ok(self.advance()),
+            false => Err(self.unexpected(&format!("`{}`", text))),
+        }
+    }
+
+    pub(super) fn identifier(&mut self) -> Result<String, MigrationError> {
+        match self.peek().kind {
+            TokenKind::Identifier => Ok(self.advance().text),
+            _ => Err(self.unexpected("an identifier")),
+        }
+    }
+
+    /// A dotted path, like `Library.Struct`
+    fn path(&mut self) -> Result<String, MigrationError> {
+        let mut path = self.identifier()?;
+        while self.at(".") && self.peek_at(1).kind == TokenKind::Identifier {
+            self.position += 1;
+            path = format!("{}.{}", path, self.advance().text);
+        }
+        Ok(path)
+    }
+
+    pub(super) fn unexpected(&self, expected: &str) -> MigrationError {
+        let token = self.peek();
+        let found = match token.kind {
+            TokenKind::Eof => "the end of the file".to_string(),
+            TokenKind::String | TokenKind::HexString => format!("\"{}\"", token.text),
+            _ => format!("`{}`", token.text),
+        };
+        error_at(
+            self.file,
+            token.line,
+            token.column,
+            format!("expected {}, found {}", expected, found),
+        )
+    }
+
+    /// The location from `start` to the last token parsed
+    pub(super) fn location(&self, start: &Token) -> SolLocation {
+        let end = match self.position {
+            0 => start.end,
+            position => self.tokens[position - 1].end.max(start.start),
+        };
+        SolLocation {
+            file: self.file.to_string(),
+            line: start.line,
+            column: start.column,
+            start: start.start,
+            end,
+        }
+    }
+
+    /// Run `parse`, going back to where it started when it fails
+    pub(super) fn attempt<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, MigrationError>,
+    ) -> Option<T> {
+        let position = self.position;
+        let result = parse(self).ok();
+        if result.is_none() {
+            self.position = position;
+        }
+        result
+    }
+
+    /// Parse `item`s separated by commas up to the closing `close`
+    pub(super) fn list<T>(
+        &mut self,
+        close: &str,
+        mut item: impl FnMut(&mut Self) -> Result<T, MigrationError>,
+    ) -> Result<Vec<T>, MigrationError> {
+        let mut items = Vec::new();
+        while !self.at(close) {
+            items.push(item(self)?);
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect(close)?;
+        Ok(items)
+    }
+
+    fn parse_source(&mut self) -> Result<SoliditySource, MigrationError> {
+        let start = self.peek().clone();
+        let mut source = SoliditySource::new(self.location(&start));
+
+        while self.peek().kind != TokenKind::Eof {
+            let token = self.peek().clone();
+            match token.text.as_str() {
+                "pragma" => {
+                    if let Some(pragma) = self.parse_pragma()? {
+                        source.version_pragma = Some(pragma);
+                    }
+                }
+                "import" => source.imports.push(self.parse_import()?),
+                "abstract" | "contract" | "interface" | "library" => {
+                    source.contracts.push(self.parse_contract()?)
+                }
+                "struct" => source.structs.push(self.parse_struct()?),
+                "enum" => source.enums.push(self.parse_enum()?),
+                "function" => source.functions.push(self.parse_function()?),
+                "event" => source.events.push(self.parse_event()?),
+                "error" => source.errors.push(self.parse_error()?),
+                "using" => source.using_directives.push(self.parse_using()?),
+                "type" if self.peek_at(2).text == "is" => {
+                    source.type_definitions.push(self.parse_type_definition()?)
+                }
+                ";" => {
+                    self.advance();
+                }
+                _ => source.constants.push(self.parse_state_variable()?),
+            }
+        }
+        source.location = self.location(&start);
+        Ok(source)
+    }
+
+    /// A pragma, which is the version pragma for `pragma solidity`
+    fn parse_pragma(&mut self) -> Result<Option<VersionPragma>, MigrationError> {
+        self.expect("pragma")?;
+        let name = self.identifier()?;
+        let start = self.peek().start;
+        while !self.at(";") && self.peek().kind != TokenKind::Eof {
+            self.advance();
+        }
+        let end = self.tokens[self.position - 1].end.max(start);
+        self.expect(";")?;
+        if name != "solidity" {
+            return Ok(None);
+        }
+        let text = self.source[start..end].trim();
+        let version = text.trim_start_matches(['^', '~', '>', '<', '=']);
+        Ok(Some(VersionPragma {
+            operator: text[..text.len() - version.len()].to_string(),
+            version: version.trim().to_string(),
+        }))
+    }
+
+    fn string(&mut self) -> Result<String, MigrationError> {
+        match self.peek().kind {
+            TokenKind::String => Ok(self.advance().text),
+            _ => Err(self.unexpected("a string")),
+        }
+    }
+
+    fn parse_import(&mut self) -> Result<ImportDirective, MigrationError> {
+        let start = self.expect("import")?;
+        let import = if self.peek().kind == TokenKind::String {
+            let path = self.string()?;
+            match self.eat("as") {
+                true => {
+                    let alias = self.identifier()?;
+                    ImportDirective::NamedImport {
+                        path,
+                        symbols: vec![("*".to_string(), Some(alias))],
+                        location: self.location(&start),
+                    }
+                }
+                false => ImportDirective::DirectImport {
+                    path,
+                    location: self.location(&start),
+                },
+            }
+        } else if self.eat("*") {
+            self.expect("as")?;
+            let alias = self.identifier()?;
+            self.expect("from")?;
+            let path = self.string()?;
+            ImportDirective::NamedImport {
+                path,
+                symbols: vec![("*".to_string(), Some(alias))],
+                location: self.location(&start),
+            }
+        } else {
+            self.expect("{")?;
+            let items = self.list("}", |parser| {
+                let start = parser.peek().clone();
+                let name = parser.identifier()?;
+                let alias = match parser.eat("as") {
+                    true => Some(parser.identifier()?),
+                    false => None,
+                };
+                Ok(ImportItem {
+                    name,
+                    alias,
+                    location: parser.location(&start),
+                })
+            })?;
+            self.expect("from")?;
+            let path = self.string()?;
+            ImportDirective::SelectiveImport {
+                path,
+                items,
+                location: self.location(&start),
+            }
+        };
+        self.expect(";")?;
+        Ok(import)
+    }
+
+    fn parse_contract(&mut self) -> Result<ContractDefinition, MigrationError> {
+        let start = self.peek().clone();
+        let is_abstract = self.eat("abstract");
+        let kind = match self.advance().text.as_str() {
+            "contract" => ContractKind::Contract,
+            "interface" => ContractKind::Interface,
+            _ => ContractKind::Library,
+        };
+        let name = self.identifier()?;
+        let mut base_contracts = Vec::new();
+        if self.eat("is") {
+            loop {
+                let start = self.peek().clone();
+                let name = self.path()?;
+                let arguments = match self.eat("(") {
+                    true => self.list(")", Self::parse_expression)?,
+                    false => Vec::new(),
+                };
+                base_contracts.push(BaseContract {
+                    name,
+                    arguments,
+                    location: self.location(&start),
+                });
+                if !self.eat(",") {
+                    break;
+                }
+            }
+        }
+
+        let mut contract = ContractDefinition {
+            base_contracts,
+            is_abstract,
+            ..ContractDefinition::new(name, kind, self.location(&start))
+        };
+        self.expect("{")?;
+        while !self.eat("}") {
+            match self.peek().text.as_str() {
+                "function" | "constructor" | "fallback" | "receive"
+                    if self.peek().kind == TokenKind::Identifier =>
+                {
+                    let mut function = self.parse_function()?;
+                    if contract.kind == ContractKind::Interface && function.body.is_none() {
+                        function.visibility = Visibility::External;
+                    }
+                    contract.functions.push(function)
+                }
+                "modifier" => contract.modifiers.push(self.parse_modifier()?),
+                "event" => contract.events.push(self.parse_event()?),
+                "error" => contract.errors.push(self.parse_error()?),
+                "struct" => contract.structs.push(self.parse_struct()?),
+                "enum" => contract.enums.push(self.parse_enum()?),
+                "using" => contract.using_directives.push(self.parse_using()?),
+                "type" if self.peek_at(2).text == "is" => contract
+                    .type_definitions
+                    .push(self.parse_type_definition()?),
+                ";" => {
+                    self.advance();
+                }
+                _ if self.peek().kind == TokenKind::Eof => return Err(self.unexpected("`}`")),
+                _ => contract.state_variables.push(self.parse_state_variable()?),
+            }
+        }
+        contract.location = self.location(&start);
+        Ok(contract)
+    }
+
+    /// The visibility, mutability, `virtual`, `override` and modifier
+    /// invocations following a function's parameters, or a variable's type
+    fn parse_specifiers(&mut self, variable: bool) -> Result<Specifiers, MigrationError> {
+        let mut specifiers = Specifiers::default();
+        loop {
+            let token = self.peek().clone();
+            if token.kind != TokenKind::Identifier {
+                return Ok(specifiers);
+            }
+            match token.text.as_str() {
+                "public" => specifiers.visibility = Some(Visibility::Public),
+                "private" => specifiers.visibility = Some(Visibility::Private),
+                "internal" => specifiers.visibility = Some(Visibility::Internal),
+                "external" => specifiers.visibility = Some(Visibility::External),
+                "pure" => specifiers.state_mutability = Some(StateMutability::Pure),
+                "view" | "constant" if !variable => {
+                    specifiers.state_mutability = Some(StateMutability::View)
+                }
+                "payable" => specifiers.state_mutability = Some(StateMutability::Payable),
+                "virtual" => specifiers.is_virtual = true,
+                "constant" => specifiers.is_constant = true,
+                "immutable" => specifiers.is_immutable = true,
+                "transient" => {}
+                "override" => {
+                    self.advance();
+                    let paths = match self.eat("(") {
+                        true => self.list(")", Self::path)?,
+                        false => Vec::new(),
+                    };
+                    specifiers.overrides = Some(paths);
+                    continue;
+                }
+                "returns" => return Ok(specifiers),
+                _ if variable => return Ok(specifiers),
+                _ => {
+                    let name = self.path()?;
+                    let arguments = match self.eat("(") {
+                        true => self.list(")", Self::parse_expression)?,
+                        false => Vec::new(),
+                    };
+                    specifiers.modifiers.push(ModifierInvocation {
+                        name,
+                        arguments,
+                        location: self.location(&token),
+                    });
+                    continue;
+                }
+            }
+            self.advance();
+        }
+    }
+
+    /// A parameter of a function, event or error, or a return parameter
+    fn parse_parameter(&mut self) -> Result<VariableDeclaration, MigrationError> {
+        let start = self.peek().clone();
+        let type_name = self.parse_type()?;
+        let storage_location = self.parse_storage_location();
+        let indexed = self.eat("indexed");
+        let name = match self.peek().kind {
+            TokenKind::Identifier => Some(self.advance().text),
+            _ => None,
+        };
+        Ok(VariableDeclaration {
+            name,
+            type_name,
+            storage_location,
+            indexed,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_parameters(&mut self) -> Result<Vec<VariableDeclaration>, MigrationError> {
+        self.expect("(")?;
+        self.list(")", Self::parse_parameter)
+    }
+
+    fn parse_storage_location(&mut self) -> StorageLocation {
+        let location = match self.peek().text.as_str() {
+            "memory" => StorageLocation::Memory,
+            "storage" => StorageLocation::Storage,
+            "calldata" => StorageLocation::Calldata,
+            _ => return StorageLocation::Default,
+        };
+        self.advance();
+        location
+    }
+
+    fn parse_function(&mut self) -> Result<FunctionDefinition, MigrationError> {
+        let start = self.advance();
+        let name = match start.text.as_str() {
+            "function" if self.at("(") => "fallback".to_string(),
+            "function" => self.identifier()?,
+            keyword => keyword.to_string(),
+        };
+        let parameters = self.parse_parameters()?;
+        let specifiers = self.parse_specifiers(false)?;
+        let return_parameters = match self.eat("returns") {
+            true => self.parse_parameters()?,
+            false => Vec::new(),
+        };
+        // Specifiers may also follow the return parameters
+        let after = self.parse_specifiers(false)?;
+        let body = match self.eat(";") {
+            true => None,
+            false => Some(self.parse_block()?),
+        };
+
+        let mut modifiers = specifiers.modifiers;
+        modifiers.extend(after.modifiers);
+        Ok(FunctionDefinition {
+            is_constructor: name == "constructor",
+            is_fallback: name == "fallback",
+            is_receive: name == "receive",
+            name,
+            parameters,
+            return_parameters,
+            body,
+            visibility: after
+                .visibility
+                .or(specifiers.visibility)
+                .unwrap_or(Visibility::Public),
+            state_mutability: after
+                .state_mutability
+                .or(specifiers.state_mutability)
+                .unwrap_or(StateMutability::NonPayable),
+            virtual_flag: specifiers.is_virtual || after.is_virtual,
+            override_specifiers: specifiers.overrides.or(after.overrides).unwrap_or_default(),
+            modifiers,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_modifier(&mut self) -> Result<ModifierDefinition, MigrationError> {
+        let start = self.expect("modifier")?;
+        let name = self.identifier()?;
+        let parameters = match self.at("(") {
+            true => self.parse_parameters()?,
+            false => Vec::new(),
+        };
+        let specifiers = self.parse_specifiers(false)?;
+        let body = match self.at(";") {
+            true => {
+                let token = self.advance();
+                Block {
+                    statements: Vec::new(),
+                    location: self.location(&token),
+                }
+            }
+            false => self.parse_block()?,
+        };
+        Ok(ModifierDefinition {
+            name,
+            parameters,
+            body,
+            visibility: Visibility::Internal,
+            is_virtual: specifiers.is_virtual,
+            override_specifiers: specifiers.overrides.unwrap_or_default(),
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_event(&mut self) -> Result<EventDefinition, MigrationError> {
+        let start = self.expect("event")?;
+        let name = self.identifier()?;
+        let parameters = self.parse_parameters()?;
+        let anonymous = self.eat("anonymous");
+        self.expect(";")?;
+        Ok(EventDefinition {
+            name,
+            parameters,
+            anonymous,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_error(&mut self) -> Result<ErrorDefinition, MigrationError> {
+        let start = self.expect("error")?;
+        let name = self.identifier()?;
+        let parameters = self.parse_parameters()?;
+        self.expect(";")?;
+        Ok(ErrorDefinition {
+            name,
+            parameters,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_struct(&mut self) -> Result<StructDefinition, MigrationError> {
+        let start = self.expect("struct")?;
+        let name = self.identifier()?;
+        self.expect("{")?;
+        let mut members = Vec::new();
+        while !self.eat("}") {
+            let start = self.peek().clone();
+            let type_name = self.parse_type()?;
+            let name = self.identifier()?;
+            self.expect(";")?;
+            members.push(VariableDeclaration {
+                name: Some(name),
+                type_name,
+                storage_location: StorageLocation::Default,
+                indexed: false,
+                location: self.location(&start),
+            });
+        }
+        Ok(StructDefinition {
+            name,
+            members,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_enum(&mut self) -> Result<EnumDefinition, MigrationError> {
+        let start = self.expect("enum")?;
+        let name = self.identifier()?;
+        self.expect("{")?;
+        let values = self.list("}", Self::identifier)?;
+        Ok(EnumDefinition {
+            name,
+            values,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_using(&mut self) -> Result<UsingDirective, MigrationError> {
+        let start = self.expect("using")?;
+        let (library, functions) = match self.eat("{") {
+            true => {
+                let functions = self.list("}", |parser| {
+                    let function = parser.path()?;
+                    if parser.eat("as") {
+                        parser.advance();
+                    }
+                    Ok(function)
+                })?;
+                (None, functions)
+            }
+            false => (Some(self.path()?), Vec::new()),
+        };
+        self.expect("for")?;
+        let type_name = match self.eat("*") {
+            true => None,
+            false => Some(self.parse_type()?),
+        };
+        self.eat("global");
+        self.expect(";")?;
+        Ok(UsingDirective {
+            library,
+            functions,
+            type_name,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_type_definition(&mut self) -> Result<TypeDefinition, MigrationError> {
+        let start = self.expect("type")?;
+        let name = self.identifier()?;
+        self.expect("is")?;
+        let underlying_type = self.parse_type()?;
+        self.expect(";")?;
+        Ok(TypeDefinition {
+            name,
+            underlying_type,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_state_variable(&mut self) -> Result<StateVariable, MigrationError> {
+        let start = self.peek().clone();
+        let type_name = self.parse_type()?;
+        let specifiers = self.parse_specifiers(true)?;
+        let name = self.identifier()?;
+        let value = match self.eat("=") {
+            true => Some(self.parse_expression()?),
+            false => None,
+        };
+        self.expect(";")?;
+        let mutability = match (specifiers.is_constant, specifiers.is_immutable) {
+            (true, _) => Mutability::Constant,
+            (_, true) => Mutability::Immutable,
+            _ => Mutability::Mutable,
+        };
+        Ok(StateVariable {
+            name,
+            type_name,
+            visibility: specifiers.visibility.unwrap_or(Visibility::Internal),
+            is_constant: mutability == Mutability::Constant,
+            mutability,
+            value,
+            overrides: specifiers.overrides,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_type(&mut self) -> Result<TypeName, MigrationError> {
+        let start = self.peek().clone();
+        let mut type_name = match start.text.as_str() {
+            "mapping" if start.kind == TokenKind::Identifier => {
+                self.advance();
+                self.expect("(")?;
+                let key_type = self.parse_type()?;
+                if self.peek().kind == TokenKind::Identifier {
+                    self.advance();
+                }
+                self.expect("=>")?;
+                let value_type = self.parse_type()?;
+                if self.peek().kind == TokenKind::Identifier {
+                    self.advance();
+                }
+                self.expect(")")?;
+                TypeName::Mapping(MappingTypeName {
+                    key_type: Box::new(key_type),
+                    value_type: Box::new(value_type),
+                    location: self.location(&start),
+                })
+            }
+            "function" if start.kind == TokenKind::Identifier => {
+                self.advance();
+                let parameter_types = self
+                    .parse_parameters()?
+                    .into_iter()
+                    .map(|parameter| parameter.type_name)
+                    .collect();
+                let mut visibility = Visibility::Internal;
+                let mut state_mutability = StateMutability::NonPayable;
+                loop {
+                    match self.peek().text.as_str() {
+                        "external" => visibility = Visibility::External,
+                        "internal" => visibility = Visibility::Internal,
+                        "pure" => state_mutability = StateMutability::Pure,
+                        "view" => state_mutability = StateMutability::View,
+                        "payable" => state_mutability = StateMutability::Payable,
+                        _ => break,
+                    }
+                    self.advance();
+                }
+                let return_types = match self.eat("returns") {
+                    true => self
+                        .parse_parameters()?
+                        .into_iter()
+                        .map(|parameter| parameter.type_name)
+                        .collect(),
+                    false => Vec::new(),
+                };
+                TypeName::Function(FunctionTypeName {
+                    parameter_types,
+                    return_types,
+                    visibility,
+                    state_mutability,
+                    location: self.location(&start),
+                })
+            }
+            name if start.kind == TokenKind::Identifier && is_elementary(name) => {
+                self.advance();
+                let payable = name == "address" && self.eat("payable");
+                TypeName::Elementary(ElementaryTypeName {
+                    name: match payable {
+                        true => "address payable".to_string(),
+                        false => name.to_string(),
+                    },
+                    location: self.location(&start),
+                })
+            }
+            keyword if KEYWORDS.contains(&keyword) => return Err(self.unexpected("a type")),
+            _ => {
+                let name = self.path()?;
+                TypeName::UserDefined(UserDefinedTypeName {
+                    name,
+                    type_arguments: Vec::new(),
+                    location: self.location(&start),
+                })
+            }
+        };
+
+        while self.at("[") {
+            self.advance();
+            let length = match self.at("]") {
+                true => None,
+                false => Some(Box::new(self.parse_expression()?)),
+            };
+            self.expect("]")?;
+            type_name = TypeName::Array(Box::new(ArrayTypeName {
+                base_type: Box::new(type_name),
+                length,
+                location: self.location(&start),
+            }));
+        }
+        Ok(type_name)
+    }
+
+    fn parse_block(&mut self) -> Result<Block, MigrationError> {
+        let start = self.expect("{")?;
+        let mut statements = Vec::new();
+        while !self.eat("}") {
+            if self.peek().kind == TokenKind::Eof {
+                return Err(self.unexpected("`}`"));
+            }
+            statements.push(self.parse_statement()?);
+        }
+        Ok(Block {
+            statements,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, MigrationError> {
+        let start = self.peek().clone();
+        let keyword = match start.kind {
+            TokenKind::Identifier | TokenKind::Punctuation => start.text.as_str(),
+            _ => "",
+        };
+        let statement = match keyword {
+            "{" => Statement::Block(self.parse_block()?),
+            "if" => {
+                self.advance();
+                self.expect("(")?;
+                let condition = self.parse_expression()?;
+                self.expect(")")?;
+                let true_body = Box::new(self.parse_statement()?);
+                let false_body = match self.eat("else") {
+                    true => Some(Box::new(self.parse_statement()?)),
+                    false => None,
+                };
+                Statement::If(IfStatement {
+                    condition,
+                    true_body,
+                    false_body,
+                    location: self.location(&start),
+                })
+            }
+            "for" => {
+                self.advance();
+                self.expect("(")?;
+                let initialization = match self.eat(";") {
+                    true => None,
+                    false => Some(Box::new(self.parse_simple_statement()?)),
+                };
+                let condition = match self.at(";") {
+                    true => None,
+                    false => Some(self.parse_expression()?),
+                };
+                self.expect(";")?;
+                let iteration = match self.at(")") {
+                    true => None,
+                    false => {
+                        let start = self.peek().clone();
+                        let expression = self.parse_expression()?;
+                        Some(Box::new(self.expression_statement(expression, &start)))
+                    }
+                };
+                self.expect(")")?;
+                let body = Box::new(self.parse_statement()?);
+                Statement::For(ForStatement {
+                    initialization,
+                    condition,
+                    iteration,
+                    body,
+                    location: self.location(&start),
+                })
+            }
+            "while" => {
+                self.advance();
+                self.expect("(")?;
+                let condition = self.parse_expression()?;
+                self.expect(")")?;
+                let body = Box::new(self.parse_statement()?);
+                Statement::While(WhileStatement {
+                    condition,
+                    body,
+                    location: self.location(&start),
+                })
+            }
+            "do" => {
+                self.advance();
+                let body = Box::new(self.parse_statement()?);
+                self.expect("while")?;
+                self.expect("(")?;
+                let condition = self.parse_expression()?;
+                self.expect(")")?;
+                self.expect(";")?;
+                Statement::DoWhile(DoWhileStatement {
+                    body,
+                    condition,
+                    location: self.location(&start),
+                })
+            }
+            "continue" => {
+                self.advance();
+                self.expect(";")?;
+                Statement::Continue(ContinueStatement {
+                    location: self.location(&start),
+                })
+            }
+            "break" => {
+                self.advance();
+                self.expect(";")?;
+                Statement::Break(BreakStatement {
+                    location: self.location(&start),
+                })
+            }
+            "return" => {
+                self.advance();
+                let expression = match self.at(";") {
+                    true => None,
+                    false => Some(self.parse_expression()?),
+                };
+                self.expect(";")?;
+                Statement::Return(ReturnStatement {
+                    expression,
+                    location: self.location(&start),
+                })
+            }
+            "emit" => {
+                self.advance();
+                let event = self.parse_expression()?;
+                self.expect(";")?;
+                Statement::Emit(EmitStatement {
+                    event,
+                    location: self.location(&start),
+                })
+            }
+            "revert" if !self.at_ahead(1, "=") && !self.at_ahead(1, ".") => {
+                self.advance();
+                let error_call = match self.at("(") {
+                    // `revert("reason")` reverts with the reason itself
+                    true => {
+                        self.advance();
+                        self.list(")", Self::parse_expression)?.into_iter().next()
+                    }
+                    false => Some(self.parse_expression()?),
+                };
+                self.expect(";")?;
+                Statement::Revert(RevertStatement {
+                    error_call,
+                    location: self.location(&start),
+                })
+            }
+            "assembly" => {
+                self.advance();
+                if self.peek().kind == TokenKind::String {
+                    self.advance();
+                }
+                if self.eat("(") {
+                    self.list(")", Self::string)?;
+                }
+                let open = self.expect("{")?;
+                let mut depth = 1;
+                while depth > 0 {
+                    let token = self.advance();
+                    match token.text.as_str() {
+                        _ if token.kind == TokenKind::Eof => return Err(self.unexpected("`}`")),
+                        "{" if token.kind == TokenKind::Punctuation => depth += 1,
+                        "}" if token.kind == TokenKind::Punctuation => depth -= 1,
+                        _ => {}
+                    }
+                }
+                let close = self.tokens[self.position - 1].start;
+                Statement::Assembly(InlineAssembly {
+                    operations: self.source[open.end..close].trim().to_string(),
+                    location: self.location(&start),
+                })
+            }
+            "unchecked" if self.at_ahead(1, "{") => {
+                self.advance();
+                let block = self.parse_block()?;
+                Statement::Unchecked(UncheckedBlock {
+                    block,
+                    location: self.location(&start),
+                })
+            }
+            "_" if self.at_ahead(1, ";") => {
+                self.advance();
+                self.advance();
+                Statement::Placeholder(PlaceholderStatement {
+                    location: self.location(&start),
+                })
+            }
+            "try" if start.kind == TokenKind::Identifier => {
+                return Err(MigrationError::UnsupportedFeature(format!(
+                    "try/catch at {}:{}:{}",
+                    self.file, start.line, start.column
+                )))
+            }
+            _ => self.parse_simple_statement()?,
+        };
+        Ok(statement)
+    }
+
+    /// A variable declaration or expression statement, with its `;`
+    fn parse_simple_statement(&mut self) -> Result<Statement, MigrationError> {
+        let start = self.peek().clone();
+        if let Some(declarations) = self.attempt(Self::parse_declarations) {
+            let initial_value = match self.eat("=") {
+                true => Some(self.parse_expression()?),
+                false => None,
+            };
+            self.expect(";")?;
+            return Ok(Statement::VariableDeclaration(
+                VariableDeclarationStatement {
+                    declarations,
+                    initial_value,
+                    location: self.location(&start),
+                },
+            ));
+        }
+        let expression = self.parse_expression()?;
+        self.expect(";")?;
+        Ok(self.expression_statement(expression, &start))
+    }
+
+    fn expression_statement(&self, expression: Expression, start: &Token) -> Statement {
+        match expression {
+            Expression::Assignment(assignment) => Statement::Assignment(AssignmentStatement {
+                assignment,
+                location: self.location(start),
+            }),
+            expression => Statement::Expression(ExpressionStatement {
+                expression,
+                location: self.location(start),
+            }),
+        }
+    }
+
+    /// The variables a declaration statement declares: `uint x` or
+    /// `(uint a, , bool c)`, failing on anything else
+    fn parse_declarations(&mut self) -> Result<Vec<VariableDeclaration>, MigrationError> {
+        let declaration = |parser: &mut Self| -> Result<VariableDeclaration, MigrationError> {
+            let start = parser.peek().clone();
+            let type_name = parser.parse_type()?;
+            let storage_location = parser.parse_storage_location();
+            let name = parser.identifier()?;
+            Ok(VariableDeclaration {
+                name: Some(name),
+                type_name,
+                storage_location,
+                indexed: false,
+                location: parser.location(&start),
+            })
+        };
+        if !self.eat("(") {
+            let declaration = declaration(self)?;
+            if !self.at("=") && !self.at(";") {
+                return Err(self.unexpected("`=` or `;`"));
+            }
+            return Ok(vec![declaration]);
+        }
+
+        let mut declarations = Vec::new();
+        loop {
+            if self.at(",") || self.at(")") {
+                let start = self.peek().clone();
+                declarations.push(VariableDeclaration {
+                    name: None,
+                    type_name: TypeName::Elementary(ElementaryTypeName {
+                        name: String::new(),
+                        location: self.location(&start),
+                    }),
+                    storage_location: StorageLocation::Default,
+                    indexed: false,
+                    location: self.location(&start),
+                });
+            } else {
+                declarations.push(declaration(self)?);
+            }
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect(")")?;
+        if !self.at("=") {
+            return Err(self.unexpected("`=`"));
+        }
+        Ok(declarations)
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let left = self.parse_conditional()?;
+        let Some(operator) = assignment_operator(&self.peek().text)
+            .filter(|_| self.peek().kind == TokenKind::Punctuation)
+        else {
+            return Ok(left);
+        };
+        self.advance();
+        let right = self.parse_expression()?;
+        Ok(Expression::Assignment(Assignment {
+            operator,
+            left: Box::new(left),
+            right: Box::new(right),
+            location: self.location(&start),
+        }))
+    }
+
+    fn parse_conditional(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let condition = self.parse_binary(1)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let true_expression = self.parse_expression()?;
+        self.expect(":")?;
+        let false_expression = self.parse_expression()?;
+        Ok(Expression::Conditional(Conditional {
+            condition: Box::new(condition),
+            true_expression: Box::new(true_expression),
+            false_expression: Box::new(false_expression),
+            location: self.location(&start),
+        }))
+    }
+
+    /// Binary operations binding at least as tightly as `precedence`
+    fn parse_binary(&mut self, precedence: u8) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let mut left = self.parse_power()?;
+        loop {
+            let token = self.peek();
+            let Some((operator, level)) = binary_operator(&token.text)
+                .filter(|(_, level)| token.kind == TokenKind::Punctuation && *level >= precedence)
+            else {
+                return Ok(left);
+            };
+            self.advance();
+            let right = self.parse_binary(level + 1)?;
+            left = Expression::BinaryOperation(BinaryOperation {
+                operator,
+                left: Box::new(left),
+                right: Box::new(right),
+                location: self.location(&start),
+            });
+        }
+    }
+
+    /// `**`, which associates to the right
+    fn parse_power(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let base = self.parse_unary()?;
+        if !self.eat("**") {
+            return Ok(base);
+        }
+        let exponent = self.parse_power()?;
+        Ok(Expression::BinaryOperation(BinaryOperation {
+            operator: BinaryOperator::Pow,
+            left: Box::new(base),
+            right: Box::new(exponent),
+            location: self.location(&start),
+        }))
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let operator = match (&start.kind, start.text.as_str()) {
+            (TokenKind::Punctuation, "!") => UnaryOperator::Not,
+            (TokenKind::Punctuation, "-") => UnaryOperator::Neg,
+            (TokenKind::Punctuation, "~") => UnaryOperator::BitNot,
+            (TokenKind::Punctuation, "++") => UnaryOperator::Inc,
+            (TokenKind::Punctuation, "--") => UnaryOperator::Dec,
+            (TokenKind::Identifier, "delete") => UnaryOperator::Delete,
+            _ => return self.parse_postfix(),
+        };
+        self.advance();
+        let operand = self.parse_unary()?;
+        Ok(Expression::UnaryOperation(UnaryOperation {
+            operator,
+            operand: Box::new(operand),
+            is_prefix: true,
+            location: self.location(&start),
+        }))
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let mut expression = self.parse_primary()?;
+        loop {
+            expression = if self.eat(".") {
+                let member_name = self.identifier()?;
+                Expression::MemberAccess(MemberAccess {
+                    expression: Box::new(expression),
+                    member_name,
+                    location: self.location(&start),
+                })
+            } else if self.eat("[") {
+                let index = self.parse_expression()?;
+                self.expect("]")?;
+                Expression::IndexAccess(IndexAccess {
+                    base: Box::new(expression),
+                    index: Box::new(index),
+                    location: self.location(&start),
+                })
+            } else if self.at("{")
+                && self.peek_at(1).kind == TokenKind::Identifier
+                && self.at_ahead(2, ":")
+            {
+                self.advance();
+                let options = self.list("}", |parser| {
+                    let name = parser.identifier()?;
+                    parser.expect(":")?;
+                    Ok((name, parser.parse_expression()?))
+                })?;
+                self.expect("(")?;
+                let (arguments, names) = self.parse_arguments()?;
+                Expression::FunctionCall(FunctionCall {
+                    expression: Box::new(expression),
+                    arguments,
+                    names,
+                    options,
+                    location: self.location(&start),
+                })
+            } else if self.eat("(") {
+                let (arguments, names) = self.parse_arguments()?;
+                Expression::FunctionCall(FunctionCall {
+                    expression: Box::new(expression),
+                    arguments,
+                    names,
+                    options: Vec::new(),
+                    location: self.location(&start),
+                })
+            } else if self.at("++") || self.at("--") {
+                let operator = match self.advance().text.as_str() {
+                    "++" => UnaryOperator::Inc,
+                    _ => UnaryOperator::Dec,
+                };
+                Expression::UnaryOperation(UnaryOperation {
+                    operator,
+                    operand: Box::new(expression),
+                    is_prefix: false,
+                    location: self.location(&start),
+                })
+            } else {
+                return Ok(expression);
+            };
+        }
+    }
+
+    /// The arguments of a call after its `(`, by position or by name
+    fn parse_arguments(&mut self) -> Result<(Vec<Expression>, Vec<String>), MigrationError> {
+        if !self.eat("{") {
+            return Ok((self.list(")", Self::parse_expression)?, Vec::new()));
+        }
+        let named = self.list("}", |parser| {
+            let name = parser.identifier()?;
+            parser.expect(":")?;
+            Ok((name, parser.parse_expression()?))
+        })?;
+        self.expect(")")?;
+        let (names, arguments) = named.into_iter().unzip();
+        Ok((arguments, names))
+    }
+
+    pub(super) fn literal(&self, start: &Token, value: String, type_name: &str) -> Expression {
+        Expression::Literal(Literal {
+            value: Some(value),
+            subdenomination: None,
+            type_name: Some(type_name.to_string()),
+            location: self.location(start),
+        })
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        match start.kind {
+            TokenKind::Number => {
+                self.advance();
+                let subdenomination = subdenomination(&self.peek().text)
+                    .filter(|_| self.peek().kind == TokenKind::Identifier);
+                if subdenomination.is_some() {
+                    self.advance();
+                }
+                return Ok(Expression::Literal(Literal {
+                    value: Some(start.text.replace('_', "")),
+                    subdenomination,
+                    type_name: Some("number".to_string()),
+                    location: self.location(&start),
+                }));
+            }
+            TokenKind::String => {
+                // Adjacent string literals are one string
+                let mut value = self.advance().text;
+                while self.peek().kind == TokenKind::String {
+                    value.push_str(&self.advance().text);
+                }
+                return Ok(self.literal(&start, value, "string"));
+            }
+            TokenKind::HexString => {
+                self.advance();
+                return Ok(self.literal(&start, start.text.replace('_', ""), "hex"));
+            }
+            TokenKind::Eof => return Err(self.unexpected("an expression")),
+            TokenKind::Identifier | TokenKind::Punctuation => {}
+        }
+
+        match start.text.as_str() {
+            "(" => {
+                self.advance();
+                let mut elements = Vec::new();
+                let mut tuple = false;
+                loop {
+                    if self.at(",") || self.at(")") {
+                        // An omitted component, as in `(, b)`
+                        tuple = true;
+                        elements.push(Expression::Location(self.location(&start)));
+                    } else {
+                        elements.push(self.parse_expression()?);
+                    }
+                    if !self.eat(",") {
+                        break;
+                    }
+                    tuple = true;
+                }
+                self.expect(")")?;
+                if !tuple && elements.len() == 1 {
+                    return Ok(elements.remove(0));
+                }
+                Ok(Expression::Tuple(TupleExpression {
+                    elements,
+                    location: self.location(&start),
+                }))
+            }
+            "[" => {
+                self.advance();
+                let elements = self.list("]", Self::parse_expression)?;
+                Ok(Expression::ArrayLiteral(ArrayLiteral {
+                    elements,
+                    location: self.location(&start),
+                }))
+            }
+            "true" | "false" if start.kind == TokenKind::Identifier => {
+                self.advance();
+                Ok(self.literal(&start, start.text.clone(), "bool"))
+            }
+            "new" if start.kind == TokenKind::Identifier => {
+                self.advance();
+                let type_name = self.parse_type()?;
+                Ok(Expression::NewExpression(NewExpression {
+                    type_name,
+                    location: self.location(&start),
+                }))
+            }
+            "payable" if self.at_ahead(1, "(") => {
+                self.advance();
+                self.type_conversion(
+                    &start,
+                    TypeName::Elementary(ElementaryTypeName {
+                        name: "address payable".to_string(),
+                        location: self.location(&start),
+                    }),
+                )
+            }
+            name if start.kind == TokenKind::Identifier && is_elementary(name) => {
+                let type_name = self.parse_type()?;
+                match self.at("(") {
+                    true => self.type_conversion(&start, type_name),
+                    // A type as an argument, as in `type(uint256)` or
+                    // `abi.decode(data, (uint256))`
+                    false => Ok(Expression::Identifier(Identifier {
+                        name: self.source[start.start..self.tokens[self.position - 1].end]
+                            .to_string(),
+                        location: self.location(&start),
+                    })),
+                }
+            }
+            _ if start.kind == TokenKind::Identifier => {
+                self.advance();
+                Ok(Expression::Identifier(Identifier {
+                    name: start.text.clone(),
+                    location: self.location(&start),
+                }))
+            }
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+
+    /// `T(x)`, after the type `T`
+    fn type_conversion(
+        &mut self,
+        start: &Token,
+        type_name: TypeName,
+    ) -> Result<Expression, MigrationError> {
+        self.expect("(")?;
+        let expression = self.parse_expression()?;
+        self.expect(")")?;
+        Ok(Expression::TypeConversion(Box::new(TypeConversion {
+            type_name: Box::new(type_name),
+            expression: Box::new(expression),
+            location: self.location(start),
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = r#"
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+import "./IERC20.sol";
+import {Ownable as Owned, Context} from "@openzeppelin/contracts/access/Ownable.sol";
+
+/// A token with an owner
+contract Token is Owned(msg.sender), Context {
+    using SafeMath for uint256;
+
+    struct Checkpoint { uint64 block; uint192 votes; }
+    enum State { Active, Paused }
+
+    uint256 public constant DECIMALS = 18;
+    address immutable admin;
+    mapping(address => mapping(address owner => uint256)) private allowances;
+    uint256[] internal history;
+
+    event Transfer(address indexed from, address indexed to, uint256 value);
+    error Insufficient(uint256 available, uint256 needed);
+
+    modifier onlyAdmin() {
+        require(msg.sender == admin, "not the admin");
+        _;
+    }
+
+    constructor(address _admin) payable {
+        admin = _admin;
+    }
+
+    function transfer(address to, uint256 amount) external virtual onlyAdmin returns (bool ok) {
+        uint256 balance = balances[msg.sender];
+        if (balance < amount) revert Insufficient({available: balance, needed: amount});
+        unchecked { balances[msg.sender] = balance - amount; }
+        balances[to] += amount * 10 ** 18;
+        (bool sent, ) = payable(to).call{value: 1 ether}("");
+        for (uint i = 0; i < history.length; i++) { delete history[i]; }
+        emit Transfer(msg.sender, to, amount);
+        return sent ? true : false;
+    }
+
+    function max() public pure returns (uint256) {
+        assembly { let x := 1 }
+        return type(uint256).max;
+    }
+
+    receive() external payable {}
+}
+
+interface IVault {
+    function deposit(uint256 amount) external returns (uint256);
+}
+"#;
+
+    #[test]
+    fn test_parse_contract() {
+        let source = parse_solidity(TOKEN, "Token.sol").unwrap();
+        let pragma = source.version_pragma.unwrap();
+        assert_eq!(
+            (pragma.operator.as_str(), pragma.version.as_str()),
+            ("^", "0.8.20")
+        );
+        assert_eq!(source.imports.len(), 2);
+        assert!(matches!(
+            &source.imports[1],
+            ImportDirective::SelectiveImport { items, .. } if items[0].alias.as_deref() == Some("Owned")
+        ));
+
+        let [token, vault] = &source.contracts[..] else {
+            panic!("expected two contracts");
+        };
+        assert_eq!(vault.kind, ContractKind::Interface);
+        assert_eq!(vault.functions[0].visibility, Visibility::External);
+        assert!(vault.functions[0].body.is_none());
+
+        assert_eq!(token.base_contracts[0].name, "Owned");
+        assert_eq!(token.base_contracts[0].arguments.len(), 1);
+        assert_eq!(
+            token.using_directives[0].library.as_deref(),
+            Some("SafeMath")
+        );
+        assert_eq!(token.structs[0].members.len(), 2);
+        assert_eq!(token.enums[0].values, ["Active", "Paused"]);
+        let variables: Vec<_> = token
+            .state_variables
+            .iter()
+            .map(|variable| (variable.name.as_str(), variable.mutability.clone()))
+            .collect();
+        assert_eq!(
+            variables,
+            [
+                ("DECIMALS", Mutability::Constant),
+                ("admin", Mutability::Immutable),
+                ("allowances", Mutability::Mutable),
+                ("history", Mutability::Mutable),
+            ]
+        );
+        assert!(matches!(
+            &token.state_variables[2].type_name,
+            TypeName::Mapping(mapping) if matches!(*mapping.value_type, TypeName::Mapping(_))
+        ));
+        assert!(matches!(
+            token.state_variables[3].type_name,
+            TypeName::Array(_)
+        ));
+        assert_eq!(token.events[0].parameters.len(), 3);
+        let indexed: Vec<bool> = token.events[0]
+            .parameters
+            .iter()
+            .map(|parameter| parameter.indexed)
+            .collect();
+        assert_eq!(indexed, [true, true, false]);
+        assert_eq!(token.errors[0].name, "Insufficient");
+        assert!(matches!(
+            token.modifiers[0].body.statements[..],
+            [Statement::Expression(_), Statement::Placeholder(_)]
+        ));
+
+        let names: Vec<_> = token.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["constructor", "transfer", "max", "receive"]);
+        let transfer = &token.functions[1];
+        assert_eq!(transfer.visibility, Visibility::External);
+        assert!(transfer.virtual_flag);
+        assert_eq!(transfer.modifiers[0].name, "onlyAdmin");
+        assert_eq!(transfer.return_parameters[0].name.as_deref(), Some("ok"));
+        assert_eq!(transfer.location.line, 32);
+        let statements = &transfer.body.as_ref().unwrap().statements;
+        assert!(matches!(
+            statements[..],
+            [
+                Statement::VariableDeclaration(_),
+                Statement::If(_),
+                Statement::Unchecked(_),
+                Statement::Assignment(_),
+                Statement::VariableDeclaration(_),
+                Statement::For(_),
+                Statement::Emit(_),
+                Statement::Return(_),
+            ]
+        ));
+        let Statement::VariableDeclaration(call) = &statements[4] else {
+            unreachable!()
+        };
+        assert_eq!(call.declarations.len(), 2);
+        assert!(matches!(
+            &call.initial_value,
+            Some(Expression::FunctionCall(call)) if call.options[0].0 == "value"
+        ));
+        let Statement::Assembly(assembly) =
+            &token.functions[2].body.as_ref().unwrap().statements[0]
+        else {
+            panic!("expected inline assembly");
+        };
+        assert_eq!(assembly.operations, "let x := 1");
+        assert!(token.functions[3].is_receive);
+    }
+
+    #[test]
+    fn test_precedence() {
+        let source = parse_solidity(
+            "contract C { function f() public { x = a + b * c ** d ** e == f || !g; } }",
+            "C.sol",
+        )
+        .unwrap();
+        let Statement::Assignment(statement) = &source.contracts[0].functions[0]
+            .body
+            .as_ref()
+            .unwrap()
+            .statements[0]
+        else {
+            panic!("expected an assignment");
+        };
+        let Expression::BinaryOperation(or) = &*statement.assignment.right else {
+            panic!("expected ||");
+        };
+        assert_eq!(or.operator, BinaryOperator::LogicalOr);
+        let Expression::BinaryOperation(equal) = &*or.left else {
+            panic!("expected ==");
+        };
+        let Expression::BinaryOperation(add) = &*equal.left else {
+            panic!("expected +");
+        };
+        let Expression::BinaryOperation(mul) = &*add.right else {
+            panic!("expected *");
+        };
+        let Expression::BinaryOperation(pow) = &*mul.right else {
+            panic!("expected **");
+        };
+        assert_eq!(pow.operator, BinaryOperator::Pow);
+        assert!(
+            matches!(&*pow.right, Expression::BinaryOperation(inner) if inner.operator == BinaryOperator::Pow)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = parse_solidity("contract C {\n    uint x\n}", "C.sol").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Parse error: C.sol:3:1: expected `;`, found `}`"
+        );
+        assert!(parse_solidity("contract C { string s = \"open; }", "C.sol").is_err());
+        assert!(matches!(
+            parse_solidity(
+                "contract C { function f() public { try this.g() {} catch {} } }",
+                "C.sol"
+            ),
+            Err(MigrationError::UnsupportedFeature(_))
+        ));
+    }
+}
@@ -0,0 +1,374 @@
+//! # Migration Report
+//!
+//! Renders the issues found while migrating Solidity sources as a
+//! compatibility report: grouped by severity, located in the sources, with
+//! an estimate of the manual work left. The report is written as Markdown
+//! and HTML for reading and as JSON for tools.
+
+use super::{IssueSeverity, MigrationError, MigrationStats};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Severities in report order, from no work to a rewrite
+const SEVERITIES: [IssueSeverity; 4] = [
+    IssueSeverity::Supported,
+    IssueSeverity::Partial,
+    IssueSeverity::Manual,
+    IssueSeverity::Unsupported,
+];
+
+/// Hours of manual work estimated for an issue of `severity`
+///
+/// Rough figures: a partial translation needs a review, a manual one a
+/// rewrite of the construct, an unsupported one a redesign.
+pub fn effort_hours(severity: &IssueSeverity) -> f64 {
+    match severity {
+        IssueSeverity::Supported => 0.0,
+        IssueSeverity::Partial => 0.5,
+        IssueSeverity::Manual => 2.0,
+        IssueSeverity::Unsupported => 4.0,
+    }
+}
+
+/// Compatibility report of a migration
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+    /// Migrated files
+    pub files: Vec<String>,
+    /// Contracts converted
+    pub contracts: usize,
+    /// Functions translated
+    pub functions: usize,
+    /// Lines of Solidity migrated
+    pub lines_of_code: usize,
+    /// Issues by severity, in `SEVERITIES` order
+    pub groups: Vec<SeverityGroup>,
+    /// Estimated hours of manual work
+    pub effort_hours: f64,
+}
+
+/// The issues of one severity
+#[derive(Debug, Clone, Serialize)]
+pub struct SeverityGroup {
+    pub severity: IssueSeverity,
+    /// Estimated hours of manual work for the issues
+    pub effort_hours: f64,
+    pub issues: Vec<ReportedIssue>,
+}
+
+/// An issue, located in its Solidity source when known
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportedIssue {
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// The source line the issue is on
+    pub source_line: Option<String>,
+    pub description: String,
+    pub suggestion: Option<String>,
+}
+
+impl ReportedIssue {
+    /// `file:line:column`, with the parts that are known
+    pub fn location(&self) -> String {
+        let mut parts: Vec<String> = self.file.iter().cloned().collect();
+        parts.extend(self.line.iter().map(usize::to_string));
+        parts.extend(self.column.iter().map(usize::to_string));
+        if parts.is_empty() {
+            "unknown location".to_string()
+        } else {
+            parts.join(":")
+        }
+    }
+}
+
+impl MigrationReport {
+    /// Build the report of the migration `stats` describe
+    pub fn new(stats: &MigrationStats) -> Self {
+        let mut groups: Vec<SeverityGroup> = SEVERITIES
+            .iter()
+            .map(|severity| SeverityGroup {
+                severity: severity.clone(),
+                effort_hours: 0.0,
+                issues: Vec::new(),
+            })
+            .collect();
+
+        for (index, issue) in stats.issues.iter().enumerate() {
+            let file = stats.files.iter().find(|file| file.issues.contains(&index));
+            let mut position = issue
+                .source_location
+                .split(':')
+                .map(|part| part.parse::<usize>().ok());
+            let line = position.next().flatten();
+            let column = position.next().flatten();
+            let source_line = file.zip(line).and_then(|(file, line)| {
+                let text = file.source.lines().nth(line.checked_sub(1)?)?;
+                Some(text.trim().to_string())
+            });
+
+            let group = groups
+                .iter_mut()
+                .find(|group| group.severity == issue.severity)
+                .expect("every severity has a group");
+            group.effort_hours += effort_hours(&issue.severity);
+            group.issues.push(ReportedIssue {
+                file: file.map(|file| file.file.clone()),
+                line,
+                column,
+                source_line,
+                description: issue.description.clone(),
+                suggestion: issue.suggestion.clone(),
+            });
+        }
+
+        MigrationReport {
+            files: stats.files.iter().map(|file| file.file.clone()).collect(),
+            contracts: stats.contracts_processed,
+            functions: stats.functions_translated,
+            lines_of_code: stats.lines_of_code,
+            effort_hours: groups.iter().map(|group| group.effort_hours).sum(),
+            groups,
+        }
+    }
+
+    /// Number of issues of every severity
+    pub fn issue_count(&self) -> usize {
+        self.groups.iter().map(|group| group.issues.len()).sum()
+    }
+
+    /// Render the report as Markdown
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("# Migration Compatibility Report\n\n");
+        out.push_str(&format!("- Files: {}\n", self.files.join(", ")));
+        out.push_str(&format!("- Contracts: {}\n", self.contracts));
+        out.push_str(&format!("- Functions: {}\n", self.functions));
+        out.push_str(&format!("- Lines of code: {}\n", self.lines_of_code));
+        out.push_str(&format!("- Issues: {}\n", self.issue_count()));
+        out.push_str(&format!(
+            "- Estimated manual effort: {:.1} hours\n\n",
+            self.effort_hours
+        ));
+
+        out.push_str("| Severity | Issues | Effort (hours) |\n|---|---|---|\n");
+        for group in &self.groups {
+            out.push_str(&format!(
+                "| {} | {} | {:.1} |\n",
+                group.severity,
+                group.issues.len(),
+                group.effort_hours
+            ));
+        }
+
+        for group in self.groups.iter().filter(|group| !group.issues.is_empty()) {
+            out.push_str(&format!(
+                "\n## {} ({})\n\n",
+                group.severity,
+                group.issues.len()
+            ));
+            for issue in &group.issues {
+                out.push_str(&format!(
+                    "- **{}**: {}\n",
+                    issue.location(),
+                    issue.description
+                ));
+                if let Some(source_line) = &issue.source_line {
+                    out.push_str(&format!("  ```solidity\n  {}\n  ```\n", source_line));
+                }
+                if let Some(suggestion) = &issue.suggestion {
+                    out.push_str(&format!("  Suggestion: {}\n", suggestion));
+                }
+            }
+        }
+        out
+    }
+
+    /// Render the report as a standalone HTML page
+    pub fn to_html(&self) -> String {
+        let mut out = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Migration Compatibility Report</title>\n<style>\n\
+             body { font-family: sans-serif; margin: 2em; }\n\
+             table { border-collapse: collapse; }\n\
+             td, th { border: 1px solid #ccc; padding: 0.3em 0.8em; }\n\
+             .supported { color: #2a7d2a; }\n\
+             .partial { color: #9a6b00; }\n\
+             .manual { color: #c0501f; }\n\
+             .unsupported { color: #b00020; }\n\
+             </style>\n</head>\n<body>\n<h1>Migration Compatibility Report</h1>\n<ul>\n",
+        );
+        for (name, value) in [
+            ("Files", escape_html(&self.files.join(", "))),
+            ("Contracts", self.contracts.to_string()),
+            ("Functions", self.functions.to_string()),
+            ("Lines of code", self.lines_of_code.to_string()),
+            ("Issues", self.issue_count().to_string()),
+            (
+                "Estimated manual effort",
+                format!("{:.1} hours", self.effort_hours),
+            ),
+        ] {
+            out.push_str(&format!("<li>{}: {}</li>\n", name, value));
+        }
+        out.push_str(
+            "</ul>\n<table>\n<tr><th>Severity</th><th>Issues</th><th>Effort (hours)</th></tr>\n",
+        );
+        for group in &self.groups {
+            out.push_str(&format!(
+                "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{:.1}</td></tr>\n",
+                severity_class(&group.severity),
+                group.severity,
+                group.issues.len(),
+                group.effort_hours
+            ));
+        }
+        out.push_str("</table>\n");
+
+        for group in self.groups.iter().filter(|group| !group.issues.is_empty()) {
+            out.push_str(&format!(
+                "<h2 class=\"{}\">{} ({})</h2>\n<ul>\n",
+                severity_class(&group.severity),
+                group.severity,
+                group.issues.len()
+            ));
+            for issue in &group.issues {
+                out.push_str(&format!(
+                    "<li><strong>{}</strong>: {}",
+                    escape_html(&issue.location()),
+                    escape_html(&issue.description)
+                ));
+                if let Some(source_line) = &issue.source_line {
+                    out.push_str(&format!("<pre>{}</pre>", escape_html(source_line)));
+                }
+                if let Some(suggestion) = &issue.suggestion {
+                    out.push_str(&format!("<p>Suggestion: {}</p>", escape_html(suggestion)));
+                }
+                out.push_str("</li>\n");
+            }
+            out.push_str("</ul>\n");
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    /// Render the report as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("the report serializes")
+    }
+
+    /// Write `migration-report.md`, `.html` and `.json` to `dir`, returning
+    /// their paths
+    pub fn write(&self, dir: &Path) -> Result<Vec<PathBuf>, MigrationError> {
+        std::fs::create_dir_all(dir)?;
+        let mut written = Vec::new();
+        for (extension, contents) in [
+            ("md", self.to_markdown()),
+            ("html", self.to_html()),
+            ("json", self.to_json()),
+        ] {
+            let path = dir.join(format!("migration-report.{}", extension));
+            std::fs::write(&path, contents)?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+/// CSS class of the issues of `severity`
+fn severity_class(severity: &IssueSeverity) -> &'static str {
+    match severity {
+        IssueSeverity::Supported => "supported",
+        IssueSeverity::Partial => "partial",
+        IssueSeverity::Manual => "manual",
+        IssueSeverity::Unsupported => "unsupported",
+    }
+}
+
+/// Escape text for HTML element content and attribute values
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration::{MigrationConfig, SolidityMigrator};
+
+    const SOURCE: &str = r#"contract Vault {
+    mapping(address => uint256) balances;
+    function deposit() external onlyOwner {
+        assembly { sstore(0, 1) }
+    }
+}
+"#;
+
+    #[test]
+    fn test_report_groups_and_locates_issues() {
+        let mut migrator = SolidityMigrator::new();
+        migrator.migrate_source(SOURCE, "Vault.sol").unwrap();
+        let report = MigrationReport::new(migrator.stats());
+
+        assert_eq!(report.files, ["Vault.sol"]);
+        let counts: Vec<usize> = report.groups.iter().map(|g| g.issues.len()).collect();
+        assert_eq!(counts, [0, 1, 2, 0]);
+        assert_eq!(report.effort_hours, 4.5);
+
+        let partial = &report.groups[1].issues[0];
+        assert_eq!(partial.location(), "Vault.sol:2:5");
+        assert_eq!(
+            partial.source_line.as_deref(),
+            Some("mapping(address => uint256) balances;")
+        );
+
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("- Estimated manual effort: 4.5 hours\n"));
+        assert!(markdown.contains("| Manual | 2 | 4.0 |\n"));
+        assert!(markdown.contains("## Partial (1)\n\n- **Vault.sol:2:5**: "));
+
+        let html = report.to_html();
+        assert!(html.contains("<pre>mapping(address =&gt; uint256) balances;</pre>"));
+        assert!(!html.contains("<h2 class=\"unsupported\">"));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["groups"][2]["severity"], "Manual");
+        assert_eq!(json["groups"][1]["issues"][0]["line"], 2);
+    }
+
+    #[test]
+    fn test_write_report() {
+        let dir =
+            std::env::temp_dir().join(format!("bend-migration-report-{}", std::process::id()));
+        let mut migrator = SolidityMigrator::with_config(MigrationConfig {
+            output_dir: dir.clone(),
+            ..MigrationConfig::default()
+        });
+        migrator.migrate_source(SOURCE, "Vault.sol").unwrap();
+        let written = migrator.write_report().unwrap();
+        assert_eq!(
+            written,
+            ["md", "html", "json"]
+                .map(|extension| dir.join(format!("migration-report.{}", extension)))
+        );
+        assert!(std::fs::read_to_string(&written[0])
+            .unwrap()
+            .starts_with("# Migration Compatibility Report\n"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let migrator = SolidityMigrator::with_config(MigrationConfig {
+            generate_report: false,
+            ..MigrationConfig::default()
+        });
+        assert!(migrator.write_report().unwrap().is_empty());
+    }
+}
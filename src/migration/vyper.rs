@@ -0,0 +1,1281 @@
+//! # Vyper Frontend
+//!
+//! Parses Vyper contracts into the migration AST, for the converter to
+//! generate Bend-PVM from them as it does from Solidity. A Vyper module is
+//! one contract, named after its file.
+//!
+//! It covers state variables with their `public`, `constant` and
+//! `immutable` wrappers, `HashMap`, `DynArray`, `String[N]` and fixed
+//! array types, events, structs, flags, interfaces, decorated functions
+//! and the statements and expressions of their bodies. Vyper idioms are
+//! lowered to their Solidity counterparts: `self.x` is the state variable
+//! `x`, `assert` is `require`, `raise` is `revert`, `log` is `emit`,
+//! `len(x)` is `x.length` and `for` over a `range` or an array is a
+//! counting loop.
+
+use super::ast::*;
+use super::parser::{
+    assignment_operator, error_at, is_elementary, tokenize, Dialect, Parser, Token, TokenKind,
+};
+use super::MigrationError;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+
+/// Parse Vyper `source`, read from `file`
+pub fn parse_vyper(source: &str, file: &str) -> Result<SoliditySource, MigrationError> {
+    let tokens = tokenize(source, file, Dialect::Vyper)?;
+    VyperParser(Parser {
+        tokens,
+        position: 0,
+        source,
+        file,
+    })
+    .parse_module(contract_name(file))
+}
+
+/// The name of the contract a module read from `file` declares: its file
+/// name without the extension
+pub(super) fn contract_name(file: &str) -> String {
+    Path::new(file)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or_else(|| "Contract".to_string())
+}
+
+/// Precedence of `not`, between `and` and the comparisons
+const NOT: u8 = 3;
+
+fn binary_operator(token: &Token) -> Option<(BinaryOperator, u8)> {
+    if !matches!(token.kind, TokenKind::Identifier | TokenKind::Punctuation) {
+        return None;
+    }
+    Some(match token.text.as_str() {
+        "or" => (BinaryOperator::LogicalOr, 1),
+        "and" => (BinaryOperator::LogicalAnd, 2),
+        "==" => (BinaryOperator::Equal, 4),
+        "!=" => (BinaryOperator::NotEqual, 4),
+        "<" => (BinaryOperator::Less, 4),
+        "<=" => (BinaryOperator::LessEqual, 4),
+        ">" => (BinaryOperator::Greater, 4),
+        ">=" => (BinaryOperator::GreaterEqual, 4),
+        "|" => (BinaryOperator::BitOr, 5),
+        "^" => (BinaryOperator::BitXor, 6),
+        "&" => (BinaryOperator::BitAnd, 7),
+        "<<" => (BinaryOperator::BitShiftLeft, 8),
+        ">>" => (BinaryOperator::BitShiftRight, 8),
+        "+" => (BinaryOperator::Add, 9),
+        "-" => (BinaryOperator::Sub, 9),
+        "*" => (BinaryOperator::Mul, 10),
+        "/" | "//" => (BinaryOperator::Div, 10),
+        "%" => (BinaryOperator::Mod, 10),
+        _ => return None,
+    })
+}
+
+/// A `@decorator` or `@decorator(arguments)` of a function
+struct Decorator {
+    name: String,
+    arguments: Vec<Expression>,
+    location: SolLocation,
+}
+
+/// The Solidity parser's token cursor, with the Vyper grammar
+struct VyperParser<'a>(Parser<'a>);
+
+impl<'a> Deref for VyperParser<'a> {
+    type Target = Parser<'a>;
+
+    fn deref(&self) -> &Parser<'a> {
+        &self.0
+    }
+}
+
+impl<'a> DerefMut for VyperParser<'a> {
+    fn deref_mut(&mut self) -> &mut Parser<'a> {
+        &mut self.0
+    }
+}
+
+impl<'a> VyperParser<'a> {
+    /// Parse `item`s separated by commas up to the closing `close`
+    fn list<T>(
+        &mut self,
+        close: &str,
+        mut item: impl FnMut(&mut Self) -> Result<T, MigrationError>,
+    ) -> Result<Vec<T>, MigrationError> {
+        let mut items = Vec::new();
+        while !self.at(close) {
+            items.push(item(self)?);
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect(close)?;
+        Ok(items)
+    }
+
+    /// Whether the next token starts a line
+    fn at_line_start(&self) -> bool {
+        self.position == 0 || self.peek().line > self.tokens[self.position - 1].line
+    }
+
+    /// Whether the statement or declaration being parsed has ended
+    fn at_line_end(&self) -> bool {
+        self.peek().kind == TokenKind::Eof || self.at_line_start()
+    }
+
+    fn end_line(&mut self) -> Result<(), MigrationError> {
+        match self.at_line_end() {
+            true => Ok(()),
+            false => Err(self.unexpected("the end of the line")),
+        }
+    }
+
+    /// Parse the lines indented under `header`, the first token of a line
+    /// ending in `:`
+    fn parse_indented<T>(
+        &mut self,
+        header: &Token,
+        mut line: impl FnMut(&mut Self) -> Result<Option<T>, MigrationError>,
+    ) -> Result<Vec<T>, MigrationError> {
+        let indent = self.peek().column;
+        if !self.at_line_start() || self.peek().kind == TokenKind::Eof || indent <= header.column {
+            return Err(self.unexpected("an indented block"));
+        }
+        let mut items = Vec::new();
+        while self.peek().kind != TokenKind::Eof && self.peek().column > header.column {
+            if self.peek().column != indent {
+                let token = self.peek();
+                return Err(error_at(
+                    self.file,
+                    token.line,
+                    token.column,
+                    "unexpected indentation".into(),
+                ));
+            }
+            items.extend(line(self)?);
+        }
+        Ok(items)
+    }
+
+    fn parse_module(&mut self, name: String) -> Result<SoliditySource, MigrationError> {
+        let start = self.peek().clone();
+        let mut source = SoliditySource::new(self.location(&start));
+        let mut contract =
+            ContractDefinition::new(name, ContractKind::Contract, self.location(&start));
+        let mut decorators = Vec::new();
+
+        while self.peek().kind != TokenKind::Eof {
+            let token = self.peek().clone();
+            if token.column != 1 {
+                return Err(error_at(
+                    self.file,
+                    token.line,
+                    token.column,
+                    "unexpected indentation".into(),
+                ));
+            }
+            if token.kind == TokenKind::String {
+                // A docstring
+                self.advance();
+                continue;
+            }
+            match token.text.as_str() {
+                "@" => decorators.push(self.parse_decorator()?),
+                "def" => {
+                    let decorators = std::mem::take(&mut decorators);
+                    contract.functions.push(self.parse_function(decorators)?)
+                }
+                _ if !decorators.is_empty() => return Err(self.unexpected("`def`")),
+                "event" => contract.events.push(self.parse_event()?),
+                "struct" => contract.structs.push(self.parse_struct()?),
+                "enum" | "flag" => contract.enums.push(self.parse_enum()?),
+                "interface" => source.contracts.push(self.parse_interface()?),
+                "implements" | "import" | "from" | "uses" | "initializes" | "exports" => {
+                    self.advance();
+                    while !self.at_line_end() {
+                        self.advance();
+                    }
+                }
+                _ => contract.state_variables.push(self.parse_state_variable()?),
+            }
+        }
+        if !decorators.is_empty() {
+            return Err(self.unexpected("`def`"));
+        }
+
+        contract.location = self.location(&start);
+        source.contracts.push(contract);
+        source.location = self.location(&start);
+        Ok(source)
+    }
+
+    fn parse_decorator(&mut self) -> Result<Decorator, MigrationError> {
+        let start = self.expect("@")?;
+        let name = self.identifier()?;
+        let arguments = match self.eat("(") {
+            true => self.list(")", Self::parse_expression)?,
+            false => Vec::new(),
+        };
+        self.end_line()?;
+        Ok(Decorator {
+            name,
+            arguments,
+            location: self.location(&start),
+        })
+    }
+
+    /// A `name: type` parameter or field, indexed when wrapped in
+    /// `indexed(...)`, whose default value is dropped
+    fn parse_declaration(&mut self) -> Result<VariableDeclaration, MigrationError> {
+        let start = self.peek().clone();
+        let name = self.identifier()?;
+        self.expect(":")?;
+        let (wrappers, type_name) = self.parse_annotation()?;
+        if self.eat("=") {
+            self.parse_expression()?;
+        }
+        Ok(VariableDeclaration {
+            name: Some(name),
+            type_name,
+            storage_location: StorageLocation::Default,
+            indexed: wrappers.iter().any(|wrapper| wrapper == "indexed"),
+            location: self.location(&start),
+        })
+    }
+
+    /// The fields of an event or struct, one per indented line
+    fn parse_fields(&mut self, header: &Token) -> Result<Vec<VariableDeclaration>, MigrationError> {
+        self.parse_indented(header, |parser| {
+            if parser.eat("pass") {
+                parser.end_line()?;
+                return Ok(None);
+            }
+            let field = parser.parse_declaration()?;
+            parser.end_line()?;
+            Ok(Some(field))
+        })
+    }
+
+    fn parse_event(&mut self) -> Result<EventDefinition, MigrationError> {
+        let start = self.expect("event")?;
+        let name = self.identifier()?;
+        self.expect(":")?;
+        let parameters = self.parse_fields(&start)?;
+        Ok(EventDefinition {
+            name,
+            parameters,
+            anonymous: false,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_struct(&mut self) -> Result<StructDefinition, MigrationError> {
+        let start = self.expect("struct")?;
+        let name = self.identifier()?;
+        self.expect(":")?;
+        let members = self.parse_fields(&start)?;
+        Ok(StructDefinition {
+            name,
+            members,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_enum(&mut self) -> Result<EnumDefinition, MigrationError> {
+        let start = self.advance();
+        let name = self.identifier()?;
+        self.expect(":")?;
+        let values = self.parse_indented(&start, |parser| {
+            let value = parser.identifier()?;
+            parser.end_line()?;
+            Ok(Some(value))
+        })?;
+        Ok(EnumDefinition {
+            name,
+            values,
+            location: self.location(&start),
+        })
+    }
+
+    /// An interface, whose functions end with their mutability instead of
+    /// a body
+    fn parse_interface(&mut self) -> Result<ContractDefinition, MigrationError> {
+        let start = self.expect("interface")?;
+        let name = self.identifier()?;
+        self.expect(":")?;
+        let functions = self.parse_indented(&start, |parser| {
+            let start = parser.expect("def")?;
+            let name = parser.identifier()?;
+            let (parameters, return_parameters) = parser.parse_signature()?;
+            parser.expect(":")?;
+            let state_mutability = match parser.identifier()?.as_str() {
+                "view" => StateMutability::View,
+                "pure" => StateMutability::Pure,
+                "payable" => StateMutability::Payable,
+                _ => StateMutability::NonPayable,
+            };
+            parser.end_line()?;
+            Ok(Some(FunctionDefinition {
+                name,
+                parameters,
+                return_parameters,
+                body: None,
+                visibility: Visibility::External,
+                state_mutability,
+                virtual_flag: false,
+                override_specifiers: Vec::new(),
+                modifiers: Vec::new(),
+                is_constructor: false,
+                is_fallback: false,
+                is_receive: false,
+                location: parser.location(&start),
+            }))
+        })?;
+        Ok(ContractDefinition {
+            functions,
+            ..ContractDefinition::new(name, ContractKind::Interface, self.location(&start))
+        })
+    }
+
+    /// The parameters of a function and its return types
+    #[allow(clippy::type_complexity)]
+    fn parse_signature(
+        &mut self,
+    ) -> Result<(Vec<VariableDeclaration>, Vec<VariableDeclaration>), MigrationError> {
+        self.expect("(")?;
+        let parameters = self.list(")", Self::parse_declaration)?;
+        let mut return_parameters = Vec::new();
+        if self.eat("->") {
+            let start = self.peek().clone();
+            let types = match self.eat("(") {
+                true => self.list(")", Self::parse_type)?,
+                false => vec![self.parse_type()?],
+            };
+            for type_name in types {
+                return_parameters.push(VariableDeclaration {
+                    name: None,
+                    type_name,
+                    storage_location: StorageLocation::Default,
+                    indexed: false,
+                    location: self.location(&start),
+                });
+            }
+        }
+        Ok((parameters, return_parameters))
+    }
+
+    fn parse_function(
+        &mut self,
+        decorators: Vec<Decorator>,
+    ) -> Result<FunctionDefinition, MigrationError> {
+        let start = self.expect("def")?;
+        let name = self.identifier()?;
+        let (parameters, return_parameters) = self.parse_signature()?;
+        self.expect(":")?;
+        let body = self.parse_body(&start)?;
+
+        let mut visibility = Visibility::Internal;
+        let mut state_mutability = StateMutability::NonPayable;
+        let mut modifiers = Vec::new();
+        for decorator in decorators {
+            match decorator.name.as_str() {
+                "external" => visibility = Visibility::External,
+                "internal" => visibility = Visibility::Internal,
+                "view" => state_mutability = StateMutability::View,
+                "pure" => state_mutability = StateMutability::Pure,
+                "payable" => state_mutability = StateMutability::Payable,
+                "nonpayable" | "deploy" => {}
+                _ => modifiers.push(ModifierInvocation {
+                    name: decorator.name,
+                    arguments: decorator.arguments,
+                    location: decorator.location,
+                }),
+            }
+        }
+        let name = match name.as_str() {
+            "__init__" => "constructor".to_string(),
+            "__default__" => "fallback".to_string(),
+            _ => name,
+        };
+        Ok(FunctionDefinition {
+            is_constructor: name == "constructor",
+            is_fallback: name == "fallback",
+            is_receive: false,
+            name,
+            parameters,
+            return_parameters,
+            body: Some(body),
+            visibility,
+            state_mutability,
+            virtual_flag: false,
+            override_specifiers: Vec::new(),
+            modifiers,
+            location: self.location(&start),
+        })
+    }
+
+    fn parse_state_variable(&mut self) -> Result<StateVariable, MigrationError> {
+        let start = self.peek().clone();
+        let name = self.identifier()?;
+        self.expect(":")?;
+        let (wrappers, type_name) = self.parse_annotation()?;
+        let value = match self.eat("=") {
+            true => Some(self.parse_expression()?),
+            false => None,
+        };
+        self.end_line()?;
+
+        let wrapped = |wrapper: &str| wrappers.iter().any(|w| w == wrapper);
+        let mutability = if wrapped("constant") {
+            Mutability::Constant
+        } else if wrapped("immutable") {
+            Mutability::Immutable
+        } else {
+            Mutability::Mutable
+        };
+        Ok(StateVariable {
+            name,
+            type_name,
+            visibility: match wrapped("public") {
+                true => Visibility::Public,
+                false => Visibility::Internal,
+            },
+            is_constant: mutability == Mutability::Constant,
+            mutability,
+            value,
+            overrides: None,
+            location: self.location(&start),
+        })
+    }
+
+    /// A type with its `public(...)`, `constant(...)`, `immutable(...)`,
+    /// `transient(...)` or `indexed(...)` wrappers, outermost first
+    fn parse_annotation(&mut self) -> Result<(Vec<String>, TypeName), MigrationError> {
+        let mut wrappers = Vec::new();
+        while matches!(
+            self.peek().text.as_str(),
+            "public" | "constant" | "immutable" | "transient" | "indexed"
+        ) && self.at_ahead(1, "(")
+        {
+            wrappers.push(self.advance().text);
+            self.advance();
+        }
+        let type_name = self.parse_type()?;
+        for _ in &wrappers {
+            self.expect(")")?;
+        }
+        Ok((wrappers, type_name))
+    }
+
+    fn parse_type(&mut self) -> Result<TypeName, MigrationError> {
+        let start = self.peek().clone();
+        let name = self.identifier()?;
+        let mut type_name = match name.as_str() {
+            "HashMap" => {
+                self.expect("[")?;
+                let key_type = Box::new(self.parse_type()?);
+                self.expect(",")?;
+                let value_type = Box::new(self.parse_type()?);
+                self.expect("]")?;
+                TypeName::Mapping(MappingTypeName {
+                    key_type,
+                    value_type,
+                    location: self.location(&start),
+                })
+            }
+            "DynArray" => {
+                // The bound of the array is dropped: storage vectors grow
+                self.expect("[")?;
+                let base_type = Box::new(self.parse_type()?);
+                self.expect(",")?;
+                self.parse_expression()?;
+                self.expect("]")?;
+                TypeName::Array(Box::new(ArrayTypeName {
+                    base_type,
+                    length: None,
+                    location: self.location(&start),
+                }))
+            }
+            "String" | "Bytes" => {
+                self.expect("[")?;
+                self.parse_expression()?;
+                self.expect("]")?;
+                TypeName::Elementary(ElementaryTypeName {
+                    name: name.to_lowercase(),
+                    location: self.location(&start),
+                })
+            }
+            _ if is_elementary(&name) || name == "decimal" => {
+                TypeName::Elementary(ElementaryTypeName {
+                    name,
+                    location: self.location(&start),
+                })
+            }
+            _ => TypeName::UserDefined(UserDefinedTypeName {
+                name,
+                type_arguments: Vec::new(),
+                location: self.location(&start),
+            }),
+        };
+        while self.eat("[") {
+            let length = self.parse_expression()?;
+            self.expect("]")?;
+            type_name = TypeName::Array(Box::new(ArrayTypeName {
+                base_type: Box::new(type_name),
+                length: Some(Box::new(length)),
+                location: self.location(&start),
+            }));
+        }
+        Ok(type_name)
+    }
+
+    /// The block after the `:` ending the line `header` starts: indented
+    /// lines, or a statement on the same line
+    fn parse_body(&mut self, header: &Token) -> Result<Block, MigrationError> {
+        let start = self.peek().clone();
+        let statements = match self.at_line_start() {
+            true => self.parse_indented(header, Self::parse_statement)?,
+            false => self.parse_statement()?.into_iter().collect(),
+        };
+        Ok(Block {
+            statements,
+            location: self.location(&start),
+        })
+    }
+
+    /// A statement, or nothing for `pass` and docstrings
+    fn parse_statement(&mut self) -> Result<Option<Statement>, MigrationError> {
+        let start = self.peek().clone();
+        if start.kind == TokenKind::String && self.peek_at(1).line > start.line {
+            self.advance();
+            return Ok(None);
+        }
+        let statement = match start.text.as_str() {
+            "pass" => {
+                self.advance();
+                self.end_line()?;
+                return Ok(None);
+            }
+            "if" => return self.parse_if().map(Some),
+            "for" => return self.parse_for().map(Some),
+            "return" => {
+                self.advance();
+                let expression = match self.at_line_end() {
+                    true => None,
+                    false => Some(self.parse_expression_list()?),
+                };
+                Statement::Return(ReturnStatement {
+                    expression,
+                    location: self.location(&start),
+                })
+            }
+            "break" => {
+                self.advance();
+                Statement::Break(BreakStatement {
+                    location: self.location(&start),
+                })
+            }
+            "continue" => {
+                self.advance();
+                Statement::Continue(ContinueStatement {
+                    location: self.location(&start),
+                })
+            }
+            "assert" => {
+                self.advance();
+                let mut arguments = vec![self.parse_expression()?];
+                if self.eat(",") {
+                    arguments.push(self.parse_expression()?);
+                }
+                let require = Expression::Identifier(Identifier {
+                    name: "require".to_string(),
+                    location: self.location(&start),
+                });
+                let expression = self.call(&start, require, arguments, Vec::new());
+                Statement::Expression(ExpressionStatement {
+                    expression,
+                    location: self.location(&start),
+                })
+            }
+            "raise" => {
+                self.advance();
+                let error_call = match self.at_line_end() {
+                    true => None,
+                    false => Some(self.parse_expression()?),
+                };
+                Statement::Revert(RevertStatement {
+                    error_call,
+                    location: self.location(&start),
+                })
+            }
+            "log" => {
+                self.advance();
+                Statement::Emit(EmitStatement {
+                    event: self.parse_expression()?,
+                    location: self.location(&start),
+                })
+            }
+            _ if start.kind == TokenKind::Identifier && self.at_ahead(1, ":") => {
+                let declaration = self.parse_declaration_statement()?;
+                Statement::VariableDeclaration(declaration)
+            }
+            _ => {
+                let left = self.parse_expression()?;
+                match assignment_operator(&self.peek().text)
+                    .or_else(|| self.at("//=").then_some(AssignmentOperator::DivAssign))
+                {
+                    Some(operator) if self.peek().kind == TokenKind::Punctuation => {
+                        self.advance();
+                        let right = self.parse_expression()?;
+                        Statement::Assignment(AssignmentStatement {
+                            assignment: Assignment {
+                                operator,
+                                left: Box::new(left),
+                                right: Box::new(right),
+                                location: self.location(&start),
+                            },
+                            location: self.location(&start),
+                        })
+                    }
+                    _ => Statement::Expression(ExpressionStatement {
+                        expression: left,
+                        location: self.location(&start),
+                    }),
+                }
+            }
+        };
+        self.end_line()?;
+        Ok(Some(statement))
+    }
+
+    /// `name: type = value`
+    fn parse_declaration_statement(
+        &mut self,
+    ) -> Result<VariableDeclarationStatement, MigrationError> {
+        let start = self.peek().clone();
+        let name = self.identifier()?;
+        self.expect(":")?;
+        let type_name = self.parse_type()?;
+        let declaration = VariableDeclaration {
+            name: Some(name),
+            type_name,
+            storage_location: StorageLocation::Default,
+            indexed: false,
+            location: self.location(&start),
+        };
+        let initial_value = match self.eat("=") {
+            true => Some(self.parse_expression()?),
+            false => None,
+        };
+        Ok(VariableDeclarationStatement {
+            declarations: vec![declaration],
+            initial_value,
+            location: self.location(&start),
+        })
+    }
+
+    /// `if`, or `elif`, with its `elif` and `else` branches
+    fn parse_if(&mut self) -> Result<Statement, MigrationError> {
+        let start = self.advance();
+        let condition = self.parse_expression()?;
+        self.expect(":")?;
+        let true_body = Statement::Block(self.parse_body(&start)?);
+        let at_branch = |parser: &Self, keyword: &str| {
+            parser.at_line_start() && parser.peek().column == start.column && parser.at(keyword)
+        };
+        let false_body = if at_branch(self, "elif") {
+            Some(Box::new(self.parse_if()?))
+        } else if at_branch(self, "else") {
+            let header = self.advance();
+            self.expect(":")?;
+            Some(Box::new(Statement::Block(self.parse_body(&header)?)))
+        } else {
+            None
+        };
+        Ok(Statement::If(IfStatement {
+            condition,
+            true_body: Box::new(true_body),
+            false_body,
+            location: self.location(&start),
+        }))
+    }
+
+    /// `for x in range(n):` or `for x: T in values:`, as a counting loop
+    fn parse_for(&mut self) -> Result<Statement, MigrationError> {
+        let start = self.expect("for")?;
+        let variable_token = self.peek().clone();
+        let variable = self.identifier()?;
+        let type_name = match self.eat(":") {
+            true => self.parse_type()?,
+            false => TypeName::Elementary(ElementaryTypeName {
+                name: "uint256".to_string(),
+                location: self.location(&variable_token),
+            }),
+        };
+        self.expect("in")?;
+        let iterable = self.parse_expression()?;
+        self.expect(":")?;
+        let mut body = self.parse_body(&start)?;
+        let location = self.location(&start);
+
+        let identifier = |name: &str| {
+            Expression::Identifier(Identifier {
+                name: name.to_string(),
+                location: location.clone(),
+            })
+        };
+        let declare = |name: &str, type_name: TypeName, value: Expression| {
+            Statement::VariableDeclaration(VariableDeclarationStatement {
+                declarations: vec![VariableDeclaration {
+                    name: Some(name.to_string()),
+                    type_name,
+                    storage_location: StorageLocation::Default,
+                    indexed: false,
+                    location: location.clone(),
+                }],
+                initial_value: Some(value),
+                location: location.clone(),
+            })
+        };
+        let zero = Expression::Literal(Literal {
+            value: Some("0".to_string()),
+            subdenomination: None,
+            type_name: Some("number".to_string()),
+            location: location.clone(),
+        });
+
+        // `range(end)` and `range(start, end)` count; arrays are walked by
+        // index, loading each element into the loop variable
+        let range = match &iterable {
+            Expression::FunctionCall(call) if matches!(&*call.expression, Expression::Identifier(id) if id.name == "range") => {
+                match call.arguments.as_slice() {
+                    [end] => Some((zero.clone(), end.clone())),
+                    [from, end, ..] => Some((from.clone(), end.clone())),
+                    [] => None,
+                }
+            }
+            _ => None,
+        };
+        let (counter, counter_type, from, end) = match range {
+            Some((from, end)) => (variable.clone(), type_name.clone(), from, end),
+            None => {
+                let counter = format!("{}_index", variable);
+                let element = Expression::IndexAccess(IndexAccess {
+                    base: Box::new(iterable.clone()),
+                    index: Box::new(identifier(&counter)),
+                    location: location.clone(),
+                });
+                body.statements
+                    .insert(0, declare(&variable, type_name.clone(), element));
+                let length = Expression::MemberAccess(MemberAccess {
+                    expression: Box::new(iterable),
+                    member_name: "length".to_string(),
+                    location: location.clone(),
+                });
+                let uint256 = TypeName::Elementary(ElementaryTypeName {
+                    name: "uint256".to_string(),
+                    location: location.clone(),
+                });
+                (counter, uint256, zero.clone(), length)
+            }
+        };
+
+        let one = Expression::Literal(Literal {
+            value: Some("1".to_string()),
+            ..match zero {
+                Expression::Literal(literal) => literal,
+                _ => unreachable!(),
+            }
+        });
+        Ok(Statement::For(ForStatement {
+            initialization: Some(Box::new(declare(&counter, counter_type, from))),
+            condition: Some(Expression::BinaryOperation(BinaryOperation {
+                operator: BinaryOperator::Less,
+                left: Box::new(identifier(&counter)),
+                right: Box::new(end),
+                location: location.clone(),
+            })),
+            iteration: Some(Box::new(Statement::Assignment(AssignmentStatement {
+                assignment: Assignment {
+                    operator: AssignmentOperator::AddAssign,
+                    left: Box::new(identifier(&counter)),
+                    right: Box::new(one),
+                    location: location.clone(),
+                },
+                location: location.clone(),
+            }))),
+            body: Box::new(Statement::Block(body)),
+            location,
+        }))
+    }
+
+    /// An expression, or a tuple of comma-separated ones as in
+    /// `return a, b`
+    fn parse_expression_list(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let first = self.parse_expression()?;
+        if !self.at(",") {
+            return Ok(first);
+        }
+        let mut elements = vec![first];
+        while self.eat(",") {
+            elements.push(self.parse_expression()?);
+        }
+        Ok(Expression::Tuple(TupleExpression {
+            elements,
+            location: self.location(&start),
+        }))
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let expression = self.parse_binary(1)?;
+        // `a if condition else b`, on one line
+        if self.at("if") && self.peek().line == self.tokens[self.position - 1].line {
+            self.advance();
+            let condition = self.parse_binary(1)?;
+            self.expect("else")?;
+            let false_expression = self.parse_expression()?;
+            return Ok(Expression::Conditional(Conditional {
+                condition: Box::new(condition),
+                true_expression: Box::new(expression),
+                false_expression: Box::new(false_expression),
+                location: self.location(&start),
+            }));
+        }
+        Ok(expression)
+    }
+
+    fn parse_binary(&mut self, precedence: u8) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let mut left = if precedence <= NOT && self.at("not") {
+            self.advance();
+            let operand = self.parse_binary(NOT)?;
+            Expression::UnaryOperation(UnaryOperation {
+                operator: UnaryOperator::Not,
+                operand: Box::new(operand),
+                is_prefix: true,
+                location: self.location(&start),
+            })
+        } else {
+            self.parse_unary()?
+        };
+        while let Some((operator, level)) = binary_operator(self.peek())
+            .filter(|(_, level)| *level >= precedence && !self.at_line_start())
+        {
+            self.advance();
+            let right = self.parse_binary(level + 1)?;
+            left = Expression::BinaryOperation(BinaryOperation {
+                operator,
+                left: Box::new(left),
+                right: Box::new(right),
+                location: self.location(&start),
+            });
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let operator = match start.text.as_str() {
+            "-" if start.kind == TokenKind::Punctuation => UnaryOperator::Neg,
+            "~" if start.kind == TokenKind::Punctuation => UnaryOperator::BitNot,
+            _ => return self.parse_power(),
+        };
+        self.advance();
+        let operand = self.parse_unary()?;
+        Ok(Expression::UnaryOperation(UnaryOperation {
+            operator,
+            operand: Box::new(operand),
+            is_prefix: true,
+            location: self.location(&start),
+        }))
+    }
+
+    /// `**`, which binds tighter than a unary operator on its left
+    fn parse_power(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let base = self.parse_postfix()?;
+        if !self.eat("**") {
+            return Ok(base);
+        }
+        let exponent = self.parse_unary()?;
+        Ok(Expression::BinaryOperation(BinaryOperation {
+            operator: BinaryOperator::Pow,
+            left: Box::new(base),
+            right: Box::new(exponent),
+            location: self.location(&start),
+        }))
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        let mut expression = self.parse_primary()?;
+        while !self.at_line_start() {
+            if self.eat(".") {
+                let member_name = match self.identifier()?.as_str() {
+                    "append" => "push".to_string(),
+                    name => name.to_string(),
+                };
+                expression = match expression {
+                    // `self.x` is the state variable or function `x`
+                    Expression::Identifier(identifier) if identifier.name == "this" => {
+                        Expression::Identifier(Identifier {
+                            name: member_name,
+                            location: self.location(&start),
+                        })
+                    }
+                    expression => Expression::MemberAccess(MemberAccess {
+                        expression: Box::new(expression),
+                        member_name,
+                        location: self.location(&start),
+                    }),
+                };
+            } else if self.eat("(") {
+                let (arguments, names) = self.parse_arguments()?;
+                expression = self.call(&start, expression, arguments, names);
+            } else if self.eat("[") {
+                let index = self.parse_expression()?;
+                self.expect("]")?;
+                expression = Expression::IndexAccess(IndexAccess {
+                    base: Box::new(expression),
+                    index: Box::new(index),
+                    location: self.location(&start),
+                });
+            } else {
+                break;
+            }
+        }
+        Ok(expression)
+    }
+
+    /// Call arguments up to the closing `)`, with their names when every
+    /// one is passed by keyword
+    fn parse_arguments(&mut self) -> Result<(Vec<Expression>, Vec<String>), MigrationError> {
+        let mut names = Vec::new();
+        let arguments = self.list(")", |parser| {
+            if parser.peek().kind == TokenKind::Identifier && parser.at_ahead(1, "=") {
+                names.push(parser.advance().text);
+                parser.advance();
+            }
+            parser.parse_expression()
+        })?;
+        if names.len() != arguments.len() {
+            names.clear();
+        }
+        Ok((arguments, names))
+    }
+
+    /// A call of `callee`, with `len(x)` lowered to `x.length`
+    fn call(
+        &self,
+        start: &Token,
+        callee: Expression,
+        mut arguments: Vec<Expression>,
+        names: Vec<String>,
+    ) -> Expression {
+        if let (Expression::Identifier(identifier), 1) = (&callee, arguments.len()) {
+            if identifier.name == "len" {
+                return Expression::MemberAccess(MemberAccess {
+                    expression: Box::new(arguments.remove(0)),
+                    member_name: "length".to_string(),
+                    location: self.location(start),
+                });
+            }
+        }
+        Expression::FunctionCall(FunctionCall {
+            expression: Box::new(callee),
+            arguments,
+            names,
+            options: Vec::new(),
+            location: self.location(start),
+        })
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, MigrationError> {
+        let start = self.peek().clone();
+        match start.kind {
+            TokenKind::Number => {
+                self.advance();
+                return Ok(self.literal(&start, start.text.replace('_', ""), "number"));
+            }
+            TokenKind::String | TokenKind::HexString => {
+                self.advance();
+                return Ok(self.literal(&start, start.text.clone(), "string"));
+            }
+            TokenKind::Eof => return Err(self.unexpected("an expression")),
+            TokenKind::Identifier | TokenKind::Punctuation => {}
+        }
+
+        match start.text.as_str() {
+            "(" => {
+                self.advance();
+                let mut elements = self.list(")", Self::parse_expression)?;
+                if elements.len() == 1 && !self.tokens[self.position - 2].text.eq(",") {
+                    return Ok(elements.remove(0));
+                }
+                Ok(Expression::Tuple(TupleExpression {
+                    elements,
+                    location: self.location(&start),
+                }))
+            }
+            "[" => {
+                self.advance();
+                let elements = self.list("]", Self::parse_expression)?;
+                Ok(Expression::ArrayLiteral(ArrayLiteral {
+                    elements,
+                    location: self.location(&start),
+                }))
+            }
+            "True" | "False" if start.kind == TokenKind::Identifier => {
+                self.advance();
+                Ok(self.literal(&start, start.text.to_lowercase(), "bool"))
+            }
+            "convert" if self.at_ahead(1, "(") => {
+                self.advance();
+                self.advance();
+                let expression = self.parse_expression()?;
+                self.expect(",")?;
+                let type_name = self.parse_type()?;
+                self.expect(")")?;
+                Ok(Expression::TypeConversion(Box::new(TypeConversion {
+                    type_name: Box::new(type_name),
+                    expression: Box::new(expression),
+                    location: self.location(&start),
+                })))
+            }
+            "empty" if self.at_ahead(1, "(") => {
+                self.advance();
+                self.advance();
+                let type_name = self.parse_type()?;
+                self.expect(")")?;
+                Ok(match type_name {
+                    TypeName::Elementary(elementary)
+                        if matches!(elementary.name.as_str(), "string" | "bytes") =>
+                    {
+                        self.literal(&start, String::new(), "string")
+                    }
+                    _ => self.literal(&start, "0".to_string(), "number"),
+                })
+            }
+            "max_value" | "min_value" if self.at_ahead(1, "(") => {
+                self.advance();
+                self.advance();
+                let type_start = self.peek().clone();
+                let type_name = self.identifier()?;
+                self.expect(")")?;
+                Ok(Expression::MemberAccess(MemberAccess {
+                    expression: Box::new(Expression::Identifier(Identifier {
+                        name: type_name,
+                        location: self.location(&type_start),
+                    })),
+                    member_name: start.text[..3].to_string(),
+                    location: self.location(&start),
+                }))
+            }
+            _ if start.kind == TokenKind::Identifier => {
+                self.advance();
+                let name = match start.text.as_str() {
+                    "self" => "this".to_string(),
+                    name => name.to_string(),
+                };
+                Ok(Expression::Identifier(Identifier {
+                    name,
+                    location: self.location(&start),
+                }))
+            }
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = r#"# pragma version ^0.3.10
+"""
+A token
+"""
+
+event Transfer:
+    sender: indexed(address)
+    receiver: indexed(address)
+    value: uint256
+
+struct Lock:
+    amount: uint256
+    until: uint256
+
+interface Receiver:
+    def on_received(sender: address, value: uint256) -> bool: nonpayable
+
+name: public(String[32])
+DECIMALS: constant(uint8) = 18
+owner: immutable(address)
+balanceOf: public(HashMap[address, uint256])
+allowance: public(HashMap[address, HashMap[address, uint256]])
+holders: DynArray[address, 100]
+
+@deploy
+def __init__(supply: uint256):
+    owner = msg.sender
+    self.balanceOf[msg.sender] = supply
+
+@external
+@nonreentrant("lock")
+def transfer(to: address, value: uint256) -> bool:
+    assert self.balanceOf[msg.sender] >= value, "balance"
+    self.balanceOf[msg.sender] -= value
+    self.balanceOf[to] += value
+    if len(self.holders) == 0 and not (to == empty(address)):
+        self.holders.append(to)
+    elif value > 10 ** 18:
+        raise "too large"
+    else:
+        pass
+    log Transfer(msg.sender, to, value)
+    return True
+
+@internal
+@view
+def _sum() -> uint256:
+    total: uint256 = 0
+    for holder: address in self.holders:
+        total += self.balanceOf[holder] // 2
+    return total
+"#;
+
+    #[test]
+    fn test_parse_module() {
+        let source = parse_vyper(TOKEN, "contracts/Token.vy").unwrap();
+        assert_eq!(source.contracts.len(), 2);
+        let receiver = &source.contracts[0];
+        assert_eq!(receiver.kind, ContractKind::Interface);
+        assert_eq!(receiver.functions[0].name, "on_received");
+
+        let token = &source.contracts[1];
+        assert_eq!(token.name, "Token");
+        assert_eq!(token.events[0].parameters.len(), 3);
+        let indexed: Vec<bool> = token.events[0]
+            .parameters
+            .iter()
+            .map(|parameter| parameter.indexed)
+            .collect();
+        assert_eq!(indexed, [true, true, false]);
+        assert_eq!(token.structs[0].members.len(), 2);
+
+        let variables: Vec<_> = token
+            .state_variables
+            .iter()
+            .map(|v| (v.name.as_str(), v.visibility.clone(), v.mutability.clone()))
+            .collect();
+        assert_eq!(
+            variables,
+            [
+                ("name", Visibility::Public, Mutability::Mutable),
+                ("DECIMALS", Visibility::Internal, Mutability::Constant),
+                ("owner", Visibility::Internal, Mutability::Immutable),
+                ("balanceOf", Visibility::Public, Mutability::Mutable),
+                ("allowance", Visibility::Public, Mutability::Mutable),
+                ("holders", Visibility::Internal, Mutability::Mutable),
+            ]
+        );
+        assert!(matches!(
+            &token.state_variables[4].type_name,
+            TypeName::Mapping(outer) if matches!(&*outer.value_type, TypeName::Mapping(_))
+        ));
+        assert!(matches!(
+            &token.state_variables[5].type_name,
+            TypeName::Array(array) if array.length.is_none()
+        ));
+
+        let functions: Vec<_> = token
+            .functions
+            .iter()
+            .map(|f| {
+                (
+                    f.name.as_str(),
+                    f.visibility.clone(),
+                    f.state_mutability.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            functions,
+            [
+                (
+                    "constructor",
+                    Visibility::Internal,
+                    StateMutability::NonPayable
+                ),
+                (
+                    "transfer",
+                    Visibility::External,
+                    StateMutability::NonPayable
+                ),
+                ("_sum", Visibility::Internal, StateMutability::View),
+            ]
+        );
+        assert!(token.functions[0].is_constructor);
+        assert_eq!(token.functions[1].modifiers[0].name, "nonreentrant");
+
+        let body = &token.functions[1].body.as_ref().unwrap().statements;
+        assert_eq!(body.len(), 6);
+        let Statement::If(branch) = &body[3] else {
+            panic!("expected an if, found {:?}", body[3]);
+        };
+        let Some(elif) = &branch.false_body else {
+            panic!("expected an elif");
+        };
+        assert!(matches!(&**elif, Statement::If(inner) if inner.false_body.is_some()));
+    }
+
+    #[test]
+    fn test_migrate_vyper() {
+        let source = parse_vyper(TOKEN, "Token.vy").unwrap();
+        let mut converter = super::super::converter::SolidityToBendConverter::new();
+        let bend = converter.convert(&source).unwrap();
+        for line in [
+            "    pub let balanceOf: StorageMap<Address, u256>;",
+            "    pub let allowance: StorageMap<(Address, Address), u256>;",
+            "    let holders: StorageVec<Address>;",
+            "        balanceOf.insert(caller(), (balanceOf.get(caller()) - value));",
+            "        assert((balanceOf.get(caller()) >= value), \"balance\");",
+            "        if ((holders.len() == 0) && !(to == 0)) {",
+            "            holders.push(to);",
+            "            assert(false, \"too large\");",
+            "        emit Transfer(caller(), to, value);",
+            "        return true;",
+            "    fn _sum() -> u256 {",
+        ] {
+            assert!(bend.contains(line), "missing `{}` in\n{}", line, bend);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |source: &str| match parse_vyper(source, "Bad.vy") {
+            Err(MigrationError::ParseError(message)) => message,
+            other => panic!("expected a parse error, found {:?}", other.map(|_| ())),
+        };
+        assert_eq!(
+            error("x: uint256\n  y: uint256\n"),
+            "Bad.vy:2:3: unexpected indentation"
+        );
+        assert_eq!(
+            error("@external\nx: uint256\n"),
+            "Bad.vy:2:1: expected `def`, found `x`"
+        );
+        assert_eq!(
+            error("def f():\nreturn 1\n"),
+            "Bad.vy:2:1: expected an indented block, found `return`"
+        );
+        assert_eq!(
+            error("def f():\n    return 1 2\n"),
+            "Bad.vy:2:14: expected the end of the line, found `2`"
+        );
+    }
+}
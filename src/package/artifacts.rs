@@ -0,0 +1,144 @@
+//! `target/manifest.json`, the artifacts builds produced
+//!
+//! Each build records the entry modules it compiled or found up to date,
+//! with the paths of their binaries, listings, ABIs and metadata, the size
+//! and SHA-256 hash of each binary, the compiler that made them and when.
+//! Build systems and editors read it to find the artifacts without knowing
+//! the layout of `target/`. Every profile has its own table, so a release
+//! build keeps what the last dev build recorded.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::build::{build_time, Artifact, TARGET_DIR};
+use super::package::PackageError;
+use super::profile::Profile;
+use super::workspace::Workspace;
+use crate::compiler::cfg::Cfg;
+
+/// Name of the artifacts manifest in the target directory
+pub const ARTIFACTS_MANIFEST: &str = "manifest.json";
+
+/// The artifacts of a package, by profile
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactsManifest {
+    pub package: String,
+    pub version: String,
+    pub compiler_version: String,
+    pub profiles: BTreeMap<String, ProfileArtifacts>,
+}
+
+/// The artifacts of the last build with one profile
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileArtifacts {
+    pub opt_level: u8,
+    pub debug: bool,
+    /// Features enabled for `#[cfg(feature = "...")]`
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Seconds since the Unix epoch, from `SOURCE_DATE_EPOCH` when it is
+    /// set; deterministic builds leave it out otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub built_at: Option<u64>,
+    pub artifacts: Vec<ManifestEntry>,
+}
+
+/// One entry module, with paths relative to the package root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub module: PathBuf,
+    pub binary: PathBuf,
+    pub listing: Option<PathBuf>,
+    #[serde(default)]
+    pub abi: PathBuf,
+    #[serde(default)]
+    pub metadata: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl ArtifactsManifest {
+    /// Where the manifest of the workspace's root package is
+    pub fn path(workspace: &Workspace) -> PathBuf {
+        workspace
+            .root()
+            .root()
+            .join(TARGET_DIR)
+            .join(ARTIFACTS_MANIFEST)
+    }
+
+    /// The manifest earlier builds wrote, if there is one for this package
+    /// and compiler
+    pub fn load(workspace: &Workspace) -> Option<Self> {
+        let text = fs::read_to_string(Self::path(workspace)).ok()?;
+        serde_json::from_str(&text).ok().filter(|manifest: &Self| {
+            manifest.package == workspace.root().name()
+                && manifest.compiler_version == env!("CARGO_PKG_VERSION")
+        })
+    }
+
+    /// Record `artifacts` as those of `profile`, built with `cfg`, and
+    /// write the manifest
+    pub fn record(
+        workspace: &Workspace,
+        profile: &Profile,
+        cfg: &Cfg,
+        artifacts: &[Artifact],
+    ) -> Result<Self, PackageError> {
+        let package = workspace.root();
+        let mut manifest = Self::load(workspace).unwrap_or_else(|| ArtifactsManifest {
+            package: package.name().to_string(),
+            version: package.version().to_string(),
+            compiler_version: env!("CARGO_PKG_VERSION").to_string(),
+            profiles: BTreeMap::new(),
+        });
+        manifest.version = package.version().to_string();
+
+        let relative = |path: &Path| {
+            path.strip_prefix(package.root())
+                .unwrap_or(path)
+                .to_path_buf()
+        };
+        let mut entries: Vec<ManifestEntry> = artifacts
+            .iter()
+            .map(|artifact| ManifestEntry {
+                module: relative(&artifact.module),
+                binary: relative(&artifact.binary),
+                listing: artifact.listing.as_deref().map(relative),
+                abi: relative(&artifact.abi),
+                metadata: relative(&artifact.metadata),
+                size: artifact.size,
+                sha256: artifact.sha256.clone(),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.module.cmp(&b.module));
+        manifest.profiles.insert(
+            profile.name().to_string(),
+            ProfileArtifacts {
+                opt_level: profile.opt_level(),
+                debug: profile.debug(),
+                features: cfg.features.iter().cloned().collect(),
+                built_at: build_time(profile),
+                artifacts: entries,
+            },
+        );
+
+        let path = Self::path(workspace);
+        let io = |e: String| PackageError::Io(format!("{}: {}", path.display(), e));
+        let text = serde_json::to_string_pretty(&manifest).map_err(|e| io(e.to_string()))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| io(e.to_string()))?;
+        }
+        fs::write(&path, text + "\n").map_err(|e| io(e.to_string()))?;
+        Ok(manifest)
+    }
+}
+
+/// The SHA-256 hash of `bytes`, in hex
+pub fn sha256(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
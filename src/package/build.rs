@@ -0,0 +1,713 @@
+//! Building the root package of a workspace
+//!
+//! Every module under `src/` is checked, then each module no other module
+//! of the package imports is compiled into `target/<profile>/`, keeping its
+//! path below `src/`: `src/token.bend` becomes `target/dev/token.bin`,
+//! with its ABI and metadata beside it as `token.abi.json` and
+//! `token.metadata.json`. Profiles with debug information also get the
+//! assembly listing as `token.s`.
+//!
+//! Each module is compiled to an object on its own, then linked with the
+//! objects of the modules it imports, which are compiled the same way or
+//! read from the `.o` files of prebuilt libraries. The object of an entry
+//! module carries the message dispatcher routing calls to its `#[message]`
+//! functions by selector, and is kept beside its binary, as `token.o`.
+//!
+//! Modules are configured with the [`Cfg`] given to [`build`]: the
+//! features enabled on the command line or by `default` in `bend.toml`.
+//!
+//! Both record the artifacts in `target/manifest.json`, see
+//! [`ArtifactsManifest`], along with the time of the build, taken from
+//! `SOURCE_DATE_EPOCH` when it is set. A [`Profile::deterministic`] build
+//! gives the same bytes wherever the package is: it records source paths
+//! relative to the package root, and no time without `SOURCE_DATE_EPOCH`.
+//!
+//! [`test`] runs the `#[test]` functions of every module and fuzzes its
+//! `#[invariant]` functions, which is what
+//! `build --test` does after a successful build.
+//!
+//! [`rebuild`] only compiles the entry modules some changed files affect,
+//! which is what `build --watch` does on each change. It checks the
+//! modules through a query [`Database`] kept from one build to the next,
+//! so only the changed modules and those importing them are checked again.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::artifacts::{sha256, ArtifactsManifest};
+use super::package::PackageError;
+use super::profile::Profile;
+use super::workspace::Workspace;
+use crate::compiler::analyzer::lints::{lint_program, Level, LintLevels};
+use crate::compiler::cfg::Cfg;
+use crate::compiler::codegen::encoder::Isa;
+use crate::compiler::codegen::metadata::ContractMetadata;
+use crate::compiler::codegen::risc_v::Instruction;
+use crate::compiler::linker::Linker;
+use crate::compiler::module::cache::{canonical, CachedModule};
+use crate::compiler::module::{record_dependencies, Module};
+use crate::compiler::object::{is_object, ObjectError, ObjectFile, OBJECT_EXTENSION};
+use crate::compiler::parser::ast::Definition;
+use crate::compiler::pipeline::contract_metadata;
+use crate::compiler::polkavm::abi::{generate_abi, ContractABI};
+use crate::compiler::polkavm::bridge::compile_to_polkavm;
+use crate::compiler::query::Database;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::stdlib::modules;
+use crate::testing::{TestError, TestResult, TestSuite};
+
+/// Where builds put their artifacts, below the package root
+pub const TARGET_DIR: &str = "target";
+
+/// A diagnostic about one module of the package
+#[derive(Debug, Clone)]
+pub struct ModuleDiagnostic {
+    pub path: PathBuf,
+    pub diagnostic: Diagnostic,
+}
+
+/// The files built for one module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    pub module: PathBuf,
+    pub binary: PathBuf,
+    /// The assembly listing, with debug information only
+    pub listing: Option<PathBuf>,
+    /// The ABI, as `<name>.abi.json`
+    pub abi: PathBuf,
+    /// The contract metadata, as `<name>.metadata.json`
+    pub metadata: PathBuf,
+    /// Size of the binary in bytes
+    pub size: u64,
+    /// SHA-256 hash of the binary, in hex
+    pub sha256: String,
+}
+
+#[derive(Debug, Default)]
+pub struct BuildOutput {
+    pub artifacts: Vec<Artifact>,
+    /// Entry modules left as they were because nothing they import changed
+    pub fresh: Vec<PathBuf>,
+    /// Warnings of the lints that are not allowed
+    pub warnings: Vec<ModuleDiagnostic>,
+}
+
+#[derive(Debug)]
+pub enum BuildError {
+    Package(PackageError),
+    /// Modules with errors or denied warnings, along with the warnings
+    /// found before them
+    Diagnostics(Vec<ModuleDiagnostic>),
+    /// A module that could not be loaded or compiled
+    Module {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Package(error) => write!(f, "{}", error),
+            BuildError::Diagnostics(diagnostics) => {
+                let errors = diagnostics
+                    .iter()
+                    .filter(|d| d.diagnostic.severity == Severity::Error)
+                    .count();
+                write!(f, "build failed with {} error(s)", errors)
+            }
+            BuildError::Module { path, message } => {
+                write!(f, "{}: {}", path.display(), message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<PackageError> for BuildError {
+    fn from(error: PackageError) -> Self {
+        BuildError::Package(error)
+    }
+}
+
+/// Seconds since the Unix epoch `SOURCE_DATE_EPOCH` sets, the time
+/// reproducible builds record instead of the current one
+pub fn source_date_epoch() -> Option<u64> {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse().ok())
+}
+
+/// Time of a build with `profile` in seconds since the Unix epoch, none for
+/// a deterministic build unless `SOURCE_DATE_EPOCH` is set
+pub fn build_time(profile: &Profile) -> Option<u64> {
+    source_date_epoch().or_else(|| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        (!profile.deterministic()).then_some(now.as_secs())
+    })
+}
+
+/// The directory the artifacts of `profile` go to
+pub fn target_dir(workspace: &Workspace, profile: &Profile) -> PathBuf {
+    workspace
+        .root()
+        .root()
+        .join(TARGET_DIR)
+        .join(profile.name())
+}
+
+/// Check the modules of the workspace's root package and compile its
+/// entry modules with the settings of `profile`. The dependencies are
+/// expected to be compiled already.
+pub fn build(
+    workspace: &Workspace,
+    profile: &Profile,
+    lint_levels: &LintLevels,
+    cfg: &Cfg,
+) -> Result<BuildOutput, BuildError> {
+    build_with(
+        workspace,
+        &mut database(workspace, cfg),
+        profile,
+        lint_levels,
+    )
+}
+
+/// Like [`build`], checking the modules through `database`, which
+/// remembers what it found for the next build
+pub fn build_with(
+    workspace: &Workspace,
+    database: &mut Database,
+    profile: &Profile,
+    lint_levels: &LintLevels,
+) -> Result<BuildOutput, BuildError> {
+    let (modules, warnings) = check_modules(workspace, database, lint_levels)?;
+    let entries = entry_modules(&modules);
+    let cfg = database.module_system().cfg();
+    let output = compile_modules(workspace, profile, cfg, &entries, warnings)?;
+    ArtifactsManifest::record(workspace, profile, cfg, &output.artifacts)?;
+    Ok(output)
+}
+
+/// Like [`build_with`], after the `changed` files changed on disk, but only
+/// compile the entry modules that import one of them, directly or not, or
+/// whose binary is missing. The others are listed as fresh.
+pub fn rebuild(
+    workspace: &Workspace,
+    database: &mut Database,
+    profile: &Profile,
+    lint_levels: &LintLevels,
+    changed: &[PathBuf],
+) -> Result<BuildOutput, BuildError> {
+    for path in changed {
+        database.reload(path);
+    }
+    let changed: HashSet<PathBuf> = changed.iter().map(|path| canonical(path)).collect();
+    let (modules, warnings) = check_modules(workspace, database, lint_levels)?;
+    let (entries, fresh): (Vec<&Module>, Vec<&Module>) =
+        entry_modules(&modules).into_iter().partition(|module| {
+            !artifact_path(workspace, profile, module, "bin").exists()
+                || imports_any(module, &changed, &mut HashSet::new())
+        });
+    let cfg = database.module_system().cfg();
+    let mut output = compile_modules(workspace, profile, cfg, &entries, warnings)?;
+    output.fresh = fresh.iter().map(|module| module.path.clone()).collect();
+
+    let mut artifacts = output.artifacts.clone();
+    for module in fresh {
+        artifacts.push(built_artifact(workspace, profile, module)?);
+    }
+    ArtifactsManifest::record(workspace, profile, cfg, &artifacts)?;
+    Ok(output)
+}
+
+/// Check the modules of the workspace's root package without compiling
+/// them, returning the warnings of the lints that are not allowed
+pub fn check(
+    workspace: &Workspace,
+    lint_levels: &LintLevels,
+    cfg: &Cfg,
+) -> Result<Vec<ModuleDiagnostic>, BuildError> {
+    check_with(workspace, &mut database(workspace, cfg), lint_levels)
+}
+
+/// Like [`check`], checking the modules through `database`
+pub fn check_with(
+    workspace: &Workspace,
+    database: &mut Database,
+    lint_levels: &LintLevels,
+) -> Result<Vec<ModuleDiagnostic>, BuildError> {
+    Ok(check_modules(workspace, database, lint_levels)?.1)
+}
+
+/// The outcome of one `#[test]` or `#[invariant]` function of a module of
+/// the package
+#[derive(Debug, Clone)]
+pub struct ModuleTest {
+    pub path: PathBuf,
+    pub name: String,
+    pub result: TestResult,
+}
+
+#[derive(Debug, Default)]
+pub struct TestOutput {
+    pub tests: Vec<ModuleTest>,
+    /// Modules whose tests could not be collected, as they do not parse or
+    /// type check
+    pub diagnostics: Vec<ModuleDiagnostic>,
+}
+
+impl TestOutput {
+    /// Whether every module was tested and every test passed
+    pub fn passed(&self) -> bool {
+        self.diagnostics.is_empty()
+            && !self
+                .tests
+                .iter()
+                .any(|test| matches!(test.result, TestResult::Failed { .. }))
+    }
+}
+
+/// Run the `#[test]` functions of every module of the workspace's root
+/// package, configured with `cfg`, on the interpreter, and check its
+/// `#[invariant]` functions after random sequences of message calls. Tests
+/// call what their module imports, found the way [`build`] finds it.
+pub fn test(workspace: &Workspace, cfg: &Cfg) -> Result<TestOutput, BuildError> {
+    let cfg = cfg.clone().with_test(true);
+    let mut database = database(workspace, &cfg);
+    let mut output = TestOutput::default();
+    for path in workspace.root().module_files()? {
+        let source = fs::read_to_string(&path).map_err(|e| BuildError::Module {
+            path: path.clone(),
+            message: e.to_string(),
+        })?;
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut suite = match TestSuite::from_source_with_cfg(&name, &source, &cfg) {
+            Ok(suite) => suite,
+            Err(TestError::Diagnostic(diagnostic)) => {
+                output.diagnostics.push(ModuleDiagnostic {
+                    path,
+                    diagnostic: *diagnostic,
+                });
+                continue;
+            }
+            Err(e) => {
+                return Err(BuildError::Module {
+                    path,
+                    message: e.to_string(),
+                })
+            }
+        };
+        let module = database.module(&path).map_err(|error| BuildError::Module {
+            path: path.clone(),
+            message: error.to_string(),
+        })?;
+        let imported = imported_definitions(&module);
+        for test in &mut suite.tests {
+            test.imported = imported.clone();
+        }
+        output.tests.extend(
+            suite
+                .run_all()
+                .into_iter()
+                .map(|(name, result)| ModuleTest {
+                    path: path.clone(),
+                    name,
+                    result,
+                }),
+        );
+    }
+    Ok(output)
+}
+
+/// The definitions of the modules `module` imports, directly or not,
+/// without its own, which [`Module::program_without_std`] lists last
+fn imported_definitions(module: &Module) -> Vec<Definition> {
+    let mut definitions = module.program_without_std().definitions;
+    definitions.truncate(definitions.len() - module.ast.definitions.len());
+    definitions
+}
+
+/// A database finding the modules of the workspace's root package and
+/// configuring them with `cfg`
+pub fn database(workspace: &Workspace, cfg: &Cfg) -> Database {
+    let mut modules = workspace.module_system(workspace.root());
+    modules.set_cfg(cfg.clone());
+    Database::new(modules)
+}
+
+/// Load, type check and lint every module of the root package
+fn check_modules(
+    workspace: &Workspace,
+    database: &mut Database,
+    lint_levels: &LintLevels,
+) -> Result<(Vec<Arc<Module>>, Vec<ModuleDiagnostic>), BuildError> {
+    let package = workspace.root();
+    let cfg = database.module_system().cfg().clone();
+    let cache = workspace.cache();
+
+    let mut diagnostics = Vec::new();
+    let mut loaded = Vec::new();
+    for path in package.module_files()? {
+        let source = database
+            .source(&path)
+            .ok_or_else(|| PackageError::Io(format!("{}: cannot be read", path.display())))?;
+        let mut report = |diagnostic: Diagnostic| {
+            diagnostics.push(ModuleDiagnostic {
+                path: path.clone(),
+                diagnostic,
+            })
+        };
+
+        let parsed = database.parse(&path);
+        let program = match &*parsed {
+            Ok(program) => program,
+            Err(error) => {
+                report(Diagnostic::from_parse_error(error, &source));
+                continue;
+            }
+        };
+        let module = database.module(&path).map_err(|error| BuildError::Module {
+            path: path.clone(),
+            message: error.to_string(),
+        })?;
+
+        // Modules checked by an earlier build keep the warnings found then
+        let mut entry = cache.get(&path, &source).unwrap_or_else(|| {
+            let mut entry = CachedModule::new(&path, &source, program.clone());
+            record_dependencies(&module, &mut entry.dependencies);
+            entry
+        });
+        entry.configured_for(&cfg);
+        let mut warnings = lint_program(&module.ast);
+        if entry.type_checked {
+            warnings.extend(entry.warnings.iter().cloned());
+        } else {
+            let checked = database.check(&path).map_err(|error| BuildError::Module {
+                path: path.clone(),
+                message: error.to_string(),
+            })?;
+            if let Some(error) = &checked.error {
+                report(Diagnostic::from_type_error(error, &source));
+                continue;
+            }
+            warnings.extend(checked.warnings.iter().cloned());
+            entry.type_checked = true;
+            entry.warnings = checked.warnings.clone();
+            // A cache that cannot be written only costs a recheck
+            let _ = cache.insert(&entry);
+        }
+
+        warnings.sort_by_key(|warning| warning.location.start);
+        for warning in &warnings {
+            let level = lint_levels.level(&module.ast, warning);
+            if level != Level::Allow {
+                report(Diagnostic::from_warning(warning, level, &source));
+            }
+        }
+        loaded.push(module);
+    }
+
+    if diagnostics
+        .iter()
+        .any(|d| d.diagnostic.severity == Severity::Error)
+    {
+        return Err(BuildError::Diagnostics(diagnostics));
+    }
+    Ok((loaded, diagnostics))
+}
+
+/// Compile `modules` into the target directory of `profile`
+fn compile_modules(
+    workspace: &Workspace,
+    profile: &Profile,
+    cfg: &Cfg,
+    modules: &[&Module],
+    warnings: Vec<ModuleDiagnostic>,
+) -> Result<BuildOutput, BuildError> {
+    let mut output = BuildOutput {
+        warnings,
+        ..BuildOutput::default()
+    };
+    let mut objects = HashMap::new();
+    for module in modules {
+        let contract = match link_contract(module, profile, cfg, &mut objects) {
+            Ok(contract) => contract,
+            Err(BuildError::Diagnostics(diagnostics)) => {
+                let mut all = output.warnings;
+                all.extend(diagnostics);
+                return Err(BuildError::Diagnostics(all));
+            }
+            Err(error) => return Err(error),
+        };
+        let path = |extension: &str| artifact_path(workspace, profile, module, extension);
+        let (binary, listing) = (path("bin"), path("s"));
+        let (abi, metadata) = (path("abi.json"), path("metadata.json"));
+
+        write(&path(OBJECT_EXTENSION), &contract.object.to_bytes())?;
+        write(&binary, &contract.binary)?;
+        write(&abi, to_json(&contract.abi).as_bytes())?;
+        write(&metadata, to_json(&contract.metadata).as_bytes())?;
+        let listing = match profile.debug() {
+            true => {
+                let source = profile.source_path(workspace.root().root(), &module.path);
+                let mut text = format!("; {}\n", source.display());
+                for instruction in &contract.code {
+                    text.push_str(&format!("{}\n", instruction));
+                }
+                write(&listing, text.as_bytes())?;
+                Some(listing)
+            }
+            false => {
+                // A listing left by an earlier debug build is stale now
+                let _ = fs::remove_file(&listing);
+                None
+            }
+        };
+        output.artifacts.push(Artifact {
+            module: module.path.clone(),
+            size: contract.binary.len() as u64,
+            sha256: sha256(&contract.binary),
+            binary,
+            listing,
+            abi,
+            metadata,
+        });
+    }
+    Ok(output)
+}
+
+/// A contract as [`build`] compiles an entry module, before it is written
+/// to the target directory
+#[derive(Debug, Clone)]
+pub struct BuiltContract {
+    /// The object of the entry module, with the message dispatcher
+    pub object: ObjectFile,
+    /// The linked code
+    pub code: Vec<Instruction>,
+    /// The PolkaVM blob
+    pub binary: Vec<u8>,
+    pub abi: ContractABI,
+    pub metadata: ContractMetadata,
+}
+
+/// Check the modules of the workspace's root package and compile the
+/// contract of the module at `path` with `profile`, configured with `cfg`,
+/// the way [`build`] does, without writing anything. This is how a
+/// contract built from a package is rebuilt to verify it.
+pub fn build_contract(
+    workspace: &Workspace,
+    profile: &Profile,
+    cfg: &Cfg,
+    path: &Path,
+) -> Result<BuiltContract, BuildError> {
+    let mut database = database(workspace, cfg);
+    let (modules, _) = check_modules(workspace, &mut database, &LintLevels::default())?;
+    let wanted = canonical(path);
+    let module = modules
+        .iter()
+        .find(|module| canonical(&module.path) == wanted)
+        .ok_or_else(|| BuildError::Module {
+            path: path.to_path_buf(),
+            message: format!("not a module of package {}", workspace.root().name()),
+        })?;
+    link_contract(module, profile, cfg, &mut HashMap::new())
+}
+
+/// Compile the contract whose entry is `module`, linking it with the
+/// objects of the modules it imports, which are compiled once into
+/// `objects`
+fn link_contract(
+    module: &Module,
+    profile: &Profile,
+    cfg: &Cfg,
+    objects: &mut HashMap<PathBuf, ObjectFile>,
+) -> Result<BuiltContract, BuildError> {
+    let failed = |message: String| BuildError::Module {
+        path: module.path.clone(),
+        message,
+    };
+
+    let entry_path = canonical(&module.path);
+    let mut linker = Linker::new();
+    let mut seen = HashSet::new();
+    for path in link_order(module, &mut seen) {
+        let object = match objects.get(&path) {
+            Some(object) => object,
+            None => {
+                // Only the entry carries the message dispatcher
+                let entry = path == entry_path;
+                let imported = module_at(module, &path);
+                let object = match compile_object(imported, profile, entry) {
+                    Ok(object) => object,
+                    Err(ObjectError::Codegen { error, .. }) => {
+                        let source = fs::read_to_string(&path).unwrap_or_default();
+                        return Err(BuildError::Diagnostics(vec![ModuleDiagnostic {
+                            path,
+                            diagnostic: Diagnostic::from_codegen_error(&error, &source),
+                        }]));
+                    }
+                    Err(error) => return Err(failed(error.to_string())),
+                };
+                objects.entry(path.clone()).or_insert(object)
+            }
+        };
+        linker = linker.with_object(object.clone());
+    }
+    let code = linker.link().map_err(|e| failed(e.to_string()))?;
+    let binary = compile_to_polkavm(&code, None)
+        .map_err(|e| failed(e.to_string()))?
+        .binary
+        .ok_or_else(|| failed("no binary generated".to_string()))?;
+
+    // The ABI lists every message the dispatcher routes calls to
+    let name = module
+        .path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let source = fs::read_to_string(&module.path).unwrap_or_default();
+    let program = module.program_without_std();
+    let metadata = contract_metadata(&name, &source, &program, cfg, Some(Isa::default()));
+    Ok(BuiltContract {
+        object: objects[&entry_path].clone(),
+        code,
+        binary,
+        abi: generate_abi(&metadata),
+        metadata,
+    })
+}
+
+/// The object of `module`, read from the file of a prebuilt library, with
+/// a message dispatcher when it is the `entry` of a contract
+fn compile_object(
+    module: &Module,
+    profile: &Profile,
+    entry: bool,
+) -> Result<ObjectFile, ObjectError> {
+    if is_object(&module.path) {
+        return ObjectFile::read(&module.path);
+    }
+    ObjectFile::compile(
+        module,
+        profile.optimization_level(),
+        Isa::default(),
+        profile.debug(),
+        entry,
+    )
+}
+
+/// Paths of `module` and of the modules it imports, directly or not, in
+/// the order their objects are linked. The standard library is left out,
+/// contracts do not carry it.
+fn link_order(module: &Module, seen: &mut HashSet<PathBuf>) -> Vec<PathBuf> {
+    let path = canonical(&module.path);
+    if modules::embedded(&module.path).is_some() || !seen.insert(path.clone()) {
+        return Vec::new();
+    }
+    let mut order = vec![path];
+    let mut imports: Vec<&Arc<Module>> = module.imports.values().collect();
+    imports.sort_by(|a, b| a.path.cmp(&b.path));
+    for import in imports {
+        order.extend(link_order(import, seen));
+    }
+    order
+}
+
+/// The module at `path` among `module` and what it imports
+fn module_at<'a>(module: &'a Module, path: &Path) -> &'a Module {
+    fn find<'a>(module: &'a Module, path: &Path) -> Option<&'a Module> {
+        if canonical(&module.path) == path {
+            return Some(module);
+        }
+        module
+            .imports
+            .values()
+            .find_map(|import| find(import, path))
+    }
+    find(module, path).unwrap_or(module)
+}
+
+/// The artifact an earlier build left for `module`
+fn built_artifact(
+    workspace: &Workspace,
+    profile: &Profile,
+    module: &Module,
+) -> Result<Artifact, PackageError> {
+    let binary = artifact_path(workspace, profile, module, "bin");
+    let bytes =
+        fs::read(&binary).map_err(|e| PackageError::Io(format!("{}: {}", binary.display(), e)))?;
+    let listing = artifact_path(workspace, profile, module, "s");
+    Ok(Artifact {
+        module: module.path.clone(),
+        listing: (profile.debug() && listing.exists()).then_some(listing),
+        abi: artifact_path(workspace, profile, module, "abi.json"),
+        metadata: artifact_path(workspace, profile, module, "metadata.json"),
+        binary,
+        size: bytes.len() as u64,
+        sha256: sha256(&bytes),
+    })
+}
+
+/// Where the artifact of `module` with the extension `extension` goes
+fn artifact_path(
+    workspace: &Workspace,
+    profile: &Profile,
+    module: &Module,
+    extension: &str,
+) -> PathBuf {
+    let source_dir = workspace.root().source_dir();
+    let relative = module
+        .path
+        .strip_prefix(&source_dir)
+        .unwrap_or(&module.path);
+    target_dir(workspace, profile)
+        .join(relative)
+        .with_extension(extension)
+}
+
+/// Whether `module` is one of `paths` or imports one of them, directly or
+/// through other modules
+fn imports_any(module: &Module, paths: &HashSet<PathBuf>, seen: &mut HashSet<PathBuf>) -> bool {
+    let path = canonical(&module.path);
+    if !seen.insert(path.clone()) {
+        return false;
+    }
+    paths.contains(&path)
+        || module
+            .imports
+            .values()
+            .any(|import| imports_any(import, paths, seen))
+}
+
+/// The modules no other module of `modules` imports
+fn entry_modules(modules: &[Arc<Module>]) -> Vec<&Module> {
+    let imported: HashSet<PathBuf> = modules
+        .iter()
+        .flat_map(|module| module.imports.values())
+        .map(|import| canonical(&import.path))
+        .collect();
+    modules
+        .iter()
+        .filter(|module| !imported.contains(&canonical(&module.path)))
+        .map(|module| &**module)
+        .collect()
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> String {
+    // The ABI and metadata only hold types serde_json can represent
+    serde_json::to_string_pretty(value).unwrap() + "\n"
+}
+
+fn write(path: &Path, contents: &[u8]) -> Result<(), PackageError> {
+    let io = |e: std::io::Error| PackageError::Io(format!("{}: {}", path.display(), e));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(io)?;
+    }
+    fs::write(path, contents).map_err(io)
+}
@@ -4,9 +4,25 @@
 //! and package registry functionality for the Bend programming language.
 
 #![allow(clippy::module_inception)]
+pub mod artifacts;
+pub mod build;
 pub mod package;
+pub mod profile;
+pub mod template;
+pub mod toml;
+pub mod watch;
+pub mod workspace;
 
+pub use artifacts::{ArtifactsManifest, ManifestEntry, ProfileArtifacts, ARTIFACTS_MANIFEST};
+pub use build::{
+    build, build_contract, build_with, check, check_with, database, rebuild, test, Artifact,
+    BuildError, BuildOutput, BuiltContract, ModuleDiagnostic, ModuleTest, TestOutput, TARGET_DIR,
+};
 pub use package::{
-    Dependency, DependencyResolver, Package, PackageError, PackageLock, PackageLockEntry,
-    PackageManifest, PackageMetadata, PackageRegistry, Version,
+    Dependency, DependencyResolver, DependencySource, Package, PackageError, PackageLock,
+    PackageLockEntry, PackageManifest, PackageMetadata, PackageRegistry, Version, DEFAULT_FEATURE,
 };
+pub use profile::Profile;
+pub use template::{Template, TemplateSource, TEMPLATES};
+pub use watch::Watcher;
+pub use workspace::{ResolvedPackage, Workspace, LOCK_FILE, MANIFEST_FILE};
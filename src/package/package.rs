@@ -1,4 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use super::profile::Profile;
+use super::toml::{self, TomlTable, TomlValue};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Version {
@@ -65,33 +69,86 @@ impl Ord for Version {
     }
 }
 
+/// Where a dependency's sources come from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DependencySource {
+    /// Fetched from the local package registry by name and version
+    Registry,
+    /// A directory, relative to the manifest that declares it
+    Path(PathBuf),
+    /// A git repository, optionally pinned to a branch, tag or revision
+    Git {
+        url: String,
+        reference: Option<String>,
+    },
+}
+
+impl std::fmt::Display for DependencySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DependencySource::Registry => write!(f, "registry"),
+            DependencySource::Path(path) => write!(f, "path+{}", path.display()),
+            DependencySource::Git {
+                url,
+                reference: Some(reference),
+            } => write!(f, "git+{}#{}", url, reference),
+            DependencySource::Git {
+                url,
+                reference: None,
+            } => write!(f, "git+{}", url),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Dependency {
     name: String,
-    version: Version,
+    version: Option<Version>,
+    source: DependencySource,
 }
 
 impl Dependency {
     pub fn new(name: String, version: Version) -> Self {
-        Dependency { name, version }
+        Dependency {
+            name,
+            version: Some(version),
+            source: DependencySource::Registry,
+        }
+    }
+
+    /// A dependency on a path or git source, with an optional version
+    /// requirement checked against the manifest found there
+    pub fn with_source(name: String, version: Option<Version>, source: DependencySource) -> Self {
+        Dependency {
+            name,
+            version,
+            source,
+        }
     }
 
     pub fn name(&self) -> &str {
         &self.name
     }
 
-    pub fn version(&self) -> &Version {
-        &self.version
+    pub fn version(&self) -> Option<&Version> {
+        self.version.as_ref()
+    }
+
+    pub fn source(&self) -> &DependencySource {
+        &self.source
     }
 
     pub fn is_satisfied_by(&self, version: &Version) -> bool {
-        version.major == self.version.major && version >= &self.version
+        match &self.version {
+            Some(required) => version.major == required.major && version >= required,
+            None => true,
+        }
     }
 }
 
 impl PartialEq for Dependency {
     fn eq(&self, other: &Self) -> bool {
-        self.name == other.name && self.version == other.version
+        self.name == other.name && self.version == other.version && self.source == other.source
     }
 }
 
@@ -212,12 +269,18 @@ impl Package {
 #[derive(Debug, Clone)]
 pub struct PackageManifest {
     package: Package,
+    /// Profiles with settings from the manifest
+    profiles: Vec<Profile>,
+    /// The `[features]` table: each feature with the features it enables
+    features: BTreeMap<String, Vec<String>>,
 }
 
 impl PackageManifest {
     pub fn new(name: String, version: Version) -> Self {
         PackageManifest {
             package: Package::new(name, version),
+            profiles: Vec::new(),
+            features: BTreeMap::new(),
         }
     }
 
@@ -237,6 +300,176 @@ impl PackageManifest {
         self.package.add_dependency(Dependency::new(name, version));
     }
 
+    /// The profile called `name`, with the manifest's settings over its
+    /// defaults, if there is such a profile
+    pub fn profile(&self, name: &str) -> Option<Profile> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.name() == name)
+            .cloned()
+            .or_else(|| Profile::named(name))
+    }
+
+    /// The features the package declares, each with the features it
+    /// enables; `default` lists those enabled unless asked otherwise
+    pub fn features(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.features
+    }
+
+    /// Declare `name`, enabling `enables` along with it
+    pub fn add_feature(&mut self, name: String, enables: Vec<String>) {
+        self.features.insert(name, enables);
+    }
+
+    /// The features enabled by `requested` and, with `default_features`,
+    /// by `default`, along with those they enable in turn. Fails on a
+    /// feature the package does not declare.
+    pub fn resolve_features(
+        &self,
+        requested: &[String],
+        default_features: bool,
+    ) -> Result<BTreeSet<String>, PackageError> {
+        let mut pending: Vec<&str> = requested.iter().map(String::as_str).collect();
+        if default_features {
+            pending.extend(
+                self.features
+                    .get(DEFAULT_FEATURE)
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str),
+            );
+        }
+
+        let mut enabled = BTreeSet::new();
+        while let Some(feature) = pending.pop() {
+            let enables = self.features.get(feature).ok_or_else(|| {
+                let declared: Vec<&str> = self
+                    .features
+                    .keys()
+                    .map(String::as_str)
+                    .filter(|name| *name != DEFAULT_FEATURE)
+                    .collect();
+                invalid_manifest(&format!(
+                    "package '{}' has no feature '{}'{}",
+                    self.package.name,
+                    feature,
+                    match declared.is_empty() {
+                        true => String::new(),
+                        false => format!(", only {}", declared.join(", ")),
+                    }
+                ))
+            })?;
+            if feature != DEFAULT_FEATURE && enabled.insert(feature.to_string()) {
+                pending.extend(enables.iter().map(String::as_str));
+            }
+        }
+        Ok(enabled)
+    }
+
+    /// Parse the contents of a `bend.toml` manifest
+    pub fn parse(source: &str) -> Result<Self, PackageError> {
+        let document = toml::parse(source)?;
+
+        let package = document
+            .get("package")
+            .and_then(TomlValue::as_table)
+            .ok_or_else(|| invalid_manifest("missing [package] table"))?;
+        let name = string_field(package, "name")?
+            .ok_or_else(|| invalid_manifest("missing package name"))?;
+        let version = string_field(package, "version")?
+            .ok_or_else(|| invalid_manifest("missing package version"))?;
+
+        let mut manifest = PackageManifest::new(name.to_string(), Version::parse(version)?);
+        let metadata = &mut manifest.package.metadata;
+        if let Some(description) = string_field(package, "description")? {
+            metadata.set_description(description.to_string());
+        }
+        if let Some(license) = string_field(package, "license")? {
+            metadata.set_license(license.to_string());
+        }
+        if let Some(repository) = string_field(package, "repository")? {
+            metadata.set_repository(repository.to_string());
+        }
+        if let Some(authors) = package.get("authors") {
+            let authors = authors
+                .as_array()
+                .ok_or_else(|| invalid_manifest("authors must be an array"))?;
+            for author in authors {
+                let author = author
+                    .as_str()
+                    .ok_or_else(|| invalid_manifest("authors must be strings"))?;
+                metadata.add_author(author.to_string());
+            }
+        }
+
+        if let Some(dependencies) = document.get("dependencies") {
+            let dependencies = dependencies
+                .as_table()
+                .ok_or_else(|| invalid_manifest("[dependencies] must be a table"))?;
+            for (name, spec) in dependencies {
+                let dependency = parse_dependency(name, spec)?;
+                manifest.package.add_dependency(dependency);
+            }
+        }
+
+        if let Some(profiles) = document.get("profile") {
+            let profiles = profiles
+                .as_table()
+                .ok_or_else(|| invalid_manifest("[profile] must be a table"))?;
+            for (name, settings) in profiles {
+                let mut profile = Profile::named(name)
+                    .ok_or_else(|| invalid_manifest(&format!("unknown profile '{}'", name)))?;
+                let settings = settings.as_table().ok_or_else(|| {
+                    invalid_manifest(&format!("[profile.{}] must be a table", name))
+                })?;
+                profile.apply(settings)?;
+                manifest.profiles.push(profile);
+            }
+        }
+
+        if let Some(features) = document.get("features") {
+            let features = features
+                .as_table()
+                .ok_or_else(|| invalid_manifest("[features] must be a table"))?;
+            for (name, enables) in features {
+                let enables = enables
+                    .as_array()
+                    .and_then(|enables| {
+                        enables
+                            .iter()
+                            .map(|feature| feature.as_str().map(str::to_string))
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .ok_or_else(|| {
+                        invalid_manifest(&format!(
+                            "feature '{}' must be an array of feature names",
+                            name
+                        ))
+                    })?;
+                manifest.features.insert(name.clone(), enables);
+            }
+            for (name, enables) in &manifest.features {
+                if let Some(unknown) = enables.iter().find(|f| !manifest.features.contains_key(*f))
+                {
+                    return Err(invalid_manifest(&format!(
+                        "feature '{}' enables '{}', which is not declared",
+                        name, unknown
+                    )));
+                }
+            }
+        }
+
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Read and parse a `bend.toml` manifest
+    pub fn load(path: &Path) -> Result<Self, PackageError> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| PackageError::Io(format!("{}: {}", path.display(), e)))?;
+        Self::parse(&source)
+    }
+
     pub fn validate(&self) -> Result<(), PackageError> {
         if self.package.name.is_empty() {
             return Err(PackageError::InvalidName(self.package.name.clone()));
@@ -256,11 +489,113 @@ impl PackageManifest {
     }
 }
 
-#[derive(Debug, Clone)]
+/// The feature listing the features enabled unless `--no-default-features`
+pub const DEFAULT_FEATURE: &str = "default";
+
+fn invalid_manifest(message: &str) -> PackageError {
+    PackageError::InvalidManifest(message.to_string())
+}
+
+fn string_field<'t>(table: &'t TomlTable, key: &str) -> Result<Option<&'t str>, PackageError> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| invalid_manifest(&format!("'{}' must be a string", key))),
+    }
+}
+
+/// Parse a version requirement, allowing the `^` prefix used by Cargo
+fn parse_requirement(requirement: &str) -> Result<Version, PackageError> {
+    Version::parse(requirement.strip_prefix('^').unwrap_or(requirement))
+}
+
+/// Parse one `[dependencies]` entry, either `name = "1.0.0"` or an inline
+/// table with `version`, `path` or `git` and `branch`/`tag`/`rev`
+fn parse_dependency(name: &str, spec: &TomlValue) -> Result<Dependency, PackageError> {
+    if !PackageManifest::is_valid_name(name) {
+        return Err(PackageError::InvalidName(name.to_string()));
+    }
+
+    let table = match spec {
+        TomlValue::String(requirement) => {
+            return Ok(Dependency::new(
+                name.to_string(),
+                parse_requirement(requirement)?,
+            ))
+        }
+        TomlValue::Table(table) => table,
+        _ => {
+            return Err(invalid_manifest(&format!(
+                "dependency '{}' must be a version or a table",
+                name
+            )))
+        }
+    };
+
+    for key in table.keys() {
+        if !matches!(
+            key.as_str(),
+            "version" | "path" | "git" | "branch" | "tag" | "rev"
+        ) {
+            return Err(invalid_manifest(&format!(
+                "unknown key '{}' in dependency '{}'",
+                key, name
+            )));
+        }
+    }
+
+    let version = string_field(table, "version")?
+        .map(parse_requirement)
+        .transpose()?;
+    let references = ["branch", "tag", "rev"]
+        .iter()
+        .filter_map(|key| string_field(table, key).transpose())
+        .collect::<Result<Vec<_>, _>>()?;
+    if references.len() > 1 {
+        return Err(invalid_manifest(&format!(
+            "dependency '{}' may only give one of branch, tag and rev",
+            name
+        )));
+    }
+
+    let source = match (string_field(table, "path")?, string_field(table, "git")?) {
+        (Some(_), Some(_)) => {
+            return Err(invalid_manifest(&format!(
+                "dependency '{}' cannot have both a path and a git source",
+                name
+            )))
+        }
+        (Some(path), None) => DependencySource::Path(PathBuf::from(path)),
+        (None, Some(url)) => DependencySource::Git {
+            url: url.to_string(),
+            reference: references.first().map(|reference| reference.to_string()),
+        },
+        (None, None) => {
+            let version = version.ok_or_else(|| {
+                invalid_manifest(&format!("dependency '{}' needs a version", name))
+            })?;
+            return Ok(Dependency::new(name.to_string(), version));
+        }
+    };
+    if !references.is_empty() && !matches!(source, DependencySource::Git { .. }) {
+        return Err(invalid_manifest(&format!(
+            "branch, tag and rev only apply to git dependencies ('{}')",
+            name
+        )));
+    }
+
+    Ok(Dependency::with_source(name.to_string(), version, source))
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct PackageLockEntry {
     name: String,
     version: Version,
     integrity: String,
+    source: String,
+    dependencies: Vec<String>,
 }
 
 impl PackageLockEntry {
@@ -269,9 +604,24 @@ impl PackageLockEntry {
             name,
             version,
             integrity,
+            source: DependencySource::Registry.to_string(),
+            dependencies: Vec::new(),
         }
     }
 
+    /// Record where the package was resolved from, e.g. `path+../math` or
+    /// `git+https://example.com/math.git#<commit>`
+    pub fn with_source(mut self, source: String) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Record the names of the package's own dependencies
+    pub fn with_dependencies(mut self, dependencies: Vec<String>) -> Self {
+        self.dependencies = dependencies;
+        self
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -283,9 +633,17 @@ impl PackageLockEntry {
     pub fn integrity(&self) -> &str {
         &self.integrity
     }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn dependencies(&self) -> &[String] {
+        &self.dependencies
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PackageLock {
     entries: HashMap<String, PackageLockEntry>,
 }
@@ -308,6 +666,95 @@ impl PackageLock {
     pub fn get(&self, name: &str) -> Option<&PackageLockEntry> {
         self.entries.get(name)
     }
+
+    /// Parse the contents of a `bend.lock` file
+    pub fn parse(source: &str) -> Result<Self, PackageError> {
+        let document = toml::parse(source)?;
+        let mut lock = PackageLock::new();
+
+        let packages = match document.get("package") {
+            None => return Ok(lock),
+            Some(packages) => packages
+                .as_array()
+                .ok_or_else(|| invalid_manifest("[[package]] must be an array of tables"))?,
+        };
+        for package in packages {
+            let package = package
+                .as_table()
+                .ok_or_else(|| invalid_manifest("[[package]] must be an array of tables"))?;
+            let field = |key: &str| {
+                string_field(package, key)?.ok_or_else(|| {
+                    invalid_manifest(&format!("locked package is missing '{}'", key))
+                })
+            };
+
+            let mut dependencies = Vec::new();
+            if let Some(names) = package.get("dependencies") {
+                let names = names
+                    .as_array()
+                    .ok_or_else(|| invalid_manifest("dependencies must be an array"))?;
+                for name in names {
+                    let name = name
+                        .as_str()
+                        .ok_or_else(|| invalid_manifest("dependencies must be strings"))?;
+                    dependencies.push(name.to_string());
+                }
+            }
+
+            lock.add_entry(
+                PackageLockEntry::new(
+                    field("name")?.to_string(),
+                    Version::parse(field("version")?)?,
+                    field("integrity")?.to_string(),
+                )
+                .with_source(field("source")?.to_string())
+                .with_dependencies(dependencies),
+            );
+        }
+
+        Ok(lock)
+    }
+
+    /// Render the lock file, with packages sorted by name
+    pub fn to_toml(&self) -> String {
+        let mut entries: Vec<_> = self.entries.values().collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut output =
+            String::from("# This file is generated by bend-pvm. Do not edit it by hand.\n");
+        for entry in entries {
+            output.push_str("\n[[package]]\n");
+            output.push_str(&format!("name = {}\n", toml::quote(&entry.name)));
+            output.push_str(&format!(
+                "version = {}\n",
+                toml::quote(&entry.version.to_string())
+            ));
+            output.push_str(&format!("source = {}\n", toml::quote(&entry.source)));
+            output.push_str(&format!("integrity = {}\n", toml::quote(&entry.integrity)));
+            if !entry.dependencies.is_empty() {
+                let names: Vec<_> = entry
+                    .dependencies
+                    .iter()
+                    .map(|name| toml::quote(name))
+                    .collect();
+                output.push_str(&format!("dependencies = [{}]\n", names.join(", ")));
+            }
+        }
+        output
+    }
+
+    /// Read a `bend.lock` file
+    pub fn load(path: &Path) -> Result<Self, PackageError> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| PackageError::Io(format!("{}: {}", path.display(), e)))?;
+        Self::parse(&source)
+    }
+
+    /// Write the lock file to `path`
+    pub fn save(&self, path: &Path) -> Result<(), PackageError> {
+        std::fs::write(path, self.to_toml())
+            .map_err(|e| PackageError::Io(format!("{}: {}", path.display(), e)))
+    }
 }
 
 impl Default for PackageLock {
@@ -394,7 +841,7 @@ impl DependencyResolver {
                 if !dep.is_satisfied_by(version) {
                     return Err(PackageError::UnsatisfiedDependency {
                         package: dep.name().to_string(),
-                        required: dep.version().clone(),
+                        required: dep.version().cloned().unwrap(),
                         available: version.clone(),
                     });
                 }
@@ -426,6 +873,25 @@ pub enum PackageError {
         required: Version,
         available: Version,
     },
+    InvalidManifest(String),
+    Io(String),
+    /// A dependency whose source has no matching package
+    NotFound {
+        package: String,
+        source: String,
+    },
+    /// Dependencies that depend on each other, first package repeated last
+    DependencyCycle(Vec<String>),
+    /// A git dependency that could not be fetched
+    Fetch {
+        package: String,
+        message: String,
+    },
+    /// A dependency whose modules failed to compile
+    Compile {
+        package: String,
+        message: String,
+    },
 }
 
 impl std::fmt::Display for PackageError {
@@ -462,6 +928,24 @@ impl std::fmt::Display for PackageError {
                     package, required, available
                 )
             }
+            PackageError::InvalidManifest(message) => {
+                write!(f, "Invalid manifest: {}", message)
+            }
+            PackageError::Io(message) => {
+                write!(f, "IO error: {}", message)
+            }
+            PackageError::NotFound { package, source } => {
+                write!(f, "Dependency {} not found in {}", package, source)
+            }
+            PackageError::DependencyCycle(cycle) => {
+                write!(f, "Dependency cycle: {}", cycle.join(" -> "))
+            }
+            PackageError::Fetch { package, message } => {
+                write!(f, "Failed to fetch {}: {}", package, message)
+            }
+            PackageError::Compile { package, message } => {
+                write!(f, "Failed to compile {}: {}", package, message)
+            }
         }
     }
 }
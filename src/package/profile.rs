@@ -0,0 +1,152 @@
+//! Build profiles, set up by the `[profile.dev]` and `[profile.release]`
+//! tables of `bend.toml`
+//!
+//! ```toml
+//! [profile.release]
+//! opt-level = 3
+//! debug = false
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use super::package::PackageError;
+use super::toml::{TomlTable, TomlValue};
+use crate::compiler::optimizer::passes::OptimizationLevel;
+
+/// The profile builds use unless `--release` is given
+pub const DEV: &str = "dev";
+pub const RELEASE: &str = "release";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    name: String,
+    /// From 0, no optimizations, to 3, aggressive ones
+    opt_level: u8,
+    /// Whether to write an assembly listing next to each binary
+    debug: bool,
+    /// Whether every build must give the same bytes wherever the package
+    /// is: source paths are recorded relative to the package root and no
+    /// build time is recorded unless `SOURCE_DATE_EPOCH` gives one
+    deterministic: bool,
+    /// Prefixes of source paths to replace in the artifacts, first match
+    /// first, as with `--remap-path-prefix FROM=TO`
+    remap_path_prefix: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Profile {
+    /// Unoptimized, with debug information
+    pub fn dev() -> Self {
+        Profile {
+            name: DEV.to_string(),
+            opt_level: 0,
+            debug: true,
+            deterministic: false,
+            remap_path_prefix: Vec::new(),
+        }
+    }
+
+    /// Fully optimized, without debug information
+    pub fn release() -> Self {
+        Profile {
+            name: RELEASE.to_string(),
+            opt_level: 3,
+            debug: false,
+            deterministic: false,
+            remap_path_prefix: Vec::new(),
+        }
+    }
+
+    /// The defaults of the profile called `name`, if there is one
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            DEV => Some(Profile::dev()),
+            RELEASE => Some(Profile::release()),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn opt_level(&self) -> u8 {
+        self.opt_level
+    }
+
+    pub fn debug(&self) -> bool {
+        self.debug
+    }
+
+    pub fn deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// The profile, building the same bytes wherever the package is when
+    /// `deterministic`, as `build --deterministic` does
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// The profile, recording source paths under each `FROM` as under `TO`
+    pub fn with_remap_path_prefix(mut self, prefixes: Vec<(PathBuf, PathBuf)>) -> Self {
+        self.remap_path_prefix = prefixes;
+        self
+    }
+
+    /// `path`, a source of the package at `root`, as the artifacts record
+    /// it
+    pub fn source_path(&self, root: &Path, path: &Path) -> PathBuf {
+        let remapped = self
+            .remap_path_prefix
+            .iter()
+            .find_map(|(from, to)| Some(to.join(path.strip_prefix(from).ok()?)));
+        match remapped {
+            Some(path) => path,
+            None if self.deterministic => path
+                .strip_prefix(root)
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|_| path.file_name().map_or_else(PathBuf::new, PathBuf::from)),
+            None => path.to_path_buf(),
+        }
+    }
+
+    pub fn optimization_level(&self) -> OptimizationLevel {
+        match self.opt_level {
+            0 => OptimizationLevel::None,
+            1 => OptimizationLevel::Basic,
+            2 => OptimizationLevel::Standard,
+            _ => OptimizationLevel::Aggressive,
+        }
+    }
+
+    /// Override the defaults with the keys of a `[profile.<name>]` table
+    pub(crate) fn apply(&mut self, table: &TomlTable) -> Result<(), PackageError> {
+        let invalid = |message: String| PackageError::InvalidManifest(message);
+        for (key, value) in table {
+            match (key.as_str(), value) {
+                ("opt-level", TomlValue::Integer(level @ 0..=3)) => self.opt_level = *level as u8,
+                ("opt-level", _) => {
+                    return Err(invalid(format!(
+                        "opt-level of profile '{}' must be 0, 1, 2 or 3",
+                        self.name
+                    )))
+                }
+                ("debug", TomlValue::Boolean(debug)) => self.debug = *debug,
+                ("debug", _) => {
+                    return Err(invalid(format!(
+                        "debug of profile '{}' must be a boolean",
+                        self.name
+                    )))
+                }
+                _ => {
+                    return Err(invalid(format!(
+                        "unknown key '{}' in profile '{}'",
+                        key, self.name
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+}
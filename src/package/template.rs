@@ -0,0 +1,307 @@
+//! Project templates for `bend-pvm init`
+//!
+//! The embedded templates live in `templates/` and are compiled into the
+//! binary: each has one contract module with its `#[test]` functions, and
+//! they share the manifest, README, `.gitignore` and CI workflow of
+//! `templates/common/`. A template can also be a git repository holding a
+//! whole package, given as `git+<url>` with an optional `#<branch, tag or
+//! revision>`, the way lock files write git sources.
+//!
+//! In every file, `{{name}}` is replaced by the name of the new package.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::package::{PackageError, PackageManifest};
+use super::workspace::{git, MANIFEST_FILE};
+
+/// A contract template compiled into the binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Template {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Name of the contract module, `src/<module>.bend`
+    pub module: &'static str,
+    pub source: &'static str,
+    /// Constructor and its arguments, as `bend-pvm deploy` takes them
+    pub deploy: &'static str,
+}
+
+pub const TEMPLATES: &[Template] = &[
+    Template {
+        name: "minimal",
+        description: "A contract keeping a counter",
+        module: "main",
+        source: include_str!("../../templates/minimal/main.bend"),
+        deploy: "",
+    },
+    Template {
+        name: "erc20",
+        description: "An ERC-20 style fungible token",
+        module: "token",
+        source: include_str!("../../templates/erc20/token.bend"),
+        deploy: " --constructor init 1000000",
+    },
+    Template {
+        name: "erc721",
+        description: "An ERC-721 style collection of non-fungible tokens",
+        module: "nft",
+        source: include_str!("../../templates/erc721/nft.bend"),
+        deploy: " --constructor init",
+    },
+    Template {
+        name: "dao",
+        description: "A DAO whose members vote on proposals by weight",
+        module: "dao",
+        source: include_str!("../../templates/dao/dao.bend"),
+        deploy: " --constructor init 1",
+    },
+    Template {
+        name: "multisig",
+        description: "A multisig wallet whose transfers need several owners to sign",
+        module: "multisig",
+        source: include_str!("../../templates/multisig/multisig.bend"),
+        deploy: " --constructor init 2",
+    },
+    Template {
+        name: "escrow",
+        description: "An escrow holding a payment until an arbiter settles it",
+        module: "escrow",
+        source: include_str!("../../templates/escrow/escrow.bend"),
+        deploy: " --constructor init 5FHneW46xGXgs5mUiveU4sbTyGBzmstUspZC92UhjJM694ty 5FLSigC9HGRKVhB9FiEo4Y3koPsNmBmLJbpXg2mp1hXcS59Y",
+    },
+];
+
+const MANIFEST: &str = include_str!("../../templates/common/bend.toml");
+const README: &str = include_str!("../../templates/common/README.md");
+const GITIGNORE: &str = include_str!("../../templates/common/gitignore");
+const CI_WORKFLOW: &str = include_str!("../../templates/common/ci.yml");
+
+impl Template {
+    pub fn named(name: &str) -> Option<&'static Template> {
+        TEMPLATES.iter().find(|template| template.name == name)
+    }
+
+    /// The files of a package named `package` made from this template, by
+    /// path relative to its root
+    pub fn files(&self, package: &str) -> Vec<(PathBuf, String)> {
+        let render = |text: &str| {
+            text.replace("{{name}}", package)
+                .replace("{{description}}", self.description)
+                .replace("{{module}}", self.module)
+                .replace("{{deploy}}", self.deploy)
+        };
+        vec![
+            (PathBuf::from(MANIFEST_FILE), render(MANIFEST)),
+            (
+                Path::new("src").join(format!("{}.bend", self.module)),
+                render(self.source),
+            ),
+            (PathBuf::from("README.md"), render(README)),
+            (PathBuf::from(".gitignore"), GITIGNORE.to_string()),
+            (
+                Path::new(".github").join("workflows").join("ci.yml"),
+                CI_WORKFLOW.to_string(),
+            ),
+        ]
+    }
+}
+
+/// Where `init` takes the files of a new package from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateSource {
+    Embedded(&'static Template),
+    /// A git repository holding a package, optionally at a branch, tag or
+    /// revision
+    Git {
+        url: String,
+        reference: Option<String>,
+    },
+}
+
+impl FromStr for TemplateSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(source) = s.strip_prefix("git+") {
+            let (url, reference) = match source.split_once('#') {
+                Some((url, reference)) => (url, Some(reference.to_string())),
+                None => (source, None),
+            };
+            return Ok(TemplateSource::Git {
+                url: url.to_string(),
+                reference,
+            });
+        }
+        Template::named(s)
+            .map(TemplateSource::Embedded)
+            .ok_or_else(|| {
+                let names: Vec<&str> = TEMPLATES.iter().map(|template| template.name).collect();
+                format!(
+                    "Unknown template '{}', expected {} or git+<url>",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for TemplateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateSource::Embedded(template) => write!(f, "{}", template.name),
+            TemplateSource::Git {
+                url,
+                reference: Some(reference),
+            } => write!(f, "git+{}#{}", url, reference),
+            TemplateSource::Git {
+                url,
+                reference: None,
+            } => write!(f, "git+{}", url),
+        }
+    }
+}
+
+impl TemplateSource {
+    /// Write a package named `name` into `dir`, creating it if needed.
+    /// Returns the files written, relative to `dir`. A directory that
+    /// already holds a package is left alone.
+    pub fn scaffold(&self, dir: &Path, name: &str) -> Result<Vec<PathBuf>, PackageError> {
+        if !PackageManifest::is_valid_name(name) {
+            return Err(PackageError::InvalidName(name.to_string()));
+        }
+        if dir.join(MANIFEST_FILE).exists() {
+            return Err(PackageError::AlreadyExists(dir.display().to_string()));
+        }
+
+        let files = match self {
+            TemplateSource::Embedded(template) => template.files(name),
+            TemplateSource::Git { url, reference } => {
+                clone_template(url, reference.as_deref(), name)?
+            }
+        };
+        for (path, contents) in &files {
+            let path = dir.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| io_error(parent, e))?;
+            }
+            fs::write(&path, contents).map_err(|e| io_error(&path, e))?;
+        }
+        Ok(files.into_iter().map(|(path, _)| path).collect())
+    }
+}
+
+/// Clone the template at `url` into a scratch directory and read the
+/// files of its package, with the package's name replaced by `package`
+fn clone_template(
+    url: &str,
+    reference: Option<&str>,
+    package: &str,
+) -> Result<Vec<(PathBuf, String)>, PackageError> {
+    let checkout = checkout_dir(url);
+    let _ = fs::remove_dir_all(&checkout);
+    let files = read_template(url, reference, package, &checkout);
+    let _ = fs::remove_dir_all(&checkout);
+    files
+}
+
+fn read_template(
+    url: &str,
+    reference: Option<&str>,
+    package: &str,
+    checkout: &Path,
+) -> Result<Vec<(PathBuf, String)>, PackageError> {
+    let parent = checkout.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent).map_err(|e| io_error(parent, e))?;
+    let destination = checkout.to_string_lossy().to_string();
+    git(url, parent, &["clone", "--quiet", url, &destination])?;
+    if let Some(reference) = reference {
+        git(url, checkout, &["checkout", "--quiet", reference])?;
+    }
+
+    let manifest = checkout.join(MANIFEST_FILE);
+    let template = PackageManifest::load(&manifest).map_err(|e| PackageError::Fetch {
+        package: url.to_string(),
+        message: format!("not a package template: {}", e),
+    })?;
+
+    let mut files = Vec::new();
+    read_files(checkout, checkout, &mut files)?;
+    for (path, contents) in &mut files {
+        *contents = contents.replace("{{name}}", package);
+        // The name of the package the template was made from is replaced
+        // too, in the manifest only
+        if path == Path::new(MANIFEST_FILE) {
+            *contents = rename_package(contents, template.package().name(), package);
+        }
+    }
+    Ok(files)
+}
+
+/// Every file below `dir` except those of `.git`, sorted by path relative
+/// to `root`
+fn read_files(
+    root: &Path,
+    dir: &Path,
+    files: &mut Vec<(PathBuf, String)>,
+) -> Result<(), PackageError> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| io_error(dir, e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path.file_name().is_some_and(|name| name == ".git") {
+            continue;
+        }
+        if path.is_dir() {
+            read_files(root, &path, files)?;
+        } else {
+            let contents = fs::read_to_string(&path).map_err(|e| io_error(&path, e))?;
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            files.push((relative, contents));
+        }
+    }
+    Ok(())
+}
+
+/// `manifest` with the `name` of its `[package]` table set to `name`
+fn rename_package(manifest: &str, old: &str, name: &str) -> String {
+    let old = format!("\"{}\"", old);
+    let mut in_package = false;
+    let mut renamed = String::new();
+    for line in manifest.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            in_package = trimmed == "[package]";
+        }
+        let is_name = trimmed
+            .strip_prefix("name")
+            .is_some_and(|rest| rest.trim_start().starts_with('='));
+        if in_package && is_name && line.contains(&old) {
+            renamed.push_str(&format!("name = \"{}\"\n", name));
+        } else {
+            renamed.push_str(line);
+            renamed.push('\n');
+        }
+    }
+    renamed
+}
+
+/// Where the template at `url` is checked out while a package is made
+/// from it
+fn checkout_dir(url: &str) -> PathBuf {
+    let id: String = url
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    std::env::temp_dir()
+        .join("bend-templates")
+        .join(format!("{}-{}", std::process::id(), id))
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> PackageError {
+    PackageError::Io(format!("{}: {}", path.display(), error))
+}
@@ -0,0 +1,363 @@
+//! Reader for the subset of TOML used by `bend.toml` and `bend.lock`
+//!
+//! Supports `[table]` and `[[array]]` headers, bare and quoted keys, basic
+//! and literal strings, integers, booleans, arrays and inline tables.
+
+use std::collections::BTreeMap;
+
+use super::package::PackageError;
+
+/// A table of keys to values, kept sorted so output is deterministic
+pub type TomlTable = BTreeMap<String, TomlValue>;
+
+/// A parsed TOML value
+#[derive(Debug, Clone, PartialEq)]
+pub enum TomlValue {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<TomlValue>),
+    Table(TomlTable),
+}
+
+impl TomlValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            TomlValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[TomlValue]> {
+        match self {
+            TomlValue::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&TomlTable> {
+        match self {
+            TomlValue::Table(table) => Some(table),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a document into its root table
+pub fn parse(source: &str) -> Result<TomlTable, PackageError> {
+    TomlParser {
+        chars: source.chars().collect(),
+        pos: 0,
+        line: 1,
+    }
+    .parse_document()
+}
+
+/// Quote a string as a TOML basic string
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+struct TomlParser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl TomlParser {
+    fn parse_document(&mut self) -> Result<TomlTable, PackageError> {
+        let mut root = TomlTable::new();
+        // Path of the table that key/value pairs currently go into
+        let mut current: Vec<String> = Vec::new();
+
+        loop {
+            self.skip_trivia(true);
+            let Some(c) = self.peek() else {
+                return Ok(root);
+            };
+
+            if c == '[' {
+                self.pos += 1;
+                let is_array = self.eat('[');
+                let path = self.parse_key_path(']')?;
+                self.expect(']')?;
+                if is_array {
+                    self.expect(']')?;
+                }
+                self.end_of_line()?;
+
+                if is_array {
+                    let (last, parents) = path.split_last().unwrap();
+                    let parent = self.table_at(&mut root, parents)?;
+                    match parent
+                        .entry(last.clone())
+                        .or_insert_with(|| TomlValue::Array(Vec::new()))
+                    {
+                        TomlValue::Array(tables) => tables.push(TomlValue::Table(TomlTable::new())),
+                        _ => return Err(self.error(&format!("'{}' is not an array", last))),
+                    }
+                } else {
+                    self.table_at(&mut root, &path)?;
+                }
+                current = path;
+            } else {
+                let key = self.parse_key_path('=')?;
+                self.skip_whitespace();
+                self.expect('=')?;
+                let value = self.parse_value()?;
+                self.end_of_line()?;
+
+                let (last, parents) = key.split_last().unwrap();
+                let mut path = current.clone();
+                path.extend(parents.iter().cloned());
+                let table = self.table_at(&mut root, &path)?;
+                if table.insert(last.clone(), value).is_some() {
+                    return Err(self.error(&format!("duplicate key '{}'", last)));
+                }
+            }
+        }
+    }
+
+    /// The table at `path`, creating missing tables and descending into the
+    /// last table of arrays of tables
+    fn table_at<'t>(
+        &self,
+        root: &'t mut TomlTable,
+        path: &[String],
+    ) -> Result<&'t mut TomlTable, PackageError> {
+        let mut table = root;
+        for key in path {
+            let entry = table
+                .entry(key.clone())
+                .or_insert_with(|| TomlValue::Table(TomlTable::new()));
+            table = match entry {
+                TomlValue::Table(inner) => inner,
+                TomlValue::Array(values) => match values.last_mut() {
+                    Some(TomlValue::Table(inner)) => inner,
+                    _ => return Err(self.error(&format!("'{}' is not a table", key))),
+                },
+                _ => return Err(self.error(&format!("'{}' is not a table", key))),
+            };
+        }
+        Ok(table)
+    }
+
+    fn parse_key_path(&mut self, terminator: char) -> Result<Vec<String>, PackageError> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_whitespace();
+            path.push(self.parse_key()?);
+            self.skip_whitespace();
+            if !self.eat('.') {
+                break;
+            }
+        }
+        if self.peek() != Some(terminator) {
+            return Err(self.error(&format!("expected '{}'", terminator)));
+        }
+        Ok(path)
+    }
+
+    fn parse_key(&mut self) -> Result<String, PackageError> {
+        match self.peek() {
+            Some('"') => self.parse_basic_string(),
+            Some('\'') => self.parse_literal_string(),
+            _ => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                {
+                    self.pos += 1;
+                }
+                if start == self.pos {
+                    return Err(self.error("expected a key"));
+                }
+                Ok(self.chars[start..self.pos].iter().collect())
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<TomlValue, PackageError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('"') => Ok(TomlValue::String(self.parse_basic_string()?)),
+            Some('\'') => Ok(TomlValue::String(self.parse_literal_string()?)),
+            Some('[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                loop {
+                    self.skip_trivia(true);
+                    if self.eat(']') {
+                        return Ok(TomlValue::Array(values));
+                    }
+                    values.push(self.parse_value()?);
+                    self.skip_trivia(true);
+                    if !self.eat(',') {
+                        self.skip_trivia(true);
+                        self.expect(']')?;
+                        return Ok(TomlValue::Array(values));
+                    }
+                }
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut table = TomlTable::new();
+                self.skip_whitespace();
+                if self.eat('}') {
+                    return Ok(TomlValue::Table(table));
+                }
+                loop {
+                    let key = self.parse_key_path('=')?;
+                    self.expect('=')?;
+                    let value = self.parse_value()?;
+                    let (last, parents) = key.split_last().unwrap();
+                    if self
+                        .table_at(&mut table, parents)?
+                        .insert(last.clone(), value)
+                        .is_some()
+                    {
+                        return Err(self.error(&format!("duplicate key '{}'", last)));
+                    }
+                    self.skip_whitespace();
+                    if self.eat('}') {
+                        return Ok(TomlValue::Table(table));
+                    }
+                    self.expect(',')?;
+                }
+            }
+            Some(c) if c.is_ascii_alphanumeric() || c == '-' || c == '+' => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '_'))
+                {
+                    self.pos += 1;
+                }
+                let word: String = self.chars[start..self.pos].iter().collect();
+                match word.as_str() {
+                    "true" => Ok(TomlValue::Boolean(true)),
+                    "false" => Ok(TomlValue::Boolean(false)),
+                    _ => word
+                        .replace('_', "")
+                        .parse()
+                        .map(TomlValue::Integer)
+                        .map_err(|_| self.error(&format!("invalid value '{}'", word))),
+                }
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, PackageError> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(value),
+                Some('\\') => match self.bump() {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('r') => value.push('\r'),
+                    _ => return Err(self.error("invalid escape sequence")),
+                },
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, PackageError> {
+        self.expect('\'')?;
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('\'') => return Ok(value),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    /// Skip whitespace and comments, and newlines too if `newlines` is set
+    fn skip_trivia(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => self.pos += 1,
+                '\n' if newlines => {
+                    self.pos += 1;
+                    self.line += 1;
+                }
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c == ' ' || c == '\t') {
+            self.pos += 1;
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), PackageError> {
+        self.skip_trivia(false);
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), PackageError> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn error(&self, message: &str) -> PackageError {
+        PackageError::InvalidManifest(format!("line {}: {}", self.line, message))
+    }
+}
@@ -0,0 +1,122 @@
+//! Noticing edits to the sources of a workspace, for `build --watch`
+//!
+//! The watcher polls: it remembers the size and modification time of the
+//! manifest and the modules of every package in the workspace and reports
+//! the files that were added, changed or removed since the last look.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use super::package::PackageError;
+use super::workspace::{Workspace, MANIFEST_FILE};
+
+/// How long the watcher waits for an editor to finish writing files
+/// before it reports them
+const SETTLE: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+pub struct Watcher {
+    /// Package roots whose manifests and `src/` directories are watched
+    roots: Vec<PathBuf>,
+    files: BTreeMap<PathBuf, Stamp>,
+}
+
+impl Watcher {
+    /// Watch the packages of `workspace` as they are now
+    pub fn new(workspace: &Workspace) -> Result<Self, PackageError> {
+        let roots = std::iter::once(workspace.root())
+            .chain(workspace.dependencies())
+            .map(|package| package.root().to_path_buf())
+            .collect();
+        let mut watcher = Watcher {
+            roots,
+            files: BTreeMap::new(),
+        };
+        watcher.files = watcher.scan()?;
+        Ok(watcher)
+    }
+
+    /// The watched files
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(PathBuf::as_path)
+    }
+
+    /// The files added, changed or removed since the last call, sorted
+    pub fn changes(&mut self) -> Result<Vec<PathBuf>, PackageError> {
+        let files = self.scan()?;
+        let mut changed: Vec<PathBuf> = files
+            .iter()
+            .filter(|(path, stamp)| self.files.get(*path) != Some(stamp))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(
+            self.files
+                .keys()
+                .filter(|path| !files.contains_key(*path))
+                .cloned(),
+        );
+        changed.sort();
+        self.files = files;
+        Ok(changed)
+    }
+
+    /// Block until some files change, checking every `interval`
+    pub fn wait(&mut self, interval: Duration) -> Result<Vec<PathBuf>, PackageError> {
+        loop {
+            thread::sleep(interval);
+            let mut changed = self.changes()?;
+            if changed.is_empty() {
+                continue;
+            }
+            // Saving several files at once reports them together
+            thread::sleep(SETTLE);
+            changed.extend(self.changes()?);
+            changed.sort();
+            changed.dedup();
+            return Ok(changed);
+        }
+    }
+
+    fn scan(&self) -> Result<BTreeMap<PathBuf, Stamp>, PackageError> {
+        let mut files = BTreeMap::new();
+        for root in &self.roots {
+            let mut paths = vec![root.join(MANIFEST_FILE)];
+            collect(&root.join("src"), &mut paths)
+                .map_err(|e| PackageError::Io(format!("{}: {}", root.display(), e)))?;
+            for path in paths {
+                // Files removed while scanning are missing from this scan
+                if let Ok(metadata) = fs::metadata(&path) {
+                    let stamp = Stamp {
+                        len: metadata.len(),
+                        modified: metadata.modified().ok(),
+                    };
+                    files.insert(path, stamp);
+                }
+            }
+        }
+        Ok(files)
+    }
+}
+
+fn collect(dir: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect(&path, paths)?;
+        } else if path.extension().is_some_and(|ext| ext == "bend") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
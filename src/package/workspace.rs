@@ -0,0 +1,528 @@
+//! Resolution of a package's `bend.toml` dependencies
+//!
+//! Path dependencies are read in place, registry dependencies come from
+//! `<home>/registry/<name>/<version>/` and git dependencies are cloned into
+//! `<home>/git/`, where `<home>` is `$BEND_HOME` or `~/.bend`. The result is
+//! recorded in `bend.lock` next to the manifest, and later resolutions reuse
+//! the locked registry versions and git commits.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use sha2::{Digest, Sha256};
+
+use super::package::{
+    Dependency, DependencySource, PackageError, PackageLock, PackageLockEntry, PackageManifest,
+    Version,
+};
+use crate::compiler::analyzer::type_checker::TypeChecker;
+use crate::compiler::cfg::Cfg;
+use crate::compiler::module::cache::{ModuleCache, CACHE_DIR};
+use crate::compiler::module::ModuleSystem;
+
+/// Name of the package manifest
+pub const MANIFEST_FILE: &str = "bend.toml";
+
+/// Name of the lock file written next to the manifest
+pub const LOCK_FILE: &str = "bend.lock";
+
+/// A package whose sources have been located on disk
+#[derive(Debug, Clone)]
+pub struct ResolvedPackage {
+    manifest: PackageManifest,
+    root: PathBuf,
+    /// Source recorded in the lock file
+    source: String,
+    integrity: String,
+}
+
+impl ResolvedPackage {
+    fn load(root: PathBuf, source: String) -> Result<Self, PackageError> {
+        let manifest = PackageManifest::load(&root.join(MANIFEST_FILE))?;
+        let integrity = integrity_of(&root)?;
+        Ok(ResolvedPackage {
+            manifest,
+            root,
+            source,
+            integrity,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        self.manifest.package().name()
+    }
+
+    pub fn version(&self) -> &Version {
+        self.manifest.package().version()
+    }
+
+    pub fn manifest(&self) -> &PackageManifest {
+        &self.manifest
+    }
+
+    /// Directory holding the package's manifest
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Directory holding the package's modules
+    pub fn source_dir(&self) -> PathBuf {
+        self.root.join("src")
+    }
+
+    /// Every module of the package, sorted by path
+    pub fn module_files(&self) -> Result<Vec<PathBuf>, PackageError> {
+        module_files(&self.source_dir())
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn integrity(&self) -> &str {
+        &self.integrity
+    }
+
+    fn lock_entry(&self) -> PackageLockEntry {
+        PackageLockEntry::new(
+            self.name().to_string(),
+            self.version().clone(),
+            self.integrity.clone(),
+        )
+        .with_source(self.source.clone())
+        .with_dependencies(
+            self.manifest
+                .dependencies()
+                .iter()
+                .map(|dependency| dependency.name().to_string())
+                .collect(),
+        )
+    }
+}
+
+/// A root package together with all of its resolved dependencies
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    root: String,
+    /// Every package by name, including the root
+    packages: BTreeMap<String, ResolvedPackage>,
+}
+
+impl Workspace {
+    /// Resolve the package whose manifest is in `root`
+    pub fn load(root: &Path) -> Result<Self, PackageError> {
+        Self::load_with_home(root, &default_home())
+    }
+
+    /// Resolve the package whose manifest is in `root`, using `home` for
+    /// the registry and git checkouts
+    pub fn load_with_home(root: &Path, home: &Path) -> Result<Self, PackageError> {
+        let root = root
+            .canonicalize()
+            .map_err(|e| PackageError::Io(format!("{}: {}", root.display(), e)))?;
+        let lock_path = root.join(LOCK_FILE);
+        let lock = if lock_path.exists() {
+            PackageLock::load(&lock_path)?
+        } else {
+            PackageLock::new()
+        };
+
+        let package = ResolvedPackage::load(root, "root".to_string())?;
+        let mut resolver = Resolver {
+            home: home.to_path_buf(),
+            lock,
+            packages: BTreeMap::new(),
+        };
+        resolver
+            .packages
+            .insert(package.name().to_string(), package.clone());
+        resolver.resolve_dependencies(&package)?;
+
+        Ok(Workspace {
+            root: package.name().to_string(),
+            packages: resolver.packages,
+        })
+    }
+
+    /// The nearest directory at or above `path` containing a manifest
+    pub fn find_root(path: &Path) -> Option<PathBuf> {
+        let path = path.canonicalize().ok()?;
+        let start = if path.is_file() {
+            path.parent()?
+        } else {
+            &path
+        };
+        start
+            .ancestors()
+            .find(|dir| dir.join(MANIFEST_FILE).is_file())
+            .map(Path::to_path_buf)
+    }
+
+    pub fn root(&self) -> &ResolvedPackage {
+        &self.packages[&self.root]
+    }
+
+    pub fn package(&self, name: &str) -> Option<&ResolvedPackage> {
+        self.packages.get(name)
+    }
+
+    /// All resolved dependencies, direct and indirect, sorted by name
+    pub fn dependencies(&self) -> impl Iterator<Item = &ResolvedPackage> {
+        self.packages
+            .values()
+            .filter(move |package| package.name() != self.root)
+    }
+
+    /// The lock file describing this resolution
+    pub fn lock(&self) -> PackageLock {
+        let mut lock = PackageLock::new();
+        for package in self.dependencies() {
+            lock.add_entry(package.lock_entry());
+        }
+        lock
+    }
+
+    /// Write `bend.lock` next to the root manifest
+    pub fn write_lock(&self) -> Result<(), PackageError> {
+        self.lock().save(&self.root().root.join(LOCK_FILE))
+    }
+
+    /// Packages ordered so that every package comes after its
+    /// dependencies, ending with the root
+    pub fn build_order(&self) -> Result<Vec<&ResolvedPackage>, PackageError> {
+        let mut order = Vec::new();
+        let mut done = HashSet::new();
+        let mut stack = Vec::new();
+        self.visit(&self.root, &mut stack, &mut done, &mut order)?;
+        Ok(order)
+    }
+
+    fn visit<'w>(
+        &'w self,
+        name: &str,
+        stack: &mut Vec<String>,
+        done: &mut HashSet<String>,
+        order: &mut Vec<&'w ResolvedPackage>,
+    ) -> Result<(), PackageError> {
+        if done.contains(name) {
+            return Ok(());
+        }
+        if let Some(start) = stack.iter().position(|entry| entry == name) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(PackageError::DependencyCycle(cycle));
+        }
+
+        let package = &self.packages[name];
+        stack.push(name.to_string());
+        for dependency in package.manifest.dependencies() {
+            self.visit(dependency.name(), stack, done, order)?;
+        }
+        stack.pop();
+
+        done.insert(name.to_string());
+        order.push(package);
+        Ok(())
+    }
+
+    /// The module cache shared by every package of the workspace
+    pub fn cache(&self) -> ModuleCache {
+        ModuleCache::new(self.root().root.join(CACHE_DIR))
+    }
+
+    /// A module system that finds the modules of `package` and of its
+    /// direct dependencies
+    pub fn module_system(&self, package: &ResolvedPackage) -> ModuleSystem {
+        let mut modules = ModuleSystem::new();
+        modules.set_cache(self.cache());
+        modules.add_search_path(package.source_dir());
+        for dependency in package.manifest.dependencies() {
+            modules.add_search_path(self.packages[dependency.name()].source_dir());
+        }
+        modules
+    }
+
+    /// Load and type check the modules of every dependency, dependencies
+    /// first, returning the package names in the order they were compiled.
+    /// Modules that passed type checking in an earlier build and have not
+    /// changed since are not checked again.
+    pub fn compile_dependencies(&self) -> Result<Vec<String>, PackageError> {
+        let mut compiled = Vec::new();
+        for package in self.build_order()? {
+            if package.name() == self.root {
+                continue;
+            }
+
+            let failed = |message: String| PackageError::Compile {
+                package: package.name().to_string(),
+                message,
+            };
+            // Dependencies are checked with their default features
+            let cfg = Cfg::new().with_features(package.manifest().resolve_features(&[], true)?);
+            let mut modules = self.module_system(package);
+            modules.set_cfg(cfg.clone());
+            for path in package.module_files()? {
+                let module = modules
+                    .load_module(&path)
+                    .map_err(|e| failed(e.to_string()))?;
+
+                let source = fs::read_to_string(&path)
+                    .map_err(|e| PackageError::Io(format!("{}: {}", path.display(), e)))?;
+                let cache = self.cache();
+                let mut entry = cache.get(&path, &source);
+                if let Some(entry) = &mut entry {
+                    entry.configured_for(&cfg);
+                }
+                if entry.as_ref().is_some_and(|entry| entry.type_checked) {
+                    continue;
+                }
+
+                let mut checker = TypeChecker::new();
+                checker
+                    .check_program(&module.program())
+                    .map_err(|e| failed(format!("{}: {}", path.display(), e)))?;
+
+                if let Some(mut entry) = entry {
+                    entry.type_checked = true;
+                    entry.warnings = checker.warnings().to_vec();
+                    // A cache that cannot be written only costs a recheck
+                    let _ = cache.insert(&entry);
+                }
+            }
+            compiled.push(package.name().to_string());
+        }
+        Ok(compiled)
+    }
+}
+
+struct Resolver {
+    home: PathBuf,
+    /// The previous lock file, if there was one
+    lock: PackageLock,
+    packages: BTreeMap<String, ResolvedPackage>,
+}
+
+impl Resolver {
+    /// Resolve the dependencies of `package` depth first. The first source
+    /// found for a name is used everywhere, and every later requirement on
+    /// that name must be satisfied by it.
+    fn resolve_dependencies(&mut self, package: &ResolvedPackage) -> Result<(), PackageError> {
+        for dependency in package.manifest.dependencies() {
+            if let Some(existing) = self.packages.get(dependency.name()) {
+                check_requirement(dependency, existing.version())?;
+                continue;
+            }
+
+            let resolved = self.fetch(&package.root, dependency)?;
+            if resolved.name() != dependency.name() {
+                return Err(PackageError::InvalidManifest(format!(
+                    "expected package '{}' in {}, found '{}'",
+                    dependency.name(),
+                    resolved.root.display(),
+                    resolved.name()
+                )));
+            }
+            check_requirement(dependency, resolved.version())?;
+            self.check_integrity(&resolved)?;
+
+            self.packages
+                .insert(resolved.name().to_string(), resolved.clone());
+            self.resolve_dependencies(&resolved)?;
+        }
+        Ok(())
+    }
+
+    fn fetch(&self, base: &Path, dependency: &Dependency) -> Result<ResolvedPackage, PackageError> {
+        let not_found = |location: &Path| PackageError::NotFound {
+            package: dependency.name().to_string(),
+            source: location.display().to_string(),
+        };
+
+        match dependency.source() {
+            DependencySource::Path(path) => {
+                let root = base.join(path);
+                let root = root.canonicalize().map_err(|_| not_found(&root))?;
+                if !root.join(MANIFEST_FILE).is_file() {
+                    return Err(not_found(&root));
+                }
+                ResolvedPackage::load(root, dependency.source().to_string())
+            }
+            DependencySource::Registry => {
+                let dir = self.home.join("registry").join(dependency.name());
+                let available: Vec<Version> = fs::read_dir(&dir)
+                    .map_err(|_| not_found(&dir))?
+                    .filter_map(|entry| entry.ok())
+                    .filter_map(|entry| Version::parse(&entry.file_name().to_string_lossy()).ok())
+                    .filter(|version| dependency.is_satisfied_by(version))
+                    .collect();
+
+                let locked = self
+                    .lock
+                    .get(dependency.name())
+                    .filter(|entry| entry.source() == DependencySource::Registry.to_string())
+                    .map(|entry| entry.version())
+                    .filter(|version| available.contains(version));
+                let version = locked
+                    .or_else(|| available.iter().max())
+                    .ok_or_else(|| not_found(&dir))?;
+
+                ResolvedPackage::load(
+                    dir.join(version.to_string()),
+                    DependencySource::Registry.to_string(),
+                )
+            }
+            DependencySource::Git { url, reference } => {
+                let checkout = self.checkout(dependency.name(), url, reference.as_deref())?;
+                let commit = git(dependency.name(), &checkout, &["rev-parse", "HEAD"])?;
+                ResolvedPackage::load(
+                    checkout,
+                    DependencySource::Git {
+                        url: url.clone(),
+                        reference: Some(commit),
+                    }
+                    .to_string(),
+                )
+            }
+        }
+    }
+
+    /// Clone `url` if needed and check out the locked commit, or else the
+    /// requested reference
+    fn checkout(
+        &self,
+        name: &str,
+        url: &str,
+        reference: Option<&str>,
+    ) -> Result<PathBuf, PackageError> {
+        let url_hash = hex::encode(Sha256::digest(url.as_bytes()));
+        let checkout = self
+            .home
+            .join("git")
+            .join(format!("{}-{}", name, &url_hash[..16]));
+
+        if !checkout.join(".git").exists() {
+            fs::create_dir_all(self.home.join("git"))
+                .map_err(|e| PackageError::Io(e.to_string()))?;
+            let destination = checkout.to_string_lossy().to_string();
+            git(name, &self.home, &["clone", "--quiet", url, &destination])?;
+        }
+
+        let locked_prefix = format!("git+{}#", url);
+        let locked = self
+            .lock
+            .get(name)
+            .and_then(|entry| entry.source().strip_prefix(&locked_prefix));
+        if let Some(target) = locked.or(reference) {
+            if git(name, &checkout, &["checkout", "--quiet", target]).is_err() {
+                git(name, &checkout, &["fetch", "--quiet", "--tags", "origin"])?;
+                git(name, &checkout, &["checkout", "--quiet", target])?;
+            }
+        }
+        Ok(checkout)
+    }
+
+    /// Registry and git packages must match the checksum in the lock file
+    /// when they resolve to the same version from the same source
+    fn check_integrity(&self, package: &ResolvedPackage) -> Result<(), PackageError> {
+        if package.source.starts_with("path+") {
+            return Ok(());
+        }
+        match self.lock.get(package.name()) {
+            Some(entry)
+                if entry.source() == package.source
+                    && entry.version() == package.version()
+                    && entry.integrity() != package.integrity =>
+            {
+                Err(PackageError::Fetch {
+                    package: package.name().to_string(),
+                    message: format!("checksum does not match {}", LOCK_FILE),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn check_requirement(dependency: &Dependency, version: &Version) -> Result<(), PackageError> {
+    match dependency.version() {
+        Some(required) if !dependency.is_satisfied_by(version) => {
+            Err(PackageError::UnsatisfiedDependency {
+                package: dependency.name().to_string(),
+                required: required.clone(),
+                available: version.clone(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Run git in `dir`, returning its trimmed output
+pub(super) fn git(package: &str, dir: &Path, args: &[&str]) -> Result<String, PackageError> {
+    let failed = |message: String| PackageError::Fetch {
+        package: package.to_string(),
+        message,
+    };
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| failed(e.to_string()))?;
+    if !output.status.success() {
+        return Err(failed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn default_home() -> PathBuf {
+    if let Some(home) = std::env::var_os("BEND_HOME") {
+        return PathBuf::from(home);
+    }
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(".bend")
+}
+
+/// All `.bend` files below `dir`, sorted by path
+fn module_files(dir: &Path) -> Result<Vec<PathBuf>, PackageError> {
+    let mut files = Vec::new();
+    if dir.is_dir() {
+        collect_module_files(dir, &mut files)
+            .map_err(|e| PackageError::Io(format!("{}: {}", dir.display(), e)))?;
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn collect_module_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_module_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "bend") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Checksum of a package's manifest and modules
+fn integrity_of(root: &Path) -> Result<String, PackageError> {
+    let mut hasher = Sha256::new();
+    let mut files = vec![root.join(MANIFEST_FILE)];
+    files.extend(module_files(&root.join("src"))?);
+    for file in files {
+        let relative = file.strip_prefix(root).unwrap_or(&file);
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(
+            fs::read(&file).map_err(|e| PackageError::Io(format!("{}: {}", file.display(), e)))?,
+        );
+    }
+    Ok(format!("sha256:{}", hex::encode(hasher.finalize())))
+}
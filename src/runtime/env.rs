@@ -1,6 +1,15 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 use thiserror::Error;
 
+use crate::analyzer::costs::CostTable;
+use crate::compiler::address::{Address, Hash};
+use crate::compiler::codegen::risc_v::Instruction;
+use crate::compiler::polkavm::host::ChainExtension;
+use crate::security::reentrancy_guard::ReentrancyGuard;
+use crate::stdlib::crypto::CryptoFunctions;
+use crate::testing::mocklib::MockStdlib;
+
 /// Error types for runtime environment
 #[derive(Debug, Error)]
 pub enum EnvError {
@@ -21,16 +30,25 @@ pub enum EnvError {
 
     #[error("Gas limit exceeded")]
     OutOfGas,
+
+    #[error("Insufficient balance: {available} available, {required} required")]
+    InsufficientBalance { available: u128, required: u128 },
+
+    #[error("Balance of 0x{0} would fall below the existential deposit")]
+    BelowExistentialDeposit(String),
 }
 
+/// Default minimum balance an account must hold to exist
+pub const EXISTENTIAL_DEPOSIT: u128 = 1;
+
 /// Context for contract execution
 #[derive(Debug, Clone)]
 pub struct ExecutionContext {
     /// Address of the contract
-    pub address: [u8; 32],
+    pub address: Address,
 
     /// Address of the caller
-    pub caller: [u8; 32],
+    pub caller: Address,
 
     /// Value sent with the call (in smallest units)
     pub value: u128,
@@ -44,6 +62,10 @@ pub struct ExecutionContext {
     /// Block timestamp
     pub block_timestamp: u64,
 
+    /// Entropy of the block: its parent hash, or the VRF output where the
+    /// runtime exposes one. Every call in the block sees the same value.
+    pub block_entropy: Hash,
+
     /// Gas limit for the execution
     pub gas_limit: u64,
 
@@ -61,14 +83,20 @@ pub struct ExecutionContext {
 
     /// Current storage deposit used
     pub storage_deposit_used: u128,
+
+    /// Total storage deposit charged, before refunds
+    pub storage_deposit_charged: u128,
+
+    /// Total storage deposit refunded by shrinking or deleting keys
+    pub storage_deposit_refunded: u128,
 }
 
 impl ExecutionContext {
     /// Create a new execution context
     #[allow(clippy::too_many_arguments)]
     pub fn new(
-        address: [u8; 32],
-        caller: [u8; 32],
+        address: Address,
+        caller: Address,
         value: u128,
         input: Vec<u8>,
         block_number: u64,
@@ -84,33 +112,50 @@ impl ExecutionContext {
             input,
             block_number,
             block_timestamp,
+            block_entropy: Hash::ZERO,
             gas_limit,
             gas_used: 0,
             proof_size_limit,
             proof_size_used: 0,
             storage_deposit_limit,
             storage_deposit_used: 0,
+            storage_deposit_charged: 0,
+            storage_deposit_refunded: 0,
         }
     }
 
     /// Create a new execution context with default values (for testing)
     pub fn new_default() -> Self {
         ExecutionContext {
-            address: [0u8; 32],
-            caller: [0u8; 32],
+            address: Address::ZERO,
+            caller: Address::ZERO,
             value: 0,
             input: Vec::new(),
             block_number: 0,
             block_timestamp: 0,
+            block_entropy: Hash::ZERO,
             gas_limit: 1000000,
             gas_used: 0,
             proof_size_limit: 1000000,
             proof_size_used: 0,
             storage_deposit_limit: 1000000,
             storage_deposit_used: 0,
+            storage_deposit_charged: 0,
+            storage_deposit_refunded: 0,
         }
     }
 
+    /// The value `random(subject)` returns in this block
+    pub fn random(&self, subject: &[u8]) -> Hash {
+        random_value(&self.block_entropy, subject)
+    }
+
+    /// Take the block entropy from `seed`, so that tests drawing random
+    /// values see the same ones on every run
+    pub fn seed_entropy(&mut self, seed: u64) {
+        self.block_entropy = seeded_entropy(seed);
+    }
+
     /// Check if there's enough gas for an operation
     pub fn check_gas(&self, gas: u64) -> Result<(), EnvError> {
         if self.gas_used + gas > self.gas_limit {
@@ -158,8 +203,35 @@ impl ExecutionContext {
     pub fn use_storage_deposit(&mut self, amount: u128) -> Result<(), EnvError> {
         self.check_storage_deposit(amount)?;
         self.storage_deposit_used += amount;
+        self.storage_deposit_charged += amount;
         Ok(())
     }
+
+    /// Return storage deposit released by shrinking or deleting a key
+    pub fn refund_storage_deposit(&mut self, amount: u128) {
+        self.storage_deposit_used = self.storage_deposit_used.saturating_sub(amount);
+        self.storage_deposit_refunded += amount;
+    }
+
+    /// Net storage deposit change: positive when the caller paid in,
+    /// negative when deletions released more deposit than was charged
+    pub fn net_storage_deposit(&self) -> i128 {
+        self.storage_deposit_charged as i128 - self.storage_deposit_refunded as i128
+    }
+}
+
+/// Pseudo-random value for `subject` in a block with `entropy`
+///
+/// Anyone who knows the block entropy can compute it, the block author
+/// first of all, so it must not decide anything worth manipulating.
+pub fn random_value(entropy: &Hash, subject: &[u8]) -> Hash {
+    let input = [entropy.as_bytes().as_slice(), subject].concat();
+    Hash::new(CryptoFunctions::blake2b_256(&input))
+}
+
+/// Block entropy derived from a test seed
+pub fn seeded_entropy(seed: u64) -> Hash {
+    Hash::new(CryptoFunctions::blake2b_256(&seed.to_le_bytes()))
 }
 
 /// Result of contract execution
@@ -203,7 +275,7 @@ pub enum ExecutionResult {
 }
 
 /// Event emitted by a contract
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Event {
     /// Event topics (indexed parameters)
     pub topics: Vec<Vec<u8>>,
@@ -211,14 +283,78 @@ pub struct Event {
     pub data: Vec<u8>,
 }
 
+/// An account: its balance and, for contracts, code and storage
+///
+/// The storage of the contract currently executing lives in
+/// [`Environment::storage`] rather than here.
+#[derive(Debug, Clone, Default)]
+pub struct Account {
+    /// Free balance (in smallest units)
+    pub balance: u128,
+    /// Hash of the code run when the account is called
+    pub code_hash: Option<Hash>,
+    /// Contract storage
+    pub storage: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+/// Host-side implementation of a chain extension: receives the argument
+/// words and returns the result word or an error message
+pub type ExtensionHandler = Arc<dyn Fn(&[u32]) -> Result<u32, String> + Send + Sync>;
+
+/// A chain extension together with its host-side implementation
+#[derive(Clone)]
+pub struct InstalledExtension {
+    /// Signature, id and gas cost
+    pub extension: ChainExtension,
+    /// Implementation
+    pub handler: ExtensionHandler,
+}
+
+/// A storage write to undo: owning contract, key and previous value
+type StorageUndo = (Address, Vec<u8>, Option<Vec<u8>>);
+
+/// State captured when a checkpoint is opened, plus the storage writes made
+/// since then
+#[derive(Debug, Clone, Default)]
+struct Checkpoint {
+    /// Keys written inside the checkpoint, tagged with the owning contract,
+    /// with their previous values
+    storage_undo: Vec<StorageUndo>,
+    /// Accounts created inside the checkpoint with their previous state
+    accounts_undo: Vec<(Address, Option<Account>)>,
+    /// Number of events emitted before the checkpoint
+    events_len: usize,
+    /// Storage deposit used, charged and refunded before the checkpoint
+    storage_deposit: (u128, u128, u128),
+}
+
 /// Runtime environment for contract execution
 pub struct Environment {
-    /// Contract storage
+    /// Storage of the contract at `context.address`
     pub storage: HashMap<Vec<u8>, Vec<u8>>,
     /// Emitted events
     pub events: Vec<Event>,
     /// Execution context
     pub context: ExecutionContext,
+    /// Accounts by address, reachable through cross-contract calls
+    pub accounts: HashMap<Address, Account>,
+    /// Minimum balance an account must keep; transfers that would leave an
+    /// account with less (but more than nothing) fail
+    pub existential_deposit: u128,
+    /// Uploaded code by code hash
+    pub code: HashMap<Hash, Arc<Vec<Instruction>>>,
+    /// Guard consulted when a call enters a contract; denies reentry by default
+    pub reentrancy_guard: ReentrancyGuard,
+    /// Canned responses for calls to addresses without code
+    pub mocks: MockStdlib,
+    /// Chain extensions by host call id
+    chain_extensions: HashMap<u32, InstalledExtension>,
+    /// Weights gas is charged from, as the gas profiler charges them
+    costs: Arc<CostTable>,
+    /// Number of contracts instantiated, used to derive fresh addresses
+    nonce: u64,
+    /// Open checkpoints, innermost last
+    checkpoints: Vec<Checkpoint>,
 }
 
 impl Environment {
@@ -228,13 +364,258 @@ impl Environment {
             storage: HashMap::new(),
             events: Vec::new(),
             context,
+            accounts: HashMap::new(),
+            existential_deposit: EXISTENTIAL_DEPOSIT,
+            code: HashMap::new(),
+            reentrancy_guard: ReentrancyGuard::new(),
+            mocks: MockStdlib::new(),
+            chain_extensions: HashMap::new(),
+            costs: Arc::new(CostTable::default()),
+            nonce: 0,
+            checkpoints: Vec::new(),
+        }
+    }
+
+    /// Charge gas from `costs` rather than the bundled cost table
+    pub fn with_costs(mut self, costs: CostTable) -> Self {
+        self.costs = Arc::new(costs);
+        self
+    }
+
+    /// The weights gas is charged from
+    pub fn costs(&self) -> &CostTable {
+        &self.costs
+    }
+
+    /// Charge the gas of an instruction class or host call
+    pub fn charge(&mut self, operation: &str) -> Result<(), EnvError> {
+        let gas = self.costs.gas(operation).unwrap_or_default();
+        self.context.use_gas(gas)
+    }
+
+    /// Charge the gas of a host call on `bytes` bytes of data, each
+    /// costing `per_byte`
+    pub fn charge_bytes(
+        &mut self,
+        operation: &str,
+        per_byte: &str,
+        bytes: usize,
+    ) -> Result<(), EnvError> {
+        let gas = self.costs.gas_with_bytes(operation, per_byte, bytes);
+        self.context.use_gas(gas)
+    }
+
+    /// Store code and return its hash
+    pub fn upload_code(&mut self, instructions: Vec<Instruction>) -> Hash {
+        let listing: Vec<String> = instructions.iter().map(|i| i.to_string()).collect();
+        let code_hash = Hash::new(CryptoFunctions::keccak256(listing.join("\n").as_bytes()));
+        self.register_code(code_hash, instructions);
+        code_hash
+    }
+
+    /// Store code under an explicit hash
+    pub fn register_code(&mut self, code_hash: Hash, instructions: Vec<Instruction>) {
+        self.code.insert(code_hash, Arc::new(instructions));
+    }
+
+    /// Create a contract account at `address` running `instructions`
+    pub fn deploy(&mut self, address: Address, instructions: Vec<Instruction>) {
+        let code_hash = self.upload_code(instructions);
+        self.journal_account(address);
+        let account = self.accounts.entry(address).or_default();
+        account.code_hash = Some(code_hash);
+        account.storage.clear();
+    }
+
+    /// Code run when `address` is called, if it is a contract
+    pub fn code_at(&self, address: &Address) -> Option<Arc<Vec<Instruction>>> {
+        let code_hash = self.accounts.get(address)?.code_hash?;
+        self.code.get(&code_hash).cloned()
+    }
+
+    /// Create an account for `code_hash` and return its address
+    ///
+    /// The address is derived from the deployer, the code hash and a nonce.
+    /// Only the first four bytes are kept so that contracts can refer to
+    /// accounts with word-sized values.
+    pub fn instantiate_account(&mut self, code_hash: Hash) -> Result<Address, EnvError> {
+        if !self.code.contains_key(&code_hash) {
+            return Err(EnvError::Call(format!(
+                "No code uploaded for hash 0x{}",
+                hex::encode(code_hash)
+            )));
+        }
+
+        let mut preimage = self.context.address.as_bytes().to_vec();
+        preimage.extend_from_slice(code_hash.as_bytes());
+        preimage.extend_from_slice(&self.nonce.to_le_bytes());
+        self.nonce += 1;
+
+        let mut address = [0u8; 32];
+        address[..4].copy_from_slice(&CryptoFunctions::keccak256(&preimage)[..4]);
+        let address = Address::new(address);
+        if address == self.context.address || self.accounts.contains_key(&address) {
+            return Err(EnvError::Call("Address already in use".to_string()));
+        }
+
+        self.journal_account(address);
+        self.accounts.insert(
+            address,
+            Account {
+                code_hash: Some(code_hash),
+                ..Default::default()
+            },
+        );
+        Ok(address)
+    }
+
+    /// Make `address` the executing contract, parking the current storage in
+    /// the accounts table. Returns the previous context.
+    pub fn enter_contract(&mut self, context: ExecutionContext) -> ExecutionContext {
+        if context.address != self.context.address {
+            let storage = std::mem::take(&mut self.storage);
+            self.accounts
+                .entry(self.context.address)
+                .or_default()
+                .storage = storage;
+            self.storage = self
+                .accounts
+                .get_mut(&context.address)
+                .map(|account| std::mem::take(&mut account.storage))
+                .unwrap_or_default();
+        }
+        std::mem::replace(&mut self.context, context)
+    }
+
+    /// Return to the caller after [`enter_contract`](Self::enter_contract),
+    /// carrying over the resources the callee consumed
+    pub fn exit_contract(&mut self, mut caller: ExecutionContext) {
+        caller.gas_used = self.context.gas_used;
+        caller.proof_size_used = self.context.proof_size_used;
+        caller.storage_deposit_used = self.context.storage_deposit_used;
+        caller.storage_deposit_charged = self.context.storage_deposit_charged;
+        caller.storage_deposit_refunded = self.context.storage_deposit_refunded;
+
+        let callee = std::mem::replace(&mut self.context, caller);
+        if callee.address != self.context.address {
+            // A terminated callee has no account left to park storage in
+            let storage = std::mem::take(&mut self.storage);
+            if let Some(account) = self.accounts.get_mut(&callee.address) {
+                account.storage = storage;
+            } else if !storage.is_empty() {
+                self.accounts.entry(callee.address).or_default().storage = storage;
+            }
+            self.storage = self
+                .accounts
+                .get_mut(&self.context.address)
+                .map(|account| std::mem::take(&mut account.storage))
+                .unwrap_or_default();
+        }
+    }
+
+    /// Open a (possibly nested) checkpoint. Storage writes, events and
+    /// storage deposit charged after this call are undone by
+    /// [`rollback`](Self::rollback); gas and proof size stay consumed.
+    pub fn checkpoint(&mut self) {
+        self.checkpoints.push(Checkpoint {
+            storage_undo: Vec::new(),
+            accounts_undo: Vec::new(),
+            events_len: self.events.len(),
+            storage_deposit: (
+                self.context.storage_deposit_used,
+                self.context.storage_deposit_charged,
+                self.context.storage_deposit_refunded,
+            ),
+        });
+    }
+
+    /// Close the innermost checkpoint, keeping its effects
+    pub fn commit(&mut self) -> Result<(), EnvError> {
+        let checkpoint = self
+            .checkpoints
+            .pop()
+            .ok_or_else(|| EnvError::Execution("No open checkpoint".to_string()))?;
+        if let Some(parent) = self.checkpoints.last_mut() {
+            parent.storage_undo.extend(checkpoint.storage_undo);
+            parent.accounts_undo.extend(checkpoint.accounts_undo);
+        }
+        Ok(())
+    }
+
+    /// Close the innermost checkpoint, discarding its storage writes, events
+    /// and storage deposit
+    pub fn rollback(&mut self) -> Result<(), EnvError> {
+        let checkpoint = self
+            .checkpoints
+            .pop()
+            .ok_or_else(|| EnvError::Execution("No open checkpoint".to_string()))?;
+        for (address, key, previous) in checkpoint.storage_undo.into_iter().rev() {
+            let storage = if address == self.context.address {
+                &mut self.storage
+            } else {
+                &mut self.accounts.entry(address).or_default().storage
+            };
+            match previous {
+                Some(value) => storage.insert(key, value),
+                None => storage.remove(&key),
+            };
+        }
+        // Storage was restored key by key above, so restored accounts keep
+        // their current storage
+        for (address, previous) in checkpoint.accounts_undo.into_iter().rev() {
+            match previous {
+                Some(mut account) => {
+                    if let Some(current) = self.accounts.get_mut(&address) {
+                        account.storage = std::mem::take(&mut current.storage);
+                    }
+                    self.accounts.insert(address, account);
+                }
+                None => {
+                    self.accounts.remove(&address);
+                }
+            }
+        }
+        self.events.truncate(checkpoint.events_len);
+        (
+            self.context.storage_deposit_used,
+            self.context.storage_deposit_charged,
+            self.context.storage_deposit_refunded,
+        ) = checkpoint.storage_deposit;
+        Ok(())
+    }
+
+    /// Number of open checkpoints
+    pub fn checkpoint_depth(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    /// Storage deposit held by one entry: one unit per key and value byte
+    fn entry_deposit(key: &[u8], value: &[u8]) -> u128 {
+        (key.len() + value.len()) as u128
+    }
+
+    /// Record the current value of `key` in the innermost checkpoint
+    fn journal_write(&mut self, key: &[u8]) {
+        if let Some(checkpoint) = self.checkpoints.last_mut() {
+            let previous = self.storage.get(key).cloned();
+            checkpoint
+                .storage_undo
+                .push((self.context.address, key.to_vec(), previous));
+        }
+    }
+
+    /// Record the current state of an account before it is replaced
+    fn journal_account(&mut self, address: Address) {
+        if let Some(checkpoint) = self.checkpoints.last_mut() {
+            let previous = self.accounts.get(&address).cloned();
+            checkpoint.accounts_undo.push((address, previous));
         }
     }
 
     /// Read from storage
     pub fn storage_get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, EnvError> {
         // Use gas for the operation
-        self.context.use_gas(200)?;
+        self.charge("storage_read")?;
 
         // Use proof size for the operation
         // Accessing storage requires proving the key, so we add the key size to proof size
@@ -247,24 +628,29 @@ impl Environment {
     /// Write to storage
     pub fn storage_set(&mut self, key: &[u8], value: &[u8]) -> Result<(), EnvError> {
         // Use gas for the operation
-        self.context.use_gas(200 + value.len() as u64)?;
+        self.charge_bytes("storage_write", "storage_write_byte", value.len())?;
 
         // Use proof size for the operation
         self.context
             .use_proof_size((key.len() + value.len()) as u64)?;
 
         // Calculate storage deposit
-        // If we're replacing an existing value, we only pay for the difference
-        let old_size = self.storage.get(key).map_or(0, |v| v.len());
-        let new_size = value.len();
+        // A new key pays for its key and value bytes; replacing a value pays
+        // for growth and refunds shrinkage
+        let old_size = self
+            .storage
+            .get(key)
+            .map_or(0, |v| Self::entry_deposit(key, v));
+        let new_size = Self::entry_deposit(key, value);
 
         if new_size > old_size {
-            // More storage is used, pay for the difference
-            let deposit_amount = (new_size - old_size) as u128;
-            self.context.use_storage_deposit(deposit_amount)?;
+            self.context.use_storage_deposit(new_size - old_size)?;
+        } else {
+            self.context.refund_storage_deposit(old_size - new_size);
         }
 
         // Store the value
+        self.journal_write(key);
         self.storage.insert(key.to_vec(), value.to_vec());
 
         Ok(())
@@ -273,26 +659,173 @@ impl Environment {
     /// Delete from storage
     pub fn storage_clear(&mut self, key: &[u8]) -> Result<(), EnvError> {
         // Use gas for the operation
-        self.context.use_gas(200)?;
+        self.charge("storage_delete")?;
 
         // Use proof size for the operation
         self.context.use_proof_size(key.len() as u64)?;
 
-        // Refund storage deposit (in a real implementation this would go back to the caller)
+        // Refund the entry's storage deposit (in a real implementation this would go back to the caller)
         if let Some(old_value) = self.storage.get(key) {
-            // This is a refund, so we don't check limits
-            self.context.storage_deposit_used = self
-                .context
-                .storage_deposit_used
-                .saturating_sub(old_value.len() as u128);
+            let refund = Self::entry_deposit(key, old_value);
+            self.context.refund_storage_deposit(refund);
         }
 
         // Remove the value
+        self.journal_write(key);
         self.storage.remove(key);
 
         Ok(())
     }
 
+    /// Free balance of `address`
+    pub fn balance_of(&self, address: &Address) -> u128 {
+        self.accounts
+            .get(address)
+            .map_or(0, |account| account.balance)
+    }
+
+    /// Set the free balance of `address`, e.g. to fund accounts in tests
+    pub fn set_balance(&mut self, address: Address, balance: u128) {
+        self.journal_account(address);
+        self.accounts.entry(address).or_default().balance = balance;
+    }
+
+    /// Move `value` from `from` to `to`
+    ///
+    /// The sender must stay at or above the existential deposit and the
+    /// recipient must reach it, so transfers never reap or create dust
+    /// accounts. Use [`terminate`](Self::terminate) to empty an account.
+    pub fn transfer(&mut self, from: Address, to: Address, value: u128) -> Result<(), EnvError> {
+        if value == 0 || from == to {
+            return Ok(());
+        }
+
+        let available = self.balance_of(&from);
+        let remaining = available
+            .checked_sub(value)
+            .ok_or(EnvError::InsufficientBalance {
+                available,
+                required: value,
+            })?;
+        if remaining < self.existential_deposit {
+            return Err(EnvError::BelowExistentialDeposit(hex::encode(from)));
+        }
+        let received = self.balance_of(&to).saturating_add(value);
+        if received < self.existential_deposit {
+            return Err(EnvError::BelowExistentialDeposit(hex::encode(to)));
+        }
+
+        self.set_balance(from, remaining);
+        self.set_balance(to, received);
+        Ok(())
+    }
+
+    /// Remove the executing contract, sending its whole balance to
+    /// `beneficiary` and refunding the deposit held by its storage
+    ///
+    /// Returns the amount sent. A beneficiary receiving a non-zero amount
+    /// must end up at or above the existential deposit.
+    pub fn terminate(&mut self, beneficiary: Address) -> Result<u128, EnvError> {
+        let address = self.context.address;
+        if beneficiary == address {
+            return Err(EnvError::InvalidInput(
+                "A contract cannot terminate to itself".to_string(),
+            ));
+        }
+        self.charge("terminate")?;
+
+        let value = self.balance_of(&address);
+        let received = self.balance_of(&beneficiary).saturating_add(value);
+        if value > 0 && received < self.existential_deposit {
+            return Err(EnvError::BelowExistentialDeposit(hex::encode(beneficiary)));
+        }
+
+        let keys: Vec<Vec<u8>> = self.storage.keys().cloned().collect();
+        for key in keys {
+            if let Some(value) = self.storage.get(&key) {
+                let refund = Self::entry_deposit(&key, value);
+                self.context.refund_storage_deposit(refund);
+            }
+            self.journal_write(&key);
+            self.storage.remove(&key);
+        }
+
+        if value > 0 {
+            self.set_balance(beneficiary, received);
+        }
+        self.journal_account(address);
+        self.accounts.remove(&address);
+        Ok(value)
+    }
+
+    /// Replace the code of the executing contract with the code uploaded as
+    /// `code_hash`, keeping its storage and balance
+    ///
+    /// The running call carries on with the old code; calls made after it
+    /// returns run the new one.
+    pub fn set_code_hash(&mut self, code_hash: Hash) -> Result<(), EnvError> {
+        if !self.code.contains_key(&code_hash) {
+            return Err(EnvError::Call(format!(
+                "No code uploaded for hash 0x{}",
+                hex::encode(code_hash)
+            )));
+        }
+
+        let address = self.context.address;
+        self.journal_account(address);
+        self.accounts.entry(address).or_default().code_hash = Some(code_hash);
+        Ok(())
+    }
+
+    /// Route calls to `extension` (as registered in a
+    /// [`ChainExtensionRegistry`](crate::compiler::polkavm::host::ChainExtensionRegistry))
+    /// to `handler`
+    pub fn install_chain_extension<F>(&mut self, extension: &ChainExtension, handler: F)
+    where
+        F: Fn(&[u32]) -> Result<u32, String> + Send + Sync + 'static,
+    {
+        self.chain_extensions.insert(
+            extension.id,
+            InstalledExtension {
+                extension: extension.clone(),
+                handler: Arc::new(handler),
+            },
+        );
+    }
+
+    /// Installed chain extension with host call id `id`
+    pub fn chain_extension(&self, id: u32) -> Option<&ChainExtension> {
+        self.chain_extensions
+            .get(&id)
+            .map(|installed| &installed.extension)
+    }
+
+    /// Call a chain extension, charging its gas cost
+    pub fn call_chain_extension(&mut self, id: u32, args: &[u32]) -> Result<u32, EnvError> {
+        let installed = self
+            .chain_extensions
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| EnvError::Call(format!("No chain extension with id {}", id)))?;
+        let extension = &installed.extension;
+        if args.len() != extension.params.len() {
+            return Err(EnvError::InvalidInput(format!(
+                "Chain extension {} takes {} arguments, got {}",
+                extension.name,
+                extension.params.len(),
+                args.len()
+            )));
+        }
+
+        self.context.use_gas(extension.gas)?;
+        (installed.handler)(args).map_err(|err| {
+            EnvError::Call(format!(
+                "Chain extension {} failed: {}",
+                extension.name, err
+            ))
+        })
+    }
+
     /// Emit an event
     pub fn emit_event(&mut self, topics: Vec<Vec<u8>>, data: Vec<u8>) -> Result<(), EnvError> {
         // Check event limitations
@@ -310,7 +843,7 @@ impl Environment {
         }
 
         // Use gas for the operation
-        self.context.use_gas(100 + total_size as u64)?;
+        self.charge_bytes("event_emit", "event_emit_byte", total_size)?;
 
         // Use proof size for the operation
         self.context.use_proof_size(total_size as u64)?;
@@ -324,7 +857,7 @@ impl Environment {
     /// Call another contract
     pub fn call(
         &mut self,
-        _address: [u8; 32],
+        address: Address,
         value: u128,
         input: Vec<u8>,
         gas_limit: u64,
@@ -335,7 +868,7 @@ impl Environment {
         // For this example, we'll just simulate it
 
         // Use gas for the operation
-        self.context.use_gas(100 + input.len() as u64)?;
+        self.charge_bytes("external_call", "external_call_byte", input.len())?;
 
         // Use proof size for the operation
         self.context.use_proof_size(input.len() as u64)?;
@@ -345,11 +878,12 @@ impl Environment {
             return Err(EnvError::OutOfGas);
         }
 
-        // Check value transfer
-        if value > 0 {
-            return Err(EnvError::Call(
-                "Value transfer not supported in this example".to_string(),
-            ));
+        // The callee runs inside its own checkpoint so a failing sub-call
+        // cannot leave partial state behind, including the value transfer
+        self.checkpoint();
+        if let Err(err) = self.transfer(self.context.address, address, value) {
+            self.rollback()?;
+            return Err(err);
         }
 
         // Simulate the call - in a real implementation, this would use PolkaVM to execute the contract
@@ -386,6 +920,11 @@ impl Environment {
             }
         }
 
+        match &result {
+            ExecutionResult::Success { .. } => self.commit()?,
+            ExecutionResult::Failure { .. } | ExecutionResult::Revert { .. } => self.rollback()?,
+        }
+
         Ok(result)
     }
 
@@ -414,3 +953,196 @@ impl Environment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback_discards_storage_and_events() {
+        let mut env = Environment::new(ExecutionContext::new_default());
+        env.storage_set(b"key", b"old").unwrap();
+
+        env.checkpoint();
+        env.storage_set(b"key", b"newer").unwrap();
+        env.storage_set(b"other", b"1").unwrap();
+        env.emit_event(vec![vec![1]], vec![2]).unwrap();
+        let gas_used = env.context.gas_used;
+        env.rollback().unwrap();
+
+        assert_eq!(env.storage.get(b"key".as_slice()), Some(&b"old".to_vec()));
+        assert!(!env.storage.contains_key(b"other".as_slice()));
+        assert!(env.events.is_empty());
+        assert_eq!(env.context.storage_deposit_used, 6);
+        assert_eq!(env.context.net_storage_deposit(), 6);
+        assert_eq!(env.context.gas_used, gas_used);
+    }
+
+    #[test]
+    fn test_committed_inner_checkpoint_rolls_back_with_outer() {
+        let mut env = Environment::new(ExecutionContext::new_default());
+
+        env.checkpoint();
+        env.checkpoint();
+        env.storage_set(b"key", b"value").unwrap();
+        env.commit().unwrap();
+        assert!(env.storage.contains_key(b"key".as_slice()));
+
+        env.rollback().unwrap();
+        assert!(env.storage.is_empty());
+        assert!(env.commit().is_err());
+    }
+
+    #[test]
+    fn test_storage_deposit_refunded_on_shrink_and_clear() {
+        let mut env = Environment::new(ExecutionContext::new_default());
+
+        env.storage_set(b"key", b"value").unwrap();
+        assert_eq!(env.context.storage_deposit_used, 8);
+
+        env.storage_set(b"key", b"v").unwrap();
+        assert_eq!(env.context.storage_deposit_used, 4);
+        assert_eq!(env.context.storage_deposit_refunded, 4);
+
+        env.storage_clear(b"key").unwrap();
+        assert_eq!(env.context.storage_deposit_used, 0);
+        assert_eq!(env.context.storage_deposit_charged, 8);
+        assert_eq!(env.context.net_storage_deposit(), 0);
+    }
+
+    #[test]
+    fn test_storage_deposit_limit_rejects_new_keys() {
+        let mut context = ExecutionContext::new_default();
+        context.storage_deposit_limit = 4;
+        let mut env = Environment::new(context);
+
+        assert!(env.storage_set(b"key", b"value").is_err());
+        assert!(env.storage.is_empty());
+        assert_eq!(env.context.net_storage_deposit(), 0);
+    }
+
+    fn account(id: u8) -> Address {
+        let mut address = [0u8; 32];
+        address[0] = id;
+        Address::new(address)
+    }
+
+    #[test]
+    fn test_transfer_respects_existential_deposit() {
+        let mut env = Environment::new(ExecutionContext::new_default());
+        env.existential_deposit = 10;
+        env.set_balance(account(1), 100);
+
+        env.transfer(account(1), account(2), 40).unwrap();
+        assert_eq!(env.balance_of(&account(1)), 60);
+        assert_eq!(env.balance_of(&account(2)), 40);
+
+        assert!(matches!(
+            env.transfer(account(1), account(2), 61),
+            Err(EnvError::InsufficientBalance { .. })
+        ));
+        assert!(matches!(
+            env.transfer(account(1), account(2), 55),
+            Err(EnvError::BelowExistentialDeposit(_))
+        ));
+        assert!(matches!(
+            env.transfer(account(1), account(3), 5),
+            Err(EnvError::BelowExistentialDeposit(_))
+        ));
+        assert_eq!(env.balance_of(&account(1)), 60);
+        assert_eq!(env.balance_of(&account(3)), 0);
+    }
+
+    #[test]
+    fn test_rollback_restores_balances() {
+        let mut env = Environment::new(ExecutionContext::new_default());
+        env.set_balance(account(1), 100);
+
+        env.checkpoint();
+        env.transfer(account(1), account(2), 30).unwrap();
+        env.rollback().unwrap();
+
+        assert_eq!(env.balance_of(&account(1)), 100);
+        assert!(!env.accounts.contains_key(&account(2)));
+    }
+
+    #[test]
+    fn test_terminate_sends_balance_and_removes_account() {
+        let mut context = ExecutionContext::new_default();
+        context.address = account(1);
+        let mut env = Environment::new(context);
+        env.deploy(account(1), Vec::new());
+        env.set_balance(account(1), 50);
+        env.storage_set(b"key", b"value").unwrap();
+
+        assert!(env.terminate(account(1)).is_err());
+        assert_eq!(env.terminate(account(2)).unwrap(), 50);
+        assert_eq!(env.balance_of(&account(2)), 50);
+        assert!(env.storage.is_empty());
+        assert!(env.code_at(&account(1)).is_none());
+        assert_eq!(env.context.net_storage_deposit(), 0);
+    }
+
+    #[test]
+    fn test_random_follows_block_entropy() {
+        let mut context = ExecutionContext::new_default();
+        let unseeded = context.random(b"dice");
+        assert_eq!(context.random(b"dice"), unseeded);
+        assert_ne!(context.random(b"coin"), unseeded);
+
+        context.seed_entropy(7);
+        let seeded = context.random(b"dice");
+        assert_ne!(seeded, unseeded);
+
+        let mut rerun = ExecutionContext::new_default();
+        rerun.seed_entropy(7);
+        assert_eq!(rerun.random(b"dice"), seeded);
+    }
+
+    #[test]
+    fn test_set_code_hash_keeps_storage_and_balance() {
+        let mut context = ExecutionContext::new_default();
+        context.address = account(1);
+        let mut env = Environment::new(context);
+        env.deploy(account(1), Vec::new());
+        env.set_balance(account(1), 50);
+        env.storage_set(b"key", b"value").unwrap();
+        let upgraded = env.upload_code(vec![Instruction::Ecall]);
+
+        assert!(env.set_code_hash(Hash::new([7; 32])).is_err());
+        env.checkpoint();
+        env.set_code_hash(upgraded).unwrap();
+        assert_eq!(env.code_at(&account(1)).unwrap().len(), 1);
+        assert_eq!(env.balance_of(&account(1)), 50);
+        assert_eq!(env.storage_get(b"key").unwrap(), Some(b"value".to_vec()));
+
+        env.rollback().unwrap();
+        assert!(env.code_at(&account(1)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_chain_extension_charges_gas() {
+        use crate::compiler::polkavm::host::{ChainExtensionRegistry, ExtensionType};
+
+        let mut registry = ChainExtensionRegistry::new();
+        let id = registry
+            .register(
+                ChainExtension::new("oracle_price")
+                    .with_param("asset", ExtensionType::U24)
+                    .with_gas(500),
+            )
+            .unwrap();
+
+        let mut env = Environment::new(ExecutionContext::new_default());
+        assert!(env.call_chain_extension(id, &[1]).is_err());
+
+        env.install_chain_extension(registry.get("oracle_price").unwrap(), |args| match args {
+            [1] => Ok(42),
+            _ => Err("unknown asset".to_string()),
+        });
+        assert_eq!(env.call_chain_extension(id, &[1]).unwrap(), 42);
+        assert_eq!(env.context.gas_used, 500);
+        assert!(env.call_chain_extension(id, &[2]).is_err());
+        assert!(env.call_chain_extension(id, &[]).is_err());
+    }
+}
@@ -0,0 +1,716 @@
+//! Reference interpreter for generated EVM bytecode
+//!
+//! Runs the bytecode of the experimental EVM backend against the runtime
+//! `Environment`, so contracts compiled with `--target evm` can be tested
+//! without an Ethereum node. It implements the instructions the backend
+//! emits, with the EVM's 256-bit words, stack and memory; storage slots are
+//! 32-byte keys of the environment and logs are its events. Gas follows the
+//! other interpreters rather than the Ethereum schedule.
+
+use thiserror::Error;
+
+use crate::runtime::env::{EnvError, Environment, ExecutionContext, ExecutionResult};
+use crate::stdlib::crypto::CryptoFunctions;
+
+/// Most values the stack holds
+pub const MAX_STACK: usize = 1024;
+
+/// Most bytes of memory a call can use
+pub const MAX_MEMORY: usize = 1 << 20;
+
+/// Errors caused by the environment or by deployment rather than by
+/// contract execution
+#[derive(Debug, Error)]
+pub enum EvmError {
+    #[error("Deployment failed: {0}")]
+    Deploy(String),
+
+    #[error("Environment error: {0}")]
+    Environment(#[from] EnvError),
+}
+
+/// Why execution stopped
+enum Halt {
+    Return(Vec<u8>),
+    Revert(Vec<u8>),
+    Trap(String),
+}
+
+/// Interpreter for bytecode generated by the EVM backend
+pub struct EvmInterpreter {
+    /// Runtime environment (storage, events, gas)
+    environment: Environment,
+
+    stack: Vec<U256>,
+
+    memory: Vec<u8>,
+
+    /// Number of instructions executed by the last run
+    steps: u64,
+}
+
+impl EvmInterpreter {
+    pub fn new(context: ExecutionContext) -> Self {
+        Self::with_environment(Environment::new(context))
+    }
+
+    /// Create a new interpreter around an existing environment
+    pub fn with_environment(environment: Environment) -> Self {
+        EvmInterpreter {
+            environment,
+            stack: Vec::new(),
+            memory: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    /// Get the runtime environment
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    /// Get a mutable reference to the runtime environment
+    pub fn environment_mut(&mut self) -> &mut Environment {
+        &mut self.environment
+    }
+
+    /// Consume the interpreter and return its environment
+    pub fn into_environment(self) -> Environment {
+        self.environment
+    }
+
+    /// Number of instructions executed by the last run
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Run the deploy code of a contract, returning its runtime code
+    pub fn deploy(&mut self, init_code: &[u8]) -> Result<Vec<u8>, EvmError> {
+        match self.execute(init_code)? {
+            ExecutionResult::Success { data, .. } => Ok(data),
+            ExecutionResult::Revert { data, .. } => Err(EvmError::Deploy(format!(
+                "reverted with 0x{}",
+                hex::encode(data)
+            ))),
+            ExecutionResult::Failure { reason, .. } => Err(EvmError::Deploy(reason)),
+        }
+    }
+
+    /// Execute bytecode with the input and value of the context
+    ///
+    /// Like on the other targets, every execution is a transaction whose
+    /// storage writes and events are only kept if it returns.
+    pub fn execute(&mut self, code: &[u8]) -> Result<ExecutionResult, EvmError> {
+        self.stack.clear();
+        self.memory.clear();
+        self.steps = 0;
+
+        self.environment.checkpoint();
+        let halt = self.run(code);
+        match &halt {
+            Halt::Return(_) => self.environment.commit()?,
+            _ => self.environment.rollback()?,
+        }
+
+        let context = &self.environment.context;
+        Ok(match halt {
+            Halt::Return(data) => ExecutionResult::Success {
+                data,
+                gas_used: context.gas_used,
+                proof_size_used: context.proof_size_used,
+                storage_deposit_used: context.storage_deposit_used,
+            },
+            Halt::Revert(data) => ExecutionResult::Revert {
+                data,
+                gas_used: context.gas_used,
+                proof_size_used: context.proof_size_used,
+                storage_deposit_used: context.storage_deposit_used,
+            },
+            Halt::Trap(reason) => ExecutionResult::Failure {
+                reason,
+                gas_used: context.gas_used,
+                proof_size_used: context.proof_size_used,
+                storage_deposit_used: context.storage_deposit_used,
+            },
+        })
+    }
+
+    /// Positions of the `JUMPDEST`s of the code, outside push data
+    fn jump_destinations(code: &[u8]) -> Vec<bool> {
+        let mut valid = vec![false; code.len()];
+        let mut pc = 0;
+        while pc < code.len() {
+            match code[pc] {
+                0x5B => valid[pc] = true,
+                opcode @ 0x60..=0x7F => pc += (opcode - 0x5F) as usize,
+                _ => {}
+            }
+            pc += 1;
+        }
+        valid
+    }
+
+    fn run(&mut self, code: &[u8]) -> Halt {
+        match self.step_all(code) {
+            Ok(()) => Halt::Return(Vec::new()),
+            Err(halt) => halt,
+        }
+    }
+
+    fn step_all(&mut self, code: &[u8]) -> Result<(), Halt> {
+        let destinations = Self::jump_destinations(code);
+        let env_error = |err: EnvError| Halt::Trap(err.to_string());
+        // Every instruction costs a step of the cost table
+        let step_gas = self.environment.costs().gas("step").unwrap_or_default();
+        let mut pc = 0;
+
+        while pc < code.len() {
+            self.steps += 1;
+            if self.environment.context.use_gas(step_gas).is_err() {
+                return Err(Halt::Trap(EnvError::OutOfGas.to_string()));
+            }
+
+            let opcode = code[pc];
+            let mut next = pc + 1;
+            match opcode {
+                0x00 => return Ok(()),
+                0x01 => self.binary(|a, b| a.add(b))?,
+                0x02 => self.binary(|a, b| a.mul(b))?,
+                0x03 => self.binary(|a, b| a.sub(b))?,
+                0x04 => self.binary(|a, b| a.div_rem(b).0)?,
+                0x05 => self.binary(|a, b| a.sdiv(b))?,
+                0x06 => self.binary(|a, b| a.div_rem(b).1)?,
+                0x07 => self.binary(|a, b| a.smod(b))?,
+                0x0A => self.binary(|a, b| a.exp(b))?,
+                0x0B => self.binary(|a, b| b.signextend(a))?,
+                0x10 => self.binary(|a, b| U256::from_bool(a < b))?,
+                0x11 => self.binary(|a, b| U256::from_bool(a > b))?,
+                0x12 => self.binary(|a, b| U256::from_bool(a.signed_lt(b)))?,
+                0x13 => self.binary(|a, b| U256::from_bool(b.signed_lt(a)))?,
+                0x14 => self.binary(|a, b| U256::from_bool(a == b))?,
+                0x15 => {
+                    let a = self.pop()?;
+                    self.push(U256::from_bool(a == U256::ZERO))?;
+                }
+                0x16 => self.binary(|a, b| a.map(b, |x, y| x & y))?,
+                0x17 => self.binary(|a, b| a.map(b, |x, y| x | y))?,
+                0x18 => self.binary(|a, b| a.map(b, |x, y| x ^ y))?,
+                0x19 => {
+                    let a = self.pop()?;
+                    self.push(a.map(a, |x, _| !x))?;
+                }
+                0x1A => self.binary(|i, x| match i.to_usize() {
+                    Some(i) if i < 32 => U256::from(x.to_bytes()[i] as u64),
+                    _ => U256::ZERO,
+                })?,
+                0x1B => self.binary(|shift, x| x.shl(shift.to_usize().unwrap_or(256)))?,
+                0x1C => self.binary(|shift, x| x.shr(shift.to_usize().unwrap_or(256)))?,
+                0x1D => self.binary(|shift, x| x.sar(shift.to_usize().unwrap_or(256)))?,
+                0x20 => {
+                    let (offset, len) = (self.pop()?, self.pop()?);
+                    let data = self.read_memory(offset, len)?;
+                    self.push(U256::from_bytes(&CryptoFunctions::keccak256(&data)))?;
+                }
+                0x30 => {
+                    let address = *self.environment.context.address.as_bytes();
+                    self.push(U256::from_bytes(&address))?;
+                }
+                0x33 => {
+                    let caller = *self.environment.context.caller.as_bytes();
+                    self.push(U256::from_bytes(&caller))?;
+                }
+                0x34 => self.push(U256::from_u128(self.environment.context.value))?,
+                0x35 => {
+                    let offset = self.pop()?;
+                    let mut bytes = [0; 32];
+                    if let Some(offset) = offset.to_usize() {
+                        let input = &self.environment.context.input;
+                        for (i, byte) in bytes.iter_mut().enumerate() {
+                            *byte = input.get(offset.saturating_add(i)).copied().unwrap_or(0);
+                        }
+                    }
+                    self.push(U256::from_bytes(&bytes))?;
+                }
+                0x36 => self.push(U256::from(self.environment.context.input.len() as u64))?,
+                0x37 | 0x39 => {
+                    let (destination, offset, len) = (self.pop()?, self.pop()?, self.pop()?);
+                    let source = if opcode == 0x37 {
+                        self.environment.context.input.clone()
+                    } else {
+                        code.to_vec()
+                    };
+                    let range = self.memory_range(destination, len)?;
+                    let start = offset.to_usize().unwrap_or(usize::MAX);
+                    for (i, byte) in self.memory[range].iter_mut().enumerate() {
+                        *byte = source.get(start.saturating_add(i)).copied().unwrap_or(0);
+                    }
+                }
+                0x50 => {
+                    self.pop()?;
+                }
+                0x51 => {
+                    let offset = self.pop()?;
+                    let bytes = self.read_memory(offset, U256::from(32))?;
+                    self.push(U256::from_bytes(&bytes.try_into().unwrap()))?;
+                }
+                0x52 => {
+                    let (offset, value) = (self.pop()?, self.pop()?);
+                    let range = self.memory_range(offset, U256::from(32))?;
+                    self.memory[range].copy_from_slice(&value.to_bytes());
+                }
+                0x53 => {
+                    let (offset, value) = (self.pop()?, self.pop()?);
+                    let range = self.memory_range(offset, U256::from(1))?;
+                    self.memory[range][0] = value.to_bytes()[31];
+                }
+                0x54 => {
+                    let key = self.pop()?.to_bytes();
+                    let value = self.environment.storage_get(&key).map_err(env_error)?;
+                    let mut bytes = [0; 32];
+                    if let Some(value) = value {
+                        let len = value.len().min(32);
+                        bytes[32 - len..].copy_from_slice(&value[value.len() - len..]);
+                    }
+                    self.push(U256::from_bytes(&bytes))?;
+                }
+                0x55 => {
+                    let (key, value) = (self.pop()?.to_bytes(), self.pop()?);
+                    if value == U256::ZERO {
+                        self.environment.storage_clear(&key).map_err(env_error)?;
+                    } else {
+                        self.environment
+                            .storage_set(&key, &value.to_bytes())
+                            .map_err(env_error)?;
+                    }
+                }
+                0x56 => next = self.jump_target(&destinations)?,
+                0x57 => {
+                    let target = self.jump_target(&destinations)?;
+                    if self.pop()? != U256::ZERO {
+                        next = target;
+                    }
+                }
+                0x5A => {
+                    let context = &self.environment.context;
+                    let left = context.gas_limit.saturating_sub(context.gas_used);
+                    self.push(U256::from(left))?;
+                }
+                0x5B => {}
+                0x60..=0x7F => {
+                    let len = (opcode - 0x5F) as usize;
+                    let mut bytes = [0; 32];
+                    for (i, byte) in bytes[32 - len..].iter_mut().enumerate() {
+                        *byte = code.get(pc + 1 + i).copied().unwrap_or(0);
+                    }
+                    self.push(U256::from_bytes(&bytes))?;
+                    next = pc + 1 + len;
+                }
+                0x80..=0x8F => {
+                    let depth = (opcode - 0x7F) as usize;
+                    let value = self.peek(depth)?;
+                    self.push(value)?;
+                }
+                0x90..=0x9F => {
+                    let depth = (opcode - 0x8F) as usize;
+                    self.peek(depth + 1)?;
+                    let top = self.stack.len() - 1;
+                    self.stack.swap(top, top - depth);
+                }
+                0xA0..=0xA4 => {
+                    let (offset, len) = (self.pop()?, self.pop()?);
+                    let mut topics = Vec::new();
+                    for _ in 0..opcode - 0xA0 {
+                        topics.push(self.pop()?.to_bytes().to_vec());
+                    }
+                    let data = self.read_memory(offset, len)?;
+                    self.environment
+                        .emit_event(topics, data)
+                        .map_err(env_error)?;
+                }
+                0xF3 | 0xFD => {
+                    let (offset, len) = (self.pop()?, self.pop()?);
+                    let data = self.read_memory(offset, len)?;
+                    return Err(if opcode == 0xF3 {
+                        Halt::Return(data)
+                    } else {
+                        Halt::Revert(data)
+                    });
+                }
+                0xFE => return Err(Halt::Trap("Invalid instruction executed".to_string())),
+                _ => {
+                    return Err(Halt::Trap(format!(
+                        "Unsupported opcode 0x{:02x} at {}",
+                        opcode, pc
+                    )))
+                }
+            }
+            pc = next;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, value: U256) -> Result<(), Halt> {
+        if self.stack.len() == MAX_STACK {
+            return Err(Halt::Trap("Stack overflow".to_string()));
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<U256, Halt> {
+        self.stack
+            .pop()
+            .ok_or_else(|| Halt::Trap("Stack underflow".to_string()))
+    }
+
+    /// The value `depth` slots down, 1 being the top
+    fn peek(&self, depth: usize) -> Result<U256, Halt> {
+        self.stack
+            .len()
+            .checked_sub(depth)
+            .map(|index| self.stack[index])
+            .ok_or_else(|| Halt::Trap("Stack underflow".to_string()))
+    }
+
+    fn binary(&mut self, operation: impl Fn(U256, U256) -> U256) -> Result<(), Halt> {
+        let (a, b) = (self.pop()?, self.pop()?);
+        self.push(operation(a, b))
+    }
+
+    /// Pop a jump target, checking it is a `JUMPDEST`
+    fn jump_target(&mut self, destinations: &[bool]) -> Result<usize, Halt> {
+        let target = self.pop()?;
+        target
+            .to_usize()
+            .filter(|&target| destinations.get(target) == Some(&true))
+            .ok_or_else(|| Halt::Trap(format!("Invalid jump destination {}", target)))
+    }
+
+    /// Expand memory to cover `len` bytes at `offset`, returning their range
+    fn memory_range(&mut self, offset: U256, len: U256) -> Result<std::ops::Range<usize>, Halt> {
+        let len = len.to_usize().filter(|&len| len <= MAX_MEMORY);
+        let Some(len) = len else {
+            return Err(Halt::Trap("Memory limit exceeded".to_string()));
+        };
+        if len == 0 {
+            return Ok(0..0);
+        }
+        let end = offset
+            .to_usize()
+            .and_then(|offset| offset.checked_add(len).map(|end| (offset, end)))
+            .filter(|&(_, end)| end <= MAX_MEMORY);
+        let Some((offset, end)) = end else {
+            return Err(Halt::Trap("Memory limit exceeded".to_string()));
+        };
+        if end > self.memory.len() {
+            self.memory.resize(end.div_ceil(32) * 32, 0);
+        }
+        Ok(offset..end)
+    }
+
+    fn read_memory(&mut self, offset: U256, len: U256) -> Result<Vec<u8>, Halt> {
+        let range = self.memory_range(offset, len)?;
+        Ok(self.memory[range].to_vec())
+    }
+}
+
+/// A 256-bit word, least significant limb first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct U256([u64; 4]);
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        U256([value, 0, 0, 0])
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl std::fmt::Display for U256 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0x{}", hex::encode(self.to_bytes()))
+    }
+}
+
+impl U256 {
+    const ZERO: U256 = U256([0; 4]);
+    const ONE: U256 = U256([1, 0, 0, 0]);
+
+    fn from_u128(value: u128) -> Self {
+        U256([value as u64, (value >> 64) as u64, 0, 0])
+    }
+
+    fn from_bool(value: bool) -> Self {
+        U256::from(value as u64)
+    }
+
+    fn from_bytes(bytes: &[u8; 32]) -> Self {
+        let mut limbs = [0; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let start = 32 - 8 * (i + 1);
+            *limb = u64::from_be_bytes(bytes[start..start + 8].try_into().unwrap());
+        }
+        U256(limbs)
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            let start = 32 - 8 * (i + 1);
+            bytes[start..start + 8].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    fn to_usize(self) -> Option<usize> {
+        if self.0[1..].iter().any(|&limb| limb != 0) {
+            return None;
+        }
+        usize::try_from(self.0[0]).ok()
+    }
+
+    fn is_negative(self) -> bool {
+        self.0[3] >> 63 == 1
+    }
+
+    fn map(self, other: U256, f: impl Fn(u64, u64) -> u64) -> U256 {
+        let mut limbs = [0; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            *limb = f(self.0[i], other.0[i]);
+        }
+        U256(limbs)
+    }
+
+    fn add(self, other: U256) -> U256 {
+        let mut limbs = [0; 4];
+        let mut carry = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (sum, first) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, second) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = first || second;
+        }
+        U256(limbs)
+    }
+
+    fn negate(self) -> U256 {
+        self.map(self, |x, _| !x).add(U256::ONE)
+    }
+
+    fn sub(self, other: U256) -> U256 {
+        self.add(other.negate())
+    }
+
+    fn mul(self, other: U256) -> U256 {
+        let mut limbs = [0u64; 4];
+        for i in 0..4 {
+            let mut carry = 0u128;
+            for j in 0..4 - i {
+                let product = self.0[i] as u128 * other.0[j] as u128 + limbs[i + j] as u128 + carry;
+                limbs[i + j] = product as u64;
+                carry = product >> 64;
+            }
+        }
+        U256(limbs)
+    }
+
+    /// Quotient and remainder, both zero when dividing by zero
+    fn div_rem(self, divisor: U256) -> (U256, U256) {
+        if divisor == U256::ZERO {
+            return (U256::ZERO, U256::ZERO);
+        }
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for bit in (0..256).rev() {
+            remainder = remainder.shl(1);
+            remainder.0[0] |= (self.0[bit / 64] >> (bit % 64)) & 1;
+            if remainder >= divisor {
+                remainder = remainder.sub(divisor);
+                quotient.0[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        (quotient, remainder)
+    }
+
+    fn abs(self) -> U256 {
+        if self.is_negative() {
+            self.negate()
+        } else {
+            self
+        }
+    }
+
+    fn sdiv(self, divisor: U256) -> U256 {
+        let quotient = self.abs().div_rem(divisor.abs()).0;
+        if self.is_negative() != divisor.is_negative() {
+            quotient.negate()
+        } else {
+            quotient
+        }
+    }
+
+    /// Remainder with the sign of the dividend
+    fn smod(self, divisor: U256) -> U256 {
+        let remainder = self.abs().div_rem(divisor.abs()).1;
+        if self.is_negative() {
+            remainder.negate()
+        } else {
+            remainder
+        }
+    }
+
+    fn exp(self, exponent: U256) -> U256 {
+        let mut result = U256::ONE;
+        let mut base = self;
+        for bit in 0..256 {
+            if (exponent.0[bit / 64] >> (bit % 64)) & 1 == 1 {
+                result = result.mul(base);
+            }
+            base = base.mul(base);
+        }
+        result
+    }
+
+    fn signed_lt(self, other: U256) -> bool {
+        match (self.is_negative(), other.is_negative()) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => self < other,
+        }
+    }
+
+    fn shl(self, shift: usize) -> U256 {
+        if shift >= 256 {
+            return U256::ZERO;
+        }
+        let (limbs, bits) = (shift / 64, shift % 64);
+        let mut result = [0u64; 4];
+        for (i, limb) in result.iter_mut().enumerate().skip(limbs) {
+            *limb = self.0[i - limbs] << bits;
+            if bits > 0 && i > limbs {
+                *limb |= self.0[i - limbs - 1] >> (64 - bits);
+            }
+        }
+        U256(result)
+    }
+
+    fn shr(self, shift: usize) -> U256 {
+        if shift >= 256 {
+            return U256::ZERO;
+        }
+        let (limbs, bits) = (shift / 64, shift % 64);
+        let mut result = [0u64; 4];
+        for (i, limb) in result.iter_mut().enumerate().take(4 - limbs) {
+            *limb = self.0[i + limbs] >> bits;
+            if bits > 0 && i + limbs < 3 {
+                *limb |= self.0[i + limbs + 1] << (64 - bits);
+            }
+        }
+        U256(result)
+    }
+
+    fn sar(self, shift: usize) -> U256 {
+        if !self.is_negative() {
+            return self.shr(shift);
+        }
+        if shift >= 256 {
+            return U256([u64::MAX; 4]);
+        }
+        let ones = U256([u64::MAX; 4]);
+        self.shr(shift).map(ones.shl(256 - shift), |x, y| x | y)
+    }
+
+    /// Extend the sign of the low `bytes + 1` bytes
+    fn signextend(self, bytes: U256) -> U256 {
+        match bytes.to_usize() {
+            Some(bytes) if bytes < 31 => {
+                let bits = 8 * (bytes + 1);
+                let mask = U256::ONE.shl(bits).sub(U256::ONE);
+                let low = self.map(mask, |x, y| x & y);
+                if (self.0[(bits - 1) / 64] >> ((bits - 1) % 64)) & 1 == 1 {
+                    low.map(mask, |x, y| x | !y)
+                } else {
+                    low
+                }
+            }
+            _ => self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(code: &[u8]) -> ExecutionResult {
+        EvmInterpreter::new(ExecutionContext::new_default())
+            .execute(code)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_word_arithmetic() {
+        let minus_one = U256::ZERO.sub(U256::ONE);
+        assert_eq!(minus_one, U256([u64::MAX; 4]));
+        assert_eq!(minus_one.add(U256::from(2)), U256::ONE);
+        assert_eq!(
+            U256::from(u64::MAX).mul(U256::from(u64::MAX)),
+            U256([1, u64::MAX - 1, 0, 0])
+        );
+        assert_eq!(
+            U256::from(100).div_rem(U256::from(7)),
+            (U256::from(14), U256::from(2))
+        );
+        assert_eq!(
+            U256::from(7).negate().sdiv(U256::from(2)),
+            U256::from(3).negate()
+        );
+        assert_eq!(
+            U256::from(7).negate().smod(U256::from(2)),
+            U256::ONE.negate()
+        );
+        assert!(minus_one.signed_lt(U256::ZERO));
+        assert_eq!(U256::from(2).exp(U256::from(100)), U256::ONE.shl(100));
+        assert_eq!(U256::ONE.shl(200).shr(199), U256::from(2));
+        assert_eq!(minus_one.sar(100), minus_one);
+        assert_eq!(U256::from(0xFF).signextend(U256::ZERO), minus_one);
+        assert_eq!(U256::from(0x17F).signextend(U256::ZERO), U256::from(0x7F));
+    }
+
+    #[test]
+    fn test_return_and_revert() {
+        // mstore(0, 42) return(0, 32)
+        let code = [0x60, 42, 0x60, 0, 0x52, 0x60, 32, 0x60, 0, 0xF3];
+        let ExecutionResult::Success { data, .. } = run(&code) else {
+            panic!("expected success");
+        };
+        assert_eq!(U256::from_bytes(&data.try_into().unwrap()), U256::from(42));
+
+        // sstore(0, 1) revert(0, 0)
+        let mut interpreter = EvmInterpreter::new(ExecutionContext::new_default());
+        let code = [0x60, 1, 0x60, 0, 0x55, 0x60, 0, 0x60, 0, 0xFD];
+        let result = interpreter.execute(&code).unwrap();
+        assert!(matches!(result, ExecutionResult::Revert { .. }));
+        assert!(interpreter.environment().storage.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_jumps_trap() {
+        let code = [0x60, 0x03, 0x56, 0x5B];
+        assert!(matches!(run(&code), ExecutionResult::Success { .. }));
+
+        // Into push data
+        let code = [0x60, 0x04, 0x56, 0x61, 0x5B, 0x00];
+        assert!(matches!(run(&code), ExecutionResult::Failure { .. }));
+    }
+}
@@ -0,0 +1,3495 @@
+//! Reference interpreter for generated RISC-V code
+//!
+//! Executes the `Instruction` stream produced by the code generator directly,
+//! without assembling it. Host calls (`ecall`) are dispatched through the
+//! runtime `Environment`, so storage, gas and events follow the same rules as
+//! the rest of the runtime. Code addresses are instruction indices; data
+//! addresses index into a flat, little-endian memory.
+//!
+//! Registers are 32 bits wide, or 64 bits for RV64 code (see
+//! [`Interpreter::with_isa`]). Either way pointers and host call arguments
+//! are the low 32 bits of a register.
+
+use std::collections::HashMap;
+use thiserror::Error;
+
+use crate::compiler::address::{Address, Hash};
+use crate::compiler::codegen::encoder::Isa;
+use crate::compiler::codegen::risc_v::{
+    export_label, Instruction, Register, RiscVCodegen, DISPATCH_LABEL,
+};
+use crate::compiler::polkavm::blob::{DEPLOY_EXPORT, ENTRY_POINT};
+use crate::compiler::polkavm::host::HostFunction;
+use crate::runtime::env::{EnvError, Environment, ExecutionContext, ExecutionResult};
+use crate::security::SecurityError;
+use crate::stdlib::crypto::CryptoFunctions;
+
+/// Default size of interpreter memory (64 KiB)
+pub const DEFAULT_MEMORY_SIZE: usize = 64 * 1024;
+
+/// Alignment of heap allocations
+const HEAP_ALIGN: u32 = 8;
+
+/// Return address used for the entry frame; jumping to it halts execution
+const EXIT_ADDRESS: u32 = u32::MAX;
+
+/// Status returned in `a0` by the call host functions
+const CALL_SUCCESS: u32 = 0;
+const CALL_REVERTED: u32 = 1;
+const CALL_FAILED: u32 = 2;
+
+/// Direction of a shift instruction
+#[derive(Debug, Clone, Copy)]
+enum Shift {
+    Left,
+    Right,
+    RightArith,
+}
+
+/// How a called contract's code is run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CallKind {
+    /// Run the callee's code on the callee's storage
+    Call,
+    /// Run the callee's code on the caller's storage, keeping the caller's
+    /// address, caller and value
+    DelegateCall,
+    /// Run a new contract's `deploy` export, or its `call` entry point when
+    /// it has none, like a call
+    Deploy,
+}
+
+/// Errors caused by malformed programs rather than by contract execution
+#[derive(Debug, Error)]
+pub enum InterpreterError {
+    #[error("Undefined label: {0}")]
+    UndefinedLabel(String),
+
+    #[error("Duplicate label: {0}")]
+    DuplicateLabel(String),
+
+    #[error("Environment error: {0}")]
+    Environment(#[from] EnvError),
+}
+
+/// Why execution stopped inside the interpreter loop
+enum Halt {
+    Return(Vec<u8>),
+    Revert(Vec<u8>),
+    Trap(String),
+}
+
+/// Interpreter for code generator output
+pub struct Interpreter {
+    /// Runtime environment (storage, events, gas)
+    environment: Environment,
+
+    /// Register file, indexed by register number. On 32-bit ISAs the upper
+    /// half is the sign extension of the lower one
+    registers: [u64; 32],
+
+    /// Instruction set the code is run as
+    isa: Isa,
+
+    /// Flat data memory
+    memory: Vec<u8>,
+
+    /// Start of unallocated heap memory, which grows up from the end of the
+    /// call input towards the stack
+    heap_break: u32,
+
+    /// Number of instructions executed by the last run
+    steps: u64,
+}
+
+impl Interpreter {
+    /// Create a new interpreter with default memory size
+    pub fn new(context: ExecutionContext) -> Self {
+        Self::with_environment(Environment::new(context))
+    }
+
+    /// Create a new interpreter around an existing environment
+    pub fn with_environment(environment: Environment) -> Self {
+        Interpreter {
+            environment,
+            registers: [0; 32],
+            isa: Isa::default(),
+            memory: vec![0; DEFAULT_MEMORY_SIZE],
+            heap_break: 0,
+            steps: 0,
+        }
+    }
+
+    /// Set the size of data memory
+    pub fn with_memory_size(mut self, size: usize) -> Self {
+        self.memory = vec![0; size];
+        self
+    }
+
+    /// Run code generated for `isa`, with 64-bit registers for RV64
+    pub fn with_isa(mut self, isa: Isa) -> Self {
+        self.isa = isa;
+        self
+    }
+
+    /// Get the runtime environment
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    /// Get a mutable reference to the runtime environment
+    pub fn environment_mut(&mut self) -> &mut Environment {
+        &mut self.environment
+    }
+
+    /// Consume the interpreter and return its environment
+    pub fn into_environment(self) -> Environment {
+        self.environment
+    }
+
+    /// Read the low 32 bits of a register
+    pub fn register(&self, register: Register) -> u32 {
+        self.registers[register as usize] as u32
+    }
+
+    /// Read a whole register, sign-extended on 32-bit ISAs
+    fn value(&self, register: Register) -> u64 {
+        self.registers[register as usize]
+    }
+
+    /// Number of instructions executed by the last run
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Execute a program
+    ///
+    /// Execution starts at the function exported as `call`, then at the
+    /// message dispatcher if one was generated, then at the `main` label if
+    /// present, otherwise at the first instruction. The call input is copied to address 0 and passed in
+    /// `a0`/`a1`. If the program returns without calling the `Return` host
+    /// function, the value of `a0` is returned as 4 little-endian bytes.
+    pub fn execute(
+        &mut self,
+        instructions: &[Instruction],
+    ) -> Result<ExecutionResult, InterpreterError> {
+        self.execute_export(instructions, ENTRY_POINT)
+    }
+
+    /// Execute a program from one of its exports, like a PolkaVM host
+    ///
+    /// `call` starts where [`Self::execute`] does unless a function is
+    /// exported as `call`; any other export enters its `#[export]` function.
+    pub fn execute_export(
+        &mut self,
+        instructions: &[Instruction],
+        export: &str,
+    ) -> Result<ExecutionResult, InterpreterError> {
+        let labels = Self::resolve_labels(instructions)?;
+        let entry = export_label(export);
+        let pc = match labels.get(entry.as_str()) {
+            Some(&pc) => pc,
+            None if export == ENTRY_POINT => labels
+                .get(DISPATCH_LABEL)
+                .or_else(|| labels.get("main"))
+                .copied()
+                .unwrap_or(0),
+            None => return Err(InterpreterError::UndefinedLabel(entry)),
+        };
+        self.execute_from(instructions, &labels, pc)
+    }
+
+    /// Execute a program from the start of the function `name`, such as a
+    /// `#[test]` function, which neither the dispatcher nor an export
+    /// enters
+    pub fn execute_function(
+        &mut self,
+        instructions: &[Instruction],
+        name: &str,
+    ) -> Result<ExecutionResult, InterpreterError> {
+        let labels = Self::resolve_labels(instructions)?;
+        let entry = RiscVCodegen::function_label(name);
+        let pc = *labels
+            .get(entry.as_str())
+            .ok_or(InterpreterError::UndefinedLabel(entry))?;
+        self.execute_from(instructions, &labels, pc)
+    }
+
+    /// Run one transaction from `pc`
+    fn execute_from(
+        &mut self,
+        instructions: &[Instruction],
+        labels: &HashMap<&str, u32>,
+        pc: u32,
+    ) -> Result<ExecutionResult, InterpreterError> {
+        self.reset()?;
+
+        // Every run is a transaction: storage writes and events are only kept
+        // if the program returns normally
+        self.environment.checkpoint();
+        let halt = self.run(instructions, labels, pc);
+        match &halt {
+            Ok(Halt::Return(_)) => self.environment.commit()?,
+            _ => self.environment.rollback()?,
+        }
+        let halt = halt?;
+
+        let context = &self.environment.context;
+        Ok(match halt {
+            Halt::Return(data) => ExecutionResult::Success {
+                data,
+                gas_used: context.gas_used,
+                proof_size_used: context.proof_size_used,
+                storage_deposit_used: context.storage_deposit_used,
+            },
+            Halt::Revert(data) => ExecutionResult::Revert {
+                data,
+                gas_used: context.gas_used,
+                proof_size_used: context.proof_size_used,
+                storage_deposit_used: context.storage_deposit_used,
+            },
+            Halt::Trap(reason) => ExecutionResult::Failure {
+                reason,
+                gas_used: context.gas_used,
+                proof_size_used: context.proof_size_used,
+                storage_deposit_used: context.storage_deposit_used,
+            },
+        })
+    }
+
+    /// Step through instructions from `pc` until the program halts
+    fn run(
+        &mut self,
+        instructions: &[Instruction],
+        labels: &HashMap<&str, u32>,
+        mut pc: u32,
+    ) -> Result<Halt, InterpreterError> {
+        // Looked up once, as the cost table does not change during a run
+        let step_gas = self.environment.costs().gas("step").unwrap_or_default();
+        Ok(loop {
+            if pc == EXIT_ADDRESS || pc as usize >= instructions.len() {
+                break Halt::Return(self.register(Register::X10).to_le_bytes().to_vec());
+            }
+
+            let instruction = &instructions[pc as usize];
+            if !matches!(instruction, Instruction::Label(_) | Instruction::Comment(_)) {
+                self.steps += 1;
+                if self.environment.context.use_gas(step_gas).is_err() {
+                    break Halt::Trap(EnvError::OutOfGas.to_string());
+                }
+            }
+
+            match self.step(instruction, pc, labels)? {
+                Ok(next) => pc = next,
+                Err(halt) => break halt,
+            }
+        })
+    }
+
+    /// Map label names to instruction indices
+    fn resolve_labels(
+        instructions: &[Instruction],
+    ) -> Result<HashMap<&str, u32>, InterpreterError> {
+        let mut labels = HashMap::new();
+        for (index, instruction) in instructions.iter().enumerate() {
+            if let Instruction::Label(name) = instruction {
+                if labels.insert(name.as_str(), index as u32).is_some() {
+                    return Err(InterpreterError::DuplicateLabel(name.clone()));
+                }
+            }
+        }
+        Ok(labels)
+    }
+
+    /// Reset registers and memory and load the call input
+    fn reset(&mut self) -> Result<(), InterpreterError> {
+        self.registers = [0; 32];
+        self.memory.iter_mut().for_each(|byte| *byte = 0);
+        self.steps = 0;
+
+        let input = self.environment.context.input.clone();
+        if input.len() > self.memory.len() {
+            return Err(EnvError::InvalidInput("Input does not fit in memory".to_string()).into());
+        }
+        self.memory[..input.len()].copy_from_slice(&input);
+        // Address 0 is never allocated, so it can signal a failed allocation
+        self.heap_break = align_up(input.len().max(1) as u32, HEAP_ALIGN);
+
+        self.set(Register::X1, EXIT_ADDRESS);
+        self.set(Register::X2, (self.memory.len() as u32) & !0xF);
+        self.set(Register::X10, 0);
+        self.set(Register::X11, input.len() as u32);
+        Ok(())
+    }
+
+    /// Set a register to a 32-bit value, sign-extending it like RV64 does
+    /// for word results
+    fn set(&mut self, register: Register, value: u32) {
+        self.set_value(register, value as i32 as u64);
+    }
+
+    /// Set a register to a register-sized result, which 32-bit ISAs
+    /// truncate
+    fn set_value(&mut self, register: Register, value: u64) {
+        let value = if self.isa.is_64_bit() {
+            value
+        } else {
+            value as i32 as u64
+        };
+        if register != Register::X0 {
+            self.registers[register as usize] = value;
+        }
+    }
+
+    fn label(labels: &HashMap<&str, u32>, name: &str) -> Result<u32, InterpreterError> {
+        labels
+            .get(name)
+            .copied()
+            .ok_or_else(|| InterpreterError::UndefinedLabel(name.to_string()))
+    }
+
+    /// Shift `rs1` by `amount` into `rd`, on the whole register if `wide`
+    /// and on its low word otherwise
+    fn shift(&mut self, shift: Shift, rd: Register, rs1: Register, amount: u32, wide: bool) {
+        if wide {
+            let (value, amount) = (self.value(rs1), amount & 0x3F);
+            let result = match shift {
+                Shift::Left => value << amount,
+                Shift::Right => value >> amount,
+                Shift::RightArith => ((value as i64) >> amount) as u64,
+            };
+            self.set_value(rd, result)
+        } else {
+            let (value, amount) = (self.register(rs1), amount & 0x1F);
+            let result = match shift {
+                Shift::Left => value << amount,
+                Shift::Right => value >> amount,
+                Shift::RightArith => ((value as i32) >> amount) as u32,
+            };
+            self.set(rd, result)
+        }
+    }
+
+    /// Execute one instruction, returning the next program counter or a halt
+    fn step(
+        &mut self,
+        instruction: &Instruction,
+        pc: u32,
+        labels: &HashMap<&str, u32>,
+    ) -> Result<Result<u32, Halt>, InterpreterError> {
+        let next = pc + 1;
+        // `r` reads the low word of a register and `x` the whole register
+        let r = |interp: &Self, reg: &Register| interp.register(*reg);
+        let x = |interp: &Self, reg: &Register| interp.value(*reg);
+        let wide = self.isa.is_64_bit();
+
+        if instruction.is_rv64_only() && !wide {
+            return Ok(Err(Halt::Trap(format!(
+                "Illegal instruction on {}: {}",
+                self.isa,
+                instruction.to_string().trim()
+            ))));
+        }
+
+        match instruction {
+            Instruction::Load(rd, rs1, offset) => {
+                let address = r(self, rs1).wrapping_add(*offset as u32);
+                match self.load_word(address) {
+                    Ok(value) => self.set(*rd, value),
+                    Err(halt) => return Ok(Err(halt)),
+                }
+            }
+            Instruction::Store(rs2, rs1, offset) => {
+                let address = r(self, rs1).wrapping_add(*offset as u32);
+                let value = r(self, rs2);
+                if let Err(halt) = self.store_word(address, value) {
+                    return Ok(Err(halt));
+                }
+            }
+            Instruction::LoadByteU(rd, rs1, offset) => {
+                let address = r(self, rs1).wrapping_add(*offset as u32);
+                match self.check_range(address, 1) {
+                    Ok(range) => self.set(*rd, self.memory[range.start] as u32),
+                    Err(halt) => return Ok(Err(halt)),
+                }
+            }
+            Instruction::StoreByte(rs2, rs1, offset) => {
+                let address = r(self, rs1).wrapping_add(*offset as u32);
+                match self.check_range(address, 1) {
+                    Ok(range) => self.memory[range.start] = r(self, rs2) as u8,
+                    Err(halt) => return Ok(Err(halt)),
+                }
+            }
+            Instruction::Load64(rd, rs1, offset) => {
+                let address = r(self, rs1).wrapping_add(*offset as u32);
+                match self.read_array::<8>(address) {
+                    Ok(bytes) => self.set_value(*rd, u64::from_le_bytes(bytes)),
+                    Err(halt) => return Ok(Err(halt)),
+                }
+            }
+            Instruction::Store64(rs2, rs1, offset) => {
+                let address = r(self, rs1).wrapping_add(*offset as u32);
+                let value = x(self, rs2);
+                if let Err(halt) = self.write_bytes(address, &value.to_le_bytes()) {
+                    return Ok(Err(halt));
+                }
+            }
+            Instruction::Add(rd, rs1, rs2) => {
+                self.set_value(*rd, x(self, rs1).wrapping_add(x(self, rs2)))
+            }
+            Instruction::AddImm(rd, rs1, imm) => {
+                self.set_value(*rd, x(self, rs1).wrapping_add(*imm as i64 as u64))
+            }
+            Instruction::Sub(rd, rs1, rs2) => {
+                self.set_value(*rd, x(self, rs1).wrapping_sub(x(self, rs2)))
+            }
+            Instruction::Mul(rd, rs1, rs2) => {
+                self.set_value(*rd, x(self, rs1).wrapping_mul(x(self, rs2)))
+            }
+            Instruction::AddW(rd, rs1, rs2) => {
+                self.set(*rd, r(self, rs1).wrapping_add(r(self, rs2)))
+            }
+            Instruction::AddImmW(rd, rs1, imm) => {
+                self.set(*rd, r(self, rs1).wrapping_add(*imm as u32))
+            }
+            Instruction::SubW(rd, rs1, rs2) => {
+                self.set(*rd, r(self, rs1).wrapping_sub(r(self, rs2)))
+            }
+            Instruction::MulW(rd, rs1, rs2) => {
+                self.set(*rd, r(self, rs1).wrapping_mul(r(self, rs2)))
+            }
+            Instruction::MulHigh(rd, rs1, rs2) if wide => {
+                let product = x(self, rs1) as i64 as i128 * x(self, rs2) as i64 as i128;
+                self.set_value(*rd, (product >> 64) as u64)
+            }
+            Instruction::MulHigh(rd, rs1, rs2) => {
+                let product = r(self, rs1) as i32 as i64 * r(self, rs2) as i32 as i64;
+                self.set(*rd, (product >> 32) as u32)
+            }
+            Instruction::MulHighU(rd, rs1, rs2) if wide => {
+                let product = x(self, rs1) as u128 * x(self, rs2) as u128;
+                self.set_value(*rd, (product >> 64) as u64)
+            }
+            Instruction::MulHighU(rd, rs1, rs2) => {
+                let product = r(self, rs1) as u64 * r(self, rs2) as u64;
+                self.set(*rd, (product >> 32) as u32)
+            }
+            Instruction::Div(rd, rs1, rs2) if wide => {
+                let (a, b) = (x(self, rs1) as i64, x(self, rs2) as i64);
+                let value = if b == 0 { -1 } else { a.wrapping_div(b) };
+                self.set_value(*rd, value as u64)
+            }
+            Instruction::Div(rd, rs1, rs2) | Instruction::DivW(rd, rs1, rs2) => {
+                let (a, b) = (r(self, rs1) as i32, r(self, rs2) as i32);
+                // RISC-V semantics: division by zero yields -1, overflow wraps
+                let value = if b == 0 { -1 } else { a.wrapping_div(b) };
+                self.set(*rd, value as u32)
+            }
+            Instruction::Rem(rd, rs1, rs2) if wide => {
+                let (a, b) = (x(self, rs1) as i64, x(self, rs2) as i64);
+                let value = if b == 0 { a } else { a.wrapping_rem(b) };
+                self.set_value(*rd, value as u64)
+            }
+            Instruction::Rem(rd, rs1, rs2) | Instruction::RemW(rd, rs1, rs2) => {
+                let (a, b) = (r(self, rs1) as i32, r(self, rs2) as i32);
+                let value = if b == 0 { a } else { a.wrapping_rem(b) };
+                self.set(*rd, value as u32)
+            }
+            Instruction::And(rd, rs1, rs2) => self.set_value(*rd, x(self, rs1) & x(self, rs2)),
+            Instruction::Or(rd, rs1, rs2) => self.set_value(*rd, x(self, rs1) | x(self, rs2)),
+            Instruction::Xor(rd, rs1, rs2) => self.set_value(*rd, x(self, rs1) ^ x(self, rs2)),
+            Instruction::AndImm(rd, rs1, imm) => {
+                self.set_value(*rd, x(self, rs1) & *imm as i64 as u64)
+            }
+            Instruction::OrImm(rd, rs1, imm) => {
+                self.set_value(*rd, x(self, rs1) | *imm as i64 as u64)
+            }
+            Instruction::XorImm(rd, rs1, imm) => {
+                self.set_value(*rd, x(self, rs1) ^ *imm as i64 as u64)
+            }
+            Instruction::ShiftLeft(rd, rs1, rs2) => {
+                self.shift(Shift::Left, *rd, *rs1, r(self, rs2), wide)
+            }
+            Instruction::ShiftRight(rd, rs1, rs2) => {
+                self.shift(Shift::Right, *rd, *rs1, r(self, rs2), wide)
+            }
+            Instruction::ShiftRightArith(rd, rs1, rs2) => {
+                self.shift(Shift::RightArith, *rd, *rs1, r(self, rs2), wide)
+            }
+            Instruction::ShiftLeftImm(rd, rs1, imm) => {
+                self.shift(Shift::Left, *rd, *rs1, *imm as u32, wide)
+            }
+            Instruction::ShiftRightImm(rd, rs1, imm) => {
+                self.shift(Shift::Right, *rd, *rs1, *imm as u32, wide)
+            }
+            Instruction::ShiftRightArithImm(rd, rs1, imm) => {
+                self.shift(Shift::RightArith, *rd, *rs1, *imm as u32, wide)
+            }
+            Instruction::ShiftLeftW(rd, rs1, rs2) => {
+                self.shift(Shift::Left, *rd, *rs1, r(self, rs2), false)
+            }
+            Instruction::ShiftRightW(rd, rs1, rs2) => {
+                self.shift(Shift::Right, *rd, *rs1, r(self, rs2), false)
+            }
+            Instruction::ShiftRightArithW(rd, rs1, rs2) => {
+                self.shift(Shift::RightArith, *rd, *rs1, r(self, rs2), false)
+            }
+            Instruction::ShiftLeftImmW(rd, rs1, imm) => {
+                self.shift(Shift::Left, *rd, *rs1, *imm as u32, false)
+            }
+            Instruction::ShiftRightImmW(rd, rs1, imm) => {
+                self.shift(Shift::Right, *rd, *rs1, *imm as u32, false)
+            }
+            Instruction::ShiftRightArithImmW(rd, rs1, imm) => {
+                self.shift(Shift::RightArith, *rd, *rs1, *imm as u32, false)
+            }
+            // Registers hold sign-extended words on 32-bit ISAs, so comparing
+            // whole registers gives the 32-bit results too
+            Instruction::SetLessThan(rd, rs1, rs2) => {
+                let value = (x(self, rs1) as i64) < (x(self, rs2) as i64);
+                self.set(*rd, value as u32)
+            }
+            Instruction::SetLessThanU(rd, rs1, rs2) => {
+                self.set(*rd, (x(self, rs1) < x(self, rs2)) as u32)
+            }
+            Instruction::SetLessThanImm(rd, rs1, imm) => {
+                self.set(*rd, ((x(self, rs1) as i64) < *imm as i64) as u32)
+            }
+            Instruction::SetLessThanImmU(rd, rs1, imm) => {
+                self.set(*rd, (x(self, rs1) < *imm as i64 as u64) as u32)
+            }
+            Instruction::BranchEq(rs1, rs2, label) => {
+                if x(self, rs1) == x(self, rs2) {
+                    return Ok(Ok(Self::label(labels, label)?));
+                }
+            }
+            Instruction::BranchNe(rs1, rs2, label) => {
+                if x(self, rs1) != x(self, rs2) {
+                    return Ok(Ok(Self::label(labels, label)?));
+                }
+            }
+            Instruction::BranchLt(rs1, rs2, label) => {
+                if (x(self, rs1) as i64) < (x(self, rs2) as i64) {
+                    return Ok(Ok(Self::label(labels, label)?));
+                }
+            }
+            Instruction::BranchLe(rs1, rs2, label) => {
+                if (x(self, rs1) as i64) <= (x(self, rs2) as i64) {
+                    return Ok(Ok(Self::label(labels, label)?));
+                }
+            }
+            Instruction::BranchGe(rs1, rs2, label) => {
+                if (x(self, rs1) as i64) >= (x(self, rs2) as i64) {
+                    return Ok(Ok(Self::label(labels, label)?));
+                }
+            }
+            Instruction::BranchLtU(rs1, rs2, label) => {
+                if x(self, rs1) < x(self, rs2) {
+                    return Ok(Ok(Self::label(labels, label)?));
+                }
+            }
+            Instruction::BranchGeU(rs1, rs2, label) => {
+                if x(self, rs1) >= x(self, rs2) {
+                    return Ok(Ok(Self::label(labels, label)?));
+                }
+            }
+            Instruction::Jump(label) => return Ok(Ok(Self::label(labels, label)?)),
+            Instruction::JumpAndLink(rd, label) => {
+                let target = Self::label(labels, label)?;
+                self.set(*rd, next);
+                return Ok(Ok(target));
+            }
+            Instruction::JumpAndLinkReg(rd, rs1, offset) => {
+                let target = r(self, rs1).wrapping_add(*offset as u32);
+                self.set(*rd, next);
+                return Ok(Ok(target));
+            }
+            Instruction::Ecall => {
+                if let Err(halt) = self.host_call() {
+                    return Ok(Err(halt));
+                }
+            }
+            Instruction::Ebreak => return Ok(Err(Halt::Trap("Breakpoint".to_string()))),
+            Instruction::Li(rd, imm) => self.set(*rd, *imm as u32),
+            Instruction::La(rd, label) => {
+                let address = Self::label(labels, label)?;
+                self.set(*rd, address)
+            }
+            Instruction::Mv(rd, rs1) => self.set_value(*rd, x(self, rs1)),
+            Instruction::Not(rd, rs1) => self.set_value(*rd, !x(self, rs1)),
+            Instruction::Neg(rd, rs1) => self.set_value(*rd, x(self, rs1).wrapping_neg()),
+            Instruction::Label(_) | Instruction::Comment(_) => {}
+        }
+
+        Ok(Ok(next))
+    }
+
+    fn check_range(&self, address: u32, len: u32) -> Result<std::ops::Range<usize>, Halt> {
+        let start = address as usize;
+        let end = start.checked_add(len as usize);
+        match end {
+            Some(end) if end <= self.memory.len() => Ok(start..end),
+            _ => Err(Halt::Trap(format!(
+                "Memory access out of bounds: {:#x}+{}",
+                address, len
+            ))),
+        }
+    }
+
+    fn load_word(&self, address: u32) -> Result<u32, Halt> {
+        let range = self.check_range(address, 4)?;
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&self.memory[range]);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn store_word(&mut self, address: u32, value: u32) -> Result<(), Halt> {
+        let range = self.check_range(address, 4)?;
+        self.memory[range].copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn read_bytes(&self, address: u32, len: u32) -> Result<Vec<u8>, Halt> {
+        let range = self.check_range(address, len)?;
+        Ok(self.memory[range].to_vec())
+    }
+
+    fn read_array<const N: usize>(&self, address: u32) -> Result<[u8; N], Halt> {
+        let range = self.check_range(address, N as u32)?;
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(&self.memory[range]);
+        Ok(bytes)
+    }
+
+    fn write_bytes(&mut self, address: u32, data: &[u8]) -> Result<(), Halt> {
+        let range = self.check_range(address, data.len() as u32)?;
+        self.memory[range].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Allocate `size` zeroed bytes of heap memory
+    ///
+    /// Returns 0 when the allocation would reach the stack.
+    fn allocate(&mut self, size: u32) -> Result<u32, Halt> {
+        let pointer = self.heap_break;
+        let end = pointer
+            .checked_add(size)
+            .map(|end| align_up(end, HEAP_ALIGN))
+            .filter(|&end| end <= self.register(Register::X2));
+        let Some(end) = end else {
+            return Ok(0);
+        };
+        let range = self.check_range(pointer, end - pointer)?;
+        self.memory[range].fill(0);
+        self.heap_break = end;
+        Ok(pointer)
+    }
+
+    /// Run the code of the contract at `address` as a sub-call
+    ///
+    /// A gas limit of 0 forwards all remaining gas. Calls to addresses
+    /// without code are answered from the environment's mocks. A call moves
+    /// `value` to the callee first and fails if the transfer does. Entering a
+    /// contract goes through the reentrancy guard; a denied call fails
+    /// without running. Returns the call status and the callee's output.
+    fn call_contract(
+        &mut self,
+        kind: CallKind,
+        address: Address,
+        value: u128,
+        gas: u32,
+        input: Vec<u8>,
+    ) -> Result<(u32, Vec<u8>), Halt> {
+        let charged =
+            self.environment
+                .charge_bytes("external_call", "external_call_byte", input.len());
+        if charged.is_err() {
+            return Err(Halt::Trap(EnvError::OutOfGas.to_string()));
+        }
+        let context = &self.environment.context;
+        let remaining = context.gas_limit - context.gas_used;
+        let forwarded = match gas as u64 {
+            0 => remaining,
+            gas => gas.min(remaining),
+        };
+
+        // The value moves inside the callee's transaction, so it returns to
+        // the caller if the callee fails
+        self.environment.checkpoint();
+        let (status, output) = self.call_contract_inner(kind, address, value, forwarded, input);
+        let closed = if status == CALL_SUCCESS {
+            self.environment.commit()
+        } else {
+            self.environment.rollback()
+        };
+        closed.map_err(|err| Halt::Trap(err.to_string()))?;
+        Ok((status, output))
+    }
+
+    /// Transfer the value and run the callee of
+    /// [`call_contract`](Self::call_contract)
+    fn call_contract_inner(
+        &mut self,
+        kind: CallKind,
+        address: Address,
+        value: u128,
+        forwarded: u64,
+        input: Vec<u8>,
+    ) -> (u32, Vec<u8>) {
+        if kind != CallKind::DelegateCall {
+            let caller = self.environment.context.address;
+            if self.environment.transfer(caller, address, value).is_err() {
+                return (CALL_FAILED, Vec::new());
+            }
+        }
+
+        let Some(code) = self.environment.code_at(&address) else {
+            let mocked = self
+                .environment
+                .mocks
+                .get_call_response(&hex::encode(address), &input);
+            return match mocked {
+                Some(output) => (CALL_SUCCESS, output),
+                None => (CALL_FAILED, Vec::new()),
+            };
+        };
+
+        let mut callee_context = self.environment.context.clone();
+        callee_context.input = input;
+        callee_context.gas_limit = callee_context.gas_used + forwarded;
+        if kind != CallKind::DelegateCall {
+            callee_context.caller = callee_context.address;
+            callee_context.address = address;
+            callee_context.value = value;
+        }
+
+        // A delegate call runs as the current contract, so only real calls
+        // enter the guard
+        let guarded = kind != CallKind::DelegateCall;
+        let entered_root = if guarded {
+            match self.enter_guard(address) {
+                Ok(entered_root) => entered_root,
+                Err(_) => return (CALL_FAILED, Vec::new()),
+            }
+        } else {
+            false
+        };
+
+        let caller_context = self.environment.enter_contract(callee_context);
+        let environment = std::mem::replace(
+            &mut self.environment,
+            Environment::new(ExecutionContext::new_default()),
+        );
+        let mut callee =
+            Interpreter::with_environment(environment).with_memory_size(self.memory.len());
+        let deploys = kind == CallKind::Deploy
+            && code.iter().any(|instruction| {
+                matches!(instruction, Instruction::Label(label) if *label == export_label(DEPLOY_EXPORT))
+            });
+        let export = if deploys { DEPLOY_EXPORT } else { ENTRY_POINT };
+        let result = callee.execute_export(&code, export);
+        self.environment = callee.into_environment();
+        self.environment.exit_contract(caller_context);
+
+        if guarded {
+            let guard = &mut self.environment.reentrancy_guard;
+            guard.exit_function(&hex::encode(address));
+            if entered_root {
+                guard.exit_function(&hex::encode(self.environment.context.address));
+            }
+        }
+
+        match result {
+            Ok(ExecutionResult::Success { data, .. }) => (CALL_SUCCESS, data),
+            Ok(ExecutionResult::Revert { data, .. }) => (CALL_REVERTED, data),
+            Ok(ExecutionResult::Failure { .. }) | Err(_) => (CALL_FAILED, Vec::new()),
+        }
+    }
+
+    /// Enter `address` in the reentrancy guard, first entering the current
+    /// contract if this is the outermost call. Returns whether the current
+    /// contract was entered.
+    fn enter_guard(&mut self, address: Address) -> Result<bool, SecurityError> {
+        let current = hex::encode(self.environment.context.address);
+        let guard = &mut self.environment.reentrancy_guard;
+        let entered_root = guard.current_depth() == 0;
+        if entered_root {
+            guard.enter_function(&current)?;
+        }
+        if let Err(err) = guard.enter_function(&hex::encode(address)) {
+            if entered_root {
+                guard.exit_function(&current);
+            }
+            return Err(err);
+        }
+        Ok(entered_root)
+    }
+
+    /// Copy call output into the caller's buffer
+    ///
+    /// The word at `len_ptr` holds the buffer capacity on entry and the number
+    /// of bytes written on return.
+    fn write_call_output(&mut self, output: &[u8], ptr: u32, len_ptr: u32) -> Result<(), Halt> {
+        let capacity = self.load_word(len_ptr)? as usize;
+        let len = output.len().min(capacity);
+        self.write_bytes(ptr, &output[..len])?;
+        self.store_word(len_ptr, len as u32)
+    }
+
+    /// Dispatch a host call selected by `a7`
+    fn host_call(&mut self) -> Result<(), Halt> {
+        let arg = |interp: &Self, index: usize| interp.registers[10 + index] as u32;
+        let id = self.register(Register::X17);
+        let env_error = |err: EnvError| Halt::Trap(err.to_string());
+
+        match id {
+            id if id == HostFunction::StorageGet as u32 => {
+                let key = self.read_bytes(arg(self, 0), arg(self, 1))?;
+                let (value_ptr, value_len_ptr) = (arg(self, 2), arg(self, 3));
+                match self.environment.storage_get(&key).map_err(env_error)? {
+                    Some(value) => {
+                        self.write_bytes(value_ptr, &value)?;
+                        self.store_word(value_len_ptr, value.len() as u32)?;
+                        self.set(Register::X10, 0);
+                    }
+                    None => self.set(Register::X10, 1),
+                }
+            }
+            id if id == HostFunction::StorageSet as u32 => {
+                let key = self.read_bytes(arg(self, 0), arg(self, 1))?;
+                let value = self.read_bytes(arg(self, 2), arg(self, 3))?;
+                self.environment
+                    .storage_set(&key, &value)
+                    .map_err(env_error)?;
+                self.set(Register::X10, 0);
+            }
+            id if id == HostFunction::StorageClear as u32 => {
+                let key = self.read_bytes(arg(self, 0), arg(self, 1))?;
+                self.environment.storage_clear(&key).map_err(env_error)?;
+                self.set(Register::X10, 0);
+            }
+            id if id == HostFunction::GetCaller as u32 => {
+                let caller = self.environment.context.caller;
+                self.write_bytes(arg(self, 0), caller.as_bytes())?;
+            }
+            id if id == HostFunction::GetAddress as u32 => {
+                let address = self.environment.context.address;
+                self.write_bytes(arg(self, 0), address.as_bytes())?;
+            }
+            id if id == HostFunction::GetCallValue as u32 => {
+                let value = self.environment.context.value;
+                self.write_bytes(arg(self, 0), &value.to_le_bytes())?;
+            }
+            id if id == HostFunction::GetBalance as u32 => {
+                let address = self.environment.context.address;
+                let balance = self.environment.balance_of(&address);
+                self.write_bytes(arg(self, 0), &balance.to_le_bytes())?;
+            }
+            id if id == HostFunction::Random as u32 => {
+                let subject = self.read_bytes(arg(self, 0), arg(self, 1))?;
+                let value = self.environment.context.random(&subject);
+                self.write_bytes(arg(self, 2), value.as_bytes())?;
+            }
+            id if id == HostFunction::Keccak256 as u32 => {
+                let input = self.read_bytes(arg(self, 0), arg(self, 1))?;
+                self.write_bytes(arg(self, 2), &CryptoFunctions::keccak256(&input))?;
+            }
+            id if id == HostFunction::Blake2b256 as u32 => {
+                let input = self.read_bytes(arg(self, 0), arg(self, 1))?;
+                self.write_bytes(arg(self, 2), &CryptoFunctions::blake2b_256(&input))?;
+            }
+            id if id == HostFunction::Sha256 as u32 => {
+                let input = self.read_bytes(arg(self, 0), arg(self, 1))?;
+                self.write_bytes(arg(self, 2), &CryptoFunctions::sha256(&input))?;
+            }
+            id if id == HostFunction::Ripemd160 as u32 => {
+                let input = self.read_bytes(arg(self, 0), arg(self, 1))?;
+                self.write_bytes(arg(self, 2), &CryptoFunctions::ripemd160(&input))?;
+            }
+            id if id == HostFunction::EcdsaRecover as u32 => {
+                let signature = self.read_array::<65>(arg(self, 0))?;
+                let hash = self.read_array::<32>(arg(self, 1))?;
+                match CryptoFunctions::ecdsa_recover(&hash, &signature) {
+                    Some(public_key) => {
+                        self.write_bytes(arg(self, 2), &public_key)?;
+                        self.set(Register::X10, 0);
+                    }
+                    None => self.set(Register::X10, 1),
+                }
+            }
+            id if id == HostFunction::Sr25519Verify as u32 => {
+                let signature = self.read_array::<64>(arg(self, 0))?;
+                let public_key = self.read_array::<32>(arg(self, 1))?;
+                let message = self.read_bytes(arg(self, 2), arg(self, 3))?;
+                let valid = CryptoFunctions::sr25519_verify(&signature, &message, &public_key);
+                self.set(Register::X10, if valid { 0 } else { 1 });
+            }
+            id if id == HostFunction::Log as u32 => {
+                let (topics_ptr, topics_count) = (arg(self, 0), arg(self, 1));
+                let mut topics = Vec::new();
+                for i in 0..topics_count {
+                    topics.push(self.read_bytes(topics_ptr.wrapping_add(i * 32), 32)?);
+                }
+                let data = self.read_bytes(arg(self, 2), arg(self, 3))?;
+                self.environment
+                    .emit_event(topics, data)
+                    .map_err(env_error)?;
+            }
+            id if id == HostFunction::Call as u32 => {
+                let address = Address::new(self.read_array::<32>(arg(self, 0))?);
+                let value = u128::from_le_bytes(self.read_array::<16>(arg(self, 1))?);
+                let input = self.read_bytes(arg(self, 3), arg(self, 4))?;
+                let (status, output) =
+                    self.call_contract(CallKind::Call, address, value, arg(self, 2), input)?;
+                self.write_call_output(&output, arg(self, 5), arg(self, 6))?;
+                self.set(Register::X10, status);
+            }
+            id if id == HostFunction::DelegateCall as u32 => {
+                let address = Address::new(self.read_array::<32>(arg(self, 0))?);
+                let input = self.read_bytes(arg(self, 2), arg(self, 3))?;
+                let value = self.environment.context.value;
+                let (status, output) = self.call_contract(
+                    CallKind::DelegateCall,
+                    address,
+                    value,
+                    arg(self, 1),
+                    input,
+                )?;
+                self.write_call_output(&output, arg(self, 4), arg(self, 5))?;
+                self.set(Register::X10, status);
+            }
+            id if id == HostFunction::Create as u32 => {
+                let code_hash = Hash::new(self.read_array::<32>(arg(self, 0))?);
+                let value = u128::from_le_bytes(self.read_array::<16>(arg(self, 1))?);
+                let input = self.read_bytes(arg(self, 3), arg(self, 4))?;
+                let address_ptr = arg(self, 5);
+
+                // The new account only survives if its constructor succeeds
+                self.environment.checkpoint();
+                let outcome = match self.environment.instantiate_account(code_hash) {
+                    Ok(address) => self
+                        .call_contract(CallKind::Deploy, address, value, arg(self, 2), input)
+                        .map(|(status, _)| (status, address)),
+                    Err(_) => Ok((CALL_FAILED, Address::ZERO)),
+                };
+                match outcome {
+                    Ok((CALL_SUCCESS, address)) => {
+                        self.environment.commit().map_err(env_error)?;
+                        self.write_bytes(address_ptr, address.as_bytes())?;
+                        self.set(Register::X10, CALL_SUCCESS);
+                    }
+                    Ok((status, _)) => {
+                        self.environment.rollback().map_err(env_error)?;
+                        self.set(Register::X10, status);
+                    }
+                    Err(halt) => {
+                        self.environment.rollback().map_err(env_error)?;
+                        return Err(halt);
+                    }
+                }
+            }
+            id if id == HostFunction::Transfer as u32 => {
+                let to = Address::new(self.read_array::<32>(arg(self, 0))?);
+                let value = u128::from_le_bytes(self.read_array::<16>(arg(self, 1))?);
+                let from = self.environment.context.address;
+                self.environment.charge("transfer").map_err(env_error)?;
+                let status = match self.environment.transfer(from, to, value) {
+                    Ok(()) => CALL_SUCCESS,
+                    Err(_) => CALL_FAILED,
+                };
+                self.set(Register::X10, status);
+            }
+            id if id == HostFunction::Terminate as u32 => {
+                let beneficiary = Address::new(self.read_array::<32>(arg(self, 0))?);
+                self.environment.terminate(beneficiary).map_err(env_error)?;
+                return Err(Halt::Return(Vec::new()));
+            }
+            id if id == HostFunction::SetCodeHash as u32 => {
+                let code_hash = Hash::new(self.read_array::<32>(arg(self, 0))?);
+                self.environment
+                    .charge("set_code_hash")
+                    .map_err(env_error)?;
+                let status = match self.environment.set_code_hash(code_hash) {
+                    Ok(()) => CALL_SUCCESS,
+                    Err(_) => CALL_FAILED,
+                };
+                self.set(Register::X10, status);
+            }
+            id if id == HostFunction::MemoryAlloc as u32 => {
+                let pointer = self.allocate(arg(self, 0))?;
+                self.set(Register::X10, pointer);
+            }
+            // Allocations live until the end of the call
+            id if id == HostFunction::MemoryFree as u32 => {}
+            id if id == HostFunction::Debug as u32 => {}
+            id if id == HostFunction::Return as u32 => {
+                let data = self.read_bytes(arg(self, 0), arg(self, 1))?;
+                return Err(Halt::Return(data));
+            }
+            id if id == HostFunction::Revert as u32 => {
+                let data = self.read_bytes(arg(self, 0), arg(self, 1))?;
+                return Err(Halt::Revert(data));
+            }
+            id if id == HostFunction::Abort as u32 => {
+                return Err(Halt::Trap("Contract aborted".to_string()));
+            }
+            id if id == HostFunction::ChargeGas as u32 => {
+                self.environment
+                    .context
+                    .use_gas(arg(self, 0) as u64)
+                    .map_err(env_error)?;
+            }
+            id if self.environment.chain_extension(id).is_some() => {
+                let arity = self
+                    .environment
+                    .chain_extension(id)
+                    .map_or(0, |extension| extension.params.len());
+                let args: Vec<u32> = (0..arity).map(|i| arg(self, i)).collect();
+                let result = self
+                    .environment
+                    .call_chain_extension(id, &args)
+                    .map_err(env_error)?;
+                self.set(Register::X10, result);
+            }
+            id => {
+                return Err(Halt::Trap(format!("Unsupported host function: {}", id)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Round `value` up to a multiple of `align`, a power of two
+fn align_up(value: u32, align: u32) -> u32 {
+    value.saturating_add(align - 1) & !(align - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::analyzer::linearity::Linearity;
+    use crate::compiler::codegen::metadata::{compute_storage_key, selector_for};
+    use crate::compiler::codegen::risc_v::{RiscVCodegen, LOOP_ITERATION_GAS};
+    use crate::compiler::lowering::lower_program;
+    use crate::compiler::parser::parser::Parser;
+    use crate::compiler::wide::WideUint;
+    use crate::security::reentrancy_guard::ProtectionMode;
+    use crate::stdlib::bytes::Bytes;
+    use crate::stdlib::storage::{derive_entry_key, StorageMap, StorageVec};
+
+    fn run(instructions: &[Instruction]) -> ExecutionResult {
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        interpreter.execute(instructions).unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic_and_return() {
+        let result = run(&[
+            Instruction::Li(Register::X5, 40),
+            Instruction::Li(Register::X6, 2),
+            Instruction::Add(Register::X10, Register::X5, Register::X6),
+            Instruction::JumpAndLinkReg(Register::X0, Register::X1, 0),
+        ]);
+
+        match result {
+            ExecutionResult::Success { data, gas_used, .. } => {
+                assert_eq!(data, 42u32.to_le_bytes().to_vec());
+                assert_eq!(gas_used, 4);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_division_by_zero_follows_riscv() {
+        let result = run(&[
+            Instruction::Li(Register::X5, 7),
+            Instruction::Div(Register::X10, Register::X5, Register::X0),
+        ]);
+
+        match result {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, vec![0xFF; 4]),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_storage_host_calls() {
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        let program = vec![
+            Instruction::Li(Register::X5, 0x1234),
+            Instruction::Li(Register::X6, 0x100),
+            Instruction::Store(Register::X5, Register::X6, 0),
+            Instruction::Li(Register::X10, 0x100), // key ptr
+            Instruction::Li(Register::X11, 4),     // key len
+            Instruction::Li(Register::X12, 0x100), // value ptr
+            Instruction::Li(Register::X13, 4),     // value len
+            Instruction::Li(Register::X17, HostFunction::StorageSet as i32),
+            Instruction::Ecall,
+        ];
+
+        let result = interpreter.execute(&program).unwrap();
+        assert!(matches!(result, ExecutionResult::Success { .. }));
+
+        let key = 0x1234u32.to_le_bytes().to_vec();
+        assert_eq!(interpreter.environment().storage.get(&key), Some(&key));
+    }
+
+    #[test]
+    fn test_keccak256_host_call() {
+        let mut context = ExecutionContext::new_default();
+        context.input = b"abc".to_vec();
+        let mut interpreter = Interpreter::new(context);
+        let program = vec![
+            // a0/a1 already point at the input
+            Instruction::Li(Register::X12, 0x100),
+            Instruction::Li(Register::X17, HostFunction::Keccak256 as i32),
+            Instruction::Ecall,
+            Instruction::Li(Register::X10, 0x100),
+            Instruction::Li(Register::X11, 32),
+            Instruction::Li(Register::X17, HostFunction::Return as i32),
+            Instruction::Ecall,
+        ];
+
+        match interpreter.execute(&program).unwrap() {
+            ExecutionResult::Success { data, .. } => {
+                assert_eq!(data, CryptoFunctions::keccak256(b"abc").to_vec())
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_revert_host_call() {
+        let result = run(&[
+            Instruction::Li(Register::X10, 0),
+            Instruction::Li(Register::X11, 0),
+            Instruction::Li(Register::X17, HostFunction::Revert as i32),
+            Instruction::Ecall,
+        ]);
+
+        assert!(matches!(result, ExecutionResult::Revert { .. }));
+    }
+
+    #[test]
+    fn test_out_of_gas_is_a_failure() {
+        let mut context = ExecutionContext::new_default();
+        context.gas_limit = 3;
+        let mut interpreter = Interpreter::new(context);
+        let result = interpreter
+            .execute(&[
+                Instruction::Label("main".to_string()),
+                Instruction::Jump("main".to_string()),
+            ])
+            .unwrap();
+
+        match result {
+            ExecutionResult::Failure { reason, .. } => assert_eq!(reason, "Gas limit exceeded"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_undefined_label_is_an_error() {
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        let result = interpreter.execute(&[Instruction::Jump("missing".to_string())]);
+        assert!(matches!(result, Err(InterpreterError::UndefinedLabel(_))));
+    }
+
+    #[test]
+    fn test_executes_generated_code() {
+        let source = r#"
+            fn main() -> u24 {
+                return 7;
+            }
+        "#;
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new().generate(&program).unwrap();
+
+        match run(&instructions) {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 7u32.to_le_bytes().to_vec()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    fn dispatch(source: &str, input: Vec<u8>) -> ExecutionResult {
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap();
+
+        let mut context = ExecutionContext::new_default();
+        context.input = input;
+        Interpreter::new(context).execute(&instructions).unwrap()
+    }
+
+    fn call_data(selector: [u8; 4], args: &[u32]) -> Vec<u8> {
+        let mut input = selector.to_vec();
+        for arg in args {
+            input.extend_from_slice(&arg.to_le_bytes());
+        }
+        input
+    }
+
+    #[test]
+    fn test_inline_asm() {
+        let source = r#"
+            #[message]
+            fn triangle(n: u24) -> u24 {
+                return asm(in a1 = n, out a0) {
+                    "li a0, 0"
+                    "triangle_loop:"
+                    "beq a1, zero, triangle_done"
+                    "add a0, a0, a1"
+                    "addi a1, a1, -1"
+                    "j triangle_loop"
+                    "triangle_done:"
+                } + 1;
+            }
+        "#;
+        let triangle = selector_for("triangle(u24)");
+
+        match dispatch(source, call_data(triangle, &[4])) {
+            ExecutionResult::Success { data, .. } => {
+                assert_eq!(data, 11u32.to_le_bytes().to_vec())
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rv64_code_matches_rv32() {
+        let source = r#"
+            #[message]
+            fn mul(a: u32, b: u32) -> u32 {
+                return a * b;
+            }
+
+            #[message]
+            fn div(a: i32, b: i32) -> i32 {
+                return a / b;
+            }
+
+            #[message]
+            fn mix(a: u32, b: u32) -> u32 {
+                unchecked: {
+                    result = a * b - a + b % 7;
+                }
+                return result;
+            }
+
+            #[message]
+            fn wide(a: u64, b: u64) -> u64 {
+                return a * b + a;
+            }
+        "#;
+        let program = Parser::new(source).parse_program().unwrap();
+        let calls = [
+            ("mul(u32,u32)", vec![3, 5]),
+            ("mul(u32,u32)", vec![0x10000, 0x10000]),
+            ("div(i32,i32)", vec![(-7i32) as u32, 2]),
+            ("mix(u32,u32)", vec![0x8000_0001, 0xFFFF_FFFF]),
+            ("wide(u64,u64)", vec![0xFFFF_FFFF, 0, 3, 0]),
+            ("wide(u64,u64)", vec![0, 1, 0, 0xFFFF_FFFF]),
+        ];
+        for (signature, args) in calls {
+            let results: Vec<_> = [Isa::Rv32Em, Isa::Rv64Im]
+                .into_iter()
+                .map(|isa| {
+                    let instructions = RiscVCodegen::new()
+                        .with_isa(isa)
+                        .with_dispatcher()
+                        .generate(&program)
+                        .unwrap();
+                    let mut context = ExecutionContext::new_default();
+                    context.input = call_data(selector_for(signature), &args);
+                    match Interpreter::new(context)
+                        .with_isa(isa)
+                        .execute(&instructions)
+                        .unwrap()
+                    {
+                        ExecutionResult::Success { data, .. } => Ok(data),
+                        ExecutionResult::Revert { data, .. } => Err(data),
+                        other => panic!("unexpected result: {:?}", other),
+                    }
+                })
+                .collect();
+            assert_eq!(results[0], results[1], "{} {:?}", signature, args);
+        }
+    }
+
+    #[test]
+    fn test_rv64_instructions_trap_on_rv32() {
+        let code = [Instruction::AddW(Register::X10, Register::X0, Register::X0)];
+        match run(&code) {
+            ExecutionResult::Failure { reason, .. } => {
+                assert_eq!(reason, "Illegal instruction on RV32EM: addw a0, zero, zero")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let mut interpreter =
+            Interpreter::new(ExecutionContext::new_default()).with_isa(Isa::Rv64Im);
+        let code = [
+            Instruction::Li(Register::X5, -1),
+            Instruction::ShiftRightImm(Register::X5, Register::X5, 32),
+            Instruction::Store64(Register::X5, Register::X2, -8),
+            Instruction::Load(Register::X10, Register::X2, -4),
+        ];
+        match interpreter.execute(&code).unwrap() {
+            // The upper word of a 64-bit logical shift of -1 by 32 is zero
+            ExecutionResult::Success { data, .. } => assert_eq!(data, vec![0; 4]),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_dispatches_by_selector() {
+        let source = r#"
+            #[message]
+            fn second(a: u24, b: u24) -> u24 {
+                return b;
+            }
+
+            #[message]
+            fn seven() -> u24 {
+                return 7;
+            }
+        "#;
+        let second = selector_for("second(u24,u24)");
+        let seven = selector_for("seven()");
+
+        match dispatch(source, call_data(second, &[2, 3])) {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 3u32.to_le_bytes().to_vec()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match dispatch(source, call_data(seven, &[])) {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 7u32.to_le_bytes().to_vec()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_dispatcher_reverts_on_bad_input() {
+        let source = r#"
+            #[message]
+            fn add(a: u24, b: u24) -> u24 {
+                return a + b;
+            }
+        "#;
+        let add = selector_for("add(u24,u24)");
+
+        for input in [
+            vec![],
+            vec![1, 2],
+            call_data([0xde, 0xad, 0xbe, 0xef], &[2, 3]),
+            call_data(add, &[2]),
+        ] {
+            assert!(matches!(
+                dispatch(source, input),
+                ExecutionResult::Revert { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_dispatcher_honors_attributes() {
+        let source = r#"
+            #[message]
+            #[selector(0x01020304)]
+            fn renamed() -> u24 {
+                return 1;
+            }
+
+            #[message]
+            #[payable]
+            fn deposit() -> u24 {
+                return 2;
+            }
+
+            #[test]
+            fn check() -> u24 {
+                return 3;
+            }
+
+            fn helper() -> u24 {
+                return 4;
+            }
+        "#;
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap();
+        let run = |selector: [u8; 4], value: u128| {
+            let mut context = ExecutionContext::new_default();
+            context.input = call_data(selector, &[]);
+            context.value = value;
+            Interpreter::new(context).execute(&instructions).unwrap()
+        };
+
+        // The override replaces the selector derived from the signature
+        assert!(matches!(
+            run([1, 2, 3, 4], 0),
+            ExecutionResult::Success { .. }
+        ));
+        assert!(matches!(
+            run(selector_for("renamed()"), 0),
+            ExecutionResult::Revert { .. }
+        ));
+
+        // Only payable functions accept value
+        assert!(matches!(
+            run([1, 2, 3, 4], 5),
+            ExecutionResult::Revert { .. }
+        ));
+        assert!(matches!(
+            run(selector_for("deposit()"), 5),
+            ExecutionResult::Success { .. }
+        ));
+
+        // Tests and helpers are not part of the contract interface
+        for helper in ["check()", "helper()"] {
+            assert!(matches!(
+                run(selector_for(helper), 0),
+                ExecutionResult::Revert { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_guards_wrap_function_bodies() {
+        let source = r#"
+            storage {
+                total: u24,
+            }
+
+            guard at_least(amount: u24, min: u24) {
+                require(amount >= min, "too small");
+                _;
+                total = total + amount;
+            }
+
+            #[message]
+            #[guard(at_least(value, 10))]
+            fn deposit(value: u24) -> u24 {
+                return total;
+            }
+
+            #[message]
+            fn get_total() -> u24 {
+                return total;
+            }
+        "#;
+        let program = lower_program(Parser::new(source).parse_program().unwrap());
+        let instructions = RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap();
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        let mut call = |signature: &str, args: &[u32]| {
+            interpreter.environment_mut().context.input = call_data(selector_for(signature), args);
+            interpreter.execute(&instructions).unwrap()
+        };
+
+        // The guard rejects the call before the body runs
+        assert!(matches!(
+            call("deposit(u24)", &[3]),
+            ExecutionResult::Revert { .. }
+        ));
+
+        // The body returns the total from before the guard adds to it
+        for (amount, before) in [(10u32, 0u32), (15, 10)] {
+            match call("deposit(u24)", &[amount]) {
+                ExecutionResult::Success { data, .. } => {
+                    assert_eq!(data, before.to_le_bytes().to_vec())
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+        match call("get_total()", &[]) {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 25u32.to_le_bytes().to_vec()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    fn wide(value: u128, bits: u16) -> Vec<u8> {
+        WideUint::from_u128(value, bits).unwrap().to_le_bytes()
+    }
+
+    fn wide_call(source: &str, signature: &str, args: &[Vec<u8>]) -> Vec<u8> {
+        let mut input = selector_for(signature).to_vec();
+        input.extend(args.concat());
+        match dispatch(source, input) {
+            ExecutionResult::Success { data, .. } => data,
+            other => panic!("unexpected result for {}: {:?}", signature, other),
+        }
+    }
+
+    #[test]
+    fn test_wide_integer_arithmetic() {
+        let source = r#"
+            #[message]
+            fn add(a: u128, b: u128) -> u128 {
+                return a + b;
+            }
+
+            #[message]
+            unchecked fn sub(a: u256, b: u256) -> u256 {
+                return a - b;
+            }
+
+            #[message]
+            fn mul(a: u128, b: u64) -> u128 {
+                total = a * b;
+                return total + 1u128;
+            }
+
+            #[message]
+            fn div(a: u256, b: u64) -> u256 {
+                return a / b;
+            }
+
+            #[message]
+            fn rem(a: u128, b: u128) -> u128 {
+                return a % b;
+            }
+
+            #[message]
+            fn less(a: u64, b: u64) -> u24 {
+                return a < b;
+            }
+
+            #[message]
+            fn equal(a: u256, b: u64) -> u24 {
+                return a == b;
+            }
+
+            #[message]
+            fn scale(factor: u24, a: u128, offset: u24) -> u128 {
+                return a * factor + offset;
+            }
+
+            #[message]
+            fn scale_sum(a: u128, b: u128) -> u128 {
+                return scale(3, add(a, b), 4);
+            }
+        "#;
+
+        let (a, b) = (u64::MAX as u128 + 7, u64::MAX as u128 * 3);
+        assert_eq!(
+            wide_call(source, "add(u128,u128)", &[wide(a, 128), wide(b, 128)]),
+            wide(a + b, 128)
+        );
+        // Borrows through every word
+        assert_eq!(
+            wide_call(source, "sub(u256,u256)", &[wide(0, 256), wide(1, 256)]),
+            vec![0xff; 32]
+        );
+        assert_eq!(
+            wide_call(
+                source,
+                "mul(u128,u64)",
+                &[wide(a, 128), wide(0xdead_beef_1234, 64)]
+            ),
+            wide(a * 0xdead_beef_1234 + 1, 128)
+        );
+        assert_eq!(
+            wide_call(
+                source,
+                "rem(u128,u128)",
+                &[wide(u128::MAX, 128), wide(0x1_0000_0001, 128)]
+            ),
+            wide(u128::MAX % 0x1_0000_0001, 128)
+        );
+
+        // 2^255 / 2^63 = 2^192
+        let mut dividend = vec![0; 32];
+        dividend[31] = 0x80;
+        let mut quotient = vec![0; 32];
+        quotient[24] = 1;
+        assert_eq!(
+            wide_call(source, "div(u256,u64)", &[dividend, wide(1 << 63, 64)]),
+            quotient
+        );
+
+        let less =
+            |a: u128, b: u128| wide_call(source, "less(u64,u64)", &[wide(a, 64), wide(b, 64)]);
+        assert_eq!(less(1 << 32, (1 << 32) + 1), 1u32.to_le_bytes().to_vec());
+        assert_eq!(less(1 << 33, (1 << 32) + 1), 0u32.to_le_bytes().to_vec());
+        assert_eq!(
+            wide_call(
+                source,
+                "equal(u256,u64)",
+                &[wide(1 << 40, 256), wide(1 << 40, 64)]
+            ),
+            1u32.to_le_bytes().to_vec()
+        );
+        // Wide arguments of direct calls are passed with all their words
+        assert_eq!(
+            wide_call(
+                source,
+                "scale_sum(u128,u128)",
+                &[wide(a, 128), wide(b, 128)]
+            ),
+            wide((a + b) * 3 + 4, 128)
+        );
+    }
+
+    #[test]
+    fn test_wide_division_by_zero_reverts() {
+        let source = r#"
+            #[message]
+            fn div(a: u128, b: u128) -> u128 {
+                return a / b;
+            }
+        "#;
+        let mut input = selector_for("div(u128,u128)").to_vec();
+        input.extend(wide(7, 128));
+        input.extend(wide(0, 128));
+        assert!(matches!(
+            dispatch(source, input),
+            ExecutionResult::Revert { .. }
+        ));
+    }
+
+    #[test]
+    fn test_checked_arithmetic_reverts_on_overflow() {
+        let source = r#"
+            #[message]
+            fn add(a: u24, b: u24) -> u24 {
+                return a + b;
+            }
+
+            #[message]
+            fn sub(a: u24, b: u24) -> u24 {
+                return a - b;
+            }
+
+            #[message]
+            fn mul(a: u32, b: u32) -> u32 {
+                return a * b;
+            }
+
+            #[message]
+            fn wide_add(a: u64, b: u64) -> u64 {
+                return a + b;
+            }
+
+            #[message]
+            fn wide_mul(a: u128, b: u128) -> u128 {
+                return a * b;
+            }
+        "#;
+        let reverts =
+            |input: Vec<u8>| matches!(dispatch(source, input), ExecutionResult::Revert { .. });
+
+        let add = selector_for("add(u24,u24)");
+        assert!(reverts(call_data(add, &[0xFF_FFFF, 1])));
+        assert!(!reverts(call_data(add, &[0xFF_FFFE, 1])));
+        assert!(reverts(call_data(selector_for("sub(u24,u24)"), &[1, 2])));
+        assert!(reverts(call_data(
+            selector_for("mul(u32,u32)"),
+            &[0x1_0000, 0x1_0000]
+        )));
+
+        let mut input = selector_for("wide_add(u64,u64)").to_vec();
+        input.extend(wide(u64::MAX as u128, 64));
+        input.extend(wide(1, 64));
+        assert!(reverts(input));
+
+        // Neither row carries, but 2^64 * 2^64 lands above the top word
+        let mut input = selector_for("wide_mul(u128,u128)").to_vec();
+        input.extend(wide(1 << 64, 128));
+        input.extend(wide(1 << 64, 128));
+        assert!(reverts(input));
+        assert_eq!(
+            wide_call(
+                source,
+                "wide_mul(u128,u128)",
+                &[wide(1 << 63, 128), wide(1 << 64, 128)]
+            ),
+            wide(1 << 127, 128)
+        );
+    }
+
+    #[test]
+    fn test_unchecked_arithmetic_wraps() {
+        let source = r#"
+            #[message]
+            unchecked fn add(a: u24, b: u24) -> u24 {
+                return a + b;
+            }
+
+            #[message]
+            fn sub(a: u32, b: u32) -> u32 {
+                unchecked: {
+                    result = a - b;
+                }
+                return result;
+            }
+
+            #[message]
+            fn checked_after(a: u32, b: u32) -> u32 {
+                unchecked: {
+                    result = a - b;
+                }
+                return a - b;
+            }
+
+            #[message]
+            fn below_zero() -> u24 {
+                unchecked: {
+                    result = 0 - 1;
+                }
+                return result;
+            }
+
+            #[message]
+            fn past_max() -> u24 {
+                unchecked: {
+                    result = 16777215 + 1 == 0;
+                }
+                return result;
+            }
+
+            #[message]
+            unchecked fn signed_add(a: i24, b: i24) -> i24 {
+                return a + b;
+            }
+
+            #[message]
+            unchecked fn signed_mul(a: i24, b: i24) -> i24 {
+                return a * b;
+            }
+        "#;
+        let returns = |signature: &str, args: &[u32], expected: u32| match dispatch(
+            source,
+            call_data(selector_for(signature), args),
+        ) {
+            ExecutionResult::Success { data, .. } => {
+                assert_eq!(data, expected.to_le_bytes().to_vec(), "{}", signature)
+            }
+            other => panic!("unexpected result of {}: {:?}", signature, other),
+        };
+
+        // 24-bit results wrap to 24 bits, zero-extended when unsigned and
+        // sign-extended when signed
+        returns("add(u24,u24)", &[0xFF_FFFF, 1], 0);
+        returns("add(u24,u24)", &[0xFF_FFFF, 3], 2);
+        returns("below_zero()", &[], 0xFF_FFFF);
+        returns("past_max()", &[], 1);
+        returns("signed_add(i24,i24)", &[0x7F_FFFF, 1], -0x80_0000i32 as u32);
+        returns("signed_mul(i24,i24)", &[0x40_0000, 2], -0x80_0000i32 as u32);
+        returns(
+            "signed_add(i24,i24)",
+            &[-0x80_0000i32 as u32, -1i32 as u32],
+            0x7F_FFFF,
+        );
+        returns("sub(u32,u32)", &[1, 2], u32::MAX);
+        assert!(matches!(
+            dispatch(
+                source,
+                call_data(selector_for("checked_after(u32,u32)"), &[1, 2])
+            ),
+            ExecutionResult::Revert { .. }
+        ));
+    }
+
+    #[test]
+    fn test_wide_storage_values() {
+        let source = r#"
+            storage {
+                supply: u256,
+                balances: StorageMap<u24, u128>,
+            }
+
+            #[message]
+            fn mint(to: u24, amount: u128) -> u256 {
+                balances.insert(to, balances.get(to) + amount);
+                supply = supply + amount;
+                return supply;
+            }
+        "#;
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap();
+
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        let amount = u64::MAX as u128 + 1;
+        for (to, expected_supply) in [(3u32, amount), (3, 2 * amount), (4, 3 * amount)] {
+            let mut input = selector_for("mint(u24,u128)").to_vec();
+            input.extend(to.to_le_bytes());
+            input.extend(wide(amount, 128));
+            interpreter.environment_mut().context.input = input;
+            match interpreter.execute(&instructions).unwrap() {
+                ExecutionResult::Success { data, .. } => {
+                    assert_eq!(data, wide(expected_supply, 256))
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        let balances = StorageMap::<u32, u128>::new("balances");
+        let storage = &interpreter.environment().storage;
+        let entry = |owner: u32| storage.get(&balances.entry_key(&owner).to_vec()).cloned();
+        assert_eq!(entry(3), Some(wide(2 * amount, 128)));
+        assert_eq!(entry(4), Some(wide(amount, 128)));
+        assert_eq!(
+            storage.get(&compute_storage_key("supply").to_vec()),
+            Some(&wide(3 * amount, 256))
+        );
+    }
+
+    #[test]
+    fn test_tuple_storage_map_keys() {
+        let source = r#"
+            storage {
+                allowance: StorageMap<(Address, u24), u128>,
+            }
+
+            #[message]
+            fn approve(spender: u24, amount: u128) -> u128 {
+                allowance.insert((caller(), spender), amount);
+                allowance.insert((caller(), spender + 1), amount + 1u128);
+                return allowance.get((caller(), spender));
+            }
+        "#;
+        let amount = u64::MAX as u128 + 3;
+        let mut input = selector_for("approve(u24,u128)").to_vec();
+        input.extend(7u32.to_le_bytes());
+        input.extend(wide(amount, 128));
+
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap();
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        interpreter.environment_mut().context.input = input;
+        match interpreter.execute(&instructions).unwrap() {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, wide(amount, 128)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // The elements of the key follow each other under the entry
+        let root = compute_storage_key("allowance");
+        let caller = interpreter.environment().context.caller.as_bytes().to_vec();
+        let entry = |spender: u32| {
+            let key = derive_entry_key(
+                &root,
+                &[caller.clone(), spender.to_le_bytes().to_vec()].concat(),
+            );
+            interpreter
+                .environment()
+                .storage
+                .get(key.as_slice())
+                .cloned()
+        };
+        assert_eq!(entry(7), Some(wide(amount, 128)));
+        assert_eq!(entry(8), Some(wide(amount + 1, 128)));
+    }
+
+    #[test]
+    fn test_wide_event_fields() {
+        let source = r#"
+            event Minted {
+                indexed id: u24,
+                amount: u128,
+                to: Address,
+                count: u24,
+            }
+
+            #[message]
+            fn mint(id: u24, amount: u128) -> u24 {
+                emit Minted(id, amount, caller(), 2);
+                return id;
+            }
+        "#;
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap();
+
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        let amount = u64::MAX as u128 + 5;
+        let mut input = selector_for("mint(u24,u128)").to_vec();
+        input.extend(7u32.to_le_bytes());
+        input.extend(wide(amount, 128));
+        interpreter.environment_mut().context.input = input;
+        let result = interpreter.execute(&instructions).unwrap();
+        assert!(
+            matches!(result, ExecutionResult::Success { .. }),
+            "{:?}",
+            result
+        );
+
+        // Wide fields take all their bytes in the data
+        let events = &interpreter.environment().events;
+        assert_eq!(events.len(), 1);
+        let caller = interpreter.environment().context.caller.as_bytes().to_vec();
+        let data = [wide(amount, 128), caller, 2u32.to_le_bytes().to_vec()].concat();
+        assert_eq!(events[0].data, data);
+    }
+
+    #[test]
+    fn test_address_and_hash_values() {
+        let source = r#"
+            storage {
+                credits: StorageMap<Address, u128>,
+            }
+
+            #[message]
+            fn is_alice(who: Address) -> u24 {
+                return who == @5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY;
+            }
+
+            #[message]
+            fn is_unset(who: Address) -> u24 {
+                return who == ZERO_ADDRESS;
+            }
+
+            #[message]
+            fn code_of(who: Address) -> Hash {
+                return to_hash(who);
+            }
+
+            #[message]
+            fn credit(who: Address, amount: u128) -> u128 {
+                credits.insert(who, credits.get(who) + amount);
+                return credits.get(who);
+            }
+        "#;
+        let alice = Address::from_ss58("5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY").unwrap();
+        let call = |signature: &str, args: &[Vec<u8>]| wide_call(source, signature, args);
+        let yes = 1u32.to_le_bytes().to_vec();
+        let no = 0u32.to_le_bytes().to_vec();
+
+        assert_eq!(call("is_alice(Address)", &[alice.as_bytes().to_vec()]), yes);
+        assert_eq!(
+            call("is_alice(Address)", &[account(2).as_bytes().to_vec()]),
+            no
+        );
+        assert_eq!(call("is_unset(Address)", &[vec![0; 32]]), yes);
+        assert_eq!(call("is_unset(Address)", &[alice.as_bytes().to_vec()]), no);
+        assert_eq!(
+            call("code_of(Address)", &[alice.as_bytes().to_vec()]),
+            alice.as_bytes().to_vec()
+        );
+
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap();
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        for (who, expected) in [(alice, 5), (alice, 10), (account(3), 5)] {
+            let mut input = selector_for("credit(Address,u128)").to_vec();
+            input.extend(who.as_bytes());
+            input.extend(wide(5, 128));
+            interpreter.environment_mut().context.input = input;
+            match interpreter.execute(&instructions).unwrap() {
+                ExecutionResult::Success { data, .. } => assert_eq!(data, wide(expected, 128)),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        let credits = StorageMap::<Address, u128>::new("credits");
+        let storage = &interpreter.environment().storage;
+        let entry = |who: Address| storage.get(&credits.entry_key(&who).to_vec()).cloned();
+        assert_eq!(entry(alice), Some(wide(10, 128)));
+        assert_eq!(entry(account(3)), Some(wide(5, 128)));
+    }
+
+    #[test]
+    fn test_maps() {
+        let source = r#"
+            storage {
+                owners: Map<u24, Address>,
+                totals: Map<Address, u128>,
+            }
+
+            #[message]
+            fn lookup(key: u24) -> u24 {
+                prices = {5: 50, 2: 20, 8: 80, 1: 10};
+                prices[2] = 21;
+                prices[9] = prices[8] + 1;
+                return prices[key];
+            }
+
+            #[message]
+            fn fresh(key: u24) -> u24 {
+                counts = Map::new();
+                counts[key] = 7;
+                return counts[key] + counts[key + 1];
+            }
+
+            #[message]
+            fn claim(token: u24, owner: Address) -> Address {
+                owners[token] = owner;
+                return owners[token];
+            }
+
+            #[message]
+            fn credit(who: Address, amount: u128) -> u128 {
+                totals[who] = totals[who] + amount;
+                return totals[who];
+            }
+        "#;
+        let word = |value: u32| value.to_le_bytes().to_vec();
+        for (key, price) in [(1, 10), (2, 21), (5, 50), (8, 80), (9, 81), (7, 0)] {
+            assert_eq!(wide_call(source, "lookup(u24)", &[word(key)]), word(price));
+        }
+        assert_eq!(wide_call(source, "fresh(u24)", &[word(4)]), word(7));
+
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap();
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        let mut run = |signature: &str, args: &[Vec<u8>]| {
+            let mut input = selector_for(signature).to_vec();
+            input.extend(args.concat());
+            interpreter.environment_mut().context.input = input;
+            match interpreter.execute(&instructions).unwrap() {
+                ExecutionResult::Success { data, .. } => data,
+                other => panic!("unexpected result for {}: {:?}", signature, other),
+            }
+        };
+        let owner = account(9).as_bytes().to_vec();
+        assert_eq!(run("claim(u24,Address)", &[word(3), owner.clone()]), owner);
+        assert_eq!(
+            run("credit(Address,u128)", &[owner.clone(), wide(5, 128)]),
+            wide(5, 128)
+        );
+        assert_eq!(
+            run("credit(Address,u128)", &[owner, wide(6, 128)]),
+            wide(11, 128)
+        );
+
+        // Map fields are laid out like storage maps
+        let storage = &interpreter.environment().storage;
+        let owners = StorageMap::<u32, Address>::new("owners");
+        let totals = StorageMap::<Address, u128>::new("totals");
+        assert_eq!(
+            storage.get(&owners.entry_key(&3).to_vec()),
+            Some(&account(9).as_bytes().to_vec())
+        );
+        assert_eq!(
+            storage.get(&totals.entry_key(&account(9)).to_vec()),
+            Some(&wide(11, 128))
+        );
+    }
+
+    #[test]
+    fn test_shared_maps_are_copied_before_writes() {
+        let compile = |source: &str, dispatcher: bool| {
+            let mut program = Parser::new(source).parse_program().unwrap();
+            Linearity::new().lower_program(&mut program);
+            let mut codegen = RiscVCodegen::new();
+            if dispatcher {
+                codegen = codegen.with_dispatcher();
+            }
+            codegen.generate(&program).unwrap()
+        };
+
+        let instructions = compile(
+            r#"
+            #[message]
+            fn alias(key: u24) -> u24 {
+                a = {5: 50, 2: 20, 8: 80, 1: 10, 9: 90};
+                b = a;
+                b[2] = 21;
+                b[7] = 70;
+                return a[key] * 1000 + b[key];
+            }
+        "#,
+            true,
+        );
+        for (key, value) in [(1, 10_010), (2, 20_021), (7, 70), (8, 80_080), (9, 90_090)] {
+            let mut context = ExecutionContext::new_default();
+            context.input = call_data(selector_for("alias(u24)"), &[key]);
+            match Interpreter::new(context).execute(&instructions).unwrap() {
+                ExecutionResult::Success { data, .. } => {
+                    assert_eq!(data, (value as u32).to_le_bytes().to_vec())
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        // The caller keeps its map when the callee writes its parameter
+        let instructions = compile(
+            r#"
+            fn main() -> u24 {
+                a = {1: 2};
+                b = bump(a);
+                return a[1] * 100 + b;
+            }
+
+            #[message]
+            fn bump(m: Map<u24, u24>) -> u24 {
+                m[1] = 9;
+                return m[1];
+            }
+        "#,
+            false,
+        );
+        match run(&instructions) {
+            ExecutionResult::Success { data, .. } => {
+                assert_eq!(data, 209u32.to_le_bytes().to_vec())
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_heap_allocation() {
+        let mut interpreter =
+            Interpreter::new(ExecutionContext::new_default()).with_memory_size(256);
+        interpreter.environment_mut().context.input = vec![1, 2, 3];
+        interpreter.reset().unwrap();
+
+        // Allocations start after the input and are aligned
+        assert!(matches!(interpreter.allocate(4), Ok(8)));
+        assert!(matches!(interpreter.allocate(16), Ok(16)));
+        // An allocation reaching the stack fails
+        assert!(matches!(interpreter.allocate(1024), Ok(0)));
+        assert!(matches!(interpreter.allocate(u32::MAX), Ok(0)));
+    }
+
+    #[test]
+    fn test_bytes_values() {
+        let source = r#"
+            #[message]
+            fn echo(data: Bytes) -> Bytes {
+                return data;
+            }
+
+            #[message]
+            fn size(data: Bytes) -> u24 {
+                return len(data);
+            }
+
+            #[message]
+            fn join(a: Bytes, b: Bytes) -> Bytes {
+                return concat(a, b);
+            }
+
+            #[message]
+            fn middle(data: Bytes, start: u24, end: u24) -> Bytes {
+                return slice(data, start, end);
+            }
+
+            #[message]
+            fn is_magic(data: Bytes) -> u24 {
+                return data == hex"deadbeef";
+            }
+
+            #[message]
+            fn differs(a: Bytes, b: Bytes) -> u24 {
+                return a != b;
+            }
+
+            #[message]
+            fn digest(data: Bytes) -> Hash {
+                return keccak256(data);
+            }
+
+            #[message]
+            fn roll(subject: Bytes) -> Hash {
+                return random(subject);
+            }
+
+            #[message]
+            fn greeting() -> Bytes {
+                hello = hex"68656c6c6f";
+                return concat(hello, hex"21");
+            }
+        "#;
+        let bytes = |data: &[u8]| Bytes::from(data).scale_encode();
+        let word = |value: u32| value.to_le_bytes().to_vec();
+        let call = |signature: &str, args: &[Vec<u8>]| wide_call(source, signature, args);
+        let long = vec![7u8; 70];
+
+        assert_eq!(call("echo(Bytes)", &[bytes(&long)]), bytes(&long));
+        assert_eq!(call("echo(Bytes)", &[bytes(&[])]), bytes(&[]));
+        assert_eq!(call("size(Bytes)", &[bytes(&long)]), word(70));
+        assert_eq!(
+            call("join(Bytes,Bytes)", &[bytes(&[1, 2]), bytes(&[3])]),
+            bytes(&[1, 2, 3])
+        );
+        assert_eq!(
+            call(
+                "middle(Bytes,u24,u24)",
+                &[bytes(&[1, 2, 3, 4]), word(1), word(3)]
+            ),
+            bytes(&[2, 3])
+        );
+        assert_eq!(
+            call("is_magic(Bytes)", &[bytes(&[0xde, 0xad, 0xbe, 0xef])]),
+            word(1)
+        );
+        assert_eq!(call("is_magic(Bytes)", &[bytes(&[0xde, 0xad])]), word(0));
+        assert_eq!(
+            call("differs(Bytes,Bytes)", &[bytes(&[1, 2]), bytes(&[1, 3])]),
+            word(1)
+        );
+        assert_eq!(
+            call("differs(Bytes,Bytes)", &[bytes(&long), bytes(&long)]),
+            word(0)
+        );
+        assert_eq!(
+            call("digest(Bytes)", &[bytes(b"abc")]),
+            CryptoFunctions::keccak256(b"abc").to_vec()
+        );
+        assert_eq!(
+            call("roll(Bytes)", &[bytes(b"dice")]),
+            ExecutionContext::new_default()
+                .random(b"dice")
+                .as_bytes()
+                .to_vec()
+        );
+        assert_eq!(call("greeting()", &[]), bytes(b"hello!"));
+
+        // Out of range slices, truncated and trailing input revert
+        let mut truncated = selector_for("echo(Bytes)").to_vec();
+        truncated.extend(&bytes(&long)[..40]);
+        let mut trailing = selector_for("echo(Bytes)").to_vec();
+        trailing.extend(bytes(&[1]));
+        trailing.push(0);
+        let mut out_of_range = selector_for("middle(Bytes,u24,u24)").to_vec();
+        out_of_range.extend([bytes(&[1, 2, 3, 4]), word(3), word(5)].concat());
+        for input in [truncated, trailing, out_of_range] {
+            assert!(matches!(
+                dispatch(source, input),
+                ExecutionResult::Revert { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_wide_values_in_bytes() {
+        let source = r#"
+            #[message]
+            fn pack(a: u256, b: u128, who: Address) -> Bytes {
+                return concat(concat(to_bytes(a), to_bytes(b)), to_bytes(who));
+            }
+
+            #[message]
+            fn sum(data: Bytes) -> u256 {
+                return u256_at(data, 0) + u256_at(data, 32);
+            }
+        "#;
+        let bytes = |data: &[u8]| Bytes::from(data).scale_encode();
+        let call = |signature: &str, args: &[Vec<u8>]| wide_call(source, signature, args);
+        let who = account(9);
+
+        let packed = [wide(3, 256), wide(4, 128), who.as_bytes().to_vec()].concat();
+        assert_eq!(
+            call(
+                "pack(u256,u128,Address)",
+                &[wide(3, 256), wide(4, 128), who.as_bytes().to_vec()]
+            ),
+            bytes(&packed)
+        );
+        let pair = [wide(5, 256), wide(1 << 100, 256)].concat();
+        assert_eq!(
+            call("sum(Bytes)", &[bytes(&pair)]),
+            wide((1 << 100) + 5, 256)
+        );
+
+        // Reading past the end reverts
+        let mut input = selector_for("sum(Bytes)").to_vec();
+        input.extend(bytes(&pair[..63]));
+        assert!(matches!(
+            dispatch(source, input),
+            ExecutionResult::Revert { .. }
+        ));
+    }
+
+    #[test]
+    fn test_bool_values() {
+        let source = r#"
+            #[message]
+            fn negate(flag: Bool) -> Bool {
+                return !flag;
+            }
+
+            #[message]
+            fn both(a: Bool, b: Bool) -> Bool {
+                return a and b;
+            }
+
+            #[message]
+            fn either(a: Bool, b: Bool) -> Bool {
+                return a || b;
+            }
+
+            #[message]
+            fn in_window(value: u24) -> Bool {
+                return value != 0 && value - 1 < 5;
+            }
+
+            #[message]
+            fn pick(flag: Bool, a: u24, b: u24) -> u24 {
+                if not flag {
+                    return b;
+                } else {
+                    return a;
+                }
+            }
+        "#;
+        let word = |value: u32| value.to_le_bytes().to_vec();
+        let flag = |value: bool| vec![value as u8];
+        let call = |signature: &str, args: &[Vec<u8>]| wide_call(source, signature, args);
+
+        assert_eq!(call("negate(Bool)", &[flag(true)]), flag(false));
+        assert_eq!(call("negate(Bool)", &[flag(false)]), flag(true));
+        for (a, b) in [(false, false), (false, true), (true, false), (true, true)] {
+            assert_eq!(call("both(Bool,Bool)", &[flag(a), flag(b)]), flag(a && b));
+            assert_eq!(call("either(Bool,Bool)", &[flag(a), flag(b)]), flag(a || b));
+        }
+        assert_eq!(
+            call("pick(Bool,u24,u24)", &[flag(true), word(1), word(2)]),
+            word(1)
+        );
+        assert_eq!(
+            call("pick(Bool,u24,u24)", &[flag(false), word(1), word(2)]),
+            word(2)
+        );
+
+        // The checked subtraction would revert if it were evaluated for 0
+        assert_eq!(call("in_window(u24)", &[word(0)]), flag(false));
+        assert_eq!(call("in_window(u24)", &[word(3)]), flag(true));
+        assert_eq!(call("in_window(u24)", &[word(9)]), flag(false));
+
+        // A Bool argument other than 0 or 1 reverts
+        let mut input = selector_for("negate(Bool)").to_vec();
+        input.push(2);
+        assert!(matches!(
+            dispatch(source, input),
+            ExecutionResult::Revert { .. }
+        ));
+    }
+
+    #[test]
+    fn test_revert_statements_return_error_data() {
+        let source = r#"
+            type TokenError {
+                InsufficientBalance(needed: u24, available: u24),
+                Frozen(account: u24, forever: Bool),
+            }
+
+            storage {
+                counter: u24,
+            }
+
+            #[message]
+            fn withdraw(amount: u24) -> u24 {
+                counter = amount;
+                require(amount <= 10, TokenError/InsufficientBalance(amount, 10));
+                return amount;
+            }
+
+            #[message]
+            fn check(value: u24) -> u24 {
+                assert(value != 0, "value is zero");
+                require(value < 100, "value too large");
+                require(value != 7);
+                assert(value != 8);
+                return value;
+            }
+
+            #[message]
+            fn freeze(account: u24) -> u24 {
+                revert(TokenError/Frozen(account, true));
+            }
+        "#;
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap();
+        let execute = |signature: &str, arg: u32| {
+            let mut context = ExecutionContext::new_default();
+            context.input = call_data(selector_for(signature), &[arg]);
+            let mut interpreter = Interpreter::new(context);
+            let result = interpreter.execute(&instructions).unwrap();
+            (result, interpreter.into_environment())
+        };
+        let reverted = |signature: &str, arg: u32| match execute(signature, arg).0 {
+            ExecutionResult::Revert { data, .. } => data,
+            other => panic!("unexpected result for {}: {:?}", signature, other),
+        };
+        let message = |signature: &str, text: &str| {
+            [
+                selector_for(signature).to_vec(),
+                Bytes::from(text.as_bytes()).scale_encode(),
+            ]
+            .concat()
+        };
+
+        // The storage write before a failing require is rolled back
+        let (result, environment) = execute("withdraw(u24)", 11);
+        match result {
+            ExecutionResult::Revert { data, .. } => assert_eq!(
+                data,
+                [
+                    selector_for("TokenError/InsufficientBalance(u24,u24)").to_vec(),
+                    11u32.to_le_bytes().to_vec(),
+                    10u32.to_le_bytes().to_vec(),
+                ]
+                .concat()
+            ),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(!environment
+            .storage
+            .contains_key(compute_storage_key("counter").as_slice()));
+
+        let (result, environment) = execute("withdraw(u24)", 5);
+        assert!(matches!(result, ExecutionResult::Success { .. }));
+        assert_eq!(
+            environment
+                .storage
+                .get(compute_storage_key("counter").as_slice()),
+            Some(&5u32.to_le_bytes().to_vec())
+        );
+
+        assert_eq!(
+            reverted("check(u24)", 0),
+            message("Panic(String)", "value is zero")
+        );
+        assert_eq!(
+            reverted("check(u24)", 100),
+            message("Error(String)", "value too large")
+        );
+        assert_eq!(reverted("check(u24)", 7), Vec::<u8>::new());
+        assert_eq!(
+            reverted("check(u24)", 8),
+            message("Panic(String)", "assertion failed")
+        );
+        assert!(matches!(
+            execute("check(u24)", 9).0,
+            ExecutionResult::Success { .. }
+        ));
+
+        assert_eq!(
+            reverted("freeze(u24)", 0x0102_0304),
+            [
+                selector_for("TokenError/Frozen(u24,Bool)").to_vec(),
+                vec![4, 3, 2, 1, 1],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_try_propagates_errors() {
+        let source = r#"
+            error TokenError {
+                InsufficientBalance(needed: u24, available: u24),
+                Paused,
+            }
+
+            storage {
+                counter: u24,
+            }
+
+            #[message]
+            fn withdraw(amount: u24, available: u24) -> Result<u24, TokenError> {
+                if amount > available {
+                    return Result/Err(TokenError/InsufficientBalance(amount, available));
+                } else {
+                    return Result/Ok(available - amount);
+                }
+            }
+
+            #[message]
+            fn transfer(amount: u24) -> Result<u24, TokenError> {
+                counter = amount;
+                left = withdraw(amount, 10)?;
+                return Result/Ok(left + withdraw(1, left)?);
+            }
+
+            #[message]
+            fn pause() -> Result<u24, TokenError> {
+                return Result/Err(TokenError/Paused);
+            }
+        "#;
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap();
+        let execute = |input: Vec<u8>| {
+            let mut context = ExecutionContext::new_default();
+            context.input = input;
+            let mut interpreter = Interpreter::new(context);
+            let result = interpreter.execute(&instructions).unwrap();
+            (result, interpreter.into_environment())
+        };
+        let transfer = |amount: u32| execute(call_data(selector_for("transfer(u24)"), &[amount]));
+        let insufficient = |needed: u32, available: u32| {
+            [
+                selector_for("TokenError/InsufficientBalance(u24,u24)").to_vec(),
+                needed.to_le_bytes().to_vec(),
+                available.to_le_bytes().to_vec(),
+            ]
+            .concat()
+        };
+
+        // 10 - 3 = 7, then 7 - 1 = 6
+        match transfer(3).0 {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 13u32.to_le_bytes().to_vec()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // The first withdrawal fails, and the storage write is rolled back
+        let (result, environment) = transfer(11);
+        match result {
+            ExecutionResult::Revert { data, .. } => assert_eq!(data, insufficient(11, 10)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(!environment
+            .storage
+            .contains_key(compute_storage_key("counter").as_slice()));
+
+        // The second one fails in the middle of an expression
+        match transfer(10).0 {
+            ExecutionResult::Revert { data, .. } => assert_eq!(data, insufficient(1, 0)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        match execute(call_data(selector_for("pause()"), &[])).0 {
+            ExecutionResult::Revert { data, .. } => {
+                assert_eq!(data, selector_for("TokenError/Paused()").to_vec())
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_loops_are_metered() {
+        let source = r#"
+            #[message]
+            fn sum_below(n: u24) -> u24 {
+                total = 0;
+                i = 0;
+                while i < n {
+                    total = total + i;
+                    i = i + 1;
+                }
+                return total;
+            }
+
+            #[message]
+            fn sum_range(start: u24, end: u24) -> u24 {
+                total = 0;
+                for i in range(start, end) bound 4 {
+                    total = total + i;
+                }
+                return total;
+            }
+        "#;
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap();
+        let execute = |signature: &str, args: &[u32], gas_limit: u64| {
+            let mut context = ExecutionContext::new_default();
+            context.input = call_data(selector_for(signature), args);
+            context.gas_limit = gas_limit;
+            Interpreter::new(context).execute(&instructions).unwrap()
+        };
+        let returned = |signature: &str, args: &[u32]| match execute(signature, args, 1_000_000) {
+            ExecutionResult::Success { data, .. } => data,
+            other => panic!("unexpected result for {}: {:?}", signature, other),
+        };
+
+        assert_eq!(
+            returned("sum_below(u24)", &[5]),
+            10u32.to_le_bytes().to_vec()
+        );
+        assert_eq!(
+            returned("sum_below(u24)", &[0]),
+            0u32.to_le_bytes().to_vec()
+        );
+        assert_eq!(
+            returned("sum_range(u24,u24)", &[3, 7]),
+            18u32.to_le_bytes().to_vec()
+        );
+        assert_eq!(
+            returned("sum_range(u24,u24)", &[7, 3]),
+            0u32.to_le_bytes().to_vec()
+        );
+
+        // A fifth iteration exceeds the bound
+        match execute("sum_range(u24,u24)", &[0, 5], 1_000_000) {
+            ExecutionResult::Revert { data, .. } => assert_eq!(
+                data,
+                [
+                    selector_for("Panic(String)").to_vec(),
+                    Bytes::from("loop bound exceeded".as_bytes()).scale_encode(),
+                ]
+                .concat()
+            ),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Every iteration charges gas on top of its instructions
+        let gas_used = |n: u32| match execute("sum_below(u24)", &[n], 1_000_000) {
+            ExecutionResult::Success { gas_used, .. } => gas_used,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        assert!(gas_used(2) - gas_used(1) > LOOP_ITERATION_GAS as u64);
+
+        match execute("sum_below(u24)", &[1_000_000], 100_000) {
+            ExecutionResult::Failure { reason, .. } => assert_eq!(reason, "Gas limit exceeded"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_storage_fields_persist_through_host_calls() {
+        let source = r#"
+            storage {
+                counter: u24,
+                owner: u24,
+            }
+
+            fn main() -> u24 {
+                counter = 41;
+                return counter;
+            }
+        "#;
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new().generate(&program).unwrap();
+
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        match interpreter.execute(&instructions).unwrap() {
+            ExecutionResult::Success { data, .. } => {
+                assert_eq!(data, 41u32.to_le_bytes().to_vec())
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let storage = &interpreter.environment().storage;
+        assert_eq!(
+            storage.get(compute_storage_key("counter").as_slice()),
+            Some(&41u32.to_le_bytes().to_vec())
+        );
+        assert!(!storage.contains_key(compute_storage_key("owner").as_slice()));
+    }
+
+    #[test]
+    fn test_unset_storage_field_reads_as_zero() {
+        let source = r#"
+            storage {
+                owner: u24,
+            }
+
+            fn main() -> u24 {
+                return owner;
+            }
+        "#;
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new().generate(&program).unwrap();
+
+        match run(&instructions) {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, vec![0; 4]),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    fn run_source(source: &str) -> (ExecutionResult, Environment) {
+        let program = Parser::new(source).parse_program().unwrap();
+        let instructions = RiscVCodegen::new().generate(&program).unwrap();
+
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        let result = interpreter.execute(&instructions).unwrap();
+        (result, interpreter.into_environment())
+    }
+
+    #[test]
+    fn test_storage_collections_use_per_entry_keys() {
+        let (result, environment) = run_source(
+            r#"
+            storage {
+                balances: StorageMap<u24, u24>,
+                holders: StorageVec<u24>,
+            }
+
+            fn main() -> u24 {
+                balances.insert(7, 100);
+                holders.push(7);
+                holders.push(9);
+                return balances.get(7);
+            }
+        "#,
+        );
+        match result {
+            ExecutionResult::Success { data, .. } => {
+                assert_eq!(data, 100u32.to_le_bytes().to_vec())
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let balances = StorageMap::<u32, u32>::new("balances");
+        let holders = StorageVec::<u32>::new("holders");
+        let storage = &environment.storage;
+        assert_eq!(
+            storage.get(balances.entry_key(&7).as_slice()),
+            Some(&100u32.to_le_bytes().to_vec())
+        );
+        assert_eq!(
+            storage.get(holders.root().as_slice()),
+            Some(&2u32.to_le_bytes().to_vec())
+        );
+        assert_eq!(
+            storage.get(holders.element_key(1).as_slice()),
+            Some(&9u32.to_le_bytes().to_vec())
+        );
+        assert_eq!(storage.len(), 4);
+    }
+
+    #[test]
+    fn test_storage_collection_reads() {
+        let (result, _) = run_source(
+            r#"
+            storage {
+                balances: StorageMap<u24, u24>,
+                holders: StorageVec<u24>,
+            }
+
+            fn main() -> u24 {
+                holders.push(3);
+                holders.push(5);
+                let last = holders.pop();
+                let missing = balances.contains(8);
+                return holders.len();
+            }
+        "#,
+        );
+        match result {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 1u32.to_le_bytes().to_vec()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let (result, _) = run_source(
+            r#"
+            storage {
+                holders: StorageVec<u24>,
+            }
+
+            fn main() -> u24 {
+                return holders.get(5);
+            }
+        "#,
+        );
+        assert!(matches!(result, ExecutionResult::Revert { .. }));
+    }
+
+    #[test]
+    fn test_storage_vectors_of_wide_values() {
+        let source = r#"
+            storage {
+                amounts: StorageVec<u128>,
+                holders: StorageVec<Address>,
+            }
+
+            #[message]
+            fn main() -> u128 {
+                amounts.push(5);
+                amounts.push(7);
+                amounts.push(9);
+                amounts.set(0, amounts.get(1) + 1);
+                amounts.pop();
+                holders.push(caller());
+                let last = amounts.pop();
+                return amounts.get(0) + last + amounts.len();
+            }
+
+            #[message]
+            fn past_end() -> u128 {
+                return amounts.get(amounts.len());
+            }
+        "#;
+        let instructions = contract(source);
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        interpreter.environment_mut().context.input = selector_for("main()").to_vec();
+        match interpreter.execute(&instructions).unwrap() {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, wide(16, 128)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Elements take all their bytes, under the keys of word elements
+        let amounts = StorageVec::<u128>::new("amounts");
+        let holders = StorageVec::<Address>::new("holders");
+        let caller = interpreter.environment().context.caller;
+        let storage = &interpreter.environment().storage;
+        assert_eq!(
+            storage.get(amounts.root().as_slice()),
+            Some(&1u32.to_le_bytes().to_vec())
+        );
+        assert_eq!(
+            storage.get(amounts.element_key(0).as_slice()),
+            Some(&wide(8, 128))
+        );
+        assert_eq!(storage.get(amounts.element_key(1).as_slice()), None);
+        assert_eq!(
+            storage.get(holders.element_key(0).as_slice()),
+            Some(&caller.as_bytes().to_vec())
+        );
+
+        interpreter.environment_mut().context.input = selector_for("past_end()").to_vec();
+        let result = interpreter.execute(&instructions).unwrap();
+        assert!(matches!(result, ExecutionResult::Revert { .. }));
+    }
+
+    #[test]
+    fn test_revert_rolls_back_storage_writes() {
+        let (result, environment) = run_source(
+            r#"
+            storage {
+                counter: u24,
+                holders: StorageVec<u24>,
+            }
+
+            fn main() -> u24 {
+                counter = 41;
+                holders.push(7);
+                return holders.get(5);
+            }
+        "#,
+        );
+        assert!(matches!(result, ExecutionResult::Revert { .. }));
+        assert!(environment.storage.is_empty());
+        assert_eq!(environment.checkpoint_depth(), 0);
+    }
+
+    fn contract(source: &str) -> Vec<Instruction> {
+        let program = Parser::new(source).parse_program().unwrap();
+        RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap()
+    }
+
+    fn account(id: u8) -> Address {
+        let mut address = [0u8; 32];
+        address[0] = id;
+        Address::new(address)
+    }
+
+    const TOKEN: &str = r#"
+        storage {
+            counter: u24,
+            holders: StorageVec<u24>,
+        }
+
+        #[message]
+        #[payable]
+        fn second(a: u24, b: u24) -> u24 {
+            counter = b;
+            return b;
+        }
+
+        #[message]
+        #[payable]
+        fn fail(a: u24) -> u24 {
+            counter = a;
+            return holders.get(a);
+        }
+    "#;
+
+    fn run_with_callee(caller: &str, callee: &str) -> (ExecutionResult, Environment) {
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        interpreter
+            .environment_mut()
+            .deploy(account(2), contract(callee));
+        let program = lower_program(Parser::new(caller).parse_program().unwrap());
+        let instructions = RiscVCodegen::new().generate(&program).unwrap();
+        let result = interpreter.execute(&instructions).unwrap();
+        (result, interpreter.into_environment())
+    }
+
+    #[test]
+    fn test_call_returns_callee_result() {
+        let (result, environment) = run_with_callee(
+            r#"
+            fn main() -> u24 {
+                return call(2, 0, 0, "second(u24,u24)", 5, 9);
+            }
+        "#,
+            TOKEN,
+        );
+        match result {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 9u32.to_le_bytes().to_vec()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // The callee wrote to its own storage, not the caller's
+        let key = compute_storage_key("counter").to_vec();
+        assert!(environment.storage.is_empty());
+        assert_eq!(
+            environment.accounts[&account(2)].storage.get(&key),
+            Some(&9u32.to_le_bytes().to_vec())
+        );
+        assert_eq!(environment.checkpoint_depth(), 0);
+    }
+
+    #[test]
+    fn test_interface_calls() {
+        let (result, environment) = run_with_callee(
+            r#"
+            interface IToken {
+                fn second(a: u24, b: u24) -> u24;
+            }
+
+            fn main() -> u24 {
+                return IToken(2).second(5, 9) + IToken(2, gas: 0).second(1, 2);
+            }
+        "#,
+            TOKEN,
+        );
+        match result {
+            ExecutionResult::Success { data, .. } => {
+                assert_eq!(data, 11u32.to_le_bytes().to_vec())
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // The binding reached the callee's dispatcher with its selector
+        let key = compute_storage_key("counter").to_vec();
+        assert_eq!(
+            environment.accounts[&account(2)].storage.get(&key),
+            Some(&2u32.to_le_bytes().to_vec())
+        );
+    }
+
+    #[test]
+    fn test_callee_revert_reverts_caller() {
+        let (result, environment) = run_with_callee(
+            r#"
+            storage {
+                counter: u24,
+            }
+
+            fn main() -> u24 {
+                counter = 1;
+                return call(2, 0, 0, "fail(u24)", 4);
+            }
+        "#,
+            TOKEN,
+        );
+        assert!(matches!(result, ExecutionResult::Revert { .. }));
+        assert!(environment.storage.is_empty());
+        assert!(environment.accounts[&account(2)].storage.is_empty());
+    }
+
+    #[test]
+    fn test_delegate_call_uses_caller_storage() {
+        let (result, environment) = run_with_callee(
+            r#"
+            fn main() -> u24 {
+                return delegate_call(2, 0, "second(u24,u24)", 5, 11);
+            }
+        "#,
+            TOKEN,
+        );
+        assert!(matches!(result, ExecutionResult::Success { .. }));
+
+        let key = compute_storage_key("counter").to_vec();
+        assert_eq!(
+            environment.storage.get(&key),
+            Some(&11u32.to_le_bytes().to_vec())
+        );
+        assert!(environment.accounts[&account(2)].storage.is_empty());
+    }
+
+    #[test]
+    fn test_calls_pass_wide_arguments() {
+        let ledger = r#"
+            storage {
+                credits: StorageMap<Address, u128>,
+            }
+
+            #[message]
+            fn credit(who: Address, amount: u128) -> u24 {
+                credits.insert(who, credits.get(who) + amount);
+                return 1;
+            }
+
+            #[message]
+            fn is_self(who: Address) -> Bool {
+                return who == address();
+            }
+        "#;
+        let (result, environment) = run_with_callee(
+            r#"
+            interface ILedger {
+                fn credit(who: Address, amount: u128) -> u24;
+            }
+
+            fn main() -> u24 {
+                ILedger(2).credit(caller(), 7);
+                return call(2, 0, 0, "credit(Address,u128)", caller(), 5);
+            }
+        "#,
+            ledger,
+        );
+        match result {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 1u32.to_le_bytes().to_vec()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let credits = StorageMap::<Address, u128>::new("credits");
+        let caller = ExecutionContext::new_default().caller;
+        assert_eq!(
+            environment.accounts[&account(2)]
+                .storage
+                .get(credits.entry_key(&caller).as_slice()),
+            Some(&wide(12, 128))
+        );
+
+        // The callee runs at its own address
+        let (result, _) = run_with_callee(
+            r#"
+            interface ILedger {
+                fn is_self(who: Address) -> Bool;
+            }
+
+            fn main() -> u24 {
+                if ILedger(2).is_self(to_address(2)) {
+                    return 1;
+                } else {
+                    return 0;
+                }
+            }
+        "#,
+            ledger,
+        );
+        match result {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 1u32.to_le_bytes().to_vec()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_call_without_code_uses_mocks() {
+        let program = Parser::new(
+            r#"
+            fn main() -> u24 {
+                return call(3, 0, 0, "price()");
+            }
+        "#,
+        )
+        .parse_program()
+        .unwrap();
+        let caller = RiscVCodegen::new().generate(&program).unwrap();
+
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        interpreter.environment_mut().mocks.mock_call(
+            &hex::encode(account(3)),
+            &selector_for("price()"),
+            250u32.to_le_bytes().to_vec(),
+        );
+        match interpreter.execute(&caller).unwrap() {
+            ExecutionResult::Success { data, .. } => {
+                assert_eq!(data, 250u32.to_le_bytes().to_vec())
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Without a mock the call fails and the caller reverts
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        assert!(matches!(
+            interpreter.execute(&caller).unwrap(),
+            ExecutionResult::Revert { .. }
+        ));
+    }
+
+    #[test]
+    fn test_reentrant_call_is_denied() {
+        // Contract 2 calls back into the caller, deployed at address 0
+        let caller = r#"
+            fn main() -> u24 {
+                return call(2, 0, 0, "bounce()");
+            }
+
+            #[message]
+            fn ping() -> u24 {
+                return 1;
+            }
+        "#;
+        let program = Parser::new(caller).parse_program().unwrap();
+        let instructions = RiscVCodegen::new().generate(&program).unwrap();
+        let bounce = contract(
+            r#"
+            #[message]
+            fn bounce() -> u24 {
+                return call(0, 0, 0, "ping()");
+            }
+        "#,
+        );
+
+        let run = |mode: ProtectionMode| {
+            let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+            let environment = interpreter.environment_mut();
+            environment.reentrancy_guard.set_mode(mode);
+            environment.deploy(account(0), contract(caller));
+            environment.deploy(account(2), bounce.clone());
+            interpreter.execute(&instructions).unwrap()
+        };
+
+        assert!(matches!(
+            run(ProtectionMode::FunctionLevel),
+            ExecutionResult::Revert { .. }
+        ));
+        match run(ProtectionMode::None) {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 1u32.to_le_bytes().to_vec()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_instantiate_creates_account() {
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        interpreter.environment_mut().register_code(
+            account(7).to_hash(),
+            contract(
+                r#"
+                storage {
+                    supply: u24,
+                }
+
+                #[message]
+                fn new(amount: u24) -> u24 {
+                    supply = amount;
+                    return 0;
+                }
+
+                #[message]
+                fn total() -> u24 {
+                    return supply;
+                }
+            "#,
+            ),
+        );
+        let program = Parser::new(
+            r#"
+            fn main() -> u24 {
+                return call(instantiate(7, 0, 0, "new(u24)", 100), 0, 0, "total()");
+            }
+        "#,
+        )
+        .parse_program()
+        .unwrap();
+        let caller = RiscVCodegen::new().generate(&program).unwrap();
+        match interpreter.execute(&caller).unwrap() {
+            ExecutionResult::Success { data, .. } => {
+                assert_eq!(data, 100u32.to_le_bytes().to_vec())
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let environment = interpreter.environment();
+        let created = environment
+            .accounts
+            .iter()
+            .find(|(_, account)| account.code_hash.is_some())
+            .map(|(address, _)| *address)
+            .unwrap();
+        assert!(created.as_bytes()[4..].iter().all(|byte| *byte == 0));
+        assert!(environment.code_at(&created).is_some());
+    }
+
+    #[test]
+    fn test_exports_and_deploy() {
+        let token = contract(
+            r#"
+            storage {
+                supply: u24,
+            }
+
+            #[export("deploy")]
+            fn init() -> u24 {
+                supply = 50;
+                return 0;
+            }
+
+            #[export]
+            fn ping() -> u24 {
+                return 7;
+            }
+
+            #[message]
+            fn total() -> u24 {
+                return supply;
+            }
+        "#,
+        );
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        match interpreter.execute_export(&token, "ping").unwrap() {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 7u32.to_le_bytes()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(matches!(
+            interpreter.execute_export(&token, "missing"),
+            Err(InterpreterError::UndefinedLabel(_))
+        ));
+
+        // Instantiating runs the deploy export, which the dispatcher does not
+        // route calls to
+        interpreter
+            .environment_mut()
+            .register_code(account(7).to_hash(), token);
+        let mut run = |second: &str| {
+            let source = format!(
+                "fn main() -> u24 {{ let token = instantiate(7, 0, 0, \"init()\"); return call(token, 0, 0, \"{}\"); }}",
+                second
+            );
+            let program = Parser::new(&source).parse_program().unwrap();
+            let caller = RiscVCodegen::new().generate(&program).unwrap();
+            interpreter.execute(&caller).unwrap()
+        };
+        match run("total()") {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 50u32.to_le_bytes()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(matches!(run("init()"), ExecutionResult::Revert { .. }));
+    }
+
+    #[test]
+    fn test_contract_getters() {
+        let token = contract(
+            r#"
+            contract Token {
+                pub let supply: u24;
+                pub let balances: StorageMap<u24, u24>;
+
+                fn constructor() {
+                    supply = 50;
+                    balances.insert(3, 9);
+                }
+            }
+        "#,
+        );
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        interpreter
+            .environment_mut()
+            .register_code(account(7).to_hash(), token);
+        let mut run = |getter: &str| {
+            let source = format!(
+                "fn main() -> u24 {{ let token = instantiate(7, 0, 0, \"\"); return call(token, 0, 0, {}); }}",
+                getter
+            );
+            let program = Parser::new(&source).parse_program().unwrap();
+            let caller = RiscVCodegen::new().generate(&program).unwrap();
+            match interpreter.execute(&caller).unwrap() {
+                ExecutionResult::Success { data, .. } => data,
+                other => panic!("unexpected result: {:?}", other),
+            }
+        };
+        assert_eq!(run("\"supply()\""), 50u32.to_le_bytes());
+        assert_eq!(run("\"balances(u24)\", 3"), 9u32.to_le_bytes());
+        assert_eq!(run("\"balances(u24)\", 4"), 0u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_call_transfers_value() {
+        let caller = r#"
+            fn main() -> u24 {
+                return call(2, 40, 0, "second(u24,u24)", 5, 9);
+            }
+        "#;
+        let failing = r#"
+            fn main() -> u24 {
+                return call(2, 40, 0, "fail(u24)", 4);
+            }
+        "#;
+
+        let run = |source: &str| {
+            let program = Parser::new(source).parse_program().unwrap();
+            let instructions = RiscVCodegen::new().generate(&program).unwrap();
+            let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+            let environment = interpreter.environment_mut();
+            environment.deploy(account(2), contract(TOKEN));
+            environment.set_balance(account(0), 100);
+            let result = interpreter.execute(&instructions).unwrap();
+            (result, interpreter.into_environment())
+        };
+
+        let (result, environment) = run(caller);
+        assert!(matches!(result, ExecutionResult::Success { .. }));
+        assert_eq!(environment.balance_of(&account(0)), 60);
+        assert_eq!(environment.balance_of(&account(2)), 40);
+
+        // A reverting callee hands the value back
+        let (result, environment) = run(failing);
+        assert!(matches!(result, ExecutionResult::Revert { .. }));
+        assert_eq!(environment.balance_of(&account(0)), 100);
+        assert_eq!(environment.balance_of(&account(2)), 0);
+    }
+
+    #[test]
+    fn test_terminate_in_callee() {
+        let (result, environment) = run_with_callee(
+            r#"
+            fn main() -> u24 {
+                return call(2, 0, 0, "close()");
+            }
+        "#,
+            r#"
+            storage {
+                counter: u24,
+            }
+
+            fn close() -> u24 {
+                counter = 3;
+                return terminate(9);
+            }
+        "#,
+        );
+        assert!(matches!(result, ExecutionResult::Success { .. }));
+        assert!(environment.code_at(&account(2)).is_none());
+        assert!(!environment.accounts.contains_key(&account(2)));
+    }
+
+    #[test]
+    fn test_set_code_hash_and_migrate() {
+        let upgraded = r#"
+            #[version(2)]
+            storage {
+                counter: u24,
+                doubled: u24,
+            }
+
+            #[message]
+            #[migrate]
+            fn migrate() -> u24 {
+                doubled = counter * 2;
+                return storage_version();
+            }
+
+            #[message]
+            fn read_doubled() -> u24 {
+                return doubled;
+            }
+        "#;
+        let mut code_hash = [0u8; 32];
+        code_hash[0] = 7;
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        let environment = interpreter.environment_mut();
+        environment.deploy(
+            account(2),
+            contract(
+                r#"
+                storage {
+                    counter: u24,
+                }
+
+                #[message]
+                fn upgrade(code_hash: u24) -> u24 {
+                    counter = 21;
+                    return set_code_hash(code_hash);
+                }
+            "#,
+            ),
+        );
+        let program = lower_program(Parser::new(upgraded).parse_program().unwrap());
+        let upgraded = RiscVCodegen::new()
+            .with_dispatcher()
+            .generate(&program)
+            .unwrap();
+        environment.register_code(Hash::new(code_hash), upgraded);
+
+        let mut run = |source: &str| {
+            let program = Parser::new(source).parse_program().unwrap();
+            let instructions = RiscVCodegen::new().generate(&program).unwrap();
+            interpreter.execute(&instructions).unwrap()
+        };
+        let returned = |result: ExecutionResult| match result {
+            ExecutionResult::Success { data, .. } => data,
+            other => panic!("unexpected result: {:?}", other),
+        };
+
+        // Unknown code is refused
+        assert!(matches!(
+            run(r#"fn main() -> u24 { return call(2, 0, 0, "upgrade(u24)", 8); }"#),
+            ExecutionResult::Revert { .. }
+        ));
+        run(r#"fn main() -> u24 { return call(2, 0, 0, "upgrade(u24)", 7); }"#);
+        // The storage was last migrated to version 0
+        let migrate = r#"fn main() -> u24 { return call(2, 0, 0, "migrate()"); }"#;
+        assert_eq!(returned(run(migrate)), 0u32.to_le_bytes().to_vec());
+        assert_eq!(
+            returned(run(
+                r#"fn main() -> u24 { return call(2, 0, 0, "read_doubled()"); }"#
+            )),
+            42u32.to_le_bytes().to_vec()
+        );
+        // Migrations only run once
+        assert!(matches!(run(migrate), ExecutionResult::Revert { .. }));
+    }
+
+    #[test]
+    fn test_chain_extension_host_call() {
+        use crate::compiler::polkavm::host::{
+            ChainExtension, ChainExtensionRegistry, ExtensionType,
+        };
+
+        let mut registry = ChainExtensionRegistry::new();
+        registry
+            .register(
+                ChainExtension::new("oracle_price")
+                    .with_param("base", ExtensionType::U24)
+                    .with_param("quote", ExtensionType::U24)
+                    .with_gas(1_000),
+            )
+            .unwrap();
+
+        let program = Parser::new(
+            r#"
+            fn oracle_price(base: u24, quote: u24) -> u24
+
+            fn main() -> u24 {
+                return oracle_price(3, 4);
+            }
+        "#,
+        )
+        .parse_program()
+        .unwrap();
+        let instructions = RiscVCodegen::new()
+            .with_chain_extensions(&registry)
+            .generate(&program)
+            .unwrap();
+
+        // Without an installed handler the host call traps
+        assert!(matches!(
+            run(&instructions),
+            ExecutionResult::Failure { .. }
+        ));
+
+        let mut interpreter = Interpreter::new(ExecutionContext::new_default());
+        interpreter
+            .environment_mut()
+            .install_chain_extension(registry.get("oracle_price").unwrap(), |args| {
+                Ok(args[0] * 100 + args[1])
+            });
+        match interpreter.execute(&instructions).unwrap() {
+            ExecutionResult::Success { data, gas_used, .. } => {
+                assert_eq!(data, 304u32.to_le_bytes().to_vec());
+                assert!(gas_used > 1_000);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
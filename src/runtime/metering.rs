@@ -58,6 +58,12 @@ pub struct GasCosts {
 
     /// Cost for instruction execution
     pub instruction: u64,
+
+    /// Cost for deriving a storage collection entry key
+    pub key_derivation: u64,
+
+    /// Cost per byte hashed when deriving an entry key
+    pub key_derivation_byte: u64,
 }
 
 impl Default for GasCosts {
@@ -77,6 +83,8 @@ impl Default for GasCosts {
             memory_alloc: 10,
             memory_alloc_byte: 1,
             instruction: 1,
+            key_derivation: 30,
+            key_derivation_byte: 1,
         }
     }
 }
@@ -153,16 +161,28 @@ impl Default for ProofSizeCosts {
 pub struct StorageDepositCosts {
     /// Cost per byte of storage
     pub byte: u128,
+
+    /// Cost per storage item, charged when a key is created
+    pub item: u128,
 }
 
 impl Default for StorageDepositCosts {
     fn default() -> Self {
         StorageDepositCosts {
             byte: 100_000, // 0.0001 token per byte
+            item: 200_000, // 0.0002 token per key
         }
     }
 }
 
+impl StorageDepositCosts {
+    /// Deposit held for a single key storing `value_len` bytes
+    pub fn deposit_for(&self, value_len: usize) -> u128 {
+        self.item
+            .saturating_add((value_len as u128).saturating_mul(self.byte))
+    }
+}
+
 /// Metering context for tracking resources
 #[derive(Clone, Debug)]
 pub struct MeteringContext {
@@ -198,6 +218,15 @@ pub struct MeteringContext {
 
     /// Storage size (key -> size)
     pub storage_sizes: HashMap<Vec<u8>, usize>,
+
+    /// Deposit currently held for each key
+    pub storage_deposits: HashMap<Vec<u8>, u128>,
+
+    /// Total storage deposit charged, before refunds
+    pub storage_deposit_charged: u128,
+
+    /// Total storage deposit refunded by shrinking or deleting keys
+    pub storage_deposit_refunded: u128,
 }
 
 impl MeteringContext {
@@ -215,6 +244,9 @@ impl MeteringContext {
             storage_deposit_used: 0,
             instruction_count: 0,
             storage_sizes: HashMap::new(),
+            storage_deposits: HashMap::new(),
+            storage_deposit_charged: 0,
+            storage_deposit_refunded: 0,
         }
     }
 
@@ -249,9 +281,22 @@ impl MeteringContext {
             return Err(MeteringError::StorageDepositLimitExceeded);
         }
         self.storage_deposit_used = self.storage_deposit_used.saturating_add(amount);
+        self.storage_deposit_charged = self.storage_deposit_charged.saturating_add(amount);
         Ok(())
     }
 
+    /// Return storage deposit released by shrinking or deleting a key
+    pub fn refund_storage_deposit(&mut self, amount: u128) {
+        self.storage_deposit_used = self.storage_deposit_used.saturating_sub(amount);
+        self.storage_deposit_refunded = self.storage_deposit_refunded.saturating_add(amount);
+    }
+
+    /// Net storage deposit change: positive when the caller paid in,
+    /// negative when deletions released more deposit than was charged
+    pub fn net_storage_deposit(&self) -> i128 {
+        self.storage_deposit_charged as i128 - self.storage_deposit_refunded as i128
+    }
+
     /// Charge resources for a storage read
     pub fn charge_storage_read(&mut self, key: &[u8]) -> Result<(), MeteringError> {
         // Charge gas
@@ -280,18 +325,20 @@ impl MeteringContext {
                 + (value.len() as u64 * self.proof_size_costs.storage_write_value_byte),
         )?;
 
-        // Charge storage deposit
-        let old_size = self.storage_sizes.get(key).cloned().unwrap_or(0);
-        let new_size = value.len();
+        // Charge the deposit difference: a new key pays the item deposit plus
+        // its bytes, an existing key pays for growth and is refunded for shrinkage
+        let old_deposit = self.storage_deposits.get(key).cloned().unwrap_or(0);
+        let new_deposit = self.storage_deposit_costs.deposit_for(value.len());
 
-        if new_size > old_size {
-            // More storage is used, pay for the difference
-            let deposit_amount = ((new_size - old_size) as u128) * self.storage_deposit_costs.byte;
-            self.charge_storage_deposit(deposit_amount)?;
+        if new_deposit > old_deposit {
+            self.charge_storage_deposit(new_deposit - old_deposit)?;
+        } else {
+            self.refund_storage_deposit(old_deposit - new_deposit);
         }
 
-        // Update storage size
-        self.storage_sizes.insert(key.to_vec(), new_size);
+        // Update storage size and held deposit
+        self.storage_sizes.insert(key.to_vec(), value.len());
+        self.storage_deposits.insert(key.to_vec(), new_deposit);
 
         Ok(())
     }
@@ -307,15 +354,23 @@ impl MeteringContext {
                 + (key.len() as u64 * self.proof_size_costs.storage_delete_key_byte),
         )?;
 
-        // Refund storage deposit
-        if let Some(old_size) = self.storage_sizes.remove(key) {
-            let refund = (old_size as u128) * self.storage_deposit_costs.byte;
-            self.storage_deposit_used = self.storage_deposit_used.saturating_sub(refund);
+        // Refund the whole deposit held for the key
+        self.storage_sizes.remove(key);
+        if let Some(deposit) = self.storage_deposits.remove(key) {
+            self.refund_storage_deposit(deposit);
         }
 
         Ok(())
     }
 
+    /// Charge gas for hashing a collection entry key
+    pub fn charge_key_derivation(&mut self, suffix_len: usize) -> Result<(), MeteringError> {
+        self.charge_gas(
+            self.gas_costs.key_derivation
+                + ((32 + suffix_len) as u64 * self.gas_costs.key_derivation_byte),
+        )
+    }
+
     /// Charge resources for an event
     pub fn charge_event(&mut self, topics: &[Vec<u8>], data: &[u8]) -> Result<(), MeteringError> {
         let mut total_size = data.len();
@@ -482,4 +537,33 @@ mod tests {
             "Storage deposit limit exceeded"
         );
     }
+
+    #[test]
+    fn test_storage_deposit_charged_per_key_and_refunded() {
+        let mut ctx = MeteringContext::new(1_000_000, 1_000, 10_000_000);
+        let costs = ctx.storage_deposit_costs;
+
+        ctx.charge_storage_write(b"key", b"value").unwrap();
+        assert_eq!(ctx.storage_deposit_used, costs.item + 5 * costs.byte);
+
+        // Shrinking refunds the byte difference but keeps the item deposit
+        ctx.charge_storage_write(b"key", b"v").unwrap();
+        assert_eq!(ctx.storage_deposit_used, costs.item + costs.byte);
+        assert_eq!(ctx.storage_deposit_refunded, 4 * costs.byte);
+
+        ctx.charge_storage_delete(b"key").unwrap();
+        assert_eq!(ctx.storage_deposit_used, 0);
+        assert_eq!(ctx.net_storage_deposit(), 0);
+        assert!(ctx.storage_deposits.is_empty());
+    }
+
+    #[test]
+    fn test_storage_deposit_limit_enforced_on_new_keys() {
+        let mut ctx = MeteringContext::new(1_000_000, 1_000, 250_000);
+        assert!(matches!(
+            ctx.charge_storage_write(b"key", b"v"),
+            Err(MeteringError::StorageDepositLimitExceeded)
+        ));
+        assert_eq!(ctx.net_storage_deposit(), 0);
+    }
 }
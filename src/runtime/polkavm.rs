@@ -0,0 +1,610 @@
+//! Embedded PolkaVM execution engine
+//!
+//! Runs the program blob the compiler produces on a real PolkaVM instance,
+//! instead of walking the `Instruction` stream like the reference
+//! [`Interpreter`](super::interpreter::Interpreter). Host calls are served
+//! by the runtime `Environment` with the interpreter's semantics, using the
+//! conventions of the [blob writer](crate::compiler::polkavm::blob): guest
+//! pointers are offsets into guest memory and `a6`/`a7` live in spill slots.
+//!
+//! PolkaVM 0.1 does not meter instructions, so only the gas charged by host
+//! calls (`ChargeGas`, storage, events) is accounted. Calls into contracts
+//! with code and contract creation are not supported; calls to mocked
+//! addresses are answered from the environment's mocks. The backend is
+//! picked by PolkaVM from the `POLKAVM_BACKEND` environment variable and
+//! defaults to its interpreter, which needs no sandbox.
+
+use polkavm::{Config, Engine, Linker, Module};
+use polkavm_common::error::Trap;
+use polkavm_common::program::Reg;
+use thiserror::Error;
+
+use crate::compiler::address::{Address, Hash};
+use crate::compiler::codegen::risc_v::Register;
+use crate::compiler::polkavm::blob::{
+    spill_address, DYNAMIC_HOST_CALL, ENTRY_POINT, GUEST_MEMORY_SIZE, LOAD_INPUT, MEMORY_BASE,
+};
+use crate::compiler::polkavm::host::HostFunction;
+use crate::runtime::env::{EnvError, Environment, ExecutionContext, ExecutionResult};
+use crate::stdlib::crypto::CryptoFunctions;
+
+/// Alignment of heap allocations, as in the interpreter
+const HEAP_ALIGN: u32 = 8;
+
+/// Status returned in `a0` by the call host functions
+const CALL_SUCCESS: u32 = 0;
+const CALL_FAILED: u32 = 2;
+
+/// Errors caused by the engine or the blob rather than by contract execution
+#[derive(Debug, Error)]
+pub enum EngineError {
+    #[error("Failed to load the program: {0}")]
+    Load(String),
+
+    #[error("Environment error: {0}")]
+    Environment(#[from] EnvError),
+}
+
+/// Why execution stopped inside a host call
+enum Halt {
+    Return(Vec<u8>),
+    Revert(Vec<u8>),
+    Trap(String),
+}
+
+/// State shared with the host calls of a running instance
+struct HostState {
+    environment: Environment,
+
+    /// Start of unallocated heap memory
+    heap_break: u32,
+
+    /// Set by the host call that stopped execution
+    halt: Option<Halt>,
+}
+
+/// Engine running program blobs on an embedded PolkaVM instance
+pub struct PolkaVmEngine {
+    /// Runtime environment (storage, events, gas)
+    environment: Environment,
+}
+
+impl PolkaVmEngine {
+    /// Create a new engine
+    pub fn new(context: ExecutionContext) -> Self {
+        Self::with_environment(Environment::new(context))
+    }
+
+    /// Create a new engine around an existing environment
+    pub fn with_environment(environment: Environment) -> Self {
+        PolkaVmEngine { environment }
+    }
+
+    /// Get the runtime environment
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    /// Get a mutable reference to the runtime environment
+    pub fn environment_mut(&mut self) -> &mut Environment {
+        &mut self.environment
+    }
+
+    /// Consume the engine and return its environment
+    pub fn into_environment(self) -> Environment {
+        self.environment
+    }
+
+    /// Execute a program blob
+    ///
+    /// Like on the interpreter, every run is a transaction whose storage
+    /// writes and events are only kept if it returns, and returning without
+    /// the `Return` host function returns `a0` as 4 little-endian bytes.
+    pub fn execute(&mut self, blob: &[u8]) -> Result<ExecutionResult, EngineError> {
+        self.execute_export(blob, ENTRY_POINT)
+    }
+
+    /// Execute a program blob from the named export, e.g. `deploy`
+    pub fn execute_export(
+        &mut self,
+        blob: &[u8],
+        export: &str,
+    ) -> Result<ExecutionResult, EngineError> {
+        // polkavm 0.1 only exposes the backend choice through the environment
+        if std::env::var_os("POLKAVM_BACKEND").is_none() {
+            std::env::set_var("POLKAVM_BACKEND", "interpreter");
+        }
+        let config = Config::from_env().map_err(load_error)?;
+        let engine = Engine::new(&config).map_err(load_error)?;
+        let module = Module::new(&engine, blob).map_err(load_error)?;
+
+        let mut linker = Linker::<HostState>::new(&engine);
+        linker.func_fallback(|mut caller, id| {
+            // The caller type is not exported by polkavm, so guest access
+            // goes through macros
+            macro_rules! read_guest {
+                ($ptr:expr, $len:expr) => {{
+                    let (ptr, len) = ($ptr, $len);
+                    guest_address(ptr, len).and_then(|address| {
+                        caller
+                            .read_memory_into_new_vec(address, len)
+                            .map_err(|_| out_of_bounds(ptr, len))
+                    })
+                }};
+            }
+            macro_rules! read_array {
+                ($ptr:expr, $len:literal) => {
+                    read_guest!($ptr, $len).map(|bytes| <[u8; $len]>::try_from(bytes).unwrap())
+                };
+            }
+            macro_rules! write_guest {
+                ($ptr:expr, $data:expr) => {{
+                    let (ptr, data): (u32, &[u8]) = ($ptr, $data);
+                    guest_address(ptr, data.len() as u32).and_then(|address| {
+                        caller
+                            .write_memory(address, data)
+                            .map_err(|_| out_of_bounds(ptr, data.len() as u32))
+                    })
+                }};
+            }
+            macro_rules! spilled {
+                ($register:expr) => {
+                    caller
+                        .read_memory_into_new_vec(spill_address($register), 4)
+                        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                        .map_err(|_| Halt::Trap("Spill area is not mapped".to_string()))
+                };
+            }
+            macro_rules! arg {
+                ($index:expr) => {
+                    match $index {
+                        6 => spilled!(Register::X16)?,
+                        index => caller.get_reg(Reg::ARG_REGS[index]),
+                    }
+                };
+            }
+            macro_rules! env {
+                () => {
+                    caller.data_mut().environment
+                };
+            }
+
+            let mut dispatch = || -> Result<(), Halt> {
+                let id = match id {
+                    LOAD_INPUT => {
+                        let input = env!().context.input.clone();
+                        write_guest!(0, &input)?;
+                        caller.data_mut().heap_break =
+                            align_up(input.len().max(1) as u32, HEAP_ALIGN);
+                        caller.set_reg(Reg::A0, 0);
+                        caller.set_reg(Reg::A1, input.len() as u32);
+                        return Ok(());
+                    }
+                    DYNAMIC_HOST_CALL => spilled!(Register::X17)?,
+                    id => id,
+                };
+                let env_error = |err: EnvError| Halt::Trap(err.to_string());
+
+                match id {
+                    id if id == HostFunction::StorageGet as u32 => {
+                        let key = read_guest!(arg!(0), arg!(1))?;
+                        match env!().storage_get(&key).map_err(env_error)? {
+                            Some(value) => {
+                                write_guest!(arg!(2), &value)?;
+                                write_guest!(arg!(3), &(value.len() as u32).to_le_bytes())?;
+                                caller.set_reg(Reg::A0, 0);
+                            }
+                            None => caller.set_reg(Reg::A0, 1),
+                        }
+                    }
+                    id if id == HostFunction::StorageSet as u32 => {
+                        let key = read_guest!(arg!(0), arg!(1))?;
+                        let value = read_guest!(arg!(2), arg!(3))?;
+                        env!().storage_set(&key, &value).map_err(env_error)?;
+                        caller.set_reg(Reg::A0, 0);
+                    }
+                    id if id == HostFunction::StorageClear as u32 => {
+                        let key = read_guest!(arg!(0), arg!(1))?;
+                        env!().storage_clear(&key).map_err(env_error)?;
+                        caller.set_reg(Reg::A0, 0);
+                    }
+                    id if id == HostFunction::GetCaller as u32 => {
+                        let caller = env!().context.caller;
+                        write_guest!(arg!(0), caller.as_bytes())?;
+                    }
+                    id if id == HostFunction::GetAddress as u32 => {
+                        let address = env!().context.address;
+                        write_guest!(arg!(0), address.as_bytes())?;
+                    }
+                    id if id == HostFunction::GetCallValue as u32 => {
+                        let value = env!().context.value;
+                        write_guest!(arg!(0), &value.to_le_bytes())?;
+                    }
+                    id if id == HostFunction::GetBalance as u32 => {
+                        let address = env!().context.address;
+                        let balance = env!().balance_of(&address);
+                        write_guest!(arg!(0), &balance.to_le_bytes())?;
+                    }
+                    id if id == HostFunction::Random as u32 => {
+                        let subject = read_guest!(arg!(0), arg!(1))?;
+                        let value = env!().context.random(&subject);
+                        write_guest!(arg!(2), value.as_bytes())?;
+                    }
+                    id if id == HostFunction::Keccak256 as u32 => {
+                        let input = read_guest!(arg!(0), arg!(1))?;
+                        write_guest!(arg!(2), &CryptoFunctions::keccak256(&input))?;
+                    }
+                    id if id == HostFunction::Blake2b256 as u32 => {
+                        let input = read_guest!(arg!(0), arg!(1))?;
+                        write_guest!(arg!(2), &CryptoFunctions::blake2b_256(&input))?;
+                    }
+                    id if id == HostFunction::Sha256 as u32 => {
+                        let input = read_guest!(arg!(0), arg!(1))?;
+                        write_guest!(arg!(2), &CryptoFunctions::sha256(&input))?;
+                    }
+                    id if id == HostFunction::Ripemd160 as u32 => {
+                        let input = read_guest!(arg!(0), arg!(1))?;
+                        write_guest!(arg!(2), &CryptoFunctions::ripemd160(&input))?;
+                    }
+                    id if id == HostFunction::EcdsaRecover as u32 => {
+                        let signature = read_array!(arg!(0), 65)?;
+                        let hash = read_array!(arg!(1), 32)?;
+                        match CryptoFunctions::ecdsa_recover(&hash, &signature) {
+                            Some(public_key) => {
+                                write_guest!(arg!(2), &public_key)?;
+                                caller.set_reg(Reg::A0, 0);
+                            }
+                            None => caller.set_reg(Reg::A0, 1),
+                        }
+                    }
+                    id if id == HostFunction::Sr25519Verify as u32 => {
+                        let signature = read_array!(arg!(0), 64)?;
+                        let public_key = read_array!(arg!(1), 32)?;
+                        let message = read_guest!(arg!(2), arg!(3))?;
+                        let valid =
+                            CryptoFunctions::sr25519_verify(&signature, &message, &public_key);
+                        caller.set_reg(Reg::A0, if valid { 0 } else { 1 });
+                    }
+                    id if id == HostFunction::Log as u32 => {
+                        let (topics_ptr, topics_count) = (arg!(0), arg!(1));
+                        let mut topics = Vec::new();
+                        for i in 0..topics_count {
+                            topics.push(read_guest!(topics_ptr.wrapping_add(i * 32), 32)?);
+                        }
+                        let data = read_guest!(arg!(2), arg!(3))?;
+                        env!().emit_event(topics, data).map_err(env_error)?;
+                    }
+                    id if id == HostFunction::Call as u32
+                        || id == HostFunction::DelegateCall as u32 =>
+                    {
+                        let delegate = id == HostFunction::DelegateCall as u32;
+                        let address = Address::new(read_array!(arg!(0), 32)?);
+                        let (input_arg, output_arg) = if delegate { (2, 4) } else { (3, 5) };
+                        let input = read_guest!(arg!(input_arg), arg!(input_arg + 1))?;
+                        if env!().code_at(&address).is_some() {
+                            return Err(Halt::Trap(
+                                "Calls into contract code are not supported by the PolkaVM engine"
+                                    .to_string(),
+                            ));
+                        }
+                        let mocked = env!()
+                            .mocks
+                            .get_call_response(&hex::encode(address), &input);
+                        let (status, output) = match mocked {
+                            Some(output) => (CALL_SUCCESS, output),
+                            None => (CALL_FAILED, Vec::new()),
+                        };
+
+                        // The word at the length pointer holds the buffer
+                        // capacity on entry and the bytes written on return
+                        let (ptr, len_ptr) = (arg!(output_arg), arg!(output_arg + 1));
+                        let capacity = u32::from_le_bytes(read_array!(len_ptr, 4)?);
+                        let len = output.len().min(capacity as usize);
+                        write_guest!(ptr, &output[..len])?;
+                        write_guest!(len_ptr, &(len as u32).to_le_bytes())?;
+                        caller.set_reg(Reg::A0, status);
+                    }
+                    id if id == HostFunction::Transfer as u32 => {
+                        let to = Address::new(read_array!(arg!(0), 32)?);
+                        let value = u128::from_le_bytes(read_array!(arg!(1), 16)?);
+                        let from = env!().context.address;
+                        env!().charge("transfer").map_err(env_error)?;
+                        let status = match env!().transfer(from, to, value) {
+                            Ok(()) => CALL_SUCCESS,
+                            Err(_) => CALL_FAILED,
+                        };
+                        caller.set_reg(Reg::A0, status);
+                    }
+                    id if id == HostFunction::Terminate as u32 => {
+                        let beneficiary = Address::new(read_array!(arg!(0), 32)?);
+                        env!().terminate(beneficiary).map_err(env_error)?;
+                        return Err(Halt::Return(Vec::new()));
+                    }
+                    id if id == HostFunction::SetCodeHash as u32 => {
+                        let code_hash = Hash::new(read_array!(arg!(0), 32)?);
+                        env!().charge("set_code_hash").map_err(env_error)?;
+                        let status = match env!().set_code_hash(code_hash) {
+                            Ok(()) => CALL_SUCCESS,
+                            Err(_) => CALL_FAILED,
+                        };
+                        caller.set_reg(Reg::A0, status);
+                    }
+                    id if id == HostFunction::MemoryAlloc as u32 => {
+                        // Allocations grow towards the stack and fail with 0
+                        // when they would reach it
+                        let (size, stack) = (arg!(0), caller.get_reg(Reg::SP));
+                        let pointer = caller.data_mut().heap_break;
+                        let end = pointer
+                            .checked_add(size)
+                            .map(|end| align_up(end, HEAP_ALIGN))
+                            .filter(|&end| end <= stack.min(GUEST_MEMORY_SIZE));
+                        let pointer = match end {
+                            Some(end) => {
+                                write_guest!(pointer, &vec![0; (end - pointer) as usize])?;
+                                caller.data_mut().heap_break = end;
+                                pointer
+                            }
+                            None => 0,
+                        };
+                        caller.set_reg(Reg::A0, pointer);
+                    }
+                    // Allocations live until the end of the call
+                    id if id == HostFunction::MemoryFree as u32 => {}
+                    id if id == HostFunction::Debug as u32 => {}
+                    id if id == HostFunction::Return as u32 => {
+                        return Err(Halt::Return(read_guest!(arg!(0), arg!(1))?));
+                    }
+                    id if id == HostFunction::Revert as u32 => {
+                        return Err(Halt::Revert(read_guest!(arg!(0), arg!(1))?));
+                    }
+                    id if id == HostFunction::Abort as u32 => {
+                        return Err(Halt::Trap("Contract aborted".to_string()));
+                    }
+                    id if id == HostFunction::ChargeGas as u32 => {
+                        let gas = arg!(0) as u64;
+                        env!().context.use_gas(gas).map_err(env_error)?;
+                    }
+                    id if env!().chain_extension(id).is_some() => {
+                        let arity = env!()
+                            .chain_extension(id)
+                            .map_or(0, |extension| extension.params.len());
+                        let mut args = Vec::with_capacity(arity);
+                        for index in 0..arity {
+                            args.push(arg!(index));
+                        }
+                        let result = env!().call_chain_extension(id, &args).map_err(env_error)?;
+                        caller.set_reg(Reg::A0, result);
+                    }
+                    id => {
+                        return Err(Halt::Trap(format!("Unsupported host function: {}", id)));
+                    }
+                }
+
+                Ok(())
+            };
+
+            dispatch().map_err(|halt| {
+                // Unwind the guest; the recorded halt decides the outcome
+                caller.data_mut().halt = Some(halt);
+                Trap::default()
+            })
+        });
+
+        let instance = linker
+            .instantiate_pre(&module)
+            .and_then(|pre| pre.instantiate())
+            .map_err(load_error)?;
+        let entry = instance
+            .get_func(export)
+            .ok_or_else(|| EngineError::Load(format!("Missing export: {}", export)))?;
+
+        let input_len = self.environment.context.input.len();
+        if input_len > GUEST_MEMORY_SIZE as usize {
+            return Err(EnvError::InvalidInput("Input does not fit in memory".to_string()).into());
+        }
+
+        let environment = std::mem::replace(
+            &mut self.environment,
+            Environment::new(ExecutionContext::new_default()),
+        );
+        let mut state = HostState {
+            environment,
+            heap_break: 0,
+            halt: None,
+        };
+
+        state.environment.checkpoint();
+        let result = entry.call(&mut state, &[]);
+        let halt = match (state.halt.take(), result) {
+            (Some(halt), _) => halt,
+            (None, Ok(value)) => {
+                let a0 = value.and_then(|value| value.u32()).unwrap_or(0);
+                Halt::Return(a0.to_le_bytes().to_vec())
+            }
+            (None, Err(err)) => Halt::Trap(err.to_string()),
+        };
+        self.environment = state.environment;
+        match &halt {
+            Halt::Return(_) => self.environment.commit()?,
+            _ => self.environment.rollback()?,
+        }
+
+        let context = &self.environment.context;
+        Ok(match halt {
+            Halt::Return(data) => ExecutionResult::Success {
+                data,
+                gas_used: context.gas_used,
+                proof_size_used: context.proof_size_used,
+                storage_deposit_used: context.storage_deposit_used,
+            },
+            Halt::Revert(data) => ExecutionResult::Revert {
+                data,
+                gas_used: context.gas_used,
+                proof_size_used: context.proof_size_used,
+                storage_deposit_used: context.storage_deposit_used,
+            },
+            Halt::Trap(reason) => ExecutionResult::Failure {
+                reason,
+                gas_used: context.gas_used,
+                proof_size_used: context.proof_size_used,
+                storage_deposit_used: context.storage_deposit_used,
+            },
+        })
+    }
+}
+
+fn load_error(error: impl std::fmt::Display) -> EngineError {
+    EngineError::Load(error.to_string())
+}
+
+/// PolkaVM address of `len` bytes of guest memory at `ptr`
+fn guest_address(ptr: u32, len: u32) -> Result<u32, Halt> {
+    match ptr.checked_add(len) {
+        Some(end) if end <= GUEST_MEMORY_SIZE => Ok(MEMORY_BASE + ptr),
+        _ => Err(out_of_bounds(ptr, len)),
+    }
+}
+
+fn out_of_bounds(address: u32, len: u32) -> Halt {
+    Halt::Trap(format!(
+        "Memory access out of bounds: {:#x}+{}",
+        address, len
+    ))
+}
+
+/// Round `value` up to a multiple of `align`, a power of two
+fn align_up(value: u32, align: u32) -> u32 {
+    value.saturating_add(align - 1) & !(align - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::codegen::risc_v::{export_label, Instruction};
+    use crate::compiler::polkavm::blob::assemble;
+
+    fn run(instructions: &[Instruction]) -> ExecutionResult {
+        let blob = assemble(instructions).unwrap();
+        PolkaVmEngine::new(ExecutionContext::new_default())
+            .execute(&blob)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic_and_return() {
+        let result = run(&[
+            Instruction::Li(Register::X5, 40),
+            Instruction::Li(Register::X6, 2),
+            Instruction::Add(Register::X10, Register::X5, Register::X6),
+        ]);
+
+        match result {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 42u32.to_le_bytes()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_division_by_zero_follows_riscv() {
+        let result = run(&[
+            Instruction::Li(Register::X5, 7),
+            Instruction::Rem(Register::X10, Register::X5, Register::X0),
+        ]);
+
+        match result {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 7u32.to_le_bytes()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_named_export() {
+        let blob = assemble(&[
+            Instruction::Li(Register::X10, 1),
+            Instruction::JumpAndLinkReg(Register::X0, Register::X1, 0),
+            Instruction::Label(export_label("deploy")),
+            Instruction::Li(Register::X10, 2),
+        ])
+        .unwrap();
+        let mut engine = PolkaVmEngine::new(ExecutionContext::new_default());
+
+        match engine.execute_export(&blob, "deploy").unwrap() {
+            ExecutionResult::Success { data, .. } => assert_eq!(data, 2u32.to_le_bytes()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(matches!(
+            engine.execute_export(&blob, "ping"),
+            Err(EngineError::Load(_))
+        ));
+    }
+
+    #[test]
+    fn test_storage_host_calls() {
+        let blob = assemble(&[
+            Instruction::Li(Register::X5, 0x1234),
+            Instruction::Li(Register::X6, 0x100),
+            Instruction::Store(Register::X5, Register::X6, 0),
+            Instruction::Li(Register::X10, 0x100),
+            Instruction::Li(Register::X11, 4),
+            Instruction::Li(Register::X12, 0x100),
+            Instruction::Li(Register::X13, 4),
+            Instruction::Li(Register::X17, HostFunction::StorageSet as i32),
+            Instruction::Ecall,
+        ])
+        .unwrap();
+        let mut engine = PolkaVmEngine::new(ExecutionContext::new_default());
+
+        let result = engine.execute(&blob).unwrap();
+        assert!(matches!(result, ExecutionResult::Success { .. }));
+
+        let key = 0x1234u32.to_le_bytes().to_vec();
+        assert_eq!(engine.environment().storage.get(&key), Some(&key));
+    }
+
+    #[test]
+    fn test_revert_discards_storage() {
+        let blob = assemble(&[
+            Instruction::Li(Register::X10, 0),
+            Instruction::Li(Register::X11, 4),
+            Instruction::Li(Register::X12, 0),
+            Instruction::Li(Register::X13, 4),
+            Instruction::Li(Register::X17, HostFunction::StorageSet as i32),
+            Instruction::Ecall,
+            Instruction::Li(Register::X11, 0),
+            Instruction::Li(Register::X17, HostFunction::Revert as i32),
+            Instruction::Ecall,
+        ])
+        .unwrap();
+        let mut engine = PolkaVmEngine::new(ExecutionContext::new_default());
+
+        let result = engine.execute(&blob).unwrap();
+        assert!(matches!(result, ExecutionResult::Revert { .. }));
+        assert!(engine.environment().storage.is_empty());
+    }
+
+    #[test]
+    fn test_out_of_bounds_pointer_fails() {
+        let result = run(&[
+            Instruction::Li(Register::X10, GUEST_MEMORY_SIZE as i32 - 2),
+            Instruction::Li(Register::X11, 4),
+            Instruction::Li(Register::X17, HostFunction::Keccak256 as i32),
+            Instruction::Ecall,
+        ]);
+
+        match result {
+            ExecutionResult::Failure { reason, .. } => {
+                assert!(reason.starts_with("Memory access out of bounds"))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_invalid_blob_is_a_load_error() {
+        let mut engine = PolkaVmEngine::new(ExecutionContext::new_default());
+        assert!(matches!(
+            engine.execute(b"not a blob"),
+            Err(EngineError::Load(_))
+        ));
+    }
+}
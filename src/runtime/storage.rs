@@ -17,6 +17,15 @@ pub enum StorageError {
 
     #[error("Storage limit exceeded")]
     StorageLimitExceeded,
+
+    #[error("Index {0} out of bounds for length {1}")]
+    IndexOutOfBounds(u32, u32),
+
+    #[error("Decode error: {0}")]
+    Decode(String),
+
+    #[error("No open storage checkpoint")]
+    NoCheckpoint,
 }
 
 /// Storage limits
@@ -42,6 +51,10 @@ impl Default for StorageLimits {
     }
 }
 
+/// Undo log for one checkpoint: namespaced keys with the value they held
+/// before the first write inside the checkpoint (`None` if absent).
+type JournalFrame = Vec<(Vec<u8>, Option<Vec<u8>>)>;
+
 /// Storage manager for contract storage
 #[derive(Clone, Debug)]
 pub struct StorageManager {
@@ -53,6 +66,9 @@ pub struct StorageManager {
 
     /// Storage limits
     limits: StorageLimits,
+
+    /// Open checkpoints, innermost last
+    journal: Vec<JournalFrame>,
 }
 
 impl StorageManager {
@@ -62,6 +78,51 @@ impl StorageManager {
             storage: HashMap::new(),
             contract_address,
             limits,
+            journal: Vec::new(),
+        }
+    }
+
+    /// Open a (possibly nested) checkpoint. Writes made after this call can
+    /// be undone with [`rollback`](Self::rollback).
+    pub fn checkpoint(&mut self) {
+        self.journal.push(Vec::new());
+    }
+
+    /// Close the innermost checkpoint, keeping its writes. If an outer
+    /// checkpoint is open the writes are folded into it so it can still
+    /// undo them.
+    pub fn commit(&mut self) -> Result<(), StorageError> {
+        let frame = self.journal.pop().ok_or(StorageError::NoCheckpoint)?;
+        if let Some(parent) = self.journal.last_mut() {
+            parent.extend(frame);
+        }
+        Ok(())
+    }
+
+    /// Close the innermost checkpoint, discarding every write made since it
+    /// was opened.
+    pub fn rollback(&mut self) -> Result<(), StorageError> {
+        let frame = self.journal.pop().ok_or(StorageError::NoCheckpoint)?;
+        for (key, previous) in frame.into_iter().rev() {
+            match previous {
+                Some(value) => self.storage.insert(key, value),
+                None => self.storage.remove(&key),
+            };
+        }
+        Ok(())
+    }
+
+    /// Number of open checkpoints
+    pub fn checkpoint_depth(&self) -> usize {
+        self.journal.len()
+    }
+
+    /// Record the current value of a namespaced key in the innermost
+    /// checkpoint before it is overwritten
+    fn journal_write(&mut self, namespaced_key: &[u8]) {
+        if let Some(frame) = self.journal.last_mut() {
+            let previous = self.storage.get(namespaced_key).cloned();
+            frame.push((namespaced_key.to_vec(), previous));
         }
     }
 
@@ -109,6 +170,7 @@ impl StorageManager {
 
         // Set storage
         let namespaced_key = self.namespaced_key(key);
+        self.journal_write(&namespaced_key);
         self.storage.insert(namespaced_key, value.to_vec());
 
         Ok(())
@@ -183,6 +245,7 @@ impl StorageManager {
 
         // Remove from storage
         let namespaced_key = self.namespaced_key(key);
+        self.journal_write(&namespaced_key);
         self.storage.remove(&namespaced_key);
 
         Ok(())
@@ -190,6 +253,12 @@ impl StorageManager {
 
     /// Clear storage (used for contract destruction)
     pub fn clear(&mut self) {
+        if !self.journal.is_empty() {
+            let keys: Vec<Vec<u8>> = self.storage.keys().cloned().collect();
+            for key in keys {
+                self.journal_write(&key);
+            }
+        }
         self.storage.clear();
     }
 
@@ -245,6 +314,7 @@ impl StorageManager {
     pub fn import(&mut self, entries: Vec<(Vec<u8>, Vec<u8>)>) {
         for (key, value) in entries {
             let namespaced_key = self.namespaced_key(&key);
+            self.journal_write(&namespaced_key);
             self.storage.insert(namespaced_key, value);
         }
     }
@@ -341,3 +411,72 @@ impl ChildStorage {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> (StorageManager, MeteringContext) {
+        (
+            StorageManager::new([7u8; 32], StorageLimits::default()),
+            MeteringContext::new(10_000_000, 1_000_000, 1_000_000_000),
+        )
+    }
+
+    #[test]
+    fn test_rollback_restores_previous_values() {
+        let (mut storage, mut meter) = manager();
+        storage.set(b"a", b"1", &mut meter).unwrap();
+
+        storage.checkpoint();
+        storage.set(b"a", b"2", &mut meter).unwrap();
+        storage.set(b"b", b"3", &mut meter).unwrap();
+        storage.remove(b"a", &mut meter).unwrap();
+        storage.rollback().unwrap();
+
+        assert_eq!(storage.get(b"a", &mut meter).unwrap(), Some(b"1".to_vec()));
+        assert_eq!(storage.get(b"b", &mut meter).unwrap(), None);
+        assert_eq!(storage.checkpoint_depth(), 0);
+    }
+
+    #[test]
+    fn test_nested_commit_is_undone_by_outer_rollback() {
+        let (mut storage, mut meter) = manager();
+
+        storage.checkpoint();
+        storage.set(b"outer", b"1", &mut meter).unwrap();
+        storage.checkpoint();
+        storage.set(b"inner", b"2", &mut meter).unwrap();
+        storage.commit().unwrap();
+        assert_eq!(storage.checkpoint_depth(), 1);
+        assert!(storage.contains(b"inner", &mut meter).unwrap());
+
+        storage.rollback().unwrap();
+        assert!(storage.entries().is_empty());
+    }
+
+    #[test]
+    fn test_inner_rollback_keeps_outer_writes() {
+        let (mut storage, mut meter) = manager();
+
+        storage.checkpoint();
+        storage.set(b"kept", b"1", &mut meter).unwrap();
+        storage.checkpoint();
+        storage.set(b"dropped", b"2", &mut meter).unwrap();
+        storage.clear();
+        storage.rollback().unwrap();
+        storage.commit().unwrap();
+
+        assert_eq!(storage.entries(), vec![(b"kept".to_vec(), b"1".to_vec())]);
+    }
+
+    #[test]
+    fn test_commit_without_checkpoint_fails() {
+        let (mut storage, _) = manager();
+        assert!(matches!(storage.commit(), Err(StorageError::NoCheckpoint)));
+        assert!(matches!(
+            storage.rollback(),
+            Err(StorageError::NoCheckpoint)
+        ));
+    }
+}
@@ -0,0 +1,532 @@
+//! Reference interpreter for generated WebAssembly modules
+//!
+//! Runs a [`WasmModule`] the way pallet-contracts would, entering it at
+//! its `call` export, with the `seal0` imports served by the runtime
+//! `Environment`. Storage, gas and events therefore follow the same rules
+//! as for the RISC-V [`Interpreter`](super::interpreter::Interpreter), so
+//! the two targets can be compared. Modules are executed as generated,
+//! without decoding the binary.
+
+use std::collections::HashMap;
+use thiserror::Error;
+
+use crate::compiler::codegen::wasm::{
+    NumericOp, WasmFunction, WasmImport, WasmInstruction, WasmModule, PAGE_SIZE,
+};
+use crate::runtime::env::{EnvError, Environment, ExecutionContext, ExecutionResult};
+use crate::stdlib::bytes::decode_compact;
+
+/// Deepest nesting of calls before execution traps
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+/// Status returned by `seal_get_storage`
+const KEY_NOT_FOUND: i64 = 3;
+const OUTPUT_BUFFER_TOO_SMALL: i64 = 5;
+
+/// Errors caused by malformed modules rather than by contract execution
+#[derive(Debug, Error)]
+pub enum WasmError {
+    #[error("Missing export: {0}")]
+    MissingExport(String),
+
+    #[error("Environment error: {0}")]
+    Environment(#[from] EnvError),
+}
+
+/// Why execution stopped
+enum Halt {
+    Return(Vec<u8>),
+    Revert(Vec<u8>),
+    Trap(String),
+}
+
+/// A block, loop or if being executed
+struct Label {
+    /// Index of the instruction opening it
+    start: usize,
+    /// Index of its `end`
+    end: usize,
+    is_loop: bool,
+    /// Height of the value stack when it was entered
+    height: usize,
+}
+
+/// Where the blocks of a function end, by the index of the instruction
+/// opening them: the `else` of an if, if any, and the `end`
+type BlockTargets = HashMap<usize, (Option<usize>, usize)>;
+
+/// Interpreter for modules generated by the WebAssembly backend
+pub struct WasmInterpreter {
+    /// Runtime environment (storage, events, gas)
+    environment: Environment,
+
+    memory: Vec<u8>,
+
+    /// Number of calls being executed
+    depth: usize,
+
+    /// Number of instructions executed by the last run
+    steps: u64,
+}
+
+impl WasmInterpreter {
+    pub fn new(context: ExecutionContext) -> Self {
+        Self::with_environment(Environment::new(context))
+    }
+
+    /// Create a new interpreter around an existing environment
+    pub fn with_environment(environment: Environment) -> Self {
+        WasmInterpreter {
+            environment,
+            memory: Vec::new(),
+            depth: 0,
+            steps: 0,
+        }
+    }
+
+    /// Get the runtime environment
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    /// Get a mutable reference to the runtime environment
+    pub fn environment_mut(&mut self) -> &mut Environment {
+        &mut self.environment
+    }
+
+    /// Consume the interpreter and return its environment
+    pub fn into_environment(self) -> Environment {
+        self.environment
+    }
+
+    /// Number of instructions executed by the last run
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Call the `call` export of a module
+    ///
+    /// Like on RISC-V, every call is a transaction whose storage writes
+    /// and events are only kept if it returns. Returning from `call`
+    /// without `seal_return` returns empty data.
+    pub fn execute(&mut self, module: &WasmModule) -> Result<ExecutionResult, WasmError> {
+        let entry = module
+            .export("call")
+            .ok_or_else(|| WasmError::MissingExport("call".to_string()))?;
+        let blocks: Vec<BlockTargets> = module
+            .functions
+            .iter()
+            .map(|function| Self::block_targets(&function.body))
+            .collect();
+
+        self.memory = vec![0; (module.memory_pages * PAGE_SIZE) as usize];
+        self.depth = 0;
+        self.steps = 0;
+
+        self.environment.checkpoint();
+        let halt = module
+            .data
+            .iter()
+            .try_for_each(|(address, bytes)| self.write_bytes(*address as i64, bytes))
+            .and_then(|_| self.invoke(module, &blocks, entry, Vec::new()))
+            .map_or_else(|halt| halt, |_| Halt::Return(Vec::new()));
+        match &halt {
+            Halt::Return(_) => self.environment.commit()?,
+            _ => self.environment.rollback()?,
+        }
+
+        let context = &self.environment.context;
+        Ok(match halt {
+            Halt::Return(data) => ExecutionResult::Success {
+                data,
+                gas_used: context.gas_used,
+                proof_size_used: context.proof_size_used,
+                storage_deposit_used: context.storage_deposit_used,
+            },
+            Halt::Revert(data) => ExecutionResult::Revert {
+                data,
+                gas_used: context.gas_used,
+                proof_size_used: context.proof_size_used,
+                storage_deposit_used: context.storage_deposit_used,
+            },
+            Halt::Trap(reason) => ExecutionResult::Failure {
+                reason,
+                gas_used: context.gas_used,
+                proof_size_used: context.proof_size_used,
+                storage_deposit_used: context.storage_deposit_used,
+            },
+        })
+    }
+
+    /// Match the blocks, loops and ifs of a body with their `else` and `end`
+    fn block_targets(body: &[WasmInstruction]) -> BlockTargets {
+        let mut targets = HashMap::new();
+        let mut open: Vec<(usize, Option<usize>)> = Vec::new();
+        for (pc, instruction) in body.iter().enumerate() {
+            match instruction {
+                WasmInstruction::Block | WasmInstruction::Loop | WasmInstruction::If => {
+                    open.push((pc, None))
+                }
+                WasmInstruction::Else => {
+                    if let Some((_, otherwise)) = open.last_mut() {
+                        *otherwise = Some(pc);
+                    }
+                }
+                WasmInstruction::End => {
+                    if let Some((start, otherwise)) = open.pop() {
+                        targets.insert(start, (otherwise, pc));
+                    }
+                }
+                _ => {}
+            }
+        }
+        targets
+    }
+
+    /// Call the function with `index`, returning its result
+    fn invoke(
+        &mut self,
+        module: &WasmModule,
+        blocks: &[BlockTargets],
+        index: u32,
+        args: Vec<i64>,
+    ) -> Result<Option<i64>, Halt> {
+        if let Some(import) = WasmImport::ALL.get(index as usize) {
+            return self.host_call(*import, &args);
+        }
+        let function = module
+            .function(index)
+            .ok_or_else(|| Halt::Trap(format!("Undefined function: {}", index)))?;
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(Halt::Trap("Call stack exhausted".to_string()));
+        }
+
+        self.depth += 1;
+        let result = self.run(module, blocks, index, function, args);
+        self.depth -= 1;
+        result
+    }
+
+    /// Execute the body of the function with `index`
+    fn run(
+        &mut self,
+        module: &WasmModule,
+        blocks: &[BlockTargets],
+        index: u32,
+        function: &WasmFunction,
+        mut locals: Vec<i64>,
+    ) -> Result<Option<i64>, Halt> {
+        let targets = &blocks[index as usize - WasmImport::ALL.len()];
+        locals.resize(locals.len() + function.locals.len(), 0);
+        let mut stack: Vec<i64> = Vec::new();
+        let mut labels: Vec<Label> = Vec::new();
+        // Every instruction costs a step of the cost table
+        let step_gas = self.environment.costs().gas("step").unwrap_or_default();
+        let mut pc = 0;
+
+        macro_rules! pop {
+            () => {
+                stack
+                    .pop()
+                    .ok_or_else(|| Halt::Trap("Value stack underflow".to_string()))?
+            };
+        }
+
+        while pc < function.body.len() {
+            self.steps += 1;
+            if self.environment.context.use_gas(step_gas).is_err() {
+                return Err(Halt::Trap(EnvError::OutOfGas.to_string()));
+            }
+
+            let mut next = pc + 1;
+            let mut branch = None;
+            match function.body[pc] {
+                WasmInstruction::Unreachable => {
+                    return Err(Halt::Trap("Unreachable executed".to_string()))
+                }
+                instruction @ (WasmInstruction::Block
+                | WasmInstruction::Loop
+                | WasmInstruction::If) => {
+                    let (otherwise, end) = targets[&pc];
+                    labels.push(Label {
+                        start: pc,
+                        end,
+                        is_loop: instruction == WasmInstruction::Loop,
+                        height: stack.len(),
+                    });
+                    if instruction == WasmInstruction::If && pop!() as i32 == 0 {
+                        match otherwise {
+                            Some(otherwise) => next = otherwise + 1,
+                            None => {
+                                labels.pop();
+                                next = end + 1;
+                            }
+                        }
+                    }
+                }
+                // The end of a taken then-branch
+                WasmInstruction::Else => {
+                    if let Some(label) = labels.pop() {
+                        next = label.end + 1;
+                    }
+                }
+                WasmInstruction::End => {
+                    labels.pop();
+                }
+                WasmInstruction::Br(depth) => branch = Some(depth),
+                WasmInstruction::BrIf(depth) => {
+                    if pop!() as i32 != 0 {
+                        branch = Some(depth);
+                    }
+                }
+                WasmInstruction::Return => break,
+                WasmInstruction::Call(index) => {
+                    let params = match WasmImport::ALL.get(index as usize) {
+                        Some(import) => import.ty().params.len(),
+                        None => module
+                            .function(index)
+                            .map_or(0, |callee| callee.ty.params.len()),
+                    };
+                    if stack.len() < params {
+                        return Err(Halt::Trap("Value stack underflow".to_string()));
+                    }
+                    let args = stack.split_off(stack.len() - params);
+                    if let Some(result) = self.invoke(module, blocks, index, args)? {
+                        stack.push(result);
+                    }
+                }
+                WasmInstruction::Drop => {
+                    pop!();
+                }
+                WasmInstruction::LocalGet(index) => stack.push(locals[index as usize]),
+                WasmInstruction::LocalSet(index) => locals[index as usize] = pop!(),
+                WasmInstruction::LocalTee(index) => {
+                    locals[index as usize] = *stack
+                        .last()
+                        .ok_or_else(|| Halt::Trap("Value stack underflow".to_string()))?
+                }
+                WasmInstruction::I32Load(offset) => {
+                    let address = pop!() as i32 as u32 as i64 + offset as i64;
+                    let bytes = self.read_bytes(address, 4)?;
+                    stack.push(i32::from_le_bytes(bytes.try_into().unwrap()) as i64);
+                }
+                WasmInstruction::I32Store(offset) | WasmInstruction::I32Store8(offset) => {
+                    let value = pop!() as i32;
+                    let address = pop!() as i32 as u32 as i64 + offset as i64;
+                    let bytes = value.to_le_bytes();
+                    let len = if matches!(function.body[pc], WasmInstruction::I32Store8(_)) {
+                        1
+                    } else {
+                        4
+                    };
+                    self.write_bytes(address, &bytes[..len])?;
+                }
+                WasmInstruction::I32Const(value) => stack.push(value as i64),
+                WasmInstruction::I64Const(value) => stack.push(value),
+                WasmInstruction::Numeric(op) => {
+                    let value = Self::numeric(op, &mut stack)?;
+                    stack.push(value);
+                }
+            }
+
+            if let Some(depth) = branch {
+                let target = labels
+                    .len()
+                    .checked_sub(1 + depth as usize)
+                    .ok_or_else(|| Halt::Trap(format!("Invalid branch depth: {}", depth)))?;
+                let label = &labels[target];
+                stack.truncate(label.height);
+                if label.is_loop {
+                    next = label.start + 1;
+                    labels.truncate(target + 1);
+                } else {
+                    next = label.end + 1;
+                    labels.truncate(target);
+                }
+            }
+            pc = next;
+        }
+
+        Ok(match function.ty.results.is_empty() {
+            true => None,
+            false => Some(pop!()),
+        })
+    }
+
+    /// Apply a numeric instruction to the operands on top of `stack`
+    fn numeric(op: NumericOp, stack: &mut Vec<i64>) -> Result<i64, Halt> {
+        let underflow = || Halt::Trap("Value stack underflow".to_string());
+        let unary = matches!(
+            op,
+            NumericOp::I32Eqz
+                | NumericOp::I32WrapI64
+                | NumericOp::I64ExtendI32S
+                | NumericOp::I64ExtendI32U
+        );
+        let b = stack.pop().ok_or_else(underflow)?;
+        let a = if unary {
+            0
+        } else {
+            stack.pop().ok_or_else(underflow)?
+        };
+        let (a32, b32) = (a as i32, b as i32);
+        let divide_by_zero = || Halt::Trap("Integer divide by zero".to_string());
+        let overflow = || Halt::Trap("Integer overflow".to_string());
+
+        Ok(match op {
+            NumericOp::I32Eqz => (b32 == 0) as i64,
+            NumericOp::I32Eq => (a32 == b32) as i64,
+            NumericOp::I32Ne => (a32 != b32) as i64,
+            NumericOp::I32LtS => (a32 < b32) as i64,
+            NumericOp::I32LtU => ((a32 as u32) < b32 as u32) as i64,
+            NumericOp::I32GtS => (a32 > b32) as i64,
+            NumericOp::I32GtU => (a32 as u32 > b32 as u32) as i64,
+            NumericOp::I32LeS => (a32 <= b32) as i64,
+            NumericOp::I32GeS => (a32 >= b32) as i64,
+            NumericOp::I32GeU => (a32 as u32 >= b32 as u32) as i64,
+            NumericOp::I64LtS => (a < b) as i64,
+            NumericOp::I64GtS => (a > b) as i64,
+            NumericOp::I64GtU => (a as u64 > b as u64) as i64,
+            NumericOp::I32Add => a32.wrapping_add(b32) as i64,
+            NumericOp::I32Sub => a32.wrapping_sub(b32) as i64,
+            NumericOp::I32Mul => a32.wrapping_mul(b32) as i64,
+            NumericOp::I32DivS => match b32 {
+                0 => return Err(divide_by_zero()),
+                _ => a32.checked_div(b32).ok_or_else(overflow)? as i64,
+            },
+            NumericOp::I32RemS => match b32 {
+                0 => return Err(divide_by_zero()),
+                _ => a32.wrapping_rem(b32) as i64,
+            },
+            NumericOp::I32And => (a32 & b32) as i64,
+            NumericOp::I32Or => (a32 | b32) as i64,
+            NumericOp::I32Xor => (a32 ^ b32) as i64,
+            NumericOp::I64Add => a.wrapping_add(b),
+            NumericOp::I64Sub => a.wrapping_sub(b),
+            NumericOp::I64Mul => a.wrapping_mul(b),
+            NumericOp::I64DivS => match b {
+                0 => return Err(divide_by_zero()),
+                _ => a.checked_div(b).ok_or_else(overflow)?,
+            },
+            NumericOp::I64DivU => match b {
+                0 => return Err(divide_by_zero()),
+                _ => (a as u64 / b as u64) as i64,
+            },
+            NumericOp::I64RemS => match b {
+                0 => return Err(divide_by_zero()),
+                _ => a.wrapping_rem(b),
+            },
+            NumericOp::I64RemU => match b {
+                0 => return Err(divide_by_zero()),
+                _ => (a as u64 % b as u64) as i64,
+            },
+            NumericOp::I32WrapI64 => b32 as i64,
+            NumericOp::I64ExtendI32S => b32 as i64,
+            NumericOp::I64ExtendI32U => b32 as u32 as i64,
+        })
+    }
+
+    /// Serve a call to an imported host function
+    fn host_call(&mut self, import: WasmImport, args: &[i64]) -> Result<Option<i64>, Halt> {
+        let env_error = |err: EnvError| Halt::Trap(err.to_string());
+        let arg = |i: usize| args[i] as i32 as u32 as i64;
+
+        match import {
+            WasmImport::Input => {
+                let input = self.environment.context.input.clone();
+                self.write_output(arg(0), arg(1), &input)?;
+                Ok(None)
+            }
+            WasmImport::Return => {
+                let data = self.read_bytes(arg(1), arg(2))?;
+                Err(if arg(0) & 1 != 0 {
+                    Halt::Revert(data)
+                } else {
+                    Halt::Return(data)
+                })
+            }
+            WasmImport::GetStorage => {
+                let key = self.read_bytes(arg(0), 32)?;
+                match self.environment.storage_get(&key).map_err(env_error)? {
+                    Some(value) => {
+                        if value.len() as i64 > self.load_word(arg(2))? {
+                            return Ok(Some(OUTPUT_BUFFER_TOO_SMALL));
+                        }
+                        self.write_output(arg(1), arg(2), &value)?;
+                        Ok(Some(0))
+                    }
+                    None => Ok(Some(KEY_NOT_FOUND)),
+                }
+            }
+            WasmImport::SetStorage => {
+                let key = self.read_bytes(arg(0), 32)?;
+                let value = self.read_bytes(arg(1), arg(2))?;
+                self.environment
+                    .storage_set(&key, &value)
+                    .map_err(env_error)?;
+                Ok(None)
+            }
+            WasmImport::ClearStorage => {
+                let key = self.read_bytes(arg(0), 32)?;
+                self.environment.storage_clear(&key).map_err(env_error)?;
+                Ok(None)
+            }
+            WasmImport::ValueTransferred => {
+                let value = self.environment.context.value.to_le_bytes();
+                self.write_output(arg(0), arg(1), &value)?;
+                Ok(None)
+            }
+            WasmImport::DepositEvent => {
+                let encoded = self.read_bytes(arg(0), arg(1))?;
+                let malformed = || Halt::Trap("Malformed event topics".to_string());
+                let (count, prefix) = decode_compact(&encoded).ok_or_else(malformed)?;
+                let topics = &encoded[prefix..];
+                if topics.len() != count as usize * 32 {
+                    return Err(malformed());
+                }
+                let topics = topics.chunks(32).map(|topic| topic.to_vec()).collect();
+                let data = self.read_bytes(arg(2), arg(3))?;
+                self.environment
+                    .emit_event(topics, data)
+                    .map_err(env_error)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Write `bytes` to a buffer whose capacity is read from `len_ptr`,
+    /// writing their length back
+    fn write_output(&mut self, ptr: i64, len_ptr: i64, bytes: &[u8]) -> Result<(), Halt> {
+        if bytes.len() as i64 > self.load_word(len_ptr)? {
+            return Err(Halt::Trap("Output buffer too small".to_string()));
+        }
+        self.write_bytes(ptr, bytes)?;
+        self.write_bytes(len_ptr, &(bytes.len() as u32).to_le_bytes())
+    }
+
+    fn load_word(&self, address: i64) -> Result<i64, Halt> {
+        let bytes = self.read_bytes(address, 4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as i64)
+    }
+
+    fn range(&self, address: i64, len: i64) -> Result<std::ops::Range<usize>, Halt> {
+        let end = address + len;
+        if address < 0 || len < 0 || end > self.memory.len() as i64 {
+            return Err(Halt::Trap(format!(
+                "Memory access out of bounds: {} bytes at {}",
+                len, address
+            )));
+        }
+        Ok(address as usize..end as usize)
+    }
+
+    fn read_bytes(&self, address: i64, len: i64) -> Result<Vec<u8>, Halt> {
+        Ok(self.memory[self.range(address, len)?].to_vec())
+    }
+
+    fn write_bytes(&mut self, address: i64, bytes: &[u8]) -> Result<(), Halt> {
+        let range = self.range(address, bytes.len() as i64)?;
+        self.memory[range].copy_from_slice(bytes);
+        Ok(())
+    }
+}
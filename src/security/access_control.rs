@@ -2,6 +2,9 @@
 ///
 /// Provides comprehensive access control mechanisms including role-based permissions,
 /// resource protection, and principal authentication for secure operations.
+///
+/// Contracts restrict their callers with the `#[only_owner]` and
+/// `#[only_role("name")]` attributes instead, see [`crate::compiler::access`].
 use crate::compiler::parser::ast::*;
 use crate::security::SecurityError;
 use std::collections::HashMap;
@@ -405,39 +408,39 @@ pub fn register_access_control_functions() -> Vec<Definition> {
     };
 
     let address_type = Type::Named {
-        name: "Address".to_string(),
+        name: "Address".into(),
         params: Vec::new(),
         location: dummy_loc.clone(),
     };
 
     let string_type = Type::Named {
-        name: "String".to_string(),
+        name: "String".into(),
         params: Vec::new(),
         location: dummy_loc.clone(),
     };
 
     let bool_type = Type::Named {
-        name: "Bool".to_string(),
+        name: "Bool".into(),
         params: Vec::new(),
         location: dummy_loc.clone(),
     };
 
     // Check access permission
     definitions.push(Definition::FunctionDef {
-        name: "AccessControl/checkAccess".to_string(),
+        name: "AccessControl/checkAccess".into(),
         params: vec![
             Parameter {
-                name: "principal".to_string(),
+                name: "principal".into(),
                 ty: address_type.clone(),
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "resource".to_string(),
+                name: "resource".into(),
                 ty: string_type.clone(),
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "operation".to_string(),
+                name: "operation".into(),
                 ty: string_type.clone(),
                 location: dummy_loc.clone(),
             },
@@ -448,14 +451,16 @@ pub fn register_access_control_functions() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     // Get principal roles
     definitions.push(Definition::FunctionDef {
-        name: "AccessControl/getRoles".to_string(),
+        name: "AccessControl/getRoles".into(),
         params: vec![Parameter {
-            name: "principal".to_string(),
+            name: "principal".into(),
             ty: address_type.clone(),
             location: dummy_loc.clone(),
         }],
@@ -468,20 +473,22 @@ pub fn register_access_control_functions() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     // Verify role membership
     definitions.push(Definition::FunctionDef {
-        name: "AccessControl/hasRole".to_string(),
+        name: "AccessControl/hasRole".into(),
         params: vec![
             Parameter {
-                name: "principal".to_string(),
+                name: "principal".into(),
                 ty: address_type.clone(),
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "role".to_string(),
+                name: "role".into(),
                 ty: string_type.clone(),
                 location: dummy_loc.clone(),
             },
@@ -492,6 +499,8 @@ pub fn register_access_control_functions() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
@@ -0,0 +1,599 @@
+//! Audits: the security scanner, the static analyzer and the lints over the
+//! modules of a project, in one report
+//!
+//! Every [`Finding`] carries a fingerprint of its rule, file, function and
+//! message, which does not change as the code around it moves, so a
+//! [`Baseline`] of accepted findings keeps matching them. Findings in a
+//! function marked `#[suppress(rule, ...)]` stay in the report as
+//! suppressed; like baselined ones, they never fail an audit.
+//!
+//! An [`AuditReport`] is written out as a human summary or as a SARIF log,
+//! with the fingerprints, baseline states and suppressions code scanning
+//! services read.
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::compiler::analyzer::attributes::FunctionAttributes;
+use crate::compiler::analyzer::lints::{lint_program, Level, LintLevels};
+use crate::compiler::parser::ast::{Attribute, Definition, Location, Parameter, Program};
+use crate::security::security_scanner::{SecurityScanner, SecuritySeverity};
+use crate::security::static_analysis::StaticAnalyzer;
+use crate::security::SecurityError;
+use crate::stdlib::crypto::CryptoFunctions;
+
+/// Version of the baseline file format
+pub const BASELINE_VERSION: u32 = 1;
+
+/// Pass that reported a finding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Tool {
+    SecurityScanner,
+    StaticAnalyzer,
+    Linter,
+}
+
+impl fmt::Display for Tool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tool::SecurityScanner => write!(f, "security-scanner"),
+            Tool::StaticAnalyzer => write!(f, "static-analyzer"),
+            Tool::Linter => write!(f, "linter"),
+        }
+    }
+}
+
+/// Something an audit found
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub tool: Tool,
+    /// Rule reporting it, e.g. `unbounded-loop` or `unused_variables`
+    pub rule: String,
+    pub severity: SecuritySeverity,
+    /// File it is in, as given to the audit
+    pub file: String,
+    /// Function it is in, if any
+    pub function: Option<String>,
+    pub location: Location,
+    pub message: String,
+    /// How to fix it
+    pub help: Option<String>,
+    pub fingerprint: String,
+    /// Accepted by a `#[suppress(...)]` of its function
+    pub suppressed: bool,
+    /// Accepted by the baseline
+    pub baselined: bool,
+}
+
+impl Finding {
+    /// Whether the finding is neither suppressed nor baselined
+    pub fn is_new(&self) -> bool {
+        !self.suppressed && !self.baselined
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}] {}\n  --> {}:{}:{}",
+            self.severity,
+            self.rule,
+            self.message,
+            self.file,
+            self.location.line,
+            self.location.column
+        )?;
+        if let Some(function) = &self.function {
+            write!(f, " (in {})", function)?;
+        }
+        if let Some(help) = &self.help {
+            write!(f, "\n  help: {}", help)?;
+        }
+        Ok(())
+    }
+}
+
+/// Least severe finding failing an audit, or none for audits that never
+/// fail
+#[derive(Debug, Clone, PartialEq)]
+pub enum FailOn {
+    Severity(SecuritySeverity),
+    Never,
+}
+
+impl FailOn {
+    /// Whether a new finding of `severity` fails the audit
+    pub fn fails(&self, severity: &SecuritySeverity) -> bool {
+        match self {
+            FailOn::Severity(threshold) => rank(severity) >= rank(threshold),
+            FailOn::Never => false,
+        }
+    }
+}
+
+impl FromStr for FailOn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(FailOn::Severity(match s {
+            "critical" => SecuritySeverity::Critical,
+            "high" => SecuritySeverity::High,
+            "medium" => SecuritySeverity::Medium,
+            "low" => SecuritySeverity::Low,
+            "info" => SecuritySeverity::Info,
+            "none" => return Ok(FailOn::Never),
+            _ => {
+                return Err(format!(
+                    "Unknown severity '{}', expected critical, high, medium, low, info or none",
+                    s
+                ))
+            }
+        }))
+    }
+}
+
+fn rank(severity: &SecuritySeverity) -> u8 {
+    match severity {
+        SecuritySeverity::Info => 0,
+        SecuritySeverity::Low => 1,
+        SecuritySeverity::Medium => 2,
+        SecuritySeverity::High => 3,
+        SecuritySeverity::Critical => 4,
+    }
+}
+
+/// An accepted finding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    pub fingerprint: String,
+    pub rule: String,
+    pub file: String,
+    #[serde(default)]
+    pub function: Option<String>,
+    pub message: String,
+}
+
+/// Findings accepted when a project started being audited, or since
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub version: u32,
+    pub findings: Vec<BaselineEntry>,
+}
+
+impl Baseline {
+    /// Read a baseline written by [`Baseline::write`]
+    pub fn read(path: &Path) -> Result<Self, SecurityError> {
+        let failed = |e: String| {
+            SecurityError::StaticAnalysisError(format!(
+                "Cannot read the baseline {}: {}",
+                path.display(),
+                e
+            ))
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| failed(e.to_string()))?;
+        let baseline: Baseline = serde_json::from_str(&text).map_err(|e| failed(e.to_string()))?;
+        if baseline.version != BASELINE_VERSION {
+            return Err(failed(format!("unsupported version {}", baseline.version)));
+        }
+        Ok(baseline)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), SecurityError> {
+        let failed = |e: String| {
+            SecurityError::StaticAnalysisError(format!(
+                "Cannot write the baseline {}: {}",
+                path.display(),
+                e
+            ))
+        };
+        let text = serde_json::to_string_pretty(self).map_err(|e| failed(e.to_string()))?;
+        std::fs::write(path, text + "\n").map_err(|e| failed(e.to_string()))
+    }
+}
+
+/// Findings of an audit over some files
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AuditReport {
+    /// Number of files audited
+    pub files: usize,
+    /// Findings in the order the files were audited, sorted by position in
+    /// each
+    pub findings: Vec<Finding>,
+}
+
+impl AuditReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Audit `program`, parsed from `file`
+    pub fn audit(&mut self, file: &str, program: &Program) -> Result<(), SecurityError> {
+        let mut findings = Vec::new();
+
+        let scan = SecurityScanner::new().scan_program(program)?;
+        for vulnerability in scan.vulnerabilities {
+            findings.push(Finding::new(
+                Tool::SecurityScanner,
+                vulnerability.vuln_type.rule_id(),
+                vulnerability.severity,
+                file,
+                vulnerability.location,
+                vulnerability.description,
+                Some(vulnerability.recommendation),
+            ));
+        }
+
+        for issue in StaticAnalyzer::new().analyze_program(program)? {
+            let severity = match issue.severity {
+                crate::security::SecuritySeverity::Critical => SecuritySeverity::Critical,
+                crate::security::SecuritySeverity::High => SecuritySeverity::High,
+                crate::security::SecuritySeverity::Medium => SecuritySeverity::Medium,
+                crate::security::SecuritySeverity::Low => SecuritySeverity::Low,
+                crate::security::SecuritySeverity::Info => SecuritySeverity::Info,
+            };
+            findings.push(Finding::new(
+                Tool::StaticAnalyzer,
+                issue.rule_id,
+                severity,
+                file,
+                issue.location,
+                issue.message,
+                Some(issue.suggestion),
+            ));
+        }
+
+        // Lints allowed where they are found are left out, as in a build
+        let levels = LintLevels::default();
+        for warning in lint_program(program) {
+            if levels.level(program, &warning) == Level::Allow {
+                continue;
+            }
+            findings.push(Finding::new(
+                Tool::Linter,
+                warning.lint.name().to_string(),
+                SecuritySeverity::Low,
+                file,
+                warning.location,
+                warning.message,
+                None,
+            ));
+        }
+
+        for finding in &mut findings {
+            if let Some((name, attributes, params)) =
+                enclosing_function(&program.definitions, finding.location.start)
+            {
+                let rule = finding.rule.replace('-', "_");
+                finding.suppressed = FunctionAttributes::from_attributes(attributes, params)
+                    .is_ok_and(|attributes| attributes.suppressed.contains(&rule));
+                finding.function = Some(name.to_string());
+            }
+            finding.fingerprint = fingerprint(finding);
+        }
+        findings.sort_by_key(|finding| (finding.location.start, finding.location.end));
+
+        self.files += 1;
+        self.findings.extend(findings);
+        Ok(())
+    }
+
+    /// Mark the findings accepted by `baseline`, each entry accepting one
+    pub fn apply_baseline(&mut self, baseline: &Baseline) {
+        let mut accepted: Vec<&str> = baseline
+            .findings
+            .iter()
+            .map(|entry| entry.fingerprint.as_str())
+            .collect();
+        for finding in self
+            .findings
+            .iter_mut()
+            .filter(|finding| !finding.suppressed)
+        {
+            if let Some(i) = accepted
+                .iter()
+                .position(|fingerprint| *fingerprint == finding.fingerprint)
+            {
+                accepted.swap_remove(i);
+                finding.baselined = true;
+            }
+        }
+    }
+
+    /// A baseline accepting every finding not suppressed
+    pub fn baseline(&self) -> Baseline {
+        Baseline {
+            version: BASELINE_VERSION,
+            findings: self
+                .findings
+                .iter()
+                .filter(|finding| !finding.suppressed)
+                .map(|finding| BaselineEntry {
+                    fingerprint: finding.fingerprint.clone(),
+                    rule: finding.rule.clone(),
+                    file: finding.file.clone(),
+                    function: finding.function.clone(),
+                    message: finding.message.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// The findings neither suppressed nor baselined
+    pub fn new_findings(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(|finding| finding.is_new())
+    }
+
+    /// Whether a new finding fails the audit
+    pub fn fails(&self, fail_on: &FailOn) -> bool {
+        self.new_findings()
+            .any(|finding| fail_on.fails(&finding.severity))
+    }
+
+    /// One line counting the new findings by severity and the accepted ones
+    pub fn summary(&self) -> String {
+        let count = |severity: SecuritySeverity| {
+            self.new_findings()
+                .filter(|finding| finding.severity == severity)
+                .count()
+        };
+        let baselined = self.findings.iter().filter(|f| f.baselined).count();
+        let suppressed = self.findings.iter().filter(|f| f.suppressed).count();
+        format!(
+            "Audited {} file(s): {} new finding(s), {} critical, {} high, {} medium, {} low, {} info; {} baselined, {} suppressed.",
+            self.files,
+            self.new_findings().count(),
+            count(SecuritySeverity::Critical),
+            count(SecuritySeverity::High),
+            count(SecuritySeverity::Medium),
+            count(SecuritySeverity::Low),
+            count(SecuritySeverity::Info),
+            baselined,
+            suppressed
+        )
+    }
+
+    /// The findings as a SARIF 2.1.0 log
+    pub fn to_sarif(&self) -> Value {
+        let mut rules: Vec<&Finding> = Vec::new();
+        for finding in &self.findings {
+            if !rules.iter().any(|rule| rule.rule == finding.rule) {
+                rules.push(finding);
+            }
+        }
+        json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "bend-pvm",
+                        "version": crate::version(),
+                        "rules": rules.iter().map(|rule| json!({
+                            "id": rule.rule,
+                            "help": { "text": rule.help.as_deref().unwrap_or(&rule.message) },
+                            "properties": { "tags": [rule.tool.to_string()] },
+                        })).collect::<Vec<_>>(),
+                    },
+                },
+                "results": self.findings.iter().map(|finding| {
+                    let mut result = json!({
+                        "ruleId": finding.rule,
+                        "level": finding.severity.sarif_level(),
+                        "message": { "text": finding.message },
+                        "locations": [{
+                            "physicalLocation": {
+                                "artifactLocation": { "uri": finding.file },
+                                "region": {
+                                    "startLine": finding.location.line,
+                                    "startColumn": finding.location.column,
+                                    "charOffset": finding.location.start,
+                                    "charLength": finding.location.end.saturating_sub(finding.location.start),
+                                },
+                            },
+                        }],
+                        "partialFingerprints": { "bendAudit/v1": finding.fingerprint },
+                        "baselineState": if finding.baselined { "unchanged" } else { "new" },
+                        "properties": { "severity": finding.severity },
+                    });
+                    if finding.suppressed {
+                        result["suppressions"] = json!([{ "kind": "inSource" }]);
+                    }
+                    result
+                }).collect::<Vec<_>>(),
+            }],
+        })
+    }
+}
+
+impl Finding {
+    fn new(
+        tool: Tool,
+        rule: String,
+        severity: SecuritySeverity,
+        file: &str,
+        location: Location,
+        message: String,
+        help: Option<String>,
+    ) -> Self {
+        Finding {
+            tool,
+            rule,
+            severity,
+            file: file.to_string(),
+            function: None,
+            location,
+            message,
+            help,
+            fingerprint: String::new(),
+            suppressed: false,
+            baselined: false,
+        }
+    }
+}
+
+/// Hash of what identifies a finding, leaving out its position
+fn fingerprint(finding: &Finding) -> String {
+    let key = format!(
+        "{}\0{}\0{}\0{}",
+        finding.rule,
+        finding.file,
+        finding.function.as_deref().unwrap_or_default(),
+        finding.message
+    );
+    hex::encode(&CryptoFunctions::sha256(key.as_bytes())[..16])
+}
+
+/// The name, attributes and parameters of the function whose source
+/// contains `offset`
+fn enclosing_function(
+    definitions: &[Definition],
+    offset: usize,
+) -> Option<(&str, &[Attribute], &[Parameter])> {
+    definitions.iter().find_map(|definition| match definition {
+        Definition::FunctionDef {
+            name,
+            attributes,
+            params,
+            location,
+            ..
+        } => (location.start..location.end).contains(&offset).then_some((
+            name.as_str(),
+            attributes.as_slice(),
+            params.as_slice(),
+        )),
+        Definition::ObjectDef { functions, .. }
+        | Definition::Module {
+            definitions: functions,
+            ..
+        } => enclosing_function(functions, offset),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VAULT: &str = r#"
+        storage {
+            balance: u24,
+        }
+
+        fn deposit(amount: u24) -> u24 {
+            balance = balance + amount;
+            return balance;
+        }
+
+        #[suppress(access_control)]
+        fn reset(unused: u24) -> u24 {
+            balance = 0;
+            return 0;
+        }
+    "#;
+
+    fn audit(source: &str) -> AuditReport {
+        let program = crate::parse_source(source).unwrap();
+        let mut report = AuditReport::new();
+        report.audit("vault.bend", &program).unwrap();
+        report
+    }
+
+    fn find<'a>(report: &'a AuditReport, rule: &str, function: &str) -> &'a Finding {
+        report
+            .findings
+            .iter()
+            .find(|finding| finding.rule == rule && finding.function.as_deref() == Some(function))
+            .unwrap_or_else(|| panic!("no {} finding in {}", rule, function))
+    }
+
+    #[test]
+    fn test_audit_and_suppressions() {
+        let report = audit(VAULT);
+
+        let unprotected = find(&report, "access-control", "deposit");
+        assert_eq!(unprotected.tool, Tool::SecurityScanner);
+        assert!(unprotected.is_new());
+        assert!(find(&report, "access-control", "reset").suppressed);
+        let unused = find(&report, "unused_variables", "reset");
+        assert_eq!(unused.tool, Tool::Linter);
+        assert!(unused.is_new());
+
+        // Positions do not change fingerprints
+        let moved = audit(&format!("\n\n{}", VAULT));
+        assert_eq!(
+            find(&moved, "access-control", "deposit").fingerprint,
+            unprotected.fingerprint
+        );
+        assert_ne!(
+            find(&moved, "access-control", "deposit").location,
+            unprotected.location
+        );
+    }
+
+    #[test]
+    fn test_baseline_and_threshold() {
+        let mut report = audit(VAULT);
+        assert!(report.fails(&FailOn::Severity(SecuritySeverity::High)));
+        assert!(!report.fails(&FailOn::Never));
+
+        let baseline = report.baseline();
+        assert!(baseline
+            .findings
+            .iter()
+            .all(|entry| entry.rule != "access-control"
+                || entry.function.as_deref() == Some("deposit")));
+        let path = std::env::temp_dir().join(format!("bend-audit-{}.json", std::process::id()));
+        baseline.write(&path).unwrap();
+        let read = Baseline::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read, baseline);
+
+        report.apply_baseline(&read);
+        assert_eq!(report.new_findings().count(), 0);
+        assert!(!report.fails(&FailOn::Severity(SecuritySeverity::Info)));
+        assert!(report.summary().contains("0 new finding(s)"));
+
+        assert_eq!(
+            "medium".parse(),
+            Ok(FailOn::Severity(SecuritySeverity::Medium))
+        );
+        assert_eq!("none".parse(), Ok(FailOn::Never));
+        assert!("severe".parse::<FailOn>().is_err());
+        assert!(FailOn::Severity(SecuritySeverity::Medium).fails(&SecuritySeverity::High));
+        assert!(!FailOn::Severity(SecuritySeverity::Medium).fails(&SecuritySeverity::Low));
+    }
+
+    #[test]
+    fn test_sarif() {
+        let mut report = audit(VAULT);
+        let mut baseline = report.baseline();
+        baseline
+            .findings
+            .retain(|entry| entry.rule == "unused_variables");
+        report.apply_baseline(&baseline);
+
+        let sarif = report.to_sarif();
+        assert_eq!(sarif["version"], "2.1.0");
+        let results = sarif["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), report.findings.len());
+        let result = |rule: &str, state: &str| {
+            results
+                .iter()
+                .find(|result| result["ruleId"] == rule && result["baselineState"] == state)
+                .unwrap_or_else(|| panic!("no {} {} result", state, rule))
+        };
+        assert!(
+            result("unused_variables", "unchanged")["partialFingerprints"]["bendAudit/v1"]
+                .is_string()
+        );
+        assert_eq!(result("access-control", "new")["level"], "error");
+        assert!(results
+            .iter()
+            .any(|result| result["suppressions"][0]["kind"] == "inSource"));
+    }
+}
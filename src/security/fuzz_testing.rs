@@ -342,7 +342,7 @@ impl FuzzTester {
         let mut functions = Vec::new();
         for definition in &program.definitions {
             if let Definition::FunctionDef { name, .. } = definition {
-                functions.push(name.clone());
+                functions.push(name.to_string());
             }
         }
         functions
@@ -609,11 +609,9 @@ impl FuzzTester {
                         TestInput::Integer(val) => {
                             *val = self.rng.gen_range(*val - 10..*val + 10);
                         }
-                        TestInput::String(s) => {
-                            if !s.is_empty() {
-                                let idx = self.rng.gen_range(0..s.len());
-                                s.remove(idx);
-                            }
+                        TestInput::String(s) if !s.is_empty() => {
+                            let idx = self.rng.gen_range(0..s.len());
+                            s.remove(idx);
                         }
                         _ => {}
                     }
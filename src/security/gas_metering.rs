@@ -395,20 +395,20 @@ pub fn register_gas_functions() -> Vec<Definition> {
     };
 
     let int_type = Type::Named {
-        name: "Int".to_string(),
+        name: "Int".into(),
         params: Vec::new(),
         location: dummy_loc.clone(),
     };
 
     let string_type = Type::Named {
-        name: "String".to_string(),
+        name: "String".into(),
         params: Vec::new(),
         location: dummy_loc.clone(),
     };
 
     // Get remaining gas
     definitions.push(Definition::FunctionDef {
-        name: "GasMeter/remaining".to_string(),
+        name: "GasMeter/remaining".into(),
         params: vec![],
         return_type: Some(int_type.clone()),
         body: Block {
@@ -416,12 +416,14 @@ pub fn register_gas_functions() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     // Get gas usage percentage
     definitions.push(Definition::FunctionDef {
-        name: "GasMeter/usage".to_string(),
+        name: "GasMeter/usage".into(),
         params: vec![],
         return_type: Some(int_type.clone()),
         body: Block {
@@ -429,14 +431,16 @@ pub fn register_gas_functions() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     // Estimate gas for operation
     definitions.push(Definition::FunctionDef {
-        name: "GasMeter/estimate".to_string(),
+        name: "GasMeter/estimate".into(),
         params: vec![Parameter {
-            name: "operation".to_string(),
+            name: "operation".into(),
             ty: string_type.clone(),
             location: dummy_loc.clone(),
         }],
@@ -446,6 +450,8 @@ pub fn register_gas_functions() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
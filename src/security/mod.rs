@@ -1,4 +1,5 @@
 pub mod access_control;
+pub mod audit;
 pub mod fuzz_testing;
 pub mod gas_metering;
 pub mod reentrancy_guard;
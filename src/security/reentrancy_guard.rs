@@ -389,28 +389,28 @@ pub fn register_reentrancy_functions() -> Vec<Definition> {
     };
 
     let string_type = Type::Named {
-        name: "String".to_string(),
+        name: "String".into(),
         params: Vec::new(),
         location: dummy_loc.clone(),
     };
 
     let bool_type = Type::Named {
-        name: "Bool".to_string(),
+        name: "Bool".into(),
         params: Vec::new(),
         location: dummy_loc.clone(),
     };
 
     let int_type = Type::Named {
-        name: "Int".to_string(),
+        name: "Int".into(),
         params: Vec::new(),
         location: dummy_loc.clone(),
     };
 
     // Check if function is reentrant-safe
     definitions.push(Definition::FunctionDef {
-        name: "ReentrancyGuard/checkSafe".to_string(),
+        name: "ReentrancyGuard/checkSafe".into(),
         params: vec![Parameter {
-            name: "function".to_string(),
+            name: "function".into(),
             ty: string_type.clone(),
             location: dummy_loc.clone(),
         }],
@@ -420,12 +420,14 @@ pub fn register_reentrancy_functions() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     // Get current call depth
     definitions.push(Definition::FunctionDef {
-        name: "ReentrancyGuard/getDepth".to_string(),
+        name: "ReentrancyGuard/getDepth".into(),
         params: vec![],
         return_type: Some(int_type.clone()),
         body: Block {
@@ -433,12 +435,14 @@ pub fn register_reentrancy_functions() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     // Reset reentrancy guard
     definitions.push(Definition::FunctionDef {
-        name: "ReentrancyGuard/reset".to_string(),
+        name: "ReentrancyGuard/reset".into(),
         params: vec![],
         return_type: None,
         body: Block {
@@ -446,6 +450,8 @@ pub fn register_reentrancy_functions() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
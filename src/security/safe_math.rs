@@ -147,6 +147,111 @@ impl SafeArithmetic for u64 {
     }
 }
 
+/// Word-sized integer types whose arithmetic generated code checks
+///
+/// Values live in 32-bit registers: unsigned values zero-extended and signed
+/// values sign-extended. An operation overflows when its result leaves the
+/// range of the type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckedInt {
+    U24,
+    I24,
+    U32,
+    I32,
+}
+
+impl CheckedInt {
+    /// The checked integer type with the given name
+    pub fn from_type_name(name: &str) -> Option<Self> {
+        match name {
+            "u24" => Some(CheckedInt::U24),
+            "i24" => Some(CheckedInt::I24),
+            "u32" => Some(CheckedInt::U32),
+            "i32" => Some(CheckedInt::I32),
+            _ => None,
+        }
+    }
+
+    /// Width in bits
+    pub fn bits(self) -> u32 {
+        match self {
+            CheckedInt::U24 | CheckedInt::I24 => 24,
+            CheckedInt::U32 | CheckedInt::I32 => 32,
+        }
+    }
+
+    /// Whether the type is signed
+    pub fn is_signed(self) -> bool {
+        matches!(self, CheckedInt::I24 | CheckedInt::I32)
+    }
+
+    /// Type of an operation on values of both types: signed if either is,
+    /// and as wide as the wider one
+    pub fn combine(self, other: CheckedInt) -> CheckedInt {
+        match (
+            self.is_signed() || other.is_signed(),
+            self.bits().max(other.bits()),
+        ) {
+            (false, 24) => CheckedInt::U24,
+            (true, 24) => CheckedInt::I24,
+            (false, _) => CheckedInt::U32,
+            (true, _) => CheckedInt::I32,
+        }
+    }
+
+    /// Smallest value of the type
+    pub fn min(self) -> i64 {
+        if self.is_signed() {
+            -(1i64 << (self.bits() - 1))
+        } else {
+            0
+        }
+    }
+
+    /// Largest value of the type
+    pub fn max(self) -> i64 {
+        if self.is_signed() {
+            (1i64 << (self.bits() - 1)) - 1
+        } else {
+            (1i64 << self.bits()) - 1
+        }
+    }
+
+    /// Check that the result of an operation fits the type
+    pub fn check(self, operation: &str, left: i64, right: i64) -> Result<i64, SafeMathError> {
+        let result = match operation {
+            "add" => left.safe_add(right)?,
+            "sub" => left.safe_sub(right)?,
+            "mul" => left.safe_mul(right)?,
+            "div" => left.safe_div(right)?,
+            "mod" => left.safe_mod(right)?,
+            _ => {
+                return Err(SafeMathError::Overflow {
+                    operation: operation.to_string(),
+                    left: left.to_string(),
+                    right: right.to_string(),
+                })
+            }
+        };
+
+        if result > self.max() {
+            Err(SafeMathError::Overflow {
+                operation: operation.to_string(),
+                left: left.to_string(),
+                right: right.to_string(),
+            })
+        } else if result < self.min() {
+            Err(SafeMathError::Underflow {
+                operation: operation.to_string(),
+                left: left.to_string(),
+                right: right.to_string(),
+            })
+        } else {
+            Ok(result)
+        }
+    }
+}
+
 /// SafeMath wrapper for type safety
 pub struct SafeMath;
 
@@ -203,22 +308,22 @@ pub fn register_safe_math() -> Vec<Definition> {
     };
 
     let int_type = Type::Named {
-        name: "Int".to_string(),
+        name: "Int".into(),
         params: Vec::new(),
         location: dummy_loc.clone(),
     };
 
     // Safe addition
     definitions.push(Definition::FunctionDef {
-        name: "SafeMath/add".to_string(),
+        name: "SafeMath/add".into(),
         params: vec![
             Parameter {
-                name: "a".to_string(),
+                name: "a".into(),
                 ty: int_type.clone(),
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "b".to_string(),
+                name: "b".into(),
                 ty: int_type.clone(),
                 location: dummy_loc.clone(),
             },
@@ -229,20 +334,22 @@ pub fn register_safe_math() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     // Safe subtraction
     definitions.push(Definition::FunctionDef {
-        name: "SafeMath/sub".to_string(),
+        name: "SafeMath/sub".into(),
         params: vec![
             Parameter {
-                name: "a".to_string(),
+                name: "a".into(),
                 ty: int_type.clone(),
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "b".to_string(),
+                name: "b".into(),
                 ty: int_type.clone(),
                 location: dummy_loc.clone(),
             },
@@ -253,20 +360,22 @@ pub fn register_safe_math() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     // Safe multiplication
     definitions.push(Definition::FunctionDef {
-        name: "SafeMath/mul".to_string(),
+        name: "SafeMath/mul".into(),
         params: vec![
             Parameter {
-                name: "a".to_string(),
+                name: "a".into(),
                 ty: int_type.clone(),
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "b".to_string(),
+                name: "b".into(),
                 ty: int_type.clone(),
                 location: dummy_loc.clone(),
             },
@@ -277,20 +386,22 @@ pub fn register_safe_math() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     // Safe division
     definitions.push(Definition::FunctionDef {
-        name: "SafeMath/div".to_string(),
+        name: "SafeMath/div".into(),
         params: vec![
             Parameter {
-                name: "a".to_string(),
+                name: "a".into(),
                 ty: int_type.clone(),
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "b".to_string(),
+                name: "b".into(),
                 ty: int_type.clone(),
                 location: dummy_loc.clone(),
             },
@@ -301,8 +412,40 @@ pub fn register_safe_math() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_int_ranges() {
+        assert_eq!(CheckedInt::U24.max(), 0xFF_FFFF);
+        assert_eq!(CheckedInt::I24.min(), -0x80_0000);
+        assert_eq!(CheckedInt::U24.combine(CheckedInt::I24), CheckedInt::I24);
+        assert_eq!(CheckedInt::I24.combine(CheckedInt::U32), CheckedInt::I32);
+
+        assert_eq!(
+            CheckedInt::U24.check("add", 0xFF_FFFE, 1).unwrap(),
+            0xFF_FFFF
+        );
+        assert!(matches!(
+            CheckedInt::U24.check("add", 0xFF_FFFF, 1),
+            Err(SafeMathError::Overflow { .. })
+        ));
+        assert!(matches!(
+            CheckedInt::U24.check("sub", 1, 2),
+            Err(SafeMathError::Underflow { .. })
+        ));
+        assert!(matches!(
+            CheckedInt::I32.check("div", 1, 0),
+            Err(SafeMathError::DivisionByZero)
+        ));
+    }
+}
@@ -1,14 +1,36 @@
-/// Security Scanner module
-///
-/// Provides comprehensive vulnerability detection and security scanning
-/// for Bend-PVM programs to identify potential security risks.
+//! Security scanner: vulnerability detectors over the syntax tree
+//!
+//! [`SecurityScanner::scan_program`] walks the functions and guards of a
+//! program and reports what its detectors find as [`Vulnerability`]s, each
+//! with a severity, the span of the code at fault and how to fix it:
+//!
+//! - unchecked arithmetic: `+`, `-`, `*` and `**` in `unchecked` code,
+//!   which wraps around instead of reverting
+//! - unbounded loops over storage: `while` and `for` loops without a
+//!   `bound` whose condition or end reads storage
+//! - missing access control: messages writing storage, transferring
+//!   value or replacing the code without checking `caller()`, themselves
+//!   or through a guard
+//! - unchecked external call results: `call`, `delegate_call`,
+//!   `instantiate` and interface calls whose result is discarded
+//! - timestamp dependence: conditions on the block timestamp or number
+//! - `terminate` misuse: messages anyone can call removing the contract
+//! - state changes after external calls: storage writes and transfers
+//!   following a call to another contract, made directly or by a function
+//!   called, in functions that are not `#[non_reentrant]`
+//!
+//! A [`ScanResult`] is written out as JSON or as a SARIF log.
+use crate::compiler::analyzer::attributes::{FunctionAttributes, Mutability};
 use crate::compiler::parser::ast::*;
 use crate::security::SecurityError;
-use regex::Regex;
-use std::collections::{HashMap, HashSet, VecDeque};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
 
 /// Security vulnerability types
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize)]
 pub enum VulnerabilityType {
     IntegerOverflow,
     IntegerUnderflow,
@@ -50,19 +72,73 @@ pub enum VulnerabilityType {
     UnrecoverableError,
 }
 
+impl VulnerabilityType {
+    /// Identifier of the rule reporting it, e.g. `unbounded-loop`
+    pub fn rule_id(&self) -> String {
+        match self {
+            VulnerabilityType::DoS => "dos".to_string(),
+            VulnerabilityType::MEV => "mev".to_string(),
+            _ => {
+                let mut id = String::new();
+                for c in format!("{:?}", self).chars() {
+                    if c.is_uppercase() && !id.is_empty() {
+                        id.push('-');
+                    }
+                    id.push(c.to_ascii_lowercase());
+                }
+                id
+            }
+        }
+    }
+}
+
 /// Security vulnerability
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Vulnerability {
     pub vuln_type: VulnerabilityType,
     pub severity: SecuritySeverity,
+    /// Function or guard the vulnerability is in
+    pub function: String,
     pub location: Location,
     pub description: String,
+    /// How to remediate it
     pub recommendation: String,
     pub confidence: f64, // 0.0 to 1.0
 }
 
+impl Vulnerability {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "rule": self.vuln_type.rule_id(),
+            "severity": self.severity,
+            "function": self.function,
+            "span": self.location,
+            "description": self.description,
+            "remediation": self.recommendation,
+            "confidence": self.confidence,
+        })
+    }
+}
+
+impl fmt::Display for Vulnerability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}] {} (in {}, line {}, column {})\n  help: {}",
+            self.severity,
+            self.vuln_type.rule_id(),
+            self.description,
+            self.function,
+            self.location.line,
+            self.location.column,
+            self.recommendation
+        )
+    }
+}
+
 /// Security severity levels
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum SecuritySeverity {
     Critical,
     High,
@@ -71,8 +147,58 @@ pub enum SecuritySeverity {
     Info,
 }
 
+impl SecuritySeverity {
+    /// The SARIF `level` of a result with this severity
+    pub fn sarif_level(&self) -> &'static str {
+        match self {
+            SecuritySeverity::Critical | SecuritySeverity::High => "error",
+            SecuritySeverity::Medium => "warning",
+            SecuritySeverity::Low | SecuritySeverity::Info => "note",
+        }
+    }
+}
+
+impl fmt::Display for SecuritySeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecuritySeverity::Critical => write!(f, "critical"),
+            SecuritySeverity::High => write!(f, "high"),
+            SecuritySeverity::Medium => write!(f, "medium"),
+            SecuritySeverity::Low => write!(f, "low"),
+            SecuritySeverity::Info => write!(f, "info"),
+        }
+    }
+}
+
+/// How a scan result is written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    /// One finding per paragraph
+    #[default]
+    Human,
+    Json,
+    /// A SARIF 2.1.0 log, as code scanning services read them
+    Sarif,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(ReportFormat::Human),
+            "json" => Ok(ReportFormat::Json),
+            "sarif" => Ok(ReportFormat::Sarif),
+            _ => Err(format!(
+                "Unknown report format '{}', expected human, json or sarif",
+                s
+            )),
+        }
+    }
+}
+
 /// Security scan result
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ScanResult {
     pub total_vulnerabilities: usize,
     pub critical_count: usize,
@@ -82,14 +208,76 @@ pub struct ScanResult {
     pub info_count: usize,
     pub vulnerabilities: Vec<Vulnerability>,
     pub scan_duration_ms: u64,
+    /// Share of the functions whose body was scanned, the others being
+    /// declared without one
     pub coverage_percentage: f64,
 }
 
+impl ScanResult {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "total": self.total_vulnerabilities,
+            "critical": self.critical_count,
+            "high": self.high_count,
+            "medium": self.medium_count,
+            "low": self.low_count,
+            "info": self.info_count,
+            "coverage": self.coverage_percentage,
+            "findings": self.vulnerabilities.iter().map(Vulnerability::to_json).collect::<Vec<_>>(),
+        })
+    }
+
+    /// The findings as a SARIF log, located in the file at `uri`
+    pub fn to_sarif(&self, uri: &str) -> Value {
+        let mut rules: Vec<&Vulnerability> = Vec::new();
+        for vulnerability in &self.vulnerabilities {
+            if !rules
+                .iter()
+                .any(|rule| rule.vuln_type == vulnerability.vuln_type)
+            {
+                rules.push(vulnerability);
+            }
+        }
+        json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "bend-pvm",
+                        "version": crate::version(),
+                        "rules": rules.iter().map(|rule| json!({
+                            "id": rule.vuln_type.rule_id(),
+                            "help": { "text": rule.recommendation },
+                        })).collect::<Vec<_>>(),
+                    },
+                },
+                "results": self.vulnerabilities.iter().map(|vulnerability| json!({
+                    "ruleId": vulnerability.vuln_type.rule_id(),
+                    "level": vulnerability.severity.sarif_level(),
+                    "message": { "text": vulnerability.description },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": uri },
+                            "region": {
+                                "startLine": vulnerability.location.line,
+                                "startColumn": vulnerability.location.column,
+                                "charOffset": vulnerability.location.start,
+                                "charLength": vulnerability.location.end.saturating_sub(vulnerability.location.start),
+                            },
+                        },
+                    }],
+                })).collect::<Vec<_>>(),
+            }],
+        })
+    }
+}
+
 /// Security scanner
 pub struct SecurityScanner {
-    vuln_patterns: HashMap<VulnerabilityType, Vec<Regex>>,
     scan_history: VecDeque<ScanResult>,
     violation_count: u32,
+    /// Rules whose findings are left out
     ignored_patterns: HashSet<String>,
 }
 
@@ -102,96 +290,44 @@ impl Default for SecurityScanner {
 impl SecurityScanner {
     /// Create a new security scanner
     pub fn new() -> Self {
-        let mut scanner = Self {
-            vuln_patterns: HashMap::new(),
+        Self {
             scan_history: VecDeque::new(),
             violation_count: 0,
             ignored_patterns: HashSet::new(),
-        };
-
-        scanner.initialize_patterns();
-        scanner
-    }
-
-    /// Initialize vulnerability detection patterns
-    fn initialize_patterns(&mut self) {
-        // Integer overflow patterns
-        let overflow_patterns = vec![
-            Regex::new(r"\+\s*\d+").expect(r"Regex pattern is valid"),
-            Regex::new(r"\*\s*\d+").expect(r"Regex pattern is valid"),
-        ];
-        self.vuln_patterns
-            .insert(VulnerabilityType::IntegerOverflow, overflow_patterns);
-
-        // Integer underflow patterns
-        let underflow_patterns = vec![Regex::new(r"-\s*\d+").expect(r"Regex pattern is valid")];
-        self.vuln_patterns
-            .insert(VulnerabilityType::IntegerUnderflow, underflow_patterns);
-
-        // Reentrancy patterns
-        let reentrancy_patterns = vec![
-            Regex::new(r"call\(").expect(r"Regex pattern is valid"),
-            Regex::new(r"delegatecall\(").expect(r"Regex pattern is valid"),
-            Regex::new(r"callcode\(").expect(r"Regex pattern is valid"),
-        ];
-        self.vuln_patterns
-            .insert(VulnerabilityType::Reentrancy, reentrancy_patterns);
-
-        // Unchecked call return patterns
-        let unchecked_patterns = vec![
-            Regex::new(r"\.call\(").expect(r"Regex pattern is valid"),
-            Regex::new(r"\.send\(").expect(r"Regex pattern is valid"),
-            Regex::new(r"\.transfer\(").expect(r"Regex pattern is valid"),
-        ];
-        self.vuln_patterns
-            .insert(VulnerabilityType::UncheckedCallReturn, unchecked_patterns);
-
-        // Timestamp dependence patterns
-        let timestamp_patterns = vec![
-            Regex::new(r"block\.timestamp").expect(r"Regex pattern is valid"),
-            Regex::new(r"now").expect(r"Regex pattern is valid"),
-            Regex::new(r"block\.number").expect(r"Regex pattern is valid"),
-        ];
-        self.vuln_patterns
-            .insert(VulnerabilityType::TimestampDependence, timestamp_patterns);
-
-        // Unprotected external call patterns
-        let external_call_patterns = vec![
-            Regex::new(r"call\(").expect(r"Regex pattern is valid"),
-            Regex::new(r"delegatecall\(").expect(r"Regex pattern is valid"),
-            Regex::new(r"staticcall\(").expect(r"Regex pattern is valid"),
-        ];
-        self.vuln_patterns.insert(
-            VulnerabilityType::UnprotectedExternalCall,
-            external_call_patterns,
-        );
-
-        // Unbounded loop patterns
-        let unbounded_patterns = vec![
-            Regex::new(r"for\s*\(").expect(r"Regex pattern is valid"),
-            Regex::new(r"while\s*\(").expect(r"Regex pattern is valid"),
-        ];
-        self.vuln_patterns
-            .insert(VulnerabilityType::UnboundedLoop, unbounded_patterns);
-
-        // Array access patterns
-        let array_patterns = vec![
-            Regex::new(r"\[\s*\]").expect(r"Regex pattern is valid"),
-            Regex::new(r"\.push\(").expect(r"Regex pattern is valid"),
-            Regex::new(r"\.pop\(").expect(r"Regex pattern is valid"),
-        ];
-        self.vuln_patterns
-            .insert(VulnerabilityType::UncheckedArrayAccess, array_patterns);
+        }
     }
 
     /// Scan a program for vulnerabilities
     pub fn scan_program(&mut self, program: &Program) -> Result<ScanResult, SecurityError> {
         let start_time = std::time::Instant::now();
-        let mut vulnerabilities = Vec::new();
+        let definitions = flatten(&program.definitions);
+        let context = Context::of(&definitions);
 
-        // Scan all definitions in the program
-        for definition in &program.definitions {
-            self.scan_definition(definition, &mut vulnerabilities)?;
+        let mut vulnerabilities = Vec::new();
+        let mut functions = 0;
+        let mut scanned = 0;
+        for definition in &definitions {
+            match definition {
+                Definition::FunctionDef { body, .. } => {
+                    functions += 1;
+                    if !body.statements.is_empty() {
+                        scanned += 1;
+                        self.scan_function(definition, &context, &mut vulnerabilities);
+                    }
+                }
+                Definition::GuardDef {
+                    name,
+                    before,
+                    after,
+                    ..
+                } => {
+                    let mut detector = Detector::new(&context, name);
+                    detector.block(before);
+                    detector.block(after);
+                    self.report(name, detector.findings, &mut vulnerabilities);
+                }
+                _ => {}
+            }
         }
 
         // Calculate severity counts
@@ -212,9 +348,10 @@ impl SecurityScanner {
         }
 
         let scan_duration = start_time.elapsed().as_millis() as u64;
-
-        // Estimate coverage (simplified)
-        let coverage_percentage = 75.0; // Placeholder calculation
+        let coverage_percentage = match functions {
+            0 => 100.0,
+            _ => scanned as f64 * 100.0 / functions as f64,
+        };
 
         let scan_result = ScanResult {
             total_vulnerabilities: vulnerabilities.len(),
@@ -239,222 +376,79 @@ impl SecurityScanner {
         Ok(scan_result)
     }
 
-    /// Scan a definition for vulnerabilities
-    fn scan_definition(
+    /// Scan a function, then check what it may do against who may call it
+    fn scan_function(
         &self,
         definition: &Definition,
+        context: &Context,
         vulnerabilities: &mut Vec<Vulnerability>,
-    ) -> Result<(), SecurityError> {
-        if let Definition::FunctionDef {
-            name, body, params, ..
+    ) {
+        let Definition::FunctionDef {
+            name,
+            body,
+            checked,
+            ..
         } = definition
-        {
-            self.scan_function(name, body, params, vulnerabilities)?;
-        }
-        Ok(())
-    }
-
-    /// Scan a function for vulnerabilities
-    fn scan_function(
-        &self,
-        name: &str,
-        body: &Block,
-        params: &[Parameter],
-        vulnerabilities: &mut Vec<Vulnerability>,
-    ) -> Result<(), SecurityError> {
-        // Scan function body
-        self.scan_block(body, vulnerabilities)?;
-
-        // Check for parameter-related vulnerabilities
-        self.scan_parameters(params, vulnerabilities)?;
-
-        // Check for function-specific patterns
-        self.check_function_patterns(name, body, vulnerabilities)?;
-
-        Ok(())
-    }
-
-    /// Scan a block for vulnerabilities
-    fn scan_block(
-        &self,
-        block: &Block,
-        vulnerabilities: &mut Vec<Vulnerability>,
-    ) -> Result<(), SecurityError> {
-        for statement in &block.statements {
-            self.scan_statement(statement, vulnerabilities)?;
-        }
-        Ok(())
-    }
-
-    /// Scan a statement for vulnerabilities
-    fn scan_statement(
-        &self,
-        statement: &Statement,
-        vulnerabilities: &mut Vec<Vulnerability>,
-    ) -> Result<(), SecurityError> {
-        match statement {
-            Statement::Assignment { pattern, value, .. } => {
-                self.scan_expression(value, vulnerabilities)?;
-                self.check_assignment_patterns(pattern, value, vulnerabilities)?;
-            }
-            Statement::Expr { expr, .. } => {
-                self.scan_expression(expr, vulnerabilities)?;
-            }
-            _ => {}
-        }
-        Ok(())
-    }
-
-    /// Scan an expression for vulnerabilities
-    fn scan_expression(
-        &self,
-        expr: &Expr,
-        vulnerabilities: &mut Vec<Vulnerability>,
-    ) -> Result<(), SecurityError> {
-        // Pattern-based scanning
-        let expr_str = format!("{:?}", expr);
-
-        for (vuln_type, patterns) in &self.vuln_patterns {
-            for pattern in patterns {
-                if pattern.is_match(&expr_str) {
-                    self.create_vulnerability(
-                        vuln_type,
-                        expr.location().clone(),
-                        &expr_str,
-                        vulnerabilities,
-                    )?;
-                }
-            }
-        }
-
-        // Recursive scanning for nested expressions
-        match expr {
-            Expr::BinaryOp { left, right, .. } => {
-                self.scan_expression(left, vulnerabilities)?;
-                self.scan_expression(right, vulnerabilities)?;
-            }
-            Expr::FunctionCall { function, args, .. } => {
-                self.scan_expression(function, vulnerabilities)?;
-                for arg in args {
-                    self.scan_expression(arg, vulnerabilities)?;
-                }
-            }
-            Expr::List { elements, .. } => {
-                for element in elements {
-                    self.scan_expression(element, vulnerabilities)?;
-                }
-            }
-            _ => {}
-        }
+        else {
+            return;
+        };
+        let attributes = FunctionAttributes::of(definition).unwrap_or_default();
 
-        Ok(())
-    }
+        let mut detector = Detector::new(context, name);
+        detector.unchecked = *checked == Some(false);
+        detector.non_reentrant = attributes.non_reentrant;
+        detector.block(body);
 
-    /// Scan function parameters for vulnerabilities
-    fn scan_parameters(
-        &self,
-        params: &[Parameter],
-        vulnerabilities: &mut Vec<Vulnerability>,
-    ) -> Result<(), SecurityError> {
-        for param in params {
-            // Check for parameters that might be used unsafely
-            if param.name.to_lowercase().contains("value")
-                || param.name.to_lowercase().contains("amount")
+        let guarded = attributes
+            .guards
+            .iter()
+            .any(|guard| context.caller_guards.contains(guard.name.as_str()));
+        let protected = detector.checks_caller || guarded || attributes.access.is_some();
+        if !protected && !attributes.test && name != "constructor" {
+            if let (Mutability::Mutable, Some(write)) =
+                (attributes.mutability, detector.state_change.clone())
             {
-                self.create_vulnerability(
-                    &VulnerabilityType::InputValidation,
-                    param.location.clone(),
-                    &format!("Parameter '{}' should be validated", param.name),
-                    vulnerabilities,
-                )?;
+                detector.findings.push((
+                    VulnerabilityType::AccessControl,
+                    write,
+                    format!("`{}` changes state without checking the caller", name),
+                ));
+            }
+            for terminate in std::mem::take(&mut detector.terminates) {
+                detector.findings.push((
+                    VulnerabilityType::UnprotectedSelfdestruct,
+                    terminate,
+                    format!("`{}` calls `terminate` without checking the caller", name),
+                ));
             }
         }
-        Ok(())
-    }
-
-    /// Check for function-specific vulnerability patterns
-    fn check_function_patterns(
-        &self,
-        name: &str,
-        body: &Block,
-        vulnerabilities: &mut Vec<Vulnerability>,
-    ) -> Result<(), SecurityError> {
-        // Check for unprotected selfdestruct
-        if name.to_lowercase().contains("kill") || name.to_lowercase().contains("destroy") {
-            self.create_vulnerability(
-                &VulnerabilityType::UnprotectedSelfdestruct,
-                body.location.clone(),
-                "Function that may selfdestruct should have access controls",
-                vulnerabilities,
-            )?;
-        }
-
-        // Check for fallback function
-        if name == "fallback" || name == "receive" {
-            self.create_vulnerability(
-                &VulnerabilityType::UnprotectedFallback,
-                body.location.clone(),
-                "Fallback function should have proper access controls and validation",
-                vulnerabilities,
-            )?;
-        }
-
-        Ok(())
+        self.report(name, detector.findings, vulnerabilities);
     }
 
-    /// Check assignment patterns for vulnerabilities
-    fn check_assignment_patterns(
+    /// Turn the findings of a detector into vulnerabilities, leaving out
+    /// those of ignored rules
+    fn report(
         &self,
-        target: &Pattern,
-        value: &Expr,
+        function: &str,
+        findings: Vec<Finding>,
         vulnerabilities: &mut Vec<Vulnerability>,
-    ) -> Result<(), SecurityError> {
-        // Check for state variable assignment patterns
-        if let Pattern::Variable { name, .. } = target {
-            if name.starts_with('_') || name.to_lowercase().contains("state") {
-                // This might be a state variable assignment
-                if let Expr::FunctionCall { function, .. } = value {
-                    if let Expr::Variable {
-                        name: func_name, ..
-                    } = function.as_ref()
-                    {
-                        if func_name == "msg.sender" || func_name == "tx.origin" {
-                            self.create_vulnerability(
-                                &VulnerabilityType::AccessControl,
-                                value.location().clone(),
-                                "State variable assignment based on msg.sender should be validated",
-                                vulnerabilities,
-                            )?;
-                        }
-                    }
-                }
+    ) {
+        for (vuln_type, location, context) in findings {
+            if self.ignored_patterns.contains(&vuln_type.rule_id()) {
+                continue;
             }
+            let (severity, description, recommendation, confidence) =
+                self.get_vulnerability_info(&vuln_type, &context);
+            vulnerabilities.push(Vulnerability {
+                vuln_type,
+                severity,
+                function: function.to_string(),
+                location,
+                description,
+                recommendation,
+                confidence,
+            });
         }
-
-        Ok(())
-    }
-
-    /// Create a vulnerability entry
-    fn create_vulnerability(
-        &self,
-        vuln_type: &VulnerabilityType,
-        location: Location,
-        context: &str,
-        vulnerabilities: &mut Vec<Vulnerability>,
-    ) -> Result<(), SecurityError> {
-        let (severity, description, recommendation, confidence) =
-            self.get_vulnerability_info(vuln_type, context);
-
-        vulnerabilities.push(Vulnerability {
-            vuln_type: vuln_type.clone(),
-            severity,
-            location,
-            description,
-            recommendation,
-            confidence,
-        });
-
-        Ok(())
     }
 
     /// Get vulnerability information based on type
@@ -465,75 +459,57 @@ impl SecurityScanner {
     ) -> (SecuritySeverity, String, String, f64) {
         match vuln_type {
             VulnerabilityType::IntegerOverflow => (
-                SecuritySeverity::High,
-                format!("Potential integer overflow: {}", context),
-                "Use SafeMath or checked arithmetic operations".to_string(),
-                0.8,
+                SecuritySeverity::Medium,
+                format!("Unchecked arithmetic may overflow: {}", context),
+                "Move the operation out of the `unchecked` block so overflow reverts, or check the operands first".to_string(),
+                0.6,
             ),
             VulnerabilityType::IntegerUnderflow => (
-                SecuritySeverity::High,
-                format!("Potential integer underflow: {}", context),
-                "Use SafeMath or checked arithmetic operations".to_string(),
-                0.8,
-            ),
-            VulnerabilityType::Reentrancy => (
-                SecuritySeverity::Critical,
-                format!("Potential reentrancy vulnerability: {}", context),
-                "Use reentrancy guards and check-effects-interactions pattern".to_string(),
-                0.9,
-            ),
-            VulnerabilityType::UncheckedCallReturn => (
-                SecuritySeverity::High,
-                format!("Unchecked external call return value: {}", context),
-                "Always check return values of external calls".to_string(),
-                0.9,
-            ),
-            VulnerabilityType::TimestampDependence => (
                 SecuritySeverity::Medium,
-                format!("Timestamp dependence detected: {}", context),
-                "Avoid using block.timestamp for critical logic".to_string(),
-                0.7,
+                format!("Unchecked arithmetic may underflow: {}", context),
+                "Move the operation out of the `unchecked` block so underflow reverts, or `require` the operands to be ordered".to_string(),
+                0.6,
             ),
-            VulnerabilityType::UnprotectedExternalCall => (
+            VulnerabilityType::UnboundedLoop => (
                 SecuritySeverity::High,
-                format!("Unprotected external call: {}", context),
-                "Add access controls and validation for external calls".to_string(),
+                format!("Loop over storage without a bound: {}", context),
+                "Give the loop a `bound`, or process the collection in batches across calls, so its cost cannot grow past the gas limit".to_string(),
                 0.8,
             ),
-            VulnerabilityType::UnboundedLoop => (
-                SecuritySeverity::Medium,
-                format!("Potential unbounded loop: {}", context),
-                "Ensure loops have bounded iterations".to_string(),
+            VulnerabilityType::AccessControl => (
+                SecuritySeverity::High,
+                format!("Missing access control: {}", context),
+                "Check `caller()` with `require`, or add a `#[guard(...)]` that does, or mark the function `#[view]` if it should not change state".to_string(),
                 0.6,
             ),
-            VulnerabilityType::UncheckedArrayAccess => (
+            VulnerabilityType::UncheckedCallReturn => (
                 SecuritySeverity::Medium,
-                format!("Potential unchecked array access: {}", context),
-                "Always check array bounds before access".to_string(),
+                format!("Result of an external call is discarded: {}", context),
+                "Bind the result of the call and check it before relying on the call having done what was asked".to_string(),
                 0.7,
             ),
-            VulnerabilityType::AccessControl => (
-                SecuritySeverity::High,
-                format!("Access control issue: {}", context),
-                "Implement proper access controls and validation".to_string(),
-                0.8,
-            ),
-            VulnerabilityType::InputValidation => (
+            VulnerabilityType::TimestampDependence => (
                 SecuritySeverity::Medium,
-                format!("Input validation needed: {}", context),
-                "Validate all user inputs before use".to_string(),
+                format!("Condition depends on the block timestamp: {}", context),
+                "Block producers can shift the timestamp; do not use it for randomness or deadlines tighter than a few blocks".to_string(),
                 0.7,
             ),
-            VulnerabilityType::UnprotectedFallback => (
+            VulnerabilityType::BlockNumberDependence => (
+                SecuritySeverity::Low,
+                format!("Condition depends on the block number: {}", context),
+                "Do not use the block number as a source of randomness, or as a clock when block times vary".to_string(),
+                0.6,
+            ),
+            VulnerabilityType::StateChangeAfterExternalCall => (
                 SecuritySeverity::High,
-                format!("Unprotected fallback function: {}", context),
-                "Implement proper validation and access controls in fallback".to_string(),
-                0.9,
+                format!("State changes after an external call: {}", context),
+                "Change state before calling other contracts (checks-effects-interactions), or mark the function `#[non_reentrant]`".to_string(),
+                0.7,
             ),
             VulnerabilityType::UnprotectedSelfdestruct => (
                 SecuritySeverity::Critical,
-                format!("Unprotected selfdestruct: {}", context),
-                "Add access controls to prevent unauthorized selfdestruct".to_string(),
+                format!("Anyone can terminate the contract: {}", context),
+                "Restrict `terminate` to the owner with a `require` on `caller()` or a guard that checks it".to_string(),
                 0.9,
             ),
             _ => (
@@ -560,12 +536,12 @@ impl SecurityScanner {
         self.scan_history.clear();
     }
 
-    /// Add pattern to ignore list
+    /// Leave out the findings of a rule, e.g. `timestamp-dependence`
     pub fn ignore_pattern(&mut self, pattern: &str) {
         self.ignored_patterns.insert(pattern.to_string());
     }
 
-    /// Remove pattern from ignore list
+    /// Report the findings of an ignored rule again
     pub fn unignore_pattern(&mut self, pattern: &str) {
         self.ignored_patterns.remove(pattern);
     }
@@ -587,3 +563,735 @@ impl SecurityScanner {
         score.max(0.0)
     }
 }
+
+/// A finding of a detector: what, where, and the code at fault
+type Finding = (VulnerabilityType, Location, String);
+
+/// Definitions of a program, with those of its modules
+fn flatten(definitions: &[Definition]) -> Vec<&Definition> {
+    let mut flat = Vec::new();
+    for definition in definitions {
+        match definition {
+            Definition::Module { definitions, .. } => flat.extend(flatten(definitions)),
+            definition => flat.push(definition),
+        }
+    }
+    flat
+}
+
+/// What the detectors know of the program being scanned
+struct Context<'a> {
+    storage: HashSet<&'a str>,
+    interfaces: HashSet<&'a str>,
+    /// Guards checking the caller before the guarded body runs
+    caller_guards: HashSet<&'a str>,
+    /// Functions calling other contracts, themselves or through the
+    /// functions they call
+    calls_out: HashSet<&'a str>,
+    /// Functions writing storage or transferring value, themselves or
+    /// through the functions they call
+    writes_state: HashSet<&'a str>,
+}
+
+impl<'a> Context<'a> {
+    fn of(definitions: &[&'a Definition]) -> Self {
+        let mut context = Context {
+            storage: HashSet::new(),
+            interfaces: HashSet::new(),
+            caller_guards: HashSet::new(),
+            calls_out: HashSet::new(),
+            writes_state: HashSet::new(),
+        };
+        for definition in definitions {
+            match definition {
+                Definition::StorageDef { fields, .. } => {
+                    context
+                        .storage
+                        .extend(fields.iter().map(|field| field.name.as_str()));
+                }
+                Definition::InterfaceDef { name, .. } => {
+                    context.interfaces.insert(name);
+                }
+                _ => {}
+            }
+        }
+        for definition in definitions {
+            if let Definition::GuardDef { name, before, .. } = definition {
+                let mut detector = Detector::new(&context, name);
+                detector.block(before);
+                if detector.checks_caller {
+                    context.caller_guards.insert(name);
+                }
+            }
+        }
+
+        // What each function does itself, then through the functions it
+        // calls, until nothing changes
+        let mut summaries = Vec::new();
+        for definition in definitions {
+            if let Definition::FunctionDef { name, body, .. } = definition {
+                let mut detector = Detector::new(&context, name);
+                detector.block(body);
+                summaries.push((
+                    name.as_str(),
+                    detector.interaction.is_some(),
+                    detector.state_change.is_some(),
+                    detector.callees,
+                ));
+            }
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (name, calls_out, writes_state, callees) in &summaries {
+                let through = |set: &HashSet<&str>| {
+                    callees.iter().any(|callee| set.contains(callee.as_str()))
+                };
+                if *calls_out || through(&context.calls_out) {
+                    changed |= context.calls_out.insert(name);
+                }
+                if *writes_state || through(&context.writes_state) {
+                    changed |= context.writes_state.insert(name);
+                }
+            }
+        }
+        context
+    }
+}
+
+/// Storage collection methods changing what is stored
+const STORAGE_WRITE_METHODS: [&str; 5] = ["insert", "remove", "push", "pop", "clear"];
+
+/// Calls to other contracts, whose result is theirs
+const CONTRACT_CALLS: [&str; 3] = ["call", "delegate_call", "instantiate"];
+
+/// Walks the body of one function, collecting findings and what the
+/// function does
+struct Detector<'a> {
+    context: &'a Context<'a>,
+    function: &'a str,
+    /// Whether arithmetic wraps around instead of reverting
+    unchecked: bool,
+    findings: Vec<Finding>,
+    /// Whether `caller()` is read anywhere
+    checks_caller: bool,
+    /// The first storage write or value transfer
+    state_change: Option<Location>,
+    terminates: Vec<Location>,
+    /// The first call to another contract, as reported
+    interaction: Option<String>,
+    /// Names of the functions called
+    callees: HashSet<String>,
+    /// Whether the function holds the reentrancy lock
+    non_reentrant: bool,
+}
+
+impl<'a> Detector<'a> {
+    fn new(context: &'a Context<'a>, function: &'a str) -> Self {
+        Detector {
+            context,
+            function,
+            unchecked: false,
+            findings: Vec::new(),
+            checks_caller: false,
+            state_change: None,
+            terminates: Vec::new(),
+            interaction: None,
+            callees: HashSet::new(),
+            non_reentrant: false,
+        }
+    }
+
+    fn find(&mut self, vuln_type: VulnerabilityType, location: &Location, context: String) {
+        self.findings.push((vuln_type, location.clone(), context));
+    }
+
+    fn changes_state(&mut self, location: &Location, what: &str) {
+        if self.state_change.is_none() {
+            self.state_change = Some(location.clone());
+        }
+        self.writes(location, what);
+    }
+
+    /// A change of state, which breaks checks-effects-interactions when it
+    /// follows a call to another contract that could call back in
+    fn writes(&mut self, location: &Location, what: &str) {
+        if let (Some(call), false) = (&self.interaction, self.non_reentrant) {
+            let context = format!("{} after the call to {}", what, call);
+            self.find(
+                VulnerabilityType::StateChangeAfterExternalCall,
+                location,
+                context,
+            );
+        }
+    }
+
+    fn interacts(&mut self, call: String) {
+        if self.interaction.is_none() {
+            self.interaction = Some(call);
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        for statement in &block.statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Assignment {
+                pattern,
+                value,
+                location,
+            } => {
+                self.expr(value);
+                let root = pattern_root(pattern);
+                if self.is_storage(root) {
+                    self.changes_state(location, &format!("`{}` is written", root));
+                }
+            }
+            Statement::Use { value, .. }
+            | Statement::Return { value, .. }
+            | Statement::Open { value, .. } => self.expr(value),
+            Statement::InPlaceOp {
+                target,
+                operator,
+                value,
+                location,
+            } => {
+                self.expr(value);
+                if self.is_storage(target) {
+                    self.changes_state(location, &format!("`{}` is written", target));
+                }
+                let overflow = match operator {
+                    InPlaceOperator::Add | InPlaceOperator::Mul => {
+                        Some(VulnerabilityType::IntegerOverflow)
+                    }
+                    InPlaceOperator::Sub => Some(VulnerabilityType::IntegerUnderflow),
+                    _ => None,
+                };
+                if let (true, Some(vuln_type)) = (self.unchecked, overflow) {
+                    self.find(
+                        vuln_type,
+                        location,
+                        format!("in-place operation on `{}` in `unchecked` code", target),
+                    );
+                }
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.condition(condition);
+                self.block(then_branch);
+                self.block(else_branch);
+            }
+            Statement::While {
+                condition,
+                body,
+                bound,
+                location,
+            } => {
+                self.condition(condition);
+                if let (None, Some(field)) = (bound, self.storage_read(condition)) {
+                    self.find(
+                        VulnerabilityType::UnboundedLoop,
+                        location,
+                        format!("`while` loop whose condition reads `{}`", field),
+                    );
+                }
+                self.block(body);
+            }
+            Statement::For {
+                start,
+                end,
+                body,
+                bound,
+                location,
+                ..
+            } => {
+                self.expr(start);
+                self.expr(end);
+                if let (None, Some(field)) = (bound, self.storage_read(end)) {
+                    self.find(
+                        VulnerabilityType::UnboundedLoop,
+                        location,
+                        format!("`for` loop up to a bound read from `{}`", field),
+                    );
+                }
+                self.block(body);
+            }
+            Statement::Switch { value, cases, .. } => {
+                self.condition(value);
+                for case in cases {
+                    self.block(&case.body);
+                }
+            }
+            Statement::Match { value, cases, .. } | Statement::Fold { value, cases, .. } => {
+                self.condition(value);
+                for case in cases {
+                    self.block(&case.body);
+                }
+            }
+            Statement::Bend {
+                initial_states,
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                for (_, value) in initial_states {
+                    self.expr(value);
+                }
+                self.condition(condition);
+                self.block(body);
+                if let Some(else_body) = else_body {
+                    self.block(else_body);
+                }
+            }
+            Statement::With { body, .. } => self.block(body),
+            Statement::Unchecked { body, .. } => {
+                let unchecked = std::mem::replace(&mut self.unchecked, true);
+                self.block(body);
+                self.unchecked = unchecked;
+            }
+            Statement::LocalDef { function_def, .. } => {
+                if let Definition::FunctionDef { body, .. } = function_def.as_ref() {
+                    self.block(body);
+                }
+            }
+            Statement::Expr { expr, location } => {
+                if let Some(callee) = self.external_call(expr) {
+                    self.find(
+                        VulnerabilityType::UncheckedCallReturn,
+                        location,
+                        format!("the result of `{}` is not used", callee),
+                    );
+                }
+                self.expr(expr);
+            }
+            Statement::TryCatch {
+                try_block,
+                catch_blocks,
+                ..
+            } => {
+                self.block(try_block);
+                for catch in catch_blocks {
+                    self.block(&catch.body);
+                }
+            }
+            Statement::Emit { args, .. } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Statement::Revert {
+                condition, reason, ..
+            } => {
+                if let Some(condition) = condition {
+                    self.condition(condition);
+                }
+                if let Some(reason) = reason {
+                    self.expr(reason);
+                }
+            }
+        }
+    }
+
+    /// An expression deciding what runs next
+    fn condition(&mut self, condition: &Expr) {
+        let mut reads = Vec::new();
+        chain_reads(condition, &mut reads);
+        for (vuln_type, name, location) in reads {
+            self.find(vuln_type, &location, format!("`{}()` in a condition", name));
+        }
+        self.expr(condition);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::FunctionCall {
+                function,
+                args,
+                location,
+                ..
+            } => {
+                if let Expr::Variable { name, .. } = function.as_ref() {
+                    let builtin = name.rsplit('/').next().unwrap_or(name);
+                    match builtin {
+                        "caller" | "get_caller" => self.checks_caller = true,
+                        "transfer" if args.len() == 2 => {
+                            self.changes_state(location, "value is transferred")
+                        }
+                        "terminate" => self.terminates.push(location.clone()),
+                        "set_code_hash" => self.changes_state(location, "the code is replaced"),
+                        _ => {}
+                    }
+                    if let Some((field, method)) = name.split_once('.') {
+                        if self.is_storage(field) && STORAGE_WRITE_METHODS.contains(&method) {
+                            self.changes_state(location, &format!("`{}` is changed", field));
+                        }
+                    }
+                }
+            }
+            Expr::BinaryOp {
+                operator, location, ..
+            } if self.unchecked => {
+                let vuln_type = match operator {
+                    BinaryOperator::Add | BinaryOperator::Mul | BinaryOperator::Pow => {
+                        Some(VulnerabilityType::IntegerOverflow)
+                    }
+                    BinaryOperator::Sub => Some(VulnerabilityType::IntegerUnderflow),
+                    _ => None,
+                };
+                if let Some(vuln_type) = vuln_type {
+                    self.find(
+                        vuln_type,
+                        location,
+                        format!("`{}` in `unchecked` code", operator_symbol(operator)),
+                    );
+                }
+            }
+            Expr::Block { block, .. } => {
+                self.block(block);
+                return;
+            }
+            _ => {}
+        }
+        for child in children(expr) {
+            self.expr(child);
+        }
+
+        // A call happens once its arguments are evaluated
+        if let Some(callee) = self.external_call(expr) {
+            self.interacts(format!("`{}`", callee));
+        } else if let Expr::FunctionCall {
+            function, location, ..
+        } = expr
+        {
+            if let Expr::Variable { name, .. } = function.as_ref() {
+                self.callees.insert(name.to_string());
+                if self.context.writes_state.contains(name.as_str()) {
+                    self.writes(location, &format!("`{}` changes state", name));
+                }
+                if self.context.calls_out.contains(name.as_str()) {
+                    self.interacts(format!("`{}`, which calls other contracts", name));
+                }
+            }
+        }
+    }
+
+    fn is_storage(&self, name: &str) -> bool {
+        self.context.storage.contains(name)
+    }
+
+    /// The first storage field `expr` reads
+    fn storage_read(&self, expr: &Expr) -> Option<String> {
+        if let Expr::Variable { name, .. } = expr {
+            let field = name
+                .split_once('.')
+                .map_or(name.as_str(), |(field, _)| field);
+            if self.is_storage(field) {
+                return Some(field.to_string());
+            }
+        }
+        children(expr)
+            .into_iter()
+            .find_map(|child| self.storage_read(child))
+    }
+
+    /// The callee of a call to another contract
+    fn external_call(&self, expr: &Expr) -> Option<String> {
+        let Expr::FunctionCall { function, .. } = expr else {
+            return None;
+        };
+        match function.as_ref() {
+            Expr::Variable { name, .. } if CONTRACT_CALLS.contains(&name.as_str()) => {
+                Some(name.to_string())
+            }
+            Expr::FieldAccess { object, field, .. } => match object.as_ref() {
+                Expr::FunctionCall { function, .. } => match function.as_ref() {
+                    Expr::Variable { name, .. }
+                        if self.context.interfaces.contains(name.as_str()) =>
+                    {
+                        Some(format!("{}.{}", name, field))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Reads of the block timestamp or number in `expr`
+fn chain_reads(expr: &Expr, reads: &mut Vec<(VulnerabilityType, String, Location)>) {
+    if let Expr::FunctionCall {
+        function, location, ..
+    } = expr
+    {
+        if let Expr::Variable { name, .. } = function.as_ref() {
+            let vuln_type = match name.rsplit('/').next().unwrap_or(name) {
+                "now" | "timestamp" | "block_timestamp" | "get_block_timestamp" => {
+                    Some(VulnerabilityType::TimestampDependence)
+                }
+                "block_number" | "get_block_number" => {
+                    Some(VulnerabilityType::BlockNumberDependence)
+                }
+                _ => None,
+            };
+            if let Some(vuln_type) = vuln_type {
+                reads.push((vuln_type, name.to_string(), location.clone()));
+            }
+        }
+    }
+    for child in children(expr) {
+        chain_reads(child, reads);
+    }
+}
+
+/// The expressions directly inside `expr`, but those in blocks
+pub(crate) fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Tuple { elements, .. }
+        | Expr::List { elements, .. }
+        | Expr::Array { elements, .. }
+        | Expr::Superposition { elements, .. } => elements.iter().collect(),
+        Expr::Constructor {
+            args, named_args, ..
+        } => args.iter().chain(named_args.values()).collect(),
+        Expr::FunctionCall {
+            function,
+            args,
+            named_args,
+            ..
+        } => std::iter::once(function.as_ref())
+            .chain(args)
+            .chain(named_args.values())
+            .collect(),
+        Expr::Lambda { body, .. }
+        | Expr::UnsccopedLambda { body, .. }
+        | Expr::TreeLeaf { value: body, .. }
+        | Expr::Try { expr: body, .. }
+        | Expr::UnaryOp { operand: body, .. }
+        | Expr::FieldAccess { object: body, .. } => vec![body],
+        Expr::BinaryOp { left, right, .. } | Expr::TreeNode { left, right, .. } => {
+            vec![left, right]
+        }
+        Expr::MapAccess { map, key, .. } => vec![map, key],
+        Expr::Map { entries, .. } => entries.iter().flat_map(|(k, v)| [k, v]).collect(),
+        Expr::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => vec![condition, then_branch, else_branch],
+        Expr::ListComprehension {
+            element,
+            iterable,
+            condition,
+            ..
+        } => [element, iterable]
+            .into_iter()
+            .chain(condition)
+            .map(|expr| expr.as_ref())
+            .collect(),
+        Expr::MapComprehension {
+            key,
+            value,
+            iterable,
+            condition,
+            ..
+        } => [key, value, iterable]
+            .into_iter()
+            .chain(condition)
+            .map(|expr| expr.as_ref())
+            .collect(),
+        Expr::InlineAsm { inputs, .. } => inputs.iter().map(|(_, value)| value).collect(),
+        Expr::Variable { .. } | Expr::Literal { .. } | Expr::Block { .. } | Expr::Eraser { .. } => {
+            Vec::new()
+        }
+    }
+}
+
+/// The variable an assignment to `pattern` writes to
+fn pattern_root(pattern: &Pattern) -> &str {
+    match pattern {
+        Pattern::Variable { name, .. } => name,
+        Pattern::Member { parent, .. } => pattern_root(parent),
+        Pattern::MapAccess { map, .. } => match map.as_ref() {
+            Expr::Variable { name, .. } => name,
+            _ => "",
+        },
+        _ => "",
+    }
+}
+
+fn operator_symbol(operator: &BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Sub => "-",
+        BinaryOperator::Mul => "*",
+        BinaryOperator::Pow => "**",
+        _ => "operator",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parser::Parser;
+
+    fn scan(source: &str) -> ScanResult {
+        let program = Parser::new(source).parse_program().unwrap();
+        SecurityScanner::new().scan_program(&program).unwrap()
+    }
+
+    fn rules(result: &ScanResult) -> Vec<(String, String)> {
+        result
+            .vulnerabilities
+            .iter()
+            .map(|v| (v.vuln_type.rule_id(), v.function.clone()))
+            .collect()
+    }
+
+    const STORAGE: &str =
+        "storage {\n    owner: u24,\n    total: u24,\n    holders: StorageVec<u24>,\n}\n\n";
+
+    #[test]
+    fn test_unchecked_arithmetic_and_unbounded_loops() {
+        let result = scan(&format!(
+            "{}#[view]\nfn sum(x: u24) -> u24 {{\n    unchecked {{\n        y = x * 2;\n    }}\n    z = x - 1;\n    i = 0;\n    while i < holders.len() {{\n        i = i + 1;\n    }}\n    for j in range(0, total) bound 10 {{\n        z = z + j;\n    }}\n    return y + z;\n}}\n",
+            STORAGE
+        ));
+        assert_eq!(
+            rules(&result),
+            [
+                ("integer-overflow".to_string(), "sum".to_string()),
+                ("unbounded-loop".to_string(), "sum".to_string()),
+            ]
+        );
+        assert_eq!(result.vulnerabilities[0].location.line, 10);
+        assert_eq!(result.high_count, 1);
+        assert_eq!(result.medium_count, 1);
+    }
+
+    #[test]
+    fn test_access_control_and_terminate() {
+        let source = format!(
+            "{}guard only_owner() {{\n    require(caller() == owner, \"not the owner\");\n    _;\n}}\n\nfn set_total(value: u24) -> u24 {{\n    total = value;\n    return 0;\n}}\n\n#[guard(only_owner)]\nfn reset() -> u24 {{\n    total = 0;\n    return 0;\n}}\n\nfn join() -> u24 {{\n    holders.push(caller());\n    return 0;\n}}\n\nfn close(to: u24) -> u24 {{\n    terminate(to);\n    return 0;\n}}\n\nfn upgrade(code: Hash) -> u24 {{\n    return set_code_hash(code);\n}}\n",
+            STORAGE
+        );
+        let result = scan(&source);
+        assert_eq!(
+            rules(&result),
+            [
+                ("access-control".to_string(), "set_total".to_string()),
+                ("unprotected-selfdestruct".to_string(), "close".to_string()),
+                ("access-control".to_string(), "upgrade".to_string()),
+            ]
+        );
+        assert_eq!(result.critical_count, 1);
+
+        let mut scanner = SecurityScanner::new();
+        scanner.ignore_pattern("access-control");
+        let program = Parser::new(&source).parse_program().unwrap();
+        assert_eq!(
+            scanner
+                .scan_program(&program)
+                .unwrap()
+                .total_vulnerabilities,
+            1
+        );
+    }
+
+    #[test]
+    fn test_state_change_after_external_call() {
+        let result = scan(&format!(
+            "{}interface Token {{\n    fn pay(to: u24) -> u24;\n}}\n\nfn payout(to: u24) -> u24 {{\n    require(caller() == owner, \"not the owner\");\n    sent = Token(to).pay(total);\n    total = 0;\n    return sent;\n}}\n\n#[non_reentrant]\nfn locked_payout(to: u24) -> u24 {{\n    require(caller() == owner, \"not the owner\");\n    sent = Token(to).pay(total);\n    total = 0;\n    return sent;\n}}\n\nfn forward(to: u24) -> u24 {{\n    return call(to, 0, 0, \"ping()\");\n}}\n\nfn clear() -> u24 {{\n    require(caller() == owner, \"not the owner\");\n    total = 0;\n    return 0;\n}}\n\nfn relay(to: u24) -> u24 {{\n    require(caller() == owner, \"not the owner\");\n    got = forward(to);\n    holders.push(got);\n    clear();\n    return got;\n}}\n\nfn settle(to: u24) -> u24 {{\n    require(caller() == owner, \"not the owner\");\n    total = 0;\n    return forward(to);\n}}\n",
+            STORAGE
+        ));
+        let found: Vec<_> = result
+            .vulnerabilities
+            .iter()
+            .filter(|v| v.vuln_type == VulnerabilityType::StateChangeAfterExternalCall)
+            .map(|v| (v.function.as_str(), v.location.line, v.description.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "payout",
+                    14,
+                    "State changes after an external call: `total` is written after the call to `Token.pay`"
+                ),
+                (
+                    "relay",
+                    39,
+                    "State changes after an external call: `holders` is changed after the call to `forward`, which calls other contracts"
+                ),
+                (
+                    "relay",
+                    40,
+                    "State changes after an external call: `clear` changes state after the call to `forward`, which calls other contracts"
+                ),
+            ]
+        );
+        assert!(result
+            .vulnerabilities
+            .iter()
+            .all(|v| v.function != "locked_payout"));
+    }
+
+    #[test]
+    fn test_external_calls_and_timestamps() {
+        let result = scan(
+            "interface Token {\n    fn burn(amount: u24) -> u24;\n}\n\n#[view]\nfn poke(to: u24) -> u24 {\n    call(to, 0, 0, \"ping()\");\n    Token(to).burn(1);\n    kept = call(to, 0, 0, \"ping()\");\n    require(Network/get_block_number() > 5, \"too early\");\n    if Network/get_block_timestamp() > 100 {\n        return kept;\n    } else {\n        return 0;\n    }\n}\n",
+        );
+        let found: Vec<_> = result
+            .vulnerabilities
+            .iter()
+            .map(|v| (v.vuln_type.rule_id(), v.location.line))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("unchecked-call-return".to_string(), 7),
+                ("unchecked-call-return".to_string(), 8),
+                ("block-number-dependence".to_string(), 10),
+                ("timestamp-dependence".to_string(), 11),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports() {
+        let result = scan(&format!(
+            "{}fn close(to: u24) -> u24 {{\n    terminate(to);\n    return 0;\n}}\n",
+            STORAGE
+        ));
+        let json = result.to_json();
+        assert_eq!(json["critical"], 1);
+        assert_eq!(json["findings"][0]["rule"], "unprotected-selfdestruct");
+        assert_eq!(json["findings"][0]["severity"], "critical");
+        assert_eq!(json["findings"][0]["span"]["line"], 8);
+
+        let sarif = result.to_sarif("contract.bend");
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(
+            run["tool"]["driver"]["rules"][0]["id"],
+            "unprotected-selfdestruct"
+        );
+        assert_eq!(run["results"][0]["level"], "error");
+        assert_eq!(
+            run["results"][0]["locations"][0]["physicalLocation"]["region"]["startLine"],
+            8
+        );
+        assert_eq!("sarif".parse(), Ok(ReportFormat::Sarif));
+    }
+}
@@ -1,8 +1,23 @@
-/// Static Analysis module
-///
-/// Provides comprehensive static code analysis for security properties,
-/// code quality assessment, and automated security verification.
+//! Static Analysis module
+//!
+//! Provides static code analysis for security properties. The taint pass
+//! marks contract inputs, the parameters of messages and what other
+//! contracts return, as tainted, follows them through assignments and
+//! expressions, and reports those reaching a sensitive sink unvalidated:
+//!
+//! - storage keys: the keys of `StorageMap` fields
+//! - array indices: the indices of `StorageVec` fields
+//! - transfer amounts: the value of `transfer`
+//! - call targets: the contract `call`, `delegate_call`, `instantiate` and
+//!   interface calls go to
+//!
+//! A value is validated once a `require` or `assert`, or an `if` whose
+//! branch reverts, checks it, or when it comes out of a sanitizer. A
+//! [`TaintConfig`] sets the sources, sinks and sanitizers.
+use std::collections::{HashMap, HashSet};
+
 use crate::compiler::parser::ast::*;
+use crate::security::security_scanner::children;
 use crate::security::{SecurityError, SecuritySeverity};
 
 /// Static analysis issue
@@ -17,9 +32,120 @@ pub struct AnalysisIssue {
     pub confidence: f64,
 }
 
+/// What a tainted value must not reach unvalidated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SinkKind {
+    StorageKey,
+    ArrayIndex,
+    TransferAmount,
+    CallTarget,
+}
+
+impl SinkKind {
+    pub fn rule_id(self) -> &'static str {
+        match self {
+            SinkKind::StorageKey => "taint-storage-key",
+            SinkKind::ArrayIndex => "taint-array-index",
+            SinkKind::TransferAmount => "taint-transfer-amount",
+            SinkKind::CallTarget => "taint-call-target",
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            SinkKind::StorageKey => "a storage key",
+            SinkKind::ArrayIndex => "an array index",
+            SinkKind::TransferAmount => "a transfer amount",
+            SinkKind::CallTarget => "a call target",
+        }
+    }
+
+    fn severity(self) -> SecuritySeverity {
+        match self {
+            SinkKind::TransferAmount | SinkKind::CallTarget => SecuritySeverity::High,
+            SinkKind::StorageKey | SinkKind::ArrayIndex => SecuritySeverity::Medium,
+        }
+    }
+}
+
+/// An argument of a function that is a sink
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaintSink {
+    pub function: String,
+    pub argument: usize,
+    pub kind: SinkKind,
+}
+
+impl TaintSink {
+    pub fn new(function: &str, argument: usize, kind: SinkKind) -> Self {
+        TaintSink {
+            function: function.to_string(),
+            argument,
+            kind,
+        }
+    }
+}
+
+/// Sources, sinks and sanitizers of the taint pass
+#[derive(Debug, Clone)]
+pub struct TaintConfig {
+    /// Whether the parameters of messages are tainted
+    pub taint_parameters: bool,
+    /// Functions whose results are tainted
+    pub sources: HashSet<String>,
+    /// Function arguments that are sinks
+    pub sinks: Vec<TaintSink>,
+    /// Sinks found in the shape of the code: storage keys, indices and
+    /// interface call targets
+    pub structural_sinks: HashSet<SinkKind>,
+    /// Functions whose results are validated
+    pub sanitizers: HashSet<String>,
+}
+
+impl Default for TaintConfig {
+    fn default() -> Self {
+        TaintConfig {
+            taint_parameters: true,
+            sources: ["call", "delegate_call"].map(String::from).into(),
+            sinks: vec![
+                TaintSink::new("transfer", 1, SinkKind::TransferAmount),
+                TaintSink::new("call", 0, SinkKind::CallTarget),
+                TaintSink::new("delegate_call", 0, SinkKind::CallTarget),
+                TaintSink::new("instantiate", 0, SinkKind::CallTarget),
+                TaintSink::new("set_code_hash", 0, SinkKind::CallTarget),
+            ],
+            structural_sinks: [
+                SinkKind::StorageKey,
+                SinkKind::ArrayIndex,
+                SinkKind::CallTarget,
+            ]
+            .into(),
+            sanitizers: HashSet::new(),
+        }
+    }
+}
+
+impl TaintConfig {
+    pub fn with_source(mut self, function: &str) -> Self {
+        self.sources.insert(function.to_string());
+        self
+    }
+
+    pub fn with_sink(mut self, sink: TaintSink) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn with_sanitizer(mut self, function: &str) -> Self {
+        self.sanitizers.insert(function.to_string());
+        self
+    }
+}
+
 /// Static analyzer
 pub struct StaticAnalyzer {
     issues: Vec<AnalysisIssue>,
+    taint: TaintConfig,
 }
 
 impl Default for StaticAnalyzer {
@@ -31,15 +157,71 @@ impl Default for StaticAnalyzer {
 impl StaticAnalyzer {
     /// Create a new static analyzer
     pub fn new() -> Self {
-        Self { issues: Vec::new() }
+        Self {
+            issues: Vec::new(),
+            taint: TaintConfig::default(),
+        }
+    }
+
+    pub fn with_taint_config(mut self, taint: TaintConfig) -> Self {
+        self.taint = taint;
+        self
     }
 
     /// Analyze a program
     pub fn analyze_program(
         &mut self,
-        _program: &Program,
+        program: &Program,
     ) -> Result<Vec<AnalysisIssue>, SecurityError> {
-        // Simplified analysis - just return existing issues
+        let storage = storage_fields(&program.definitions);
+        let interfaces = program
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                Definition::InterfaceDef { name, .. } => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        self.issues.clear();
+        for definition in &program.definitions {
+            if let Definition::FunctionDef {
+                name,
+                params,
+                body,
+                attributes,
+                ..
+            } = definition
+            {
+                if attributes.iter().any(|attribute| attribute.name == "test") {
+                    continue;
+                }
+                let mut pass = TaintPass {
+                    config: &self.taint,
+                    storage: &storage,
+                    interfaces: &interfaces,
+                    function: name,
+                    tainted: HashMap::new(),
+                    issues: Vec::new(),
+                };
+                if self.taint.taint_parameters {
+                    for param in params {
+                        pass.tainted.insert(
+                            param.name.to_string(),
+                            format!("parameter `{}`", param.name),
+                        );
+                    }
+                }
+                pass.block(body);
+                for issue in pass.issues {
+                    if !self.issues.iter().any(|found| {
+                        found.rule_id == issue.rule_id && found.location == issue.location
+                    }) {
+                        self.issues.push(issue);
+                    }
+                }
+            }
+        }
         Ok(self.issues.clone())
     }
 
@@ -48,3 +230,466 @@ impl StaticAnalyzer {
         &self.issues
     }
 }
+
+/// Storage field names with the type of collection they are
+fn storage_fields(definitions: &[Definition]) -> HashMap<&str, &str> {
+    let mut fields = HashMap::new();
+    for definition in definitions {
+        if let Definition::StorageDef {
+            fields: storage, ..
+        } = definition
+        {
+            for field in storage {
+                let ty = match &field.ty {
+                    Type::Named { name, .. } => name.as_str(),
+                    _ => "",
+                };
+                fields.insert(field.name.as_str(), ty);
+            }
+        }
+    }
+    fields
+}
+
+/// Follows tainted values through one function
+struct TaintPass<'a> {
+    config: &'a TaintConfig,
+    storage: &'a HashMap<&'a str, &'a str>,
+    interfaces: &'a HashSet<&'a str>,
+    function: &'a str,
+    /// Tainted variables with where their taint comes from
+    tainted: HashMap<String, String>,
+    issues: Vec<AnalysisIssue>,
+}
+
+impl TaintPass<'_> {
+    fn block(&mut self, block: &Block) {
+        for statement in &block.statements {
+            self.statement(statement);
+        }
+    }
+
+    /// Run each of `blocks` from the current taint, then keep what is
+    /// tainted after any of them
+    fn branches(&mut self, blocks: &[&Block]) {
+        let before = self.tainted.clone();
+        let mut after = HashMap::new();
+        for block in blocks {
+            self.tainted = before.clone();
+            self.block(block);
+            after.extend(self.tainted.drain());
+        }
+        self.tainted = after;
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Assignment { pattern, value, .. } => {
+                let origin = self.expr(value);
+                if let Pattern::MapAccess { map, key, .. } = pattern {
+                    self.map_key(map, key);
+                }
+                let mut names = Vec::new();
+                bound_names(pattern, &mut names);
+                for name in names {
+                    self.assign(name, origin.clone());
+                }
+            }
+            Statement::Use { name, value, .. } => {
+                let origin = self.expr(value);
+                self.assign(name, origin);
+            }
+            Statement::InPlaceOp { target, value, .. } => {
+                if let Some(origin) = self.expr(value) {
+                    self.tainted.insert(target.to_string(), origin);
+                }
+            }
+            Statement::Return { value, .. } | Statement::Open { value, .. } => {
+                self.expr(value);
+            }
+            Statement::Expr { expr, .. } => {
+                self.expr(expr);
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expr(condition);
+                // A branch that reverts leaves the rest of the function to
+                // the values the condition accepts
+                if reverts(then_branch) || reverts(else_branch) {
+                    self.sanitize(condition);
+                }
+                self.branches(&[then_branch, else_branch]);
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.expr(condition);
+                // Twice, for the taint of one iteration to reach the next
+                self.branches(&[body, body]);
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                ..
+            } => {
+                let origin = self.expr(start).or(self.expr(end));
+                self.assign(variable, origin);
+                self.branches(&[body, body]);
+            }
+            Statement::Switch { value, cases, .. } => {
+                self.expr(value);
+                let blocks: Vec<&Block> = cases.iter().map(|case| &case.body).collect();
+                self.branches(&blocks);
+            }
+            Statement::Match { value, cases, .. } | Statement::Fold { value, cases, .. } => {
+                let origin = self.expr(value);
+                let before = self.tainted.clone();
+                let mut after = HashMap::new();
+                for case in cases {
+                    self.tainted = before.clone();
+                    let mut names = Vec::new();
+                    bound_names(&case.pattern, &mut names);
+                    for name in names {
+                        self.assign(name, origin.clone());
+                    }
+                    self.block(&case.body);
+                    after.extend(self.tainted.drain());
+                }
+                self.tainted = after;
+            }
+            Statement::Bend {
+                initial_states,
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                for (name, value) in initial_states {
+                    let origin = self.expr(value);
+                    self.assign(name, origin);
+                }
+                self.expr(condition);
+                match else_body {
+                    Some(else_body) => self.branches(&[body, else_body]),
+                    None => self.branches(&[body]),
+                }
+            }
+            Statement::With { body, .. } | Statement::Unchecked { body, .. } => self.block(body),
+            Statement::LocalDef { .. } => {}
+            Statement::TryCatch {
+                try_block,
+                catch_blocks,
+                ..
+            } => {
+                let mut blocks = vec![try_block];
+                blocks.extend(catch_blocks.iter().map(|catch| &catch.body));
+                self.branches(&blocks);
+            }
+            Statement::Emit { args, .. } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Statement::Revert {
+                kind,
+                condition,
+                reason,
+                ..
+            } => {
+                if let Some(condition) = condition {
+                    self.expr(condition);
+                    if *kind != RevertKind::Revert {
+                        self.sanitize(condition);
+                    }
+                }
+                if let Some(reason) = reason {
+                    self.expr(reason);
+                }
+            }
+        }
+    }
+
+    fn assign(&mut self, name: &str, origin: Option<String>) {
+        match origin {
+            Some(origin) => {
+                self.tainted.insert(name.to_string(), origin);
+            }
+            None => {
+                self.tainted.remove(name);
+            }
+        }
+    }
+
+    /// Validate the variables `condition` checks
+    fn sanitize(&mut self, condition: &Expr) {
+        let mut names = Vec::new();
+        variables(condition, &mut names);
+        for name in names {
+            self.tainted.remove(name);
+        }
+    }
+
+    /// Where the taint of `expr` comes from, reporting the sinks it
+    /// reaches on the way
+    fn expr(&mut self, expr: &Expr) -> Option<String> {
+        match expr {
+            Expr::Variable { name, .. } => self.tainted.get(name.as_str()).cloned(),
+            Expr::Literal { .. } | Expr::Eraser { .. } => None,
+            Expr::Block { block, .. } => {
+                self.block(block);
+                None
+            }
+            Expr::MapAccess { map, key, .. } => {
+                self.map_key(map, key);
+                self.expr(map).or(self.expr(key))
+            }
+            Expr::FunctionCall { function, args, .. } => {
+                let origins: Vec<Option<String>> = args.iter().map(|arg| self.expr(arg)).collect();
+                self.call(function, args, &origins);
+                match function.as_ref() {
+                    Expr::Variable { name, .. } => {
+                        if self.config.sanitizers.contains(name.as_str()) {
+                            None
+                        } else if self.config.sources.contains(name.as_str()) {
+                            Some(format!("the result of `{}`", name))
+                        } else if name
+                            .split_once('.')
+                            .is_some_and(|(field, _)| self.storage.contains_key(field))
+                        {
+                            // What storage holds was validated when written
+                            None
+                        } else {
+                            origins.into_iter().flatten().next()
+                        }
+                    }
+                    function => {
+                        let callee = self.expr(function);
+                        origins.into_iter().flatten().next().or(callee)
+                    }
+                }
+            }
+            expr => {
+                let mut origin = None;
+                for child in children(expr) {
+                    origin = self.expr(child).or(origin);
+                }
+                origin
+            }
+        }
+    }
+
+    /// Report the tainted arguments of a call that reach a sink
+    fn call(&mut self, function: &Expr, args: &[Expr], origins: &[Option<String>]) {
+        match function {
+            Expr::Variable { name, .. } => {
+                let sinks: Vec<(usize, SinkKind)> = self
+                    .config
+                    .sinks
+                    .iter()
+                    .filter(|sink| &sink.function == name)
+                    .map(|sink| (sink.argument, sink.kind))
+                    .collect();
+                for (argument, kind) in sinks {
+                    if let (Some(arg), Some(Some(origin))) =
+                        (args.get(argument), origins.get(argument))
+                    {
+                        self.report(kind, arg, origin.clone(), name);
+                    }
+                }
+                let Some((field, method)) = name.split_once('.') else {
+                    return;
+                };
+                let kind = match (self.storage.get(field).copied(), method) {
+                    (Some("StorageMap"), "get" | "insert" | "remove" | "contains") => {
+                        SinkKind::StorageKey
+                    }
+                    (Some("StorageVec"), "get" | "set") => SinkKind::ArrayIndex,
+                    _ => return,
+                };
+                if let (Some(arg), Some(Some(origin))) = (args.first(), origins.first()) {
+                    self.structural(kind, arg, origin.clone(), name);
+                }
+            }
+            // `Interface(address).message(args)`
+            Expr::FieldAccess { object, field, .. } => {
+                if let Expr::FunctionCall {
+                    function: interface,
+                    args: address,
+                    ..
+                } = object.as_ref()
+                {
+                    if let Expr::Variable { name, .. } = interface.as_ref() {
+                        if self.interfaces.contains(name.as_str()) {
+                            if let Some(address) = address.first() {
+                                if let Some(origin) = self.expr(address) {
+                                    let callee = format!("{}.{}", name, field);
+                                    self.structural(SinkKind::CallTarget, address, origin, &callee);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Report a tainted key indexing a storage map
+    fn map_key(&mut self, map: &Expr, key: &Expr) {
+        let Expr::Variable { name, .. } = map else {
+            return;
+        };
+        if !self.storage.contains_key(name.as_str()) {
+            return;
+        }
+        if let Some(origin) = self.expr(key) {
+            self.structural(SinkKind::StorageKey, key, origin, name);
+        }
+    }
+
+    fn structural(&mut self, kind: SinkKind, arg: &Expr, origin: String, callee: &str) {
+        if self.config.structural_sinks.contains(&kind) {
+            self.report(kind, arg, origin, callee);
+        }
+    }
+
+    fn report(&mut self, kind: SinkKind, arg: &Expr, origin: String, callee: &str) {
+        self.issues.push(AnalysisIssue {
+            rule_id: kind.rule_id().to_string(),
+            rule_name: format!("Untrusted input used as {}", kind.describe()),
+            severity: kind.severity(),
+            location: arg.location().clone(),
+            message: format!(
+                "In `{}`, {} reaches {} of `{}` without validation",
+                self.function,
+                origin,
+                kind.describe(),
+                callee
+            ),
+            suggestion:
+                "Check the value with `require` before using it, or pass it through a sanitizer"
+                    .to_string(),
+            confidence: 0.7,
+        });
+    }
+}
+
+/// Whether running `block` always ends in a revert
+fn reverts(block: &Block) -> bool {
+    block.statements.last().is_some_and(|statement| {
+        matches!(
+            statement,
+            Statement::Revert {
+                kind: RevertKind::Revert,
+                ..
+            }
+        )
+    })
+}
+
+/// The variables a pattern binds
+fn bound_names<'a>(pattern: &'a Pattern, names: &mut Vec<&'a str>) {
+    match pattern {
+        Pattern::Variable { name, .. } => names.push(name),
+        Pattern::Tuple { elements, .. } => {
+            for element in elements {
+                bound_names(element, names);
+            }
+        }
+        Pattern::Constructor { fields, .. } => {
+            for field in fields.values() {
+                bound_names(field, names);
+            }
+        }
+        Pattern::TupleConstructor { args, .. } => {
+            for arg in args {
+                bound_names(arg, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The variables `expr` reads
+fn variables<'a>(expr: &'a Expr, names: &mut Vec<&'a str>) {
+    if let Expr::Variable { name, .. } = expr {
+        names.push(name);
+    }
+    for child in children(expr) {
+        variables(child, names);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parser::Parser;
+
+    fn analyze(config: TaintConfig, source: &str) -> Vec<(String, usize)> {
+        let program = Parser::new(source).parse_program().unwrap();
+        StaticAnalyzer::new()
+            .with_taint_config(config)
+            .analyze_program(&program)
+            .unwrap()
+            .into_iter()
+            .map(|issue| (issue.rule_id, issue.location.line))
+            .collect()
+    }
+
+    const STORAGE: &str =
+        "storage {\n    balances: StorageMap<u24, u24>,\n    holders: StorageVec<u24>,\n}\n\n";
+
+    #[test]
+    fn test_inputs_reaching_sinks() {
+        let source = format!(
+            "{}fn pay(who: u24, index: u24, amount: u24) -> u24 {{\n    balances.insert(who, 1);\n    h = holders.get(index + 1);\n    transfer(h, amount);\n    stored = balances.get(0);\n    transfer(h, stored);\n    return call(who, 0, 0, \"ping()\");\n}}\n",
+            STORAGE
+        );
+        assert_eq!(
+            analyze(TaintConfig::default(), &source),
+            [
+                ("taint-storage-key".to_string(), 7),
+                ("taint-array-index".to_string(), 8),
+                ("taint-transfer-amount".to_string(), 9),
+                ("taint-call-target".to_string(), 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validation_clears_taint() {
+        let source = format!(
+            "{}fn pay(who: u24, amount: u24, other: u24) -> u24 {{\n    require(amount < 100, \"too much\");\n    if who > 10 {{\n        revert(\"unknown\");\n    }} else {{\n        x = 0;\n    }}\n    balances.insert(who, amount);\n    transfer(1, amount);\n    clamped = clamp(other);\n    transfer(1, clamped);\n    return 0;\n}}\n",
+            STORAGE
+        );
+        assert_eq!(
+            analyze(TaintConfig::default(), &source),
+            [("taint-transfer-amount".to_string(), 16)]
+        );
+        assert!(analyze(TaintConfig::default().with_sanitizer("clamp"), &source).is_empty());
+    }
+
+    #[test]
+    fn test_configured_sources_and_sinks() {
+        let source = "interface Vault {\n    fn pull(amount: u24) -> u24;\n}\n\nfn relay() -> u24 {\n    target = oracle();\n    Vault(target).pull(1);\n    return send_to(target);\n}\n";
+        assert!(analyze(TaintConfig::default(), source).is_empty());
+        assert_eq!(
+            analyze(
+                TaintConfig::default()
+                    .with_source("oracle")
+                    .with_sink(TaintSink::new("send_to", 0, SinkKind::TransferAmount)),
+                source
+            ),
+            [
+                ("taint-call-target".to_string(), 7),
+                ("taint-transfer-amount".to_string(), 8),
+            ]
+        );
+    }
+}
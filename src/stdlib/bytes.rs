@@ -0,0 +1,229 @@
+//! Raw byte arrays
+//!
+//! `Bytes` backs the language's `Bytes` type. In contract memory a value is a
+//! pointer to a `[len: u32][data]` block; across the contract boundary it is
+//! SCALE-encoded as a compact length prefix followed by the raw bytes.
+
+use std::fmt;
+
+use crate::stdlib::storage::StorageCodec;
+
+/// A growable byte array
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Bytes(Vec<u8>);
+
+impl Bytes {
+    /// Create an empty byte array
+    pub fn new() -> Self {
+        Bytes(Vec::new())
+    }
+
+    /// Parse a `0x`-prefixed (or bare) hex string
+    pub fn from_hex(s: &str) -> Result<Self, hex::FromHexError> {
+        hex::decode(s.strip_prefix("0x").unwrap_or(s)).map(Bytes)
+    }
+
+    /// Number of bytes
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check if there are no bytes
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get the bytes in range [start, end), or `None` when out of bounds
+    pub fn slice(&self, start: usize, end: usize) -> Option<Bytes> {
+        self.0.get(start..end).map(|bytes| Bytes(bytes.to_vec()))
+    }
+
+    /// Append `other` to a copy of these bytes
+    pub fn concat(&self, other: &Bytes) -> Bytes {
+        let mut bytes = self.0.clone();
+        bytes.extend_from_slice(&other.0);
+        Bytes(bytes)
+    }
+
+    /// Borrow the raw bytes
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Take the raw bytes
+    pub fn into_vec(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// SCALE encoding: compact length prefix, then the bytes
+    pub fn scale_encode(&self) -> Vec<u8> {
+        let mut out = encode_compact(self.0.len() as u32);
+        out.extend_from_slice(&self.0);
+        out
+    }
+
+    /// Decode a SCALE-encoded value, returning it and the bytes consumed
+    pub fn scale_decode(input: &[u8]) -> Option<(Bytes, usize)> {
+        let (len, prefix) = decode_compact(input)?;
+        let end = prefix.checked_add(len as usize)?;
+        let data = input.get(prefix..end)?;
+        Some((Bytes(data.to_vec()), end))
+    }
+
+    /// Solidity ABI encoding of a `bytes` tail: a 32-byte big-endian length,
+    /// then the bytes right-padded to a multiple of 32
+    pub fn abi_encode(&self) -> Vec<u8> {
+        let mut out = vec![0u8; 28];
+        out.extend_from_slice(&(self.0.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.0);
+        out.resize(32 + self.0.len().div_ceil(32) * 32, 0);
+        out
+    }
+
+    /// Decode a Solidity ABI `bytes` tail
+    pub fn abi_decode(input: &[u8]) -> Option<Bytes> {
+        let head = input.get(..32)?;
+        if head[..28].iter().any(|&b| b != 0) {
+            return None;
+        }
+        let len = u32::from_be_bytes(head[28..].try_into().ok()?) as usize;
+        input.get(32..32 + len).map(|data| Bytes(data.to_vec()))
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bytes(bytes)
+    }
+}
+
+impl From<&[u8]> for Bytes {
+    fn from(bytes: &[u8]) -> Self {
+        Bytes(bytes.to_vec())
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{}", hex::encode(&self.0))
+    }
+}
+
+impl StorageCodec for Bytes {
+    fn encode(&self) -> Vec<u8> {
+        self.scale_encode()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        match Bytes::scale_decode(bytes)? {
+            (value, used) if used == bytes.len() => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// SCALE compact encoding of a length
+///
+/// Values below 2^30 use the one, two and four byte modes; larger values use
+/// the big-integer mode with a four byte payload.
+pub fn encode_compact(value: u32) -> Vec<u8> {
+    match value {
+        0..=0x3F => vec![(value << 2) as u8],
+        0x40..=0x3FFF => ((value << 2) as u16 | 0b01).to_le_bytes().to_vec(),
+        0x4000..=0x3FFF_FFFF => ((value << 2) | 0b10).to_le_bytes().to_vec(),
+        _ => {
+            let mut out = vec![0b11];
+            out.extend_from_slice(&value.to_le_bytes());
+            out
+        }
+    }
+}
+
+/// Decode a SCALE compact length, returning it and the prefix size
+pub fn decode_compact(input: &[u8]) -> Option<(u32, usize)> {
+    let first = *input.first()?;
+    match first & 0b11 {
+        0b00 => Some(((first >> 2) as u32, 1)),
+        0b01 => {
+            let raw = u16::from_le_bytes(input.get(..2)?.try_into().ok()?);
+            let value = (raw >> 2) as u32;
+            (value > 0x3F).then_some((value, 2))
+        }
+        0b10 => {
+            let raw = u32::from_le_bytes(input.get(..4)?.try_into().ok()?);
+            let value = raw >> 2;
+            (value > 0x3FFF).then_some((value, 4))
+        }
+        _ => {
+            // Only four byte payloads fit a u32 length
+            if first >> 2 != 0 {
+                return None;
+            }
+            let value = u32::from_le_bytes(input.get(1..5)?.try_into().ok()?);
+            (value > 0x3FFF_FFFF).then_some((value, 5))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bytes_operations() {
+        let bytes = Bytes::from_hex("0xdeadbeef").unwrap();
+        assert_eq!(bytes.len(), 4);
+        assert!(!bytes.is_empty());
+        assert!(Bytes::new().is_empty());
+        assert_eq!(bytes.slice(1, 3).unwrap().as_slice(), &[0xad, 0xbe]);
+        assert_eq!(bytes.slice(3, 5), None);
+        assert_eq!(bytes.slice(3, 2), None);
+        assert_eq!(
+            bytes.concat(&Bytes::from(vec![1, 2])).to_string(),
+            "0xdeadbeef0102"
+        );
+        assert!(Bytes::from_hex("0xabc").is_err());
+    }
+
+    #[test]
+    fn test_compact_encoding() {
+        for (value, encoded) in [
+            (0u32, vec![0x00]),
+            (1, vec![0x04]),
+            (63, vec![0xfc]),
+            (64, vec![0x01, 0x01]),
+            (16383, vec![0xfd, 0xff]),
+            (16384, vec![0x02, 0x00, 0x01, 0x00]),
+            (0x3FFF_FFFF, vec![0xfe, 0xff, 0xff, 0xff]),
+            (0x4000_0000, vec![0x03, 0x00, 0x00, 0x00, 0x40]),
+        ] {
+            assert_eq!(encode_compact(value), encoded);
+            assert_eq!(decode_compact(&encoded), Some((value, encoded.len())));
+        }
+
+        // Non-canonical encodings are rejected
+        assert_eq!(decode_compact(&[0x01, 0x00]), None);
+        assert_eq!(decode_compact(&[]), None);
+    }
+
+    #[test]
+    fn test_scale_and_abi_roundtrip() {
+        let bytes = Bytes::from(vec![7u8; 70]);
+        let encoded = bytes.scale_encode();
+        assert_eq!(&encoded[..2], &[0x19, 0x01]);
+        assert_eq!(Bytes::scale_decode(&encoded), Some((bytes.clone(), 72)));
+        assert_eq!(Bytes::scale_decode(&encoded[..71]), None);
+        assert_eq!(StorageCodec::decode(&bytes.encode()), Some(bytes.clone()));
+
+        let abi = bytes.abi_encode();
+        assert_eq!(abi.len(), 32 + 96);
+        assert_eq!(abi[31], 70);
+        assert_eq!(Bytes::abi_decode(&abi), Some(bytes));
+    }
+}
@@ -106,22 +106,22 @@ impl StdlibModule {
         };
 
         let string_type = Type::Named {
-            name: "String".to_string(),
+            name: "String".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         };
 
         // String/concat
         definitions.push(Definition::FunctionDef {
-            name: "String/concat".to_string(),
+            name: "String/concat".into(),
             params: vec![
                 Parameter {
-                    name: "a".to_string(),
+                    name: "a".into(),
                     ty: string_type.clone(),
                     location: dummy_loc.clone(),
                 },
                 Parameter {
-                    name: "b".to_string(),
+                    name: "b".into(),
                     ty: string_type.clone(),
                     location: dummy_loc.clone(),
                 },
@@ -132,14 +132,16 @@ impl StdlibModule {
                 location: dummy_loc.clone(),
             },
             checked: Some(true),
+            attributes: Vec::new(),
+            visibility: Visibility::Public,
             location: dummy_loc.clone(),
         });
 
         // String/length
         definitions.push(Definition::FunctionDef {
-            name: "String/length".to_string(),
+            name: "String/length".into(),
             params: vec![Parameter {
-                name: "s".to_string(),
+                name: "s".into(),
                 ty: string_type.clone(),
                 location: dummy_loc.clone(),
             }],
@@ -151,14 +153,16 @@ impl StdlibModule {
                 location: dummy_loc.clone(),
             },
             checked: Some(true),
+            attributes: Vec::new(),
+            visibility: Visibility::Public,
             location: dummy_loc.clone(),
         });
 
         // String/to_uppercase
         definitions.push(Definition::FunctionDef {
-            name: "String/to_uppercase".to_string(),
+            name: "String/to_uppercase".into(),
             params: vec![Parameter {
-                name: "s".to_string(),
+                name: "s".into(),
                 ty: string_type.clone(),
                 location: dummy_loc.clone(),
             }],
@@ -168,14 +172,16 @@ impl StdlibModule {
                 location: dummy_loc.clone(),
             },
             checked: Some(true),
+            attributes: Vec::new(),
+            visibility: Visibility::Public,
             location: dummy_loc.clone(),
         });
 
         // String/to_lowercase
         definitions.push(Definition::FunctionDef {
-            name: "String/to_lowercase".to_string(),
+            name: "String/to_lowercase".into(),
             params: vec![Parameter {
-                name: "s".to_string(),
+                name: "s".into(),
                 ty: string_type.clone(),
                 location: dummy_loc.clone(),
             }],
@@ -185,14 +191,16 @@ impl StdlibModule {
                 location: dummy_loc.clone(),
             },
             checked: Some(true),
+            attributes: Vec::new(),
+            visibility: Visibility::Public,
             location: dummy_loc.clone(),
         });
 
         // String/trim
         definitions.push(Definition::FunctionDef {
-            name: "String/trim".to_string(),
+            name: "String/trim".into(),
             params: vec![Parameter {
-                name: "s".to_string(),
+                name: "s".into(),
                 ty: string_type.clone(),
                 location: dummy_loc.clone(),
             }],
@@ -202,20 +210,22 @@ impl StdlibModule {
                 location: dummy_loc.clone(),
             },
             checked: Some(true),
+            attributes: Vec::new(),
+            visibility: Visibility::Public,
             location: dummy_loc.clone(),
         });
 
         // String/contains
         definitions.push(Definition::FunctionDef {
-            name: "String/contains".to_string(),
+            name: "String/contains".into(),
             params: vec![
                 Parameter {
-                    name: "s".to_string(),
+                    name: "s".into(),
                     ty: string_type.clone(),
                     location: dummy_loc.clone(),
                 },
                 Parameter {
-                    name: "substring".to_string(),
+                    name: "substring".into(),
                     ty: string_type.clone(),
                     location: dummy_loc.clone(),
                 },
@@ -228,6 +238,8 @@ impl StdlibModule {
                 location: dummy_loc.clone(),
             },
             checked: Some(true),
+            attributes: Vec::new(),
+            visibility: Visibility::Public,
             location: dummy_loc.clone(),
         });
 
@@ -248,21 +260,21 @@ impl StdlibModule {
         };
 
         let string_type = Type::Named {
-            name: "String".to_string(),
+            name: "String".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         };
 
         // IO/storage_get
         definitions.push(Definition::FunctionDef {
-            name: "IO/storage_get".to_string(),
+            name: "IO/storage_get".into(),
             params: vec![Parameter {
-                name: "key".to_string(),
+                name: "key".into(),
                 ty: string_type.clone(),
                 location: dummy_loc.clone(),
             }],
             return_type: Some(Type::Named {
-                name: "Result".to_string(),
+                name: "Result".into(),
                 params: vec![string_type.clone(), string_type.clone()],
                 location: dummy_loc.clone(),
             }),
@@ -271,6 +283,8 @@ impl StdlibModule {
                 location: dummy_loc.clone(),
             },
             checked: Some(true),
+            attributes: Vec::new(),
+            visibility: Visibility::Public,
             location: dummy_loc.clone(),
         });
 
@@ -292,21 +306,21 @@ impl StdlibModule {
 
         // List type definition
         definitions.push(Definition::TypeDef {
-            name: "List".to_string(),
+            name: "List".into(),
             type_params: vec!["T".to_string()],
             variants: vec![
                 TypeVariant {
-                    name: "Nil".to_string(),
+                    name: "Nil".into(),
                     fields: Vec::new(),
                     location: dummy_loc.clone(),
                 },
                 TypeVariant {
-                    name: "Cons".to_string(),
+                    name: "Cons".into(),
                     fields: vec![
                         Field {
-                            name: "head".to_string(),
+                            name: "head".into(),
                             type_annotation: Some(Type::Named {
-                                name: "T".to_string(),
+                                name: "T".into(),
                                 params: Vec::new(),
                                 location: dummy_loc.clone(),
                             }),
@@ -314,11 +328,11 @@ impl StdlibModule {
                             location: dummy_loc.clone(),
                         },
                         Field {
-                            name: "tail".to_string(),
+                            name: "tail".into(),
                             type_annotation: Some(Type::Named {
-                                name: "List".to_string(),
+                                name: "List".into(),
                                 params: vec![Type::Named {
-                                    name: "T".to_string(),
+                                    name: "T".into(),
                                     params: Vec::new(),
                                     location: dummy_loc.clone(),
                                 }],
@@ -331,6 +345,8 @@ impl StdlibModule {
                     location: dummy_loc.clone(),
                 },
             ],
+            attributes: Vec::new(),
+            visibility: Visibility::Public,
             location: dummy_loc.clone(),
         });
 
@@ -352,20 +368,20 @@ impl StdlibModule {
 
         // Option type definition
         definitions.push(Definition::TypeDef {
-            name: "Option".to_string(),
+            name: "Option".into(),
             type_params: vec!["T".to_string()],
             variants: vec![
                 TypeVariant {
-                    name: "None".to_string(),
+                    name: "None".into(),
                     fields: Vec::new(),
                     location: dummy_loc.clone(),
                 },
                 TypeVariant {
-                    name: "Some".to_string(),
+                    name: "Some".into(),
                     fields: vec![Field {
-                        name: "value".to_string(),
+                        name: "value".into(),
                         type_annotation: Some(Type::Named {
-                            name: "T".to_string(),
+                            name: "T".into(),
                             params: Vec::new(),
                             location: dummy_loc.clone(),
                         }),
@@ -375,6 +391,8 @@ impl StdlibModule {
                     location: dummy_loc.clone(),
                 },
             ],
+            attributes: Vec::new(),
+            visibility: Visibility::Public,
             location: dummy_loc.clone(),
         });
 
@@ -396,15 +414,15 @@ impl StdlibModule {
 
         // Result type definition
         definitions.push(Definition::TypeDef {
-            name: "Result".to_string(),
+            name: "Result".into(),
             type_params: vec!["T".to_string(), "E".to_string()],
             variants: vec![
                 TypeVariant {
-                    name: "Ok".to_string(),
+                    name: "Ok".into(),
                     fields: vec![Field {
-                        name: "value".to_string(),
+                        name: "value".into(),
                         type_annotation: Some(Type::Named {
-                            name: "T".to_string(),
+                            name: "T".into(),
                             params: Vec::new(),
                             location: dummy_loc.clone(),
                         }),
@@ -414,11 +432,11 @@ impl StdlibModule {
                     location: dummy_loc.clone(),
                 },
                 TypeVariant {
-                    name: "Err".to_string(),
+                    name: "Err".into(),
                     fields: vec![Field {
-                        name: "error".to_string(),
+                        name: "error".into(),
                         type_annotation: Some(Type::Named {
-                            name: "E".to_string(),
+                            name: "E".into(),
                             params: Vec::new(),
                             location: dummy_loc.clone(),
                         }),
@@ -428,6 +446,8 @@ impl StdlibModule {
                     location: dummy_loc.clone(),
                 },
             ],
+            attributes: Vec::new(),
+            visibility: Visibility::Public,
             location: dummy_loc.clone(),
         });
 
@@ -2,6 +2,17 @@ use crate::compiler::parser::ast::*;
 use crate::runtime::env::Environment;
 use crate::runtime::metering::MeteringError;
 
+use blake2::digest::consts::U32;
+use blake2::digest::{Update, VariableOutput};
+use blake2::{Blake2b, Blake2bVar};
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+use tiny_keccak::{Hasher, Keccak};
+
+/// Signing context used for sr25519 signatures on Substrate chains
+pub const SR25519_SIGNING_CONTEXT: &[u8] = b"substrate";
+
 /// Crypto functions implementation
 pub struct CryptoFunctions;
 
@@ -16,6 +27,74 @@ impl CryptoFunctions {
     pub fn new() -> Self {
         CryptoFunctions
     }
+
+    /// Keccak-256 hash
+    pub fn keccak256(data: &[u8]) -> [u8; 32] {
+        let mut keccak = Keccak::v256();
+        let mut output = [0u8; 32];
+        keccak.update(data);
+        keccak.finalize(&mut output);
+        output
+    }
+
+    /// SHA2-256 hash
+    pub fn sha256(data: &[u8]) -> [u8; 32] {
+        Sha256::digest(data).into()
+    }
+
+    /// RIPEMD-160 hash
+    pub fn ripemd160(data: &[u8]) -> [u8; 20] {
+        Ripemd160::digest(data).into()
+    }
+
+    /// BLAKE2b hash with a 256-bit digest
+    pub fn blake2b_256(data: &[u8]) -> [u8; 32] {
+        Blake2b::<U32>::digest(data).into()
+    }
+
+    /// BLAKE2b hash with a digest of `digest_size` bytes (1 to 64)
+    pub fn blake2b(data: &[u8], digest_size: usize) -> Result<Vec<u8>, String> {
+        if !(1..=64).contains(&digest_size) {
+            return Err(format!("Invalid BLAKE2b digest size: {}", digest_size));
+        }
+        let mut hasher = Blake2bVar::new(digest_size).map_err(|e| e.to_string())?;
+        hasher.update(data);
+        let mut output = vec![0u8; digest_size];
+        hasher
+            .finalize_variable(&mut output)
+            .map_err(|e| e.to_string())?;
+        Ok(output)
+    }
+
+    /// Recover the compressed secp256k1 public key that signed `hash`
+    ///
+    /// The signature is 65 bytes: `r || s || v`, where `v` is the recovery
+    /// id, either raw (0-3) or Ethereum-style (27-30).
+    pub fn ecdsa_recover(hash: &[u8; 32], signature: &[u8; 65]) -> Option<[u8; 33]> {
+        let recovery_byte = match signature[64] {
+            v @ 27..=30 => v - 27,
+            v => v,
+        };
+        let recovery_id = RecoveryId::from_byte(recovery_byte)?;
+        let signature = Signature::from_slice(&signature[..64]).ok()?;
+        let key = VerifyingKey::recover_from_prehash(hash, &signature, recovery_id).ok()?;
+
+        key.to_encoded_point(true).as_bytes().try_into().ok()
+    }
+
+    /// Verify an sr25519 signature made with the Substrate signing context
+    pub fn sr25519_verify(signature: &[u8; 64], message: &[u8], public_key: &[u8; 32]) -> bool {
+        let (Ok(signature), Ok(public_key)) = (
+            schnorrkel::Signature::from_bytes(signature),
+            schnorrkel::PublicKey::from_bytes(public_key),
+        ) else {
+            return false;
+        };
+
+        public_key
+            .verify_simple(SR25519_SIGNING_CONTEXT, message, &signature)
+            .is_ok()
+    }
 }
 
 /// Register crypto functions in the runtime environment
@@ -45,9 +124,9 @@ pub fn generate_crypto_ast() -> Vec<Definition> {
         let mut params = Vec::new();
         for i in 0..arity {
             params.push(Parameter {
-                name: format!("x{}", i),
+                name: format!("x{}", i).into(),
                 ty: Type::Named {
-                    name: "Bytes".to_string(),
+                    name: "Bytes".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
@@ -56,10 +135,10 @@ pub fn generate_crypto_ast() -> Vec<Definition> {
         }
 
         definitions.push(Definition::FunctionDef {
-            name: format!("Crypto/{}", name),
+            name: format!("Crypto/{}", name).into(),
             params,
             return_type: Some(Type::Named {
-                name: "Bytes".to_string(),
+                name: "Bytes".into(),
                 params: Vec::new(),
                 location: dummy_loc.clone(),
             }),
@@ -68,36 +147,38 @@ pub fn generate_crypto_ast() -> Vec<Definition> {
                 location: dummy_loc.clone(),
             },
             checked: Some(true),
+            attributes: Vec::new(),
+            visibility: Visibility::Public,
             location: dummy_loc.clone(),
         });
     }
 
     // Signature verification functions
     definitions.push(Definition::FunctionDef {
-        name: "Crypto/verify_ecdsa".to_string(),
+        name: "Crypto/verify_ecdsa".into(),
         params: vec![
             Parameter {
-                name: "message".to_string(),
+                name: "message".into(),
                 ty: Type::Named {
-                    name: "Bytes".to_string(),
+                    name: "Bytes".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "signature".to_string(),
+                name: "signature".into(),
                 ty: Type::Named {
-                    name: "Bytes".to_string(),
+                    name: "Bytes".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "public_key".to_string(),
+                name: "public_key".into(),
                 ty: Type::Named {
-                    name: "Bytes".to_string(),
+                    name: "Bytes".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
@@ -105,7 +186,7 @@ pub fn generate_crypto_ast() -> Vec<Definition> {
             },
         ],
         return_type: Some(Type::Named {
-            name: "Bool".to_string(),
+            name: "Bool".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         }),
@@ -114,6 +195,8 @@ pub fn generate_crypto_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
@@ -127,9 +210,9 @@ pub fn generate_crypto_ast() -> Vec<Definition> {
         let mut params = Vec::new();
         for i in 0..arity {
             params.push(Parameter {
-                name: format!("x{}", i),
+                name: format!("x{}", i).into(),
                 ty: Type::Named {
-                    name: "Bytes".to_string(),
+                    name: "Bytes".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
@@ -138,10 +221,10 @@ pub fn generate_crypto_ast() -> Vec<Definition> {
         }
 
         definitions.push(Definition::FunctionDef {
-            name: format!("Crypto/{}", name),
+            name: format!("Crypto/{}", name).into(),
             params,
             return_type: Some(Type::Named {
-                name: "Bytes".to_string(),
+                name: "Bytes".into(),
                 params: Vec::new(),
                 location: dummy_loc.clone(),
             }),
@@ -150,9 +233,101 @@ pub fn generate_crypto_ast() -> Vec<Definition> {
                 location: dummy_loc.clone(),
             },
             checked: Some(true),
+            attributes: Vec::new(),
+            visibility: Visibility::Public,
             location: dummy_loc.clone(),
         });
     }
 
     definitions
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+
+    #[test]
+    fn test_hash_vectors() {
+        assert_eq!(
+            hex::encode(CryptoFunctions::keccak256(b"")),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert_eq!(
+            hex::encode(CryptoFunctions::sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex::encode(CryptoFunctions::blake2b_256(b"")),
+            "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8"
+        );
+        assert_eq!(
+            hex::encode(CryptoFunctions::ripemd160(b"")),
+            "9c1185a5c5e9fc54612808977ee8f548b2258d31"
+        );
+    }
+
+    #[test]
+    fn test_blake2b_digest_size() {
+        assert_eq!(
+            CryptoFunctions::blake2b(b"abc", 32).unwrap(),
+            CryptoFunctions::blake2b_256(b"abc").to_vec()
+        );
+        assert_eq!(CryptoFunctions::blake2b(b"abc", 64).unwrap().len(), 64);
+        assert!(CryptoFunctions::blake2b(b"abc", 0).is_err());
+        assert!(CryptoFunctions::blake2b(b"abc", 65).is_err());
+    }
+
+    #[test]
+    fn test_ecdsa_recover() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32].into()).unwrap();
+        let hash = CryptoFunctions::keccak256(b"transfer 100");
+        let (signature, recovery_id) = signing_key.sign_prehash_recoverable(&hash).unwrap();
+
+        let mut bytes = [0u8; 65];
+        bytes[..64].copy_from_slice(&signature.to_bytes());
+        bytes[64] = recovery_id.to_byte() + 27;
+
+        let expected = signing_key.verifying_key().to_encoded_point(true);
+        let recovered = CryptoFunctions::ecdsa_recover(&hash, &bytes).unwrap();
+        assert_eq!(&recovered[..], expected.as_bytes());
+
+        // A different message recovers a different key
+        let other = CryptoFunctions::keccak256(b"transfer 101");
+        assert_ne!(
+            CryptoFunctions::ecdsa_recover(&other, &bytes),
+            Some(recovered)
+        );
+
+        // Invalid recovery ids are rejected
+        bytes[64] = 42;
+        assert_eq!(CryptoFunctions::ecdsa_recover(&hash, &bytes), None);
+    }
+
+    #[test]
+    fn test_sr25519_verify() {
+        let keypair = schnorrkel::MiniSecretKey::from_bytes(&[9u8; 32])
+            .unwrap()
+            .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519);
+        let signature = keypair
+            .sign_simple(SR25519_SIGNING_CONTEXT, b"hello")
+            .to_bytes();
+        let public_key = keypair.public.to_bytes();
+
+        assert!(CryptoFunctions::sr25519_verify(
+            &signature,
+            b"hello",
+            &public_key
+        ));
+        assert!(!CryptoFunctions::sr25519_verify(
+            &signature,
+            b"hellp",
+            &public_key
+        ));
+        assert!(!CryptoFunctions::sr25519_verify(
+            &[0u8; 64],
+            b"hello",
+            &public_key
+        ));
+    }
+}
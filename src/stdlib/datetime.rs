@@ -13,7 +13,7 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
     };
 
     definitions.push(Definition::FunctionDef {
-        name: "DateTime/now".to_string(),
+        name: "DateTime/now".into(),
         params: Vec::new(),
         return_type: Some(Type::U24 {
             location: dummy_loc.clone(),
@@ -23,20 +23,22 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "DateTime/from_timestamp".to_string(),
+        name: "DateTime/from_timestamp".into(),
         params: vec![Parameter {
-            name: "timestamp".to_string(),
+            name: "timestamp".into(),
             ty: Type::U24 {
                 location: dummy_loc.clone(),
             },
             location: dummy_loc.clone(),
         }],
         return_type: Some(Type::Named {
-            name: "DateTime".to_string(),
+            name: "DateTime".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         }),
@@ -45,15 +47,17 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "DateTime/year".to_string(),
+        name: "DateTime/year".into(),
         params: vec![Parameter {
-            name: "dt".to_string(),
+            name: "dt".into(),
             ty: Type::Named {
-                name: "DateTime".to_string(),
+                name: "DateTime".into(),
                 params: Vec::new(),
                 location: dummy_loc.clone(),
             },
@@ -67,15 +71,17 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "DateTime/month".to_string(),
+        name: "DateTime/month".into(),
         params: vec![Parameter {
-            name: "dt".to_string(),
+            name: "dt".into(),
             ty: Type::Named {
-                name: "DateTime".to_string(),
+                name: "DateTime".into(),
                 params: Vec::new(),
                 location: dummy_loc.clone(),
             },
@@ -89,15 +95,17 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "DateTime/day".to_string(),
+        name: "DateTime/day".into(),
         params: vec![Parameter {
-            name: "dt".to_string(),
+            name: "dt".into(),
             ty: Type::Named {
-                name: "DateTime".to_string(),
+                name: "DateTime".into(),
                 params: Vec::new(),
                 location: dummy_loc.clone(),
             },
@@ -111,15 +119,17 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "DateTime/hour".to_string(),
+        name: "DateTime/hour".into(),
         params: vec![Parameter {
-            name: "dt".to_string(),
+            name: "dt".into(),
             ty: Type::Named {
-                name: "DateTime".to_string(),
+                name: "DateTime".into(),
                 params: Vec::new(),
                 location: dummy_loc.clone(),
             },
@@ -133,15 +143,17 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "DateTime/minute".to_string(),
+        name: "DateTime/minute".into(),
         params: vec![Parameter {
-            name: "dt".to_string(),
+            name: "dt".into(),
             ty: Type::Named {
-                name: "DateTime".to_string(),
+                name: "DateTime".into(),
                 params: Vec::new(),
                 location: dummy_loc.clone(),
             },
@@ -155,15 +167,17 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "DateTime/second".to_string(),
+        name: "DateTime/second".into(),
         params: vec![Parameter {
-            name: "dt".to_string(),
+            name: "dt".into(),
             ty: Type::Named {
-                name: "DateTime".to_string(),
+                name: "DateTime".into(),
                 params: Vec::new(),
                 location: dummy_loc.clone(),
             },
@@ -177,25 +191,27 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "DateTime/format".to_string(),
+        name: "DateTime/format".into(),
         params: vec![
             Parameter {
-                name: "dt".to_string(),
+                name: "dt".into(),
                 ty: Type::Named {
-                    name: "DateTime".to_string(),
+                    name: "DateTime".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "format".to_string(),
+                name: "format".into(),
                 ty: Type::Named {
-                    name: "String".to_string(),
+                    name: "String".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
@@ -203,7 +219,7 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             },
         ],
         return_type: Some(Type::Named {
-            name: "String".to_string(),
+            name: "String".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         }),
@@ -212,23 +228,25 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "DateTime/add_days".to_string(),
+        name: "DateTime/add_days".into(),
         params: vec![
             Parameter {
-                name: "dt".to_string(),
+                name: "dt".into(),
                 ty: Type::Named {
-                    name: "DateTime".to_string(),
+                    name: "DateTime".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "days".to_string(),
+                name: "days".into(),
                 ty: Type::U24 {
                     location: dummy_loc.clone(),
                 },
@@ -236,7 +254,7 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             },
         ],
         return_type: Some(Type::Named {
-            name: "DateTime".to_string(),
+            name: "DateTime".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         }),
@@ -245,23 +263,25 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "DateTime/subtract_days".to_string(),
+        name: "DateTime/subtract_days".into(),
         params: vec![
             Parameter {
-                name: "dt".to_string(),
+                name: "dt".into(),
                 ty: Type::Named {
-                    name: "DateTime".to_string(),
+                    name: "DateTime".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "days".to_string(),
+                name: "days".into(),
                 ty: Type::U24 {
                     location: dummy_loc.clone(),
                 },
@@ -269,7 +289,7 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             },
         ],
         return_type: Some(Type::Named {
-            name: "DateTime".to_string(),
+            name: "DateTime".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         }),
@@ -278,25 +298,27 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "DateTime/days_between".to_string(),
+        name: "DateTime/days_between".into(),
         params: vec![
             Parameter {
-                name: "dt1".to_string(),
+                name: "dt1".into(),
                 ty: Type::Named {
-                    name: "DateTime".to_string(),
+                    name: "DateTime".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "dt2".to_string(),
+                name: "dt2".into(),
                 ty: Type::Named {
-                    name: "DateTime".to_string(),
+                    name: "DateTime".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
@@ -311,6 +333,8 @@ pub fn generate_datetime_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
@@ -242,11 +242,7 @@ impl SafeMath {
 
     /// Safe division
     pub fn div(a: u128, b: u128) -> Result<u128, String> {
-        if b == 0 {
-            Err("Division by zero".to_string())
-        } else {
-            Ok(a / b)
-        }
+        a.checked_div(b).ok_or("Division by zero".to_string())
     }
 
     /// Safe modulus
@@ -458,20 +454,16 @@ pub struct Percentage;
 impl Percentage {
     /// Calculate percentage: (value * 100) / total
     pub fn of(value: u128, total: u128) -> Result<u128, String> {
-        if total == 0 {
-            Err("Total cannot be zero".to_string())
-        } else {
-            Ok((value * 100) / total)
-        }
+        (value * 100)
+            .checked_div(total)
+            .ok_or("Total cannot be zero".to_string())
     }
 
     /// Calculate basis points (1/100 of 1%)
     pub fn bps(value: u128, total: u128) -> Result<u128, String> {
-        if total == 0 {
-            Err("Total cannot be zero".to_string())
-        } else {
-            Ok((value * 10000) / total)
-        }
+        (value * 10000)
+            .checked_div(total)
+            .ok_or("Total cannot be zero".to_string())
     }
 
     /// Apply percentage: value * percent / 100
@@ -519,7 +511,7 @@ pub fn generate_math_ast() -> Vec<Definition> {
 
     // Math constants
     definitions.push(Definition::FunctionDef {
-        name: "Math/PI".to_string(),
+        name: "Math/PI".into(),
         params: Vec::new(),
         return_type: Some(Type::F24 {
             location: dummy_loc.clone(),
@@ -535,11 +527,13 @@ pub fn generate_math_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "Math/E".to_string(),
+        name: "Math/E".into(),
         params: Vec::new(),
         return_type: Some(Type::F24 {
             location: dummy_loc.clone(),
@@ -555,6 +549,8 @@ pub fn generate_math_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
@@ -578,7 +574,7 @@ pub fn generate_math_ast() -> Vec<Definition> {
         let mut params = Vec::new();
         for i in 0..arity {
             params.push(Parameter {
-                name: format!("x{}", i),
+                name: format!("x{}", i).into(),
                 ty: Type::F24 {
                     location: dummy_loc.clone(),
                 },
@@ -587,7 +583,7 @@ pub fn generate_math_ast() -> Vec<Definition> {
         }
 
         definitions.push(Definition::FunctionDef {
-            name: format!("Math/{}", name),
+            name: format!("Math/{}", name).into(),
             params,
             return_type: Some(Type::F24 {
                 location: dummy_loc.clone(),
@@ -597,6 +593,8 @@ pub fn generate_math_ast() -> Vec<Definition> {
                 location: dummy_loc.clone(),
             },
             checked: Some(true),
+            attributes: Vec::new(),
+            visibility: Visibility::Public,
             location: dummy_loc.clone(),
         });
     }
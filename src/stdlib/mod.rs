@@ -1,14 +1,18 @@
 //! Standard library for Bend-PVM
 //!
-//! Provides built-in functions and utilities including math, crypto,
-//! string manipulation, collections, datetime, and network operations.
+//! Provides built-in functions and utilities including math, crypto, bytes,
+//! string manipulation, collections, datetime, and network operations, as
+//! well as the standard library modules written in Bend.
 
+pub mod bytes;
 pub mod collections;
 pub mod core;
 pub mod crypto;
 pub mod datetime;
 pub mod math;
+pub mod modules;
 pub mod network;
+pub mod storage;
 pub mod string;
 
 use self::collections::{Collections, MapUtils, SetUtils, VecUtils};
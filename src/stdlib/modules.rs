@@ -0,0 +1,293 @@
+//! Standard library modules written in Bend
+//!
+//! The modules are embedded in the compiler and imported with the `std/`
+//! prefix, e.g. `from std/List import List/map;`. They appear to live in
+//! [`STD_DIR`], which the module system searches after every other search
+//! path, and every module outside the standard library implicitly imports
+//! the prelude. A contract compiled on its own is linked with the
+//! definitions of the modules it imports that it uses, see [`link`].
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::compiler::analyzer::linearity::{visit_expr, visit_statement, visit_statements};
+use crate::compiler::module::import_paths;
+use crate::compiler::parser::ast::{
+    Attribute, Block, Definition, Expr, Import, Pattern, Program, Statement, Symbol,
+};
+use crate::compiler::parser::parser::Parser;
+
+/// Directory the embedded modules appear to be in
+pub const STD_DIR: &str = "<std>";
+
+/// Import path prefix of the standard library
+pub const STD_PREFIX: &str = "std/";
+
+/// Import path of the module imported implicitly by every other module
+pub const PRELUDE: &str = "std/prelude";
+
+/// Import paths and sources of every module
+const MODULES: &[(&str, &str)] = &[
+    ("std/Option", include_str!("modules/Option.bend")),
+    ("std/Result", include_str!("modules/Result.bend")),
+    ("std/List", include_str!("modules/List.bend")),
+    ("std/Map", include_str!("modules/Map.bend")),
+    ("std/String", include_str!("modules/String.bend")),
+    ("std/Math", include_str!("modules/Math.bend")),
+    ("std/Crypto", include_str!("modules/Crypto.bend")),
+    ("std/Random", include_str!("modules/Random.bend")),
+    ("std/prelude", include_str!("modules/prelude.bend")),
+];
+
+/// Import paths of every module
+pub fn modules() -> impl Iterator<Item = &'static str> {
+    MODULES.iter().map(|(path, _)| *path)
+}
+
+/// Source of the module imported as `import_path`
+pub fn source(import_path: &str) -> Option<&'static str> {
+    MODULES
+        .iter()
+        .find(|(path, _)| *path == import_path)
+        .map(|(_, source)| *source)
+}
+
+/// Path the module imported as `import_path` appears to be at
+pub fn module_path(import_path: &str) -> Option<PathBuf> {
+    let name = import_path.strip_prefix(STD_PREFIX)?;
+    source(import_path)?;
+    Some(Path::new(STD_DIR).join(format!("{}.bend", name)))
+}
+
+/// Source of the module at `path`, if it is one of the embedded modules
+pub fn embedded(path: &Path) -> Option<&'static str> {
+    let name = path.strip_prefix(STD_DIR).ok()?.file_stem()?.to_str()?;
+    source(&format!("{}{}", STD_PREFIX, name))
+}
+
+/// Imports every module outside the standard library starts with
+pub fn prelude_imports() -> Vec<Import> {
+    let source = source(PRELUDE).expect("the prelude is embedded");
+    Parser::new(source)
+        .parse_program()
+        .expect("the prelude parses")
+        .imports
+}
+
+/// The module imported as `import_path`, parsed
+///
+/// Its names are interned in the current session, so it is parsed again
+/// by every program that links it.
+fn parsed(import_path: &str) -> Option<Program> {
+    let source = source(import_path)?;
+    let program = Parser::new(source)
+        .parse_program()
+        .unwrap_or_else(|e| panic!("{} does not parse: {}", import_path, e));
+    Some(program)
+}
+
+/// The definitions of the standard library that `program`, a module
+/// compiled on its own, uses: those of the modules it imports, along with
+/// the prelude, that its definitions refer to, directly or through other
+/// definitions of the library
+///
+/// The functions are marked `#[internal]`, so contracts do not route
+/// messages to them. Definitions of the library whose name `program`
+/// defines itself are left out, `program`'s own taking their place.
+pub fn link(program: &Program) -> Vec<Definition> {
+    // The modules imported, then the ones they import
+    let mut paths: Vec<String> = Vec::new();
+    let prelude = prelude_imports();
+    let imports = prelude.iter().chain(&program.imports);
+    let mut pending: Vec<String> = imports.flat_map(import_paths).map(String::from).collect();
+    let mut modules = Vec::new();
+    while let Some(path) = pending.pop() {
+        if paths.contains(&path) {
+            continue;
+        }
+        let Some(module) = parsed(&path) else {
+            continue;
+        };
+        pending.extend(
+            module
+                .imports
+                .iter()
+                .flat_map(import_paths)
+                .map(String::from),
+        );
+        paths.push(path);
+        modules.push(module);
+    }
+
+    let own: HashSet<Symbol> = program
+        .definitions
+        .iter()
+        .filter_map(definition_name)
+        .collect();
+    let library: Vec<&Definition> = modules
+        .iter()
+        .flat_map(|module| &module.definitions)
+        .filter(|definition| definition_name(definition).is_none_or(|name| !own.contains(&name)))
+        .collect();
+
+    let mut names = HashSet::new();
+    for definition in &program.definitions {
+        mentions(definition, &mut names);
+    }
+    let mut linked = vec![false; library.len()];
+    loop {
+        // A type is used through its variants, e.g. `Option/Some`
+        let types: HashSet<&str> = names
+            .iter()
+            .filter_map(|name| Some(name.as_str().rsplit_once('/')?.0))
+            .collect();
+        let mut more = HashSet::new();
+        for (definition, linked) in library.iter().zip(&mut linked) {
+            let used = match definition {
+                Definition::FunctionDef { name, .. } => names.contains(name),
+                Definition::TypeDef { name, .. } => types.contains(name.as_str()),
+                _ => false,
+            };
+            if used && !*linked {
+                *linked = true;
+                mentions(definition, &mut more);
+            }
+        }
+        if more.is_subset(&names) {
+            break;
+        }
+        names.extend(more);
+    }
+
+    library
+        .into_iter()
+        .zip(linked)
+        .filter(|(_, linked)| *linked)
+        .map(|(definition, _)| {
+            let mut definition = definition.clone();
+            if let Definition::FunctionDef {
+                attributes,
+                location,
+                ..
+            } = &mut definition
+            {
+                attributes.push(Attribute {
+                    name: "internal".into(),
+                    args: Vec::new(),
+                    location: location.clone(),
+                });
+            }
+            definition
+        })
+        .collect()
+}
+
+/// The name `definition` defines, if any
+fn definition_name(definition: &Definition) -> Option<Symbol> {
+    match definition {
+        Definition::FunctionDef { name, .. }
+        | Definition::TypeDef { name, .. }
+        | Definition::ObjectDef { name, .. }
+        | Definition::TypeAlias { name, .. }
+        | Definition::EventDef { name, .. }
+        | Definition::ErrorDef { name, .. }
+        | Definition::InterfaceDef { name, .. } => Some(*name),
+        _ => None,
+    }
+}
+
+/// Add the names `definition` refers to in its code, such as the functions
+/// it calls and the variants it builds or matches, to `names`
+fn mentions(definition: &Definition, names: &mut HashSet<Symbol>) {
+    let blocks: Vec<&Block> = match definition {
+        Definition::FunctionDef { body, .. } => vec![body],
+        Definition::GuardDef { before, after, .. } => vec![before, after],
+        _ => return,
+    };
+    let mut mention = |expr: &Expr| match expr {
+        Expr::Variable { name, .. } => {
+            names.insert(*name);
+        }
+        // Comprehensions are lowered to code building and matching lists
+        Expr::ListComprehension { .. } => {
+            names.extend(["List/Cons", "List/Nil"].map(Symbol::from));
+        }
+        _ => {}
+    };
+    for attribute in definition.attributes() {
+        for arg in &attribute.args {
+            visit_expr(arg, &mut mention);
+        }
+    }
+    for block in &blocks {
+        for statement in &block.statements {
+            visit_statement(statement, &mut mention);
+        }
+    }
+    for block in blocks {
+        for statement in &block.statements {
+            visit_statements(statement, &mut |statement| {
+                if let Statement::Match { cases, .. } | Statement::Fold { cases, .. } = statement {
+                    for case in cases {
+                        pattern_names(&case.pattern, names);
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Add the variants and variables `pattern` names to `names`
+fn pattern_names(pattern: &Pattern, names: &mut HashSet<Symbol>) {
+    match pattern {
+        Pattern::Variable { name, .. } => {
+            names.insert(*name);
+        }
+        Pattern::Constructor { name, fields, .. } => {
+            names.insert(*name);
+            for field in fields.values() {
+                pattern_names(field, names);
+            }
+        }
+        Pattern::TupleConstructor { name, args, .. } => {
+            names.insert(*name);
+            for arg in args {
+                pattern_names(arg, names);
+            }
+        }
+        Pattern::Tuple { elements, .. } => {
+            for element in elements {
+                pattern_names(element, names);
+            }
+        }
+        Pattern::Member { parent, .. } => pattern_names(parent, names),
+        Pattern::Literal { .. } | Pattern::MapAccess { .. } | Pattern::Wildcard { .. } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_module_parses() {
+        for path in modules() {
+            let source = source(path).unwrap();
+            if let Err(e) = Parser::new(source).parse_program() {
+                panic!("{} does not parse: {}", path, e);
+            }
+        }
+    }
+
+    #[test]
+    fn test_module_paths_round_trip() {
+        let path = module_path("std/List").unwrap();
+        assert_eq!(path, Path::new("<std>/List.bend"));
+        assert_eq!(embedded(&path), source("std/List"));
+
+        assert!(module_path("std/Missing").is_none());
+        assert!(module_path("List").is_none());
+        assert!(embedded(Path::new("src/List.bend")).is_none());
+        assert!(!prelude_imports().is_empty());
+    }
+}
@@ -13,7 +13,7 @@ pub fn generate_network_ast() -> Vec<Definition> {
     };
 
     definitions.push(Definition::FunctionDef {
-        name: "Network/get_block_number".to_string(),
+        name: "Network/get_block_number".into(),
         params: Vec::new(),
         return_type: Some(Type::U24 {
             location: dummy_loc.clone(),
@@ -23,11 +23,13 @@ pub fn generate_network_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "Network/get_block_timestamp".to_string(),
+        name: "Network/get_block_timestamp".into(),
         params: Vec::new(),
         return_type: Some(Type::U24 {
             location: dummy_loc.clone(),
@@ -37,20 +39,22 @@ pub fn generate_network_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "Network/get_block_hash".to_string(),
+        name: "Network/get_block_hash".into(),
         params: vec![Parameter {
-            name: "block_number".to_string(),
+            name: "block_number".into(),
             ty: Type::U24 {
                 location: dummy_loc.clone(),
             },
             location: dummy_loc.clone(),
         }],
         return_type: Some(Type::Named {
-            name: "String".to_string(),
+            name: "String".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         }),
@@ -59,14 +63,16 @@ pub fn generate_network_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "Network/get_caller".to_string(),
+        name: "Network/get_caller".into(),
         params: Vec::new(),
         return_type: Some(Type::Named {
-            name: "String".to_string(),
+            name: "String".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         }),
@@ -75,14 +81,16 @@ pub fn generate_network_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "Network/get_origin".to_string(),
+        name: "Network/get_origin".into(),
         params: Vec::new(),
         return_type: Some(Type::Named {
-            name: "String".to_string(),
+            name: "String".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         }),
@@ -91,11 +99,13 @@ pub fn generate_network_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "Network/get_chain_id".to_string(),
+        name: "Network/get_chain_id".into(),
         params: Vec::new(),
         return_type: Some(Type::U24 {
             location: dummy_loc.clone(),
@@ -105,11 +115,13 @@ pub fn generate_network_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "Network/get_gas_price".to_string(),
+        name: "Network/get_gas_price".into(),
         params: Vec::new(),
         return_type: Some(Type::U24 {
             location: dummy_loc.clone(),
@@ -119,15 +131,17 @@ pub fn generate_network_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "Network/get_balance".to_string(),
+        name: "Network/get_balance".into(),
         params: vec![Parameter {
-            name: "address".to_string(),
+            name: "address".into(),
             ty: Type::Named {
-                name: "String".to_string(),
+                name: "String".into(),
                 params: Vec::new(),
                 location: dummy_loc.clone(),
             },
@@ -141,32 +155,34 @@ pub fn generate_network_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "Network/send_transaction".to_string(),
+        name: "Network/send_transaction".into(),
         params: vec![
             Parameter {
-                name: "to".to_string(),
+                name: "to".into(),
                 ty: Type::Named {
-                    name: "String".to_string(),
+                    name: "String".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "amount".to_string(),
+                name: "amount".into(),
                 ty: Type::U24 {
                     location: dummy_loc.clone(),
                 },
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "data".to_string(),
+                name: "data".into(),
                 ty: Type::Named {
-                    name: "String".to_string(),
+                    name: "String".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
@@ -174,7 +190,7 @@ pub fn generate_network_ast() -> Vec<Definition> {
             },
         ],
         return_type: Some(Type::Named {
-            name: "String".to_string(),
+            name: "String".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         }),
@@ -183,25 +199,27 @@ pub fn generate_network_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "Network/call_contract".to_string(),
+        name: "Network/call_contract".into(),
         params: vec![
             Parameter {
-                name: "contract_address".to_string(),
+                name: "contract_address".into(),
                 ty: Type::Named {
-                    name: "String".to_string(),
+                    name: "String".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "calldata".to_string(),
+                name: "calldata".into(),
                 ty: Type::Named {
-                    name: "String".to_string(),
+                    name: "String".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
@@ -209,7 +227,7 @@ pub fn generate_network_ast() -> Vec<Definition> {
             },
         ],
         return_type: Some(Type::Named {
-            name: "String".to_string(),
+            name: "String".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         }),
@@ -218,22 +236,24 @@ pub fn generate_network_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "Network/get_contract_code".to_string(),
+        name: "Network/get_contract_code".into(),
         params: vec![Parameter {
-            name: "contract_address".to_string(),
+            name: "contract_address".into(),
             ty: Type::Named {
-                name: "String".to_string(),
+                name: "String".into(),
                 params: Vec::new(),
                 location: dummy_loc.clone(),
             },
             location: dummy_loc.clone(),
         }],
         return_type: Some(Type::Named {
-            name: "String".to_string(),
+            name: "String".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         }),
@@ -242,25 +262,27 @@ pub fn generate_network_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
     definitions.push(Definition::FunctionDef {
-        name: "Network/get_storage_at".to_string(),
+        name: "Network/get_storage_at".into(),
         params: vec![
             Parameter {
-                name: "contract_address".to_string(),
+                name: "contract_address".into(),
                 ty: Type::Named {
-                    name: "String".to_string(),
+                    name: "String".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
                 location: dummy_loc.clone(),
             },
             Parameter {
-                name: "key".to_string(),
+                name: "key".into(),
                 ty: Type::Named {
-                    name: "String".to_string(),
+                    name: "String".into(),
                     params: Vec::new(),
                     location: dummy_loc.clone(),
                 },
@@ -268,7 +290,7 @@ pub fn generate_network_ast() -> Vec<Definition> {
             },
         ],
         return_type: Some(Type::Named {
-            name: "String".to_string(),
+            name: "String".into(),
             params: Vec::new(),
             location: dummy_loc.clone(),
         }),
@@ -277,6 +299,8 @@ pub fn generate_network_ast() -> Vec<Definition> {
             location: dummy_loc.clone(),
         },
         checked: Some(true),
+        attributes: Vec::new(),
+        visibility: Visibility::Public,
         location: dummy_loc.clone(),
     });
 
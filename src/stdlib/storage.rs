@@ -0,0 +1,505 @@
+//! Lazy storage collections
+//!
+//! `StorageValue`, `StorageMap` and `StorageVec` never load a whole structure.
+//! Every entry lives under its own key, derived by hashing the collection's
+//! root key with the encoded entry key, so reading one balance of a large
+//! mapping only pays gas and proof size for that balance.
+
+use std::marker::PhantomData;
+
+use crate::compiler::address::{Address, Hash};
+use crate::compiler::codegen::metadata::compute_storage_key;
+use crate::compiler::wide::WideUint;
+use crate::runtime::metering::MeteringContext;
+use crate::runtime::storage::{StorageError, StorageManager};
+use crate::stdlib::crypto::CryptoFunctions;
+
+/// Encoding of values kept in contract storage
+///
+/// Integers are little-endian and fixed-width, matching the words written
+/// by generated code.
+pub trait StorageCodec: Sized {
+    /// Encode the value
+    fn encode(&self) -> Vec<u8>;
+
+    /// Decode a value, returning `None` for malformed bytes
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_int_codec {
+    ($($ty:ty),*) => {
+        $(
+            impl StorageCodec for $ty {
+                fn encode(&self) -> Vec<u8> {
+                    self.to_le_bytes().to_vec()
+                }
+
+                fn decode(bytes: &[u8]) -> Option<Self> {
+                    Some(<$ty>::from_le_bytes(bytes.try_into().ok()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_int_codec!(u8, u16, u32, u64, u128, i32, i64);
+
+impl StorageCodec for WideUint {
+    fn encode(&self) -> Vec<u8> {
+        self.to_le_bytes()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        WideUint::from_le_bytes(bytes)
+    }
+}
+
+impl StorageCodec for bool {
+    fn encode(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl StorageCodec for [u8; 32] {
+    fn encode(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok()
+    }
+}
+
+macro_rules! impl_bytes32_codec {
+    ($($ty:ty),*) => {
+        $(
+            impl StorageCodec for $ty {
+                fn encode(&self) -> Vec<u8> {
+                    self.as_bytes().to_vec()
+                }
+
+                fn decode(bytes: &[u8]) -> Option<Self> {
+                    <$ty>::from_slice(bytes).ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_bytes32_codec!(Address, Hash);
+
+impl StorageCodec for Vec<u8> {
+    fn encode(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+impl StorageCodec for String {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+/// Derive the key of a collection entry from the collection root
+pub fn derive_entry_key(root: &[u8; 32], suffix: &[u8]) -> [u8; 32] {
+    let mut input = Vec::with_capacity(root.len() + suffix.len());
+    input.extend_from_slice(root);
+    input.extend_from_slice(suffix);
+    CryptoFunctions::keccak256(&input)
+}
+
+fn read<T: StorageCodec>(
+    storage: &mut StorageManager,
+    key: &[u8; 32],
+    metering: &mut MeteringContext,
+) -> Result<Option<T>, StorageError> {
+    match storage.get(key, metering)? {
+        Some(bytes) => T::decode(&bytes).map(Some).ok_or_else(|| {
+            StorageError::Decode(format!("Malformed value under key 0x{}", hex::encode(key)))
+        }),
+        None => Ok(None),
+    }
+}
+
+fn entry_key(
+    root: &[u8; 32],
+    suffix: &[u8],
+    metering: &mut MeteringContext,
+) -> Result<[u8; 32], StorageError> {
+    metering
+        .charge_key_derivation(suffix.len())
+        .map_err(|e| StorageError::Metering(e.to_string()))?;
+    Ok(derive_entry_key(root, suffix))
+}
+
+/// A single value stored under a fixed key
+#[derive(Debug, Clone)]
+pub struct StorageValue<T> {
+    key: [u8; 32],
+    _marker: PhantomData<T>,
+}
+
+impl<T: StorageCodec> StorageValue<T> {
+    /// Create a value stored under the hashed path
+    pub fn new(path: &str) -> Self {
+        Self::from_key(compute_storage_key(path))
+    }
+
+    /// Create a value stored under an explicit key
+    pub fn from_key(key: [u8; 32]) -> Self {
+        StorageValue {
+            key,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Storage key of the value
+    pub fn key(&self) -> &[u8; 32] {
+        &self.key
+    }
+
+    /// Read the value
+    pub fn get(
+        &self,
+        storage: &mut StorageManager,
+        metering: &mut MeteringContext,
+    ) -> Result<Option<T>, StorageError> {
+        read(storage, &self.key, metering)
+    }
+
+    /// Write the value
+    pub fn set(
+        &self,
+        storage: &mut StorageManager,
+        value: &T,
+        metering: &mut MeteringContext,
+    ) -> Result<(), StorageError> {
+        storage.set(&self.key, &value.encode(), metering)
+    }
+
+    /// Remove the value
+    pub fn clear(
+        &self,
+        storage: &mut StorageManager,
+        metering: &mut MeteringContext,
+    ) -> Result<(), StorageError> {
+        storage.remove(&self.key, metering)
+    }
+}
+
+/// A mapping whose entries are read and written individually
+#[derive(Debug, Clone)]
+pub struct StorageMap<K, V> {
+    root: [u8; 32],
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K: StorageCodec, V: StorageCodec> StorageMap<K, V> {
+    /// Create a map rooted at the hashed path
+    pub fn new(path: &str) -> Self {
+        StorageMap {
+            root: compute_storage_key(path),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Root key of the map
+    pub fn root(&self) -> &[u8; 32] {
+        &self.root
+    }
+
+    /// Storage key of the entry for `key`
+    pub fn entry_key(&self, key: &K) -> [u8; 32] {
+        derive_entry_key(&self.root, &key.encode())
+    }
+
+    /// Read the entry for `key`
+    pub fn get(
+        &self,
+        storage: &mut StorageManager,
+        key: &K,
+        metering: &mut MeteringContext,
+    ) -> Result<Option<V>, StorageError> {
+        let key = entry_key(&self.root, &key.encode(), metering)?;
+        read(storage, &key, metering)
+    }
+
+    /// Write the entry for `key`
+    pub fn insert(
+        &self,
+        storage: &mut StorageManager,
+        key: &K,
+        value: &V,
+        metering: &mut MeteringContext,
+    ) -> Result<(), StorageError> {
+        let key = entry_key(&self.root, &key.encode(), metering)?;
+        storage.set(&key, &value.encode(), metering)
+    }
+
+    /// Remove the entry for `key`
+    pub fn remove(
+        &self,
+        storage: &mut StorageManager,
+        key: &K,
+        metering: &mut MeteringContext,
+    ) -> Result<(), StorageError> {
+        let key = entry_key(&self.root, &key.encode(), metering)?;
+        storage.remove(&key, metering)
+    }
+
+    /// Check whether an entry exists for `key`
+    pub fn contains(
+        &self,
+        storage: &mut StorageManager,
+        key: &K,
+        metering: &mut MeteringContext,
+    ) -> Result<bool, StorageError> {
+        let key = entry_key(&self.root, &key.encode(), metering)?;
+        storage.contains(&key, metering)
+    }
+}
+
+/// A vector whose length and elements live under separate keys
+///
+/// The length is stored under the root key; element `i` under the root
+/// hashed with `i` as a little-endian `u32`.
+#[derive(Debug, Clone)]
+pub struct StorageVec<T> {
+    root: [u8; 32],
+    _marker: PhantomData<T>,
+}
+
+impl<T: StorageCodec> StorageVec<T> {
+    /// Create a vector rooted at the hashed path
+    pub fn new(path: &str) -> Self {
+        StorageVec {
+            root: compute_storage_key(path),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Root key of the vector, which holds its length
+    pub fn root(&self) -> &[u8; 32] {
+        &self.root
+    }
+
+    /// Storage key of element `index`
+    pub fn element_key(&self, index: u32) -> [u8; 32] {
+        derive_entry_key(&self.root, &index.to_le_bytes())
+    }
+
+    /// Number of elements
+    pub fn len(
+        &self,
+        storage: &mut StorageManager,
+        metering: &mut MeteringContext,
+    ) -> Result<u32, StorageError> {
+        Ok(read::<u32>(storage, &self.root, metering)?.unwrap_or(0))
+    }
+
+    /// Whether the vector has no elements
+    pub fn is_empty(
+        &self,
+        storage: &mut StorageManager,
+        metering: &mut MeteringContext,
+    ) -> Result<bool, StorageError> {
+        Ok(self.len(storage, metering)? == 0)
+    }
+
+    /// Read element `index`, or `None` if it is out of bounds
+    pub fn get(
+        &self,
+        storage: &mut StorageManager,
+        index: u32,
+        metering: &mut MeteringContext,
+    ) -> Result<Option<T>, StorageError> {
+        if index >= self.len(storage, metering)? {
+            return Ok(None);
+        }
+        let key = entry_key(&self.root, &index.to_le_bytes(), metering)?;
+        read(storage, &key, metering)
+    }
+
+    /// Overwrite element `index`
+    pub fn set(
+        &self,
+        storage: &mut StorageManager,
+        index: u32,
+        value: &T,
+        metering: &mut MeteringContext,
+    ) -> Result<(), StorageError> {
+        let len = self.len(storage, metering)?;
+        if index >= len {
+            return Err(StorageError::IndexOutOfBounds(index, len));
+        }
+        let key = entry_key(&self.root, &index.to_le_bytes(), metering)?;
+        storage.set(&key, &value.encode(), metering)
+    }
+
+    /// Append an element
+    pub fn push(
+        &self,
+        storage: &mut StorageManager,
+        value: &T,
+        metering: &mut MeteringContext,
+    ) -> Result<(), StorageError> {
+        let len = self.len(storage, metering)?;
+        let key = entry_key(&self.root, &len.to_le_bytes(), metering)?;
+        storage.set(&key, &value.encode(), metering)?;
+        storage.set(&self.root, &(len + 1).encode(), metering)
+    }
+
+    /// Remove and return the last element
+    pub fn pop(
+        &self,
+        storage: &mut StorageManager,
+        metering: &mut MeteringContext,
+    ) -> Result<Option<T>, StorageError> {
+        let len = self.len(storage, metering)?;
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let key = entry_key(&self.root, &(len - 1).to_le_bytes(), metering)?;
+        let value = read(storage, &key, metering)?;
+        storage.remove(&key, metering)?;
+        storage.set(&self.root, &(len - 1).encode(), metering)?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::storage::StorageLimits;
+
+    fn setup() -> (StorageManager, MeteringContext) {
+        (
+            StorageManager::new([0u8; 32], StorageLimits::default()),
+            MeteringContext::new(10_000_000, 10_000_000, u128::MAX),
+        )
+    }
+
+    #[test]
+    fn test_storage_value_roundtrip() {
+        let (mut storage, mut metering) = setup();
+        let owner = StorageValue::<u32>::new("owner");
+
+        assert_eq!(owner.get(&mut storage, &mut metering).unwrap(), None);
+        owner.set(&mut storage, &7, &mut metering).unwrap();
+        assert_eq!(owner.get(&mut storage, &mut metering).unwrap(), Some(7));
+        assert_eq!(
+            storage
+                .get(&compute_storage_key("owner"), &mut metering)
+                .unwrap(),
+            Some(7u32.to_le_bytes().to_vec())
+        );
+
+        owner.clear(&mut storage, &mut metering).unwrap();
+        assert_eq!(owner.get(&mut storage, &mut metering).unwrap(), None);
+    }
+
+    #[test]
+    fn test_storage_map_entries_are_independent_keys() {
+        let (mut storage, mut metering) = setup();
+        let balances = StorageMap::<u32, u64>::new("balances");
+
+        balances
+            .insert(&mut storage, &1, &100, &mut metering)
+            .unwrap();
+        balances
+            .insert(&mut storage, &2, &250, &mut metering)
+            .unwrap();
+
+        assert_eq!(
+            balances.get(&mut storage, &1, &mut metering).unwrap(),
+            Some(100)
+        );
+        assert_eq!(balances.get(&mut storage, &3, &mut metering).unwrap(), None);
+        assert!(balances.contains(&mut storage, &2, &mut metering).unwrap());
+
+        // One storage entry per map entry, nothing under the root
+        assert_eq!(storage.keys().len(), 2);
+        assert!(storage.keys().contains(&balances.entry_key(&2).to_vec()));
+
+        balances.remove(&mut storage, &2, &mut metering).unwrap();
+        assert!(!balances.contains(&mut storage, &2, &mut metering).unwrap());
+    }
+
+    #[test]
+    fn test_storage_map_reads_are_metered_per_entry() {
+        let (mut storage, mut metering) = setup();
+        let balances = StorageMap::<u32, u32>::new("balances");
+        for holder in 0..100 {
+            balances
+                .insert(&mut storage, &holder, &holder, &mut metering)
+                .unwrap();
+        }
+
+        let before = metering.proof_size_used;
+        balances.get(&mut storage, &42, &mut metering).unwrap();
+        let one_read = metering.proof_size_used - before;
+
+        let before = metering.proof_size_used;
+        balances.get(&mut storage, &7, &mut metering).unwrap();
+        assert_eq!(metering.proof_size_used - before, one_read);
+    }
+
+    #[test]
+    fn test_storage_vec_push_get_pop() {
+        let (mut storage, mut metering) = setup();
+        let holders = StorageVec::<u32>::new("holders");
+
+        assert!(holders.is_empty(&mut storage, &mut metering).unwrap());
+        holders.push(&mut storage, &10, &mut metering).unwrap();
+        holders.push(&mut storage, &20, &mut metering).unwrap();
+
+        assert_eq!(holders.len(&mut storage, &mut metering).unwrap(), 2);
+        assert_eq!(
+            holders.get(&mut storage, 1, &mut metering).unwrap(),
+            Some(20)
+        );
+        assert_eq!(holders.get(&mut storage, 2, &mut metering).unwrap(), None);
+
+        holders.set(&mut storage, 0, &11, &mut metering).unwrap();
+        assert_eq!(
+            holders.get(&mut storage, 0, &mut metering).unwrap(),
+            Some(11)
+        );
+        assert!(matches!(
+            holders.set(&mut storage, 5, &1, &mut metering),
+            Err(StorageError::IndexOutOfBounds(5, 2))
+        ));
+
+        assert_eq!(holders.pop(&mut storage, &mut metering).unwrap(), Some(20));
+        assert_eq!(holders.len(&mut storage, &mut metering).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_malformed_value_is_a_decode_error() {
+        let (mut storage, mut metering) = setup();
+        let flag = StorageValue::<bool>::new("flag");
+        storage.set(flag.key(), &[2], &mut metering).unwrap();
+
+        assert!(matches!(
+            flag.get(&mut storage, &mut metering),
+            Err(StorageError::Decode(_))
+        ));
+    }
+}
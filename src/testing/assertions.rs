@@ -1,3 +1,5 @@
+use crate::compiler::codegen::metadata::compute_event_topic;
+use crate::runtime::env::Event;
 use crate::testing::{TestEnvironment, TestError};
 
 /// Test assertions for verifying test results
@@ -118,17 +120,284 @@ impl<'a> TestAssertions<'a> {
         }
     }
 
+    /// Assert the net storage deposit change of the last run
+    pub fn assert_net_storage_deposit(&self, expected: i128) -> Result<(), TestError> {
+        let net = self.environment.context.net_storage_deposit();
+
+        if net == expected {
+            Ok(())
+        } else {
+            Err(TestError::AssertionFailed(format!(
+                "Net storage deposit {} does not match expected {}",
+                net, expected
+            )))
+        }
+    }
+
+    /// Assert the balance of `address` after the last run
+    pub fn assert_balance(&self, address: &[u8; 32], expected: u128) -> Result<(), TestError> {
+        let balance = self.environment.balance_of(address);
+
+        if balance == expected {
+            Ok(())
+        } else {
+            Err(TestError::AssertionFailed(format!(
+                "Balance of 0x{} is {}, expected {}",
+                hex::encode(address),
+                balance,
+                expected
+            )))
+        }
+    }
+
+    /// Assert how much the balance of `address` changed from its initial value
+    pub fn assert_balance_change(
+        &self,
+        address: &[u8; 32],
+        expected: i128,
+    ) -> Result<(), TestError> {
+        let change = self.environment.balance_change(address);
+
+        if change == expected {
+            Ok(())
+        } else {
+            Err(TestError::AssertionFailed(format!(
+                "Balance of 0x{} changed by {}, expected {}",
+                hex::encode(address),
+                change,
+                expected
+            )))
+        }
+    }
+
     /// Assert that an event was emitted
-    pub fn assert_event_emitted(&self, _name: &str) -> Result<(), TestError> {
-        // In a real implementation, this would check the events emitted by the contract
-        // For now, we just return Ok
-        Ok(())
+    ///
+    /// `signature` is the canonical event signature, e.g. `Transfer(u24,u24,u24)`.
+    pub fn assert_event_emitted(&self, signature: &str) -> Result<(), TestError> {
+        if self.events_matching(signature).next().is_some() {
+            Ok(())
+        } else {
+            Err(TestError::AssertionFailed(format!(
+                "Event {} was not emitted",
+                signature
+            )))
+        }
     }
 
     /// Assert that an event was emitted with specific data
-    pub fn assert_event_data(&self, _name: &str, _data: &[u8]) -> Result<(), TestError> {
-        // In a real implementation, this would check the events emitted by the contract
-        // For now, we just return Ok
-        Ok(())
+    pub fn assert_event_data(&self, signature: &str, data: &[u8]) -> Result<(), TestError> {
+        let mut found = Vec::new();
+        for event in self.events_matching(signature) {
+            if event.data == data {
+                return Ok(());
+            }
+            found.push(hex::encode(&event.data));
+        }
+
+        Err(TestError::AssertionFailed(format!(
+            "Event {} was not emitted with data 0x{} (emitted with: [{}])",
+            signature,
+            hex::encode(data),
+            found.join(", ")
+        )))
+    }
+
+    /// Assert that an event was emitted with the given indexed topics
+    ///
+    /// `topics` excludes the signature topic.
+    pub fn assert_event_topics(
+        &self,
+        signature: &str,
+        topics: &[Vec<u8>],
+    ) -> Result<(), TestError> {
+        if self
+            .events_matching(signature)
+            .any(|event| event.topics[1..] == *topics)
+        {
+            Ok(())
+        } else {
+            Err(TestError::AssertionFailed(format!(
+                "Event {} was not emitted with topics {:?}",
+                signature, topics
+            )))
+        }
+    }
+
+    /// Assert the total number of emitted events
+    pub fn assert_event_count(&self, expected: usize) -> Result<(), TestError> {
+        let count = self.environment.events.len();
+        if count == expected {
+            Ok(())
+        } else {
+            Err(TestError::AssertionFailed(format!(
+                "{} events were emitted, expected {}",
+                count, expected
+            )))
+        }
+    }
+
+    /// Events whose first topic matches the signature
+    fn events_matching<'b>(&'b self, signature: &str) -> impl Iterator<Item = &'b Event> + 'b {
+        let topic = compute_event_topic(signature).to_vec();
+        self.environment
+            .events
+            .iter()
+            .filter(move |event| event.topics.first() == Some(&topic))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::runner::TestRunner;
+    use crate::testing::TestCase;
+
+    const SOURCE: &str = r#"
+event Transfer {
+    indexed sender: u24,
+    indexed receiver: u24,
+    amount: u24,
+}
+
+fn main() -> u24 {
+    emit Transfer(1, 2, 500);
+    return 0;
+}
+"#;
+
+    fn word_topic(value: u32) -> Vec<u8> {
+        let mut topic = value.to_le_bytes().to_vec();
+        topic.resize(32, 0);
+        topic
+    }
+
+    #[test]
+    fn test_emitted_event_assertions() {
+        let mut runner = TestRunner::new();
+        runner
+            .setup(&TestCase {
+                source: SOURCE.to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        runner.run().unwrap();
+
+        let assertions = TestAssertions::new(runner.environment());
+        let signature = "Transfer(u24,u24,u24)";
+        assertions.assert_event_count(1).unwrap();
+        assertions.assert_event_emitted(signature).unwrap();
+        assertions
+            .assert_event_data(signature, &500u32.to_le_bytes())
+            .unwrap();
+        assertions
+            .assert_event_topics(signature, &[word_topic(1), word_topic(2)])
+            .unwrap();
+
+        assert!(assertions.assert_event_emitted("Approval(u24)").is_err());
+        assert!(assertions.assert_event_data(signature, &[0]).is_err());
+        assert!(assertions
+            .assert_event_topics(signature, &[word_topic(2), word_topic(1)])
+            .is_err());
+    }
+
+    #[test]
+    fn test_storage_assertions_follow_committed_state() {
+        let mut runner = TestRunner::new();
+        runner
+            .setup(&TestCase {
+                source: "storage { counter: u24 }\nfn main() -> u24 { counter = 9; return 0; }"
+                    .to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        runner.run().unwrap();
+
+        let key = crate::compiler::codegen::metadata::compute_storage_key("counter");
+        let assertions = TestAssertions::new(runner.environment());
+        assertions
+            .assert_storage_eq(&key, &9u32.to_le_bytes())
+            .unwrap();
+        assertions.assert_net_storage_deposit(36).unwrap();
+
+        let mut environment = runner.environment().clone();
+        environment.checkpoint();
+        environment.storage.clear();
+        environment.rollback().unwrap();
+        TestAssertions::new(&environment)
+            .assert_storage_exists(&key)
+            .unwrap();
+    }
+
+    fn account(id: u8) -> [u8; 32] {
+        let mut address = [0u8; 32];
+        address[0] = id;
+        address
+    }
+
+    #[test]
+    fn test_balance_assertions() {
+        let mut runner = TestRunner::new();
+        runner
+            .setup(&TestCase {
+                source: "fn main() -> u24 { transfer(5, 30); return balance(); }".to_string(),
+                initial_balances: [(account(0), 100)].into_iter().collect(),
+                ..Default::default()
+            })
+            .unwrap();
+        runner.run().unwrap();
+
+        let assertions = TestAssertions::new(runner.environment());
+        assertions.assert_balance(&account(0), 70).unwrap();
+        assertions.assert_balance(&account(5), 30).unwrap();
+        assertions.assert_balance_change(&account(0), -30).unwrap();
+        assertions.assert_balance_change(&account(5), 30).unwrap();
+        assert!(assertions.assert_balance(&account(5), 31).is_err());
+
+        // Leaving less than the existential deposit reverts the run
+        let mut runner = TestRunner::new();
+        runner
+            .setup(&TestCase {
+                source: "fn main() -> u24 { transfer(5, 100); return 0; }".to_string(),
+                initial_balances: [(account(0), 100)].into_iter().collect(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(runner.run().is_err());
+        TestAssertions::new(runner.environment())
+            .assert_balance_change(&account(0), 0)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_seeded_randomness() {
+        let source =
+            "storage { roll: Hash }\nfn main() -> u24 { roll = random(hex\"64696365\"); return 0; }";
+        let key = crate::compiler::codegen::metadata::compute_storage_key("roll");
+        let run = |random_seed| {
+            let mut runner = TestRunner::new();
+            runner
+                .setup(&TestCase {
+                    source: source.to_string(),
+                    random_seed,
+                    ..Default::default()
+                })
+                .unwrap();
+            runner.run().unwrap();
+            runner
+        };
+
+        // The same seed draws the same value on every run
+        let runner = run(7);
+        let expected = runner.context().random(b"dice");
+        let assertions = TestAssertions::new(runner.environment());
+        assertions
+            .assert_storage_eq(&key, expected.as_bytes())
+            .unwrap();
+        TestAssertions::new(run(7).environment())
+            .assert_storage_eq(&key, expected.as_bytes())
+            .unwrap();
+        assert!(TestAssertions::new(run(8).environment())
+            .assert_storage_eq(&key, expected.as_bytes())
+            .is_err());
     }
 }
@@ -0,0 +1,668 @@
+//! # Differential Testing
+//!
+//! Runs the same compiled contract and input through two execution backends
+//! and compares return data, gas and storage effects. The reference backend is
+//! the internal interpreter; the candidate is the interpreter of the
+//! WebAssembly backend's output or, with the `polkavm-engine` feature, an
+//! embedded PolkaVM engine. Any divergence is reported as a suspected bug in
+//! either code generation or gas metering.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::compiler::address::Address;
+use crate::compiler::cfg::Cfg;
+use crate::compiler::codegen::risc_v::Instruction;
+use crate::compiler::codegen::wasm::WasmModule;
+use crate::compiler::parser::ast::Definition;
+use crate::compiler::pipeline::{CompilerPipeline, Source};
+use crate::runtime::env::{Environment, ExecutionContext, ExecutionResult};
+use crate::runtime::interpreter::Interpreter;
+use crate::runtime::wasm::WasmInterpreter;
+use crate::testing::{TestCase, TestError};
+use crate::CompilerOptions;
+
+/// Contract storage as seen by a backend
+pub type StorageMap = HashMap<Vec<u8>, Vec<u8>>;
+
+/// A contract compiled for every backend
+#[derive(Debug, Clone)]
+pub struct CompiledContract {
+    /// Generated RISC-V instructions (used by the interpreter)
+    pub instructions: Vec<Instruction>,
+
+    /// Linked PolkaVM binary (used by the PolkaVM engine)
+    pub binary: Vec<u8>,
+
+    /// WebAssembly module, unless the contract uses features the
+    /// WebAssembly backend does not support
+    pub wasm: Option<WasmModule>,
+}
+
+impl CompiledContract {
+    /// Compile a contract from source
+    pub fn from_source(source: &str) -> Result<Self, TestError> {
+        Self::compile(source, &[], &Cfg::new(), false, false)
+    }
+
+    /// Compile a contract with its `#[cfg]` predicates evaluated against
+    /// `cfg`, e.g. to keep its `#[cfg(test)]` helpers
+    pub fn with_cfg(source: &str, cfg: &Cfg) -> Result<Self, TestError> {
+        Self::compile(source, &[], cfg, false, false)
+    }
+
+    /// Compile the contract a test runs, with the definitions of the
+    /// modules its source imports
+    pub fn for_test(test: &TestCase) -> Result<Self, TestError> {
+        Self::compile(&test.source, &test.imported, &test.cfg, false, false)
+    }
+
+    /// Compile a contract with a message dispatcher, as it is deployed, so
+    /// calls select the function to run
+    pub fn with_dispatcher(source: &str) -> Result<Self, TestError> {
+        Self::compile(source, &[], &Cfg::new(), true, false)
+    }
+
+    /// Compile a contract with a message dispatcher and its specifications
+    /// checked, as debug builds do
+    pub fn with_specifications(source: &str) -> Result<Self, TestError> {
+        Self::compile(source, &[], &Cfg::new(), true, true)
+    }
+
+    /// Compile the contract of an `#[invariant]` test case as
+    /// [`with_specifications`](Self::with_specifications) does, with the
+    /// definitions it imports and its configuration
+    pub fn for_invariants(test: &TestCase) -> Result<Self, TestError> {
+        Self::compile(&test.source, &test.imported, &test.cfg, true, true)
+    }
+
+    fn compile(
+        source: &str,
+        imported: &[Definition],
+        cfg: &Cfg,
+        dispatcher: bool,
+        debug: bool,
+    ) -> Result<Self, TestError> {
+        // Unoptimized, so both backends run the code as written
+        let options = CompilerOptions {
+            optimize: false,
+            debug,
+            cfg: cfg.clone(),
+            ..CompilerOptions::default()
+        };
+        let source = Source::new("contract", source);
+        let mut pipeline = CompilerPipeline::new(&options);
+        if dispatcher {
+            pipeline = pipeline.with_dispatcher();
+        }
+        let compile = |e: crate::CompileError| TestError::Compile(e.to_string());
+        let mut ast = pipeline.parse(&source).map_err(compile)?;
+        // Imported definitions come first, as functions are checked in order
+        ast.program
+            .definitions
+            .splice(0..0, imported.iter().cloned());
+        let typed = pipeline.check(&source, ast).map_err(compile)?;
+        let ir = pipeline.lower(&typed).map_err(compile)?;
+        let instructions = pipeline.codegen(&source, &ir).map_err(compile)?;
+        let binary = pipeline.encode(&instructions).map_err(compile)?;
+        let wasm = pipeline.codegen_wasm(&source, &ir).ok();
+
+        Ok(CompiledContract {
+            instructions,
+            binary,
+            wasm,
+        })
+    }
+}
+
+/// How an execution ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutcomeStatus {
+    /// Execution returned normally
+    Success,
+
+    /// Execution reverted
+    Revert,
+
+    /// Execution trapped
+    Failure(String),
+}
+
+impl fmt::Display for OutcomeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutcomeStatus::Success => write!(f, "success"),
+            OutcomeStatus::Revert => write!(f, "revert"),
+            OutcomeStatus::Failure(reason) => write!(f, "failure ({})", reason),
+        }
+    }
+}
+
+/// Observable effects of running a contract on one backend
+#[derive(Debug, Clone)]
+pub struct BackendOutcome {
+    /// How execution ended
+    pub status: OutcomeStatus,
+
+    /// Return or revert data
+    pub return_data: Vec<u8>,
+
+    /// Gas used, if the backend meters gas
+    pub gas_used: Option<u64>,
+
+    /// Storage after execution
+    pub storage: StorageMap,
+}
+
+impl BackendOutcome {
+    /// Build an outcome from a runtime execution result
+    pub fn from_execution_result(result: ExecutionResult, storage: StorageMap) -> Self {
+        let (status, return_data, gas_used) = match result {
+            ExecutionResult::Success { data, gas_used, .. } => {
+                (OutcomeStatus::Success, data, gas_used)
+            }
+            ExecutionResult::Revert { data, gas_used, .. } => {
+                (OutcomeStatus::Revert, data, gas_used)
+            }
+            ExecutionResult::Failure {
+                reason, gas_used, ..
+            } => (OutcomeStatus::Failure(reason), Vec::new(), gas_used),
+        };
+
+        BackendOutcome {
+            status,
+            return_data,
+            gas_used: Some(gas_used),
+            storage,
+        }
+    }
+}
+
+/// An engine able to execute compiled contracts
+pub trait ExecutionBackend {
+    /// Name of the backend, used in reports
+    fn name(&self) -> &str;
+
+    /// Execute a contract with the given context and initial storage
+    fn execute(
+        &mut self,
+        contract: &CompiledContract,
+        context: &ExecutionContext,
+        storage: &StorageMap,
+    ) -> Result<BackendOutcome, TestError>;
+}
+
+/// Backend running the internal RISC-V interpreter
+#[derive(Debug, Default)]
+pub struct InterpreterBackend;
+
+impl ExecutionBackend for InterpreterBackend {
+    fn name(&self) -> &str {
+        "interpreter"
+    }
+
+    fn execute(
+        &mut self,
+        contract: &CompiledContract,
+        context: &ExecutionContext,
+        storage: &StorageMap,
+    ) -> Result<BackendOutcome, TestError> {
+        let mut environment = Environment::new(context.clone());
+        environment.storage = storage.clone();
+
+        let mut interpreter = Interpreter::with_environment(environment);
+        let result = interpreter
+            .execute(&contract.instructions)
+            .map_err(|e| TestError::Execution(e.to_string()))?;
+
+        let storage = interpreter.into_environment().storage;
+        Ok(BackendOutcome::from_execution_result(result, storage))
+    }
+}
+
+/// Backend running the WebAssembly module on its interpreter
+///
+/// Gas is metered per WebAssembly instruction, which does not match the
+/// RISC-V figures, so outcomes from this backend carry no gas and gas is
+/// not compared.
+#[derive(Debug, Default)]
+pub struct WasmBackend;
+
+impl ExecutionBackend for WasmBackend {
+    fn name(&self) -> &str {
+        "wasm"
+    }
+
+    fn execute(
+        &mut self,
+        contract: &CompiledContract,
+        context: &ExecutionContext,
+        storage: &StorageMap,
+    ) -> Result<BackendOutcome, TestError> {
+        let module = contract.wasm.as_ref().ok_or_else(|| {
+            TestError::Setup("The contract was not compiled to WebAssembly".to_string())
+        })?;
+        let mut environment = Environment::new(context.clone());
+        environment.storage = storage.clone();
+
+        let mut interpreter = WasmInterpreter::with_environment(environment);
+        let result = interpreter
+            .execute(module)
+            .map_err(|e| TestError::Execution(e.to_string()))?;
+
+        let storage = interpreter.into_environment().storage;
+        Ok(BackendOutcome {
+            gas_used: None,
+            ..BackendOutcome::from_execution_result(result, storage)
+        })
+    }
+}
+
+#[cfg(feature = "polkavm-engine")]
+pub use self::polkavm_engine::PolkaVmBackend;
+
+#[cfg(feature = "polkavm-engine")]
+mod polkavm_engine {
+    use super::{BackendOutcome, CompiledContract, ExecutionBackend, StorageMap};
+    use crate::runtime::env::{Environment, ExecutionContext};
+    use crate::runtime::polkavm::PolkaVmEngine;
+    use crate::testing::TestError;
+
+    /// Backend running the linked binary on an embedded PolkaVM engine
+    ///
+    /// PolkaVM 0.1 has no gas metering, so outcomes from this backend never
+    /// carry gas figures and gas is not compared.
+    #[derive(Debug, Default)]
+    pub struct PolkaVmBackend;
+
+    impl PolkaVmBackend {
+        /// Create a new backend
+        pub fn new() -> Result<Self, TestError> {
+            Ok(PolkaVmBackend)
+        }
+    }
+
+    impl ExecutionBackend for PolkaVmBackend {
+        fn name(&self) -> &str {
+            "polkavm"
+        }
+
+        fn execute(
+            &mut self,
+            contract: &CompiledContract,
+            context: &ExecutionContext,
+            storage: &StorageMap,
+        ) -> Result<BackendOutcome, TestError> {
+            let mut environment = Environment::new(context.clone());
+            environment.storage = storage.clone();
+
+            let mut engine = PolkaVmEngine::with_environment(environment);
+            let result = engine
+                .execute(&contract.binary)
+                .map_err(|e| TestError::Execution(e.to_string()))?;
+
+            let storage = engine.into_environment().storage;
+            Ok(BackendOutcome {
+                gas_used: None,
+                ..BackendOutcome::from_execution_result(result, storage)
+            })
+        }
+    }
+}
+
+/// Which observable effect diverged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MismatchKind {
+    /// Backends ended in different states (success/revert/failure)
+    Status,
+
+    /// Backends returned different data
+    ReturnData,
+
+    /// Gas usage differs by more than the tolerance
+    Gas,
+
+    /// A storage entry differs after execution
+    Storage,
+}
+
+/// Compiler component most likely responsible for a mismatch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuspectedComponent {
+    /// The generated code behaves differently
+    Codegen,
+
+    /// The gas schedule disagrees
+    Metering,
+}
+
+impl MismatchKind {
+    /// Component most likely at fault for this kind of mismatch
+    pub fn suspected_component(&self) -> SuspectedComponent {
+        match self {
+            MismatchKind::Gas => SuspectedComponent::Metering,
+            _ => SuspectedComponent::Codegen,
+        }
+    }
+}
+
+/// A single divergence between the two backends
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    /// What diverged
+    pub kind: MismatchKind,
+
+    /// Value observed on the reference backend
+    pub reference: String,
+
+    /// Value observed on the candidate backend
+    pub candidate: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} mismatch (suspected {:?} bug): reference = {}, candidate = {}",
+            self.kind,
+            self.kind.suspected_component(),
+            self.reference,
+            self.candidate
+        )
+    }
+}
+
+/// Result of a differential run
+#[derive(Debug, Clone)]
+pub struct DifferentialReport {
+    /// Name of the reference backend
+    pub reference: String,
+
+    /// Name of the candidate backend
+    pub candidate: String,
+
+    /// All divergences found
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl DifferentialReport {
+    /// Whether both backends agreed
+    pub fn is_consistent(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    /// Turn the report into a test error if the backends disagreed
+    pub fn into_result(self) -> Result<(), TestError> {
+        if self.is_consistent() {
+            return Ok(());
+        }
+
+        let details: Vec<String> = self.mismatches.iter().map(|m| m.to_string()).collect();
+        Err(TestError::AssertionFailed(format!(
+            "{} and {} disagree: {}",
+            self.reference,
+            self.candidate,
+            details.join("; ")
+        )))
+    }
+}
+
+/// Runs contracts on two backends and compares the outcomes
+pub struct DifferentialTester {
+    reference: Box<dyn ExecutionBackend>,
+    candidate: Box<dyn ExecutionBackend>,
+    gas_tolerance: u64,
+}
+
+impl DifferentialTester {
+    /// Create a tester comparing two backends
+    pub fn new(reference: Box<dyn ExecutionBackend>, candidate: Box<dyn ExecutionBackend>) -> Self {
+        DifferentialTester {
+            reference,
+            candidate,
+            gas_tolerance: 0,
+        }
+    }
+
+    /// Create a tester comparing the RISC-V and WebAssembly backends
+    pub fn interpreter_vs_wasm() -> Self {
+        Self::new(Box::new(InterpreterBackend), Box::new(WasmBackend))
+    }
+
+    /// Create a tester comparing the interpreter against PolkaVM
+    #[cfg(feature = "polkavm-engine")]
+    pub fn interpreter_vs_polkavm() -> Result<Self, TestError> {
+        Ok(Self::new(
+            Box::new(InterpreterBackend),
+            Box::new(PolkaVmBackend::new()?),
+        ))
+    }
+
+    /// Allow gas figures to differ by up to `tolerance`
+    pub fn with_gas_tolerance(mut self, tolerance: u64) -> Self {
+        self.gas_tolerance = tolerance;
+        self
+    }
+
+    /// Run a compiled contract on both backends and compare the results
+    pub fn run(
+        &mut self,
+        contract: &CompiledContract,
+        context: &ExecutionContext,
+        storage: &StorageMap,
+    ) -> Result<DifferentialReport, TestError> {
+        let reference = self.reference.execute(contract, context, storage)?;
+        let candidate = self.candidate.execute(contract, context, storage)?;
+
+        Ok(DifferentialReport {
+            reference: self.reference.name().to_string(),
+            candidate: self.candidate.name().to_string(),
+            mismatches: self.compare(&reference, &candidate),
+        })
+    }
+
+    /// Compile a test case and run it on both backends
+    pub fn run_test_case(&mut self, test: &TestCase) -> Result<DifferentialReport, TestError> {
+        let contract = CompiledContract::for_test(test)?;
+
+        let context = ExecutionContext::new(
+            Address::ZERO,
+            Address::ZERO,
+            0,
+            Vec::new(),
+            1,
+            1000000,
+            test.gas_limit,
+            test.proof_size_limit,
+            test.storage_deposit_limit,
+        );
+        let storage = test
+            .initial_storage
+            .iter()
+            .map(|(key, value)| (key.as_bytes().to_vec(), value.clone()))
+            .collect();
+
+        self.run(&contract, &context, &storage)
+    }
+
+    fn compare(&self, reference: &BackendOutcome, candidate: &BackendOutcome) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+
+        if reference.status != candidate.status {
+            mismatches.push(Mismatch {
+                kind: MismatchKind::Status,
+                reference: reference.status.to_string(),
+                candidate: candidate.status.to_string(),
+            });
+        }
+
+        if reference.return_data != candidate.return_data {
+            mismatches.push(Mismatch {
+                kind: MismatchKind::ReturnData,
+                reference: hex::encode(&reference.return_data),
+                candidate: hex::encode(&candidate.return_data),
+            });
+        }
+
+        if let (Some(reference_gas), Some(candidate_gas)) = (reference.gas_used, candidate.gas_used)
+        {
+            if reference_gas.abs_diff(candidate_gas) > self.gas_tolerance {
+                mismatches.push(Mismatch {
+                    kind: MismatchKind::Gas,
+                    reference: reference_gas.to_string(),
+                    candidate: candidate_gas.to_string(),
+                });
+            }
+        }
+
+        let keys: BTreeSet<&Vec<u8>> = reference
+            .storage
+            .keys()
+            .chain(candidate.storage.keys())
+            .collect();
+        for key in keys {
+            let (left, right) = (reference.storage.get(key), candidate.storage.get(key));
+            if left != right {
+                let show =
+                    |value: Option<&Vec<u8>>| value.map_or("<none>".to_string(), hex::encode);
+                mismatches.push(Mismatch {
+                    kind: MismatchKind::Storage,
+                    reference: format!("{} => {}", hex::encode(key), show(left)),
+                    candidate: format!("{} => {}", hex::encode(key), show(right)),
+                });
+            }
+        }
+
+        mismatches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::codegen::risc_v::Register;
+
+    /// Backend that returns a fixed outcome
+    struct FixedBackend(BackendOutcome);
+
+    impl ExecutionBackend for FixedBackend {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn execute(
+            &mut self,
+            _contract: &CompiledContract,
+            _context: &ExecutionContext,
+            _storage: &StorageMap,
+        ) -> Result<BackendOutcome, TestError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn contract() -> CompiledContract {
+        CompiledContract {
+            instructions: vec![Instruction::Li(Register::X10, 5)],
+            binary: Vec::new(),
+            wasm: None,
+        }
+    }
+
+    fn outcome(data: Vec<u8>, gas: u64) -> BackendOutcome {
+        BackendOutcome {
+            status: OutcomeStatus::Success,
+            return_data: data,
+            gas_used: Some(gas),
+            storage: StorageMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_same_backend_is_consistent() {
+        let mut tester =
+            DifferentialTester::new(Box::new(InterpreterBackend), Box::new(InterpreterBackend));
+        let report = tester
+            .run(
+                &contract(),
+                &ExecutionContext::new_default(),
+                &StorageMap::new(),
+            )
+            .unwrap();
+
+        assert!(report.is_consistent());
+        assert!(report.into_result().is_ok());
+    }
+
+    #[test]
+    fn test_run_test_case_compiles_source() {
+        let test = TestCase {
+            name: "returns_constant".to_string(),
+            source: "fn main() -> u24 { return 3; }".to_string(),
+            ..Default::default()
+        };
+
+        let mut tester =
+            DifferentialTester::new(Box::new(InterpreterBackend), Box::new(InterpreterBackend));
+        let report = tester.run_test_case(&test).unwrap();
+        assert!(report.is_consistent());
+    }
+
+    #[test]
+    fn test_return_data_mismatch_blames_codegen() {
+        let mut tester = DifferentialTester::new(
+            Box::new(InterpreterBackend),
+            Box::new(FixedBackend(outcome(vec![6, 0, 0, 0], 1))),
+        );
+        let report = tester
+            .run(
+                &contract(),
+                &ExecutionContext::new_default(),
+                &StorageMap::new(),
+            )
+            .unwrap();
+
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].kind, MismatchKind::ReturnData);
+        assert_eq!(
+            report.mismatches[0].kind.suspected_component(),
+            SuspectedComponent::Codegen
+        );
+        assert!(report.into_result().is_err());
+    }
+
+    #[test]
+    fn test_gas_mismatch_respects_tolerance() {
+        let reference = outcome(vec![1], 100);
+        let candidate = outcome(vec![1], 110);
+
+        let tester = DifferentialTester::new(
+            Box::new(FixedBackend(reference.clone())),
+            Box::new(FixedBackend(candidate.clone())),
+        );
+        let mismatches = tester.compare(&reference, &candidate);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(
+            mismatches[0].kind.suspected_component(),
+            SuspectedComponent::Metering
+        );
+
+        let tester = tester.with_gas_tolerance(10);
+        assert!(tester.compare(&reference, &candidate).is_empty());
+    }
+
+    #[test]
+    fn test_storage_mismatch_is_reported() {
+        let reference = outcome(Vec::new(), 1);
+        let mut candidate = reference.clone();
+        candidate.storage.insert(b"key".to_vec(), b"value".to_vec());
+
+        let tester = DifferentialTester::new(
+            Box::new(FixedBackend(reference.clone())),
+            Box::new(FixedBackend(candidate.clone())),
+        );
+        let mismatches = tester.compare(&reference, &candidate);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].kind, MismatchKind::Storage);
+        assert!(mismatches[0].reference.ends_with("<none>"));
+    }
+}
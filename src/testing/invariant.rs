@@ -0,0 +1,1102 @@
+//! # Invariant Testing
+//!
+//! Stateful sequence fuzzing against contract invariants. The messages of a
+//! contract and the arguments they take come from its ABI, and invariants are
+//! `#[invariant]` functions of the contract returning whether they hold
+//! (for example "total supply equals the sum of balances"), or checks over
+//! its storage given in Rust. The tester generates random sequences of
+//! message calls, executes them one after the other against the same
+//! storage, and checks every invariant after each call. When an invariant is
+//! broken, the failing call sequence is shrunk to a minimal reproduction
+//! before it is reported.
+//!
+//! Contracts compiled with their specifications checked also break an
+//! invariant when a call panics on a failed `#[ensures(...)]` or
+//! `#[invariant(...)]`, named after the panic message.
+
+use std::fmt;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::compiler::address::Address;
+use crate::compiler::analyzer::attributes::FunctionAttributes;
+use crate::compiler::codegen::metadata::{
+    collect_function_metadata, selector_for, FunctionMetadata, PANIC_SIGNATURE,
+};
+use crate::compiler::lowering::{INVARIANT_FAILED, POSTCONDITION_FAILED};
+use crate::compiler::parser::ast::{Definition, Program};
+use crate::compiler::pipeline::{CompilerPipeline, Source};
+use crate::compiler::wide::{wide_type_bits, WideUint};
+use crate::runtime::env::{Environment, ExecutionContext, ExecutionResult};
+use crate::runtime::interpreter::Interpreter;
+use crate::stdlib::bytes::Bytes;
+use crate::testing::differential::{
+    CompiledContract, ExecutionBackend, InterpreterBackend, OutcomeStatus, StorageMap,
+};
+use crate::testing::{TestCase, TestError};
+use crate::CompilerOptions;
+
+/// Name reported when a call reverts while `fail_on_revert` is set
+const REVERT_VIOLATION: &str = "<call reverted>";
+
+/// Longest `Bytes` argument generated for a message taken from the ABI
+const MAX_BYTES_LEN: usize = 64;
+
+/// Width of the range near zero most integer arguments are drawn from, as
+/// messages tend to check their arguments against small bounds
+const SMALL_RANGE: u32 = 1024;
+
+/// Argument of a message and how to generate it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgSpec {
+    /// A 32-bit word in `min..=max`, encoded little-endian
+    Word { min: u32, max: u32 },
+
+    /// A signed 32-bit word in `min..=max`, encoded little-endian
+    Int { min: i32, max: i32 },
+
+    /// A `Bool`, encoded as one byte
+    Bool,
+
+    /// An `Address`: one of the callers, the zero address or a random one
+    Address,
+
+    /// A `Hash` of 32 random bytes
+    Hash,
+
+    /// An unsigned integer of `bits` bits, encoded little-endian
+    Wide { bits: u16 },
+
+    /// `Bytes` of up to `max_len` bytes, SCALE-encoded with their length
+    Bytes { max_len: usize },
+}
+
+impl ArgSpec {
+    /// How to generate an argument of the ABI type `type_name`, as
+    /// [`type_name`](crate::compiler::codegen::metadata::type_name) renders
+    /// it, or `None` for a type messages cannot take
+    pub fn for_type(type_name: &str) -> Option<Self> {
+        Some(match type_name {
+            "u24" | "f24" => ArgSpec::Word {
+                min: 0,
+                max: 0xFF_FFFF,
+            },
+            "u32" => ArgSpec::Word {
+                min: 0,
+                max: u32::MAX,
+            },
+            "i24" => ArgSpec::Int {
+                min: -0x80_0000,
+                max: 0x7F_FFFF,
+            },
+            "i32" => ArgSpec::Int {
+                min: i32::MIN,
+                max: i32::MAX,
+            },
+            "Bool" | "bool" => ArgSpec::Bool,
+            "Address" => ArgSpec::Address,
+            "Hash" => ArgSpec::Hash,
+            "Bytes" => ArgSpec::Bytes {
+                max_len: MAX_BYTES_LEN,
+            },
+            _ => ArgSpec::Wide {
+                bits: wide_type_bits(type_name)?,
+            },
+        })
+    }
+}
+
+/// Concrete argument value of a generated call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgValue {
+    /// A 32-bit word
+    Word(u32),
+
+    /// A signed 32-bit word
+    Int(i32),
+
+    /// A `Bool`
+    Bool(bool),
+
+    /// An `Address`
+    Address([u8; 32]),
+
+    /// A `Hash`
+    Hash([u8; 32]),
+
+    /// A wide unsigned integer
+    Wide(WideUint),
+
+    /// The contents of `Bytes`
+    Bytes(Vec<u8>),
+}
+
+impl ArgValue {
+    /// Append the ABI encoding of the value, as the dispatcher decodes it
+    fn encode_into(&self, input: &mut Vec<u8>) {
+        match self {
+            ArgValue::Word(value) => input.extend_from_slice(&value.to_le_bytes()),
+            ArgValue::Int(value) => input.extend_from_slice(&value.to_le_bytes()),
+            ArgValue::Bool(value) => input.push(*value as u8),
+            ArgValue::Address(bytes) | ArgValue::Hash(bytes) => input.extend_from_slice(bytes),
+            ArgValue::Wide(value) => input.extend_from_slice(&value.to_le_bytes()),
+            ArgValue::Bytes(bytes) => input.extend(Bytes::from(bytes.as_slice()).scale_encode()),
+        }
+    }
+}
+
+impl fmt::Display for ArgValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgValue::Word(value) => write!(f, "{}", value),
+            ArgValue::Int(value) => write!(f, "{}", value),
+            ArgValue::Bool(value) => write!(f, "{}", value),
+            ArgValue::Address(bytes) | ArgValue::Hash(bytes) => {
+                write!(f, "0x{}", hex::encode(bytes))
+            }
+            ArgValue::Wide(value) => write!(f, "{}", value),
+            ArgValue::Bytes(bytes) => write!(f, "0x{}", hex::encode(bytes)),
+        }
+    }
+}
+
+/// A message the contract accepts
+#[derive(Debug, Clone)]
+pub struct MessageSpec {
+    /// Name of the message, used in reports
+    pub name: String,
+
+    /// Bytes prepended to the encoded arguments
+    pub selector: Vec<u8>,
+
+    /// Arguments of the message
+    pub args: Vec<ArgSpec>,
+}
+
+impl MessageSpec {
+    /// Create a message with the given name and selector
+    pub fn new(name: &str, selector: Vec<u8>) -> Self {
+        MessageSpec {
+            name: name.to_string(),
+            selector,
+            args: Vec::new(),
+        }
+    }
+
+    /// Add an argument to the message
+    pub fn arg(mut self, arg: ArgSpec) -> Self {
+        self.args.push(arg);
+        self
+    }
+
+    /// The message described by the ABI entry of a function, with an
+    /// argument generated for each of its parameters
+    pub fn from_metadata(function: &FunctionMetadata) -> Result<Self, TestError> {
+        let mut message = MessageSpec::new(&function.name, function.selector.to_vec());
+        for param in &function.params {
+            let arg = ArgSpec::for_type(&param.type_name).ok_or_else(|| {
+                TestError::InvalidTestCase(format!(
+                    "Cannot generate argument '{}' of message '{}', of type {}",
+                    param.name, function.name, param.type_name
+                ))
+            })?;
+            message = message.arg(arg);
+        }
+        Ok(message)
+    }
+}
+
+/// A single generated message call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    /// Index of the message in the tester's message list
+    pub message: usize,
+
+    /// Name of the message
+    pub name: String,
+
+    /// Caller address
+    pub caller: [u8; 32],
+
+    /// Argument values
+    pub args: Vec<ArgValue>,
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let args: Vec<String> = self.args.iter().map(ToString::to_string).collect();
+        write!(
+            f,
+            "{}({}) from 0x{}",
+            self.name,
+            args.join(", "),
+            hex::encode(&self.caller[..4])
+        )
+    }
+}
+
+/// An invariant over contract storage, checked in Rust
+#[allow(clippy::type_complexity)]
+pub struct Invariant {
+    /// Name of the invariant
+    pub name: String,
+
+    /// Returns `true` while the invariant holds
+    pub check: Box<dyn Fn(&StorageMap) -> bool>,
+}
+
+/// Invariant testing configuration
+#[derive(Debug, Clone)]
+pub struct InvariantConfig {
+    /// Number of random call sequences to try
+    pub runs: u32,
+
+    /// Number of calls in each sequence
+    pub depth: usize,
+
+    /// Seed for sequence generation
+    pub seed: u64,
+
+    /// Treat reverted or trapped calls as invariant violations
+    pub fail_on_revert: bool,
+
+    /// Maximum number of sequence replays spent on shrinking
+    pub max_shrink_replays: u32,
+
+    /// Addresses calls are sent from
+    pub callers: Vec<[u8; 32]>,
+}
+
+impl Default for InvariantConfig {
+    fn default() -> Self {
+        InvariantConfig {
+            runs: 256,
+            depth: 16,
+            seed: 0,
+            fail_on_revert: false,
+            max_shrink_replays: 1024,
+            callers: vec![[1u8; 32], [2u8; 32], [3u8; 32]],
+        }
+    }
+}
+
+/// A broken invariant together with a minimal call sequence reproducing it
+#[derive(Debug, Clone)]
+pub struct InvariantFailure {
+    /// Name of the violated invariant
+    pub invariant: String,
+
+    /// Shrunk call sequence; the invariant is broken after its last call
+    pub sequence: Vec<Call>,
+
+    /// Length of the sequence before shrinking
+    pub original_length: usize,
+}
+
+impl fmt::Display for InvariantFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "invariant '{}' broken after {} call(s) (shrunk from {}):",
+            self.invariant,
+            self.sequence.len(),
+            self.original_length
+        )?;
+        for (index, call) in self.sequence.iter().enumerate() {
+            writeln!(f, "  {}. {}", index + 1, call)?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of an invariant testing session
+#[derive(Debug, Clone)]
+pub struct InvariantReport {
+    /// Seed used to generate sequences
+    pub seed: u64,
+
+    /// Number of sequences executed
+    pub runs: u32,
+
+    /// Number of calls executed, excluding shrinking
+    pub calls: u64,
+
+    /// Number of calls that reverted or trapped
+    pub reverts: u64,
+
+    /// First invariant violation found, if any
+    pub failure: Option<InvariantFailure>,
+}
+
+impl InvariantReport {
+    /// Whether every invariant held
+    pub fn is_success(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    /// Convert the report into a test result
+    pub fn into_result(self) -> Result<(), TestError> {
+        match self.failure {
+            None => Ok(()),
+            Some(failure) => Err(TestError::AssertionFailed(format!(
+                "{}(seed {})",
+                failure, self.seed
+            ))),
+        }
+    }
+}
+
+/// Outcome of replaying a sequence
+struct Replay {
+    /// Calls executed
+    calls: u64,
+
+    /// Calls that reverted or trapped
+    reverts: u64,
+
+    /// Index of the call after which an invariant broke, and its name
+    violation: Option<(usize, String)>,
+}
+
+/// Stateful fuzzer checking invariants after every message call
+pub struct InvariantTester {
+    contract: CompiledContract,
+    backend: Box<dyn ExecutionBackend>,
+    context: ExecutionContext,
+    initial_storage: StorageMap,
+    messages: Vec<MessageSpec>,
+    invariants: Vec<Invariant>,
+    /// Names of the contract's `#[invariant]` functions to check
+    invariant_functions: Vec<String>,
+    config: InvariantConfig,
+}
+
+impl InvariantTester {
+    /// Create a tester running the contract on the interpreter
+    pub fn new(contract: CompiledContract) -> Self {
+        InvariantTester {
+            contract,
+            backend: Box::new(InterpreterBackend),
+            context: ExecutionContext::new_default(),
+            initial_storage: StorageMap::new(),
+            messages: Vec::new(),
+            invariants: Vec::new(),
+            invariant_functions: Vec::new(),
+            config: InvariantConfig::default(),
+        }
+    }
+
+    /// Compile a contract from source, as deployed and with its
+    /// specifications checked, and create a tester calling the messages of
+    /// its ABI and checking all of its `#[invariant]` functions
+    pub fn from_source(source: &str) -> Result<Self, TestError> {
+        let test = TestCase {
+            source: source.to_string(),
+            ..Default::default()
+        };
+        let (mut tester, program) = Self::with_abi(&test)?;
+        for definition in &program.definitions {
+            if let Definition::FunctionDef { name, .. } = definition {
+                if FunctionAttributes::of(definition).is_ok_and(|attributes| attributes.invariant) {
+                    tester = tester.invariant_function(name);
+                }
+            }
+        }
+        Ok(tester)
+    }
+
+    /// Create a tester for the `#[invariant]` function of a test case,
+    /// calling the messages of its contract's ABI from its initial storage,
+    /// with sequences generated from its random seed
+    pub fn for_test(test: &TestCase) -> Result<Self, TestError> {
+        let (tester, _) = Self::with_abi(test)?;
+        let initial_storage = test
+            .initial_storage
+            .iter()
+            .map(|(key, value)| (key.as_bytes().to_vec(), value.clone()))
+            .collect();
+        Ok(tester
+            .invariant_function(&test.function)
+            .with_initial_storage(initial_storage)
+            .with_config(InvariantConfig {
+                seed: test.random_seed,
+                ..Default::default()
+            }))
+    }
+
+    /// Compile the contract of a test case and create a tester calling the
+    /// messages of its ABI, in the order they are defined
+    fn with_abi(test: &TestCase) -> Result<(Self, Program), TestError> {
+        let contract = CompiledContract::for_invariants(test)?;
+        let options = CompilerOptions {
+            cfg: test.cfg.clone(),
+            ..CompilerOptions::default()
+        };
+        let program = CompilerPipeline::new(&options)
+            .parse(&Source::new(&test.name, &test.source))
+            .map_err(|e| TestError::Compile(e.to_string()))?
+            .program;
+
+        let functions = collect_function_metadata(&program);
+        let mut tester = Self::new(contract);
+        for definition in &program.definitions {
+            if let Definition::FunctionDef { name, .. } = definition {
+                if let Some(function) = functions.get(name.as_str()) {
+                    tester = tester.message(MessageSpec::from_metadata(function)?);
+                }
+            }
+        }
+        Ok((tester, program))
+    }
+
+    /// Use a different execution backend
+    pub fn with_backend(mut self, backend: Box<dyn ExecutionBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Set the execution context calls are derived from
+    pub fn with_context(mut self, context: ExecutionContext) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Set the storage every sequence starts from
+    pub fn with_initial_storage(mut self, storage: StorageMap) -> Self {
+        self.initial_storage = storage;
+        self
+    }
+
+    /// Set the configuration
+    pub fn with_config(mut self, config: InvariantConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Register a message the fuzzer may call
+    pub fn message(mut self, message: MessageSpec) -> Self {
+        self.messages.push(message);
+        self
+    }
+
+    /// Register an invariant checked after every call
+    pub fn invariant<F>(mut self, name: &str, check: F) -> Self
+    where
+        F: Fn(&StorageMap) -> bool + 'static,
+    {
+        self.invariants.push(Invariant {
+            name: name.to_string(),
+            check: Box::new(check),
+        });
+        self
+    }
+
+    /// Register the contract's `#[invariant]` function `name`, checked after
+    /// every call. It runs on the interpreter whatever the backend, as only
+    /// exports can be entered on PolkaVM, and does not hold if it reverts
+    /// or traps.
+    pub fn invariant_function(mut self, name: &str) -> Self {
+        self.invariant_functions.push(name.to_string());
+        self
+    }
+
+    /// Run random call sequences until an invariant breaks or all runs pass
+    pub fn run(&mut self) -> Result<InvariantReport, TestError> {
+        if self.messages.is_empty() {
+            return Err(TestError::InvalidTestCase(
+                "Invariant testing needs at least one message".to_string(),
+            ));
+        }
+        if self.config.callers.is_empty() {
+            return Err(TestError::InvalidTestCase(
+                "Invariant testing needs at least one caller".to_string(),
+            ));
+        }
+
+        let mut report = InvariantReport {
+            seed: self.config.seed,
+            runs: 0,
+            calls: 0,
+            reverts: 0,
+            failure: None,
+        };
+
+        if let Some(name) = self.violated_invariant(&self.initial_storage)? {
+            report.failure = Some(InvariantFailure {
+                invariant: name,
+                sequence: Vec::new(),
+                original_length: 0,
+            });
+            return Ok(report);
+        }
+
+        let mut rng = StdRng::seed_from_u64(self.config.seed);
+        for _ in 0..self.config.runs {
+            let sequence: Vec<Call> = (0..self.config.depth)
+                .map(|_| self.generate_call(&mut rng))
+                .collect();
+
+            let replay = self.replay(&sequence)?;
+            report.runs += 1;
+            report.calls += replay.calls;
+            report.reverts += replay.reverts;
+
+            if let Some((index, invariant)) = replay.violation {
+                let original_length = index + 1;
+                let sequence = self.shrink(sequence[..original_length].to_vec(), &invariant)?;
+                report.failure = Some(InvariantFailure {
+                    invariant,
+                    sequence,
+                    original_length,
+                });
+                break;
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn generate_call(&self, rng: &mut StdRng) -> Call {
+        let message = rng.gen_range(0..self.messages.len());
+        let spec = &self.messages[message];
+        let caller = self.config.callers[rng.gen_range(0..self.config.callers.len())];
+
+        let args = spec
+            .args
+            .iter()
+            .map(|arg| match arg {
+                ArgSpec::Word { min, max } => ArgValue::Word(match rng.gen_range(0..4) {
+                    0 => rng.gen_range(*min..=*max),
+                    1 => *max,
+                    _ => rng.gen_range(*min..=(*max).min(min.saturating_add(SMALL_RANGE))),
+                }),
+                ArgSpec::Int { min, max } => ArgValue::Int(match rng.gen_range(0..4) {
+                    0 => rng.gen_range(*min..=*max),
+                    1 => *max,
+                    _ => {
+                        let small = SMALL_RANGE as i32;
+                        let low = (*min).max(-small).min(*max);
+                        rng.gen_range(low..=(*max).min(low.saturating_add(2 * small)))
+                    }
+                }),
+                ArgSpec::Bool => ArgValue::Bool(rng.gen()),
+                ArgSpec::Address => ArgValue::Address(match rng.gen_range(0..4) {
+                    0 => [0u8; 32],
+                    1 => rng.gen(),
+                    _ => self.config.callers[rng.gen_range(0..self.config.callers.len())],
+                }),
+                ArgSpec::Hash => ArgValue::Hash(rng.gen()),
+                ArgSpec::Wide { bits } => {
+                    // Spread the magnitudes over every byte length
+                    let mut bytes = vec![0u8; *bits as usize / 8];
+                    let len = rng.gen_range(0..=bytes.len());
+                    rng.fill(&mut bytes[..len]);
+                    ArgValue::Wide(WideUint::from_le_bytes(&bytes).expect("wide integer bits"))
+                }
+                ArgSpec::Bytes { max_len } => {
+                    let len = rng.gen_range(0..=*max_len);
+                    ArgValue::Bytes((0..len).map(|_| rng.gen()).collect())
+                }
+            })
+            .collect();
+
+        Call {
+            message,
+            name: spec.name.clone(),
+            caller,
+            args,
+        }
+    }
+
+    fn violated_invariant(&self, storage: &StorageMap) -> Result<Option<String>, TestError> {
+        for name in &self.invariant_functions {
+            if !self.function_holds(name, storage)? {
+                return Ok(Some(name.clone()));
+            }
+        }
+        Ok(self
+            .invariants
+            .iter()
+            .find(|invariant| !(invariant.check)(storage))
+            .map(|invariant| invariant.name.clone()))
+    }
+
+    /// Whether the `#[invariant]` function `name` returns true on `storage`
+    fn function_holds(&self, name: &str, storage: &StorageMap) -> Result<bool, TestError> {
+        let mut environment = Environment::new(self.context.clone());
+        environment.storage = storage.clone();
+
+        let mut interpreter = Interpreter::with_environment(environment);
+        let result = interpreter
+            .execute_function(&self.contract.instructions, name)
+            .map_err(|e| TestError::Execution(e.to_string()))?;
+        Ok(
+            matches!(result, ExecutionResult::Success { data, .. } if data.iter().any(|&byte| byte != 0)),
+        )
+    }
+
+    /// Execute a sequence from the initial storage, stopping at the first violation
+    fn replay(&mut self, sequence: &[Call]) -> Result<Replay, TestError> {
+        let mut storage = self.initial_storage.clone();
+        let mut replay = Replay {
+            calls: 0,
+            reverts: 0,
+            violation: None,
+        };
+
+        for (index, call) in sequence.iter().enumerate() {
+            let mut context = self.context.clone();
+            context.caller = Address::new(call.caller);
+            context.block_number = self.context.block_number + index as u64;
+            context.input = self.messages[call.message].selector.clone();
+            for arg in &call.args {
+                arg.encode_into(&mut context.input);
+            }
+
+            let outcome = self.backend.execute(&self.contract, &context, &storage)?;
+            replay.calls += 1;
+
+            // Reverted and trapped calls leave storage untouched
+            if outcome.status == OutcomeStatus::Success {
+                storage = outcome.storage;
+            } else if let Some(message) = specification_failure(&outcome.return_data) {
+                replay.violation = Some((index, message));
+                break;
+            } else {
+                replay.reverts += 1;
+                if self.config.fail_on_revert {
+                    replay.violation = Some((index, REVERT_VIOLATION.to_string()));
+                    break;
+                }
+            }
+
+            if let Some(name) = self.violated_invariant(&storage)? {
+                replay.violation = Some((index, name));
+                break;
+            }
+        }
+
+        Ok(replay)
+    }
+
+    /// Shrink a failing sequence while it keeps breaking the same invariant
+    fn shrink(&mut self, mut sequence: Vec<Call>, invariant: &str) -> Result<Vec<Call>, TestError> {
+        let mut budget = self.config.max_shrink_replays;
+
+        // Returns the truncated sequence if the candidate still fails
+        let mut still_fails =
+            |tester: &mut Self, candidate: &[Call]| -> Result<Option<Vec<Call>>, TestError> {
+                if budget == 0 {
+                    return Ok(None);
+                }
+                budget -= 1;
+                Ok(match tester.replay(candidate)?.violation {
+                    Some((index, name)) if name == invariant => Some(candidate[..=index].to_vec()),
+                    _ => None,
+                })
+            };
+
+        // Drop calls that are not needed to reproduce the failure
+        let mut index = 0;
+        while index < sequence.len() {
+            let mut candidate = sequence.clone();
+            candidate.remove(index);
+            match still_fails(self, &candidate)? {
+                Some(shorter) => sequence = shorter,
+                None => index += 1,
+            }
+        }
+
+        // Move every argument as close to its simplest value as possible
+        for call_index in 0..sequence.len() {
+            for arg_index in 0..sequence[call_index].args.len() {
+                let message = sequence[call_index].message;
+                match (
+                    self.messages[message].args[arg_index].clone(),
+                    sequence[call_index].args[arg_index].clone(),
+                ) {
+                    (ArgSpec::Word { min, .. }, ArgValue::Word(value)) => {
+                        // Binary search for the smallest failing value in min..=value
+                        let (mut low, mut high) = (min, value);
+                        while low < high {
+                            let mid = low + (high - low) / 2;
+                            let mut candidate = sequence.clone();
+                            candidate[call_index].args[arg_index] = ArgValue::Word(mid);
+                            match still_fails(self, &candidate)? {
+                                Some(shorter) if shorter.len() == sequence.len() => {
+                                    sequence = shorter;
+                                    high = mid;
+                                }
+                                _ => low = mid + 1,
+                            }
+                        }
+                    }
+                    (ArgSpec::Int { min, max }, ArgValue::Int(value)) => {
+                        // Binary search for the failing value closest to
+                        // zero, by its distance from zero on the side of value
+                        let sign: i64 = if value < 0 { -1 } else { 1 };
+                        let nearest = if value < 0 { max.min(0) } else { min.max(0) };
+                        let (mut low, mut high) = (nearest as i64 * sign, value as i64 * sign);
+                        while low < high {
+                            let mid = low + (high - low) / 2;
+                            let mut candidate = sequence.clone();
+                            candidate[call_index].args[arg_index] =
+                                ArgValue::Int((mid * sign) as i32);
+                            match still_fails(self, &candidate)? {
+                                Some(shorter) if shorter.len() == sequence.len() => {
+                                    sequence = shorter;
+                                    high = mid;
+                                }
+                                _ => low = mid + 1,
+                            }
+                        }
+                    }
+                    (ArgSpec::Bool, ArgValue::Bool(true)) => {
+                        let mut candidate = sequence.clone();
+                        candidate[call_index].args[arg_index] = ArgValue::Bool(false);
+                        match still_fails(self, &candidate)? {
+                            Some(shorter) if shorter.len() == sequence.len() => sequence = shorter,
+                            _ => {}
+                        }
+                    }
+                    (ArgSpec::Wide { .. }, ArgValue::Wide(mut value)) => {
+                        while !value.is_zero() {
+                            value = halve(&value);
+                            let mut candidate = sequence.clone();
+                            candidate[call_index].args[arg_index] = ArgValue::Wide(value);
+                            match still_fails(self, &candidate)? {
+                                Some(shorter) if shorter.len() == sequence.len() => {
+                                    sequence = shorter
+                                }
+                                _ => break,
+                            }
+                        }
+                    }
+                    (ArgSpec::Bytes { .. }, ArgValue::Bytes(mut bytes)) => {
+                        while !bytes.is_empty() {
+                            bytes.truncate(bytes.len() / 2);
+                            let mut candidate = sequence.clone();
+                            candidate[call_index].args[arg_index] = ArgValue::Bytes(bytes.clone());
+                            match still_fails(self, &candidate)? {
+                                Some(shorter) if shorter.len() == sequence.len() => {
+                                    sequence = shorter
+                                }
+                                _ => break,
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(sequence)
+    }
+}
+
+/// Half of a wide integer, rounded down
+fn halve(value: &WideUint) -> WideUint {
+    let mut bytes = value.to_le_bytes();
+    let mut carry = 0;
+    for byte in bytes.iter_mut().rev() {
+        let next = *byte & 1;
+        *byte = (*byte >> 1) | (carry << 7);
+        carry = next;
+    }
+    WideUint::from_le_bytes(&bytes).expect("wide integer bits")
+}
+
+/// The message of a panic on a failed postcondition or invariant
+fn specification_failure(data: &[u8]) -> Option<String> {
+    let message = data.strip_prefix(&selector_for(PANIC_SIGNATURE)[..])?;
+    let (message, _) = Bytes::scale_decode(message)?;
+    let message = String::from_utf8(message.into_vec()).ok()?;
+    (message.starts_with(POSTCONDITION_FAILED) || message.starts_with(INVARIANT_FAILED))
+        .then_some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::codegen::risc_v::{Instruction, Register};
+    use crate::compiler::parser::parser::Parser;
+    use crate::compiler::polkavm::host::HostFunction;
+
+    const COUNTER_KEY: u32 = 0x1234;
+
+    /// Contract adding the first input word to a counter in storage
+    fn counter_contract() -> CompiledContract {
+        let instructions = vec![
+            Instruction::Load(Register::X7, Register::X0, 0), // input word
+            Instruction::Li(Register::X5, COUNTER_KEY as i32),
+            Instruction::Li(Register::X6, 0x100),
+            Instruction::Store(Register::X5, Register::X6, 0),
+            Instruction::Li(Register::X10, 0x100), // key ptr
+            Instruction::Li(Register::X11, 4),     // key len
+            Instruction::Li(Register::X12, 0x200), // value ptr
+            Instruction::Li(Register::X13, 0x300), // value len ptr
+            Instruction::Li(Register::X17, HostFunction::StorageGet as i32),
+            Instruction::Ecall,
+            Instruction::Li(Register::X6, 0x200),
+            Instruction::Load(Register::X8, Register::X6, 0),
+            Instruction::Add(Register::X8, Register::X8, Register::X7),
+            Instruction::Store(Register::X8, Register::X6, 0),
+            Instruction::Li(Register::X10, 0x100),
+            Instruction::Li(Register::X11, 4),
+            Instruction::Li(Register::X12, 0x200),
+            Instruction::Li(Register::X13, 4),
+            Instruction::Li(Register::X17, HostFunction::StorageSet as i32),
+            Instruction::Ecall,
+        ];
+
+        CompiledContract {
+            instructions,
+            binary: Vec::new(),
+            wasm: None,
+        }
+    }
+
+    fn counter(storage: &StorageMap) -> u32 {
+        storage
+            .get(&COUNTER_KEY.to_le_bytes().to_vec())
+            .map(|value| u32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+            .unwrap_or(0)
+    }
+
+    fn add_message(max: u32) -> MessageSpec {
+        MessageSpec::new("add", Vec::new()).arg(ArgSpec::Word { min: 0, max })
+    }
+
+    #[test]
+    fn test_holding_invariant_passes() {
+        let mut tester = InvariantTester::new(counter_contract())
+            .with_config(InvariantConfig {
+                runs: 8,
+                depth: 4,
+                ..Default::default()
+            })
+            .message(add_message(10))
+            .invariant("counter_bounded", |storage| counter(storage) <= 40);
+
+        let report = tester.run().unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.runs, 8);
+        assert_eq!(report.calls, 32);
+        assert!(report.into_result().is_ok());
+    }
+
+    #[test]
+    fn test_failing_sequence_is_shrunk() {
+        let mut tester = InvariantTester::new(counter_contract())
+            .message(add_message(60))
+            .invariant("counter_below_100", |storage| counter(storage) < 100);
+
+        let report = tester.run().unwrap();
+        let failure = report.failure.clone().expect("invariant should break");
+        assert_eq!(failure.invariant, "counter_below_100");
+        assert!(failure.sequence.len() <= failure.original_length);
+
+        // Every argument is minimal: the counter lands exactly on the bound
+        let total: u32 = failure
+            .sequence
+            .iter()
+            .map(|call| match call.args[0] {
+                ArgValue::Word(value) => value,
+                _ => unreachable!(),
+            })
+            .sum();
+        assert_eq!(total, 100);
+        assert!(failure.sequence.len() >= 2);
+
+        let error = report.into_result().unwrap_err();
+        assert!(error.to_string().contains("counter_below_100"));
+    }
+
+    #[test]
+    fn test_single_call_shrinks_to_boundary() {
+        let mut tester = InvariantTester::new(counter_contract())
+            .message(add_message(1000))
+            .invariant("counter_below_100", |storage| counter(storage) < 100);
+
+        let failure = tester.run().unwrap().failure.unwrap();
+        assert_eq!(failure.sequence.len(), 1);
+        assert_eq!(failure.sequence[0].args, vec![ArgValue::Word(100)]);
+    }
+
+    #[test]
+    fn test_initial_state_is_checked() {
+        let mut storage = StorageMap::new();
+        storage.insert(
+            COUNTER_KEY.to_le_bytes().to_vec(),
+            500u32.to_le_bytes().to_vec(),
+        );
+
+        let mut tester = InvariantTester::new(counter_contract())
+            .with_initial_storage(storage)
+            .message(add_message(1))
+            .invariant("counter_below_100", |storage| counter(storage) < 100);
+
+        let failure = tester.run().unwrap().failure.unwrap();
+        assert!(failure.sequence.is_empty());
+    }
+
+    #[test]
+    fn test_specified_invariants_are_checked() {
+        let source = r#"
+#[invariant(total <= 100)]
+storage {
+    total: u24,
+}
+
+#[message]
+#[ensures(result >= amount)]
+fn add(amount: u24) -> u24 {
+    total = total + amount;
+    return total;
+}
+"#;
+        let mut tester = InvariantTester::from_source(source).unwrap();
+
+        let failure = tester.run().unwrap().failure.unwrap();
+        assert_eq!(failure.invariant, "invariant failed (line 2)");
+        let total: u32 = failure
+            .sequence
+            .iter()
+            .map(|call| match call.args[0] {
+                ArgValue::Word(value) => value,
+                _ => unreachable!(),
+            })
+            .sum();
+        assert_eq!(total, 101);
+    }
+
+    #[test]
+    fn test_contract_invariants_break_through_abi_messages() {
+        let source = r#"
+storage {
+    held: u24,
+    sent: u24,
+    supply: u24,
+    last: Address,
+}
+
+#[message]
+fn mint(amount: u24) -> u24 {
+    require(amount <= 1000);
+    held = held + amount;
+    supply = supply + amount;
+    return supply;
+}
+
+#[message]
+fn transfer(to: Address, amount: u24, memo: Bytes, note: Hash, twice: Bool, delta: i24, fee: u64) -> Bool {
+    require(fee > 0);
+    require(amount <= held);
+    held = held - amount;
+    sent = sent + amount;
+    last = to;
+    if twice {
+        sent = sent + amount;
+    } else {
+        sent = sent;
+    }
+    return twice;
+}
+
+#[invariant]
+fn supply_is_held_and_sent() -> Bool {
+    return held + sent == supply;
+}
+"#;
+        let mut tester = InvariantTester::from_source(source).unwrap();
+        assert_eq!(
+            tester.messages[1].args,
+            [
+                ArgSpec::Address,
+                ArgSpec::Word {
+                    min: 0,
+                    max: 0xFF_FFFF
+                },
+                ArgSpec::Bytes {
+                    max_len: MAX_BYTES_LEN
+                },
+                ArgSpec::Hash,
+                ArgSpec::Bool,
+                ArgSpec::Int {
+                    min: -0x80_0000,
+                    max: 0x7F_FFFF
+                },
+                ArgSpec::Wide { bits: 64 },
+            ]
+        );
+
+        let failure = tester.run().unwrap().failure.unwrap();
+        assert_eq!(failure.invariant, "supply_is_held_and_sent");
+        let names: Vec<&str> = failure
+            .sequence
+            .iter()
+            .map(|call| call.name.as_str())
+            .collect();
+        assert_eq!(names, ["mint", "transfer"]);
+
+        // Every argument the transfer decoded is shrunk to its simplest value
+        let transfer = &failure.sequence[1].args;
+        assert_eq!(transfer[1], ArgValue::Word(1));
+        assert_eq!(transfer[2], ArgValue::Bytes(Vec::new()));
+        assert_eq!(transfer[4], ArgValue::Bool(true));
+        assert_eq!(transfer[5], ArgValue::Int(0));
+        assert_eq!(
+            transfer[6],
+            ArgValue::Wide(WideUint::from_u128(1, 64).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_holding_contract_invariants_pass() {
+        let source = r#"
+storage {
+    total: u24,
+}
+
+#[message]
+fn add(amount: u24) -> u24 {
+    require(amount <= 10);
+    total = total + amount;
+    return total;
+}
+
+#[invariant]
+fn total_is_bounded() -> Bool {
+    return total <= 160;
+}
+"#;
+        let mut tester = InvariantTester::from_source(source).unwrap();
+        assert!(tester.run().unwrap().is_success());
+    }
+
+    #[test]
+    fn test_unsupported_argument_types_are_rejected() {
+        assert_eq!(ArgSpec::for_type("List<u24>"), None);
+        assert_eq!(ArgSpec::for_type("u128"), Some(ArgSpec::Wide { bits: 128 }));
+
+        let mut function = collect_function_metadata(
+            &Parser::new("#[message]\nfn add(amount: u24) -> u24 {\n    return amount;\n}\n")
+                .parse_program()
+                .unwrap(),
+        )
+        .remove("add")
+        .unwrap();
+        function.params[0].type_name = "List<u24>".to_string();
+        assert!(matches!(
+            MessageSpec::from_metadata(&function),
+            Err(TestError::InvalidTestCase(_))
+        ));
+    }
+
+    #[test]
+    fn test_requires_messages() {
+        let mut tester = InvariantTester::new(counter_contract());
+        assert!(matches!(tester.run(), Err(TestError::InvalidTestCase(_))));
+    }
+}
@@ -1,5 +1,10 @@
 use std::collections::HashMap;
 
+use crate::compiler::address::Hash;
+use crate::runtime::env::{random_value, seeded_entropy};
+use crate::stdlib::crypto::CryptoFunctions;
+use crate::testing::TestError;
+
 /// Mock standard library for testing
 pub struct MockStdlib {
     /// Mock responses for external calls
@@ -7,6 +12,12 @@ pub struct MockStdlib {
 
     /// Mock responses for storage gets
     pub storage_responses: HashMap<Vec<u8>, Vec<u8>>,
+
+    /// Overridden crypto results, keyed by function and input
+    pub crypto_responses: HashMap<String, Option<Vec<u8>>>,
+
+    /// Block entropy random values are drawn from
+    pub entropy: Hash,
 }
 
 impl Default for MockStdlib {
@@ -21,6 +32,8 @@ impl MockStdlib {
         MockStdlib {
             call_responses: HashMap::new(),
             storage_responses: HashMap::new(),
+            crypto_responses: HashMap::new(),
+            entropy: Hash::ZERO,
         }
     }
 
@@ -51,31 +64,180 @@ impl MockStdlib {
         format!("{}:{}", address, hex::encode(data))
     }
 
-    /// Mock keccak256 hash
-    pub fn keccak256(&self, _data: &[u8]) -> Vec<u8> {
-        // In a real implementation, this would compute a keccak256 hash
-        // For testing, we'll just return a fixed value
-        vec![0x12, 0x34, 0x56, 0x78]
+    /// Override the result of a crypto function for a given input
+    ///
+    /// `function` is one of `keccak256`, `sha256`, `blake2b_256`, `random`,
+    /// `ecdsa_recover` or `sr25519_verify`. For `ecdsa_recover` the input is
+    /// the hash followed by the signature, and a `None` response makes recovery
+    /// fail. For `sr25519_verify` the input is signature, public key and
+    /// message concatenated, and the response is `[1]` for valid or `[0]` for
+    /// invalid. The hashes and `random` always return a value, so a `None`
+    /// response for them is an error.
+    pub fn mock_crypto(
+        &mut self,
+        function: &str,
+        input: &[u8],
+        response: Option<Vec<u8>>,
+    ) -> Result<(), TestError> {
+        if response.is_none() && !matches!(function, "ecdsa_recover" | "sr25519_verify") {
+            return Err(TestError::Setup(format!(
+                "{} cannot fail, so its mocked result needs a value",
+                function
+            )));
+        }
+        let key = Self::call_key(function, input);
+        self.crypto_responses.insert(key, response);
+        Ok(())
+    }
+
+    /// Get an overridden crypto result
+    fn crypto_response(&self, function: &str, input: &[u8]) -> Option<Option<Vec<u8>>> {
+        let key = Self::call_key(function, input);
+        self.crypto_responses.get(&key).cloned()
+    }
+
+    /// Keccak-256 hash, unless overridden
+    pub fn keccak256(&self, data: &[u8]) -> Vec<u8> {
+        self.crypto_response("keccak256", data)
+            .flatten()
+            .unwrap_or_else(|| CryptoFunctions::keccak256(data).to_vec())
+    }
+
+    /// SHA2-256 hash, unless overridden
+    pub fn sha256(&self, data: &[u8]) -> Vec<u8> {
+        self.crypto_response("sha256", data)
+            .flatten()
+            .unwrap_or_else(|| CryptoFunctions::sha256(data).to_vec())
+    }
+
+    /// BLAKE2b-256 hash, unless overridden
+    pub fn blake2b_256(&self, data: &[u8]) -> Vec<u8> {
+        self.crypto_response("blake2b_256", data)
+            .flatten()
+            .unwrap_or_else(|| CryptoFunctions::blake2b_256(data).to_vec())
+    }
+
+    /// Recover the compressed secp256k1 public key, unless overridden
+    pub fn ecdsa_recover(&self, hash: &[u8], signature: &[u8]) -> Option<Vec<u8>> {
+        let input = [hash, signature].concat();
+        if let Some(response) = self.crypto_response("ecdsa_recover", &input) {
+            return response;
+        }
+
+        let hash: [u8; 32] = hash.try_into().ok()?;
+        let signature: [u8; 65] = signature.try_into().ok()?;
+        CryptoFunctions::ecdsa_recover(&hash, &signature).map(|key| key.to_vec())
+    }
+
+    /// Verify an sr25519 signature, unless overridden
+    pub fn sr25519_verify(&self, signature: &[u8], message: &[u8], public_key: &[u8]) -> bool {
+        let input = [signature, public_key, message].concat();
+        if let Some(response) = self.crypto_response("sr25519_verify", &input) {
+            return response == Some(vec![1]);
+        }
+
+        match (signature.try_into(), public_key.try_into()) {
+            (Ok(signature), Ok(public_key)) => {
+                CryptoFunctions::sr25519_verify(signature, message, public_key)
+            }
+            _ => false,
+        }
+    }
+
+    /// Draw random values from block entropy derived from `seed`, as an
+    /// [`ExecutionContext`](crate::runtime::env::ExecutionContext) seeded
+    /// the same way does
+    pub fn seed_random(&mut self, seed: u64) {
+        self.entropy = seeded_entropy(seed);
+    }
+
+    /// The value `random(subject)` returns, unless overridden
+    pub fn random(&self, subject: &[u8]) -> Vec<u8> {
+        self.crypto_response("random", subject)
+            .flatten()
+            .unwrap_or_else(|| random_value(&self.entropy, subject).as_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashes_default_to_real_implementations() {
+        let mock = MockStdlib::new();
+        assert_eq!(mock.keccak256(b"abc"), CryptoFunctions::keccak256(b"abc"));
+        assert_eq!(mock.sha256(b"abc"), CryptoFunctions::sha256(b"abc"));
+        assert_eq!(
+            mock.blake2b_256(b"abc"),
+            CryptoFunctions::blake2b_256(b"abc")
+        );
     }
 
-    /// Mock sha256 hash
-    pub fn sha256(&self, _data: &[u8]) -> Vec<u8> {
-        // In a real implementation, this would compute a sha256 hash
-        // For testing, we'll just return a fixed value
-        vec![0x87, 0x65, 0x43, 0x21]
+    #[test]
+    fn test_crypto_overrides() {
+        let mut mock = MockStdlib::new();
+        mock.mock_crypto("keccak256", b"abc", Some(vec![1, 2, 3]))
+            .unwrap();
+        assert_eq!(mock.keccak256(b"abc"), vec![1, 2, 3]);
+        assert_eq!(mock.keccak256(b"abd"), CryptoFunctions::keccak256(b"abd"));
+
+        let (hash, signature) = ([1u8; 32], [2u8; 65]);
+        assert_eq!(mock.ecdsa_recover(&hash, &signature), None);
+        mock.mock_crypto(
+            "ecdsa_recover",
+            &[&hash[..], &signature[..]].concat(),
+            Some(vec![0x42; 33]),
+        )
+        .unwrap();
+        assert_eq!(mock.ecdsa_recover(&hash, &signature), Some(vec![0x42; 33]));
+
+        assert!(!mock.sr25519_verify(&[0u8; 64], b"msg", &[0u8; 32]));
+        mock.mock_crypto(
+            "sr25519_verify",
+            &[&[0u8; 64][..], &[0u8; 32][..], b"msg"].concat(),
+            Some(vec![1]),
+        )
+        .unwrap();
+        assert!(mock.sr25519_verify(&[0u8; 64], b"msg", &[0u8; 32]));
     }
 
-    /// Mock ECDSA recovery
-    pub fn ecdsa_recover(&self, _hash: &[u8], _signature: &[u8]) -> Option<Vec<u8>> {
-        // In a real implementation, this would recover a public key from a signature
-        // For testing, we'll just return a fixed value
-        Some(vec![0x42, 0x42, 0x42, 0x42])
+    #[test]
+    fn test_infallible_crypto_cannot_be_mocked_to_fail() {
+        let mut mock = MockStdlib::new();
+        for function in ["keccak256", "sha256", "blake2b_256", "random"] {
+            assert!(
+                matches!(
+                    mock.mock_crypto(function, b"abc", None),
+                    Err(TestError::Setup(_))
+                ),
+                "{}",
+                function
+            );
+        }
+        // A rejected override leaves the real result in place
+        assert_eq!(mock.keccak256(b"abc"), CryptoFunctions::keccak256(b"abc"));
+        assert!(mock.crypto_responses.is_empty());
+
+        // Recovery can fail, and so can its mock
+        mock.mock_crypto("ecdsa_recover", &[0u8; 97], None).unwrap();
     }
 
-    /// Mock random number generation
-    pub fn random(&self, _seed: &[u8]) -> Vec<u8> {
-        // In a real implementation, this would generate a random number
-        // For testing, we'll just return a fixed value
-        vec![0x12, 0x34, 0x56, 0x78]
+    #[test]
+    fn test_seeded_random() {
+        use crate::runtime::env::ExecutionContext;
+
+        let mut mock = MockStdlib::new();
+        let unseeded = mock.random(b"dice");
+        assert_eq!(unseeded.len(), 32);
+        mock.seed_random(42);
+        assert_ne!(mock.random(b"dice"), unseeded);
+
+        let mut context = ExecutionContext::new_default();
+        context.seed_entropy(42);
+        assert_eq!(mock.random(b"dice"), context.random(b"dice").as_bytes());
+
+        mock.mock_crypto("random", b"dice", Some(vec![6])).unwrap();
+        assert_eq!(mock.random(b"dice"), vec![6]);
     }
 }
@@ -4,6 +4,8 @@
 //! including test runners, assertions, and mock environments.
 
 pub mod assertions;
+pub mod differential;
+pub mod invariant;
 pub mod mocklib;
 pub mod runner;
 
@@ -11,9 +13,23 @@ use std::collections::HashMap;
 use std::time::{Duration, Instant};
 use thiserror::Error;
 
-use crate::runtime::env::ExecutionContext;
+use crate::compiler::address::Address;
+use crate::compiler::analyzer::attributes::FunctionAttributes;
+use crate::compiler::cfg::Cfg;
+use crate::compiler::parser::ast::Definition;
+use crate::compiler::pipeline::{CompilerPipeline, Source};
+use crate::diagnostics::Diagnostic;
+use crate::runtime::env::{Event, ExecutionContext};
 use crate::runtime::metering::MeteringContext;
 use crate::runtime::storage::{StorageLimits, StorageManager};
+use crate::testing::differential::{DifferentialReport, DifferentialTester};
+use crate::testing::invariant::{InvariantReport, InvariantTester};
+use crate::testing::runner::TestRunner;
+use crate::{CompileError, CompilerOptions};
+
+/// Account sending every run of a test, so that it is never mistaken for
+/// the zero address contracts use for no account
+pub const TEST_CALLER: Address = Address::new([1; 32]);
 
 /// Test case definition
 #[derive(Debug, Clone)]
@@ -24,6 +40,10 @@ pub struct TestCase {
     /// Source code to test
     pub source: String,
 
+    /// Definitions of the modules the source imports, compiled along with
+    /// it
+    pub imported: Vec<Definition>,
+
     /// Function to test
     pub function: String,
 
@@ -39,6 +59,9 @@ pub struct TestCase {
     /// Initial storage state
     pub initial_storage: HashMap<String, Vec<u8>>,
 
+    /// Initial account balances
+    pub initial_balances: HashMap<[u8; 32], u128>,
+
     /// Gas limit for the test
     pub gas_limit: u64,
 
@@ -48,11 +71,25 @@ pub struct TestCase {
     /// Storage deposit limit
     pub storage_deposit_limit: u128,
 
+    /// Seed of the block entropy `random` draws from
+    pub random_seed: u64,
+
+    /// What the `#[cfg]` predicates of the source are evaluated against
+    pub cfg: Cfg,
+
     /// Timeout in milliseconds
     pub timeout: u64,
 
     /// Whether this test is disabled
     pub disabled: bool,
+
+    /// Whether the test passes only when it reverts or traps, from
+    /// `#[should_revert]`
+    pub should_revert: bool,
+
+    /// Whether the function is an `#[invariant]`, checked after every call
+    /// of random message sequences rather than run once
+    pub invariant: bool,
 }
 
 impl Default for TestCase {
@@ -60,16 +97,22 @@ impl Default for TestCase {
         TestCase {
             name: "unnamed_test".to_string(),
             source: String::new(),
+            imported: Vec::new(),
             function: "test".to_string(),
             arguments: Vec::new(),
             expected_return: None,
             expected_error: None,
             initial_storage: HashMap::new(),
+            initial_balances: HashMap::new(),
             gas_limit: 10_000_000,
             proof_size_limit: 1_000_000,
             storage_deposit_limit: 1_000_000_000,
+            random_seed: 0,
+            cfg: Cfg::new(),
             timeout: 5000,
             disabled: false,
+            should_revert: false,
+            invariant: false,
         }
     }
 }
@@ -83,12 +126,16 @@ pub enum TestResult {
         duration: Duration,
         /// Gas used
         gas_used: u64,
+        /// Net storage deposit change (negative for a net refund)
+        storage_deposit: i128,
     },
 
     /// Test failed
     Failed {
         /// Time taken to run the test
         duration: Duration,
+        /// Gas used until the test failed, 0 if it did not run
+        gas_used: u64,
         /// Error that caused the failure
         error: TestError,
     },
@@ -130,6 +177,10 @@ pub enum TestError {
     /// Invalid test case
     #[error("Invalid test case: {0}")]
     InvalidTestCase(String),
+
+    /// The test source does not parse or type check
+    #[error("{0}")]
+    Diagnostic(Box<Diagnostic>),
 }
 
 /// Test environment
@@ -144,6 +195,15 @@ pub struct TestEnvironment {
     /// Metering context
     pub metering: MeteringContext,
 
+    /// Events emitted by the last run, in emission order
+    pub events: Vec<Event>,
+
+    /// Account balances after the last successful run
+    pub balances: HashMap<[u8; 32], u128>,
+
+    /// Account balances before the first run
+    pub initial_balances: HashMap<[u8; 32], u128>,
+
     /// Test start time
     start_time: Instant,
 }
@@ -152,12 +212,12 @@ impl TestEnvironment {
     /// Create a new test environment
     pub fn new(gas_limit: u64, proof_size_limit: u64, storage_deposit_limit: u128) -> Self {
         let context = ExecutionContext::new(
-            [0u8; 32],  // address
-            [0u8; 32],  // caller
-            0,          // value
-            Vec::new(), // input
-            1,          // block_number
-            1000000,    // block_timestamp
+            Address::ZERO, // address
+            TEST_CALLER,   // caller
+            0,             // value
+            Vec::new(),    // input
+            1,             // block_number
+            1000000,       // block_timestamp
             gas_limit,
             proof_size_limit,
             storage_deposit_limit,
@@ -170,6 +230,9 @@ impl TestEnvironment {
             context,
             storage,
             metering,
+            events: Vec::new(),
+            balances: HashMap::new(),
+            initial_balances: HashMap::new(),
             start_time: Instant::now(),
         }
     }
@@ -183,6 +246,48 @@ impl TestEnvironment {
         }
     }
 
+    /// Set initial account balances
+    pub fn set_initial_balances(&mut self, balances: HashMap<[u8; 32], u128>) {
+        self.balances.extend(balances.iter().map(|(k, v)| (*k, *v)));
+        self.initial_balances.extend(balances);
+    }
+
+    /// Balance of `address` after the last successful run
+    pub fn balance_of(&self, address: &[u8; 32]) -> u128 {
+        self.balances.get(address).copied().unwrap_or(0)
+    }
+
+    /// Change in the balance of `address` since the initial balances
+    pub fn balance_change(&self, address: &[u8; 32]) -> i128 {
+        let initial = self.initial_balances.get(address).copied().unwrap_or(0);
+        self.balance_of(address) as i128 - initial as i128
+    }
+
+    /// Derive the block entropy from `seed`, so `random` returns the same
+    /// values on every run with the same seed
+    pub fn seed_randomness(&mut self, seed: u64) {
+        self.context.seed_entropy(seed);
+    }
+
+    /// Open a storage checkpoint, e.g. before a step of a multi-call test
+    pub fn checkpoint(&mut self) {
+        self.storage.checkpoint();
+    }
+
+    /// Commit the innermost storage checkpoint
+    pub fn commit(&mut self) -> Result<(), TestError> {
+        self.storage
+            .commit()
+            .map_err(|e| TestError::Runtime(e.to_string()))
+    }
+
+    /// Undo every storage write made since the innermost checkpoint
+    pub fn rollback(&mut self) -> Result<(), TestError> {
+        self.storage
+            .rollback()
+            .map_err(|e| TestError::Runtime(e.to_string()))
+    }
+
     /// Get elapsed time
     pub fn elapsed(&self) -> Duration {
         self.start_time.elapsed()
@@ -208,6 +313,50 @@ impl TestSuite {
         }
     }
 
+    /// Create a suite with a test case for every `#[test]` and
+    /// `#[invariant]` function of a source file
+    pub fn from_source(name: &str, source: &str) -> Result<Self, TestError> {
+        Self::from_source_with_cfg(name, source, &Cfg::new())
+    }
+
+    /// Like [`from_source`](Self::from_source), with the features of `cfg`
+    /// enabled. `#[cfg(test)]` always holds.
+    pub fn from_source_with_cfg(name: &str, source: &str, cfg: &Cfg) -> Result<Self, TestError> {
+        let options = CompilerOptions {
+            cfg: cfg.clone().with_test(true),
+            ..CompilerOptions::default()
+        };
+        let program = match CompilerPipeline::new(&options).parse(&Source::new(name, source)) {
+            Ok(ast) => ast.program,
+            Err(CompileError::Diagnostic(diagnostic)) => {
+                return Err(TestError::Diagnostic(diagnostic))
+            }
+            Err(e) => return Err(TestError::Compile(e.to_string())),
+        };
+
+        let mut suite = TestSuite::new(name);
+        for definition in &program.definitions {
+            if let Definition::FunctionDef { name, .. } = definition {
+                let attributes = FunctionAttributes::of(definition).map_err(|e| {
+                    TestError::Diagnostic(Box::new(Diagnostic::from_type_error(&e, source)))
+                })?;
+                if attributes.test || attributes.invariant {
+                    suite.add_test(TestCase {
+                        name: name.to_string(),
+                        source: source.to_string(),
+                        function: name.to_string(),
+                        cfg: options.cfg.clone(),
+                        should_revert: attributes.should_revert,
+                        invariant: attributes.invariant,
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+
+        Ok(suite)
+    }
+
     /// Add a test case
     pub fn add_test(&mut self, test: TestCase) {
         self.tests.push(test);
@@ -225,13 +374,64 @@ impl TestSuite {
             .collect()
     }
 
-    /// Run a single test
-    fn run_test(&self, _test: &TestCase) -> TestResult {
-        // In a real implementation, this would run the test
-        // For now, we just return a passed result
-        TestResult::Passed {
-            duration: Duration::from_millis(1),
-            gas_used: 1000,
+    /// Run all tests on two backends and report any divergence
+    pub fn run_differential(
+        &self,
+        tester: &mut DifferentialTester,
+    ) -> Vec<(String, Result<DifferentialReport, TestError>)> {
+        self.tests
+            .iter()
+            .filter(|t| !t.disabled)
+            .map(|test| (test.name.clone(), tester.run_test_case(test)))
+            .collect()
+    }
+
+    /// Run a single test: compile its source and run its function on the
+    /// interpreter. A test fails when it reverts or traps, unless it
+    /// should revert, when it fails if it returns. An invariant fails when
+    /// a sequence of message calls breaks it.
+    fn run_test(&self, test: &TestCase) -> TestResult {
+        let start = Instant::now();
+        if test.invariant {
+            let outcome = InvariantTester::for_test(test)
+                .and_then(|mut tester| tester.run())
+                .and_then(InvariantReport::into_result);
+            return match outcome {
+                Ok(()) => TestResult::Passed {
+                    duration: start.elapsed(),
+                    gas_used: 0,
+                    storage_deposit: 0,
+                },
+                Err(error) => TestResult::Failed {
+                    duration: start.elapsed(),
+                    gas_used: 0,
+                    error,
+                },
+            };
+        }
+
+        let mut runner = TestRunner::new();
+        let outcome = runner.setup(test).and_then(|()| {
+            match (runner.run_function(&test.function), test.should_revert) {
+                (Ok(()), true) => Err(TestError::AssertionFailed(
+                    "the test returned but should revert".to_string(),
+                )),
+                (Err(TestError::Runtime(_)), true) => Ok(()),
+                (outcome, _) => outcome,
+            }
+        });
+        let context = runner.context();
+        match outcome {
+            Ok(()) => TestResult::Passed {
+                duration: start.elapsed(),
+                gas_used: context.gas_used,
+                storage_deposit: context.net_storage_deposit(),
+            },
+            Err(error) => TestResult::Failed {
+                duration: start.elapsed(),
+                gas_used: context.gas_used,
+                error,
+            },
         }
     }
 }
@@ -278,3 +478,164 @@ macro_rules! test_case {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suite_runs_tests() {
+        let source = r#"
+fn double(x: u24) -> u24 {
+    return x * 2;
+}
+
+#[cfg(test)]
+fn expected() -> u24 {
+    return 6;
+}
+
+#[test]
+fn test_double() -> u24 {
+    assert(double(3) == expected(), "doubled");
+    return 0;
+}
+
+#[test]
+fn test_wrong() -> u24 {
+    assert(double(1) == 3, "never");
+    return 0;
+}
+
+#[test]
+fn test_division_by_zero() -> u24 {
+    zero = double(0);
+    return 5 / zero;
+}
+"#;
+        let results = TestSuite::from_source("math", source).unwrap().run_all();
+        let names: Vec<&str> = results.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["test_double", "test_wrong", "test_division_by_zero"]
+        );
+        match &results[0].1 {
+            TestResult::Passed { gas_used, .. } => assert!(*gas_used > 0),
+            other => panic!("test_double did not pass: {:?}", other),
+        }
+        for (name, result) in &results[1..] {
+            match result {
+                TestResult::Failed {
+                    gas_used, error, ..
+                } => {
+                    assert!(*gas_used > 0, "{}", name);
+                    assert!(matches!(error, TestError::Runtime(_)), "{}", name);
+                }
+                other => panic!("{} did not fail: {:?}", name, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_revert() {
+        let source = r#"
+#[test]
+#[should_revert]
+fn test_reverts() -> u24 {
+    assert(1 == 2, "never");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_returns() -> u24 {
+    return 0;
+}
+"#;
+        let results = TestSuite::from_source("reverts", source).unwrap().run_all();
+        assert!(matches!(results[0].1, TestResult::Passed { .. }));
+        match &results[1].1 {
+            TestResult::Failed { error, .. } => {
+                assert!(matches!(error, TestError::AssertionFailed(_)))
+            }
+            other => panic!("test_returns did not fail: {:?}", other),
+        }
+
+        let error = TestSuite::from_source(
+            "reverts",
+            "#[should_revert]\nfn f() -> u24 {\n    return 0;\n}\n",
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("only test functions"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_suite_checks_invariants() {
+        let source = r#"
+storage {
+    total: u24,
+}
+
+#[message]
+fn add(amount: u24) -> u24 {
+    require(amount <= 1000);
+    total = total + amount;
+    return total;
+}
+
+#[invariant]
+fn total_is_bounded() -> Bool {
+    return total <= 16000;
+}
+
+#[invariant]
+fn total_is_small() -> Bool {
+    return total < 3000;
+}
+"#;
+        let suite = TestSuite::from_source("ledger", source).unwrap();
+        assert!(suite.tests.iter().all(|test| test.invariant));
+        let results = suite.run_all();
+        assert!(matches!(results[0].1, TestResult::Passed { .. }));
+        match &results[1].1 {
+            TestResult::Failed {
+                error: TestError::AssertionFailed(message),
+                ..
+            } => assert!(
+                message.contains("invariant 'total_is_small' broken"),
+                "{}",
+                message
+            ),
+            other => panic!("total_is_small did not fail: {:?}", other),
+        }
+
+        let results = TestSuite::from_source(
+            "ledger",
+            "#[invariant]\nfn total() -> u24 {\n    return 0;\n}\n",
+        )
+        .unwrap()
+        .run_all();
+        match &results[0].1 {
+            TestResult::Failed {
+                error: TestError::Compile(message),
+                ..
+            } => assert!(message.contains("must return Bool"), "{}", message),
+            other => panic!("total did not fail: {:?}", other),
+        }
+
+        let error = TestSuite::from_source(
+            "ledger",
+            "#[invariant]\nfn holds(x: u24) -> Bool {\n    return true;\n}\n",
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("take no parameters"),
+            "{}",
+            error
+        );
+    }
+}
@@ -1,11 +1,10 @@
 use std::time::{Duration, Instant};
 
-use crate::compiler::analyzer::type_checker::TypeChecker;
-use crate::compiler::codegen::risc_v::RiscVCodegen;
-use crate::compiler::optimizer::passes::OptimizationManager;
-use crate::compiler::parser::parser::Parser;
-use crate::compiler::polkavm::bridge::compile_to_polkavm;
+use crate::compiler::address::Address;
+use crate::compiler::codegen::risc_v::Instruction;
 use crate::runtime::env::{Environment, ExecutionContext, ExecutionResult};
+use crate::runtime::interpreter::Interpreter;
+use crate::testing::differential::CompiledContract;
 use crate::testing::{TestCase, TestEnvironment, TestError};
 
 /// Test runner for running test cases
@@ -13,11 +12,15 @@ pub struct TestRunner {
     /// Test environment
     environment: TestEnvironment,
 
-    /// Compiled code
-    code: Vec<u8>,
+    /// Compiled contract
+    contract: Option<CompiledContract>,
 
     /// Test timeout
     timeout: Duration,
+
+    /// Run the linked blob on the embedded PolkaVM engine
+    #[cfg(feature = "polkavm-engine")]
+    polkavm: bool,
 }
 
 impl Default for TestRunner {
@@ -38,16 +41,30 @@ impl TestRunner {
 
         TestRunner {
             environment,
-            code: Vec::new(),
+            contract: None,
             timeout: Duration::from_secs(5),
+            #[cfg(feature = "polkavm-engine")]
+            polkavm: false,
         }
     }
 
+    /// Run tests on the embedded PolkaVM engine instead of the interpreter
+    #[cfg(feature = "polkavm-engine")]
+    pub fn with_polkavm(mut self) -> Self {
+        self.polkavm = true;
+        self
+    }
+
     /// Get a reference to the execution context
     pub fn context(&self) -> &ExecutionContext {
         &self.environment.context
     }
 
+    /// Get a reference to the test environment
+    pub fn environment(&self) -> &TestEnvironment {
+        &self.environment
+    }
+
     /// Set up the test runner
     pub fn setup(&mut self, test_case: &TestCase) -> Result<(), TestError> {
         // Set timeout
@@ -63,82 +80,107 @@ impl TestRunner {
         // Set initial storage
         self.environment
             .set_initial_storage(test_case.initial_storage.clone());
+        self.environment
+            .set_initial_balances(test_case.initial_balances.clone());
+        self.environment.seed_randomness(test_case.random_seed);
 
         // Compile the test code
-        self.compile(&test_case.source)?;
+        self.contract = Some(CompiledContract::for_test(test_case)?);
 
         Ok(())
     }
 
-    /// Compile the test code
-    fn compile(&mut self, source: &str) -> Result<(), TestError> {
-        // Parse the source
-        let mut parser = Parser::new(source);
-        let mut program = parser
-            .parse_program()
-            .map_err(|e| TestError::Compile(e.to_string()))?;
-
-        // Type check the program
-        let mut type_checker = TypeChecker::new();
-        type_checker
-            .check_program(&program)
-            .map_err(|e| TestError::Compile(e.to_string()))?;
-
-        // Optimize the program
-        let mut optimizer = OptimizationManager::new();
-        program = optimizer
-            .optimize(program)
-            .map_err(|e| TestError::Compile(e.to_string()))?;
-
-        // Generate code
-        let mut codegen = RiscVCodegen::new();
-        let instructions = codegen
-            .generate(&program)
-            .map_err(|e| TestError::Compile(e.to_string()))?;
-
-        // Compile to PolkaVM
-        let module = compile_to_polkavm(&instructions, None)
-            .map_err(|e| TestError::Compile(e.to_string()))?;
-
-        // Get the binary
-        if let Some(binary) = module.binary {
-            self.code = binary;
+    /// Run the test
+    pub fn run(&mut self) -> Result<(), TestError> {
+        let start_time = Instant::now();
+        let contract = self.contract()?;
+        let env = self.run_environment();
+
+        #[cfg(feature = "polkavm-engine")]
+        let (result, env) = if self.polkavm {
+            let mut engine = crate::runtime::polkavm::PolkaVmEngine::with_environment(env);
+            let result = engine
+                .execute(&contract.binary)
+                .map_err(|err| TestError::Runtime(err.to_string()))?;
+            (result, engine.into_environment())
         } else {
-            return Err(TestError::Compile("Failed to generate binary".to_string()));
-        }
+            Self::interpret(&contract.instructions, env, None)?
+        };
+        #[cfg(not(feature = "polkavm-engine"))]
+        let (result, env) = Self::interpret(&contract.instructions, env, None)?;
 
-        Ok(())
+        self.finish(start_time, result, env)
     }
 
-    /// Run the test
-    pub fn run(&mut self) -> Result<(), TestError> {
+    /// Run the function `name` of the contract, such as a `#[test]`
+    /// function. Functions are entered on the interpreter, since only
+    /// exports can be entered on PolkaVM.
+    pub fn run_function(&mut self, name: &str) -> Result<(), TestError> {
         let start_time = Instant::now();
+        let contract = self.contract()?;
+        let env = self.run_environment();
+        let (result, env) = Self::interpret(&contract.instructions, env, Some(name))?;
+        self.finish(start_time, result, env)
+    }
 
-        // Create an environment
+    fn contract(&self) -> Result<&CompiledContract, TestError> {
+        self.contract
+            .as_ref()
+            .ok_or_else(|| TestError::Setup("No contract compiled".to_string()))
+    }
+
+    /// An environment holding the storage and balances of the test
+    /// environment
+    fn run_environment(&self) -> Environment {
         let mut env = Environment::new(self.environment.context.clone());
 
         // Import the initial storage
         for (key, value) in self.environment.storage.entries() {
             env.storage.insert(key, value);
         }
+        for (address, balance) in &self.environment.balances {
+            env.set_balance(Address::new(*address), *balance);
+        }
+        env
+    }
 
-        // Run the contract with a timeout
-        let result = match env.execute(&self.code) {
-            Ok(result) => result,
-            Err(err) => return Err(TestError::Runtime(err.to_string())),
-        };
+    /// Take the effects of a run into the test environment if it succeeded
+    fn finish(
+        &mut self,
+        start_time: Instant,
+        result: ExecutionResult,
+        env: Environment,
+    ) -> Result<(), TestError> {
+        // Gas is charged whether or not the run succeeds
+        self.environment.context.gas_used = env.context.gas_used;
 
         // Check for timeout
         if start_time.elapsed() > self.timeout {
             return Err(TestError::Timeout(self.timeout));
         }
 
-        // Check the result
+        // Check the result; reverted and trapped runs leave the test
+        // environment's storage untouched
         match result {
             ExecutionResult::Success { .. } => {
-                // Update the context with gas and storage deposit used
-                self.environment.context.gas_used = env.context.gas_used;
+                // Update the context with the storage deposit used
                 self.environment.context.storage_deposit_used = env.context.storage_deposit_used;
+                self.environment.context.storage_deposit_charged =
+                    env.context.storage_deposit_charged;
+                self.environment.context.storage_deposit_refunded =
+                    env.context.storage_deposit_refunded;
+                self.environment.events = env.events;
+                self.environment.balances = env
+                    .accounts
+                    .iter()
+                    .map(|(address, account)| (*address.as_bytes(), account.balance))
+                    .collect();
+
+                // Persist the committed storage so assertions see the writes
+                self.environment.storage.clear();
+                self.environment
+                    .storage
+                    .import(env.storage.into_iter().collect());
 
                 Ok(())
             }
@@ -148,4 +190,20 @@ impl TestRunner {
             )),
         }
     }
+
+    /// Run the contract on the interpreter, from the start of `function`
+    /// if given
+    fn interpret(
+        instructions: &[Instruction],
+        env: Environment,
+        function: Option<&str>,
+    ) -> Result<(ExecutionResult, Environment), TestError> {
+        let mut interpreter = Interpreter::with_environment(env);
+        let result = match function {
+            Some(name) => interpreter.execute_function(instructions, name),
+            None => interpreter.execute(instructions),
+        }
+        .map_err(|err| TestError::Runtime(err.to_string()))?;
+        Ok((result, interpreter.into_environment()))
+    }
 }
@@ -0,0 +1,413 @@
+use bend_pvm::compiler::analyzer::lints::LintLevels;
+use bend_pvm::compiler::cfg::Cfg;
+use bend_pvm::compiler::codegen::encoder::{disassemble, Isa};
+use bend_pvm::compiler::object::ObjectFile;
+use bend_pvm::compiler::pipeline::Target;
+use bend_pvm::diagnostics::Severity;
+use bend_pvm::testing::{TestResult, TestSuite};
+use bend_pvm::{
+    compile, compile_object, compile_to_artifacts, link, CompileError, CompilerOptions,
+};
+use std::fs;
+use std::path::Path;
+
+const SOURCE: &str = r#"#[message]
+fn add(a: u24, b: u24) -> u24 {
+    return a + b;
+}
+
+#[message]
+fn scale(factor: u24, unused: u24) -> u24 {
+    return add(factor, factor);
+}
+"#;
+
+#[cfg(test)]
+mod compile_api_tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_to_artifacts() {
+        let options = CompilerOptions {
+            assembly: true,
+            debug: true,
+            ..CompilerOptions::default()
+        };
+        let result = compile_to_artifacts("points", SOURCE, &options).unwrap();
+
+        assert!(!result.blob.is_empty());
+        assert!(result
+            .assembly
+            .unwrap()
+            .starts_with("; Assembly for points"));
+        let abi = result.abi.unwrap();
+        assert_eq!(abi.name, "points");
+        assert!(abi.methods.iter().any(|method| method.name == "scale"));
+        assert_eq!(result.metadata.unwrap().name, "points");
+
+        let debug_info = result.debug_info.unwrap();
+        let add = &debug_info.functions["add"];
+        let scale = &debug_info.functions["scale"];
+        assert_eq!((add.start_line, add.end_line), (2, 4));
+        assert_eq!((scale.start_line, scale.end_line), (7, 9));
+        assert!(add.end <= scale.start || scale.end <= add.start);
+        assert_eq!(debug_info.instruction_to_line[&scale.start], 7);
+
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(result.diagnostics[0].code, "W0102");
+        let gas_report = result.gas_report.unwrap();
+        assert_eq!(gas_report.estimates.len(), 2);
+        assert!(result
+            .timings
+            .top_level()
+            .any(|phase| phase.name == "codegen"));
+
+        let minimal = CompilerOptions {
+            abi: false,
+            metadata: false,
+            ..CompilerOptions::default()
+        };
+        let result = compile_to_artifacts("points", SOURCE, &minimal).unwrap();
+        assert!(result.abi.is_none() && result.metadata.is_none());
+        assert!(result.assembly.is_none() && result.debug_info.is_none());
+    }
+
+    #[test]
+    fn test_denied_warnings_carry_diagnostics() {
+        let mut lint_levels = LintLevels::default();
+        lint_levels.deny_warnings();
+        let options = CompilerOptions {
+            lint_levels,
+            ..CompilerOptions::default()
+        };
+        match compile_to_artifacts("points", SOURCE, &options) {
+            Err(CompileError::DeniedWarnings(diagnostics)) => {
+                assert_eq!(diagnostics.len(), 1);
+                assert_eq!(diagnostics[0].severity, Severity::Error);
+            }
+            other => panic!("expected denied warnings, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_compile_writes_artifacts() {
+        let dir = std::env::temp_dir().join(format!("bend_compile_api_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("points.bend");
+        fs::write(&source, SOURCE).unwrap();
+
+        let output = compile(&source, CompilerOptions::default()).unwrap();
+        assert_eq!(output.binary, dir.join("points.bin"));
+        assert_eq!(fs::read(&output.binary).unwrap(), output.result.blob);
+        assert_eq!(output.abi, Some(dir.join("points.abi.json")));
+        let abi = bend_pvm::compiler::polkavm::abi::parse_abi(
+            &fs::read_to_string(dir.join("points.abi.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(abi.name, "points");
+        assert!(dir.join("points.metadata.json").exists());
+        assert_eq!(output.assembly, None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_deterministic_builds() {
+        let source = (0..16)
+            .map(|i| format!("fn f{0}(a: u24) -> u24 {{\n    return a + {0};\n}}\n", i))
+            .collect::<String>();
+        let build = |run: &str| {
+            let dir = std::env::temp_dir().join(format!(
+                "bend_deterministic_{}_{}",
+                std::process::id(),
+                run
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("points.bend");
+            fs::write(&path, &source).unwrap();
+            let options = CompilerOptions {
+                assembly: true,
+                debug: true,
+                deterministic: true,
+                ..CompilerOptions::default()
+            };
+            let output = compile(&path, options).unwrap();
+            let files = [
+                Some(output.binary),
+                output.assembly,
+                output.abi,
+                output.metadata,
+            ]
+            .map(|path| fs::read(path.unwrap()).unwrap());
+            let source_path = output.result.debug_info.unwrap().source_path;
+            let _ = fs::remove_dir_all(&dir);
+            (files, source_path)
+        };
+
+        let (first, source_path) = build("first");
+        let (second, _) = build("second");
+        assert_eq!(first, second);
+        assert_eq!(source_path, Path::new("points.bend"));
+    }
+
+    #[test]
+    fn test_remap_path_prefix() {
+        let options = CompilerOptions {
+            remap_path_prefix: vec![("/home/dev/src".into(), "/src".into())],
+            ..CompilerOptions::default()
+        };
+        let remap = |path: &str| options.remap_path(Path::new(path));
+        assert_eq!(
+            remap("/home/dev/src/token.bend"),
+            Path::new("/src/token.bend")
+        );
+        assert_eq!(remap("/tmp/token.bend"), Path::new("/tmp/token.bend"));
+
+        let options = CompilerOptions {
+            deterministic: true,
+            ..options
+        };
+        let remap = |path: &str| options.remap_path(Path::new(path));
+        assert_eq!(
+            remap("/home/dev/src/token.bend"),
+            Path::new("/src/token.bend")
+        );
+        assert_eq!(remap("/tmp/token.bend"), Path::new("token.bend"));
+        assert_eq!(
+            remap("contracts/token.bend"),
+            Path::new("contracts/token.bend")
+        );
+    }
+
+    #[test]
+    fn test_cfg_features() {
+        let source = r#"#[cfg(feature = "testnet")]
+#[message]
+fn faucet(amount: u24) -> u24 {
+    return amount;
+}
+
+#[cfg(test)]
+fn helper() -> u24 {
+    return undefined_in_builds(1);
+}
+
+#[message]
+fn total(a: u24) -> u24 {
+    return a;
+}
+"#;
+        let result = compile_to_artifacts("faucet", source, &CompilerOptions::default()).unwrap();
+        let metadata = result.metadata.unwrap();
+        assert!(metadata.features.is_empty());
+        assert!(!metadata.functions.contains_key("faucet"));
+        assert!(!metadata.functions.contains_key("helper"));
+
+        let options = CompilerOptions {
+            cfg: Cfg::new().with_features(["testnet"]),
+            ..CompilerOptions::default()
+        };
+        let result = compile_to_artifacts("faucet", source, &options).unwrap();
+        let metadata = result.metadata.unwrap();
+        assert_eq!(metadata.features, ["testnet"]);
+        assert!(metadata.functions.contains_key("faucet"));
+
+        let options = CompilerOptions {
+            cfg: Cfg::new().with_test(true),
+            ..CompilerOptions::default()
+        };
+        assert!(matches!(
+            compile_to_artifacts("faucet", source, &options),
+            Err(CompileError::Diagnostic(_))
+        ));
+    }
+
+    #[test]
+    fn test_comprehensions_compile_and_run() {
+        let source = r#"
+fn squares(n: u24) -> u24 {
+    table = {x: x * x for x in range(n) if x % 2 == 0};
+    return table[4] + table[3];
+}
+
+#[test]
+fn test_squares() -> u24 {
+    assert(squares(6) == 16, "even squares only");
+    return 0;
+}
+"#;
+        let result = compile_to_artifacts("squares", source, &CompilerOptions::default()).unwrap();
+        assert!(!result.blob.is_empty());
+        let results = TestSuite::from_source("squares", source).unwrap().run_all();
+        assert!(
+            matches!(results[..], [(_, TestResult::Passed { .. })]),
+            "{:?}",
+            results
+        );
+
+        // List comprehensions build lists, over ranges and over lists
+        let source = r#"
+# The elements of `xs` as decimal digits, the first one lowest
+fn digits(xs: List<u24>) -> u24 {
+    match xs {
+        List/Nil => {
+            return 0;
+        }
+        List/Cons(head, tail) => {
+            return head + 10 * digits(tail);
+        }
+    }
+}
+
+fn evens(n: u24) -> List<u24> {
+    return [x * 2 for x in range(n)];
+}
+
+fn above(xs: List<u24>, low: u24) -> List<u24> {
+    ys = [x + 1 for x in xs if x > low];
+    return ys;
+}
+
+#[test]
+fn test_lists() -> u24 {
+    x = 9;
+    assert(digits(evens(4)) == 6420, "evens");
+    assert(digits(above(List/range(0, 6), 2)) == 654, "filtered");
+    odd = [y * 3 for y in [x for x in List/range(0, 6) if x % 2 == 1]];
+    assert(digits(odd) == 1593, "nested");
+    assert(x == 9, "the variable is local to the comprehension");
+    return 0;
+}
+"#;
+        let result = compile_to_artifacts("lists", source, &CompilerOptions::default()).unwrap();
+        assert!(!result.blob.is_empty());
+        let results = TestSuite::from_source("lists", source).unwrap().run_all();
+        assert!(
+            matches!(results[..], [(_, TestResult::Passed { .. })]),
+            "{:?}",
+            results
+        );
+    }
+
+    #[test]
+    fn test_wasm_target() {
+        let options = CompilerOptions {
+            assembly: true,
+            target: Target::Wasm32,
+            ..CompilerOptions::default()
+        };
+        let result = compile_to_artifacts("points", SOURCE, &options).unwrap();
+
+        assert!(result.blob.starts_with(b"\0asm"));
+        assert!(result.assembly.unwrap().starts_with("(module"));
+        assert!(result.metadata.unwrap().functions.contains_key("add"));
+        assert!(result.debug_info.is_none());
+
+        let source = "storage {\n    balances: StorageMap<u24, u24>,\n}\n";
+        let Err(CompileError::Diagnostic(diagnostic)) =
+            compile_to_artifacts("balances", source, &options)
+        else {
+            panic!("expected an unsupported feature diagnostic");
+        };
+        assert_eq!(diagnostic.code, "E0301");
+        assert_eq!(&source[diagnostic.span().unwrap()], "balances");
+    }
+
+    #[test]
+    fn test_evm_target() {
+        let options = CompilerOptions {
+            assembly: true,
+            target: Target::Evm,
+            ..CompilerOptions::default()
+        };
+        let result = compile_to_artifacts("points", SOURCE, &options).unwrap();
+
+        assert!(!result.blob.is_empty());
+        assert!(result.assembly.unwrap().starts_with("object \"points\" {"));
+        assert!(result.metadata.unwrap().functions.contains_key("add"));
+
+        let source = "fn hash(data: Bytes) -> u24 {\n    return 0;\n}\n";
+        let Err(CompileError::Diagnostic(diagnostic)) =
+            compile_to_artifacts("hash", source, &options)
+        else {
+            panic!("expected an unsupported feature diagnostic");
+        };
+        assert_eq!(diagnostic.code, "E0301");
+        assert_eq!(&source[diagnostic.span().unwrap()], "data");
+    }
+
+    #[test]
+    fn test_rv64_target() {
+        let result = compile_to_artifacts("points", SOURCE, &CompilerOptions::default()).unwrap();
+        assert_eq!(result.metadata.unwrap().isa, Some(Isa::Rv32Em));
+
+        let options = CompilerOptions {
+            assembly: true,
+            isa: Isa::Rv64Im,
+            ..CompilerOptions::default()
+        };
+        let result = compile_to_artifacts("points", SOURCE, &options).unwrap();
+
+        assert!(!disassemble(&result.blob).unwrap().is_empty());
+        assert!(result.assembly.unwrap().contains("AddW"));
+        assert_eq!(result.metadata.unwrap().isa, Some(Isa::Rv64Im));
+
+        let options = CompilerOptions {
+            target: Target::Wasm32,
+            ..CompilerOptions::default()
+        };
+        let result = compile_to_artifacts("points", SOURCE, &options).unwrap();
+        assert_eq!(result.metadata.unwrap().isa, None);
+    }
+
+    #[test]
+    fn test_separate_compilation() {
+        let dir = std::env::temp_dir().join(format!("bend_objects_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("mathlib.bend"),
+            "pub fn double(x: u24) -> u24 {\n    return x * 2;\n}\n\npub fn unused(x: u24) -> u24 {\n    return x;\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("app.bend"),
+            "from mathlib import double;\n\nfn main() -> u24 {\n    return double(21);\n}\n",
+        )
+        .unwrap();
+
+        let options = CompilerOptions::default();
+        let library = compile_object(&dir.join("mathlib.bend"), &options).unwrap();
+        assert_eq!(library.undefined().count(), 0);
+        assert!(library.interface.iter().all(|definition| matches!(
+            definition,
+            bend_pvm::compiler::parser::ast::Definition::FunctionDef { body, attributes, .. }
+                if body.statements.is_empty() && attributes.iter().any(|a| a.name == "extern")
+        )));
+        library.write(&dir.join("mathlib.o")).unwrap();
+
+        // Without its source, the module is imported from its object
+        fs::remove_file(dir.join("mathlib.bend")).unwrap();
+        let app = compile_object(&dir.join("app.bend"), &options).unwrap();
+        assert_eq!(
+            app.undefined()
+                .map(|symbol| symbol.name.as_str())
+                .collect::<Vec<_>>(),
+            ["function.double"]
+        );
+        app.write(&dir.join("app.o")).unwrap();
+        assert_eq!(ObjectFile::read(&dir.join("app.o")).unwrap().module, "app");
+
+        let objects = [dir.join("app.o"), dir.join("mathlib.o")];
+        let binary = link(&objects, false).unwrap();
+        assert!(binary.len() < link(&objects, true).unwrap().len());
+        assert!(matches!(
+            link(&objects[..1], false),
+            Err(CompileError::Link(message)) if message.contains("function.double")
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,433 @@
+//! Conformance tests comparing the RISC-V and WebAssembly backends
+//!
+//! Every contract is compiled with a dispatcher for both targets and each
+//! call runs on the RISC-V interpreter and the WebAssembly interpreter. The
+//! backends must agree on status, return data and storage.
+
+use bend_pvm::compiler::codegen::metadata::{compute_storage_key, selector_for};
+use bend_pvm::compiler::lowering::REENTRANCY_LOCK;
+use bend_pvm::runtime::env::{Environment, ExecutionContext};
+use bend_pvm::runtime::interpreter::Interpreter;
+use bend_pvm::runtime::wasm::WasmInterpreter;
+use bend_pvm::testing::differential::{
+    BackendOutcome, CompiledContract, DifferentialTester, ExecutionBackend, InterpreterBackend,
+    OutcomeStatus, StorageMap,
+};
+
+const ARITHMETIC: &str = r#"
+#[message]
+fn add(a: u24, b: u24) -> u24 {
+    return a + b;
+}
+
+#[message]
+fn sub(a: u24, b: u24) -> u24 {
+    return a - b;
+}
+
+#[message]
+fn mul(a: u24, b: u24) -> u24 {
+    return a * b;
+}
+
+#[message]
+fn div(a: u24, b: u24) -> u24 {
+    return a / b;
+}
+
+#[message]
+fn rem(a: u24, b: u24) -> u24 {
+    return a % b;
+}
+
+#[message]
+fn neg(a: i24, b: i24) -> i24 {
+    return a - b;
+}
+
+#[message]
+fn wrapping(a: u24, b: u24) -> u24 {
+    unchecked: {
+        result = a - b;
+    }
+    return result;
+}
+
+#[message]
+unchecked fn signed_wrapping(a: i24, b: i24) -> i24 {
+    return a + b;
+}
+"#;
+
+const CONTROL_FLOW: &str = r#"
+#[message]
+fn factorial(n: u24) -> u24 {
+    result = 1;
+    while n > 1 {
+        result = result * n;
+        n = n - 1;
+    }
+    return result;
+}
+
+#[message]
+fn sum(start: u24, end: u24) -> u24 {
+    total = 0;
+    for i in range(start, end) bound 4 {
+        total = total + i;
+    }
+    return total;
+}
+
+#[message]
+fn max(a: i24, b: i24) -> i24 {
+    if a > b {
+        return a;
+    } else {
+        return b;
+    }
+}
+
+#[message]
+fn fib(n: u24) -> u24 {
+    if n < 2 {
+        return n;
+    } else {
+        return fib(n - 1) + fib(n - 2);
+    }
+}
+
+#[message]
+fn between(x: u24, low: u24, high: u24) -> bool {
+    return x >= low && x <= high;
+}
+
+#[message]
+fn outside(x: u24, low: u24, high: u24) -> bool {
+    return x < low || x > high;
+}
+"#;
+
+const COUNTER: &str = r#"
+storage {
+    count: u24,
+}
+
+#[message]
+fn increment(by: u24) -> u24 {
+    count = count + by;
+    return count;
+}
+
+#[message]
+fn limited(by: u24) -> u24 {
+    count = count + by;
+    require(count < 10, "count too large");
+    return count;
+}
+
+#[message]
+fn guarded(by: u24) -> u24 {
+    assert(by != 0);
+    return by;
+}
+
+#[message]
+#[payable]
+fn deposit(amount: u24) -> u24 {
+    count = amount;
+    return amount;
+}
+"#;
+
+const LOCKED: &str = r#"
+storage {
+    balance: u24,
+}
+
+#[message]
+#[non_reentrant]
+fn deposit(amount: u24) -> u24 {
+    balance = balance + amount;
+    return balance;
+}
+"#;
+
+const EVENTS: &str = r#"
+event Transfer {
+    indexed sender: u24,
+    indexed receiver: u24,
+    amount: u24,
+}
+
+#[message]
+fn transfer(sender: u24, receiver: u24, amount: u24) -> u24 {
+    emit Transfer(sender, receiver, amount);
+    return amount;
+}
+"#;
+
+fn call_data(signature: &str, args: &[u32]) -> Vec<u8> {
+    let mut input = selector_for(signature).to_vec();
+    for arg in args {
+        input.extend_from_slice(&arg.to_le_bytes());
+    }
+    input
+}
+
+fn compile(source: &str) -> CompiledContract {
+    let contract = CompiledContract::with_dispatcher(source).unwrap();
+    assert!(
+        contract.wasm.is_some(),
+        "contract should compile to WebAssembly"
+    );
+    contract
+}
+
+/// Run a call on every backend, returning the reference outcome
+fn call_with(
+    contract: &CompiledContract,
+    input: Vec<u8>,
+    value: u128,
+    storage: &StorageMap,
+) -> BackendOutcome {
+    let mut context = ExecutionContext::new_default();
+    context.input = input;
+    context.value = value;
+
+    DifferentialTester::interpreter_vs_wasm()
+        .run(contract, &context, storage)
+        .unwrap()
+        .into_result()
+        .unwrap();
+    #[cfg(feature = "polkavm-engine")]
+    DifferentialTester::interpreter_vs_polkavm()
+        .unwrap()
+        .run(contract, &context, storage)
+        .unwrap()
+        .into_result()
+        .unwrap();
+    InterpreterBackend
+        .execute(contract, &context, storage)
+        .unwrap()
+}
+
+fn call(contract: &CompiledContract, signature: &str, args: &[u32]) -> BackendOutcome {
+    call_with(contract, call_data(signature, args), 0, &StorageMap::new())
+}
+
+fn returns(outcome: &BackendOutcome, value: u32) {
+    assert_eq!(outcome.status, OutcomeStatus::Success);
+    assert_eq!(outcome.return_data, value.to_le_bytes().to_vec());
+}
+
+fn reverts(outcome: &BackendOutcome) {
+    assert_eq!(outcome.status, OutcomeStatus::Revert);
+}
+
+#[cfg(test)]
+mod conformance_tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let contract = compile(ARITHMETIC);
+
+        returns(&call(&contract, "add(u24,u24)", &[2, 3]), 5);
+        returns(&call(&contract, "sub(u24,u24)", &[7, 3]), 4);
+        returns(&call(&contract, "mul(u24,u24)", &[6, 7]), 42);
+        returns(&call(&contract, "div(u24,u24)", &[42, 5]), 8);
+        returns(&call(&contract, "rem(u24,u24)", &[42, 5]), 2);
+
+        reverts(&call(&contract, "sub(u24,u24)", &[3, 7]));
+        reverts(&call(&contract, "add(u24,u24)", &[0xFF_FFFF, 1]));
+        reverts(&call(&contract, "mul(u24,u24)", &[4096, 4096]));
+        reverts(&call(&contract, "div(u24,u24)", &[1, 0]));
+        reverts(&call(&contract, "rem(u24,u24)", &[1, 0]));
+    }
+
+    #[test]
+    fn test_signed_and_unchecked_arithmetic() {
+        let contract = compile(ARITHMETIC);
+
+        returns(&call(&contract, "neg(i24,i24)", &[3, 5]), (-2i32) as u32);
+        returns(
+            &call(&contract, "neg(i24,i24)", &[(-4i32) as u32, (-6i32) as u32]),
+            2,
+        );
+        reverts(&call(
+            &contract,
+            "neg(i24,i24)",
+            &[(-0x80_0000i32) as u32, 1],
+        ));
+        returns(&call(&contract, "wrapping(u24,u24)", &[1, 2]), 0xFF_FFFF);
+        returns(
+            &call(&contract, "signed_wrapping(i24,i24)", &[0x7F_FFFF, 1]),
+            (-0x80_0000i32) as u32,
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let contract = compile(CONTROL_FLOW);
+
+        returns(&call(&contract, "factorial(u24)", &[5]), 120);
+        returns(&call(&contract, "factorial(u24)", &[0]), 1);
+        returns(&call(&contract, "sum(u24,u24)", &[2, 5]), 9);
+        returns(&call(&contract, "max(i24,i24)", &[(-3i32) as u32, 2]), 2);
+        returns(
+            &call(&contract, "max(i24,i24)", &[(-3i32) as u32, (-7i32) as u32]),
+            (-3i32) as u32,
+        );
+        returns(&call(&contract, "fib(u24)", &[10]), 55);
+    }
+
+    #[test]
+    fn test_boolean_results() {
+        let contract = compile(CONTROL_FLOW);
+
+        let outcome = call(&contract, "between(u24,u24,u24)", &[5, 1, 10]);
+        assert_eq!(outcome.return_data, vec![1]);
+        let outcome = call(&contract, "between(u24,u24,u24)", &[11, 1, 10]);
+        assert_eq!(outcome.return_data, vec![0]);
+        let outcome = call(&contract, "outside(u24,u24,u24)", &[0, 1, 10]);
+        assert_eq!(outcome.return_data, vec![1]);
+    }
+
+    #[test]
+    fn test_loop_bound_exceeded() {
+        let contract = compile(CONTROL_FLOW);
+
+        let outcome = call(&contract, "sum(u24,u24)", &[0, 10]);
+        reverts(&outcome);
+        assert!(!outcome.return_data.is_empty());
+    }
+
+    #[test]
+    fn test_storage_persists_between_calls() {
+        let contract = compile(COUNTER);
+
+        let first = call_with(
+            &contract,
+            call_data("increment(u24)", &[3]),
+            0,
+            &StorageMap::new(),
+        );
+        returns(&first, 3);
+        let second = call_with(
+            &contract,
+            call_data("increment(u24)", &[4]),
+            0,
+            &first.storage,
+        );
+        returns(&second, 7);
+    }
+
+    #[test]
+    fn test_reverts_roll_back_storage() {
+        let contract = compile(COUNTER);
+
+        let first = call_with(
+            &contract,
+            call_data("limited(u24)", &[5]),
+            0,
+            &StorageMap::new(),
+        );
+        returns(&first, 5);
+        let second = call_with(
+            &contract,
+            call_data("limited(u24)", &[5]),
+            0,
+            &first.storage,
+        );
+        reverts(&second);
+        assert!(!second.return_data.is_empty());
+        assert_eq!(second.storage, first.storage);
+
+        reverts(&call(&contract, "guarded(u24)", &[0]));
+        returns(&call(&contract, "guarded(u24)", &[1]), 1);
+    }
+
+    #[test]
+    fn test_non_reentrant_lock() {
+        let contract = compile(LOCKED);
+        let lock = compute_storage_key(REENTRANCY_LOCK).to_vec();
+
+        let first = call(&contract, "deposit(u24)", &[4]);
+        returns(&first, 4);
+        // Released before returning
+        assert_eq!(first.storage[&lock], 0u32.to_le_bytes());
+        // A call made while the lock is held, as a reentrant one is, reverts
+        let mut held = first.storage.clone();
+        held.insert(lock, 1u32.to_le_bytes().to_vec());
+        reverts(&call_with(
+            &contract,
+            call_data("deposit(u24)", &[4]),
+            0,
+            &held,
+        ));
+        returns(
+            &call_with(
+                &contract,
+                call_data("deposit(u24)", &[4]),
+                0,
+                &first.storage,
+            ),
+            8,
+        );
+    }
+
+    #[test]
+    fn test_payable_messages() {
+        let contract = compile(COUNTER);
+        let storage = StorageMap::new();
+
+        returns(
+            &call_with(&contract, call_data("deposit(u24)", &[9]), 100, &storage),
+            9,
+        );
+        reverts(&call_with(
+            &contract,
+            call_data("increment(u24)", &[1]),
+            100,
+            &storage,
+        ));
+    }
+
+    #[test]
+    fn test_malformed_calls_revert() {
+        let contract = compile(COUNTER);
+        let storage = StorageMap::new();
+
+        reverts(&call_with(&contract, vec![1, 2], 0, &storage));
+        reverts(&call_with(
+            &contract,
+            call_data("missing(u24)", &[1]),
+            0,
+            &storage,
+        ));
+        reverts(&call_with(
+            &contract,
+            call_data("increment(u24)", &[1, 2]),
+            0,
+            &storage,
+        ));
+    }
+
+    #[test]
+    fn test_events_match() {
+        let contract = compile(EVENTS);
+        let mut context = ExecutionContext::new_default();
+        context.input = call_data("transfer(u24,u24,u24)", &[1, 2, 500]);
+
+        let mut interpreter = Interpreter::new(context.clone());
+        interpreter.execute(&contract.instructions).unwrap();
+        let mut wasm = WasmInterpreter::with_environment(Environment::new(context));
+        wasm.execute(contract.wasm.as_ref().unwrap()).unwrap();
+
+        let expected = &interpreter.environment().events;
+        assert_eq!(expected.len(), 1);
+        assert_eq!(expected[0].topics.len(), 3);
+        assert_eq!(&wasm.environment().events, expected);
+    }
+}
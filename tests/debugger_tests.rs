@@ -327,3 +327,57 @@ mod expression_evaluator_tests {
         assert!(result.is_err());
     }
 }
+
+#[cfg(test)]
+mod disassembler_tests {
+    use super::*;
+    use bend_pvm::compiler::codegen::risc_v::{Instruction, Register};
+    use bend_pvm::debugger::disassembler::Disassembler;
+
+    fn debug_info() -> DebugInfo {
+        DebugInfo {
+            source_path: PathBuf::from("test.bend"),
+            source_code: "fn main() -> u24 {\n    return 0x12345;\n}".to_string(),
+            line_to_instruction: HashMap::from([(2, vec![1, 2])]),
+            instruction_to_line: HashMap::from([(1, 2), (2, 2)]),
+            functions: HashMap::new(),
+            locals: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_disassembly_uses_machine_code_addresses() {
+        let disassembler = Disassembler::new(
+            debug_info(),
+            vec![
+                Instruction::Label("main".to_string()),
+                Instruction::Li(Register::X10, 0x12345),
+                Instruction::JumpAndLinkReg(Register::X0, Register::X1, 0),
+            ],
+        );
+
+        let lines = disassembler.disassemble_range(0, 3);
+        assert_eq!(
+            lines.iter().map(|line| line.address).collect::<Vec<_>>(),
+            [0, 0, 8]
+        );
+        assert!(lines[0].bytes.is_empty());
+        assert_eq!(lines[2].bytes, [0x67, 0x80, 0x00, 0x00]);
+        assert_eq!(
+            lines[2].to_string_with_source(),
+            "00000008: 67800000              jalr zero, ra, 0 // line 2"
+        );
+    }
+
+    #[test]
+    fn test_disassembly_without_machine_code() {
+        let disassembler = Disassembler::new(
+            debug_info(),
+            vec![Instruction::Jump("missing".to_string()), Instruction::Ecall],
+        );
+
+        let line = disassembler.disassemble_instruction(1).unwrap();
+        assert_eq!(line.address, 4);
+        assert!(line.bytes.is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+//! Tests of contracts compiled with `--target evm`
+//!
+//! Every contract is deployed on the EVM interpreter and called with
+//! Solidity ABI encoded call data, the way Ethereum tooling calls it.
+
+use bend_pvm::compiler::codegen::metadata::selector_for;
+use bend_pvm::compiler::pipeline::Target;
+use bend_pvm::runtime::env::{ExecutionContext, ExecutionResult};
+use bend_pvm::runtime::evm::EvmInterpreter;
+use bend_pvm::stdlib::crypto::CryptoFunctions;
+use bend_pvm::{compile_to_artifacts, CompilerOptions};
+
+const ARITHMETIC: &str = r#"
+#[message]
+fn add(a: u24, b: u24) -> u24 {
+    return a + b;
+}
+
+#[message]
+fn sub(a: u24, b: u24) -> u24 {
+    return a - b;
+}
+
+#[message]
+fn div(a: u24, b: u24) -> u24 {
+    return a / b;
+}
+
+#[message]
+fn neg(a: i24, b: i24) -> i24 {
+    return a - b;
+}
+
+#[message]
+fn wrapping(a: u24, b: u24) -> u24 {
+    unchecked: {
+        result = a - b;
+    }
+    return result;
+}
+
+#[message]
+fn factorial(n: u24) -> u24 {
+    result = 1;
+    while n > 1 {
+        result = result * n;
+        n = n - 1;
+    }
+    return result;
+}
+
+#[message]
+fn sum(start: u24, end: u24) -> u24 {
+    total = 0;
+    for i in range(start, end) bound 4 {
+        total = total + i;
+    }
+    return total;
+}
+
+#[message]
+fn fib(n: u24) -> u24 {
+    if n < 2 {
+        return n;
+    } else {
+        return fib(n - 1) + fib(n - 2);
+    }
+}
+
+#[message]
+fn between(x: u24, low: u24, high: u24) -> bool {
+    return x >= low && x <= high;
+}
+"#;
+
+const COUNTER: &str = r#"
+storage {
+    count: u24,
+}
+
+event Transfer {
+    indexed sender: u24,
+    indexed receiver: u24,
+    amount: u24,
+}
+
+#[message]
+fn increment(by: u24) -> u24 {
+    count = count + by;
+    return count;
+}
+
+#[message]
+fn limited(by: u24) -> u24 {
+    count = count + by;
+    require(count < 10, "count too large");
+    return count;
+}
+
+#[message]
+fn guarded(by: u24) -> u24 {
+    assert(by != 0);
+    return by;
+}
+
+#[message]
+#[payable]
+fn deposit(amount: u24) -> u24 {
+    count = amount;
+    return amount;
+}
+
+#[message]
+fn transfer(sender: u24, receiver: u24, amount: u24) -> u24 {
+    emit Transfer(sender, receiver, amount);
+    return amount;
+}
+"#;
+
+/// A deployed contract and the interpreter holding its storage
+struct Deployed {
+    code: Vec<u8>,
+    interpreter: EvmInterpreter,
+}
+
+fn deploy(source: &str) -> Deployed {
+    let options = CompilerOptions {
+        target: Target::Evm,
+        ..CompilerOptions::default()
+    };
+    let init_code = compile_to_artifacts("contract", source, &options)
+        .unwrap()
+        .blob;
+    let mut interpreter = EvmInterpreter::new(ExecutionContext::new_default());
+    let code = interpreter.deploy(&init_code).unwrap();
+    Deployed { code, interpreter }
+}
+
+fn word(value: i64) -> [u8; 32] {
+    let mut word = if value < 0 { [0xFF; 32] } else { [0; 32] };
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+fn call_data(signature: &str, args: &[i64]) -> Vec<u8> {
+    let mut input = selector_for(signature).to_vec();
+    for arg in args {
+        input.extend_from_slice(&word(*arg));
+    }
+    input
+}
+
+impl Deployed {
+    fn call_with(&mut self, input: Vec<u8>, value: u128) -> ExecutionResult {
+        let context = &mut self.interpreter.environment_mut().context;
+        context.input = input;
+        context.value = value;
+        context.gas_used = 0;
+        self.interpreter.execute(&self.code).unwrap()
+    }
+
+    fn call(&mut self, signature: &str, args: &[i64]) -> ExecutionResult {
+        self.call_with(call_data(signature, args), 0)
+    }
+}
+
+fn returns(result: &ExecutionResult, value: i64) {
+    match result {
+        ExecutionResult::Success { data, .. } => assert_eq!(data, &word(value)),
+        _ => panic!("expected success, got {:?}", result),
+    }
+}
+
+fn revert_data(result: &ExecutionResult) -> &[u8] {
+    match result {
+        ExecutionResult::Revert { data, .. } => data,
+        _ => panic!("expected a revert, got {:?}", result),
+    }
+}
+
+fn panics(result: &ExecutionResult, code: i64) {
+    let mut expected = selector_for("Panic(uint256)").to_vec();
+    expected.extend_from_slice(&word(code));
+    assert_eq!(revert_data(result), expected);
+}
+
+#[cfg(test)]
+mod evm_tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let mut contract = deploy(ARITHMETIC);
+
+        returns(&contract.call("add(uint24,uint24)", &[2, 3]), 5);
+        returns(&contract.call("sub(uint24,uint24)", &[7, 3]), 4);
+        returns(&contract.call("div(uint24,uint24)", &[42, 5]), 8);
+        returns(&contract.call("neg(int24,int24)", &[3, 5]), -2);
+
+        panics(&contract.call("sub(uint24,uint24)", &[3, 7]), 0x11);
+        panics(&contract.call("add(uint24,uint24)", &[0xFF_FFFF, 1]), 0x11);
+        panics(&contract.call("neg(int24,int24)", &[-0x80_0000, 1]), 0x11);
+        panics(&contract.call("div(uint24,uint24)", &[1, 0]), 0x12);
+        returns(
+            &contract.call("wrapping(uint24,uint24)", &[1, 2]),
+            0xFF_FFFF,
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let mut contract = deploy(ARITHMETIC);
+
+        returns(&contract.call("factorial(uint24)", &[5]), 120);
+        returns(&contract.call("sum(uint24,uint24)", &[2, 5]), 9);
+        returns(&contract.call("fib(uint24)", &[10]), 55);
+        returns(
+            &contract.call("between(uint24,uint24,uint24)", &[5, 1, 10]),
+            1,
+        );
+        returns(
+            &contract.call("between(uint24,uint24,uint24)", &[11, 1, 10]),
+            0,
+        );
+
+        let result = contract.call("sum(uint24,uint24)", &[0, 10]);
+        assert_eq!(
+            &revert_data(&result)[..4],
+            selector_for("Error(string)").as_slice()
+        );
+    }
+
+    #[test]
+    fn test_storage_and_reverts() {
+        let mut contract = deploy(COUNTER);
+
+        returns(&contract.call("increment(uint24)", &[3]), 3);
+        returns(&contract.call("increment(uint24)", &[4]), 7);
+
+        let result = contract.call("limited(uint24)", &[5]);
+        let data = revert_data(&result);
+        assert_eq!(&data[..4], selector_for("Error(string)").as_slice());
+        assert_eq!(&data[36..68], &word(15));
+        assert_eq!(&data[68..83], b"count too large");
+        returns(&contract.call("increment(uint24)", &[0]), 7);
+
+        panics(&contract.call("guarded(uint24)", &[0]), 0x01);
+        returns(&contract.call("guarded(uint24)", &[1]), 1);
+    }
+
+    #[test]
+    fn test_payable_and_malformed_calls() {
+        let mut contract = deploy(COUNTER);
+
+        returns(
+            &contract.call_with(call_data("deposit(uint24)", &[9]), 100),
+            9,
+        );
+        revert_data(&contract.call_with(call_data("increment(uint24)", &[1]), 100));
+
+        revert_data(&contract.call_with(vec![1, 2], 0));
+        revert_data(&contract.call("missing(uint24)", &[1]));
+        revert_data(&contract.call_with(selector_for("increment(uint24)").to_vec(), 0));
+        revert_data(&contract.call("increment(uint24)", &[0x100_0000]));
+    }
+
+    #[test]
+    fn test_events() {
+        let mut contract = deploy(COUNTER);
+
+        returns(
+            &contract.call("transfer(uint24,uint24,uint24)", &[1, 2, 500]),
+            500,
+        );
+        let events = &contract.interpreter.environment().events;
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].topics,
+            [
+                CryptoFunctions::keccak256(b"Transfer(uint24,uint24,uint24)").to_vec(),
+                word(1).to_vec(),
+                word(2).to_vec(),
+            ]
+        );
+        assert_eq!(events[0].data, word(500));
+    }
+}
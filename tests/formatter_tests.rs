@@ -3,7 +3,7 @@
 //! RED PHASE: These tests define expected behavior
 //! All tests should FAIL initially, then pass after implementation
 
-use bend_pvm::formatter::{FormatResult, Formatter, FormatterConfig};
+use bend_pvm::formatter::{FormatResult, Formatter, FormatterConfig, TrailingComma};
 
 #[cfg(test)]
 mod formatter_config_tests {
@@ -27,12 +27,50 @@ mod formatter_config_tests {
             use_tabs: true,
             blank_line_after_fn: false,
             space_around_operators: false,
+            trailing_comma: TrailingComma::Always,
+            reflow_comments: true,
         };
         assert_eq!(config.indent_size, 2);
         assert!(config.use_tabs);
     }
 }
 
+#[cfg(test)]
+mod trailing_comma_tests {
+    use super::*;
+
+    const SOURCE: &str = "type Point {\n    At(x: u24, y: u24),\n    Origin,\n}\n\nfn long_parameter_list(first_parameter: u24, second_parameter: u24) -> u24 {\n    return first_parameter;\n}\n";
+
+    fn format(trailing_comma: TrailingComma) -> String {
+        let config = FormatterConfig {
+            max_line_length: 40,
+            trailing_comma,
+            ..Default::default()
+        };
+        Formatter::with_config(config)
+            .format_source(SOURCE)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_vertical_lists_end_with_a_comma() {
+        let result = format(TrailingComma::Vertical);
+        assert!(result.contains("    At(x: u24, y: u24),\n    Origin,\n}"));
+        assert!(result.contains("    second_parameter: u24,\n) -> u24 {"));
+    }
+
+    #[test]
+    fn test_always_and_never() {
+        let always = format(TrailingComma::Always);
+        assert!(always.contains("    At(x: u24, y: u24,),\n"));
+        assert!(always.contains("    second_parameter: u24,\n) -> u24 {"));
+
+        let never = format(TrailingComma::Never);
+        assert!(never.contains("    At(x: u24, y: u24),\n    Origin\n}"));
+        assert!(never.contains("    second_parameter: u24\n) -> u24 {"));
+    }
+}
+
 #[cfg(test)]
 mod basic_formatting_tests {
     use super::*;
@@ -40,11 +78,11 @@ mod basic_formatting_tests {
     #[test]
     fn test_removes_extra_spaces_between_tokens() {
         let mut formatter = Formatter::new();
-        let input = "fn   test(  x:i32,   y:i32  ) ->  i32  {";
+        let input = "fn   test(  x:i32,   y:i32  ) ->  i32  {\nreturn x;\n}";
         let result = formatter.format_source(input).unwrap();
         assert!(result.contains("fn test(x: i32, y: i32) -> i32 {"));
-        let content_without_indent = result.trim();
-        assert!(!content_without_indent.contains("  "));
+        let signature = result.lines().next().unwrap();
+        assert!(!signature.contains("  "));
     }
 
     #[test]
@@ -58,7 +96,7 @@ mod basic_formatting_tests {
     #[test]
     fn test_normalizes_operator_spacing() {
         let mut formatter = Formatter::new();
-        let input = "let x = a+b;";
+        let input = "fn test() {\nlet x = a+b;\n}";
         let result = formatter.format_source(input).unwrap();
         assert!(result.contains("let x = a + b;"));
     }
@@ -79,7 +117,7 @@ mod indentation_tests {
     #[test]
     fn test_indents_nested_blocks() {
         let mut formatter = Formatter::new();
-        let input = "fn outer() {\nif true {\nreturn 1;\n}\n}";
+        let input = "fn outer() {\nif true {\nreturn 1;\n} else {\nreturn 0;\n}\n}";
         let result = formatter.format_source(input).unwrap();
         assert!(result.contains("        return 1")); // 8 spaces (nested)
     }
@@ -246,9 +284,9 @@ mod regression_tests {
     #[test]
     fn test_issue_22_001_no_double_spaces_after_keywords() {
         let mut formatter = Formatter::new();
-        let input = "if   (x > 0) {";
+        let input = "fn test(x: u24) {\nif   (x > 0) {\nreturn 1;\n} else {\nreturn 0;\n}\n}";
         let result = formatter.format_source(input).unwrap();
-        assert!(result.contains("if (x > 0) {"));
+        assert!(result.contains("if x > 0 {"));
         assert!(!result.contains("if   "));
     }
 
@@ -263,9 +301,9 @@ mod regression_tests {
     #[test]
     fn test_issue_22_003_curly_brace_spacing() {
         let mut formatter = Formatter::new();
-        let input = "if (true)  {";
+        let input = "fn test() {\nif (true)  {\nreturn 1;\n} else {\nreturn 0;\n}\n}";
         let result = formatter.format_source(input).unwrap();
-        assert!(result.contains("if (true) {"));
+        assert!(result.contains("if true {"));
     }
 
     #[test]
@@ -276,6 +314,16 @@ mod regression_tests {
         assert!(result.contains("fn test(a: i32, b: i32)"));
     }
 
+    #[test]
+    fn test_superposition_spacing() {
+        let mut formatter = Formatter::new();
+        let input = "fn test(a:u24) {\npay({a,1});\npay({a,});\n}";
+        let result = formatter.format_source(input).unwrap();
+        assert!(result.contains("pay({a, 1});"));
+        // A lone alternative keeps its comma, or it would be a block
+        assert!(result.contains("pay({a,});"));
+    }
+
     #[test]
     fn test_issue_22_005_empty_lines_at_end() {
         let mut formatter = Formatter::new();
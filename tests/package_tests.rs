@@ -1,6 +1,4 @@
-use bend_pvm::package::{
-    Dependency, Package, PackageError, PackageManifest, PackageMetadata, Version,
-};
+use bend_pvm::package::{Dependency, Package, PackageError, PackageManifest, Version};
 
 fn create_test_package() -> Package {
     Package::new("test_pkg".to_string(), Version::new(1, 0, 0))
@@ -217,3 +215,1291 @@ mod package_tests {
         assert_eq!(lock.entries().len(), 1);
     }
 }
+
+mod manifest_tests {
+    use super::*;
+    use bend_pvm::package::{DependencySource, PackageLock, PackageLockEntry};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_manifest_parse() {
+        let manifest = PackageManifest::parse(
+            r#"
+[package]
+name = "token"
+version = "0.1.0"
+authors = ["Your Name <your.email@example.com>"]
+
+[dependencies]
+# Add your dependencies here
+math = "^1.2.0"
+utils = { path = "../utils" }
+oracle = { git = "https://example.com/oracle.git", tag = "v2.0.0", version = "2.0.0" }
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.package().name(), "token");
+        assert_eq!(manifest.package().version(), &Version::new(0, 1, 0));
+        assert_eq!(manifest.package().authors().len(), 1);
+
+        let deps = manifest.dependencies();
+        assert_eq!(deps.len(), 3);
+        assert_eq!(deps[0].name(), "math");
+        assert_eq!(deps[0].version(), Some(&Version::new(1, 2, 0)));
+        assert_eq!(deps[0].source(), &DependencySource::Registry);
+        assert_eq!(deps[1].name(), "oracle");
+        assert_eq!(
+            deps[1].source(),
+            &DependencySource::Git {
+                url: "https://example.com/oracle.git".to_string(),
+                reference: Some("v2.0.0".to_string()),
+            }
+        );
+        assert_eq!(deps[2].name(), "utils");
+        assert_eq!(deps[2].version(), None);
+        assert_eq!(
+            deps[2].source(),
+            &DependencySource::Path(PathBuf::from("../utils"))
+        );
+    }
+
+    #[test]
+    fn test_manifest_parse_errors() {
+        let invalid = [
+            "[dependencies]\nmath = \"1.0.0\"\n",
+            "[package]\nname = \"a\"\n",
+            "[package]\nname = \"a\"\nversion = \"1.0\"\n",
+            "[package]\nname = \"a\"\nversion = \"1.0.0\"\n[dependencies]\nb = { path = \"b\", git = \"c\" }\n",
+            "[package]\nname = \"a\"\nversion = \"1.0.0\"\n[dependencies]\nb = { path = \"b\", tag = \"v1\" }\n",
+            "[package]\nname = \"a\"\nversion = \"1.0.0\"\n[dependencies]\nb = { }\n",
+            "[package]\nname = \"a\"\nversion = \"1.0.0\"\nname = \"b\"\n",
+            "[package]\nname = \"a\nversion = \"1.0.0\"\n",
+        ];
+        for source in invalid {
+            assert!(PackageManifest::parse(source).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_manifest_profiles() {
+        let manifest = PackageManifest::parse(
+            "[package]\nname = \"a\"\nversion = \"1.0.0\"\n\n[profile.release]\nopt-level = 2\n\n[profile.dev]\ndebug = false\n",
+        )
+        .unwrap();
+        let release = manifest.profile("release").unwrap();
+        assert_eq!(release.opt_level(), 2);
+        assert!(!release.debug());
+        let dev = manifest.profile("dev").unwrap();
+        assert_eq!(dev.opt_level(), 0);
+        assert!(!dev.debug());
+        assert!(manifest.profile("bench").is_none());
+
+        let defaults =
+            PackageManifest::parse("[package]\nname = \"a\"\nversion = \"1.0.0\"\n").unwrap();
+        assert!(defaults.profile("dev").unwrap().debug());
+        assert_eq!(defaults.profile("release").unwrap().opt_level(), 3);
+
+        let invalid = [
+            "[profile.bench]\nopt-level = 1\n",
+            "[profile.release]\nopt-level = 4\n",
+            "[profile.release]\ndebug = \"yes\"\n",
+            "[profile.release]\nlto = true\n",
+        ];
+        for profile in invalid {
+            let source = format!("[package]\nname = \"a\"\nversion = \"1.0.0\"\n{}", profile);
+            assert!(matches!(
+                PackageManifest::parse(&source),
+                Err(PackageError::InvalidManifest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_manifest_features() {
+        let manifest = PackageManifest::parse(
+            "[package]\nname = \"a\"\nversion = \"1.0.0\"\n\n[features]\ndefault = [\"debug\"]\ndebug = []\ntestnet = []\nfull = [\"debug\", \"testnet\"]\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.features().len(), 4);
+        assert_eq!(manifest.features()["full"], ["debug", "testnet"]);
+
+        let enabled = |requested: &[&str], default_features: bool| {
+            let requested: Vec<String> = requested.iter().map(|f| f.to_string()).collect();
+            manifest
+                .resolve_features(&requested, default_features)
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(enabled(&[], true), ["debug"]);
+        assert!(enabled(&[], false).is_empty());
+        assert_eq!(enabled(&["testnet"], false), ["testnet"]);
+        assert_eq!(enabled(&["full"], false), ["debug", "full", "testnet"]);
+        assert!(matches!(
+            manifest.resolve_features(&["mainnet".to_string()], true),
+            Err(PackageError::InvalidManifest(message)) if message.contains("only debug, full, testnet")
+        ));
+
+        let invalid = [
+            "[features]\ndebug = \"yes\"\n",
+            "[features]\nfull = [\"debug\"]\n",
+            "[features]\ndefault = [\"debug\"]\n",
+        ];
+        for features in invalid {
+            let source = format!("[package]\nname = \"a\"\nversion = \"1.0.0\"\n{}", features);
+            assert!(matches!(
+                PackageManifest::parse(&source),
+                Err(PackageError::InvalidManifest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_lock_round_trip() {
+        let mut lock = PackageLock::new();
+        lock.add_entry(
+            PackageLockEntry::new(
+                "math".to_string(),
+                Version::new(1, 2, 0),
+                "sha256:abc".to_string(),
+            )
+            .with_dependencies(vec!["utils".to_string()]),
+        );
+        lock.add_entry(
+            PackageLockEntry::new(
+                "utils".to_string(),
+                Version::new(0, 1, 0),
+                "sha256:def".to_string(),
+            )
+            .with_source("path+../utils".to_string()),
+        );
+
+        let text = lock.to_toml();
+        assert!(text.find("name = \"math\"").unwrap() < text.find("name = \"utils\"").unwrap());
+        let parsed = PackageLock::parse(&text).unwrap();
+        assert_eq!(parsed, lock);
+        assert_eq!(parsed.get("math").unwrap().dependencies(), ["utils"]);
+        assert_eq!(parsed.get("utils").unwrap().source(), "path+../utils");
+    }
+}
+
+mod workspace_tests {
+    use bend_pvm::compiler::analyzer::lints::LintLevels;
+    use bend_pvm::compiler::cfg::Cfg;
+    use bend_pvm::compiler::codegen::risc_v::DISPATCH_LABEL;
+    use bend_pvm::compiler::polkavm::abi::parse_abi;
+    use bend_pvm::deployment::package_contract;
+    use bend_pvm::diagnostics::{to_json, Severity};
+    use bend_pvm::package::{
+        build, database, rebuild, test, ArtifactsManifest, BuildError, PackageError, PackageLock,
+        Version, Watcher, Workspace, LOCK_FILE,
+    };
+    use bend_pvm::testing::{TestError, TestResult};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bend_pkg_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_package(dir: &Path, name: &str, version: &str, deps: &str, modules: &[(&str, &str)]) {
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join("bend.toml"),
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"{}\"\n\n[dependencies]\n{}",
+                name, version, deps
+            ),
+        )
+        .unwrap();
+        for (module, source) in modules {
+            fs::write(dir.join("src").join(format!("{}.bend", module)), source).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_path_dependencies_build_in_order() {
+        let dir = scratch_dir("path");
+        write_package(
+            &dir.join("utils"),
+            "utils",
+            "0.1.0",
+            "",
+            &[(
+                "utils",
+                "pub fn double(x: u24) -> u24 {\n    return x * 2;\n}\n",
+            )],
+        );
+        write_package(
+            &dir.join("math"),
+            "math",
+            "1.0.0",
+            "utils = { path = \"../utils\" }\n",
+            &[(
+                "math",
+                "from utils import double;\n\nfn quadruple(x: u24) -> u24 {\n    return double(double(x));\n}\n",
+            )],
+        );
+        write_package(
+            &dir.join("app"),
+            "app",
+            "0.1.0",
+            "math = { path = \"../math\", version = \"1.0.0\" }\nutils = { path = \"../utils\" }\n",
+            &[],
+        );
+
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        let order: Vec<_> = workspace
+            .build_order()
+            .unwrap()
+            .iter()
+            .map(|package| package.name().to_string())
+            .collect();
+        assert_eq!(order, ["utils", "math", "app"]);
+        assert_eq!(workspace.compile_dependencies().unwrap(), ["utils", "math"]);
+
+        workspace.write_lock().unwrap();
+        let lock = PackageLock::load(&dir.join("app").join(LOCK_FILE)).unwrap();
+        assert_eq!(lock.entries().len(), 2);
+        assert_eq!(lock.get("math").unwrap().dependencies(), ["utils"]);
+        assert_eq!(lock.get("utils").unwrap().source(), "path+../utils");
+        assert!(lock.get("math").unwrap().integrity().starts_with("sha256:"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_dependency_modules_are_cached() {
+        let dir = scratch_dir("cached");
+        write_package(
+            &dir.join("utils"),
+            "utils",
+            "0.1.0",
+            "",
+            &[(
+                "utils",
+                "pub fn double(x: u24) -> u24 {\n    return x * 2;\n}\n",
+            )],
+        );
+        let math = "from utils import double;\n\nfn quadruple(x: u24) -> u24 {\n    return double(double(x));\n}\n";
+        write_package(
+            &dir.join("math"),
+            "math",
+            "1.0.0",
+            "utils = { path = \"../utils\" }\n",
+            &[("math", math)],
+        );
+        write_package(
+            &dir.join("app"),
+            "app",
+            "0.1.0",
+            "math = { path = \"../math\" }\n",
+            &[],
+        );
+
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        workspace.compile_dependencies().unwrap();
+
+        let math_path = dir.join("math").join("src").join("math.bend");
+        let entry = workspace.cache().get(&math_path, math).unwrap();
+        assert!(entry.type_checked);
+        assert_eq!(entry.dependencies.len(), 1);
+
+        // Editing an imported module invalidates the modules importing it
+        fs::write(
+            dir.join("utils").join("src").join("utils.bend"),
+            "pub fn triple(x: u24) -> u24 {\n    return x * 3;\n}\n",
+        )
+        .unwrap();
+        assert!(workspace.cache().get(&math_path, math).is_none());
+        assert!(matches!(
+            workspace.compile_dependencies(),
+            Err(PackageError::Compile { package, .. }) if package == "math"
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_dependencies_use_the_standard_library() {
+        let dir = scratch_dir("std");
+        write_package(
+            &dir.join("stats"),
+            "stats",
+            "0.1.0",
+            "",
+            &[(
+                "stats",
+                "from std/Math import Math/max;\nfrom std/List import List/sum;\n\npub fn first_or(xs: List<u24>, fallback: u24) -> u24 {\n    match List/head(xs) {\n        Option/Some(first) => {\n            return Math/max(first, fallback);\n        }\n        Option/None => {\n            return fallback + List/sum(xs);\n        }\n    }\n}\n",
+            )],
+        );
+        write_package(
+            &dir.join("app"),
+            "app",
+            "0.1.0",
+            "stats = { path = \"../stats\" }\n",
+            &[],
+        );
+
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        assert_eq!(workspace.compile_dependencies().unwrap(), ["stats"]);
+
+        // The prelude is imported before the module's own imports
+        let stats = workspace.package("stats").unwrap();
+        let path = stats.source_dir().join("stats.bend");
+        let imported = |prelude: bool| {
+            let mut modules = workspace.module_system(stats);
+            modules.set_prelude(prelude);
+            let mut names: Vec<_> = modules
+                .load_module(&path)
+                .unwrap()
+                .imports
+                .keys()
+                .cloned()
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(imported(true), ["List", "Math", "Option", "Result"]);
+        assert_eq!(imported(false), ["List", "Math"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_private_definitions_are_not_exported() {
+        let dir = scratch_dir("private");
+        write_package(
+            &dir.join("utils"),
+            "utils",
+            "0.1.0",
+            "",
+            &[(
+                "utils",
+                "fn helper(x: u24) -> u24 {\n    return x;\n}\n\npub fn double(x: u24) -> u24 {\n    return helper(x) * 2;\n}\n",
+            )],
+        );
+        write_package(
+            &dir.join("lib"),
+            "lib",
+            "0.1.0",
+            "utils = { path = \"../utils\" }\n",
+            &[(
+                "lib",
+                "from utils import helper;\n\nfn f(x: u24) -> u24 {\n    return helper(x);\n}\n",
+            )],
+        );
+        write_package(
+            &dir.join("app"),
+            "app",
+            "0.1.0",
+            "lib = { path = \"../lib\" }\n",
+            &[],
+        );
+
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        match workspace.compile_dependencies() {
+            Err(PackageError::Compile { package, message }) => {
+                assert_eq!(package, "lib");
+                assert!(
+                    message.contains("helper is private to module utils"),
+                    "{}",
+                    message
+                );
+            }
+            other => panic!("expected a compile error, got {:?}", other),
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_dependency_compile_errors() {
+        let dir = scratch_dir("compile");
+        write_package(
+            &dir.join("broken"),
+            "broken",
+            "0.1.0",
+            "",
+            &[("broken", "fn f() -> u24 {\n    return missing(1);\n}\n")],
+        );
+        write_package(
+            &dir.join("app"),
+            "app",
+            "0.1.0",
+            "broken = { path = \"../broken\" }\n",
+            &[],
+        );
+
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        assert!(matches!(
+            workspace.compile_dependencies(),
+            Err(PackageError::Compile { package, .. }) if package == "broken"
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_registry_dependencies() {
+        let dir = scratch_dir("registry");
+        let home = dir.join("home");
+        let registry = home.join("registry").join("math");
+        for version in ["1.0.0", "1.4.0", "2.0.0"] {
+            write_package(&registry.join(version), "math", version, "", &[]);
+        }
+        write_package(&dir.join("app"), "app", "0.1.0", "math = \"1.1.0\"\n", &[]);
+
+        let workspace = Workspace::load_with_home(&dir.join("app"), &home).unwrap();
+        assert_eq!(
+            workspace.package("math").unwrap().version(),
+            &Version::new(1, 4, 0)
+        );
+        assert_eq!(workspace.package("math").unwrap().source(), "registry");
+
+        write_package(&dir.join("app"), "app", "0.1.0", "math = \"3.0.0\"\n", &[]);
+        assert!(matches!(
+            Workspace::load_with_home(&dir.join("app"), &home),
+            Err(PackageError::NotFound { .. })
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_registry_dependencies_use_lock() {
+        let dir = scratch_dir("locked");
+        let home = dir.join("home");
+        let registry = home.join("registry").join("math");
+        write_package(&registry.join("1.0.0"), "math", "1.0.0", "", &[]);
+        write_package(&dir.join("app"), "app", "0.1.0", "math = \"1.0.0\"\n", &[]);
+        Workspace::load_with_home(&dir.join("app"), &home)
+            .unwrap()
+            .write_lock()
+            .unwrap();
+
+        // A newer compatible release does not replace the locked version
+        write_package(&registry.join("1.4.0"), "math", "1.4.0", "", &[]);
+        let workspace = Workspace::load_with_home(&dir.join("app"), &home).unwrap();
+        assert_eq!(
+            workspace.package("math").unwrap().version(),
+            &Version::new(1, 0, 0)
+        );
+
+        // Locked packages whose contents changed are rejected
+        write_package(
+            &registry.join("1.0.0"),
+            "math",
+            "1.0.0",
+            "",
+            &[("math", "fn one() -> u24 {\n    return 1;\n}\n")],
+        );
+        assert!(matches!(
+            Workspace::load_with_home(&dir.join("app"), &home),
+            Err(PackageError::Fetch { .. })
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_dependency_errors() {
+        let dir = scratch_dir("errors");
+        let home = dir.join("home");
+
+        write_package(
+            &dir.join("a"),
+            "a",
+            "0.1.0",
+            "b = { path = \"../b\" }\n",
+            &[],
+        );
+        write_package(
+            &dir.join("b"),
+            "b",
+            "0.1.0",
+            "a = { path = \"../a\" }\n",
+            &[],
+        );
+        let workspace = Workspace::load_with_home(&dir.join("a"), &home).unwrap();
+        assert_eq!(
+            workspace.build_order().unwrap_err(),
+            PackageError::DependencyCycle(vec!["a".to_string(), "b".to_string(), "a".to_string()])
+        );
+
+        write_package(
+            &dir.join("c"),
+            "c",
+            "0.1.0",
+            "b = { path = \"../b\", version = \"1.0.0\" }\n",
+            &[],
+        );
+        assert!(matches!(
+            Workspace::load_with_home(&dir.join("c"), &home),
+            Err(PackageError::UnsatisfiedDependency { .. })
+        ));
+
+        write_package(
+            &dir.join("d"),
+            "d",
+            "0.1.0",
+            "a = { path = \"../b\" }\n",
+            &[],
+        );
+        assert!(matches!(
+            Workspace::load_with_home(&dir.join("d"), &home),
+            Err(PackageError::InvalidManifest(_))
+        ));
+
+        write_package(
+            &dir.join("e"),
+            "e",
+            "0.1.0",
+            "z = { path = \"../z\" }\n",
+            &[],
+        );
+        assert!(matches!(
+            Workspace::load_with_home(&dir.join("e"), &home),
+            Err(PackageError::NotFound { .. })
+        ));
+
+        assert_eq!(
+            Workspace::find_root(&dir.join("a").join("src")),
+            Some(dir.join("a").canonicalize().unwrap())
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_build_profiles() {
+        let dir = scratch_dir("build");
+        write_package(
+            &dir.join("app"),
+            "app",
+            "0.1.0",
+            "\n[profile.release]\nopt-level = 2\n",
+            &[
+                (
+                    "utils",
+                    "pub fn double(x: u24) -> u24 {\n    return x * 2;\n}\n",
+                ),
+                (
+                    "main",
+                    "from utils import double;\n\nfn main(unused: u24) -> u24 {\n    return double(21);\n}\n",
+                ),
+            ],
+        );
+
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        let manifest = workspace.root().manifest();
+        let levels = LintLevels::default();
+
+        // Only modules nothing imports are built
+        let dev = build(
+            &workspace,
+            &manifest.profile("dev").unwrap(),
+            &levels,
+            &Cfg::new(),
+        )
+        .unwrap();
+        let target = workspace.root().root().join("target");
+        assert_eq!(dev.artifacts.len(), 1);
+        assert_eq!(dev.artifacts[0].binary, target.join("dev").join("main.bin"));
+        assert!(dev.artifacts[0].binary.exists());
+        assert!(dev.artifacts[0].listing.as_ref().unwrap().exists());
+        assert_eq!(dev.warnings.len(), 1);
+        assert_eq!(dev.warnings[0].diagnostic.code, "W0102");
+
+        let release = build(
+            &workspace,
+            &manifest.profile("release").unwrap(),
+            &levels,
+            &Cfg::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            release.artifacts[0].binary,
+            target.join("release").join("main.bin")
+        );
+        assert_eq!(release.artifacts[0].listing, None);
+
+        // The manifest keeps the artifacts of both profiles
+        let artifacts = ArtifactsManifest::load(&workspace).unwrap();
+        assert_eq!(artifacts.package, "app");
+        assert_eq!(artifacts.version, "0.1.0");
+        assert_eq!(artifacts.profiles.len(), 2);
+        assert!(artifacts.profiles["dev"].built_at.is_some());
+        let entry = &artifacts.profiles["dev"].artifacts[0];
+        assert_eq!(entry.module, Path::new("src").join("main.bend"));
+        assert_eq!(
+            entry.binary,
+            Path::new("target").join("dev").join("main.bin")
+        );
+        assert_eq!(entry.sha256, dev.artifacts[0].sha256);
+        assert_eq!(
+            entry.size,
+            fs::metadata(&dev.artifacts[0].binary).unwrap().len()
+        );
+        let release_entry = &artifacts.profiles["release"];
+        assert_eq!(release_entry.opt_level, 2);
+        assert_eq!(release_entry.artifacts[0].listing, None);
+
+        let mut levels = LintLevels::default();
+        levels.deny_warnings();
+        assert!(matches!(
+            build(&workspace, &manifest.profile("dev").unwrap(), &levels, &Cfg::new()),
+            Err(BuildError::Diagnostics(diagnostics)) if diagnostics.len() == 1
+        ));
+
+        fs::write(
+            dir.join("app").join("src").join("main.bend"),
+            "fn main() -> u24 {\n    return missing(1);\n}\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            build(&workspace, &manifest.profile("dev").unwrap(), &levels, &Cfg::new()),
+            Err(BuildError::Diagnostics(diagnostics)) if diagnostics[0].diagnostic.code.starts_with('E')
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_codegen_errors_are_diagnostics() {
+        let source =
+            "fn evens(n: u24) -> u24 {\n    return List/length([x * 2 for x in range(n)]);\n}\n";
+        let dir = scratch_dir("codegen");
+        write_package(&dir.join("app"), "app", "0.1.0", "", &[("main", source)]);
+
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        let profile = workspace.root().manifest().profile("dev").unwrap();
+        let diagnostics = match build(&workspace, &profile, &LintLevels::default(), &Cfg::new()) {
+            Err(BuildError::Diagnostics(diagnostics)) => diagnostics,
+            other => panic!("expected diagnostics, got {:?}", other),
+        };
+        let error = diagnostics
+            .iter()
+            .find(|d| d.diagnostic.severity == Severity::Error)
+            .unwrap();
+        assert!(error.path.ends_with("main.bend"));
+        assert_eq!(error.diagnostic.code, "E0301");
+        assert_eq!(&source[error.diagnostic.span().unwrap()], "[");
+
+        // What `--message-format=json` writes out for it
+        let json = to_json(&error.diagnostic, "src/main.bend", source);
+        assert_eq!(json["code"], "E0301");
+        assert_eq!(json["labels"][0]["line"], 2);
+        assert_eq!(json["labels"][0]["column"], 24);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rebuild_changed_modules() {
+        let dir = scratch_dir("rebuild");
+        write_package(
+            &dir.join("app"),
+            "app",
+            "0.1.0",
+            "",
+            &[
+                (
+                    "utils",
+                    "pub fn double(x: u24) -> u24 {\n    return x * 2;\n}\n",
+                ),
+                (
+                    "main",
+                    "from utils import double;\n\nfn main() -> u24 {\n    return double(21);\n}\n",
+                ),
+                ("other", "fn other() -> u24 {\n    return 1;\n}\n"),
+            ],
+        );
+
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        let profile = workspace.root().manifest().profile("dev").unwrap();
+        let levels = LintLevels::default();
+        let mut watcher = Watcher::new(&workspace).unwrap();
+        assert_eq!(watcher.files().count(), 4);
+        assert!(watcher.changes().unwrap().is_empty());
+
+        // Missing binaries are built whatever changed
+        let mut database = database(&workspace, &Cfg::new());
+        let output = rebuild(&workspace, &mut database, &profile, &levels, &[]).unwrap();
+        assert_eq!(output.artifacts.len(), 2);
+        assert!(output.fresh.is_empty());
+
+        let utils = workspace.root().source_dir().join("utils.bend");
+        fs::write(
+            &utils,
+            "pub fn double(x: u24) -> u24 {\n    return x + x;\n}\n",
+        )
+        .unwrap();
+        let changed = watcher.changes().unwrap();
+        assert_eq!(changed, std::slice::from_ref(&utils));
+
+        let output = rebuild(&workspace, &mut database, &profile, &levels, &changed).unwrap();
+        let built: Vec<_> = output
+            .artifacts
+            .iter()
+            .map(|artifact| artifact.module.file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(built, ["main.bend"]);
+        assert_eq!(output.fresh.len(), 1);
+        assert!(output.fresh[0].ends_with("other.bend"));
+
+        // Fresh modules stay in the manifest
+        let artifacts = ArtifactsManifest::load(&workspace).unwrap();
+        let modules: Vec<_> = artifacts.profiles["dev"]
+            .artifacts
+            .iter()
+            .map(|entry| entry.module.clone())
+            .collect();
+        assert_eq!(
+            modules,
+            [
+                Path::new("src").join("main.bend"),
+                Path::new("src").join("other.bend")
+            ]
+        );
+
+        // The database kept between rebuilds sees what changed on disk
+        fs::write(
+            &utils,
+            "pub fn double(x: u24) -> u24 {\n    return true;\n}\n",
+        )
+        .unwrap();
+        let changed = watcher.changes().unwrap();
+        assert!(matches!(
+            rebuild(&workspace, &mut database, &profile, &levels, &changed),
+            Err(BuildError::Diagnostics(_))
+        ));
+
+        let other = workspace.root().source_dir().join("other.bend");
+        fs::remove_file(&other).unwrap();
+        assert_eq!(watcher.changes().unwrap(), [other]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_build_features() {
+        let dir = scratch_dir("features");
+        write_package(
+            &dir.join("app"),
+            "app",
+            "0.1.0",
+            "\n[features]\ndefault = [\"fast\"]\nfast = []\ntestnet = []\n",
+            &[(
+                "main",
+                concat!(
+                    "#[cfg(feature = \"fast\")]\nfn scale(x: u24) -> u24 {\n    return x * 2;\n}\n\n",
+                    "#[cfg(not(feature = \"fast\"))]\nfn scale(x: u24) -> u24 {\n    return x + x;\n}\n\n",
+                    "#[cfg(feature = \"testnet\")]\nfn faucet() -> u24 {\n    return missing(1);\n}\n\n",
+                    "#[cfg(test)]\nfn helper() -> u24 {\n    return missing(2);\n}\n\n",
+                    "fn main() -> u24 {\n    return scale(21);\n}\n",
+                ),
+            )],
+        );
+
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        let manifest = workspace.root().manifest();
+        let profile = manifest.profile("dev").unwrap();
+        let levels = LintLevels::default();
+        let cfg = |requested: &[&str], default_features: bool| {
+            let requested: Vec<String> = requested.iter().map(|f| f.to_string()).collect();
+            Cfg::new().with_features(
+                manifest
+                    .resolve_features(&requested, default_features)
+                    .unwrap(),
+            )
+        };
+
+        // Definitions whose cfg does not hold are not even type checked
+        let fast = build(&workspace, &profile, &levels, &cfg(&[], true)).unwrap();
+        let plain = build(&workspace, &profile, &levels, &cfg(&[], false)).unwrap();
+        assert_ne!(fast.artifacts[0].sha256, plain.artifacts[0].sha256);
+        let artifacts = ArtifactsManifest::load(&workspace).unwrap();
+        assert!(artifacts.profiles["dev"].features.is_empty());
+
+        // The module cache does not carry results over between features
+        let again = build(&workspace, &profile, &levels, &cfg(&[], true)).unwrap();
+        assert_eq!(again.artifacts[0].sha256, fast.artifacts[0].sha256);
+        let artifacts = ArtifactsManifest::load(&workspace).unwrap();
+        assert_eq!(artifacts.profiles["dev"].features, ["fast"]);
+
+        assert!(matches!(
+            build(&workspace, &profile, &levels, &cfg(&["testnet"], true)),
+            Err(BuildError::Diagnostics(diagnostics)) if diagnostics[0].diagnostic.code.starts_with('E')
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_package_tests_run() {
+        let dir = scratch_dir("tests");
+        write_package(
+            &dir.join("app"),
+            "app",
+            "0.1.0",
+            "",
+            &[(
+                "main",
+                concat!(
+                    "fn double(x: u24) -> u24 {\n    return x * 2;\n}\n\n",
+                    "#[test]\nfn test_double() -> u24 {\n    assert(double(2) == 4, \"doubled\");\n    return 0;\n}\n\n",
+                    "#[test]\nfn test_broken() -> u24 {\n    assert(double(2) == 5, \"never\");\n    return 0;\n}\n",
+                ),
+            )],
+        );
+
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        let output = test(&workspace, &Cfg::new()).unwrap();
+        assert!(!output.passed());
+        let results: Vec<(&str, bool)> = output
+            .tests
+            .iter()
+            .map(|test| {
+                (
+                    test.name.as_str(),
+                    matches!(test.result, TestResult::Passed { .. }),
+                )
+            })
+            .collect();
+        assert_eq!(results, [("test_double", true), ("test_broken", false)]);
+        assert!(matches!(
+            &output.tests[1].result,
+            TestResult::Failed {
+                error: TestError::Runtime(_),
+                ..
+            }
+        ));
+
+        // Tests of a module that does not compile fail too
+        let main = dir.join("app").join("src").join("main.bend");
+        fs::write(
+            &main,
+            "#[test]\nfn test_missing() -> u24 {\n    return missing(1);\n}\n",
+        )
+        .unwrap();
+        let output = test(&workspace, &Cfg::new()).unwrap();
+        assert!(matches!(
+            &output.tests[0].result,
+            TestResult::Failed {
+                error: TestError::Compile(_),
+                ..
+            }
+        ));
+        assert!(!output.passed());
+
+        fs::write(&main, "#[test]\nfn test_unfinished(\n").unwrap();
+        let output = test(&workspace, &Cfg::new()).unwrap();
+        assert_eq!(output.diagnostics.len(), 1);
+        assert!(!output.passed());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_package_tests_call_imported_functions() {
+        let dir = scratch_dir("imported_tests");
+        write_package(
+            &dir.join("app"),
+            "app",
+            "0.1.0",
+            "",
+            &[
+                (
+                    "lib",
+                    "pub fn double(x: u24) -> u24 {\n    return x * 2;\n}\n",
+                ),
+                (
+                    "main",
+                    concat!(
+                        "from lib import double;\n\n",
+                        "#[test]\nfn test_double() -> u24 {\n    assert(double(2) == 4, \"doubled\");\n    return 0;\n}\n",
+                    ),
+                ),
+            ],
+        );
+
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        let output = test(&workspace, &Cfg::new()).unwrap();
+        assert!(output.passed(), "{:?}", output);
+        assert_eq!(output.tests.len(), 1);
+        assert_eq!(output.tests[0].name, "test_double");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_package_invariants_find_violations() {
+        let dir = scratch_dir("invariants");
+        write_package(
+            &dir.join("app"),
+            "app",
+            "0.1.0",
+            "",
+            &[
+                (
+                    "lib",
+                    "#[pure]\npub fn sum(a: u24, b: u24) -> u24 {\n    return a + b;\n}\n",
+                ),
+                (
+                    "main",
+                    concat!(
+                        "from lib import sum;\n\n",
+                        "storage {\n    held: u24,\n    sent: u24,\n    supply: u24,\n}\n\n",
+                        "#[message]\nfn mint(amount: u24) -> u24 {\n    require(amount <= 1000);\n    held = held + amount;\n    supply = supply + amount;\n    return supply;\n}\n\n",
+                        // Burning more than is held leaves the supply alone
+                        "#[message]\nfn burn(amount: u24) -> u24 {\n    if amount <= held {\n        held = held - amount;\n        supply = supply - amount;\n    } else {\n        held = 0;\n    }\n    return supply;\n}\n\n",
+                        "#[message]\nfn send(to: Address, amount: u24) -> Bool {\n    require(amount <= held);\n    held = held - amount;\n    sent = sent + amount;\n    return true;\n}\n\n",
+                        "#[invariant]\nfn supply_is_held_and_sent() -> Bool {\n    return sum(held, sent) == supply;\n}\n",
+                    ),
+                ),
+            ],
+        );
+
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        let output = test(&workspace, &Cfg::new()).unwrap();
+        assert!(!output.passed());
+        assert_eq!(output.tests.len(), 1);
+        assert_eq!(output.tests[0].name, "supply_is_held_and_sent");
+        match &output.tests[0].result {
+            TestResult::Failed {
+                error: TestError::AssertionFailed(message),
+                ..
+            } => {
+                assert!(
+                    message.contains("invariant 'supply_is_held_and_sent' broken after 2 call(s)"),
+                    "{}",
+                    message
+                );
+                assert!(message.contains("1. mint(1)"), "{}", message);
+                assert!(message.contains("2. burn(2)"), "{}", message);
+            }
+            other => panic!("the invariant held: {:?}", other),
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    /// A package whose entry module calls a function of another module
+    fn contract_package(dir: &Path) {
+        write_package(
+            dir,
+            "app",
+            "0.1.0",
+            "",
+            &[
+                (
+                    "utils",
+                    "pub fn double(x: u24) -> u24 {\n    return x * 2;\n}\n\npub fn count(xs: List<u24>) -> u24 {\n    match xs {\n        List/Nil => {\n            return 0;\n        }\n        List/Cons(_head, tail) => {\n            return 1 + count(tail);\n        }\n    }\n}\n",
+                ),
+                (
+                    "main",
+                    "from utils import double;\n\n#[message]\nfn seven() -> u24 {\n    return 7;\n}\n\n#[message]\nfn quadruple(x: u24) -> u24 {\n    return double(double(x));\n}\n",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_built_contracts_have_a_dispatcher() {
+        let dir = scratch_dir("dispatcher");
+        contract_package(&dir.join("app"));
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        let dev = workspace.root().manifest().profile("dev").unwrap();
+        let output = build(&workspace, &dev, &LintLevels::default(), &Cfg::new()).unwrap();
+
+        let listing = fs::read_to_string(output.artifacts[0].listing.as_ref().unwrap()).unwrap();
+        let dispatcher = format!("{}:", DISPATCH_LABEL);
+        assert_eq!(
+            listing.lines().filter(|line| *line == dispatcher).count(),
+            1
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_built_contracts_verify() {
+        let dir = scratch_dir("verify");
+        let root = dir.join("app");
+        contract_package(&root);
+        let workspace = Workspace::load_with_home(&root, &dir.join("home")).unwrap();
+        let release = workspace.root().manifest().profile("release").unwrap();
+        let output = build(&workspace, &release, &LintLevels::default(), &Cfg::new()).unwrap();
+        let artifact = &output.artifacts[0];
+        assert_eq!(artifact.abi, root.join("target/release/main.abi.json"));
+        assert_eq!(
+            artifact.metadata,
+            root.join("target/release/main.metadata.json")
+        );
+        let abi = parse_abi(&fs::read_to_string(&artifact.abi).unwrap()).unwrap();
+        let mut methods: Vec<&str> = abi.methods.iter().map(|m| m.name.as_str()).collect();
+        methods.sort();
+        // Imported library functions are linked in, but are not messages
+        assert_eq!(methods, ["quadruple", "seven"]);
+        assert!(fs::read_to_string(&artifact.metadata)
+            .unwrap()
+            .contains("\"storage_layout\""));
+        let entry = &ArtifactsManifest::load(&workspace).unwrap().profiles["release"].artifacts[0];
+        assert_eq!(entry.abi, Path::new("target/release/main.abi.json"));
+
+        // Verifying the source rebuilds the very blob and ABI written
+        let (contract, profile) =
+            package_contract(&root, &root.join("src").join("main.bend")).unwrap();
+        assert_eq!(profile, release);
+        assert_eq!(contract.binary, fs::read(&artifact.binary).unwrap());
+        assert_eq!(
+            serde_json::to_value(&contract.abi).unwrap(),
+            serde_json::to_value(&abi).unwrap()
+        );
+        assert!(package_contract(&root, &root.join("src").join("missing.bend")).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_deterministic_builds() {
+        let dir = scratch_dir("deterministic");
+        let build_in = |name: &str| {
+            let root = dir.join(name).join("app");
+            contract_package(&root);
+            let workspace = Workspace::load_with_home(&root, &dir.join("home")).unwrap();
+            let dev = workspace
+                .root()
+                .manifest()
+                .profile("dev")
+                .unwrap()
+                .with_deterministic(true);
+            build(&workspace, &dev, &LintLevels::default(), &Cfg::new()).unwrap();
+            let target = root.join("target");
+            let mut files: Vec<(PathBuf, Vec<u8>)> = fs::read_dir(target.join("dev"))
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .chain([target.join("manifest.json")])
+                .map(|path| {
+                    let bytes = fs::read(&path).unwrap();
+                    (path.strip_prefix(&target).unwrap().to_path_buf(), bytes)
+                })
+                .collect();
+            files.sort();
+            files
+        };
+
+        // Two checkouts of the package at different paths build the same bytes
+        let first = build_in("one");
+        let second = build_in("two");
+        assert_eq!(first.len(), 6);
+        assert_eq!(first, second);
+        let listing = &first
+            .iter()
+            .find(|(path, _)| path.extension() == Some("s".as_ref()))
+            .unwrap()
+            .1;
+        let header = format!("; {}\n", Path::new("src").join("main.bend").display());
+        assert!(listing.starts_with(header.as_bytes()));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "polkavm-engine")]
+    #[test]
+    fn test_built_contracts_dispatch_by_selector() {
+        use bend_pvm::compiler::codegen::metadata::selector_for;
+        use bend_pvm::runtime::env::{ExecutionContext, ExecutionResult};
+        use bend_pvm::runtime::polkavm::PolkaVmEngine;
+
+        let dir = scratch_dir("selectors");
+        contract_package(&dir.join("app"));
+        let workspace = Workspace::load_with_home(&dir.join("app"), &dir.join("home")).unwrap();
+        let release = workspace.root().manifest().profile("release").unwrap();
+        let output = build(&workspace, &release, &LintLevels::default(), &Cfg::new()).unwrap();
+        let blob = fs::read(&output.artifacts[0].binary).unwrap();
+
+        let call = |input: Vec<u8>| {
+            let mut context = ExecutionContext::new_default();
+            context.input = input;
+            PolkaVmEngine::new(context).execute(&blob).unwrap()
+        };
+        let returned = |result: ExecutionResult| match result {
+            ExecutionResult::Success { data, .. } => data,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(
+            returned(call(selector_for("seven()").to_vec())),
+            7u32.to_le_bytes()
+        );
+        let mut input = selector_for("quadruple(u24)").to_vec();
+        input.extend_from_slice(&5u32.to_le_bytes());
+        assert_eq!(returned(call(input)), 20u32.to_le_bytes());
+        assert!(!matches!(
+            call(selector_for("missing()").to_vec()),
+            ExecutionResult::Success { .. }
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
+
+mod template_tests {
+    use bend_pvm::compiler::analyzer::lints::LintLevels;
+    use bend_pvm::compiler::cfg::Cfg;
+    use bend_pvm::deployment::Contract;
+    use bend_pvm::package::{build, test, PackageError, TemplateSource, Workspace, TEMPLATES};
+    use bend_pvm::testing::TestSuite;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bend_tpl_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=bend", "-c", "user.email=bend@example.com"])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn test_embedded_templates_build() {
+        let dir = scratch_dir("embedded");
+        for template in TEMPLATES {
+            let root = dir.join(template.name);
+            let files = TemplateSource::Embedded(template)
+                .scaffold(&root, "app")
+                .unwrap();
+            assert!(files.contains(&PathBuf::from(".github/workflows/ci.yml")));
+
+            let workspace = Workspace::load_with_home(&root, &dir.join("home")).unwrap();
+            let manifest = workspace.root().manifest();
+            assert_eq!(manifest.package().name(), "app");
+            assert_eq!(manifest.package().description(), Some(template.description));
+
+            let mut levels = LintLevels::default();
+            levels.deny_warnings();
+            let profile = manifest.profile("release").unwrap();
+            let output = build(&workspace, &profile, &levels, &Cfg::new())
+                .unwrap_or_else(|e| panic!("{}: {}", template.name, e));
+            assert_eq!(output.artifacts.len(), 1);
+            assert!(output.warnings.is_empty());
+
+            let module = root.join("src").join(format!("{}.bend", template.module));
+            let source = fs::read_to_string(&module).unwrap();
+            assert!(!source.contains("{{"), "{}", template.name);
+            let suite = TestSuite::from_source(template.module, &source).unwrap();
+            assert!(!suite.tests.is_empty(), "{} has no tests", template.name);
+            // What the `build --release --test` step of the CI workflow runs
+            let tests = test(&workspace, &Cfg::new()).unwrap();
+            assert!(tests.passed(), "{}: {:?}", template.name, tests.tests);
+
+            let contract = Contract::compile(template.module, &source).unwrap();
+            assert!(!contract.abi.methods.is_empty());
+            // The constructor the README deploys with takes its arguments
+            let mut deploy = template.deploy.split_whitespace().skip(1);
+            if let Some(constructor) = deploy.next() {
+                let args: Vec<String> = deploy.map(String::from).collect();
+                contract
+                    .call_data(constructor, &args)
+                    .unwrap_or_else(|e| panic!("{}: {}", template.name, e));
+            }
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_template_sources() {
+        assert_eq!(
+            "erc20".parse::<TemplateSource>().unwrap().to_string(),
+            "erc20"
+        );
+        assert_eq!(
+            "git+https://example.com/starter.git#v1".parse::<TemplateSource>(),
+            Ok(TemplateSource::Git {
+                url: "https://example.com/starter.git".to_string(),
+                reference: Some("v1".to_string()),
+            })
+        );
+        let error = "erc1155".parse::<TemplateSource>().unwrap_err();
+        assert!(error.contains("minimal, erc20, erc721, dao, multisig, escrow"));
+
+        let dir = scratch_dir("sources");
+        let minimal: TemplateSource = "minimal".parse().unwrap();
+        assert!(matches!(
+            minimal.scaffold(&dir, "not a name"),
+            Err(PackageError::InvalidName(_))
+        ));
+        minimal.scaffold(&dir, "app").unwrap();
+        assert!(matches!(
+            minimal.scaffold(&dir, "app"),
+            Err(PackageError::AlreadyExists(_))
+        ));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_git_templates() {
+        let dir = scratch_dir("git");
+        let repository = dir.join("starter");
+        fs::create_dir_all(repository.join("src")).unwrap();
+        fs::write(
+            repository.join("bend.toml"),
+            "[package]\nname = \"starter\"\nversion = \"0.2.0\"\n\n[dependencies]\n",
+        )
+        .unwrap();
+        fs::write(
+            repository.join("src").join("main.bend"),
+            "# {{name}}\n\nfn one() -> u24 {\n    return 1;\n}\n",
+        )
+        .unwrap();
+        git(&repository, &["init", "--quiet"]);
+        git(&repository, &["add", "-A"]);
+        git(&repository, &["commit", "--quiet", "-m", "Starter"]);
+        git(&repository, &["tag", "v1"]);
+        fs::write(repository.join("src").join("main.bend"), "broken").unwrap();
+        git(&repository, &["commit", "--quiet", "-am", "Break"]);
+
+        let source: TemplateSource = format!("git+{}#v1", repository.display()).parse().unwrap();
+        let root = dir.join("app");
+        let files = source.scaffold(&root, "app").unwrap();
+        assert_eq!(
+            files,
+            [
+                PathBuf::from("bend.toml"),
+                Path::new("src").join("main.bend")
+            ]
+        );
+        let workspace = Workspace::load_with_home(&root, &dir.join("home")).unwrap();
+        assert_eq!(workspace.root().name(), "app");
+        assert!(fs::read_to_string(root.join("src").join("main.bend"))
+            .unwrap()
+            .starts_with("# app\n"));
+
+        let missing: TemplateSource = format!("git+{}", dir.join("missing").display())
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            missing.scaffold(&dir.join("other"), "other"),
+            Err(PackageError::Fetch { .. })
+        ));
+        assert!(!dir.join("other").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -15,6 +15,7 @@ mod tests {
                 name: "TestContract".to_string(),
                 version: "1.0.0".to_string(),
                 methods: vec![],
+                constructor: None,
                 events: vec![],
                 errors: vec![],
                 state_variables: vec![],
@@ -35,12 +36,14 @@ mod tests {
                 outputs: vec![],
                 state_mutability: StateMutability::NonPayable,
                 payable: false,
+                access: None,
             };
 
             let abi = ContractABI {
                 name: "TestContract".to_string(),
                 version: "1.0.0".to_string(),
                 methods: vec![method],
+                constructor: None,
                 events: vec![],
                 errors: vec![],
                 state_variables: vec![],
@@ -79,6 +82,7 @@ mod tests {
                 outputs: vec![output],
                 state_mutability: StateMutability::NonPayable,
                 payable: false,
+                access: None,
             };
 
             assert_eq!(method.inputs.len(), 1);
@@ -96,6 +100,7 @@ mod tests {
                 outputs: vec![],
                 state_mutability: StateMutability::View,
                 payable: false,
+                access: None,
             };
 
             assert!(method.selector.starts_with("0x"));
@@ -200,6 +205,7 @@ mod tests {
         fn test_error_abi_creation() {
             let error = ErrorABI {
                 name: "InsufficientBalance".to_string(),
+                selector: "0x00000000".to_string(),
                 inputs: vec![],
             };
 
@@ -250,6 +256,7 @@ mod tests {
                 name: "Test".to_string(),
                 version: "1.0.0".to_string(),
                 methods: vec![],
+                constructor: None,
                 events: vec![],
                 errors: vec![],
                 state_variables: vec![],
@@ -299,7 +306,9 @@ mod tests {
                     outputs: vec![],
                     state_mutability: StateMutability::View,
                     payable: false,
+                    access: None,
                 }],
+                constructor: None,
                 events: vec![],
                 errors: vec![],
                 state_variables: vec![],
@@ -314,4 +323,483 @@ mod tests {
             assert_eq!(parsed.methods.len(), original.methods.len());
         }
     }
+
+    mod generated_event_abi_tests {
+        use super::*;
+        use bend_pvm::compiler::codegen::metadata::{
+            build_metadata, collect_event_metadata, compute_event_topic,
+        };
+        use std::collections::HashMap;
+
+        #[test]
+        fn test_generate_abi_includes_events() {
+            let program = bend_pvm::parse_source(
+                r#"
+                event Transfer {
+                    indexed sender: u24,
+                    indexed receiver: u24,
+                    amount: u24,
+                }
+
+                event Approval {
+                    owner: u24,
+                }
+                "#,
+            )
+            .unwrap();
+
+            let mut metadata = build_metadata(
+                "Token",
+                "1.0.0",
+                &[],
+                HashMap::new(),
+                HashMap::new(),
+                HashMap::new(),
+            );
+            metadata.events = collect_event_metadata(&program);
+
+            let transfer = &metadata.events["Transfer"];
+            assert_eq!(transfer.signature, "Transfer(u24,u24,u24)");
+            assert_eq!(transfer.topic, compute_event_topic("Transfer(u24,u24,u24)"));
+
+            let abi = generate_abi(&metadata);
+            let names: Vec<&str> = abi.events.iter().map(|e| e.name.as_str()).collect();
+            assert_eq!(names, vec!["Approval", "Transfer"]);
+
+            let indexed: Vec<Option<bool>> =
+                abi.events[1].inputs.iter().map(|i| i.indexed).collect();
+            assert_eq!(indexed, vec![Some(true), Some(true), Some(false)]);
+        }
+    }
+
+    mod generated_error_abi_tests {
+        use super::*;
+        use bend_pvm::compiler::codegen::metadata::{
+            build_metadata, collect_error_metadata, selector_for,
+        };
+        use std::collections::HashMap;
+
+        #[test]
+        fn test_generate_abi_includes_errors() {
+            let program = bend_pvm::parse_source(
+                r#"
+                type TokenError {
+                    InsufficientBalance(needed: u24, available: u24),
+                    Paused,
+                }
+
+                type Unused {
+                    Never,
+                }
+
+                fn withdraw(amount: u24) -> u24 {
+                    if amount > 10 {
+                        revert(TokenError/InsufficientBalance(amount, 10));
+                    } else {
+                        return amount;
+                    }
+                }
+                "#,
+            )
+            .unwrap();
+
+            let mut metadata = build_metadata(
+                "Token",
+                "1.0.0",
+                &[],
+                HashMap::new(),
+                HashMap::new(),
+                HashMap::new(),
+            );
+            metadata.errors = collect_error_metadata(&program);
+
+            let insufficient = &metadata.errors["TokenError/InsufficientBalance"];
+            assert_eq!(
+                insufficient.signature,
+                "TokenError/InsufficientBalance(u24,u24)"
+            );
+            assert_eq!(
+                insufficient.selector,
+                selector_for("TokenError/InsufficientBalance(u24,u24)")
+            );
+
+            let abi = generate_abi(&metadata);
+            let names: Vec<&str> = abi.errors.iter().map(|e| e.name.as_str()).collect();
+            assert_eq!(
+                names,
+                vec![
+                    "Error",
+                    "Panic",
+                    "TokenError/InsufficientBalance",
+                    "TokenError/Paused"
+                ]
+            );
+
+            let inputs: Vec<(&str, &str)> = abi.errors[2]
+                .inputs
+                .iter()
+                .map(|i| (i.name.as_str(), i.type_.as_str()))
+                .collect();
+            assert_eq!(inputs, vec![("needed", "u24"), ("available", "u24")]);
+            assert_eq!(
+                abi.errors[0].selector,
+                format!("0x{}", hex::encode(selector_for("Error(String)")))
+            );
+        }
+
+        #[test]
+        fn test_error_definitions_are_always_registered() {
+            let program = bend_pvm::parse_source(
+                r#"
+                error VaultError {
+                    Locked(until: u24),
+                    Closed,
+                }
+
+                fn unlock() -> Result<u24, VaultError> {
+                    return Result/Ok(1);
+                }
+                "#,
+            )
+            .unwrap();
+
+            let errors = collect_error_metadata(&program);
+            let mut names: Vec<&str> = errors.keys().map(String::as_str).collect();
+            names.sort();
+            assert_eq!(
+                names,
+                vec!["Error", "Panic", "VaultError/Closed", "VaultError/Locked"]
+            );
+            assert_eq!(
+                errors["VaultError/Locked"].selector,
+                selector_for("VaultError/Locked(u24)")
+            );
+        }
+    }
+
+    mod generated_method_abi_tests {
+        use super::*;
+        use bend_pvm::compiler::codegen::metadata::{
+            build_metadata, collect_function_metadata, selector_for,
+        };
+        use std::collections::HashMap;
+
+        #[test]
+        fn test_generate_abi_reflects_attributes() {
+            let program = bend_pvm::parse_source(
+                r#"
+                #[message]
+                #[payable]
+                fn deposit() -> u24 {
+                    return 1;
+                }
+
+                #[message]
+                #[view, selector(0x01020304)]
+                fn total(a: u24) -> u24 {
+                    return a;
+                }
+
+                #[message]
+                #[pure]
+                fn double(a: u24) -> u24 {
+                    return a * 2;
+                }
+
+                #[message]
+                fn reset() -> u24 {
+                    return 0;
+                }
+
+                #[test]
+                fn check() -> u24 {
+                    return 0;
+                }
+                "#,
+            )
+            .unwrap();
+
+            let functions = collect_function_metadata(&program);
+            assert!(!functions.contains_key("check"));
+            assert_eq!(functions["total"].selector, [1, 2, 3, 4]);
+            assert_eq!(functions["double"].selector, selector_for("double(u24)"));
+
+            let metadata = build_metadata(
+                "Vault",
+                "1.0.0",
+                &[],
+                functions,
+                HashMap::new(),
+                HashMap::new(),
+            );
+            let abi = generate_abi(&metadata);
+            let methods: Vec<(&str, StateMutability, bool)> = abi
+                .methods
+                .iter()
+                .map(|m| (m.name.as_str(), m.state_mutability, m.payable))
+                .collect();
+            assert_eq!(
+                methods,
+                vec![
+                    ("deposit", StateMutability::Payable, true),
+                    ("double", StateMutability::Pure, false),
+                    ("reset", StateMutability::NonPayable, false),
+                    ("total", StateMutability::View, false),
+                ]
+            );
+            assert_eq!(abi.methods[3].selector, "0x01020304");
+        }
+    }
+
+    mod storage_layout_tests {
+        use super::*;
+        use bend_pvm::compiler::codegen::metadata::{
+            build_metadata, collect_storage_layout, compute_storage_key,
+        };
+        use std::collections::HashMap;
+
+        #[test]
+        fn test_generate_abi_includes_storage_layout() {
+            let program = bend_pvm::parse_source(
+                r#"
+                storage {
+                    total_supply: u24,
+                    owner: u24,
+                }
+                "#,
+            )
+            .unwrap();
+
+            let mut metadata = build_metadata(
+                "Token",
+                "1.0.0",
+                &[],
+                HashMap::new(),
+                HashMap::new(),
+                HashMap::new(),
+            );
+            metadata.storage_layout = collect_storage_layout(&program);
+
+            assert_eq!(metadata.storage_layout.len(), 2);
+            assert_eq!(
+                metadata.storage_layout[0].key,
+                compute_storage_key("total_supply")
+            );
+
+            let abi = generate_abi(&metadata);
+            let variables: Vec<(&str, &str)> = abi
+                .state_variables
+                .iter()
+                .map(|v| (v.name.as_str(), v.type_.as_str()))
+                .collect();
+            assert_eq!(variables, vec![("total_supply", "u24"), ("owner", "u24")]);
+        }
+    }
+
+    mod bindgen_tests {
+        use super::*;
+        use bend_pvm::compiler::codegen::metadata::{
+            build_metadata, collect_error_metadata, collect_function_metadata,
+        };
+        use bend_pvm::compiler::polkavm::bindgen::{generate_bindings, BindgenError, Language};
+        use std::collections::HashMap;
+
+        fn vault_abi() -> ContractABI {
+            let program = bend_pvm::parse_source(
+                r#"
+                error VaultError {
+                    Locked,
+                }
+
+                #[message]
+                #[view, selector(0x01020304)]
+                fn balance_of(owner: Address) -> u128 {
+                    return 0;
+                }
+
+                #[message]
+                #[payable]
+                fn deposit(amount: u64, memo: Bytes) -> Bool {
+                    return true;
+                }
+
+                #[message]
+                fn query() -> u24 {
+                    return 1;
+                }
+                "#,
+            )
+            .unwrap();
+            let mut metadata = build_metadata(
+                "vault",
+                "1.0.0",
+                &[],
+                collect_function_metadata(&program),
+                HashMap::new(),
+                HashMap::new(),
+            );
+            metadata.errors = collect_error_metadata(&program);
+            generate_abi(&metadata)
+        }
+
+        #[test]
+        fn test_rust_bindings() {
+            let rust = generate_bindings(&vault_abi(), Language::Rust).unwrap();
+            assert!(rust.contains("pub struct BalanceOf {\n    pub owner: [u8; 32],\n}"));
+            assert!(rust.contains("const SELECTOR: [u8; 4] = [0x01, 0x02, 0x03, 0x04];"));
+            assert!(rust
+                .contains("pub struct Deposit {\n    pub amount: u64,\n    pub memo: Vec<u8>,\n}"));
+            assert!(rust.contains("const PAYABLE: bool = true;"));
+            assert!(rust.contains("pub struct VaultClient<T>"));
+            assert!(rust.contains("\"VaultError/Locked\""));
+
+            // Views are only queried, payable messages take the value sent
+            assert!(rust.contains(
+                "pub async fn balance_of(&self, owner: [u8; 32]) -> Result<u128, T::Error>"
+            ));
+            assert!(!rust.contains("call_balance_of"));
+            assert!(rust.contains(
+                "pub async fn call_deposit(&self, amount: u64, memo: Vec<u8>, value: u128)"
+            ));
+
+            // A message named like a client method gets a name of its own
+            assert!(rust.contains("pub struct Query;"));
+            assert!(rust.contains("pub async fn call_query(&self)"));
+            assert!(rust.contains("pub async fn query_message(&self) -> Result<u32, T::Error>"));
+        }
+
+        #[test]
+        fn test_typescript_bindings() {
+            let ts = generate_bindings(&vault_abi(), Language::TypeScript).unwrap();
+            assert!(ts.contains("import type { ApiPromise } from \"@polkadot/api\";"));
+            assert!(ts.contains("export class VaultClient {"));
+            assert!(ts.contains("encode(owner: Uint8Array): Uint8Array {"));
+            assert!(ts.contains("writer.fixed(owner, 32);"));
+            assert!(ts.contains("writer.uint(amount, 8);\n      writer.vec(memo);"));
+            assert!(ts.contains("return reader.uint(16);"));
+            assert!(ts.contains(
+                "async deposit(origin: string, amount: bigint, memo: Uint8Array, value: bigint = 0n): Promise<boolean>"
+            ));
+            assert!(ts.contains("async sendDeposit(signer: KeyringPair,"));
+            assert!(!ts.contains("sendBalanceOf"));
+            assert!(ts.contains("async query_(origin: string): Promise<number>"));
+        }
+
+        #[test]
+        fn test_bindings_reject_unsupported_arguments() {
+            let mut abi = vault_abi();
+            abi.methods[0].inputs[0].type_ = "List<u24>".to_string();
+            let error = generate_bindings(&abi, Language::Rust).unwrap_err();
+            assert!(matches!(
+                error,
+                BindgenError::UnsupportedType { ref method, .. } if method == "balance_of"
+            ));
+            assert_eq!("ts".parse::<Language>(), Ok(Language::TypeScript));
+            assert!("go".parse::<Language>().is_err());
+        }
+    }
+
+    mod indexer_tests {
+        use super::*;
+        use bend_pvm::compiler::codegen::metadata::{
+            build_metadata, collect_event_metadata, compute_event_topic,
+        };
+        use bend_pvm::compiler::polkavm::bindgen::BindgenError;
+        use bend_pvm::compiler::polkavm::indexer::{generate_indexer, Indexer};
+        use std::collections::HashMap;
+        use std::path::PathBuf;
+
+        fn token_abi(source: &str) -> ContractABI {
+            let program = bend_pvm::parse_source(source).unwrap();
+            let mut metadata = build_metadata(
+                "token",
+                "1.0.0",
+                &[],
+                HashMap::new(),
+                HashMap::new(),
+                HashMap::new(),
+            );
+            metadata.events = collect_event_metadata(&program);
+            generate_abi(&metadata)
+        }
+
+        const EVENTS: &str = r#"
+            event Transfer {
+                indexed sender: Address,
+                indexed receiver: Address,
+                value: u24,
+                approved: Bool,
+            }
+
+            event Minted {
+                indexed amount: u128,
+                id: u24,
+                supply: u256,
+            }
+        "#;
+
+        #[test]
+        fn test_subsquid_project() {
+            let files = generate_indexer(&token_abi(EVENTS), Indexer::Subsquid).unwrap();
+            let paths: Vec<&PathBuf> = files.iter().map(|file| &file.path).collect();
+            assert_eq!(
+                paths,
+                [
+                    &PathBuf::from("schema.graphql"),
+                    &PathBuf::from("src/token.ts")
+                ]
+            );
+
+            let schema = &files[0].contents;
+            assert!(
+                schema.contains("# `Transfer(Address,Address,u24,Bool)`\ntype Transfer @entity {")
+            );
+            assert!(schema.contains("  timestamp: DateTime!\n"));
+            assert!(schema.contains(
+                "  sender: String! @index\n  receiver: String! @index\n  value: Int!\n  approved: Boolean!\n}"
+            ));
+            assert!(schema.contains("  amount: BigInt! @index\n  id_: Int!\n  supply: BigInt!\n}"));
+
+            let mapping = &files[1].contents;
+            let topic = hex::encode(compute_event_topic("Transfer(Address,Address,u24,Bool)"));
+            assert!(mapping.contains(&format!("export const TRANSFER_TOPIC = \"0x{}\";", topic)));
+            assert!(mapping.contains("import { Minted, Transfer } from \"./model\";"));
+            assert!(mapping.contains(
+                "    sender: toHex(topics[1]),\n    receiver: toHex(topics[2]),\n    value: Number(uint(data.subarray(0, 4))),\n    approved: data[4] !== 0,\n"
+            ));
+            assert!(mapping.contains("    amount: uint(topics[1].subarray(0, 16)),\n"));
+            assert!(mapping.contains("    supply: uint(data.subarray(4, 36)),\n"));
+            assert!(mapping.contains(
+                "      case MINTED_TOPIC:\n        minted.push(new Minted({ ...columns, ...decodeMinted(topics, data) }));"
+            ));
+            assert!(mapping.contains("  await store.insert(transfer);"));
+        }
+
+        #[test]
+        fn test_subquery_project() {
+            let files = generate_indexer(&token_abi(EVENTS), Indexer::SubQuery).unwrap();
+            assert_eq!(files[1].path, PathBuf::from("src/mappings/token.ts"));
+            assert!(files[0].contents.contains("  timestamp: Date!\n"));
+            let mapping = &files[1].contents;
+            assert!(mapping.contains("import type { SubstrateEvent } from \"@subql/types\";"));
+            assert!(mapping.contains(
+                "export async function handleContractEmitted(event: SubstrateEvent): Promise<void> {"
+            ));
+            assert!(mapping.contains(
+                "      await Transfer.create({ ...columns, ...decodeTransfer(topicBytes, dataBytes) }).save();"
+            ));
+        }
+
+        #[test]
+        fn test_indexer_needs_events() {
+            let error = generate_indexer(
+                &token_abi("fn main() -> u24 { return 0; }"),
+                Indexer::Subsquid,
+            )
+            .unwrap_err();
+            assert!(matches!(error, BindgenError::NoEvents(ref name) if name == "token"));
+            assert_eq!("subql".parse::<Indexer>(), Ok(Indexer::SubQuery));
+            assert!("graph".parse::<Indexer>().is_err());
+        }
+    }
 }
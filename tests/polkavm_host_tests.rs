@@ -22,6 +22,8 @@ mod tests {
             assert_eq!(HostFunction::GetCallValue as u32, 11);
             assert_eq!(HostFunction::GetBlockNumber as u32, 12);
             assert_eq!(HostFunction::GetBlockTimestamp as u32, 13);
+            assert_eq!(HostFunction::GetBalance as u32, 14);
+            assert_eq!(HostFunction::Random as u32, 15);
         }
 
         #[test]
@@ -31,6 +33,7 @@ mod tests {
             assert_eq!(HostFunction::DelegateCall as u32, 22);
             assert_eq!(HostFunction::Create as u32, 23);
             assert_eq!(HostFunction::Create2 as u32, 24);
+            assert_eq!(HostFunction::Transfer as u32, 25);
         }
 
         #[test]
@@ -40,6 +43,7 @@ mod tests {
             assert_eq!(HostFunction::Sha256 as u32, 32);
             assert_eq!(HostFunction::Ripemd160 as u32, 33);
             assert_eq!(HostFunction::EcdsaRecover as u32, 34);
+            assert_eq!(HostFunction::Sr25519Verify as u32, 35);
         }
 
         #[test]
@@ -59,6 +63,13 @@ mod tests {
             assert_eq!(HostFunction::Abort as u32, 60);
             assert_eq!(HostFunction::Return as u32, 61);
             assert_eq!(HostFunction::Revert as u32, 62);
+            assert_eq!(HostFunction::Terminate as u32, 63);
+            assert_eq!(HostFunction::SetCodeHash as u32, 64);
+        }
+
+        #[test]
+        fn test_metering_operations() {
+            assert_eq!(HostFunction::ChargeGas as u32, 70);
         }
     }
 
@@ -92,6 +103,8 @@ mod tests {
             assert!(bindings.contains("get_call_value"));
             assert!(bindings.contains("get_block_number"));
             assert!(bindings.contains("get_block_timestamp"));
+            assert!(bindings.contains("get_balance"));
+            assert!(bindings.contains(".macro random"));
         }
 
         #[test]
@@ -99,6 +112,9 @@ mod tests {
             let bindings = generate_host_bindings();
             assert!(bindings.contains(".macro call"));
             assert!(bindings.contains(".macro static_call"));
+            assert!(bindings.contains(".macro delegate_call"));
+            assert!(bindings.contains(".macro instantiate"));
+            assert!(bindings.contains(".macro transfer"));
         }
 
         #[test]
@@ -119,6 +135,9 @@ mod tests {
             let bindings = generate_host_bindings();
             assert!(bindings.contains(".macro finish"));
             assert!(bindings.contains(".macro revert"));
+            assert!(bindings.contains(".macro terminate"));
+            assert!(bindings.contains(".macro set_code_hash"));
+            assert!(bindings.contains(".macro charge_gas"));
         }
 
         #[test]
@@ -197,4 +216,78 @@ mod tests {
             assert!(prelude.contains("Common utility functions"));
         }
     }
+
+    mod chain_extension_tests {
+        use super::*;
+
+        fn xcm_send() -> ChainExtension {
+            ChainExtension::new("xcm_send")
+                .with_param("dest", ExtensionType::U24)
+                .with_param("message", ExtensionType::U24)
+                .with_gas(5_000)
+        }
+
+        #[test]
+        fn test_register_assigns_ids() {
+            let mut registry = ChainExtensionRegistry::new();
+            assert_eq!(registry.register(xcm_send()).unwrap(), CHAIN_EXTENSION_BASE);
+            let oracle = ChainExtension::new("oracle_read").with_param("feed", ExtensionType::U24);
+            assert_eq!(registry.register(oracle).unwrap(), CHAIN_EXTENSION_BASE + 1);
+
+            assert_eq!(registry.len(), 2);
+            assert_eq!(registry.get("xcm_send").unwrap().gas, 5_000);
+            assert_eq!(
+                registry.by_id(CHAIN_EXTENSION_BASE + 1).unwrap().name,
+                "oracle_read"
+            );
+        }
+
+        #[test]
+        fn test_register_rejects_invalid_extensions() {
+            let mut registry = ChainExtensionRegistry::new();
+            registry.register(xcm_send()).unwrap();
+
+            assert_eq!(
+                registry.register(xcm_send()),
+                Err(HostError::DuplicateExtension("xcm_send".to_string()))
+            );
+            assert!(matches!(
+                registry.register(ChainExtension::new("xcm-send")),
+                Err(HostError::InvalidName(_))
+            ));
+            assert!(matches!(
+                registry.register(ChainExtension::new("noop").with_param("x", ExtensionType::Unit)),
+                Err(HostError::InvalidParameter(_))
+            ));
+
+            let mut wide = ChainExtension::new("wide");
+            for i in 0..=MAX_CHAIN_EXTENSION_ARGS {
+                wide = wide.with_param(&format!("a{}", i), ExtensionType::U24);
+            }
+            assert!(matches!(
+                registry.register(wide),
+                Err(HostError::TooManyArguments { .. })
+            ));
+            assert_eq!(registry.len(), 1);
+        }
+
+        #[test]
+        fn test_generated_declarations_and_bindings() {
+            let mut registry = ChainExtensionRegistry::new();
+            registry.register(xcm_send()).unwrap();
+            registry
+                .register(ChainExtension::new("log_value").with_return(ExtensionType::Unit))
+                .unwrap();
+
+            let declarations = registry.generate_extern_declarations();
+            assert!(declarations.contains("fn xcm_send(dest: u24, message: u24) -> u24\n"));
+            assert!(declarations.contains("fn log_value()\n"));
+            assert!(declarations.contains("# Host call 1000, 5000 gas"));
+
+            let bindings = registry.generate_bindings();
+            assert!(bindings.contains(".macro xcm_send dest message"));
+            assert!(bindings.contains("li a7, 1000  # xcm_send"));
+            assert!(bindings.contains("mv a1, \\message"));
+        }
+    }
 }
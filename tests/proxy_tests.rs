@@ -1,4 +1,4 @@
-use bend_pvm::runtime::proxy::{Proxy, ProxyError, ProxyState, VersionInfo};
+use bend_pvm::runtime::proxy::{ProxyError, ProxyState, VersionInfo};
 
 fn create_test_proxy() -> ProxyState {
     ProxyState::new("impl_v1".to_string())
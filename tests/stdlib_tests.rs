@@ -42,3 +42,162 @@ fn test_load_crypto_module() {
 
     assert!(has_keccak);
 }
+
+#[test]
+fn test_bend_modules_type_check() {
+    use bend_pvm::compiler::analyzer::type_checker::TypeChecker;
+    use bend_pvm::compiler::module::{Module, ModuleSystem};
+    use bend_pvm::compiler::parser::ast::Program;
+    use bend_pvm::stdlib::modules;
+    use std::collections::HashSet;
+
+    // Imported definitions come first, as functions are checked in order
+    fn collect(module: &Module, seen: &mut HashSet<String>, program: &mut Program) {
+        if !seen.insert(module.name.to_string()) {
+            return;
+        }
+        for imported in module.imports.values() {
+            collect(imported, seen, program);
+        }
+        program
+            .definitions
+            .extend(module.ast.definitions.iter().cloned());
+    }
+
+    for path in modules::modules() {
+        let mut system = ModuleSystem::new();
+        let module = system
+            .load_module(modules::module_path(path).unwrap())
+            .unwrap();
+
+        let mut program = Program {
+            imports: Vec::new(),
+            definitions: Vec::new(),
+            location: module.ast.location.clone(),
+        };
+        collect(&module, &mut HashSet::new(), &mut program);
+        if let Err(e) = TypeChecker::new().check_program(&program) {
+            panic!("{} does not type check: {}", path, e);
+        }
+    }
+}
+
+#[test]
+fn test_imported_modules_are_shared() {
+    use bend_pvm::compiler::module::ModuleSystem;
+    use bend_pvm::stdlib::modules;
+    use std::sync::Arc;
+
+    let mut system = ModuleSystem::new();
+    let loaded: Vec<_> = modules::modules()
+        .map(|path| {
+            system
+                .load_module(modules::module_path(path).unwrap())
+                .unwrap()
+        })
+        .collect();
+
+    // Every importer holds the one copy of the module it imports
+    assert!(loaded.iter().any(|module| !module.imports.is_empty()));
+    for module in &loaded {
+        for imported in module.imports.values() {
+            let again = system.load_module(&imported.path).unwrap();
+            assert!(Arc::ptr_eq(imported, &again), "{}", imported.name);
+        }
+        for name in module.namespace.defined_names() {
+            assert!(module.namespace.lookup(&name).is_some(), "{}", name);
+        }
+    }
+}
+
+#[test]
+fn test_bend_modules_generate_code() {
+    use bend_pvm::compiler::analyzer::linearity::Linearity;
+    use bend_pvm::compiler::codegen::risc_v::RiscVCodegen;
+    use bend_pvm::compiler::lowering::Lowering;
+    use bend_pvm::compiler::module::ModuleSystem;
+    use bend_pvm::stdlib::modules;
+
+    // Contracts link the functions they use, so every one must lower
+    for path in modules::modules() {
+        let mut system = ModuleSystem::new();
+        let module = system
+            .load_module(modules::module_path(path).unwrap())
+            .unwrap();
+        let mut program = module.program();
+        Lowering::new().lower_program(&mut program);
+        Linearity::new().lower_program(&mut program);
+        if let Err(e) = RiscVCodegen::new().generate(&program) {
+            panic!("{} does not generate code: {}", path, e);
+        }
+    }
+}
+
+#[test]
+fn test_contract_calls_the_prelude() {
+    use bend_pvm::testing::{TestResult, TestSuite};
+    use bend_pvm::{compile_to_artifacts, CompilerOptions};
+
+    let source = r#"
+fn double(x: u24) -> u24 {
+    return x * 2;
+}
+
+#[message]
+fn main() -> Bool {
+    r = Result/Ok(1);
+    return Result/is_ok(r);
+}
+
+#[test]
+fn test_option() -> u24 {
+    assert(Option/unwrap_or(Option/Some(5), 0) == 5, "some");
+    assert(Option/unwrap_or(Option/None, 7) == 7, "none");
+    assert(Option/is_some(Option/Some(1)), "is_some");
+    return 0;
+}
+
+#[test]
+fn test_result() -> u24 {
+    assert(main(), "ok");
+    assert(!Result/is_ok(Result/Err(2)), "err");
+    assert(Result/unwrap_or(Result/Err(2), 9) == 9, "unwrap_or");
+    return 0;
+}
+
+#[test]
+fn test_list() -> u24 {
+    xs = List/range(1, 5);
+    assert(List/length(xs) == 4, "length");
+    assert(List/sum(xs) == 10, "sum");
+    assert(List/contains(xs, 3), "contains");
+    assert(!List/contains(xs, 5), "does not contain");
+    assert(List/sum(List/map(xs, double)) == 20, "map");
+    return 0;
+}
+
+#[test]
+#[should_revert]
+fn test_wrong_sum() -> u24 {
+    assert(List/sum(List/range(1, 5)) == 11, "sum");
+    return 0;
+}
+"#;
+    let result = compile_to_artifacts("prelude", source, &CompilerOptions::default()).unwrap();
+    assert!(!result.blob.is_empty());
+    // The library functions are linked in, but no message reaches them
+    let abi = result.abi.unwrap();
+    assert!(abi.methods.iter().any(|method| method.name == "main"));
+    assert!(!abi.methods.iter().any(|method| method.name.contains('/')));
+
+    let results = TestSuite::from_source("prelude", source).unwrap().run_all();
+    assert_eq!(results.len(), 4);
+    for (name, result) in &results {
+        assert!(
+            matches!(result, TestResult::Passed { .. }),
+            "{}: {:?}",
+            name,
+            result
+        );
+    }
+}
@@ -1,7 +1,9 @@
 use std::path::{Path, PathBuf};
 use std::fs;
 use std::io::{self, Read, Write};
+use std::thread;
 use clap::{Parser, Subcommand};
+use bend_pvm::formatter::{collect_files, format_files, unified_diff, Formatter, FormatterConfig};
 
 #[derive(Parser, Debug)]
 #[command(name = "bend-fmt")]
@@ -15,27 +17,37 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    /// Format a file
+    /// Format files, with the settings of the closest `.bendfmt.toml` or
+    /// the `[fmt]` table of `bend.toml`
     Format {
-        /// File to format
+        /// Files, directories or glob patterns to format, or `-` for stdin;
+        /// directories are searched for .bend files .gitignore does not ignore
         #[arg(required = true)]
-        file: PathBuf,
+        paths: Vec<PathBuf>,
 
-        /// Output file (defaults to overwriting the input file)
+        /// Output file for a single input (defaults to overwriting it)
         #[arg(short, long)]
         output: Option<PathBuf>,
 
-        /// Check if the file is formatted correctly without modifying it
+        /// Check if the files are formatted correctly without modifying them
         #[arg(short, long)]
         check: bool,
 
-        /// Indent using tabs instead of spaces
+        /// Print the changes formatting would make as unified diffs
+        #[arg(long, requires = "check")]
+        diff: bool,
+
+        /// Number of files formatted at once (defaults to the CPU count)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Indent using tabs instead of spaces, whatever the settings say
         #[arg(short, long)]
         tabs: bool,
 
-        /// Number of spaces for indentation
-        #[arg(short, long, default_value_t = 4)]
-        indent: usize,
+        /// Number of spaces for indentation, overriding the settings
+        #[arg(short, long)]
+        indent: Option<usize>,
     },
 }
 
@@ -43,43 +55,65 @@ fn main() -> io::Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Format { file, output, check, tabs, indent } => {
-            let content = if file.as_os_str() == "-" {
-                // Read from stdin
-                let mut buffer = String::new();
-                io::stdin().read_to_string(&mut buffer)?;
-                buffer
-            } else {
-                // Read from file
-                fs::read_to_string(&file)?
+        Commands::Format { paths, output, check, diff, jobs, tabs, indent } => {
+            let adjust = |config: &mut FormatterConfig| {
+                if tabs {
+                    config.use_tabs = true;
+                }
+                if let Some(indent) = indent {
+                    config.indent_size = indent;
+                }
             };
 
-            // Format the content
-            let formatted = format_content(&content, tabs, indent)?;
+            if paths.len() == 1 && paths[0].as_os_str() == "-" {
+                return format_stdin(output, check, diff, &adjust);
+            }
 
-            if check {
-                // Check if the file is already formatted correctly
-                if content == formatted {
-                    println!("{} is already formatted correctly.", file.display());
-                    return Ok(());
+            let files = collect_files(&paths).map_err(invalid)?;
+            if output.is_some() && (files.len() != 1 || check) {
+                return Err(invalid("--output takes a single file and cannot be used with --check".to_string()));
+            }
+
+            let jobs = jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+            let mut failed = 0;
+            let mut changed = 0;
+            for file in format_files(&files, jobs, &adjust) {
+                let formatted = match &file.result {
+                    Ok(formatted) => formatted,
+                    Err(e) => {
+                        eprintln!("Failed to format {}: {}", file.path.display(), e);
+                        failed += 1;
+                        continue;
+                    }
+                };
+                if let Some(output_file) = &output {
+                    fs::write(output_file, formatted)?;
+                    println!("Formatted {} and wrote to {}.", file.path.display(), output_file.display());
+                    continue;
+                }
+                if !file.changed() {
+                    continue;
+                }
+                changed += 1;
+                if diff {
+                    let name = file.path.display().to_string();
+                    print!("{}", unified_diff(&name, &file.source, formatted));
+                } else if check {
+                    eprintln!("{} would be reformatted.", file.path.display());
                 } else {
-                    eprintln!("{} would be reformatted.", file.display());
-                    return Err(io::Error::new(io::ErrorKind::Other, "File is not formatted correctly"));
+                    fs::write(&file.path, formatted)?;
+                    println!("Formatted {}.", file.path.display());
                 }
             }
 
-            // Write the formatted content
-            if let Some(output_file) = output {
-                // Write to output file
-                fs::write(&output_file, formatted)?;
-                println!("Formatted {} and wrote to {}.", file.display(), output_file.display());
-            } else if file.as_os_str() == "-" {
-                // Write to stdout
-                io::stdout().write_all(formatted.as_bytes())?;
-            } else {
-                // Overwrite input file
-                fs::write(&file, formatted)?;
-                println!("Formatted {}.", file.display());
+            if failed > 0 {
+                return Err(invalid(format!("{} files could not be formatted", failed)));
+            }
+            if check && changed > 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("{} of {} files are not formatted correctly", changed, files.len()),
+                ));
             }
         }
     }
@@ -87,99 +121,40 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
-/// Format Bend code content
-fn format_content(content: &str, use_tabs: bool, indent_size: usize) -> io::Result<String> {
-    // This is a simplified formatter that just handles basic indentation
-    // A real formatter would parse the code and format it more comprehensively
-    
-    let mut formatted = String::new();
-    let mut indent_level = 0;
-    let mut in_string = false;
-    let mut in_comment = false;
-    let mut in_multiline_comment = false;
-    
-    let indent_str = if use_tabs {
-        "\t".to_string()
-    } else {
-        " ".repeat(indent_size)
-    };
-    
-    let lines = content.lines().collect::<Vec<_>>();
-    
-    for (i, line) in lines.iter().enumerate() {
-        let trimmed = line.trim();
-        
-        // Skip empty lines
-        if trimmed.is_empty() {
-            formatted.push_str("\n");
-            continue;
-        }
-        
-        // Handle comments
-        if trimmed.starts_with("#") && !in_string {
-            formatted.push_str(line);
-            formatted.push_str("\n");
-            continue;
-        }
-        
-        // Check for multiline comment start
-        if trimmed.starts_with("#{") && !in_string && !in_multiline_comment {
-            in_multiline_comment = true;
-            formatted.push_str(line);
-            formatted.push_str("\n");
-            continue;
-        }
-        
-        // Check for multiline comment end
-        if trimmed.contains("}#") && in_multiline_comment {
-            in_multiline_comment = false;
-            formatted.push_str(line);
-            formatted.push_str("\n");
-            continue;
-        }
-        
-        // Inside multiline comment
-        if in_multiline_comment {
-            formatted.push_str(line);
-            formatted.push_str("\n");
-            continue;
-        }
-        
-        // Handle indentation for next line
-        if trimmed.ends_with(":") {
-            indent_level += 1;
-        }
-        
-        // Reduce indentation for closing blocks
-        if trimmed == "else:" || trimmed.starts_with("elif ") || trimmed.starts_with("case ") {
-            indent_level = indent_level.saturating_sub(1);
-        }
-        
-        // Add indentation
-        for _ in 0..indent_level {
-            formatted.push_str(&indent_str);
+/// Format stdin to stdout, or check it
+fn format_stdin(
+    output: Option<PathBuf>,
+    check: bool,
+    diff: bool,
+    adjust: &dyn Fn(&mut FormatterConfig),
+) -> io::Result<()> {
+    let mut content = String::new();
+    io::stdin().read_to_string(&mut content)?;
+
+    // Settings for stdin are looked up from the working directory
+    let mut config = FormatterConfig::discover(Path::new("."))
+        .map_err(invalid)?
+        .unwrap_or_default();
+    adjust(&mut config);
+    let formatted = Formatter::with_config(config)
+        .format_source(&content)
+        .map_err(invalid)?;
+
+    if check {
+        if content == formatted {
+            return Ok(());
         }
-        
-        // Add the line content
-        formatted.push_str(trimmed);
-        formatted.push_str("\n");
-        
-        // Check for block end
-        if i + 1 < lines.len() {
-            let next_line = lines[i + 1].trim();
-            if !next_line.is_empty() && !next_line.starts_with("#") && 
-               !trimmed.ends_with(":") && !trimmed.ends_with(",") &&
-               (next_line.starts_with("else:") || next_line.starts_with("elif ") || 
-                next_line.starts_with("case ")) {
-                indent_level = indent_level.saturating_sub(1);
-            }
+        if diff {
+            print!("{}", unified_diff("-", &content, &formatted));
         }
+        return Err(io::Error::new(io::ErrorKind::Other, "stdin is not formatted correctly"));
     }
-    
-    // Remove trailing newline
-    if formatted.ends_with("\n") {
-        formatted.truncate(formatted.len() - 1);
+    match output {
+        Some(output_file) => fs::write(output_file, formatted),
+        None => io::stdout().write_all(formatted.as_bytes()),
     }
-    
-    Ok(formatted)
-}
\ No newline at end of file
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,785 @@
+//! Quick fixes and refactorings
+//!
+//! Fixes the compiler suggests with a diagnostic are offered as they are.
+//! Names the type checker reports as undefined can be imported from the
+//! standard library or from another module the document can import.
+//! Locals that are never read can be prefixed with `_`, functions without
+//! an annotated result can be given the one found for their body, a
+//! `match` can be completed with arms for the variants it leaves out, and
+//! unchecked functions and blocks can be turned back into checked
+//! arithmetic.
+
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Diagnostic, Position, Range, TextEdit, Url,
+    WorkspaceEdit,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use bend_pvm::compiler::analyzer::type_checker::TypeInfo;
+use bend_pvm::compiler::lexer::lexer::BendLexer;
+use bend_pvm::compiler::lexer::token::Token;
+use bend_pvm::compiler::module::cache::canonical;
+use bend_pvm::compiler::parser::ast::{
+    Block, Definition, Expr, Import, Pattern, Program, Statement, TypeVariant, Visibility,
+};
+use bend_pvm::compiler::parser::parser::Parser;
+use bend_pvm::stdlib::modules;
+
+use crate::diagnostics;
+use crate::documents::{Document, DocumentStore};
+use crate::hover;
+use crate::rename;
+use crate::symbols::{DefinitionKind, LineIndex, SymbolIndex};
+
+/// Messages of the errors naming something that is not in scope
+const UNDEFINED: &[&str] = &[
+    "Undefined variable '",
+    "Undefined type '",
+    "Undefined constructor '",
+];
+
+/// Code actions for `range`, fixing any of `diagnostics` that can be fixed
+pub fn code_actions(
+    documents: &DocumentStore,
+    uri: &Url,
+    range: Range,
+    diagnostics: &[Diagnostic],
+) -> Vec<CodeActionOrCommand> {
+    let Some(text) = documents.source(uri) else {
+        return Vec::new();
+    };
+    let mut actions = Vec::new();
+    for diagnostic in diagnostics {
+        actions.extend(suggested_fixes(uri, diagnostic));
+        actions.extend(import_fixes(documents, uri, &text, diagnostic));
+    }
+    actions.extend(prefix_unused(documents, uri, &text, range.start));
+
+    if let Ok(program) = Parser::new(&text).parse_program() {
+        let offset = Document::new(0, text.clone()).offset(range.start);
+        let functions = functions(&program.definitions);
+        actions.extend(annotate_result(documents, uri, &text, &functions, offset));
+        actions.extend(missing_arms(
+            documents, uri, &text, &program, &functions, offset,
+        ));
+        actions.extend(checked_arithmetic(uri, &text, &functions, offset));
+    }
+    actions
+        .into_iter()
+        .map(CodeActionOrCommand::CodeAction)
+        .collect()
+}
+
+/// The fixes the compiler suggested along with a diagnostic
+fn suggested_fixes(uri: &Url, diagnostic: &Diagnostic) -> Vec<CodeAction> {
+    diagnostics::suggested_fixes(diagnostic)
+        .into_iter()
+        .enumerate()
+        .map(|(i, fix)| CodeAction {
+            title: capitalize(&fix.title),
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: Some(vec![diagnostic.clone()]),
+            edit: Some(edit(uri, vec![fix.edit])),
+            is_preferred: Some(i == 0),
+            ..CodeAction::default()
+        })
+        .collect()
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
+
+/// Imports of an undefined name from every module exporting it
+fn import_fixes(
+    documents: &DocumentStore,
+    uri: &Url,
+    text: &str,
+    diagnostic: &Diagnostic,
+) -> Vec<CodeAction> {
+    let Some(name) = UNDEFINED
+        .iter()
+        .find_map(|prefix| diagnostic.message.strip_prefix(prefix))
+        .and_then(|rest| rest.split('\'').next())
+    else {
+        return Vec::new();
+    };
+
+    let position = import_position(text);
+    let mut actions = Vec::new();
+    for (module, source) in importable_modules(documents, uri) {
+        let Some(imported) = exports(&source)
+            .into_iter()
+            .find_map(|(exported, imported)| (exported == name).then_some(imported))
+        else {
+            continue;
+        };
+        let import = format!("from {} import {};\n", module, imported);
+        actions.push(CodeAction {
+            title: format!("Import `{}` from `{}`", imported, module),
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: Some(vec![diagnostic.clone()]),
+            edit: Some(edit(
+                uri,
+                vec![TextEdit::new(Range::new(position, position), import)],
+            )),
+            is_preferred: Some(actions.is_empty()),
+            ..CodeAction::default()
+        });
+    }
+    actions
+}
+
+/// Where a new import goes: after the last one, or at the top
+fn import_position(text: &str) -> Position {
+    let last_line = Parser::new(text).parse_program().ok().and_then(|program| {
+        program
+            .imports
+            .iter()
+            .map(|import| match import {
+                Import::FromImport { location, .. } | Import::DirectImport { location, .. } => {
+                    location.line
+                }
+            })
+            .max()
+    });
+    // Import locations are 1-based, so the line after is the same number
+    Position::new(last_line.unwrap_or(0) as u32, 0)
+}
+
+/// Import paths and sources of the modules the document at `uri` could
+/// import: the standard library, and the modules of its package and of the
+/// packages it depends on, or the modules next to it
+fn importable_modules(documents: &DocumentStore, uri: &Url) -> Vec<(String, String)> {
+    let mut found: Vec<(String, String)> = modules::modules()
+        .filter(|path| *path != modules::PRELUDE)
+        .filter_map(|path| Some((path.to_string(), modules::source(path)?.to_string())))
+        .collect();
+    let Ok(path) = uri.to_file_path() else {
+        return found;
+    };
+
+    let dirs: Vec<(PathBuf, Vec<PathBuf>)> = match diagnostics::workspace(&path) {
+        Some(workspace) => {
+            let package = workspace.root();
+            std::iter::once(package)
+                .chain(
+                    package
+                        .manifest()
+                        .dependencies()
+                        .iter()
+                        .filter_map(|dependency| workspace.package(dependency.name())),
+                )
+                .map(|package| {
+                    (
+                        package.source_dir(),
+                        package.module_files().unwrap_or_default(),
+                    )
+                })
+                .collect()
+        }
+        None => path
+            .parent()
+            .map(|dir| (dir.to_path_buf(), sibling_modules(dir)))
+            .into_iter()
+            .collect(),
+    };
+
+    let own = canonical(&path);
+    for (dir, files) in dirs {
+        for file in files {
+            if canonical(&file) == own {
+                continue;
+            }
+            let (Some(module), Ok(file_uri)) = (
+                module_path(&dir, &file),
+                Url::from_file_path(canonical(&file)),
+            ) else {
+                continue;
+            };
+            if let Some(source) = documents.source(&file_uri) {
+                found.push((module, source));
+            }
+        }
+    }
+    found
+}
+
+/// The modules in the same directory as a module outside any package
+fn sibling_modules(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "bend"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// The path `file` is imported by from the search path `dir`
+fn module_path(dir: &Path, file: &Path) -> Option<String> {
+    let relative = file.strip_prefix(dir).ok()?.with_extension("");
+    let parts: Vec<&str> = relative
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<_>>()?;
+    Some(parts.join("/"))
+}
+
+/// Names a module exports, each with the name importing it. Variants are
+/// imported with their type.
+fn exports(source: &str) -> Vec<(String, String)> {
+    let Ok(program) = Parser::new(source).parse_program() else {
+        return Vec::new();
+    };
+    let mut exports = Vec::new();
+    for definition in &program.definitions {
+        if definition.visibility() != Visibility::Public {
+            continue;
+        }
+        match definition {
+            Definition::TypeDef { name, variants, .. }
+            | Definition::ErrorDef { name, variants, .. } => {
+                exports.push((name.to_string(), name.to_string()));
+                for variant in variants {
+                    exports.push((format!("{}/{}", name, variant.name), name.to_string()));
+                }
+            }
+            Definition::FunctionDef { name, .. }
+            | Definition::ObjectDef { name, .. }
+            | Definition::TypeAlias { name, .. }
+            | Definition::EventDef { name, .. }
+            | Definition::InterfaceDef { name, .. }
+            | Definition::Module { name, .. } => exports.push((name.to_string(), name.to_string())),
+            _ => {}
+        }
+    }
+    exports
+}
+
+/// Prefix a local or parameter that is never read with `_`
+fn prefix_unused(
+    documents: &DocumentStore,
+    uri: &Url,
+    text: &str,
+    position: Position,
+) -> Option<CodeAction> {
+    let index = SymbolIndex::build(documents, uri);
+    let occurrence = index.occurrence_at(uri, position)?;
+    let site = &occurrence.site;
+    if !matches!(
+        site.kind,
+        DefinitionKind::Variable | DefinitionKind::Parameter
+    ) || index
+        .occurrences_of(site)
+        .any(|(_, occurrence)| !occurrence.is_definition && !occurrence.is_write)
+    {
+        return None;
+    }
+
+    let document = Document::new(0, text.to_string());
+    let name = &text[document.offset(site.range.start)..document.offset(site.range.end)];
+    if name.starts_with('_') {
+        return None;
+    }
+    let edit = rename::rename(documents, uri, position, &format!("_{}", name)).ok()?;
+    Some(CodeAction {
+        title: format!("Prefix unused `{}` with `_`", name),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(edit),
+        ..CodeAction::default()
+    })
+}
+
+/// Annotate a function without a result type with the one found for its
+/// body, when the cursor is in its signature
+fn annotate_result(
+    documents: &DocumentStore,
+    uri: &Url,
+    text: &str,
+    functions: &[&Definition],
+    offset: usize,
+) -> Option<CodeAction> {
+    let lines = LineIndex::new(text);
+    let (name, close) = functions.iter().find_map(|function| {
+        let Definition::FunctionDef {
+            name,
+            return_type: None,
+            checked,
+            location,
+            ..
+        } = function
+        else {
+            return None;
+        };
+        let start = lines.offset(text, location)?;
+        let (open, _) = braced(text, start, Token::LParen, Token::RParen)?;
+        let body = text[open..]
+            .find('{')
+            .map_or(text.len(), |body| open + body);
+        (*checked != Some(false) && (start..=body).contains(&offset))
+            .then_some((name, braced(text, open, Token::LParen, Token::RParen)?.1))
+    })?;
+
+    let checked = documents.check(uri).ok()?;
+    if checked.error.is_some() {
+        return None;
+    }
+    let result = checked
+        .inferred_results
+        .iter()
+        .find(|(function, _)| function == name)
+        .and_then(|(_, result)| hover::written_type(result))?;
+
+    let position = lines.position(text, close + 1);
+    Some(CodeAction {
+        title: format!("Annotate the result of `{}` as `{}`", name, result),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(edit(
+            uri,
+            vec![TextEdit::new(
+                Range::new(position, position),
+                format!(" -> {}", result),
+            )],
+        )),
+        ..CodeAction::default()
+    })
+}
+
+/// Add arms for the variants the innermost `match` at the cursor leaves out
+fn missing_arms(
+    documents: &DocumentStore,
+    uri: &Url,
+    text: &str,
+    program: &Program,
+    functions: &[&Definition],
+    offset: usize,
+) -> Option<CodeAction> {
+    let lines = LineIndex::new(text);
+    let (value, cases, start, open, close) = statements(functions)
+        .into_iter()
+        .filter_map(|statement| match statement {
+            Statement::Match {
+                value,
+                cases,
+                location,
+            } => {
+                let start = lines.offset(text, location)?;
+                let (open, close) = braced(text, start, Token::LBrace, Token::RBrace)?;
+                (start..=close)
+                    .contains(&offset)
+                    .then_some((value, cases, start, open, close))
+            }
+            _ => None,
+        })
+        .max_by_key(|(_, _, start, _, _)| *start)?;
+
+    // Imported types count too, like those of the prelude
+    let program = documents
+        .module(uri)
+        .map(|module| module.program())
+        .unwrap_or_else(|_| program.clone());
+    let types: Vec<(&str, &[TypeVariant])> = program
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            Definition::TypeDef { name, variants, .. }
+            | Definition::ErrorDef { name, variants, .. } => {
+                Some((name.as_str(), variants.as_slice()))
+            }
+            _ => None,
+        })
+        .collect();
+    let variant_of = |name: &str| match name.rsplit_once('/') {
+        Some((type_name, variant)) => Some((type_name.to_string(), variant.to_string())),
+        None => types
+            .iter()
+            .find(|(_, variants)| variants.iter().any(|variant| variant.name == name))
+            .map(|(type_name, _)| (type_name.to_string(), name.to_string())),
+    };
+
+    let mut covered = Vec::new();
+    for case in cases {
+        match &case.pattern {
+            Pattern::Constructor { name, .. } | Pattern::TupleConstructor { name, .. } => {
+                covered.extend(variant_of(name));
+            }
+            Pattern::Variable { name, .. } if variant_of(name).is_some() => {
+                covered.extend(variant_of(name));
+            }
+            // Anything else may match every variant
+            _ => return None,
+        }
+    }
+
+    let type_name = match covered.first() {
+        Some((type_name, _)) => type_name.clone(),
+        None => {
+            let Expr::Variable { name, location } = value else {
+                return None;
+            };
+            let range = lines.find(text, name, location)?;
+            match hover::local_type_info(documents, uri, text, name, range)? {
+                TypeInfo::Named(type_name, _) => type_name,
+                _ => return None,
+            }
+        }
+    };
+    let (_, variants) = types.iter().find(|(name, _)| *name == type_name)?;
+    let missing: Vec<&TypeVariant> = variants
+        .iter()
+        .filter(|variant| !covered.contains(&(type_name.clone(), variant.name.to_string())))
+        .collect();
+    if missing.is_empty() {
+        return None;
+    }
+
+    let match_indent = indentation(text, start);
+    // New arms line up with the first one
+    let arm_indent = match text[open + 1..close].find(|c: char| !c.is_whitespace()) {
+        Some(first) => indentation(text, open + 1 + first),
+        None => format!("{}    ", match_indent),
+    };
+    let mut arms = String::new();
+    for variant in &missing {
+        let fields: Vec<&str> = variant
+            .fields
+            .iter()
+            .map(|field| field.name.as_str())
+            .collect();
+        let fields = if fields.is_empty() {
+            String::new()
+        } else {
+            format!("({})", fields.join(", "))
+        };
+        arms.push_str(&format!(
+            "{indent}{}/{}{} => {{\n{indent}}}\n",
+            type_name,
+            variant.name,
+            fields,
+            indent = arm_indent
+        ));
+    }
+
+    // Arms go on their own lines before the closing brace
+    let line_start = text[..close].rfind('\n').map_or(0, |newline| newline + 1);
+    let (at, arms) = if text[line_start..close].trim().is_empty() {
+        (line_start, arms)
+    } else {
+        (close, format!("\n{}{}", arms, match_indent))
+    };
+    let position = lines.position(text, at);
+    let title = match missing.as_slice() {
+        [variant] => format!("Add the missing arm for `{}/{}`", type_name, variant.name),
+        _ => format!("Add {} missing match arms", missing.len()),
+    };
+    Some(CodeAction {
+        title,
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(edit(
+            uri,
+            vec![TextEdit::new(Range::new(position, position), arms)],
+        )),
+        ..CodeAction::default()
+    })
+}
+
+/// Check the arithmetic of the innermost `unchecked` block at the cursor,
+/// or else of the unchecked function it is in
+fn checked_arithmetic(
+    uri: &Url,
+    text: &str,
+    functions: &[&Definition],
+    offset: usize,
+) -> Option<CodeAction> {
+    let lines = LineIndex::new(text);
+    let block = statements(functions)
+        .into_iter()
+        .filter_map(|statement| match statement {
+            Statement::Unchecked { location, .. } => {
+                let start = lines.offset(text, location)?;
+                let (open, close) = braced(text, start, Token::LBrace, Token::RBrace)?;
+                (start..=close)
+                    .contains(&offset)
+                    .then_some((start, open, close))
+            }
+            _ => None,
+        })
+        .max_by_key(|(start, _, _)| *start);
+
+    if let Some((start, open, close)) = block {
+        let statements = dedent(&text[open + 1..close], &indentation(text, start));
+        let range = Range::new(lines.position(text, start), lines.position(text, close + 1));
+        return Some(CodeAction {
+            title: "Check the arithmetic of this block".to_string(),
+            kind: Some(CodeActionKind::REFACTOR_REWRITE),
+            edit: Some(edit(uri, vec![TextEdit::new(range, statements)])),
+            ..CodeAction::default()
+        });
+    }
+
+    functions.iter().find_map(|function| {
+        let Definition::FunctionDef {
+            name,
+            checked: Some(false),
+            location,
+            ..
+        } = function
+        else {
+            return None;
+        };
+        let start = lines.offset(text, location)?;
+        let (_, close) = braced(text, start, Token::LBrace, Token::RBrace)?;
+        if !(start..=close).contains(&offset) {
+            return None;
+        }
+        // Remove the modifier along with the space after it
+        let mut lexer = BendLexer::new(&text[start..]);
+        let modifier = lexer.next_token();
+        if modifier.token != Token::Unchecked {
+            return None;
+        }
+        let next = lexer.next_token();
+        let range = Range::new(
+            lines.position(text, start + modifier.start),
+            lines.position(text, start + next.start),
+        );
+        Some(CodeAction {
+            title: format!("Check the arithmetic of `{}`", name),
+            kind: Some(CodeActionKind::REFACTOR_REWRITE),
+            edit: Some(edit(uri, vec![TextEdit::new(range, String::new())])),
+            ..CodeAction::default()
+        })
+    })
+}
+
+/// Every function, including methods and local functions
+fn functions(definitions: &[Definition]) -> Vec<&Definition> {
+    let mut found = Vec::new();
+    for definition in definitions {
+        match definition {
+            Definition::FunctionDef { body, .. } => {
+                found.push(definition);
+                for statement in block_statements(body) {
+                    if let Statement::LocalDef { function_def, .. } = statement {
+                        found.extend(functions(std::slice::from_ref(&**function_def)));
+                    }
+                }
+            }
+            Definition::ObjectDef {
+                functions: methods, ..
+            } => found.extend(functions(methods)),
+            Definition::Module { definitions, .. } => found.extend(functions(definitions)),
+            _ => {}
+        }
+    }
+    found
+}
+
+/// Every statement in the bodies of `functions`, outside local functions,
+/// which are among `functions` themselves
+fn statements<'a>(functions: &[&'a Definition]) -> Vec<&'a Statement> {
+    functions
+        .iter()
+        .filter_map(|function| match function {
+            Definition::FunctionDef { body, .. } => Some(block_statements(body)),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+/// Every statement in a block and the blocks nested in it
+fn block_statements(block: &Block) -> Vec<&Statement> {
+    let mut found = Vec::new();
+    for statement in &block.statements {
+        found.push(statement);
+        for nested in statement.nested_blocks() {
+            found.extend(block_statements(nested));
+        }
+    }
+    found
+}
+
+/// Offsets of the first `open` token at or after `from` and of the token
+/// closing it
+fn braced(text: &str, from: usize, open: Token, close: Token) -> Option<(usize, usize)> {
+    let mut lexer = BendLexer::new(&text[from..]);
+    let mut opened = None;
+    let mut depth = 0;
+    loop {
+        let token = lexer.next_token();
+        if token.token == Token::EOF {
+            return None;
+        } else if token.token == open {
+            opened.get_or_insert(from + token.start);
+            depth += 1;
+        } else if token.token == close && opened.is_some() {
+            depth -= 1;
+            if depth == 0 {
+                return Some((opened?, from + token.start));
+            }
+        }
+    }
+}
+
+/// The whitespace starting the line `offset` is on
+fn indentation(text: &str, offset: usize) -> String {
+    let line_start = text[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+    text[line_start..]
+        .chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .collect()
+}
+
+/// The statements of a block, moved out to `indent`. The first line takes
+/// the place of the block, so it is not indented.
+fn dedent(body: &str, indent: &str) -> String {
+    let lines: Vec<&str> = body
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    let common = lines
+        .iter()
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| line[common..].trim_end())
+        .collect::<Vec<_>>()
+        .join(&format!("\n{}", indent))
+}
+
+fn edit(uri: &Url, edits: Vec<TextEdit>) -> WorkspaceEdit {
+    WorkspaceEdit::new(HashMap::from([(uri.clone(), edits)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actions_at(text: &str, position: Position, diagnostics: &[Diagnostic]) -> Vec<CodeAction> {
+        let uri = Url::parse("untitled:actions.bend").unwrap();
+        let mut documents = DocumentStore::default();
+        documents.open(uri.clone(), 1, text.to_string());
+        code_actions(
+            &documents,
+            &uri,
+            Range::new(position, position),
+            diagnostics,
+        )
+        .into_iter()
+        .map(|action| match action {
+            CodeActionOrCommand::CodeAction(action) => action,
+            CodeActionOrCommand::Command(_) => panic!("expected a code action"),
+        })
+        .collect()
+    }
+
+    /// The document after applying the edit of the action titled `title`
+    fn applied(text: &str, actions: &[CodeAction], title: &str) -> String {
+        let action = actions
+            .iter()
+            .find(|action| action.title == title)
+            .unwrap_or_else(|| panic!("no action `{}` in {:?}", title, actions));
+        let changes = action.edit.clone().unwrap().changes.unwrap();
+        let mut edits: Vec<&TextEdit> = changes.values().flatten().collect();
+        let document = Document::new(0, text.to_string());
+        edits.sort_by_key(|edit| std::cmp::Reverse(document.offset(edit.range.start)));
+        let mut text = text.to_string();
+        for edit in edits {
+            let range = document.offset(edit.range.start)..document.offset(edit.range.end);
+            text.replace_range(range, &edit.new_text);
+        }
+        text
+    }
+
+    #[test]
+    fn test_import_undefined_names() {
+        let text = "from std/List import List/map;\n\nfn main() -> u24 {\n    x = Option/Some(1);\n    return 0;\n}\n";
+        let diagnostic = Diagnostic {
+            message: "Undefined constructor 'Option/Some' at line 4, column 9".to_string(),
+            ..Diagnostic::default()
+        };
+        let actions = actions_at(text, Position::new(3, 8), &[diagnostic]);
+        let fixed = applied(text, &actions, "Import `Option` from `std/Option`");
+        assert!(
+            fixed.starts_with("from std/List import List/map;\nfrom std/Option import Option;\n")
+        );
+        assert_eq!(actions[0].is_preferred, Some(true));
+    }
+
+    #[test]
+    fn test_apply_suggested_fixes() {
+        let text = "fn main(count: u24) -> u24 {\n    return cuont + 1;\n}\n";
+        let uri = Url::parse("untitled:actions.bend").unwrap();
+        let mut documents = DocumentStore::default();
+        documents.open(uri.clone(), 1, text.to_string());
+        let diagnostics = diagnostics::analyze(&documents, &uri);
+        let actions = actions_at(text, Position::new(1, 11), &diagnostics);
+        let fixed = applied(
+            text,
+            &actions,
+            "A name with a similar spelling exists: `count`",
+        );
+        assert_eq!(
+            fixed,
+            "fn main(count: u24) -> u24 {\n    return count + 1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_prefix_unused_and_annotate_result() {
+        let text = "fn double(x: u24, y: u24) {\n    z = x * 2;\n    return x * 2;\n}\n";
+        let actions = actions_at(text, Position::new(0, 18), &[]);
+        let fixed = applied(text, &actions, "Prefix unused `y` with `_`");
+        assert!(fixed.starts_with("fn double(x: u24, _y: u24) {"));
+        let fixed = applied(text, &actions, "Annotate the result of `double` as `u24`");
+        assert!(fixed.starts_with("fn double(x: u24, y: u24) -> u24 {"));
+
+        let actions = actions_at(text, Position::new(1, 4), &[]);
+        let fixed = applied(text, &actions, "Prefix unused `z` with `_`");
+        assert!(fixed.contains("    _z = x * 2;"));
+        // `x` is read
+        assert!(actions_at(text, Position::new(0, 10), &[])
+            .iter()
+            .all(|action| !action.title.starts_with("Prefix")));
+    }
+
+    #[test]
+    fn test_add_missing_match_arms() {
+        let text = "type Shape {\n    Circle(radius: u24),\n    Square(side: u24),\n    Empty,\n}\n\nfn area(shape: Shape) -> u24 {\n    match shape {\n        Shape/Circle(r) => {\n            return r;\n        }\n    }\n}\n";
+        let actions = actions_at(text, Position::new(7, 6), &[]);
+        let fixed = applied(text, &actions, "Add 2 missing match arms");
+        assert!(fixed.contains("            return r;\n        }\n        Shape/Square(side) => {\n        }\n        Shape/Empty => {\n        }\n    }\n}\n"));
+
+        let complete = fixed.replace("Shape/Square(side) => {\n        }\n        ", "");
+        let actions = actions_at(&complete, Position::new(7, 6), &[]);
+        let fixed = applied(
+            &complete,
+            &actions,
+            "Add the missing arm for `Shape/Square`",
+        );
+        assert!(fixed.contains("Shape/Square(side) => {"));
+    }
+
+    #[test]
+    fn test_convert_to_checked_arithmetic() {
+        let text = "fn step(i: u24) -> u24 {\n    unchecked: {\n        i = i + 1;\n        i = i * 2;\n    }\n    return i;\n}\n\nunchecked fn wrap(i: u24) -> u24 {\n    return i + 1;\n}\n";
+        let actions = actions_at(text, Position::new(2, 8), &[]);
+        let fixed = applied(text, &actions, "Check the arithmetic of this block");
+        assert!(fixed.starts_with(
+            "fn step(i: u24) -> u24 {\n    i = i + 1;\n    i = i * 2;\n    return i;\n}\n"
+        ));
+
+        let actions = actions_at(text, Position::new(9, 8), &[]);
+        let fixed = applied(text, &actions, "Check the arithmetic of `wrap`");
+        assert!(fixed.ends_with("\nfn wrap(i: u24) -> u24 {\n    return i + 1;\n}\n"));
+    }
+}
@@ -0,0 +1,128 @@
+//! Code lenses above functions
+//!
+//! Public functions show the gas the profiler estimates for them, and
+//! `#[test]` functions offer to run the test. The editor runs a test with
+//! the [`RUN_TEST`] command, whose arguments are the path of the file and
+//! the name of the test, as `bend-pvm test <file> --filter <name>`.
+
+use lsp_types::{CodeLens, Command, Url};
+use serde_json::json;
+
+use bend_pvm::analyzer::gas_profiler::{GasEstimate, GasProfiler};
+use bend_pvm::compiler::analyzer::attributes::FunctionAttributes;
+use bend_pvm::compiler::parser::ast::{Definition, Visibility};
+use bend_pvm::compiler::parser::parser::Parser;
+
+use crate::documents::DocumentStore;
+use crate::symbols::LineIndex;
+
+/// Command the editor runs a test with
+pub const RUN_TEST: &str = "bend-pvm.runTest";
+
+/// Lenses for the functions of the document at `uri`
+pub fn code_lenses(documents: &DocumentStore, uri: &Url) -> Vec<CodeLens> {
+    let Some(text) = documents.source(uri) else {
+        return Vec::new();
+    };
+    let Ok(program) = Parser::new(&text).parse_program() else {
+        return Vec::new();
+    };
+    let estimates = GasProfiler::new()
+        .profile_source(&text, uri.path())
+        .map(|profile| profile.estimates)
+        .unwrap_or_default();
+    let path = uri.to_file_path().ok();
+    let lines = LineIndex::new(&text);
+
+    let mut lenses = Vec::new();
+    for definition in &program.definitions {
+        let Definition::FunctionDef { name, location, .. } = definition else {
+            continue;
+        };
+        let Some(range) = lines.find(&text, name, location) else {
+            continue;
+        };
+
+        let is_test = FunctionAttributes::of(definition).is_ok_and(|attributes| attributes.test);
+        if let (true, Some(path)) = (is_test, &path) {
+            lenses.push(CodeLens {
+                range,
+                command: Some(Command {
+                    title: "Run test".to_string(),
+                    command: RUN_TEST.to_string(),
+                    arguments: Some(vec![json!(path.display().to_string()), json!(name)]),
+                }),
+                data: None,
+            });
+        }
+
+        let estimate = estimates.iter().find(|estimate| estimate.name == *name);
+        if let (Visibility::Public, Some(estimate)) = (definition.visibility(), estimate) {
+            lenses.push(CodeLens {
+                range,
+                // Lenses without a command to run only show their title
+                command: Some(Command {
+                    title: gas_title(estimate),
+                    command: String::new(),
+                    arguments: None,
+                }),
+                data: None,
+            });
+        }
+    }
+    lenses
+}
+
+fn gas_title(estimate: &GasEstimate) -> String {
+    let mut title = format!("Estimated gas: {}", estimate.avg_cost);
+    if estimate.has_unbounded_loops || estimate.is_recursive {
+        title.push_str(" (unbounded)");
+    } else if estimate.max_cost > estimate.avg_cost {
+        title.push_str(&format!(" (up to {})", estimate.max_cost));
+    }
+    title
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Position, Range};
+
+    #[test]
+    fn test_gas_and_test_lenses() {
+        let dir = std::env::temp_dir().join(format!("bend-lsp-lens-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lens.bend");
+        let text = "pub fn total(a: u24, b: u24) -> u24 {\n    return a + b;\n}\n\nfn helper() -> u24 {\n    return 1;\n}\n\n#[test]\nfn check_total() -> u24 {\n    return total(1, 2);\n}\n";
+        std::fs::write(&path, text).unwrap();
+        let uri = Url::from_file_path(&path).unwrap();
+
+        let lenses = code_lenses(&DocumentStore::default(), &uri);
+        let titles: Vec<(u32, String)> = lenses
+            .iter()
+            .map(|lens| {
+                let command = lens.command.as_ref().unwrap();
+                (lens.range.start.line, command.title.clone())
+            })
+            .collect();
+        assert_eq!(titles.len(), 2, "{:?}", titles);
+        assert_eq!(titles[0].0, 0);
+        assert!(titles[0].1.starts_with("Estimated gas: "));
+        assert_eq!(titles[1], (9, "Run test".to_string()));
+
+        let run = lenses[1].command.as_ref().unwrap();
+        assert_eq!(run.command, RUN_TEST);
+        assert_eq!(
+            run.arguments,
+            Some(vec![
+                json!(path.display().to_string()),
+                json!("check_total")
+            ])
+        );
+        assert_eq!(
+            lenses[1].range,
+            Range::new(Position::new(9, 3), Position::new(9, 14))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,389 @@
+//! Diagnostics reported for open documents
+//!
+//! A document is parsed and type checked together with the modules it
+//! imports, through the analysis its [`DocumentStore`] remembers. Errors and warnings become the same
+//! diagnostics the command line prints, with their codes, spans and
+//! suggested fixes.
+
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range, TextEdit, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bend_pvm::compiler::analyzer::lints::{lint_program, LintLevels};
+use bend_pvm::compiler::module::cache::ModuleCache;
+use bend_pvm::compiler::module::{Module, ModuleError, ModuleSystem};
+use bend_pvm::compiler::parser::ast::{Import, Program};
+use bend_pvm::compiler::pipeline::{lint_diagnostics, CompilerPipeline, Source};
+use bend_pvm::diagnostics::{Diagnostic as CompilerDiagnostic, Severity};
+use bend_pvm::package::Workspace;
+use bend_pvm::{CompileError, CompilerOptions};
+
+use crate::documents::DocumentStore;
+use crate::symbols::LineIndex;
+
+/// How long typing has to pause before a changed document is analyzed
+pub const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Parse and type check the document at `uri`, reusing what the analysis
+/// of earlier versions found for the modules it imports
+pub fn analyze(documents: &DocumentStore, uri: &Url) -> Vec<Diagnostic> {
+    let Some(text) = documents.source(uri) else {
+        return Vec::new();
+    };
+    let text = text.as_str();
+    let parsed = documents.parse(uri);
+    let program = match &*parsed {
+        Ok(program) => program,
+        Err(e) => {
+            return vec![convert(
+                text,
+                &CompilerDiagnostic::from_parse_error(e, text),
+            )]
+        }
+    };
+
+    let module = match documents.module(uri) {
+        Ok(module) => module,
+        Err(e) => {
+            // Configuring the module fails with a type error, which points
+            // at the definition it is about
+            let options = CompilerOptions::default();
+            let source = Source::new(uri.as_str(), text);
+            let diagnostic = match CompilerPipeline::new(&options).parse(&source) {
+                Err(CompileError::Diagnostic(diagnostic)) => *diagnostic,
+                _ => CompilerDiagnostic::from_module_error(&e, program),
+            };
+            return vec![convert(text, &diagnostic)];
+        }
+    };
+
+    // Errors in imported modules would point into the wrong file, so the
+    // imported modules are checked on their own first
+    let mut imported: Vec<&Arc<Module>> = module.imports.values().collect();
+    imported.sort_by(|a, b| a.path.cmp(&b.path));
+    for import in imported {
+        let error = match documents.query(uri, |database, _| database.check(&import.path)) {
+            Ok(checked) => checked.error.as_ref().map(ToString::to_string),
+            Err(e) => Some(e.to_string()),
+        };
+        if let Some(error) = error {
+            let message = format!("Error in an imported module: {}", error);
+            return vec![diagnostic(imports_range(text, &module.ast), message)];
+        }
+    }
+
+    let checked = match documents.check(uri) {
+        Ok(checked) => checked,
+        Err(e) => return vec![diagnostic(line_range(text, 1), e.to_string())],
+    };
+    let mut diagnostics = Vec::new();
+    if let Some(e) = &checked.error {
+        diagnostics.push(convert(text, &CompilerDiagnostic::from_type_error(e, text)));
+    }
+
+    // Lints run on the document alone, with the levels of its attributes
+    let mut warnings = lint_program(&module.ast);
+    warnings.extend(checked.warnings.iter().cloned());
+    let (lints, _) = lint_diagnostics(text, &module.ast, warnings, &LintLevels::default());
+    diagnostics.extend(lints.iter().map(|lint| convert(text, lint)));
+    diagnostics
+}
+
+/// The LSP form of a compiler diagnostic. Notes are appended to the
+/// message, and suggested fixes are kept in `data` for the quick fixes of
+/// [`crate::code_actions`].
+fn convert(text: &str, compiled: &CompilerDiagnostic) -> Diagnostic {
+    let lines = LineIndex::new(text);
+    let range_of = |span: &std::ops::Range<usize>| {
+        Range::new(
+            lines.position(text, span.start),
+            lines.position(text, span.end),
+        )
+    };
+    let range = compiled
+        .span()
+        .map_or_else(|| line_range(text, 1), |span| range_of(&span));
+    let mut message = compiled.message.clone();
+    for note in &compiled.notes {
+        message.push_str("\nnote: ");
+        message.push_str(note);
+    }
+    let fixes: Vec<SuggestedFix> = compiled
+        .suggestions
+        .iter()
+        .map(|suggestion| SuggestedFix {
+            title: suggestion.message.clone(),
+            edit: TextEdit::new(range_of(&suggestion.span), suggestion.replacement.clone()),
+        })
+        .collect();
+
+    Diagnostic {
+        severity: Some(match compiled.severity {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+        }),
+        code: Some(NumberOrString::String(compiled.code.to_string())),
+        data: (!fixes.is_empty()).then(|| serde_json::json!(fixes)),
+        ..diagnostic(range, message)
+    }
+}
+
+/// A fix the compiler suggests, as carried in the `data` of a diagnostic
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SuggestedFix {
+    pub title: String,
+    pub edit: TextEdit,
+}
+
+/// The fixes suggested for a diagnostic published by [`analyze`]
+pub fn suggested_fixes(diagnostic: &Diagnostic) -> Vec<SuggestedFix> {
+    diagnostic
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value(data).ok())
+        .unwrap_or_default()
+}
+
+/// Load the document at `uri` along with the modules it imports
+pub fn load_module(uri: &Url, text: &str) -> Result<Arc<Module>, ModuleError> {
+    // Documents that are not files can only import the standard library
+    let (path, mut modules) = match uri.to_file_path() {
+        Ok(path) => {
+            let modules = module_system(&path);
+            (path, modules)
+        }
+        Err(()) => (PathBuf::from("untitled.bend"), ModuleSystem::new()),
+    };
+    modules.load_source(&path, text)
+}
+
+/// Find imports the way the compiler would for the file at `path`: through
+/// its package when it belongs to one, and next to it otherwise
+pub fn module_system(path: &Path) -> ModuleSystem {
+    match workspace(path) {
+        Some(workspace) => workspace.module_system(workspace.root()),
+        None => standalone_module_system(path),
+    }
+}
+
+/// The workspace of the package the file at `path` belongs to
+pub fn workspace(path: &Path) -> Option<Workspace> {
+    Workspace::find_root(path).and_then(|root| Workspace::load(&root).ok())
+}
+
+/// Find imports next to a file that is not part of a package
+pub fn standalone_module_system(path: &Path) -> ModuleSystem {
+    let mut modules = ModuleSystem::new();
+    modules.set_cache(ModuleCache::for_path(path));
+    if let Some(dir) = path.parent() {
+        modules.add_search_path(dir);
+    }
+    modules
+}
+
+/// The first import, where problems with imported modules are reported
+fn imports_range(text: &str, program: &Program) -> Range {
+    let line = match program.imports.first() {
+        Some(Import::FromImport { location, .. } | Import::DirectImport { location, .. }) => {
+            location.line
+        }
+        None => 1,
+    };
+    line_range(text, line)
+}
+
+fn diagnostic(range: Range, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        message,
+        source: Some("bend-pvm".to_string()),
+        ..Diagnostic::default()
+    }
+}
+
+/// A whole 1-based line
+fn line_range(text: &str, line: usize) -> Range {
+    let start = position(text, line, 1);
+    let end = Position {
+        character: line_text(text, line).encode_utf16().count() as u32,
+        ..start
+    };
+    Range { start, end }
+}
+
+/// Convert a 1-based line and column counted in characters into an LSP
+/// position counted in UTF-16 code units
+fn position(text: &str, line: usize, column: usize) -> Position {
+    let character = line_text(text, line)
+        .chars()
+        .take(column.saturating_sub(1))
+        .map(char::len_utf16)
+        .sum::<usize>();
+    Position {
+        line: line.saturating_sub(1) as u32,
+        character: character as u32,
+    }
+}
+
+fn line_text(text: &str, line: usize) -> &str {
+    text.lines().nth(line.saturating_sub(1)).unwrap_or("")
+}
+
+/// Documents waiting to be analyzed until typing pauses
+#[derive(Debug, Default)]
+pub struct PendingAnalysis {
+    deadlines: HashMap<Url, Instant>,
+}
+
+impl PendingAnalysis {
+    /// Analyze a document once it has not changed for [`DEBOUNCE`]
+    pub fn schedule(&mut self, uri: Url) {
+        self.deadlines.insert(uri, Instant::now() + DEBOUNCE);
+    }
+
+    pub fn cancel(&mut self, uri: &Url) {
+        self.deadlines.remove(uri);
+    }
+
+    /// When the next document is due
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadlines.values().min().copied()
+    }
+
+    /// Remove and return the documents due at `now`
+    pub fn take_due(&mut self, now: Instant) -> Vec<Url> {
+        let due: Vec<Url> = self
+            .deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        for uri in &due {
+            self.deadlines.remove(uri);
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri() -> Url {
+        Url::parse("untitled:test.bend").unwrap()
+    }
+
+    fn analyze(text: &str) -> Vec<Diagnostic> {
+        let mut documents = DocumentStore::default();
+        documents.open(uri(), 1, text.to_string());
+        super::analyze(&documents, &uri())
+    }
+
+    #[test]
+    fn test_valid_document_has_no_diagnostics() {
+        let text = "fn main() -> u24 {\n    return 1;\n}\n";
+        assert!(analyze(text).is_empty());
+    }
+
+    #[test]
+    fn test_parse_error_range() {
+        let text = "fn main() -> u24 {\n    return 1 +;\n}\n";
+        let diagnostics = analyze(text);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(diagnostics[0].range.start.line, 1);
+    }
+
+    #[test]
+    fn test_type_error_range() {
+        let text = "fn main() -> u24 {\n    return missing;\n}\n";
+        let diagnostics = analyze(text);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("missing"));
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("E0101".to_string()))
+        );
+        assert_eq!(
+            diagnostics[0].range,
+            Range {
+                start: Position {
+                    line: 1,
+                    character: 11
+                },
+                end: Position {
+                    line: 1,
+                    character: 18
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_lint_warnings() {
+        let text = "fn main(unused: u24) -> u24 {\n    return 1;\n}\n\n#[allow(unused_variables)]\nfn quiet(unused: u24) -> u24 {\n    return 1;\n}\n";
+        let diagnostics = analyze(text);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("W0102".to_string()))
+        );
+        assert_eq!(diagnostics[0].range.start, Position::new(0, 8));
+    }
+
+    #[test]
+    fn test_missing_import_is_reported_on_the_import() {
+        let text = "from nowhere import thing;\n\nfn main() -> u24 {\n    return 1;\n}\n";
+        let diagnostics = analyze(text);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start.line, 0);
+        assert!(diagnostics[0].message.contains("nowhere"));
+    }
+
+    #[test]
+    fn test_edits_to_open_imports() {
+        let dir = std::env::temp_dir().join(format!("bend_lsp_imports_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let utils = "pub fn one() -> u24 {\n    return 1;\n}\n";
+        let main = "from utils import one;\n\nfn main() -> u24 {\n    return one();\n}\n";
+        std::fs::write(dir.join("utils.bend"), utils).unwrap();
+        std::fs::write(dir.join("main.bend"), main).unwrap();
+        let utils_uri = Url::from_file_path(dir.join("utils.bend")).unwrap();
+        let main_uri = Url::from_file_path(dir.join("main.bend")).unwrap();
+
+        let mut documents = DocumentStore::default();
+        documents.open(main_uri.clone(), 1, main.to_string());
+        assert!(super::analyze(&documents, &main_uri).is_empty());
+
+        // The unsaved text of an imported module is what gets checked
+        let broken = "pub fn one() -> u24 {\n    return true;\n}\n";
+        documents.open(utils_uri.clone(), 1, broken.to_string());
+        let diagnostics = super::analyze(&documents, &main_uri);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0]
+            .message
+            .starts_with("Error in an imported module"));
+
+        documents.close(&utils_uri);
+        assert!(super::analyze(&documents, &main_uri).is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_pending_analysis_waits_for_the_last_change() {
+        let mut pending = PendingAnalysis::default();
+        pending.schedule(uri());
+        let first = pending.deadline().unwrap();
+        pending.schedule(uri());
+        assert!(pending.deadline().unwrap() >= first);
+        assert!(pending.take_due(first - DEBOUNCE).is_empty());
+
+        assert_eq!(pending.take_due(pending.deadline().unwrap()), vec![uri()]);
+        assert!(pending.deadline().is_none());
+    }
+}
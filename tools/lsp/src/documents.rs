@@ -0,0 +1,361 @@
+//! Open documents, kept in memory as the editor changes them
+//!
+//! Handlers read documents from here so that unsaved edits are visible.
+//! Each document keeps the byte offset of every line start, which turns
+//! the UTF-16 positions of incremental changes into byte ranges without
+//! rescanning the text before the edit.
+//!
+//! The analysis of modules is remembered in a query database per workspace,
+//! which sees open documents as they are in the editor and every other
+//! module as it is on disk. An edit only redoes the analysis of the edited
+//! module and of the modules importing it.
+//!
+//! Names are interned into an interner of the store, which the server
+//! enters while it handles each message. Every edit interns the names
+//! typed so far, so once the interner holds too many the analysis is
+//! dropped along with it and starts over in a new one.
+
+use lsp_types::{Position, TextDocumentContentChangeEvent, Url};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bend_pvm::compiler::intern::Interner;
+use bend_pvm::compiler::module::{Module, ModuleError, ModuleSystem};
+use bend_pvm::compiler::parser::ast::Program;
+use bend_pvm::compiler::parser::parser::ParseError;
+use bend_pvm::compiler::query::{Checked, Database};
+use bend_pvm::package::Workspace;
+
+use crate::diagnostics;
+
+/// The contents of one open document
+#[derive(Debug, Clone)]
+pub struct Document {
+    version: i32,
+    text: String,
+    /// Byte offset of the start of every line
+    line_starts: Vec<usize>,
+}
+
+impl Document {
+    pub fn new(version: i32, text: String) -> Self {
+        let mut document = Document {
+            version,
+            text,
+            line_starts: vec![0],
+        };
+        document.index_lines(0);
+        document
+    }
+
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Apply a change, which replaces the whole text when it has no range
+    pub fn apply(&mut self, change: TextDocumentContentChangeEvent) {
+        let Some(range) = change.range else {
+            self.text = change.text;
+            self.line_starts.truncate(1);
+            self.index_lines(0);
+            return;
+        };
+
+        let start = self.offset(range.start);
+        let end = self.offset(range.end).max(start);
+        self.text.replace_range(start..end, &change.text);
+
+        // Lines before the edit keep their offsets
+        let line = (range.start.line as usize).min(self.line_starts.len() - 1);
+        self.line_starts.truncate(line + 1);
+        self.index_lines(line);
+    }
+
+    /// Byte offset of a position, clamped to the end of its line
+    pub fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.line_starts.get(position.line as usize) else {
+            return self.text.len();
+        };
+        let line = self.text[start..].split('\n').next().unwrap_or("");
+
+        let mut units = 0;
+        for (offset, c) in line.char_indices() {
+            if units >= position.character as usize {
+                return start + offset;
+            }
+            units += c.len_utf16();
+        }
+        start + line.len()
+    }
+
+    /// Record the start of every line after the start of `line`
+    fn index_lines(&mut self, line: usize) {
+        let from = self.line_starts[line];
+        self.line_starts.extend(
+            self.text[from..]
+                .match_indices('\n')
+                .map(|(offset, _)| from + offset + 1),
+        );
+    }
+}
+
+/// Every open document by URI
+#[derive(Debug, Default)]
+pub struct DocumentStore {
+    documents: HashMap<Url, Document>,
+    /// The analysis of the modules of each workspace, by its root, or of
+    /// the directory of files outside any
+    databases: RefCell<HashMap<PathBuf, Database>>,
+    /// The names the analysis is keyed on
+    interner: Interner,
+}
+
+/// Number of names interned after which the analysis starts over
+const MAX_NAMES: usize = 1 << 16;
+
+/// A copy starts its analysis over, so that it can be edited on its own
+impl Clone for DocumentStore {
+    fn clone(&self) -> Self {
+        DocumentStore {
+            documents: self.documents.clone(),
+            databases: RefCell::default(),
+            interner: self.interner.clone(),
+        }
+    }
+}
+
+impl DocumentStore {
+    /// The interner of the session the store is analyzed in
+    pub fn interner(&self) -> Interner {
+        self.interner.clone()
+    }
+
+    /// Drop the analysis and the names it interned once there are too
+    /// many names, outside of any session of the store
+    pub fn bound_names(&mut self) {
+        if self.interner.len() > MAX_NAMES {
+            self.databases.get_mut().clear();
+            self.interner = Interner::new();
+        }
+    }
+
+    pub fn open(&mut self, uri: Url, version: i32, text: String) {
+        let path = document_path(&uri);
+        for database in self.databases.get_mut().values_mut() {
+            database.set_source(&path, &text);
+        }
+        self.documents.insert(uri, Document::new(version, text));
+    }
+
+    /// Apply changes in the order the editor made them. Changes to a
+    /// document that is not open are ignored.
+    pub fn change(
+        &mut self,
+        uri: &Url,
+        version: i32,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) {
+        if let Some(document) = self.documents.get_mut(uri) {
+            for change in changes {
+                document.apply(change);
+            }
+            document.version = version;
+
+            let path = document_path(uri);
+            for database in self.databases.get_mut().values_mut() {
+                database.set_source(&path, document.text());
+            }
+        }
+    }
+
+    pub fn close(&mut self, uri: &Url) {
+        self.documents.remove(uri);
+        let path = document_path(uri);
+        for database in self.databases.get_mut().values_mut() {
+            database.close_source(&path);
+        }
+    }
+
+    /// Read a file that is not open again, after it changed on disk
+    pub fn reload(&mut self, uri: &Url) {
+        let path = document_path(uri);
+        for database in self.databases.get_mut().values_mut() {
+            database.reload(&path);
+        }
+    }
+
+    pub fn get(&self, uri: &Url) -> Option<&Document> {
+        self.documents.get(uri)
+    }
+
+    /// Every open document
+    pub fn uris(&self) -> impl Iterator<Item = &Url> {
+        self.documents.keys()
+    }
+
+    /// The text of a document: from memory while it is open, and otherwise
+    /// from disk
+    pub fn source(&self, uri: &Url) -> Option<String> {
+        match self.get(uri) {
+            Some(document) => Some(document.text().to_string()),
+            None => fs::read_to_string(uri.to_file_path().ok()?).ok(),
+        }
+    }
+
+    /// The syntax tree of a document: from memory while it is open, and
+    /// otherwise from disk
+    pub fn program(&self, uri: &Url) -> Option<Program> {
+        self.parse(uri).as_ref().as_ref().ok().cloned()
+    }
+
+    /// The syntax tree of a document, or why it does not parse
+    pub fn parse(&self, uri: &Url) -> Arc<Result<Program, ParseError>> {
+        self.query(uri, Database::parse)
+    }
+
+    /// A document linked with the modules it imports
+    pub fn module(&self, uri: &Url) -> Result<Arc<Module>, ModuleError> {
+        self.query(uri, Database::module)
+    }
+
+    /// Type check a document along with the modules it imports
+    pub fn check(&self, uri: &Url) -> Result<Arc<Checked>, ModuleError> {
+        self.query(uri, Database::check)
+    }
+
+    /// Ask the database of the workspace `uri` belongs to, along with the
+    /// path of its module
+    pub fn query<T>(&self, uri: &Url, query: impl FnOnce(&mut Database, &Path) -> T) -> T {
+        let path = document_path(uri);
+        // Documents that are not files can only import the standard library
+        let root = match uri.to_file_path() {
+            Ok(_) => Workspace::find_root(&path)
+                .or_else(|| path.parent().map(Path::to_path_buf))
+                .unwrap_or_default(),
+            Err(()) => PathBuf::new(),
+        };
+
+        let mut databases = self.databases.borrow_mut();
+        let database = databases.entry(root).or_insert_with(|| {
+            let modules = match uri.to_file_path() {
+                Ok(_) => diagnostics::module_system(&path),
+                Err(()) => ModuleSystem::new(),
+            };
+            let mut database = Database::new(modules);
+            for (uri, document) in &self.documents {
+                database.set_source(&document_path(uri), document.text());
+            }
+            database
+        });
+        query(database, &path)
+    }
+}
+
+/// The path a document is analyzed under
+fn document_path(uri: &Url) -> PathBuf {
+    uri.to_file_path()
+        .unwrap_or_else(|()| PathBuf::from(uri.path()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bend_pvm::compiler::intern::Symbol;
+    use lsp_types::Range;
+
+    fn edit(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(start.0, start.1),
+                Position::new(end.0, end.1),
+            )),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_incremental_changes() {
+        let uri = Url::parse("file:///test.bend").unwrap();
+        let mut store = DocumentStore::default();
+        store.open(uri.clone(), 1, "fn a() {\n}\n".to_string());
+
+        store.change(
+            &uri,
+            2,
+            vec![
+                edit((0, 3), (0, 4), "main"),
+                edit((0, 11), (0, 11), "\n    return 1;"),
+            ],
+        );
+        let document = store.get(&uri).unwrap();
+        assert_eq!(document.version(), 2);
+        assert_eq!(document.text(), "fn main() {\n    return 1;\n}\n");
+        assert_eq!(document.line_starts, vec![0, 12, 26, 28]);
+
+        store.change(&uri, 3, vec![edit((1, 11), (2, 1), "2;\n}")]);
+        assert_eq!(
+            store.get(&uri).unwrap().text(),
+            "fn main() {\n    return 2;\n}\n"
+        );
+
+        store.close(&uri);
+        assert!(store.get(&uri).is_none());
+    }
+
+    #[test]
+    fn test_positions_count_utf16_units() {
+        let document = Document::new(1, "# é𝄞x\nfn".to_string());
+        assert_eq!(document.offset(Position::new(0, 3)), 4);
+        assert_eq!(document.offset(Position::new(0, 5)), 8);
+        assert_eq!(document.offset(Position::new(0, 99)), 9);
+        assert_eq!(document.offset(Position::new(1, 1)), 11);
+        assert_eq!(document.offset(Position::new(5, 0)), 12);
+    }
+
+    #[test]
+    fn test_unsaved_text_is_parsed() {
+        let uri = Url::parse("file:///does/not/exist.bend").unwrap();
+        let mut store = DocumentStore::default();
+        assert!(store.program(&uri).is_none());
+
+        store.open(uri.clone(), 1, "fn main() -> u24 {\n}\n".to_string());
+        store.change(&uri, 2, vec![edit((0, 18), (0, 18), " return 1; ")]);
+        assert_eq!(store.program(&uri).unwrap().definitions.len(), 1);
+    }
+
+    #[test]
+    fn test_too_many_names_start_the_analysis_over() {
+        let uri = Url::parse("file:///does/not/exist.bend").unwrap();
+        let mut store = DocumentStore::default();
+        store.open(
+            uri.clone(),
+            1,
+            "fn main() -> u24 {\n  return 1;\n}\n".to_string(),
+        );
+
+        let session = store.interner();
+        session.enter(|| {
+            assert_eq!(store.program(&uri).unwrap().definitions.len(), 1);
+            for i in 0..MAX_NAMES {
+                Symbol::intern(&format!("name_{}", i));
+            }
+        });
+        store.bound_names();
+        assert!(store.databases.get_mut().is_empty());
+        assert!(store.interner().is_empty());
+        drop(session);
+
+        store.interner().enter(|| {
+            assert_eq!(store.program(&uri).unwrap().definitions.len(), 1);
+        });
+        assert!(!store.interner().is_empty());
+    }
+}
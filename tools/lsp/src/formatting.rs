@@ -0,0 +1,224 @@
+//! Document, range and on-type formatting
+//!
+//! Documents are formatted with the settings of the closest `.bendfmt.toml`
+//! or `[fmt]` table of `bend.toml`, like the command line formatter does.
+//! Documents without settings are indented the way the editor asks.
+//!
+//! Formatting a range, or the line just typed, only edits the lines it
+//! touches. The whole document still has to parse for either.
+
+use lsp_types::{FormattingOptions, Position, Range, TextEdit, Url};
+
+use bend_pvm::formatter::{FormatEdit, Formatter, FormatterConfig};
+
+use crate::documents::{Document, DocumentStore};
+use crate::symbols::LineIndex;
+
+/// Characters that format the line they are typed on, after the first one
+pub const ON_TYPE_TRIGGERS: [&str; 2] = [":", "\n"];
+
+/// The edit that formats the document at `uri`. Nothing when the document
+/// is already formatted or does not parse, since half-typed code should not
+/// be reported as an error on every save. Broken settings are an error.
+pub fn format_document(
+    documents: &DocumentStore,
+    uri: &Url,
+    options: &FormattingOptions,
+) -> Result<Option<Vec<TextEdit>>, String> {
+    let Some(text) = documents.source(uri) else {
+        return Ok(None);
+    };
+    let Ok(formatted) = Formatter::with_config(config(uri, options)?).format_source(&text) else {
+        return Ok(None);
+    };
+    if formatted == text {
+        return Ok(None);
+    }
+    let lines = LineIndex::new(&text);
+    let whole = Range::new(Position::new(0, 0), lines.position(&text, text.len()));
+    Ok(Some(vec![TextEdit::new(whole, formatted)]))
+}
+
+/// The edits that format the lines of `range`
+pub fn format_range(
+    documents: &DocumentStore,
+    uri: &Url,
+    range: Range,
+    options: &FormattingOptions,
+) -> Result<Option<Vec<TextEdit>>, String> {
+    line_edits(documents, uri, range, options, |_| true)
+}
+
+/// The edits that format the line `ch` was typed on. A new line formats the
+/// line it ends, and leaves the indentation the editor gave the cursor.
+pub fn format_on_type(
+    documents: &DocumentStore,
+    uri: &Url,
+    position: Position,
+    ch: &str,
+    options: &FormattingOptions,
+) -> Result<Option<Vec<TextEdit>>, String> {
+    let line = match ch {
+        "\n" => match position.line.checked_sub(1) {
+            Some(line) => line,
+            None => return Ok(None),
+        },
+        _ => position.line,
+    };
+    let range = Range::new(Position::new(line, 0), Position::new(line, u32::MAX));
+    line_edits(documents, uri, range, options, |edit| {
+        ch != "\n" || edit.range.end.line <= position.line
+    })
+}
+
+/// The formatter's edits to the lines of `range` that `keep` accepts
+fn line_edits(
+    documents: &DocumentStore,
+    uri: &Url,
+    range: Range,
+    options: &FormattingOptions,
+    keep: impl Fn(&TextEdit) -> bool,
+) -> Result<Option<Vec<TextEdit>>, String> {
+    let Some(text) = documents.source(uri) else {
+        return Ok(None);
+    };
+    let document = Document::new(0, text.clone());
+    let selection = document.offset(range.start)..document.offset(range.end);
+    let Ok(edits) = Formatter::with_config(config(uri, options)?).format_range(&text, selection)
+    else {
+        return Ok(None);
+    };
+
+    let lines = LineIndex::new(&text);
+    let edits: Vec<TextEdit> = edits
+        .into_iter()
+        .map(|FormatEdit { range, new_text }| {
+            let start = lines.position(&text, range.start);
+            let end = lines.position(&text, range.end);
+            TextEdit::new(Range::new(start, end), new_text)
+        })
+        .filter(keep)
+        .collect();
+    Ok((!edits.is_empty()).then_some(edits))
+}
+
+/// The project's settings, or the editor's indentation without any
+fn config(uri: &Url, options: &FormattingOptions) -> Result<FormatterConfig, String> {
+    let config = match uri.to_file_path() {
+        Ok(path) => FormatterConfig::discover(&path)?,
+        Err(()) => None,
+    };
+    Ok(config.unwrap_or_else(|| FormatterConfig {
+        indent_size: options.tab_size as usize,
+        use_tabs: !options.insert_spaces,
+        ..FormatterConfig::default()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(tab_size: u32, insert_spaces: bool) -> FormattingOptions {
+        FormattingOptions {
+            tab_size,
+            insert_spaces,
+            ..FormattingOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_format_with_editor_options() {
+        let uri = Url::parse("untitled:format.bend").unwrap();
+        let mut documents = DocumentStore::default();
+        documents.open(
+            uri.clone(),
+            1,
+            "fn main() -> u24 {\nreturn 1;\n}".to_string(),
+        );
+
+        let edits = format_document(&documents, &uri, &options(2, true))
+            .unwrap()
+            .unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(0, 0), Position::new(2, 1))
+        );
+        assert_eq!(edits[0].new_text, "fn main() -> u24 {\n  return 1;\n}\n");
+
+        documents.open(uri.clone(), 2, edits[0].new_text.clone());
+        assert_eq!(
+            format_document(&documents, &uri, &options(2, true)).unwrap(),
+            None
+        );
+
+        documents.open(uri.clone(), 3, "fn main( {".to_string());
+        assert_eq!(
+            format_document(&documents, &uri, &options(2, true)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_range_and_on_type_formatting() {
+        let uri = Url::parse("untitled:range.bend").unwrap();
+        let mut documents = DocumentStore::default();
+        let text = "fn main() -> u24 {\nx = 1+ 2;\ny = x* 3;\n    \nreturn y;\n}\n";
+        documents.open(uri.clone(), 1, text.to_string());
+        let line = |n: u32| Range::new(Position::new(n, 0), Position::new(n, 1));
+
+        let edits = format_range(&documents, &uri, line(2), &options(4, true))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            edits,
+            vec![TextEdit::new(
+                Range::new(Position::new(2, 0), Position::new(3, 0)),
+                "    y = x * 3;\n".to_string()
+            )]
+        );
+
+        // A new line formats the line it ends, not the cursor's
+        let typed = format_on_type(
+            &documents,
+            &uri,
+            Position::new(3, 4),
+            "\n",
+            &options(4, true),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(typed, edits);
+        assert_eq!(
+            format_on_type(
+                &documents,
+                &uri,
+                Position::new(0, 0),
+                "\n",
+                &options(4, true)
+            )
+            .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_settings_file_wins_over_editor_options() {
+        let dir = std::env::temp_dir().join(format!("bend-lsp-format-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(".bendfmt.toml"), "use_tabs = true\n").unwrap();
+        let path = dir.join("main.bend");
+        std::fs::write(&path, "fn main() -> u24 {\nreturn 1;\n}\n").unwrap();
+        let uri = Url::from_file_path(&path).unwrap();
+
+        let edits = format_document(&DocumentStore::default(), &uri, &options(2, true))
+            .unwrap()
+            .unwrap();
+        assert_eq!(edits[0].new_text, "fn main() -> u24 {\n\treturn 1;\n}\n");
+
+        std::fs::write(dir.join(".bendfmt.toml"), "use_tabs = 1\n").unwrap();
+        assert!(format_document(&DocumentStore::default(), &uri, &options(2, true)).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
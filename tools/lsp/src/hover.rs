@@ -0,0 +1,349 @@
+//! Hover information for names
+//!
+//! Hovering a name shows the signature of what it refers to in a `bend`
+//! code block, followed by the comments written directly above the
+//! definition. Locals show the type the type checker found for them.
+
+use lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Position, Range, Url};
+
+use bend_pvm::compiler::analyzer::type_checker::TypeInfo;
+use bend_pvm::compiler::parser::ast::{Definition, Parameter, TypeVariant, Visibility};
+use bend_pvm::compiler::parser::parser::Parser;
+
+use crate::documents::{Document, DocumentStore};
+use crate::symbols::{LineIndex, SymbolIndex, SymbolSite};
+
+/// Hover information for the name at `position`
+pub fn hover(documents: &DocumentStore, uri: &Url, position: Position) -> Option<Hover> {
+    let index = SymbolIndex::build(documents, uri);
+    let occurrence = index.occurrence_at(uri, position)?;
+    let document = Document::new(0, documents.source(uri)?);
+    let name = &document.text()
+        [document.offset(occurrence.range.start)..document.offset(occurrence.range.end)];
+
+    let site_text = documents.source(&occurrence.site.uri)?;
+    let signature = match definition_signature(&site_text, &occurrence.site) {
+        Some(signature) => signature,
+        None => {
+            // Locals are described by the type found for them
+            let type_info = local_type(documents, uri, document.text(), name, occurrence.range)
+                .unwrap_or_else(|| "_".to_string());
+            format!("{}: {}", name, type_info)
+        }
+    };
+
+    let mut value = format!("```bend\n{}\n```", signature);
+    if let Some(doc) = doc_comment(&site_text, occurrence.site.range.start.line as usize) {
+        value.push_str("\n\n");
+        value.push_str(&doc);
+    }
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(occurrence.range),
+    })
+}
+
+/// The type of the local `name` appearing at `range`, from type checking
+/// the document
+pub fn local_type(
+    documents: &DocumentStore,
+    uri: &Url,
+    text: &str,
+    name: &str,
+    range: Range,
+) -> Option<String> {
+    local_type_info(documents, uri, text, name, range).map(|type_info| type_info.to_string())
+}
+
+/// The type the type checker found for the local `name` at `range`
+pub fn local_type_info(
+    documents: &DocumentStore,
+    uri: &Url,
+    text: &str,
+    name: &str,
+    range: Range,
+) -> Option<TypeInfo> {
+    // Types found before an error are still worth showing
+    let checked = documents.check(uri).ok()?;
+
+    let lines = LineIndex::new(text);
+    checked
+        .name_types
+        .iter()
+        .filter(|(bound, _, _)| bound == name)
+        .find(|(_, location, _)| lines.find(text, name, location) == Some(range))
+        .map(|(_, _, type_info)| type_info.clone())
+}
+
+/// A type as it is written in source, unless it cannot be written
+pub fn written_type(type_info: &TypeInfo) -> Option<String> {
+    let list = |types: &[TypeInfo]| {
+        types
+            .iter()
+            .map(written_type)
+            .collect::<Option<Vec<_>>>()
+            .map(|types| types.join(", "))
+    };
+    match type_info {
+        TypeInfo::Named(name, params) if params.is_empty() => Some(name.clone()),
+        TypeInfo::Named(name, params) => Some(format!("{}<{}>", name, list(params)?)),
+        TypeInfo::Tuple(elements) => Some(format!("({})", list(elements)?)),
+        TypeInfo::Function(..) | TypeInfo::Unknown => None,
+        primitive => Some(primitive.to_string()),
+    }
+}
+
+/// The signature of the top-level definition, or type variant, defined at
+/// `site`
+fn definition_signature(text: &str, site: &SymbolSite) -> Option<String> {
+    let program = Parser::new(text).parse_program().ok()?;
+    let lines = LineIndex::new(text);
+    let defined_at = |name: &str, location| lines.find(text, name, location) == Some(site.range);
+
+    for definition in &program.definitions {
+        let signature = match definition {
+            Definition::FunctionDef {
+                name,
+                params,
+                return_type,
+                visibility,
+                location,
+                ..
+            } if defined_at(name, location) => {
+                let mut signature =
+                    format!("{}fn {}({})", public(*visibility), name, parameters(params));
+                if let Some(return_type) = return_type {
+                    signature.push_str(&format!(" -> {}", return_type));
+                }
+                signature
+            }
+            Definition::TypeDef {
+                name,
+                type_params,
+                variants,
+                visibility,
+                location,
+                ..
+            } if defined_at(name, location) => {
+                let mut signature = format!("{}type {}", public(*visibility), name);
+                if !type_params.is_empty() {
+                    signature.push_str(&format!("<{}>", type_params.join(", ")));
+                }
+                signature + &variant_list(variants)
+            }
+            Definition::ErrorDef {
+                name,
+                variants,
+                visibility,
+                location,
+                ..
+            } if defined_at(name, location) => {
+                format!("{}error {}", public(*visibility), name) + &variant_list(variants)
+            }
+            Definition::TypeDef { name, variants, .. }
+            | Definition::ErrorDef { name, variants, .. } => {
+                match variants
+                    .iter()
+                    .find(|variant| defined_at(&variant.name, &variant.location))
+                {
+                    Some(variant) => format!("{}/{}", name, variant_signature(variant)),
+                    None => continue,
+                }
+            }
+            Definition::ObjectDef {
+                name,
+                fields,
+                visibility,
+                location,
+                ..
+            } if defined_at(name, location) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|field| match &field.type_annotation {
+                        Some(ty) => format!("    {}: {},", field.name, ty),
+                        None => format!("    {},", field.name),
+                    })
+                    .collect();
+                format!(
+                    "{}object {} {{\n{}\n}}",
+                    public(*visibility),
+                    name,
+                    fields.join("\n")
+                )
+            }
+            Definition::TypeAlias {
+                name,
+                target_type,
+                visibility,
+                location,
+                ..
+            } if defined_at(name, location) => {
+                format!("{}type {} = {}", public(*visibility), name, target_type)
+            }
+            Definition::EventDef {
+                name,
+                fields,
+                visibility,
+                location,
+            } if defined_at(name, location) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|field| {
+                        let indexed = if field.indexed { "indexed " } else { "" };
+                        format!("{}{}: {}", indexed, field.name, field.ty)
+                    })
+                    .collect();
+                format!(
+                    "{}event {}({})",
+                    public(*visibility),
+                    name,
+                    fields.join(", ")
+                )
+            }
+            Definition::StorageDef { fields, .. } => {
+                match fields
+                    .iter()
+                    .find(|field| defined_at(&field.name, &field.location))
+                {
+                    Some(field) => format!("storage {}: {}", field.name, field.ty),
+                    None => continue,
+                }
+            }
+            Definition::GuardDef {
+                name,
+                params,
+                location,
+                ..
+            } if defined_at(name, location) => {
+                format!("guard {}({})", name, parameters(params))
+            }
+            Definition::InterfaceDef {
+                name,
+                visibility,
+                location,
+                ..
+            } if defined_at(name, location) => {
+                format!("{}interface {}", public(*visibility), name)
+            }
+            Definition::Module { name, location, .. } if defined_at(name, location) => {
+                format!("module {}", name)
+            }
+            _ => continue,
+        };
+        return Some(signature);
+    }
+    None
+}
+
+fn public(visibility: Visibility) -> &'static str {
+    match visibility {
+        Visibility::Public => "pub ",
+        Visibility::Private => "",
+    }
+}
+
+/// Parameters as they are written in a signature
+pub fn parameters(params: &[Parameter]) -> String {
+    params
+        .iter()
+        .map(|param| format!("{}: {}", param.name, param.ty))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn variant_signature(variant: &TypeVariant) -> String {
+    if variant.fields.is_empty() {
+        return variant.name.to_string();
+    }
+    let fields: Vec<String> = variant
+        .fields
+        .iter()
+        .map(|field| match &field.type_annotation {
+            Some(ty) => format!("{}: {}", field.name, ty),
+            None => field.name.to_string(),
+        })
+        .collect();
+    format!("{}({})", variant.name, fields.join(", "))
+}
+
+fn variant_list(variants: &[TypeVariant]) -> String {
+    let variants: Vec<String> = variants
+        .iter()
+        .map(|variant| format!("    {},", variant_signature(variant)))
+        .collect();
+    format!(" {{\n{}\n}}", variants.join("\n"))
+}
+
+/// The `#` comments directly above a 0-based line, skipping attributes
+/// between them and the definition
+pub fn doc_comment(text: &str, line: usize) -> Option<String> {
+    let lines: Vec<&str> = text.lines().take(line).collect();
+    let mut doc = Vec::new();
+    for line in lines.iter().rev().map(|line| line.trim()) {
+        if line.starts_with("#[") {
+            if doc.is_empty() {
+                continue;
+            }
+            break;
+        }
+        match line.strip_prefix('#') {
+            Some(comment) => doc.push(comment.strip_prefix(' ').unwrap_or(comment)),
+            None => break,
+        }
+    }
+    if doc.is_empty() {
+        return None;
+    }
+    doc.reverse();
+    Some(doc.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hover_text(text: &str, position: Position) -> Option<String> {
+        let uri = Url::parse("untitled:hover.bend").unwrap();
+        let mut documents = DocumentStore::default();
+        documents.open(uri.clone(), 1, text.to_string());
+        let hover = hover(&documents, &uri, position)?;
+        match hover.contents {
+            HoverContents::Markup(markup) => Some(markup.value),
+            _ => None,
+        }
+    }
+
+    const SOURCE: &str = "type Shape {\n    Circle(radius: u24),\n    Empty,\n}\n\n# The area of a shape.\n# Empty shapes have none.\n#[view]\npub fn area(shape: Shape) -> u24 {\n    match shape {\n        Shape/Circle(r) => { return r * r; }\n        Empty => { return 0; }\n    }\n}\n\nfn main() -> u24 {\n    total = area(Shape/Empty);\n    return total;\n}\n";
+
+    #[test]
+    fn test_function_signature_and_doc_comment() {
+        let value = hover_text(SOURCE, Position::new(16, 14)).unwrap();
+        assert_eq!(
+            value,
+            "```bend\npub fn area(shape: Shape) -> u24\n```\n\nThe area of a shape.\nEmpty shapes have none."
+        );
+    }
+
+    #[test]
+    fn test_variants_and_types() {
+        let value = hover_text(SOURCE, Position::new(16, 20)).unwrap();
+        assert_eq!(value, "```bend\nShape/Empty\n```");
+
+        let value = hover_text(SOURCE, Position::new(0, 6)).unwrap();
+        assert!(value.starts_with("```bend\ntype Shape {\n    Circle(radius: u24),"));
+    }
+
+    #[test]
+    fn test_local_types() {
+        let value = hover_text(SOURCE, Position::new(17, 12)).unwrap();
+        assert_eq!(value, "```bend\ntotal: u24\n```");
+
+        let value = hover_text(SOURCE, Position::new(10, 36)).unwrap();
+        assert_eq!(value, "```bend\nr: u24\n```");
+
+        assert!(hover_text(SOURCE, Position::new(4, 0)).is_none());
+    }
+}
@@ -0,0 +1,315 @@
+//! Inlay hints for the types of locals and the names of arguments
+//!
+//! Locals are bound by assignments and patterns without a type written
+//! next to them, so the type the type checker found is shown after the
+//! name that binds them. Arguments are labelled with the parameter they
+//! are passed to, unless they are a variable of the same name. Either kind
+//! of hint can be turned off in the initialization options:
+//!
+//! ```json
+//! { "inlayHints": { "types": false, "parameterNames": true } }
+//! ```
+
+use lsp_types::{InlayHint, InlayHintKind, InlayHintLabel, Range, Url};
+use serde_json::Value;
+
+use bend_pvm::compiler::lexer::lexer::BendLexer;
+use bend_pvm::compiler::lexer::token::Token;
+
+use crate::documents::DocumentStore;
+use crate::hover;
+use crate::signature_help;
+use crate::symbols::{DefinitionKind, LineIndex, SymbolIndex};
+
+/// Which hints to show
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InlayHintOptions {
+    pub types: bool,
+    pub parameter_names: bool,
+}
+
+impl Default for InlayHintOptions {
+    fn default() -> Self {
+        InlayHintOptions {
+            types: true,
+            parameter_names: true,
+        }
+    }
+}
+
+impl InlayHintOptions {
+    /// Read the options from the `inlayHints` section of the client's
+    /// initialization options, keeping the defaults for anything left out
+    pub fn from_initialization_options(options: Option<&Value>) -> Self {
+        let defaults = InlayHintOptions::default();
+        let section = options.and_then(|options| options.get("inlayHints"));
+        let flag = |key: &str, default: bool| {
+            section
+                .and_then(|section| section.get(key))
+                .and_then(Value::as_bool)
+                .unwrap_or(default)
+        };
+        InlayHintOptions {
+            types: flag("types", defaults.types),
+            parameter_names: flag("parameterNames", defaults.parameter_names),
+        }
+    }
+}
+
+/// Hints for the part of the document at `uri` within `range`
+pub fn inlay_hints(
+    documents: &DocumentStore,
+    uri: &Url,
+    range: Range,
+    options: InlayHintOptions,
+) -> Vec<InlayHint> {
+    let Some(text) = documents.source(uri) else {
+        return Vec::new();
+    };
+    let index = SymbolIndex::build(documents, uri);
+    let mut hints = Vec::new();
+    if options.types {
+        hints.extend(type_hints(documents, &index, uri, &text));
+    }
+    if options.parameter_names {
+        hints.extend(parameter_hints(documents, &index, uri, &text));
+    }
+    hints.retain(|hint| hint.position >= range.start && hint.position <= range.end);
+    hints.sort_by_key(|hint| (hint.position.line, hint.position.character));
+    hints
+}
+
+/// The type of every local where it is first bound
+fn type_hints(
+    documents: &DocumentStore,
+    index: &SymbolIndex,
+    uri: &Url,
+    text: &str,
+) -> Vec<InlayHint> {
+    // Types found before an error are still worth showing
+    let Ok(checked) = documents.check(uri) else {
+        return Vec::new();
+    };
+
+    let lines = LineIndex::new(text);
+    let types: Vec<(Range, String)> = checked
+        .name_types
+        .iter()
+        .filter_map(|(name, location, type_info)| {
+            Some((
+                lines.find(text, name, location)?,
+                hover::written_type(type_info)?,
+            ))
+        })
+        .collect();
+
+    index
+        .occurrences(uri)
+        .iter()
+        .filter(|occurrence| {
+            occurrence.is_definition && occurrence.site.kind == DefinitionKind::Variable
+        })
+        .filter_map(|occurrence| {
+            let (_, type_name) = types.iter().find(|(range, _)| *range == occurrence.range)?;
+            Some(InlayHint {
+                position: occurrence.range.end,
+                label: InlayHintLabel::String(format!(": {}", type_name)),
+                kind: Some(InlayHintKind::TYPE),
+                text_edits: None,
+                tooltip: None,
+                padding_left: None,
+                padding_right: None,
+                data: None,
+            })
+        })
+        .collect()
+}
+
+/// The parameter name before every argument of every call
+fn parameter_hints(
+    documents: &DocumentStore,
+    index: &SymbolIndex,
+    uri: &Url,
+    text: &str,
+) -> Vec<InlayHint> {
+    let Ok(module) = documents.module(uri) else {
+        return Vec::new();
+    };
+    let program = module.program();
+    let lines = LineIndex::new(text);
+
+    let mut hints = Vec::new();
+    for call in calls(text) {
+        // Variants are declared like calls
+        let declared = index
+            .occurrence_at(uri, lines.position(text, call.offset))
+            .is_some_and(|occurrence| occurrence.is_definition);
+        if declared {
+            continue;
+        }
+        let Some(names) = signature_help::parameter_names(
+            documents,
+            index,
+            uri,
+            text,
+            &program,
+            &call.name,
+            call.offset,
+        ) else {
+            continue;
+        };
+        for (name, argument) in names.iter().zip(&call.arguments) {
+            // `f(amount)` for a parameter named `amount` says enough
+            if text[argument.start..argument.end].trim() == name {
+                continue;
+            }
+            hints.push(InlayHint {
+                position: lines.position(text, argument.start),
+                label: InlayHintLabel::String(format!("{}:", name)),
+                kind: Some(InlayHintKind::PARAMETER),
+                text_edits: None,
+                tooltip: None,
+                padding_left: None,
+                padding_right: Some(true),
+                data: None,
+            });
+        }
+    }
+    hints
+}
+
+/// A call found in the text
+struct Call {
+    /// The called name and its byte offset
+    name: String,
+    offset: usize,
+    /// Byte ranges of the arguments
+    arguments: Vec<std::ops::Range<usize>>,
+}
+
+/// Every call in the text. Names followed by parentheses are calls unless
+/// they are being defined, or are a pattern like `Shape/Circle(r) =>`.
+fn calls(text: &str) -> Vec<Call> {
+    let mut lexer = BendLexer::new(text);
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next_token();
+        if token.token == Token::EOF {
+            break;
+        }
+        tokens.push(token);
+    }
+
+    let mut calls = Vec::new();
+    for i in 1..tokens.len() {
+        let Token::Identifier(name) = &tokens[i - 1].token else {
+            continue;
+        };
+        let defined = i >= 2 && tokens[i - 2].token == Token::Fn;
+        if tokens[i].token != Token::LParen || defined {
+            continue;
+        }
+
+        // Split the arguments at the commas between the parentheses
+        let mut arguments = Vec::new();
+        let mut start = None;
+        let mut depth = 0;
+        let mut close = None;
+        for (j, token) in tokens.iter().enumerate().skip(i) {
+            match token.token {
+                Token::LParen | Token::LBracket | Token::LBrace => {
+                    depth += 1;
+                    if depth == 1 {
+                        continue;
+                    }
+                }
+                Token::RParen | Token::RBracket | Token::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        arguments.extend(start.map(|start| start..tokens[j - 1].end));
+                        close = Some(j);
+                        break;
+                    }
+                }
+                Token::Comma if depth == 1 => {
+                    arguments.extend(start.take().map(|start| start..tokens[j - 1].end));
+                    continue;
+                }
+                _ => {}
+            }
+            start.get_or_insert(token.start);
+        }
+        let is_pattern = close
+            .and_then(|close| tokens.get(close + 1))
+            .is_some_and(|token| token.token == Token::FatArrow);
+        if close.is_some() && !is_pattern {
+            calls.push(Call {
+                name: name.to_string(),
+                offset: tokens[i - 1].start,
+                arguments,
+            });
+        }
+    }
+    calls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::Position;
+    use serde_json::json;
+
+    const SOURCE: &str = "type Shape {\n    Circle(radius: u24),\n}\n\nfn scale(value: u24, factor: u24) -> u24 {\n    return value * factor;\n}\n\nfn main() -> u24 {\n    factor = 2;\n    shape = Shape/Circle(scale(1, factor));\n    match shape {\n        Shape/Circle(r) => { return r; }\n    }\n}\n";
+
+    fn hints(text: &str, options: InlayHintOptions) -> Vec<(u32, u32, String)> {
+        let uri = Url::parse("untitled:hints.bend").unwrap();
+        let mut documents = DocumentStore::default();
+        documents.open(uri.clone(), 1, text.to_string());
+        let everything = Range::new(Position::new(0, 0), Position::new(u32::MAX, 0));
+        inlay_hints(&documents, &uri, everything, options)
+            .into_iter()
+            .map(|hint| {
+                let InlayHintLabel::String(label) = hint.label else {
+                    panic!("expected a plain label");
+                };
+                (hint.position.line, hint.position.character, label)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_type_and_parameter_hints() {
+        let hints = hints(SOURCE, InlayHintOptions::default());
+        assert_eq!(
+            hints,
+            vec![
+                (9, 10, ": u24".to_string()),
+                (10, 9, ": Shape".to_string()),
+                (10, 25, "radius:".to_string()),
+                (10, 31, "value:".to_string()),
+                (12, 22, ": u24".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_options_turn_hints_off() {
+        let options = InlayHintOptions::from_initialization_options(Some(&json!({
+            "inlayHints": { "types": false }
+        })));
+        assert_eq!(
+            options,
+            InlayHintOptions {
+                types: false,
+                parameter_names: true,
+            }
+        );
+        assert!(hints(SOURCE, options)
+            .iter()
+            .all(|(_, _, label)| !label.starts_with(':')));
+        assert_eq!(
+            InlayHintOptions::from_initialization_options(None),
+            InlayHintOptions::default()
+        );
+    }
+}
@@ -1,38 +1,47 @@
-use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+mod code_actions;
+mod code_lens;
+mod diagnostics;
+mod documents;
+mod formatting;
+mod hover;
+mod inlay_hints;
+mod outline;
+mod rename;
+mod semantic_tokens;
+mod signature_help;
+mod symbols;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
 use lsp_types::notification::{
-    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
-    PublishDiagnostics,
+    DidChangeTextDocument, DidChangeWatchedFiles, DidCloseTextDocument, DidOpenTextDocument,
+    DidSaveTextDocument, Notification as _, PublishDiagnostics,
 };
+use lsp_types::request::{CodeLensRefresh, Request as _};
 use lsp_types::*;
 use serde_json::Value;
 use std::error::Error;
-use std::fs;
-use std::path::Path;
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
-
-use bend_pvm::compiler::parser::{
-    ast::{Definition, Expr, Location as AstLocation, LocationProvider, Program, Statement},
-    parser::{ParseError, Parser},
-};
-
-// PERFORMANCE: Document cache with TTL for faster response times
-type DocumentCache = Arc<Mutex<lru_time_cache::LruCache<Url, (Program, Instant)>>>;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::Instant;
 
-// PERFORMANCE: Track parse times for monitoring
-type ParseMetrics = Arc<Mutex<Vec<Duration>>>;
-
-// PERFORMANCE: Configuration for caching and timeouts
-const CACHE_TTL_SECONDS: u64 = 300; // 5 minutes cache TTL
-const MAX_CACHE_SIZE: usize = 100; // Max documents in cache
-const PARSE_TIMEOUT_MS: u64 = 5000; // 5 second timeout for parsing
+use bend_pvm::compiler::module::cache::ModuleCache;
+use bend_pvm::compiler::query::{ModuleSymbol, SymbolKind};
+use diagnostics::PendingAnalysis;
+use documents::DocumentStore;
+use inlay_hints::InlayHintOptions;
+use symbols::SymbolIndex;
 
 fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
     let (connection, io_threads) = Connection::stdio();
 
     let server_capabilities = serde_json::to_value(ServerCapabilities {
-        text_document_sync: Some(TextDocumentSyncCapability::Kind(
-            TextDocumentSyncKind::INCREMENTAL,
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::INCREMENTAL),
+                save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                ..TextDocumentSyncOptions::default()
+            },
         )),
         completion_provider: Some(CompletionOptions {
             resolve_provider: Some(false),
@@ -47,62 +56,120 @@ fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
         }),
         definition_provider: Some(OneOf::Left(true)),
         references_provider: Some(OneOf::Left(true)),
+        document_highlight_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Right(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        })),
         document_symbol_provider: Some(OneOf::Left(true)),
         workspace_symbol_provider: Some(OneOf::Left(true)),
         code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
-            code_action_kinds: Some(vec![CodeActionKind::QUICKFIX, CodeActionKind::REFACTOR]),
+            code_action_kinds: Some(vec![
+                CodeActionKind::QUICKFIX,
+                CodeActionKind::REFACTOR,
+                CodeActionKind::REFACTOR_REWRITE,
+            ]),
             ..Default::default()
         })),
         semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
             SemanticTokensOptions {
-                legend: SemanticTokensLegend {
-                    token_types: vec![
-                        SemanticTokenType::KEYWORD,
-                        SemanticTokenType::TYPE,
-                        SemanticTokenType::FUNCTION,
-                        SemanticTokenType::VARIABLE,
-                        SemanticTokenType::STRING,
-                        SemanticTokenType::NUMBER,
-                        SemanticTokenType::COMMENT,
-                        SemanticTokenType::OPERATOR,
-                    ],
-                    token_modifiers: vec![
-                        SemanticTokenModifier::DECLARATION,
-                        SemanticTokenModifier::DEFINITION,
-                        SemanticTokenModifier::READONLY,
-                    ],
-                },
+                legend: semantic_tokens::legend(),
                 full: Some(SemanticTokensFullOptions::Bool(true)),
                 range: Some(true),
                 ..Default::default()
             },
         )),
         inlay_hint_provider: Some(OneOf::Left(true)),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
         document_formatting_provider: Some(OneOf::Left(true)),
+        document_range_formatting_provider: Some(OneOf::Left(true)),
+        document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+            first_trigger_character: formatting::ON_TYPE_TRIGGERS[0].to_string(),
+            more_trigger_character: Some(
+                formatting::ON_TYPE_TRIGGERS[1..]
+                    .iter()
+                    .map(|ch| ch.to_string())
+                    .collect(),
+            ),
+        }),
         ..ServerCapabilities::default()
     })
     .unwrap();
 
     let params = connection.initialize(server_capabilities)?;
-    let _init_params: InitializeParams = serde_json::from_value(params).unwrap();
+    let init_params: InitializeParams = serde_json::from_value(params).unwrap();
+    let roots = workspace_roots(&init_params);
+    let hint_options =
+        InlayHintOptions::from_initialization_options(init_params.initialization_options.as_ref());
+    let refresh_code_lenses = init_params
+        .capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.code_lens.as_ref())
+        .and_then(|code_lens| code_lens.refresh_support)
+        .unwrap_or(false);
+
+    let mut documents = DocumentStore::default();
+    let mut pending = PendingAnalysis::default();
+    loop {
+        documents.bound_names();
+        let session = documents.interner();
+
+        // Wait for the next message, analyzing changed documents whenever
+        // typing pauses long enough
+        let msg = match pending.deadline() {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                match connection.receiver.recv_timeout(timeout) {
+                    Ok(msg) => msg,
+                    Err(e) if e.is_timeout() => {
+                        session.enter(|| {
+                            for uri in pending.take_due(Instant::now()) {
+                                if let Err(e) = publish_diagnostics(&connection, &documents, uri) {
+                                    eprintln!("Error publishing diagnostics: {}", e);
+                                }
+                            }
+                        });
+                        continue;
+                    }
+                    Err(_) => break,
+                }
+            }
+            None => match connection.receiver.recv() {
+                Ok(msg) => msg,
+                Err(_) => break,
+            },
+        };
 
-    for msg in &connection.receiver {
         match msg {
             Message::Request(req) => {
                 if connection.handle_shutdown(&req)? {
                     break;
                 }
 
-                match handle_request(&connection, req) {
-                    Ok(()) => {}
-                    Err(e) => eprintln!("Error handling request: {}", e),
+                let handled = session
+                    .enter(|| handle_request(&connection, &documents, &roots, hint_options, req));
+                if let Err(e) = handled {
+                    eprintln!("Error handling request: {}", e);
                 }
             }
             Message::Response(_resp) => {}
-            Message::Notification(not) => match handle_notification(&connection, not) {
-                Ok(()) => {}
-                Err(e) => eprintln!("Error handling notification: {}", e),
-            },
+            Message::Notification(not) => {
+                let handled = session.enter(|| {
+                    handle_notification(
+                        &connection,
+                        &mut documents,
+                        &mut pending,
+                        refresh_code_lenses,
+                        not,
+                    )
+                });
+                if let Err(e) = handled {
+                    eprintln!("Error handling notification: {}", e);
+                }
+            }
         }
     }
 
@@ -110,14 +177,29 @@ fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
     Ok(())
 }
 
+/// Folders open in the editor, which workspace symbols are searched in
+#[allow(deprecated)]
+fn workspace_roots(params: &InitializeParams) -> Vec<PathBuf> {
+    let uris = match &params.workspace_folders {
+        Some(folders) => folders.iter().map(|folder| folder.uri.clone()).collect(),
+        None => params.root_uri.iter().cloned().collect::<Vec<_>>(),
+    };
+    uris.iter()
+        .filter_map(|uri| uri.to_file_path().ok())
+        .collect()
+}
+
 fn handle_request(
     connection: &Connection,
+    documents: &DocumentStore,
+    roots: &[PathBuf],
+    hint_options: InlayHintOptions,
     req: Request,
 ) -> Result<(), Box<dyn Error + Sync + Send>> {
     match req.method.as_str() {
         "textDocument/completion" => {
             let params = serde_json::from_value::<CompletionParams>(req.params.clone())?;
-            let completion_items = get_completion_items(&params);
+            let completion_items = get_completion_items(&params, documents);
             let result = Some(CompletionResponse::Array(completion_items));
             let resp = Response {
                 id: req.id,
@@ -128,7 +210,7 @@ fn handle_request(
         }
         "textDocument/hover" => {
             let params = serde_json::from_value::<HoverParams>(req.params.clone())?;
-            let hover = get_hover(&params);
+            let hover = get_hover(&params, documents);
             let resp = Response {
                 id: req.id,
                 result: Some(serde_json::to_value(hover)?),
@@ -138,7 +220,7 @@ fn handle_request(
         }
         "textDocument/definition" => {
             let params = serde_json::from_value::<GotoDefinitionParams>(req.params.clone())?;
-            let location = get_definition(&params);
+            let location = get_definition(&params, documents);
             let resp = Response {
                 id: req.id,
                 result: Some(serde_json::to_value(location)?),
@@ -148,7 +230,7 @@ fn handle_request(
         }
         "textDocument/references" => {
             let params = serde_json::from_value::<ReferenceParams>(req.params.clone())?;
-            let references = find_references(&params);
+            let references = find_references(&params, documents);
             let resp = Response {
                 id: req.id,
                 result: Some(serde_json::to_value(references)?),
@@ -156,9 +238,53 @@ fn handle_request(
             };
             connection.sender.send(Message::Response(resp))?;
         }
+        "textDocument/documentHighlight" => {
+            let params = serde_json::from_value::<DocumentHighlightParams>(req.params.clone())?;
+            let highlights = get_document_highlights(&params, documents);
+            let resp = Response {
+                id: req.id,
+                result: Some(serde_json::to_value(highlights)?),
+                error: None,
+            };
+            connection.sender.send(Message::Response(resp))?;
+        }
+        "textDocument/prepareRename" => {
+            let params = serde_json::from_value::<TextDocumentPositionParams>(req.params.clone())?;
+            let resp =
+                match rename::prepare_rename(documents, &params.text_document.uri, params.position)
+                {
+                    Ok(response) => Response {
+                        id: req.id,
+                        result: Some(serde_json::to_value(response)?),
+                        error: None,
+                    },
+                    Err(message) => {
+                        Response::new_err(req.id, ErrorCode::RequestFailed as i32, message)
+                    }
+                };
+            connection.sender.send(Message::Response(resp))?;
+        }
+        "textDocument/rename" => {
+            let params = serde_json::from_value::<RenameParams>(req.params.clone())?;
+            let position = &params.text_document_position;
+            let resp = match rename::rename(
+                documents,
+                &position.text_document.uri,
+                position.position,
+                &params.new_name,
+            ) {
+                Ok(edit) => Response {
+                    id: req.id,
+                    result: Some(serde_json::to_value(edit)?),
+                    error: None,
+                },
+                Err(message) => Response::new_err(req.id, ErrorCode::RequestFailed as i32, message),
+            };
+            connection.sender.send(Message::Response(resp))?;
+        }
         "textDocument/documentSymbol" => {
             let params = serde_json::from_value::<DocumentSymbolParams>(req.params.clone())?;
-            let symbols = get_document_symbols(&params);
+            let symbols = get_document_symbols(&params, documents);
             let resp = Response {
                 id: req.id,
                 result: Some(serde_json::to_value(symbols)?),
@@ -166,9 +292,9 @@ fn handle_request(
             };
             connection.sender.send(Message::Response(resp))?;
         }
-        "textDocument/semanticTokens" | "textDocument/semanticTokensFull" => {
+        "textDocument/semanticTokens/full" => {
             let params = serde_json::from_value::<SemanticTokensParams>(req.params.clone())?;
-            let tokens = get_semantic_tokens(&params);
+            let tokens = get_semantic_tokens(&params, documents);
             let resp = Response {
                 id: req.id,
                 result: Some(serde_json::to_value(tokens)?),
@@ -176,9 +302,9 @@ fn handle_request(
             };
             connection.sender.send(Message::Response(resp))?;
         }
-        "textDocument/semanticTokensRange" => {
+        "textDocument/semanticTokens/range" => {
             let params = serde_json::from_value::<SemanticTokensRangeParams>(req.params.clone())?;
-            let tokens = get_semantic_tokens_range(&params);
+            let tokens = get_semantic_tokens_range(&params, documents);
             let resp = Response {
                 id: req.id,
                 result: Some(serde_json::to_value(tokens)?),
@@ -186,9 +312,73 @@ fn handle_request(
             };
             connection.sender.send(Message::Response(resp))?;
         }
+        "textDocument/codeLens" => {
+            let params = serde_json::from_value::<CodeLensParams>(req.params.clone())?;
+            let lenses = get_code_lenses(&params, documents);
+            let resp = Response {
+                id: req.id,
+                result: Some(serde_json::to_value(lenses)?),
+                error: None,
+            };
+            connection.sender.send(Message::Response(resp))?;
+        }
+        "textDocument/formatting" => {
+            let params = serde_json::from_value::<DocumentFormattingParams>(req.params.clone())?;
+            let resp = match formatting::format_document(
+                documents,
+                &params.text_document.uri,
+                &params.options,
+            ) {
+                Ok(edits) => Response {
+                    id: req.id,
+                    result: Some(serde_json::to_value(edits)?),
+                    error: None,
+                },
+                Err(message) => Response::new_err(req.id, ErrorCode::RequestFailed as i32, message),
+            };
+            connection.sender.send(Message::Response(resp))?;
+        }
+        "textDocument/rangeFormatting" => {
+            let params =
+                serde_json::from_value::<DocumentRangeFormattingParams>(req.params.clone())?;
+            let resp = match formatting::format_range(
+                documents,
+                &params.text_document.uri,
+                params.range,
+                &params.options,
+            ) {
+                Ok(edits) => Response {
+                    id: req.id,
+                    result: Some(serde_json::to_value(edits)?),
+                    error: None,
+                },
+                Err(message) => Response::new_err(req.id, ErrorCode::RequestFailed as i32, message),
+            };
+            connection.sender.send(Message::Response(resp))?;
+        }
+        "textDocument/onTypeFormatting" => {
+            let params =
+                serde_json::from_value::<DocumentOnTypeFormattingParams>(req.params.clone())?;
+            let position = params.text_document_position;
+            let resp = match formatting::format_on_type(
+                documents,
+                &position.text_document.uri,
+                position.position,
+                &params.ch,
+                &params.options,
+            ) {
+                Ok(edits) => Response {
+                    id: req.id,
+                    result: Some(serde_json::to_value(edits)?),
+                    error: None,
+                },
+                Err(message) => Response::new_err(req.id, ErrorCode::RequestFailed as i32, message),
+            };
+            connection.sender.send(Message::Response(resp))?;
+        }
         "textDocument/inlayHint" => {
             let params = serde_json::from_value::<InlayHintParams>(req.params.clone())?;
-            let hints = get_inlay_hints(&params);
+            let hints = get_inlay_hints(&params, documents, hint_options);
             let resp = Response {
                 id: req.id,
                 result: Some(serde_json::to_value(hints)?),
@@ -198,7 +388,7 @@ fn handle_request(
         }
         "textDocument/signatureHelp" => {
             let params = serde_json::from_value::<SignatureHelpParams>(req.params.clone())?;
-            let signature_help = get_signature_help(&params);
+            let signature_help = get_signature_help(&params, documents);
             let resp = Response {
                 id: req.id,
                 result: Some(serde_json::to_value(signature_help)?),
@@ -208,7 +398,7 @@ fn handle_request(
         }
         "textDocument/codeAction" => {
             let params = serde_json::from_value::<CodeActionParams>(req.params.clone())?;
-            let code_actions = get_code_actions(&params);
+            let code_actions = get_code_actions(&params, documents);
             let resp = Response {
                 id: req.id,
                 result: Some(serde_json::to_value(code_actions)?),
@@ -218,7 +408,7 @@ fn handle_request(
         }
         "workspace/symbol" => {
             let params = serde_json::from_value::<WorkspaceSymbolParams>(req.params.clone())?;
-            let symbols = get_workspace_symbols(&params);
+            let symbols = get_workspace_symbols(&params, documents, roots);
             let resp = Response {
                 id: req.id,
                 result: Some(serde_json::to_value(symbols)?),
@@ -241,120 +431,89 @@ fn handle_request(
 
 fn handle_notification(
     connection: &Connection,
+    documents: &mut DocumentStore,
+    pending: &mut PendingAnalysis,
+    refresh_code_lenses: bool,
     not: Notification,
 ) -> Result<(), Box<dyn Error + Sync + Send>> {
     match not.method.as_str() {
         DidOpenTextDocument::METHOD => {
             let params = serde_json::from_value::<DidOpenTextDocumentParams>(not.params)?;
-            publish_diagnostics(
-                connection,
-                params.text_document.uri,
-                &params.text_document.text,
-            )?;
+            let document = params.text_document;
+            documents.open(document.uri.clone(), document.version, document.text);
+            publish_diagnostics(connection, documents, document.uri)?;
         }
         DidChangeTextDocument::METHOD => {
             let params = serde_json::from_value::<DidChangeTextDocumentParams>(not.params)?;
-            if let Some(change) = params.content_changes.first() {
-                if change.range.is_none() {
-                    publish_diagnostics(connection, params.text_document.uri, &change.text)?;
-                } else {
-                    publish_diagnostics(connection, params.text_document.uri, &change.text)?;
-                }
+            let document = params.text_document;
+            // Modules importing the edited one must be rebuilt
+            if let Ok(path) = document.uri.to_file_path() {
+                ModuleCache::for_path(&path).invalidate(&path);
+            }
+            documents.change(&document.uri, document.version, params.content_changes);
+            pending.schedule(document.uri);
+        }
+        // Files changed outside the editor may be imported by open documents
+        DidChangeWatchedFiles::METHOD => {
+            let params = serde_json::from_value::<DidChangeWatchedFilesParams>(not.params)?;
+            for change in &params.changes {
+                documents.reload(&change.uri);
+            }
+            let open: Vec<Url> = documents.uris().cloned().collect();
+            for uri in open {
+                pending.schedule(uri);
             }
         }
+        // Gas estimates and tests change with the saved code
+        DidSaveTextDocument::METHOD if refresh_code_lenses => {
+            let request = Request::new(
+                next_request_id(),
+                CodeLensRefresh::METHOD.to_string(),
+                Value::Null,
+            );
+            connection.sender.send(Message::Request(request))?;
+        }
+        DidCloseTextDocument::METHOD => {
+            let params = serde_json::from_value::<DidCloseTextDocumentParams>(not.params)?;
+            let uri = params.text_document.uri;
+            documents.close(&uri);
+            pending.cancel(&uri);
+            send_diagnostics(connection, uri, Vec::new(), None)?;
+        }
         _ => {}
     }
     Ok(())
 }
 
+/// Id for a request sent to the client
+fn next_request_id() -> lsp_server::RequestId {
+    static NEXT: AtomicI32 = AtomicI32::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed).into()
+}
+
+/// Analyze an open document and publish what was found
 fn publish_diagnostics(
     connection: &Connection,
+    documents: &DocumentStore,
     uri: Url,
-    text: &str,
 ) -> Result<(), Box<dyn Error + Sync + Send>> {
-    let mut diagnostics = Vec::new();
-
-    let mut parser = Parser::new(text);
-    if let Err(e) = parser.parse_program() {
-        let diagnostic = match e {
-            ParseError::UnexpectedToken {
-                line,
-                column,
-                found,
-                expected,
-                ..
-            } => Diagnostic {
-                range: Range {
-                    start: Position {
-                        line: (line - 1) as u32,
-                        character: (column - 1) as u32,
-                    },
-                    end: Position {
-                        line: (line - 1) as u32,
-                        character: (column - 1 + found.len()) as u32,
-                    },
-                },
-                severity: Some(DiagnosticSeverity::ERROR),
-                message: format!("Unexpected token '{}', expected '{}'", found, expected),
-                source: Some("bend-pvm".to_string()),
-                ..Diagnostic::default()
-            },
-            ParseError::LexicalError(msg) => Diagnostic {
-                range: Range {
-                    start: Position {
-                        line: 0,
-                        character: 0,
-                    },
-                    end: Position {
-                        line: 0,
-                        character: 1,
-                    },
-                },
-                severity: Some(DiagnosticSeverity::ERROR),
-                message: format!("Lexical error: {}", msg),
-                source: Some("bend-pvm".to_string()),
-                ..Diagnostic::default()
-            },
-            ParseError::InvalidPattern(msg) => Diagnostic {
-                range: Range {
-                    start: Position {
-                        line: 0,
-                        character: 0,
-                    },
-                    end: Position {
-                        line: 0,
-                        character: 1,
-                    },
-                },
-                severity: Some(DiagnosticSeverity::ERROR),
-                message: format!("Invalid pattern: {}", msg),
-                source: Some("bend-pvm".to_string()),
-                ..Diagnostic::default()
-            },
-            _ => Diagnostic {
-                range: Range {
-                    start: Position {
-                        line: 0,
-                        character: 0,
-                    },
-                    end: Position {
-                        line: 0,
-                        character: 1,
-                    },
-                },
-                severity: Some(DiagnosticSeverity::ERROR),
-                message: format!("Parse error: {}", e),
-                source: Some("bend-pvm".to_string()),
-                ..Diagnostic::default()
-            },
-        };
-        diagnostics.push(diagnostic);
-    }
+    let Some(document) = documents.get(&uri) else {
+        return Ok(());
+    };
+    let diagnostics = diagnostics::analyze(documents, &uri);
+    send_diagnostics(connection, uri, diagnostics, Some(document.version()))
+}
 
+fn send_diagnostics(
+    connection: &Connection,
+    uri: Url,
+    diagnostics: Vec<Diagnostic>,
+    version: Option<i32>,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
     let params = PublishDiagnosticsParams {
         uri,
         diagnostics,
-        version: None,
+        version,
     };
 
     let not = Notification {
@@ -366,8 +525,13 @@ fn publish_diagnostics(
     Ok(())
 }
 
-fn get_completion_items(_params: &CompletionParams) -> Vec<CompletionItem> {
-    vec![
+/// Keywords, then the names the document defines and those the modules it
+/// imports export
+fn get_completion_items(
+    params: &CompletionParams,
+    documents: &DocumentStore,
+) -> Vec<CompletionItem> {
+    let mut items = vec![
         CompletionItem {
             label: "def".to_string(),
             kind: Some(CompletionItemKind::KEYWORD),
@@ -413,431 +577,225 @@ fn get_completion_items(_params: &CompletionParams) -> Vec<CompletionItem> {
             kind: Some(CompletionItemKind::KEYWORD),
             ..CompletionItem::default()
         },
-    ]
-}
-
-fn get_definition(params: &GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
-    let position = params.text_document_position_params.position;
-    let document_uri = params
-        .text_document_position_params
-        .text_document
-        .uri
-        .clone();
-    let document_path = document_uri.to_file_path().ok()?;
-    let document_text = fs::read_to_string(document_path).ok()?;
-
-    let mut parser = Parser::new(&document_text);
-    let program = parser.parse_program().ok()?;
-
-    let target_name = find_identifier_at_pos(
-        &program,
-        (position.line + 1) as usize,
-        (position.character + 1) as usize,
-    )?;
-    let def_loc = find_definition(&program, &target_name)?;
-
-    Some(GotoDefinitionResponse::Scalar(Location {
-        uri: document_uri,
-        range: Range {
-            start: Position {
-                line: (def_loc.line - 1) as u32,
-                character: (def_loc.column - 1) as u32,
-            },
-            end: Position {
-                line: (def_loc.line - 1) as u32,
-                character: (def_loc.column - 1 + target_name.len()) as u32,
-            },
-        },
-    }))
-}
-
-fn get_hover(params: &HoverParams) -> Option<Hover> {
-    let position = params.text_document_position_params.position;
-    let document_uri = params
-        .text_document_position_params
-        .text_document
-        .uri
-        .clone();
-    let document_path = document_uri.to_file_path().ok()?;
-    let document_text = fs::read_to_string(document_path).ok()?;
-
-    let mut parser = Parser::new(&document_text);
-    if let Ok(program) = parser.parse_program() {
-        if let Some(name) = find_identifier_at_pos(
-            &program,
-            (position.line + 1) as usize,
-            (position.character + 1) as usize,
-        ) {
-            if let Some(_) = find_definition(&program, &name) {
-                return Some(Hover {
-                    contents: HoverContents::Markup(MarkupContent {
-                        kind: MarkupKind::Markdown,
-                        value: format!("**Function**: `{}`", name),
-                    }),
-                    range: None,
-                });
-            }
-        }
-    }
-    None
-}
-
-fn find_identifier_at_pos(program: &Program, line: usize, col: usize) -> Option<String> {
-    for def in &program.definitions {
-        if let Some(name) = find_in_def(def, line, col) {
-            return Some(name);
-        }
-    }
-    None
-}
-
-fn find_in_def(def: &Definition, line: usize, col: usize) -> Option<String> {
-    match def {
-        Definition::FunctionDef { body, .. } => find_in_block(body, line, col),
-        _ => None,
-    }
-}
+    ];
+
+    let uri = &params.text_document_position.text_document.uri;
+    documents.query(uri, |database, path| {
+        let item = |symbol: &ModuleSymbol| CompletionItem {
+            label: symbol.name.clone(),
+            kind: Some(match symbol.kind {
+                SymbolKind::Function | SymbolKind::Guard => CompletionItemKind::FUNCTION,
+                SymbolKind::Type => CompletionItemKind::ENUM,
+                SymbolKind::Object => CompletionItemKind::STRUCT,
+                SymbolKind::Module => CompletionItemKind::MODULE,
+                SymbolKind::Event => CompletionItemKind::EVENT,
+            }),
+            ..CompletionItem::default()
+        };
+        items.extend(database.symbols(path).iter().map(item));
 
-fn find_in_block(
-    block: &bend_pvm::compiler::parser::ast::Block,
-    line: usize,
-    col: usize,
-) -> Option<String> {
-    for stmt in &block.statements {
-        if let Some(name) = find_in_stmt(stmt, line, col) {
-            return Some(name);
+        let Ok(module) = database.module(path) else {
+            return;
+        };
+        let mut imported: Vec<&PathBuf> =
+            module.imports.values().map(|module| &module.path).collect();
+        imported.sort();
+        for path in imported {
+            let symbols = database.symbols(path);
+            items.extend(symbols.iter().filter(|symbol| symbol.public).map(item));
         }
-    }
-    None
+    });
+    items
 }
 
-fn find_in_stmt(stmt: &Statement, line: usize, col: usize) -> Option<String> {
-    match stmt {
-        Statement::Expr { expr, .. } => find_in_expr(expr, line, col),
-        Statement::Assignment { value, .. } => find_in_expr(value, line, col),
-        Statement::Return { value, .. } => find_in_expr(value, line, col),
-        Statement::LocalDef { function_def, .. } => find_in_def(function_def, line, col),
-        _ => None,
-    }
+fn get_definition(
+    params: &GotoDefinitionParams,
+    documents: &DocumentStore,
+) -> Option<GotoDefinitionResponse> {
+    let position = &params.text_document_position_params;
+    let index = SymbolIndex::build(documents, &position.text_document.uri);
+    let location = index.definition(&position.text_document.uri, position.position)?;
+    Some(GotoDefinitionResponse::Scalar(location))
 }
 
-fn find_in_expr(expr: &Expr, line: usize, col: usize) -> Option<String> {
-    let loc = expr.location();
-    match expr {
-        Expr::Variable { name, location } => {
-            if location.line == line && col >= location.column && col < location.column + name.len()
-            {
-                return Some(name.clone());
-            }
-            None
-        }
-        Expr::FunctionCall { function, args, .. } => {
-            if let Some(name) = find_in_expr(function, line, col) {
-                return Some(name);
-            }
-            for arg in args {
-                if let Some(name) = find_in_expr(arg, line, col) {
-                    return Some(name);
-                }
-            }
-            None
-        }
-        Expr::BinaryOp { left, right, .. } => {
-            if let Some(name) = find_in_expr(left, line, col) {
-                return Some(name);
-            }
-            if let Some(name) = find_in_expr(right, line, col) {
-                return Some(name);
-            }
-            None
-        }
-        _ => None,
-    }
+fn get_hover(params: &HoverParams, documents: &DocumentStore) -> Option<Hover> {
+    let position = &params.text_document_position_params;
+    hover::hover(documents, &position.text_document.uri, position.position)
 }
 
-fn find_definition(program: &Program, name: &str) -> Option<AstLocation> {
-    for def in &program.definitions {
-        match def {
-            Definition::FunctionDef {
-                name: def_name,
-                location,
-                ..
-            } => {
-                if def_name == name {
-                    return Some(location.clone());
-                }
-            }
-            Definition::TypeDef {
-                name: def_name,
-                location,
-                ..
-            } => {
-                if def_name == name {
-                    return Some(location.clone());
-                }
-            }
-            Definition::ObjectDef {
-                name: def_name,
-                location,
-                ..
-            } => {
-                if def_name == name {
-                    return Some(location.clone());
-                }
-            }
-            Definition::TypeAlias {
-                name: def_name,
-                location,
-                ..
-            } => {
-                if def_name == name {
-                    return Some(location.clone());
-                }
-            }
-            Definition::Module {
-                name: def_name,
-                location,
-                ..
-            } => {
-                if def_name == name {
-                    return Some(location.clone());
-                }
-            }
-        }
-    }
-    None
+fn find_references(params: &ReferenceParams, documents: &DocumentStore) -> Option<Vec<Location>> {
+    let position = &params.text_document_position;
+    let index = SymbolIndex::build(documents, &position.text_document.uri);
+    Some(index.references(
+        &position.text_document.uri,
+        position.position,
+        params.context.include_declaration,
+    ))
 }
 
-fn find_references(_params: &ReferenceParams) -> Option<Vec<Location>> {
-    Some(Vec::new())
+fn get_document_highlights(
+    params: &DocumentHighlightParams,
+    documents: &DocumentStore,
+) -> Option<Vec<DocumentHighlight>> {
+    let position = &params.text_document_position_params;
+    let index = SymbolIndex::build(documents, &position.text_document.uri);
+    Some(index.highlights(&position.text_document.uri, position.position))
 }
 
-fn get_document_symbols(params: &DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+fn get_document_symbols(
+    params: &DocumentSymbolParams,
+    documents: &DocumentStore,
+) -> Option<DocumentSymbolResponse> {
     let document_uri = &params.text_document.uri;
-    let document_path = document_uri.to_file_path().ok()?;
-    let document_text = fs::read_to_string(document_path).ok()?;
-
-    let mut parser = Parser::new(&document_text);
-    let program = parser.parse_program().ok()?;
-
-    let mut symbols = Vec::new();
-
-    for def in &program.definitions {
-        if let Some(symbol) = convert_definition_to_symbol(def, document_uri) {
-            symbols.push(symbol);
-        }
-    }
-
-    Some(DocumentSymbolResponse::Nested(symbols))
-}
-
-fn convert_definition_to_symbol(def: &Definition, uri: &Url) -> Option<DocumentSymbol> {
-    match def {
-        Definition::FunctionDef {
-            name,
-            location,
-            body,
-            ..
-        } => {
-            let range = Range {
-                start: Position {
-                    line: (location.line - 1) as u32,
-                    character: (location.column - 1) as u32,
-                },
-                end: Position {
-                    line: (location.line - 1) as u32,
-                    character: (location.column - 1 + name.len()) as u32,
-                },
-            };
-
-            let mut children = Vec::new();
-            collect_block_symbols(body, uri, &mut children);
-
-            Some(DocumentSymbol {
-                name: name.clone(),
-                kind: SymbolKind::FUNCTION,
-                tags: None,
-                detail: None,
-                range,
-                selection_range: range,
-                children: if children.is_empty() {
-                    None
-                } else {
-                    Some(children)
-                },
-                deprecated: None,
-            })
-        }
-        Definition::TypeDef { name, location, .. } => {
-            let range = Range {
-                start: Position {
-                    line: (location.line - 1) as u32,
-                    character: (location.column - 1) as u32,
-                },
-                end: Position {
-                    line: (location.line - 1) as u32,
-                    character: (location.column - 1 + name.len()) as u32,
-                },
-            };
-
-            Some(DocumentSymbol {
-                name: name.clone(),
-                kind: SymbolKind::TYPE_PARAMETER,
-                tags: None,
-                detail: None,
-                range,
-                selection_range: range,
-                children: None,
-                deprecated: None,
-            })
-        }
-        Definition::ObjectDef { name, location, .. } => {
-            let range = Range {
-                start: Position {
-                    line: (location.line - 1) as u32,
-                    character: (location.column - 1) as u32,
-                },
-                end: Position {
-                    line: (location.line - 1) as u32,
-                    character: (location.column - 1 + name.len()) as u32,
-                },
-            };
-
-            Some(DocumentSymbol {
-                name: name.clone(),
-                kind: SymbolKind::OBJECT,
-                tags: None,
-                detail: None,
-                range,
-                selection_range: range,
-                children: None,
-                deprecated: None,
-            })
-        }
-        Definition::TypeAlias { name, location, .. } => {
-            let range = Range {
-                start: Position {
-                    line: (location.line - 1) as u32,
-                    character: (location.column - 1) as u32,
-                },
-                end: Position {
-                    line: (location.line - 1) as u32,
-                    character: (location.column - 1 + name.len()) as u32,
-                },
-            };
-
-            Some(DocumentSymbol {
-                name: name.clone(),
-                kind: SymbolKind::CLASS,
-                tags: None,
-                detail: None,
-                range,
-                selection_range: range,
-                children: None,
-                deprecated: None,
-            })
-        }
-        Definition::Module { name, location, .. } => {
-            let range = Range {
-                start: Position {
-                    line: (location.line - 1) as u32,
-                    character: (location.column - 1) as u32,
-                },
-                end: Position {
-                    line: (location.line - 1) as u32,
-                    character: (location.column - 1 + name.len()) as u32,
-                },
-            };
-
-            Some(DocumentSymbol {
-                name: name.clone(),
-                kind: SymbolKind::MODULE,
-                tags: None,
-                detail: None,
-                range,
-                selection_range: range,
-                children: None,
-                deprecated: None,
-            })
-        }
-    }
-}
-
-fn collect_block_symbols(
-    block: &bend_pvm::compiler::parser::ast::Block,
-    uri: &Url,
-    symbols: &mut Vec<DocumentSymbol>,
-) {
-    for stmt in &block.statements {
-        match stmt {
-            Statement::LocalDef { function_def, .. } => {
-                if let Some(symbol) = convert_definition_to_symbol(function_def, uri) {
-                    symbols.push(symbol);
-                }
-            }
-            _ => {}
-        }
-    }
+    let text = documents.source(document_uri)?;
+    let program = documents.program(document_uri)?;
+    Some(DocumentSymbolResponse::Nested(outline::document_symbols(
+        &text, &program,
+    )))
 }
 
-fn get_workspace_symbols(_params: &WorkspaceSymbolParams) -> Option<Vec<WorkspaceSymbol>> {
-    Some(Vec::new())
+fn get_workspace_symbols(
+    params: &WorkspaceSymbolParams,
+    documents: &DocumentStore,
+    roots: &[PathBuf],
+) -> Option<Vec<WorkspaceSymbol>> {
+    Some(outline::workspace_symbols(documents, roots, &params.query))
 }
 
-fn get_semantic_tokens(_params: &SemanticTokensParams) -> Option<SemanticTokensResult> {
-    None
+fn get_semantic_tokens(
+    params: &SemanticTokensParams,
+    documents: &DocumentStore,
+) -> Option<SemanticTokensResult> {
+    semantic_tokens::semantic_tokens(documents, &params.text_document.uri, None)
+        .map(SemanticTokensResult::Tokens)
 }
 
 fn get_semantic_tokens_range(
-    _params: &SemanticTokensRangeParams,
+    params: &SemanticTokensRangeParams,
+    documents: &DocumentStore,
 ) -> Option<SemanticTokensRangeResult> {
-    None
+    semantic_tokens::semantic_tokens(documents, &params.text_document.uri, Some(params.range))
+        .map(SemanticTokensRangeResult::Tokens)
 }
 
-fn get_inlay_hints(_params: &InlayHintParams) -> Option<Vec<InlayHint>> {
-    Some(Vec::new())
+fn get_code_lenses(params: &CodeLensParams, documents: &DocumentStore) -> Option<Vec<CodeLens>> {
+    Some(code_lens::code_lenses(documents, &params.text_document.uri))
 }
 
-fn get_signature_help(_params: &SignatureHelpParams) -> Option<SignatureHelp> {
-    Some(SignatureHelp {
+fn get_inlay_hints(
+    params: &InlayHintParams,
+    documents: &DocumentStore,
+    options: InlayHintOptions,
+) -> Option<Vec<InlayHint>> {
+    Some(inlay_hints::inlay_hints(
+        documents,
+        &params.text_document.uri,
+        params.range,
+        options,
+    ))
+}
+
+fn get_signature_help(
+    params: &SignatureHelpParams,
+    documents: &DocumentStore,
+) -> Option<SignatureHelp> {
+    let position = &params.text_document_position_params;
+    let help =
+        signature_help::signature_help(documents, &position.text_document.uri, position.position);
+    Some(help.unwrap_or(SignatureHelp {
         signatures: Vec::new(),
         active_signature: None,
         active_parameter: None,
-    })
+    }))
 }
 
-fn get_code_actions(_params: &CodeActionParams) -> Option<Vec<CodeAction>> {
-    Some(Vec::new())
+fn get_code_actions(
+    params: &CodeActionParams,
+    documents: &DocumentStore,
+) -> Option<CodeActionResponse> {
+    let mut actions = code_actions::code_actions(
+        documents,
+        &params.text_document.uri,
+        params.range,
+        &params.context.diagnostics,
+    );
+    // Clients may only ask for some kinds, e.g. only quick fixes
+    if let Some(only) = &params.context.only {
+        actions.retain(|action| match action {
+            CodeActionOrCommand::CodeAction(CodeAction {
+                kind: Some(kind), ..
+            }) => only.iter().any(|wanted| {
+                kind.as_str() == wanted.as_str()
+                    || kind.as_str().starts_with(&format!("{}.", wanted.as_str()))
+            }),
+            _ => false,
+        });
+    }
+    Some(actions)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn test_document() -> TextDocumentIdentifier {
+        TextDocumentIdentifier::new(Url::parse("file:///test.bend").unwrap())
+    }
+
+    fn test_position() -> TextDocumentPositionParams {
+        TextDocumentPositionParams::new(test_document(), Position::new(0, 0))
+    }
+
     #[test]
     fn test_get_completion_items_returns_keywords() {
-        let params = CompletionParams::new(
-            TextDocumentIdentifier::new(Url::parse("file:///test.bend").unwrap()),
-            Position::new(0, 0),
-            CompletionContext::new(),
-        );
-        let items = get_completion_items(&params);
+        let params = CompletionParams {
+            text_document_position: test_position(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+        let items = get_completion_items(&params, &DocumentStore::default());
         assert!(!items.is_empty());
         assert_eq!(items[0].label, "def");
         assert_eq!(items[0].kind, Some(CompletionItemKind::KEYWORD));
     }
 
     #[test]
-    fn test_get_signature_help_returns_empty() {
-        let params = SignatureHelpParams::new(
-            TextDocumentPositionParams::new(
-                TextDocumentIdentifier::new(Url::parse("file:///test.bend").unwrap()),
+    fn test_get_completion_items_include_symbols() {
+        let uri = Url::parse("untitled:completion.bend").unwrap();
+        let mut documents = DocumentStore::default();
+        let text =
+            "from std/Option import Option;\n\nfn twice(x: u24) -> u24 {\n    return x * 2;\n}\n";
+        documents.open(uri.clone(), 1, text.to_string());
+        let params = CompletionParams {
+            text_document_position: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri),
                 Position::new(0, 0),
             ),
-            None,
-            None,
-        );
-        let help = get_signature_help(&params);
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+
+        let items = get_completion_items(&params, &documents);
+        let kind = |label: &str| {
+            items
+                .iter()
+                .find(|item| item.label == label)
+                .map(|item| item.kind)
+        };
+        assert_eq!(kind("twice"), Some(Some(CompletionItemKind::FUNCTION)));
+        assert_eq!(kind("Option"), Some(Some(CompletionItemKind::ENUM)));
+    }
+
+    #[test]
+    fn test_get_signature_help_returns_empty() {
+        let params = SignatureHelpParams {
+            context: None,
+            text_document_position_params: test_position(),
+            work_done_progress_params: Default::default(),
+        };
+        let help = get_signature_help(&params, &DocumentStore::default());
         assert!(help.is_some());
         let help = help.unwrap();
         assert!(help.signatures.is_empty());
@@ -847,65 +805,68 @@ mod tests {
 
     #[test]
     fn test_get_code_actions_returns_empty() {
-        let params = CodeActionParams::new(
-            TextDocumentIdentifier::new(Url::parse("file:///test.bend").unwrap()),
-            Range::new(Position::new(0, 0), Position::new(0, 10)),
-            CodeActionContext::new(),
-        );
-        let actions = get_code_actions(&params);
+        let params = CodeActionParams {
+            text_document: test_document(),
+            range: Range::new(Position::new(0, 0), Position::new(0, 10)),
+            context: CodeActionContext::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = get_code_actions(&params, &DocumentStore::default());
         assert!(actions.is_some());
         assert!(actions.unwrap().is_empty());
     }
 
     #[test]
     fn test_get_workspace_symbols_returns_empty() {
-        let params = WorkspaceSymbolParams::new(Query::new("test".to_string(), None));
-        let symbols = get_workspace_symbols(&params);
+        let params = WorkspaceSymbolParams {
+            query: "test".to_string(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let symbols = get_workspace_symbols(&params, &DocumentStore::default(), &[]);
         assert!(symbols.is_some());
         assert!(symbols.unwrap().is_empty());
     }
 
     #[test]
     fn test_find_references_returns_empty() {
-        let params = ReferenceParams::new(
-            TextDocumentPositionParams::new(
-                TextDocumentIdentifier::new(Url::parse("file:///test.bend").unwrap()),
-                Position::new(0, 0),
-            ),
-            None,
-            None,
-            None,
-        );
-        let refs = find_references(&params);
+        let params = ReferenceParams {
+            text_document_position: test_position(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: ReferenceContext {
+                include_declaration: true,
+            },
+        };
+        let refs = find_references(&params, &DocumentStore::default());
         assert!(refs.is_some());
         assert!(refs.unwrap().is_empty());
     }
 
     #[test]
     fn test_get_semantic_tokens_returns_empty() {
-        let params = SemanticTokensParams::new(
-            TextDocumentIdentifier::new(Url::parse("file:///test.bend").unwrap()),
-            None,
-        );
-        let tokens = get_semantic_tokens(&params);
-        assert!(tokens.is_some());
-        let tokens = tokens.unwrap();
-        match tokens {
-            SemanticTokensResult::Partial(semantic_tokens) => {
-                assert!(semantic_tokens.data.is_empty());
-            }
-            _ => {}
-        }
+        let params = SemanticTokensParams {
+            text_document: test_document(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let tokens = get_semantic_tokens(&params, &DocumentStore::default());
+        assert!(tokens.is_none());
     }
 
     #[test]
     fn test_get_inlay_hints_returns_empty() {
-        let params = InlayHintParams::new(
-            TextDocumentIdentifier::new(Url::parse("file:///test.bend").unwrap()),
-            Range::new(Position::new(0, 0), Position::new(10, 0)),
-            None,
+        let params = InlayHintParams {
+            text_document: test_document(),
+            range: Range::new(Position::new(0, 0), Position::new(10, 0)),
+            work_done_progress_params: Default::default(),
+        };
+        let hints = get_inlay_hints(
+            &params,
+            &DocumentStore::default(),
+            InlayHintOptions::default(),
         );
-        let hints = get_inlay_hints(&params);
         assert!(hints.is_some());
         assert!(hints.unwrap().is_empty());
     }
@@ -0,0 +1,611 @@
+//! Document outlines and workspace symbol search
+//!
+//! The outline nests variants under their types, fields and methods under
+//! their objects, and local functions under the functions defining them.
+//! The parser only records where a definition starts, so a definition is
+//! taken to extend to the next one, less the blank lines and comments in
+//! between.
+//!
+//! Workspace symbols flatten the outlines of every module in the open
+//! workspace folders and every open document, and are matched against the
+//! query as a fuzzy subsequence.
+
+use lsp_types::{DocumentSymbol, Location, OneOf, Range, SymbolKind, Url, WorkspaceSymbol};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bend_pvm::compiler::module::cache::canonical;
+use bend_pvm::compiler::parser::ast::{
+    Block, Definition, Field, Location as AstLocation, Program, Statement, TypeVariant,
+};
+
+use crate::diagnostics;
+use crate::documents::DocumentStore;
+use crate::hover;
+use crate::symbols::LineIndex;
+
+/// Most symbols returned for one workspace search
+const MAX_WORKSPACE_SYMBOLS: usize = 256;
+
+/// The outline of a document
+pub fn document_symbols(text: &str, program: &Program) -> Vec<DocumentSymbol> {
+    let outline = Outline {
+        text,
+        lines: LineIndex::new(text),
+    };
+    outline.definitions(&program.definitions, text.len())
+}
+
+struct Outline<'a> {
+    text: &'a str,
+    lines: LineIndex,
+}
+
+impl Outline<'_> {
+    /// Symbols for sibling definitions, none of which extends past `end`
+    fn definitions(&self, definitions: &[Definition], end: usize) -> Vec<DocumentSymbol> {
+        let starts: Vec<Option<usize>> = definitions
+            .iter()
+            .map(|definition| {
+                self.lines
+                    .offset(self.text, definition_location(definition))
+            })
+            .collect();
+        definitions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, definition)| {
+                let start = starts[i]?;
+                let next = starts[i + 1..].iter().flatten().next().copied();
+                let end = self.extent_end(start, next.unwrap_or(end).min(end));
+                self.definition(definition, start, end)
+            })
+            .collect()
+    }
+
+    fn definition(
+        &self,
+        definition: &Definition,
+        start: usize,
+        end: usize,
+    ) -> Option<DocumentSymbol> {
+        let range = Range::new(
+            self.lines.position(self.text, start),
+            self.lines.position(self.text, end),
+        );
+        let (name, location, kind, detail, children) = match definition {
+            Definition::FunctionDef {
+                name,
+                params,
+                return_type,
+                body,
+                location,
+                ..
+            } => {
+                let mut detail = format!("({})", hover::parameters(params));
+                if let Some(return_type) = return_type {
+                    detail.push_str(&format!(" -> {}", return_type));
+                }
+                let children = self.local_functions(body, end);
+                (name, location, SymbolKind::FUNCTION, Some(detail), children)
+            }
+            Definition::TypeDef {
+                name,
+                variants,
+                location,
+                ..
+            }
+            | Definition::ErrorDef {
+                name,
+                variants,
+                location,
+                ..
+            } => {
+                let children = self.variants(variants, end);
+                (name, location, SymbolKind::ENUM, None, children)
+            }
+            Definition::ObjectDef {
+                name,
+                fields,
+                functions,
+                location,
+                ..
+            } => {
+                let mut children = self.fields(fields, end);
+                children.extend(self.methods(functions, end));
+                (name, location, SymbolKind::STRUCT, None, children)
+            }
+            Definition::TypeAlias {
+                name,
+                target_type,
+                location,
+                ..
+            } => (
+                name,
+                location,
+                SymbolKind::CLASS,
+                Some(target_type.to_string()),
+                Vec::new(),
+            ),
+            Definition::Module {
+                name,
+                definitions,
+                location,
+                ..
+            } => {
+                let children = self.definitions(definitions, end);
+                (name, location, SymbolKind::MODULE, None, children)
+            }
+            Definition::EventDef {
+                name,
+                fields,
+                location,
+                ..
+            } => {
+                let children = fields
+                    .iter()
+                    .filter_map(|field| {
+                        self.member(
+                            &field.name,
+                            &field.location,
+                            SymbolKind::FIELD,
+                            Some(field.ty.to_string()),
+                            end,
+                        )
+                    })
+                    .collect();
+                (name, location, SymbolKind::EVENT, None, children)
+            }
+            Definition::StorageDef { fields, .. } => {
+                let children = fields
+                    .iter()
+                    .filter_map(|field| {
+                        self.member(
+                            &field.name,
+                            &field.location,
+                            SymbolKind::FIELD,
+                            Some(field.ty.to_string()),
+                            end,
+                        )
+                    })
+                    .collect();
+                return Some(symbol(
+                    "storage".to_string(),
+                    SymbolKind::STRUCT,
+                    None,
+                    range,
+                    Range::new(
+                        range.start,
+                        self.lines
+                            .position(self.text, (start + "storage".len()).min(end)),
+                    ),
+                    children,
+                ));
+            }
+            Definition::GuardDef {
+                name,
+                params,
+                location,
+                ..
+            } => (
+                name,
+                location,
+                SymbolKind::FUNCTION,
+                Some(format!("({})", hover::parameters(params))),
+                Vec::new(),
+            ),
+            Definition::InterfaceDef {
+                name,
+                functions,
+                location,
+                ..
+            } => {
+                let children = self.methods(functions, end);
+                (name, location, SymbolKind::INTERFACE, None, children)
+            }
+            Definition::ContractDef {
+                name,
+                fields,
+                definitions,
+                location,
+                ..
+            } => {
+                let mut children: Vec<DocumentSymbol> = fields
+                    .iter()
+                    .filter_map(|field| {
+                        self.member(
+                            &field.name,
+                            &field.location,
+                            SymbolKind::FIELD,
+                            Some(field.ty.to_string()),
+                            end,
+                        )
+                    })
+                    .collect();
+                children.extend(self.definitions(definitions, end));
+                (name, location, SymbolKind::CLASS, None, children)
+            }
+        };
+        let selection = self.lines.find(self.text, name, location)?;
+        Some(symbol(
+            name.to_string(),
+            kind,
+            detail,
+            range,
+            selection,
+            children,
+        ))
+    }
+
+    /// Functions defined in objects and interfaces
+    fn methods(&self, functions: &[Definition], end: usize) -> Vec<DocumentSymbol> {
+        self.definitions(functions, end)
+            .into_iter()
+            .map(|method| DocumentSymbol {
+                kind: SymbolKind::METHOD,
+                ..method
+            })
+            .collect()
+    }
+
+    /// Functions defined in a function body, at any depth
+    fn local_functions(&self, body: &Block, end: usize) -> Vec<DocumentSymbol> {
+        let mut definitions = Vec::new();
+        collect_local_functions(body, &mut definitions);
+        self.definitions(&definitions, end)
+    }
+
+    fn variants(&self, variants: &[TypeVariant], end: usize) -> Vec<DocumentSymbol> {
+        variants
+            .iter()
+            .filter_map(|variant| {
+                let mut member = self.member(
+                    &variant.name,
+                    &variant.location,
+                    SymbolKind::ENUM_MEMBER,
+                    None,
+                    end,
+                )?;
+                let fields = self.fields(&variant.fields, end);
+                if !fields.is_empty() {
+                    member.children = Some(fields);
+                }
+                Some(member)
+            })
+            .collect()
+    }
+
+    fn fields(&self, fields: &[Field], end: usize) -> Vec<DocumentSymbol> {
+        fields
+            .iter()
+            .filter_map(|field| {
+                let detail = field.type_annotation.as_ref().map(ToString::to_string);
+                self.member(&field.name, &field.location, SymbolKind::FIELD, detail, end)
+            })
+            .collect()
+    }
+
+    /// A symbol declared on a single line, like a field or variant
+    fn member(
+        &self,
+        name: &str,
+        location: &AstLocation,
+        kind: SymbolKind,
+        detail: Option<String>,
+        end: usize,
+    ) -> Option<DocumentSymbol> {
+        let selection = self.lines.find(self.text, name, location)?;
+        let start = self.lines.offset(self.text, location)?;
+        let line_end = self.text[start..]
+            .find('\n')
+            .map_or(self.text.len(), |newline| start + newline);
+        let line_end = start
+            + self.text[start..line_end.min(end).max(start)]
+                .trim_end()
+                .len();
+        let range = Range::new(
+            selection.start.min(self.lines.position(self.text, start)),
+            selection.end.max(self.lines.position(self.text, line_end)),
+        );
+        Some(symbol(
+            name.to_string(),
+            kind,
+            detail,
+            range,
+            selection,
+            Vec::new(),
+        ))
+    }
+
+    /// Where a definition starting at `start` ends, given where the next
+    /// one starts: before the blank lines and comments leading up to it
+    fn extent_end(&self, start: usize, next: usize) -> usize {
+        let mut end = start + self.text[start..next].trim_end().len();
+        loop {
+            let line_start = self.text[start..end]
+                .rfind('\n')
+                .map_or(start, |i| start + i + 1);
+            if line_start == start || !self.text[line_start..end].trim_start().starts_with('#') {
+                return end;
+            }
+            end = start + self.text[start..line_start].trim_end().len();
+        }
+    }
+}
+
+fn collect_local_functions(block: &Block, definitions: &mut Vec<Definition>) {
+    for statement in &block.statements {
+        if let Statement::LocalDef { function_def, .. } = statement {
+            definitions.push((**function_def).clone());
+        }
+    }
+}
+
+fn definition_location(definition: &Definition) -> &AstLocation {
+    match definition {
+        Definition::FunctionDef { location, .. }
+        | Definition::TypeDef { location, .. }
+        | Definition::ObjectDef { location, .. }
+        | Definition::TypeAlias { location, .. }
+        | Definition::Module { location, .. }
+        | Definition::EventDef { location, .. }
+        | Definition::ErrorDef { location, .. }
+        | Definition::StorageDef { location, .. }
+        | Definition::GuardDef { location, .. }
+        | Definition::InterfaceDef { location, .. }
+        | Definition::ContractDef { location, .. } => location,
+    }
+}
+
+#[allow(deprecated)]
+fn symbol(
+    name: String,
+    kind: SymbolKind,
+    detail: Option<String>,
+    range: Range,
+    selection_range: Range,
+    children: Vec<DocumentSymbol>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name,
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range,
+        children: if children.is_empty() {
+            None
+        } else {
+            Some(children)
+        },
+    }
+}
+
+/// Symbols anywhere in the workspace matching `query`, best matches first
+pub fn workspace_symbols(
+    documents: &DocumentStore,
+    roots: &[PathBuf],
+    query: &str,
+) -> Vec<WorkspaceSymbol> {
+    let mut matches = Vec::new();
+    for uri in workspace_documents(documents, roots) {
+        let (Some(text), Some(program)) = (documents.source(&uri), documents.program(&uri)) else {
+            continue;
+        };
+        let mut symbols = Vec::new();
+        flatten(&document_symbols(&text, &program), None, &mut symbols);
+        for (symbol, container) in symbols {
+            if let Some(score) = fuzzy_score(query, &symbol.name) {
+                matches.push((score, symbol, container, uri.clone()));
+            }
+        }
+    }
+
+    matches.sort_by(|(a, a_symbol, ..), (b, b_symbol, ..)| {
+        b.cmp(a)
+            .then(a_symbol.name.len().cmp(&b_symbol.name.len()))
+            .then(a_symbol.name.cmp(&b_symbol.name))
+    });
+    matches
+        .into_iter()
+        .take(MAX_WORKSPACE_SYMBOLS)
+        .map(|(_, symbol, container_name, uri)| WorkspaceSymbol {
+            name: symbol.name,
+            kind: symbol.kind,
+            tags: None,
+            container_name,
+            location: OneOf::Left(Location::new(uri, symbol.selection_range)),
+            data: None,
+        })
+        .collect()
+}
+
+/// Every symbol of an outline along with the name of its parent
+fn flatten(
+    symbols: &[DocumentSymbol],
+    container: Option<&str>,
+    flat: &mut Vec<(DocumentSymbol, Option<String>)>,
+) {
+    for symbol in symbols {
+        flat.push((
+            DocumentSymbol {
+                children: None,
+                ..symbol.clone()
+            },
+            container.map(str::to_string),
+        ));
+        if let Some(children) = &symbol.children {
+            flatten(children, Some(&symbol.name), flat);
+        }
+    }
+}
+
+/// Open documents and the modules of every workspace folder
+fn workspace_documents(documents: &DocumentStore, roots: &[PathBuf]) -> Vec<Url> {
+    let mut files = Vec::new();
+    for root in roots {
+        match diagnostics::workspace(root) {
+            Some(workspace) => files.extend(workspace.root().module_files().unwrap_or_default()),
+            None => collect_modules(root, &mut files),
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut uris: Vec<Url> = documents.uris().cloned().collect();
+    uris.extend(
+        files
+            .iter()
+            .filter_map(|file| Url::from_file_path(canonical(file)).ok()),
+    );
+    uris.retain(|uri| {
+        let key = uri.to_file_path().map(|path| canonical(&path)).ok();
+        seen.insert(key.map_or_else(|| uri.to_string(), |path| path.display().to_string()))
+    });
+    uris
+}
+
+/// Modules in a folder that is not a package, skipping hidden folders
+fn collect_modules(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if path.is_dir() && !hidden {
+            collect_modules(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "bend") {
+            files.push(path);
+        }
+    }
+}
+
+/// How well `query` matches `name`, if every character of the query
+/// appears in the name in order, ignoring case. Characters matching at the
+/// start of a word, or right after the previous match, score higher.
+fn fuzzy_score(query: &str, name: &str) -> Option<u32> {
+    let name: Vec<char> = name.chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.chars().filter(|c| !c.is_whitespace()) {
+        let found = (next..name.len()).find(|&i| chars_match(wanted, name[i]))?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 2;
+        }
+        if is_word_start(&name, found) {
+            score += 3;
+        }
+        previous = Some(found);
+        next = found + 1;
+    }
+    Some(score)
+}
+
+fn chars_match(wanted: char, found: char) -> bool {
+    wanted.to_lowercase().eq(found.to_lowercase())
+}
+
+fn is_word_start(name: &[char], i: usize) -> bool {
+    i == 0
+        || matches!(name[i - 1], '_' | '/' | '.')
+        || (name[i].is_uppercase() && name[i - 1].is_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bend_pvm::compiler::parser::parser::Parser;
+    use lsp_types::Position;
+
+    const SOURCE: &str = "# A shape\ntype Shape {\n    Circle(radius: u24),\n    Empty,\n}\n\n# Where things are\nobject Point {\n    let x: u24;\n    let y: u24;\n\n    fn norm() -> u24 {\n        return 0;\n    }\n}\n\nfn area(shape: Shape) -> u24 {\n    return 0;\n}\n";
+
+    fn outline(text: &str) -> Vec<DocumentSymbol> {
+        document_symbols(text, &Parser::new(text).parse_program().unwrap())
+    }
+
+    fn names(symbols: &[DocumentSymbol]) -> Vec<&str> {
+        symbols.iter().map(|symbol| symbol.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_outline_nests_members() {
+        let symbols = outline(SOURCE);
+        assert_eq!(names(&symbols), vec!["Shape", "Point", "area"]);
+
+        let shape = &symbols[0];
+        assert_eq!(shape.kind, SymbolKind::ENUM);
+        assert_eq!(
+            shape.range,
+            Range::new(Position::new(1, 0), Position::new(4, 1))
+        );
+        let variants = shape.children.as_ref().unwrap();
+        assert_eq!(names(variants), vec!["Circle", "Empty"]);
+        let fields = variants[0].children.as_ref().unwrap();
+        assert_eq!(fields[0].name, "radius");
+        assert_eq!(fields[0].detail.as_deref(), Some("u24"));
+
+        let point = &symbols[1];
+        assert_eq!(point.kind, SymbolKind::STRUCT);
+        let members = point.children.as_ref().unwrap();
+        assert_eq!(names(members), vec!["x", "y", "norm"]);
+        assert_eq!(members[2].kind, SymbolKind::METHOD);
+
+        let area = &symbols[2];
+        assert_eq!(area.detail.as_deref(), Some("(shape: Shape) -> u24"));
+        assert_eq!(
+            area.selection_range,
+            Range::new(Position::new(16, 3), Position::new(16, 7))
+        );
+        assert_eq!(area.range.end, Position::new(18, 1));
+    }
+
+    #[test]
+    fn test_children_stay_within_their_parent() {
+        fn check(symbol: &DocumentSymbol) {
+            for child in symbol.children.iter().flatten() {
+                assert!(symbol.range.start <= child.range.start);
+                assert!(child.range.end <= symbol.range.end);
+                assert!(child.range.start <= child.selection_range.start);
+                assert!(child.selection_range.end <= child.range.end);
+                check(child);
+            }
+        }
+        for symbol in outline(SOURCE) {
+            check(&symbol);
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("", "area").is_some());
+        assert!(fuzzy_score("shp", "Shape").is_some());
+        assert!(fuzzy_score("hsp", "Shape").is_none());
+        assert!(fuzzy_score("gb", "get_balance") > fuzzy_score("gb", "debug"));
+        assert!(fuzzy_score("are", "area") > fuzzy_score("are", "a_rate_e"));
+    }
+
+    #[test]
+    fn test_workspace_symbols_search_open_documents() {
+        let uri = Url::parse("untitled:outline.bend").unwrap();
+        let mut documents = DocumentStore::default();
+        documents.open(uri.clone(), 1, SOURCE.to_string());
+
+        let symbols = workspace_symbols(&documents, &[], "circ");
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].name, "Circle");
+        assert_eq!(symbols[0].container_name.as_deref(), Some("Shape"));
+        assert_eq!(
+            symbols[0].location,
+            OneOf::Left(Location::new(
+                uri,
+                Range::new(Position::new(2, 4), Position::new(2, 10))
+            ))
+        );
+        assert!(workspace_symbols(&documents, &[], "zzz").is_empty());
+    }
+}
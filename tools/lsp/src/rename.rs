@@ -0,0 +1,323 @@
+//! Renaming symbols across the workspace
+//!
+//! Every occurrence the symbol index links to a definition is renamed,
+//! including the variant part of qualified names like `Shape/Circle` and
+//! the type part when the type itself is renamed. Aliases from imports
+//! keep their own names. A rename is refused when the renamed workspace,
+//! indexed again, would link any name to a different definition, which is
+//! what happens when the new name collides with or shadows another one.
+
+use lsp_types::{Position, PrepareRenameResponse, Range, TextEdit, Url, WorkspaceEdit};
+use std::collections::HashMap;
+
+use bend_pvm::compiler::lexer::lexer::BendLexer;
+use bend_pvm::compiler::lexer::token::Token;
+use bend_pvm::compiler::parser::ast::Definition;
+use bend_pvm::compiler::parser::parser::Parser;
+
+use crate::documents::{Document, DocumentStore};
+use crate::symbols::{DefinitionKind, LineIndex, Occurrence, SymbolIndex, SymbolSite};
+
+/// The name at `position`, if it can be renamed
+pub fn prepare_rename(
+    documents: &DocumentStore,
+    uri: &Url,
+    position: Position,
+) -> Result<PrepareRenameResponse, String> {
+    let index = SymbolIndex::build(documents, uri);
+    let occurrence = index
+        .occurrence_at(uri, position)
+        .ok_or("There is no symbol to rename here")?;
+    let name = site_name(documents, &occurrence.site)?;
+    let text = documents.source(uri).unwrap_or_default();
+    let range = name_range(&text, occurrence, &name)
+        .ok_or_else(|| format!("This is an alias of `{}`; rename it in the import", name))?;
+    Ok(PrepareRenameResponse::RangeWithPlaceholder {
+        range,
+        placeholder: name,
+    })
+}
+
+/// The edits renaming the symbol at `position` to `new_name`
+pub fn rename(
+    documents: &DocumentStore,
+    uri: &Url,
+    position: Position,
+    new_name: &str,
+) -> Result<WorkspaceEdit, String> {
+    check_name(new_name)?;
+    let index = SymbolIndex::build(documents, uri);
+    let occurrence = index
+        .occurrence_at(uri, position)
+        .ok_or("There is no symbol to rename here")?;
+    let site = occurrence.site.clone();
+    let old_name = site_name(documents, &site)?;
+    if old_name == new_name {
+        return Ok(WorkspaceEdit::default());
+    }
+
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+    let mut texts: HashMap<Url, String> = HashMap::new();
+    let mut edit = |uri: &Url, range: Range| {
+        let edits = changes.entry(uri.clone()).or_default();
+        if !edits.iter().any(|edit| edit.range == range) {
+            edits.push(TextEdit::new(range, new_name.to_string()));
+        }
+    };
+
+    for (uri, occurrence) in index.occurrences_of(&site) {
+        let text = texts
+            .entry(uri.clone())
+            .or_insert_with(|| documents.source(uri).unwrap_or_default());
+        if let Some(range) = name_range(text, occurrence, &old_name) {
+            edit(uri, range);
+        }
+    }
+
+    // Renaming a type renames it where it qualifies its variants
+    let site_text = documents.source(&site.uri).unwrap_or_default();
+    for variant in variant_sites(&site_text, &site) {
+        for (uri, occurrence) in index.occurrences_of(&variant) {
+            let text = texts
+                .entry(uri.clone())
+                .or_insert_with(|| documents.source(uri).unwrap_or_default());
+            if let Some(range) = qualifier_range(text, occurrence, &old_name) {
+                edit(uri, range);
+            }
+        }
+    }
+
+    // The rename is safe when every name still refers to what it did
+    let mut renamed = documents.clone();
+    for (uri, edits) in &changes {
+        let text = apply_edits(&texts[uri], edits);
+        renamed.open(uri.clone(), 0, text);
+    }
+    if SymbolIndex::build(&renamed, uri).bindings() != index.bindings() {
+        return Err(format!(
+            "Renaming `{}` to `{}` would collide with or shadow another name",
+            old_name, new_name
+        ));
+    }
+
+    Ok(WorkspaceEdit::new(changes))
+}
+
+/// Refuse names that would not lex as a single plain identifier
+fn check_name(name: &str) -> Result<(), String> {
+    let mut lexer = BendLexer::new(name);
+    let valid = matches!(&lexer.next_token().token, Token::Identifier(lexed) if lexed == name)
+        && lexer.next_token().token == Token::EOF
+        && !name.contains(['/', '.']);
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("`{}` is not a valid name", name))
+    }
+}
+
+/// The name a symbol is defined with
+fn site_name(documents: &DocumentStore, site: &SymbolSite) -> Result<String, String> {
+    let text = documents
+        .source(&site.uri)
+        .ok_or_else(|| format!("Cannot read {}", site.uri))?;
+    Ok(slice(&text, site.range).to_string())
+}
+
+/// The part of an occurrence naming the symbol: all of it, or the last
+/// part of a qualified name. Aliases have none.
+fn name_range(text: &str, occurrence: &Occurrence, name: &str) -> Option<Range> {
+    let written = slice(text, occurrence.range);
+    if written == name {
+        return Some(occurrence.range);
+    }
+    written.strip_suffix(name)?.strip_suffix('/')?;
+    let end = occurrence.range.end;
+    let start = Position::new(end.line, end.character - name.encode_utf16().count() as u32);
+    Some(Range::new(start, end))
+}
+
+/// The type qualifying a variant in an occurrence like `Shape/Circle`
+fn qualifier_range(text: &str, occurrence: &Occurrence, type_name: &str) -> Option<Range> {
+    slice(text, occurrence.range)
+        .strip_prefix(type_name)?
+        .strip_prefix('/')?;
+    let start = occurrence.range.start;
+    let end = Position::new(
+        start.line,
+        start.character + type_name.encode_utf16().count() as u32,
+    );
+    Some(Range::new(start, end))
+}
+
+/// The variants of the type or error defined at `site`
+fn variant_sites(text: &str, site: &SymbolSite) -> Vec<SymbolSite> {
+    let Ok(program) = Parser::new(text).parse_program() else {
+        return Vec::new();
+    };
+    let lines = LineIndex::new(text);
+    for definition in &program.definitions {
+        let (Definition::TypeDef {
+            name,
+            variants,
+            location,
+            ..
+        }
+        | Definition::ErrorDef {
+            name,
+            variants,
+            location,
+            ..
+        }) = definition
+        else {
+            continue;
+        };
+        if lines.find(text, name, location) != Some(site.range) {
+            continue;
+        }
+        return variants
+            .iter()
+            .filter_map(|variant| lines.find(text, &variant.name, &variant.location))
+            .map(|range| SymbolSite {
+                uri: site.uri.clone(),
+                range,
+                kind: DefinitionKind::Constructor,
+            })
+            .collect();
+    }
+    Vec::new()
+}
+
+fn slice(text: &str, range: Range) -> &str {
+    let document = Document::new(0, text.to_string());
+    let (start, end) = (document.offset(range.start), document.offset(range.end));
+    &text[start..end]
+}
+
+/// Apply edits that do not overlap
+fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    let document = Document::new(0, text.to_string());
+    let mut edits: Vec<(usize, usize, &str)> = edits
+        .iter()
+        .map(|edit| {
+            (
+                document.offset(edit.range.start),
+                document.offset(edit.range.end),
+                edit.new_text.as_str(),
+            )
+        })
+        .collect();
+    edits.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+
+    let mut text = text.to_string();
+    for (start, end, new_text) in edits {
+        text.replace_range(start..end, new_text);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const SOURCE: &str = "type Shape {\n    Circle(radius: u24),\n    Empty,\n}\n\nfn area(shape: Shape) -> u24 {\n    match shape {\n        Shape/Circle(r) => { return r * r; }\n        Empty => { return 0; }\n    }\n}\n\nfn main() -> u24 {\n    total = area(Shape/Circle(2));\n    other = 1;\n    return total + other;\n}\n";
+
+    fn store(text: &str) -> (DocumentStore, Url) {
+        let uri = Url::parse("untitled:rename.bend").unwrap();
+        let mut documents = DocumentStore::default();
+        documents.open(uri.clone(), 1, text.to_string());
+        (documents, uri)
+    }
+
+    fn renamed(position: Position, new_name: &str) -> Result<String, String> {
+        let (documents, uri) = store(SOURCE);
+        let edit = rename(&documents, &uri, position, new_name)?;
+        let changes = edit.changes.unwrap_or_default();
+        Ok(apply_edits(
+            SOURCE,
+            changes.get(&uri).map_or(&[], Vec::as_slice),
+        ))
+    }
+
+    #[test]
+    fn test_prepare_rename() {
+        let (documents, uri) = store(SOURCE);
+        let response = prepare_rename(&documents, &uri, Position::new(13, 28)).unwrap();
+        assert_eq!(
+            response,
+            PrepareRenameResponse::RangeWithPlaceholder {
+                range: Range::new(Position::new(13, 23), Position::new(13, 29)),
+                placeholder: "Circle".to_string(),
+            }
+        );
+        assert!(prepare_rename(&documents, &uri, Position::new(4, 0)).is_err());
+    }
+
+    #[test]
+    fn test_rename_functions_locals_and_types() {
+        let text = renamed(Position::new(5, 4), "size").unwrap();
+        assert!(text.contains("fn size(shape: Shape)"));
+        assert!(text.contains("total = size(Shape/Circle(2));"));
+
+        let text = renamed(Position::new(7, 36), "radius").unwrap();
+        assert!(text.contains("Shape/Circle(radius) => { return radius * radius; }"));
+
+        let text = renamed(Position::new(1, 6), "Round").unwrap();
+        assert!(text.contains("    Round(radius: u24),"));
+        assert!(text.contains("total = area(Shape/Round(2));"));
+
+        let text = renamed(Position::new(0, 6), "Form").unwrap();
+        assert!(text.contains("type Form {"));
+        assert!(text.contains("fn area(shape: Form) -> u24"));
+        assert!(text.contains("Form/Circle(r) =>"));
+    }
+
+    #[test]
+    fn test_refuse_invalid_and_colliding_names() {
+        assert!(renamed(Position::new(5, 4), "fn").is_err());
+        assert!(renamed(Position::new(5, 4), "two words").is_err());
+        assert!(renamed(Position::new(5, 4), "List/map").is_err());
+        // Another function, and another local in scope
+        assert!(renamed(Position::new(5, 4), "main").is_err());
+        assert!(renamed(Position::new(13, 4), "other").is_err());
+        // The call comes before the local, so it still finds the function
+        assert!(renamed(Position::new(13, 4), "area").is_ok());
+
+        // A local shadowing a function called after it
+        let text = "fn helper() -> u24 {\n    return 1;\n}\n\nfn main() -> u24 {\n    x = 2;\n    return x + helper();\n}\n";
+        let (documents, uri) = store(text);
+        assert!(rename(&documents, &uri, Position::new(5, 4), "helper").is_err());
+        assert!(rename(&documents, &uri, Position::new(5, 4), "y").is_ok());
+    }
+
+    #[test]
+    fn test_rename_across_files() {
+        let dir = std::env::temp_dir().join(format!("bend-lsp-rename-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let utils = dir.join("utils.bend");
+        let main = dir.join("main.bend");
+        fs::write(
+            &utils,
+            "pub fn double(x: u24) -> u24 {\n    return x * 2;\n}\n",
+        )
+        .unwrap();
+        let text = "from utils import double;\n\nfn main() -> u24 {\n    return double(2);\n}\n";
+        fs::write(&main, text).unwrap();
+
+        let main_uri = Url::from_file_path(&main).unwrap();
+        let documents = DocumentStore::default();
+        let edit = rename(&documents, &main_uri, Position::new(3, 12), "twice").unwrap();
+        let changes = edit.changes.unwrap();
+        let utils_uri = Url::from_file_path(&utils).unwrap();
+        assert_eq!(changes[&main_uri].len(), 2);
+        assert_eq!(
+            changes[&utils_uri],
+            vec![TextEdit::new(
+                Range::new(Position::new(0, 7), Position::new(0, 13)),
+                "twice".to_string()
+            )]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,328 @@
+//! Semantic tokens for highlighting
+//!
+//! Keywords, literals, operators and comments come from the lexer.
+//! Identifiers are classified by what the symbol index
+//! resolves them to, so a name is highlighted as a function, type,
+//! constructor, parameter or variable wherever it is used. Tokens never
+//! span lines, since not every editor supports multiline tokens.
+
+use lsp_types::{
+    Position, Range, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokens,
+    SemanticTokensLegend, Url,
+};
+
+use bend_pvm::compiler::lexer::lexer::BendLexer;
+use bend_pvm::compiler::lexer::token::Token;
+
+use crate::documents::DocumentStore;
+use crate::symbols::{DefinitionKind, LineIndex, SymbolIndex};
+
+/// Token types, in the order of their indices in the legend
+const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::TYPE,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::STRING,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::OPERATOR,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::ENUM_MEMBER,
+    SemanticTokenType::EVENT,
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::PROPERTY,
+];
+
+/// Token modifiers, in the order of their bits in the legend
+const TOKEN_MODIFIERS: &[SemanticTokenModifier] = &[
+    SemanticTokenModifier::DECLARATION,
+    SemanticTokenModifier::DEFINITION,
+    SemanticTokenModifier::READONLY,
+];
+
+/// Bit of the `definition` modifier
+const DEFINITION: u32 = 1 << 1;
+
+/// Types the type checker knows without a definition
+const BUILTIN_TYPES: &[&str] = &[
+    "u24", "i24", "f24", "u64", "u128", "u256", "Address", "Hash", "Bytes", "Bool", "bool", "Any",
+    "None",
+];
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.to_vec(),
+        token_modifiers: TOKEN_MODIFIERS.to_vec(),
+    }
+}
+
+/// A classified token, positioned within a single line
+#[derive(Debug, Clone, PartialEq)]
+struct Classified {
+    start: Position,
+    /// Length in UTF-16 code units
+    length: u32,
+    token_type: SemanticTokenType,
+    modifiers: u32,
+}
+
+/// Tokens of the whole document, or of the lines overlapping `range`
+pub fn semantic_tokens(
+    documents: &DocumentStore,
+    uri: &Url,
+    range: Option<Range>,
+) -> Option<SemanticTokens> {
+    let text = documents.source(uri)?;
+    let mut tokens = classify(&text, &SymbolIndex::build(documents, uri), uri);
+    if let Some(range) = range {
+        tokens.retain(|token| {
+            token.start.line >= range.start.line && token.start.line <= range.end.line
+        });
+    }
+    Some(SemanticTokens {
+        result_id: None,
+        data: encode(&tokens),
+    })
+}
+
+fn classify(text: &str, index: &SymbolIndex, uri: &Url) -> Vec<Classified> {
+    let lines = LineIndex::new(text);
+    let mut tokens = Vec::new();
+    let mut push = |start: usize, end: usize, token_type: SemanticTokenType, modifiers: u32| {
+        // Split tokens spanning several lines into one per line
+        let mut from = start;
+        for line in text[start..end].split_inclusive('\n') {
+            let part = line.trim_end_matches(['\n', '\r']);
+            if !part.is_empty() {
+                tokens.push(Classified {
+                    start: lines.position(text, from),
+                    length: part.encode_utf16().count() as u32,
+                    token_type: token_type.clone(),
+                    modifiers,
+                });
+            }
+            from += line.len();
+        }
+    };
+
+    let occurrences = index.occurrences(uri);
+    let mut lexer = BendLexer::with_trivia(text);
+    loop {
+        let token = lexer.next_token();
+        let token_type = match &token.token {
+            Token::EOF => break,
+            Token::Comment(_) => SemanticTokenType::COMMENT,
+            Token::Whitespace(_) => continue,
+            Token::Identifier(name) => {
+                let start = lines.position(text, token.start);
+                match occurrences
+                    .iter()
+                    .find(|occurrence| occurrence.range.start == start)
+                {
+                    Some(occurrence) => {
+                        let modifiers = if occurrence.is_definition {
+                            DEFINITION
+                        } else {
+                            0
+                        };
+                        push(
+                            token.start,
+                            token.end,
+                            kind_type(occurrence.site.kind),
+                            modifiers,
+                        );
+                        continue;
+                    }
+                    None if BUILTIN_TYPES.contains(&name.as_str()) => SemanticTokenType::TYPE,
+                    None => continue,
+                }
+            }
+            Token::UintLiteral(_)
+            | Token::WideUintLiteral(_)
+            | Token::IntLiteral(_)
+            | Token::FloatLiteral(_)
+            | Token::HashLiteral(_)
+            | Token::BytesLiteral(_)
+            | Token::AddressLiteral(_) => SemanticTokenType::NUMBER,
+            Token::StringLiteral(_) | Token::CharLiteral(_) | Token::SymbolLiteral(_) => {
+                SemanticTokenType::STRING
+            }
+            Token::Error(_) => continue,
+            _ => {
+                let lexeme = &text[token.start..token.end];
+                if lexeme.chars().all(char::is_alphabetic) {
+                    // Keywords, including `and`, `or` and `not`
+                    SemanticTokenType::KEYWORD
+                } else if is_operator(&token.token) {
+                    SemanticTokenType::OPERATOR
+                } else {
+                    continue;
+                }
+            }
+        };
+        push(token.start, token.end, token_type, 0);
+    }
+    tokens
+}
+
+fn kind_type(kind: DefinitionKind) -> SemanticTokenType {
+    match kind {
+        DefinitionKind::Function => SemanticTokenType::FUNCTION,
+        DefinitionKind::Type => SemanticTokenType::TYPE,
+        DefinitionKind::Constructor => SemanticTokenType::ENUM_MEMBER,
+        DefinitionKind::Event => SemanticTokenType::EVENT,
+        DefinitionKind::Module => SemanticTokenType::NAMESPACE,
+        DefinitionKind::Storage => SemanticTokenType::PROPERTY,
+        DefinitionKind::Parameter => SemanticTokenType::PARAMETER,
+        DefinitionKind::Variable => SemanticTokenType::VARIABLE,
+    }
+}
+
+fn is_operator(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Plus
+            | Token::Minus
+            | Token::Star
+            | Token::Slash
+            | Token::Percent
+            | Token::Caret
+            | Token::Ampersand
+            | Token::Pipe
+            | Token::GreaterThan
+            | Token::LessThan
+            | Token::GreaterEqual
+            | Token::LessEqual
+            | Token::EqualEqual
+            | Token::NotEqual
+            | Token::AndAnd
+            | Token::OrOr
+            | Token::Bang
+            | Token::Equal
+            | Token::PlusEqual
+            | Token::MinusEqual
+            | Token::StarEqual
+            | Token::SlashEqual
+            | Token::PercentEqual
+            | Token::CaretEqual
+            | Token::AmpersandEqual
+            | Token::PipeEqual
+            | Token::Arrow
+            | Token::FatArrow
+            | Token::LeftArrow
+            | Token::Question
+    )
+}
+
+/// Encode tokens relative to the one before, as the protocol requires
+fn encode(tokens: &[Classified]) -> Vec<SemanticToken> {
+    let mut tokens: Vec<&Classified> = tokens.iter().collect();
+    tokens.sort_by_key(|token| (token.start.line, token.start.character));
+
+    let mut previous = Position::new(0, 0);
+    tokens
+        .into_iter()
+        .map(|token| {
+            let delta_line = token.start.line - previous.line;
+            let delta_start = if delta_line == 0 {
+                token.start.character - previous.character
+            } else {
+                token.start.character
+            };
+            previous = token.start;
+            SemanticToken {
+                delta_line,
+                delta_start,
+                length: token.length,
+                token_type: TOKEN_TYPES
+                    .iter()
+                    .position(|token_type| *token_type == token.token_type)
+                    .unwrap_or_default() as u32,
+                token_modifiers_bitset: token.modifiers,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "# Shapes\ntype Shape {\n    Circle(radius: u24),\n}\n\nfn area(shape: Shape) -> u24 {\n    match shape {\n        Shape/Circle(r) => { return r * 3; }\n    }\n}\n";
+
+    fn tokens(text: &str) -> Vec<(u32, u32, u32, SemanticTokenType, u32)> {
+        let uri = Url::parse("untitled:tokens.bend").unwrap();
+        let mut documents = DocumentStore::default();
+        documents.open(uri.clone(), 1, text.to_string());
+        let index = SymbolIndex::build(&documents, &uri);
+        let mut tokens = classify(text, &index, &uri);
+        tokens.sort_by_key(|token| (token.start.line, token.start.character));
+        tokens
+            .into_iter()
+            .map(|token| {
+                (
+                    token.start.line,
+                    token.start.character,
+                    token.length,
+                    token.token_type,
+                    token.modifiers,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_classify_by_resolved_symbol() {
+        use SemanticTokenType as T;
+        let tokens = tokens(SOURCE);
+        let expected = [
+            (0, 0, 8, T::COMMENT, 0),
+            (1, 0, 4, T::KEYWORD, 0),
+            (1, 5, 5, T::TYPE, DEFINITION),
+            (2, 4, 6, T::ENUM_MEMBER, DEFINITION),
+            (2, 19, 3, T::TYPE, 0),
+            (5, 0, 2, T::KEYWORD, 0),
+            (5, 3, 4, T::FUNCTION, DEFINITION),
+            (5, 8, 5, T::PARAMETER, DEFINITION),
+            (5, 15, 5, T::TYPE, 0),
+            (5, 22, 2, T::OPERATOR, 0),
+            (5, 25, 3, T::TYPE, 0),
+            (6, 4, 5, T::KEYWORD, 0),
+            (6, 10, 5, T::PARAMETER, 0),
+            (7, 8, 12, T::ENUM_MEMBER, 0),
+            (7, 21, 1, T::VARIABLE, DEFINITION),
+            (7, 24, 2, T::OPERATOR, 0),
+            (7, 29, 6, T::KEYWORD, 0),
+            (7, 36, 1, T::VARIABLE, 0),
+            (7, 38, 1, T::OPERATOR, 0),
+            (7, 40, 1, T::NUMBER, 0),
+        ];
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn test_multiline_comments_are_split() {
+        let text = "#{ one\ntwo }#\nfn main() -> u24 {\n    return \"a\";\n}\n";
+        let tokens = tokens(text);
+        assert_eq!(tokens[0], (0, 0, 6, SemanticTokenType::COMMENT, 0));
+        assert_eq!(tokens[1], (1, 0, 6, SemanticTokenType::COMMENT, 0));
+        assert!(tokens.contains(&(3, 11, 3, SemanticTokenType::STRING, 0)));
+    }
+
+    #[test]
+    fn test_encoding_is_relative() {
+        let token = |line, character, length| Classified {
+            start: Position::new(line, character),
+            length,
+            token_type: SemanticTokenType::KEYWORD,
+            modifiers: 0,
+        };
+        let data = encode(&[token(2, 4, 1), token(0, 3, 2), token(0, 9, 3)]);
+        let deltas: Vec<(u32, u32, u32)> = data
+            .iter()
+            .map(|token| (token.delta_line, token.delta_start, token.length))
+            .collect();
+        assert_eq!(deltas, vec![(0, 3, 2), (0, 6, 3), (2, 4, 1)]);
+    }
+}
@@ -0,0 +1,399 @@
+//! Signature help for calls
+//!
+//! The call around the cursor is found by lexing the text before it and
+//! tracking open brackets, so that commas inside nested calls and lists
+//! are not counted. The called name is resolved through the symbol index,
+//! or by name among everything the module defines and imports when the
+//! index does not know it. That covers the standard library, and calls
+//! still being typed: a document that does not parse is parsed again
+//! without the top-level definition containing the cursor.
+
+use lsp_types::{
+    Documentation, MarkupContent, MarkupKind, ParameterInformation, ParameterLabel, Position,
+    SignatureHelp, SignatureInformation, Url,
+};
+
+use bend_pvm::compiler::lexer::lexer::BendLexer;
+use bend_pvm::compiler::lexer::token::Token;
+use bend_pvm::compiler::parser::ast::{Definition, Parameter, Program, Type, TypeVariant};
+use bend_pvm::compiler::parser::parser::Parser;
+
+use crate::diagnostics;
+use crate::documents::{Document, DocumentStore};
+use crate::hover;
+use crate::symbols::{LineIndex, SymbolIndex};
+
+/// The call enclosing a cursor
+#[derive(Debug, PartialEq)]
+struct Call {
+    /// The called name and its byte offset
+    name: String,
+    offset: usize,
+    /// Index of the argument the cursor is in
+    argument: u32,
+}
+
+/// Something that can be called, with its parameters
+struct Callable {
+    name: String,
+    params: Vec<(String, String)>,
+    return_type: Option<String>,
+    documentation: Option<String>,
+}
+
+/// Signature help for the call around `position`
+pub fn signature_help(
+    documents: &DocumentStore,
+    uri: &Url,
+    position: Position,
+) -> Option<SignatureHelp> {
+    let document = Document::new(0, documents.source(uri)?);
+    let text = document.text();
+    let call = enclosing_call(text, document.offset(position))?;
+
+    let index = SymbolIndex::build(documents, uri);
+    let callable = resolve(documents, &index, uri, text, &call)
+        .or_else(|| by_name(uri, text, call.offset, &call.name))?;
+    let (label, parameters) = label(&callable);
+    Some(SignatureHelp {
+        signatures: vec![SignatureInformation {
+            label,
+            documentation: callable.documentation.map(|doc| {
+                Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: doc,
+                })
+            }),
+            parameters: Some(parameters),
+            active_parameter: None,
+        }],
+        active_signature: Some(0),
+        active_parameter: Some(call.argument),
+    })
+}
+
+/// The innermost call whose arguments contain `offset`
+fn enclosing_call(text: &str, offset: usize) -> Option<Call> {
+    // One entry per open bracket; parentheses following a name are calls
+    let mut open: Vec<Option<Call>> = Vec::new();
+    let mut previous: Option<(String, usize)> = None;
+    let mut lexer = BendLexer::new(text);
+    loop {
+        let token = lexer.next_token();
+        if token.token == Token::EOF || token.end > offset {
+            break;
+        }
+        match &token.token {
+            Token::LParen => open.push(previous.take().map(|(name, offset)| Call {
+                name,
+                offset,
+                argument: 0,
+            })),
+            Token::LBracket | Token::LBrace => open.push(None),
+            Token::RParen | Token::RBracket | Token::RBrace => {
+                open.pop();
+            }
+            Token::Comma => {
+                if let Some(Some(call)) = open.last_mut() {
+                    call.argument += 1;
+                }
+            }
+            _ => {}
+        }
+        previous = match token.token {
+            Token::Identifier(name) => Some((name.to_string(), token.start)),
+            _ => None,
+        };
+    }
+    open.into_iter().rev().flatten().next()
+}
+
+/// Parameter names of the function or constructor `name` called at
+/// `offset`, resolved through `index` or else found by name in `program`
+pub fn parameter_names(
+    documents: &DocumentStore,
+    index: &SymbolIndex,
+    uri: &Url,
+    text: &str,
+    program: &Program,
+    name: &str,
+    offset: usize,
+) -> Option<Vec<String>> {
+    let call = Call {
+        name: name.to_string(),
+        offset,
+        argument: 0,
+    };
+    let callable = resolve(documents, index, uri, text, &call)
+        .or_else(|| find_by_name(program, name).map(|(callable, _)| callable))?;
+    Some(callable.params.into_iter().map(|(name, _)| name).collect())
+}
+
+/// What the index resolves the called name to
+fn resolve(
+    documents: &DocumentStore,
+    index: &SymbolIndex,
+    uri: &Url,
+    text: &str,
+    call: &Call,
+) -> Option<Callable> {
+    let position = LineIndex::new(text).position(text, call.offset);
+    let site = &index.occurrence_at(uri, position)?.site;
+
+    let site_text = documents.source(&site.uri)?;
+    let program = Parser::new(&site_text).parse_program().ok()?;
+    let lines = LineIndex::new(&site_text);
+    let defined_at =
+        |name: &str, location| lines.find(&site_text, name, location) == Some(site.range);
+
+    let mut callable = program
+        .definitions
+        .iter()
+        .find_map(|definition| match definition {
+            Definition::FunctionDef {
+                name,
+                params,
+                return_type,
+                location,
+                ..
+            } if defined_at(name, location) => Some(Callable {
+                name: name.to_string(),
+                params: parameters(params, |param| {
+                    // Holes are filled in with the type the checker found
+                    let range = lines.find(&site_text, &param.name, &param.location)?;
+                    hover::local_type(documents, &site.uri, &site_text, &param.name, range)
+                }),
+                return_type: return_type.as_ref().map(ToString::to_string),
+                documentation: None,
+            }),
+            Definition::TypeDef { name, variants, .. }
+            | Definition::ErrorDef { name, variants, .. } => {
+                let variant = variants
+                    .iter()
+                    .find(|variant| defined_at(&variant.name, &variant.location))?;
+                Some(constructor(name, variant))
+            }
+            _ => None,
+        })?;
+    callable.documentation = hover::doc_comment(&site_text, site.range.start.line as usize);
+    Some(callable)
+}
+
+/// A function or constructor the document defines or imports, found by
+/// name
+fn by_name(uri: &Url, text: &str, offset: usize, name: &str) -> Option<Callable> {
+    let text = match Parser::new(text).parse_program() {
+        Ok(_) => text.to_string(),
+        Err(_) => without_definition_at(text, offset),
+    };
+    let module = diagnostics::load_module(uri, &text).ok()?;
+    if let Some((mut callable, line)) = find_by_name(&module.ast, name) {
+        callable.documentation = hover::doc_comment(&text, line.saturating_sub(1));
+        return Some(callable);
+    }
+    find_by_name(&module.program(), name).map(|(callable, _)| callable)
+}
+
+/// A callable named `called`, along with the 1-based line defining it
+fn find_by_name(program: &Program, called: &str) -> Option<(Callable, usize)> {
+    program
+        .definitions
+        .iter()
+        .find_map(|definition| match definition {
+            Definition::FunctionDef {
+                name,
+                params,
+                return_type,
+                location,
+                ..
+            } if name == called => {
+                let callable = Callable {
+                    name: name.to_string(),
+                    params: parameters(params, |_| None),
+                    return_type: return_type.as_ref().map(ToString::to_string),
+                    documentation: None,
+                };
+                Some((callable, location.line))
+            }
+            Definition::TypeDef { name, variants, .. }
+            | Definition::ErrorDef { name, variants, .. } => variants
+                .iter()
+                .find(|variant| {
+                    variant.name == called || format!("{}/{}", name, variant.name) == called
+                })
+                .map(|variant| (constructor(name, variant), variant.location.line)),
+            _ => None,
+        })
+}
+
+/// Blank out the top-level definition containing `offset`, keeping every
+/// other line where it was. Definitions start at the beginning of a line,
+/// along with the comments and attributes before them.
+fn without_definition_at(text: &str, offset: usize) -> String {
+    let mut lines: Vec<&str> = text.split('\n').collect();
+    let cursor = text[..offset.min(text.len())].matches('\n').count();
+    let starts_definition = |line: &str| {
+        line.chars()
+            .next()
+            .is_some_and(|c| !c.is_whitespace() && c != '}' && c != '#')
+    };
+
+    let mut start = (0..=cursor)
+        .rev()
+        .find(|&i| starts_definition(lines[i]))
+        .unwrap_or(0);
+    while start > 0 && lines[start - 1].starts_with('#') {
+        start -= 1;
+    }
+    let mut end = (cursor + 1..lines.len())
+        .find(|&i| starts_definition(lines[i]))
+        .unwrap_or(lines.len());
+    // Comments and attributes belong to the next definition
+    while end > cursor + 1 && lines[end - 1].starts_with('#') {
+        end -= 1;
+    }
+    for line in &mut lines[start..end] {
+        *line = "";
+    }
+    lines.join("\n")
+}
+
+/// Parameter names and types, asking `inferred` for the types left out
+fn parameters(
+    params: &[Parameter],
+    inferred: impl Fn(&Parameter) -> Option<String>,
+) -> Vec<(String, String)> {
+    params
+        .iter()
+        .map(|param| {
+            let ty = match &param.ty {
+                Type::Hole { .. } | Type::Unknown { .. } => {
+                    inferred(param).unwrap_or_else(|| param.ty.to_string())
+                }
+                ty => ty.to_string(),
+            };
+            (param.name.to_string(), ty)
+        })
+        .collect()
+}
+
+fn constructor(type_name: &str, variant: &TypeVariant) -> Callable {
+    Callable {
+        name: format!("{}/{}", type_name, variant.name),
+        params: variant
+            .fields
+            .iter()
+            .map(|field| {
+                let ty = field
+                    .type_annotation
+                    .as_ref()
+                    .map_or_else(|| "_".to_string(), ToString::to_string);
+                (field.name.to_string(), ty)
+            })
+            .collect(),
+        return_type: Some(type_name.to_string()),
+        documentation: None,
+    }
+}
+
+/// The signature label, and where each parameter is in it in UTF-16 units
+fn label(callable: &Callable) -> (String, Vec<ParameterInformation>) {
+    let mut label = format!("{}(", callable.name);
+    let mut parameters = Vec::new();
+    for (i, (name, ty)) in callable.params.iter().enumerate() {
+        if i > 0 {
+            label.push_str(", ");
+        }
+        let start = label.encode_utf16().count() as u32;
+        label.push_str(&format!("{}: {}", name, ty));
+        let end = label.encode_utf16().count() as u32;
+        parameters.push(ParameterInformation {
+            label: ParameterLabel::LabelOffsets([start, end]),
+            documentation: None,
+        });
+    }
+    label.push(')');
+    if let Some(return_type) = &callable.return_type {
+        label.push_str(&format!(" -> {}", return_type));
+    }
+    (label, parameters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "type Shape {\n    Circle(radius: u24),\n}\n\n# Scale a value.\nfn scale(value: u24, factor: u24) -> u24 {\n    return value * factor;\n}\n\nfn main() -> u24 {\n    return scale(scale(1, 2), [3, 4]);\n}\n";
+
+    fn signature_at(text: &str, line: u32, character: u32) -> Option<SignatureHelp> {
+        let uri = Url::parse("untitled:signature.bend").unwrap();
+        let mut documents = DocumentStore::default();
+        documents.open(uri.clone(), 1, text.to_string());
+        signature_help(&documents, &uri, Position::new(line, character))
+    }
+
+    #[test]
+    fn test_enclosing_call_skips_nested_brackets() {
+        let call = |prefix: &str| enclosing_call(prefix, prefix.len());
+        assert_eq!(
+            call("f(a, g(b, c), [d, e], "),
+            Some(Call {
+                name: "f".to_string(),
+                offset: 0,
+                argument: 3,
+            })
+        );
+        assert_eq!(call("f(a, g(b, ").unwrap().name, "g");
+        assert_eq!(call("f(a, (b, ").unwrap().name, "f");
+        assert!(call("f(a)").is_none());
+    }
+
+    #[test]
+    fn test_signature_and_active_parameter() {
+        let help = signature_at(SOURCE, 10, 31).unwrap();
+        let signature = &help.signatures[0];
+        assert_eq!(signature.label, "scale(value: u24, factor: u24) -> u24");
+        assert_eq!(
+            signature.parameters.as_ref().unwrap()[1].label,
+            ParameterLabel::LabelOffsets([18, 29])
+        );
+        assert_eq!(help.active_parameter, Some(1));
+        assert_eq!(
+            signature.documentation,
+            Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: "Scale a value.".to_string(),
+            }))
+        );
+
+        // Inside the nested call
+        let help = signature_at(SOURCE, 10, 26).unwrap();
+        assert_eq!(help.active_parameter, Some(1));
+        let help = signature_at(SOURCE, 10, 23).unwrap();
+        assert_eq!(help.active_parameter, Some(0));
+    }
+
+    #[test]
+    fn test_signatures_while_typing() {
+        let text = "type Shape {\n    Circle(radius: u24),\n}\n\nfn main() -> u24 {\n    x = Shape/Circle(\n\n# Doubles.\nfn double(x: u24) -> u24 {\n    return x * 2;\n}\n";
+        let help = signature_at(text, 5, 21).unwrap();
+        assert_eq!(
+            help.signatures[0].label,
+            "Shape/Circle(radius: u24) -> Shape"
+        );
+
+        let text = text.replace("Shape/Circle(", "double(");
+        let help = signature_at(&text, 5, 15).unwrap();
+        assert_eq!(help.signatures[0].label, "double(x: u24) -> u24");
+        assert_eq!(
+            help.signatures[0].documentation,
+            Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: "Doubles.".to_string(),
+            }))
+        );
+
+        // Not a call at all
+        assert!(signature_at(SOURCE, 6, 4).is_none());
+    }
+}
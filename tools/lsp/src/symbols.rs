@@ -0,0 +1,1258 @@
+//! Index of every symbol in the workspace of a document
+//!
+//! The index covers the modules of the package a document belongs to, or
+//! the modules next to it when it is not part of a package, along with
+//! every module they import that exists on disk. Each symbol is identified
+//! by the place it is defined, and every occurrence of a name, including
+//! the definition itself, points at that place. Imports are resolved with
+//! the module system, and locals follow the scoping of blocks, patterns
+//! and loops.
+
+use lsp_types::{DocumentHighlight, DocumentHighlightKind, Location, Position, Range, Url};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use bend_pvm::compiler::module::cache::canonical;
+use bend_pvm::compiler::module::ModuleSystem;
+use bend_pvm::compiler::parser::ast::{
+    Block, Definition, Expr, Import, Location as AstLocation, Parameter, Pattern, Program,
+    Statement, Type, TypeVariant,
+};
+use bend_pvm::compiler::parser::parser::Parser;
+
+use crate::diagnostics;
+use crate::documents::DocumentStore;
+
+/// Where a symbol is defined, which also identifies it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolSite {
+    pub uri: Url,
+    pub range: Range,
+    pub kind: DefinitionKind,
+}
+
+/// What a symbol's definition defines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    /// Functions and guards
+    Function,
+    /// Types, errors, objects, aliases and interfaces
+    Type,
+    /// Variants of types and errors
+    Constructor,
+    Event,
+    Module,
+    /// Storage fields
+    Storage,
+    Parameter,
+    Variable,
+}
+
+/// One place a symbol's name appears
+#[derive(Debug, Clone, PartialEq)]
+pub struct Occurrence {
+    pub range: Range,
+    pub site: SymbolSite,
+    pub is_definition: bool,
+    /// Whether the occurrence gives the symbol a new value, e.g. the
+    /// target of an assignment
+    pub is_write: bool,
+}
+
+/// Every occurrence of every symbol, by document
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    files: HashMap<Url, Vec<Occurrence>>,
+}
+
+/// A parsed module waiting to be indexed
+struct IndexedFile {
+    uri: Url,
+    text: String,
+    program: Program,
+    /// Top-level names defined by the module
+    globals: Scope,
+    /// Documents of the modules imported by path
+    imports: HashMap<String, Url>,
+}
+
+/// Names visible in one scope, with the sites they refer to
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    names: HashMap<String, SymbolSite>,
+    /// Names of type variants, which patterns match rather than bind
+    constructors: HashSet<String>,
+}
+
+impl SymbolIndex {
+    /// Index the workspace containing `uri`, reading open documents from
+    /// memory and everything else from disk
+    pub fn build(documents: &DocumentStore, uri: &Url) -> Self {
+        let Ok(path) = uri.to_file_path() else {
+            // Documents that are not files only see themselves
+            let mut index = SymbolIndex::default();
+            if let Some(document) = documents.get(uri) {
+                index.index_files(vec![(uri.clone(), document.text().to_string())], |_| None);
+            }
+            return index;
+        };
+
+        let (files, modules) = match diagnostics::workspace(&path) {
+            Some(workspace) => (
+                workspace.root().module_files().unwrap_or_default(),
+                workspace.module_system(workspace.root()),
+            ),
+            None => (
+                path.parent()
+                    .and_then(|dir| fs::read_dir(dir).ok())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "bend"))
+                    .collect(),
+                diagnostics::standalone_module_system(&path),
+            ),
+        };
+
+        let mut paths: Vec<PathBuf> = files.iter().map(|file| canonical(file)).collect();
+        let own = canonical(&path);
+        if !paths.contains(&own) {
+            paths.push(own);
+        }
+
+        let sources = paths
+            .into_iter()
+            .filter_map(|path| {
+                let uri = Url::from_file_path(&path).ok()?;
+                let text = documents.source(&uri)?;
+                Some((uri, text))
+            })
+            .collect();
+
+        let mut index = SymbolIndex::default();
+        index.index_files(sources, |import| {
+            resolve_import(&modules, documents, import)
+        });
+        index
+    }
+
+    /// Index `sources`, and whatever `resolve` finds for the paths they
+    /// import
+    fn index_files(
+        &mut self,
+        mut sources: Vec<(Url, String)>,
+        resolve: impl Fn(&str) -> Option<(Url, String)>,
+    ) {
+        let mut files: Vec<IndexedFile> = Vec::new();
+        let mut seen: HashSet<Url> = sources.iter().map(|(uri, _)| uri.clone()).collect();
+        while let Some((uri, text)) = sources.pop() {
+            let Ok(program) = Parser::new(&text).parse_program() else {
+                continue;
+            };
+
+            let mut imports = HashMap::new();
+            for path in program.imports.iter().flat_map(import_paths) {
+                if let Some((imported, source)) = resolve(path) {
+                    if seen.insert(imported.clone()) {
+                        sources.push((imported.clone(), source));
+                    }
+                    imports.insert(path.to_string(), imported);
+                }
+            }
+
+            let globals = top_level_names(&uri, &text, &program);
+            files.push(IndexedFile {
+                uri,
+                text,
+                program,
+                globals,
+                imports,
+            });
+        }
+
+        let exports: HashMap<Url, Scope> = files
+            .iter()
+            .map(|file| (file.uri.clone(), file.globals.clone()))
+            .collect();
+        for file in &files {
+            let mut walker = Walker::new(file);
+            walker.index_imports(&file.program.imports, &file.imports, &exports);
+            for definition in &file.program.definitions {
+                walker.definition(definition);
+            }
+            self.files.insert(file.uri.clone(), walker.occurrences);
+        }
+    }
+
+    /// Every occurrence in a document, in no particular order
+    pub fn occurrences(&self, uri: &Url) -> &[Occurrence] {
+        self.files.get(uri).map_or(&[], Vec::as_slice)
+    }
+
+    /// The occurrence of a symbol at `position`
+    pub fn occurrence_at(&self, uri: &Url, position: Position) -> Option<&Occurrence> {
+        self.files
+            .get(uri)?
+            .iter()
+            .find(|occurrence| contains(&occurrence.range, position))
+    }
+
+    /// Where the symbol at `position` is defined
+    pub fn definition(&self, uri: &Url, position: Position) -> Option<Location> {
+        let site = &self.occurrence_at(uri, position)?.site;
+        Some(Location::new(site.uri.clone(), site.range))
+    }
+
+    /// Every occurrence of the symbol at `position`, across all documents
+    pub fn references(
+        &self,
+        uri: &Url,
+        position: Position,
+        include_declaration: bool,
+    ) -> Vec<Location> {
+        let Some(site) = self.occurrence_at(uri, position).map(|o| o.site.clone()) else {
+            return Vec::new();
+        };
+        let mut references: Vec<Location> = self
+            .occurrences_of(&site)
+            .filter(|(_, occurrence)| include_declaration || !occurrence.is_definition)
+            .map(|(uri, occurrence)| Location::new(uri.clone(), occurrence.range))
+            .collect();
+        references.sort_by_key(|location| {
+            (
+                location.uri.to_string(),
+                location.range.start.line,
+                location.range.start.character,
+            )
+        });
+        references
+    }
+
+    /// Occurrences of the symbol at `position` within its document
+    pub fn highlights(&self, uri: &Url, position: Position) -> Vec<DocumentHighlight> {
+        let Some(site) = self.occurrence_at(uri, position).map(|o| o.site.clone()) else {
+            return Vec::new();
+        };
+        let mut occurrences: Vec<&Occurrence> = self
+            .occurrences_of(&site)
+            .filter(|(document, _)| *document == uri)
+            .map(|(_, occurrence)| occurrence)
+            .collect();
+        occurrences.sort_by_key(|occurrence| {
+            (
+                occurrence.range.start.line,
+                occurrence.range.start.character,
+            )
+        });
+        occurrences
+            .into_iter()
+            .map(|occurrence| DocumentHighlight {
+                range: occurrence.range,
+                kind: Some(if occurrence.is_definition || occurrence.is_write {
+                    DocumentHighlightKind::WRITE
+                } else {
+                    DocumentHighlightKind::READ
+                }),
+            })
+            .collect()
+    }
+
+    /// The definition every occurrence refers to, in document order. A
+    /// definition is identified by its document and its position among the
+    /// definitions there, which stay the same when names change length.
+    pub fn bindings(&self) -> HashMap<Url, Vec<(Url, usize)>> {
+        let definitions: HashMap<&Url, Vec<Range>> = self
+            .files
+            .iter()
+            .map(|(uri, occurrences)| {
+                let mut ranges: Vec<Range> = occurrences
+                    .iter()
+                    .filter(|occurrence| occurrence.is_definition)
+                    .map(|occurrence| occurrence.range)
+                    .collect();
+                ranges.sort_by_key(|range| (range.start.line, range.start.character));
+                ranges.dedup();
+                (uri, ranges)
+            })
+            .collect();
+
+        self.files
+            .iter()
+            .map(|(uri, occurrences)| {
+                let mut occurrences: Vec<&Occurrence> = occurrences.iter().collect();
+                occurrences.sort_by_key(|occurrence| {
+                    (
+                        occurrence.range.start.line,
+                        occurrence.range.start.character,
+                    )
+                });
+                let bindings = occurrences
+                    .into_iter()
+                    .map(|occurrence| {
+                        let site = &occurrence.site;
+                        let ordinal = definitions
+                            .get(&site.uri)
+                            .and_then(|ranges| ranges.iter().position(|r| *r == site.range))
+                            .unwrap_or(usize::MAX);
+                        (site.uri.clone(), ordinal)
+                    })
+                    .collect();
+                (uri.clone(), bindings)
+            })
+            .collect()
+    }
+
+    /// Every occurrence pointing at `site`
+    pub fn occurrences_of<'a>(
+        &'a self,
+        site: &'a SymbolSite,
+    ) -> impl Iterator<Item = (&'a Url, &'a Occurrence)> + 'a {
+        self.files.iter().flat_map(move |(uri, occurrences)| {
+            occurrences
+                .iter()
+                .filter(move |occurrence| occurrence.site == *site)
+                .map(move |occurrence| (uri, occurrence))
+        })
+    }
+}
+
+/// The document and source of the module imported as `path`, when it is a
+/// file rather than part of the embedded standard library
+fn resolve_import(
+    modules: &ModuleSystem,
+    documents: &DocumentStore,
+    path: &str,
+) -> Option<(Url, String)> {
+    let file = canonical(&modules.resolve_module_path(path)?);
+    let uri = Url::from_file_path(&file).ok()?;
+    let text = documents.source(&uri)?;
+    Some((uri, text))
+}
+
+fn import_paths(import: &Import) -> Vec<&str> {
+    match import {
+        Import::FromImport { path, .. } => vec![path.as_str()],
+        Import::DirectImport { names, .. } => names.iter().map(String::as_str).collect(),
+    }
+}
+
+fn contains(range: &Range, position: Position) -> bool {
+    range.start <= position && position <= range.end
+}
+
+/// The names a module defines at the top level
+fn top_level_names(uri: &Url, text: &str, program: &Program) -> Scope {
+    let lines = LineIndex::new(text);
+    let mut scope = Scope::default();
+    let mut define = |name: &str, location: &AstLocation, kind| {
+        if let Some(range) = lines.find(text, name, location) {
+            let site = SymbolSite {
+                uri: uri.clone(),
+                range,
+                kind,
+            };
+            scope.names.insert(name.to_string(), site);
+        }
+    };
+
+    let mut variants = Vec::new();
+    for definition in &program.definitions {
+        match definition {
+            Definition::TypeDef {
+                name,
+                variants: type_variants,
+                location,
+                ..
+            }
+            | Definition::ErrorDef {
+                name,
+                variants: type_variants,
+                location,
+                ..
+            } => {
+                define(name, location, DefinitionKind::Type);
+                variants.extend(type_variants.iter().map(|variant| (name, variant)));
+            }
+            Definition::FunctionDef { name, location, .. }
+            | Definition::GuardDef { name, location, .. } => {
+                define(name, location, DefinitionKind::Function)
+            }
+            Definition::ObjectDef { name, location, .. }
+            | Definition::TypeAlias { name, location, .. }
+            | Definition::InterfaceDef { name, location, .. } => {
+                define(name, location, DefinitionKind::Type)
+            }
+            Definition::Module { name, location, .. } => {
+                define(name, location, DefinitionKind::Module)
+            }
+            Definition::EventDef { name, location, .. } => {
+                define(name, location, DefinitionKind::Event)
+            }
+            Definition::StorageDef { fields, .. } => {
+                for field in fields {
+                    define(&field.name, &field.location, DefinitionKind::Storage);
+                }
+            }
+            // Lowered by the parser to the definitions above
+            Definition::ContractDef { .. } => {}
+        }
+    }
+
+    // Variants are used both bare and qualified with their type's name
+    for (type_name, variant) in variants {
+        let Some(range) = lines.find(text, &variant.name, &variant.location) else {
+            continue;
+        };
+        let site = SymbolSite {
+            uri: uri.clone(),
+            range,
+            kind: DefinitionKind::Constructor,
+        };
+        for name in [
+            variant.name.to_string(),
+            format!("{}/{}", type_name, variant.name),
+        ] {
+            scope.constructors.insert(name.clone());
+            scope.names.insert(name, site.clone());
+        }
+    }
+    scope
+}
+
+/// Byte offsets of line starts, for turning compiler locations into
+/// document ranges
+pub struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+        LineIndex { starts }
+    }
+
+    /// The first whole-word occurrence of `name` at or after a location.
+    /// Compiler locations point at the name itself or at the start of the
+    /// construct introducing it, like `fn` or `let`.
+    pub fn find(&self, text: &str, name: &str, location: &AstLocation) -> Option<Range> {
+        let mut search = self.offset(text, location)?;
+        while let Some(found) = text[search..].find(name) {
+            let start = search + found;
+            let end = start + name.len();
+            let before = text[..start].chars().next_back();
+            let after = text[end..].chars().next();
+            if !before.is_some_and(is_name_char) && !after.is_some_and(is_name_char) {
+                return Some(Range::new(
+                    self.position(text, start),
+                    self.position(text, end),
+                ));
+            }
+            search = end;
+        }
+        None
+    }
+
+    /// The byte offset of a compiler location
+    pub fn offset(&self, text: &str, location: &AstLocation) -> Option<usize> {
+        let line_start = *self.starts.get(location.line.checked_sub(1)?)?;
+        Some(
+            text[line_start..]
+                .char_indices()
+                .nth(location.column.saturating_sub(1))
+                .map_or(text.len(), |(offset, _)| line_start + offset),
+        )
+    }
+
+    /// The position of a byte offset
+    pub fn position(&self, text: &str, offset: usize) -> Position {
+        let line = self.starts.partition_point(|start| *start <= offset) - 1;
+        let character = text[self.starts[line]..offset].encode_utf16().count();
+        Position::new(line as u32, character as u32)
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '/' | '.')
+}
+
+/// Records the occurrences in one module while tracking which names are
+/// in scope
+struct Walker<'a> {
+    file: &'a IndexedFile,
+    lines: LineIndex,
+    /// Top-level and imported names
+    globals: Scope,
+    /// Local scopes, innermost last
+    scopes: Vec<HashMap<String, SymbolSite>>,
+    occurrences: Vec<Occurrence>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(file: &'a IndexedFile) -> Self {
+        Walker {
+            file,
+            lines: LineIndex::new(&file.text),
+            globals: file.globals.clone(),
+            scopes: Vec::new(),
+            occurrences: Vec::new(),
+        }
+    }
+
+    /// Bring imported names into scope, recording the names in the
+    /// import statements as references
+    fn index_imports(
+        &mut self,
+        imports: &[Import],
+        paths: &HashMap<String, Url>,
+        exports: &HashMap<Url, Scope>,
+    ) {
+        for import in imports {
+            match import {
+                Import::FromImport { path, names, .. } => {
+                    let Some(module) = paths.get(path).and_then(|uri| exports.get(uri)) else {
+                        continue;
+                    };
+                    for name in names {
+                        let Some(site) = module.names.get(name.name.as_str()) else {
+                            continue;
+                        };
+                        self.reference(&name.name, &name.location, site.clone(), false);
+                        let alias = name.alias.as_ref().unwrap_or(&name.name);
+                        self.globals.names.insert(alias.to_string(), site.clone());
+                        if module.constructors.contains(name.name.as_str()) {
+                            self.globals.constructors.insert(alias.to_string());
+                        }
+
+                        // Importing a type brings in its variants
+                        let prefix = format!("{}/", name.name);
+                        for (qualified, site) in &module.names {
+                            let Some(variant) = qualified.strip_prefix(&prefix) else {
+                                continue;
+                            };
+                            if module.constructors.contains(qualified) {
+                                for name in [format!("{}/{}", alias, variant), variant.to_string()]
+                                {
+                                    self.globals.constructors.insert(name.clone());
+                                    self.globals.names.insert(name, site.clone());
+                                }
+                            }
+                        }
+                    }
+                }
+                Import::DirectImport { names, .. } => {
+                    // Direct imports make every name available qualified
+                    // with the module's path
+                    for path in names {
+                        let Some(module) = paths.get(path).and_then(|uri| exports.get(uri)) else {
+                            continue;
+                        };
+                        for (name, site) in &module.names {
+                            let qualified = format!("{}/{}", path, name);
+                            if module.constructors.contains(name) {
+                                self.globals.constructors.insert(qualified.clone());
+                            }
+                            self.globals.names.insert(qualified, site.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn range(&self, name: &str, location: &AstLocation) -> Option<Range> {
+        self.lines.find(&self.file.text, name, location)
+    }
+
+    fn lookup(&self, name: &str) -> Option<SymbolSite> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.globals.names.get(name))
+            .cloned()
+    }
+
+    /// Record a use of `name` referring to `site`
+    fn reference(&mut self, name: &str, location: &AstLocation, site: SymbolSite, write: bool) {
+        if let Some(range) = self.range(name, location) {
+            self.occurrences.push(Occurrence {
+                range,
+                is_definition: site.uri == self.file.uri && site.range == range,
+                site,
+                is_write: write,
+            });
+        }
+    }
+
+    /// Record a use of `name`, if it refers to anything known
+    fn use_name(&mut self, name: &str, location: &AstLocation) {
+        if let Some(site) = self.lookup(name) {
+            self.reference(name, location, site, false);
+        }
+    }
+
+    /// Record a top-level definition
+    fn define_global(&mut self, name: &str) {
+        if let Some(site) = self.globals.names.get(name).cloned() {
+            if site.uri == self.file.uri {
+                self.occurrences.push(Occurrence {
+                    range: site.range,
+                    site,
+                    is_definition: true,
+                    is_write: false,
+                });
+            }
+        }
+    }
+
+    /// Bind a local in the innermost scope
+    fn define_local(&mut self, name: &str, location: &AstLocation, kind: DefinitionKind) {
+        let Some(range) = self.range(name, location) else {
+            return;
+        };
+        let site = SymbolSite {
+            uri: self.file.uri.clone(),
+            range,
+            kind,
+        };
+        self.occurrences.push(Occurrence {
+            range,
+            site: site.clone(),
+            is_definition: true,
+            is_write: false,
+        });
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), site);
+        }
+    }
+
+    fn scoped(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashMap::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    fn definition(&mut self, definition: &Definition) {
+        match definition {
+            Definition::FunctionDef {
+                name,
+                params,
+                return_type,
+                body,
+                ..
+            } => {
+                self.define_global(name);
+                self.function(params, return_type.as_ref(), Some(body));
+            }
+            Definition::TypeDef { name, variants, .. }
+            | Definition::ErrorDef { name, variants, .. } => {
+                self.define_global(name);
+                self.variants(name, variants);
+            }
+            Definition::ObjectDef {
+                name,
+                fields,
+                functions,
+                ..
+            } => {
+                self.define_global(name);
+                for field in fields {
+                    if let Some(ty) = &field.type_annotation {
+                        self.type_names(ty);
+                    }
+                }
+                for function in functions {
+                    self.method(function);
+                }
+            }
+            Definition::TypeAlias {
+                name, target_type, ..
+            } => {
+                self.define_global(name);
+                self.type_names(target_type);
+            }
+            Definition::Module {
+                name, definitions, ..
+            } => {
+                self.define_global(name);
+                for definition in definitions {
+                    self.method(definition);
+                }
+            }
+            Definition::EventDef { name, fields, .. } => {
+                self.define_global(name);
+                for field in fields {
+                    self.type_names(&field.ty);
+                }
+            }
+            Definition::StorageDef { fields, .. } => {
+                for field in fields {
+                    self.define_global(&field.name);
+                    self.type_names(&field.ty);
+                }
+            }
+            // Lowered by the parser to the definitions above
+            Definition::ContractDef { .. } => {}
+            Definition::GuardDef {
+                name,
+                params,
+                before,
+                after,
+                ..
+            } => {
+                self.define_global(name);
+                // Both halves of a guard share one scope
+                self.scoped(|walker| {
+                    walker.parameters(params);
+                    for statement in before.statements.iter().chain(&after.statements) {
+                        walker.statement(statement);
+                    }
+                });
+            }
+            Definition::InterfaceDef {
+                name, functions, ..
+            } => {
+                self.define_global(name);
+                for function in functions {
+                    self.method(function);
+                }
+            }
+        }
+    }
+
+    /// A definition nested in another, whose name is not a global
+    fn method(&mut self, definition: &Definition) {
+        match definition {
+            Definition::FunctionDef {
+                params,
+                return_type,
+                body,
+                ..
+            } => self.function(params, return_type.as_ref(), Some(body)),
+            other => self.definition(other),
+        }
+    }
+
+    fn function(&mut self, params: &[Parameter], return_type: Option<&Type>, body: Option<&Block>) {
+        if let Some(ty) = return_type {
+            self.type_names(ty);
+        }
+        self.scoped(|walker| {
+            walker.parameters(params);
+            if let Some(body) = body {
+                walker.statements(body);
+            }
+        });
+    }
+
+    fn parameters(&mut self, params: &[Parameter]) {
+        for param in params {
+            self.type_names(&param.ty);
+            self.define_local(&param.name, &param.location, DefinitionKind::Parameter);
+        }
+    }
+
+    fn variants(&mut self, type_name: &str, variants: &[TypeVariant]) {
+        for variant in variants {
+            if let Some(site) = self.lookup(&format!("{}/{}", type_name, variant.name)) {
+                if site.uri == self.file.uri {
+                    self.occurrences.push(Occurrence {
+                        range: site.range,
+                        site,
+                        is_definition: true,
+                        is_write: false,
+                    });
+                }
+            }
+            for field in &variant.fields {
+                if let Some(ty) = &field.type_annotation {
+                    self.type_names(ty);
+                }
+            }
+        }
+    }
+
+    fn type_names(&mut self, ty: &Type) {
+        match ty {
+            Type::Named {
+                name,
+                params,
+                location,
+            } => {
+                self.use_name(name, location);
+                for param in params {
+                    self.type_names(param);
+                }
+            }
+            Type::Function { param, result, .. } => {
+                self.type_names(param);
+                self.type_names(result);
+            }
+            Type::Effect { input, output, .. } => {
+                self.type_names(input);
+                self.type_names(output);
+            }
+            Type::Tuple { elements, .. } => {
+                for element in elements {
+                    self.type_names(element);
+                }
+            }
+            Type::Constrained { base, .. } => self.type_names(base),
+            _ => {}
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scoped(|walker| walker.statements(block));
+    }
+
+    fn statements(&mut self, block: &Block) {
+        for statement in &block.statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Assignment { pattern, value, .. } => {
+                self.expr(value);
+                self.bind(pattern);
+            }
+            Statement::Use {
+                name,
+                value,
+                location,
+            } => {
+                self.expr(value);
+                self.define_local(name, location, DefinitionKind::Variable);
+            }
+            Statement::InPlaceOp {
+                target,
+                value,
+                location,
+                ..
+            } => {
+                if let Some(site) = self.lookup(target) {
+                    self.reference(target, location, site, true);
+                }
+                self.expr(value);
+            }
+            Statement::Return { value, .. } | Statement::Expr { expr: value, .. } => {
+                self.expr(value)
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expr(condition);
+                self.block(then_branch);
+                self.block(else_branch);
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.expr(condition);
+                self.block(body);
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                body,
+                location,
+                ..
+            } => {
+                self.expr(start);
+                self.expr(end);
+                self.scoped(|walker| {
+                    walker.define_local(variable, location, DefinitionKind::Variable);
+                    walker.statements(body);
+                });
+            }
+            Statement::Switch { value, cases, .. } => {
+                self.expr(value);
+                for case in cases {
+                    self.block(&case.body);
+                }
+            }
+            Statement::Match { value, cases, .. } | Statement::Fold { value, cases, .. } => {
+                self.expr(value);
+                for case in cases {
+                    self.scoped(|walker| {
+                        walker.bind(&case.pattern);
+                        walker.statements(&case.body);
+                    });
+                }
+            }
+            Statement::Bend {
+                initial_states,
+                condition,
+                body,
+                else_body,
+                location,
+            } => {
+                for (_, value) in initial_states {
+                    self.expr(value);
+                }
+                self.scoped(|walker| {
+                    for (name, _) in initial_states {
+                        walker.define_local(name, location, DefinitionKind::Variable);
+                    }
+                    walker.expr(condition);
+                    walker.block(body);
+                    if let Some(else_body) = else_body {
+                        walker.block(else_body);
+                    }
+                });
+            }
+            Statement::Open {
+                type_name,
+                value,
+                location,
+            } => {
+                self.use_name(type_name, location);
+                self.expr(value);
+            }
+            Statement::With { body, .. } | Statement::Unchecked { body, .. } => self.block(body),
+            Statement::LocalDef { function_def, .. } => {
+                if let Definition::FunctionDef {
+                    name,
+                    params,
+                    return_type,
+                    body,
+                    location,
+                    ..
+                } = function_def.as_ref()
+                {
+                    self.define_local(name, location, DefinitionKind::Variable);
+                    self.function(params, return_type.as_ref(), Some(body));
+                }
+            }
+            Statement::TryCatch {
+                try_block,
+                catch_blocks,
+                ..
+            } => {
+                self.block(try_block);
+                for catch in catch_blocks {
+                    if let Some(error_type) = &catch.error_type {
+                        self.use_name(error_type, &catch.location);
+                    }
+                    self.scoped(|walker| {
+                        if let Some(error_var) = &catch.error_var {
+                            walker.define_local(
+                                error_var,
+                                &catch.location,
+                                DefinitionKind::Variable,
+                            );
+                        }
+                        walker.statements(&catch.body);
+                    });
+                }
+            }
+            Statement::Emit {
+                event,
+                args,
+                location,
+            } => {
+                self.use_name(event, location);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Statement::Revert {
+                condition, reason, ..
+            } => {
+                for expr in condition.iter().chain(reason) {
+                    self.expr(expr);
+                }
+            }
+        }
+    }
+
+    /// Bind the names a pattern introduces. Assigning to a local that is
+    /// already in scope writes to it rather than binding a new one.
+    fn bind(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Variable { name, location } => {
+                if self.globals.constructors.contains(name.as_str()) {
+                    self.use_name(name, location);
+                } else if let Some(site) = self
+                    .scopes
+                    .iter()
+                    .rev()
+                    .find_map(|scope| scope.get(name.as_str()))
+                    .cloned()
+                {
+                    self.reference(name, location, site, true);
+                } else {
+                    self.define_local(name, location, DefinitionKind::Variable);
+                }
+            }
+            Pattern::Tuple { elements, .. } => {
+                for element in elements {
+                    self.bind(element);
+                }
+            }
+            Pattern::Constructor {
+                name,
+                fields,
+                location,
+            } => {
+                self.use_name(name, location);
+                let mut fields: Vec<_> = fields.iter().collect();
+                fields.sort_by_key(|(field, _)| *field);
+                for (_, field) in fields {
+                    self.bind(field);
+                }
+            }
+            Pattern::TupleConstructor {
+                name,
+                args,
+                location,
+            } => {
+                self.use_name(name, location);
+                for arg in args {
+                    self.bind(arg);
+                }
+            }
+            Pattern::Member { parent, .. } => self.bind(parent),
+            Pattern::MapAccess { map, key, .. } => {
+                self.expr(map);
+                self.expr(key);
+            }
+            Pattern::Literal { .. } | Pattern::Wildcard { .. } => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Variable { name, location } => self.use_name(name, location),
+            Expr::Constructor {
+                name,
+                args,
+                named_args,
+                location,
+            } => {
+                self.use_name(name, location);
+                self.exprs(args);
+                self.named_exprs(named_args);
+            }
+            Expr::FunctionCall {
+                function,
+                args,
+                named_args,
+                ..
+            } => {
+                self.expr(function);
+                self.exprs(args);
+                self.named_exprs(named_args);
+            }
+            Expr::Lambda { params, body, .. } => self.scoped(|walker| {
+                walker.parameters(params);
+                walker.expr(body);
+            }),
+            Expr::UnsccopedLambda {
+                params,
+                body,
+                location,
+            } => self.scoped(|walker| {
+                for param in params {
+                    walker.define_local(param, location, DefinitionKind::Parameter);
+                }
+                walker.expr(body);
+            }),
+            Expr::BinaryOp { left, right, .. }
+            | Expr::TreeNode { left, right, .. }
+            | Expr::MapAccess {
+                map: left,
+                key: right,
+                ..
+            } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::UnaryOp { operand: inner, .. }
+            | Expr::FieldAccess { object: inner, .. }
+            | Expr::TreeLeaf { value: inner, .. }
+            | Expr::Try { expr: inner, .. } => self.expr(inner),
+            Expr::Tuple { elements, .. }
+            | Expr::List { elements, .. }
+            | Expr::Array { elements, .. }
+            | Expr::Superposition { elements, .. } => self.exprs(elements),
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expr(condition);
+                self.expr(then_branch);
+                self.expr(else_branch);
+            }
+            Expr::Block { block, .. } => self.block(block),
+            Expr::InlineAsm { inputs, .. } => {
+                for (_, input) in inputs {
+                    self.expr(input);
+                }
+            }
+            Expr::ListComprehension {
+                element,
+                variable,
+                iterable,
+                condition,
+                location,
+            } => {
+                self.expr(iterable);
+                self.scoped(|walker| {
+                    walker.define_local(variable, location, DefinitionKind::Variable);
+                    walker.expr(element);
+                    if let Some(condition) = condition {
+                        walker.expr(condition);
+                    }
+                });
+            }
+            Expr::MapComprehension {
+                key,
+                value,
+                variable,
+                iterable,
+                condition,
+                location,
+            } => {
+                self.expr(iterable);
+                self.scoped(|walker| {
+                    walker.define_local(variable, location, DefinitionKind::Variable);
+                    walker.expr(key);
+                    walker.expr(value);
+                    if let Some(condition) = condition {
+                        walker.expr(condition);
+                    }
+                });
+            }
+            Expr::Literal { .. } | Expr::Eraser { .. } => {}
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn named_exprs(&mut self, exprs: &HashMap<String, Expr>) {
+        let mut exprs: Vec<_> = exprs.iter().collect();
+        exprs.sort_by_key(|(name, _)| *name);
+        for (_, expr) in exprs {
+            self.expr(expr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(sources: &[(&str, &str)]) -> SymbolIndex {
+        let sources: Vec<(Url, String)> = sources
+            .iter()
+            .map(|(name, text)| (uri(name), text.to_string()))
+            .collect();
+        let modules = sources.clone();
+        let mut index = SymbolIndex::default();
+        index.index_files(sources, |path| {
+            modules
+                .iter()
+                .find(|(uri, _)| uri.path() == format!("/{}.bend", path))
+                .cloned()
+        });
+        index
+    }
+
+    fn uri(name: &str) -> Url {
+        Url::parse(&format!("file:///{}.bend", name.trim_end_matches(".bend"))).unwrap()
+    }
+
+    fn at(line: u32, character: u32) -> Position {
+        Position::new(line, character)
+    }
+
+    const UTILS: &str = "pub type Shape {\n    Circle(radius: u24),\n    Empty,\n}\n\npub fn area(shape: Shape) -> u24 {\n    match shape {\n        Shape/Circle(r) => { return r * r; }\n        Empty => { return 0; }\n    }\n}\n";
+
+    const MAIN: &str = "from utils import area, Shape;\n\nfn main() -> u24 {\n    let total = area(Shape/Empty);\n    total = total + 1;\n    return total;\n}\n";
+
+    #[test]
+    fn test_definition_across_files() {
+        let index = index(&[("utils", UTILS), ("main", MAIN)]);
+
+        // `area` in the call goes to the function in utils
+        let location = index.definition(&uri("main"), at(3, 17)).unwrap();
+        assert_eq!(location.uri, uri("utils"));
+        assert_eq!(location.range, Range::new(at(5, 7), at(5, 11)));
+
+        // Qualified constructors go to their variant
+        let location = index.definition(&uri("main"), at(3, 22)).unwrap();
+        assert_eq!(location.range, Range::new(at(2, 4), at(2, 9)));
+    }
+
+    #[test]
+    fn test_locals_follow_scopes() {
+        let index = index(&[("utils", UTILS)]);
+
+        // The pattern variable `r` is bound by its match case
+        let location = index.definition(&uri("utils"), at(7, 36)).unwrap();
+        assert_eq!(location.range, Range::new(at(7, 21), at(7, 22)));
+
+        // Bare nullary constructors in patterns are references
+        let location = index.definition(&uri("utils"), at(8, 9)).unwrap();
+        assert_eq!(location.range, Range::new(at(2, 4), at(2, 9)));
+
+        // The parameter `shape`
+        let location = index.definition(&uri("utils"), at(6, 11)).unwrap();
+        assert_eq!(location.range, Range::new(at(5, 12), at(5, 17)));
+    }
+
+    #[test]
+    fn test_references_and_highlights() {
+        let index = index(&[("utils", UTILS), ("main", MAIN)]);
+
+        let references = index.references(&uri("utils"), at(5, 8), true);
+        assert_eq!(
+            references
+                .iter()
+                .map(|location| (location.uri.path().to_string(), location.range.start.line))
+                .collect::<Vec<_>>(),
+            vec![("/main.bend".to_string(), 0), ("/main.bend".to_string(), 3)]
+                .into_iter()
+                .chain([("/utils.bend".to_string(), 5)])
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(index.references(&uri("utils"), at(5, 8), false).len(), 2);
+
+        let highlights = index.highlights(&uri("main"), at(5, 12));
+        let kinds: Vec<_> = highlights
+            .iter()
+            .map(|highlight| (highlight.range.start.line, highlight.kind.unwrap()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (3, DocumentHighlightKind::WRITE),
+                (4, DocumentHighlightKind::WRITE),
+                (4, DocumentHighlightKind::READ),
+                (5, DocumentHighlightKind::READ),
+            ]
+        );
+    }
+}